@@ -6,7 +6,11 @@
 
 use std::borrow::Cow;
 
-use directory::{backend::RcptType, Directory};
+use ahash::AHashSet;
+use directory::{
+    backend::{DomainRouting, RcptType},
+    Directory,
+};
 use utils::config::{utils::AsKey, Config};
 
 use crate::{
@@ -24,32 +28,56 @@ impl Server {
         email: &str,
         session_id: u64,
     ) -> trc::Result<Option<u32>> {
-        let mut address = self
-            .core
-            .smtp
-            .session
-            .rcpt
-            .subaddressing
-            .to_subaddress(self, email, session_id)
-            .await;
+        let result = directory.email_to_id(email).await?;
+        if result.is_some() {
+            Ok(result)
+        } else {
+            self.email_to_id_fallback(directory, email, session_id)
+                .await
+        }
+    }
 
-        for _ in 0..2 {
-            let result = directory.email_to_id(address.as_ref()).await?;
+    /// Bulk version of `email_to_id`, results are returned in the order of
+    /// `emails`. Exact matches are resolved at once, only the misses go through
+    /// subaddressing and catch-all resolution.
+    pub async fn email_to_ids(
+        &self,
+        directory: &Directory,
+        emails: &[&str],
+        session_id: u64,
+    ) -> trc::Result<Vec<Option<u32>>> {
+        let mut ids = directory.email_to_ids(emails).await?;
+        for (id, email) in ids.iter_mut().zip(emails) {
+            if id.is_none() {
+                *id = self
+                    .email_to_id_fallback(directory, email, session_id)
+                    .await?;
+            }
+        }
 
+        Ok(ids)
+    }
+
+    async fn email_to_id_fallback(
+        &self,
+        directory: &Directory,
+        email: &str,
+        session_id: u64,
+    ) -> trc::Result<Option<u32>> {
+        let routing = match email.rsplit_once('@') {
+            Some((_, domain)) => directory.domain_routing(domain).await?,
+            None => None,
+        };
+        let fallback = self
+            .rcpt_fallback(routing.unwrap_or_default(), email, session_id)
+            .await;
+        for address in [fallback.subaddress, fallback.catch_all]
+            .into_iter()
+            .flatten()
+        {
+            let result = directory.email_to_id(&address).await?;
             if result.is_some() {
                 return Ok(result);
-            } else if let Some(catch_all) = self
-                .core
-                .smtp
-                .session
-                .rcpt
-                .catch_all
-                .to_catch_all(self, email, session_id)
-                .await
-            {
-                address = catch_all;
-            } else {
-                break;
             }
         }
 
@@ -62,36 +90,124 @@ impl Server {
         email: &str,
         session_id: u64,
     ) -> trc::Result<RcptType> {
-        // Expand subaddress
-        let mut address = self
-            .core
-            .smtp
-            .session
-            .rcpt
-            .subaddressing
-            .to_subaddress(self, email, session_id)
-            .await;
+        let rcpt_type = directory.rcpt(email).await?;
+        if rcpt_type != RcptType::Invalid {
+            return Ok(rcpt_type);
+        }
 
-        for _ in 0..2 {
-            let rcpt_type = directory.rcpt(address.as_ref()).await?;
+        let routing = match email.rsplit_once('@') {
+            Some((_, domain)) => directory.domain_routing(domain).await?,
+            None => None,
+        };
+        let fallback = self
+            .rcpt_fallback(routing.unwrap_or_default(), email, session_id)
+            .await;
+        for address in [fallback.subaddress, fallback.catch_all]
+            .into_iter()
+            .flatten()
+        {
+            let rcpt_type = directory.rcpt(&address).await?;
             if rcpt_type != RcptType::Invalid {
                 return Ok(rcpt_type);
-            } else if let Some(catch_all) = self
-                .core
-                .smtp
-                .session
-                .rcpt
+            }
+        }
+
+        Ok(RcptType::Invalid)
+    }
+
+    /// Addresses to try when the exact lookup of a recipient misses. Settings
+    /// stored on the recipient's domain take precedence over the global
+    /// subaddressing and catch-all configuration.
+    pub async fn rcpt_fallback(
+        &self,
+        routing: DomainRouting,
+        email: &str,
+        session_id: u64,
+    ) -> RcptFallback {
+        let rcpt = &self.core.smtp.session.rcpt;
+
+        let subaddress = match routing.subaddressing {
+            Some(subaddressing) => subaddressing.to_subaddress(email),
+            None => Some(
+                rcpt.subaddressing
+                    .to_subaddress(self, email, session_id)
+                    .await
+                    .into_owned(),
+            ),
+        }
+        .filter(|address| address != email);
+        let catch_all = match routing.catch_all {
+            Some(catch_all) => Some(catch_all),
+            None => rcpt
                 .catch_all
                 .to_catch_all(self, email, session_id)
                 .await
-            {
-                address = catch_all;
-            } else {
+                .map(Cow::into_owned),
+        };
+
+        RcptFallback {
+            subaddress,
+            catch_all,
+        }
+    }
+
+    /// Resolves the final destinations of an alias, following local aliases
+    /// and lists it forwards to. Destinations leading back to an address
+    /// being expanded are skipped and reported as loops.
+    pub async fn expand_alias(
+        &self,
+        directory: &Directory,
+        address: &str,
+        forward_to: Vec<String>,
+        session_id: u64,
+    ) -> trc::Result<AliasExpansion> {
+        let max_fan_out = self.core.smtp.session.rcpt.alias_max_fan_out;
+        let mut expansion = AliasExpansion::default();
+        let mut seen = AHashSet::new();
+        let mut pending = forward_to
+            .into_iter()
+            .rev()
+            .map(|destination| (destination, vec![address.to_string()]))
+            .collect::<Vec<_>>();
+
+        while let Some((destination, path)) = pending.pop() {
+            let destination = destination.to_lowercase();
+            if path.contains(&destination) {
+                if !expansion.loops.contains(&destination) {
+                    expansion.loops.push(destination);
+                }
+                continue;
+            } else if !seen.insert(destination.clone()) {
+                continue;
+            }
+
+            // Local aliases and lists are expanded in place
+            if let Some((_, domain)) = destination.rsplit_once('@') {
+                if directory.is_local_domain(domain).await? {
+                    if let RcptType::Alias(members) | RcptType::List(members) =
+                        self.rcpt(directory, &destination, session_id).await?
+                    {
+                        let mut path = path;
+                        path.push(destination);
+                        pending.extend(
+                            members
+                                .into_iter()
+                                .rev()
+                                .map(|member| (member, path.clone())),
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            if expansion.destinations.len() >= max_fan_out {
+                expansion.exceeded = true;
                 break;
             }
+            expansion.destinations.push(destination);
         }
 
-        Ok(RcptType::Invalid)
+        Ok(expansion)
     }
 
     pub async fn vrfy(
@@ -135,6 +251,19 @@ impl Server {
     }
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RcptFallback {
+    pub subaddress: Option<String>,
+    pub catch_all: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AliasExpansion {
+    pub destinations: Vec<String>,
+    pub loops: Vec<String>,
+    pub exceeded: bool,
+}
+
 impl AddressMapping {
     pub fn parse(config: &mut Config, key: impl AsKey) -> Self {
         let key = key.as_key();
@@ -5,8 +5,8 @@
  */
 
 use directory::{
-    backend::internal::{lookup::DirectoryStore, PrincipalField},
-    Permission, Principal, QueryBy,
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory, PrincipalField},
+    Permission, Principal, QueryBy, Type,
 };
 use jmap_proto::{
     request::RequestMethod,
@@ -33,6 +33,26 @@ impl Server {
     pub async fn build_access_token(&self, mut principal: Principal) -> trc::Result<AccessToken> {
         let mut role_permissions = RolePermissions::default();
 
+        // Inherit the groups and roles of nested groups
+        for member_of in self
+            .store()
+            .get_member_of_recursive(principal.id(), self.core.jmap.member_of_max_depth)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if member_of.depth > 1 {
+                match member_of.typ {
+                    Type::Role => {
+                        principal.append_int(PrincipalField::Roles, member_of.principal_id);
+                    }
+                    Type::List => {}
+                    _ => {
+                        principal.append_int(PrincipalField::MemberOf, member_of.principal_id);
+                    }
+                }
+            }
+        }
+
         // Apply role permissions
         for role_id in principal.iter_int(PrincipalField::Roles) {
             role_permissions.union(self.get_role_permissions(role_id as u32).await?.as_ref());
@@ -57,6 +77,12 @@ impl Server {
             }
         }
 
+        // Disabled principals keep their data but cannot log in,
+        // this also invalidates previously issued OAuth tokens
+        if !principal.is_enabled() {
+            role_permissions.disabled.set(Permission::Authenticate.id());
+        }
+
         // Apply principal permissions
         let mut permissions = role_permissions.finalize();
 
@@ -108,7 +134,11 @@ impl Server {
                 .take_str_array(PrincipalField::Emails)
                 .unwrap_or_default(),
             quota: principal.quota(),
+            message_quota: principal.message_quota(),
             permissions,
+            on_hold: principal.is_on_hold(),
+            credentials_epoch: principal.credentials_epoch(),
+            api_key_id: None,
         })
     }
 
@@ -237,6 +267,19 @@ impl AccessToken {
         self.primary_id
     }
 
+    /// Identifies the caller when scoping rate limits and idempotency keys.
+    /// API keys share the same account id, so each key is told apart by its
+    /// tenant and key id.
+    pub fn caller_id(&self) -> String {
+        match self.api_key_id {
+            Some(key_id) => format!(
+                "key:{}:{key_id}",
+                self.tenant.map_or(u32::MAX, |tenant| tenant.id)
+            ),
+            None => self.primary_id.to_string(),
+        }
+    }
+
     pub fn secondary_ids(&self) -> impl Iterator<Item = &u32> {
         self.member_of
             .iter()
@@ -362,6 +405,9 @@ impl AccessToken {
                     Permission::JmapPrincipalGet
                 }
                 jmap_proto::method::get::RequestArguments::Quota => Permission::JmapQuotaGet,
+                jmap_proto::method::get::RequestArguments::ThreadRule => {
+                    Permission::JmapThreadRuleGet
+                }
                 jmap_proto::method::get::RequestArguments::Blob(_) => Permission::JmapBlobGet,
             },
             RequestMethod::Set(m) => match &m.arguments {
@@ -380,6 +426,9 @@ impl AccessToken {
                 jmap_proto::method::set::RequestArguments::VacationResponse => {
                     Permission::JmapVacationResponseSet
                 }
+                jmap_proto::method::set::RequestArguments::ThreadRule => {
+                    Permission::JmapThreadRuleSet
+                }
             },
             RequestMethod::Changes(m) => match m.arguments {
                 jmap_proto::method::changes::RequestArguments::Email => {
@@ -464,7 +513,9 @@ impl AccessToken {
         ResourceToken {
             account_id: self.primary_id,
             quota: self.quota,
+            message_quota: self.message_quota,
             tenant: self.tenant,
+            on_hold: self.on_hold,
         }
     }
 }
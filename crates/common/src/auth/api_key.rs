@@ -0,0 +1,268 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Write, sync::Arc};
+
+use directory::{
+    backend::internal::{lookup::DirectoryStore, PrincipalField},
+    Permission, Permissions, QueryBy, ROLE_TENANT_ADMIN,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use store::{
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    write::{now, BatchBuilder, TaskClass, ValueClass},
+    IterateParams, ValueKey,
+};
+use trc::AddContext;
+use utils::map::vec_map::VecMap;
+
+use crate::Server;
+
+use super::{AccessToken, TenantInfo};
+
+const API_KEY: &[u8] = b"apikey.";
+pub const API_KEY_PREFIX: &str = "stk_";
+const API_KEY_SECRET_LEN: usize = 40;
+const LAST_USED_INTERVAL: u64 = 60;
+
+/// Account id of the access tokens issued for API keys. It does not belong to
+/// any principal, so keys never act as the tenant or as the fallback admin.
+/// Limits and idempotency keys are scoped per key using `api_key_id`.
+pub const API_KEY_ACCOUNT_ID: u32 = u32::MAX - 1;
+
+/// Management API credential bound to a tenant. Keys are not principals, they
+/// resolve to an access token carrying the tenant id and a subset of the
+/// creator's permissions, and are never accepted by the mail protocols.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: u64,
+    pub name: String,
+    pub tenant_id: u32,
+    pub permissions: Vec<Permission>,
+    pub secret_hash: String,
+    pub created_by: String,
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+    pub last_used_at: Option<u64>,
+}
+
+impl Server {
+    pub async fn authenticate_api_key(&self, token: &str) -> trc::Result<Arc<AccessToken>> {
+        let failed = || {
+            trc::AuthEvent::Failed
+                .into_err()
+                .details("Invalid API key.")
+                .caused_by(trc::location!())
+        };
+        let (id, secret) = parse_api_key(token).ok_or_else(failed)?;
+        let mut key = self
+            .api_key(id)
+            .await?
+            .filter(|key| secret_matches(&key.secret_hash, &hash_secret(secret)))
+            .ok_or_else(failed)?;
+
+        let now = now();
+        if key.expires_at.is_some_and(|expires_at| expires_at <= now) {
+            return Err(trc::AuthEvent::TokenExpired
+                .into_err()
+                .details("API key has expired.")
+                .id(id)
+                .caused_by(trc::location!()));
+        }
+
+        // Usage is recorded at most once per interval to avoid a write per request
+        if key
+            .last_used_at
+            .is_none_or(|last_used_at| last_used_at + LAST_USED_INTERVAL <= now)
+        {
+            key.last_used_at = Some(now);
+            self.write_api_key(&key).await?;
+        }
+
+        // Permissions are limited to what a tenant administrator is allowed
+        let mut permissions = Permissions::new();
+        for permission in &key.permissions {
+            if !permission.is_user_permission() {
+                permissions.set(permission.id());
+            }
+        }
+        permissions.intersection(&self.get_role_permissions(ROLE_TENANT_ADMIN).await?.enabled);
+
+        let quota = self
+            .store()
+            .query(QueryBy::Id(key.tenant_id), false)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| {
+                trc::SecurityEvent::Unauthorized
+                    .into_err()
+                    .details("Tenant not found")
+                    .id(key.tenant_id)
+                    .caused_by(trc::location!())
+            })?
+            .get_int(PrincipalField::Quota)
+            .unwrap_or_default();
+
+        Ok(Arc::new(AccessToken {
+            primary_id: API_KEY_ACCOUNT_ID,
+            member_of: vec![],
+            access_to: VecMap::new(),
+            name: key.name,
+            description: Some(format!("API key {}", key.id)),
+            emails: vec![],
+            quota: 0,
+            message_quota: 0,
+            permissions,
+            tenant: Some(TenantInfo {
+                id: key.tenant_id,
+                quota,
+            }),
+            on_hold: false,
+            credentials_epoch: 0,
+            api_key_id: Some(key.id),
+        }))
+    }
+
+    pub async fn create_api_key(
+        &self,
+        name: String,
+        tenant_id: u32,
+        permissions: Vec<Permission>,
+        expires_at: Option<u64>,
+        created_by: String,
+    ) -> trc::Result<(ApiKey, String)> {
+        let secret = thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(API_KEY_SECRET_LEN)
+            .map(char::from)
+            .collect::<String>();
+        let created_at = now();
+        let key = ApiKey {
+            id: self
+                .inner
+                .data
+                .queue_id_gen
+                .generate()
+                .unwrap_or(created_at),
+            name,
+            tenant_id,
+            permissions,
+            secret_hash: hash_secret(&secret),
+            created_by,
+            created_at,
+            expires_at,
+            last_used_at: None,
+        };
+        self.write_api_key(&key).await?;
+        let token = format!("{API_KEY_PREFIX}{:x}_{secret}", key.id);
+
+        Ok((key, token))
+    }
+
+    pub async fn api_key(&self, id: u64) -> trc::Result<Option<ApiKey>> {
+        self.core
+            .storage
+            .data
+            .get_value::<String>(ValueKey::from(ValueClass::Task(TaskClass::State(key_id(
+                id,
+            )))))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    pub async fn list_api_keys(&self, tenant_id: Option<u32>) -> trc::Result<Vec<ApiKey>> {
+        let from_key =
+            ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(key_id(0))));
+        let to_key =
+            ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(key_id(u64::MAX))));
+
+        let mut keys = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    if let Ok(key) = serde_json::from_slice::<ApiKey>(value) {
+                        if tenant_id.is_none_or(|tenant_id| tenant_id == key.tenant_id) {
+                            keys.push(key);
+                        }
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(keys)
+    }
+
+    pub async fn revoke_api_key(&self, id: u64) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Task(TaskClass::State(key_id(id))));
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn write_api_key(&self, key: &ApiKey) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(key_id(key.id))),
+            serde_json::to_vec(key).unwrap_or_default(),
+        );
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+pub fn is_api_key(token: &str) -> bool {
+    token.starts_with(API_KEY_PREFIX)
+}
+
+fn parse_api_key(token: &str) -> Option<(u64, &str)> {
+    let (id, secret) = token.strip_prefix(API_KEY_PREFIX)?.split_once('_')?;
+    Some((u64::from_str_radix(id, 16).ok()?, secret))
+}
+
+fn hash_secret(secret: &str) -> String {
+    let mut hash = String::with_capacity(64);
+    for byte in Sha256::digest(secret.as_bytes()) {
+        let _ = write!(hash, "{byte:02x}");
+    }
+    hash
+}
+
+// Compares the hashes in constant time to avoid leaking how much of the
+// secret matched through the response time
+fn secret_matches(stored: &str, provided: &str) -> bool {
+    stored.len() == provided.len()
+        && stored
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn key_id(id: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(API_KEY.len() + std::mem::size_of::<u64>());
+    key.extend_from_slice(API_KEY);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
@@ -0,0 +1,117 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{net::IpAddr, sync::Arc};
+
+use directory::{ClientCertificate, Directory, Permission, QueryBy};
+use x509_parser::{certificate::X509Certificate, extensions::GeneralName, prelude::FromDer};
+
+use crate::{config::server::tls::certificate_fingerprint, Server};
+
+use super::AccessToken;
+
+/// Obtains the identity presented by a DER encoded client certificate.
+pub fn client_certificate(der: &[u8]) -> Option<ClientCertificate> {
+    let (_, certificate) = X509Certificate::from_der(der).ok()?;
+    let emails = certificate
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::RFC822Name(email) => Some(email.to_lowercase()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let subject = certificate
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(|cn| cn.to_string());
+
+    Some(ClientCertificate {
+        subject,
+        emails,
+        fingerprint: certificate_fingerprint(der),
+    })
+}
+
+impl Server {
+    /// Authenticates a client certificate verified by the listener. Returns `None`
+    /// when certificate authentication is disabled or the certificate does not
+    /// match any principal, in which case the client has to authenticate using SASL.
+    pub async fn authenticate_certificate(
+        &self,
+        der: &[u8],
+        directory: Option<&Directory>,
+        session_id: u64,
+        remote_ip: IpAddr,
+    ) -> trc::Result<Option<Arc<AccessToken>>> {
+        if !self.core.jmap.allow_client_certs {
+            return Ok(None);
+        }
+        let Some(certificate) = client_certificate(der) else {
+            trc::event!(
+                Auth(trc::AuthEvent::Failed),
+                SpanId = session_id,
+                RemoteIp = remote_ip,
+                Details = "Failed to parse client certificate",
+            );
+            return Ok(None);
+        };
+
+        let directory = directory.unwrap_or(&self.core.storage.directory);
+        let Some(principal) = directory
+            .query(QueryBy::Certificate(&certificate), true)
+            .await?
+        else {
+            trc::event!(
+                Auth(trc::AuthEvent::Failed),
+                SpanId = session_id,
+                RemoteIp = remote_ip,
+                Details = "Client certificate does not match any account",
+                Id = certificate.fingerprint,
+            );
+            return Ok(None);
+        };
+
+        if !principal.is_enabled() {
+            return Err(trc::AuthEvent::AccountDisabled
+                .ctx(trc::Key::RemoteIp, remote_ip)
+                .ctx(trc::Key::AccountName, principal.name().to_string())
+                .ctx(trc::Key::AccountId, principal.id()));
+        }
+        if self.core.account_lockout.is_enabled() {
+            if let Some(locked_until) = self.account_locked_until(principal.id()).await? {
+                return Err(trc::AuthEvent::AccountLocked
+                    .ctx(trc::Key::RemoteIp, remote_ip)
+                    .ctx(trc::Key::AccountName, principal.name().to_string())
+                    .ctx(trc::Key::AccountId, principal.id())
+                    .ctx(trc::Key::Expires, trc::Value::Timestamp(locked_until)));
+            }
+        }
+
+        let access_token = self.principal_access_token(principal).await?;
+        access_token.assert_has_permission(Permission::Authenticate)?;
+        self.record_login(access_token.primary_id()).await;
+
+        trc::event!(
+            Auth(trc::AuthEvent::Success),
+            AccountName = access_token.name.clone(),
+            AccountId = access_token.primary_id(),
+            SpanId = session_id,
+            Details = "Client certificate",
+        );
+
+        Ok(Some(access_token))
+    }
+}
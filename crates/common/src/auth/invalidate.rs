@@ -0,0 +1,186 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use store::{write::Bincode, Serialize};
+use trc::AddContext;
+
+use crate::Server;
+
+// Published changes are kept long enough for nodes to catch up after a restart
+const CHANGE_LOG_TTL: u64 = 3600;
+// Nodes further behind than this clear their caches instead of replaying the log
+const MAX_REPLAYED_CHANGES: u64 = 1000;
+// Evictions gossiped per heartbeat, peers clear their caches when more are pending
+const MAX_GOSSIPED_EVICTIONS: usize = 1000;
+
+/// Position of this node in the cluster-wide directory change log and the
+/// evictions waiting to be gossiped to its peers.
+#[derive(Debug, Default)]
+pub struct DirectorySync {
+    sequence: Option<u64>,
+    checked_at: Option<Instant>,
+    gossip: bool,
+    evictions: Vec<u32>,
+    evict_all: bool,
+}
+
+impl DirectorySync {
+    /// Queues evictions for the gossip protocol to broadcast to the cluster.
+    pub fn enable_gossip(&mut self) {
+        self.gossip = true;
+    }
+
+    fn queue_eviction(&mut self, account_id: u32) {
+        if self.gossip && !self.evict_all && !self.evictions.contains(&account_id) {
+            if self.evictions.len() < MAX_GOSSIPED_EVICTIONS {
+                self.evictions.push(account_id);
+            } else {
+                self.evictions.clear();
+                self.evict_all = true;
+            }
+        }
+    }
+
+    /// Returns the evictions queued since the last call, an empty list means
+    /// that peers have to clear their caches.
+    pub fn take_evictions(&mut self) -> Option<Vec<u32>> {
+        if self.evict_all {
+            self.evict_all = false;
+            Some(Vec::new())
+        } else if !self.evictions.is_empty() {
+            Some(std::mem::take(&mut self.evictions))
+        } else {
+            None
+        }
+    }
+}
+
+impl Server {
+    /// Evicts the cached credentials and access token of a principal and
+    /// gossips the change so that other nodes evict them as well. Changes are
+    /// also published to the lookup store, where nodes that missed a gossip
+    /// message pick them up.
+    pub async fn invalidate_principal(&self, account_id: u32) -> trc::Result<()> {
+        self.evict_principal(account_id);
+        self.inner
+            .data
+            .directory_sync
+            .lock()
+            .queue_eviction(account_id);
+
+        let lookup = &self.core.storage.lookup;
+        let sequence = lookup
+            .counter_incr(SEQUENCE_KEY.to_vec(), 1, None, true)
+            .await
+            .caused_by(trc::location!())?;
+        lookup
+            .key_set(
+                change_key(sequence as u64),
+                Bincode::new(account_id).serialize(),
+                Some(CHANGE_LOG_TTL),
+            )
+            .await
+            .caused_by(trc::location!())
+    }
+
+    /// Applies the changes published by other nodes since the last check, at
+    /// most once per sync interval. Nodes that missed part of the change log
+    /// clear their caches.
+    ///
+    /// Evictions are gossiped as they happen, this is a fallback for lost
+    /// gossip messages and nodes that are not part of a cluster.
+    pub async fn sync_directory_changes(&self) {
+        let last_sequence = {
+            let mut sync = self.inner.data.directory_sync.lock();
+            if sync.checked_at.is_some_and(|checked_at| {
+                checked_at.elapsed() < self.core.jmap.directory_sync_interval
+            }) {
+                return;
+            }
+            sync.checked_at = Some(Instant::now());
+            sync.sequence
+        };
+
+        match self.apply_directory_changes(last_sequence).await {
+            Ok(sequence) => {
+                self.inner.data.directory_sync.lock().sequence = Some(sequence);
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to synchronize directory changes"));
+            }
+        }
+    }
+
+    async fn apply_directory_changes(&self, last_sequence: Option<u64>) -> trc::Result<u64> {
+        let lookup = &self.core.storage.lookup;
+        let sequence = lookup
+            .counter_get(SEQUENCE_KEY.to_vec())
+            .await
+            .caused_by(trc::location!())?
+            .max(0) as u64;
+
+        match last_sequence {
+            Some(last_sequence)
+                if sequence > last_sequence && sequence - last_sequence <= MAX_REPLAYED_CHANGES =>
+            {
+                for change in last_sequence + 1..=sequence {
+                    match lookup
+                        .key_get::<Bincode<u32>>(change_key(change))
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        Some(account_id) => self.evict_principal(account_id.inner),
+                        None => {
+                            self.evict_all_principals();
+                            break;
+                        }
+                    }
+                }
+            }
+            Some(last_sequence) if sequence != last_sequence => {
+                self.evict_all_principals();
+            }
+            _ => (),
+        }
+
+        Ok(sequence)
+    }
+
+    /// Applies evictions gossiped by another node, an empty list clears the
+    /// cached principals.
+    pub fn apply_evictions(&self, account_ids: &[u32]) {
+        if !account_ids.is_empty() {
+            for account_id in account_ids {
+                self.evict_principal(*account_id);
+            }
+        } else {
+            self.evict_all_principals();
+        }
+    }
+
+    fn evict_principal(&self, account_id: u32) {
+        self.inner
+            .data
+            .http_auth_cache
+            .retain(|_, id| id.item != account_id);
+        self.inner.data.access_tokens.remove(&account_id);
+        self.inner.data.principal_names.remove(&account_id);
+    }
+
+    fn evict_all_principals(&self) {
+        self.inner.data.http_auth_cache.clear();
+        self.inner.data.access_tokens.clear();
+        self.inner.data.principal_names.clear();
+    }
+}
+
+const SEQUENCE_KEY: &[u8] = b"dir:seq";
+
+fn change_key(sequence: u64) -> Vec<u8> {
+    format!("dir:chg:{sequence}").into_bytes()
+}
@@ -0,0 +1,189 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory, PrincipalField},
+    QueryBy,
+};
+use store::{
+    write::{now, Bincode},
+    Serialize,
+};
+use trc::AddContext;
+
+use crate::Server;
+
+/// Failed login counter and lock of a principal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LockoutStatus {
+    pub failures: u64,
+    pub locked_until: Option<u64>,
+}
+
+impl Server {
+    /// Returns the id of the principal a login refers to, used to track failed
+    /// logins of accounts that could not be authenticated.
+    pub async fn lockout_principal_id(&self, login: &str) -> trc::Result<Option<u32>> {
+        let store = &self.core.storage.data;
+        match store
+            .get_principal_id(login)
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(id) => Ok(Some(id)),
+            None if login.contains('@') => store
+                .email_to_id(&login.to_lowercase())
+                .await
+                .caused_by(trc::location!()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the time until the principal is locked, if it is locked.
+    pub async fn account_locked_until(&self, account_id: u32) -> trc::Result<Option<u64>> {
+        self.core
+            .storage
+            .lookup
+            .key_get::<Bincode<u64>>(lock_key(account_id))
+            .await
+            .caused_by(trc::location!())
+            .map(|until| {
+                until
+                    .map(|until| until.inner)
+                    .filter(|until| *until > now())
+            })
+    }
+
+    /// Counts a failed login and locks the principal once the configured
+    /// threshold is reached. Returns the time until the principal is locked.
+    pub async fn record_failed_login(&self, account_id: u32) -> trc::Result<Option<u64>> {
+        let config = &self.core.account_lockout;
+        let Some(threshold) = config.threshold else {
+            return Ok(None);
+        };
+
+        let failures = self
+            .core
+            .storage
+            .lookup
+            .counter_incr(
+                failures_key(account_id),
+                1,
+                Some(config.duration.as_secs()),
+                true,
+            )
+            .await
+            .caused_by(trc::location!())?;
+        if (failures as u64) < threshold || self.is_lockout_exempt(account_id).await? {
+            return Ok(None);
+        }
+
+        let locked_until = now() + config.duration.as_secs();
+        self.core
+            .storage
+            .lookup
+            .key_set(
+                lock_key(account_id),
+                Bincode::new(locked_until).serialize(),
+                Some(config.duration.as_secs()),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        self.core
+            .storage
+            .lookup
+            .counter_delete(failures_key(account_id))
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Security(trc::SecurityEvent::AccountLockout),
+            AccountId = account_id,
+            Total = failures,
+            Expires = trc::Value::Timestamp(locked_until),
+        );
+
+        Ok(Some(locked_until))
+    }
+
+    /// Resets the failed login counter after a successful login.
+    pub async fn reset_failed_logins(&self, account_id: u32) -> trc::Result<()> {
+        let lookup = &self.core.storage.lookup;
+        if lookup
+            .counter_get(failures_key(account_id))
+            .await
+            .caused_by(trc::location!())?
+            > 0
+        {
+            lookup
+                .counter_delete(failures_key(account_id))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn account_lockout_status(&self, account_id: u32) -> trc::Result<LockoutStatus> {
+        Ok(LockoutStatus {
+            failures: self
+                .core
+                .storage
+                .lookup
+                .counter_get(failures_key(account_id))
+                .await
+                .caused_by(trc::location!())?
+                .max(0) as u64,
+            locked_until: self.account_locked_until(account_id).await?,
+        })
+    }
+
+    /// Unlocks the principal and resets its failed login counter.
+    pub async fn clear_account_lockout(&self, account_id: u32) -> trc::Result<()> {
+        let lookup = &self.core.storage.lookup;
+        lookup
+            .key_delete(lock_key(account_id))
+            .await
+            .caused_by(trc::location!())?;
+        lookup
+            .counter_delete(failures_key(account_id))
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn is_lockout_exempt(&self, account_id: u32) -> trc::Result<bool> {
+        let exempt_roles = &self.core.account_lockout.exempt_roles;
+        if exempt_roles.is_empty() {
+            return Ok(false);
+        }
+
+        let store = &self.core.storage.data;
+        let Some(mut principal) = store
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(false);
+        };
+        store
+            .map_field_ids(&mut principal, &[PrincipalField::Roles])
+            .await
+            .caused_by(trc::location!())?;
+
+        let is_exempt = principal
+            .iter_str(PrincipalField::Roles)
+            .any(|role| exempt_roles.contains(&role.to_lowercase()));
+        Ok(is_exempt)
+    }
+}
+
+fn failures_key(account_id: u32) -> Vec<u8> {
+    format!("lockout:fail:{account_id}").into_bytes()
+}
+
+fn lock_key(account_id: u32) -> Vec<u8> {
+    format!("lockout:lock:{account_id}").into_bytes()
+}
@@ -7,19 +7,31 @@
 use std::{net::IpAddr, sync::Arc, time::Instant};
 
 use directory::{
-    core::secret::verify_secret_hash, Directory, Permission, Permissions, Principal, QueryBy,
+    backend::internal::{manage::ManageDirectory, AppScope},
+    core::secret::verify_secret_hash,
+    Directory, Permission, Permissions, Principal, QueryBy,
 };
 use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use oauth::GrantType;
+use store::write::now;
 use utils::map::{bitmap::Bitmap, ttl_dashmap::TtlMap, vec_map::VecMap};
 
 use crate::Server;
 
 pub mod access_token;
+pub mod api_key;
+pub mod certificate;
+pub mod invalidate;
+pub mod lockout;
+pub mod notify;
 pub mod oauth;
+pub mod reputation;
 pub mod roles;
 pub mod sasl;
+pub mod sessions;
+
+const LAST_LOGIN_INTERVAL: u64 = 3600;
 
 #[derive(Debug, Clone, Default)]
 pub struct AccessToken {
@@ -30,8 +42,12 @@ pub struct AccessToken {
     pub description: Option<String>,
     pub emails: Vec<String>,
     pub quota: u64,
+    pub message_quota: u64,
     pub permissions: Permissions,
     pub tenant: Option<TenantInfo>,
+    pub on_hold: bool,
+    pub credentials_epoch: u64,
+    pub api_key_id: Option<u64>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
@@ -44,7 +60,9 @@ pub struct TenantInfo {
 pub struct ResourceToken {
     pub account_id: u32,
     pub quota: u64,
+    pub message_quota: u64,
     pub tenant: Option<TenantInfo>,
+    pub on_hold: bool,
 }
 
 pub struct AuthRequest<'x> {
@@ -53,6 +71,7 @@ pub struct AuthRequest<'x> {
     remote_ip: IpAddr,
     return_member_of: bool,
     directory: Option<&'x Directory>,
+    scope: Option<AppScope>,
 }
 
 impl Server {
@@ -61,7 +80,7 @@ impl Server {
         let directory = req.directory.unwrap_or(&self.core.storage.directory);
 
         // Validate credentials
-        match &req.credentials {
+        let result = match &req.credentials {
             Credentials::OAuthBearer { token } if !directory.has_bearer_token_support() => {
                 match self
                     .validate_access_token(GrantType::AccessToken.into(), token)
@@ -72,21 +91,7 @@ impl Server {
                 }
             }
             _ => match self.authenticate_credentials(req, directory).await {
-                Ok(principal) => {
-                    if let Some(access_token) =
-                        self.inner.data.access_tokens.get_with_ttl(&principal.id())
-                    {
-                        Ok(access_token)
-                    } else {
-                        self.build_access_token(principal)
-                            .await
-                            .map(|access_token| {
-                                let access_token = Arc::new(access_token);
-                                self.cache_access_token(access_token.clone());
-                                access_token
-                            })
-                    }
-                }
+                Ok(principal) => self.principal_access_token(principal).await,
                 Err(err) => Err(err),
             },
         }
@@ -94,7 +99,50 @@ impl Server {
             token
                 .assert_has_permission(Permission::Authenticate)
                 .map(|_| token)
-        })
+        });
+
+        if let Ok(access_token) = &result {
+            self.record_login(access_token.primary_id()).await;
+        }
+
+        result
+    }
+
+    async fn principal_access_token(&self, principal: Principal) -> trc::Result<Arc<AccessToken>> {
+        if let Some(access_token) = self.inner.data.access_tokens.get_with_ttl(&principal.id()) {
+            Ok(access_token)
+        } else {
+            self.build_access_token(principal)
+                .await
+                .map(|access_token| {
+                    let access_token = Arc::new(access_token);
+                    self.cache_access_token(access_token.clone());
+                    access_token
+                })
+        }
+    }
+
+    // Login times are written at most once per interval to avoid write amplification
+    async fn record_login(&self, account_id: u32) {
+        if account_id == u32::MAX {
+            return;
+        }
+
+        let now = now();
+        {
+            let mut last_logins = self.inner.data.last_logins.lock();
+            let last_login = last_logins.entry(account_id).or_default();
+            if now < *last_login + LAST_LOGIN_INTERVAL {
+                return;
+            }
+            *last_login = now;
+        }
+
+        if let Err(err) = self.core.storage.data.set_last_login(account_id, now).await {
+            trc::error!(err
+                .details("Failed to record last login")
+                .account_id(account_id));
+        }
     }
 
     async fn authenticate_credentials(
@@ -102,12 +150,57 @@ impl Server {
         req: &AuthRequest<'_>,
         directory: &Directory,
     ) -> trc::Result<Principal> {
+        // Reject logins to locked accounts before verifying the credentials
+        let lockout_id = match req
+            .credentials
+            .login()
+            .filter(|_| self.core.account_lockout.is_enabled())
+        {
+            Some(login) => self.lockout_principal_id(login).await?,
+            None => None,
+        };
+        if let Some(account_id) = lockout_id {
+            if let Some(locked_until) = self.account_locked_until(account_id).await? {
+                return Err(trc::AuthEvent::AccountLocked
+                    .ctx(trc::Key::RemoteIp, req.remote_ip)
+                    .ctx_opt(
+                        trc::Key::AccountName,
+                        req.credentials.login().map(|s| s.to_string()),
+                    )
+                    .ctx(trc::Key::AccountId, account_id)
+                    .ctx(trc::Key::Expires, trc::Value::Timestamp(locked_until)));
+            }
+        }
+
         // First try to authenticate the user against the default directory
         let result = match directory
             .query(QueryBy::Credentials(&req.credentials), req.return_member_of)
             .await
         {
             Ok(Some(principal)) => {
+                if !principal.is_enabled() {
+                    return Err(account_disabled(&principal, req));
+                }
+
+                if let (
+                    Some(scope),
+                    Credentials::Plain { secret, .. } | Credentials::XOauth2 { secret, .. },
+                ) = (req.scope, &req.credentials)
+                {
+                    if !principal.verify_scope(secret, scope).await? {
+                        return Err(trc::AuthEvent::Failed
+                            .ctx(trc::Key::RemoteIp, req.remote_ip)
+                            .ctx(trc::Key::AccountName, principal.name().to_string())
+                            .ctx(trc::Key::AccountId, principal.id())
+                            .details("App password not valid for this protocol")
+                            .ctx(trc::Key::Type, scope.as_str()));
+                    }
+                }
+
+                if self.core.account_lockout.is_enabled() {
+                    self.reset_failed_logins(principal.id()).await?;
+                }
+
                 trc::event!(
                     Auth(trc::AuthEvent::Success),
                     AccountName = principal.name().to_string(),
@@ -157,6 +250,10 @@ impl Server {
                         .query(QueryBy::Name(username), req.return_member_of)
                         .await?
                     {
+                        if !principal.is_enabled() {
+                            return Err(account_disabled(&principal, req));
+                        }
+
                         trc::event!(
                             Auth(trc::AuthEvent::Success),
                             AccountName = username.to_string(),
@@ -172,9 +269,12 @@ impl Server {
             _ => {}
         }
 
-        if let Err(err) = result {
-            Err(err)
-        } else if self.has_auth_fail2ban() {
+        result?;
+        if let Some(account_id) = lockout_id {
+            self.record_failed_login(account_id).await?;
+        }
+
+        if self.has_auth_fail2ban() {
             let login = req.credentials.login();
             if self.is_auth_fail2banned(req.remote_ip, login).await? {
                 Err(trc::SecurityEvent::AuthenticationBan
@@ -205,6 +305,13 @@ impl Server {
     }
 }
 
+fn account_disabled(principal: &Principal, req: &AuthRequest<'_>) -> trc::Error {
+    trc::AuthEvent::AccountDisabled
+        .ctx(trc::Key::RemoteIp, req.remote_ip)
+        .ctx(trc::Key::AccountName, principal.name().to_string())
+        .ctx(trc::Key::AccountId, principal.id())
+}
+
 impl<'x> AuthRequest<'x> {
     pub fn from_credentials(
         credentials: Credentials<String>,
@@ -217,6 +324,7 @@ impl<'x> AuthRequest<'x> {
             remote_ip,
             return_member_of: true,
             directory: None,
+            scope: None,
         }
     }
 
@@ -245,6 +353,12 @@ impl<'x> AuthRequest<'x> {
         self.directory = Some(directory);
         self
     }
+
+    /// Rejects app passwords that are not valid for the protocol.
+    pub fn with_scope(mut self, scope: AppScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
 }
 
 pub(crate) trait CredentialsUsername {
@@ -0,0 +1,87 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Instant;
+
+use ahash::AHashMap;
+use store::write::now;
+use trc::ManageEvent;
+
+use crate::{config::notify::NotifyEvent, Server};
+
+#[derive(Debug, Clone)]
+pub struct DirectoryAlert {
+    pub event: NotifyEvent,
+    pub target: String,
+    pub details: String,
+    pub performed_by: String,
+    pub created: u64,
+}
+
+/// Alerts waiting for the next batch along with the last time each
+/// (event, target) pair was reported.
+#[derive(Debug, Default)]
+pub struct DirectoryAlerts {
+    pending: Vec<DirectoryAlert>,
+    last_sent: AHashMap<(NotifyEvent, String), Instant>,
+}
+
+impl Server {
+    pub fn queue_directory_alert(
+        &self,
+        event: NotifyEvent,
+        target: &str,
+        details: impl Into<String>,
+        performed_by: &str,
+    ) {
+        let config = &self.core.directory_notify;
+        if !config.is_enabled() || !config.is_watched(event) {
+            return;
+        }
+
+        let target = target.to_lowercase();
+        let details = details.into();
+        {
+            // Repeated alerts for the same target are suppressed for a while
+            let mut alerts = self.inner.data.directory_alerts.lock();
+            let key = (event, target.clone());
+            if alerts
+                .last_sent
+                .get(&key)
+                .is_some_and(|last_sent| last_sent.elapsed() < config.suppress)
+            {
+                return;
+            }
+            alerts.last_sent.insert(key, Instant::now());
+            alerts.pending.push(DirectoryAlert {
+                event,
+                target: target.clone(),
+                details: details.clone(),
+                performed_by: performed_by.to_string(),
+                created: now(),
+            });
+        }
+
+        if config.webhook {
+            trc::event!(
+                Manage(ManageEvent::DirectoryAlert),
+                Type = event.as_str(),
+                Id = target,
+                Details = details,
+                AccountName = performed_by.to_string(),
+            );
+        }
+    }
+
+    pub fn take_directory_alerts(&self) -> Vec<DirectoryAlert> {
+        let suppress = self.core.directory_notify.suppress;
+        let mut alerts = self.inner.data.directory_alerts.lock();
+        alerts
+            .last_sent
+            .retain(|_, last_sent| last_sent.elapsed() < suppress);
+        std::mem::take(&mut alerts.pending)
+    }
+}
@@ -54,14 +54,18 @@ impl Server {
         } else {
             String::new()
         };
+        let credentials_epoch = self
+            .credentials_epoch(account_id)
+            .await
+            .caused_by(trc::location!())?;
 
         let key = &self.core.oauth.oauth_key;
-        let context = format!(
-            "{} {} {} {}",
-            grant_type.as_str(),
+        let context = token_context(
+            grant_type,
             client_id,
             account_id,
-            password_hash
+            &password_hash,
+            credentials_epoch,
         );
 
         // Set expiration time
@@ -164,14 +168,20 @@ impl Server {
             String::new()
         };
 
+        // Tokens issued before the credentials were revoked no longer decrypt
+        let credentials_epoch = self
+            .credentials_epoch(account_id)
+            .await
+            .map_err(|err| trc::AuthEvent::Error.into_err().ctx(trc::Key::Details, err))?;
+
         // Build context
         let key = self.core.oauth.oauth_key.clone();
-        let context = format!(
-            "{} {} {} {}",
-            grant_type.as_str(),
-            client_id,
+        let context = token_context(
+            grant_type,
+            &client_id,
             account_id,
-            password_hash
+            &password_hash,
+            credentials_epoch,
         );
 
         // Calculate nonce
@@ -215,6 +225,12 @@ impl Server {
         })
     }
 
+    pub async fn credentials_epoch(&self, account_id: u32) -> trc::Result<u64> {
+        self.get_cached_access_token(account_id)
+            .await
+            .map(|access_token| access_token.credentials_epoch)
+    }
+
     pub async fn password_hash(&self, account_id: u32) -> trc::Result<String> {
         if account_id != u32::MAX {
             self.core
@@ -248,3 +264,28 @@ impl Server {
         }
     }
 }
+
+fn token_context(
+    grant_type: GrantType,
+    client_id: &str,
+    account_id: u32,
+    password_hash: &str,
+    credentials_epoch: u64,
+) -> String {
+    let mut context = format!(
+        "{} {} {} {}",
+        grant_type.as_str(),
+        client_id,
+        account_id,
+        password_hash
+    );
+
+    // Principals that never had their credentials revoked keep the original
+    // context, so tokens issued before epochs were introduced remain valid
+    if credentials_epoch > 0 {
+        context.push(' ');
+        context.push_str(&credentials_epoch.to_string());
+    }
+
+    context
+}
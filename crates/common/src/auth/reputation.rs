@@ -0,0 +1,369 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use directory::Permission;
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{now, BatchBuilder, TaskClass, ValueClass},
+    IterateParams, ValueKey,
+};
+use trc::AddContext;
+
+use crate::Server;
+
+const REPUTATION_STATE: &[u8] = b"reputation.state.";
+const HOUR: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReputationLevel {
+    #[default]
+    Normal,
+    Throttle,
+    Reauthenticate,
+    Suspend,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReputationSignals {
+    pub recipients: u64,
+    pub recipients_per_hour: u64,
+    pub bounces: u64,
+    pub complaints: u64,
+    pub bounce_ratio: f64,
+    pub score: u64,
+}
+
+/// Restrictions applied automatically to an account, they are kept until
+/// an administrator clears them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReputationState {
+    pub account_id: u32,
+    pub level: ReputationLevel,
+    pub since: u64,
+    pub signals: ReputationSignals,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Signal {
+    Recipients,
+    HourlyRecipients,
+    Bounces,
+    Complaints,
+}
+
+impl Server {
+    pub async fn record_submission(&self, account_id: u32, recipients: u64) -> trc::Result<()> {
+        if !self.core.sender_reputation.enable || recipients == 0 {
+            return Ok(());
+        }
+
+        for signal in [Signal::Recipients, Signal::HourlyRecipients] {
+            self.reputation_counter_incr(account_id, signal, recipients)
+                .await?;
+        }
+        self.evaluate_reputation(account_id).await
+    }
+
+    pub async fn record_bounces(&self, sender: &str, bounces: u64) -> trc::Result<()> {
+        if !self.core.sender_reputation.enable || bounces == 0 {
+            return Ok(());
+        }
+
+        if let Some(account_id) = self.reputation_account_id(sender).await? {
+            self.reputation_counter_incr(account_id, Signal::Bounces, bounces)
+                .await?;
+            self.evaluate_reputation(account_id).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn record_complaint(&self, sender: &str) -> trc::Result<()> {
+        if !self.core.sender_reputation.enable {
+            return Ok(());
+        }
+
+        if let Some(account_id) = self.reputation_account_id(sender).await? {
+            self.reputation_counter_incr(account_id, Signal::Complaints, 1)
+                .await?;
+            self.evaluate_reputation(account_id).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn sender_reputation(&self, account_id: u32) -> trc::Result<Option<ReputationState>> {
+        self.core
+            .storage
+            .data
+            .get_value::<String>(ValueKey::from(ValueClass::Task(TaskClass::State(
+                state_key(account_id),
+            ))))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    pub async fn list_sender_reputation(&self) -> trc::Result<Vec<ReputationState>> {
+        let from_key =
+            ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(state_key(0))));
+        let to_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            state_key(u32::MAX),
+        )));
+
+        let mut states = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    if let Ok(state) = serde_json::from_slice::<ReputationState>(value) {
+                        states.push(state);
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(states)
+    }
+
+    pub async fn reputation_signals(&self, account_id: u32) -> trc::Result<ReputationSignals> {
+        let config = &self.core.sender_reputation;
+        let mut signals = ReputationSignals {
+            recipients: self
+                .reputation_counter_get(account_id, Signal::Recipients)
+                .await?,
+            recipients_per_hour: self
+                .reputation_counter_get(account_id, Signal::HourlyRecipients)
+                .await?,
+            bounces: self
+                .reputation_counter_get(account_id, Signal::Bounces)
+                .await?,
+            complaints: self
+                .reputation_counter_get(account_id, Signal::Complaints)
+                .await?,
+            ..Default::default()
+        };
+        if signals.recipients > 0 {
+            signals.bounce_ratio = signals.bounces as f64 / signals.recipients as f64;
+        }
+
+        // The score is the signal closest to its limit, 100 means a limit was reached
+        let mut score: f64 = 0.0;
+        if config.max_recipients_per_hour > 0 {
+            score = score
+                .max(signals.recipients_per_hour as f64 / config.max_recipients_per_hour as f64);
+        }
+        if config.max_complaints > 0 {
+            score = score.max(signals.complaints as f64 / config.max_complaints as f64);
+        }
+        if config.max_bounce_ratio > 0.0 && signals.recipients >= config.min_recipients {
+            score = score.max(signals.bounce_ratio / config.max_bounce_ratio);
+        }
+        signals.score = (score * 100.0).round() as u64;
+
+        Ok(signals)
+    }
+
+    pub async fn clear_sender_reputation(&self, account_id: u32) -> trc::Result<bool> {
+        let was_restricted = self.sender_reputation(account_id).await?.is_some();
+
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Task(TaskClass::State(state_key(account_id))));
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        for signal in [
+            Signal::Recipients,
+            Signal::HourlyRecipients,
+            Signal::Bounces,
+            Signal::Complaints,
+        ] {
+            let bucket = self.reputation_bucket(signal);
+            for bucket in [bucket.saturating_sub(1), bucket] {
+                self.core
+                    .storage
+                    .lookup
+                    .counter_delete(counter_key(account_id, signal, bucket))
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        Ok(was_restricted)
+    }
+
+    async fn evaluate_reputation(&self, account_id: u32) -> trc::Result<()> {
+        let config = &self.core.sender_reputation;
+        let signals = self.reputation_signals(account_id).await?;
+        let Some(level) = [
+            (ReputationLevel::Suspend, config.suspend_score),
+            (ReputationLevel::Reauthenticate, config.reauth_score),
+            (ReputationLevel::Throttle, config.throttle_score),
+        ]
+        .into_iter()
+        .find(|(_, threshold)| *threshold > 0 && signals.score >= *threshold)
+        .map(|(level, _)| level) else {
+            return Ok(());
+        };
+
+        // Restrictions only escalate, lowering them requires a review
+        let current = self
+            .sender_reputation(account_id)
+            .await?
+            .map(|state| state.level)
+            .unwrap_or_default();
+        if current >= level {
+            return Ok(());
+        }
+
+        // Exempt principals are never restricted automatically
+        let access_token = self.get_cached_access_token(account_id).await?;
+        if access_token.has_permission(Permission::SenderReputationExempt) {
+            return Ok(());
+        }
+
+        let state = ReputationState {
+            account_id,
+            level,
+            since: now(),
+            signals,
+        };
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(state_key(account_id))),
+            serde_json::to_vec(&state).unwrap_or_default(),
+        );
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Security(trc::SecurityEvent::SenderReputationAction),
+            AccountId = account_id,
+            AccountName = access_token.name.clone(),
+            Details = format!("{level:?}"),
+            Total = signals.score,
+        );
+
+        // Cached credentials are dropped so the account has to log in again
+        if level >= ReputationLevel::Reauthenticate && current < ReputationLevel::Reauthenticate {
+            self.inner
+                .data
+                .http_auth_cache
+                .retain(|_, id| id.item != account_id);
+            self.inner.data.access_tokens.remove(&account_id);
+            self.disconnect_sessions(account_id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn reputation_account_id(&self, sender: &str) -> trc::Result<Option<u32>> {
+        if sender.is_empty() {
+            return Ok(None);
+        }
+
+        self.core
+            .storage
+            .directory
+            .email_to_id(sender)
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn reputation_counter_incr(
+        &self,
+        account_id: u32,
+        signal: Signal,
+        value: u64,
+    ) -> trc::Result<()> {
+        self.core
+            .storage
+            .lookup
+            .counter_incr(
+                counter_key(account_id, signal, self.reputation_bucket(signal)),
+                value as i64,
+                Some(self.reputation_period(signal).as_secs() * 2),
+                false,
+            )
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn reputation_counter_get(&self, account_id: u32, signal: Signal) -> trc::Result<u64> {
+        // Approximates a sliding window by weighting the previous bucket by
+        // the part of it that still overlaps the window
+        let period = self.reputation_period(signal).as_secs().max(1);
+        let bucket = self.reputation_bucket(signal);
+        let current = self
+            .core
+            .storage
+            .lookup
+            .counter_get(counter_key(account_id, signal, bucket))
+            .await
+            .caused_by(trc::location!())?
+            .max(0) as f64;
+        let previous = self
+            .core
+            .storage
+            .lookup
+            .counter_get(counter_key(account_id, signal, bucket.saturating_sub(1)))
+            .await
+            .caused_by(trc::location!())?
+            .max(0) as f64;
+        let overlap = 1.0 - (now() % period) as f64 / period as f64;
+
+        Ok((current + previous * overlap).round() as u64)
+    }
+
+    fn reputation_period(&self, signal: Signal) -> Duration {
+        match signal {
+            Signal::HourlyRecipients => HOUR,
+            Signal::Recipients | Signal::Bounces | Signal::Complaints => {
+                self.core.sender_reputation.window
+            }
+        }
+    }
+
+    fn reputation_bucket(&self, signal: Signal) -> u64 {
+        now() / self.reputation_period(signal).as_secs().max(1)
+    }
+}
+
+fn state_key(account_id: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(REPUTATION_STATE.len() + std::mem::size_of::<u32>());
+    key.extend_from_slice(REPUTATION_STATE);
+    key.extend_from_slice(&account_id.to_be_bytes());
+    key
+}
+
+fn counter_key(account_id: u32, signal: Signal, bucket: u64) -> Vec<u8> {
+    let signal = match signal {
+        Signal::Recipients => "r",
+        Signal::HourlyRecipients => "h",
+        Signal::Bounces => "b",
+        Signal::Complaints => "c",
+    };
+    format!("srep:{signal}:{account_id}:{bucket}").into_bytes()
+}
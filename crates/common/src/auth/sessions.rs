@@ -0,0 +1,373 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use directory::backend::internal::manage::ManageDirectory;
+use store::{
+    write::{now, Bincode},
+    Serialize,
+};
+use tokio::sync::Notify;
+use trc::AddContext;
+
+use crate::{config::sessions::SessionProtocol, Server};
+
+use super::AccessToken;
+
+/// A session registered on this node for an authenticated principal.
+#[derive(Debug, Clone)]
+pub struct ActiveSession {
+    pub session_id: u64,
+    pub protocol: SessionProtocol,
+    pub remote_ip: IpAddr,
+    pub created: u64,
+    counted_until: u64,
+    disconnect: Arc<Notify>,
+}
+
+/// Removes the session from the local registry and the cluster-wide
+/// counters when the session ends.
+pub struct SessionGuard {
+    server: Server,
+    account_id: u32,
+    session_id: u64,
+    disconnect: Arc<Notify>,
+    credentials_epoch: u64,
+    started: Instant,
+}
+
+impl Server {
+    pub async fn register_session(
+        &self,
+        access_token: &AccessToken,
+        protocol: SessionProtocol,
+        session_id: u64,
+        remote_ip: IpAddr,
+    ) -> trc::Result<SessionGuard> {
+        let account_id = access_token.primary_id();
+        let bucket = self.session_bucket();
+        let disconnect = Arc::new(Notify::new());
+
+        if self.core.session_limits.is_enabled() {
+            let limit = self
+                .session_limit(
+                    &access_token.name,
+                    access_token.tenant.map(|t| t.id),
+                    protocol,
+                )
+                .await?;
+
+            // Sessions are counted in the current and next window, windows
+            // expire unless a node refreshes them so sessions of failed nodes
+            // are not counted for longer than two heartbeats
+            let sessions = self
+                .session_counter_incr(account_id, protocol, bucket, 1, true)
+                .await?;
+            if limit.is_some_and(|limit| sessions > limit as i64) {
+                self.session_counter_incr(account_id, protocol, bucket, -1, false)
+                    .await?;
+
+                return Err(trc::LimitEvent::ConcurrentConnection
+                    .into_err()
+                    .details(format!(
+                        "Too many concurrent {} sessions for this account (limit {}).",
+                        protocol.as_str().to_uppercase(),
+                        limit.unwrap_or_default()
+                    ))
+                    .ctx(trc::Key::Limit, limit)
+                    .ctx(trc::Key::AccountId, account_id));
+            }
+            self.session_counter_incr(account_id, protocol, bucket + 1, 1, false)
+                .await?;
+        }
+
+        self.inner
+            .data
+            .active_sessions
+            .lock()
+            .entry(account_id)
+            .or_default()
+            .push(ActiveSession {
+                session_id,
+                protocol,
+                remote_ip,
+                created: now(),
+                counted_until: bucket + 1,
+                disconnect: disconnect.clone(),
+            });
+
+        Ok(SessionGuard {
+            server: self.clone(),
+            account_id,
+            session_id,
+            disconnect,
+            credentials_epoch: access_token.credentials_epoch,
+            started: Instant::now(),
+        })
+    }
+
+    /// Returns the session limit of a principal, account limits take
+    /// precedence over tenant limits which take precedence over defaults.
+    pub async fn session_limit(
+        &self,
+        name: &str,
+        tenant_id: Option<u32>,
+        protocol: SessionProtocol,
+    ) -> trc::Result<Option<u64>> {
+        let limits = &self.core.session_limits;
+        if let Some(limit) = limits.accounts.get(&(name.to_lowercase(), protocol)) {
+            return Ok(Some(*limit));
+        }
+
+        if let Some(tenant_id) = tenant_id.filter(|_| !limits.tenants.is_empty()) {
+            if let Some(limit) = self
+                .core
+                .storage
+                .data
+                .get_principal(tenant_id)
+                .await
+                .caused_by(trc::location!())?
+                .and_then(|tenant| {
+                    limits
+                        .tenants
+                        .get(&(tenant.name().to_lowercase(), protocol))
+                })
+            {
+                return Ok(Some(*limit));
+            }
+        }
+
+        Ok(limits.default.get(&protocol).copied())
+    }
+
+    /// Returns the number of sessions open across the cluster.
+    pub async fn session_count(
+        &self,
+        account_id: u32,
+        protocol: SessionProtocol,
+    ) -> trc::Result<i64> {
+        if self.core.session_limits.is_enabled() {
+            self.core
+                .storage
+                .lookup
+                .counter_get(session_key(account_id, protocol, self.session_bucket()))
+                .await
+                .map(|count| count.max(0))
+        } else {
+            Ok(self
+                .local_sessions(account_id)
+                .iter()
+                .filter(|session| session.protocol == protocol)
+                .count() as i64)
+        }
+    }
+
+    pub fn local_sessions(&self, account_id: u32) -> Vec<ActiveSession> {
+        self.inner
+            .data
+            .active_sessions
+            .lock()
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Disconnects the sessions open on this node and asks other nodes to do
+    /// the same on their next heartbeat.
+    pub async fn disconnect_sessions(&self, account_id: u32) -> trc::Result<usize> {
+        let disconnected = self.disconnect_local_sessions(account_id, u64::MAX);
+
+        if self.core.session_limits.is_enabled() {
+            self.core
+                .storage
+                .lookup
+                .key_set(
+                    disconnect_key(account_id),
+                    Bincode::new(now()).serialize(),
+                    Some(self.core.session_limits.heartbeat.as_secs() * 2),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(disconnected)
+    }
+
+    /// Refreshes the counters of the sessions open on this node, must be
+    /// called at least once per heartbeat interval.
+    pub async fn session_heartbeat(&self) -> trc::Result<()> {
+        if !self.core.session_limits.is_enabled() {
+            return Ok(());
+        }
+
+        let bucket = self.session_bucket();
+        let mut updates = Vec::new();
+        let account_ids = {
+            let mut sessions = self.inner.data.active_sessions.lock();
+            for (account_id, sessions) in sessions.iter_mut() {
+                for session in sessions {
+                    for pending in (session.counted_until + 1).max(bucket)..=bucket + 1 {
+                        updates.push((*account_id, session.protocol, pending));
+                    }
+                    session.counted_until = bucket + 1;
+                }
+            }
+            sessions.keys().copied().collect::<Vec<_>>()
+        };
+
+        for (account_id, protocol, bucket) in updates {
+            self.session_counter_incr(account_id, protocol, bucket, 1, false)
+                .await?;
+        }
+
+        // Apply disconnect requests issued on other nodes
+        for account_id in account_ids {
+            if let Some(requested) = self
+                .core
+                .storage
+                .lookup
+                .key_get::<Bincode<u64>>(disconnect_key(account_id))
+                .await
+                .caused_by(trc::location!())?
+            {
+                self.disconnect_local_sessions(account_id, requested.inner);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn disconnect_local_sessions(&self, account_id: u32, created_before: u64) -> usize {
+        let sessions = self.inner.data.active_sessions.lock();
+        let mut disconnected = 0;
+        for session in sessions.get(&account_id).into_iter().flatten() {
+            if session.created <= created_before {
+                session.disconnect.notify_one();
+                disconnected += 1;
+            }
+        }
+
+        disconnected
+    }
+
+    async fn session_counter_incr(
+        &self,
+        account_id: u32,
+        protocol: SessionProtocol,
+        bucket: u64,
+        value: i64,
+        return_value: bool,
+    ) -> trc::Result<i64> {
+        self.core
+            .storage
+            .lookup
+            .counter_incr(
+                session_key(account_id, protocol, bucket),
+                value,
+                Some(self.core.session_limits.heartbeat.as_secs() * 2),
+                return_value,
+            )
+            .await
+            .caused_by(trc::location!())
+    }
+
+    fn session_bucket(&self) -> u64 {
+        now() / self.core.session_limits.heartbeat.as_secs().max(1)
+    }
+}
+
+impl SessionGuard {
+    /// Resolves when the session was disconnected by an administrator or
+    /// the credentials of the principal were revoked after it was opened.
+    pub fn disconnected(&self) -> impl Future<Output = ()> + Send + 'static {
+        let disconnect = self.disconnect.clone();
+        let server = self.server.clone();
+        let account_id = self.account_id;
+        let credentials_epoch = self.credentials_epoch;
+        let started = self.started;
+
+        async move {
+            let revoked = async {
+                let interval = server
+                    .core
+                    .session_limits
+                    .credentials_check
+                    .max(Duration::from_millis(100));
+                loop {
+                    // Checks are aligned to the start of the session, so they
+                    // are not postponed when the future is created again
+                    let elapsed = started.elapsed().as_nanos() % interval.as_nanos();
+                    tokio::time::sleep(interval - Duration::from_nanos(elapsed as u64)).await;
+
+                    match server.get_cached_access_token(account_id).await {
+                        Ok(access_token) if access_token.credentials_epoch != credentials_epoch => {
+                            return;
+                        }
+                        Ok(_) => {}
+                        Err(err) => {
+                            trc::error!(err
+                                .account_id(account_id)
+                                .details("Failed to verify session credentials"));
+                        }
+                    }
+                }
+            };
+
+            tokio::select! {
+                _ = disconnect.notified() => {}
+                _ = revoked => {}
+            }
+        }
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        let session = {
+            let mut sessions = self.server.inner.data.active_sessions.lock();
+            let Some(account_sessions) = sessions.get_mut(&self.account_id) else {
+                return;
+            };
+            let session = account_sessions
+                .iter()
+                .position(|session| session.session_id == self.session_id)
+                .map(|idx| account_sessions.swap_remove(idx));
+            if account_sessions.is_empty() {
+                sessions.remove(&self.account_id);
+            }
+            session
+        };
+
+        if let Some(session) = session.filter(|_| self.server.core.session_limits.is_enabled()) {
+            let server = self.server.clone();
+            let account_id = self.account_id;
+            tokio::spawn(async move {
+                for bucket in server.session_bucket()..=session.counted_until {
+                    if let Err(err) = server
+                        .session_counter_incr(account_id, session.protocol, bucket, -1, false)
+                        .await
+                    {
+                        trc::error!(err.details("Failed to update session counters"));
+                    }
+                }
+            });
+        }
+    }
+}
+
+fn session_key(account_id: u32, protocol: SessionProtocol, bucket: u64) -> Vec<u8> {
+    format!("sess:{}:{account_id}:{bucket}", protocol.as_str()).into_bytes()
+}
+
+fn disconnect_key(account_id: u32) -> Vec<u8> {
+    format!("sess:disconnect:{account_id}").into_bytes()
+}
@@ -0,0 +1,118 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use directory::Type;
+use serde::{Deserialize, Serialize};
+use utils::config::{utils::ParseValue, Config};
+
+#[derive(Clone)]
+pub struct Approval {
+    pub protected: AHashSet<ProtectedOperation>,
+    pub expiry: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProtectedOperation {
+    DeleteIndividual,
+    DeleteGroup,
+    DeleteList,
+    DeleteDomain,
+    DeleteTenant,
+    DeleteRole,
+    PurgeAccount,
+    PurgeData,
+    PurgeBlob,
+    PurgeLookup,
+}
+
+impl Approval {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut protected = AHashSet::new();
+        for (key, value) in config
+            .values("approval.protected")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            match ProtectedOperation::parse_value(&value) {
+                Ok(operation) => {
+                    protected.insert(operation);
+                }
+                Err(err) => config.new_parse_error(key, err),
+            }
+        }
+
+        Approval {
+            protected,
+            expiry: config
+                .property_or_default("approval.expiry", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
+        }
+    }
+
+    pub fn is_protected(&self, operation: ProtectedOperation) -> bool {
+        self.protected.contains(&operation)
+    }
+}
+
+impl Default for Approval {
+    fn default() -> Self {
+        Approval {
+            protected: AHashSet::new(),
+            expiry: Duration::from_secs(86400),
+        }
+    }
+}
+
+impl ProtectedOperation {
+    pub fn delete(typ: Type) -> Option<Self> {
+        match typ {
+            Type::Individual => Some(ProtectedOperation::DeleteIndividual),
+            Type::Group => Some(ProtectedOperation::DeleteGroup),
+            Type::List => Some(ProtectedOperation::DeleteList),
+            Type::Domain => Some(ProtectedOperation::DeleteDomain),
+            Type::Tenant => Some(ProtectedOperation::DeleteTenant),
+            Type::Role => Some(ProtectedOperation::DeleteRole),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtectedOperation::DeleteIndividual => "delete-individual",
+            ProtectedOperation::DeleteGroup => "delete-group",
+            ProtectedOperation::DeleteList => "delete-list",
+            ProtectedOperation::DeleteDomain => "delete-domain",
+            ProtectedOperation::DeleteTenant => "delete-tenant",
+            ProtectedOperation::DeleteRole => "delete-role",
+            ProtectedOperation::PurgeAccount => "purge-account",
+            ProtectedOperation::PurgeData => "purge-data",
+            ProtectedOperation::PurgeBlob => "purge-blob",
+            ProtectedOperation::PurgeLookup => "purge-lookup",
+        }
+    }
+}
+
+impl ParseValue for ProtectedOperation {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "delete-individual" => Ok(ProtectedOperation::DeleteIndividual),
+            "delete-group" => Ok(ProtectedOperation::DeleteGroup),
+            "delete-list" => Ok(ProtectedOperation::DeleteList),
+            "delete-domain" => Ok(ProtectedOperation::DeleteDomain),
+            "delete-tenant" => Ok(ProtectedOperation::DeleteTenant),
+            "delete-role" => Ok(ProtectedOperation::DeleteRole),
+            "purge-account" => Ok(ProtectedOperation::PurgeAccount),
+            "purge-data" => Ok(ProtectedOperation::PurgeData),
+            "purge-blob" => Ok(ProtectedOperation::PurgeBlob),
+            "purge-lookup" => Ok(ProtectedOperation::PurgeLookup),
+            _ => Err(format!("Invalid protected operation {:?}.", value)),
+        }
+    }
+}
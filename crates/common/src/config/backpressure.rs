@@ -0,0 +1,106 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use utils::config::{utils::ParseValue, Config};
+
+#[derive(Clone)]
+pub struct Backpressure {
+    pub enable: bool,
+    pub interval: Duration,
+    pub retry_after: Duration,
+    pub queue_depth: Option<Threshold<u64>>,
+    pub write_latency: Option<Threshold<Duration>>,
+    pub write_latency_percentile: f64,
+    pub memory: Option<Threshold<u64>>,
+}
+
+/// Load shedding starts once a signal exceeds `high` and stops after every
+/// signal dropped below `low`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Threshold<T> {
+    pub high: T,
+    pub low: T,
+}
+
+impl Backpressure {
+    pub fn parse(config: &mut Config) -> Self {
+        let backpressure = Backpressure {
+            enable: config
+                .property_or_default("server.backpressure.enable", "false")
+                .unwrap_or(false),
+            interval: config
+                .property_or_default("server.backpressure.interval", "10s")
+                .unwrap_or_else(|| Duration::from_secs(10)),
+            retry_after: config
+                .property_or_default("server.backpressure.retry-after", "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+            queue_depth: Threshold::parse(config, "server.backpressure.queue"),
+            write_latency: Threshold::parse(config, "server.backpressure.latency"),
+            write_latency_percentile: config
+                .property_or_default("server.backpressure.latency.percentile", "95")
+                .unwrap_or(95.0),
+            memory: Threshold::parse(config, "server.backpressure.memory"),
+        };
+
+        if backpressure.enable
+            && backpressure.queue_depth.is_none()
+            && backpressure.write_latency.is_none()
+            && backpressure.memory.is_none()
+        {
+            config.new_build_warning(
+                "server.backpressure.enable",
+                "Backpressure is enabled but no thresholds are configured",
+            );
+        }
+
+        backpressure
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enable
+            && (self.queue_depth.is_some() || self.write_latency.is_some() || self.memory.is_some())
+    }
+}
+
+impl<T: ParseValue + PartialOrd + Copy> Threshold<T> {
+    fn parse(config: &mut Config, prefix: &str) -> Option<Self> {
+        let high = config.property::<T>((prefix, "high"))?;
+        let low = config.property::<T>((prefix, "low")).unwrap_or(high);
+        if low <= high {
+            Some(Threshold { high, low })
+        } else {
+            config.new_build_error(
+                (prefix, "low"),
+                "The low threshold cannot be greater than the high threshold",
+            );
+            None
+        }
+    }
+
+    pub fn is_exceeded(&self, value: T) -> bool {
+        value > self.high
+    }
+
+    pub fn is_recovered(&self, value: T) -> bool {
+        value < self.low
+    }
+}
+
+impl Default for Backpressure {
+    fn default() -> Self {
+        Backpressure {
+            enable: false,
+            interval: Duration::from_secs(10),
+            retry_after: Duration::from_secs(30),
+            queue_depth: None,
+            write_latency: None,
+            write_latency_percentile: 95.0,
+            memory: None,
+        }
+    }
+}
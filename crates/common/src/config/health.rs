@@ -0,0 +1,92 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use serde::Serialize;
+use utils::config::{utils::ParseValue, Config};
+
+#[derive(Clone)]
+pub struct HealthConfig {
+    pub cache_ttl: Duration,
+    pub timeout: Duration,
+    pub non_fatal: AHashSet<HealthCheck>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthCheck {
+    Store,
+    Blob,
+    Certificates,
+    Directory,
+}
+
+impl HealthConfig {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut non_fatal = AHashSet::new();
+        for (key, value) in config
+            .values("server.health.non-fatal")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            match HealthCheck::parse_value(&value) {
+                Ok(check) => {
+                    non_fatal.insert(check);
+                }
+                Err(err) => config.new_parse_error(key, err),
+            }
+        }
+
+        HealthConfig {
+            cache_ttl: config
+                .property_or_default("server.health.cache-ttl", "2s")
+                .unwrap_or_else(|| Duration::from_secs(2)),
+            timeout: config
+                .property_or_default("server.health.timeout", "5s")
+                .unwrap_or_else(|| Duration::from_secs(5)),
+            non_fatal,
+        }
+    }
+
+    pub fn is_fatal(&self, check: HealthCheck) -> bool {
+        !self.non_fatal.contains(&check)
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            cache_ttl: Duration::from_secs(2),
+            timeout: Duration::from_secs(5),
+            non_fatal: AHashSet::new(),
+        }
+    }
+}
+
+impl HealthCheck {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthCheck::Store => "store",
+            HealthCheck::Blob => "blob",
+            HealthCheck::Certificates => "certificates",
+            HealthCheck::Directory => "directory",
+        }
+    }
+}
+
+impl ParseValue for HealthCheck {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "store" => Ok(HealthCheck::Store),
+            "blob" => Ok(HealthCheck::Blob),
+            "certificates" => Ok(HealthCheck::Certificates),
+            "directory" => Ok(HealthCheck::Directory),
+            _ => Err(format!("Invalid health check {:?}.", value)),
+        }
+    }
+}
@@ -62,6 +62,8 @@ impl Data {
             .map(Arc::new),
             access_tokens: TtlDashMap::with_capacity(capacity, shard_amount),
             http_auth_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            sieve_list_cache: TtlDashMap::with_capacity(capacity, shard_amount),
+            principal_names: TtlDashMap::with_capacity(capacity, shard_amount),
             blocked_ips: RwLock::new(BlockedIps::parse(config).blocked_ip_addresses),
             blocked_ips_version: 0.into(),
             permissions: Default::default(),
@@ -79,6 +81,10 @@ impl Data {
                 RandomState::default(),
                 shard_amount,
             ),
+            api_key_limiter: DashMap::with_hasher_and_shard_amount(
+                RandomState::default(),
+                shard_amount,
+            ),
             imap_limiter: DashMap::with_capacity_and_hasher_and_shard_amount(
                 capacity,
                 RandomState::default(),
@@ -94,6 +100,16 @@ impl Data {
                 config.property("cache.thread.size").unwrap_or(2048),
             ),
             logos: Default::default(),
+            active_sessions: Default::default(),
+            volume_stats: Default::default(),
+            tls_usage: Default::default(),
+            tls_watch: Default::default(),
+            last_logins: Default::default(),
+            directory_sync: Default::default(),
+            idempotency_locks: Default::default(),
+            readiness: Default::default(),
+            load_shedding: Default::default(),
+            directory_alerts: Default::default(),
             smtp_session_throttle: DashMap::with_capacity_and_hasher_and_shard_amount(
                 capacity,
                 ThrottleKeyHasherBuilder::default(),
@@ -117,6 +133,8 @@ impl Data {
                     .unwrap_or_else(|| Duration::from_secs(3600)),
             ),
             remote_lists: Default::default(),
+            dnsbl_health: Default::default(),
+            rewrite_map: Default::default(),
         }
     }
 }
@@ -128,22 +146,37 @@ impl Default for Data {
             tls_self_signed_cert: Default::default(),
             access_tokens: Default::default(),
             http_auth_cache: Default::default(),
+            sieve_list_cache: Default::default(),
+            principal_names: Default::default(),
             blocked_ips: Default::default(),
             blocked_ips_version: 0.into(),
             permissions: Default::default(),
             permissions_version: 0.into(),
             remote_lists: Default::default(),
+            dnsbl_health: Default::default(),
+            rewrite_map: Default::default(),
             jmap_id_gen: Default::default(),
             queue_id_gen: Default::default(),
             span_id_gen: Default::default(),
             webadmin: Default::default(),
             config_version: Default::default(),
             jmap_limiter: Default::default(),
+            api_key_limiter: Default::default(),
             imap_limiter: Default::default(),
             account_cache: LruCache::with_capacity(2048),
             mailbox_cache: LruCache::with_capacity(2048),
             threads_cache: LruCache::with_capacity(2048),
             logos: Default::default(),
+            active_sessions: Default::default(),
+            volume_stats: Default::default(),
+            tls_usage: Default::default(),
+            tls_watch: Default::default(),
+            last_logins: Default::default(),
+            directory_sync: Default::default(),
+            idempotency_locks: Default::default(),
+            readiness: Default::default(),
+            load_shedding: Default::default(),
+            directory_alerts: Default::default(),
             smtp_session_throttle: Default::default(),
             smtp_queue_throttle: Default::default(),
             smtp_connectors: Default::default(),
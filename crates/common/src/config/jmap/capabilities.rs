@@ -15,6 +15,8 @@ use jmap_proto::{
 };
 use utils::{config::Config, map::vec_map::VecMap};
 
+use crate::config::scripts::EXT_LIST_PERSONAL;
+
 use super::settings::JmapConfig;
 
 impl JmapConfig {
@@ -108,6 +110,17 @@ impl JmapConfig {
             notification_methods.push("mailto".to_string());
         }
 
+        let mut ext_lists = Vec::new();
+        for (_, list) in config.values("sieve.untrusted.ext-lists.lookup") {
+            ext_lists.push(list.to_string());
+        }
+        if config
+            .property_or_default("sieve.untrusted.ext-lists.personal", "true")
+            .unwrap_or(true)
+        {
+            ext_lists.push(EXT_LIST_PERSONAL.to_string());
+        }
+
         let mut capabilities: AHashSet<sieve::compiler::grammar::Capability> =
             AHashSet::from_iter(sieve::compiler::grammar::Capability::all().iter().cloned());
 
@@ -142,7 +155,11 @@ impl JmapConfig {
                 } else {
                     None
                 },
-                ext_lists: None,
+                ext_lists: if !ext_lists.is_empty() {
+                    ext_lists.into()
+                } else {
+                    None
+                },
             }),
         );
 
@@ -174,5 +191,15 @@ impl JmapConfig {
             Capability::Quota,
             Capabilities::Empty(EmptyCapabilities::default()),
         );
+
+        // Add thread rules capabilities
+        self.capabilities.session.append(
+            Capability::ThreadRules,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
+        self.capabilities.account.append(
+            Capability::ThreadRules,
+            Capabilities::Empty(EmptyCapabilities::default()),
+        );
     }
 }
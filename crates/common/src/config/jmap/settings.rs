@@ -4,8 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{str::FromStr, time::Duration};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
+use directory::backend::internal::PrincipalField;
 use jmap_proto::request::capability::BaseCapabilities;
 use mail_parser::HeaderName;
 use nlp::language::Language;
@@ -14,6 +15,7 @@ use utils::config::{cron::SimpleCron, utils::ParseValue, Config, Rate};
 #[derive(Default, Clone)]
 pub struct JmapConfig {
     pub default_language: Language,
+    pub fts_rebuild_concurrency: usize,
     pub query_max_results: usize,
     pub snippet_max_results: usize,
 
@@ -40,11 +42,13 @@ pub struct JmapConfig {
     pub mail_parse_max_items: usize,
     pub mail_max_size: usize,
     pub mail_autoexpunge_after: Option<Duration>,
+    pub mail_hold_count_quota: bool,
 
     pub sieve_max_script_name: usize,
     pub sieve_max_scripts: usize,
 
     pub session_cache_ttl: Duration,
+    pub directory_sync_interval: Duration,
     pub rate_authenticated: Option<Rate>,
     pub rate_authenticate_req: Option<Rate>,
     pub rate_anonymous: Option<Rate>,
@@ -63,11 +67,22 @@ pub struct JmapConfig {
     pub web_socket_heartbeat: Duration,
 
     pub fallback_admin: Option<(String, String)>,
+    pub self_service_fields: Vec<PrincipalField>,
+    pub undo_max_entries: usize,
+    pub member_of_max_depth: u32,
     pub master_user: Option<(String, String)>,
+    pub allow_client_certs: bool,
 
     pub spam_header: Option<(HeaderName<'static>, String)>,
     pub default_folders: Vec<DefaultFolder>,
     pub shared_folder: String,
+    pub provision_welcome: bool,
+    pub provision_sieve: Option<(String, String)>,
+    pub tenant_export_path: PathBuf,
+    pub tenant_export_expiry: Duration,
+    pub collected_recipients_max: usize,
+    pub thread_rules_max: usize,
+    pub thread_rules_expiry: Duration,
 
     pub http_headers: Vec<(hyper::header::HeaderName, hyper::header::HeaderValue)>,
     pub http_use_forwarded: bool,
@@ -87,6 +102,14 @@ pub struct DefaultFolder {
     pub special_use: SpecialUse,
     pub subscribe: bool,
     pub create: bool,
+    pub max_messages: Option<u64>,
+    pub on_limit: FolderLimitAction,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FolderLimitAction {
+    Reject,
+    ExpungeOldest,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -133,6 +156,54 @@ impl JmapConfig {
             .map_err(|e| config.new_parse_error("server.http.headers", e))
             .unwrap_or_default();
 
+        // Parse self-service fields
+        let mut self_service_fields = Vec::new();
+        for (key, value) in config
+            .values("authentication.self-service.fields")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            match PrincipalField::try_parse(&value) {
+                Some(
+                    PrincipalField::Type
+                    | PrincipalField::Quota
+                    | PrincipalField::UsedQuota
+                    | PrincipalField::Tenant
+                    | PrincipalField::Roles
+                    | PrincipalField::EnabledPermissions
+                    | PrincipalField::DisabledPermissions
+                    | PrincipalField::Members
+                    | PrincipalField::MemberOf
+                    | PrincipalField::Hold
+                    | PrincipalField::ExternalId
+                    | PrincipalField::LastLogin
+                    | PrincipalField::CredentialsEpoch,
+                ) => {
+                    config.new_parse_error(
+                        key,
+                        format!("Field {value:?} cannot be managed by users"),
+                    );
+                }
+                Some(field) => {
+                    if !self_service_fields.contains(&field) {
+                        self_service_fields.push(field);
+                    }
+                }
+                None => {
+                    config.new_parse_error(key, format!("Unknown principal field {value:?}"));
+                }
+            }
+        }
+        if self_service_fields.is_empty() {
+            self_service_fields = vec![
+                PrincipalField::Description,
+                PrincipalField::Secrets,
+                PrincipalField::Picture,
+                PrincipalField::Locale,
+                PrincipalField::Timezone,
+            ];
+        }
+
         // Parse default folders
         let mut default_folders = Vec::new();
         let mut shared_folder = "Shared Folders".to_string();
@@ -160,19 +231,25 @@ impl JmapConfig {
                         .value(("jmap.folders", key.as_str(), "name"))
                         .map(|name| name.trim())
                         .filter(|name| !name.is_empty())
+                        .map(|name| name.to_string())
                     {
+                        let aliases = config
+                            .value(("jmap.folders", key.as_str(), "aliases"))
+                            .unwrap_or_default()
+                            .split(',')
+                            .map(|s| s.trim().to_string())
+                            .filter(|s| !s.is_empty())
+                            .collect();
+                        let (max_messages, on_limit) =
+                            parse_folder_limits(config, &key, special_use);
                         default_folders.push(DefaultFolder {
-                            name: name.to_string(),
-                            aliases: config
-                                .value(("jmap.folders", key.as_str(), "aliases"))
-                                .unwrap_or_default()
-                                .split(',')
-                                .map(|s| s.trim().to_string())
-                                .filter(|s| !s.is_empty())
-                                .collect(),
+                            name,
+                            aliases,
                             special_use,
                             subscribe,
                             create,
+                            max_messages,
+                            on_limit,
                         });
                     }
                 }
@@ -189,12 +266,19 @@ impl JmapConfig {
             (SpecialUse::Sent, "Sent Items"),
         ] {
             if !default_folders.iter().any(|f| f.special_use == special_use) {
+                let (max_messages, on_limit) = parse_folder_limits(
+                    config,
+                    special_use.as_role().unwrap_or_default(),
+                    special_use,
+                );
                 default_folders.push(DefaultFolder {
                     name: name.to_string(),
                     aliases: Vec::new(),
                     special_use,
                     subscribe: true,
                     create: true,
+                    max_messages,
+                    on_limit,
                 });
             }
         }
@@ -239,6 +323,10 @@ impl JmapConfig {
                     .unwrap_or("en"),
             )
             .unwrap_or(Language::English),
+            fts_rebuild_concurrency: config
+                .property_or_default::<usize>("storage.full-text.rebuild.concurrency", "4")
+                .unwrap_or(4)
+                .max(1),
             query_max_results: config
                 .property("jmap.protocol.query.max-results")
                 .unwrap_or(5000),
@@ -294,6 +382,9 @@ impl JmapConfig {
             mail_autoexpunge_after: config
                 .property_or_default::<Option<Duration>>("jmap.email.auto-expunge", "30d")
                 .unwrap_or_default(),
+            mail_hold_count_quota: config
+                .property_or_default("jmap.email.hold.count-quota", "true")
+                .unwrap_or(true),
             sieve_max_script_name: config
                 .property("sieve.untrusted.limits.name-length")
                 .unwrap_or(512),
@@ -304,6 +395,9 @@ impl JmapConfig {
             session_cache_ttl: config
                 .property("cache.session.ttl")
                 .unwrap_or(Duration::from_secs(3600)),
+            directory_sync_interval: config
+                .property("cache.directory.sync-interval")
+                .unwrap_or(Duration::from_secs(5)),
             rate_authenticated: config
                 .property_or_default::<Option<Rate>>("jmap.rate-limit.account", "1000/1m")
                 .unwrap_or_default(),
@@ -385,8 +479,48 @@ impl JmapConfig {
                     .value("authentication.master.secret")
                     .map(|p| (u.to_string(), p.to_string()))
             }),
+            allow_client_certs: config
+                .property_or_default("auth.allow-client-certs", "false")
+                .unwrap_or(false),
+            self_service_fields,
+            undo_max_entries: config
+                .property_or_default("directory.undo.max-entries", "100")
+                .unwrap_or(100),
+            member_of_max_depth: config
+                .property_or_default("directory.member-of.max-depth", "10")
+                .unwrap_or(10),
             default_folders,
             shared_folder,
+            provision_welcome: config
+                .property_or_default("jmap.account.provision.welcome", "false")
+                .unwrap_or(false),
+            provision_sieve: config
+                .value("jmap.account.provision.sieve.script")
+                .map(|script| {
+                    (
+                        config
+                            .value("jmap.account.provision.sieve.name")
+                            .unwrap_or("default")
+                            .to_string(),
+                        script.to_string(),
+                    )
+                }),
+            tenant_export_path: config
+                .value("directory.tenant.export.path")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| std::env::temp_dir().join("stalwart-export")),
+            tenant_export_expiry: config
+                .property_or_default("directory.tenant.export.expiry", "7d")
+                .unwrap_or_else(|| Duration::from_secs(7 * 86400)),
+            collected_recipients_max: config
+                .property_or_default("jmap.email.collected-recipients.max-entries", "500")
+                .unwrap_or(500),
+            thread_rules_max: config
+                .property_or_default("jmap.email.thread-rules.max-entries", "1000")
+                .unwrap_or(1000),
+            thread_rules_expiry: config
+                .property_or_default("jmap.email.thread-rules.idle-expiry", "90d")
+                .unwrap_or_else(|| Duration::from_secs(90 * 86400)),
         };
 
         // Add capabilities
@@ -395,6 +529,54 @@ impl JmapConfig {
     }
 }
 
+fn parse_folder_limits(
+    config: &mut Config,
+    key: &str,
+    special_use: SpecialUse,
+) -> (Option<u64>, FolderLimitAction) {
+    let max_messages = config
+        .property::<u64>(("jmap.folders", key, "max-messages"))
+        .filter(|max| *max > 0);
+
+    // Junk is trimmed rather than rejecting legitimate mail
+    let on_limit = config
+        .property_or_default::<FolderLimitAction>(
+            ("jmap.folders", key, "on-limit"),
+            if special_use == SpecialUse::Junk {
+                "expunge-oldest"
+            } else {
+                "reject"
+            },
+        )
+        .unwrap_or(FolderLimitAction::Reject);
+
+    (max_messages, on_limit)
+}
+
+impl SpecialUse {
+    pub fn as_role(&self) -> Option<&'static str> {
+        match self {
+            SpecialUse::Inbox => Some("inbox"),
+            SpecialUse::Trash => Some("trash"),
+            SpecialUse::Junk => Some("junk"),
+            SpecialUse::Drafts => Some("drafts"),
+            SpecialUse::Archive => Some("archive"),
+            SpecialUse::Sent => Some("sent"),
+            SpecialUse::Shared | SpecialUse::None => None,
+        }
+    }
+}
+
+impl ParseValue for FolderLimitAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "reject" => Ok(FolderLimitAction::Reject),
+            "expunge-oldest" => Ok(FolderLimitAction::ExpungeOldest),
+            other => Err(format!("Unknown folder limit action {other:?}")),
+        }
+    }
+}
+
 impl ParseValue for SpecialUse {
     fn parse_value(value: &str) -> Result<Self, String> {
         match value {
@@ -0,0 +1,51 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use utils::config::Config;
+
+#[derive(Clone)]
+pub struct AccountLockout {
+    // Consecutive failed logins before the account is locked
+    pub threshold: Option<u64>,
+    pub duration: Duration,
+    // Principals holding any of these roles are never locked
+    pub exempt_roles: AHashSet<String>,
+}
+
+impl AccountLockout {
+    pub fn parse(config: &mut Config) -> Self {
+        AccountLockout {
+            threshold: config
+                .property::<u64>("authentication.lockout.threshold")
+                .filter(|threshold| *threshold > 0),
+            duration: config
+                .property_or_default("authentication.lockout.duration", "15m")
+                .unwrap_or_else(|| Duration::from_secs(15 * 60)),
+            exempt_roles: config
+                .values("authentication.lockout.exempt-roles")
+                .map(|(_, s)| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.threshold.is_some()
+    }
+}
+
+impl Default for AccountLockout {
+    fn default() -> Self {
+        AccountLockout {
+            threshold: None,
+            duration: Duration::from_secs(15 * 60),
+            exempt_roles: AHashSet::new(),
+        }
+    }
+}
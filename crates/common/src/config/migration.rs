@@ -0,0 +1,74 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use directory::backend::internal::PRINCIPAL_INFO_MIGRATION;
+use utils::config::Config;
+
+#[derive(Clone)]
+pub struct Migrations {
+    pub run_at_startup: bool,
+    pub chunk_size: usize,
+    pub lock_expiry: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MigrationType {
+    PrincipalInfoV2,
+}
+
+impl Migrations {
+    pub fn parse(config: &mut Config) -> Self {
+        Migrations {
+            run_at_startup: config
+                .property_or_default("migration.run-at-startup", "true")
+                .unwrap_or(true),
+            chunk_size: config
+                .property_or_default::<usize>("migration.chunk-size", "500")
+                .unwrap_or(500)
+                .max(1),
+            lock_expiry: config
+                .property_or_default("migration.lock-expiry", "10m")
+                .unwrap_or_else(|| Duration::from_secs(600)),
+        }
+    }
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Migrations {
+            run_at_startup: true,
+            chunk_size: 500,
+            lock_expiry: Duration::from_secs(600),
+        }
+    }
+}
+
+impl MigrationType {
+    /// Migrations in the order they are applied.
+    pub fn all() -> &'static [MigrationType] {
+        &[MigrationType::PrincipalInfoV2]
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::all().iter().find(|t| t.as_str() == value).copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MigrationType::PrincipalInfoV2 => PRINCIPAL_INFO_MIGRATION,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            MigrationType::PrincipalInfoV2 => {
+                "Rewrite principal name mappings using the current layout"
+            }
+        }
+    }
+}
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{str::FromStr, sync::Arc};
+use std::{str::FromStr, sync::Arc, time::Duration};
 
 use arc_swap::ArcSwap;
 use base64::{engine::general_purpose, Engine};
@@ -14,9 +14,14 @@ use hyper::{
     HeaderMap,
 };
 use ring::signature::{EcdsaKeyPair, RsaKeyPair};
-use store::{BlobBackend, BlobStore, FtsStore, LookupStore, Store, Stores};
+use store::{
+    write::tier::BlobTiering, BlobBackend, BlobStore, FtsStore, LookupStore, Store, Stores,
+};
 use telemetry::Metrics;
-use utils::config::{utils::AsKey, Config};
+use utils::{
+    config::{utils::AsKey, Config},
+    locale::Localization,
+};
 
 use crate::{
     auth::oauth::config::OAuthConfig, expr::*, listener::tls::AcmeProviders,
@@ -24,19 +29,33 @@ use crate::{
 };
 
 use self::{
-    imap::ImapConfig, jmap::settings::JmapConfig, scripts::Scripting, smtp::SmtpConfig,
-    storage::Storage,
+    approval::Approval, backpressure::Backpressure, health::HealthConfig, imap::ImapConfig,
+    jmap::settings::JmapConfig, lockout::AccountLockout, migration::Migrations,
+    notify::DirectoryNotify, replication::Replication, reputation::SenderReputation,
+    scheduler::Scheduler, scripts::Scripting, sessions::SessionLimits, smtp::SmtpConfig,
+    storage::Storage, volume::VolumeStats,
 };
 
+pub mod approval;
+pub mod backpressure;
+pub mod health;
 pub mod imap;
 pub mod inner;
 pub mod jmap;
+pub mod lockout;
+pub mod migration;
 pub mod network;
+pub mod notify;
+pub mod replication;
+pub mod reputation;
+pub mod scheduler;
 pub mod scripts;
 pub mod server;
+pub mod sessions;
 pub mod smtp;
 pub mod storage;
 pub mod telemetry;
+pub mod volume;
 
 pub(crate) const CONNECTION_VARS: &[u32; 7] = &[
     V_LISTENER,
@@ -103,6 +122,24 @@ impl Core {
                 }
             })
             .unwrap_or_default();
+        if let Some(id) = config
+            .value("storage.blob-tiering.store")
+            .map(|id| id.to_string())
+        {
+            if let Some(store) = stores.blob_stores.get(&id) {
+                let min_age = config
+                    .property_or_default::<Duration>("storage.blob-tiering.min-age", "90d")
+                    .unwrap_or(Duration::from_secs(90 * 86400))
+                    .as_secs();
+                let promote_after = config.property::<u32>("storage.blob-tiering.promote-after");
+                blob = blob.with_tiering(BlobTiering::new(store.clone(), min_age, promote_after));
+            } else {
+                config.new_parse_error(
+                    "storage.blob-tiering.store",
+                    format!("Blob store {id:?} not found"),
+                );
+            }
+        }
         let mut lookup = config
             .value_require("storage.lookup")
             .map(|id| id.to_string())
@@ -181,6 +218,18 @@ impl Core {
             oauth: OAuthConfig::parse(config),
             acme: AcmeProviders::parse(config),
             metrics: Metrics::parse(config),
+            scheduler: Scheduler::parse(config),
+            migrations: Migrations::parse(config),
+            replication: Replication::parse(config),
+            approval: Approval::parse(config),
+            health: HealthConfig::parse(config),
+            session_limits: SessionLimits::parse(config),
+            account_lockout: AccountLockout::parse(config),
+            volume: VolumeStats::parse(config),
+            sender_reputation: SenderReputation::parse(config),
+            backpressure: Backpressure::parse(config),
+            directory_notify: DirectoryNotify::parse(config),
+            localization: Localization::parse(config),
             storage: Storage {
                 data,
                 blob,
@@ -4,6 +4,8 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Duration;
+
 use crate::expr::{if_block::IfBlock, tokenizer::TokenMap};
 use utils::config::{Config, Rate};
 
@@ -16,6 +18,7 @@ pub struct Network {
     pub contact_form: Option<ContactForm>,
     pub http_response_url: IfBlock,
     pub http_allowed_endpoint: IfBlock,
+    pub certificate_watch: Option<Duration>,
 }
 
 #[derive(Clone)]
@@ -62,6 +65,7 @@ impl Default for Network {
                 "protocol + '://' + key_get('default', 'hostname') + ':' + local_port",
             ),
             http_allowed_endpoint: IfBlock::new::<()>("server.http.allowed-endpoint", [], "200"),
+            certificate_watch: None,
         }
     }
 }
@@ -126,6 +130,14 @@ impl Network {
             node_id: config.property("cluster.node-id").unwrap_or_default(),
             security: Security::parse(config),
             contact_form: ContactForm::parse(config),
+            certificate_watch: config
+                .property_or_default::<bool>("server.tls.watch.enable", "true")
+                .unwrap_or(true)
+                .then(|| {
+                    config
+                        .property_or_default("server.tls.watch.interval", "1m")
+                        .unwrap_or_else(|| Duration::from_secs(60))
+                }),
             ..Default::default()
         };
         let token_map = &TokenMap::default().with_variables(HTTP_VARS);
@@ -0,0 +1,158 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use serde::{Deserialize, Serialize};
+use utils::config::{utils::ParseValue, Config};
+
+#[derive(Clone)]
+pub struct DirectoryNotify {
+    pub events: AHashSet<NotifyEvent>,
+    pub from_name: Option<String>,
+    pub from_addr: String,
+    // Addresses or principal names
+    pub to: Vec<String>,
+    pub webhook: bool,
+    // Principals whose secret changes are watched
+    pub protected: AHashSet<String>,
+    pub admin_roles: AHashSet<String>,
+    pub batch_interval: Duration,
+    pub suppress: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyEvent {
+    RoleGrant,
+    PermissionChange,
+    SecretChange,
+    TenantCreate,
+}
+
+impl DirectoryNotify {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut events = AHashSet::new();
+        for (key, value) in config
+            .values("directory.notify.events")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            match NotifyEvent::parse_value(&value) {
+                Ok(event) => {
+                    events.insert(event);
+                }
+                Err(err) => config.new_parse_error(key, err),
+            }
+        }
+
+        let mut notify = DirectoryNotify {
+            events,
+            from_name: config
+                .value("directory.notify.email.from-name")
+                .map(|s| s.to_string()),
+            from_addr: config
+                .value("directory.notify.email.from-addr")
+                .unwrap_or("MAILER-DAEMON@localhost")
+                .trim()
+                .to_string(),
+            to: config
+                .values("directory.notify.email.to")
+                .map(|(_, s)| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            webhook: config
+                .property_or_default("directory.notify.webhook", "false")
+                .unwrap_or(false),
+            protected: config
+                .values("directory.notify.protected")
+                .map(|(_, s)| s.trim().to_lowercase())
+                .collect(),
+            admin_roles: config
+                .values("directory.notify.admin-roles")
+                .map(|(_, s)| s.trim().to_lowercase())
+                .collect(),
+            batch_interval: config
+                .property_or_default("directory.notify.batch", "1m")
+                .unwrap_or_else(|| Duration::from_secs(60)),
+            suppress: config
+                .property_or_default("directory.notify.suppress", "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+        };
+
+        if notify.admin_roles.is_empty() {
+            notify.admin_roles = ["admin", "tenant-admin"]
+                .into_iter()
+                .map(|s| s.to_string())
+                .collect();
+        }
+        if !notify.events.is_empty() && notify.to.is_empty() && !notify.webhook {
+            config.new_build_warning(
+                "directory.notify.events",
+                "No notification destinations configured",
+            );
+        }
+
+        notify
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.events.is_empty() && (!self.to.is_empty() || self.webhook)
+    }
+
+    pub fn is_watched(&self, event: NotifyEvent) -> bool {
+        self.events.contains(&event)
+    }
+}
+
+impl Default for DirectoryNotify {
+    fn default() -> Self {
+        DirectoryNotify {
+            events: AHashSet::new(),
+            from_name: None,
+            from_addr: "MAILER-DAEMON@localhost".to_string(),
+            to: Vec::new(),
+            webhook: false,
+            protected: AHashSet::new(),
+            admin_roles: AHashSet::new(),
+            batch_interval: Duration::from_secs(60),
+            suppress: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl NotifyEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotifyEvent::RoleGrant => "role-grant",
+            NotifyEvent::PermissionChange => "permission-change",
+            NotifyEvent::SecretChange => "secret-change",
+            NotifyEvent::TenantCreate => "tenant-create",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            NotifyEvent::RoleGrant => "Administrator role granted",
+            NotifyEvent::PermissionChange => "Role permissions changed",
+            NotifyEvent::SecretChange => "Protected principal credentials changed",
+            NotifyEvent::TenantCreate => "Tenant created",
+        }
+    }
+}
+
+impl ParseValue for NotifyEvent {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "role-grant" => Ok(NotifyEvent::RoleGrant),
+            "permission-change" => Ok(NotifyEvent::PermissionChange),
+            "secret-change" => Ok(NotifyEvent::SecretChange),
+            "tenant-create" => Ok(NotifyEvent::TenantCreate),
+            _ => Err(format!("Invalid directory notification event {:?}.", value)),
+        }
+    }
+}
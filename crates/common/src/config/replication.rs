@@ -0,0 +1,75 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use utils::config::Config;
+
+#[derive(Clone, Default)]
+pub struct Replication {
+    pub peer: Option<ReplicationPeer>,
+    pub standby: bool,
+}
+
+#[derive(Clone)]
+pub struct ReplicationPeer {
+    pub url: String,
+    pub username: String,
+    pub secret: String,
+    pub timeout: Duration,
+    pub tls_allow_invalid_certs: bool,
+    pub config_prefixes: Vec<String>,
+}
+
+impl Replication {
+    pub fn parse(config: &mut Config) -> Self {
+        let standby = config
+            .property_or_default("replication.standby.enable", "false")
+            .unwrap_or(false);
+        let peer = config
+            .value("replication.peer.url")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .and_then(|url| {
+                Some(ReplicationPeer {
+                    url,
+                    username: config
+                        .value_require("replication.peer.auth.username")?
+                        .to_string(),
+                    secret: config
+                        .value_require("replication.peer.auth.secret")?
+                        .to_string(),
+                    timeout: config
+                        .property_or_default("replication.peer.timeout", "30s")
+                        .unwrap_or_else(|| Duration::from_secs(30)),
+                    tls_allow_invalid_certs: config
+                        .property_or_default("replication.peer.tls.allow-invalid-certs", "false")
+                        .unwrap_or(false),
+                    config_prefixes: config
+                        .values("replication.peer.config")
+                        .map(|(_, prefix)| prefix.to_string())
+                        .collect::<Vec<_>>(),
+                })
+            })
+            .map(|mut peer| {
+                if peer.config_prefixes.is_empty() {
+                    peer.config_prefixes = ["queue.", "remote.", "session.rcpt."]
+                        .into_iter()
+                        .map(String::from)
+                        .collect();
+                }
+                peer
+            });
+
+        if peer.is_some() && standby {
+            config.new_build_error(
+                "replication.standby.enable",
+                "A standby server cannot replicate to another peer",
+            );
+        }
+
+        Replication { peer, standby }
+    }
+}
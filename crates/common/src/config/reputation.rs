@@ -0,0 +1,78 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use utils::config::Config;
+
+#[derive(Clone)]
+pub struct SenderReputation {
+    pub enable: bool,
+    pub window: Duration,
+    pub min_recipients: u64,
+    pub max_bounce_ratio: f64,
+    pub max_complaints: u64,
+    pub max_recipients_per_hour: u64,
+    pub throttle_score: u64,
+    pub reauth_score: u64,
+    pub suspend_score: u64,
+    pub throttle_rate: u64,
+}
+
+impl SenderReputation {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = SenderReputation::default();
+        SenderReputation {
+            enable: config
+                .property_or_default("sender-reputation.enable", "false")
+                .unwrap_or(false),
+            window: config
+                .property_or_default("sender-reputation.window", "1d")
+                .unwrap_or(default.window),
+            min_recipients: config
+                .property("sender-reputation.min-recipients")
+                .unwrap_or(default.min_recipients),
+            max_bounce_ratio: config
+                .property("sender-reputation.limit.bounce-ratio")
+                .unwrap_or(default.max_bounce_ratio),
+            max_complaints: config
+                .property("sender-reputation.limit.complaints")
+                .unwrap_or(default.max_complaints),
+            max_recipients_per_hour: config
+                .property("sender-reputation.limit.recipients-per-hour")
+                .unwrap_or(default.max_recipients_per_hour),
+            throttle_score: config
+                .property("sender-reputation.action.throttle")
+                .unwrap_or(default.throttle_score),
+            reauth_score: config
+                .property("sender-reputation.action.reauthenticate")
+                .unwrap_or(default.reauth_score),
+            suspend_score: config
+                .property("sender-reputation.action.suspend")
+                .unwrap_or(default.suspend_score),
+            throttle_rate: config
+                .property("sender-reputation.throttle-rate")
+                .unwrap_or(default.throttle_rate),
+        }
+    }
+}
+
+impl Default for SenderReputation {
+    fn default() -> Self {
+        SenderReputation {
+            enable: false,
+            window: Duration::from_secs(86400),
+            min_recipients: 20,
+            max_bounce_ratio: 0.2,
+            max_complaints: 3,
+            max_recipients_per_hour: 1000,
+            throttle_score: 100,
+            reauth_score: 150,
+            suspend_score: 200,
+            throttle_rate: 50,
+        }
+    }
+}
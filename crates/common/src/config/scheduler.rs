@@ -0,0 +1,146 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashMap;
+use utils::config::{cron::SimpleCron, Config};
+
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    pub tasks: AHashMap<TaskType, ScheduledTask>,
+}
+
+#[derive(Clone)]
+pub struct ScheduledTask {
+    pub task: TaskType,
+    pub cron: Option<SimpleCron>,
+    pub interval: Option<Duration>,
+    pub lock_expiry: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskType {
+    PurgeAccounts,
+    PurgeDataStore,
+    PurgeBlobStore,
+    PurgeLookupStore,
+    PurgeExpired,
+    ReplicateDirectory,
+    RecalculateQuota,
+    TierBlobs,
+}
+
+impl Scheduler {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut tasks = AHashMap::new();
+
+        // Make sure that all configured tasks are known
+        for task_id in config
+            .sub_keys("scheduler.task", "")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            if TaskType::parse(&task_id).is_none() {
+                config.new_parse_error(
+                    ("scheduler.task", task_id.as_str()),
+                    format!("Unknown scheduled task {task_id:?}"),
+                );
+            }
+        }
+
+        for task in TaskType::all() {
+            // Replication and tiering run at a fixed interval when configured
+            let default_interval = match task {
+                TaskType::ReplicateDirectory if config.contains_key("replication.peer.url") => {
+                    Some("1m")
+                }
+                TaskType::TierBlobs if config.contains_key("storage.blob-tiering.store") => {
+                    Some("1d")
+                }
+                TaskType::PurgeExpired => Some("1h"),
+                _ => None,
+            };
+            let (cron, interval) = if config
+                .property_or_default::<bool>(("scheduler.task", task.as_str(), "enable"), "true")
+                .unwrap_or(true)
+            {
+                (
+                    config.property::<SimpleCron>(("scheduler.task", task.as_str(), "schedule")),
+                    if let Some(default_interval) = default_interval {
+                        config.property_or_default::<Duration>(
+                            ("scheduler.task", task.as_str(), "interval"),
+                            default_interval,
+                        )
+                    } else {
+                        config.property::<Duration>(("scheduler.task", task.as_str(), "interval"))
+                    },
+                )
+            } else {
+                (None, None)
+            };
+
+            tasks.insert(
+                *task,
+                ScheduledTask {
+                    task: *task,
+                    cron,
+                    interval,
+                    lock_expiry: config
+                        .property_or_default::<Duration>(
+                            ("scheduler.task", task.as_str(), "lock-expiry"),
+                            "1h",
+                        )
+                        .unwrap_or_else(|| Duration::from_secs(3600)),
+                },
+            );
+        }
+
+        Scheduler { tasks }
+    }
+}
+
+impl ScheduledTask {
+    pub fn time_to_next(&self) -> Option<Duration> {
+        match (&self.cron, self.interval) {
+            (Some(cron), Some(interval)) => Some(std::cmp::min(cron.time_to_next(), interval)),
+            (Some(cron), None) => Some(cron.time_to_next()),
+            (None, interval) => interval,
+        }
+    }
+}
+
+impl TaskType {
+    pub fn all() -> &'static [TaskType] {
+        &[
+            TaskType::PurgeAccounts,
+            TaskType::PurgeDataStore,
+            TaskType::PurgeBlobStore,
+            TaskType::PurgeLookupStore,
+            TaskType::PurgeExpired,
+            TaskType::ReplicateDirectory,
+            TaskType::RecalculateQuota,
+            TaskType::TierBlobs,
+        ]
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::all().iter().find(|t| t.as_str() == value).copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskType::PurgeAccounts => "purge-accounts",
+            TaskType::PurgeDataStore => "purge-data-store",
+            TaskType::PurgeBlobStore => "purge-blob-store",
+            TaskType::PurgeLookupStore => "purge-lookup-store",
+            TaskType::PurgeExpired => "purge-expired",
+            TaskType::ReplicateDirectory => "replicate-directory",
+            TaskType::RecalculateQuota => "recalculate-quota",
+            TaskType::TierBlobs => "tier-blobs",
+        }
+    }
+}
@@ -32,8 +32,20 @@ pub struct Scripting {
     pub sign: IfBlock,
     pub trusted_scripts: AHashMap<String, Arc<Sieve>>,
     pub untrusted_scripts: AHashMap<String, Arc<Sieve>>,
+    pub untrusted_ext_lists: ExtLists,
 }
 
+#[derive(Clone)]
+pub struct ExtLists {
+    pub lookups: Vec<String>,
+    pub groups: bool,
+    pub personal: bool,
+    pub cache_ttl: Duration,
+}
+
+pub const EXT_LIST_PERSONAL: &str = ":addrbook:personal";
+pub const EXT_LIST_GROUP_PREFIX: &str = ":group:";
+
 #[derive(Clone)]
 pub struct RemoteList {
     pub entries: HashSet<String>,
@@ -42,6 +54,9 @@ pub struct RemoteList {
 
 impl Scripting {
     pub async fn parse(config: &mut Config, stores: &Stores) -> Self {
+        // Parse external lists available to untrusted scripts
+        let untrusted_ext_lists = ExtLists::parse(config, stores);
+
         // Parse untrusted compiler
         let mut fnc_map_untrusted = register_functions_untrusted().register_plugins_untrusted();
         let untrusted_compiler = Compiler::new()
@@ -332,6 +347,7 @@ impl Scripting {
             untrusted_compiler,
             untrusted_runtime,
             trusted_runtime,
+            untrusted_ext_lists,
             from_addr: IfBlock::try_parse(config, "sieve.trusted.from-addr", &token_map)
                 .unwrap_or_else(|| {
                     IfBlock::new::<()>(
@@ -387,6 +403,7 @@ impl Default for Scripting {
             ),
             untrusted_scripts: AHashMap::new(),
             trusted_scripts: AHashMap::new(),
+            untrusted_ext_lists: ExtLists::default(),
         }
     }
 }
@@ -403,6 +420,56 @@ impl Clone for Scripting {
             sign: self.sign.clone(),
             trusted_scripts: self.trusted_scripts.clone(),
             untrusted_scripts: self.untrusted_scripts.clone(),
+            untrusted_ext_lists: self.untrusted_ext_lists.clone(),
+        }
+    }
+}
+
+impl ExtLists {
+    pub fn parse(config: &mut Config, stores: &Stores) -> Self {
+        let mut lookups = Vec::new();
+        for (key, id) in config
+            .values("sieve.untrusted.ext-lists.lookup")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            if stores.lookup_stores.contains_key(&id) {
+                lookups.push(id);
+            } else {
+                config.new_parse_error(key, format!("Lookup store {id:?} not found"));
+            }
+        }
+
+        ExtLists {
+            lookups,
+            groups: config
+                .property_or_default("sieve.untrusted.ext-lists.groups", "true")
+                .unwrap_or(true),
+            personal: config
+                .property_or_default("sieve.untrusted.ext-lists.personal", "true")
+                .unwrap_or(true),
+            cache_ttl: config
+                .property_or_default("sieve.untrusted.ext-lists.cache-ttl", "5m")
+                .unwrap_or_else(|| Duration::from_secs(300)),
+        }
+    }
+
+    pub fn static_names(&self) -> Vec<String> {
+        let mut names = self.lookups.clone();
+        if self.personal {
+            names.push(EXT_LIST_PERSONAL.to_string());
+        }
+        names
+    }
+}
+
+impl Default for ExtLists {
+    fn default() -> Self {
+        Self {
+            lookups: Vec::new(),
+            groups: true,
+            personal: true,
+            cache_ttl: Duration::from_secs(300),
         }
     }
 }
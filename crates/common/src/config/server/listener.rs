@@ -4,12 +4,14 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{io::Cursor, net::SocketAddr, sync::Arc, time::Duration};
 
 use rustls::{
     crypto::ring::{default_provider, ALL_CIPHER_SUITES},
-    ServerConfig, SupportedCipherSuite, ALL_VERSIONS,
+    server::WebPkiClientVerifier,
+    ProtocolVersion, RootCertStore, ServerConfig, SupportedCipherSuite, ALL_VERSIONS,
 };
+use rustls_pemfile::certs;
 
 use tokio::net::TcpSocket;
 use tokio_rustls::TlsAcceptor;
@@ -22,7 +24,7 @@ use utils::{
 };
 
 use crate::{
-    listener::{tls::CertificateResolver, TcpAcceptor},
+    listener::{tls::CertificateResolver, TcpAcceptor, TlsPolicy},
     Inner,
 };
 
@@ -251,6 +253,58 @@ impl Listeners {
                     );
                 }
 
+                // Parse minimum protocol version
+                let min_version_key =
+                    if config.contains_key(("server.listener", id, "tls.min-version")) {
+                        ("server.listener", id, "tls.min-version").as_key()
+                    } else {
+                        "server.tls.min-version".as_key()
+                    };
+                if let Some(value) = config.value(&min_version_key).map(|v| v.to_string()) {
+                    match parse_tls_version(&value) {
+                        Some(ProtocolVersion::TLSv1_3) => tls_v2 = false,
+                        Some(ProtocolVersion::TLSv1_2) => (),
+                        Some(_) => {
+                            config.new_build_warning(
+                                min_version_key,
+                                format!("{value} is not supported, using TLSv1.2 instead"),
+                            );
+                        }
+                        None => {
+                            config.new_parse_error(
+                                min_version_key,
+                                format!("Unsupported TLS protocol {value:?}"),
+                            );
+                        }
+                    }
+                }
+                if !tls_v2 && !tls_v3 {
+                    config.new_build_error(
+                        ("server.listener", id, "tls"),
+                        "All TLS protocol versions are disabled",
+                    );
+                    tls_v3 = true;
+                }
+
+                // Parse target protocol version, sessions below it are reported
+                let target_version = config
+                    .value_or_else(
+                        ("server.listener", id, "tls.target-version"),
+                        "server.tls.target-version",
+                    )
+                    .map(|v| v.to_string())
+                    .and_then(|value| {
+                        let version = parse_tls_version(&value);
+                        if version.is_none() {
+                            config.new_parse_error(
+                                ("server.listener", id, "tls.target-version"),
+                                format!("Unsupported TLS protocol {value:?}"),
+                            );
+                        }
+                        version
+                    })
+                    .unwrap_or(ProtocolVersion::TLSv1_3);
+
                 // Parse cipher suites
                 let mut disabled_ciphers: Vec<SupportedCipherSuite> = Vec::new();
                 let cipher_keys =
@@ -262,19 +316,88 @@ impl Listeners {
                 for (_, protocol) in config.properties::<SupportedCipherSuite>(cipher_keys) {
                     disabled_ciphers.push(protocol);
                 }
+                let mut allowed_ciphers: Vec<SupportedCipherSuite> = Vec::new();
+                let cipher_keys = if config.has_prefix(("server.listener", id, "tls.ciphers")) {
+                    ("server.listener", id, "tls.ciphers").as_key()
+                } else {
+                    "server.tls.ciphers".as_key()
+                };
+                for (_, protocol) in config.properties::<SupportedCipherSuite>(cipher_keys) {
+                    allowed_ciphers.push(protocol);
+                }
 
                 // Build cert provider
                 let mut provider = default_provider();
-                if !disabled_ciphers.is_empty() {
+                if !disabled_ciphers.is_empty() || !allowed_ciphers.is_empty() {
                     provider.cipher_suites = ALL_CIPHER_SUITES
                         .iter()
-                        .filter(|suite| !disabled_ciphers.contains(suite))
+                        .filter(|suite| {
+                            !disabled_ciphers.contains(suite)
+                                && (allowed_ciphers.is_empty() || allowed_ciphers.contains(suite))
+                        })
                         .copied()
                         .collect();
                 }
+                let provider = Arc::new(provider);
+
+                // Build client certificate verifier
+                let client_auth = config
+                    .property_or_else::<TlsClientAuth>(
+                        ("server.listener", id, "tls.client-auth"),
+                        "server.tls.client-auth",
+                        "none",
+                    )
+                    .unwrap_or(TlsClientAuth::None);
+                let client_verifier = if client_auth != TlsClientAuth::None {
+                    let mut roots = RootCertStore::empty();
+                    for pem in config
+                        .values_or_else(
+                            ("server.listener", id, "tls.client-ca"),
+                            "server.tls.client-ca",
+                        )
+                        .map(|(_, pem)| pem.to_string())
+                        .collect::<Vec<_>>()
+                    {
+                        for cert in certs(&mut Cursor::new(pem.as_bytes())) {
+                            match cert
+                                .map_err(|err| err.to_string())
+                                .and_then(|cert| roots.add(cert).map_err(|err| err.to_string()))
+                            {
+                                Ok(_) => (),
+                                Err(err) => {
+                                    config.new_build_error(
+                                        ("server.listener", id, "tls.client-ca"),
+                                        format!("Failed to add client CA certificate: {err}"),
+                                    );
+                                }
+                            }
+                        }
+                    }
+
+                    let builder = WebPkiClientVerifier::builder_with_provider(
+                        Arc::new(roots),
+                        provider.clone(),
+                    );
+                    match if client_auth == TlsClientAuth::Optional {
+                        builder.allow_unauthenticated().build()
+                    } else {
+                        builder.build()
+                    } {
+                        Ok(verifier) => Some(verifier),
+                        Err(err) => {
+                            config.new_build_error(
+                                ("server.listener", id, "tls.client-ca"),
+                                format!("Failed to build client certificate verifier: {err}"),
+                            );
+                            return;
+                        }
+                    }
+                } else {
+                    None
+                };
 
                 // Build server config
-                let mut server_config = match ServerConfig::builder_with_provider(provider.into())
+                let mut server_config = match ServerConfig::builder_with_provider(provider)
                     .with_protocol_versions(if tls_v3 == tls_v2 {
                         ALL_VERSIONS
                     } else if tls_v3 {
@@ -282,9 +405,14 @@ impl Listeners {
                     } else {
                         TLS12_VERSION
                     }) {
-                    Ok(server_config) => server_config
-                        .with_no_client_auth()
-                        .with_cert_resolver(resolver.clone()),
+                    Ok(server_config) => {
+                        let server_config = if let Some(client_verifier) = client_verifier {
+                            server_config.with_client_cert_verifier(client_verifier)
+                        } else {
+                            server_config.with_no_client_auth()
+                        };
+                        server_config.with_cert_resolver(resolver.clone())
+                    }
                     Err(err) => {
                         config.new_build_error(
                             ("server.listener", id, "tls"),
@@ -310,6 +438,10 @@ impl Listeners {
                     implicit: config
                         .property_or_default(("server.listener", id, "tls.implicit"), "false")
                         .unwrap_or(false),
+                    policy: Arc::new(TlsPolicy {
+                        target_version,
+                        usage: inner.data.tls_usage.clone(),
+                    }),
                 }
             } else {
                 TcpAcceptor::Plain
@@ -320,6 +452,34 @@ impl Listeners {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TlsClientAuth {
+    None,
+    Optional,
+    Require,
+}
+
+fn parse_tls_version(value: &str) -> Option<ProtocolVersion> {
+    match value {
+        "TLSv1.0" | "0x0301" => Some(ProtocolVersion::TLSv1_0),
+        "TLSv1.1" | "0x0302" => Some(ProtocolVersion::TLSv1_1),
+        "TLSv1.2" | "0x0303" => Some(ProtocolVersion::TLSv1_2),
+        "TLSv1.3" | "0x0304" => Some(ProtocolVersion::TLSv1_3),
+        _ => None,
+    }
+}
+
+impl ParseValue for TlsClientAuth {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "none" | "false" => Ok(Self::None),
+            "optional" => Ok(Self::Optional),
+            "require" | "required" | "true" => Ok(Self::Require),
+            _ => Err(format!("Invalid client authentication mode {value:?}")),
+        }
+    }
+}
+
 impl ParseValue for ServerProtocol {
     fn parse_value(value: &str) -> Result<Self, String> {
         if value.eq_ignore_ascii_case("smtp") {
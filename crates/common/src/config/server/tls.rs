@@ -26,6 +26,7 @@ use rustls::{
 };
 use rustls_pemfile::{certs, read_one, Item};
 use rustls_pki_types::PrivateKeyDer;
+use sha2::{Digest, Sha256};
 use utils::config::Config;
 use x509_parser::{
     certificate::X509Certificate,
@@ -397,6 +398,77 @@ pub(crate) fn build_certified_key(cert: Vec<u8>, pk: Vec<u8>) -> Result<Certifie
     })
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificateInfo {
+    pub fingerprint: String,
+    pub not_before: i64,
+    pub not_after: i64,
+}
+
+// Checks a certificate before it replaces one that is already being served
+pub(crate) fn validate_certified_key(cert: &CertifiedKey) -> Result<CertificateInfo, String> {
+    cert.keys_match()
+        .map_err(|err| format!("Private key does not match the certificate: {err}"))?;
+
+    let chain = cert
+        .cert
+        .iter()
+        .map(|der| {
+            X509Certificate::from_der(der.as_ref())
+                .map(|(_, cert)| cert)
+                .map_err(|err| format!("Failed to parse certificate: {err}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Each certificate must be issued by the one that follows it
+    for pair in chain.windows(2) {
+        if pair[0].issuer().as_raw() != pair[1].subject().as_raw() {
+            return Err(format!(
+                "Certificate chain is out of order: {:?} was not issued by {:?}",
+                pair[0].subject().to_string(),
+                pair[1].subject().to_string()
+            ));
+        }
+    }
+
+    let validity = chain
+        .first()
+        .ok_or_else(|| "No certificates found.".to_string())?
+        .validity();
+    if !validity.is_valid() {
+        return Err(format!(
+            "Certificate is only valid from {} to {}",
+            validity.not_before, validity.not_after
+        ));
+    }
+
+    Ok(CertificateInfo {
+        fingerprint: certificate_fingerprint(cert.cert[0].as_ref()),
+        not_before: validity.not_before.timestamp(),
+        not_after: validity.not_after.timestamp(),
+    })
+}
+
+pub fn certificate_info(cert: &CertifiedKey) -> Option<CertificateInfo> {
+    let der = cert.cert.first()?;
+    let (_, parsed) = X509Certificate::from_der(der.as_ref()).ok()?;
+    let validity = parsed.validity();
+
+    Some(CertificateInfo {
+        fingerprint: certificate_fingerprint(der.as_ref()),
+        not_before: validity.not_before.timestamp(),
+        not_after: validity.not_after.timestamp(),
+    })
+}
+
+pub fn certificate_fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
 pub(crate) fn build_self_signed_cert(
     domains: impl Into<Vec<String>>,
 ) -> Result<CertifiedKey, String> {
@@ -0,0 +1,110 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashMap;
+use serde::{Deserialize, Serialize};
+use utils::config::Config;
+
+#[derive(Clone)]
+pub struct SessionLimits {
+    pub default: AHashMap<SessionProtocol, u64>,
+    pub tenants: AHashMap<(String, SessionProtocol), u64>,
+    pub accounts: AHashMap<(String, SessionProtocol), u64>,
+    pub heartbeat: Duration,
+    pub credentials_check: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionProtocol {
+    Imap,
+    Pop3,
+    ManageSieve,
+    Jmap,
+}
+
+impl SessionLimits {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut limits = SessionLimits {
+            heartbeat: config
+                .property_or_default("session-limit.heartbeat", "1m")
+                .unwrap_or_else(|| Duration::from_secs(60)),
+            credentials_check: config
+                .property_or_default("session-limit.credentials-check", "1m")
+                .unwrap_or_else(|| Duration::from_secs(60)),
+            ..Default::default()
+        };
+
+        for protocol in SessionProtocol::ALL {
+            if let Some(limit) = config.property::<u64>(("session-limit", protocol.as_str())) {
+                limits.default.insert(protocol, limit);
+            }
+
+            // Names may contain dots, so they are obtained by suffix
+            for (prefix, map) in [
+                ("session-limit.tenant", &mut limits.tenants),
+                ("session-limit.account", &mut limits.accounts),
+            ] {
+                let suffix = format!(".{}", protocol.as_str());
+                for name in config
+                    .sub_keys(prefix, &suffix)
+                    .map(|name| name.to_string())
+                    .collect::<Vec<_>>()
+                {
+                    if let Some(limit) =
+                        config.property::<u64>((prefix, name.as_str(), protocol.as_str()))
+                    {
+                        map.insert((name.to_lowercase(), protocol), limit);
+                    }
+                }
+            }
+        }
+
+        limits
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.default.is_empty() || !self.tenants.is_empty() || !self.accounts.is_empty()
+    }
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        SessionLimits {
+            default: AHashMap::new(),
+            tenants: AHashMap::new(),
+            accounts: AHashMap::new(),
+            heartbeat: Duration::from_secs(60),
+            credentials_check: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SessionProtocol {
+    pub const ALL: [SessionProtocol; 4] = [
+        SessionProtocol::Imap,
+        SessionProtocol::Pop3,
+        SessionProtocol::ManageSieve,
+        SessionProtocol::Jmap,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionProtocol::Imap => "imap",
+            SessionProtocol::Pop3 => "pop3",
+            SessionProtocol::ManageSieve => "managesieve",
+            SessionProtocol::Jmap => "jmap",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        SessionProtocol::ALL
+            .into_iter()
+            .find(|protocol| protocol.as_str() == value)
+    }
+}
@@ -4,7 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use ahash::AHashMap;
+use std::{
+    net::{IpAddr, UdpSocket},
+    time::Duration,
+};
+
+use ahash::{AHashMap, AHashSet};
 use mail_auth::IpLookupStrategy;
 use mail_send::Credentials;
 use utils::config::{
@@ -34,6 +39,7 @@ pub struct QueueConfig {
     pub max_mx: IfBlock,
     pub max_multihomed: IfBlock,
     pub ip_strategy: IfBlock,
+    pub ipv6_demotion: Option<Ipv6Demotion>,
     pub source_ip: QueueOutboundSourceIp,
     pub tls: QueueOutboundTls,
     pub dsn: Dsn,
@@ -47,12 +53,63 @@ pub struct QueueConfig {
 
     // Relay hosts
     pub relay_hosts: AHashMap<String, RelayHost>,
+
+    // Per deferral class schedules
+    pub retry_policy: QueueRetryPolicy,
+
+    // Per tenant sending windows and holds
+    pub sending_policies: AHashMap<String, SendingPolicy>,
 }
 
 #[derive(Clone)]
 pub struct QueueOutboundSourceIp {
     pub ipv4: IfBlock,
     pub ipv6: IfBlock,
+    pub rules: Vec<SourceIpRule>,
+    pub fallback: SourceIpFallback,
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceIpRule {
+    pub id: String,
+    pub tenant: Option<String>,
+    pub sender_domains: AHashSet<String>,
+    pub rcpt_domains: AHashSet<String>,
+    pub ipv4: Vec<IpAddr>,
+    pub ipv6: Vec<IpAddr>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv6Demotion {
+    // Consecutive IPv6 connection failures before a destination is demoted
+    pub failures: u64,
+    // Time during which IPv4 addresses are tried first
+    pub duration: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceIpFallback {
+    // Connect using the address chosen by the operating system
+    #[default]
+    Any,
+    // Treat the connection attempt as failed
+    Fail,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SendingPolicy {
+    pub hold: bool,
+    pub utc_offset: i64,
+    pub windows: Vec<SendingWindow>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SendingWindow {
+    // Bit 0 is Monday
+    pub days: u8,
+    // Minutes since local midnight
+    pub start: u32,
+    pub end: u32,
 }
 
 #[derive(Clone)]
@@ -73,6 +130,7 @@ pub struct QueueOutboundTls {
 #[derive(Clone)]
 pub struct QueueOutboundTimeout {
     pub connect: IfBlock,
+    pub happy_eyeballs: IfBlock,
     pub greeting: IfBlock,
     pub tls: IfBlock,
     pub ehlo: IfBlock,
@@ -115,6 +173,35 @@ pub struct RelayHost {
     pub tls_allow_invalid_certs: bool,
 }
 
+#[derive(Clone, Default)]
+pub struct QueueRetryPolicy {
+    pub classes: AHashMap<DeferralClass, RetrySchedule>,
+    pub groups: Vec<RetryGroup>,
+}
+
+#[derive(Clone)]
+pub struct RetryGroup {
+    pub id: String,
+    pub domains: AHashSet<String>,
+    pub classes: AHashMap<DeferralClass, RetrySchedule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetrySchedule {
+    pub retry: Vec<Duration>,
+    pub expire: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum DeferralClass {
+    Connection,
+    Dns,
+    Tls,
+    MailboxFull,
+    Greylist,
+    Transient,
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub enum RequireOptional {
     #[default]
@@ -153,9 +240,15 @@ impl Default for QueueConfig {
                 [],
                 "ipv4_then_ipv6",
             ),
+            ipv6_demotion: Some(Ipv6Demotion {
+                failures: 3,
+                duration: Duration::from_secs(3600),
+            }),
             source_ip: QueueOutboundSourceIp {
                 ipv4: IfBlock::empty("queue.outbound.source-ip.v4"),
                 ipv6: IfBlock::empty("queue.outbound.source-ip.v6"),
+                rules: Vec::new(),
+                fallback: SourceIpFallback::Any,
             },
             tls: QueueOutboundTls {
                 dane: IfBlock::new::<RequireOptional>("queue.outbound.tls.dane", [], "optional"),
@@ -196,6 +289,11 @@ impl Default for QueueConfig {
             },
             timeout: QueueOutboundTimeout {
                 connect: IfBlock::new::<()>("queue.outbound.timeouts.connect", [], "5m"),
+                happy_eyeballs: IfBlock::new::<()>(
+                    "queue.outbound.timeouts.happy-eyeballs",
+                    [],
+                    "250ms",
+                ),
                 greeting: IfBlock::new::<()>("queue.outbound.timeouts.greeting", [], "5m"),
                 tls: IfBlock::new::<()>("queue.outbound.timeouts.tls", [], "3m"),
                 ehlo: IfBlock::new::<()>("queue.outbound.timeouts.ehlo", [], "5m"),
@@ -215,6 +313,8 @@ impl Default for QueueConfig {
                 rcpt_domain: Default::default(),
             },
             relay_hosts: Default::default(),
+            retry_policy: Default::default(),
+            sending_policies: Default::default(),
         }
     }
 }
@@ -226,7 +326,7 @@ impl QueueConfig {
         let sender_vars = TokenMap::default().with_variables(SMTP_QUEUE_SENDER_VARS);
         let mx_vars = TokenMap::default().with_variables(SMTP_QUEUE_MX_VARS);
         let host_vars = TokenMap::default().with_variables(SMTP_QUEUE_HOST_VARS);
-        let ip_strategy_vars = sender_vars.clone().with_constants::<IpLookupStrategy>();
+        let ip_strategy_vars = mx_vars.clone().with_constants::<IpLookupStrategy>();
         let dane_vars = mx_vars.clone().with_constants::<RequireOptional>();
         let mta_sts_vars = rcpt_vars.clone().with_constants::<RequireOptional>();
 
@@ -278,6 +378,11 @@ impl QueueConfig {
                 "queue.outbound.timeouts.connect",
                 &host_vars,
             ),
+            (
+                &mut queue.timeout.happy_eyeballs,
+                "queue.outbound.timeouts.happy-eyeballs",
+                &host_vars,
+            ),
             (
                 &mut queue.timeout.greeting,
                 "queue.outbound.timeouts.greeting",
@@ -352,10 +457,419 @@ impl QueueConfig {
             },
         );
 
+        // Parse retry schedules
+        queue.retry_policy = parse_retry_policy(config);
+
+        // Parse source IP rules
+        queue.source_ip.rules = parse_source_ip_rules(config);
+        queue.source_ip.fallback = config
+            .property_or_default("queue.outbound.source-ip.fallback", "any")
+            .unwrap_or_default();
+
+        // Parse IPv6 demotion
+        let failures = config
+            .property_or_default::<u64>("queue.outbound.ipv6-demotion.failures", "3")
+            .unwrap_or(3);
+        queue.ipv6_demotion = (failures > 0).then(|| Ipv6Demotion {
+            failures,
+            duration: config
+                .property_or_default::<Duration>("queue.outbound.ipv6-demotion.duration", "1h")
+                .unwrap_or(Duration::from_secs(3600)),
+        });
+
+        // Parse tenant sending policies
+        queue.sending_policies = parse_sending_policies(config);
+
         queue
     }
 }
 
+impl QueueRetryPolicy {
+    pub fn schedule(&self, domain: &str, class: DeferralClass) -> Option<(&str, &RetrySchedule)> {
+        self.groups
+            .iter()
+            .filter(|group| group.matches(domain))
+            .find_map(|group| {
+                group
+                    .classes
+                    .get(&class)
+                    .map(|schedule| (group.id.as_str(), schedule))
+            })
+            .or_else(|| {
+                self.classes
+                    .get(&class)
+                    .map(|schedule| ("default", schedule))
+            })
+    }
+}
+
+impl RetryGroup {
+    pub fn matches(&self, domain: &str) -> bool {
+        matches_domain(&self.domains, domain)
+    }
+}
+
+impl SourceIpRule {
+    pub fn matches(&self, tenant: Option<&str>, sender_domain: &str, rcpt_domain: &str) -> bool {
+        self.tenant
+            .as_deref()
+            .is_none_or(|expected| tenant == Some(expected))
+            && (self.sender_domains.is_empty()
+                || matches_domain(&self.sender_domains, sender_domain))
+            && (self.rcpt_domains.is_empty() || matches_domain(&self.rcpt_domains, rcpt_domain))
+    }
+}
+
+impl QueueOutboundSourceIp {
+    pub fn has_tenant_rules(&self) -> bool {
+        self.rules.iter().any(|rule| rule.tenant.is_some())
+    }
+}
+
+impl SendingPolicy {
+    // Returns the time the next sending window opens, or None if sending is allowed now
+    pub fn next_window(&self, now: u64) -> Option<u64> {
+        if self.windows.is_empty() {
+            return None;
+        }
+
+        let local = now as i64 + self.utc_offset;
+        let today = local.div_euclid(86400);
+        let mut next_start = None;
+        for day in today..=today + 7 {
+            // 1970-01-01 was a Thursday
+            let weekday = (day + 3).rem_euclid(7) as u8;
+            for window in &self.windows {
+                if window.days & (1 << weekday) == 0 {
+                    continue;
+                }
+                let start = day * 86400 + window.start as i64 * 60;
+                let end = day * 86400 + window.end as i64 * 60;
+                if (start..end).contains(&local) {
+                    return None;
+                } else if start > local && next_start.is_none_or(|next| start < next) {
+                    next_start = Some(start);
+                }
+            }
+        }
+
+        next_start.map(|start| (start - self.utc_offset) as u64)
+    }
+}
+
+fn matches_domain(domains: &AHashSet<String>, domain: &str) -> bool {
+    domains.contains(domain)
+        || domain
+            .match_indices('.')
+            .any(|(pos, _)| domains.contains(&format!("*{}", &domain[pos..])))
+}
+
+impl ParseValue for SendingWindow {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        let (days, hours) = value
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("Invalid sending window {value:?}."))?;
+        let (start, end) = hours
+            .trim()
+            .split_once('-')
+            .and_then(|(start, end)| Some((parse_time(start)?, parse_time(end)?)))
+            .filter(|(start, end)| start < end)
+            .ok_or_else(|| format!("Invalid sending window hours {hours:?}."))?;
+
+        const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+        let day_num = |day: &str| {
+            DAYS.iter()
+                .position(|d| d.eq_ignore_ascii_case(day.trim()))
+                .ok_or_else(|| format!("Invalid sending window day {day:?}."))
+        };
+        let mut mask = 0u8;
+        for range in days.split(',') {
+            if range.trim() == "*" {
+                mask = 0x7f;
+            } else if let Some((from, to)) = range.split_once('-') {
+                let (from, to) = (day_num(from)?, day_num(to)?);
+                let mut day = from;
+                loop {
+                    mask |= 1 << day;
+                    if day == to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            } else {
+                mask |= 1 << day_num(range)?;
+            }
+        }
+
+        Ok(SendingWindow {
+            days: mask,
+            start,
+            end,
+        })
+    }
+}
+
+fn parse_time(value: &str) -> Option<u32> {
+    let (hours, minutes) = value.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    if minutes < 60 && (hours < 24 || (hours == 24 && minutes == 0)) {
+        Some(hours * 60 + minutes)
+    } else {
+        None
+    }
+}
+
+fn parse_utc_offset(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case("utc") || value == "Z" {
+        return Some(0);
+    }
+    let value = value.strip_prefix("UTC").unwrap_or(value);
+    let (sign, offset) = if let Some(offset) = value.strip_prefix('+') {
+        (1, offset)
+    } else {
+        (-1, value.strip_prefix('-')?)
+    };
+    let (hours, minutes) = offset.split_once(':').unwrap_or_else(|| {
+        offset.split_at(if offset.len() == 4 && offset.is_ascii() {
+            2
+        } else {
+            offset.len()
+        })
+    });
+    let (hours, minutes) = (
+        hours.parse::<i64>().ok()?,
+        if minutes.is_empty() {
+            0
+        } else {
+            minutes.parse::<i64>().ok()?
+        },
+    );
+    if hours <= 14 && minutes < 60 {
+        Some(sign * (hours * 3600 + minutes * 60))
+    } else {
+        None
+    }
+}
+
+impl ParseValue for SourceIpFallback {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "any" => Ok(SourceIpFallback::Any),
+            "fail" => Ok(SourceIpFallback::Fail),
+            _ => Err(format!("Invalid source IP fallback {:?}.", value)),
+        }
+    }
+}
+
+impl DeferralClass {
+    pub const ALL: &'static [DeferralClass] = &[
+        DeferralClass::Connection,
+        DeferralClass::Dns,
+        DeferralClass::Tls,
+        DeferralClass::MailboxFull,
+        DeferralClass::Greylist,
+        DeferralClass::Transient,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        DeferralClass::ALL
+            .iter()
+            .find(|class| class.as_str() == value)
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeferralClass::Connection => "connection",
+            DeferralClass::Dns => "dns",
+            DeferralClass::Tls => "tls",
+            DeferralClass::MailboxFull => "mailbox-full",
+            DeferralClass::Greylist => "greylist",
+            DeferralClass::Transient => "transient",
+        }
+    }
+}
+
+fn parse_retry_policy(config: &mut Config) -> QueueRetryPolicy {
+    let mut policy = QueueRetryPolicy {
+        classes: parse_retry_classes(config, "queue.schedule"),
+        groups: Vec::new(),
+    };
+
+    for id in config
+        .sub_keys("queue.schedule.group", "")
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+    {
+        let prefix = format!("queue.schedule.group.{id}");
+        let domains = config
+            .values(format!("{prefix}.domains"))
+            .map(|(_, domain)| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect::<AHashSet<_>>();
+        if domains.is_empty() {
+            config.new_parse_error(
+                format!("{prefix}.domains"),
+                "Retry schedule group must list at least one domain",
+            );
+            continue;
+        }
+        let classes = parse_retry_classes(config, &prefix);
+        policy.groups.push(RetryGroup {
+            id,
+            domains,
+            classes,
+        });
+    }
+
+    policy
+}
+
+fn parse_source_ip_rules(config: &mut Config) -> Vec<SourceIpRule> {
+    let mut rules = Vec::new();
+
+    for id in config
+        .sub_keys("queue.outbound.source-ip.rule", "")
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+    {
+        let prefix = format!("queue.outbound.source-ip.rule.{id}");
+        let domains = |config: &mut Config, key: &str| {
+            config
+                .values(format!("{prefix}.{key}"))
+                .map(|(_, domain)| domain.trim().to_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect::<AHashSet<_>>()
+        };
+        let sender_domains = domains(config, "sender-domain");
+        let rcpt_domains = domains(config, "rcpt-domain");
+        let tenant = config
+            .value(format!("{prefix}.tenant"))
+            .map(|tenant| tenant.trim().to_string())
+            .filter(|tenant| !tenant.is_empty());
+
+        // Addresses that cannot be bound would fail every connection attempt
+        let mut ipv4 = Vec::new();
+        let mut ipv6 = Vec::new();
+        for (key, ip) in config.properties::<IpAddr>(format!("{prefix}.address")) {
+            if UdpSocket::bind((ip, 0)).is_err() {
+                config.new_parse_error(
+                    key,
+                    format!("Source address {ip} is not available on this host"),
+                );
+            } else if ip.is_ipv4() {
+                ipv4.push(ip);
+            } else {
+                ipv6.push(ip);
+            }
+        }
+
+        if ipv4.is_empty() && ipv6.is_empty() {
+            config.new_parse_error(
+                format!("{prefix}.address"),
+                "Source IP rule must list at least one available address",
+            );
+            continue;
+        }
+
+        rules.push(SourceIpRule {
+            id,
+            tenant,
+            sender_domains,
+            rcpt_domains,
+            ipv4,
+            ipv6,
+        });
+    }
+
+    rules
+}
+
+fn parse_sending_policies(config: &mut Config) -> AHashMap<String, SendingPolicy> {
+    let mut policies = AHashMap::new();
+
+    for tenant in config
+        .sub_keys("queue.sending-policy", "")
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+    {
+        let prefix = format!("queue.sending-policy.{tenant}");
+        let utc_offset = match config.value(format!("{prefix}.timezone")) {
+            Some(value) => match parse_utc_offset(value) {
+                Some(offset) => offset,
+                None => {
+                    let err = format!("Invalid UTC offset {value:?}");
+                    config.new_parse_error(format!("{prefix}.timezone"), err);
+                    continue;
+                }
+            },
+            None => 0,
+        };
+
+        policies.insert(
+            tenant,
+            SendingPolicy {
+                hold: config
+                    .property_or_default((prefix.as_str(), "hold"), "false")
+                    .unwrap_or(false),
+                utc_offset,
+                windows: config
+                    .properties::<SendingWindow>(format!("{prefix}.window"))
+                    .into_iter()
+                    .map(|(_, window)| window)
+                    .collect(),
+            },
+        );
+    }
+
+    policies
+}
+
+fn parse_retry_classes(
+    config: &mut Config,
+    prefix: &str,
+) -> AHashMap<DeferralClass, RetrySchedule> {
+    let mut classes = AHashMap::new();
+
+    for id in config
+        .sub_keys(format!("{prefix}.class"), "")
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>()
+    {
+        let Some(class) = DeferralClass::parse(&id) else {
+            config.new_parse_error(
+                format!("{prefix}.class.{id}"),
+                format!("Unknown deferral class {id:?}"),
+            );
+            continue;
+        };
+        let retry = config
+            .properties::<Duration>(format!("{prefix}.class.{id}.retry"))
+            .into_iter()
+            .map(|(_, duration)| duration)
+            .collect::<Vec<_>>();
+        if retry.is_empty() {
+            config.new_parse_error(
+                format!("{prefix}.class.{id}.retry"),
+                "Retry schedule cannot be empty",
+            );
+            continue;
+        }
+
+        classes.insert(
+            class,
+            RetrySchedule {
+                retry,
+                expire: config.property(format!("{prefix}.class.{id}.expire")),
+            },
+        );
+    }
+
+    classes
+}
+
 fn parse_relay_host(config: &mut Config, id: &str) -> Option<RelayHost> {
     Some(RelayHost {
         address: config.property_require(("remote", id, "address"))?,
@@ -7,15 +7,16 @@
 use std::{
     net::{SocketAddr, ToSocketAddrs},
     str::FromStr,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use ahash::AHashSet;
+use ahash::{AHashMap, AHashSet};
 use base64::{engine::general_purpose::STANDARD, Engine};
 use hyper::{
     header::{HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
     HeaderMap,
 };
+use serde::{Deserialize, Serialize};
 use smtp_proto::*;
 use utils::config::{utils::ParseValue, Config};
 
@@ -43,6 +44,10 @@ pub struct SessionConfig {
     pub data: Data,
     pub extensions: Extensions,
     pub mta_sts_policy: Option<Policy>,
+    pub callout: Callout,
+    pub recipient_policy: RecipientPolicies,
+    pub dnsbl: Dnsbl,
+    pub rewrite_map: RewriteMap,
 
     pub milters: Vec<Milter>,
     pub hooks: Vec<MTAHook>,
@@ -113,10 +118,132 @@ pub struct Rcpt {
 
     // Limits
     pub max_recipients: IfBlock,
+    pub alias_max_fan_out: usize,
 
     // Catch-all and sub-addressing
     pub catch_all: AddressMapping,
     pub subaddressing: AddressMapping,
+
+    // Recipient verification
+    pub callout: IfBlock,
+}
+
+#[derive(Debug, Clone)]
+pub struct Callout {
+    pub timeout: Duration,
+    pub cache_positive: Duration,
+    pub cache_negative: Duration,
+    pub on_failure: CalloutPolicy,
+    pub pool_max_connections: usize,
+    pub pool_idle_timeout: Duration,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CalloutPolicy {
+    Accept,
+    #[default]
+    Defer,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecipientPolicies {
+    pub tenants: AHashMap<String, RecipientPolicy>,
+    pub groups: AHashMap<String, RecipientPolicy>,
+    pub cache_ttl: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RecipientPolicy {
+    pub mode: RecipientPolicyMode,
+    pub allowed_domains: AHashSet<String>,
+    pub exempt: AHashSet<String>,
+}
+
+// Ordered from the most to the least restrictive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RecipientPolicyMode {
+    InternalOnly,
+    AllowList,
+    #[default]
+    AllowAll,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dnsbl {
+    pub lists: Vec<DnsblList>,
+    pub timeout: Duration,
+    pub probe_interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsblList {
+    pub id: String,
+    pub zone: String,
+    pub typ: DnsblType,
+    pub weight: f64,
+    pub codes: Vec<DnsblCode>,
+    pub blocking: bool,
+    pub probe: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DnsblType {
+    Ip,
+    Domain,
+}
+
+/// Maps the last octet of a 127.0.0.x answer to a category, the score is
+/// multiplied by the list weight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsblCode {
+    pub from: u8,
+    pub to: u8,
+    pub category: String,
+    pub score: f64,
+}
+
+/// Recipient rewrite rules are kept in a lookup store so they can be
+/// changed at runtime without reloading the configuration.
+#[derive(Debug, Clone)]
+pub struct RewriteMap {
+    pub store: Option<String>,
+    pub max_hops: usize,
+    pub cache_ttl: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewriteRule {
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "type")]
+    pub typ: RewriteRuleType,
+    pub pattern: String,
+    pub target: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewriteRuleType {
+    Exact,
+    Regex,
+}
+
+/// Compiled copy of the rewrite rules, refreshed from the lookup store
+/// once it is older than the configured cache TTL.
+#[derive(Debug)]
+pub struct CompiledRewriteMap {
+    pub loaded: Instant,
+    pub exact: AHashMap<String, RewriteRule>,
+    pub regex: Vec<(regex::Regex, RewriteRule)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DnsblHealth {
+    pub disabled: bool,
+    pub next_probe: Instant,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -144,6 +271,59 @@ pub struct Data {
     pub add_auth_results: IfBlock,
     pub add_message_id: IfBlock,
     pub add_date: IfBlock,
+
+    // Attachment policies
+    pub attachments: AttachmentPolicies,
+
+    // MIME structure limits
+    pub mime_limits: MimeLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MimeLimits {
+    pub max_header_size: usize,
+    pub max_parts: usize,
+    pub max_depth: usize,
+    pub action: MimeLimitAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MimeLimitAction {
+    #[default]
+    Opaque,
+    Reject,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AttachmentPolicies {
+    pub global: Option<AttachmentPolicy>,
+    pub tenants: AHashMap<String, AttachmentPolicy>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttachmentPolicy {
+    pub direction: AttachmentDirection,
+    pub action: AttachmentAction,
+    pub max_size: Option<usize>,
+    pub max_total_size: Option<usize>,
+    pub banned_extensions: AHashSet<String>,
+    pub banned_types: AHashSet<String>,
+    pub inspect_archives: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AttachmentDirection {
+    Submission,
+    Delivery,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AttachmentAction {
+    #[default]
+    Reject,
+    Warn,
 }
 
 // Ceci n'est pas une pipe
@@ -215,6 +395,9 @@ impl SessionConfig {
         let mut session = SessionConfig::default();
         session.rcpt.catch_all = AddressMapping::parse(config, "session.rcpt.catch-all");
         session.rcpt.subaddressing = AddressMapping::parse(config, "session.rcpt.sub-addressing");
+        session.rcpt.alias_max_fan_out = config
+            .property_or_default("session.rcpt.alias.max-fan-out", "100")
+            .unwrap_or(100);
         session.milters = config
             .sub_keys("session.milter", ".hostname")
             .map(|s| s.to_string())
@@ -238,6 +421,12 @@ impl SessionConfig {
             .collect();
         session.throttle = SessionThrottle::parse(config);
         session.mta_sts_policy = Policy::try_parse(config);
+        session.callout = Callout::parse(config);
+        session.recipient_policy = RecipientPolicies::parse(config);
+        session.dnsbl = Dnsbl::parse(config);
+        session.rewrite_map = RewriteMap::parse(config);
+        session.data.attachments = AttachmentPolicies::parse(config);
+        session.data.mime_limits = MimeLimits::parse(config);
 
         for (value, key, token_map) in [
             (&mut session.duration, "session.duration", &has_conn_vars),
@@ -407,6 +596,11 @@ impl SessionConfig {
                 "session.rcpt.rewrite",
                 &has_rcpt_vars,
             ),
+            (
+                &mut session.rcpt.callout,
+                "session.rcpt.callout",
+                &has_rcpt_vars,
+            ),
             (
                 &mut session.data.script,
                 "session.data.script",
@@ -467,6 +661,452 @@ impl SessionConfig {
     }
 }
 
+impl Callout {
+    pub fn parse(config: &mut Config) -> Self {
+        Callout {
+            timeout: config
+                .property_or_default("session.callout.timeout", "15s")
+                .unwrap_or_else(|| Duration::from_secs(15)),
+            cache_positive: config
+                .property_or_default("session.callout.cache.positive", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
+            cache_negative: config
+                .property_or_default("session.callout.cache.negative", "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+            on_failure: config
+                .property_or_default("session.callout.on-failure", "defer")
+                .unwrap_or_default(),
+            pool_max_connections: config
+                .property_or_default("session.callout.pool.max-connections", "5")
+                .unwrap_or(5),
+            pool_idle_timeout: config
+                .property_or_default("session.callout.pool.idle-timeout", "30s")
+                .unwrap_or_else(|| Duration::from_secs(30)),
+        }
+    }
+}
+
+impl Default for Callout {
+    fn default() -> Self {
+        Callout {
+            timeout: Duration::from_secs(15),
+            cache_positive: Duration::from_secs(86400),
+            cache_negative: Duration::from_secs(3600),
+            on_failure: CalloutPolicy::Defer,
+            pool_max_connections: 5,
+            pool_idle_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ParseValue for CalloutPolicy {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "accept" => Ok(CalloutPolicy::Accept),
+            "defer" => Ok(CalloutPolicy::Defer),
+            _ => Err(format!("Invalid callout failure policy {:?}.", value)),
+        }
+    }
+}
+
+impl RecipientPolicies {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut policies = RecipientPolicies {
+            cache_ttl: config
+                .property_or_default("session.rcpt.policy.cache-ttl", "5m")
+                .unwrap_or_else(|| Duration::from_secs(300)),
+            ..Default::default()
+        };
+
+        // Names may contain dots, so they are obtained by suffix
+        for (prefix, map) in [
+            ("session.rcpt.policy.tenant", &mut policies.tenants),
+            ("session.rcpt.policy.group", &mut policies.groups),
+        ] {
+            for name in config
+                .sub_keys(prefix, ".mode")
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+            {
+                let Some(mode) =
+                    config.property::<RecipientPolicyMode>((prefix, name.as_str(), "mode"))
+                else {
+                    continue;
+                };
+                let allowed_domains = config
+                    .values((prefix, name.as_str(), "allowed-domains"))
+                    .map(|(_, domain)| domain.trim().to_lowercase())
+                    .collect::<AHashSet<_>>();
+                let exempt = config
+                    .values((prefix, name.as_str(), "exempt"))
+                    .map(|(_, name)| name.trim().to_lowercase())
+                    .collect::<AHashSet<_>>();
+
+                if mode == RecipientPolicyMode::AllowList && allowed_domains.is_empty() {
+                    config.new_build_warning(
+                        (prefix, name.as_str(), "allowed-domains"),
+                        "No domains are allowed, the policy behaves as internal-only",
+                    );
+                }
+
+                map.insert(
+                    name.to_lowercase(),
+                    RecipientPolicy {
+                        mode,
+                        allowed_domains,
+                        exempt,
+                    },
+                );
+            }
+        }
+
+        policies
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.tenants.is_empty() || !self.groups.is_empty()
+    }
+}
+
+impl Default for RecipientPolicies {
+    fn default() -> Self {
+        RecipientPolicies {
+            tenants: AHashMap::new(),
+            groups: AHashMap::new(),
+            cache_ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RecipientPolicyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecipientPolicyMode::InternalOnly => "internal-only",
+            RecipientPolicyMode::AllowList => "allow-list",
+            RecipientPolicyMode::AllowAll => "allow-all",
+        }
+    }
+}
+
+impl ParseValue for RecipientPolicyMode {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "internal-only" => Ok(RecipientPolicyMode::InternalOnly),
+            "allow-list" => Ok(RecipientPolicyMode::AllowList),
+            "allow-all" => Ok(RecipientPolicyMode::AllowAll),
+            _ => Err(format!("Invalid recipient policy {:?}.", value)),
+        }
+    }
+}
+
+impl Dnsbl {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut lists = Vec::new();
+        for id in config
+            .sub_keys("session.dnsbl.list", ".zone")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            if let Some(list) = DnsblList::parse(config, &id) {
+                lists.push(list);
+            }
+        }
+
+        Dnsbl {
+            lists,
+            timeout: config
+                .property_or_default("session.dnsbl.timeout", "5s")
+                .unwrap_or_else(|| Duration::from_secs(5)),
+            probe_interval: config
+                .property_or_default("session.dnsbl.probe-interval", "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+        }
+    }
+}
+
+impl Default for Dnsbl {
+    fn default() -> Self {
+        Dnsbl {
+            lists: Vec::new(),
+            timeout: Duration::from_secs(5),
+            probe_interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl RewriteMap {
+    pub fn parse(config: &mut Config) -> Self {
+        RewriteMap {
+            store: config
+                .value("session.rewrite-map.store")
+                .map(|s| s.to_string()),
+            max_hops: config
+                .property_or_default("session.rewrite-map.max-hops", "5")
+                .unwrap_or(5),
+            cache_ttl: config
+                .property_or_default("session.rewrite-map.cache", "1m")
+                .unwrap_or_else(|| Duration::from_secs(60)),
+        }
+    }
+}
+
+impl Default for RewriteMap {
+    fn default() -> Self {
+        RewriteMap {
+            store: None,
+            max_hops: 5,
+            cache_ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+impl DnsblList {
+    fn parse(config: &mut Config, id: &str) -> Option<Self> {
+        let prefix = format!("session.dnsbl.list.{id}");
+        let prefix = prefix.as_str();
+        if !config
+            .property_or_default((prefix, "enable"), "true")
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        let zone = config
+            .value_require((prefix, "zone"))?
+            .trim()
+            .trim_matches('.')
+            .to_lowercase();
+        let typ = config
+            .property_or_default((prefix, "type"), "ip")
+            .unwrap_or(DnsblType::Ip);
+        let mut codes = Vec::new();
+        for (key, value) in config
+            .values((prefix, "codes"))
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+        {
+            match DnsblCode::parse_value(&value) {
+                Ok(code) => codes.push(code),
+                Err(err) => config.new_parse_error(key, err),
+            }
+        }
+
+        // RFC 5782 test entries that must never be listed
+        let probe = config
+            .value((prefix, "probe"))
+            .map(|probe| probe.trim().trim_matches('.').to_lowercase())
+            .unwrap_or_else(|| {
+                match typ {
+                    DnsblType::Ip => "1.0.0.127",
+                    DnsblType::Domain => "invalid",
+                }
+                .to_string()
+            });
+
+        Some(DnsblList {
+            id: id.to_string(),
+            zone,
+            typ,
+            weight: config
+                .property_or_default((prefix, "weight"), "1.0")
+                .unwrap_or(1.0),
+            codes,
+            blocking: config
+                .property_or_default((prefix, "blocking"), "false")
+                .unwrap_or(false),
+            probe,
+        })
+    }
+
+    pub fn match_code(&self, octet: u8) -> Option<(Option<&str>, f64)> {
+        if self.codes.is_empty() {
+            Some((None, self.weight))
+        } else {
+            self.codes
+                .iter()
+                .find(|code| (code.from..=code.to).contains(&octet))
+                .map(|code| (Some(code.category.as_str()), code.score * self.weight))
+        }
+    }
+}
+
+impl ParseValue for DnsblType {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "ip" => Ok(DnsblType::Ip),
+            "domain" => Ok(DnsblType::Domain),
+            _ => Err(format!("Invalid DNSBL type {:?}.", value)),
+        }
+    }
+}
+
+impl ParseValue for DnsblCode {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        // Format is "<octet>[-<octet>] <category> [score]"
+        let mut parts = value.split_ascii_whitespace();
+        let range = parts.next().unwrap_or_default();
+        let (from, to) = range.split_once('-').unwrap_or((range, range));
+        let (Ok(from), Ok(to)) = (from.parse::<u8>(), to.parse::<u8>()) else {
+            return Err(format!("Invalid DNSBL return code {:?}.", value));
+        };
+        let category = parts
+            .next()
+            .ok_or_else(|| format!("Missing category in DNSBL return code {:?}.", value))?
+            .to_string();
+        let score = parts
+            .next()
+            .map(|score| score.parse::<f64>())
+            .transpose()
+            .map_err(|_| format!("Invalid score in DNSBL return code {:?}.", value))?
+            .unwrap_or(1.0);
+
+        if from <= to {
+            Ok(DnsblCode {
+                from,
+                to,
+                category,
+                score,
+            })
+        } else {
+            Err(format!("Invalid DNSBL return code range {:?}.", value))
+        }
+    }
+}
+
+impl AttachmentPolicies {
+    pub fn parse(config: &mut Config) -> Self {
+        let global = if config.has_prefix("session.data.attachments.global") {
+            AttachmentPolicy::parse(config, "session.data.attachments.global")
+        } else {
+            None
+        };
+        let tenants = config
+            .sub_keys("session.data.attachments.tenant", "")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+            .filter_map(|tenant| {
+                AttachmentPolicy::parse(
+                    config,
+                    &format!("session.data.attachments.tenant.{tenant}"),
+                )
+                .map(|policy| (tenant, policy))
+            })
+            .collect();
+
+        AttachmentPolicies { global, tenants }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.global.is_none() && self.tenants.is_empty()
+    }
+}
+
+impl AttachmentPolicy {
+    fn parse(config: &mut Config, prefix: &str) -> Option<Self> {
+        if !config
+            .property_or_default((prefix, "enable"), "true")
+            .unwrap_or(true)
+        {
+            return None;
+        }
+
+        Some(AttachmentPolicy {
+            direction: config
+                .property_or_default((prefix, "direction"), "both")
+                .unwrap_or_default(),
+            action: config
+                .property_or_default((prefix, "action"), "reject")
+                .unwrap_or_default(),
+            max_size: config.property((prefix, "max-size")),
+            max_total_size: config.property((prefix, "max-total-size")),
+            banned_extensions: config
+                .values((prefix, "banned-extensions"))
+                .map(|(_, ext)| ext.trim_start_matches('.').to_lowercase())
+                .collect(),
+            banned_types: config
+                .values((prefix, "banned-types"))
+                .map(|(_, typ)| typ.to_lowercase())
+                .collect(),
+            inspect_archives: config
+                .property_or_default((prefix, "inspect-archives"), "true")
+                .unwrap_or(true),
+        })
+    }
+}
+
+impl AttachmentDirection {
+    pub fn matches(&self, is_submission: bool) -> bool {
+        match self {
+            AttachmentDirection::Submission => is_submission,
+            AttachmentDirection::Delivery => !is_submission,
+            AttachmentDirection::Both => true,
+        }
+    }
+}
+
+impl ParseValue for AttachmentDirection {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "submission" => Ok(AttachmentDirection::Submission),
+            "delivery" => Ok(AttachmentDirection::Delivery),
+            "both" => Ok(AttachmentDirection::Both),
+            _ => Err(format!("Invalid attachment policy direction {:?}.", value)),
+        }
+    }
+}
+
+impl MimeLimits {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = MimeLimits::default();
+        MimeLimits {
+            max_header_size: config
+                .property("session.data.mime.max-header-size")
+                .unwrap_or(default.max_header_size),
+            max_parts: config
+                .property("session.data.mime.max-parts")
+                .unwrap_or(default.max_parts),
+            max_depth: config
+                .property("session.data.mime.max-depth")
+                .unwrap_or(default.max_depth),
+            action: config
+                .property_or_default("session.data.mime.action", "opaque")
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for MimeLimits {
+    fn default() -> Self {
+        MimeLimits {
+            max_header_size: 512 * 1024,
+            max_parts: 2000,
+            max_depth: 25,
+            action: MimeLimitAction::Opaque,
+        }
+    }
+}
+
+impl ParseValue for MimeLimitAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "opaque" => Ok(MimeLimitAction::Opaque),
+            "reject" => Ok(MimeLimitAction::Reject),
+            _ => Err(format!("Invalid MIME limit action {:?}.", value)),
+        }
+    }
+}
+
+impl ParseValue for AttachmentAction {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "reject" => Ok(AttachmentAction::Reject),
+            "warn" => Ok(AttachmentAction::Warn),
+            _ => Err(format!("Invalid attachment policy action {:?}.", value)),
+        }
+    }
+}
+
 impl SessionThrottle {
     pub fn parse(config: &mut Config) -> Self {
         let mut throttle = SessionThrottle::default();
@@ -795,8 +1435,10 @@ impl Default for SessionConfig {
                 errors_max: IfBlock::new::<()>("session.rcpt.errors.total", [], "5"),
                 errors_wait: IfBlock::new::<()>("session.rcpt.errors.wait", [], "5s"),
                 max_recipients: IfBlock::new::<()>("session.rcpt.max-recipients", [], "100"),
+                alias_max_fan_out: 100,
                 catch_all: AddressMapping::Enable,
                 subaddressing: AddressMapping::Enable,
+                callout: IfBlock::empty("session.rcpt.callout"),
             },
             data: Data {
                 #[cfg(feature = "test_mode")]
@@ -845,6 +1487,8 @@ impl Default for SessionConfig {
                     [("local_port == 25", "true")],
                     "false",
                 ),
+                attachments: Default::default(),
+                mime_limits: Default::default(),
             },
             extensions: Extensions {
                 pipelining: IfBlock::new::<()>("session.extensions.pipelining", [], "true"),
@@ -883,6 +1527,10 @@ impl Default for SessionConfig {
                 ),
             },
             mta_sts_policy: None,
+            callout: Callout::default(),
+            recipient_policy: RecipientPolicies::default(),
+            dnsbl: Dnsbl::default(),
+            rewrite_map: RewriteMap::default(),
             milters: Default::default(),
             hooks: Default::default(),
         }
@@ -21,7 +21,10 @@ use opentelemetry_sdk::{
 };
 use opentelemetry_semantic_conventions::resource::{SERVICE_NAME, SERVICE_VERSION};
 use store::Stores;
-use trc::{ipc::subscriber::Interests, EventType, Level, TelemetryEvent};
+use trc::{
+    ipc::{privacy::PrivacyMode, subscriber::Interests},
+    EventType, Level, TelemetryEvent,
+};
 use utils::config::{utils::ParseValue, Config};
 
 use super::parse_http_headers;
@@ -32,6 +35,7 @@ pub struct TelemetrySubscriber {
     pub interests: Interests,
     pub typ: TelemetrySubscriberType,
     pub lossy: bool,
+    pub privacy: PrivacyMode,
 }
 
 #[derive(Debug)]
@@ -114,6 +118,8 @@ pub struct Tracers {
     pub interests: Interests,
     pub levels: AHashMap<EventType, Level>,
     pub subscribers: Vec<TelemetrySubscriber>,
+    pub privacy_secret: Option<Vec<u8>>,
+    pub privacy_rotate: Duration,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -455,6 +461,9 @@ impl Tracers {
                 lossy: config
                     .property_or_default(("tracer", id, "lossy"), "false")
                     .unwrap_or(false),
+                privacy: config
+                    .property_or_default(("tracer", id, "privacy"), "off")
+                    .unwrap_or_default(),
                 typ,
             };
 
@@ -529,6 +538,9 @@ impl Tracers {
                             id: "history".to_string(),
                             interests: Default::default(),
                             lossy: false,
+                            privacy: config
+                                .property_or_default("tracing.history.privacy", "off")
+                                .unwrap_or_default(),
                             typ: TelemetrySubscriberType::StoreTracer(StoreTracer {
                                 store: store.clone(),
                             }),
@@ -581,6 +593,7 @@ impl Tracers {
                     buffered: true,
                 }),
                 lossy: false,
+                privacy: PrivacyMode::Off,
             });
         }
 
@@ -588,6 +601,12 @@ impl Tracers {
             subscribers: tracers,
             interests: global_interests,
             levels: custom_levels,
+            privacy_secret: config
+                .value("tracing.privacy.secret")
+                .map(|secret| secret.as_bytes().to_vec()),
+            privacy_rotate: config
+                .property_or_default("tracing.privacy.rotate", "1d")
+                .unwrap_or_else(|| Duration::from_secs(86400)),
         }
     }
 }
@@ -762,6 +781,9 @@ fn parse_webhook(
         lossy: config
             .property_or_default(("webhook", id, "lossy"), "false")
             .unwrap_or(false),
+        privacy: config
+            .property_or_default(("webhook", id, "privacy"), "off")
+            .unwrap_or_default(),
         typ: TelemetrySubscriberType::Webhook(WebhookTracer {
             url: config.value_require(("webhook", id, "url"))?.to_string(),
             timeout: config
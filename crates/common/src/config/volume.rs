@@ -0,0 +1,42 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use utils::config::Config;
+
+#[derive(Clone)]
+pub struct VolumeStats {
+    pub enable: bool,
+    pub flush_interval: Duration,
+    pub retention_days: u32,
+}
+
+impl VolumeStats {
+    pub fn parse(config: &mut Config) -> Self {
+        VolumeStats {
+            enable: config
+                .property_or_default("metrics.volume.enable", "false")
+                .unwrap_or(false),
+            flush_interval: config
+                .property_or_default("metrics.volume.flush-interval", "5m")
+                .unwrap_or_else(|| Duration::from_secs(5 * 60)),
+            retention_days: config
+                .property_or_default::<Duration>("metrics.volume.retention", "90d")
+                .map_or(90, |retention| (retention.as_secs() / 86400) as u32),
+        }
+    }
+}
+
+impl Default for VolumeStats {
+    fn default() -> Self {
+        VolumeStats {
+            enable: false,
+            flush_interval: Duration::from_secs(5 * 60),
+            retention_days: 90,
+        }
+    }
+}
@@ -6,13 +6,18 @@
 
 use std::sync::Arc;
 
-use directory::{backend::internal::manage::ManageDirectory, Directory, Type};
+use ahash::AHashMap;
+use directory::{
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory, PrincipalField},
+    Directory, Type,
+};
 use sieve::Sieve;
 use store::{
     write::{QueueClass, ValueClass},
     BlobStore, FtsStore, IterateParams, LookupStore, Store, ValueKey,
 };
 use trc::AddContext;
+use utils::locale::Localization;
 
 use crate::{
     config::smtp::{
@@ -185,6 +190,41 @@ impl Server {
             .await
             .caused_by(trc::location!())
     }
+
+    pub async fn total_principals(&self) -> trc::Result<AHashMap<Type, u64>> {
+        self.store()
+            .count_principals_by_type(None)
+            .await
+            .caused_by(trc::location!())
+    }
+
+    /// Locale and time zone preferred by an account, unset values fall back
+    /// to the server defaults.
+    pub async fn localization(&self, account_id: u32) -> trc::Result<Localization> {
+        Ok(self
+            .store()
+            .get_principal(account_id)
+            .await
+            .caused_by(trc::location!())?
+            .map_or(self.core.localization, |principal| {
+                self.core.localization.with_overrides(
+                    principal.get_str(PrincipalField::Locale),
+                    principal.get_str(PrincipalField::Timezone),
+                )
+            }))
+    }
+
+    pub async fn localization_by_address(&self, address: &str) -> trc::Result<Localization> {
+        match self
+            .store()
+            .email_to_id(address)
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(account_id) => self.localization(account_id).await,
+            None => Ok(self.core.localization),
+        }
+    }
 }
 
 pub trait BuildServer {
@@ -43,9 +43,31 @@ pub enum DeliveryEvent {
         message: IngestMessage,
         result_tx: oneshot::Sender<Vec<DeliveryResult>>,
     },
+    SubmissionStatus(SubmissionStatus),
     Stop,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SubmissionStatus {
+    pub queue_id: u64,
+    pub recipients: Vec<SubmissionRecipient>,
+    pub is_final: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SubmissionRecipient {
+    pub address: String,
+    pub delivered: Delivered,
+    pub smtp_reply: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Delivered {
+    Queued,
+    Yes,
+    No,
+}
+
 #[derive(Debug)]
 pub struct IngestMessage {
     pub sender_address: String,
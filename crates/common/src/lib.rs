@@ -9,35 +9,58 @@ use std::{
     hash::{BuildHasher, Hasher},
     net::IpAddr,
     sync::{atomic::AtomicU8, Arc},
+    time::Instant,
 };
 
 use ahash::{AHashMap, AHashSet, RandomState};
 use arc_swap::ArcSwap;
-use auth::{oauth::config::OAuthConfig, roles::RolePermissions, AccessToken};
+use auth::{
+    invalidate::DirectorySync, notify::DirectoryAlerts, oauth::config::OAuthConfig,
+    roles::RolePermissions, sessions::ActiveSession, AccessToken,
+};
 use config::{
+    approval::Approval,
+    backpressure::Backpressure,
+    health::HealthConfig,
     imap::ImapConfig,
     jmap::settings::JmapConfig,
+    lockout::AccountLockout,
+    migration::Migrations,
     network::Network,
+    notify::DirectoryNotify,
+    replication::Replication,
+    reputation::SenderReputation,
+    scheduler::Scheduler,
     scripts::{RemoteList, Scripting},
-    smtp::SmtpConfig,
+    sessions::SessionLimits,
+    smtp::{
+        session::{CompiledRewriteMap, DnsblHealth},
+        SmtpConfig,
+    },
     storage::Storage,
     telemetry::Metrics,
+    volume::VolumeStats,
 };
 use dashmap::DashMap;
 
 use futures::StreamExt;
 use imap_proto::protocol::list::Attribute;
 use ipc::{DeliveryEvent, HousekeeperEvent, QueueEvent, ReportingEvent, StateEvent};
-use listener::{blocked::Security, limiter::ConcurrencyLimiter, tls::AcmeProviders};
+use listener::{
+    backpressure::LoadShedding, blocked::Security, limiter::ConcurrencyLimiter, tls::AcmeProviders,
+    tls_usage::TlsUsage, tls_watch::CertificateWatch,
+};
 
 use manager::webadmin::{Resource, WebAdminManager};
 use nlp::bayes::cache::BayesTokenCache;
 use parking_lot::{Mutex, RwLock};
 use reqwest::Response;
 use rustls::sign::CertifiedKey;
+use telemetry::{health::Readiness, volume::VolumeCounters};
 use tokio::sync::{mpsc, Notify};
 use tokio_rustls::TlsConnector;
 use utils::{
+    locale::Localization,
     lru_cache::LruCache,
     map::ttl_dashmap::{ADashMap, TtlDashMap},
     snowflake::SnowflakeIdGenerator,
@@ -55,6 +78,7 @@ pub mod listener;
 pub mod manager;
 pub mod scripts;
 pub mod telemetry;
+pub mod templates;
 
 pub use psl;
 
@@ -81,6 +105,8 @@ pub struct Data {
 
     pub access_tokens: TtlDashMap<u32, Arc<AccessToken>>,
     pub http_auth_cache: TtlDashMap<String, u32>,
+    pub sieve_list_cache: TtlDashMap<u32, Arc<AHashSet<String>>>,
+    pub principal_names: TtlDashMap<u32, Option<String>>,
 
     pub blocked_ips: RwLock<AHashSet<IpAddr>>,
     pub blocked_ips_version: AtomicU8,
@@ -90,6 +116,8 @@ pub struct Data {
 
     pub bayes_cache: BayesTokenCache,
     pub remote_lists: RwLock<AHashMap<String, RemoteList>>,
+    pub dnsbl_health: RwLock<AHashMap<String, DnsblHealth>>,
+    pub rewrite_map: RwLock<Option<Arc<CompiledRewriteMap>>>,
 
     pub jmap_id_gen: SnowflakeIdGenerator,
     pub queue_id_gen: SnowflakeIdGenerator,
@@ -99,6 +127,7 @@ pub struct Data {
     pub config_version: AtomicU8,
 
     pub jmap_limiter: DashMap<u32, Arc<ConcurrencyLimiters>, RandomState>,
+    pub api_key_limiter: DashMap<(u32, u64), Arc<ConcurrencyLimiters>, RandomState>,
     pub imap_limiter: DashMap<u32, Arc<ConcurrencyLimiters>, RandomState>,
 
     pub account_cache: LruCache<AccountId, Arc<Account>>,
@@ -106,6 +135,16 @@ pub struct Data {
     pub threads_cache: LruCache<u32, Arc<Threads>>,
 
     pub logos: Mutex<AHashMap<String, Option<Resource<Vec<u8>>>>>,
+    pub active_sessions: Mutex<AHashMap<u32, Vec<ActiveSession>>>,
+    pub volume_stats: Mutex<AHashMap<(u32, String), VolumeCounters>>,
+    pub tls_usage: Arc<TlsUsage>,
+    pub tls_watch: CertificateWatch,
+    pub last_logins: Mutex<AHashMap<u32, u64>>,
+    pub directory_sync: Mutex<DirectorySync>,
+    pub idempotency_locks: Mutex<AHashMap<Vec<u8>, Arc<tokio::sync::Mutex<()>>>>,
+    pub readiness: Mutex<Option<(Instant, Arc<Readiness>)>>,
+    pub load_shedding: LoadShedding,
+    pub directory_alerts: Mutex<DirectoryAlerts>,
 
     pub smtp_session_throttle: DashMap<ThrottleKey, ConcurrencyLimiter, ThrottleKeyHasherBuilder>,
     pub smtp_queue_throttle: DashMap<ThrottleKey, ConcurrencyLimiter, ThrottleKeyHasherBuilder>,
@@ -207,6 +246,18 @@ pub struct Core {
     pub jmap: JmapConfig,
     pub imap: ImapConfig,
     pub metrics: Metrics,
+    pub scheduler: Scheduler,
+    pub migrations: Migrations,
+    pub replication: Replication,
+    pub approval: Approval,
+    pub health: HealthConfig,
+    pub session_limits: SessionLimits,
+    pub account_lockout: AccountLockout,
+    pub volume: VolumeStats,
+    pub sender_reputation: SenderReputation,
+    pub backpressure: Backpressure,
+    pub directory_notify: DirectoryNotify,
+    pub localization: Localization,
     #[cfg(feature = "enterprise")]
     pub enterprise: Option<enterprise::Enterprise>,
 }
@@ -0,0 +1,141 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use store::dispatch::latency::{LatencySnapshot, DATA_WRITE_LATENCY};
+use trc::{AddContext, ServerEvent};
+
+use crate::{config::backpressure::Backpressure, Server};
+
+// New connections and transactions are rejected while load shedding is active
+#[derive(Default)]
+pub struct LoadShedding {
+    active: AtomicBool,
+    last_writes: Mutex<LatencySnapshot>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadSignals {
+    pub queue_depth: u64,
+    pub write_latency: Option<Duration>,
+    pub memory: Option<u64>,
+}
+
+impl Server {
+    #[inline(always)]
+    pub fn is_shedding_load(&self) -> bool {
+        self.inner.data.load_shedding.active.load(Ordering::Relaxed)
+    }
+
+    pub fn assert_accepting_load(&self) -> trc::Result<()> {
+        if !self.is_shedding_load() {
+            Ok(())
+        } else {
+            Err(trc::LimitEvent::LoadShed.into_err())
+        }
+    }
+
+    pub async fn update_load_shedding(&self, memory: Option<u64>) -> trc::Result<()> {
+        let config = &self.core.backpressure;
+        let state = &self.inner.data.load_shedding;
+
+        if !config.is_enabled() {
+            if state.active.swap(false, Ordering::Relaxed) {
+                trc::event!(
+                    Server(ServerEvent::BackpressureStop),
+                    Details = "Backpressure disabled",
+                );
+            }
+            return Ok(());
+        }
+
+        let signals = LoadSignals {
+            queue_depth: if config.queue_depth.is_some() {
+                self.total_queued_messages()
+                    .await
+                    .caused_by(trc::location!())?
+            } else {
+                0
+            },
+            write_latency: {
+                let snapshot = DATA_WRITE_LATENCY.snapshot();
+                let mut last_writes = state.last_writes.lock();
+                let latency = snapshot.percentile(&last_writes, config.write_latency_percentile);
+                *last_writes = snapshot;
+                latency
+            },
+            memory,
+        };
+
+        if !state.active.load(Ordering::Relaxed) {
+            if let Some(reason) = signals.exceeded(config) {
+                state.active.store(true, Ordering::Relaxed);
+                trc::event!(
+                    Server(ServerEvent::BackpressureStart),
+                    Reason = reason,
+                    Total = signals.queue_depth,
+                    Elapsed = signals.write_latency,
+                    Size = signals.memory,
+                );
+            }
+        } else if signals.is_recovered(config) {
+            state.active.store(false, Ordering::Relaxed);
+            trc::event!(
+                Server(ServerEvent::BackpressureStop),
+                Total = signals.queue_depth,
+                Elapsed = signals.write_latency,
+                Size = signals.memory,
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl LoadSignals {
+    pub fn exceeded(&self, config: &Backpressure) -> Option<&'static str> {
+        if config
+            .queue_depth
+            .is_some_and(|t| t.is_exceeded(self.queue_depth))
+        {
+            Some("Queue depth exceeded")
+        } else if config
+            .write_latency
+            .zip(self.write_latency)
+            .is_some_and(|(t, latency)| t.is_exceeded(latency))
+        {
+            Some("Store write latency exceeded")
+        } else if config
+            .memory
+            .zip(self.memory)
+            .is_some_and(|(t, memory)| t.is_exceeded(memory))
+        {
+            Some("Memory usage exceeded")
+        } else {
+            None
+        }
+    }
+
+    // Signals that could not be measured are considered recovered
+    pub fn is_recovered(&self, config: &Backpressure) -> bool {
+        config
+            .queue_depth
+            .is_none_or(|t| t.is_recovered(self.queue_depth))
+            && config
+                .write_latency
+                .zip(self.write_latency)
+                .is_none_or(|(t, latency)| t.is_recovered(latency))
+            && config
+                .memory
+                .zip(self.memory)
+                .is_none_or(|(t, memory)| t.is_recovered(memory))
+    }
+}
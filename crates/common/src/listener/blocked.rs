@@ -131,6 +131,10 @@ impl Security {
                 .unwrap_or_default(),
         }
     }
+
+    pub fn auth_fail_rate(&self) -> Option<&Rate> {
+        self.auth_fail_rate.as_ref()
+    }
 }
 
 impl Server {
@@ -361,32 +361,13 @@ impl ServerInstance {
     pub async fn tls_accept<T: SessionStream>(
         &self,
         stream: T,
+        remote_ip: IpAddr,
         session_id: u64,
     ) -> Result<TlsStream<T>, ()> {
         match &self.acceptor {
             TcpAcceptor::Tls { acceptor, .. } => match acceptor.accept(stream).await {
                 Ok(stream) => {
-                    trc::event!(
-                        Tls(trc::TlsEvent::Handshake),
-                        ListenerId = self.id.clone(),
-                        SpanId = session_id,
-                        Version = format!(
-                            "{:?}",
-                            stream
-                                .get_ref()
-                                .1
-                                .protocol_version()
-                                .unwrap_or(rustls::ProtocolVersion::TLSv1_3)
-                        ),
-                        Details = format!(
-                            "{:?}",
-                            stream
-                                .get_ref()
-                                .1
-                                .negotiated_cipher_suite()
-                                .unwrap_or(TLS13_AES_128_GCM_SHA256)
-                        )
-                    );
+                    self.tls_handshake(&stream, remote_ip, session_id);
                     Ok(stream)
                 }
                 Err(err) => {
@@ -409,4 +390,51 @@ impl ServerInstance {
             }
         }
     }
+
+    pub fn tls_handshake<T: SessionStream>(
+        &self,
+        stream: &TlsStream<T>,
+        remote_ip: IpAddr,
+        session_id: u64,
+    ) {
+        let version = stream
+            .get_ref()
+            .1
+            .protocol_version()
+            .unwrap_or(rustls::ProtocolVersion::TLSv1_3);
+
+        trc::event!(
+            Tls(trc::TlsEvent::Handshake),
+            ListenerId = self.id.clone(),
+            SpanId = session_id,
+            RemoteIp = remote_ip,
+            Version = format!("{:?}", version),
+            Details = format!(
+                "{:?}",
+                stream
+                    .get_ref()
+                    .1
+                    .negotiated_cipher_suite()
+                    .unwrap_or(TLS13_AES_128_GCM_SHA256)
+            )
+        );
+
+        // Keep track of hosts below the target version
+        if let TcpAcceptor::Tls { policy, .. } = &self.acceptor {
+            if u16::from(version) < u16::from(policy.target_version) {
+                let (version, cipher) = stream.tls_version_and_cipher();
+
+                trc::event!(
+                    Tls(trc::TlsEvent::BelowTargetVersion),
+                    ListenerId = self.id.clone(),
+                    SpanId = session_id,
+                    RemoteIp = remote_ip,
+                    Version = version.clone(),
+                    Details = cipher.clone(),
+                );
+
+                policy.usage.record(&self.id, remote_ip, version, cipher);
+            }
+        }
+    }
 }
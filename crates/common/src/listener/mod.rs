@@ -6,7 +6,8 @@
 
 use std::{borrow::Cow, net::IpAddr, sync::Arc, time::Instant};
 
-use rustls::ServerConfig;
+use rustls::{ProtocolVersion, ServerConfig};
+use rustls_pki_types::CertificateDer;
 use std::fmt::Debug;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
@@ -22,14 +23,20 @@ use crate::{
     Server,
 };
 
-use self::limiter::{ConcurrencyLimiter, InFlight};
+use self::{
+    limiter::{ConcurrencyLimiter, InFlight},
+    tls_usage::TlsUsage,
+};
 
 pub mod acme;
+pub mod backpressure;
 pub mod blocked;
 pub mod limiter;
 pub mod listen;
 pub mod stream;
 pub mod tls;
+pub mod tls_usage;
+pub mod tls_watch;
 
 pub struct ServerInstance {
     pub id: String,
@@ -47,11 +54,17 @@ pub enum TcpAcceptor {
         config: Arc<ServerConfig>,
         acceptor: TlsAcceptor,
         implicit: bool,
+        policy: Arc<TlsPolicy>,
     },
     #[default]
     Plain,
 }
 
+pub struct TlsPolicy {
+    pub target_version: ProtocolVersion,
+    pub usage: Arc<TlsUsage>,
+}
+
 #[allow(clippy::large_enum_variant)]
 pub enum TcpAcceptorResult<IO>
 where
@@ -77,6 +90,7 @@ pub struct SessionData<T: SessionStream> {
 pub trait SessionStream: AsyncRead + AsyncWrite + Unpin + 'static + Sync + Send {
     fn is_tls(&self) -> bool;
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>);
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>>;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -128,6 +142,11 @@ pub trait SessionManager: Sync + Send + 'static + Clone {
                                 ],
                             )
                             .send_with_metrics();
+                            session.instance.tls_handshake(
+                                &stream,
+                                session.remote_ip,
+                                session.session_id,
+                            );
 
                             manager
                                 .handle(SessionData {
@@ -236,15 +255,28 @@ impl<T: SessionStream> ResolveVariable for SessionData<T> {
     }
 }
 
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        Self {
+            target_version: ProtocolVersion::TLSv1_3,
+            usage: Default::default(),
+        }
+    }
+}
+
 impl Debug for TcpAcceptor {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Tls {
-                config, implicit, ..
+                config,
+                implicit,
+                policy,
+                ..
             } => f
                 .debug_struct("Tls")
                 .field("config", config)
                 .field("implicit", implicit)
+                .field("target_version", &policy.target_version)
                 .finish(),
             Self::Plain => write!(f, "Plain"),
         }
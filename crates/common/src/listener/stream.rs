@@ -7,6 +7,7 @@
 use std::borrow::Cow;
 
 use proxy_header::io::ProxiedStream;
+use rustls_pki_types::CertificateDer;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpStream,
@@ -23,6 +24,10 @@ impl SessionStream for TcpStream {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         (Cow::Borrowed(""), Cow::Borrowed(""))
     }
+
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        None
+    }
 }
 
 impl<T: SessionStream> SessionStream for TlsStream<T> {
@@ -62,6 +67,14 @@ impl<T: SessionStream> SessionStream for TlsStream<T> {
             .into(),
         )
     }
+
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        self.get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .map(|cert| cert.clone().into_owned())
+    }
 }
 
 impl SessionStream for ProxiedStream<TcpStream> {
@@ -82,6 +95,10 @@ impl SessionStream for ProxiedStream<TcpStream> {
             })
             .unwrap_or((Cow::Borrowed("unknown"), Cow::Borrowed("unknown")))
     }
+
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        None
+    }
 }
 
 #[derive(Default)]
@@ -140,4 +157,8 @@ impl SessionStream for NullIo {
             std::borrow::Cow::Borrowed(""),
         )
     }
+
+    fn peer_certificate(&self) -> Option<CertificateDer<'static>> {
+        None
+    }
 }
@@ -114,6 +114,7 @@ impl TcpAcceptor {
                 config,
                 acceptor,
                 implicit,
+                ..
             } if *implicit => match enable_acme {
                 None => TcpAcceptorResult::Tls(acceptor.accept(stream)),
                 Some(core) => {
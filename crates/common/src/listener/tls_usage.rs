@@ -0,0 +1,98 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{borrow::Cow, cmp::Reverse, net::IpAddr};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use store::write::now;
+
+const MAX_HOSTS: usize = 10_000;
+
+// Keeps track of the remote hosts that negotiated a TLS version below
+// the listener's target, used to plan raising the minimum version.
+#[derive(Default)]
+pub struct TlsUsage {
+    hosts: Mutex<AHashMap<(String, IpAddr), TlsUsageEntry>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsUsageEntry {
+    pub listener_id: String,
+    pub remote_ip: IpAddr,
+    pub domain: Option<String>,
+    pub version: Cow<'static, str>,
+    pub cipher: Cow<'static, str>,
+    pub sessions: u64,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+impl TlsUsage {
+    pub fn record(
+        &self,
+        listener_id: &str,
+        remote_ip: IpAddr,
+        version: Cow<'static, str>,
+        cipher: Cow<'static, str>,
+    ) {
+        let now = now();
+        let mut hosts = self.hosts.lock();
+        if let Some(entry) = hosts.get_mut(&(listener_id.to_string(), remote_ip)) {
+            entry.version = version;
+            entry.cipher = cipher;
+            entry.sessions += 1;
+            entry.last_seen = now;
+            return;
+        }
+
+        if hosts.len() >= MAX_HOSTS {
+            if let Some(oldest) = hosts
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_seen)
+                .map(|(key, _)| key.clone())
+            {
+                hosts.remove(&oldest);
+            }
+        }
+
+        hosts.insert(
+            (listener_id.to_string(), remote_ip),
+            TlsUsageEntry {
+                listener_id: listener_id.to_string(),
+                remote_ip,
+                domain: None,
+                version,
+                cipher,
+                sessions: 1,
+                first_seen: now,
+                last_seen: now,
+            },
+        );
+    }
+
+    pub fn set_domain(&self, listener_id: &str, remote_ip: IpAddr, domain: &str) {
+        if let Some(entry) = self
+            .hosts
+            .lock()
+            .get_mut(&(listener_id.to_string(), remote_ip))
+        {
+            entry.domain = Some(domain.to_lowercase());
+        }
+    }
+
+    pub fn entries(&self, since: u64) -> Vec<TlsUsageEntry> {
+        let mut entries = self
+            .hosts
+            .lock()
+            .values()
+            .filter(|entry| entry.last_seen >= since)
+            .cloned()
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|entry| Reverse(entry.last_seen));
+        entries
+    }
+}
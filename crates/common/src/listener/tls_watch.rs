@@ -0,0 +1,245 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{path::PathBuf, sync::Arc, time::SystemTime};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use rustls::sign::CertifiedKey;
+use store::write::now;
+use utils::config::{Config, ConfigError};
+
+use crate::{
+    config::server::tls::{
+        certificate_info, parse_certificates, validate_certified_key, CertificateInfo,
+    },
+    Server,
+};
+
+// Keeps track of the files backing each certificate. Certificates are
+// resolved on every handshake, so replacing them only affects new sessions.
+#[derive(Default)]
+pub struct CertificateWatch {
+    certificates: Mutex<AHashMap<String, WatchedCertificate>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServedCertificate {
+    pub names: Vec<String>,
+    pub info: Option<CertificateInfo>,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WatchedCertificate {
+    pub files: Vec<(PathBuf, Option<SystemTime>)>,
+    pub names: Vec<String>,
+    pub info: Option<CertificateInfo>,
+    pub reloaded_at: Option<u64>,
+    pub error: Option<String>,
+}
+
+impl CertificateWatch {
+    pub fn entries(&self) -> Vec<(String, WatchedCertificate)> {
+        let mut entries = self
+            .certificates
+            .lock()
+            .iter()
+            .map(|(id, cert)| (id.clone(), cert.clone()))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+}
+
+impl Server {
+    // Certificates currently installed, grouped by the names they are served for
+    pub fn served_certificates(&self) -> Vec<ServedCertificate> {
+        let certs = self.inner.data.tls_certificates.load();
+        let default = certs.get("*").or_else(|| certs.values().next()).or(self
+            .inner
+            .data
+            .tls_self_signed_cert
+            .as_ref());
+
+        let mut served: Vec<(&Arc<CertifiedKey>, Vec<String>)> = Vec::new();
+        for (name, cert) in certs.iter() {
+            if let Some((_, names)) = served.iter_mut().find(|(c, _)| Arc::ptr_eq(c, cert)) {
+                names.push(name.clone());
+            } else {
+                served.push((cert, vec![name.clone()]));
+            }
+        }
+        if let Some(self_signed) = self
+            .inner
+            .data
+            .tls_self_signed_cert
+            .as_ref()
+            .filter(|_| certs.is_empty())
+        {
+            served.push((self_signed, Vec::new()));
+        }
+
+        served
+            .into_iter()
+            .map(|(cert, mut names)| {
+                names.sort_unstable();
+                ServedCertificate {
+                    names,
+                    info: certificate_info(cert),
+                    is_default: default.is_some_and(|d| Arc::ptr_eq(d, cert)),
+                }
+            })
+            .collect()
+    }
+
+    pub async fn watch_certificates(&self) -> trc::Result<()> {
+        // Read the certificate settings before file macros are expanded
+        let mut config = Config {
+            keys: self.core.storage.config.cfg_local.load().as_ref().clone(),
+            ..Default::default()
+        };
+        self.core
+            .storage
+            .config
+            .extend_config(&mut config, "certificate")
+            .await?;
+
+        for cert_id in config
+            .sub_keys("certificate", ".cert")
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+        {
+            let mut files = Vec::new();
+            for key in ["cert", "private-key"] {
+                for path in config
+                    .value(("certificate", cert_id.as_str(), key))
+                    .map(file_macros)
+                    .unwrap_or_default()
+                {
+                    let modified = tokio::fs::metadata(&path)
+                        .await
+                        .and_then(|metadata| metadata.modified())
+                        .ok();
+                    files.push((path, modified));
+                }
+            }
+
+            // Certificates stored inline never change on disk
+            if files.is_empty() {
+                continue;
+            }
+
+            let previous = self
+                .inner
+                .data
+                .tls_watch
+                .certificates
+                .lock()
+                .get(&cert_id)
+                .cloned();
+            if previous.as_ref().is_some_and(|p| p.files == files) {
+                continue;
+            }
+            let is_reload = previous.is_some();
+            let mut watched = previous.unwrap_or_default();
+            watched.files = files;
+
+            match self.load_certificate(&cert_id).await? {
+                Ok((cert, names, info)) => {
+                    // Swap the certificate atomically, handshakes in progress keep the old one
+                    let mut certificates = self.inner.data.tls_certificates.load().as_ref().clone();
+                    for name in &watched.names {
+                        if !names.contains(name) {
+                            certificates.remove(name);
+                        }
+                    }
+                    for name in &names {
+                        certificates.insert(name.clone(), cert.clone());
+                    }
+                    self.inner.data.tls_certificates.store(certificates.into());
+
+                    if is_reload {
+                        trc::event!(
+                            Tls(trc::TlsEvent::CertificateReloaded),
+                            Id = cert_id.clone(),
+                            Details = info.fingerprint.clone(),
+                            Hostname = names.as_slice(),
+                            ValidTo = trc::Value::Timestamp(info.not_after as u64),
+                        );
+                    }
+
+                    watched.names = names;
+                    watched.info = Some(info);
+                    watched.reloaded_at = Some(now());
+                    watched.error = None;
+                }
+                Err(err) => {
+                    trc::event!(
+                        Tls(trc::TlsEvent::CertificateReloadFailed),
+                        Id = cert_id.clone(),
+                        Reason = err.clone(),
+                    );
+
+                    watched.error = Some(err);
+                }
+            }
+
+            self.inner
+                .data
+                .tls_watch
+                .certificates
+                .lock()
+                .insert(cert_id, watched);
+        }
+
+        Ok(())
+    }
+
+    async fn load_certificate(
+        &self,
+        cert_id: &str,
+    ) -> trc::Result<Result<(Arc<CertifiedKey>, Vec<String>, CertificateInfo), String>> {
+        let mut config = self.core.storage.config.build_config("certificate").await?;
+
+        // Parse this certificate only
+        let prefix = format!("certificate.{cert_id}.");
+        config.keys.retain(|key, _| key.starts_with(&prefix));
+        config.errors.retain(|key, _| key.starts_with(&prefix));
+        let mut certificates = AHashMap::new();
+        parse_certificates(&mut config, &mut certificates, &mut Default::default());
+        if let Some(err) = config.errors.values().next() {
+            return Ok(Err(match err {
+                ConfigError::Parse { error }
+                | ConfigError::Build { error }
+                | ConfigError::Macro { error } => error.clone(),
+            }));
+        }
+
+        let Some(cert) = certificates.values().next().cloned() else {
+            return Ok(Err("No certificate was loaded".to_string()));
+        };
+
+        Ok(validate_certified_key(&cert)
+            .map(|info| (cert, certificates.into_keys().collect(), info)))
+    }
+}
+
+// Paths referenced by %{file:<path>}% macros
+fn file_macros(value: &str) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let mut snippet = value;
+    while let Some((_, rest)) = snippet.split_once("%{file:") {
+        let Some((location, rest)) = rest.split_once("}%") else {
+            break;
+        };
+        paths.push(PathBuf::from(
+            location.strip_prefix("//").unwrap_or(location),
+        ));
+        snippet = rest;
+    }
+    paths
+}
@@ -9,6 +9,7 @@ use std::{path::PathBuf, sync::Arc};
 use arc_swap::ArcSwap;
 use pwhash::sha512_crypt;
 use store::{
+    dispatch::copy::{copy_store, CopyMode},
     rand::{distributions::Alphanumeric, thread_rng, Rng},
     Stores,
 };
@@ -57,6 +58,12 @@ Options:
   -c, --config <PATH>              Start server with the specified configuration file
   -e, --export <PATH>              Export all store data to a specific path
   -i, --import <PATH>              Import store data from a specific path
+  -b, --backup <PATH>              Back up the data and blob stores to an archive
+  -s, --since <PATH>               Only back up changes made since a previous archive
+  -r, --restore <PATH>             Restore an archive into empty data and blob stores,
+                                   repeat to apply incremental backups in order
+  -m, --migrate <STORE>            Copy the data and blob stores to another configured store
+  -M, --migrate-mode <MODE>        Migration pass: full (default), delta or verify
   -o, --console                    Open the store console
   -I, --init <PATH>                Initialize a new server at a specific path
   -h, --help                       Print help
@@ -68,6 +75,9 @@ Options:
 enum StoreOp {
     Export(BackupParams),
     Import(PathBuf),
+    Backup(PathBuf),
+    Restore(Vec<PathBuf>),
+    Migrate(String),
     Console,
     None,
 }
@@ -76,6 +86,8 @@ impl BootManager {
     pub async fn init() -> Self {
         let mut config_path = std::env::var("CONFIG_PATH").ok();
         let mut import_export = StoreOp::None;
+        let mut backup_parent: Option<PathBuf> = None;
+        let mut migrate_mode = CopyMode::Full;
 
         if config_path.is_none() {
             let mut args = std::env::args().skip(1);
@@ -113,6 +125,27 @@ impl BootManager {
                     ("import" | "i", Some(value)) => {
                         import_export = StoreOp::Import(value.into());
                     }
+                    ("backup" | "b", Some(value)) => {
+                        import_export = StoreOp::Backup(value.into());
+                    }
+                    ("since" | "s", Some(value)) => {
+                        backup_parent = Some(value.into());
+                    }
+                    ("restore" | "r", Some(value)) => {
+                        if let StoreOp::Restore(paths) = &mut import_export {
+                            paths.push(value.into());
+                        } else {
+                            import_export = StoreOp::Restore(vec![value.into()]);
+                        }
+                    }
+                    ("migrate" | "m", Some(value)) => {
+                        import_export = StoreOp::Migrate(value);
+                    }
+                    ("migrate-mode" | "M", Some(value)) => {
+                        migrate_mode = CopyMode::parse(&value).unwrap_or_else(|| {
+                            failed(&format!("Invalid migration mode '{value}', try '--help'."))
+                        });
+                    }
                     ("console" | "o", None) => {
                         import_export = StoreOp::Console;
                     }
@@ -125,6 +158,10 @@ impl BootManager {
                 }
             }
 
+            if backup_parent.is_some() && !matches!(import_export, StoreOp::Backup(_)) {
+                failed("Argument '--since' requires '--backup', try '--help'.");
+            }
+
             if config_path.is_none() {
                 if import_export == StoreOp::None {
                     eprintln!("{HELP}");
@@ -399,6 +436,100 @@ impl BootManager {
                     .await;
                 std::process::exit(0);
             }
+            StoreOp::Backup(path) => {
+                // Enable telemetry
+                telemetry.enable(false);
+
+                // Parse settings and write archive
+                let core = Core::parse(&mut config, stores, manager).await;
+                let stats = if let Some(parent) = &backup_parent {
+                    core.storage
+                        .data
+                        .backup_incremental(&core.storage.blob, &path, parent)
+                        .await
+                } else {
+                    core.storage.data.backup(&core.storage.blob, &path).await
+                }
+                .failed("Failed to back up store");
+                println!(
+                    "Backed up {} keys and {} blobs to {}.",
+                    stats.keys,
+                    stats.blobs,
+                    path.display()
+                );
+                if let Some(parent) = &backup_parent {
+                    println!(
+                        "Included {} accounts changed since {}.",
+                        stats.accounts,
+                        parent.display()
+                    );
+                }
+                if stats.missing_blobs > 0 {
+                    eprintln!(
+                        "Warning: {} linked blobs were not found in the blob store.",
+                        stats.missing_blobs
+                    );
+                }
+                std::process::exit(0);
+            }
+            StoreOp::Restore(paths) => {
+                // Enable telemetry
+                telemetry.enable(false);
+
+                // Parse settings and restore archives
+                let core = Core::parse(&mut config, stores, manager).await;
+                let stats = core
+                    .storage
+                    .data
+                    .restore_chain(&core.storage.blob, &paths)
+                    .await
+                    .failed("Failed to restore store");
+                println!(
+                    "Restored {} keys and {} blobs from {} archive(s).",
+                    stats.keys,
+                    stats.blobs,
+                    paths.len()
+                );
+                std::process::exit(0);
+            }
+            StoreOp::Migrate(id) => {
+                // Enable telemetry
+                telemetry.enable(false);
+
+                // Obtain destination stores
+                if config.value("storage.data") == Some(id.as_str()) {
+                    failed("Cannot migrate the data store to itself.");
+                }
+                let (Some(dest), Some(blob_dest)) = (
+                    stores.stores.get(&id).cloned(),
+                    stores.blob_stores.get(&id).cloned(),
+                ) else {
+                    failed(&format!("Store '{id}' not found."));
+                };
+
+                // Parse settings and copy stores
+                let core = Core::parse(&mut config, stores, manager).await;
+                let stats = copy_store(
+                    &core.storage.data,
+                    &dest,
+                    &core.storage.blob,
+                    &blob_dest,
+                    migrate_mode,
+                )
+                .await
+                .failed("Failed to migrate store");
+                println!(
+                    "Processed {} keys and {} blobs ({} bytes), {} keys differed.",
+                    stats.keys, stats.blobs, stats.bytes, stats.mismatches
+                );
+                if stats.missing_blobs > 0 || stats.corrupted_blobs > 0 {
+                    eprintln!(
+                        "Warning: {} linked blobs were not found and {} did not match their hash.",
+                        stats.missing_blobs, stats.corrupted_blobs
+                    );
+                }
+                std::process::exit(0);
+            }
             StoreOp::Console => {
                 // Store console
                 store_console(Core::parse(&mut config, stores, manager).await.storage.data).await;
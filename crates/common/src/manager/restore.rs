@@ -177,66 +177,98 @@ async fn restore_file(store: Store, blob_store: BlobStore, path: &Path) {
                     }
                     Family::Directory => {
                         let key = key.as_slice();
-                        let class: DirectoryClass<MaybeDynamicId> =
-                            match key.first().expect("Failed to read directory key type") {
-                                0 => DirectoryClass::NameToId(
-                                    key.get(1..)
-                                        .expect("Failed to read directory string")
-                                        .to_vec(),
+                        let class: DirectoryClass<MaybeDynamicId> = match key
+                            .first()
+                            .expect("Failed to read directory key type")
+                        {
+                            0 => DirectoryClass::NameToId(
+                                key.get(1..)
+                                    .expect("Failed to read directory string")
+                                    .to_vec(),
+                            ),
+                            1 => DirectoryClass::EmailToId(
+                                key.get(1..)
+                                    .expect("Failed to read directory string")
+                                    .to_vec(),
+                            ),
+                            2 => DirectoryClass::Principal(MaybeDynamicId::Static(
+                                key.get(1..)
+                                    .expect("Failed to read range for principal id")
+                                    .deserialize_leb128::<u32>()
+                                    .expect("Failed to deserialize principal id"),
+                            )),
+                            /*3 => DirectoryClass::Domain(
+                                key.get(1..)
+                                    .expect("Failed to read directory string")
+                                    .to_vec(),
+                            ),*/
+                            4 => {
+                                batch.add(
+                                    ValueClass::Directory(DirectoryClass::UsedQuota(
+                                        key.get(1..)
+                                            .expect("Failed to read principal id")
+                                            .deserialize_leb128()
+                                            .expect("Failed to read principal id"),
+                                    )),
+                                    i64::deserialize(&value).expect("Failed to deserialize quota"),
+                                );
+
+                                continue;
+                            }
+                            5 => DirectoryClass::MemberOf {
+                                principal_id: MaybeDynamicId::Static(
+                                    key.deserialize_be_u32(1)
+                                        .expect("Failed to read principal id"),
                                 ),
-                                1 => DirectoryClass::EmailToId(
-                                    key.get(1..)
-                                        .expect("Failed to read directory string")
-                                        .to_vec(),
+                                member_of: MaybeDynamicId::Static(
+                                    key.deserialize_be_u32(1 + U32_LEN)
+                                        .expect("Failed to read principal id"),
                                 ),
-                                2 => DirectoryClass::Principal(MaybeDynamicId::Static(
-                                    key.get(1..)
-                                        .expect("Failed to read range for principal id")
-                                        .deserialize_leb128::<u32>()
-                                        .expect("Failed to deserialize principal id"),
-                                )),
-                                /*3 => DirectoryClass::Domain(
-                                    key.get(1..)
-                                        .expect("Failed to read directory string")
-                                        .to_vec(),
-                                ),*/
-                                4 => {
-                                    batch.add(
-                                        ValueClass::Directory(DirectoryClass::UsedQuota(
-                                            key.get(1..)
-                                                .expect("Failed to read principal id")
-                                                .deserialize_leb128()
-                                                .expect("Failed to read principal id"),
-                                        )),
-                                        i64::deserialize(&value)
-                                            .expect("Failed to deserialize quota"),
-                                    );
-
-                                    continue;
-                                }
-                                5 => DirectoryClass::MemberOf {
-                                    principal_id: MaybeDynamicId::Static(
-                                        key.deserialize_be_u32(1)
-                                            .expect("Failed to read principal id"),
-                                    ),
-                                    member_of: MaybeDynamicId::Static(
-                                        key.deserialize_be_u32(1 + U32_LEN)
-                                            .expect("Failed to read principal id"),
-                                    ),
-                                },
-                                6 => DirectoryClass::Members {
-                                    principal_id: MaybeDynamicId::Static(
-                                        key.deserialize_be_u32(1)
-                                            .expect("Failed to read principal id"),
-                                    ),
-                                    has_member: MaybeDynamicId::Static(
-                                        key.deserialize_be_u32(1 + U32_LEN)
-                                            .expect("Failed to read principal id"),
-                                    ),
-                                },
+                            },
+                            6 => DirectoryClass::Members {
+                                principal_id: MaybeDynamicId::Static(
+                                    key.deserialize_be_u32(1)
+                                        .expect("Failed to read principal id"),
+                                ),
+                                has_member: MaybeDynamicId::Static(
+                                    key.deserialize_be_u32(1 + U32_LEN)
+                                        .expect("Failed to read principal id"),
+                                ),
+                            },
+                            7 => DirectoryClass::Template {
+                                tenant_id: key
+                                    .deserialize_be_u32(1)
+                                    .expect("Failed to read tenant id"),
+                                kind: *key.get(1 + U32_LEN).expect("Failed to read template kind"),
+                            },
+                            8 => DirectoryClass::Delegation {
+                                principal_id: key
+                                    .deserialize_be_u32(1)
+                                    .expect("Failed to read principal id"),
+                                domain_id: key
+                                    .deserialize_be_u32(1 + U32_LEN)
+                                    .expect("Failed to read domain id"),
+                            },
+                            9 => DirectoryClass::ReadOnly(
+                                key.deserialize_be_u32(1).expect("Failed to read tenant id"),
+                            ),
+                            10 => DirectoryClass::ExternalIdToId(
+                                key.get(1..)
+                                    .expect("Failed to read directory string")
+                                    .to_vec(),
+                            ),
+                            11 => DirectoryClass::LastLogin(
+                                key.deserialize_be_u32(1)
+                                    .expect("Failed to read principal id"),
+                            ),
+                            12 => DirectoryClass::CertificateToId(
+                                key.get(1..)
+                                    .expect("Failed to read directory string")
+                                    .to_vec(),
+                            ),
 
-                                _ => failed("Invalid directory key"),
-                            };
+                            _ => failed("Invalid directory key"),
+                        };
                         batch.set(ValueClass::Directory(class), value);
                     }
                     Family::Queue => {
@@ -0,0 +1,168 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{sync::Arc, time::Instant};
+
+use ahash::AHashSet;
+use directory::{
+    backend::internal::{manage::ManageDirectory, PrincipalField},
+    Type,
+};
+use sieve::MatchAs;
+use trc::AddContext;
+use utils::map::ttl_dashmap::TtlMap;
+
+use crate::{
+    auth::AccessToken,
+    config::scripts::{EXT_LIST_GROUP_PREFIX, EXT_LIST_PERSONAL},
+    Server,
+};
+
+impl Server {
+    pub async fn sieve_list_contains(
+        &self,
+        access_token: &AccessToken,
+        lists: &[String],
+        values: &[String],
+        match_as: MatchAs,
+    ) -> trc::Result<bool> {
+        let ext_lists = &self.core.sieve.untrusted_ext_lists;
+        let values = values
+            .iter()
+            .map(|value| {
+                if matches!(match_as, MatchAs::Lowercase) {
+                    value.to_lowercase()
+                } else {
+                    value.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for list in lists {
+            if list == EXT_LIST_PERSONAL {
+                if ext_lists.personal {
+                    for value in &values {
+                        if self
+                            .core
+                            .storage
+                            .lookup
+                            .key_exists(personal_list_key(access_token.primary_id, value))
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    continue;
+                }
+            } else if let Some(group) = list.strip_prefix(EXT_LIST_GROUP_PREFIX) {
+                if ext_lists.groups {
+                    if let Some(members) = self.sieve_group_members(access_token, group).await? {
+                        if values
+                            .iter()
+                            .any(|value| members.contains(&value.to_lowercase()))
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    continue;
+                }
+            } else if ext_lists.lookups.contains(list) {
+                if let Some(store) = self.core.storage.lookups.get(list) {
+                    for value in &values {
+                        if store
+                            .key_exists(value.clone().into_bytes())
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            trc::event!(
+                Sieve(trc::SieveEvent::ListNotFound),
+                AccountId = access_token.primary_id,
+                Details = list.clone(),
+            );
+        }
+
+        Ok(false)
+    }
+
+    pub async fn sieve_ext_list_names(&self, access_token: &AccessToken) -> Vec<String> {
+        let ext_lists = &self.core.sieve.untrusted_ext_lists;
+        let mut names = ext_lists.static_names();
+
+        if ext_lists.groups {
+            for group_id in &access_token.member_of {
+                match self.core.storage.data.get_principal(*group_id).await {
+                    Ok(Some(principal)) if principal.typ() == Type::Group => {
+                        names.push(format!("{EXT_LIST_GROUP_PREFIX}{}", principal.name()));
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        trc::error!(err
+                            .account_id(access_token.primary_id)
+                            .caused_by(trc::location!()));
+                    }
+                }
+            }
+        }
+
+        names
+    }
+
+    async fn sieve_group_members(
+        &self,
+        access_token: &AccessToken,
+        group: &str,
+    ) -> trc::Result<Option<Arc<AHashSet<String>>>> {
+        // Scripts may only query the groups the account belongs to
+        let Some(group_id) = self
+            .core
+            .storage
+            .data
+            .get_principal_info(group)
+            .await
+            .caused_by(trc::location!())?
+            .filter(|info| info.typ == Type::Group && access_token.member_of.contains(&info.id))
+            .map(|info| info.id)
+        else {
+            return Ok(None);
+        };
+
+        if let Some(members) = self.inner.data.sieve_list_cache.get_with_ttl(&group_id) {
+            return Ok(Some(members));
+        }
+
+        let members = Arc::new(
+            self.core
+                .storage
+                .data
+                .get_members_expanded(group_id, &[Type::Individual])
+                .await
+                .caused_by(trc::location!())?
+                .iter()
+                .flat_map(|member| member.iter_str(PrincipalField::Emails))
+                .map(|email| email.to_lowercase())
+                .collect::<AHashSet<_>>(),
+        );
+        self.inner.data.sieve_list_cache.insert_with_ttl(
+            group_id,
+            members.clone(),
+            Instant::now() + self.core.sieve.untrusted_ext_lists.cache_ttl,
+        );
+
+        Ok(Some(members))
+    }
+}
+
+pub fn personal_list_key(account_id: u32, address: &str) -> Vec<u8> {
+    format!("addrbook:{account_id}:{}", address.to_lowercase()).into_bytes()
+}
@@ -11,6 +11,7 @@ use store::Value;
 
 use crate::IntoString;
 
+pub mod ext_lists;
 pub mod functions;
 pub mod plugins;
 
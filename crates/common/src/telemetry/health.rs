@@ -0,0 +1,129 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc, time::Instant};
+
+use serde::Serialize;
+use store::{
+    write::{now, TaskClass, ValueClass},
+    ValueKey,
+};
+
+use crate::{config::health::HealthCheck, Server};
+
+const HEALTH_PROBE: &[u8] = b"health.probe";
+const HEALTH_PROBE_DOMAIN: &str = "health.invalid";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    pub ready: bool,
+    pub checked_at: u64,
+    pub components: Vec<ComponentHealth>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComponentHealth {
+    pub name: HealthCheck,
+    pub healthy: bool,
+    pub fatal: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Readiness {
+    pub fn failing(&self) -> impl Iterator<Item = &ComponentHealth> {
+        self.components.iter().filter(|c| !c.healthy)
+    }
+}
+
+impl Server {
+    /// Returns the readiness of the server's dependencies, results are cached
+    /// briefly so the endpoint can be polled frequently without load.
+    pub async fn readiness(&self) -> Arc<Readiness> {
+        if let Some((checked, readiness)) = self.inner.data.readiness.lock().as_ref() {
+            if checked.elapsed() < self.core.health.cache_ttl {
+                return readiness.clone();
+            }
+        }
+
+        let (store, blob, certificates, directory) = tokio::join!(
+            self.check_component(HealthCheck::Store, async {
+                if self.core.storage.data.is_none() {
+                    return Err("No data store configured".to_string());
+                }
+                self.core
+                    .storage
+                    .data
+                    .get_value::<u64>(ValueKey::from(ValueClass::Task(TaskClass::State(
+                        HEALTH_PROBE.to_vec(),
+                    ))))
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string())
+            }),
+            self.check_component(HealthCheck::Blob, async {
+                self.core
+                    .storage
+                    .blob
+                    .get_blob(HEALTH_PROBE, 0..1)
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string())
+            }),
+            self.check_component(HealthCheck::Certificates, async {
+                if !self.inner.data.tls_certificates.load().is_empty()
+                    || self.inner.data.tls_self_signed_cert.is_some()
+                {
+                    Ok(())
+                } else {
+                    Err("No certificates loaded".to_string())
+                }
+            }),
+            self.check_component(HealthCheck::Directory, async {
+                self.core
+                    .storage
+                    .directory
+                    .is_local_domain(HEALTH_PROBE_DOMAIN)
+                    .await
+                    .map(|_| ())
+                    .map_err(|err| err.to_string())
+            }),
+        );
+
+        let components = vec![store, blob, certificates, directory];
+        let readiness = Arc::new(Readiness {
+            ready: components.iter().all(|c| c.healthy || !c.fatal),
+            checked_at: now(),
+            components,
+        });
+        *self.inner.data.readiness.lock() = Some((Instant::now(), readiness.clone()));
+
+        readiness
+    }
+
+    async fn check_component(
+        &self,
+        name: HealthCheck,
+        check: impl Future<Output = Result<(), String>>,
+    ) -> ComponentHealth {
+        let start = Instant::now();
+        let result = match tokio::time::timeout(self.core.health.timeout, check).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("Timed out after {:?}", self.core.health.timeout)),
+        };
+
+        ComponentHealth {
+            name,
+            healthy: result.is_ok(),
+            fatal: self.core.health.is_fatal(name),
+            latency_ms: start.elapsed().as_millis() as u64,
+            error: result.err(),
+        }
+    }
+}
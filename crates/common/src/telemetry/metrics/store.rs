@@ -135,7 +135,10 @@ impl MetricsStore for Store {
 
             for gauge in Collector::collect_gauges(true) {
                 let gauge_id = gauge.id();
-                if matches!(gauge_id, MetricType::QueueCount | MetricType::ServerMemory) {
+                if matches!(
+                    gauge_id,
+                    MetricType::QueueCount | MetricType::ServerMemory | MetricType::FtsIndexLag
+                ) {
                     let value = gauge.get();
                     if value > 0 {
                         batch.set(
@@ -4,12 +4,15 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod health;
 pub mod metrics;
 pub mod tracers;
+pub mod volume;
 pub mod webhooks;
 
 use std::time::Duration;
 
+use store::rand::{distributions::Alphanumeric, thread_rng, Rng};
 use tracers::log::spawn_log_tracer;
 use tracers::otel::spawn_otel_tracer;
 use tracers::stdout::spawn_console_tracer;
@@ -22,12 +25,25 @@ pub const LONG_SLUMBER: Duration = Duration::from_secs(60 * 60 * 24 * 365);
 
 impl Telemetry {
     pub fn enable(self, is_enterprise: bool) {
+        // A random key is used when no secret is configured, pseudonyms
+        // then only correlate until the server is restarted
+        Collector::update_privacy_key(
+            Some(self.tracers.privacy_secret.unwrap_or_else(|| {
+                thread_rng()
+                    .sample_iter(Alphanumeric)
+                    .take(64)
+                    .collect::<Vec<_>>()
+            })),
+            self.tracers.privacy_rotate.as_secs(),
+        );
+
         // Spawn tracers
         for tracer in self.tracers.subscribers {
             tracer.typ.spawn(
                 SubscriberBuilder::new(tracer.id)
                     .with_interests(tracer.interests)
-                    .with_lossy(tracer.lossy),
+                    .with_lossy(tracer.lossy)
+                    .with_privacy(tracer.privacy),
                 is_enterprise,
             );
         }
@@ -54,14 +70,24 @@ impl Telemetry {
         }
 
         // Activate new tracers or update existing ones
+        Collector::update_privacy_key(
+            self.tracers.privacy_secret,
+            self.tracers.privacy_rotate.as_secs(),
+        );
         for tracer in self.tracers.subscribers {
             if active_subscribers.contains(&tracer.id) {
-                Collector::update_subscriber(tracer.id, tracer.interests, tracer.lossy);
+                Collector::update_subscriber(
+                    tracer.id,
+                    tracer.interests,
+                    tracer.lossy,
+                    tracer.privacy,
+                );
             } else {
                 tracer.typ.spawn(
                     SubscriberBuilder::new(tracer.id)
                         .with_interests(tracer.interests)
-                        .with_lossy(tracer.lossy),
+                        .with_lossy(tracer.lossy)
+                        .with_privacy(tracer.privacy),
                     is_enterprise,
                 );
             }
@@ -0,0 +1,361 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use chrono::{DateTime, Datelike};
+use directory::{backend::internal::manage::ManageDirectory, Type};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{key::DeserializeBigEndian, now, BatchBuilder, TaskClass, ValueClass},
+    IterateParams, ValueKey, U64_LEN,
+};
+use trc::AddContext;
+
+use crate::Server;
+
+const VOLUME_PREFIX: &[u8] = b"volume.";
+const DAY: u64 = 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeEvent {
+    Received,
+    Sent,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeCounters {
+    pub received: u64,
+    pub sent: u64,
+    pub bytes_received: u64,
+    pub bytes_sent: u64,
+    pub rejected: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumePeriod {
+    Day,
+    Month,
+}
+
+/// Message volume of a local domain over a day or, once the retention
+/// period has passed, over a whole month.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeRecord {
+    pub period: VolumePeriod,
+    pub start: u32,
+    pub domain: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<u32>,
+    #[serde(flatten)]
+    pub counters: VolumeCounters,
+}
+
+impl Server {
+    pub fn record_volume(&self, domain: &str, event: VolumeEvent, bytes: u64) {
+        if !self.core.volume.enable || domain.is_empty() {
+            return;
+        }
+
+        let day = (now() / DAY) as u32;
+        let mut stats = self.inner.data.volume_stats.lock();
+        let counters = stats.entry((day, domain.to_lowercase())).or_default();
+        match event {
+            VolumeEvent::Received => {
+                counters.received += 1;
+                counters.bytes_received += bytes;
+            }
+            VolumeEvent::Sent => {
+                counters.sent += 1;
+                counters.bytes_sent += bytes;
+            }
+            VolumeEvent::Rejected => {
+                counters.rejected += 1;
+            }
+        }
+    }
+
+    pub async fn flush_volume_stats(&self) -> trc::Result<()> {
+        let pending = std::mem::take(&mut *self.inner.data.volume_stats.lock());
+        if !pending.is_empty() {
+            if let Err(err) = self.write_volume_stats(&pending).await {
+                // Keep the counters for the next flush
+                let mut stats = self.inner.data.volume_stats.lock();
+                for (key, counters) in pending {
+                    stats.entry(key).or_default().add(&counters);
+                }
+                return Err(err);
+            }
+        }
+
+        self.rollup_volume_stats().await
+    }
+
+    pub async fn query_volume_stats(
+        &self,
+        from_day: u32,
+        to_day: u32,
+    ) -> trc::Result<Vec<VolumeRecord>> {
+        // Each node writes its own records, they are merged here
+        let mut merged: AHashMap<(VolumePeriod, u32, String), VolumeRecord> = AHashMap::new();
+        for (period, from, to) in [
+            (VolumePeriod::Day, from_day, to_day),
+            (VolumePeriod::Month, month_of(from_day), month_of(to_day)),
+        ] {
+            for (_, record) in self.iterate_volume_stats(period, from, to + 1).await? {
+                merged
+                    .entry((record.period, record.start, record.domain.clone()))
+                    .and_modify(|entry| entry.counters.add(&record.counters))
+                    .or_insert(record);
+            }
+        }
+
+        let mut records = merged.into_values().collect::<Vec<_>>();
+        records.sort_unstable_by(|a, b| {
+            (a.period, a.start, &a.domain).cmp(&(b.period, b.start, &b.domain))
+        });
+        Ok(records)
+    }
+
+    async fn write_volume_stats(
+        &self,
+        pending: &AHashMap<(u32, String), VolumeCounters>,
+    ) -> trc::Result<()> {
+        let node_id = self.core.network.node_id;
+        let mut tenants: AHashMap<&str, Option<Option<u32>>> = AHashMap::new();
+        let mut batch = BatchBuilder::new();
+
+        for ((day, domain), counters) in pending {
+            // Only local domains are tracked, this keeps spoofed sender
+            // domains from growing the statistics without bounds
+            let tenant_id = match tenants.get(domain.as_str()) {
+                Some(tenant_id) => *tenant_id,
+                None => {
+                    let tenant_id = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal_info(domain)
+                        .await
+                        .caused_by(trc::location!())?
+                        .filter(|info| info.typ == Type::Domain)
+                        .map(|info| info.tenant);
+                    tenants.insert(domain.as_str(), tenant_id);
+                    tenant_id
+                }
+            };
+            let Some(tenant_id) = tenant_id else {
+                continue;
+            };
+
+            let mut record = self
+                .get_volume_record(VolumePeriod::Day, *day, domain, node_id)
+                .await?
+                .unwrap_or_else(|| VolumeRecord {
+                    period: VolumePeriod::Day,
+                    start: *day,
+                    domain: domain.clone(),
+                    tenant_id,
+                    counters: VolumeCounters::default(),
+                });
+            record.tenant_id = tenant_id;
+            record.counters.add(counters);
+            batch.set(
+                ValueClass::Task(TaskClass::State(volume_key(
+                    VolumePeriod::Day,
+                    *day,
+                    domain,
+                    node_id,
+                ))),
+                serde_json::to_vec(&record).unwrap_or_default(),
+            );
+        }
+
+        if !batch.is_empty() {
+            self.core
+                .storage
+                .data
+                .write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+
+    async fn rollup_volume_stats(&self) -> trc::Result<()> {
+        let retention_days = self.core.volume.retention_days;
+        let today = (now() / DAY) as u32;
+        if retention_days == 0 || today <= retention_days {
+            return Ok(());
+        }
+
+        // Each node rolls up its own records so nodes never write the same keys
+        let node_id = self.core.network.node_id;
+        let mut months: AHashMap<(u32, String), VolumeRecord> = AHashMap::new();
+        let mut batch = BatchBuilder::new();
+        for (record_node_id, record) in self
+            .iterate_volume_stats(VolumePeriod::Day, 0, today - retention_days)
+            .await?
+            .into_iter()
+            .filter(|(record_node_id, _)| *record_node_id == node_id)
+        {
+            batch.clear(ValueClass::Task(TaskClass::State(volume_key(
+                VolumePeriod::Day,
+                record.start,
+                &record.domain,
+                record_node_id,
+            ))));
+
+            let month = month_of(record.start);
+            let entry = months
+                .entry((month, record.domain.clone()))
+                .or_insert_with(|| VolumeRecord {
+                    period: VolumePeriod::Month,
+                    start: month,
+                    domain: record.domain,
+                    tenant_id: record.tenant_id,
+                    counters: VolumeCounters::default(),
+                });
+            entry.counters.add(&record.counters);
+        }
+
+        if months.is_empty() {
+            return Ok(());
+        }
+
+        for ((month, domain), mut record) in months {
+            if let Some(existing) = self
+                .get_volume_record(VolumePeriod::Month, month, &domain, node_id)
+                .await?
+            {
+                record.counters.add(&existing.counters);
+            }
+            batch.set(
+                ValueClass::Task(TaskClass::State(volume_key(
+                    VolumePeriod::Month,
+                    month,
+                    &domain,
+                    node_id,
+                ))),
+                serde_json::to_vec(&record).unwrap_or_default(),
+            );
+        }
+
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn get_volume_record(
+        &self,
+        period: VolumePeriod,
+        start: u32,
+        domain: &str,
+        node_id: u64,
+    ) -> trc::Result<Option<VolumeRecord>> {
+        self.core
+            .storage
+            .data
+            .get_value::<String>(ValueKey::from(ValueClass::Task(TaskClass::State(
+                volume_key(period, start, domain, node_id),
+            ))))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    async fn iterate_volume_stats(
+        &self,
+        period: VolumePeriod,
+        from: u32,
+        to: u32,
+    ) -> trc::Result<Vec<(u64, VolumeRecord)>> {
+        let from_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            period_key(period, from),
+        )));
+        let to_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            period_key(period, to),
+        )));
+
+        let mut records = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
+                    // Keys end with the id of the node that wrote them
+                    let node_id = key.deserialize_be_u64(key.len().saturating_sub(U64_LEN))?;
+                    if let Ok(record) = serde_json::from_slice::<VolumeRecord>(value) {
+                        records.push((node_id, record));
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(records)
+    }
+}
+
+impl VolumeCounters {
+    pub fn add(&mut self, other: &VolumeCounters) {
+        self.received += other.received;
+        self.sent += other.sent;
+        self.bytes_received += other.bytes_received;
+        self.bytes_sent += other.bytes_sent;
+        self.rejected += other.rejected;
+    }
+}
+
+impl VolumeRecord {
+    /// The period formatted as YYYY-MM-DD for days and YYYY-MM for months.
+    pub fn date(&self) -> String {
+        match self.period {
+            VolumePeriod::Day => DateTime::from_timestamp(self.start as i64 * DAY as i64, 0)
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            VolumePeriod::Month => {
+                format!("{:04}-{:02}", self.start / 12, self.start % 12 + 1)
+            }
+        }
+    }
+}
+
+fn month_of(day: u32) -> u32 {
+    DateTime::from_timestamp(day as i64 * DAY as i64, 0)
+        .map(|date| date.year() as u32 * 12 + date.month0())
+        .unwrap_or_default()
+}
+
+fn period_key(period: VolumePeriod, start: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(VOLUME_PREFIX.len() + 1 + std::mem::size_of::<u32>());
+    key.extend_from_slice(VOLUME_PREFIX);
+    key.push(match period {
+        VolumePeriod::Day => b'd',
+        VolumePeriod::Month => b'm',
+    });
+    key.extend_from_slice(&start.to_be_bytes());
+    key
+}
+
+fn volume_key(period: VolumePeriod, start: u32, domain: &str, node_id: u64) -> Vec<u8> {
+    let mut key = period_key(period, start);
+    key.extend_from_slice(domain.as_bytes());
+    key.push(0);
+    key.extend_from_slice(&node_id.to_be_bytes());
+    key
+}
@@ -0,0 +1,589 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::backend::internal::{
+    lookup::DirectoryStore,
+    manage::{err_code, ErrorCode, ManageDirectory},
+};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{BatchBuilder, Bincode, DirectoryClass, ValueClass},
+    Serialize as _, ValueKey,
+};
+use trc::AddContext;
+use utils::locale::Locale;
+
+use crate::Server;
+
+// Templates that are not assigned to a tenant apply server-wide
+pub const TEMPLATE_GLOBAL: u32 = u32::MAX;
+
+const MAX_FROM_NAME_LEN: usize = 128;
+const MAX_SUBJECT_LEN: usize = 255;
+const MAX_BODY_LEN: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TemplateKind {
+    DsnSuccess,
+    DsnDelay,
+    DsnFailure,
+    DsnPartial,
+    DsnMixed,
+    QuotaWarning,
+    Welcome,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TemplateVariable {
+    Sender,
+    Hostname,
+    Name,
+    Email,
+    QuotaUsed,
+    QuotaLimit,
+    QuotaPercent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateSource {
+    #[serde(default)]
+    pub from_name: Option<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    from_name: Option<Vec<Segment>>,
+    subject: Vec<Segment>,
+    body: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderedTemplate {
+    pub from_name: Option<String>,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Variable(TemplateVariable),
+}
+
+impl TemplateKind {
+    pub const ALL: &'static [TemplateKind] = &[
+        TemplateKind::DsnSuccess,
+        TemplateKind::DsnDelay,
+        TemplateKind::DsnFailure,
+        TemplateKind::DsnPartial,
+        TemplateKind::DsnMixed,
+        TemplateKind::QuotaWarning,
+        TemplateKind::Welcome,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        TemplateKind::ALL
+            .iter()
+            .find(|kind| kind.as_str() == value)
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemplateKind::DsnSuccess => "dsn-success",
+            TemplateKind::DsnDelay => "dsn-delay",
+            TemplateKind::DsnFailure => "dsn-failure",
+            TemplateKind::DsnPartial => "dsn-partial",
+            TemplateKind::DsnMixed => "dsn-mixed",
+            TemplateKind::QuotaWarning => "quota-warning",
+            TemplateKind::Welcome => "welcome",
+        }
+    }
+
+    // Stored as part of the key, ids must never be reused
+    pub fn id(&self) -> u8 {
+        match self {
+            TemplateKind::DsnSuccess => 0,
+            TemplateKind::DsnDelay => 1,
+            TemplateKind::DsnFailure => 2,
+            TemplateKind::DsnPartial => 3,
+            TemplateKind::DsnMixed => 4,
+            TemplateKind::QuotaWarning => 5,
+            TemplateKind::Welcome => 6,
+        }
+    }
+
+    pub fn variables(&self) -> &'static [TemplateVariable] {
+        match self {
+            TemplateKind::DsnSuccess
+            | TemplateKind::DsnDelay
+            | TemplateKind::DsnFailure
+            | TemplateKind::DsnPartial
+            | TemplateKind::DsnMixed => &[TemplateVariable::Sender, TemplateVariable::Hostname],
+            TemplateKind::QuotaWarning => &[
+                TemplateVariable::Name,
+                TemplateVariable::Email,
+                TemplateVariable::Hostname,
+                TemplateVariable::QuotaUsed,
+                TemplateVariable::QuotaLimit,
+                TemplateVariable::QuotaPercent,
+            ],
+            TemplateKind::Welcome => &[
+                TemplateVariable::Name,
+                TemplateVariable::Email,
+                TemplateVariable::Hostname,
+            ],
+        }
+    }
+
+    pub fn default_source(&self) -> TemplateSource {
+        let (subject, body) = match self {
+            TemplateKind::DsnSuccess => (
+                "Successfully delivered message",
+                "Your message has been successfully delivered to the following recipients:",
+            ),
+            TemplateKind::DsnDelay => (
+                "Warning: Delay in message delivery",
+                "There was a temporary problem delivering your message to the following recipients:",
+            ),
+            TemplateKind::DsnFailure => (
+                "Failed to deliver message",
+                "Your message could not be delivered to the following recipients:",
+            ),
+            TemplateKind::DsnPartial => (
+                "Partially delivered message",
+                "Your message has been partially delivered:",
+            ),
+            TemplateKind::DsnMixed => (
+                "Warning: Temporary and permanent failures during message delivery",
+                "Your message could not be delivered to some recipients:",
+            ),
+            TemplateKind::QuotaWarning => (
+                "Your mailbox is almost full",
+                concat!(
+                    "Hello {{name}},\r\n\r\n",
+                    "Your mailbox {{email}} is using {{quota_used}} of {{quota_limit}} ",
+                    "bytes ({{quota_percent}}%).\r\n",
+                    "Please delete some messages to avoid losing incoming mail.\r\n"
+                ),
+            ),
+            TemplateKind::Welcome => (
+                "Welcome to {{hostname}}",
+                "Hello {{name}},\r\n\r\nYour account {{email}} is ready to use.\r\n",
+            ),
+        };
+
+        TemplateSource {
+            from_name: None,
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    /// Translation of the default template, only delivery reports are
+    /// translated and stored templates are always used as they are.
+    pub fn localized_source(&self, locale: Locale) -> TemplateSource {
+        let (subject, body) = match (locale, self) {
+            (Locale::De, TemplateKind::DsnSuccess) => (
+                "Nachricht erfolgreich zugestellt",
+                "Ihre Nachricht wurde erfolgreich an die folgenden Empfänger zugestellt:",
+            ),
+            (Locale::De, TemplateKind::DsnDelay) => (
+                "Warnung: Verzögerung bei der Zustellung",
+                "Bei der Zustellung Ihrer Nachricht an die folgenden Empfänger ist ein vorübergehendes Problem aufgetreten:",
+            ),
+            (Locale::De, TemplateKind::DsnFailure) => (
+                "Nachricht konnte nicht zugestellt werden",
+                "Ihre Nachricht konnte an die folgenden Empfänger nicht zugestellt werden:",
+            ),
+            (Locale::De, TemplateKind::DsnPartial) => (
+                "Nachricht teilweise zugestellt",
+                "Ihre Nachricht wurde teilweise zugestellt:",
+            ),
+            (Locale::De, TemplateKind::DsnMixed) => (
+                "Warnung: Vorübergehende und dauerhafte Fehler bei der Zustellung",
+                "Ihre Nachricht konnte an einige Empfänger nicht zugestellt werden:",
+            ),
+            (Locale::Es, TemplateKind::DsnSuccess) => (
+                "Mensaje entregado correctamente",
+                "Su mensaje se ha entregado correctamente a los siguientes destinatarios:",
+            ),
+            (Locale::Es, TemplateKind::DsnDelay) => (
+                "Aviso: Retraso en la entrega del mensaje",
+                "Se ha producido un problema temporal al entregar su mensaje a los siguientes destinatarios:",
+            ),
+            (Locale::Es, TemplateKind::DsnFailure) => (
+                "No se pudo entregar el mensaje",
+                "No se ha podido entregar su mensaje a los siguientes destinatarios:",
+            ),
+            (Locale::Es, TemplateKind::DsnPartial) => (
+                "Mensaje entregado parcialmente",
+                "Su mensaje se ha entregado parcialmente:",
+            ),
+            (Locale::Es, TemplateKind::DsnMixed) => (
+                "Aviso: Errores temporales y permanentes en la entrega del mensaje",
+                "No se ha podido entregar su mensaje a algunos destinatarios:",
+            ),
+            (Locale::Fr, TemplateKind::DsnSuccess) => (
+                "Message distribué avec succès",
+                "Votre message a été distribué avec succès aux destinataires suivants :",
+            ),
+            (Locale::Fr, TemplateKind::DsnDelay) => (
+                "Avertissement : retard dans la distribution du message",
+                "Un problème temporaire a empêché la distribution de votre message aux destinataires suivants :",
+            ),
+            (Locale::Fr, TemplateKind::DsnFailure) => (
+                "Échec de la distribution du message",
+                "Votre message n'a pas pu être distribué aux destinataires suivants :",
+            ),
+            (Locale::Fr, TemplateKind::DsnPartial) => (
+                "Message partiellement distribué",
+                "Votre message a été partiellement distribué :",
+            ),
+            (Locale::Fr, TemplateKind::DsnMixed) => (
+                "Avertissement : échecs temporaires et permanents lors de la distribution",
+                "Votre message n'a pas pu être distribué à certains destinataires :",
+            ),
+            (Locale::Pt, TemplateKind::DsnSuccess) => (
+                "Mensagem entregue com sucesso",
+                "A sua mensagem foi entregue com sucesso aos seguintes destinatários:",
+            ),
+            (Locale::Pt, TemplateKind::DsnDelay) => (
+                "Aviso: Atraso na entrega da mensagem",
+                "Ocorreu um problema temporário ao entregar a sua mensagem aos seguintes destinatários:",
+            ),
+            (Locale::Pt, TemplateKind::DsnFailure) => (
+                "Falha na entrega da mensagem",
+                "Não foi possível entregar a sua mensagem aos seguintes destinatários:",
+            ),
+            (Locale::Pt, TemplateKind::DsnPartial) => (
+                "Mensagem entregue parcialmente",
+                "A sua mensagem foi entregue parcialmente:",
+            ),
+            (Locale::Pt, TemplateKind::DsnMixed) => (
+                "Aviso: Falhas temporárias e permanentes na entrega da mensagem",
+                "Não foi possível entregar a sua mensagem a alguns destinatários:",
+            ),
+            _ => return self.default_source(),
+        };
+
+        TemplateSource {
+            from_name: None,
+            subject: subject.to_string(),
+            body: body.to_string(),
+        }
+    }
+}
+
+impl TemplateVariable {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "sender" => Some(TemplateVariable::Sender),
+            "hostname" => Some(TemplateVariable::Hostname),
+            "name" => Some(TemplateVariable::Name),
+            "email" => Some(TemplateVariable::Email),
+            "quota_used" => Some(TemplateVariable::QuotaUsed),
+            "quota_limit" => Some(TemplateVariable::QuotaLimit),
+            "quota_percent" => Some(TemplateVariable::QuotaPercent),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemplateVariable::Sender => "sender",
+            TemplateVariable::Hostname => "hostname",
+            TemplateVariable::Name => "name",
+            TemplateVariable::Email => "email",
+            TemplateVariable::QuotaUsed => "quota_used",
+            TemplateVariable::QuotaLimit => "quota_limit",
+            TemplateVariable::QuotaPercent => "quota_percent",
+        }
+    }
+
+    pub fn sample(&self) -> &'static str {
+        match self {
+            TemplateVariable::Sender => "jane@example.org",
+            TemplateVariable::Hostname => "mail.example.org",
+            TemplateVariable::Name => "Jane Doe",
+            TemplateVariable::Email => "jane@example.org",
+            TemplateVariable::QuotaUsed => "943718400",
+            TemplateVariable::QuotaLimit => "1073741824",
+            TemplateVariable::QuotaPercent => "88",
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(kind: TemplateKind, source: &TemplateSource) -> trc::Result<Self> {
+        let from_name = source
+            .from_name
+            .as_deref()
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| parse_line(kind, "fromName", name, MAX_FROM_NAME_LEN))
+            .transpose()?;
+        let subject = parse_line(kind, "subject", &source.subject, MAX_SUBJECT_LEN)?;
+
+        if source.body.len() > MAX_BODY_LEN {
+            return Err(invalid(
+                "body",
+                format!("Body exceeds the maximum size of {MAX_BODY_LEN} bytes"),
+            ));
+        }
+        let body = parse_segments(kind, "body", &source.body)?
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => Segment::Text(normalize_line_endings(&text)),
+                variable => variable,
+            })
+            .collect();
+
+        Ok(Template {
+            from_name,
+            subject,
+            body,
+        })
+    }
+
+    pub fn render(&self, values: &[(TemplateVariable, &str)]) -> RenderedTemplate {
+        RenderedTemplate {
+            from_name: self
+                .from_name
+                .as_ref()
+                .map(|from_name| render_segments(from_name, values, true)),
+            subject: render_segments(&self.subject, values, true),
+            body: render_segments(&self.body, values, false),
+        }
+    }
+
+    pub fn preview(&self, kind: TemplateKind) -> RenderedTemplate {
+        self.render(
+            &kind
+                .variables()
+                .iter()
+                .map(|variable| (*variable, variable.sample()))
+                .collect::<Vec<_>>(),
+        )
+    }
+}
+
+impl Server {
+    pub async fn template(&self, tenant_id: Option<u32>, kind: TemplateKind) -> Template {
+        self.localized_template(tenant_id, kind, Locale::default())
+            .await
+    }
+
+    pub async fn localized_template(
+        &self,
+        tenant_id: Option<u32>,
+        kind: TemplateKind,
+        locale: Locale,
+    ) -> Template {
+        // Tenant overrides take precedence over the global ones
+        for tenant_id in [tenant_id, Some(TEMPLATE_GLOBAL)].into_iter().flatten() {
+            match self.template_source(tenant_id, kind).await {
+                Ok(Some(source)) => match Template::parse(kind, &source) {
+                    Ok(template) => return template,
+                    Err(err) => {
+                        trc::error!(err
+                            .details("Failed to parse stored template")
+                            .caused_by(trc::location!()));
+                    }
+                },
+                Ok(None) => {}
+                Err(err) => {
+                    trc::error!(err
+                        .details("Failed to obtain template")
+                        .caused_by(trc::location!()));
+                }
+            }
+        }
+
+        Template::parse(kind, &kind.localized_source(locale)).expect("Invalid default template")
+    }
+
+    pub async fn template_source(
+        &self,
+        tenant_id: u32,
+        kind: TemplateKind,
+    ) -> trc::Result<Option<TemplateSource>> {
+        self.core
+            .storage
+            .data
+            .get_value::<Bincode<TemplateSource>>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::Template {
+                    tenant_id,
+                    kind: kind.id(),
+                },
+            )))
+            .await
+            .map(|source| source.map(|source| source.inner))
+            .caused_by(trc::location!())
+    }
+
+    pub async fn set_template(
+        &self,
+        tenant_id: u32,
+        kind: TemplateKind,
+        source: TemplateSource,
+    ) -> trc::Result<()> {
+        // Syntax errors are reported at upload rather than at generation time
+        Template::parse(kind, &source)?;
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Directory(DirectoryClass::Template {
+                tenant_id,
+                kind: kind.id(),
+            }),
+            Bincode::new(source).serialize(),
+        );
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .map(|_| ())
+            .caused_by(trc::location!())
+    }
+
+    pub async fn delete_template(&self, tenant_id: u32, kind: TemplateKind) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Directory(DirectoryClass::Template {
+            tenant_id,
+            kind: kind.id(),
+        }));
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .map(|_| ())
+            .caused_by(trc::location!())
+    }
+
+    pub async fn tenant_by_address(&self, address: &str) -> trc::Result<Option<u32>> {
+        let store = &self.core.storage.data;
+        if let Some(account_id) = store
+            .email_to_id(address)
+            .await
+            .caused_by(trc::location!())?
+        {
+            return store
+                .get_principal(account_id)
+                .await
+                .caused_by(trc::location!())
+                .map(|principal| principal.and_then(|p| p.tenant()));
+        }
+
+        // Addresses without an account belong to the tenant owning the domain
+        match address.rsplit_once('@') {
+            Some((_, domain)) => store
+                .get_principal_info(domain)
+                .await
+                .caused_by(trc::location!())
+                .map(|info| info.and_then(|info| info.tenant)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_line(
+    kind: TemplateKind,
+    field: &'static str,
+    value: &str,
+    max_len: usize,
+) -> trc::Result<Vec<Segment>> {
+    if value.len() > max_len {
+        Err(invalid(
+            field,
+            format!("Field exceeds the maximum length of {max_len} bytes"),
+        ))
+    } else if value.chars().any(|ch| ch.is_control()) {
+        Err(invalid(field, "Field cannot contain control characters"))
+    } else {
+        parse_segments(kind, field, value)
+    }
+}
+
+fn parse_segments(
+    kind: TemplateKind,
+    field: &'static str,
+    value: &str,
+) -> trc::Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut value = value;
+
+    while let Some(start) = value.find("{{") {
+        if start > 0 {
+            segments.push(Segment::Text(value[..start].to_string()));
+        }
+        let rest = &value[start + 2..];
+        let end = rest
+            .find("}}")
+            .ok_or_else(|| invalid(field, "Unterminated variable, expected \"}}\""))?;
+        let name = rest[..end].trim();
+        let variable = TemplateVariable::parse(name)
+            .filter(|variable| kind.variables().contains(variable))
+            .ok_or_else(|| {
+                invalid(
+                    field,
+                    format!(
+                        "Variable {name:?} is not available for {:?} templates",
+                        kind.as_str()
+                    ),
+                )
+            })?;
+        segments.push(Segment::Variable(variable));
+        value = &rest[end + 2..];
+    }
+
+    if !value.is_empty() {
+        segments.push(Segment::Text(value.to_string()));
+    }
+
+    Ok(segments)
+}
+
+fn render_segments(
+    segments: &[Segment],
+    values: &[(TemplateVariable, &str)],
+    is_single_line: bool,
+) -> String {
+    let mut result = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(text) => result.push_str(text),
+            Segment::Variable(variable) => {
+                if let Some((_, value)) = values.iter().find(|(v, _)| v == variable) {
+                    if is_single_line {
+                        // Prevent header injection through substituted values
+                        result.extend(
+                            value
+                                .chars()
+                                .map(|ch| if ch.is_control() { ' ' } else { ch }),
+                        );
+                    } else {
+                        result.push_str(value);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+fn invalid(field: &'static str, reason: impl Into<trc::Value>) -> trc::Error {
+    err_code(ErrorCode::TemplateInvalid, "Invalid template", Some(reason)).ctx(trc::Key::Key, field)
+}
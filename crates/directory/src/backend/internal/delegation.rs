@@ -0,0 +1,278 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use store::{
+    write::{key::DeserializeBigEndian, BatchBuilder, Bincode, DirectoryClass, ValueClass},
+    Deserialize, IterateParams, Serialize, Store, ValueKey, U32_LEN,
+};
+use trc::AddContext;
+
+use crate::{Permission, Principal, Type};
+
+use super::{
+    manage::{ManageDirectory, PrincipalFilter, PrincipalList},
+    PrincipalField,
+};
+
+/// Permissions that can be delegated to manage the principals of a domain.
+pub const DELEGABLE_PERMISSIONS: &[Permission] = &[
+    Permission::IndividualList,
+    Permission::IndividualGet,
+    Permission::IndividualCreate,
+    Permission::IndividualUpdate,
+    Permission::IndividualDelete,
+    Permission::GroupList,
+    Permission::GroupGet,
+    Permission::GroupCreate,
+    Permission::GroupUpdate,
+    Permission::GroupDelete,
+    Permission::MailingListList,
+    Permission::MailingListGet,
+    Permission::MailingListCreate,
+    Permission::MailingListUpdate,
+    Permission::MailingListDelete,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DomainDelegation {
+    pub domain_id: u32,
+    pub permissions: Vec<Permission>,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait DomainDelegationStore: Sync + Send {
+    async fn get_delegations(&self, principal_id: u32) -> trc::Result<Vec<DomainDelegation>>;
+    async fn get_delegated_domains(
+        &self,
+        principal_id: u32,
+        permission: Permission,
+    ) -> trc::Result<Vec<String>>;
+    async fn set_delegation(
+        &self,
+        principal_id: u32,
+        delegation: DomainDelegation,
+    ) -> trc::Result<()>;
+    async fn delete_delegation(&self, principal_id: u32, domain_id: u32) -> trc::Result<()>;
+    async fn delete_principal_delegations(&self, principal: &Principal) -> trc::Result<()>;
+    #[allow(clippy::too_many_arguments)]
+    async fn list_delegated_principals(
+        &self,
+        filter: Option<&PrincipalFilter>,
+        tenant_id: Option<u32>,
+        types: &[Type],
+        fields: &[PrincipalField],
+        domains: &[String],
+        page: usize,
+        limit: usize,
+    ) -> trc::Result<PrincipalList>;
+}
+
+impl DomainDelegationStore for Store {
+    async fn get_delegations(&self, principal_id: u32) -> trc::Result<Vec<DomainDelegation>> {
+        let mut delegations = Vec::new();
+        self.iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Directory(DirectoryClass::Delegation {
+                    principal_id,
+                    domain_id: 0,
+                })),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::Delegation {
+                    principal_id,
+                    domain_id: u32::MAX,
+                })),
+            ),
+            |key, value| {
+                delegations.push(DomainDelegation {
+                    domain_id: key.deserialize_be_u32(1 + U32_LEN)?,
+                    permissions: Bincode::<Vec<Permission>>::deserialize(value)?.inner,
+                });
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())
+        .map(|_| delegations)
+    }
+
+    async fn get_delegated_domains(
+        &self,
+        principal_id: u32,
+        permission: Permission,
+    ) -> trc::Result<Vec<String>> {
+        let mut domains = Vec::new();
+        for delegation in self
+            .get_delegations(principal_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if delegation.permissions.contains(&permission) {
+                if let Some(domain) = self
+                    .get_principal(delegation.domain_id)
+                    .await
+                    .caused_by(trc::location!())?
+                    .filter(|p| p.typ == Type::Domain)
+                {
+                    domains.push(domain.name().to_lowercase());
+                }
+            }
+        }
+
+        Ok(domains)
+    }
+
+    async fn set_delegation(
+        &self,
+        principal_id: u32,
+        delegation: DomainDelegation,
+    ) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Directory(DirectoryClass::Delegation {
+                principal_id,
+                domain_id: delegation.domain_id,
+            }),
+            Bincode::new(delegation.permissions).serialize(),
+        );
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn delete_delegation(&self, principal_id: u32, domain_id: u32) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Directory(DirectoryClass::Delegation {
+            principal_id,
+            domain_id,
+        }));
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn delete_principal_delegations(&self, principal: &Principal) -> trc::Result<()> {
+        // Grants held by the principal
+        self.delete_range(
+            ValueKey::from(ValueClass::Directory(DirectoryClass::Delegation {
+                principal_id: principal.id,
+                domain_id: 0,
+            })),
+            ValueKey::from(ValueClass::Directory(DirectoryClass::Delegation {
+                principal_id: principal.id,
+                domain_id: u32::MAX,
+            })),
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        // Grants on a deleted domain
+        if principal.typ == Type::Domain {
+            let mut holders = Vec::new();
+            self.iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Directory(DirectoryClass::Delegation {
+                        principal_id: 0,
+                        domain_id: 0,
+                    })),
+                    ValueKey::from(ValueClass::Directory(DirectoryClass::Delegation {
+                        principal_id: u32::MAX,
+                        domain_id: u32::MAX,
+                    })),
+                )
+                .no_values(),
+                |key, _| {
+                    if key.deserialize_be_u32(1 + U32_LEN)? == principal.id {
+                        holders.push(key.deserialize_be_u32(1)?);
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            if !holders.is_empty() {
+                let mut batch = BatchBuilder::new();
+                for principal_id in holders {
+                    batch.clear(ValueClass::Directory(DirectoryClass::Delegation {
+                        principal_id,
+                        domain_id: principal.id,
+                    }));
+                }
+                self.write(batch.build())
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn list_delegated_principals(
+        &self,
+        filter: Option<&PrincipalFilter>,
+        tenant_id: Option<u32>,
+        types: &[Type],
+        fields: &[PrincipalField],
+        domains: &[String],
+        page: usize,
+        limit: usize,
+    ) -> trc::Result<PrincipalList> {
+        // Names and addresses are needed to match the delegated domains
+        let mut principals = self
+            .list_principals(filter, tenant_id, types, &[], 0, 0)
+            .await
+            .caused_by(trc::location!())?
+            .items
+            .into_iter()
+            .filter(|principal| principal.is_under_domains(domains))
+            .collect::<Vec<_>>();
+        let total = principals.len() as u64;
+
+        if limit > 0 {
+            let offset = limit * page.saturating_sub(1);
+            principals = principals.into_iter().skip(offset).take(limit).collect();
+        }
+        if !fields.is_empty() {
+            for principal in &mut principals {
+                principal.fields.retain(|k, _| fields.contains(k));
+            }
+        }
+
+        Ok(PrincipalList {
+            items: principals,
+            total,
+        })
+    }
+}
+
+impl Principal {
+    /// Returns the domains of the principal's name and e-mail addresses.
+    pub fn domains(&self) -> Vec<String> {
+        let mut domains: Vec<String> = Vec::new();
+        for address in std::iter::once(self.name()).chain(
+            self.get_str_array(PrincipalField::Emails)
+                .unwrap_or_default()
+                .iter()
+                .map(|v| v.as_str()),
+        ) {
+            if let Some((_, domain)) = address.rsplit_once('@') {
+                let domain = domain.to_lowercase();
+                if !domains.contains(&domain) {
+                    domains.push(domain);
+                }
+            }
+        }
+        domains
+    }
+
+    /// A principal falls under a set of domains when its name and all of its
+    /// addresses belong to them.
+    pub fn is_under_domains(&self, domains: &[String]) -> bool {
+        let principal_domains = self.domains();
+        !principal_domains.is_empty() && principal_domains.iter().all(|d| domains.contains(d))
+    }
+}
@@ -4,16 +4,24 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use futures::{StreamExt, TryStreamExt};
 use mail_send::Credentials;
 use store::{
-    write::{DirectoryClass, ValueClass},
+    write::{assert::HashedValue, DirectoryClass, ValueClass},
     Deserialize, IterateParams, Store, ValueKey,
 };
 use trc::AddContext;
 
-use crate::{backend::RcptType, Principal, QueryBy, Type};
+use crate::{
+    backend::{DomainRouting, ListPolicy, PostingPolicy, RcptType, Subaddressing},
+    core::secret::hash_secret,
+    Principal, QueryBy, Type,
+};
 
-use super::{manage::ManageDirectory, PrincipalField, PrincipalInfo};
+use super::{
+    manage::{ManageDirectory, UpdatePrincipal, MAX_CONCURRENT_LOOKUPS},
+    PrincipalField, PrincipalInfo, PrincipalUpdate, PrincipalValue, SpecialSecrets,
+};
 
 #[allow(async_fn_in_trait)]
 pub trait DirectoryStore: Sync + Send {
@@ -22,9 +30,16 @@ pub trait DirectoryStore: Sync + Send {
         by: QueryBy<'_>,
         return_member_of: bool,
     ) -> trc::Result<Option<Principal>>;
+    async fn query_and_upgrade(
+        &self,
+        by: QueryBy<'_>,
+        return_member_of: bool,
+    ) -> trc::Result<Option<Principal>>;
     async fn email_to_id(&self, address: &str) -> trc::Result<Option<u32>>;
     async fn is_local_domain(&self, domain: &str) -> trc::Result<bool>;
     async fn rcpt(&self, address: &str) -> trc::Result<RcptType>;
+    async fn domain_routing(&self, domain: &str) -> trc::Result<Option<DomainRouting>>;
+    async fn list_policy(&self, address: &str) -> trc::Result<Option<ListPolicy>>;
     async fn vrfy(&self, address: &str) -> trc::Result<Vec<String>>;
     async fn expn(&self, address: &str) -> trc::Result<Vec<String>>;
     async fn expn_by_id(&self, id: u32) -> trc::Result<Vec<String>>;
@@ -36,46 +51,17 @@ impl DirectoryStore for Store {
         by: QueryBy<'_>,
         return_member_of: bool,
     ) -> trc::Result<Option<Principal>> {
-        let (account_id, secret) = match by {
-            QueryBy::Name(name) => (self.get_principal_id(name).await?, None),
-            QueryBy::Id(account_id) => (account_id.into(), None),
-            QueryBy::Credentials(credentials) => match credentials {
-                Credentials::Plain { username, secret } => (
-                    self.get_principal_id(username).await?,
-                    secret.as_str().into(),
-                ),
-                Credentials::OAuthBearer { token } => {
-                    (self.get_principal_id(token).await?, token.as_str().into())
-                }
-                Credentials::XOauth2 { username, secret } => (
-                    self.get_principal_id(username).await?,
-                    secret.as_str().into(),
-                ),
-            },
-        };
-
-        if let Some(account_id) = account_id {
-            if let Some(mut principal) = self.get_principal(account_id).await? {
-                if let Some(secret) = secret {
-                    if !principal.verify_secret(secret).await? {
-                        return Ok(None);
-                    }
-                }
+        query_principal(self, by, return_member_of, false).await
+    }
 
-                if return_member_of {
-                    for member in self.get_member_of(principal.id).await? {
-                        let field = match member.typ {
-                            Type::List => PrincipalField::Lists,
-                            Type::Role => PrincipalField::Roles,
-                            _ => PrincipalField::MemberOf,
-                        };
-                        principal.append_int(field, member.principal_id);
-                    }
-                }
-                return Ok(Some(principal));
-            }
-        }
-        Ok(None)
+    /// Same as `query`, but passwords stored using a legacy scheme are
+    /// re-hashed after a successful verification.
+    async fn query_and_upgrade(
+        &self,
+        by: QueryBy<'_>,
+        return_member_of: bool,
+    ) -> trc::Result<Option<Principal>> {
+        query_principal(self, by, return_member_of, true).await
     }
 
     async fn email_to_id(&self, address: &str) -> trc::Result<Option<u32>> {
@@ -101,16 +87,92 @@ impl DirectoryStore for Store {
             )))
             .await?
         {
-            if pinfo.typ != Type::List {
-                Ok(RcptType::Mailbox)
-            } else {
-                self.expn_by_id(pinfo.id).await.map(RcptType::List)
+            match pinfo.typ {
+                Type::List => self.expn_by_id(pinfo.id).await.map(RcptType::List),
+                // Service accounts have no mailbox
+                Type::Service => Ok(RcptType::Invalid),
+                // Aliases are expanded by the caller, which tracks loops
+                Type::Alias => Ok(self
+                    .get_principal(pinfo.id)
+                    .await?
+                    .and_then(|mut p| p.take_str_array(PrincipalField::ForwardTo))
+                    .filter(|forward_to| !forward_to.is_empty())
+                    .map_or(RcptType::Invalid, RcptType::Alias)),
+                _ => Ok(RcptType::Mailbox),
             }
         } else {
             Ok(RcptType::Invalid)
         }
     }
 
+    async fn domain_routing(&self, domain: &str) -> trc::Result<Option<DomainRouting>> {
+        let Some(info) = self
+            .get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::NameToId(domain.as_bytes().to_vec()),
+            )))
+            .await?
+            .filter(|p| p.typ == Type::Domain)
+        else {
+            return Ok(None);
+        };
+        let Some(mut principal) = self.get_principal(info.id).await? else {
+            return Ok(None);
+        };
+        let routing = DomainRouting {
+            catch_all: principal.take_str(PrincipalField::CatchAll),
+            subaddressing: principal
+                .take_str(PrincipalField::Subaddressing)
+                .and_then(|value| Subaddressing::parse(&value)),
+        };
+
+        Ok(Some(routing).filter(|routing| *routing != DomainRouting::default()))
+    }
+
+    async fn list_policy(&self, address: &str) -> trc::Result<Option<ListPolicy>> {
+        let Some(info) = self
+            .get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::EmailToId(address.as_bytes().to_vec()),
+            )))
+            .await?
+            .filter(|p| p.typ == Type::List)
+        else {
+            return Ok(None);
+        };
+        let Some(mut principal) = self.get_principal(info.id).await? else {
+            return Ok(None);
+        };
+        let posting = principal
+            .get_str(PrincipalField::PostingPolicy)
+            .and_then(PostingPolicy::parse)
+            .unwrap_or_default();
+        let reply_to_list = principal
+            .get_int(PrincipalField::ReplyToList)
+            .is_some_and(|v| v != 0);
+        if posting == PostingPolicy::Anyone && !reply_to_list {
+            return Ok(None);
+        }
+
+        Ok(Some(ListPolicy {
+            list_id: info.id,
+            posting,
+            moderators: principal
+                .take_int_array(PrincipalField::Moderators)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|id| id as u32)
+                .collect(),
+            members: if posting == PostingPolicy::Members {
+                self.get_members(info.id).await?
+            } else {
+                Vec::new()
+            },
+            external_members: principal
+                .take_str_array(PrincipalField::ExternalMembers)
+                .unwrap_or_default(),
+            reply_to_list,
+        }))
+    }
+
     async fn vrfy(&self, address: &str) -> trc::Result<Vec<String>> {
         let mut results = Vec::new();
         let address = address.split('@').next().unwrap_or(address);
@@ -126,10 +188,12 @@ impl DirectoryStore for Store {
                     let key =
                         std::str::from_utf8(key.get(1..).unwrap_or_default()).unwrap_or_default();
                     if key.split('@').next().unwrap_or(key).contains(address)
-                        && PrincipalInfo::deserialize(value)
-                            .caused_by(trc::location!())?
-                            .typ
-                            != Type::List
+                        && !matches!(
+                            PrincipalInfo::deserialize(value)
+                                .caused_by(trc::location!())?
+                                .typ,
+                            Type::List | Type::Alias
+                        )
                     {
                         results.push(key.to_string());
                     }
@@ -158,16 +222,20 @@ impl DirectoryStore for Store {
     }
 
     async fn expn_by_id(&self, list_id: u32) -> trc::Result<Vec<String>> {
-        let mut results = Vec::new();
-        for account_id in self.get_members(list_id).await? {
-            if let Some(email) = self
-                .get_principal(account_id)
+        // Members are fetched concurrently, one round-trip at a time is too
+        // slow for large lists
+        let mut results = futures::stream::iter(
+            self.get_members(list_id)
                 .await?
-                .and_then(|mut p| p.take_str(PrincipalField::Emails))
-            {
-                results.push(email);
-            }
-        }
+                .into_iter()
+                .map(|account_id| self.get_principal(account_id)),
+        )
+        .buffered(MAX_CONCURRENT_LOOKUPS)
+        .try_filter_map(|principal| async move {
+            Ok(principal.and_then(|mut p| p.take_str(PrincipalField::Emails)))
+        })
+        .try_collect::<Vec<_>>()
+        .await?;
 
         if let Some(emails) = self
             .get_principal(list_id)
@@ -180,3 +248,179 @@ impl DirectoryStore for Store {
         Ok(results)
     }
 }
+
+async fn query_principal(
+    store: &Store,
+    by: QueryBy<'_>,
+    return_member_of: bool,
+    upgrade_secrets: bool,
+) -> trc::Result<Option<Principal>> {
+    let (account_id, secret) = match by {
+        QueryBy::Name(name) => (store.get_principal_id(name).await?, None),
+        QueryBy::Id(account_id) => (account_id.into(), None),
+        QueryBy::ExternalId(external_id) => (store.external_id_to_id(external_id).await?, None),
+        QueryBy::Certificate(certificate) => (store.certificate_to_id(certificate).await?, None),
+        QueryBy::Credentials(credentials) => match credentials {
+            Credentials::Plain { username, secret } => (
+                store.get_principal_id(username).await?,
+                secret.as_str().into(),
+            ),
+            Credentials::OAuthBearer { token } => {
+                (store.get_principal_id(token).await?, token.as_str().into())
+            }
+            Credentials::XOauth2 { username, secret } => (
+                store.get_principal_id(username).await?,
+                secret.as_str().into(),
+            ),
+        },
+    };
+
+    if let Some(account_id) = account_id {
+        // The hash of the stored value guards the changes made to the
+        // secrets during authentication
+        let principal = if secret.is_some() {
+            store
+                .get_value::<HashedValue<Principal>>(ValueKey::from(ValueClass::Directory(
+                    DirectoryClass::Principal(account_id),
+                )))
+                .await
+                .caused_by(trc::location!())?
+                .map(|mut principal| {
+                    principal.inner.id = account_id;
+                    (principal.inner, Some(principal.hash))
+                })
+        } else {
+            store
+                .get_principal(account_id)
+                .await?
+                .map(|principal| (principal, None))
+        };
+
+        if let Some((mut principal, hash)) = principal {
+            if let (Some(secret), Some(hash)) = (secret, hash) {
+                if !principal.verify_secret(secret).await? {
+                    // Recovery codes can be used in place of the TOTP token
+                    if !consume_recovery_code(store, &mut principal, hash, secret).await? {
+                        return Ok(None);
+                    }
+                } else if upgrade_secrets {
+                    if let Err(err) = upgrade_secret(store, &mut principal, hash, secret).await {
+                        // Concurrent logins or changes fail the assertion, the
+                        // upgrade is attempted again on the next login
+                        if !err.is_assertion_failure() {
+                            trc::error!(err
+                                .details("Failed to upgrade legacy secret")
+                                .account_id(account_id));
+                        }
+                    }
+                }
+            }
+
+            if return_member_of {
+                for member in store.get_member_of(principal.id).await? {
+                    let field = match member.typ {
+                        Type::List => PrincipalField::Lists,
+                        Type::Role => PrincipalField::Roles,
+                        _ => PrincipalField::MemberOf,
+                    };
+                    principal.append_int(field, member.principal_id);
+                }
+            }
+            return Ok(Some(principal));
+        }
+    }
+    Ok(None)
+}
+
+async fn consume_recovery_code(
+    store: &Store,
+    principal: &mut Principal,
+    hash: u64,
+    code: &str,
+) -> trc::Result<bool> {
+    let Some(recovery_code) = principal
+        .verify_recovery_code(code)
+        .await?
+        .map(|s| s.to_string())
+    else {
+        return Ok(false);
+    };
+
+    // Removing the code fails if it was used concurrently. Updates look up
+    // principals themselves, so the future is boxed to allow the recursion.
+    match Box::pin(
+        store.update_principal(
+            UpdatePrincipal::by_id(principal.id)
+                .if_unchanged(hash)
+                .keep_sessions()
+                .with_updates(vec![PrincipalUpdate::remove_item(
+                    PrincipalField::Secrets,
+                    PrincipalValue::String(recovery_code.clone()),
+                )]),
+        ),
+    )
+    .await
+    {
+        Ok(_) => {}
+        Err(err) if err.is_assertion_failure() => return Ok(false),
+        Err(err) => return Err(err.caused_by(trc::location!())),
+    }
+    principal.retain_str(PrincipalField::Secrets, |secret| *secret != recovery_code);
+
+    trc::event!(
+        Auth(trc::AuthEvent::RecoveryCodeUsed),
+        AccountId = principal.id,
+        AccountName = principal.name().to_string(),
+        Total = principal
+            .iter_str(PrincipalField::Secrets)
+            .filter(|secret| secret.is_recovery_code())
+            .count(),
+    );
+
+    Ok(true)
+}
+
+async fn upgrade_secret(
+    store: &Store,
+    principal: &mut Principal,
+    hash: u64,
+    code: &str,
+) -> trc::Result<()> {
+    let Some((legacy_secret, password)) = principal.legacy_secret(code).await? else {
+        return Ok(());
+    };
+    let legacy_secret = legacy_secret.to_string();
+    let new_secret = hash_secret(password).await?;
+
+    // Replace the legacy hash only, app passwords and OTP URLs are kept
+    Box::pin(
+        store.update_principal(
+            UpdatePrincipal::by_id(principal.id)
+                .if_unchanged(hash)
+                .keep_sessions()
+                .with_updates(vec![
+                    PrincipalUpdate::remove_item(
+                        PrincipalField::Secrets,
+                        PrincipalValue::String(legacy_secret.clone()),
+                    ),
+                    PrincipalUpdate::add_item(
+                        PrincipalField::Secrets,
+                        PrincipalValue::String(new_secret.clone()),
+                    ),
+                ]),
+        ),
+    )
+    .await
+    .caused_by(trc::location!())?;
+
+    trc::event!(
+        Auth(trc::AuthEvent::SecretUpgraded),
+        AccountId = principal.id,
+        AccountName = principal.name().to_string(),
+    );
+
+    principal.retain_str(PrincipalField::Secrets, |secret| *secret != legacy_secret);
+    principal.append_str(PrincipalField::Secrets, new_secret);
+
+    Ok(())
+}
@@ -0,0 +1,439 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::{AHashMap, AHashSet};
+use store::{
+    write::{
+        key::DeserializeBigEndian, BatchBuilder, Bincode, DirectoryClass, MaybeDynamicId,
+        ValueClass,
+    },
+    Deserialize, IterateParams, Serialize, Store, ValueKey, U32_LEN,
+};
+use trc::AddContext;
+use utils::codec::leb128::Leb128Reader;
+
+use crate::Principal;
+
+use super::{
+    manage::{err_code, ErrorCode},
+    PrincipalField, PrincipalInfo,
+};
+
+// Repairs are committed in batches of this many issues
+const REPAIR_BATCH_SIZE: usize = 100;
+
+/// Key used to store the server-wide switch.
+pub const SERVER_READ_ONLY_ID: u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ReadOnlyMode {
+    pub reason: Option<String>,
+    pub enabled_by: String,
+    pub enabled_at: u64,
+}
+
+/// Read-only switches are kept in the data store so every node in the cluster
+/// observes them. The switch is checked before a mutation starts, changes that
+/// already passed the check are committed atomically and are never torn.
+#[allow(async_fn_in_trait)]
+pub trait MaintenanceStore: Sync + Send {
+    async fn get_read_only(&self, tenant_id: Option<u32>) -> trc::Result<Option<ReadOnlyMode>>;
+    async fn set_read_only(
+        &self,
+        tenant_id: Option<u32>,
+        mode: Option<ReadOnlyMode>,
+    ) -> trc::Result<()>;
+    async fn assert_not_read_only(&self, tenant_id: Option<u32>) -> trc::Result<()>;
+    async fn verify_directory(&self, repair: bool) -> trc::Result<DirectoryVerification>;
+}
+
+/// Result of a directory consistency check.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryVerification {
+    pub principals: usize,
+    pub issues: Vec<DirectoryIssue>,
+    pub repaired: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryIssue {
+    pub kind: DirectoryIssueKind,
+    pub principal_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub related_id: Option<u32>,
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DirectoryIssueKind {
+    /// Name key pointing to a missing principal or to one with another name
+    OrphanedName,
+    /// Principal without a name key pointing back to it
+    MissingName,
+    /// Email key pointing to a missing principal or to one without that address
+    OrphanedEmail,
+    /// External id key pointing to a missing principal
+    OrphanedExternalId,
+    /// Membership edge referencing a missing principal
+    OrphanedMembership,
+    /// MemberOf edge without its Members counterpart
+    MissingMembers,
+    /// Members edge without its MemberOf counterpart
+    MissingMemberOf,
+}
+
+impl DirectoryIssueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DirectoryIssueKind::OrphanedName => "orphanedName",
+            DirectoryIssueKind::MissingName => "missingName",
+            DirectoryIssueKind::OrphanedEmail => "orphanedEmail",
+            DirectoryIssueKind::OrphanedExternalId => "orphanedExternalId",
+            DirectoryIssueKind::OrphanedMembership => "orphanedMembership",
+            DirectoryIssueKind::MissingMembers => "missingMembers",
+            DirectoryIssueKind::MissingMemberOf => "missingMemberOf",
+        }
+    }
+}
+
+struct PrincipalKeys {
+    name: String,
+    info: PrincipalInfo,
+    emails: AHashSet<String>,
+}
+
+enum Repair {
+    Clear(DirectoryClass<MaybeDynamicId>),
+    Set(DirectoryClass<MaybeDynamicId>, Vec<u8>),
+}
+
+impl MaintenanceStore for Store {
+    async fn get_read_only(&self, tenant_id: Option<u32>) -> trc::Result<Option<ReadOnlyMode>> {
+        self.get_value::<Bincode<ReadOnlyMode>>(ValueKey::from(ValueClass::Directory(
+            DirectoryClass::ReadOnly(tenant_id.unwrap_or(SERVER_READ_ONLY_ID)),
+        )))
+        .await
+        .caused_by(trc::location!())
+        .map(|mode| mode.map(|mode| mode.inner))
+    }
+
+    async fn set_read_only(
+        &self,
+        tenant_id: Option<u32>,
+        mode: Option<ReadOnlyMode>,
+    ) -> trc::Result<()> {
+        let class = ValueClass::Directory(DirectoryClass::ReadOnly(
+            tenant_id.unwrap_or(SERVER_READ_ONLY_ID),
+        ));
+        let mut batch = BatchBuilder::new();
+        if let Some(mode) = mode {
+            batch.set(class, Bincode::new(mode).serialize());
+        } else {
+            batch.clear(class);
+        }
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn assert_not_read_only(&self, tenant_id: Option<u32>) -> trc::Result<()> {
+        // The server-wide switch applies to every tenant
+        for tenant_id in std::iter::once(None).chain(tenant_id.map(Some)) {
+            if let Some(mode) = self
+                .get_read_only(tenant_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                return Err(err_code(
+                    ErrorCode::MaintenanceMode,
+                    "Directory is in maintenance mode",
+                    mode.reason
+                        .unwrap_or_else(|| "Changes are disabled during maintenance".to_string())
+                        .into(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn verify_directory(&self, repair: bool) -> trc::Result<DirectoryVerification> {
+        // Changes made while the directory is scanned can be reported as
+        // inconsistencies, checks should run while the directory is idle
+        let mut principals: AHashMap<u32, PrincipalKeys> = AHashMap::new();
+        let mut names = Vec::new();
+        let mut emails = Vec::new();
+        let mut external_ids = Vec::new();
+        let mut member_of = AHashSet::new();
+        let mut members = AHashSet::new();
+
+        self.iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![]))),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::LastLogin(u32::MAX))),
+            ),
+            |key, value| {
+                match key.first() {
+                    Some(0) => names.push((
+                        String::from_utf8_lossy(&key[1..]).into_owned(),
+                        PrincipalInfo::deserialize(value).caused_by(trc::location!())?,
+                    )),
+                    Some(1) => emails.push((
+                        String::from_utf8_lossy(&key[1..]).into_owned(),
+                        PrincipalInfo::deserialize(value).caused_by(trc::location!())?,
+                    )),
+                    Some(2) => {
+                        let principal_id = key
+                            .get(1..)
+                            .and_then(|id| id.read_leb128::<u32>())
+                            .map(|(id, _)| id)
+                            .ok_or_else(|| {
+                                trc::StoreEvent::DataCorruption
+                                    .caused_by(trc::location!())
+                                    .ctx(trc::Key::Key, key)
+                            })?;
+                        let mut principal =
+                            Principal::deserialize(value).caused_by(trc::location!())?;
+                        principals.insert(
+                            principal_id,
+                            PrincipalKeys {
+                                name: principal.take_str(PrincipalField::Name).unwrap_or_default(),
+                                info: PrincipalInfo::new(
+                                    principal_id,
+                                    principal.typ(),
+                                    principal.tenant(),
+                                ),
+                                emails: principal
+                                    .take_str_array(PrincipalField::Emails)
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .collect(),
+                            },
+                        );
+                    }
+                    Some(5) => {
+                        member_of.insert((
+                            key.deserialize_be_u32(1)?,
+                            key.deserialize_be_u32(1 + U32_LEN)?,
+                        ));
+                    }
+                    Some(6) => {
+                        members.insert((
+                            key.deserialize_be_u32(1)?,
+                            key.deserialize_be_u32(1 + U32_LEN)?,
+                        ));
+                    }
+                    Some(10) => external_ids.push((
+                        String::from_utf8_lossy(&key[1..]).into_owned(),
+                        PrincipalInfo::deserialize(value).caused_by(trc::location!())?,
+                    )),
+                    _ => {}
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        let mut issues = Vec::new();
+        let mut name_ids = AHashMap::with_capacity(names.len());
+        for (name, info) in names {
+            if principals
+                .get(&info.id)
+                .is_none_or(|principal| principal.name != name)
+            {
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::OrphanedName, info.id).with_key(&name),
+                    Some(Repair::Clear(DirectoryClass::NameToId(name.into_bytes()))),
+                ));
+            } else {
+                name_ids.insert(name, info.id);
+            }
+        }
+        for (principal_id, principal) in &principals {
+            if !name_ids.contains_key(&principal.name) {
+                // Names claimed by more than one principal are only assigned once
+                let repair = (!principal.name.is_empty()).then(|| {
+                    name_ids.insert(principal.name.clone(), *principal_id);
+                    Repair::Set(
+                        DirectoryClass::NameToId(principal.name.as_bytes().to_vec()),
+                        PrincipalInfo::new(
+                            *principal_id,
+                            principal.info.typ,
+                            principal.info.tenant,
+                        )
+                        .serialize(),
+                    )
+                });
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::MissingName, *principal_id)
+                        .with_key(&principal.name),
+                    repair,
+                ));
+            }
+        }
+        for (email, info) in emails {
+            if principals
+                .get(&info.id)
+                .is_none_or(|principal| !principal.emails.contains(&email))
+            {
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::OrphanedEmail, info.id)
+                        .with_key(&email),
+                    Some(Repair::Clear(DirectoryClass::EmailToId(email.into_bytes()))),
+                ));
+            }
+        }
+        for (external_id, info) in external_ids {
+            if !principals.contains_key(&info.id) {
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::OrphanedExternalId, info.id)
+                        .with_key(&external_id),
+                    Some(Repair::Clear(DirectoryClass::ExternalIdToId(
+                        external_id.into_bytes(),
+                    ))),
+                ));
+            }
+        }
+        for &(principal_id, group_id) in &member_of {
+            let edge = DirectoryClass::MemberOf {
+                principal_id: MaybeDynamicId::Static(principal_id),
+                member_of: MaybeDynamicId::Static(group_id),
+            };
+            if !principals.contains_key(&principal_id) || !principals.contains_key(&group_id) {
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::OrphanedMembership, principal_id)
+                        .with_related_id(group_id),
+                    Some(Repair::Clear(edge)),
+                ));
+            } else if !members.contains(&(group_id, principal_id)) {
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::MissingMembers, group_id)
+                        .with_related_id(principal_id),
+                    Some(Repair::Set(
+                        DirectoryClass::Members {
+                            principal_id: MaybeDynamicId::Static(group_id),
+                            has_member: MaybeDynamicId::Static(principal_id),
+                        },
+                        vec![],
+                    )),
+                ));
+            }
+        }
+        for &(group_id, principal_id) in &members {
+            let edge = DirectoryClass::Members {
+                principal_id: MaybeDynamicId::Static(group_id),
+                has_member: MaybeDynamicId::Static(principal_id),
+            };
+            if let (Some(_), Some(group)) =
+                (principals.get(&principal_id), principals.get(&group_id))
+            {
+                if !member_of.contains(&(principal_id, group_id)) {
+                    issues.push((
+                        DirectoryIssue::new(DirectoryIssueKind::MissingMemberOf, principal_id)
+                            .with_related_id(group_id),
+                        Some(Repair::Set(
+                            DirectoryClass::MemberOf {
+                                principal_id: MaybeDynamicId::Static(principal_id),
+                                member_of: MaybeDynamicId::Static(group_id),
+                            },
+                            vec![group.info.typ as u8],
+                        )),
+                    ));
+                }
+            } else {
+                issues.push((
+                    DirectoryIssue::new(DirectoryIssueKind::OrphanedMembership, group_id)
+                        .with_related_id(principal_id),
+                    Some(Repair::Clear(edge)),
+                ));
+            }
+        }
+
+        // Report and repair inconsistencies
+        let mut result = DirectoryVerification {
+            principals: principals.len(),
+            ..Default::default()
+        };
+        let mut batch = BatchBuilder::new();
+        let mut pending = 0;
+        for (mut issue, action) in issues {
+            if let Some(action) = action.filter(|_| repair) {
+                match action {
+                    Repair::Clear(class) => {
+                        batch.clear(ValueClass::Directory(class));
+                    }
+                    Repair::Set(class, value) => {
+                        batch.set(ValueClass::Directory(class), value);
+                    }
+                }
+                issue.repaired = true;
+                result.repaired += 1;
+                pending += 1;
+                if pending >= REPAIR_BATCH_SIZE {
+                    self.write(batch.build_batch())
+                        .await
+                        .caused_by(trc::location!())?;
+                    pending = 0;
+                }
+            }
+
+            trc::event!(
+                Manage(trc::ManageEvent::DirectoryInconsistency),
+                Type = issue.kind.as_str(),
+                Id = issue.principal_id,
+                Key = issue.key.clone(),
+                AccountId = issue.related_id,
+                Result = issue.repaired,
+            );
+
+            result.issues.push(issue);
+        }
+        if pending > 0 {
+            self.write(batch.build_batch())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        trc::event!(
+            Manage(trc::ManageEvent::DirectoryVerified),
+            Total = result.principals,
+            Details = result.issues.len(),
+            Result = result.repaired,
+        );
+
+        Ok(result)
+    }
+}
+
+impl DirectoryIssue {
+    fn new(kind: DirectoryIssueKind, principal_id: u32) -> Self {
+        DirectoryIssue {
+            kind,
+            principal_id,
+            key: None,
+            related_id: None,
+            repaired: false,
+        }
+    }
+
+    fn with_key(mut self, key: &str) -> Self {
+        self.key = Some(key.to_string());
+        self
+    }
+
+    fn with_related_id(mut self, related_id: u32) -> Self {
+        self.related_id = Some(related_id);
+        self
+    }
+}
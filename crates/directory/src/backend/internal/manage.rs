@@ -4,83 +4,360 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use ahash::AHashSet;
+use std::{sync::LazyLock, time::Duration};
+
+use ahash::{AHashMap, AHashSet};
+use futures::{StreamExt, TryStreamExt};
 use jmap_proto::types::collection::Collection;
 use store::{
+    rand::{self, Rng},
     write::{
-        assert::HashedValue, key::DeserializeBigEndian, AssignedIds, BatchBuilder, DirectoryClass,
-        MaybeDynamicId, MaybeDynamicValue, SerializeWithId, ValueClass,
+        assert::HashedValue, key::DeserializeBigEndian, log::ChangeLogBuilder, now, AssignedIds,
+        BatchBuilder, DirectoryClass, MaybeDynamicId, MaybeDynamicValue, SerializeWithId,
+        ValueClass,
     },
-    Deserialize, IterateParams, Serialize, Store, ValueKey, U32_LEN,
+    BitmapKey, Deserialize, IterateParams, Serialize, Store, ValueKey, U32_LEN,
 };
 use trc::AddContext;
-use utils::sanitize_email;
+use utils::{
+    locale::{parse_timezone, Locale},
+    sanitize_email,
+    snowflake::SnowflakeIdGenerator,
+};
 
 use crate::{
-    backend::RcptType, Permission, Permissions, Principal, QueryBy, Type, MAX_TYPE_ID, ROLE_ADMIN,
+    backend::{PostingPolicy, RcptType, Subaddressing},
+    ClientCertificate, Permission, Permissions, Principal, QueryBy, Type, MAX_TYPE_ID, ROLE_ADMIN,
     ROLE_TENANT_ADMIN, ROLE_USER,
 };
 
 use super::{
-    lookup::DirectoryStore, PrincipalAction, PrincipalField, PrincipalInfo, PrincipalUpdate,
-    PrincipalValue, SpecialSecrets,
+    delegation::DomainDelegationStore, lookup::DirectoryStore, maintenance::MaintenanceStore,
+    PrincipalAction, PrincipalField, PrincipalInfo, PrincipalUpdate, PrincipalValue,
+    SpecialSecrets, PRINCIPAL_INFO_MIGRATION, RECOVERY_CODE_PREFIX,
 };
 
+static DIRECTORY_CHANGE_ID: LazyLock<SnowflakeIdGenerator> =
+    LazyLock::new(SnowflakeIdGenerator::new);
+
+/// Retry policy used when concurrent writers create the same principal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreateRetry {
+    pub max_retries: u32,
+    pub backoff_min: Duration,
+    pub backoff_max: Duration,
+}
+
 pub struct MemberOf {
     pub principal_id: u32,
     pub typ: Type,
+    // Number of membership hops from the queried principal
+    pub depth: u32,
+}
+
+/// Group, list or role a principal belongs to, directly or through other groups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Membership {
+    pub principal_id: u32,
+    pub typ: Type,
+    pub name: String,
+    pub description: Option<String>,
+    pub depth: u32,
+}
+
+/// Outcome of a bulk creation. `ids` holds the ids assigned to the principals
+/// written so far, in input order. When `errors` is not empty it contains
+/// either every validation error (and nothing was written), the write error
+/// of the first chunk that could not be committed or the tenant quota error
+/// that caused the written principals to be rolled back.
+#[derive(Debug, Default)]
+pub struct CreatedPrincipals {
+    pub ids: Vec<u32>,
+    pub errors: Vec<(usize, trc::Error)>,
 }
 
+// Number of principals written per batch during bulk creation
+const CREATE_CHUNK_SIZE: usize = 100;
+
+// Maximum number of store lookups issued concurrently by bulk reads
+pub(crate) const MAX_CONCURRENT_LOOKUPS: usize = 32;
+
 #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
 pub struct PrincipalList {
     pub items: Vec<Principal>,
     pub total: u64,
 }
 
+/// A page of principals obtained by key, `next` is the cursor to pass to
+/// obtain the following page.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct PrincipalPage {
+    pub items: Vec<Principal>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+}
+
+/// Filter applied when listing principals. Free text terms are matched
+/// against every string field while field terms only check the named field.
+/// Principals that can log in are matched by `last_login_before` when they
+/// have not logged in since the given timestamp.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PrincipalFilter {
+    pub text: Vec<String>,
+    pub fields: Vec<(PrincipalField, String)>,
+    pub last_login_before: Option<u64>,
+}
+
+/// Changes applied to an existing principal. Changes run in the order they
+/// were submitted against a working copy of the principal, so `Set` replaces
+/// whatever earlier changes produced and later `AddItem`/`RemoveItem` changes
+/// build on it. Repeated values collapse into one. Either every change is
+/// applied or, if any of them fails validation, none is.
 pub struct UpdatePrincipal<'x> {
     query: QueryBy<'x>,
     allowed_permissions: Option<&'x Permissions>,
     changes: Vec<PrincipalUpdate>,
     tenant_id: Option<u32>,
     create_domains: bool,
+    cascade_rename: bool,
+    expected_hash: Option<u64>,
+    keep_sessions: bool,
+}
+
+/// Declarative description of the roles defined in the directory. Lists are
+/// kept sorted so that exported documents can be reviewed and diffed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RoleDocument {
+    #[serde(default)]
+    pub roles: Vec<RoleDefinition>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleDefinition {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub enabled_permissions: Vec<String>,
+    #[serde(default)]
+    pub disabled_permissions: Vec<String>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleChange {
+    pub name: String,
+    pub action: RoleAction,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<PrincipalField>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RoleAction {
+    Create,
+    Update,
+    Unchanged,
+    Extra,
+    Delete,
+}
+
+/// Portable copy of the directory. Principals reference their groups, lists,
+/// roles and tenant by name so that the export can be imported on a directory
+/// that assigned different ids.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct PrincipalExport {
+    #[serde(default)]
+    pub principals: Vec<Principal>,
+}
+
+/// How to handle imported principals whose name already exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportMode {
+    Skip,
+    Overwrite,
+    #[default]
+    Fail,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedPrincipals {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+/// Account read from a bulk import file, identified by its e-mail address.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportAccount {
+    pub email: String,
+    #[serde(default, alias = "password_hash")]
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub quota: Option<u64>,
+    #[serde(default, alias = "display_name")]
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ImportStatus {
+    Created,
+    Skipped,
+    Error,
+}
+
+/// Outcome of importing one row, `row` is the position of the account in
+/// the import file.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedAccount {
+    pub row: usize,
+    pub email: String,
+    pub status: ImportStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Data that `delete_principal` would remove, computed without writing
+/// anything so that deletions can be reviewed before they are performed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeletePreview {
+    pub id: u32,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: Type,
+    pub messages: u64,
+    pub bytes: u64,
+    pub blob_links: u64,
+    pub acl_grants: u64,
+    pub member_of: Vec<String>,
+    pub members: Vec<String>,
 }
 
 #[allow(async_fn_in_trait)]
 pub trait ManageDirectory: Sized {
     async fn get_principal_id(&self, name: &str) -> trc::Result<Option<u32>>;
     async fn get_principal_info(&self, name: &str) -> trc::Result<Option<PrincipalInfo>>;
-    async fn get_or_create_principal_id(&self, name: &str, typ: Type) -> trc::Result<u32>;
+    async fn get_principal_infos(&self, names: &[&str]) -> trc::Result<Vec<Option<PrincipalInfo>>>;
+    async fn external_id_to_id(&self, external_id: &str) -> trc::Result<Option<u32>>;
+    async fn certificate_to_id(&self, certificate: &ClientCertificate) -> trc::Result<Option<u32>>;
+    async fn get_or_create_principal_id(
+        &self,
+        name: &str,
+        typ: Type,
+        retry: CreateRetry,
+    ) -> trc::Result<u32>;
     async fn get_principal(&self, principal_id: u32) -> trc::Result<Option<Principal>>;
+    async fn get_last_login(&self, principal_id: u32) -> trc::Result<Option<u64>>;
+    async fn set_last_login(&self, principal_id: u32, timestamp: u64) -> trc::Result<()>;
     async fn get_member_of(&self, principal_id: u32) -> trc::Result<Vec<MemberOf>>;
+    async fn get_member_of_recursive(
+        &self,
+        principal_id: u32,
+        max_depth: u32,
+    ) -> trc::Result<Vec<MemberOf>>;
+    async fn get_memberships(
+        &self,
+        principal_id: u32,
+        max_depth: u32,
+        include_roles: bool,
+    ) -> trc::Result<Vec<Membership>>;
     async fn get_members(&self, principal_id: u32) -> trc::Result<Vec<u32>>;
+    async fn get_members_expanded(
+        &self,
+        principal_id: u32,
+        types: &[Type],
+    ) -> trc::Result<Vec<Principal>>;
     async fn create_principal(
         &self,
         principal: Principal,
         tenant_id: Option<u32>,
         allowed_permissions: Option<&Permissions>,
     ) -> trc::Result<u32>;
+    async fn create_principals(
+        &self,
+        principals: Vec<Principal>,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+    ) -> trc::Result<CreatedPrincipals>;
     async fn update_principal(&self, params: UpdatePrincipal<'_>) -> trc::Result<()>;
+    async fn revoke_credentials(&self, principal_id: u32) -> trc::Result<u64>;
     async fn delete_principal(&self, by: QueryBy<'_>) -> trc::Result<()>;
+    async fn delete_principal_cascade(
+        &self,
+        by: QueryBy<'_>,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<PrincipalInfo>>;
+    async fn preview_delete_principal(&self, by: QueryBy<'_>) -> trc::Result<DeletePreview>;
     async fn list_principals(
         &self,
-        filter: Option<&str>,
+        filter: Option<&PrincipalFilter>,
         tenant_id: Option<u32>,
         types: &[Type],
         fields: &[PrincipalField],
         page: usize,
         limit: usize,
     ) -> trc::Result<PrincipalList>;
+    async fn list_principals_after(
+        &self,
+        filter: Option<&PrincipalFilter>,
+        tenant_id: Option<u32>,
+        types: &[Type],
+        fields: &[PrincipalField],
+        after: Option<&str>,
+        limit: usize,
+    ) -> trc::Result<PrincipalPage>;
     async fn count_principals(
         &self,
         filter: Option<&str>,
         typ: Option<Type>,
         tenant_id: Option<u32>,
     ) -> trc::Result<u64>;
+    async fn count_principals_by_type(
+        &self,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<AHashMap<Type, u64>>;
     async fn map_field_ids(
         &self,
         principal: &mut Principal,
         fields: &[PrincipalField],
     ) -> trc::Result<()>;
+    async fn export_roles(&self, tenant_id: Option<u32>) -> trc::Result<RoleDocument>;
+    async fn apply_roles(
+        &self,
+        document: RoleDocument,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+        prune: bool,
+        dry_run: bool,
+    ) -> trc::Result<Vec<RoleChange>>;
+    async fn export_principals(&self, tenant_id: Option<u32>) -> trc::Result<PrincipalExport>;
+    async fn import_principals(
+        &self,
+        data: PrincipalExport,
+        mode: ImportMode,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+    ) -> trc::Result<ImportedPrincipals>;
+    async fn import_accounts(
+        &self,
+        accounts: Vec<(usize, ImportAccount)>,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+        create_domains: bool,
+    ) -> trc::Result<Vec<ImportedAccount>>;
 }
 
 #[allow(async_fn_in_trait)]
@@ -90,7 +367,67 @@ trait ValidateDirectory: Sized {
         email: &str,
         tenant_id: Option<u32>,
         create_if_missing: bool,
+    ) -> trc::Result<Option<String>>;
+
+    async fn has_membership_path(&self, from: &[u32], to: &[u32], skip: u32) -> trc::Result<bool>;
+
+    async fn validate_tenant_defaults(
+        &self,
+        field: PrincipalField,
+        values: Vec<String>,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<String>>;
+
+    async fn validate_moderators(
+        &self,
+        names: Vec<String>,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<u64>>;
+
+    async fn validate_new_principal(
+        &self,
+        principal: Principal,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+        pending: &mut PendingPrincipals,
+    ) -> trc::Result<NewPrincipal>;
+
+    #[cfg(feature = "enterprise")]
+    async fn assert_no_members(&self, principal: &Principal) -> trc::Result<()>;
+
+    async fn delete_principal_record(&self, principal: Principal) -> trc::Result<()>;
+
+    async fn clear_principal_record(
+        &self,
+        batch: &mut BatchBuilder,
+        principal: &Principal,
     ) -> trc::Result<()>;
+
+    async fn purge_principal_data(&self, principal: &Principal) -> trc::Result<()>;
+
+    async fn assert_tenant_quota(&self, tenant_id: u32, typ: Type, limit: u64) -> trc::Result<()>;
+
+    async fn rollback_created(&self, principal_ids: &[u32]) -> trc::Result<()>;
+
+    async fn get_principal_by(&self, by: QueryBy<'_>) -> trc::Result<Principal>;
+
+    async fn assert_admins_remain(
+        &self,
+        principal: &Principal,
+        removed_roles: Option<&[u32]>,
+    ) -> trc::Result<()>;
+
+    async fn role_holders(
+        &self,
+        role_id: u32,
+        tenant_id: Option<u32>,
+        exclude: Option<(u32, Option<&[u32]>)>,
+    ) -> trc::Result<Vec<u32>>;
+
+    async fn list_domain_addresses(
+        &self,
+        domain: &str,
+    ) -> trc::Result<Vec<(String, PrincipalInfo)>>;
 }
 
 impl ManageDirectory for Store {
@@ -108,6 +445,26 @@ impl ManageDirectory for Store {
         })
     }
 
+    async fn get_last_login(&self, principal_id: u32) -> trc::Result<Option<u64>> {
+        self.get_value::<u64>(ValueKey::from(ValueClass::Directory(
+            DirectoryClass::LastLogin(principal_id),
+        )))
+        .await
+        .caused_by(trc::location!())
+    }
+
+    async fn set_last_login(&self, principal_id: u32, timestamp: u64) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Directory(DirectoryClass::LastLogin(principal_id)),
+            timestamp.serialize(),
+        );
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
     async fn get_principal_id(&self, name: &str) -> trc::Result<Option<u32>> {
         self.get_principal_info(name).await.map(|v| v.map(|v| v.id))
     }
@@ -119,8 +476,71 @@ impl ManageDirectory for Store {
         .caused_by(trc::location!())
     }
 
+    /// Resolves email addresses (when they contain an '@') or principal names
+    /// concurrently, results are returned in the order of `names`.
+    async fn get_principal_infos(&self, names: &[&str]) -> trc::Result<Vec<Option<PrincipalInfo>>> {
+        let keys = names
+            .iter()
+            .map(|name| {
+                if name.contains('@') {
+                    DirectoryClass::EmailToId(name.as_bytes().to_vec())
+                } else {
+                    DirectoryClass::NameToId(name.as_bytes().to_vec())
+                }
+            })
+            .collect::<Vec<_>>();
+        futures::stream::iter(
+            keys.into_iter().map(|key| {
+                self.get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(key)))
+            }),
+        )
+        .buffered(MAX_CONCURRENT_LOOKUPS)
+        .try_collect()
+        .await
+        .caused_by(trc::location!())
+    }
+    async fn external_id_to_id(&self, external_id: &str) -> trc::Result<Option<u32>> {
+        self.get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(
+            DirectoryClass::ExternalIdToId(external_id.as_bytes().to_vec()),
+        )))
+        .await
+        .map(|v| v.map(|v| v.id))
+        .caused_by(trc::location!())
+    }
+
+    /// Fingerprints registered on a principal take precedence over the
+    /// e-mail addresses listed in the certificate.
+    async fn certificate_to_id(&self, certificate: &ClientCertificate) -> trc::Result<Option<u32>> {
+        if let Some(info) = self
+            .get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::CertificateToId(certificate.fingerprint.as_bytes().to_vec()),
+            )))
+            .await
+            .caused_by(trc::location!())?
+        {
+            return Ok(Some(info.id));
+        }
+
+        for email in &certificate.emails {
+            if let Some(id) = self
+                .email_to_id(&email.to_lowercase())
+                .await
+                .caused_by(trc::location!())?
+            {
+                return Ok(Some(id));
+            }
+        }
+
+        Ok(None)
+    }
+
     // Used by all directories except internal
-    async fn get_or_create_principal_id(&self, name: &str, typ: Type) -> trc::Result<u32> {
+    async fn get_or_create_principal_id(
+        &self,
+        name: &str,
+        typ: Type,
+        retry: CreateRetry,
+    ) -> trc::Result<u32> {
         let mut try_count = 0;
         let name = name.to_lowercase();
 
@@ -180,13 +600,22 @@ impl ManageDirectory for Store {
                 Ok(principal_id) => {
                     return Ok(principal_id);
                 }
-                Err(err) => {
-                    if err.is_assertion_failure() && try_count < 3 {
+                Err(err) if err.is_assertion_failure() => {
+                    if try_count < retry.max_retries {
+                        tokio::time::sleep(retry.backoff(try_count)).await;
                         try_count += 1;
                         continue;
-                    } else {
-                        return Err(err.caused_by(trc::location!()));
                     }
+
+                    // Another writer may have won the race on the last attempt
+                    return self
+                        .get_principal_id(&name)
+                        .await
+                        .caused_by(trc::location!())?
+                        .ok_or_else(|| err.caused_by(trc::location!()));
+                }
+                Err(err) => {
+                    return Err(err.caused_by(trc::location!()));
                 }
             }
         }
@@ -194,312 +623,247 @@ impl ManageDirectory for Store {
 
     async fn create_principal(
         &self,
-        mut principal: Principal,
-        mut tenant_id: Option<u32>,
+        principal: Principal,
+        tenant_id: Option<u32>,
         allowed_permissions: Option<&Permissions>,
     ) -> trc::Result<u32> {
-        // Make sure the principal has a name
-        let name = principal.name().to_lowercase();
-        if name.is_empty() {
-            return Err(err_missing(PrincipalField::Name));
-        }
-        let mut valid_domains: AHashSet<String> = AHashSet::new();
+        let principal = self
+            .validate_new_principal(
+                principal,
+                tenant_id,
+                allowed_permissions,
+                &mut PendingPrincipals::default(),
+            )
+            .await?;
+        self.assert_not_read_only(principal.tenant_id).await?;
+        let memberships = principal.memberships(0);
+        let quota = principal
+            .tenant_id
+            .zip(principal.quota_limit)
+            .map(|(tenant_id, limit)| (tenant_id, principal.principal.typ, limit));
 
-        // SPDX-SnippetBegin
-        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
-        // SPDX-License-Identifier: LicenseRef-SEL
+        // Write principal
+        let principal_id = self
+            .write(principals_batch(vec![principal], &memberships, 0, &[]).build())
+            .await
+            .and_then(|r| r.last_document_id())?;
 
-        // Validate tenant
-        #[cfg(feature = "enterprise")]
-        if let Some(tenant_id) = tenant_id {
-            let tenant = self
-                .query(QueryBy::Id(tenant_id), false)
-                .await?
-                .ok_or_else(|| {
-                    trc::ManageEvent::NotFound
-                        .into_err()
-                        .id(tenant_id)
-                        .details("Tenant not found")
-                        .caused_by(trc::location!())
-                })?;
+        // Log change
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(u32::MAX)
+            .with_collection(Collection::Principal)
+            .custom(directory_change_log().with_log_insert(Collection::Principal, principal_id));
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
 
-            // Enforce tenant quotas
-            if let Some(limit) = tenant
-                .get_int_array(PrincipalField::Quota)
-                .and_then(|quotas| quotas.get(principal.typ() as usize + 1))
-                .copied()
-                .filter(|q| *q > 0)
-            {
-                // Obtain number of principals
-                let total = self
-                    .count_principals(None, principal.typ().into(), tenant_id.into())
+        // Quotas are checked before writing, concurrent creations may have
+        // taken the remaining slots in the meantime
+        if let Some((tenant_id, typ, limit)) = quota {
+            if let Err(err) = self.assert_tenant_quota(tenant_id, typ, limit).await {
+                self.rollback_created(&[principal_id])
                     .await
                     .caused_by(trc::location!())?;
-
-                if total >= limit {
-                    trc::bail!(trc::LimitEvent::TenantQuota
-                        .into_err()
-                        .details("Tenant principal quota exceeded")
-                        .ctx(trc::Key::Details, principal.typ().as_str())
-                        .ctx(trc::Key::Limit, limit)
-                        .ctx(trc::Key::Total, total));
-                }
+                return Err(err);
             }
         }
 
-        // SPDX-SnippetEnd
-
-        // Make sure new name is not taken
-        if self
-            .get_principal_id(&name)
-            .await
-            .caused_by(trc::location!())?
-            .is_some()
-        {
-            return Err(err_exists(PrincipalField::Name, name));
-        }
-
-        // SPDX-SnippetBegin
-        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
-        // SPDX-License-Identifier: LicenseRef-SEL
-
-        // Obtain tenant id, only if no default tenant is provided
-        #[cfg(feature = "enterprise")]
-        if let (Some(tenant_name), None) = (principal.take_str(PrincipalField::Tenant), tenant_id) {
-            tenant_id = self
-                .get_principal_info(&tenant_name)
-                .await
-                .caused_by(trc::location!())?
-                .filter(|v| v.typ == Type::Tenant)
-                .ok_or_else(|| not_found(tenant_name.clone()))?
-                .id
-                .into();
-        }
+        Ok(principal_id)
+    }
 
-        // Tenants must provide principal names including a valid domain
-        #[cfg(feature = "enterprise")]
-        if let Some(tenant_id) = tenant_id {
-            if matches!(principal.typ, Type::Tenant) {
-                return Err(error(
-                    "Invalid field",
-                    "Tenants cannot contain a tenant field".into(),
-                ));
+    async fn create_principals(
+        &self,
+        principals: Vec<Principal>,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+    ) -> trc::Result<CreatedPrincipals> {
+        let mut result = CreatedPrincipals::default();
+
+        // Detect names, e-mails and external ids repeated within the batch
+        let mut pending = PendingPrincipals::default();
+        let mut emails = AHashSet::new();
+        let mut external_ids = AHashSet::new();
+        for (idx, principal) in principals.iter().enumerate() {
+            let name = principal.name().to_lowercase();
+            if !name.is_empty() {
+                if pending.names.contains_key(&name) {
+                    result
+                        .errors
+                        .push((idx, err_exists(PrincipalField::Name, name)));
+                    continue;
+                }
+                pending.names.insert(name, (idx, principal.typ));
             }
-
-            principal.set(PrincipalField::Tenant, tenant_id);
-
-            if !matches!(principal.typ, Type::Tenant | Type::Domain) {
-                if let Some(domain) = name.split('@').nth(1) {
-                    if self
-                        .get_principal_info(domain)
-                        .await
-                        .caused_by(trc::location!())?
-                        .filter(|v| v.typ == Type::Domain && v.has_tenant_access(tenant_id.into()))
-                        .is_some()
-                    {
-                        valid_domains.insert(domain.to_string());
-                    }
+            for email in principal.iter_str(PrincipalField::Emails) {
+                let email = normalize_email(email).unwrap_or_else(|_| email.to_lowercase());
+                if !emails.insert(email.clone()) {
+                    result
+                        .errors
+                        .push((idx, err_exists(PrincipalField::Emails, email)));
+                    break;
                 }
-
-                if valid_domains.is_empty() {
-                    return Err(error(
-                        "Invalid principal name",
-                        "Principal name must include a valid domain assigned to the tenant".into(),
+            }
+            if let Some(external_id) = principal
+                .get_str(PrincipalField::ExternalId)
+                .filter(|v| !v.is_empty())
+            {
+                if !external_ids.insert(external_id.to_string()) {
+                    result.errors.push((
+                        idx,
+                        err_exists(PrincipalField::ExternalId, external_id.to_string()),
                     ));
                 }
             }
         }
-        // SPDX-SnippetEnd
 
-        principal.set(PrincipalField::Name, name);
-
-        // Map member names
-        let mut members = Vec::new();
-        let mut member_of = Vec::new();
-        for (field, expected_type) in [
-            (PrincipalField::Members, None),
-            (PrincipalField::MemberOf, Some(Type::Group)),
-            (PrincipalField::Lists, Some(Type::List)),
-            (PrincipalField::Roles, Some(Type::Role)),
-        ] {
-            if let Some(names) = principal.take_str_array(field) {
-                let list = if field == PrincipalField::Members {
-                    &mut members
-                } else {
-                    &mut member_of
-                };
-
-                for name in names {
-                    list.push(
-                        self.get_principal_info(&name)
-                            .await
-                            .caused_by(trc::location!())?
-                            .filter(|v| {
-                                expected_type.map_or(true, |t| v.typ == t)
-                                    && v.has_tenant_access(tenant_id)
-                            })
-                            .or_else(|| field.map_internal_roles(&name))
-                            .ok_or_else(|| not_found(name))?,
-                    );
+        // Validate every principal before writing any of them
+        let mut validated = Vec::with_capacity(principals.len());
+        for (idx, principal) in principals.into_iter().enumerate() {
+            match self
+                .validate_new_principal(principal, tenant_id, allowed_permissions, &mut pending)
+                .await
+            {
+                Ok(principal) => {
+                    validated.push(principal);
                 }
-            }
-        }
-
-        // Map permissions
-        for field in [
-            PrincipalField::EnabledPermissions,
-            PrincipalField::DisabledPermissions,
-        ] {
-            if let Some(names) = principal.take_str_array(field) {
-                let mut permissions = Vec::with_capacity(names.len());
-                for name in names {
-                    let permission = Permission::from_name(&name)
-                        .ok_or_else(|| {
-                            error(
-                                format!("Invalid {} value", field.as_str()),
-                                format!("Permission {name:?} is invalid").into(),
-                            )
-                        })?
-                        .id() as u64;
-
-                    if !permissions.contains(&permission) {
-                        if allowed_permissions
-                            .as_ref()
-                            .map_or(true, |p| p.get(permission as usize))
-                            || field == PrincipalField::DisabledPermissions
-                        {
-                            permissions.push(permission);
-                        } else {
-                            return Err(error(
-                                "Invalid permission",
-                                format!("Your account cannot grant the {name:?} permission").into(),
-                            ));
-                        }
+                Err(err)
+                    if matches!(
+                        err.as_ref(),
+                        trc::EventType::Manage(_) | trc::EventType::Limit(_)
+                    ) =>
+                {
+                    if !result.errors.iter().any(|(err_idx, _)| *err_idx == idx) {
+                        result.errors.push((idx, err));
                     }
                 }
-
-                if !permissions.is_empty() {
-                    principal.set(field, permissions);
+                Err(err) => {
+                    return Err(err);
                 }
             }
         }
-
-        // Make sure the e-mail is not taken and validate domain
-        if principal.typ != Type::OauthClient {
-            for email in principal.iter_mut_str(PrincipalField::Emails) {
-                *email = email.to_lowercase();
-                if self.rcpt(email).await.caused_by(trc::location!())? != RcptType::Invalid {
-                    return Err(err_exists(PrincipalField::Emails, email.to_string()));
-                }
-                if let Some(domain) = email.split('@').nth(1) {
-                    if valid_domains.insert(domain.to_string()) {
-                        self.get_principal_info(domain)
-                            .await
-                            .caused_by(trc::location!())?
-                            .filter(|v| v.typ == Type::Domain && v.has_tenant_access(tenant_id))
-                            .ok_or_else(|| not_found(domain.to_string()))?;
-                    }
+        if !result.errors.is_empty() {
+            result.errors.sort_unstable_by_key(|(idx, _)| *idx);
+            return Ok(result);
+        }
+        let mut tenant_ids = AHashSet::new();
+        for principal in &validated {
+            if tenant_ids.insert(principal.tenant_id) {
+                self.assert_not_read_only(principal.tenant_id).await?;
+            }
+        }
+        let memberships = validated
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, principal)| principal.memberships(idx))
+            .collect::<Vec<_>>();
+        let mut quotas: Vec<(usize, u32, Type, u64)> = Vec::new();
+        for (idx, principal) in validated.iter().enumerate() {
+            if let (Some(tenant_id), Some(limit)) = (principal.tenant_id, principal.quota_limit) {
+                let typ = principal.principal.typ;
+                if !quotas.iter().any(|(_, quota_tenant, quota_typ, _)| {
+                    *quota_tenant == tenant_id && *quota_typ == typ
+                }) {
+                    quotas.push((idx, tenant_id, typ, limit));
                 }
             }
         }
 
-        // Write principal
-        let mut batch = BatchBuilder::new();
-        let pinfo_name = DynamicPrincipalInfo::new(principal.typ, tenant_id);
-        let pinfo_email = DynamicPrincipalInfo::new(principal.typ, None);
-        batch
-            .with_account_id(u32::MAX)
-            .with_collection(Collection::Principal)
-            .create_document()
-            .assert_value(
-                ValueClass::Directory(DirectoryClass::NameToId(
-                    principal.name().to_string().into_bytes(),
-                )),
-                (),
-            )
-            .set(
-                ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Dynamic(0))),
-                (&principal).serialize(),
-            )
-            .set(
-                ValueClass::Directory(DirectoryClass::NameToId(
-                    principal
-                        .take_str(PrincipalField::Name)
-                        .unwrap()
-                        .into_bytes(),
-                )),
-                pinfo_name,
-            );
-
-        // Write email to id mapping
-        if let Some(emails) = principal
-            .take(PrincipalField::Emails)
-            .map(|v| v.into_str_array())
-        {
-            for email in emails {
-                batch.set(
-                    ValueClass::Directory(DirectoryClass::EmailToId(email.into_bytes())),
-                    pinfo_email,
-                );
+        // Write principals in chunks, each chunk is committed atomically
+        let mut validated = validated.into_iter();
+        loop {
+            let chunk = validated
+                .by_ref()
+                .take(CREATE_CHUNK_SIZE)
+                .collect::<Vec<_>>();
+            if chunk.is_empty() {
+                break;
+            }
+            let chunk_start = result.ids.len();
+            match self
+                .write(principals_batch(chunk, &memberships, chunk_start, &result.ids).build())
+                .await
+            {
+                Ok(ids) => {
+                    let mut changes = directory_change_log();
+                    for id in &ids.document_ids {
+                        changes.log_insert(Collection::Principal, *id);
+                    }
+                    result.ids.extend(ids.document_ids);
+
+                    // Log changes
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(u32::MAX)
+                        .with_collection(Collection::Principal)
+                        .custom(changes);
+                    self.write(batch.build())
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                Err(err) => {
+                    result.errors.push((chunk_start, err));
+                    break;
+                }
             }
         }
 
-        // Write membership
-        for member_of in member_of {
-            batch.set(
-                ValueClass::Directory(DirectoryClass::MemberOf {
-                    principal_id: MaybeDynamicId::Dynamic(0),
-                    member_of: MaybeDynamicId::Static(member_of.id),
-                }),
-                vec![member_of.typ as u8],
-            );
-            batch.set(
-                ValueClass::Directory(DirectoryClass::Members {
-                    principal_id: MaybeDynamicId::Static(member_of.id),
-                    has_member: MaybeDynamicId::Dynamic(0),
-                }),
-                vec![],
-            );
-        }
-        for member in members {
-            batch.set(
-                ValueClass::Directory(DirectoryClass::MemberOf {
-                    principal_id: MaybeDynamicId::Static(member.id),
-                    member_of: MaybeDynamicId::Dynamic(0),
-                }),
-                vec![principal.typ as u8],
-            );
-            batch.set(
-                ValueClass::Directory(DirectoryClass::Members {
-                    principal_id: MaybeDynamicId::Dynamic(0),
-                    has_member: MaybeDynamicId::Static(member.id),
-                }),
-                vec![],
-            );
+        // Verify tenant quotas once written, the whole batch is rolled back
+        // if concurrent creations took the remaining slots
+        for (idx, tenant_id, typ, limit) in quotas {
+            if idx >= result.ids.len() {
+                continue;
+            }
+            if let Err(err) = self.assert_tenant_quota(tenant_id, typ, limit).await {
+                self.rollback_created(&result.ids)
+                    .await
+                    .caused_by(trc::location!())?;
+                result.ids.clear();
+                result.errors = vec![(idx, err)];
+                break;
+            }
         }
 
-        self.write(batch.build())
-            .await
-            .and_then(|r| r.last_document_id())
+        Ok(result)
     }
 
     async fn delete_principal(&self, by: QueryBy<'_>) -> trc::Result<()> {
-        // Obtain principal
-        let principal_id = match by {
-            QueryBy::Name(name) => self
-                .get_principal_id(name)
+        let principal = self.get_principal_by(by).await?;
+        self.assert_not_read_only(principal.tenant()).await?;
+        assert_not_on_hold(&principal)?;
+        self.assert_admins_remain(&principal, None).await?;
+
+        // Domains are not deleted while addresses still use them
+        if principal.typ == Type::Domain {
+            let addresses = self
+                .list_domain_addresses(principal.name())
                 .await
-                .caused_by(trc::location!())?
-                .ok_or_else(|| not_found(name.to_string()))?,
-            QueryBy::Id(principal_id) => principal_id,
-            QueryBy::Credentials(_) => unreachable!(),
-        };
-        let mut principal = self
-            .get_principal(principal_id)
-            .await
-            .caused_by(trc::location!())?
-            .ok_or_else(|| not_found(principal_id.to_string()))?;
-        let mut batch = BatchBuilder::new();
+                .caused_by(trc::location!())?;
+
+            if !addresses.is_empty() {
+                let mut message =
+                    String::from("Domain addresses must be removed before deletion: Found: ");
+
+                for (num, (email, _)) in addresses.iter().take(5).enumerate() {
+                    if num > 0 {
+                        message.push_str(", ");
+                    }
+                    message.push_str(email);
+                }
+
+                if addresses.len() > 5 {
+                    message.push_str(" and ");
+                    message.push_str(&(addresses.len() - 5).to_string());
+                    message.push_str(" others");
+                }
+
+                return Err(err_code(
+                    ErrorCode::PrincipalHasMembers,
+                    "Domain has addresses",
+                    message.into(),
+                ));
+            }
+        }
 
         // SPDX-SnippetBegin
         // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
@@ -507,191 +871,223 @@ impl ManageDirectory for Store {
 
         // Make sure tenant has no data
         #[cfg(feature = "enterprise")]
-        match principal.typ {
-            Type::Individual | Type::Group => {
-                // Update tenant quota
-                if let Some(tenant_id) = principal.tenant() {
-                    let quota = self
-                        .get_counter(DirectoryClass::UsedQuota(principal_id))
+        self.assert_no_members(&principal).await?;
+
+        // SPDX-SnippetEnd
+
+        self.delete_principal_record(principal).await
+    }
+
+    async fn delete_principal_cascade(
+        &self,
+        by: QueryBy<'_>,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<PrincipalInfo>> {
+        let principal = self.get_principal_by(by).await?;
+        let principal_id = principal.id();
+        if !PrincipalInfo::new(principal_id, principal.typ, principal.tenant())
+            .has_tenant_access(tenant_id)
+        {
+            return Err(err_not_found(
+                ErrorCode::PrincipalNotFound,
+                principal.name().to_string(),
+            ));
+        }
+        self.assert_not_read_only(principal.tenant()).await?;
+        assert_not_on_hold(&principal)?;
+        self.assert_admins_remain(&principal, None).await?;
+
+        // The principal is deleted along with the changes to the accounts
+        // using it in a single batch, nothing is modified if any of them
+        // changed in the meantime
+        let mut batch = BatchBuilder::new();
+        let mut changes = directory_change_log();
+        let mut deleted = Vec::new();
+
+        if principal.typ == Type::Domain {
+            // Group the domain's addresses by the principal holding them
+            let mut holders: AHashMap<u32, Vec<String>> = AHashMap::new();
+            for (email, pinfo) in self
+                .list_domain_addresses(principal.name())
+                .await
+                .caused_by(trc::location!())?
+            {
+                holders.entry(pinfo.id).or_default().push(email);
+            }
+
+            for (holder_id, emails) in holders {
+                let Some(mut holder) = self
+                    .get_value::<HashedValue<Principal>>(ValueKey::from(ValueClass::Directory(
+                        DirectoryClass::Principal(holder_id),
+                    )))
+                    .await
+                    .caused_by(trc::location!())?
+                else {
+                    continue;
+                };
+                holder.inner.id = holder_id;
+
+                // Only principals the caller has access to can be modified,
+                // and nothing is modified if any of them is under hold
+                if !PrincipalInfo::new(holder_id, holder.inner.typ, holder.inner.tenant())
+                    .has_tenant_access(tenant_id)
+                {
+                    return Err(err_code(
+                        ErrorCode::PrincipalHasMembers,
+                        "Domain has addresses outside the tenant",
+                        format!(
+                            "Address {} belongs to a principal of another tenant",
+                            emails.first().map(String::as_str).unwrap_or_default()
+                        )
+                        .into(),
+                    ));
+                }
+                assert_not_on_hold(&holder.inner)?;
+
+                batch.assert_value(
+                    ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(
+                        holder_id,
+                    ))),
+                    &holder,
+                );
+
+                if holder
+                    .inner
+                    .iter_str(PrincipalField::Emails)
+                    .all(|email| emails.contains(email))
+                {
+                    // Principals left without any address are deleted
+                    self.assert_admins_remain(&holder.inner, None).await?;
+                    self.clear_principal_record(&mut batch, &holder.inner)
                         .await
                         .caused_by(trc::location!())?;
-                    if quota > 0 {
-                        batch.add(DirectoryClass::UsedQuota(tenant_id), -quota);
+                    changes.log_delete(Collection::Principal, holder_id);
+                    deleted.push(holder.inner);
+                } else {
+                    holder
+                        .inner
+                        .retain_str(PrincipalField::Emails, |email| !emails.contains(email));
+                    for email in emails {
+                        batch.clear(ValueClass::Directory(DirectoryClass::EmailToId(
+                            email.into_bytes(),
+                        )));
                     }
+                    batch.set(
+                        ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(
+                            holder_id,
+                        ))),
+                        holder.inner.serialize(),
+                    );
+                    changes.log_update(Collection::Principal, holder_id);
                 }
             }
-            Type::Tenant => {
-                let tenant_members = self
-                    .list_principals(
-                        None,
-                        principal.id().into(),
-                        &[
-                            Type::Individual,
-                            Type::Group,
-                            Type::Role,
-                            Type::List,
-                            Type::Resource,
-                            Type::Other,
-                            Type::Location,
-                            Type::Domain,
-                            Type::ApiKey,
-                        ],
-                        &[PrincipalField::Name],
-                        0,
-                        0,
-                    )
-                    .await
-                    .caused_by(trc::location!())?;
+        } else {
+            // SPDX-SnippetBegin
+            // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+            // SPDX-License-Identifier: LicenseRef-SEL
 
-                if tenant_members.total > 0 {
-                    let mut message =
-                        String::from("Tenant must have no members to be deleted: Found: ");
+            #[cfg(feature = "enterprise")]
+            self.assert_no_members(&principal).await?;
 
-                    for (num, principal) in tenant_members.items.iter().enumerate() {
-                        if num > 0 {
-                            message.push_str(", ");
-                        }
-                        message.push_str(principal.name());
-                    }
+            // SPDX-SnippetEnd
+        }
 
-                    if tenant_members.total > 5 {
-                        message.push_str(" and ");
-                        message.push_str(&(tenant_members.total - 5).to_string());
-                        message.push_str(" others");
-                    }
+        self.clear_principal_record(&mut batch, &principal)
+            .await
+            .caused_by(trc::location!())?;
+        changes.log_delete(Collection::Principal, principal_id);
+        batch
+            .with_account_id(u32::MAX)
+            .with_collection(Collection::Principal)
+            .custom(changes);
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
 
-                    return Err(error("Tenant has members", message.into()));
-                }
-            }
-            Type::Domain => {
-                if let Some(tenant_id) = principal.tenant() {
-                    let name = principal.name();
-                    let tenant_members = self
-                        .list_principals(
-                            None,
-                            tenant_id.into(),
-                            &[
-                                Type::Individual,
-                                Type::Group,
-                                Type::Role,
-                                Type::List,
-                                Type::Resource,
-                                Type::Other,
-                                Type::Location,
-                            ],
-                            &[PrincipalField::Name],
-                            0,
-                            0,
-                        )
-                        .await
-                        .caused_by(trc::location!())?;
-                    let domain_members = tenant_members
-                        .items
-                        .iter()
-                        .filter(|v| {
-                            v.name()
-                                .rsplit_once('@')
-                                .map_or(false, |(_, d)| d.eq_ignore_ascii_case(name))
-                        })
-                        .collect::<Vec<_>>();
-                    let total_domain_members = domain_members.len();
+        // Account data is purged once the directory changes are committed
+        for principal in deleted.iter().chain([&principal]) {
+            self.purge_principal_data(principal)
+                .await
+                .caused_by(trc::location!())?;
+        }
 
-                    if total_domain_members > 0 {
-                        let mut message =
-                            String::from("Domains must have no members to be deleted: Found: ");
+        Ok(deleted
+            .into_iter()
+            .map(|holder| PrincipalInfo::new(holder.id(), holder.typ, holder.tenant()))
+            .collect())
+    }
 
-                        for (num, principal) in domain_members.iter().enumerate() {
-                            if num > 0 {
-                                message.push_str(", ");
-                            }
-                            message.push_str(principal.name());
-                        }
+    async fn preview_delete_principal(&self, by: QueryBy<'_>) -> trc::Result<DeletePreview> {
+        let principal = self.get_principal_by(by).await?;
+        let principal_id = principal.id();
+        self.assert_not_read_only(principal.tenant()).await?;
+        self.assert_admins_remain(&principal, None).await?;
 
-                        if total_domain_members > 5 {
-                            message.push_str(" and ");
-                            message.push_str(&(total_domain_members - 5).to_string());
-                            message.push_str(" others");
-                        }
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
 
-                        return Err(error("Domain has members", message.into()));
-                    }
-                }
-            }
+        // Report the same error the deletion would fail with
+        #[cfg(feature = "enterprise")]
+        self.assert_no_members(&principal).await?;
 
-            _ => {}
-        }
         // SPDX-SnippetEnd
 
-        // Unlink all principal's blobs
-        self.blob_hash_unlink_account(principal_id)
-            .await
-            .caused_by(trc::location!())?;
-
-        // Revoke ACLs
-        self.acl_revoke_all(principal_id)
-            .await
-            .caused_by(trc::location!())?;
-
-        // Delete principal data
-        self.purge_account(principal_id)
-            .await
-            .caused_by(trc::location!())?;
-
-        // Delete principal
-        batch
-            .with_account_id(principal_id)
-            .clear(DirectoryClass::NameToId(
-                principal
-                    .take_str(PrincipalField::Name)
-                    .unwrap_or_default()
-                    .into_bytes(),
-            ))
-            .clear(DirectoryClass::Principal(MaybeDynamicId::Static(
-                principal_id,
-            )))
-            .clear(DirectoryClass::UsedQuota(principal_id));
-
-        if let Some(emails) = principal.take_str_array(PrincipalField::Emails) {
-            for email in emails {
-                batch.clear(DirectoryClass::EmailToId(email.into_bytes()));
-            }
-        }
+        let mut preview = DeletePreview {
+            id: principal_id,
+            name: principal.name().to_string(),
+            typ: principal.typ,
+            messages: self
+                .get_bitmap(BitmapKey::document_ids(principal_id, Collection::Email))
+                .await
+                .caused_by(trc::location!())?
+                .map_or(0, |ids| ids.len()),
+            bytes: self
+                .get_counter(DirectoryClass::UsedQuota(principal_id))
+                .await
+                .caused_by(trc::location!())?
+                .max(0) as u64,
+            blob_links: self
+                .blob_hash_count_account(principal_id)
+                .await
+                .caused_by(trc::location!())? as u64,
+            acl_grants: self
+                .acl_count_grants_on(principal_id)
+                .await
+                .caused_by(trc::location!())? as u64,
+            member_of: Vec::new(),
+            members: Vec::new(),
+        };
 
+        // Memberships that would be cleared
         for member in self
             .get_member_of(principal_id)
             .await
             .caused_by(trc::location!())?
         {
-            batch.clear(DirectoryClass::MemberOf {
-                principal_id: MaybeDynamicId::Static(principal_id),
-                member_of: MaybeDynamicId::Static(member.principal_id),
-            });
-            batch.clear(DirectoryClass::Members {
-                principal_id: MaybeDynamicId::Static(member.principal_id),
-                has_member: MaybeDynamicId::Static(principal_id),
-            });
+            if let Some(principal) = self
+                .get_principal(member.principal_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                preview.member_of.push(principal.name().to_string());
+            }
         }
-
         for member_id in self
             .get_members(principal_id)
             .await
             .caused_by(trc::location!())?
         {
-            batch.clear(DirectoryClass::MemberOf {
-                principal_id: MaybeDynamicId::Static(member_id),
-                member_of: MaybeDynamicId::Static(principal_id),
-            });
-            batch.clear(DirectoryClass::Members {
-                principal_id: MaybeDynamicId::Static(principal_id),
-                has_member: MaybeDynamicId::Static(member_id),
-            });
+            if let Some(principal) = self
+                .get_principal(member_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                preview.members.push(principal.name().to_string());
+            }
         }
 
-        self.write(batch.build())
-            .await
-            .caused_by(trc::location!())?;
-
-        Ok(())
+        Ok(preview)
     }
 
     async fn update_principal(&self, params: UpdatePrincipal<'_>) -> trc::Result<()> {
@@ -700,12 +1096,23 @@ impl ManageDirectory for Store {
                 .get_principal_id(name)
                 .await
                 .caused_by(trc::location!())?
-                .ok_or_else(|| not_found(name.to_string()))?,
-            QueryBy::Id(principal_id) => principal_id,
-            QueryBy::Credentials(_) => unreachable!(),
+                .ok_or_else(|| err_principal_not_found(name))?,
+            QueryBy::Id(principal_id) => {
+                assert_not_builtin_role(principal_id)?;
+                principal_id
+            }
+            QueryBy::ExternalId(external_id) => self
+                .external_id_to_id(external_id)
+                .await
+                .caused_by(trc::location!())?
+                .ok_or_else(|| {
+                    err_not_found(ErrorCode::PrincipalNotFound, external_id.to_string())
+                })?,
+            QueryBy::Credentials(_) | QueryBy::Certificate(_) => unreachable!(),
         };
         let changes = params.changes;
         let tenant_id = params.tenant_id;
+        let keep_sessions = params.keep_sessions;
 
         // Fetch principal
         let mut principal = self
@@ -714,8 +1121,17 @@ impl ManageDirectory for Store {
             )))
             .await
             .caused_by(trc::location!())?
-            .ok_or_else(|| not_found(principal_id))?;
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, principal_id))?;
+        if params
+            .expected_hash
+            .is_some_and(|hash| hash != principal.hash)
+        {
+            return Err(trc::StoreEvent::AssertValueFailed
+                .into_err()
+                .caused_by(trc::location!()));
+        }
         principal.inner.id = principal_id;
+        self.assert_not_read_only(principal.inner.tenant()).await?;
         let validate_emails = principal.inner.typ != Type::OauthClient;
 
         // Obtain members and memberOf
@@ -726,6 +1142,7 @@ impl ManageDirectory for Store {
             .into_iter()
             .map(|v| v.principal_id)
             .collect::<Vec<_>>();
+        let stored_member_of = member_of.clone();
         let mut members = self
             .get_members(principal_id)
             .await
@@ -736,7 +1153,8 @@ impl ManageDirectory for Store {
         let mut pinfo_name =
             PrincipalInfo::new(principal_id, principal.inner.typ, principal.inner.tenant())
                 .serialize();
-        let pinfo_email = PrincipalInfo::new(principal_id, principal.inner.typ, None).serialize();
+        let mut pinfo_email =
+            PrincipalInfo::new(principal_id, principal.inner.typ, None).serialize();
         let update_principal = !changes.is_empty()
             && !changes.iter().all(|c| {
                 matches!(
@@ -783,7 +1201,7 @@ impl ManageDirectory for Store {
 
         // Allowed principal types for Member fields
         let allowed_member_types = match principal.inner.typ() {
-            Type::Group => &[Type::Individual, Type::Group][..],
+            Type::Group => &[Type::Individual, Type::Group, Type::Service][..],
             Type::Resource => &[Type::Resource][..],
             Type::Location => &[
                 Type::Location,
@@ -798,11 +1216,29 @@ impl ManageDirectory for Store {
             | Type::Tenant
             | Type::Individual
             | Type::ApiKey
-            | Type::OauthClient => &[][..],
+            | Type::OauthClient
+            | Type::Service
+            | Type::Alias => &[][..],
             Type::Role => &[Type::Role][..],
         };
         let mut valid_domains = AHashSet::new();
 
+        // Changes are validated against a working copy of the principal and
+        // staged in the batch, nothing is written until all of them succeed.
+        // Addresses owned by the principal before the update can be removed
+        // and added back without being reported as taken.
+        let mut new_domains = AHashSet::new();
+        let stored_emails = principal
+            .inner
+            .iter_str(PrincipalField::Emails)
+            .cloned()
+            .collect::<AHashSet<_>>();
+        let stored_secrets = principal
+            .inner
+            .iter_str(PrincipalField::Secrets)
+            .cloned()
+            .collect::<Vec<_>>();
+
         // Process changes
         for change in changes {
             match (change.action, change.field, change.value) {
@@ -828,10 +1264,13 @@ impl ManageDirectory for Store {
                             }
 
                             if valid_domains.is_empty() {
-                                return Err(error(
+                                return Err(err_code(
+                                    ErrorCode::DomainNotAllowed,
                                     "Invalid principal name",
                                     "Principal name must include a valid domain assigned to the tenant".into(),
-                                ));
+                                )
+                                .ctx(trc::Key::Key, PrincipalField::Name)
+                                .ctx(trc::Key::Value, new_name.clone()));
                             }
                         }
 
@@ -839,11 +1278,60 @@ impl ManageDirectory for Store {
                             .get_principal_id(&new_name)
                             .await
                             .caused_by(trc::location!())?
-                            .is_some()
+                            .is_some_and(|id| id != principal_id)
+                            || (principal.inner.typ == Type::Role
+                                && PrincipalField::Roles
+                                    .map_internal_role_name(&new_name)
+                                    .is_some())
                         {
                             return Err(err_exists(PrincipalField::Name, new_name));
                         }
 
+                        // Move the address that embeds the login name
+                        if params.cascade_rename
+                            && new_name.contains('@')
+                            && principal
+                                .inner
+                                .has_str_value(PrincipalField::Emails, principal.inner.name())
+                            && !principal
+                                .inner
+                                .has_str_value(PrincipalField::Emails, &new_name)
+                        {
+                            if validate_emails && !stored_emails.contains(&new_name) {
+                                new_domains.extend(
+                                    self.validate_email(
+                                        &new_name,
+                                        tenant_id,
+                                        params.create_domains,
+                                    )
+                                    .await?,
+                                );
+                            }
+                            let old_name = principal.inner.name().to_string();
+                            let emails = principal
+                                .inner
+                                .iter_str(PrincipalField::Emails)
+                                .map(|email| {
+                                    if *email == old_name {
+                                        new_name.clone()
+                                    } else {
+                                        email.clone()
+                                    }
+                                })
+                                .collect::<Vec<_>>();
+                            principal.inner.set(PrincipalField::Emails, emails);
+                            batch
+                                .clear(ValueClass::Directory(DirectoryClass::EmailToId(
+                                    old_name.into_bytes(),
+                                )))
+                                .set(
+                                    ValueClass::Directory(DirectoryClass::EmailToId(
+                                        new_name.as_bytes().to_vec(),
+                                    )),
+                                    pinfo_email.clone(),
+                                );
+                        }
+
                         batch.clear(ValueClass::Directory(DirectoryClass::NameToId(
                             principal.inner.name().as_bytes().to_vec(),
                         )));
@@ -871,13 +1359,18 @@ impl ManageDirectory for Store {
                             .get_principal_info(&tenant_name)
                             .await
                             .caused_by(trc::location!())?
-                            .ok_or_else(|| not_found(tenant_name.clone()))?;
+                            .ok_or_else(|| {
+                                err_not_found(ErrorCode::TenantNotFound, tenant_name.clone())
+                            })?;
 
                         if tenant_info.typ != Type::Tenant {
-                            return Err(error(
+                            return Err(err_code(
+                                ErrorCode::PrincipalInvalidType,
                                 "Not a tenant",
                                 format!("Principal {tenant_name:?} is not a tenant").into(),
-                            ));
+                            )
+                            .ctx(trc::Key::Key, PrincipalField::Tenant)
+                            .ctx(trc::Key::Value, tenant_name));
                         }
 
                         if principal.inner.tenant() == Some(tenant_info.id) {
@@ -921,6 +1414,94 @@ impl ManageDirectory for Store {
                 }
 
                 // SPDX-SnippetEnd
+                (PrincipalAction::Set, PrincipalField::Type, PrincipalValue::String(new_type)) => {
+                    let new_type = Type::parse(&new_type).ok_or_else(|| {
+                        err_code(
+                            ErrorCode::PrincipalInvalidType,
+                            "Invalid principal type",
+                            format!("Unknown principal type {new_type:?}").into(),
+                        )
+                        .ctx(trc::Key::Key, PrincipalField::Type)
+                    })?;
+                    if new_type == principal.inner.typ {
+                        continue;
+                    }
+
+                    // Only conversions between individuals and service accounts are supported
+                    if !matches!(
+                        (principal.inner.typ, new_type),
+                        (Type::Individual, Type::Service) | (Type::Service, Type::Individual)
+                    ) {
+                        return Err(err_code(
+                            ErrorCode::PrincipalInvalidType,
+                            "Unsupported type conversion",
+                            format!(
+                                "{} principals cannot be converted to {}",
+                                principal.inner.typ.as_str(),
+                                new_type.as_str()
+                            )
+                            .into(),
+                        )
+                        .ctx(trc::Key::Key, PrincipalField::Type)
+                        .ctx(trc::Key::Value, new_type.to_jmap()));
+                    }
+
+                    // Service accounts do not receive mail
+                    if new_type == Type::Service
+                        && self
+                            .get_member_of(principal_id)
+                            .await
+                            .caused_by(trc::location!())?
+                            .iter()
+                            .any(|member| member.typ == Type::List)
+                    {
+                        return Err(err_code(
+                            ErrorCode::MemberInvalid,
+                            "Unsupported type conversion",
+                            "Service accounts cannot be members of mailing lists".into(),
+                        )
+                        .ctx(trc::Key::Key, PrincipalField::Lists));
+                    }
+
+                    principal.inner.typ = new_type;
+                    pinfo_name =
+                        PrincipalInfo::new(principal_id, new_type, principal.inner.tenant())
+                            .serialize();
+                    pinfo_email = PrincipalInfo::new(principal_id, new_type, None).serialize();
+                    batch.set(
+                        ValueClass::Directory(DirectoryClass::NameToId(
+                            principal.inner.name().as_bytes().to_vec(),
+                        )),
+                        pinfo_name.clone(),
+                    );
+                    for email in principal.inner.iter_str(PrincipalField::Emails) {
+                        batch.set(
+                            ValueClass::Directory(DirectoryClass::EmailToId(
+                                email.as_bytes().to_vec(),
+                            )),
+                            pinfo_email.clone(),
+                        );
+                    }
+                    if let Some(external_id) = principal.inner.get_str(PrincipalField::ExternalId) {
+                        batch.set(
+                            ValueClass::Directory(DirectoryClass::ExternalIdToId(
+                                external_id.as_bytes().to_vec(),
+                            )),
+                            pinfo_email.clone(),
+                        );
+                    }
+                    for fingerprint in principal
+                        .inner
+                        .iter_str(PrincipalField::CertificateFingerprints)
+                    {
+                        batch.set(
+                            ValueClass::Directory(DirectoryClass::CertificateToId(
+                                fingerprint.as_bytes().to_vec(),
+                            )),
+                            pinfo_email.clone(),
+                        );
+                    }
+                }
                 (
                     PrincipalAction::Set,
                     PrincipalField::Secrets,
@@ -950,10 +1531,22 @@ impl ManageDirectory for Store {
                     PrincipalField::Secrets,
                     PrincipalValue::String(secret),
                 ) => {
-                    if secret.is_app_password() || secret.is_otp_auth() {
+                    if secret.is_app_password() {
+                        // App passwords are removed by name, regardless of their scopes
+                        let name = secret.app_password_name().unwrap_or_default();
+                        let is_label = secret.app_password().is_none();
+                        principal.inner.retain_str(PrincipalField::Secrets, |v| {
+                            *v != secret && (!is_label || v.app_password_name() != Some(name))
+                        });
+                    } else if secret.is_otp_auth() {
                         principal.inner.retain_str(PrincipalField::Secrets, |v| {
                             *v != secret && !v.starts_with(&secret)
                         });
+                    } else if secret == RECOVERY_CODE_PREFIX {
+                        // Remove all recovery codes
+                        principal
+                            .inner
+                            .retain_str(PrincipalField::Secrets, |v| !v.is_recovery_code());
                     } else if !secret.is_empty() {
                         principal
                             .inner
@@ -975,81 +1568,263 @@ impl ManageDirectory for Store {
                         principal.inner.remove(change.field);
                     }
                 }
-                (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::Integer(quota))
-                    if matches!(
-                        principal.inner.typ,
-                        Type::Individual | Type::Group | Type::Tenant
-                    ) =>
-                {
-                    principal.inner.set(PrincipalField::Quota, quota);
-                }
-                (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::String(quota))
-                    if matches!(
-                        principal.inner.typ,
-                        Type::Individual | Type::Group | Type::Tenant
-                    ) && quota.is_empty() =>
-                {
-                    principal.inner.remove(PrincipalField::Quota);
-                }
                 (
                     PrincipalAction::Set,
-                    PrincipalField::Quota,
-                    PrincipalValue::IntegerList(quotas),
-                ) if matches!(principal.inner.typ, Type::Tenant)
-                    && quotas.len() <= (MAX_TYPE_ID + 2) =>
-                {
-                    principal.inner.set(PrincipalField::Quota, quotas);
+                    PrincipalField::Locale | PrincipalField::Timezone,
+                    PrincipalValue::String(value),
+                ) => {
+                    if !value.is_empty() {
+                        let value = validate_locale_setting(change.field, &value)?;
+                        principal.inner.set(change.field, value);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
                 }
-
-                // Emails
                 (
                     PrincipalAction::Set,
-                    PrincipalField::Emails,
-                    PrincipalValue::StringList(emails),
+                    PrincipalField::ExternalId,
+                    PrincipalValue::String(external_id),
                 ) => {
-                    // Validate unique emails
-                    let emails = emails
-                        .into_iter()
-                        .map(|v| v.to_lowercase())
-                        .collect::<Vec<_>>();
-                    for email in &emails {
-                        if !principal.inner.has_str_value(PrincipalField::Emails, email) {
-                            if validate_emails {
-                                self.validate_email(email, tenant_id, params.create_domains)
-                                    .await?;
-                            }
-                            batch.set(
-                                ValueClass::Directory(DirectoryClass::EmailToId(
-                                    email.as_bytes().to_vec(),
-                                )),
-                                pinfo_email.clone(),
-                            );
-                        }
+                    if principal.inner.get_str(PrincipalField::ExternalId)
+                        == Some(external_id.as_str())
+                    {
+                        continue;
                     }
 
-                    for email in principal.inner.iter_str(PrincipalField::Emails) {
-                        if !emails.contains(email) {
-                            batch.clear(ValueClass::Directory(DirectoryClass::EmailToId(
-                                email.as_bytes().to_vec(),
-                            )));
+                    // Make sure the new external id is not taken
+                    if !external_id.is_empty() {
+                        if self
+                            .external_id_to_id(&external_id)
+                            .await
+                            .caused_by(trc::location!())?
+                            .is_some()
+                        {
+                            return Err(err_exists(PrincipalField::ExternalId, external_id));
                         }
+                        batch.assert_value(
+                            ValueClass::Directory(DirectoryClass::ExternalIdToId(
+                                external_id.as_bytes().to_vec(),
+                            )),
+                            (),
+                        );
                     }
 
-                    principal.inner.set(PrincipalField::Emails, emails);
-                }
-                (
-                    PrincipalAction::AddItem,
-                    PrincipalField::Emails,
-                    PrincipalValue::String(email),
+                    if let Some(old_external_id) =
+                        principal.inner.take_str(PrincipalField::ExternalId)
+                    {
+                        batch.clear(ValueClass::Directory(DirectoryClass::ExternalIdToId(
+                            old_external_id.into_bytes(),
+                        )));
+                    }
+
+                    if !external_id.is_empty() {
+                        batch.set(
+                            ValueClass::Directory(DirectoryClass::ExternalIdToId(
+                                external_id.as_bytes().to_vec(),
+                            )),
+                            pinfo_email.clone(),
+                        );
+                        principal.inner.set(PrincipalField::ExternalId, external_id);
+                    }
+                }
+                (
+                    action @ (PrincipalAction::Set | PrincipalAction::AddItem),
+                    PrincipalField::CertificateFingerprints,
+                    value @ (PrincipalValue::StringList(_) | PrincipalValue::String(_)),
+                ) => {
+                    let mut fingerprints = Vec::new();
+                    for fingerprint in value.into_str_array() {
+                        let fingerprint = normalize_fingerprint(&fingerprint)?;
+                        if !fingerprints.contains(&fingerprint) {
+                            fingerprints.push(fingerprint);
+                        }
+                    }
+
+                    if action == PrincipalAction::Set {
+                        for fingerprint in principal
+                            .inner
+                            .take_str_array(PrincipalField::CertificateFingerprints)
+                            .unwrap_or_default()
+                        {
+                            if !fingerprints.contains(&fingerprint) {
+                                batch.clear(ValueClass::Directory(
+                                    DirectoryClass::CertificateToId(fingerprint.into_bytes()),
+                                ));
+                            } else {
+                                principal.inner.append_str(
+                                    PrincipalField::CertificateFingerprints,
+                                    fingerprint,
+                                );
+                            }
+                        }
+                    }
+
+                    for fingerprint in fingerprints {
+                        if principal
+                            .inner
+                            .has_str_value(PrincipalField::CertificateFingerprints, &fingerprint)
+                        {
+                            continue;
+                        }
+
+                        // Make sure the fingerprint is not registered to another principal
+                        let key = fingerprint.as_bytes().to_vec();
+                        if self
+                            .get_value::<()>(ValueKey::from(ValueClass::Directory(
+                                DirectoryClass::CertificateToId(key.clone()),
+                            )))
+                            .await
+                            .caused_by(trc::location!())?
+                            .is_some()
+                        {
+                            return Err(err_exists(
+                                PrincipalField::CertificateFingerprints,
+                                fingerprint,
+                            ));
+                        }
+                        batch
+                            .assert_value(
+                                ValueClass::Directory(DirectoryClass::CertificateToId(key.clone())),
+                                (),
+                            )
+                            .set(
+                                ValueClass::Directory(DirectoryClass::CertificateToId(key)),
+                                pinfo_email.clone(),
+                            );
+                        principal
+                            .inner
+                            .append_str(PrincipalField::CertificateFingerprints, fingerprint);
+                    }
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::CertificateFingerprints,
+                    PrincipalValue::String(fingerprint),
+                ) => {
+                    let fingerprint = normalize_fingerprint(&fingerprint)?;
+                    if principal
+                        .inner
+                        .has_str_value(PrincipalField::CertificateFingerprints, &fingerprint)
+                    {
+                        principal
+                            .inner
+                            .retain_str(PrincipalField::CertificateFingerprints, |v| {
+                                *v != fingerprint
+                            });
+                        batch.clear(ValueClass::Directory(DirectoryClass::CertificateToId(
+                            fingerprint.into_bytes(),
+                        )));
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Enabled,
+                    PrincipalValue::Integer(enabled),
+                ) => {
+                    // Only disabled principals store the flag
+                    if enabled == 0 {
+                        principal.inner.set(PrincipalField::Enabled, 0u64);
+                    } else {
+                        principal.inner.remove(PrincipalField::Enabled);
+                    }
+                }
+                (PrincipalAction::Set, PrincipalField::Hold, PrincipalValue::Integer(hold)) => {
+                    // The flag stores when the hold was placed
+                    if hold == 0 {
+                        principal.inner.remove(PrincipalField::Hold);
+                    } else if !principal.inner.is_on_hold() {
+                        principal.inner.set(PrincipalField::Hold, now());
+                    }
+                }
+                (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::Integer(quota))
+                    if matches!(
+                        principal.inner.typ,
+                        Type::Individual | Type::Group | Type::Tenant
+                    ) =>
+                {
+                    principal.inner.set(PrincipalField::Quota, quota);
+                }
+                (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::String(quota))
+                    if matches!(
+                        principal.inner.typ,
+                        Type::Individual | Type::Group | Type::Tenant
+                    ) && quota.is_empty() =>
+                {
+                    principal.inner.remove(PrincipalField::Quota);
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Quota,
+                    PrincipalValue::IntegerList(quotas),
+                ) if matches!(principal.inner.typ, Type::Tenant)
+                    && quotas.len() <= (MAX_TYPE_ID + 2) =>
+                {
+                    principal.inner.set(PrincipalField::Quota, quotas);
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Quota,
+                    PrincipalValue::IntegerList(quotas),
+                ) if matches!(principal.inner.typ, Type::Individual | Type::Group)
+                    && quotas.len() <= 2 =>
+                {
+                    principal.inner.set(PrincipalField::Quota, quotas);
+                }
+
+                // Emails
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Emails,
+                    PrincipalValue::StringList(emails),
+                ) => {
+                    // Validate syntax and unique emails
+                    let mut emails = emails
+                        .iter()
+                        .map(|v| normalize_email(v))
+                        .collect::<trc::Result<Vec<_>>>()?;
+                    dedup_values(&mut emails);
+                    for email in &emails {
+                        if !principal.inner.has_str_value(PrincipalField::Emails, email) {
+                            if validate_emails && !stored_emails.contains(email) {
+                                new_domains.extend(
+                                    self.validate_email(email, tenant_id, params.create_domains)
+                                        .await?,
+                                );
+                            }
+                            batch.set(
+                                ValueClass::Directory(DirectoryClass::EmailToId(
+                                    email.as_bytes().to_vec(),
+                                )),
+                                pinfo_email.clone(),
+                            );
+                        }
+                    }
+
+                    for email in principal.inner.iter_str(PrincipalField::Emails) {
+                        if !emails.contains(email) {
+                            batch.clear(ValueClass::Directory(DirectoryClass::EmailToId(
+                                email.as_bytes().to_vec(),
+                            )));
+                        }
+                    }
+
+                    principal.inner.set(PrincipalField::Emails, emails);
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::Emails,
+                    PrincipalValue::String(email),
                 ) => {
-                    let email = email.to_lowercase();
+                    let email = normalize_email(&email)?;
                     if !principal
                         .inner
                         .has_str_value(PrincipalField::Emails, &email)
                     {
-                        if validate_emails {
-                            self.validate_email(&email, tenant_id, params.create_domains)
-                                .await?;
+                        if validate_emails && !stored_emails.contains(&email) {
+                            new_domains.extend(
+                                self.validate_email(&email, tenant_id, params.create_domains)
+                                    .await?,
+                            );
                         }
                         batch.set(
                             ValueClass::Directory(DirectoryClass::EmailToId(
@@ -1065,7 +1840,8 @@ impl ManageDirectory for Store {
                     PrincipalField::Emails,
                     PrincipalValue::String(email),
                 ) => {
-                    let email = email.to_lowercase();
+                    // Addresses stored before validation was enforced can still be removed
+                    let email = normalize_email(&email).unwrap_or_else(|_| email.to_lowercase());
                     if principal
                         .inner
                         .has_str_value(PrincipalField::Emails, &email)
@@ -1083,17 +1859,22 @@ impl ManageDirectory for Store {
                 (
                     PrincipalAction::Set,
                     PrincipalField::MemberOf | PrincipalField::Lists | PrincipalField::Roles,
-                    PrincipalValue::StringList(members),
+                    PrincipalValue::StringList(names),
                 ) => {
                     let mut new_member_of = Vec::new();
-                    for member in members {
+                    for member in names {
                         let member_info = self
                             .get_principal_info(&member)
                             .await
                             .caused_by(trc::location!())?
                             .filter(|p| p.has_tenant_access(tenant_id))
                             .or_else(|| change.field.map_internal_roles(&member))
-                            .ok_or_else(|| not_found(member.clone()))?;
+                            .ok_or_else(|| {
+                                err_not_found(ErrorCode::PrincipalNotFound, member.clone())
+                            })?;
+                        if new_member_of.contains(&member_info.id) {
+                            continue;
+                        }
 
                         validate_member_of(
                             change.field,
@@ -1103,6 +1884,18 @@ impl ManageDirectory for Store {
                         )?;
 
                         if !member_of.contains(&member_info.id) {
+                            if self
+                                .has_membership_path(
+                                    &[member_info.id],
+                                    &[&[principal_id][..], &members].concat(),
+                                    principal_id,
+                                )
+                                .await
+                                .caused_by(trc::location!())?
+                            {
+                                return Err(err_circular_membership(change.field, &member));
+                            }
+
                             batch.set(
                                 ValueClass::Directory(DirectoryClass::MemberOf {
                                     principal_id: MaybeDynamicId::Static(principal_id),
@@ -1148,7 +1941,9 @@ impl ManageDirectory for Store {
                         .caused_by(trc::location!())?
                         .filter(|p| p.has_tenant_access(tenant_id))
                         .or_else(|| change.field.map_internal_roles(&member))
-                        .ok_or_else(|| not_found(member.clone()))?;
+                        .ok_or_else(|| {
+                            err_not_found(ErrorCode::PrincipalNotFound, member.clone())
+                        })?;
 
                     if !member_of.contains(&member_info.id) {
                         validate_member_of(
@@ -1158,6 +1953,18 @@ impl ManageDirectory for Store {
                             &member,
                         )?;
 
+                        if self
+                            .has_membership_path(
+                                &[member_info.id],
+                                &[&[principal_id][..], &members].concat(),
+                                principal_id,
+                            )
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            return Err(err_circular_membership(change.field, &member));
+                        }
+
                         batch.set(
                             ValueClass::Directory(DirectoryClass::MemberOf {
                                 principal_id: MaybeDynamicId::Static(principal_id),
@@ -1217,10 +2024,16 @@ impl ManageDirectory for Store {
                             .await
                             .caused_by(trc::location!())?
                             .filter(|p| p.has_tenant_access(tenant_id))
-                            .ok_or_else(|| not_found(member.clone()))?;
+                            .ok_or_else(|| {
+                                err_not_found(ErrorCode::PrincipalNotFound, member.clone())
+                            })?;
+                        if new_members.contains(&member_info.id) {
+                            continue;
+                        }
 
                         if !allowed_member_types.contains(&member_info.typ) {
-                            return Err(error(
+                            return Err(err_code(
+                                ErrorCode::MemberInvalid,
                                 "Invalid members value",
                                 format!(
                                     "Principal {member:?} is not one of {}.",
@@ -1231,10 +2044,27 @@ impl ManageDirectory for Store {
                                         .join(", ")
                                 )
                                 .into(),
-                            ));
+                            )
+                            .ctx(trc::Key::Key, PrincipalField::Members)
+                            .ctx(trc::Key::Value, member.clone()));
                         }
 
                         if !members.contains(&member_info.id) {
+                            if self
+                                .has_membership_path(
+                                    &[&[principal_id][..], &member_of].concat(),
+                                    &[member_info.id],
+                                    principal_id,
+                                )
+                                .await
+                                .caused_by(trc::location!())?
+                            {
+                                return Err(err_circular_membership(
+                                    PrincipalField::Members,
+                                    &member,
+                                ));
+                            }
+
                             batch.set(
                                 ValueClass::Directory(DirectoryClass::MemberOf {
                                     principal_id: MaybeDynamicId::Static(member_info.id),
@@ -1279,11 +2109,14 @@ impl ManageDirectory for Store {
                         .await
                         .caused_by(trc::location!())?
                         .filter(|p| p.has_tenant_access(tenant_id))
-                        .ok_or_else(|| not_found(member.clone()))?;
+                        .ok_or_else(|| {
+                            err_not_found(ErrorCode::PrincipalNotFound, member.clone())
+                        })?;
 
                     if !members.contains(&member_info.id) {
                         if !allowed_member_types.contains(&member_info.typ) {
-                            return Err(error(
+                            return Err(err_code(
+                                ErrorCode::MemberInvalid,
                                 "Invalid members value",
                                 format!(
                                     "Principal {member:?} is not one of {}.",
@@ -1294,7 +2127,21 @@ impl ManageDirectory for Store {
                                         .join(", ")
                                 )
                                 .into(),
-                            ));
+                            )
+                            .ctx(trc::Key::Key, PrincipalField::Members)
+                            .ctx(trc::Key::Value, member.clone()));
+                        }
+
+                        if self
+                            .has_membership_path(
+                                &[&[principal_id][..], &member_of].concat(),
+                                &[member_info.id],
+                                principal_id,
+                            )
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            return Err(err_circular_membership(PrincipalField::Members, &member));
                         }
 
                         batch.set(
@@ -1347,10 +2194,13 @@ impl ManageDirectory for Store {
                     for name in names {
                         let permission = Permission::from_name(&name)
                             .ok_or_else(|| {
-                                error(
+                                err_code(
+                                    ErrorCode::PermissionInvalid,
                                     format!("Invalid {} value", change.field.as_str()),
                                     format!("Permission {name:?} is invalid").into(),
                                 )
+                                .ctx(trc::Key::Key, change.field)
+                                .ctx(trc::Key::Value, name.clone())
                             })?
                             .id() as u64;
 
@@ -1363,11 +2213,14 @@ impl ManageDirectory for Store {
                             {
                                 permissions.push(permission);
                             } else {
-                                return Err(error(
+                                return Err(err_code(
+                                    ErrorCode::PermissionNotGrantable,
                                     "Invalid permission",
                                     format!("Your account cannot grant the {name:?} permission")
                                         .into(),
-                                ));
+                                )
+                                .ctx(trc::Key::Key, change.field)
+                                .ctx(trc::Key::Value, name.clone()));
                             }
                         }
                     }
@@ -1385,10 +2238,13 @@ impl ManageDirectory for Store {
                 ) => {
                     let permission = Permission::from_name(&name)
                         .ok_or_else(|| {
-                            error(
+                            err_code(
+                                ErrorCode::PermissionInvalid,
                                 format!("Invalid {} value", change.field.as_str()),
                                 format!("Permission {name:?} is invalid").into(),
                             )
+                            .ctx(trc::Key::Key, change.field)
+                            .ctx(trc::Key::Value, name.clone())
                         })?
                         .id() as u64;
 
@@ -1398,12 +2254,17 @@ impl ManageDirectory for Store {
                         .map_or(true, |p| p.get(permission as usize))
                         || change.field == PrincipalField::DisabledPermissions
                     {
-                        principal.inner.append_int(change.field, permission);
+                        if !principal.inner.has_int_value(change.field, permission) {
+                            principal.inner.append_int(change.field, permission);
+                        }
                     } else {
-                        return Err(error(
+                        return Err(err_code(
+                            ErrorCode::PermissionNotGrantable,
                             "Invalid permission",
                             format!("Your account cannot grant the {name:?} permission").into(),
-                        ));
+                        )
+                        .ctx(trc::Key::Key, change.field)
+                        .ctx(trc::Key::Value, name.clone()));
                     }
                 }
                 (
@@ -1413,10 +2274,13 @@ impl ManageDirectory for Store {
                 ) => {
                     let permission = Permission::from_name(&name)
                         .ok_or_else(|| {
-                            error(
+                            err_code(
+                                ErrorCode::PermissionInvalid,
                                 format!("Invalid {} value", change.field.as_str()),
                                 format!("Permission {name:?} is invalid").into(),
                             )
+                            .ctx(trc::Key::Key, change.field)
+                            .ctx(trc::Key::Value, name.clone())
                         })?
                         .id() as u64;
 
@@ -1424,17 +2288,73 @@ impl ManageDirectory for Store {
                         .inner
                         .retain_int(change.field, |v| *v != permission);
                 }
+                (_, PrincipalField::ForwardTo, _) if principal.inner.typ != Type::Alias => {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only aliases can forward messages".into(),
+                    )
+                    .ctx(trc::Key::Key, change.field));
+                }
+                (_, PrincipalField::CatchAll | PrincipalField::Subaddressing, _)
+                    if principal.inner.typ != Type::Domain =>
+                {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only domains can configure recipient routing".into(),
+                    )
+                    .ctx(trc::Key::Key, change.field));
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::CatchAll,
+                    PrincipalValue::String(address),
+                ) => {
+                    if !address.is_empty() {
+                        let address = sanitize_email(&address).ok_or_else(|| {
+                            err_code(
+                                ErrorCode::AddressInvalid,
+                                "Invalid email address",
+                                format!("Invalid value {address:?} for catchAll").into(),
+                            )
+                            .ctx(trc::Key::Key, PrincipalField::CatchAll)
+                            .ctx(trc::Key::Value, address.clone())
+                        })?;
+                        principal.inner.set(change.field, address);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Subaddressing,
+                    PrincipalValue::String(value),
+                ) => {
+                    if !value.is_empty() {
+                        validate_subaddressing(&value)?;
+                        principal.inner.set(change.field, value);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
+                }
                 (
                     PrincipalAction::Set,
-                    PrincipalField::Urls | PrincipalField::ExternalMembers,
+                    PrincipalField::Urls
+                    | PrincipalField::ExternalMembers
+                    | PrincipalField::ForwardTo,
                     PrincipalValue::StringList(mut items),
                 ) => {
-                    if matches!(change.field, PrincipalField::ExternalMembers) {
+                    if matches!(
+                        change.field,
+                        PrincipalField::ExternalMembers | PrincipalField::ForwardTo
+                    ) {
                         items = items
                             .into_iter()
                             .map(|item| {
                                 sanitize_email(&item).ok_or_else(|| {
-                                    error(
+                                    err_code(
+                                        ErrorCode::AddressInvalid,
                                         "Invalid email address",
                                         format!(
                                             "Invalid value {:?} for {}",
@@ -1443,29 +2363,42 @@ impl ManageDirectory for Store {
                                         )
                                         .into(),
                                     )
+                                    .ctx(trc::Key::Key, change.field)
+                                    .ctx(trc::Key::Value, item.clone())
                                 })
                             })
                             .collect::<trc::Result<_>>()?;
                     }
+                    dedup_values(&mut items);
 
                     if !items.is_empty() {
                         principal.inner.set(change.field, items);
+                    } else if change.field == PrincipalField::ForwardTo {
+                        return Err(err_missing(PrincipalField::ForwardTo));
                     } else {
                         principal.inner.remove(change.field);
                     }
                 }
                 (
                     PrincipalAction::AddItem,
-                    PrincipalField::Urls | PrincipalField::ExternalMembers,
+                    PrincipalField::Urls
+                    | PrincipalField::ExternalMembers
+                    | PrincipalField::ForwardTo,
                     PrincipalValue::String(mut item),
                 ) => {
-                    if matches!(change.field, PrincipalField::ExternalMembers) {
+                    if matches!(
+                        change.field,
+                        PrincipalField::ExternalMembers | PrincipalField::ForwardTo
+                    ) {
                         item = sanitize_email(&item).ok_or_else(|| {
-                            error(
+                            err_code(
+                                ErrorCode::AddressInvalid,
                                 "Invalid email address",
                                 format!("Invalid value {:?} for {}", item, change.field.as_str())
                                     .into(),
                             )
+                            .ctx(trc::Key::Key, change.field)
+                            .ctx(trc::Key::Value, item.clone())
                         })?
                     }
 
@@ -1475,103 +2408,340 @@ impl ManageDirectory for Store {
                 }
                 (
                     PrincipalAction::RemoveItem,
-                    PrincipalField::Urls | PrincipalField::ExternalMembers,
+                    PrincipalField::Urls
+                    | PrincipalField::ExternalMembers
+                    | PrincipalField::ForwardTo,
                     PrincipalValue::String(item),
                 ) => {
                     if principal.inner.has_str_value(change.field, &item) {
                         principal.inner.retain_str(change.field, |v| *v != item);
                     }
-                }
 
-                (_, field, value) => {
-                    return Err(error(
-                        "Invalid parameter",
-                        format!("Invalid value {:?} for {}", value, field.as_str()).into(),
-                    ));
+                    // Aliases always forward somewhere
+                    if change.field == PrincipalField::ForwardTo
+                        && !principal.inner.has_field(PrincipalField::ForwardTo)
+                    {
+                        return Err(err_missing(PrincipalField::ForwardTo));
+                    }
                 }
-            }
-        }
-
-        if update_principal {
-            batch.set(
-                ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(
-                    principal_id,
-                ))),
-                principal.inner.serialize(),
-            );
-        }
-
-        self.write(batch.build())
-            .await
-            .caused_by(trc::location!())?;
-
-        Ok(())
-    }
-
-    async fn list_principals(
-        &self,
-        filter: Option<&str>,
-        tenant_id: Option<u32>,
-        types: &[Type],
-        fields: &[PrincipalField],
-        page: usize,
-        limit: usize,
-    ) -> trc::Result<PrincipalList> {
-        let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![])));
-        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![
-            u8::MAX;
-            10
-        ])));
-
-        let mut results = Vec::new();
-        self.iterate(
-            IterateParams::new(from_key, to_key).ascending(),
-            |key, value| {
-                let pt = PrincipalInfo::deserialize(value).caused_by(trc::location!())?;
-
-                if (types.is_empty() || types.contains(&pt.typ)) && pt.has_tenant_access(tenant_id)
-                {
-                    results.push(Principal::new(pt.id, pt.typ).with_field(
-                        PrincipalField::Name,
-                        String::from_utf8_lossy(key.get(1..).unwrap_or_default()).into_owned(),
-                    ));
+                (
+                    _,
+                    PrincipalField::PostingPolicy
+                    | PrincipalField::Moderators
+                    | PrincipalField::ReplyToList,
+                    _,
+                ) if principal.inner.typ != Type::List => {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only mailing lists can configure posting settings".into(),
+                    )
+                    .ctx(trc::Key::Key, change.field));
                 }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::PostingPolicy,
+                    PrincipalValue::String(value),
+                ) => {
+                    if !value.is_empty() {
+                        validate_posting_policy(&value)?;
+                        principal.inner.set(change.field, value);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::ReplyToList,
+                    PrincipalValue::Integer(value),
+                ) => {
+                    // Only lists rewriting Reply-To store the flag
+                    if value != 0 {
+                        principal.inner.set(change.field, 1u64);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::Moderators,
+                    PrincipalValue::StringList(names),
+                ) => {
+                    let moderators = self.validate_moderators(names, tenant_id).await?;
+                    if !moderators.is_empty() {
+                        principal.inner.set(change.field, moderators);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::Moderators,
+                    PrincipalValue::String(name),
+                ) => {
+                    for moderator_id in self.validate_moderators(vec![name], tenant_id).await? {
+                        if !principal.inner.has_int_value(change.field, moderator_id) {
+                            principal.inner.append_int(change.field, moderator_id);
+                        }
+                    }
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::Moderators,
+                    PrincipalValue::String(name),
+                ) => {
+                    if let Some(moderator_id) = self
+                        .get_principal_id(&name)
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        principal
+                            .inner
+                            .retain_int(change.field, |v| *v != moderator_id as u64);
+                    }
+                }
+                (_, PrincipalField::DefaultRoles | PrincipalField::DefaultPermissions, _)
+                    if principal.inner.typ != Type::Tenant =>
+                {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only tenants can define default roles and permissions".into(),
+                    )
+                    .ctx(trc::Key::Key, change.field));
+                }
+                (
+                    PrincipalAction::Set,
+                    PrincipalField::DefaultRoles | PrincipalField::DefaultPermissions,
+                    PrincipalValue::StringList(items),
+                ) => {
+                    let items = self
+                        .validate_tenant_defaults(change.field, items, principal_id.into())
+                        .await?;
+                    if !items.is_empty() {
+                        principal.inner.set(change.field, items);
+                    } else {
+                        principal.inner.remove(change.field);
+                    }
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::DefaultRoles | PrincipalField::DefaultPermissions,
+                    PrincipalValue::String(item),
+                ) => {
+                    for item in self
+                        .validate_tenant_defaults(change.field, vec![item], principal_id.into())
+                        .await?
+                    {
+                        if !principal.inner.has_str_value(change.field, &item) {
+                            principal.inner.append_str(change.field, item);
+                        }
+                    }
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::DefaultRoles | PrincipalField::DefaultPermissions,
+                    PrincipalValue::String(item),
+                ) => {
+                    principal
+                        .inner
+                        .retain_str(change.field, |v| !v.eq_ignore_ascii_case(&item));
+                }
+
+                (_, field, value) => {
+                    return Err(err_code(
+                        ErrorCode::FieldInvalid,
+                        "Invalid parameter",
+                        format!("Invalid value {:?} for {}", value, field.as_str()).into(),
+                    )
+                    .ctx(trc::Key::Key, field));
+                }
+            }
+        }
+
+        // The last administrator cannot be demoted
+        let removed_roles = stored_member_of
+            .into_iter()
+            .filter(|id| !member_of.contains(id))
+            .collect::<Vec<_>>();
+        if !removed_roles.is_empty() {
+            self.assert_admins_remain(&principal.inner, Some(&removed_roles))
+                .await?;
+        }
 
-                Ok(true)
-            },
-        )
-        .await
-        .caused_by(trc::location!())?;
-
-        if filter.is_none()
-            && !fields.is_empty()
-            && fields.iter().all(|f| matches!(f, PrincipalField::Name))
+        // Sessions and tokens issued before a secret was removed or replaced
+        // are revoked, adding secrets leaves them untouched
+        if !keep_sessions
+            && stored_secrets.iter().any(|secret| {
+                !principal
+                    .inner
+                    .has_str_value(PrincipalField::Secrets, secret)
+            })
         {
-            return Ok(PrincipalList {
-                total: results.len() as u64,
-                items: results
-                    .into_iter()
-                    .skip(page.saturating_sub(1) * limit)
-                    .take(if limit > 0 { limit } else { usize::MAX })
-                    .collect(),
-            });
+            principal.inner.set(
+                PrincipalField::CredentialsEpoch,
+                principal.inner.credentials_epoch() + 1,
+            );
         }
 
-        let mut result = PrincipalList::default();
-        let filters = filter.and_then(|filter| {
-            let filters = filter
-                .split_whitespace()
-                .map(|r| r.to_lowercase())
-                .collect::<Vec<_>>();
-            if !filters.is_empty() {
-                Some(filters)
-            } else {
-                None
+        if update_principal {
+            batch.set(
+                ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(
+                    principal_id,
+                ))),
+                principal.inner.serialize(),
+            );
+        }
+
+        // Log change
+        batch
+            .with_account_id(u32::MAX)
+            .with_collection(Collection::Principal)
+            .custom(directory_change_log().with_log_update(Collection::Principal, principal_id));
+
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        // Create the domains of new addresses once the update is committed,
+        // a failed update must not leave orphaned domains behind
+        for domain in new_domains {
+            match self
+                .create_principal(
+                    Principal::new(0, Type::Domain)
+                        .with_field(PrincipalField::Name, domain.clone())
+                        .with_field(PrincipalField::Description, domain),
+                    tenant_id,
+                    None,
+                )
+                .await
+            {
+                Ok(_) => {}
+                Err(err)
+                    if err.matches(trc::EventType::Manage(trc::ManageEvent::AlreadyExists)) =>
+                {
+                    // Domain created concurrently
+                }
+                Err(err) => return Err(err.caused_by(trc::location!())),
             }
-        });
+        }
+
+        Ok(())
+    }
+
+    /// Bumps the credentials epoch of a principal, which invalidates the
+    /// sessions and tokens issued so far. Returns the new epoch.
+    async fn revoke_credentials(&self, principal_id: u32) -> trc::Result<u64> {
+        let mut principal = self
+            .get_value::<HashedValue<Principal>>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::Principal(principal_id),
+            )))
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, principal_id))?;
+
+        // Revocations are allowed in read-only mode, they are usually issued
+        // in response to a compromised account
+        let epoch = principal.inner.credentials_epoch() + 1;
+        let mut batch = BatchBuilder::new();
+        batch.assert_value(
+            ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(
+                principal_id,
+            ))),
+            &principal,
+        );
+        principal.inner.set(PrincipalField::CredentialsEpoch, epoch);
+        batch.set(
+            ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Static(
+                principal_id,
+            ))),
+            principal.inner.serialize(),
+        );
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(epoch)
+    }
+
+    async fn list_principals(
+        &self,
+        filter: Option<&PrincipalFilter>,
+        tenant_id: Option<u32>,
+        types: &[Type],
+        fields: &[PrincipalField],
+        page: usize,
+        limit: usize,
+    ) -> trc::Result<PrincipalList> {
+        // Entries are read up to the end of the requested page, principals are
+        // only loaded at this point when required by the filter
+        let offset = limit * page.saturating_sub(1);
+        let field_filter = filter.filter(|filter| !filter.is_name_only());
+        let PrincipalPage { items, next } = self
+            .list_principals_after(
+                filter,
+                tenant_id,
+                types,
+                if field_filter.is_none() {
+                    &[PrincipalField::Name]
+                } else {
+                    fields
+                },
+                None,
+                if limit > 0 { offset + limit } else { 0 },
+            )
+            .await
+            .caused_by(trc::location!())?;
+        let after = offset
+            .checked_sub(1)
+            .and_then(|idx| items.get(idx))
+            .map(|principal| principal.name().to_string());
+        let mut result = PrincipalList {
+            total: items.len() as u64,
+            items: items.into_iter().skip(offset).collect(),
+        };
+
+        // Entries past the page are only counted
+        if let Some(next) = next {
+            result.total += self
+                .list_principals_after(
+                    filter,
+                    tenant_id,
+                    types,
+                    &[PrincipalField::Name],
+                    Some(&next),
+                    0,
+                )
+                .await
+                .caused_by(trc::location!())?
+                .items
+                .len() as u64;
+        }
+
+        // Load the principals on the page
+        if field_filter.is_none()
+            && !result.items.is_empty()
+            && (fields.is_empty() || fields.iter().any(|f| *f != PrincipalField::Name))
+        {
+            result.items = self
+                .list_principals_after(filter, tenant_id, types, fields, after.as_deref(), limit)
+                .await
+                .caused_by(trc::location!())?
+                .items;
+        }
+
+        Ok(result)
+    }
 
-        let mut offset = limit * page.saturating_sub(1);
-        let mut is_done = false;
+    async fn list_principals_after(
+        &self,
+        filter: Option<&PrincipalFilter>,
+        tenant_id: Option<u32>,
+        types: &[Type],
+        fields: &[PrincipalField],
+        after: Option<&str>,
+        limit: usize,
+    ) -> trc::Result<PrincipalPage> {
         let map_principals = fields.is_empty()
             || fields.iter().any(|f| {
                 matches!(
@@ -1584,44 +2754,111 @@ impl ManageDirectory for Store {
                         | PrincipalField::DisabledPermissions
                         | PrincipalField::Members
                         | PrincipalField::UsedQuota
+                        | PrincipalField::LastLogin
+                        | PrincipalField::Enabled
+                        | PrincipalField::Moderators
                 )
             });
+        let field_filter = filter.filter(|filter| !filter.is_name_only());
+        let limit = if limit > 0 { limit } else { usize::MAX };
+        let batch_size = limit.clamp(1, 1000);
+        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![
+            u8::MAX;
+            10
+        ])));
 
-        for mut principal in results {
-            if !is_done || filters.is_some() {
-                principal = self
-                    .query(QueryBy::Id(principal.id), map_principals)
-                    .await
-                    .caused_by(trc::location!())?
-                    .ok_or_else(|| not_found(principal.name().to_string()))?;
-            }
+        let value_prefixes = principal_filter_prefixes(self, types, tenant_id).await?;
+        let mut page = PrincipalPage::default();
+        let mut last_key = after.map(|after| after.as_bytes().to_vec());
+        loop {
+            // Keys are read in batches, stopping as soon as the batch is full
+            let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(
+                last_key.as_ref().map_or_else(Vec::new, |key| {
+                    let mut key = key.clone();
+                    key.push(0);
+                    key
+                }),
+            )));
+            let mut batch = Vec::with_capacity(batch_size);
+            self.iterate(
+                IterateParams::new(from_key, to_key.clone())
+                    .ascending()
+                    .with_value_prefixes(value_prefixes.clone()),
+                |key, value| {
+                    let pt = PrincipalInfo::deserialize(value).caused_by(trc::location!())?;
+                    let name = key.get(1..).unwrap_or_default();
+                    last_key = Some(name.to_vec());
+                    let name = String::from_utf8_lossy(name);
+
+                    if (types.is_empty() || types.contains(&pt.typ))
+                        && pt.has_tenant_access(tenant_id)
+                        && filter.is_none_or(|filter| filter.matches_name(&name))
+                    {
+                        batch.push(
+                            Principal::new(pt.id, pt.typ)
+                                .with_field(PrincipalField::Name, name.into_owned()),
+                        );
+                    }
 
-            if filters.as_ref().map_or(true, |filters| {
-                filters.iter().all(|f| principal.find_str(f))
-            }) {
-                result.total += 1;
+                    Ok(batch.len() < batch_size)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
 
-                if offset == 0 {
-                    if !is_done {
-                        if !fields.is_empty() {
-                            principal.fields.retain(|k, _| fields.contains(k));
-                        }
+            let is_last = batch.len() < batch_size;
+            for mut principal in batch {
+                if field_filter.is_some()
+                    || fields.is_empty()
+                    || fields.iter().any(|f| *f != PrincipalField::Name)
+                {
+                    principal = self
+                        .query(QueryBy::Id(principal.id), map_principals)
+                        .await
+                        .caused_by(trc::location!())?
+                        .ok_or_else(|| {
+                            err_not_found(
+                                ErrorCode::PrincipalNotFound,
+                                principal.name().to_string(),
+                            )
+                        })?;
 
-                        if map_principals {
-                            self.map_field_ids(&mut principal, fields)
-                                .await
-                                .caused_by(trc::location!())?;
+                    // Logins are stored outside the principal
+                    if field_filter.is_some_and(|filter| filter.last_login_before.is_some()) {
+                        if let Some(last_login) = self
+                            .get_last_login(principal.id)
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            principal.set(PrincipalField::LastLogin, last_login);
                         }
-                        result.items.push(principal);
-                        is_done = limit != 0 && result.items.len() >= limit;
                     }
-                } else {
-                    offset -= 1;
+
+                    if !field_filter.is_none_or(|filter| filter.matches(&principal)) {
+                        continue;
+                    }
+
+                    if !fields.is_empty() {
+                        principal.fields.retain(|k, _| fields.contains(k));
+                    }
+                    if map_principals {
+                        self.map_field_ids(&mut principal, fields)
+                            .await
+                            .caused_by(trc::location!())?;
+                    }
+                }
+
+                page.items.push(principal);
+                if page.items.len() >= limit {
+                    page.next = page.items.last().map(|p| p.name().to_string());
+                    return Ok(page);
                 }
             }
-        }
 
-        Ok(result)
+            if is_last {
+                return Ok(page);
+            }
+        }
     }
 
     async fn count_principals(
@@ -1636,9 +2873,13 @@ impl ManageDirectory for Store {
             10
         ])));
 
+        let value_prefixes = principal_filter_prefixes(self, typ.as_slice(), tenant_id).await?;
+
         let mut count = 0;
         self.iterate(
-            IterateParams::new(from_key, to_key).ascending(),
+            IterateParams::new(from_key, to_key)
+                .ascending()
+                .with_value_prefixes(value_prefixes),
             |key, value| {
                 let pt = PrincipalInfo::deserialize(value).caused_by(trc::location!())?;
                 let name =
@@ -1659,6 +2900,38 @@ impl ManageDirectory for Store {
         .map(|_| count)
     }
 
+    async fn count_principals_by_type(
+        &self,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<AHashMap<Type, u64>> {
+        let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![])));
+        let to_key = ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![
+            u8::MAX;
+            10
+        ])));
+
+        let value_prefixes = principal_filter_prefixes(self, &[], tenant_id).await?;
+
+        let mut counts = AHashMap::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key)
+                .ascending()
+                .with_value_prefixes(value_prefixes),
+            |_, value| {
+                let pt = PrincipalInfo::deserialize(value).caused_by(trc::location!())?;
+
+                if pt.has_tenant_access(tenant_id) {
+                    *counts.entry(pt.typ).or_insert(0) += 1;
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())
+        .map(|_| counts)
+    }
+
     async fn get_member_of(&self, principal_id: u32) -> trc::Result<Vec<MemberOf>> {
         let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::MemberOf {
             principal_id,
@@ -1676,6 +2949,7 @@ impl ManageDirectory for Store {
                     .first()
                     .map(|v| Type::from_u8(*v))
                     .unwrap_or(Type::Group),
+                depth: 1,
             });
             Ok(true)
         })
@@ -1684,6 +2958,78 @@ impl ManageDirectory for Store {
         Ok(results)
     }
 
+    async fn get_member_of_recursive(
+        &self,
+        principal_id: u32,
+        max_depth: u32,
+    ) -> trc::Result<Vec<MemberOf>> {
+        let mut results = Vec::new();
+        let mut seen = AHashSet::from_iter([principal_id]);
+        let mut level = vec![principal_id];
+        let mut depth = 1;
+
+        while !level.is_empty() && depth <= max_depth {
+            let mut next_level = Vec::new();
+            for principal_id in level {
+                for member_of in self
+                    .get_member_of(principal_id)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    if seen.insert(member_of.principal_id) {
+                        // Lists and roles do not pass on their own memberships
+                        if !matches!(member_of.typ, Type::List | Type::Role) {
+                            next_level.push(member_of.principal_id);
+                        }
+                        results.push(MemberOf { depth, ..member_of });
+                    }
+                }
+            }
+            level = next_level;
+            depth += 1;
+        }
+
+        Ok(results)
+    }
+
+    async fn get_memberships(
+        &self,
+        principal_id: u32,
+        max_depth: u32,
+        include_roles: bool,
+    ) -> trc::Result<Vec<Membership>> {
+        let member_of = self
+            .get_member_of_recursive(principal_id, max_depth)
+            .await
+            .caused_by(trc::location!())?
+            .into_iter()
+            .filter(|member_of| include_roles || member_of.typ != Type::Role)
+            .collect::<Vec<_>>();
+
+        // Resolve all names at once, memberships pointing to deleted principals are skipped
+        let principals = futures::future::try_join_all(
+            member_of
+                .iter()
+                .map(|member_of| self.get_principal(member_of.principal_id)),
+        )
+        .await?;
+
+        Ok(member_of
+            .into_iter()
+            .zip(principals)
+            .filter_map(|(member_of, principal)| {
+                let mut principal = principal?;
+                Some(Membership {
+                    principal_id: member_of.principal_id,
+                    typ: member_of.typ,
+                    name: principal.take_str(PrincipalField::Name)?,
+                    description: principal.take_str(PrincipalField::Description),
+                    depth: member_of.depth,
+                })
+            })
+            .collect())
+    }
+
     async fn get_members(&self, principal_id: u32) -> trc::Result<Vec<u32>> {
         let from_key = ValueKey::from(ValueClass::Directory(DirectoryClass::Members {
             principal_id,
@@ -1706,7 +3052,45 @@ impl ManageDirectory for Store {
         Ok(results)
     }
 
-    async fn map_field_ids(
+    async fn get_members_expanded(
+        &self,
+        principal_id: u32,
+        types: &[Type],
+    ) -> trc::Result<Vec<Principal>> {
+        let mut results = Vec::new();
+        let mut seen = AHashSet::from_iter([principal_id]);
+        let mut pending = vec![principal_id];
+
+        // Nested groups and lists are expanded, the seen set protects against cycles
+        while let Some(principal_id) = pending.pop() {
+            for member_id in self
+                .get_members(principal_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                if !seen.insert(member_id) {
+                    continue;
+                }
+
+                if let Some(member) = self
+                    .get_principal(member_id)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    if matches!(member.typ, Type::Group | Type::List) {
+                        pending.push(member_id);
+                    }
+                    if types.is_empty() || types.contains(&member.typ) {
+                        results.push(member);
+                    }
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    async fn map_field_ids(
         &self,
         principal: &mut Principal,
         fields: &[PrincipalField],
@@ -1716,6 +3100,7 @@ impl ManageDirectory for Store {
             PrincipalField::MemberOf,
             PrincipalField::Lists,
             PrincipalField::Roles,
+            PrincipalField::Moderators,
         ] {
             if let Some(member_of) = principal
                 .take_int_array(field)
@@ -1833,71 +3218,2294 @@ impl ManageDirectory for Store {
             }
         }
 
-        // SPDX-SnippetEnd
-
-        // Obtain used quota
-        if matches!(principal.typ, Type::Individual | Type::Group | Type::Tenant)
-            && (fields.is_empty() || fields.contains(&PrincipalField::UsedQuota))
-        {
-            let quota = self
-                .get_counter(DirectoryClass::UsedQuota(principal.id))
-                .await
-                .caused_by(trc::location!())?;
-            if quota > 0 {
-                principal.set(PrincipalField::UsedQuota, quota as u64);
+        // SPDX-SnippetEnd
+
+        // Report the enabled flag of principals that can authenticate
+        if matches!(
+            principal.typ,
+            Type::Individual | Type::Group | Type::Service | Type::ApiKey
+        ) && (fields.is_empty() || fields.contains(&PrincipalField::Enabled))
+            && !principal.has_field(PrincipalField::Enabled)
+        {
+            principal.set(PrincipalField::Enabled, 1u64);
+        }
+
+        // Obtain used quota
+        if matches!(principal.typ, Type::Individual | Type::Group | Type::Tenant)
+            && (fields.is_empty() || fields.contains(&PrincipalField::UsedQuota))
+        {
+            let quota = self
+                .get_counter(DirectoryClass::UsedQuota(principal.id))
+                .await
+                .caused_by(trc::location!())?;
+            if quota > 0 {
+                principal.set(PrincipalField::UsedQuota, quota as u64);
+            }
+        }
+
+        // Obtain last login
+        if fields.is_empty() || fields.contains(&PrincipalField::LastLogin) {
+            if let Some(last_login) = self
+                .get_last_login(principal.id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                principal.set(PrincipalField::LastLogin, last_login);
+            }
+        }
+
+        // Map permissions
+        for field in [
+            PrincipalField::EnabledPermissions,
+            PrincipalField::DisabledPermissions,
+        ] {
+            if let Some(permissions) = principal.take_int_array(field) {
+                for permission in permissions {
+                    if let Some(name) = Permission::from_id(permission as usize) {
+                        principal.append_str(field, name.name().to_string());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn export_roles(&self, tenant_id: Option<u32>) -> trc::Result<RoleDocument> {
+        self.list_roles(tenant_id).await.map(|roles| RoleDocument {
+            roles: roles.into_iter().map(|(_, role)| role).collect(),
+        })
+    }
+
+    async fn apply_roles(
+        &self,
+        document: RoleDocument,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+        prune: bool,
+        dry_run: bool,
+    ) -> trc::Result<Vec<RoleChange>> {
+        // Normalize and validate the document before making any changes
+        let mut roles: AHashMap<String, RoleDefinition> = AHashMap::new();
+        for role in document.roles {
+            let role = role.normalize();
+            if role.name.is_empty() {
+                return Err(err_missing(PrincipalField::Name));
+            }
+            for (field, names) in [
+                (
+                    PrincipalField::EnabledPermissions,
+                    &role.enabled_permissions,
+                ),
+                (
+                    PrincipalField::DisabledPermissions,
+                    &role.disabled_permissions,
+                ),
+            ] {
+                for name in names {
+                    let permission = Permission::from_name(name).ok_or_else(|| {
+                        err_code(
+                            ErrorCode::PermissionInvalid,
+                            format!("Invalid {} value", field.as_str()),
+                            format!("Permission {name:?} is invalid").into(),
+                        )
+                        .ctx(trc::Key::Key, field)
+                        .ctx(trc::Key::Value, name.clone())
+                    })?;
+
+                    if field == PrincipalField::EnabledPermissions
+                        && !allowed_permissions.is_none_or(|p| p.get(permission.id()))
+                    {
+                        return Err(err_code(
+                            ErrorCode::PermissionNotGrantable,
+                            "Invalid permission",
+                            format!("Your account cannot grant the {name:?} permission").into(),
+                        )
+                        .ctx(trc::Key::Key, field)
+                        .ctx(trc::Key::Value, name.clone()));
+                    }
+                }
+            }
+            if roles.contains_key(&role.name) {
+                return Err(err_exists(PrincipalField::Name, role.name));
+            }
+            roles.insert(role.name.clone(), role);
+        }
+
+        // Obtain the roles currently defined
+        let current = self
+            .list_roles(tenant_id)
+            .await
+            .caused_by(trc::location!())?
+            .into_iter()
+            .map(|(id, role)| (role.name.clone(), (id, role)))
+            .collect::<AHashMap<_, _>>();
+
+        // New roles cannot clash with other principals
+        for name in roles.keys() {
+            if !current.contains_key(name)
+                && self
+                    .get_principal_id(name)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some()
+            {
+                return Err(err_exists(PrincipalField::Name, name.clone()));
+            }
+        }
+
+        // Validate nested roles
+        let mut graph: AHashMap<&str, &[String]> = AHashMap::new();
+        for (name, (_, role)) in &current {
+            if !prune || roles.contains_key(name) {
+                graph.insert(name.as_str(), role.roles.as_slice());
+            }
+        }
+        for (name, role) in &roles {
+            graph.insert(name.as_str(), role.roles.as_slice());
+            for nested in &role.roles {
+                if !graph.contains_key(nested.as_str())
+                    && !roles.contains_key(nested)
+                    && PrincipalField::Roles
+                        .map_internal_role_name(nested)
+                        .is_none()
+                {
+                    return Err(err_not_found(ErrorCode::PrincipalNotFound, nested.clone())
+                        .ctx(trc::Key::Key, PrincipalField::Roles));
+                }
+            }
+        }
+        let order = sort_roles(&graph)?;
+
+        // Build change plan
+        let mut changes = Vec::with_capacity(roles.len() + current.len());
+        for name in &order {
+            if let Some(role) = roles.get(*name) {
+                let (action, fields) = match current.get(*name) {
+                    Some((_, existing)) => {
+                        let fields = role.diff(existing);
+                        if fields.is_empty() {
+                            (RoleAction::Unchanged, fields)
+                        } else {
+                            (RoleAction::Update, fields)
+                        }
+                    }
+                    None => (RoleAction::Create, role.diff(&RoleDefinition::default())),
+                };
+                changes.push(RoleChange {
+                    name: name.to_string(),
+                    action,
+                    fields,
+                });
+            }
+        }
+        for name in current.keys() {
+            if !roles.contains_key(name) {
+                changes.push(RoleChange {
+                    name: name.clone(),
+                    action: if prune {
+                        RoleAction::Delete
+                    } else {
+                        RoleAction::Extra
+                    },
+                    fields: vec![],
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        if dry_run {
+            return Ok(changes);
+        }
+
+        // Create roles, nested roles are created first
+        for name in order {
+            if let (Some(role), None) = (roles.get(name), current.get(name)) {
+                let mut principal = Principal::new(0, Type::Role)
+                    .with_field(PrincipalField::Name, role.name.clone());
+                if let Some(description) = &role.description {
+                    principal.set(PrincipalField::Description, description.clone());
+                }
+                for (field, values) in [
+                    (
+                        PrincipalField::EnabledPermissions,
+                        &role.enabled_permissions,
+                    ),
+                    (
+                        PrincipalField::DisabledPermissions,
+                        &role.disabled_permissions,
+                    ),
+                    (PrincipalField::Roles, &role.roles),
+                ] {
+                    if !values.is_empty() {
+                        principal.set(field, values.clone());
+                    }
+                }
+                self.create_principal(principal, tenant_id, allowed_permissions)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        // Update existing roles, each role is updated in a single transaction
+        for change in &changes {
+            if change.action != RoleAction::Update {
+                continue;
+            }
+            let (role, (principal_id, _)) = (&roles[&change.name], &current[&change.name]);
+            let updates = change
+                .fields
+                .iter()
+                .map(|field| {
+                    let value = match field {
+                        PrincipalField::Description => {
+                            PrincipalValue::String(role.description.clone().unwrap_or_default())
+                        }
+                        PrincipalField::EnabledPermissions => {
+                            PrincipalValue::StringList(role.enabled_permissions.clone())
+                        }
+                        PrincipalField::DisabledPermissions => {
+                            PrincipalValue::StringList(role.disabled_permissions.clone())
+                        }
+                        _ => PrincipalValue::StringList(role.roles.clone()),
+                    };
+                    PrincipalUpdate::set(*field, value)
+                })
+                .collect();
+            let mut update = UpdatePrincipal::by_id(*principal_id)
+                .with_tenant(tenant_id)
+                .with_updates(updates);
+            if let Some(allowed_permissions) = allowed_permissions {
+                update = update.with_allowed_permissions(allowed_permissions);
+            }
+            self.update_principal(update)
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        // Remove roles missing from the document
+        for change in &changes {
+            if change.action == RoleAction::Delete {
+                self.delete_principal(QueryBy::Id(current[&change.name].0))
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        Ok(changes)
+    }
+
+    async fn export_principals(&self, tenant_id: Option<u32>) -> trc::Result<PrincipalExport> {
+        let mut principals = self
+            .list_principals(None, tenant_id, &[], &[], 0, 0)
+            .await
+            .caused_by(trc::location!())?
+            .items;
+
+        // Member counts, quota usage, logins and revocations are tracked by the
+        // importing directory
+        for principal in &mut principals {
+            principal.remove(PrincipalField::UsedQuota);
+            principal.remove(PrincipalField::LastLogin);
+            principal.remove(PrincipalField::CredentialsEpoch);
+            if matches!(principal.typ, Type::Domain | Type::Tenant) {
+                principal.remove(PrincipalField::Members);
+            }
+        }
+
+        Ok(PrincipalExport { principals })
+    }
+
+    async fn import_principals(
+        &self,
+        data: PrincipalExport,
+        mode: ImportMode,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+    ) -> trc::Result<ImportedPrincipals> {
+        let mut result = ImportedPrincipals::default();
+
+        // Tenants are created first as other principals reference them by name
+        let mut principals = data.principals;
+        principals.sort_by_key(|principal| principal.typ != Type::Tenant);
+
+        // Resolve name conflicts before writing anything
+        let mut names = AHashSet::with_capacity(principals.len());
+        let mut create: [Vec<Principal>; 2] = Default::default();
+        let mut overwrite = Vec::new();
+        let mut memberships = Vec::with_capacity(principals.len());
+        for mut principal in principals {
+            let name = principal.name().to_lowercase();
+            if name.is_empty() {
+                return Err(err_missing(PrincipalField::Name));
+            } else if !names.insert(name.clone()) {
+                return Err(err_exists(PrincipalField::Name, name));
+            }
+
+            principal.remove(PrincipalField::UsedQuota);
+            principal.remove(PrincipalField::LastLogin);
+            principal.remove(PrincipalField::CredentialsEpoch);
+            if matches!(principal.typ, Type::Domain | Type::Tenant) {
+                principal.remove(PrincipalField::Members);
+            }
+
+            let existing_id = match (
+                self.get_principal_info(&name)
+                    .await
+                    .caused_by(trc::location!())?,
+                mode,
+            ) {
+                (None, _) => None,
+                (Some(_), ImportMode::Skip) => {
+                    result.skipped.push(name);
+                    continue;
+                }
+                (Some(pinfo), ImportMode::Overwrite)
+                    if pinfo.typ == principal.typ && pinfo.has_tenant_access(tenant_id) =>
+                {
+                    Some(pinfo.id)
+                }
+                (Some(_), _) => {
+                    return Err(err_exists(PrincipalField::Name, name));
+                }
+            };
+
+            // Memberships are set by name once all principals exist
+            let mut updates = Vec::with_capacity(4);
+            for field in [
+                PrincipalField::MemberOf,
+                PrincipalField::Lists,
+                PrincipalField::Roles,
+            ] {
+                updates.push(PrincipalUpdate::set(
+                    field,
+                    PrincipalValue::StringList(principal.take_str_array(field).unwrap_or_default()),
+                ));
+            }
+            let members = principal.take_str_array(PrincipalField::Members);
+            if matches!(principal.typ, Type::Group | Type::List | Type::Role) {
+                updates.push(PrincipalUpdate::set(
+                    PrincipalField::Members,
+                    PrincipalValue::StringList(members.unwrap_or_default()),
+                ));
+            }
+            memberships.push((name.clone(), updates));
+
+            if let Some(principal_id) = existing_id {
+                overwrite.push((principal_id, name, principal));
+            } else {
+                create[(principal.typ != Type::Tenant) as usize].push(principal);
+            }
+        }
+
+        // Create new principals, addresses are validated against the existing
+        // ones and the principals are written in bounded batches
+        for principals in create {
+            if principals.is_empty() {
+                continue;
+            }
+            let names = principals
+                .iter()
+                .map(|principal| principal.name().to_lowercase())
+                .collect::<Vec<_>>();
+            let created = self
+                .create_principals(principals, tenant_id, allowed_permissions)
+                .await
+                .caused_by(trc::location!())?;
+            if let Some((idx, err)) = created.errors.into_iter().next() {
+                return Err(err.ctx(trc::Key::Id, names[idx].clone()));
+            }
+            result.created.extend(names);
+        }
+
+        // Replace the fields of existing principals, tenant administrators
+        // cannot move principals to other tenants
+        for (principal_id, name, principal) in overwrite {
+            let mut update = UpdatePrincipal::by_id(principal_id)
+                .with_tenant(tenant_id)
+                .with_updates(
+                    field_updates(principal)
+                        .into_iter()
+                        .filter(|update| {
+                            tenant_id.is_none() || update.field != PrincipalField::Tenant
+                        })
+                        .collect(),
+                );
+            if let Some(allowed_permissions) = allowed_permissions {
+                update = update.with_allowed_permissions(allowed_permissions);
+            }
+            self.update_principal(update)
+                .await
+                .map_err(|err| err.ctx(trc::Key::Id, name.clone()))?;
+            result.updated.push(name);
+        }
+
+        // Link principals
+        for (name, updates) in memberships {
+            self.update_principal(
+                UpdatePrincipal::by_name(&name)
+                    .with_tenant(tenant_id)
+                    .with_updates(updates),
+            )
+            .await
+            .map_err(|err| err.ctx(trc::Key::Id, name.clone()))?;
+        }
+
+        Ok(result)
+    }
+
+    async fn import_accounts(
+        &self,
+        accounts: Vec<(usize, ImportAccount)>,
+        tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+        create_domains: bool,
+    ) -> trc::Result<Vec<ImportedAccount>> {
+        let mut results = Vec::with_capacity(accounts.len());
+        let mut create: Vec<(usize, Principal)> = Vec::with_capacity(accounts.len());
+        let mut domains: AHashMap<String, Option<trc::Error>> = AHashMap::new();
+        let mut emails = AHashSet::with_capacity(accounts.len());
+
+        for (row, account) in accounts {
+            let mut result = ImportedAccount {
+                row,
+                email: account.email.clone(),
+                status: ImportStatus::Error,
+                id: None,
+                code: None,
+                reason: None,
+            };
+
+            // Catch-all addresses cannot be used as account names
+            let email = match normalize_email(&account.email) {
+                Ok(email) if email.starts_with('@') => {
+                    result.set_error(&err_invalid_email(email));
+                    results.push(result);
+                    continue;
+                }
+                Ok(email) => email,
+                Err(err) => {
+                    result.set_error(&err);
+                    results.push(result);
+                    continue;
+                }
+            };
+            result.email = email.clone();
+
+            // Accounts are skipped when the name or address is already taken
+            if !emails.insert(email.clone())
+                || self
+                    .get_principal_id(&email)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some()
+                || self
+                    .email_to_id(&email)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some()
+            {
+                result.status = ImportStatus::Skipped;
+                result.code = Some(ErrorCode::PrincipalAlreadyExists.as_str().to_string());
+                result.reason = Some(format!("Account {email:?} already exists"));
+                results.push(result);
+                continue;
+            }
+
+            // The domain has to exist or be created when requested
+            let domain = email
+                .rsplit_once('@')
+                .map(|(_, domain)| domain)
+                .unwrap_or_default();
+            let domain_error = match domains.get(domain) {
+                Some(error) => error.clone(),
+                None => {
+                    let error = match self
+                        .get_principal_info(domain)
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        Some(info)
+                            if info.typ == Type::Domain && info.has_tenant_access(tenant_id) =>
+                        {
+                            None
+                        }
+                        None if create_domains => self
+                            .create_principal(
+                                Principal::new(0, Type::Domain)
+                                    .with_field(PrincipalField::Name, domain.to_string()),
+                                tenant_id,
+                                allowed_permissions,
+                            )
+                            .await
+                            .err(),
+                        _ => Some(err_not_found(ErrorCode::DomainNotFound, domain.to_string())),
+                    };
+                    domains.insert(domain.to_string(), error.clone());
+                    error
+                }
+            };
+            if let Some(err) = domain_error {
+                result.set_error(&err);
+                results.push(result);
+                continue;
+            }
+
+            let mut principal = Principal::new(0, Type::Individual)
+                .with_field(PrincipalField::Name, email.clone())
+                .with_field(PrincipalField::Emails, email)
+                .with_opt_field(
+                    PrincipalField::Secrets,
+                    account.password_hash.filter(|v| !v.is_empty()),
+                )
+                .with_opt_field(
+                    PrincipalField::Description,
+                    account.display_name.filter(|v| !v.is_empty()),
+                );
+            if let Some(quota) = account.quota.filter(|quota| *quota > 0) {
+                principal.set(PrincipalField::Quota, quota);
+            }
+
+            // Tenant members receive the default roles of their tenant
+            if tenant_id.is_none() {
+                principal.set(PrincipalField::Roles, vec!["user".to_string()]);
+            }
+
+            create.push((results.len(), principal));
+            results.push(result);
+        }
+
+        // Bulk creation rejects the whole batch when any principal is invalid,
+        // invalid rows are reported and the remaining ones created again
+        while !create.is_empty() {
+            let created = self
+                .create_principals(
+                    create
+                        .iter()
+                        .map(|(_, principal)| principal.clone())
+                        .collect(),
+                    tenant_id,
+                    allowed_permissions,
+                )
+                .await
+                .caused_by(trc::location!())?;
+
+            for (pos, id) in created.ids.iter().enumerate() {
+                let result = &mut results[create[pos].0];
+                result.status = ImportStatus::Created;
+                result.id = Some(*id);
+            }
+
+            if created.errors.is_empty() {
+                break;
+            } else if !created.ids.is_empty()
+                || created
+                    .errors
+                    .iter()
+                    .any(|(_, err)| !matches!(err.as_ref(), trc::EventType::Manage(_)))
+            {
+                // Write and quota failures apply to every account not yet created
+                let err = &created.errors[0].1;
+                for (idx, _) in &create[created.ids.len()..] {
+                    results[*idx].set_error(err);
+                }
+                break;
+            }
+
+            let mut failed = created.errors.into_iter().peekable();
+            create = create
+                .into_iter()
+                .enumerate()
+                .filter_map(|(pos, (idx, principal))| {
+                    if let Some((_, err)) = failed.next_if(|(err_pos, _)| *err_pos == pos) {
+                        results[idx].set_error(&err);
+                        None
+                    } else {
+                        Some((idx, principal))
+                    }
+                })
+                .collect();
+        }
+
+        Ok(results)
+    }
+}
+
+impl ImportedAccount {
+    fn set_error(&mut self, err: &trc::Error) {
+        self.status = ImportStatus::Error;
+        self.code = Some(
+            err.value_as_str(trc::Key::Code)
+                .or_else(|| match err.as_ref() {
+                    trc::EventType::Manage(cause) => Some(ErrorCode::from(*cause).as_str()),
+                    _ => None,
+                })
+                .unwrap_or(ErrorCode::Other.as_str())
+                .to_string(),
+        );
+        self.reason = Some(
+            match (
+                err.as_ref(),
+                err.value_as_str(trc::Key::Reason)
+                    .or_else(|| err.value_as_str(trc::Key::Details)),
+                err.value_as_str(trc::Key::Key),
+                err.value_as_str(trc::Key::Value),
+            ) {
+                (_, Some(details), _, _) => details.to_string(),
+                (trc::EventType::Manage(trc::ManageEvent::NotFound), None, Some(item), _) => {
+                    format!("{item:?} not found")
+                }
+                (
+                    trc::EventType::Manage(trc::ManageEvent::AlreadyExists),
+                    None,
+                    Some(field),
+                    Some(value),
+                ) => format!("{field} {value:?} already exists"),
+                (_, None, Some(field), _) => format!("Invalid {field}"),
+                _ => err.as_ref().message().to_string(),
+            },
+        );
+    }
+}
+
+/// Updates that replace the editable fields of an existing principal with
+/// those of `principal`. Memberships are not included.
+pub fn field_updates(mut principal: Principal) -> Vec<PrincipalUpdate> {
+    let mut updates = Vec::with_capacity(10);
+
+    for field in [
+        PrincipalField::Description,
+        PrincipalField::Picture,
+        PrincipalField::ExternalId,
+        PrincipalField::Locale,
+        PrincipalField::Timezone,
+    ] {
+        updates.push(PrincipalUpdate::set(
+            field,
+            PrincipalValue::String(principal.take_str(field).unwrap_or_default()),
+        ));
+    }
+    updates.push(PrincipalUpdate::set(
+        PrincipalField::Enabled,
+        PrincipalValue::Integer(principal.is_enabled() as u64),
+    ));
+    updates.push(PrincipalUpdate::set(
+        PrincipalField::Quota,
+        principal
+            .take(PrincipalField::Quota)
+            .unwrap_or_else(|| PrincipalValue::String(String::new())),
+    ));
+    for field in [
+        PrincipalField::Secrets,
+        PrincipalField::Emails,
+        PrincipalField::EnabledPermissions,
+        PrincipalField::DisabledPermissions,
+        PrincipalField::Urls,
+        PrincipalField::ExternalMembers,
+        PrincipalField::CertificateFingerprints,
+    ] {
+        updates.push(PrincipalUpdate::set(
+            field,
+            PrincipalValue::StringList(principal.take_str_array(field).unwrap_or_default()),
+        ));
+    }
+    if principal.typ == Type::Domain {
+        for field in [PrincipalField::CatchAll, PrincipalField::Subaddressing] {
+            updates.push(PrincipalUpdate::set(
+                field,
+                PrincipalValue::String(principal.take_str(field).unwrap_or_default()),
+            ));
+        }
+    }
+    if principal.typ == Type::List {
+        updates.push(PrincipalUpdate::set(
+            PrincipalField::PostingPolicy,
+            PrincipalValue::String(
+                principal
+                    .take_str(PrincipalField::PostingPolicy)
+                    .unwrap_or_default(),
+            ),
+        ));
+        updates.push(PrincipalUpdate::set(
+            PrincipalField::Moderators,
+            PrincipalValue::StringList(
+                principal
+                    .take_str_array(PrincipalField::Moderators)
+                    .unwrap_or_default(),
+            ),
+        ));
+        updates.push(PrincipalUpdate::set(
+            PrincipalField::ReplyToList,
+            PrincipalValue::Integer(
+                principal
+                    .take_int(PrincipalField::ReplyToList)
+                    .unwrap_or_default(),
+            ),
+        ));
+    }
+    if principal.typ == Type::Tenant {
+        for field in [
+            PrincipalField::DefaultRoles,
+            PrincipalField::DefaultPermissions,
+        ] {
+            updates.push(PrincipalUpdate::set(
+                field,
+                PrincipalValue::StringList(principal.take_str_array(field).unwrap_or_default()),
+            ));
+        }
+    }
+    if principal.typ == Type::Alias {
+        updates.push(PrincipalUpdate::set(
+            PrincipalField::ForwardTo,
+            PrincipalValue::StringList(
+                principal
+                    .take_str_array(PrincipalField::ForwardTo)
+                    .unwrap_or_default(),
+            ),
+        ));
+    }
+
+    // SPDX-SnippetBegin
+    // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+    // SPDX-License-Identifier: LicenseRef-SEL
+    #[cfg(feature = "enterprise")]
+    updates.push(PrincipalUpdate::set(
+        PrincipalField::Tenant,
+        PrincipalValue::String(
+            principal
+                .take_str(PrincipalField::Tenant)
+                .unwrap_or_default(),
+        ),
+    ));
+    // SPDX-SnippetEnd
+
+    updates
+}
+
+#[allow(async_fn_in_trait)]
+trait ListRoles: Sized {
+    async fn list_roles(&self, tenant_id: Option<u32>) -> trc::Result<Vec<(u32, RoleDefinition)>>;
+}
+
+impl ListRoles for Store {
+    async fn list_roles(&self, tenant_id: Option<u32>) -> trc::Result<Vec<(u32, RoleDefinition)>> {
+        let mut roles = self
+            .list_principals(
+                None,
+                tenant_id,
+                &[Type::Role],
+                &[
+                    PrincipalField::Name,
+                    PrincipalField::Description,
+                    PrincipalField::EnabledPermissions,
+                    PrincipalField::DisabledPermissions,
+                    PrincipalField::Roles,
+                ],
+                0,
+                0,
+            )
+            .await
+            .caused_by(trc::location!())?
+            .items
+            .into_iter()
+            .map(|principal| {
+                (
+                    principal.id,
+                    RoleDefinition {
+                        name: principal.name().to_string(),
+                        description: principal.description().map(|d| d.to_string()),
+                        enabled_permissions: principal
+                            .iter_str(PrincipalField::EnabledPermissions)
+                            .cloned()
+                            .collect(),
+                        disabled_permissions: principal
+                            .iter_str(PrincipalField::DisabledPermissions)
+                            .cloned()
+                            .collect(),
+                        roles: principal.iter_str(PrincipalField::Roles).cloned().collect(),
+                    }
+                    .normalize(),
+                )
+            })
+            .collect::<Vec<_>>();
+        roles.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        Ok(roles)
+    }
+}
+
+impl RoleDefinition {
+    fn normalize(mut self) -> Self {
+        self.name = self.name.trim().to_lowercase();
+        self.description = self.description.filter(|d| !d.is_empty());
+        for list in [
+            &mut self.enabled_permissions,
+            &mut self.disabled_permissions,
+            &mut self.roles,
+        ] {
+            for item in list.iter_mut() {
+                *item = item.trim().to_lowercase();
+            }
+            list.sort_unstable();
+            list.dedup();
+        }
+        self
+    }
+
+    fn diff(&self, other: &RoleDefinition) -> Vec<PrincipalField> {
+        let mut fields = Vec::new();
+        if self.description != other.description {
+            fields.push(PrincipalField::Description);
+        }
+        if self.enabled_permissions != other.enabled_permissions {
+            fields.push(PrincipalField::EnabledPermissions);
+        }
+        if self.disabled_permissions != other.disabled_permissions {
+            fields.push(PrincipalField::DisabledPermissions);
+        }
+        if self.roles != other.roles {
+            fields.push(PrincipalField::Roles);
+        }
+        fields
+    }
+}
+
+// Orders roles so that nested roles come before the roles that include them,
+// rejecting circular definitions.
+fn sort_roles<'x>(graph: &AHashMap<&'x str, &'x [String]>) -> trc::Result<Vec<&'x str>> {
+    let mut names = graph.keys().copied().collect::<Vec<_>>();
+    names.sort_unstable();
+
+    let mut order = Vec::with_capacity(names.len());
+    let mut visited: AHashMap<&str, bool> = AHashMap::new();
+    for name in names {
+        let mut stack = vec![(name, 0)];
+        while let Some((name, pos)) = stack.pop() {
+            if pos == 0 {
+                match visited.get(name) {
+                    Some(true) => continue,
+                    Some(false) => {
+                        return Err(err_code(
+                            ErrorCode::MemberInvalid,
+                            "Circular role definition",
+                            format!("Role {name:?} includes itself").into(),
+                        )
+                        .ctx(trc::Key::Key, PrincipalField::Roles)
+                        .ctx(trc::Key::Value, name.to_string()));
+                    }
+                    None => {
+                        visited.insert(name, false);
+                    }
+                }
+            }
+
+            if let Some(next) = graph[name].get(pos) {
+                stack.push((name, pos + 1));
+
+                // Internal roles are not part of the graph
+                if let Some((next, _)) = graph.get_key_value(next.as_str()) {
+                    if visited.get(next) != Some(&true) {
+                        stack.push((*next, 0));
+                    }
+                }
+            } else {
+                visited.insert(name, true);
+                order.push(name);
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+/// Directory changes are logged under the directory account so they can be
+/// followed by replication feeds.
+#[derive(Default)]
+struct PendingPrincipals {
+    // Lowercase name to batch position and type
+    names: AHashMap<String, (usize, Type)>,
+    // Principals counted towards tenant quotas, by tenant and type
+    quota_used: AHashMap<(u32, u8), u64>,
+}
+
+struct NewPrincipal {
+    principal: Principal,
+    tenant_id: Option<u32>,
+    // Per-type tenant limit the principal was admitted under
+    quota_limit: Option<u64>,
+    members: Vec<PrincipalRef>,
+    member_of: Vec<PrincipalRef>,
+}
+
+#[derive(Clone, Copy)]
+enum PrincipalRef {
+    Stored { id: u32, typ: Type },
+    Pending { idx: usize, typ: Type },
+}
+
+struct PendingMembership {
+    member: PrincipalRef,
+    group: PrincipalRef,
+}
+
+impl NewPrincipal {
+    fn memberships(&self, idx: usize) -> Vec<PendingMembership> {
+        let this = PrincipalRef::Pending {
+            idx,
+            typ: self.principal.typ,
+        };
+        self.member_of
+            .iter()
+            .map(|group| PendingMembership {
+                member: this,
+                group: *group,
+            })
+            .chain(self.members.iter().map(|member| PendingMembership {
+                member: *member,
+                group: this,
+            }))
+            .collect()
+    }
+}
+
+impl PrincipalRef {
+    fn typ(&self) -> Type {
+        match self {
+            PrincipalRef::Stored { typ, .. } | PrincipalRef::Pending { typ, .. } => *typ,
+        }
+    }
+
+    fn pending_idx(&self) -> usize {
+        match self {
+            PrincipalRef::Stored { .. } => 0,
+            PrincipalRef::Pending { idx, .. } => *idx,
+        }
+    }
+
+    fn id(&self, chunk_start: usize, ids: &[u32]) -> MaybeDynamicId {
+        match self {
+            PrincipalRef::Stored { id, .. } => MaybeDynamicId::Static(*id),
+            PrincipalRef::Pending { idx, .. } if *idx < chunk_start => {
+                MaybeDynamicId::Static(ids[*idx])
+            }
+            PrincipalRef::Pending { idx, .. } => MaybeDynamicId::Dynamic(*idx - chunk_start),
+        }
+    }
+}
+
+// Memberships are written along with the last of the principals they reference
+fn principals_batch(
+    principals: Vec<NewPrincipal>,
+    memberships: &[PendingMembership],
+    chunk_start: usize,
+    ids: &[u32],
+) -> BatchBuilder {
+    let chunk = chunk_start..chunk_start + principals.len();
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(u32::MAX)
+        .with_collection(Collection::Principal);
+
+    for (
+        document_idx,
+        NewPrincipal {
+            mut principal,
+            tenant_id,
+            ..
+        },
+    ) in principals.into_iter().enumerate()
+    {
+        let pinfo_name = DynamicPrincipalInfo::new(principal.typ, tenant_id);
+        let pinfo_email = DynamicPrincipalInfo::new(principal.typ, None);
+        batch
+            .create_document()
+            .assert_value(
+                ValueClass::Directory(DirectoryClass::NameToId(
+                    principal.name().to_string().into_bytes(),
+                )),
+                (),
+            )
+            .set(
+                ValueClass::Directory(DirectoryClass::Principal(MaybeDynamicId::Dynamic(
+                    document_idx,
+                ))),
+                (&principal).serialize(),
+            )
+            .set(
+                ValueClass::Directory(DirectoryClass::NameToId(
+                    principal
+                        .take_str(PrincipalField::Name)
+                        .unwrap()
+                        .into_bytes(),
+                )),
+                pinfo_name,
+            );
+
+        // Write external id to id mapping
+        if let Some(external_id) = principal.get_str(PrincipalField::ExternalId) {
+            batch
+                .assert_value(
+                    ValueClass::Directory(DirectoryClass::ExternalIdToId(
+                        external_id.as_bytes().to_vec(),
+                    )),
+                    (),
+                )
+                .set(
+                    ValueClass::Directory(DirectoryClass::ExternalIdToId(
+                        external_id.as_bytes().to_vec(),
+                    )),
+                    pinfo_email,
+                );
+        }
+
+        // Write certificate fingerprint to id mapping
+        for fingerprint in principal.iter_str(PrincipalField::CertificateFingerprints) {
+            batch
+                .assert_value(
+                    ValueClass::Directory(DirectoryClass::CertificateToId(
+                        fingerprint.as_bytes().to_vec(),
+                    )),
+                    (),
+                )
+                .set(
+                    ValueClass::Directory(DirectoryClass::CertificateToId(
+                        fingerprint.as_bytes().to_vec(),
+                    )),
+                    pinfo_email,
+                );
+        }
+
+        // Write email to id mapping
+        if let Some(emails) = principal
+            .take(PrincipalField::Emails)
+            .map(|v| v.into_str_array())
+        {
+            for email in emails {
+                batch.set(
+                    ValueClass::Directory(DirectoryClass::EmailToId(email.into_bytes())),
+                    pinfo_email,
+                );
+            }
+        }
+    }
+
+    // Write membership
+    for membership in memberships
+        .iter()
+        .filter(|m| chunk.contains(&m.member.pending_idx().max(m.group.pending_idx())))
+    {
+        let member = membership.member.id(chunk_start, ids);
+        let group = membership.group.id(chunk_start, ids);
+        batch.set(
+            ValueClass::Directory(DirectoryClass::MemberOf {
+                principal_id: member,
+                member_of: group,
+            }),
+            vec![membership.group.typ() as u8],
+        );
+        batch.set(
+            ValueClass::Directory(DirectoryClass::Members {
+                principal_id: group,
+                has_member: member,
+            }),
+            vec![],
+        );
+    }
+
+    batch
+}
+
+// Value prefixes only match the current layout, until the migration has
+// rewritten all legacy rows the full range is read and filtered by the caller
+async fn principal_filter_prefixes(
+    store: &Store,
+    types: &[Type],
+    tenant_id: Option<u32>,
+) -> trc::Result<Vec<Vec<u8>>> {
+    let prefixes = PrincipalInfo::filter_prefixes(types, tenant_id);
+    if prefixes.is_empty()
+        || store
+            .is_migration_finished(PRINCIPAL_INFO_MIGRATION)
+            .await
+            .caused_by(trc::location!())?
+    {
+        Ok(prefixes)
+    } else {
+        Ok(vec![])
+    }
+}
+
+pub fn directory_change_log() -> ChangeLogBuilder {
+    ChangeLogBuilder::with_change_id(DIRECTORY_CHANGE_ID.generate().unwrap_or_else(now))
+}
+
+impl ValidateDirectory for Store {
+    async fn delete_principal_record(&self, principal: Principal) -> trc::Result<()> {
+        let principal_id = principal.id();
+        let mut batch = BatchBuilder::new();
+        self.clear_principal_record(&mut batch, &principal)
+            .await
+            .caused_by(trc::location!())?;
+        self.purge_principal_data(&principal)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Log change
+        batch
+            .with_account_id(u32::MAX)
+            .with_collection(Collection::Principal)
+            .custom(directory_change_log().with_log_delete(Collection::Principal, principal_id));
+
+        self.write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(())
+    }
+
+    async fn clear_principal_record(
+        &self,
+        batch: &mut BatchBuilder,
+        principal: &Principal,
+    ) -> trc::Result<()> {
+        let principal_id = principal.id();
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        // Update tenant quota
+        #[cfg(feature = "enterprise")]
+        if let (Type::Individual | Type::Group, Some(tenant_id)) =
+            (principal.typ, principal.tenant())
+        {
+            let quota = self
+                .get_counter(DirectoryClass::UsedQuota(principal_id))
+                .await
+                .caused_by(trc::location!())?;
+            if quota > 0 {
+                batch.add(DirectoryClass::UsedQuota(tenant_id), -quota);
+            }
+        }
+
+        // SPDX-SnippetEnd
+
+        // Delete principal
+        batch
+            .with_account_id(principal_id)
+            .clear(DirectoryClass::NameToId(
+                principal.name().as_bytes().to_vec(),
+            ))
+            .clear(DirectoryClass::Principal(MaybeDynamicId::Static(
+                principal_id,
+            )))
+            .clear(DirectoryClass::UsedQuota(principal_id))
+            .clear(DirectoryClass::LastLogin(principal_id));
+
+        for email in principal.iter_str(PrincipalField::Emails) {
+            batch.clear(DirectoryClass::EmailToId(email.as_bytes().to_vec()));
+        }
+
+        if let Some(external_id) = principal.get_str(PrincipalField::ExternalId) {
+            batch.clear(DirectoryClass::ExternalIdToId(
+                external_id.as_bytes().to_vec(),
+            ));
+        }
+
+        for fingerprint in principal.iter_str(PrincipalField::CertificateFingerprints) {
+            batch.clear(DirectoryClass::CertificateToId(
+                fingerprint.as_bytes().to_vec(),
+            ));
+        }
+
+        for member in self
+            .get_member_of(principal_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            batch.clear(DirectoryClass::MemberOf {
+                principal_id: MaybeDynamicId::Static(principal_id),
+                member_of: MaybeDynamicId::Static(member.principal_id),
+            });
+            batch.clear(DirectoryClass::Members {
+                principal_id: MaybeDynamicId::Static(member.principal_id),
+                has_member: MaybeDynamicId::Static(principal_id),
+            });
+        }
+
+        for member_id in self
+            .get_members(principal_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            batch.clear(DirectoryClass::MemberOf {
+                principal_id: MaybeDynamicId::Static(member_id),
+                member_of: MaybeDynamicId::Static(principal_id),
+            });
+            batch.clear(DirectoryClass::Members {
+                principal_id: MaybeDynamicId::Static(principal_id),
+                has_member: MaybeDynamicId::Static(member_id),
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn purge_principal_data(&self, principal: &Principal) -> trc::Result<()> {
+        let principal_id = principal.id();
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        // Delete template overrides
+        #[cfg(feature = "enterprise")]
+        if principal.typ == Type::Tenant {
+            self.delete_range(
+                ValueKey::from(DirectoryClass::Template {
+                    tenant_id: principal_id,
+                    kind: 0,
+                }),
+                ValueKey::from(DirectoryClass::Template {
+                    tenant_id: principal_id,
+                    kind: u8::MAX,
+                }),
+            )
+            .await
+            .caused_by(trc::location!())?;
+        }
+
+        // SPDX-SnippetEnd
+
+        // Unlink all principal's blobs
+        self.blob_hash_unlink_account(principal_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Revoke ACLs
+        self.acl_revoke_all(principal_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Remove domain delegations held by or granted on the principal
+        self.delete_principal_delegations(principal)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Delete principal data
+        self.purge_account(principal_id)
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn assert_tenant_quota(&self, tenant_id: u32, typ: Type, limit: u64) -> trc::Result<()> {
+        let total = self
+            .count_principals(None, typ.into(), tenant_id.into())
+            .await
+            .caused_by(trc::location!())?;
+
+        if total <= limit {
+            Ok(())
+        } else {
+            Err(err_tenant_quota(typ, limit, total))
+        }
+    }
+
+    async fn rollback_created(&self, principal_ids: &[u32]) -> trc::Result<()> {
+        for principal_id in principal_ids {
+            if let Some(principal) = self
+                .get_principal(*principal_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                self.delete_principal_record(principal)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_principal_by(&self, by: QueryBy<'_>) -> trc::Result<Principal> {
+        let principal_id = match by {
+            QueryBy::Name(name) => self
+                .get_principal_id(name)
+                .await
+                .caused_by(trc::location!())?
+                .ok_or_else(|| err_principal_not_found(name))?,
+            QueryBy::Id(principal_id) => {
+                assert_not_builtin_role(principal_id)?;
+                principal_id
+            }
+            QueryBy::ExternalId(external_id) => self
+                .external_id_to_id(external_id)
+                .await
+                .caused_by(trc::location!())?
+                .ok_or_else(|| {
+                    err_not_found(ErrorCode::PrincipalNotFound, external_id.to_string())
+                })?,
+            QueryBy::Credentials(_) | QueryBy::Certificate(_) => unreachable!(),
+        };
+        self.get_principal(principal_id)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, principal_id.to_string()))
+    }
+
+    async fn assert_admins_remain(
+        &self,
+        principal: &Principal,
+        removed_roles: Option<&[u32]>,
+    ) -> trc::Result<()> {
+        if !matches!(principal.typ, Type::Individual | Type::Service | Type::Role) {
+            return Ok(());
+        }
+        let principal_id = principal.id();
+
+        // Server administrators
+        if !self.role_holders(ROLE_ADMIN, None, None).await?.is_empty()
+            && self
+                .role_holders(ROLE_ADMIN, None, Some((principal_id, removed_roles)))
+                .await?
+                .is_empty()
+        {
+            return Err(err_last_admin(
+                "At least one account must keep the admin role.",
+            ));
+        }
+
+        // Tenant administrators, as long as the tenant has other accounts
+        if let Some(tenant_id) = principal.tenant() {
+            if !self
+                .role_holders(ROLE_TENANT_ADMIN, Some(tenant_id), None)
+                .await?
+                .is_empty()
+                && self
+                    .role_holders(
+                        ROLE_TENANT_ADMIN,
+                        Some(tenant_id),
+                        Some((principal_id, removed_roles)),
+                    )
+                    .await?
+                    .is_empty()
+            {
+                let mut accounts = self
+                    .count_principals(None, Type::Individual.into(), tenant_id.into())
+                    .await
+                    .caused_by(trc::location!())?;
+                if removed_roles.is_none() && principal.typ == Type::Individual {
+                    accounts = accounts.saturating_sub(1);
+                }
+                if accounts > 0 {
+                    return Err(err_last_admin(
+                        "At least one account in the tenant must keep the tenant-admin role.",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn role_holders(
+        &self,
+        role_id: u32,
+        tenant_id: Option<u32>,
+        exclude: Option<(u32, Option<&[u32]>)>,
+    ) -> trc::Result<Vec<u32>> {
+        let mut holders = Vec::new();
+        let mut pending = vec![role_id];
+        let mut seen = AHashSet::from_iter([role_id]);
+
+        // Follow custom roles that include the role
+        while let Some(role_id) = pending.pop() {
+            for member_id in self
+                .get_members(role_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                // Excluded principals are either deleted or lose some of their roles
+                if exclude.is_some_and(|(principal_id, removed_roles)| {
+                    member_id == principal_id
+                        && removed_roles.is_none_or(|roles| roles.contains(&role_id))
+                }) || !seen.insert(member_id)
+                {
+                    continue;
+                }
+
+                if let Some(member) = self
+                    .get_principal(member_id)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    match member.typ {
+                        Type::Role => pending.push(member_id),
+                        Type::Individual | Type::Service
+                            if tenant_id.is_none_or(|id| member.tenant() == Some(id)) =>
+                        {
+                            holders.push(member_id);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(holders)
+    }
+
+    async fn list_domain_addresses(
+        &self,
+        domain: &str,
+    ) -> trc::Result<Vec<(String, PrincipalInfo)>> {
+        let mut addresses = Vec::new();
+        self.iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Directory(DirectoryClass::EmailToId(vec![]))),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::EmailToId(vec![
+                    u8::MAX;
+                    10
+                ]))),
+            ),
+            |key, value| {
+                let email =
+                    std::str::from_utf8(key.get(1..).unwrap_or_default()).unwrap_or_default();
+                if email
+                    .rsplit_once('@')
+                    .is_some_and(|(_, email_domain)| email_domain == domain)
+                {
+                    addresses.push((
+                        email.to_string(),
+                        PrincipalInfo::deserialize(value).caused_by(trc::location!())?,
+                    ));
+                }
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(addresses)
+    }
+
+    // SPDX-SnippetBegin
+    // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+    // SPDX-License-Identifier: LicenseRef-SEL
+
+    #[cfg(feature = "enterprise")]
+    async fn assert_no_members(&self, principal: &Principal) -> trc::Result<()> {
+        match principal.typ {
+            Type::Tenant => {
+                let tenant_members = self
+                    .list_principals(
+                        None,
+                        principal.id().into(),
+                        &[
+                            Type::Individual,
+                            Type::Group,
+                            Type::Role,
+                            Type::List,
+                            Type::Resource,
+                            Type::Other,
+                            Type::Location,
+                            Type::Domain,
+                            Type::ApiKey,
+                            Type::Service,
+                            Type::Alias,
+                        ],
+                        &[PrincipalField::Name],
+                        0,
+                        0,
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+
+                if tenant_members.total > 0 {
+                    let mut message =
+                        String::from("Tenant must have no members to be deleted: Found: ");
+
+                    for (num, principal) in tenant_members.items.iter().enumerate() {
+                        if num > 0 {
+                            message.push_str(", ");
+                        }
+                        message.push_str(principal.name());
+                    }
+
+                    if tenant_members.total > 5 {
+                        message.push_str(" and ");
+                        message.push_str(&(tenant_members.total - 5).to_string());
+                        message.push_str(" others");
+                    }
+
+                    return Err(err_code(
+                        ErrorCode::PrincipalHasMembers,
+                        "Tenant has members",
+                        message.into(),
+                    ));
+                }
+            }
+            Type::Domain => {
+                if let Some(tenant_id) = principal.tenant() {
+                    let name = principal.name();
+                    let tenant_members = self
+                        .list_principals(
+                            None,
+                            tenant_id.into(),
+                            &[
+                                Type::Individual,
+                                Type::Group,
+                                Type::Role,
+                                Type::List,
+                                Type::Resource,
+                                Type::Other,
+                                Type::Location,
+                                Type::Service,
+                                Type::Alias,
+                            ],
+                            &[PrincipalField::Name],
+                            0,
+                            0,
+                        )
+                        .await
+                        .caused_by(trc::location!())?;
+                    let domain_members = tenant_members
+                        .items
+                        .iter()
+                        .filter(|v| {
+                            v.name()
+                                .rsplit_once('@')
+                                .is_some_and(|(_, d)| d.eq_ignore_ascii_case(name))
+                        })
+                        .collect::<Vec<_>>();
+                    let total_domain_members = domain_members.len();
+
+                    if total_domain_members > 0 {
+                        let mut message =
+                            String::from("Domains must have no members to be deleted: Found: ");
+
+                        for (num, principal) in domain_members.iter().enumerate() {
+                            if num > 0 {
+                                message.push_str(", ");
+                            }
+                            message.push_str(principal.name());
+                        }
+
+                        if total_domain_members > 5 {
+                            message.push_str(" and ");
+                            message.push_str(&(total_domain_members - 5).to_string());
+                            message.push_str(" others");
+                        }
+
+                        return Err(err_code(
+                            ErrorCode::PrincipalHasMembers,
+                            "Domain has members",
+                            message.into(),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+    // SPDX-SnippetEnd
+
+    async fn validate_new_principal(
+        &self,
+        mut principal: Principal,
+        mut tenant_id: Option<u32>,
+        allowed_permissions: Option<&Permissions>,
+        pending: &mut PendingPrincipals,
+    ) -> trc::Result<NewPrincipal> {
+        // Make sure the principal has a name
+        let name = principal.name().to_lowercase();
+        if name.is_empty() {
+            return Err(err_missing(PrincipalField::Name));
+        }
+        let mut valid_domains: AHashSet<String> = AHashSet::new();
+        let mut quota_limit = None;
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        // Obtain tenant id, only if no default tenant is provided
+        #[cfg(feature = "enterprise")]
+        if let (Some(tenant_name), None) = (principal.take_str(PrincipalField::Tenant), tenant_id) {
+            tenant_id = self
+                .get_principal_info(&tenant_name)
+                .await
+                .caused_by(trc::location!())?
+                .filter(|v| v.typ == Type::Tenant)
+                .ok_or_else(|| err_not_found(ErrorCode::TenantNotFound, tenant_name.clone()))?
+                .id
+                .into();
+        }
+
+        // Validate tenant
+        #[cfg(feature = "enterprise")]
+        if let Some(tenant_id) = tenant_id {
+            let tenant = self
+                .query(QueryBy::Id(tenant_id), false)
+                .await?
+                .ok_or_else(|| {
+                    trc::ManageEvent::NotFound
+                        .into_err()
+                        .id(tenant_id)
+                        .details("Tenant not found")
+                        .ctx(trc::Key::Code, ErrorCode::TenantNotFound)
+                        .caused_by(trc::location!())
+                })?;
+
+            // Enforce tenant quotas
+            if let Some(limit) = tenant
+                .get_int_array(PrincipalField::Quota)
+                .and_then(|quotas| quotas.get(principal.typ() as usize + 1))
+                .copied()
+                .filter(|q| *q > 0)
+            {
+                // Obtain number of principals, including those pending creation
+                let total = self
+                    .count_principals(None, principal.typ().into(), tenant_id.into())
+                    .await
+                    .caused_by(trc::location!())?
+                    + pending
+                        .quota_used
+                        .get(&(tenant_id, principal.typ() as u8))
+                        .copied()
+                        .unwrap_or_default();
+
+                if total >= limit {
+                    return Err(err_tenant_quota(principal.typ(), limit, total));
+                }
+                quota_limit = Some(limit);
+            }
+        }
+
+        // SPDX-SnippetEnd
+
+        // Make sure new name is not taken, roles cannot shadow the built-in ones
+        if self
+            .get_principal_id(&name)
+            .await
+            .caused_by(trc::location!())?
+            .is_some()
+            || (principal.typ == Type::Role
+                && PrincipalField::Roles
+                    .map_internal_role_name(&name)
+                    .is_some())
+        {
+            return Err(err_exists(PrincipalField::Name, name));
+        }
+
+        // Make sure the external id is not taken
+        if let Some(external_id) = principal.take_str(PrincipalField::ExternalId) {
+            if !external_id.is_empty() {
+                if self
+                    .external_id_to_id(&external_id)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some()
+                {
+                    return Err(err_exists(PrincipalField::ExternalId, external_id));
+                }
+                principal.set(PrincipalField::ExternalId, external_id);
+            }
+        }
+
+        // Make sure the certificate fingerprints are not taken
+        if let Some(values) = principal.take_str_array(PrincipalField::CertificateFingerprints) {
+            let mut fingerprints = Vec::with_capacity(values.len());
+            for value in values {
+                let fingerprint = normalize_fingerprint(&value)?;
+                if self
+                    .get_value::<()>(ValueKey::from(ValueClass::Directory(
+                        DirectoryClass::CertificateToId(fingerprint.as_bytes().to_vec()),
+                    )))
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some()
+                {
+                    return Err(err_exists(
+                        PrincipalField::CertificateFingerprints,
+                        fingerprint,
+                    ));
+                }
+                if !fingerprints.contains(&fingerprint) {
+                    fingerprints.push(fingerprint);
+                }
+            }
+            if !fingerprints.is_empty() {
+                principal.set(PrincipalField::CertificateFingerprints, fingerprints);
+            }
+        }
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        // Tenants must provide principal names including a valid domain
+        #[cfg(feature = "enterprise")]
+        if let Some(tenant_id) = tenant_id {
+            if matches!(principal.typ, Type::Tenant) {
+                return Err(err_code(
+                    ErrorCode::TenantInvalid,
+                    "Invalid field",
+                    "Tenants cannot contain a tenant field".into(),
+                )
+                .ctx(trc::Key::Key, PrincipalField::Tenant));
+            }
+
+            principal.set(PrincipalField::Tenant, tenant_id);
+
+            if !matches!(principal.typ, Type::Tenant | Type::Domain) {
+                if let Some(domain) = name.split('@').nth(1) {
+                    if self
+                        .get_principal_info(domain)
+                        .await
+                        .caused_by(trc::location!())?
+                        .filter(|v| v.typ == Type::Domain && v.has_tenant_access(tenant_id.into()))
+                        .is_some()
+                    {
+                        valid_domains.insert(domain.to_string());
+                    }
+                }
+
+                if valid_domains.is_empty() {
+                    return Err(err_code(
+                        ErrorCode::DomainNotAllowed,
+                        "Invalid principal name",
+                        "Principal name must include a valid domain assigned to the tenant".into(),
+                    )
+                    .ctx(trc::Key::Key, PrincipalField::Name)
+                    .ctx(trc::Key::Value, name.clone()));
+                }
+            }
+        }
+        // SPDX-SnippetEnd
+
+        principal.set(PrincipalField::Name, name);
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        // Principals created without roles receive the defaults of their tenant
+        #[cfg(feature = "enterprise")]
+        if let Some(tenant_id) = tenant_id.filter(|_| {
+            matches!(principal.typ, Type::Individual | Type::Group)
+                && !principal.has_field(PrincipalField::Roles)
+        }) {
+            if let Some(mut tenant) = self
+                .query(QueryBy::Id(tenant_id), false)
+                .await
+                .caused_by(trc::location!())?
+            {
+                for (default_field, field) in [
+                    (PrincipalField::DefaultRoles, PrincipalField::Roles),
+                    (
+                        PrincipalField::DefaultPermissions,
+                        PrincipalField::EnabledPermissions,
+                    ),
+                ] {
+                    for value in tenant.take_str_array(default_field).unwrap_or_default() {
+                        if !principal.has_str_value(field, &value) {
+                            principal.append_str(field, value);
+                        }
+                    }
+                }
+            }
+        }
+
+        // SPDX-SnippetEnd
+
+        // Map member names
+        let mut members = Vec::new();
+        let mut member_of = Vec::new();
+        for (field, expected_type) in [
+            (PrincipalField::Members, None),
+            (PrincipalField::MemberOf, Some(Type::Group)),
+            (PrincipalField::Lists, Some(Type::List)),
+            (PrincipalField::Roles, Some(Type::Role)),
+        ] {
+            if let Some(names) = principal.take_str_array(field) {
+                let list = if field == PrincipalField::Members {
+                    &mut members
+                } else {
+                    &mut member_of
+                };
+
+                for name in names {
+                    list.push(
+                        if let Some(info) = self
+                            .get_principal_info(&name)
+                            .await
+                            .caused_by(trc::location!())?
+                            .filter(|v| {
+                                expected_type.is_none_or(|t| v.typ == t)
+                                    && v.has_tenant_access(tenant_id)
+                            })
+                            .or_else(|| field.map_internal_roles(&name))
+                        {
+                            PrincipalRef::Stored {
+                                id: info.id,
+                                typ: info.typ,
+                            }
+                        } else {
+                            // Principals can reference others created in the same batch
+                            pending
+                                .names
+                                .get(&name.to_lowercase())
+                                .filter(|(_, typ)| expected_type.is_none_or(|t| *typ == t))
+                                .map(|(idx, typ)| PrincipalRef::Pending {
+                                    idx: *idx,
+                                    typ: *typ,
+                                })
+                                .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name))?
+                        },
+                    );
+                }
+            }
+        }
+
+        // Map permissions
+        for field in [
+            PrincipalField::EnabledPermissions,
+            PrincipalField::DisabledPermissions,
+        ] {
+            if let Some(names) = principal.take_str_array(field) {
+                let mut permissions = Vec::with_capacity(names.len());
+                for name in names {
+                    let permission = Permission::from_name(&name)
+                        .ok_or_else(|| {
+                            err_code(
+                                ErrorCode::PermissionInvalid,
+                                format!("Invalid {} value", field.as_str()),
+                                format!("Permission {name:?} is invalid").into(),
+                            )
+                            .ctx(trc::Key::Key, field)
+                            .ctx(trc::Key::Value, name.clone())
+                        })?
+                        .id() as u64;
+
+                    if !permissions.contains(&permission) {
+                        if allowed_permissions
+                            .as_ref()
+                            .is_none_or(|p| p.get(permission as usize))
+                            || field == PrincipalField::DisabledPermissions
+                        {
+                            permissions.push(permission);
+                        } else {
+                            return Err(err_code(
+                                ErrorCode::PermissionNotGrantable,
+                                "Invalid permission",
+                                format!("Your account cannot grant the {name:?} permission").into(),
+                            )
+                            .ctx(trc::Key::Key, field)
+                            .ctx(trc::Key::Value, name.clone()));
+                        }
+                    }
+                }
+
+                if !permissions.is_empty() {
+                    principal.set(field, permissions);
+                }
+            }
+        }
+
+        // Aliases must forward to at least one valid address
+        if principal.typ == Type::Alias {
+            let mut has_destinations = false;
+            for address in principal.iter_mut_str(PrincipalField::ForwardTo) {
+                *address = sanitize_email(address).ok_or_else(|| {
+                    err_code(
+                        ErrorCode::AddressInvalid,
+                        "Invalid email address",
+                        format!("Invalid value {address:?} for forwardTo").into(),
+                    )
+                    .ctx(trc::Key::Key, PrincipalField::ForwardTo)
+                    .ctx(trc::Key::Value, address.clone())
+                })?;
+                has_destinations = true;
+            }
+            if !has_destinations {
+                return Err(err_missing(PrincipalField::ForwardTo));
+            }
+        } else if principal.has_field(PrincipalField::ForwardTo) {
+            return Err(err_code(
+                ErrorCode::FieldNotAllowed,
+                "Invalid parameter",
+                "Only aliases can forward messages".into(),
+            )
+            .ctx(trc::Key::Key, PrincipalField::ForwardTo));
+        }
+
+        // Locale settings are stored in their canonical form
+        for field in [PrincipalField::Locale, PrincipalField::Timezone] {
+            if let Some(value) = principal.take_str(field).filter(|v| !v.is_empty()) {
+                principal.set(field, validate_locale_setting(field, &value)?);
+            }
+        }
+
+        // Only domains configure catch-all and subaddressing
+        if principal.typ == Type::Domain {
+            if let Some(address) = principal.get_str(PrincipalField::CatchAll) {
+                let address = sanitize_email(address).ok_or_else(|| {
+                    err_code(
+                        ErrorCode::AddressInvalid,
+                        "Invalid email address",
+                        format!("Invalid value {address:?} for catchAll").into(),
+                    )
+                    .ctx(trc::Key::Key, PrincipalField::CatchAll)
+                    .ctx(trc::Key::Value, address.to_string())
+                })?;
+                principal.set(PrincipalField::CatchAll, address);
+            }
+            if let Some(value) = principal.get_str(PrincipalField::Subaddressing) {
+                validate_subaddressing(value)?;
+            }
+        } else {
+            for field in [PrincipalField::CatchAll, PrincipalField::Subaddressing] {
+                if principal.has_field(field) {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only domains can configure recipient routing".into(),
+                    )
+                    .ctx(trc::Key::Key, field));
+                }
+            }
+        }
+
+        // Only tenants define defaults for new principals
+        for field in [
+            PrincipalField::DefaultRoles,
+            PrincipalField::DefaultPermissions,
+        ] {
+            if let Some(values) = principal.take_str_array(field) {
+                if principal.typ != Type::Tenant {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only tenants can define default roles and permissions".into(),
+                    )
+                    .ctx(trc::Key::Key, field));
+                }
+                let values = self.validate_tenant_defaults(field, values, None).await?;
+                if !values.is_empty() {
+                    principal.set(field, values);
+                }
+            }
+        }
+
+        // Only mailing lists have posting settings
+        if principal.typ == Type::List {
+            if let Some(value) = principal.get_str(PrincipalField::PostingPolicy) {
+                validate_posting_policy(value)?;
+            }
+            if let Some(names) = principal.take_str_array(PrincipalField::Moderators) {
+                let moderators = self.validate_moderators(names, tenant_id).await?;
+                if !moderators.is_empty() {
+                    principal.set(PrincipalField::Moderators, moderators);
+                }
+            }
+            if principal.get_int(PrincipalField::ReplyToList) == Some(0) {
+                principal.remove(PrincipalField::ReplyToList);
+            }
+        } else {
+            for field in [
+                PrincipalField::PostingPolicy,
+                PrincipalField::Moderators,
+                PrincipalField::ReplyToList,
+            ] {
+                if principal.has_field(field) {
+                    return Err(err_code(
+                        ErrorCode::FieldNotAllowed,
+                        "Invalid parameter",
+                        "Only mailing lists can configure posting settings".into(),
+                    )
+                    .ctx(trc::Key::Key, field));
+                }
             }
         }
 
-        // Map permissions
-        for field in [
-            PrincipalField::EnabledPermissions,
-            PrincipalField::DisabledPermissions,
-        ] {
-            if let Some(permissions) = principal.take_int_array(field) {
-                for permission in permissions {
-                    if let Some(name) = Permission::from_id(permission as usize) {
-                        principal.append_str(field, name.name().to_string());
+        // Make sure the e-mail is not taken and validate domain
+        if principal.typ != Type::OauthClient {
+            for email in principal.iter_mut_str(PrincipalField::Emails) {
+                *email = normalize_email(email)?;
+                if self.rcpt(email).await.caused_by(trc::location!())? != RcptType::Invalid {
+                    return Err(err_exists(PrincipalField::Emails, email.to_string()));
+                }
+                if let Some(domain) = email.split('@').nth(1) {
+                    if valid_domains.insert(domain.to_string())
+                        && !pending
+                            .names
+                            .get(domain)
+                            .is_some_and(|(_, typ)| *typ == Type::Domain)
+                    {
+                        self.get_principal_info(domain)
+                            .await
+                            .caused_by(trc::location!())?
+                            .filter(|v| v.typ == Type::Domain && v.has_tenant_access(tenant_id))
+                            .ok_or_else(|| {
+                                err_not_found(ErrorCode::DomainNotFound, domain.to_string())
+                            })?;
                     }
                 }
             }
         }
 
-        Ok(())
+        // Only principals that passed validation count towards the tenant quota
+        if let (Some(tenant_id), Some(_)) = (tenant_id, quota_limit) {
+            *pending
+                .quota_used
+                .entry((tenant_id, principal.typ() as u8))
+                .or_default() += 1;
+        }
+
+        Ok(NewPrincipal {
+            principal,
+            tenant_id,
+            quota_limit,
+            members,
+            member_of,
+        })
     }
-}
 
-impl ValidateDirectory for Store {
     async fn validate_email(
         &self,
         email: &str,
         tenant_id: Option<u32>,
         create_if_missing: bool,
-    ) -> trc::Result<()> {
+    ) -> trc::Result<Option<String>> {
         if self.rcpt(email).await.caused_by(trc::location!())? != RcptType::Invalid {
             Err(err_exists(PrincipalField::Emails, email.to_string()))
         } else if let Some(domain) = email.split('@').nth(1) {
+            // Missing domains are returned so they are only created once the
+            // whole update is known to be valid
             match self
                 .get_principal_info(domain)
                 .await
                 .caused_by(trc::location!())?
             {
-                Some(v) if v.typ == Type::Domain && v.has_tenant_access(tenant_id) => Ok(()),
-                None if create_if_missing => self
-                    .create_principal(
-                        Principal::new(0, Type::Domain)
-                            .with_field(PrincipalField::Name, domain.to_string())
-                            .with_field(PrincipalField::Description, domain.to_string()),
-                        tenant_id,
-                        None,
-                    )
-                    .await
-                    .caused_by(trc::location!())
-                    .map(|_| ()),
-                _ => Err(not_found(domain.to_string())),
+                Some(v) if v.typ == Type::Domain && v.has_tenant_access(tenant_id) => Ok(None),
+                None if create_if_missing => Ok(Some(domain.to_string())),
+                _ => Err(err_not_found(ErrorCode::DomainNotFound, domain.to_string())),
             }
         } else {
-            Err(error("Invalid email", "Email address is invalid".into()))
+            Err(err_code(
+                ErrorCode::AddressInvalid,
+                "Invalid email",
+                "Email address is invalid".into(),
+            ))
+        }
+    }
+
+    /// Validates the default roles or permissions of a tenant. Roles must be
+    /// internal or belong to the tenant, new tenants can only use internal roles.
+    async fn validate_tenant_defaults(
+        &self,
+        field: PrincipalField,
+        values: Vec<String>,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<String>> {
+        let mut result = Vec::with_capacity(values.len());
+        for value in values {
+            let value = if field == PrincipalField::DefaultRoles {
+                let role = value.to_lowercase();
+                if PrincipalField::Roles
+                    .map_internal_role_name(&role)
+                    .is_none()
+                    && self
+                        .get_principal_info(&role)
+                        .await
+                        .caused_by(trc::location!())?
+                        .filter(|v| {
+                            v.typ == Type::Role
+                                && tenant_id.is_some_and(|id| v.has_tenant_access(Some(id)))
+                        })
+                        .is_none()
+                {
+                    return Err(err_not_found(ErrorCode::PrincipalNotFound, role));
+                }
+                role
+            } else {
+                Permission::from_name(&value)
+                    .ok_or_else(|| {
+                        err_code(
+                            ErrorCode::PermissionInvalid,
+                            format!("Invalid {} value", field.as_str()),
+                            format!("Permission {value:?} is invalid").into(),
+                        )
+                        .ctx(trc::Key::Key, field)
+                        .ctx(trc::Key::Value, value.clone())
+                    })?
+                    .name()
+                    .to_string()
+            };
+
+            if !result.contains(&value) {
+                result.push(value);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Maps moderator names to ids, moderators have to be individuals
+    /// visible to the tenant.
+    async fn validate_moderators(
+        &self,
+        names: Vec<String>,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<u64>> {
+        let mut result = Vec::with_capacity(names.len());
+        for name in names {
+            let moderator_id = self
+                .get_principal_info(&name)
+                .await
+                .caused_by(trc::location!())?
+                .filter(|v| v.typ == Type::Individual && v.has_tenant_access(tenant_id))
+                .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name))?
+                .id as u64;
+            if !result.contains(&moderator_id) {
+                result.push(moderator_id);
+            }
+        }
+
+        Ok(result)
+    }
+
+    // Walks the stored memberOf edges upwards from `from`, returning whether any
+    // of the principals in `to` is reached. `skip` is not expanded as its edges
+    // are being modified by the caller.
+    async fn has_membership_path(&self, from: &[u32], to: &[u32], skip: u32) -> trc::Result<bool> {
+        let mut visited = AHashSet::from_iter([skip]);
+        let mut pending = from.to_vec();
+        while let Some(principal_id) = pending.pop() {
+            if to.contains(&principal_id) {
+                return Ok(true);
+            } else if visited.insert(principal_id) {
+                pending.extend(
+                    self.get_member_of(principal_id)
+                        .await
+                        .caused_by(trc::location!())?
+                        .into_iter()
+                        .map(|member_of| member_of.principal_id),
+                );
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+impl CreateRetry {
+    // Capped exponential backoff with jitter so that writers do not retry in lockstep
+    pub fn backoff(&self, try_count: u32) -> Duration {
+        let delay = self
+            .backoff_min
+            .saturating_mul(1 << try_count.min(16))
+            .min(self.backoff_max)
+            .as_micros() as u64;
+        Duration::from_micros(rand::thread_rng().gen_range(delay / 2..=delay))
+    }
+}
+
+impl Default for CreateRetry {
+    fn default() -> Self {
+        CreateRetry {
+            max_retries: 5,
+            backoff_min: Duration::from_millis(10),
+            backoff_max: Duration::from_millis(500),
+        }
+    }
+}
+
+impl PrincipalFilter {
+    /// Parses whitespace separated terms, `field:value` terms are scoped to
+    /// a single field (for example `emails:@example.org`).
+    pub fn parse(filter: &str) -> Self {
+        let mut result = PrincipalFilter::default();
+        for term in filter.split_whitespace() {
+            if let Some((field, value)) = term.split_once(':').and_then(|(field, value)| {
+                PrincipalField::try_parse(field)
+                    .filter(|field| Self::is_filterable(*field))
+                    .map(|field| (field, value))
+            }) {
+                if !value.is_empty() {
+                    result.fields.push((field, value.to_lowercase()));
+                }
+            } else {
+                result.text.push(term.to_lowercase());
+            }
         }
+        result
+    }
+
+    pub fn with_field(mut self, field: PrincipalField, value: impl AsRef<str>) -> Self {
+        self.fields.push((field, value.as_ref().to_lowercase()));
+        self
+    }
+
+    pub fn with_last_login_before(mut self, timestamp: u64) -> Self {
+        self.last_login_before = Some(timestamp);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty() && self.fields.is_empty() && self.last_login_before.is_none()
+    }
+
+    /// Returns true when the filter can be evaluated without fetching the principal.
+    pub fn is_name_only(&self) -> bool {
+        self.text.is_empty()
+            && self.last_login_before.is_none()
+            && self
+                .fields
+                .iter()
+                .all(|(field, _)| *field == PrincipalField::Name)
+    }
+
+    pub fn matches_name(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.fields
+            .iter()
+            .filter(|(field, _)| *field == PrincipalField::Name)
+            .all(|(_, value)| name.contains(value.as_str()))
+    }
+
+    pub fn matches(&self, principal: &Principal) -> bool {
+        self.text.iter().all(|value| principal.find_str(value))
+            && self.fields.iter().all(|(field, value)| {
+                principal
+                    .fields
+                    .get(field)
+                    .is_some_and(|v| v.find_str(value))
+            })
+            && self.last_login_before.is_none_or(|before| {
+                matches!(
+                    principal.typ,
+                    Type::Individual | Type::Group | Type::Service | Type::ApiKey
+                ) && principal
+                    .get_int(PrincipalField::LastLogin)
+                    .is_none_or(|last_login| last_login < before)
+            })
+    }
+
+    // Secrets and fields holding ids cannot be filtered on
+    fn is_filterable(field: PrincipalField) -> bool {
+        matches!(
+            field,
+            PrincipalField::Name
+                | PrincipalField::Description
+                | PrincipalField::Emails
+                | PrincipalField::Urls
+                | PrincipalField::ExternalMembers
+                | PrincipalField::ForwardTo
+                | PrincipalField::ExternalId
+        )
     }
 }
 
@@ -1937,8 +5545,11 @@ impl<'x> UpdatePrincipal<'x> {
             query: QueryBy::Id(id),
             changes: Vec::new(),
             create_domains: false,
+            cascade_rename: false,
             tenant_id: None,
             allowed_permissions: None,
+            expected_hash: None,
+            keep_sessions: false,
         }
     }
 
@@ -1947,8 +5558,24 @@ impl<'x> UpdatePrincipal<'x> {
             query: QueryBy::Name(name),
             changes: Vec::new(),
             create_domains: false,
+            cascade_rename: false,
             tenant_id: None,
             allowed_permissions: None,
+            expected_hash: None,
+            keep_sessions: false,
+        }
+    }
+
+    pub fn by_external_id(external_id: &'x str) -> Self {
+        Self {
+            query: QueryBy::ExternalId(external_id),
+            changes: Vec::new(),
+            create_domains: false,
+            cascade_rename: false,
+            tenant_id: None,
+            allowed_permissions: None,
+            expected_hash: None,
+            keep_sessions: false,
         }
     }
 
@@ -1971,6 +5598,131 @@ impl<'x> UpdatePrincipal<'x> {
         self.create_domains = true;
         self
     }
+
+    /// Renames the e-mail address matching the login name along with the principal.
+    pub fn cascade_rename(mut self) -> Self {
+        self.cascade_rename = true;
+        self
+    }
+
+    /// Keeps existing sessions and tokens when secrets are replaced by
+    /// equivalent ones or consumed by the principal itself.
+    pub fn keep_sessions(mut self) -> Self {
+        self.keep_sessions = true;
+        self
+    }
+
+    /// Fails with an assertion error if the stored principal no longer
+    /// matches the hash of the value the changes were based on.
+    pub fn if_unchanged(mut self, hash: u64) -> Self {
+        self.expected_hash = hash.into();
+        self
+    }
+
+    pub fn changes(&self) -> &[PrincipalUpdate] {
+        &self.changes
+    }
+}
+
+// Removes repeated values while keeping the order in which they were submitted
+fn dedup_values<T: PartialEq>(values: &mut Vec<T>) {
+    let mut pos = 0;
+    while pos < values.len() {
+        if values[..pos].contains(&values[pos]) {
+            values.remove(pos);
+        } else {
+            pos += 1;
+        }
+    }
+}
+
+/// Fingerprints are SHA-256 digests of the DER encoded certificate, stored
+/// as uppercase hex pairs separated by colons.
+fn normalize_fingerprint(value: &str) -> trc::Result<String> {
+    let hex = value
+        .chars()
+        .filter(|ch| !matches!(ch, ':' | ' '))
+        .map(|ch| ch.to_ascii_uppercase())
+        .collect::<Vec<_>>();
+    if hex.len() == 64 && hex.iter().all(|ch| ch.is_ascii_hexdigit()) {
+        Ok(hex
+            .chunks(2)
+            .map(|pair| pair.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join(":"))
+    } else {
+        Err(err_code(
+            ErrorCode::FieldInvalid,
+            "Invalid certificate fingerprint",
+            format!("Invalid value {value:?} for certificateFingerprints").into(),
+        )
+        .ctx(trc::Key::Key, PrincipalField::CertificateFingerprints)
+        .ctx(trc::Key::Value, value.to_string()))
+    }
+}
+
+fn validate_posting_policy(value: &str) -> trc::Result<()> {
+    if PostingPolicy::parse(value).is_some() {
+        Ok(())
+    } else {
+        Err(err_code(
+            ErrorCode::FieldInvalid,
+            "Invalid posting policy",
+            format!("Expected \"anyone\", \"members\" or \"moderators\", found {value:?}").into(),
+        )
+        .ctx(trc::Key::Key, PrincipalField::PostingPolicy)
+        .ctx(trc::Key::Value, value.to_string()))
+    }
+}
+
+fn validate_locale_setting(field: PrincipalField, value: &str) -> trc::Result<String> {
+    let canonical = if field == PrincipalField::Locale {
+        Locale::parse(value).map(|locale| locale.as_str().to_string())
+    } else {
+        parse_timezone(value).map(|timezone| timezone.name().to_string())
+    };
+
+    canonical.ok_or_else(|| {
+        let reason = if field == PrincipalField::Locale {
+            format!(
+                "Unsupported locale {value:?}, expected one of {}",
+                Locale::ALL
+                    .iter()
+                    .map(|locale| format!("{:?}", locale.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        } else {
+            format!(
+                "Unknown time zone {value:?}, expected a tz database name such as \"Europe/Paris\""
+            )
+        };
+        err_code(
+            ErrorCode::FieldInvalid,
+            "Invalid locale setting",
+            reason.into(),
+        )
+        .ctx(trc::Key::Key, field)
+        .ctx(trc::Key::Value, value.to_string())
+    })
+}
+
+fn validate_subaddressing(value: &str) -> trc::Result<()> {
+    if Subaddressing::parse(value).is_some() {
+        Ok(())
+    } else {
+        Err(err_code(
+            ErrorCode::FieldInvalid,
+            "Invalid subaddressing setting",
+            format!(
+                "Expected {:?} or a list of separator characters, found {value:?}",
+                Subaddressing::DISABLED
+            )
+            .into(),
+        )
+        .ctx(trc::Key::Key, PrincipalField::Subaddressing)
+        .ctx(trc::Key::Value, value.to_string()))
+    }
 }
 
 fn validate_member_of(
@@ -1981,14 +5733,17 @@ fn validate_member_of(
 ) -> trc::Result<()> {
     let expected_types = match (field, typ) {
         (PrincipalField::MemberOf, Type::Individual) => &[Type::Group, Type::Individual][..],
-        (PrincipalField::MemberOf, Type::Group) => &[Type::Group][..],
+        (PrincipalField::MemberOf, Type::Group | Type::Service) => &[Type::Group][..],
         (PrincipalField::Lists, Type::Individual | Type::Group) => &[Type::List][..],
-        (PrincipalField::Roles, Type::Individual | Type::Tenant | Type::Role) => &[Type::Role][..],
+        (PrincipalField::Roles, Type::Individual | Type::Tenant | Type::Role | Type::Service) => {
+            &[Type::Role][..]
+        }
         _ => &[][..],
     };
 
     if expected_types.is_empty() || !expected_types.contains(&member_type) {
-        Err(error(
+        Err(err_code(
+            ErrorCode::MemberInvalid,
             format!("Invalid {} value", field.as_str()),
             if !expected_types.is_empty() {
                 format!(
@@ -2003,12 +5758,24 @@ fn validate_member_of(
             } else {
                 format!("Principal {member_name:?} cannot be added as a member.").into()
             },
-        ))
+        )
+        .ctx(trc::Key::Key, field)
+        .ctx(trc::Key::Value, member_name.to_string()))
     } else {
         Ok(())
     }
 }
 
+fn err_circular_membership(field: PrincipalField, member_name: &str) -> trc::Error {
+    err_code(
+        ErrorCode::MemberInvalid,
+        "Circular membership",
+        format!("Adding {member_name:?} would make the principal a member of itself.").into(),
+    )
+    .ctx(trc::Key::Key, field)
+    .ctx(trc::Key::Value, member_name.to_string())
+}
+
 #[derive(Clone, Copy)]
 pub(crate) struct DynamicPrincipalInfo {
     typ: Type,
@@ -2034,26 +5801,164 @@ impl From<DynamicPrincipalInfo> for MaybeDynamicValue {
     }
 }
 
+/// Maximum length of a path (RFC 5321, section 4.5.3.1.3) without the angle brackets.
+const MAX_EMAIL_LEN: usize = 254;
+const MAX_LOCAL_PART_LEN: usize = 64;
+const MAX_DOMAIN_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+/// Validates the syntax of an e-mail address and returns it lowercased, with
+/// internationalized domains converted to punycode.
+pub fn normalize_email(email: &str) -> trc::Result<String> {
+    email
+        .rsplit_once('@')
+        // An empty local part denotes a catch-all address ("@domain")
+        .filter(|(local, _)| local.is_empty() || is_valid_local_part(local))
+        .and_then(|(local, domain)| {
+            idna::domain_to_ascii(domain)
+                .ok()
+                .filter(|domain| is_valid_domain(domain))
+                .map(|domain| format!("{}@{domain}", local.to_lowercase()))
+        })
+        .filter(|email| email.len() <= MAX_EMAIL_LEN)
+        .ok_or_else(|| err_invalid_email(email))
+}
+
+fn is_valid_local_part(local: &str) -> bool {
+    local.len() <= MAX_LOCAL_PART_LEN
+        && local.split('.').all(|atom| {
+            !atom.is_empty()
+                && atom.chars().all(|ch| {
+                    ch.is_ascii_alphanumeric()
+                        || "!#$%&'*+-/=?^_`{|}~".contains(ch)
+                        || (!ch.is_ascii() && !ch.is_whitespace() && !ch.is_control())
+                })
+        })
+}
+
+fn is_valid_domain(domain: &str) -> bool {
+    domain.len() <= MAX_DOMAIN_LEN
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_LABEL_LEN
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == b'-')
+        })
+}
+
+pub fn err_invalid_email(email: impl Into<String>) -> trc::Error {
+    let email = email.into();
+    err_code(
+        ErrorCode::AddressInvalid,
+        "Invalid email address",
+        format!("{email:?} is not a valid email address").into(),
+    )
+    .ctx(trc::Key::Key, PrincipalField::Emails)
+    .ctx(trc::Key::Value, email)
+}
+
+fn err_tenant_quota(typ: Type, limit: u64, total: u64) -> trc::Error {
+    trc::LimitEvent::TenantQuota
+        .into_err()
+        .details("Tenant principal quota exceeded")
+        .ctx(trc::Key::Details, typ.as_str())
+        .ctx(trc::Key::Limit, limit)
+        .ctx(trc::Key::Total, total)
+}
+
+fn assert_not_builtin_role(principal_id: u32) -> trc::Result<()> {
+    if !matches!(principal_id, ROLE_ADMIN | ROLE_TENANT_ADMIN | ROLE_USER) {
+        Ok(())
+    } else {
+        Err(err_builtin_role())
+    }
+}
+
+fn err_principal_not_found(name: &str) -> trc::Error {
+    if PrincipalField::Roles.map_internal_role_name(name).is_none() {
+        err_not_found(ErrorCode::PrincipalNotFound, name.to_string())
+    } else {
+        err_builtin_role()
+    }
+}
+
+fn err_builtin_role() -> trc::Error {
+    err_code(
+        ErrorCode::PrincipalProtected,
+        "Built-in roles cannot be modified",
+        "The admin, tenant-admin and user roles are managed by the server".into(),
+    )
+}
+
+fn err_last_admin(reason: &'static str) -> trc::Error {
+    err_code(
+        ErrorCode::PrincipalProtected,
+        "Cannot remove the last administrator",
+        reason.into(),
+    )
+}
+
+pub(crate) fn assert_not_on_hold(principal: &Principal) -> trc::Result<()> {
+    if !principal.is_on_hold() {
+        Ok(())
+    } else {
+        Err(err_code(
+            ErrorCode::PrincipalOnHold,
+            "Principal is under litigation hold",
+            format!(
+                "The hold on {:?} must be released before it can be deleted",
+                principal.name()
+            )
+            .into(),
+        ))
+    }
+}
+
 pub fn err_missing(field: impl Into<trc::Value>) -> trc::Error {
-    trc::ManageEvent::MissingParameter.ctx(trc::Key::Key, field)
+    trc::ManageEvent::MissingParameter
+        .ctx(trc::Key::Code, ErrorCode::FieldMissing)
+        .ctx(trc::Key::Key, field)
 }
 
 pub fn err_exists(field: impl Into<trc::Value>, value: impl Into<trc::Value>) -> trc::Error {
+    let field = field.into();
+    let code = match field.as_str() {
+        Some("name") => ErrorCode::PrincipalAlreadyExists,
+        Some("emails") => ErrorCode::AddressAlreadyExists,
+        _ => ErrorCode::ResourceAlreadyExists,
+    };
+
     trc::ManageEvent::AlreadyExists
+        .ctx(trc::Key::Code, code)
         .ctx(trc::Key::Key, field)
         .ctx(trc::Key::Value, value)
 }
 
 pub fn not_found(value: impl Into<trc::Value>) -> trc::Error {
-    trc::ManageEvent::NotFound.ctx(trc::Key::Key, value)
+    trc::ManageEvent::NotFound
+        .ctx(trc::Key::Code, ErrorCode::ResourceNotFound)
+        .ctx(trc::Key::Key, value)
+}
+
+pub fn err_not_found(code: ErrorCode, value: impl Into<trc::Value>) -> trc::Error {
+    trc::ManageEvent::NotFound
+        .ctx(trc::Key::Code, code)
+        .ctx(trc::Key::Key, value)
 }
 
 pub fn unsupported(details: impl Into<trc::Value>) -> trc::Error {
-    trc::ManageEvent::NotSupported.ctx(trc::Key::Details, details)
+    trc::ManageEvent::NotSupported
+        .ctx(trc::Key::Code, ErrorCode::Unsupported)
+        .ctx(trc::Key::Details, details)
 }
 
 pub fn enterprise() -> trc::Error {
-    trc::ManageEvent::NotSupported.ctx(trc::Key::Details, "Enterprise feature")
+    trc::ManageEvent::NotSupported
+        .ctx(trc::Key::Code, ErrorCode::EnterpriseRequired)
+        .ctx(trc::Key::Details, "Enterprise feature")
 }
 
 pub fn error(details: impl Into<trc::Value>, reason: Option<impl Into<trc::Value>>) -> trc::Error {
@@ -2062,6 +5967,201 @@ pub fn error(details: impl Into<trc::Value>, reason: Option<impl Into<trc::Value
         .ctx_opt(trc::Key::Reason, reason)
 }
 
+pub fn err_code(
+    code: ErrorCode,
+    details: impl Into<trc::Value>,
+    reason: Option<impl Into<trc::Value>>,
+) -> trc::Error {
+    error(details, reason).ctx(trc::Key::Code, code)
+}
+
+/// Stable machine-readable codes for the errors returned by the management API.
+/// Codes must never be renamed or reused, add new variants instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    FieldMissing,
+    FieldInvalid,
+    FieldNotAllowed,
+    PrincipalAlreadyExists,
+    PrincipalNotFound,
+    PrincipalInvalidType,
+    PrincipalHasMembers,
+    AddressAlreadyExists,
+    AddressInvalid,
+    DomainNotFound,
+    DomainNotAllowed,
+    TenantNotFound,
+    TenantInvalid,
+    PermissionInvalid,
+    PermissionNotGrantable,
+    MemberInvalid,
+    TemplateInvalid,
+    ResourceAlreadyExists,
+    ResourceNotFound,
+    AssertFailed,
+    Unsupported,
+    EnterpriseRequired,
+    DirectoryReadOnly,
+    ApprovalNotAllowed,
+    IdempotencyConflict,
+    MaintenanceMode,
+    PrincipalOnHold,
+    PrincipalProtected,
+    Other,
+}
+
+impl ErrorCode {
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::FieldMissing,
+        ErrorCode::FieldInvalid,
+        ErrorCode::FieldNotAllowed,
+        ErrorCode::PrincipalAlreadyExists,
+        ErrorCode::PrincipalNotFound,
+        ErrorCode::PrincipalInvalidType,
+        ErrorCode::PrincipalHasMembers,
+        ErrorCode::AddressAlreadyExists,
+        ErrorCode::AddressInvalid,
+        ErrorCode::DomainNotFound,
+        ErrorCode::DomainNotAllowed,
+        ErrorCode::TenantNotFound,
+        ErrorCode::TenantInvalid,
+        ErrorCode::PermissionInvalid,
+        ErrorCode::PermissionNotGrantable,
+        ErrorCode::MemberInvalid,
+        ErrorCode::TemplateInvalid,
+        ErrorCode::ResourceAlreadyExists,
+        ErrorCode::ResourceNotFound,
+        ErrorCode::AssertFailed,
+        ErrorCode::Unsupported,
+        ErrorCode::EnterpriseRequired,
+        ErrorCode::DirectoryReadOnly,
+        ErrorCode::ApprovalNotAllowed,
+        ErrorCode::IdempotencyConflict,
+        ErrorCode::MaintenanceMode,
+        ErrorCode::PrincipalOnHold,
+        ErrorCode::PrincipalProtected,
+        ErrorCode::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::FieldMissing => "field.missing",
+            ErrorCode::FieldInvalid => "field.invalid",
+            ErrorCode::FieldNotAllowed => "field.notAllowed",
+            ErrorCode::PrincipalAlreadyExists => "principal.alreadyExists",
+            ErrorCode::PrincipalNotFound => "principal.notFound",
+            ErrorCode::PrincipalInvalidType => "principal.invalidType",
+            ErrorCode::PrincipalHasMembers => "principal.hasMembers",
+            ErrorCode::AddressAlreadyExists => "address.alreadyExists",
+            ErrorCode::AddressInvalid => "address.invalid",
+            ErrorCode::DomainNotFound => "domain.notFound",
+            ErrorCode::DomainNotAllowed => "domain.notAllowed",
+            ErrorCode::TenantNotFound => "tenant.notFound",
+            ErrorCode::TenantInvalid => "tenant.invalid",
+            ErrorCode::PermissionInvalid => "permission.invalid",
+            ErrorCode::PermissionNotGrantable => "permission.notGrantable",
+            ErrorCode::MemberInvalid => "member.invalid",
+            ErrorCode::TemplateInvalid => "template.invalid",
+            ErrorCode::ResourceAlreadyExists => "resource.alreadyExists",
+            ErrorCode::ResourceNotFound => "resource.notFound",
+            ErrorCode::AssertFailed => "request.assertFailed",
+            ErrorCode::Unsupported => "request.unsupported",
+            ErrorCode::EnterpriseRequired => "request.enterpriseRequired",
+            ErrorCode::DirectoryReadOnly => "directory.readOnly",
+            ErrorCode::ApprovalNotAllowed => "approval.notAllowed",
+            ErrorCode::IdempotencyConflict => "idempotency.conflict",
+            ErrorCode::MaintenanceMode => "directory.maintenance",
+            ErrorCode::PrincipalOnHold => "principal.onHold",
+            ErrorCode::PrincipalProtected => "principal.protected",
+            ErrorCode::Other => "error.other",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ErrorCode::FieldMissing => "A required field was not provided",
+            ErrorCode::FieldInvalid => "A field contains an invalid value",
+            ErrorCode::FieldNotAllowed => "The field cannot be modified by the account owner",
+            ErrorCode::PrincipalAlreadyExists => "A principal with the same name already exists",
+            ErrorCode::PrincipalNotFound => "The principal does not exist",
+            ErrorCode::PrincipalInvalidType => "The principal is not of the expected type",
+            ErrorCode::PrincipalHasMembers => {
+                "The principal cannot be deleted while it still has members"
+            }
+            ErrorCode::AddressAlreadyExists => {
+                "The email address is already assigned to another principal"
+            }
+            ErrorCode::AddressInvalid => "The email address is not valid",
+            ErrorCode::DomainNotFound => "The domain does not exist",
+            ErrorCode::DomainNotAllowed => "The domain is not assigned to the tenant",
+            ErrorCode::TenantNotFound => "The tenant does not exist",
+            ErrorCode::TenantInvalid => "The tenant assignment is not valid",
+            ErrorCode::PermissionInvalid => "The permission name is not valid",
+            ErrorCode::PermissionNotGrantable => {
+                "The account is not allowed to grant the permission"
+            }
+            ErrorCode::MemberInvalid => "The member is not of an allowed principal type",
+            ErrorCode::TemplateInvalid => "The template contains a syntax error or is too large",
+            ErrorCode::ResourceAlreadyExists => "The resource already exists",
+            ErrorCode::ResourceNotFound => "The requested resource does not exist",
+            ErrorCode::AssertFailed => "The resource was modified by another request",
+            ErrorCode::Unsupported => "The requested action is not supported",
+            ErrorCode::EnterpriseRequired => "The feature requires an enterprise license",
+            ErrorCode::DirectoryReadOnly => {
+                "The directory is replicated from another server and cannot be modified"
+            }
+            ErrorCode::ApprovalNotAllowed => {
+                "The request must be approved by a different administrator"
+            }
+            ErrorCode::IdempotencyConflict => {
+                "The idempotency key was used for a different or unfinished request"
+            }
+            ErrorCode::MaintenanceMode => {
+                "The directory is in read-only maintenance mode and cannot be modified"
+            }
+            ErrorCode::PrincipalOnHold => {
+                "The principal is under litigation hold and its data cannot be destroyed"
+            }
+            ErrorCode::PrincipalProtected => {
+                "The principal is required to administer the server and cannot be removed"
+            }
+            ErrorCode::Other => "An unexpected error occurred",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        ErrorCode::ALL.iter().find(|c| c.as_str() == value).copied()
+    }
+}
+
+impl From<trc::ManageEvent> for ErrorCode {
+    fn from(event: trc::ManageEvent) -> Self {
+        match event {
+            trc::ManageEvent::MissingParameter => ErrorCode::FieldMissing,
+            trc::ManageEvent::AlreadyExists => ErrorCode::ResourceAlreadyExists,
+            trc::ManageEvent::AssertFailed => ErrorCode::AssertFailed,
+            trc::ManageEvent::NotFound => ErrorCode::ResourceNotFound,
+            trc::ManageEvent::NotSupported => ErrorCode::Unsupported,
+            trc::ManageEvent::Error
+            | trc::ManageEvent::DirectoryAlert
+            | trc::ManageEvent::MaintenanceEnabled
+            | trc::ManageEvent::MaintenanceDisabled
+            | trc::ManageEvent::HoldPlaced
+            | trc::ManageEvent::HoldReleased
+            | trc::ManageEvent::DirectoryInconsistency
+            | trc::ManageEvent::DirectoryVerified
+            | trc::ManageEvent::QuotaMismatch
+            | trc::ManageEvent::QuotaRecalculated => ErrorCode::Other,
+        }
+    }
+}
+
+impl From<ErrorCode> for trc::Value {
+    fn from(value: ErrorCode) -> Self {
+        trc::Value::Static(value.as_str())
+    }
+}
+
 impl From<PrincipalField> for trc::Value {
     fn from(value: PrincipalField) -> Self {
         trc::Value::Static(value.as_str())
@@ -4,8 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod delegation;
 pub mod lookup;
+pub mod maintenance;
 pub mod manage;
+pub mod permissions;
 
 use std::{fmt::Display, slice::Iter};
 
@@ -14,14 +17,16 @@ use jmap_proto::types::collection::Collection;
 use manage::DynamicPrincipalInfo;
 use store::{
     write::{
-        key::KeySerializer, AnyClass, BatchBuilder, DirectoryClass, MaybeDynamicId, ValueClass,
+        key::{DeserializeBigEndian, KeySerializer},
+        migrate::MigrationChunk,
+        AnyClass, BatchBuilder, DirectoryClass, MaybeDynamicId, ValueClass,
     },
     Deserialize, IterateParams, Serialize, Store, ValueKey, SUBSPACE_DIRECTORY, U32_LEN,
 };
 use trc::AddContext;
 use utils::codec::leb128::{Leb128Iterator, Leb128Reader};
 
-use crate::{Principal, Type, ROLE_ADMIN, ROLE_USER};
+use crate::{Principal, Type, MAX_TYPE_ID, ROLE_ADMIN, ROLE_USER};
 
 const INT_MARKER: u8 = 1 << 7;
 
@@ -121,25 +126,73 @@ impl PrincipalInfo {
     }
 }
 
+/// Migration rewriting the name mappings stored using the legacy layout.
+pub const PRINCIPAL_INFO_MIGRATION: &str = "principal-info-v2";
+
+// A non-canonical LEB128 zero, which the legacy layout (starting with the
+// LEB128 encoded principal id) never produces.
+const PRINCIPAL_INFO_V2: [u8; 2] = [0x80, 0x00];
+
+const PRINCIPAL_TYPES: [Type; MAX_TYPE_ID] = [
+    Type::Individual,
+    Type::Group,
+    Type::Resource,
+    Type::Location,
+    Type::List,
+    Type::Other,
+    Type::Domain,
+    Type::Tenant,
+    Type::Role,
+    Type::ApiKey,
+    Type::OauthClient,
+    Type::Service,
+    Type::Alias,
+];
+
 impl Serialize for PrincipalInfo {
     fn serialize(self) -> Vec<u8> {
+        // Type and tenant come first so that they can be matched by prefix
+        let serializer = KeySerializer::new((U32_LEN * 2) + 4)
+            .write(PRINCIPAL_INFO_V2.as_slice())
+            .write(self.typ as u8);
+
         if let Some(tenant) = self.tenant {
-            KeySerializer::new((U32_LEN * 2) + 1)
-                .write_leb128(self.id)
-                .write(self.typ as u8)
-                .write_leb128(tenant)
-                .finalize()
+            serializer.write(1u8).write(tenant)
         } else {
-            KeySerializer::new(U32_LEN + 1)
-                .write_leb128(self.id)
-                .write(self.typ as u8)
-                .finalize()
+            serializer.write(0u8)
         }
+        .write_leb128(self.id)
+        .finalize()
     }
 }
 
 impl Deserialize for PrincipalInfo {
     fn deserialize(bytes_: &[u8]) -> trc::Result<Self> {
+        if let Some(bytes) = bytes_.strip_prefix(PRINCIPAL_INFO_V2.as_slice()) {
+            let (typ, tenant, id) = match bytes {
+                [typ, 0, id @ ..] => (*typ, None, id),
+                [typ, 1, rest @ ..] if rest.len() > U32_LEN => {
+                    (*typ, Some(rest.deserialize_be_u32(0)?), &rest[U32_LEN..])
+                }
+                _ => {
+                    return Err(trc::StoreEvent::DataCorruption
+                        .caused_by(trc::location!())
+                        .ctx(trc::Key::Value, bytes_))
+                }
+            };
+
+            return Ok(PrincipalInfo {
+                id: id.read_leb128().map(|(id, _)| id).ok_or_else(|| {
+                    trc::StoreEvent::DataCorruption
+                        .caused_by(trc::location!())
+                        .ctx(trc::Key::Value, bytes_)
+                })?,
+                typ: Type::from_u8(typ),
+                tenant,
+            });
+        }
+
+        // Legacy layout
         let mut bytes = bytes_.iter();
         Ok(PrincipalInfo {
             id: bytes.next_leb128().ok_or_else(|| {
@@ -165,6 +218,46 @@ impl PrincipalInfo {
             tenant,
         }
     }
+
+    /// Returns the serialized value prefixes matching the principals of the
+    /// given types that are visible to a tenant. The result is a superset of
+    /// the matching rows meant to be pushed down to the store, callers still
+    /// need to validate each row. An empty list means that no filter applies.
+    pub fn filter_prefixes(types: &[Type], tenant_id: Option<u32>) -> Vec<Vec<u8>> {
+        let tenant_id = tenant_id.filter(|_| cfg!(feature = "enterprise"));
+        let types = match (types.is_empty(), tenant_id.is_some()) {
+            (false, _) => types,
+            (true, true) => PRINCIPAL_TYPES.as_slice(),
+            (true, false) => return vec![],
+        };
+
+        let mut prefixes = Vec::with_capacity(types.len() + 1);
+        for typ in types {
+            let prefix = PRINCIPAL_INFO_V2
+                .iter()
+                .copied()
+                .chain([*typ as u8])
+                .collect::<Vec<_>>();
+
+            if let Some(tenant_id) = tenant_id {
+                // Tenants are not members of themselves but are visible to their admins
+                if *typ == Type::Tenant {
+                    prefixes.push(prefix.iter().copied().chain([0u8]).collect());
+                }
+                prefixes.push(
+                    prefix
+                        .into_iter()
+                        .chain([1u8])
+                        .chain(tenant_id.to_be_bytes())
+                        .collect(),
+                );
+            } else {
+                prefixes.push(prefix);
+            }
+        }
+
+        prefixes
+    }
 }
 
 fn deserialize(bytes: &[u8]) -> Option<Principal> {
@@ -248,6 +341,12 @@ fn deserialize(bytes: &[u8]) -> Option<Principal> {
 
 pub trait MigrateDirectory: Sync + Send {
     fn migrate_directory(&self) -> impl std::future::Future<Output = trc::Result<()>> + Send;
+
+    fn migrate_principal_info(
+        &self,
+        from: Option<Vec<u8>>,
+        chunk_size: usize,
+    ) -> impl std::future::Future<Output = trc::Result<MigrationChunk>> + Send;
 }
 
 impl MigrateDirectory for Store {
@@ -389,6 +488,60 @@ impl MigrateDirectory for Store {
 
         Ok(())
     }
+
+    // Rewrites name mappings using the legacy layout, value filters pushed
+    // down to the store only match the current layout
+    async fn migrate_principal_info(
+        &self,
+        from: Option<Vec<u8>>,
+        chunk_size: usize,
+    ) -> trc::Result<MigrationChunk> {
+        let mut chunk = MigrationChunk::default();
+        let mut last_name = None;
+        chunk
+            .batch
+            .with_account_id(u32::MAX)
+            .with_collection(Collection::Principal);
+
+        self.iterate(
+            IterateParams::new(
+                ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(
+                    from.unwrap_or_default(),
+                ))),
+                ValueKey::from(ValueClass::Directory(DirectoryClass::NameToId(vec![
+                    u8::MAX;
+                    10
+                ]))),
+            ),
+            |key, value| {
+                let name = key.get(1..).unwrap_or_default();
+                if !value.starts_with(PRINCIPAL_INFO_V2.as_slice()) {
+                    let info = PrincipalInfo::deserialize(value).caused_by(trc::location!())?;
+                    chunk.batch.set(
+                        ValueClass::Directory(DirectoryClass::NameToId(name.to_vec())),
+                        PrincipalInfo::new(info.id, info.typ, info.tenant).serialize(),
+                    );
+                    chunk.migrated += 1;
+                }
+                chunk.processed += 1;
+                last_name = Some(name.to_vec());
+
+                Ok(chunk.processed < chunk_size as u64)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        // Continue right after the last name read
+        if chunk.processed == chunk_size as u64 {
+            chunk.next_key = last_name.map(|mut name| {
+                name.push(0);
+                name
+            });
+        }
+
+        Ok(chunk)
+    }
 }
 
 #[derive(
@@ -413,6 +566,22 @@ pub enum PrincipalField {
     Picture,
     Urls,
     ExternalMembers,
+    Enabled,
+    Hold,
+    ExternalId,
+    LastLogin,
+    ForwardTo,
+    CatchAll,
+    Subaddressing,
+    DefaultRoles,
+    DefaultPermissions,
+    CertificateFingerprints,
+    PostingPolicy,
+    Moderators,
+    ReplyToList,
+    Locale,
+    Timezone,
+    CredentialsEpoch,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -493,6 +662,22 @@ impl PrincipalField {
             PrincipalField::Picture => 14,
             PrincipalField::Urls => 15,
             PrincipalField::ExternalMembers => 16,
+            PrincipalField::Enabled => 17,
+            PrincipalField::Hold => 18,
+            PrincipalField::ExternalId => 19,
+            PrincipalField::LastLogin => 20,
+            PrincipalField::ForwardTo => 21,
+            PrincipalField::CatchAll => 22,
+            PrincipalField::Subaddressing => 23,
+            PrincipalField::DefaultRoles => 24,
+            PrincipalField::DefaultPermissions => 25,
+            PrincipalField::CertificateFingerprints => 26,
+            PrincipalField::PostingPolicy => 27,
+            PrincipalField::Moderators => 28,
+            PrincipalField::ReplyToList => 29,
+            PrincipalField::Locale => 30,
+            PrincipalField::Timezone => 31,
+            PrincipalField::CredentialsEpoch => 32,
         }
     }
 
@@ -515,6 +700,22 @@ impl PrincipalField {
             14 => Some(PrincipalField::Picture),
             15 => Some(PrincipalField::Urls),
             16 => Some(PrincipalField::ExternalMembers),
+            17 => Some(PrincipalField::Enabled),
+            18 => Some(PrincipalField::Hold),
+            19 => Some(PrincipalField::ExternalId),
+            20 => Some(PrincipalField::LastLogin),
+            21 => Some(PrincipalField::ForwardTo),
+            22 => Some(PrincipalField::CatchAll),
+            23 => Some(PrincipalField::Subaddressing),
+            24 => Some(PrincipalField::DefaultRoles),
+            25 => Some(PrincipalField::DefaultPermissions),
+            26 => Some(PrincipalField::CertificateFingerprints),
+            27 => Some(PrincipalField::PostingPolicy),
+            28 => Some(PrincipalField::Moderators),
+            29 => Some(PrincipalField::ReplyToList),
+            30 => Some(PrincipalField::Locale),
+            31 => Some(PrincipalField::Timezone),
+            32 => Some(PrincipalField::CredentialsEpoch),
             _ => None,
         }
     }
@@ -538,6 +739,22 @@ impl PrincipalField {
             PrincipalField::Picture => "picture",
             PrincipalField::Urls => "urls",
             PrincipalField::ExternalMembers => "externalMembers",
+            PrincipalField::Enabled => "enabled",
+            PrincipalField::Hold => "hold",
+            PrincipalField::ExternalId => "externalId",
+            PrincipalField::LastLogin => "lastLogin",
+            PrincipalField::ForwardTo => "forwardTo",
+            PrincipalField::CatchAll => "catchAll",
+            PrincipalField::Subaddressing => "subaddressing",
+            PrincipalField::DefaultRoles => "defaultRoles",
+            PrincipalField::DefaultPermissions => "defaultPermissions",
+            PrincipalField::CertificateFingerprints => "certificateFingerprints",
+            PrincipalField::PostingPolicy => "postingPolicy",
+            PrincipalField::Moderators => "moderators",
+            PrincipalField::ReplyToList => "replyToList",
+            PrincipalField::Locale => "locale",
+            PrincipalField::Timezone => "timezone",
+            PrincipalField::CredentialsEpoch => "credentialsEpoch",
         }
     }
 
@@ -560,6 +777,22 @@ impl PrincipalField {
             "picture" => Some(PrincipalField::Picture),
             "urls" => Some(PrincipalField::Urls),
             "externalMembers" => Some(PrincipalField::ExternalMembers),
+            "enabled" => Some(PrincipalField::Enabled),
+            "hold" => Some(PrincipalField::Hold),
+            "externalId" => Some(PrincipalField::ExternalId),
+            "lastLogin" => Some(PrincipalField::LastLogin),
+            "forwardTo" => Some(PrincipalField::ForwardTo),
+            "catchAll" => Some(PrincipalField::CatchAll),
+            "subaddressing" => Some(PrincipalField::Subaddressing),
+            "defaultRoles" => Some(PrincipalField::DefaultRoles),
+            "defaultPermissions" => Some(PrincipalField::DefaultPermissions),
+            "certificateFingerprints" => Some(PrincipalField::CertificateFingerprints),
+            "postingPolicy" => Some(PrincipalField::PostingPolicy),
+            "moderators" => Some(PrincipalField::Moderators),
+            "replyToList" => Some(PrincipalField::ReplyToList),
+            "locale" => Some(PrincipalField::Locale),
+            "timezone" => Some(PrincipalField::Timezone),
+            "credentialsEpoch" => Some(PrincipalField::CredentialsEpoch),
             _ => None,
         }
     }
@@ -574,10 +807,95 @@ fn deserialize_string(bytes: &mut Iter<'_, u8>) -> Option<String> {
     String::from_utf8(string).ok()
 }
 
+/// Protocols an app password can be limited to. App passwords are stored as
+/// `$app$<name>$<secret>`, or as `$app$<name>[<scope>,...]$<secret>` when
+/// they are only valid for some protocols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppScope {
+    Imap,
+    Pop3,
+    Smtp,
+    Sieve,
+    Http,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPassword<'x> {
+    pub name: &'x str,
+    pub scopes: Option<Vec<AppScope>>,
+    pub secret: &'x str,
+}
+
+impl AppScope {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "imap" => Some(AppScope::Imap),
+            "pop3" => Some(AppScope::Pop3),
+            "smtp" => Some(AppScope::Smtp),
+            "sieve" => Some(AppScope::Sieve),
+            "http" => Some(AppScope::Http),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppScope::Imap => "imap",
+            AppScope::Pop3 => "pop3",
+            AppScope::Smtp => "smtp",
+            AppScope::Sieve => "sieve",
+            AppScope::Http => "http",
+        }
+    }
+}
+
+impl<'x> AppPassword<'x> {
+    pub fn new(name: &'x str, scopes: Option<Vec<AppScope>>, secret: &'x str) -> Self {
+        AppPassword {
+            name,
+            scopes,
+            secret,
+        }
+    }
+
+    /// App passwords without scopes are valid for every protocol.
+    pub fn allows(&self, scope: AppScope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty() && !name.contains(['$', '[', ']'])
+    }
+
+    pub fn build(&self) -> String {
+        if let Some(scopes) = &self.scopes {
+            format!(
+                "$app${}[{}]${}",
+                self.name,
+                scopes
+                    .iter()
+                    .map(|scope| scope.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                self.secret
+            )
+        } else {
+            format!("$app${}${}", self.name, self.secret)
+        }
+    }
+}
+
+pub const RECOVERY_CODE_PREFIX: &str = "$recovery$";
+
 pub trait SpecialSecrets {
     fn is_otp_auth(&self) -> bool;
     fn is_app_password(&self) -> bool;
+    fn is_recovery_code(&self) -> bool;
     fn is_password(&self) -> bool;
+    fn app_password(&self) -> Option<AppPassword<'_>>;
+    fn app_password_name(&self) -> Option<&str>;
 }
 
 impl<T> SpecialSecrets for T
@@ -592,7 +910,41 @@ where
         self.as_ref().starts_with("$app$")
     }
 
+    /// Hashes of one-time recovery codes are stored as `$recovery$<hash>`.
+    fn is_recovery_code(&self) -> bool {
+        self.as_ref().starts_with(RECOVERY_CODE_PREFIX)
+    }
+
     fn is_password(&self) -> bool {
-        !self.is_otp_auth() && !self.is_app_password()
+        !self.is_otp_auth() && !self.is_app_password() && !self.is_recovery_code()
+    }
+
+    fn app_password(&self) -> Option<AppPassword<'_>> {
+        let (label, secret) = self.as_ref().strip_prefix("$app$")?.split_once('$')?;
+        let (name, scopes) = match label
+            .strip_suffix(']')
+            .and_then(|label| label.split_once('['))
+        {
+            // Unknown scopes are ignored, they grant no access
+            Some((name, scopes)) => (
+                name,
+                Some(scopes.split(',').filter_map(AppScope::parse).collect()),
+            ),
+            None => (label, None),
+        };
+
+        Some(AppPassword {
+            name,
+            scopes,
+            secret,
+        })
+    }
+
+    /// Returns the name of an app password, also accepts the `$app$<name>`
+    /// form used to remove app passwords by label.
+    fn app_password_name(&self) -> Option<&str> {
+        let label = self.as_ref().strip_prefix("$app$")?;
+        let label = label.split_once('$').map_or(label, |(label, _)| label);
+        Some(label.split_once('[').map_or(label, |(name, _)| name))
     }
 }
@@ -0,0 +1,265 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashSet;
+use store::Store;
+use trc::AddContext;
+
+use crate::{
+    Permission, Permissions, Principal, QueryBy, Type, ROLE_ADMIN, ROLE_TENANT_ADMIN, ROLE_USER,
+};
+
+use super::{
+    delegation::DomainDelegationStore, lookup::DirectoryStore, manage::ManageDirectory,
+    PrincipalField,
+};
+
+/// Computed permissions of a principal together with the origin of every grant
+/// and revocation.
+///
+/// Permissions are resolved in the same order used when building access tokens:
+///
+/// 1. Roles assigned to the principal, roles inherited through nested groups and
+///    the parent roles of custom roles are collected recursively.
+/// 2. The enabled and disabled permissions of every role and of the principal
+///    itself are merged. A permission disabled by any source is revoked, even
+///    when another source enables it.
+/// 3. Disabled principals lose the `authenticate` permission.
+/// 4. Domain delegations grant principal management permissions on the members
+///    of a domain only, they are reported but do not make a permission effective.
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct EffectivePermissions {
+    pub permissions: Vec<Permission>,
+    pub sources: Vec<PermissionSources>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PermissionSources {
+    pub permission: Permission,
+    pub effective: bool,
+    pub granted_by: Vec<PermissionSource>,
+    pub revoked_by: Vec<PermissionSource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionSource {
+    Role { name: String },
+    EnabledPermissions,
+    DisabledPermissions,
+    AccountDisabled,
+    Tenant { name: String },
+    Delegation { domain: String },
+}
+
+#[allow(async_fn_in_trait)]
+pub trait PermissionResolver: Sync + Send {
+    async fn get_effective_permissions(
+        &self,
+        principal_id: u32,
+        max_depth: u32,
+    ) -> trc::Result<Option<EffectivePermissions>>;
+}
+
+impl PermissionResolver for Store {
+    async fn get_effective_permissions(
+        &self,
+        principal_id: u32,
+        max_depth: u32,
+    ) -> trc::Result<Option<EffectivePermissions>> {
+        let Some(principal) = self
+            .query(QueryBy::Id(principal_id), false)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(None);
+        };
+        let mut sources = SourceMap::default();
+
+        // Collect direct and inherited roles
+        let mut role_ids = principal
+            .iter_int(PrincipalField::Roles)
+            .map(|id| id as u32)
+            .collect::<Vec<_>>();
+        for member_of in self
+            .get_member_of_recursive(principal_id, max_depth)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if member_of.typ == Type::Role {
+                role_ids.push(member_of.principal_id);
+            }
+        }
+
+        // Walk the role hierarchy
+        let mut fetched_role_ids = AHashSet::new();
+        while let Some(role_id) = role_ids.pop() {
+            if !fetched_role_ids.insert(role_id) {
+                continue;
+            }
+
+            match role_id {
+                ROLE_ADMIN | ROLE_TENANT_ADMIN | ROLE_USER => {
+                    let source = PermissionSource::Role {
+                        name: builtin_role_name(role_id).to_string(),
+                    };
+                    for permission in (0..Permission::COUNT).filter_map(Permission::from_id) {
+                        let is_granted = match role_id {
+                            ROLE_ADMIN => true,
+                            ROLE_TENANT_ADMIN => permission.is_tenant_admin_permission(),
+                            _ => permission.is_user_permission(),
+                        };
+                        if is_granted {
+                            sources.grant(permission, source.clone());
+                        }
+                    }
+                }
+                role_id => {
+                    let Some(role) = self
+                        .query(QueryBy::Id(role_id), true)
+                        .await
+                        .caused_by(trc::location!())?
+                    else {
+                        continue;
+                    };
+                    let source = PermissionSource::Role {
+                        name: role.name().to_string(),
+                    };
+                    sources.apply(&role, source.clone(), source);
+                    role_ids.extend(role.iter_int(PrincipalField::Roles).map(|id| id as u32));
+                }
+            }
+        }
+
+        // Principal permissions
+        sources.apply(
+            &principal,
+            PermissionSource::EnabledPermissions,
+            PermissionSource::DisabledPermissions,
+        );
+        if !principal.is_enabled() {
+            sources.revoke(Permission::Authenticate, PermissionSource::AccountDisabled);
+        }
+
+        // Domain delegations
+        for delegation in self
+            .get_delegations(principal_id)
+            .await
+            .caused_by(trc::location!())?
+        {
+            if let Some(domain) = self
+                .get_principal(delegation.domain_id)
+                .await
+                .caused_by(trc::location!())?
+            {
+                for permission in delegation.permissions {
+                    sources.grant(
+                        permission,
+                        PermissionSource::Delegation {
+                            domain: domain.name().to_string(),
+                        },
+                    );
+                }
+            }
+        }
+
+        Ok(Some(sources.build()))
+    }
+}
+
+impl EffectivePermissions {
+    /// Revokes the permissions not present in `allowed`, used to apply the
+    /// limits of the tenant the principal belongs to.
+    pub fn restrict(&mut self, allowed: &Permissions, source: PermissionSource) {
+        for item in &mut self.sources {
+            if item.effective && !allowed.get(item.permission.id()) {
+                item.effective = false;
+                item.revoked_by.push(source.clone());
+            }
+        }
+        self.permissions
+            .retain(|permission| allowed.get(permission.id()));
+    }
+}
+
+#[derive(Default)]
+struct SourceMap {
+    sources: Vec<Option<PermissionSources>>,
+}
+
+impl SourceMap {
+    fn entry(&mut self, permission: Permission) -> &mut PermissionSources {
+        if self.sources.is_empty() {
+            self.sources.resize(Permission::COUNT, None);
+        }
+        self.sources[permission.id()].get_or_insert_with(|| PermissionSources {
+            permission,
+            effective: false,
+            granted_by: Vec::new(),
+            revoked_by: Vec::new(),
+        })
+    }
+
+    fn grant(&mut self, permission: Permission, source: PermissionSource) {
+        let entry = self.entry(permission);
+        if !entry.granted_by.contains(&source) {
+            entry.granted_by.push(source);
+        }
+    }
+
+    fn revoke(&mut self, permission: Permission, source: PermissionSource) {
+        let entry = self.entry(permission);
+        if !entry.revoked_by.contains(&source) {
+            entry.revoked_by.push(source);
+        }
+    }
+
+    fn apply(
+        &mut self,
+        principal: &Principal,
+        enabled: PermissionSource,
+        disabled: PermissionSource,
+    ) {
+        for permission in principal
+            .iter_int(PrincipalField::EnabledPermissions)
+            .filter_map(|id| Permission::from_id(id as usize))
+        {
+            self.grant(permission, enabled.clone());
+        }
+        for permission in principal
+            .iter_int(PrincipalField::DisabledPermissions)
+            .filter_map(|id| Permission::from_id(id as usize))
+        {
+            self.revoke(permission, disabled.clone());
+        }
+    }
+
+    fn build(self) -> EffectivePermissions {
+        let mut result = EffectivePermissions::default();
+        for mut item in self.sources.into_iter().flatten() {
+            item.effective = item.revoked_by.is_empty()
+                && item
+                    .granted_by
+                    .iter()
+                    .any(|source| !matches!(source, PermissionSource::Delegation { .. }));
+            if item.effective {
+                result.permissions.push(item.permission);
+            }
+            result.sources.push(item);
+        }
+        result
+    }
+}
+
+fn builtin_role_name(role_id: u32) -> &'static str {
+    match role_id {
+        ROLE_ADMIN => "admin",
+        ROLE_TENANT_ADMIN => "tenant-admin",
+        _ => "user",
+    }
+}
@@ -7,15 +7,24 @@
 use std::time::Duration;
 
 use ldap3::LdapConnSettings;
+use parking_lot::Mutex;
 use store::Store;
 use utils::config::{utils::AsKey, Config};
 
-use crate::core::config::build_pool;
+use crate::{backend::internal::manage::CreateRetry, core::config::build_pool};
 
-use super::{AuthBind, Bind, LdapConnectionManager, LdapDirectory, LdapFilter, LdapMappings};
+use super::{
+    AuthBind, Bind, LdapConnectionManager, LdapDirectory, LdapFilter, LdapMappings, LdapWrite,
+    NestedGroups,
+};
 
 impl LdapDirectory {
-    pub fn from_config(config: &mut Config, prefix: impl AsKey, data_store: Store) -> Option<Self> {
+    pub fn from_config(
+        config: &mut Config,
+        prefix: impl AsKey,
+        data_store: Store,
+        create_retry: CreateRetry,
+    ) -> Option<Self> {
         let prefix = prefix.as_key();
         let bind_dn = if let Some(dn) = config.value((&prefix, "bind.dn")) {
             Bind::new(
@@ -52,6 +61,7 @@ impl LdapDirectory {
             base_dn: config.value_require((&prefix, "base-dn"))?.to_string(),
             filter_name: LdapFilter::from_config(config, (&prefix, "filter.name")),
             filter_email: LdapFilter::from_config(config, (&prefix, "filter.email")),
+            filter_sync: String::new(),
             attr_name: config
                 .values((&prefix, "attributes.name"))
                 .map(|(_, v)| v.to_string())
@@ -85,8 +95,15 @@ impl LdapDirectory {
                 .map(|(_, v)| v.to_string())
                 .collect(),
             attrs_principal: vec!["objectClass".to_string()],
+            attrs_group: Vec::new(),
         };
 
+        // Synchronization enumerates every entry matching the name filter by default
+        mappings.filter_sync = config
+            .value((&prefix, "sync.filter"))
+            .map(|v| v.to_string())
+            .unwrap_or_else(|| mappings.filter_name.filter.join("*"));
+
         for attr in [
             &mappings.attr_name,
             &mappings.attr_type,
@@ -99,6 +116,9 @@ impl LdapDirectory {
         ] {
             mappings.attrs_principal.extend(attr.iter().cloned());
         }
+        for attr in [&mappings.attr_name, &mappings.attr_groups] {
+            mappings.attrs_group.extend(attr.iter().cloned());
+        }
 
         let auth_bind = if config
             .property_or_default::<bool>((&prefix, "bind.auth.enable"), "false")
@@ -113,6 +133,77 @@ impl LdapDirectory {
             None
         };
 
+        let nested_groups = if config
+            .property_or_default::<bool>((&prefix, "groups.nested.enable"), "false")
+            .unwrap_or_default()
+        {
+            let in_chain = match config
+                .value((&prefix, "groups.nested.method"))
+                .unwrap_or("iterative")
+            {
+                "iterative" => false,
+                "in-chain" => true,
+                method => {
+                    let err = format!("Invalid nested group resolution method {method:?}");
+                    config.new_parse_error((&prefix, "groups.nested.method"), err);
+                    false
+                }
+            };
+
+            Some(NestedGroups {
+                max_depth: config
+                    .property_or_default((&prefix, "groups.nested.max-depth"), "8")
+                    .unwrap_or(8),
+                in_chain,
+                ttl: config
+                    .property_or_default((&prefix, "groups.nested.cache.ttl"), "5m")
+                    .unwrap_or_else(|| Duration::from_secs(300)),
+                cache: Mutex::new(lru_cache::LruCache::with_hasher(
+                    config
+                        .property_or_default((&prefix, "groups.nested.cache.size"), "1024")
+                        .unwrap_or(1024),
+                    ahash::RandomState::new(),
+                )),
+            })
+        } else {
+            None
+        };
+
+        let write = if config
+            .property_or_default::<bool>((&prefix, "write.enable"), "false")
+            .unwrap_or_default()
+        {
+            let bind = if let Some(dn) = config.value((&prefix, "write.bind.dn")) {
+                Bind::new(
+                    dn.to_string(),
+                    config
+                        .value_require((&prefix, "write.bind.secret"))?
+                        .to_string(),
+                )
+                .into()
+            } else {
+                None
+            };
+
+            // Writes go to the first attribute used for lookups unless configured
+            let write_attribute = |key: &str, attrs: &[String]| {
+                config
+                    .value((prefix.as_str(), "write.attributes", key))
+                    .map(|v| v.to_string())
+                    .or_else(|| attrs.first().cloned())
+            };
+
+            Some(LdapWrite {
+                bind,
+                attr_secret: write_attribute("secret", &mappings.attr_secret),
+                attr_description: write_attribute("description", &mappings.attr_description),
+                attr_email_address: write_attribute("email", &mappings.attr_email_address),
+                attr_email_alias: write_attribute("email-alias", &mappings.attr_email_alias),
+            })
+        } else {
+            None
+        };
+
         Some(LdapDirectory {
             mappings,
             pool: build_pool(config, &prefix, manager)
@@ -121,7 +212,10 @@ impl LdapDirectory {
                 })
                 .ok()?,
             auth_bind,
+            nested_groups,
+            write,
             data_store,
+            create_retry,
         })
     }
 }
@@ -4,7 +4,10 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use ldap3::{Ldap, LdapConnAsync, ResultEntry, Scope, SearchEntry};
+use std::collections::VecDeque;
+
+use ahash::{AHashMap, AHashSet};
+use ldap3::{ldap_escape, Ldap, LdapConnAsync, ResultEntry, Scope, SearchEntry};
 use mail_send::Credentials;
 use trc::AddContext;
 
@@ -13,14 +16,17 @@ use crate::{
         internal::{
             lookup::DirectoryStore,
             manage::{self, ManageDirectory, UpdatePrincipal},
-            PrincipalField,
+            PrincipalField, PrincipalUpdate, PrincipalValue,
         },
         RcptType,
     },
     IntoError, Principal, QueryBy, Type, ROLE_ADMIN, ROLE_USER,
 };
 
-use super::{LdapDirectory, LdapMappings};
+use super::{CachedGroup, LdapDirectory, LdapMappings, NestedGroups};
+
+// Active Directory matching rule that walks the whole membership chain
+const LDAP_MATCHING_RULE_IN_CHAIN: &str = "1.2.840.113556.1.4.1941";
 
 impl LdapDirectory {
     pub async fn query(
@@ -44,11 +50,8 @@ impl LdapDirectory {
                     return Ok(None);
                 }
             }
-            QueryBy::Id(uid) => {
-                if let Some(stored_principal_) = self
-                    .data_store
-                    .query(QueryBy::Id(uid), return_member_of)
-                    .await?
+            by @ (QueryBy::Id(_) | QueryBy::ExternalId(_) | QueryBy::Certificate(_)) => {
+                if let Some(stored_principal_) = self.data_store.query(by, return_member_of).await?
                 {
                     if let Some(principal) = self
                         .find_principal(
@@ -139,6 +142,11 @@ impl LdapDirectory {
 
         // Query groups
         match external_principal.take_str_array(PrincipalField::MemberOf) {
+            Some(names) if return_member_of && self.nested_groups.is_some() => {
+                let nested = self.nested_groups.as_ref().unwrap();
+                let member_of = self.resolve_nested_groups(&mut conn, nested, names).await?;
+                external_principal.set(PrincipalField::MemberOf, member_of);
+            }
             Some(names) if return_member_of => {
                 let mut member_of = Vec::with_capacity(names.len());
                 for mut name in names {
@@ -170,7 +178,7 @@ impl LdapDirectory {
 
                     member_of.push(
                         self.data_store
-                            .get_or_create_principal_id(&name, Type::Group)
+                            .get_or_create_principal_id(&name, Type::Group, self.create_retry)
                             .await
                             .caused_by(trc::location!())?,
                     );
@@ -188,7 +196,11 @@ impl LdapDirectory {
         } else {
             let id = self
                 .data_store
-                .get_or_create_principal_id(external_principal.name(), Type::Individual)
+                .get_or_create_principal_id(
+                    external_principal.name(),
+                    Type::Individual,
+                    self.create_retry,
+                )
                 .await
                 .caused_by(trc::location!())?;
 
@@ -247,7 +259,7 @@ impl LdapDirectory {
                     if !name.is_empty() {
                         return self
                             .data_store
-                            .get_or_create_principal_id(name, Type::Individual)
+                            .get_or_create_principal_id(name, Type::Individual, self.create_retry)
                             .await
                             .map(Some);
                     }
@@ -296,7 +308,7 @@ impl LdapDirectory {
             Ok(result)
         } else {
             self.data_store.rcpt(address).await.map(|result| {
-                if matches!(result, RcptType::List(_)) {
+                if matches!(result, RcptType::List(_) | RcptType::Alias(_)) {
                     result
                 } else {
                     RcptType::Invalid
@@ -316,6 +328,47 @@ impl LdapDirectory {
     pub async fn is_local_domain(&self, domain: &str) -> trc::Result<bool> {
         self.data_store.is_local_domain(domain).await
     }
+
+    pub async fn list_principals(&self) -> trc::Result<Vec<Principal>> {
+        let filter = &self.mappings.filter_sync;
+        let rs = self
+            .pool
+            .get()
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .search(
+                &self.mappings.base_dn,
+                Scope::Subtree,
+                filter,
+                &self.mappings.attrs_principal,
+            )
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map(|(rs, _res)| rs)
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+
+        trc::event!(
+            Store(trc::StoreEvent::LdapQuery),
+            Details = filter.clone(),
+            Total = rs.len()
+        );
+
+        Ok(rs
+            .into_iter()
+            .filter_map(|entry| {
+                let mut principal = self
+                    .mappings
+                    .entry_to_principal(SearchEntry::construct(entry));
+
+                // Secrets and memberships are resolved at login time
+                principal.remove(PrincipalField::Secrets);
+                principal.remove(PrincipalField::MemberOf);
+
+                Some(principal).filter(|p| !p.name().is_empty())
+            })
+            .collect())
+    }
 }
 
 impl LdapDirectory {
@@ -349,7 +402,215 @@ impl LdapDirectory {
     }
 }
 
+impl LdapDirectory {
+    async fn resolve_nested_groups(
+        &self,
+        conn: &mut Ldap,
+        nested: &NestedGroups,
+        names: Vec<String>,
+    ) -> trc::Result<Vec<u32>> {
+        let mut member_of = Vec::with_capacity(names.len());
+        let mut resolved: AHashMap<String, (u32, String)> = AHashMap::new();
+        let mut fetched = AHashSet::new();
+        let mut expanded = Vec::new();
+        let mut queue = names
+            .into_iter()
+            .map(|name| (name, 1))
+            .collect::<VecDeque<_>>();
+
+        // Breadth-first walk over the group hierarchy, visiting each group once
+        while let Some((dn, depth)) = queue.pop_front() {
+            if resolved.contains_key(&dn) {
+                continue;
+            }
+
+            // Plain group names cannot be looked up and end the chain
+            let group = if !dn.contains('=') {
+                CachedGroup::leaf(dn.clone())
+            } else if let Some(group) = nested.get(&dn) {
+                group
+            } else {
+                self.fetch_group(conn, nested, &dn, &mut fetched).await?
+            };
+
+            let group_id = self
+                .data_store
+                .get_or_create_principal_id(&group.name, Type::Group, self.create_retry)
+                .await
+                .caused_by(trc::location!())?;
+            member_of.push(group_id);
+
+            if depth < nested.max_depth {
+                queue.extend(
+                    group
+                        .parents
+                        .iter()
+                        .map(|parent| (parent.clone(), depth + 1)),
+                );
+                if fetched.contains(&dn) {
+                    expanded.push((group_id, group.parents.clone()));
+                }
+            }
+            resolved.insert(dn, (group_id, group.name));
+        }
+
+        // Mirror the hierarchy of the groups fetched from the server
+        for (group_id, parents) in expanded {
+            let parents = parents
+                .iter()
+                .filter_map(|dn| resolved.get(dn))
+                .collect::<Vec<_>>();
+            if let Err(err) = self.mirror_group_parents(group_id, &parents).await {
+                trc::error!(err
+                    .caused_by(trc::location!())
+                    .details("Failed to mirror LDAP group membership")
+                    .id(group_id));
+            }
+        }
+
+        Ok(member_of)
+    }
+
+    async fn fetch_group(
+        &self,
+        conn: &mut Ldap,
+        nested: &NestedGroups,
+        dn: &str,
+        fetched: &mut AHashSet<String>,
+    ) -> trc::Result<CachedGroup> {
+        // Active Directory returns every ancestor of the group in a single query
+        if nested.in_chain {
+            let filter = format!(
+                "(member:{LDAP_MATCHING_RULE_IN_CHAIN}:={})",
+                ldap_escape(dn)
+            );
+            for entry in self
+                .search_groups(conn, &self.mappings.base_dn, Scope::Subtree, &filter)
+                .await?
+            {
+                let (name, parents) = self.mappings.entry_to_group(&entry);
+                if !name.is_empty() {
+                    nested.insert(entry.dn.clone(), name, parents);
+                    fetched.insert(entry.dn);
+                }
+            }
+        }
+
+        let (name, parents) = self
+            .search_groups(conn, dn, Scope::Base, "objectClass=*")
+            .await?
+            .first()
+            .map(|entry| self.mappings.entry_to_group(entry))
+            .unwrap_or_default();
+        fetched.insert(dn.to_string());
+
+        Ok(nested.insert(
+            dn.to_string(),
+            if !name.is_empty() {
+                name
+            } else {
+                dn.to_string()
+            },
+            parents,
+        ))
+    }
+
+    async fn search_groups(
+        &self,
+        conn: &mut Ldap,
+        base: &str,
+        scope: Scope,
+        filter: &str,
+    ) -> trc::Result<Vec<SearchEntry>> {
+        let (rs, _res) = conn
+            .search(base, scope, filter, &self.mappings.attrs_group)
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+
+        trc::event!(
+            Store(trc::StoreEvent::LdapQuery),
+            Details = filter.to_string(),
+            Total = rs.len()
+        );
+
+        Ok(rs.into_iter().map(SearchEntry::construct).collect())
+    }
+
+    async fn mirror_group_parents(
+        &self,
+        group_id: u32,
+        parents: &[&(u32, String)],
+    ) -> trc::Result<()> {
+        let current = self
+            .data_store
+            .get_member_of(group_id)
+            .await
+            .caused_by(trc::location!())?
+            .into_iter()
+            .filter(|member| member.typ == Type::Group)
+            .map(|member| member.principal_id)
+            .collect::<Vec<_>>();
+
+        let mut changes = Vec::new();
+        for (parent_id, name) in parents.iter().copied() {
+            if *parent_id != group_id && !current.contains(parent_id) {
+                changes.push(PrincipalUpdate::add_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String(name.clone()),
+                ));
+            }
+        }
+        for member_id in current {
+            if !parents.iter().any(|(parent_id, _)| *parent_id == member_id) {
+                if let Some(mut principal) = self
+                    .data_store
+                    .get_principal(member_id)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    if let Some(name) = principal.take_str(PrincipalField::Name) {
+                        changes.push(PrincipalUpdate::remove_item(
+                            PrincipalField::MemberOf,
+                            PrincipalValue::String(name),
+                        ));
+                    }
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            self.data_store
+                .update_principal(UpdatePrincipal::by_id(group_id).with_updates(changes))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+}
+
 impl LdapMappings {
+    fn entry_to_group(&self, entry: &SearchEntry) -> (String, Vec<String>) {
+        let mut name = String::new();
+        let mut parents = Vec::new();
+
+        for (attr, value) in &entry.attrs {
+            if self.attr_name.contains(attr) {
+                if name.is_empty() {
+                    if let Some(group) = value.iter().find(|v| !v.is_empty()) {
+                        name = group.clone();
+                    }
+                }
+            } else if self.attr_groups.contains(attr) {
+                parents.extend(value.iter().cloned());
+            }
+        }
+
+        (name, parents)
+    }
+
     fn entry_to_principal(&self, entry: SearchEntry) -> Principal {
         let mut principal = Principal::default();
         let mut role = ROLE_USER;
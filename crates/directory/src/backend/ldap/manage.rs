@@ -0,0 +1,273 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ldap3::{Ldap, LdapConnAsync, Mod, Scope, SearchEntry};
+use trc::AddContext;
+
+use crate::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{self, ErrorCode, ManageDirectory, UpdatePrincipal},
+        PrincipalAction, PrincipalField, PrincipalValue, SpecialSecrets,
+    },
+    IntoError, QueryBy,
+};
+
+use super::{LdapDirectory, LdapWrite};
+
+impl LdapDirectory {
+    /// LDAP directories can be updated when write support is enabled.
+    pub fn is_writable(&self) -> bool {
+        self.write.is_some()
+    }
+
+    /// Applies the changes to the LDAP entry of the principal before storing
+    /// them internally. Fields without an LDAP attribute are only stored in the
+    /// internal store, which is left untouched when the LDAP server rejects
+    /// the changes. The LDAP entry is restored if the internal update fails.
+    pub async fn update_principal(
+        &self,
+        principal_id: u32,
+        update: UpdatePrincipal<'_>,
+    ) -> trc::Result<()> {
+        let write = self
+            .write
+            .as_ref()
+            .ok_or_else(|| manage::unsupported("LDAP directory is read-only"))?;
+        let principal = self
+            .data_store
+            .query(QueryBy::Id(principal_id), false)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| manage::err_not_found(ErrorCode::PrincipalNotFound, principal_id))?;
+
+        // Obtain the values stored in LDAP once all changes are applied
+        let mut secret = None;
+        let mut description = None;
+        let mut emails = None;
+        for change in update.changes() {
+            match (&change.action, change.field, &change.value) {
+                (PrincipalAction::Set, PrincipalField::Secrets, value) => {
+                    secret = Some(value.iter_str().find(|s| s.is_password()).cloned());
+                }
+                (PrincipalAction::AddItem, PrincipalField::Secrets, PrincipalValue::String(s))
+                    if s.is_password() =>
+                {
+                    secret = Some(Some(s.clone()));
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::Secrets,
+                    PrincipalValue::String(s),
+                ) if s.is_password() => {
+                    secret = Some(None);
+                }
+                (PrincipalAction::Set, PrincipalField::Description, value) => {
+                    description = Some(value.iter_str().next().filter(|v| !v.is_empty()).cloned());
+                }
+                (action, PrincipalField::Emails, value) => {
+                    let emails = emails.get_or_insert_with(|| {
+                        principal
+                            .get_str_array(PrincipalField::Emails)
+                            .unwrap_or_default()
+                            .to_vec()
+                    });
+                    match action {
+                        PrincipalAction::Set => {
+                            emails.clear();
+                            emails.extend(value.iter_str().map(|email| email.to_lowercase()));
+                        }
+                        PrincipalAction::AddItem => {
+                            for email in value.iter_str() {
+                                let email = email.to_lowercase();
+                                if !emails.contains(&email) {
+                                    emails.push(email);
+                                }
+                            }
+                        }
+                        PrincipalAction::RemoveItem => {
+                            for email in value.iter_str() {
+                                let email = email.to_lowercase();
+                                emails.retain(|v| *v != email);
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // Build the list of attributes to replace
+        let mut attributes: Vec<(String, Vec<String>)> = Vec::new();
+        if let Some(secret) = secret {
+            attributes.push((
+                write_attribute(&write.attr_secret, PrincipalField::Secrets)?,
+                secret.into_iter().collect(),
+            ));
+        }
+        if let Some(description) = description {
+            attributes.push((
+                write_attribute(&write.attr_description, PrincipalField::Description)?,
+                description.into_iter().collect(),
+            ));
+        }
+        if let Some(mut emails) = emails {
+            // Make sure the addresses are available before modifying the entry
+            for email in &emails {
+                if self
+                    .data_store
+                    .email_to_id(email)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some_and(|id| id != principal_id)
+                {
+                    return Err(manage::err_exists(PrincipalField::Emails, email.clone()));
+                }
+            }
+
+            match (&write.attr_email_address, &write.attr_email_alias) {
+                (Some(attr_address), Some(attr_alias)) if attr_address != attr_alias => {
+                    let aliases = if emails.len() > 1 {
+                        emails.split_off(1)
+                    } else {
+                        Vec::new()
+                    };
+                    attributes.push((attr_address.clone(), emails));
+                    attributes.push((attr_alias.clone(), aliases));
+                }
+                (Some(attr), _) | (None, Some(attr)) => {
+                    attributes.push((attr.clone(), emails));
+                }
+                (None, None) => {
+                    return Err(unsupported_field(PrincipalField::Emails));
+                }
+            }
+        }
+
+        if attributes.is_empty() {
+            return self
+                .data_store
+                .update_principal(update.create_domains())
+                .await
+                .caused_by(trc::location!());
+        }
+
+        // Obtain the current values, used to restore the entry on failure
+        let mut conn = self.write_connection(write).await?;
+        let filter = self.mappings.filter_name.build(principal.name());
+        let (rs, _) = conn
+            .search(
+                &self.mappings.base_dn,
+                Scope::Subtree,
+                &filter,
+                attributes
+                    .iter()
+                    .map(|(attr, _)| attr.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .await
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?
+            .success()
+            .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+        let entry = rs
+            .into_iter()
+            .next()
+            .map(SearchEntry::construct)
+            .ok_or_else(|| {
+                manage::err_not_found(ErrorCode::PrincipalNotFound, principal.name().to_string())
+            })?;
+        let previous = attributes
+            .iter()
+            .filter_map(|(attr, _)| match entry.attrs.get(attr) {
+                Some(values) => Some((attr.clone(), values.clone())),
+                // Passwords are usually not readable, keep them as they are
+                None if write.attr_secret.as_ref() == Some(attr) => None,
+                None => Some((attr.clone(), Vec::new())),
+            })
+            .collect::<Vec<_>>();
+
+        // Modify the entry
+        trc::event!(
+            Store(trc::StoreEvent::LdapModify),
+            Details = entry.dn.clone(),
+            Key = attributes
+                .iter()
+                .map(|(attr, _)| trc::Value::String(attr.clone()))
+                .collect::<Vec<_>>(),
+        );
+        modify_entry(&mut conn, &entry.dn, attributes).await?;
+
+        // Keep the internal store up to date with the LDAP server
+        let result = self
+            .data_store
+            .update_principal(update.create_domains())
+            .await
+            .caused_by(trc::location!());
+        if result.is_err() {
+            if let Err(err) = modify_entry(&mut conn, &entry.dn, previous).await {
+                trc::error!(err
+                    .details("Failed to restore LDAP entry")
+                    .ctx(trc::Key::Id, entry.dn.clone()));
+            }
+        }
+        let _ = conn.unbind().await;
+
+        result
+    }
+
+    async fn write_connection(&self, write: &LdapWrite) -> trc::Result<Ldap> {
+        let manager = self.pool.manager();
+        let (conn, mut ldap) =
+            LdapConnAsync::with_settings(manager.settings.clone(), &manager.address)
+                .await
+                .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+
+        ldap3::drive!(conn);
+
+        if let Some(bind) = write.bind.as_ref().or(manager.bind_dn.as_ref()) {
+            trc::event!(Store(trc::StoreEvent::LdapBind), Details = bind.dn.clone());
+
+            ldap.simple_bind(&bind.dn, &bind.password)
+                .await
+                .map_err(|err| err.into_error().caused_by(trc::location!()))?
+                .success()
+                .map_err(|err| err.into_error().caused_by(trc::location!()))?;
+        }
+
+        Ok(ldap)
+    }
+}
+
+async fn modify_entry(
+    conn: &mut Ldap,
+    dn: &str,
+    attributes: Vec<(String, Vec<String>)>,
+) -> trc::Result<()> {
+    conn.modify(
+        dn,
+        attributes
+            .into_iter()
+            .map(|(attr, values)| Mod::Replace(attr, values.into_iter().collect()))
+            .collect(),
+    )
+    .await
+    .map_err(|err| err.into_error().caused_by(trc::location!()))?
+    .success()
+    .map(|_| ())
+    .map_err(|err| err.into_error().caused_by(trc::location!()))
+}
+
+fn write_attribute(attr: &Option<String>, field: PrincipalField) -> trc::Result<String> {
+    attr.clone().ok_or_else(|| unsupported_field(field))
+}
+
+fn unsupported_field(field: PrincipalField) -> trc::Error {
+    manage::unsupported(format!(
+        "LDAP directory has no attribute mapped to the {} field",
+        field.as_str()
+    ))
+    .ctx(trc::Key::Key, field)
+}
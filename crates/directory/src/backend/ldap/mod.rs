@@ -4,19 +4,28 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::{Duration, Instant};
+
 use deadpool::managed::Pool;
 use ldap3::{ldap_escape, LdapConnSettings};
+use parking_lot::Mutex;
 use store::Store;
 
+use super::internal::manage::CreateRetry;
+
 pub mod config;
 pub mod lookup;
+pub mod manage;
 pub mod pool;
 
 pub struct LdapDirectory {
     pool: Pool<LdapConnectionManager>,
     mappings: LdapMappings,
     auth_bind: Option<AuthBind>,
+    nested_groups: Option<NestedGroups>,
+    write: Option<LdapWrite>,
     pub(crate) data_store: Store,
+    pub(crate) create_retry: CreateRetry,
 }
 
 #[derive(Debug, Default)]
@@ -24,6 +33,7 @@ pub struct LdapMappings {
     base_dn: String,
     filter_name: LdapFilter,
     filter_email: LdapFilter,
+    filter_sync: String,
     attr_name: Vec<String>,
     attr_type: Vec<String>,
     attr_groups: Vec<String>,
@@ -33,6 +43,7 @@ pub struct LdapMappings {
     attr_email_alias: Vec<String>,
     attr_quota: Vec<String>,
     attrs_principal: Vec<String>,
+    attrs_group: Vec<String>,
 }
 
 #[derive(Debug, Default)]
@@ -78,3 +89,61 @@ pub(crate) struct AuthBind {
     filter: LdapFilter,
     search: bool,
 }
+
+// Attributes modified when principals are updated, along with the
+// credentials used to bind for writes when they differ from the lookup ones
+pub(crate) struct LdapWrite {
+    bind: Option<Bind>,
+    attr_secret: Option<String>,
+    attr_description: Option<String>,
+    attr_email_address: Option<String>,
+    attr_email_alias: Option<String>,
+}
+
+pub(crate) struct NestedGroups {
+    max_depth: usize,
+    in_chain: bool,
+    ttl: Duration,
+    cache: Mutex<lru_cache::LruCache<String, CachedGroup, ahash::RandomState>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CachedGroup {
+    name: String,
+    parents: Vec<String>,
+    valid_until: Instant,
+}
+
+impl CachedGroup {
+    pub fn leaf(name: String) -> Self {
+        CachedGroup {
+            name,
+            parents: Vec::new(),
+            valid_until: Instant::now(),
+        }
+    }
+}
+
+impl NestedGroups {
+    pub fn get(&self, dn: &str) -> Option<CachedGroup> {
+        let mut cache = self.cache.lock();
+        match cache.get_mut(dn) {
+            Some(group) if group.valid_until >= Instant::now() => Some(group.clone()),
+            Some(_) => {
+                cache.remove(dn);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, dn: String, name: String, parents: Vec<String>) -> CachedGroup {
+        let group = CachedGroup {
+            name,
+            parents,
+            valid_until: Instant::now() + self.ttl,
+        };
+        self.cache.lock().insert(dn, group.clone());
+        group
+    }
+}
@@ -8,7 +8,10 @@ use store::Store;
 use utils::config::{utils::AsKey, Config};
 
 use crate::{
-    backend::internal::{manage::ManageDirectory, PrincipalField},
+    backend::internal::{
+        manage::{CreateRetry, ManageDirectory},
+        PrincipalField,
+    },
     Principal, Type, ROLE_ADMIN, ROLE_USER,
 };
 
@@ -19,6 +22,7 @@ impl MemoryDirectory {
         config: &mut Config,
         prefix: impl AsKey,
         data_store: Store,
+        create_retry: CreateRetry,
     ) -> Option<Self> {
         let prefix = prefix.as_key();
         let mut directory = MemoryDirectory {
@@ -48,7 +52,7 @@ impl MemoryDirectory {
             // Obtain id
             let id = directory
                 .data_store
-                .get_or_create_principal_id(&name, Type::Individual)
+                .get_or_create_principal_id(&name, Type::Individual, create_retry)
                 .await
                 .map_err(|err| {
                     config.new_build_error(
@@ -82,7 +86,7 @@ impl MemoryDirectory {
                     PrincipalField::MemberOf,
                     directory
                         .data_store
-                        .get_or_create_principal_id(&group, Type::Group)
+                        .get_or_create_principal_id(&group, Type::Group, create_retry)
                         .await
                         .map_err(|err| {
                             config.new_build_error(
@@ -30,6 +30,27 @@ impl MemoryDirectory {
                     }
                 }
             }
+            QueryBy::ExternalId(external_id) => {
+                for principal in &self.principals {
+                    if principal.get_str(PrincipalField::ExternalId) == Some(external_id) {
+                        return Ok(Some(principal.clone()));
+                    }
+                }
+            }
+            QueryBy::Certificate(certificate) => {
+                for principal in &self.principals {
+                    if principal.has_str_value(
+                        PrincipalField::CertificateFingerprints,
+                        &certificate.fingerprint,
+                    ) || certificate
+                        .emails
+                        .iter()
+                        .any(|email| principal.has_str_value(PrincipalField::Emails, email))
+                    {
+                        return Ok(Some(principal.clone()));
+                    }
+                }
+            }
             QueryBy::Credentials(credentials) => {
                 let (username, secret) = match credentials {
                     Credentials::Plain { username, secret } => (username, secret),
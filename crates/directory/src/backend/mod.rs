@@ -17,10 +17,109 @@ pub mod sql;
 pub enum RcptType {
     Mailbox,
     List(Vec<String>),
+    Alias(Vec<String>),
     #[default]
     Invalid,
 }
 
+/// Recipient routing overrides stored on a domain principal, settings that
+/// are not present fall back to the global ones.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct DomainRouting {
+    pub catch_all: Option<String>,
+    pub subaddressing: Option<Subaddressing>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subaddressing {
+    Disabled,
+    Separators(String),
+}
+
+impl Subaddressing {
+    pub const DISABLED: &'static str = "disabled";
+
+    pub fn parse(value: &str) -> Option<Self> {
+        if value == Self::DISABLED {
+            Some(Subaddressing::Disabled)
+        } else if !value.is_empty()
+            && value
+                .chars()
+                .all(|ch| ch.is_ascii_punctuation() && !matches!(ch, '@' | '"' | '\\'))
+        {
+            Some(Subaddressing::Separators(value.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Removes the tag from the local part, `None` if the address has no tag.
+    pub fn to_subaddress(&self, address: &str) -> Option<String> {
+        match self {
+            Subaddressing::Separators(separators) => {
+                let (local_part, domain_part) = address.rsplit_once('@')?;
+                let (local_part, _) = local_part.split_once(|ch| separators.contains(ch))?;
+                Some(format!("{local_part}@{domain_part}")).filter(|_| !local_part.is_empty())
+            }
+            Subaddressing::Disabled => None,
+        }
+    }
+}
+
+/// Who is allowed to post to a mailing list.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PostingPolicy {
+    #[default]
+    Anyone,
+    Members,
+    Moderators,
+}
+
+impl PostingPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "anyone" => Some(PostingPolicy::Anyone),
+            "members" => Some(PostingPolicy::Members),
+            "moderators" => Some(PostingPolicy::Moderators),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostingPolicy::Anyone => "anyone",
+            PostingPolicy::Members => "members",
+            PostingPolicy::Moderators => "moderators",
+        }
+    }
+}
+
+/// Posting settings of a mailing list, lists without any settings are open
+/// to anyone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListPolicy {
+    pub list_id: u32,
+    pub posting: PostingPolicy,
+    pub moderators: Vec<u32>,
+    pub members: Vec<u32>,
+    pub external_members: Vec<String>,
+    pub reply_to_list: bool,
+}
+
+impl ListPolicy {
+    pub fn is_moderator(&self, principal_id: u32) -> bool {
+        self.moderators.contains(&principal_id)
+    }
+
+    pub fn is_member(&self, principal_ids: &[u32], address: &str) -> bool {
+        principal_ids.iter().any(|id| self.members.contains(id))
+            || self
+                .external_members
+                .iter()
+                .any(|member| member.eq_ignore_ascii_case(address))
+    }
+}
+
 impl From<bool> for RcptType {
     fn from(value: bool) -> Self {
         if value {
@@ -10,14 +10,24 @@
 
 use std::time::Duration;
 
+use ahash::AHashMap;
 use base64::{engine::general_purpose, Engine};
 use store::Store;
 use utils::config::{utils::AsKey, Config};
 
-use super::{Authentication, EndpointType, OpenIdConfig, OpenIdDirectory};
+use crate::backend::internal::manage::CreateRetry;
+
+use super::{
+    Authentication, ClaimTarget, EndpointType, OpenIdClaims, OpenIdConfig, OpenIdDirectory,
+};
 
 impl OpenIdDirectory {
-    pub fn from_config(config: &mut Config, prefix: impl AsKey, data_store: Store) -> Option<Self> {
+    pub fn from_config(
+        config: &mut Config,
+        prefix: impl AsKey,
+        data_store: Store,
+        create_retry: CreateRetry,
+    ) -> Option<Self> {
         let prefix = prefix.as_key();
         let endpoint_type = match config.value_require((&prefix, "endpoint.method"))? {
             "introspect" => match config.value_require((&prefix, "auth.method"))? {
@@ -59,6 +69,47 @@ impl OpenIdDirectory {
             }
         };
 
+        // Group and role claims
+        let fields = config
+            .values((&prefix, "claims.groups"))
+            .map(|(_, v)| v.to_string())
+            .collect::<Vec<_>>();
+        let claims = if !fields.is_empty() {
+            let map_prefix = format!("{prefix}.claims.map.");
+            let mut map = AHashMap::new();
+            for (key, value) in config
+                .values((&prefix, "claims.map"))
+                .filter_map(|(key, value)| {
+                    key.strip_prefix(&map_prefix)
+                        .map(|key| (key.to_string(), value.to_string()))
+                })
+                .collect::<Vec<_>>()
+            {
+                match ClaimTarget::parse(&value) {
+                    Some(target) => {
+                        map.insert(key.to_lowercase(), target);
+                    }
+                    None => {
+                        config.new_parse_error(
+                            (prefix.as_str(), "claims.map", key.as_str()),
+                            format!("Invalid claim mapping {value:?}"),
+                        );
+                    }
+                }
+            }
+
+            Some(OpenIdClaims {
+                fields,
+                map,
+                protected: config
+                    .values((&prefix, "claims.protected"))
+                    .map(|(_, v)| v.to_lowercase())
+                    .collect(),
+            })
+        } else {
+            None
+        };
+
         Some(OpenIdDirectory {
             config: OpenIdConfig {
                 endpoint: config.value_require((&prefix, "endpoint.url"))?.to_string(),
@@ -73,8 +124,33 @@ impl OpenIdDirectory {
                 full_name_field: config
                     .value((&prefix, "fields.full-name"))
                     .map(|v| v.to_string()),
+                claims,
             },
             data_store,
+            create_retry,
         })
     }
 }
+
+impl ClaimTarget {
+    // Values are either "role:<name>" or "group:<name>", groups by default
+    fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase();
+        let target = if let Some(name) = value.strip_prefix("role:") {
+            ClaimTarget::Role(name.trim().to_string())
+        } else {
+            ClaimTarget::Group(
+                value
+                    .strip_prefix("group:")
+                    .unwrap_or(&value)
+                    .trim()
+                    .to_string(),
+            )
+        };
+
+        match &target {
+            ClaimTarget::Group(name) | ClaimTarget::Role(name) if !name.is_empty() => Some(target),
+            _ => None,
+        }
+    }
+}
@@ -18,9 +18,9 @@ use crate::{
         internal::{
             lookup::DirectoryStore,
             manage::{self, ManageDirectory, UpdatePrincipal},
-            PrincipalField,
+            PrincipalField, PrincipalUpdate, PrincipalValue,
         },
-        oidc::{Authentication, EndpointType},
+        oidc::{Authentication, ClaimTarget, EndpointType, OpenIdClaims},
         RcptType,
     },
     Principal, QueryBy, Type, ROLE_USER,
@@ -88,20 +88,28 @@ impl OpenIdDirectory {
                         })?;
 
                         // Deserialize response
-                        let external_principal =
-                            serde_json::from_slice::<OpenIdResponse>(&response)
-                                .map_err(|err| {
-                                    AuthEvent::Error
-                                        .into_err()
-                                        .reason(err)
-                                        .details("Failed to deserialize OIDC response")
-                                })?
-                                .build_principal(&self.config)?;
+                        let mut response = serde_json::from_slice::<OpenIdResponse>(&response)
+                            .map_err(|err| {
+                                AuthEvent::Error
+                                    .into_err()
+                                    .reason(err)
+                                    .details("Failed to deserialize OIDC response")
+                            })?;
+                        let claims = self
+                            .config
+                            .claims
+                            .as_ref()
+                            .and_then(|claims| Some((claims, response.claim_values(claims)?)));
+                        let external_principal = response.build_principal(&self.config)?;
 
                         // Fetch principal
                         let id = self
                             .data_store
-                            .get_or_create_principal_id(external_principal.name(), Type::Individual)
+                            .get_or_create_principal_id(
+                                external_principal.name(),
+                                Type::Individual,
+                                self.create_retry,
+                            )
                             .await
                             .caused_by(trc::location!())?;
                         let mut principal = self
@@ -124,6 +132,20 @@ impl OpenIdDirectory {
                                 .caused_by(trc::location!())?;
                         }
 
+                        // Synchronize memberships with the group claims
+                        if let Some((claims, values)) = claims {
+                            if self.sync_claims(&principal, claims, values).await? {
+                                principal = self
+                                    .data_store
+                                    .query(QueryBy::Id(id), return_member_of)
+                                    .await
+                                    .caused_by(trc::location!())?
+                                    .ok_or_else(|| {
+                                        manage::not_found(id).caused_by(trc::location!())
+                                    })?;
+                            }
+                        }
+
                         Ok(Some(principal))
                     }
                     StatusCode::UNAUTHORIZED => Err(trc::AuthEvent::Failed
@@ -160,10 +182,114 @@ impl OpenIdDirectory {
     pub async fn is_local_domain(&self, domain: &str) -> trc::Result<bool> {
         self.data_store.is_local_domain(domain).await
     }
+
+    // Adds and removes the groups and roles of a principal so they match the
+    // claims, returns whether any membership changed.
+    async fn sync_claims(
+        &self,
+        principal: &Principal,
+        claims: &OpenIdClaims,
+        values: Vec<String>,
+    ) -> trc::Result<bool> {
+        // Map claim values to groups and roles
+        let mut groups = Vec::new();
+        let mut roles = Vec::new();
+        for value in values {
+            let (targets, name) = match claims.map.get(&value) {
+                Some(ClaimTarget::Role(name)) => (&mut roles, name.clone()),
+                Some(ClaimTarget::Group(name)) => (&mut groups, name.clone()),
+                None => (&mut groups, value),
+            };
+            if !claims.protected.contains(&name) && !targets.contains(&name) {
+                targets.push(name);
+            }
+        }
+
+        // Roles that do not exist cannot be granted
+        let mut valid_roles = Vec::with_capacity(roles.len());
+        for role in roles {
+            if PrincipalField::Roles.map_internal_roles(&role).is_some()
+                || self
+                    .data_store
+                    .get_principal_info(&role)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_some_and(|info| info.typ == Type::Role)
+            {
+                valid_roles.push(role);
+            } else {
+                trc::event!(
+                    Auth(AuthEvent::Error),
+                    Details = "Role in OIDC claim does not exist",
+                    AccountName = principal.name().to_string(),
+                    Key = role,
+                );
+            }
+        }
+        if valid_roles.is_empty() {
+            valid_roles.push("user".to_string());
+        }
+
+        // Obtain current memberships
+        let mut current = self
+            .data_store
+            .query(QueryBy::Id(principal.id), true)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| manage::not_found(principal.id).caused_by(trc::location!()))?;
+        self.data_store
+            .map_field_ids(
+                &mut current,
+                &[PrincipalField::MemberOf, PrincipalField::Roles],
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        let mut changes = Vec::new();
+        for (field, targets) in [
+            (PrincipalField::MemberOf, groups),
+            (PrincipalField::Roles, valid_roles),
+        ] {
+            let current = current.take_str_array(field).unwrap_or_default();
+            for name in &current {
+                if !targets.contains(name) && !claims.protected.contains(name) {
+                    changes.push(PrincipalUpdate::remove_item(
+                        field,
+                        PrincipalValue::String(name.clone()),
+                    ));
+                }
+            }
+            for name in targets {
+                if !current.contains(&name) {
+                    if field == PrincipalField::MemberOf {
+                        self.data_store
+                            .get_or_create_principal_id(&name, Type::Group, self.create_retry)
+                            .await
+                            .caused_by(trc::location!())?;
+                    }
+                    changes.push(PrincipalUpdate::add_item(
+                        field,
+                        PrincipalValue::String(name),
+                    ));
+                }
+            }
+        }
+
+        if !changes.is_empty() {
+            self.data_store
+                .update_principal(UpdatePrincipal::by_id(principal.id).with_updates(changes))
+                .await
+                .caused_by(trc::location!())?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
 }
 
 trait BuildPrincipal {
     fn build_principal(&mut self, config: &OpenIdConfig) -> trc::Result<Principal>;
+    fn claim_values(&self, claims: &OpenIdClaims) -> Option<Vec<String>>;
     fn take_required_field(&mut self, field: &str) -> trc::Result<String>;
     fn take_field(&mut self, field: &str) -> Option<String>;
 }
@@ -196,6 +322,45 @@ impl BuildPrincipal for OpenIdResponse {
             .with_opt_field(PrincipalField::Description, full_name))
     }
 
+    fn claim_values(&self, claims: &OpenIdClaims) -> Option<Vec<String>> {
+        let mut values = Vec::new();
+        let mut has_claims = false;
+
+        for field in &claims.fields {
+            // Nested claims such as "realm_access.roles" are separated by dots
+            let mut path = field.split('.');
+            let mut value = path.next().and_then(|key| self.get(key));
+            for key in path {
+                value = value.and_then(|value| value.get(key));
+            }
+
+            match value {
+                Some(serde_json::Value::Array(items)) => {
+                    has_claims = true;
+                    values.extend(
+                        items
+                            .iter()
+                            .filter_map(|item| item.as_str())
+                            .map(|item| item.to_lowercase()),
+                    );
+                }
+                Some(serde_json::Value::String(item)) => {
+                    has_claims = true;
+                    values.push(item.to_lowercase());
+                }
+                _ => (),
+            }
+        }
+
+        // Missing claims leave memberships unchanged
+        if has_claims {
+            values.retain(|value| !value.is_empty());
+            Some(values)
+        } else {
+            None
+        }
+    }
+
     fn take_required_field(&mut self, field: &str) -> trc::Result<String> {
         match self.remove(field) {
             Some(serde_json::Value::String(value)) if !value.is_empty() => Ok(value),
@@ -13,11 +13,15 @@ pub mod lookup;
 
 use std::time::Duration;
 
+use ahash::{AHashMap, AHashSet};
 use store::Store;
 
+use super::internal::manage::CreateRetry;
+
 pub struct OpenIdDirectory {
     config: OpenIdConfig,
     pub(crate) data_store: Store,
+    pub(crate) create_retry: CreateRetry,
 }
 
 struct OpenIdConfig {
@@ -27,6 +31,21 @@ struct OpenIdConfig {
     pub email_field: String,
     pub username_field: Option<String>,
     pub full_name_field: Option<String>,
+    pub claims: Option<OpenIdClaims>,
+}
+
+// Claims holding the groups and roles of the user, memberships are
+// synchronized on login except for the protected groups and roles
+struct OpenIdClaims {
+    pub fields: Vec<String>,
+    pub map: AHashMap<String, ClaimTarget>,
+    pub protected: AHashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClaimTarget {
+    Group(String),
+    Role(String),
 }
 
 #[derive(Debug)]
@@ -7,6 +7,8 @@
 use store::{Store, Stores};
 use utils::config::{utils::AsKey, Config};
 
+use crate::backend::internal::manage::CreateRetry;
+
 use super::{SqlDirectory, SqlMappings};
 
 impl SqlDirectory {
@@ -15,6 +17,7 @@ impl SqlDirectory {
         prefix: impl AsKey,
         stores: &Stores,
         data_store: Store,
+        create_retry: CreateRetry,
     ) -> Option<Self> {
         let prefix = prefix.as_key();
         let store_id = config.value_require((&prefix, "store"))?.to_string();
@@ -56,6 +59,18 @@ impl SqlDirectory {
             ("emails", &mut mappings.query_emails),
             ("recipients", &mut mappings.query_recipients),
             ("secrets", &mut mappings.query_secrets),
+            ("list", &mut mappings.query_list),
+            ("insert", &mut mappings.query_insert),
+            ("update-secret", &mut mappings.query_update_secret),
+            ("update-description", &mut mappings.query_update_description),
+            ("update-quota", &mut mappings.query_update_quota),
+            ("delete", &mut mappings.query_delete),
+            ("insert-email", &mut mappings.query_insert_email),
+            ("delete-email", &mut mappings.query_delete_email),
+            ("delete-emails", &mut mappings.query_delete_emails),
+            ("insert-member", &mut mappings.query_insert_member),
+            ("delete-member", &mut mappings.query_delete_member),
+            ("delete-members", &mut mappings.query_delete_members),
         ] {
             *query = config
                 .value(("store", store_id.as_str(), "query", query_id))
@@ -67,6 +82,7 @@ impl SqlDirectory {
             store,
             mappings,
             data_store,
+            create_retry,
         })
     }
 }
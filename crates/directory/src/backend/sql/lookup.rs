@@ -41,10 +41,10 @@ impl SqlDirectory {
                     .map(|p| p.with_field(PrincipalField::Name, username.to_string())),
                 None,
             ),
-            QueryBy::Id(uid) => {
+            by @ (QueryBy::Id(_) | QueryBy::ExternalId(_) | QueryBy::Certificate(_)) => {
                 if let Some(principal) = self
                     .data_store
-                    .query(QueryBy::Id(uid), return_member_of)
+                    .query(by, return_member_of)
                     .await
                     .caused_by(trc::location!())?
                 {
@@ -121,7 +121,7 @@ impl SqlDirectory {
                     external_principal.append_int(
                         PrincipalField::MemberOf,
                         self.data_store
-                            .get_or_create_principal_id(account_id, Type::Group)
+                            .get_or_create_principal_id(account_id, Type::Group, self.create_retry)
                             .await
                             .caused_by(trc::location!())?,
                     );
@@ -169,7 +169,11 @@ impl SqlDirectory {
         } else {
             let id = self
                 .data_store
-                .get_or_create_principal_id(external_principal.name(), Type::Individual)
+                .get_or_create_principal_id(
+                    external_principal.name(),
+                    Type::Individual,
+                    self.create_retry,
+                )
                 .await
                 .caused_by(trc::location!())?;
 
@@ -207,7 +211,7 @@ impl SqlDirectory {
             if let Some(Value::Text(name)) = row.values.first() {
                 return self
                     .data_store
-                    .get_or_create_principal_id(name, Type::Individual)
+                    .get_or_create_principal_id(name, Type::Individual, self.create_retry)
                     .await
                     .caused_by(trc::location!())
                     .map(Some);
@@ -230,7 +234,7 @@ impl SqlDirectory {
             Ok(RcptType::Mailbox)
         } else {
             self.data_store.rcpt(address).await.map(|result| {
-                if matches!(result, RcptType::List(_)) {
+                if matches!(result, RcptType::List(_) | RcptType::Alias(_)) {
                     result
                 } else {
                     RcptType::Invalid
@@ -250,6 +254,63 @@ impl SqlDirectory {
     pub async fn is_local_domain(&self, domain: &str) -> trc::Result<bool> {
         self.data_store.is_local_domain(domain).await
     }
+
+    pub async fn list_principals(&self) -> trc::Result<Vec<Principal>> {
+        if self.mappings.query_list.is_empty() {
+            return Err(trc::StoreEvent::NotSupported
+                .into_err()
+                .details("Missing list query")
+                .caused_by(trc::location!()));
+        }
+
+        let mut principals = Vec::new();
+        for row in self
+            .store
+            .query::<Rows>(&self.mappings.query_list, vec![])
+            .await
+            .caused_by(trc::location!())?
+            .rows
+        {
+            let Some(Value::Text(name)) = row.values.into_iter().next() else {
+                continue;
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let Some(mut principal) = self
+                .mappings
+                .row_to_principal(
+                    self.store
+                        .query::<NamedRows>(&self.mappings.query_name, vec![name.as_ref().into()])
+                        .await
+                        .caused_by(trc::location!())?,
+                )
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+
+            // Secrets are verified at login time
+            principal.remove(PrincipalField::Secrets);
+
+            if !self.mappings.query_emails.is_empty() {
+                principal.set(
+                    PrincipalField::Emails,
+                    PrincipalValue::StringList(
+                        self.store
+                            .query::<Rows>(&self.mappings.query_emails, vec![name.as_ref().into()])
+                            .await
+                            .caused_by(trc::location!())?
+                            .into(),
+                    ),
+                );
+            }
+
+            principals.push(principal.with_field(PrincipalField::Name, name.into_owned()));
+        }
+
+        Ok(principals)
+    }
 }
 
 impl SqlMappings {
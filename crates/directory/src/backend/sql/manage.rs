@@ -0,0 +1,487 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use store::{Rows, Value};
+use trc::AddContext;
+
+use crate::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{self, ErrorCode, ManageDirectory, UpdatePrincipal},
+        PrincipalAction, PrincipalField, PrincipalUpdate, PrincipalValue, SpecialSecrets,
+    },
+    Principal, QueryBy, Type,
+};
+
+use super::{SqlDirectory, SqlMappings};
+
+// Statements executed within a single transaction, along with the field and
+// value protected by a unique constraint on each of them
+#[derive(Default)]
+struct SqlTransaction<'x> {
+    statements: Vec<(&'x str, Vec<Value<'static>>)>,
+    keys: Vec<Option<(PrincipalField, String)>>,
+}
+
+impl SqlDirectory {
+    /// SQL directories can be managed when a query to insert principals is configured.
+    pub fn is_writable(&self) -> bool {
+        !self.mappings.query_insert.is_empty()
+    }
+
+    pub async fn create_principal(&self, mut principal: Principal) -> trc::Result<u32> {
+        // Make sure the principal has a name
+        let name = principal.name().to_lowercase();
+        if name.is_empty() {
+            return Err(manage::err_missing(PrincipalField::Name));
+        }
+        for field in principal.fields.keys() {
+            if !matches!(
+                field,
+                PrincipalField::Name
+                    | PrincipalField::Secrets
+                    | PrincipalField::Description
+                    | PrincipalField::Quota
+                    | PrincipalField::Emails
+                    | PrincipalField::MemberOf
+                    | PrincipalField::Roles
+            ) {
+                return Err(unsupported_field(*field));
+            }
+        }
+        let class = sql_class(
+            principal.typ,
+            principal
+                .take_str_array(PrincipalField::Roles)
+                .unwrap_or_default(),
+        )?;
+        let secrets = principal
+            .take_str_array(PrincipalField::Secrets)
+            .unwrap_or_default();
+        let secret = sql_secret(&secrets)?;
+        let description = principal.take_str(PrincipalField::Description);
+        let quota = principal
+            .take_int(PrincipalField::Quota)
+            .unwrap_or_default();
+        let mut emails = Vec::new();
+        for email in principal
+            .take_str_array(PrincipalField::Emails)
+            .unwrap_or_default()
+        {
+            emails.push(self.validate_email(&email).await?);
+        }
+
+        // Write principal
+        let mut trx = SqlTransaction::default();
+        trx.push(
+            self.mappings.statement("insert")?,
+            vec![
+                name.clone().into(),
+                class.into(),
+                secret.map_or(Value::Null, |secret| secret.to_string().into()),
+                description.clone().map_or(Value::Null, Value::from),
+                (quota as i64).into(),
+            ],
+            Some((PrincipalField::Name, name.clone())),
+        );
+        for email in &emails {
+            trx.push(
+                self.mappings.statement("insert-email")?,
+                vec![name.clone().into(), email.clone().into()],
+                Some((PrincipalField::Emails, email.clone())),
+            );
+        }
+        for group in principal
+            .take_str_array(PrincipalField::MemberOf)
+            .unwrap_or_default()
+        {
+            trx.push(
+                self.mappings.statement("insert-member")?,
+                vec![name.clone().into(), group.clone().into()],
+                Some((PrincipalField::MemberOf, group)),
+            );
+        }
+        self.execute(trx).await?;
+
+        // Keep the internal store up to date with the SQL server
+        let principal_id = self
+            .data_store
+            .get_or_create_principal_id(&name, principal.typ, self.create_retry)
+            .await
+            .caused_by(trc::location!())?;
+        let mut changes = vec![
+            PrincipalUpdate::set(PrincipalField::Secrets, PrincipalValue::StringList(secrets)),
+            PrincipalUpdate::set(PrincipalField::Emails, PrincipalValue::StringList(emails)),
+            PrincipalUpdate::set(PrincipalField::Quota, PrincipalValue::Integer(quota)),
+        ];
+        if let Some(description) = description {
+            changes.push(PrincipalUpdate::set(
+                PrincipalField::Description,
+                PrincipalValue::String(description),
+            ));
+        }
+        self.data_store
+            .update_principal(
+                UpdatePrincipal::by_id(principal_id)
+                    .with_updates(changes)
+                    .create_domains(),
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(principal_id)
+    }
+
+    pub async fn update_principal(
+        &self,
+        principal_id: u32,
+        changes: Vec<PrincipalUpdate>,
+    ) -> trc::Result<()> {
+        let name = self.principal_name(principal_id).await?;
+        let mut trx = SqlTransaction::default();
+        let mut secret = None;
+        let mut mirror = Vec::with_capacity(changes.len());
+
+        for change in changes {
+            match (&change.action, change.field, &change.value) {
+                (PrincipalAction::Set, PrincipalField::Secrets, value) => {
+                    let secrets = value.iter_str().cloned().collect::<Vec<_>>();
+                    secret = Some(sql_secret(&secrets)?.map(|s| s.to_string()));
+                }
+                (PrincipalAction::AddItem, PrincipalField::Secrets, PrincipalValue::String(s)) => {
+                    secret = Some(sql_secret(std::slice::from_ref(s))?.map(|s| s.to_string()));
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::Secrets,
+                    PrincipalValue::String(s),
+                ) if s.is_empty() => {
+                    secret = Some(None);
+                }
+                (PrincipalAction::Set, PrincipalField::Description, value) => {
+                    trx.push(
+                        self.mappings.statement("update-description")?,
+                        vec![
+                            value
+                                .iter_str()
+                                .next()
+                                .filter(|v| !v.is_empty())
+                                .map_or(Value::Null, |v| v.clone().into()),
+                            name.clone().into(),
+                        ],
+                        None,
+                    );
+                }
+                (PrincipalAction::Set, PrincipalField::Quota, PrincipalValue::Integer(quota)) => {
+                    trx.push(
+                        self.mappings.statement("update-quota")?,
+                        vec![(*quota as i64).into(), name.clone().into()],
+                        None,
+                    );
+                }
+                (PrincipalAction::Set, PrincipalField::Emails, value) => {
+                    let current = self
+                        .query_values(&self.mappings.query_emails, &name)
+                        .await?;
+                    let mut emails = Vec::new();
+                    for email in value.iter_str() {
+                        emails.push(self.validate_email(email).await?);
+                    }
+                    for email in current.iter().filter(|email| !emails.contains(email)) {
+                        trx.push(
+                            self.mappings.statement("delete-email")?,
+                            vec![name.clone().into(), email.clone().into()],
+                            None,
+                        );
+                    }
+                    for email in emails.iter().filter(|email| !current.contains(email)) {
+                        trx.push(
+                            self.mappings.statement("insert-email")?,
+                            vec![name.clone().into(), email.clone().into()],
+                            Some((PrincipalField::Emails, email.clone())),
+                        );
+                    }
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::Emails,
+                    PrincipalValue::String(email),
+                ) => {
+                    let email = self.validate_email(email).await?;
+                    trx.push(
+                        self.mappings.statement("insert-email")?,
+                        vec![name.clone().into(), email.clone().into()],
+                        Some((PrincipalField::Emails, email)),
+                    );
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::Emails,
+                    PrincipalValue::String(email),
+                ) => {
+                    trx.push(
+                        self.mappings.statement("delete-email")?,
+                        vec![name.clone().into(), email.to_lowercase().into()],
+                        None,
+                    );
+                }
+                (PrincipalAction::Set, PrincipalField::MemberOf, value) => {
+                    let current = self
+                        .query_values(&self.mappings.query_members, &name)
+                        .await?;
+                    let groups = value.iter_str().cloned().collect::<Vec<_>>();
+                    for group in current.iter().filter(|group| !groups.contains(group)) {
+                        trx.push(
+                            self.mappings.statement("delete-member")?,
+                            vec![name.clone().into(), group.clone().into()],
+                            None,
+                        );
+                    }
+                    for group in groups.iter().filter(|group| !current.contains(group)) {
+                        trx.push(
+                            self.mappings.statement("insert-member")?,
+                            vec![name.clone().into(), group.clone().into()],
+                            Some((PrincipalField::MemberOf, group.clone())),
+                        );
+                    }
+                }
+                (
+                    PrincipalAction::AddItem,
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String(group),
+                ) => {
+                    trx.push(
+                        self.mappings.statement("insert-member")?,
+                        vec![name.clone().into(), group.clone().into()],
+                        Some((PrincipalField::MemberOf, group.clone())),
+                    );
+                }
+                (
+                    PrincipalAction::RemoveItem,
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String(group),
+                ) => {
+                    trx.push(
+                        self.mappings.statement("delete-member")?,
+                        vec![name.clone().into(), group.clone().into()],
+                        None,
+                    );
+                }
+                (_, field, _) => {
+                    return Err(unsupported_field(field));
+                }
+            }
+
+            // Memberships are obtained from the SQL server on every lookup
+            if change.field != PrincipalField::MemberOf {
+                mirror.push(change);
+            }
+        }
+
+        if let Some(secret) = secret {
+            trx.push(
+                self.mappings.statement("update-secret")?,
+                vec![secret.map_or(Value::Null, Value::from), name.into()],
+                None,
+            );
+        }
+        self.execute(trx).await?;
+
+        // Keep the internal store up to date with the SQL server, differences
+        // left by a failure here are reconciled on the next lookup
+        if !mirror.is_empty() {
+            self.data_store
+                .update_principal(
+                    UpdatePrincipal::by_id(principal_id)
+                        .with_updates(mirror)
+                        .create_domains(),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete_principal(&self, principal_id: u32) -> trc::Result<()> {
+        let principal = self
+            .data_store
+            .get_principal(principal_id)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| manage::err_not_found(ErrorCode::PrincipalNotFound, principal_id))?;
+        manage::assert_not_on_hold(&principal)?;
+        let name = principal.name().to_string();
+
+        // Emails and memberships can also be removed by the database on cascade
+        let mut trx = SqlTransaction::default();
+        for (query, id) in [
+            (&self.mappings.query_delete_emails, "delete-emails"),
+            (&self.mappings.query_delete_members, "delete-members"),
+        ] {
+            if !query.is_empty() {
+                trx.push(
+                    self.mappings.statement(id)?,
+                    vec![name.clone().into()],
+                    None,
+                );
+            }
+        }
+        trx.push(self.mappings.statement("delete")?, vec![name.into()], None);
+        self.execute(trx).await?;
+
+        self.data_store
+            .delete_principal(QueryBy::Id(principal_id))
+            .await
+            .caused_by(trc::location!())
+    }
+
+    async fn execute(&self, trx: SqlTransaction<'_>) -> trc::Result<()> {
+        match self.store.execute_transaction(&trx.statements).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.matches(trc::EventType::Store(trc::StoreEvent::AssertValueFailed)) => {
+                // Translate unique constraint violations into the errors
+                // returned by the internal directory
+                match err
+                    .value(trc::Key::Id)
+                    .and_then(|idx| idx.to_uint())
+                    .and_then(|idx| trx.keys.get(idx as usize))
+                {
+                    Some(Some((field, value))) => Err(manage::err_exists(*field, value.clone())),
+                    _ => Err(err),
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn principal_name(&self, principal_id: u32) -> trc::Result<String> {
+        self.data_store
+            .get_principal(principal_id)
+            .await
+            .caused_by(trc::location!())?
+            .map(|principal| principal.name().to_string())
+            .ok_or_else(|| manage::err_not_found(ErrorCode::PrincipalNotFound, principal_id))
+    }
+
+    async fn query_values(&self, query: &str, name: &str) -> trc::Result<Vec<String>> {
+        if !query.is_empty() {
+            self.store
+                .query::<Rows>(query, vec![name.into()])
+                .await
+                .caused_by(trc::location!())
+                .map(Into::into)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    async fn validate_email(&self, email: &str) -> trc::Result<String> {
+        let email = manage::normalize_email(email)?;
+        let domain = email
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default();
+        if self
+            .data_store
+            .is_local_domain(domain)
+            .await
+            .caused_by(trc::location!())?
+        {
+            Ok(email)
+        } else {
+            Err(manage::err_not_found(
+                ErrorCode::DomainNotFound,
+                domain.to_string(),
+            ))
+        }
+    }
+}
+
+impl<'x> SqlTransaction<'x> {
+    fn push(
+        &mut self,
+        query: &'x str,
+        params: Vec<Value<'static>>,
+        key: Option<(PrincipalField, String)>,
+    ) {
+        self.statements.push((query, params));
+        self.keys.push(key);
+    }
+}
+
+impl SqlMappings {
+    fn statement(&self, id: &str) -> trc::Result<&str> {
+        let query = match id {
+            "insert" => &self.query_insert,
+            "update-secret" => &self.query_update_secret,
+            "update-description" => &self.query_update_description,
+            "update-quota" => &self.query_update_quota,
+            "delete" => &self.query_delete,
+            "insert-email" => &self.query_insert_email,
+            "delete-email" => &self.query_delete_email,
+            "delete-emails" => &self.query_delete_emails,
+            "insert-member" => &self.query_insert_member,
+            "delete-member" => &self.query_delete_member,
+            "delete-members" => &self.query_delete_members,
+            _ => "",
+        };
+
+        if !query.is_empty() {
+            Ok(query)
+        } else {
+            Err(manage::unsupported(format!(
+                "SQL directory is missing the {id:?} query"
+            )))
+        }
+    }
+}
+
+// Values written to the type column, as understood by `row_to_principal`
+fn sql_class(typ: Type, roles: Vec<String>) -> trc::Result<&'static str> {
+    let mut is_admin = false;
+    for role in roles {
+        match role.as_str() {
+            "user" => {}
+            "admin" if typ == Type::Individual => is_admin = true,
+            _ => {
+                return Err(manage::unsupported(format!(
+                    "SQL directory does not support the {role:?} role"
+                )))
+            }
+        }
+    }
+
+    match typ {
+        Type::Individual if is_admin => Ok("admin"),
+        Type::Individual => Ok("individual"),
+        Type::Group => Ok("group"),
+        _ => Err(manage::unsupported(format!(
+            "SQL directory does not support {} principals",
+            typ.as_str()
+        ))),
+    }
+}
+
+// The secret column holds a single password, app passwords, OTP secrets
+// and recovery codes have no place in it
+fn sql_secret(secrets: &[String]) -> trc::Result<Option<&str>> {
+    match secrets {
+        [] => Ok(None),
+        [secret] if secret.is_password() => Ok(Some(secret)),
+        _ => Err(manage::unsupported(
+            "SQL directory only supports a single password per principal",
+        )),
+    }
+}
+
+fn unsupported_field(field: PrincipalField) -> trc::Error {
+    manage::unsupported(format!(
+        "SQL directory does not support updating the {} field",
+        field.as_str()
+    ))
+    .ctx(trc::Key::Key, field)
+}
@@ -6,13 +6,17 @@
 
 use store::{LookupStore, Store};
 
+use super::internal::manage::CreateRetry;
+
 pub mod config;
 pub mod lookup;
+pub mod manage;
 
 pub struct SqlDirectory {
     store: LookupStore,
     mappings: SqlMappings,
     pub(crate) data_store: Store,
+    pub(crate) create_retry: CreateRetry,
 }
 
 #[derive(Debug, Default)]
@@ -22,6 +26,18 @@ pub(crate) struct SqlMappings {
     query_emails: String,
     query_recipients: String,
     query_secrets: String,
+    query_list: String,
+    query_insert: String,
+    query_update_secret: String,
+    query_update_description: String,
+    query_update_quota: String,
+    query_delete: String,
+    query_insert_email: String,
+    query_delete_email: String,
+    query_delete_emails: String,
+    query_insert_member: String,
+    query_delete_member: String,
+    query_delete_members: String,
     column_description: String,
     column_secret: String,
     column_email: String,
@@ -13,11 +13,19 @@ use std::{
 use parking_lot::Mutex;
 use utils::config::{utils::AsKey, Config};
 
-use crate::backend::RcptType;
+use crate::backend::{DomainRouting, RcptType};
 
 pub struct CachedDirectory {
     cached_domains: Mutex<LookupCache<String>>,
     cached_rcpts: Mutex<LookupCache<String>>,
+    cached_routing: Mutex<RoutingCache>,
+}
+
+#[derive(Debug)]
+struct RoutingCache {
+    entries: lru_cache::LruCache<String, (Option<DomainRouting>, Instant), ahash::RandomState>,
+    ttl_pos: Duration,
+    ttl_neg: Duration,
 }
 
 #[allow(clippy::type_complexity)]
@@ -51,6 +59,14 @@ impl CachedDirectory {
                 cache_ttl_positive,
                 cache_ttl_negative,
             )),
+            cached_routing: Mutex::new(RoutingCache {
+                entries: lru_cache::LruCache::with_hasher(
+                    cached_entries,
+                    ahash::RandomState::new(),
+                ),
+                ttl_pos: cache_ttl_positive,
+                ttl_neg: cache_ttl_negative,
+            }),
         })
     }
 
@@ -62,10 +78,34 @@ impl CachedDirectory {
         match exists {
             RcptType::Mailbox => self.cached_rcpts.lock().insert_pos(address.to_string()),
             RcptType::Invalid => self.cached_rcpts.lock().insert_neg(address.to_string()),
-            RcptType::List(_) => {}
+            RcptType::List(_) | RcptType::Alias(_) => {}
+        }
+    }
+
+    pub fn get_domain_routing(&self, domain: &str) -> Option<Option<DomainRouting>> {
+        let mut cache = self.cached_routing.lock();
+        let (routing, valid_until) = cache.entries.get_mut(domain)?;
+        if *valid_until >= Instant::now() {
+            Some(routing.clone())
+        } else {
+            cache.entries.remove(domain);
+            None
         }
     }
 
+    pub fn set_domain_routing(&self, domain: &str, routing: &Option<DomainRouting>) {
+        let mut cache = self.cached_routing.lock();
+        let valid_until = Instant::now()
+            + if routing.is_some() {
+                cache.ttl_pos
+            } else {
+                cache.ttl_neg
+            };
+        cache
+            .entries
+            .insert(domain.to_string(), (routing.clone(), valid_until));
+    }
+
     pub fn get_domain(&self, domain: &str) -> Option<bool> {
         self.cached_domains.lock().get(domain)
     }
@@ -16,13 +16,13 @@ use ahash::AHashMap;
 
 use crate::{
     backend::{
-        imap::ImapDirectory, ldap::LdapDirectory, memory::MemoryDirectory, smtp::SmtpDirectory,
-        sql::SqlDirectory,
+        imap::ImapDirectory, internal::manage::CreateRetry, ldap::LdapDirectory,
+        memory::MemoryDirectory, smtp::SmtpDirectory, sql::SqlDirectory,
     },
     Directories, Directory, DirectoryInner,
 };
 
-use super::cache::CachedDirectory;
+use super::{cache::CachedDirectory, sync::DirectorySync};
 
 impl Directories {
     pub async fn parse(
@@ -32,6 +32,7 @@ impl Directories {
         is_enterprise: bool,
     ) -> Self {
         let mut directories = AHashMap::new();
+        let create_retry = CreateRetry::parse(config);
 
         for id in config
             .sub_keys("directory", ".type")
@@ -70,10 +71,18 @@ impl Directories {
                         continue;
                     },
                 )),
-                "ldap" => LdapDirectory::from_config(config, prefix, data_store.clone())
-                    .map(DirectoryInner::Ldap),
-                "sql" => SqlDirectory::from_config(config, prefix, stores, data_store.clone())
-                    .map(DirectoryInner::Sql),
+                "ldap" => {
+                    LdapDirectory::from_config(config, prefix, data_store.clone(), create_retry)
+                        .map(DirectoryInner::Ldap)
+                }
+                "sql" => SqlDirectory::from_config(
+                    config,
+                    prefix,
+                    stores,
+                    data_store.clone(),
+                    create_retry,
+                )
+                .map(DirectoryInner::Sql),
                 "imap" => ImapDirectory::from_config(config, prefix).map(DirectoryInner::Imap),
                 "smtp" => {
                     SmtpDirectory::from_config(config, prefix, false).map(DirectoryInner::Smtp)
@@ -81,14 +90,17 @@ impl Directories {
                 "lmtp" => {
                     SmtpDirectory::from_config(config, prefix, true).map(DirectoryInner::Smtp)
                 }
-                "memory" => MemoryDirectory::from_config(config, prefix, data_store.clone())
-                    .await
-                    .map(DirectoryInner::Memory),
+                "memory" => {
+                    MemoryDirectory::from_config(config, prefix, data_store.clone(), create_retry)
+                        .await
+                        .map(DirectoryInner::Memory)
+                }
                 #[cfg(feature = "enterprise")]
                 "oidc" => crate::backend::oidc::OpenIdDirectory::from_config(
                     config,
                     prefix,
                     data_store.clone(),
+                    create_retry,
                 )
                 .map(DirectoryInner::OpenId),
                 unknown => {
@@ -108,9 +120,36 @@ impl Directories {
                     continue;
                 }
 
+                // Synchronization is only available for external directories
+                let sync = DirectorySync::try_from_config(config, ("directory", id));
+                if sync.is_some()
+                    && !matches!(store, DirectoryInner::Ldap(_) | DirectoryInner::Sql(_))
+                {
+                    let message =
+                        format!("Directory {protocol:?} does not support synchronization");
+                    config.new_parse_error(("directory", id, "sync.enable"), message);
+                    continue;
+                }
+
+                // Legacy password hashes can only be upgraded in the internal store
+                let upgrade_secrets = config
+                    .property_or_default::<bool>(
+                        ("directory", id, "password.upgrade-hash"),
+                        "false",
+                    )
+                    .unwrap_or(false);
+                if upgrade_secrets && !matches!(store, DirectoryInner::Internal(_)) {
+                    let message =
+                        format!("Directory {protocol:?} does not support password hash upgrades");
+                    config.new_parse_error(("directory", id, "password.upgrade-hash"), message);
+                    continue;
+                }
+
                 let directory = Arc::new(Directory {
                     store,
                     cache: CachedDirectory::try_from_config(config, ("directory", id)),
+                    sync,
+                    upgrade_secrets,
                 });
 
                 // Add directory
@@ -122,6 +161,33 @@ impl Directories {
     }
 }
 
+impl CreateRetry {
+    pub fn parse(config: &mut Config) -> Self {
+        let default = CreateRetry::default();
+        let retry = CreateRetry {
+            max_retries: config
+                .property("directory.create.max-retries")
+                .unwrap_or(default.max_retries),
+            backoff_min: config
+                .property("directory.create.backoff.min")
+                .unwrap_or(default.backoff_min),
+            backoff_max: config
+                .property("directory.create.backoff.max")
+                .unwrap_or(default.backoff_max),
+        };
+
+        if retry.backoff_min > retry.backoff_max {
+            config.new_build_error(
+                "directory.create.backoff.min",
+                "Minimum backoff must not exceed the maximum backoff",
+            );
+            default
+        } else {
+            retry
+        }
+    }
+}
+
 pub(crate) fn build_pool<M: Manager>(
     config: &mut Config,
     prefix: &str,
@@ -7,7 +7,10 @@
 use trc::AddContext;
 
 use crate::{
-    backend::{internal::lookup::DirectoryStore, RcptType},
+    backend::{
+        internal::{lookup::DirectoryStore, manage::ManageDirectory},
+        DomainRouting, ListPolicy, RcptType,
+    },
     Directory, DirectoryInner, Principal, QueryBy,
 };
 
@@ -18,6 +21,9 @@ impl Directory {
         return_member_of: bool,
     ) -> trc::Result<Option<Principal>> {
         match &self.store {
+            DirectoryInner::Internal(store) if self.upgrade_secrets => {
+                store.query_and_upgrade(by, return_member_of).await
+            }
             DirectoryInner::Internal(store) => store.query(by, return_member_of).await,
             DirectoryInner::Ldap(store) => store.query(by, return_member_of).await,
             DirectoryInner::Sql(store) => store.query(by, return_member_of).await,
@@ -44,6 +50,29 @@ impl Directory {
         .caused_by(trc::location!())
     }
 
+    /// Resolves multiple addresses at once, results are returned in the order
+    /// of `addresses`.
+    pub async fn email_to_ids(&self, addresses: &[&str]) -> trc::Result<Vec<Option<u32>>> {
+        match &self.store {
+            DirectoryInner::Internal(store) => {
+                store.get_principal_infos(addresses).await.map(|infos| {
+                    infos
+                        .into_iter()
+                        .map(|info| info.map(|info| info.id))
+                        .collect()
+                })
+            }
+            _ => {
+                let mut ids = Vec::with_capacity(addresses.len());
+                for address in addresses {
+                    ids.push(self.email_to_id(address).await?);
+                }
+                Ok(ids)
+            }
+        }
+        .caused_by(trc::location!())
+    }
+
     pub async fn is_local_domain(&self, domain: &str) -> trc::Result<bool> {
         // Check cache
         if let Some(cache) = &self.cache {
@@ -100,6 +129,50 @@ impl Directory {
         Ok(result)
     }
 
+    /// Routing overrides of a domain, only the internal directory stores them.
+    pub async fn domain_routing(&self, domain: &str) -> trc::Result<Option<DomainRouting>> {
+        // Check cache
+        if let Some(cache) = &self.cache {
+            if let Some(result) = cache.get_domain_routing(domain) {
+                return Ok(result);
+            }
+        }
+
+        let result = match &self.store {
+            DirectoryInner::Internal(store) => store.domain_routing(domain).await,
+            DirectoryInner::Ldap(_)
+            | DirectoryInner::Sql(_)
+            | DirectoryInner::Imap(_)
+            | DirectoryInner::Smtp(_)
+            | DirectoryInner::Memory(_) => Ok(None),
+            #[cfg(feature = "enterprise")]
+            DirectoryInner::OpenId(_) => Ok(None),
+        }
+        .caused_by(trc::location!())?;
+
+        // Update cache
+        if let Some(cache) = &self.cache {
+            cache.set_domain_routing(domain, &result);
+        }
+
+        Ok(result)
+    }
+
+    /// Posting settings of a mailing list, only the internal directory stores them.
+    pub async fn list_policy(&self, address: &str) -> trc::Result<Option<ListPolicy>> {
+        match &self.store {
+            DirectoryInner::Internal(store) => store.list_policy(address).await,
+            DirectoryInner::Ldap(_)
+            | DirectoryInner::Sql(_)
+            | DirectoryInner::Imap(_)
+            | DirectoryInner::Smtp(_)
+            | DirectoryInner::Memory(_) => Ok(None),
+            #[cfg(feature = "enterprise")]
+            DirectoryInner::OpenId(_) => Ok(None),
+        }
+        .caused_by(trc::location!())
+    }
+
     pub async fn vrfy(&self, address: &str) -> trc::Result<Vec<String>> {
         match &self.store {
             DirectoryInner::Internal(store) => store.vrfy(address).await,
@@ -11,6 +11,7 @@ pub mod config;
 pub mod dispatch;
 pub mod principal;
 pub mod secret;
+pub mod sync;
 
 impl Permission {
     pub fn description(&self) -> &'static str {
@@ -198,6 +199,68 @@ impl Permission {
             Permission::OauthClientUpdate => "Modify OAuth clients",
             Permission::OauthClientDelete => "Remove OAuth clients",
             Permission::AiModelInteract => "Interact with AI models",
+            Permission::TaskList => "View scheduled tasks and their run history",
+            Permission::TaskRun => "Run scheduled tasks on demand",
+            Permission::PrincipalActivity => "View account activity summaries",
+            Permission::CalloutCacheFlush => "Flush the SMTP callout verification cache",
+            Permission::ManageAccountSettings => "Manage own account settings",
+            Permission::TemplateGet => "View and preview generated mail templates",
+            Permission::TemplateUpdate => "Modify generated mail templates",
+            Permission::TemplateDelete => "Remove generated mail template overrides",
+            Permission::ReplicationApply => {
+                "Apply directory changes replicated from a primary server"
+            }
+            Permission::ApprovalList => "View and cancel pending approval requests",
+            Permission::ApprovalDecide => "Approve or reject operations requested by others",
+            Permission::SessionList => "View the sessions open by a principal",
+            Permission::SessionDisconnect => "Disconnect the sessions open by a principal",
+            Permission::RewriteRuleGet => "View recipient rewrite rules and test their resolution",
+            Permission::RewriteRuleUpdate => "Create or modify recipient rewrite rules",
+            Permission::RewriteRuleDelete => "Delete recipient rewrite rules",
+            Permission::MailboxTreeGet => "View the mailbox tree of an account",
+            Permission::MailboxTreeUpdate => "Rename or move mailboxes of an account",
+            Permission::MailboxTreeDelete => "Delete mailboxes of an account",
+            Permission::UndoList => "View the log of recent directory operations",
+            Permission::UndoApply => "Revert recorded directory operations",
+            Permission::VolumeStatsGet => "View and export message volume statistics",
+            Permission::SenderReputationGet => "View the outbound reputation of accounts",
+            Permission::SenderReputationClear => {
+                "Clear the automatic restrictions applied to accounts with a poor reputation"
+            }
+            Permission::SenderReputationExempt => {
+                "Exempt from automatic restrictions due to a poor sender reputation"
+            }
+            Permission::MessageRecall => "Recall delivered messages from the mailboxes of accounts",
+            Permission::PrivacyModeGet => "View the privacy mode of tracers",
+            Permission::PrivacyModeUpdate => "Change the privacy mode of tracers at runtime",
+            Permission::DuplicateReportGet => {
+                "View duplicate messages and attachments of an account"
+            }
+            Permission::MessageQueueReview => "Release or reject messages held for review",
+            Permission::FtsVerify => "Verify the full-text search index",
+            Permission::TlsUsageReport => "View remote hosts using outdated TLS versions",
+            Permission::TenantExport => "Export all data belonging to a tenant",
+            Permission::ManageCollectedRecipients => {
+                "Query and remove own collected recipient addresses"
+            }
+            Permission::DelegationList => "View domain administration delegations",
+            Permission::DelegationUpdate => "Grant and revoke domain administration delegations",
+            Permission::PrincipalExport => "Export principals for backup or migration",
+            Permission::PrincipalImport => "Import principals from an export",
+            Permission::MaintenanceMode => "Enable or disable read-only maintenance mode",
+            Permission::RecipientExpand => "Preview the final recipients of an address",
+            Permission::EffectivePermissionsGet => "View the effective permissions of a principal",
+            Permission::LitigationHold => "Place, release and report litigation holds on accounts",
+            Permission::TlsCertificateStatus => "View the certificates served by each listener",
+            Permission::DirectoryVerify => "Verify and repair the consistency of the directory",
+            Permission::JmapThreadRuleGet => "Retrieve thread rules via JMAP",
+            Permission::JmapThreadRuleSet => "Create, modify or delete thread rules via JMAP",
+            Permission::ManageThreadRules => "View and remove own thread rules",
+            Permission::LockoutGet => "View the failed login lockout status of a principal",
+            Permission::LockoutClear => "Unlock principals locked after failed logins",
+            Permission::MembershipList => "View own group and mailing list memberships",
+            Permission::ListModerate => "Review posts held for the mailing lists one moderates",
+            Permission::QuotaRecalculate => "Recalculate the used quota of accounts",
         }
     }
 }
@@ -47,6 +47,19 @@ impl Principal {
         self.get_int(PrincipalField::Quota).unwrap_or_default()
     }
 
+    pub fn message_quota(&self) -> u64 {
+        // Accounts store their message count limit in the second quota slot,
+        // tenants use the remaining slots for principal counts
+        if matches!(self.typ, Type::Individual | Type::Group) {
+            self.get_int_array(PrincipalField::Quota)
+                .and_then(|quotas| quotas.get(1))
+                .copied()
+                .unwrap_or_default()
+        } else {
+            0
+        }
+    }
+
     // SPDX-SnippetBegin
     // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
     // SPDX-License-Identifier: LicenseRef-SEL
@@ -55,6 +68,19 @@ impl Principal {
     }
     // SPDX-SnippetEnd
 
+    pub fn is_enabled(&self) -> bool {
+        self.get_int(PrincipalField::Enabled).is_none_or(|v| v != 0)
+    }
+
+    pub fn is_on_hold(&self) -> bool {
+        self.get_int(PrincipalField::Hold).is_some_and(|v| v != 0)
+    }
+
+    pub fn credentials_epoch(&self) -> u64 {
+        self.get_int(PrincipalField::CredentialsEpoch)
+            .unwrap_or_default()
+    }
+
     pub fn description(&self) -> Option<&str> {
         self.get_str(PrincipalField::Description)
     }
@@ -593,6 +619,8 @@ impl Type {
             Self::Domain => "domain",
             Self::ApiKey => "apiKey",
             Self::OauthClient => "oauthClient",
+            Self::Service => "service",
+            Self::Alias => "alias",
         }
     }
 
@@ -609,6 +637,8 @@ impl Type {
             Self::Domain => "Domain",
             Self::ApiKey => "API Key",
             Self::OauthClient => "OAuth Client",
+            Self::Service => "Service Account",
+            Self::Alias => "Alias",
         }
     }
 
@@ -625,6 +655,8 @@ impl Type {
             "domain" => Some(Type::Domain),
             "apiKey" => Some(Type::ApiKey),
             "oauthClient" => Some(Type::OauthClient),
+            "service" => Some(Type::Service),
+            "alias" => Some(Type::Alias),
             _ => None,
         }
     }
@@ -643,6 +675,8 @@ impl Type {
             9 => Type::Role,
             10 => Type::ApiKey,
             11 => Type::OauthClient,
+            12 => Type::Service,
+            13 => Type::Alias,
             _ => Type::Other,
         }
     }
@@ -668,6 +702,11 @@ impl serde::Serialize for Principal {
 
         for (key, value) in &self.fields {
             match value {
+                PrincipalValue::Integer(v)
+                    if matches!(key, PrincipalField::Enabled | PrincipalField::ReplyToList) =>
+                {
+                    map.serialize_entry(key.as_str(), &(*v != 0))?
+                }
                 PrincipalValue::String(v) => map.serialize_entry(key.as_str(), v)?,
                 PrincipalValue::StringList(v) => map.serialize_entry(key.as_str(), v)?,
                 PrincipalValue::Integer(v) => map.serialize_entry(key.as_str(), v)?,
@@ -716,6 +755,13 @@ impl<'de> serde::Deserialize<'de> for PrincipalValue {
                 Ok(PrincipalValue::Integer(value))
             }
 
+            fn visit_bool<E>(self, value: bool) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(PrincipalValue::Integer(value as u64))
+            }
+
             fn visit_string<E>(self, value: String) -> Result<Self::Value, E>
             where
                 E: de::Error,
@@ -818,7 +864,13 @@ impl<'de> serde::Deserialize<'de> for Principal {
                         }
                         PrincipalField::Description
                         | PrincipalField::Tenant
-                        | PrincipalField::Picture => {
+                        | PrincipalField::Picture
+                        | PrincipalField::ExternalId
+                        | PrincipalField::CatchAll
+                        | PrincipalField::Subaddressing
+                        | PrincipalField::PostingPolicy
+                        | PrincipalField::Locale
+                        | PrincipalField::Timezone => {
                             if let Some(v) = map.next_value::<Option<String>>()? {
                                 if v.len() <= MAX_STRING_LEN {
                                     PrincipalValue::String(v)
@@ -835,7 +887,23 @@ impl<'de> serde::Deserialize<'de> for Principal {
                             })?;
                             continue;
                         }
-                        PrincipalField::Quota => map.next_value::<PrincipalValue>()?,
+                        PrincipalField::Quota | PrincipalField::Hold => {
+                            map.next_value::<PrincipalValue>()?
+                        }
+                        PrincipalField::Enabled => {
+                            if map.next_value::<bool>()? {
+                                continue;
+                            } else {
+                                PrincipalValue::Integer(0)
+                            }
+                        }
+                        PrincipalField::ReplyToList => {
+                            if map.next_value::<bool>()? {
+                                PrincipalValue::Integer(1)
+                            } else {
+                                continue;
+                            }
+                        }
                         PrincipalField::Secrets
                         | PrincipalField::Emails
                         | PrincipalField::MemberOf
@@ -845,19 +913,24 @@ impl<'de> serde::Deserialize<'de> for Principal {
                         | PrincipalField::EnabledPermissions
                         | PrincipalField::DisabledPermissions
                         | PrincipalField::Urls
-                        | PrincipalField::ExternalMembers => {
-                            match map.next_value::<StringOrMany>()? {
-                                StringOrMany::One(v) => PrincipalValue::StringList(vec![v]),
-                                StringOrMany::Many(v) => {
-                                    if !v.is_empty() {
-                                        PrincipalValue::StringList(v)
-                                    } else {
-                                        continue;
-                                    }
+                        | PrincipalField::ExternalMembers
+                        | PrincipalField::ForwardTo
+                        | PrincipalField::DefaultRoles
+                        | PrincipalField::DefaultPermissions
+                        | PrincipalField::CertificateFingerprints
+                        | PrincipalField::Moderators => match map.next_value::<StringOrMany>()? {
+                            StringOrMany::One(v) => PrincipalValue::StringList(vec![v]),
+                            StringOrMany::Many(v) => {
+                                if !v.is_empty() {
+                                    PrincipalValue::StringList(v)
+                                } else {
+                                    continue;
                                 }
                             }
-                        }
-                        PrincipalField::UsedQuota => {
+                        },
+                        PrincipalField::UsedQuota
+                        | PrincipalField::LastLogin
+                        | PrincipalField::CredentialsEpoch => {
                             // consume and ignore
                             map.next_value::<IgnoredAny>()?;
                             continue;
@@ -1003,6 +1076,11 @@ impl Permission {
                 | Permission::EmailReceive
                 | Permission::ManageEncryption
                 | Permission::ManagePasswords
+                | Permission::ManageAccountSettings
+                | Permission::ManageCollectedRecipients
+                | Permission::ManageThreadRules
+                | Permission::MembershipList
+                | Permission::ListModerate
                 | Permission::JmapEmailGet
                 | Permission::JmapMailboxGet
                 | Permission::JmapThreadGet
@@ -1012,6 +1090,7 @@ impl Permission {
                 | Permission::JmapSieveScriptGet
                 | Permission::JmapVacationResponseGet
                 | Permission::JmapQuotaGet
+                | Permission::JmapThreadRuleGet
                 | Permission::JmapBlobGet
                 | Permission::JmapEmailSet
                 | Permission::JmapMailboxSet
@@ -1020,6 +1099,7 @@ impl Permission {
                 | Permission::JmapPushSubscriptionSet
                 | Permission::JmapSieveScriptSet
                 | Permission::JmapVacationResponseSet
+                | Permission::JmapThreadRuleSet
                 | Permission::JmapEmailChanges
                 | Permission::JmapMailboxChanges
                 | Permission::JmapThreadChanges
@@ -1149,6 +1229,33 @@ impl Permission {
                 | Permission::ApiKeyCreate
                 | Permission::ApiKeyUpdate
                 | Permission::ApiKeyDelete
+                | Permission::PrincipalActivity
+                | Permission::TemplateGet
+                | Permission::TemplateUpdate
+                | Permission::TemplateDelete
+                | Permission::ApprovalList
+                | Permission::ApprovalDecide
+                | Permission::SessionList
+                | Permission::SessionDisconnect
+                | Permission::LockoutGet
+                | Permission::LockoutClear
+                | Permission::MailboxTreeGet
+                | Permission::MailboxTreeUpdate
+                | Permission::MailboxTreeDelete
+                | Permission::UndoList
+                | Permission::UndoApply
+                | Permission::VolumeStatsGet
+                | Permission::SenderReputationGet
+                | Permission::SenderReputationClear
+                | Permission::MessageRecall
+                | Permission::DuplicateReportGet
+                | Permission::MessageQueueReview
+                | Permission::DelegationList
+                | Permission::DelegationUpdate
+                | Permission::PrincipalExport
+                | Permission::PrincipalImport
+                | Permission::RecipientExpand
+                | Permission::EffectivePermissionsGet
         ) || self.is_user_permission()
     }
 
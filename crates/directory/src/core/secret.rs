@@ -7,7 +7,7 @@
 use argon2::Argon2;
 use mail_builder::encoders::base64::base64_encode;
 use mail_parser::decoders::base64::base64_decode;
-use password_hash::PasswordHash;
+use password_hash::{PasswordHash, PasswordHasher, SaltString};
 use pbkdf2::Pbkdf2;
 use pwhash::{bcrypt, bsdi_crypt, md5_crypt, sha1_crypt, sha256_crypt, sha512_crypt, unix_crypt};
 use scrypt::Scrypt;
@@ -15,12 +15,16 @@ use sha1::Digest;
 use sha1::Sha1;
 use sha2::Sha256;
 use sha2::Sha512;
+use store::rand::{rngs::OsRng, Rng};
 use tokio::sync::oneshot;
 use totp_rs::TOTP;
 
+use crate::backend::internal::AppScope;
 use crate::backend::internal::PrincipalField;
 use crate::backend::internal::SpecialSecrets;
+use crate::backend::internal::RECOVERY_CODE_PREFIX;
 use crate::Principal;
+use crate::Type;
 
 impl Principal {
     pub async fn verify_secret(&self, mut code: &str) -> trc::Result<bool> {
@@ -63,12 +67,14 @@ impl Principal {
                         .check_current(totp_token)
                         .unwrap_or(false);
                 }
+            } else if secret.is_recovery_code() {
+                // Recovery codes are verified separately as they are consumed on use
+                continue;
             } else if !is_authenticated && !is_app_authenticated {
-                if let Some((_, app_secret)) =
-                    secret.strip_prefix("$app$").and_then(|s| s.split_once('$'))
-                {
-                    is_app_authenticated = verify_secret_hash(app_secret, code).await?;
-                } else {
+                if let Some(app_password) = secret.app_password() {
+                    is_app_authenticated = verify_secret_hash(app_password.secret, code).await?;
+                } else if self.typ != Type::Service {
+                    // Service accounts only authenticate with API tokens
                     is_authenticated = verify_secret_hash(secret, code).await?;
                 }
             }
@@ -97,7 +103,10 @@ impl Principal {
             if is_totp_verified {
                 // TOTP URL appeared after password hash in secrets list
                 for secret in self.iter_str(PrincipalField::Secrets) {
-                    if secret.is_password() && verify_secret_hash(secret, code).await? {
+                    if secret.is_password()
+                        && self.typ != Type::Service
+                        && verify_secret_hash(secret, code).await?
+                    {
                         return Ok(true);
                     }
                 }
@@ -106,6 +115,190 @@ impl Principal {
             Ok(false)
         }
     }
+
+    /// Returns `false` when the code matches an app password that is not
+    /// valid for the protocol, the code must have been verified beforehand.
+    pub async fn verify_scope(&self, code: &str, scope: AppScope) -> trc::Result<bool> {
+        // Avoid verifying the code again when all app passwords are allowed
+        if !self
+            .iter_str(PrincipalField::Secrets)
+            .filter_map(|secret| secret.app_password())
+            .any(|app_password| !app_password.allows(scope))
+        {
+            return Ok(true);
+        }
+
+        let mut is_denied = false;
+        for secret in self.iter_str(PrincipalField::Secrets) {
+            if let Some(app_password) = secret.app_password() {
+                if verify_secret_hash(app_password.secret, code).await? {
+                    if app_password.allows(scope) {
+                        return Ok(true);
+                    }
+                    is_denied = true;
+                }
+            }
+        }
+
+        // Reject the code unless it is also the account password
+        if is_denied {
+            for secret in self.iter_str(PrincipalField::Secrets) {
+                if secret.is_password()
+                    && self.typ != Type::Service
+                    && verify_secret_hash(secret, code).await?
+                {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(!is_denied)
+    }
+
+    /// Verifies a code of the form `<password>$<recovery code>`, where the
+    /// recovery code replaces the TOTP token, and returns the matching
+    /// recovery code secret. The caller is responsible for consuming it.
+    pub async fn verify_recovery_code(&self, code: &str) -> trc::Result<Option<&str>> {
+        if self.typ == Type::Service
+            || !self
+                .iter_str(PrincipalField::Secrets)
+                .any(|secret| secret.is_otp_auth())
+        {
+            return Ok(None);
+        }
+
+        let Some((password, token)) = code
+            .rsplit_once('$')
+            .filter(|(c, t)| !c.is_empty() && is_recovery_token(t))
+        else {
+            return Ok(None);
+        };
+
+        let mut is_authenticated = false;
+        for secret in self.iter_str(PrincipalField::Secrets) {
+            if secret.is_password() && verify_secret_hash(secret, password).await? {
+                is_authenticated = true;
+                break;
+            }
+        }
+
+        if is_authenticated {
+            let token = token.to_ascii_lowercase();
+            for secret in self.iter_str(PrincipalField::Secrets) {
+                if let Some(hash) = secret.strip_prefix(RECOVERY_CODE_PREFIX) {
+                    if verify_secret_hash(hash, &token).await? {
+                        return Ok(Some(secret.as_str()));
+                    }
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the password hash stored using a legacy scheme that matches
+    /// the code along with the plain text password, the code must have been
+    /// verified beforehand.
+    pub async fn legacy_secret<'x>(
+        &'x self,
+        code: &'x str,
+    ) -> trc::Result<Option<(&'x str, &'x str)>> {
+        if self.typ == Type::Service {
+            return Ok(None);
+        }
+
+        // Remove the TOTP token, if present
+        let code = if self
+            .iter_str(PrincipalField::Secrets)
+            .any(|secret| secret.is_otp_auth())
+        {
+            code.rsplit_once('$')
+                .filter(|(c, t)| {
+                    !c.is_empty()
+                        && (6..=8).contains(&t.len())
+                        && t.as_bytes().iter().all(|b| b.is_ascii_digit())
+                })
+                .map_or(code, |(c, _)| c)
+        } else {
+            code
+        };
+
+        for secret in self.iter_str(PrincipalField::Secrets) {
+            if secret.is_password()
+                && is_legacy_hash(secret)
+                && verify_secret_hash(secret, code).await?
+            {
+                return Ok(Some((secret.as_str(), code)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// Generates a recovery code formatted as two groups of five characters.
+pub fn generate_recovery_code() -> String {
+    let mut rng = store::rand::thread_rng();
+    let mut code = String::with_capacity(RECOVERY_CODE_LEN + 1);
+    for pos in 0..RECOVERY_CODE_LEN {
+        if pos == RECOVERY_CODE_LEN / 2 {
+            code.push('-');
+        }
+        code.push(RECOVERY_CODE_ALPHABET[rng.gen_range(0..RECOVERY_CODE_ALPHABET.len())] as char);
+    }
+    code
+}
+
+fn is_recovery_token(token: &str) -> bool {
+    token.len() == RECOVERY_CODE_LEN + 1
+        && token.char_indices().all(|(pos, ch)| {
+            if pos == RECOVERY_CODE_LEN / 2 {
+                ch == '-'
+            } else {
+                ch.is_ascii_alphanumeric()
+            }
+        })
+}
+
+/// MD5, SHA-1 and DES based schemes are considered legacy.
+pub fn is_legacy_hash(hashed_secret: &str) -> bool {
+    if let Some(hashed_secret) = hashed_secret.strip_prefix('{') {
+        hashed_secret.split_once('}').is_some_and(|(algo, hash)| {
+            matches!(algo, "SHA" | "SSHA" | "MD5")
+                || (matches!(algo, "CRYPT" | "crypt")
+                    && (!hash.starts_with('$') || is_legacy_hash(hash)))
+        })
+    } else {
+        hashed_secret.starts_with("$1$")
+            || hashed_secret.starts_with("$sha1")
+            || hashed_secret.starts_with('_')
+    }
+}
+
+/// Hashes a secret using Argon2id with the default parameters.
+pub async fn hash_secret(secret: &str) -> trc::Result<String> {
+    let (tx, rx) = oneshot::channel();
+    let secret = secret.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        tx.send(
+            Argon2::default()
+                .hash_password(secret.as_bytes(), &SaltString::generate(&mut OsRng))
+                .map(|hash| hash.to_string())
+                .map_err(|err| trc::AuthEvent::Error.reason(err)),
+        )
+        .ok();
+    });
+
+    match rx.await {
+        Ok(result) => result,
+        Err(err) => Err(trc::EventType::Server(trc::ServerEvent::ThreadError)
+            .caused_by(trc::location!())
+            .reason(err)),
+    }
 }
 
 async fn verify_hash_prefix(hashed_secret: &str, secret: &str) -> trc::Result<bool> {
@@ -0,0 +1,239 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use ahash::AHashSet;
+use store::{
+    write::{BatchBuilder, Bincode, TaskClass, ValueClass},
+    Serialize, ValueKey,
+};
+use trc::AddContext;
+use utils::config::{
+    utils::{AsKey, ParseValue},
+    Config,
+};
+
+use crate::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Directory, DirectoryInner, QueryBy,
+};
+
+#[derive(Debug, Clone)]
+pub struct DirectorySync {
+    pub interval: Duration,
+    pub delete_policy: SyncDeletePolicy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncDeletePolicy {
+    Keep,
+    Disable,
+    Delete,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncResult {
+    pub created: u64,
+    pub updated: u64,
+    pub removed: u64,
+}
+
+/// Principals imported by a previous run, used to detect entries that vanished
+/// from the external directory.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct SyncState {
+    synced: Vec<u32>,
+    disabled: Vec<u32>,
+}
+
+impl DirectorySync {
+    pub fn try_from_config(config: &mut Config, prefix: impl AsKey) -> Option<Self> {
+        let prefix = prefix.as_key();
+        if !config
+            .property_or_default::<bool>((&prefix, "sync.enable"), "false")
+            .unwrap_or_default()
+        {
+            return None;
+        }
+
+        Some(DirectorySync {
+            interval: config
+                .property_or_default((&prefix, "sync.interval"), "1h")
+                .unwrap_or_else(|| Duration::from_secs(3600)),
+            delete_policy: config
+                .property_or_default((&prefix, "sync.delete-policy"), "keep")
+                .unwrap_or(SyncDeletePolicy::Keep),
+        })
+    }
+}
+
+impl Directory {
+    pub async fn synchronize(&self, id: &str) -> trc::Result<SyncResult> {
+        let mut result = SyncResult::default();
+        let Some(sync) = &self.sync else {
+            return Ok(result);
+        };
+        let (principals, data_store, create_retry) = match &self.store {
+            DirectoryInner::Ldap(store) => (
+                store.list_principals().await.caused_by(trc::location!())?,
+                &store.data_store,
+                store.create_retry,
+            ),
+            DirectoryInner::Sql(store) => (
+                store.list_principals().await.caused_by(trc::location!())?,
+                &store.data_store,
+                store.create_retry,
+            ),
+            _ => {
+                return Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details("Directory does not support synchronization")
+                    .ctx(trc::Key::Id, id.to_string()));
+            }
+        };
+        let state_key = format!("directory-sync:{id}").into_bytes();
+        let state = data_store
+            .get_value::<Bincode<SyncState>>(ValueKey::from(ValueClass::Task(TaskClass::State(
+                state_key.clone(),
+            ))))
+            .await
+            .caused_by(trc::location!())?
+            .map(|state| state.inner)
+            .unwrap_or_default();
+        let mut disabled = AHashSet::from_iter(state.disabled);
+        let mut seen = AHashSet::with_capacity(principals.len());
+        let is_empty = principals.is_empty();
+
+        // Create or update principals present in the external directory
+        for external in principals {
+            let (principal_id, is_new) = match data_store
+                .get_principal_id(external.name())
+                .await
+                .caused_by(trc::location!())?
+            {
+                Some(principal_id) => (principal_id, false),
+                None => (
+                    data_store
+                        .get_or_create_principal_id(external.name(), external.typ(), create_retry)
+                        .await
+                        .caused_by(trc::location!())?,
+                    true,
+                ),
+            };
+            if !seen.insert(principal_id) {
+                continue;
+            }
+            let Some(mut principal) = data_store
+                .query(QueryBy::Id(principal_id), false)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+
+            let mut changes = principal.update_external(external);
+            let has_changes = !changes.is_empty();
+            if disabled.remove(&principal_id) {
+                changes.push(PrincipalUpdate::set(
+                    PrincipalField::Enabled,
+                    PrincipalValue::Integer(1),
+                ));
+            }
+            if !changes.is_empty() {
+                data_store
+                    .update_principal(
+                        UpdatePrincipal::by_id(principal_id)
+                            .with_updates(changes)
+                            .create_domains(),
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+
+            if is_new {
+                result.created += 1;
+            } else if has_changes {
+                result.updated += 1;
+            }
+        }
+
+        // An empty result is more likely a misconfigured filter or query than a
+        // directory without entries, vanished principals are left untouched.
+        if !is_empty {
+            for principal_id in state.synced {
+                if seen.contains(&principal_id) || disabled.contains(&principal_id) {
+                    continue;
+                }
+                if data_store
+                    .get_principal(principal_id)
+                    .await
+                    .caused_by(trc::location!())?
+                    .is_none()
+                {
+                    continue;
+                }
+
+                match sync.delete_policy {
+                    SyncDeletePolicy::Keep => continue,
+                    SyncDeletePolicy::Disable => {
+                        data_store
+                            .update_principal(UpdatePrincipal::by_id(principal_id).with_updates(
+                                vec![PrincipalUpdate::set(
+                                    PrincipalField::Enabled,
+                                    PrincipalValue::Integer(0),
+                                )],
+                            ))
+                            .await
+                            .caused_by(trc::location!())?;
+                        disabled.insert(principal_id);
+                    }
+                    SyncDeletePolicy::Delete => {
+                        data_store
+                            .delete_principal(QueryBy::Id(principal_id))
+                            .await
+                            .caused_by(trc::location!())?;
+                    }
+                }
+                result.removed += 1;
+            }
+        } else {
+            seen.extend(state.synced);
+        }
+
+        // Persist the set of synchronized principals for the next run
+        let state = SyncState {
+            synced: seen.into_iter().collect(),
+            disabled: disabled.into_iter().collect(),
+        };
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(state_key)),
+            Bincode::new(state).serialize(),
+        );
+        data_store
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(result)
+    }
+}
+
+impl ParseValue for SyncDeletePolicy {
+    fn parse_value(value: &str) -> Result<Self, String> {
+        match value {
+            "keep" => Ok(SyncDeletePolicy::Keep),
+            "disable" => Ok(SyncDeletePolicy::Disable),
+            "delete" => Ok(SyncDeletePolicy::Delete),
+            _ => Err(format!("Invalid synchronization delete policy {value:?}.")),
+        }
+    }
+}
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use core::cache::CachedDirectory;
+use core::{cache::CachedDirectory, sync::DirectorySync};
 use std::{fmt::Debug, sync::Arc};
 
 use ahash::AHashMap;
@@ -29,6 +29,8 @@ pub mod core;
 pub struct Directory {
     pub store: DirectoryInner,
     pub cache: Option<CachedDirectory>,
+    pub sync: Option<DirectorySync>,
+    pub upgrade_secrets: bool,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -39,7 +41,9 @@ pub struct Principal {
     pub(crate) fields: AHashMap<PrincipalField, PrincipalValue>,
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
 #[serde(rename_all = "camelCase")]
 pub enum Type {
     #[default]
@@ -54,9 +58,11 @@ pub enum Type {
     Role = 9,
     ApiKey = 10,
     OauthClient = 11,
+    Service = 12,
+    Alias = 13,
 }
 
-pub const MAX_TYPE_ID: usize = 11;
+pub const MAX_TYPE_ID: usize = 13;
 
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, EnumMethods,
@@ -264,6 +270,68 @@ pub enum Permission {
     OauthClientOverride,
 
     AiModelInteract,
+
+    // Scheduled tasks
+    TaskList,
+    TaskRun,
+
+    // Support
+    PrincipalActivity,
+
+    // SMTP callouts
+    CalloutCacheFlush,
+
+    // Self-service
+    ManageAccountSettings,
+
+    // Generated mail templates
+    TemplateGet,
+    TemplateUpdate,
+    TemplateDelete,
+    ReplicationApply,
+    ApprovalList,
+    ApprovalDecide,
+    SessionList,
+    SessionDisconnect,
+    RewriteRuleGet,
+    RewriteRuleUpdate,
+    RewriteRuleDelete,
+    MailboxTreeGet,
+    MailboxTreeUpdate,
+    MailboxTreeDelete,
+    UndoList,
+    UndoApply,
+    VolumeStatsGet,
+    SenderReputationGet,
+    SenderReputationClear,
+    SenderReputationExempt,
+    MessageRecall,
+    PrivacyModeGet,
+    PrivacyModeUpdate,
+    DuplicateReportGet,
+    MessageQueueReview,
+    FtsVerify,
+    TlsUsageReport,
+    TenantExport,
+    ManageCollectedRecipients,
+    DelegationList,
+    DelegationUpdate,
+    PrincipalExport,
+    PrincipalImport,
+    MaintenanceMode,
+    RecipientExpand,
+    EffectivePermissionsGet,
+    LitigationHold,
+    TlsCertificateStatus,
+    DirectoryVerify,
+    JmapThreadRuleGet,
+    JmapThreadRuleSet,
+    ManageThreadRules,
+    LockoutGet,
+    LockoutClear,
+    MembershipList,
+    ListModerate,
+    QuotaRecalculate,
     // WARNING: add new ids at the end (TODO: use static ids)
 }
 
@@ -288,6 +356,16 @@ pub enum QueryBy<'x> {
     Name(&'x str),
     Id(u32),
     Credentials(&'x Credentials<String>),
+    ExternalId(&'x str),
+    Certificate(&'x ClientCertificate),
+}
+
+/// Identity presented by a TLS client certificate verified by the listener.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientCertificate {
+    pub subject: Option<String>,
+    pub emails: Vec<String>,
+    pub fingerprint: String,
 }
 
 impl Default for Directory {
@@ -295,6 +373,8 @@ impl Default for Directory {
         Self {
             store: DirectoryInner::Internal(Store::None),
             cache: None,
+            sync: None,
+            upgrade_secrets: false,
         }
     }
 }
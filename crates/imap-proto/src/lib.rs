@@ -128,6 +128,7 @@ pub enum ResponseCode {
     ReadOnly,
     ReadWrite,
     ServerBug,
+    TooBig,
     TryCreate,
     UidNext,
     UidNotSticky,
@@ -232,6 +233,15 @@ impl StatusResponse {
         }
     }
 
+    pub fn preauth(message: impl Into<Cow<'static, str>>) -> Self {
+        StatusResponse {
+            tag: None,
+            code: None,
+            message: message.into(),
+            rtype: ResponseType::PreAuth,
+        }
+    }
+
     pub fn bye(message: impl Into<Cow<'static, str>>) -> Self {
         StatusResponse {
             tag: None,
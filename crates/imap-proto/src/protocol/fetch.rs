@@ -9,7 +9,7 @@ use std::borrow::Cow;
 use mail_parser::DateTime;
 
 use super::{
-    literal_string, quoted_or_literal_string, quoted_or_literal_string_or_nil,
+    literal_header, literal_string, quoted_or_literal_string, quoted_or_literal_string_or_nil,
     quoted_rfc2822_or_nil, quoted_timestamp, Flag, ImapResponse, Sequence,
 };
 
@@ -97,6 +97,11 @@ pub enum DataItem<'x> {
         origin_octet: Option<u32>,
         contents: Cow<'x, [u8]>,
     },
+    BodySectionStream {
+        sections: Vec<Section>,
+        origin_octet: Option<u32>,
+        size: usize,
+    },
     Envelope {
         envelope: Envelope<'x>,
     },
@@ -112,6 +117,9 @@ pub enum DataItem<'x> {
     Rfc822 {
         contents: Cow<'x, [u8]>,
     },
+    Rfc822Stream {
+        size: usize,
+    },
     Rfc822Header {
         contents: Cow<'x, [u8]>,
     },
@@ -783,6 +791,27 @@ impl DataItem<'_> {
                 }
                 literal_string(buf, contents);
             }
+            DataItem::BodySectionStream {
+                sections,
+                origin_octet,
+                size,
+            } => {
+                buf.extend_from_slice(b"BODY[");
+                for (pos, section) in sections.iter().enumerate() {
+                    if pos > 0 {
+                        buf.push(b'.');
+                    }
+                    section.serialize(buf);
+                }
+                if let Some(origin_octet) = origin_octet {
+                    buf.extend_from_slice(b"]<");
+                    buf.extend_from_slice(origin_octet.to_string().as_bytes());
+                    buf.extend_from_slice(b"> ");
+                } else {
+                    buf.extend_from_slice(b"] ");
+                }
+                literal_header(buf, *size);
+            }
             DataItem::Envelope { envelope } => {
                 buf.extend_from_slice(b"ENVELOPE ");
                 envelope.serialize(buf);
@@ -809,6 +838,10 @@ impl DataItem<'_> {
                 buf.extend_from_slice(b"RFC822 ");
                 literal_string(buf, contents);
             }
+            DataItem::Rfc822Stream { size } => {
+                buf.extend_from_slice(b"RFC822 ");
+                literal_header(buf, *size);
+            }
             DataItem::Rfc822Header { contents } => {
                 buf.extend_from_slice(b"RFC822.HEADER ");
                 literal_string(buf, contents);
@@ -846,10 +879,24 @@ impl DataItem<'_> {
             }
         }
     }
+
+    pub fn is_stream(&self) -> bool {
+        matches!(
+            self,
+            DataItem::BodySectionStream { .. } | DataItem::Rfc822Stream { .. }
+        )
+    }
 }
 
 impl FetchItem<'_> {
     pub fn serialize(&self, buf: &mut Vec<u8>) {
+        let next = self.serialize_head(buf);
+        self.serialize_tail(buf, next);
+    }
+
+    // Serializes items up to and including the first streamed literal, whose
+    // contents are to be written by the caller before calling serialize_tail.
+    pub fn serialize_head(&self, buf: &mut Vec<u8>) -> usize {
         buf.extend_from_slice(b"* ");
         buf.extend_from_slice(self.id.to_string().as_bytes());
         buf.extend_from_slice(b" FETCH (");
@@ -858,6 +905,17 @@ impl FetchItem<'_> {
                 buf.push(b' ');
             }
             item.serialize(buf);
+            if item.is_stream() {
+                return pos + 1;
+            }
+        }
+        self.items.len()
+    }
+
+    pub fn serialize_tail(&self, buf: &mut Vec<u8>, from: usize) {
+        for item in self.items.iter().skip(from) {
+            buf.push(b' ');
+            item.serialize(buf);
         }
         buf.extend_from_slice(b")\r\n");
     }
@@ -1402,4 +1460,40 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn serialize_fetch_stream() {
+        let item = FetchItem {
+            id: 7,
+            items: vec![
+                super::DataItem::Uid { uid: 12 },
+                super::DataItem::BodySectionStream {
+                    sections: vec![],
+                    origin_octet: 5000.into(),
+                    size: 0,
+                },
+                super::DataItem::Rfc822Size { size: 1457 },
+            ],
+        };
+        let mut buf = Vec::new();
+        let next = item.serialize_head(&mut buf);
+        assert_eq!(next, 2);
+        assert_eq!(
+            String::from_utf8(buf.clone()).unwrap(),
+            "* 7 FETCH (UID 12 BODY[]<5000> {0}\r\n"
+        );
+        item.serialize_tail(&mut buf, next);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "* 7 FETCH (UID 12 BODY[]<5000> {0}\r\n RFC822.SIZE 1457)\r\n"
+        );
+
+        let mut buf = Vec::new();
+        FetchItem {
+            id: 1,
+            items: vec![super::DataItem::Rfc822Stream { size: 3 }],
+        }
+        .serialize_head(&mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "* 1 FETCH (RFC822 {3}\r\n");
+    }
 }
@@ -192,10 +192,14 @@ pub fn quoted_string_or_nil(buf: &mut Vec<u8>, text: Option<&str>) {
 }
 
 pub fn literal_string(buf: &mut Vec<u8>, text: &[u8]) {
+    literal_header(buf, text.len());
+    buf.extend_from_slice(text);
+}
+
+pub fn literal_header(buf: &mut Vec<u8>, size: usize) {
     buf.push(b'{');
-    buf.extend_from_slice(text.len().to_string().as_bytes());
+    buf.extend_from_slice(size.to_string().as_bytes());
     buf.extend_from_slice(b"}\r\n");
-    buf.extend_from_slice(text);
 }
 
 pub fn quoted_timestamp(buf: &mut Vec<u8>, timestamp: i64) {
@@ -356,6 +360,7 @@ impl ResponseCode {
             ResponseCode::ReadOnly => b"READ-ONLY",
             ResponseCode::ReadWrite => b"READ-WRITE",
             ResponseCode::ServerBug => b"SERVERBUG",
+            ResponseCode::TooBig => b"TOOBIG",
             ResponseCode::TryCreate => b"TRYCREATE",
             ResponseCode::UidNext => b"UIDNEXT",
             ResponseCode::UidNotSticky => b"UIDNOTSTICKY",
@@ -412,6 +417,7 @@ impl ResponseCode {
             ResponseCode::ReadOnly => "READ-ONLY",
             ResponseCode::ReadWrite => "READ-WRITE",
             ResponseCode::ServerBug => "SERVERBUG",
+            ResponseCode::TooBig => "TOOBIG",
             ResponseCode::TryCreate => "TRYCREATE",
             ResponseCode::UidNext => "UIDNEXT",
             ResponseCode::UidNotSticky => "UIDNOTSTICKY",
@@ -505,6 +511,12 @@ impl SerializeResponse for trc::Error {
                     Some(ResponseCode::OverQuota.as_str())
                 }
                 trc::EventType::Limit(_) => Some(ResponseCode::Limit.as_str()),
+                trc::EventType::Auth(trc::AuthEvent::AccountDisabled) => {
+                    Some(ResponseCode::ContactAdmin.as_str())
+                }
+                trc::EventType::Auth(trc::AuthEvent::AccountLocked) => {
+                    Some(ResponseCode::Unavailable.as_str())
+                }
                 trc::EventType::Auth(_) => Some(ResponseCode::AuthenticationFailed.as_str()),
                 trc::EventType::Security(_) => Some(ResponseCode::AuthorizationFailed.as_str()),
                 _ => None,
@@ -8,6 +8,9 @@ use std::fmt::Display;
 
 use super::{ResponseCode, ResponseType};
 
+/// Maximum size of a non-synchronizing literal under LITERAL- (RFC 7888)
+pub const MAX_NON_SYNC_LITERAL: usize = 4096;
+
 #[derive(Debug, Clone)]
 pub enum Error {
     NeedsMoreData,
@@ -68,7 +71,9 @@ pub struct Receiver<T: CommandParser> {
     pub state: State,
     pub max_request_size: usize,
     pub current_request_size: usize,
+    pub max_non_sync_size: usize,
     pub start_state: State,
+    discard: Option<String>,
 }
 
 impl<T: CommandParser> Receiver<T> {
@@ -92,22 +97,49 @@ impl<T: CommandParser> Receiver<T> {
         }
     }
 
+    pub fn with_max_non_sync_size(mut self, max_non_sync_size: usize) -> Self {
+        self.max_non_sync_size = max_non_sync_size;
+        self
+    }
+
     pub fn error_reset(&mut self, message: impl Into<trc::Value>) -> Error {
+        self.error_reset_with_code(message, ResponseCode::Parse)
+    }
+
+    fn error_reset_with_code(
+        &mut self,
+        message: impl Into<trc::Value>,
+        code: ResponseCode,
+    ) -> Error {
         let request = std::mem::take(&mut self.request);
-        let err = Error::err(
+        let err = Error::err_with_code(
             if !request.tag.is_empty() {
                 request.tag.into()
             } else {
                 None
             },
             message,
+            code,
         );
         self.buf = Vec::with_capacity(10);
         self.state = self.start_state;
         self.current_request_size = 0;
+        self.discard = None;
         err
     }
 
+    fn request_complete(&mut self) -> Result<Request<T>, Error> {
+        // Requests containing an oversized non-synchronizing literal are only
+        // rejected once fully received, so the connection stays in sync
+        if let Some(message) = self.discard.take() {
+            Err(self.error_reset_with_code(message, ResponseCode::TooBig))
+        } else {
+            self.state = self.start_state;
+            self.current_request_size = 0;
+            Ok(std::mem::take(&mut self.request))
+        }
+    }
+
     fn push_argument(&mut self, in_quote: bool) -> Result<(), Error> {
         if !self.buf.is_empty() {
             self.current_request_size += self.buf.len();
@@ -196,9 +228,7 @@ impl<T: CommandParser> Receiver<T> {
                                 if ch != b'\n' {
                                     self.state = State::Argument { last_ch: b' ' };
                                 } else {
-                                    self.state = self.start_state;
-                                    self.current_request_size = 0;
-                                    return Ok(std::mem::take(&mut self.request));
+                                    return self.request_complete();
                                 }
                             } else {
                                 self.buf.clear();
@@ -257,9 +287,7 @@ impl<T: CommandParser> Receiver<T> {
                     }
                     b'\n' => {
                         self.push_argument(false)?;
-                        self.state = self.start_state;
-                        self.current_request_size = 0;
-                        return Ok(std::mem::take(&mut self.request));
+                        return self.request_complete();
                     }
                     _ if ch.is_ascii_whitespace() => {
                         self.push_argument(false)?;
@@ -313,15 +341,40 @@ impl<T: CommandParser> Receiver<T> {
                                     .map_err(|_| {
                                     self.error_reset("Literal size is not a valid number.")
                                 })?;
-                                if self.current_request_size + size as usize > self.max_request_size
+                                let exceeds_non_sync =
+                                    non_sync && size as usize > self.max_non_sync_size;
+                                if self.discard.is_some()
+                                    || exceeds_non_sync
+                                    || self.current_request_size + size as usize
+                                        > self.max_request_size
                                 {
-                                    return Err(self.error_reset(format!(
-                                        "Literal exceeds the maximum request size of {} bytes.",
-                                        self.max_request_size
-                                    )));
+                                    let message = if let Some(message) = self.discard.take() {
+                                        message
+                                    } else if exceeds_non_sync {
+                                        format!(
+                                            "Non-synchronizing literal exceeds the maximum size of {} bytes.",
+                                            self.max_non_sync_size
+                                        )
+                                    } else {
+                                        format!(
+                                            "Literal exceeds the maximum request size of {} bytes.",
+                                            self.max_request_size
+                                        )
+                                    };
+
+                                    if !non_sync {
+                                        return Err(self
+                                            .error_reset_with_code(message, ResponseCode::TooBig));
+                                    }
+
+                                    // The client does not wait before sending a non-synchronizing
+                                    // literal, discard it instead of parsing it as a command
+                                    self.discard = Some(message);
+                                    self.buf = Vec::new();
+                                } else {
+                                    self.buf = Vec::with_capacity(size as usize);
                                 }
                                 self.state = State::LiteralSeek { size, non_sync };
-                                self.buf = Vec::with_capacity(size as usize);
                             } else {
                                 return Err(self.error_reset("Invalid empty literal."));
                             }
@@ -368,7 +421,9 @@ impl<T: CommandParser> Receiver<T> {
                     }
                 }
                 State::LiteralData { remaining } => {
-                    self.buf.push(ch);
+                    if self.discard.is_none() {
+                        self.buf.push(ch);
+                    }
                     if remaining > 1 {
                         self.state = State::LiteralData {
                             remaining: remaining - 1,
@@ -463,12 +518,20 @@ impl Display for Token {
 
 impl Error {
     pub fn err(tag: Option<String>, message: impl Into<trc::Value>) -> Self {
+        Self::err_with_code(tag, message, ResponseCode::Parse)
+    }
+
+    pub fn err_with_code(
+        tag: Option<String>,
+        message: impl Into<trc::Value>,
+        code: ResponseCode,
+    ) -> Self {
         Error::Error {
             response: trc::ImapEvent::Error
                 .ctx(trc::Key::Details, message)
                 .ctx_opt(trc::Key::Id, tag)
                 .ctx(trc::Key::Type, ResponseType::Bad)
-                .code(ResponseCode::Parse),
+                .code(code),
         }
     }
 }
@@ -482,6 +545,8 @@ impl<T: CommandParser> Default for Receiver<T> {
             start_state: State::Start,
             max_request_size: 25 * 1024 * 1024,
             current_request_size: 0,
+            max_non_sync_size: usize::MAX,
+            discard: None,
         }
     }
 }
@@ -1024,4 +1089,58 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn receiver_parse_oversized_literal() {
+        let mut receiver =
+            Receiver::<Command>::with_max_request_size(1024).with_max_non_sync_size(10);
+
+        // Oversized non-synchronizing literals are drained before the request is
+        // rejected, requests pipelined after them are parsed normally
+        let mut bytes = concat!(
+            "a001 APPEND INBOX {11+}\r\nhello world\r\n",
+            "a002 APPEND INBOX {5+}\r\nhello {12+}\r\nhello\r\nworld\r\n",
+            "a003 NOOP\r\n"
+        )
+        .as_bytes()
+        .iter();
+        for tag in ["a001", "a002"] {
+            match receiver.parse(&mut bytes) {
+                Err(Error::Error { response }) => {
+                    assert_eq!(response.value_as_str(trc::Key::Id), Some(tag));
+                    assert_eq!(response.value_as_str(trc::Key::Code), Some("TOOBIG"));
+                }
+                result => panic!("Expected error, got: {:?}", result),
+            }
+        }
+        assert_eq!(
+            receiver.parse(&mut bytes).unwrap(),
+            Request {
+                tag: "a003".to_string(),
+                command: Command::Noop,
+                tokens: vec![],
+            }
+        );
+
+        // Literals within the limit are accepted
+        let mut bytes = "a004 APPEND INBOX {10+}\r\nhelloworld\r\n"
+            .as_bytes()
+            .iter();
+        assert_eq!(
+            receiver.parse(&mut bytes).unwrap().tokens,
+            vec![
+                Token::Argument(b"INBOX".to_vec()),
+                Token::Argument(b"helloworld".to_vec())
+            ]
+        );
+
+        // Synchronizing literals are rejected before the client sends them
+        let mut bytes = "a005 APPEND INBOX {2048}\r\n".as_bytes().iter();
+        match receiver.parse(&mut bytes) {
+            Err(Error::Error { response }) => {
+                assert_eq!(response.value_as_str(trc::Key::Code), Some("TOOBIG"));
+            }
+            result => panic!("Expected error, got: {:?}", result),
+        }
+    }
 }
@@ -51,6 +51,13 @@ impl<T: SessionStream> Session<T> {
                     break;
                 }
                 Err(receiver::Error::Error { response }) => {
+                    // Oversized literals are rejected after being fully received,
+                    // any pipelined requests that follow can still be parsed
+                    let is_too_big = matches!(
+                        response.key(trc::Key::Code),
+                        Some(trc::Value::Static("TOOBIG"))
+                    );
+
                     // Check for port scanners
                     if matches!(
                         (&self.state, response.key(trc::Key::Code)),
@@ -82,7 +89,9 @@ impl<T: SessionStream> Session<T> {
                     if !self.write_error(response).await {
                         return SessionResult::Close;
                     }
-                    break;
+                    if !is_too_big {
+                        break;
+                    }
                 }
             }
         }
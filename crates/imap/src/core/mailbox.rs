@@ -5,7 +5,7 @@ use std::{
 
 use ahash::AHashMap;
 use common::{
-    auth::AccessToken,
+    auth::{sessions::SessionGuard, AccessToken},
     config::jmap::settings::SpecialUse,
     listener::{limiter::InFlight, SessionStream},
     AccountId, Mailbox,
@@ -34,6 +34,7 @@ impl<T: SessionStream> SessionData<T> {
         session: &Session<T>,
         access_token: Arc<AccessToken>,
         in_flight: Option<InFlight>,
+        session_guard: SessionGuard,
     ) -> trc::Result<Self> {
         let mut session = SessionData {
             stream_tx: session.stream_tx.clone(),
@@ -44,6 +45,7 @@ impl<T: SessionStream> SessionData<T> {
             state: access_token.state().into(),
             access_token,
             in_flight,
+            session_guard,
         };
         let access_token = session.access_token.clone();
 
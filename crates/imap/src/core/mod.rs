@@ -10,7 +10,7 @@ use std::{
 };
 
 use common::{
-    auth::AccessToken,
+    auth::{sessions::SessionGuard, AccessToken},
     listener::{limiter::InFlight, ServerInstance, SessionStream},
     Account, ImapId, Inner, MailboxId, MailboxState, Server,
 };
@@ -62,6 +62,7 @@ pub struct SessionData<T: SessionStream> {
     pub stream_tx: Arc<tokio::sync::Mutex<WriteHalf<T>>>,
     pub state: AtomicU32,
     pub in_flight: Option<InFlight>,
+    pub session_guard: SessionGuard,
 }
 
 pub struct SelectedMailbox {
@@ -152,6 +153,7 @@ impl<T: SessionStream> SessionData<T> {
             stream_tx: new_stream,
             state: self.state,
             in_flight: self.in_flight,
+            session_guard: self.session_guard,
             access_token: self.access_token,
         }
     }
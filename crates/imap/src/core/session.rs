@@ -12,12 +12,12 @@ use common::{
 };
 use imap_proto::{
     protocol::{ProtocolVersion, SerializeResponse},
-    receiver::Receiver,
+    receiver::{Receiver, MAX_NON_SYNC_LITERAL},
 };
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio_rustls::server::TlsStream;
 
-use crate::{GREETING_WITHOUT_TLS, GREETING_WITH_TLS};
+use crate::{GREETING_PREAUTH, GREETING_WITHOUT_TLS, GREETING_WITH_TLS};
 
 use super::{ImapSessionManager, Session, State};
 
@@ -50,6 +50,13 @@ impl<T: SessionStream> Session<T> {
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
 
         loop {
+            let disconnected = match &self.state {
+                State::Authenticated { data } | State::Selected { data, .. } => {
+                    Some(data.session_guard.disconnected())
+                }
+                State::NotAuthenticated { .. } => None,
+            };
+
             tokio::select! {
                 result = tokio::time::timeout(
                     if !matches!(self.state, State::NotAuthenticated {..}) {
@@ -109,6 +116,24 @@ impl<T: SessionStream> Session<T> {
                     self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
                     break;
                 }
+                Some(_) = async {
+                    match disconnected {
+                        Some(disconnected) => {
+                            disconnected.await;
+                            Some(())
+                        }
+                        None => None,
+                    }
+                } => {
+                    trc::event!(
+                        Network(trc::NetworkEvent::Closed),
+                        SpanId = self.session_id,
+                        Reason = "Disconnected by administrator",
+                        CausedBy = trc::location!()
+                    );
+                    self.write_bytes(&b"* BYE Session terminated by administrator.\r\n"[..]).await.ok();
+                    break;
+                }
             };
         }
 
@@ -116,34 +141,19 @@ impl<T: SessionStream> Session<T> {
     }
 
     pub async fn new(
-        mut session: SessionData<T>,
+        session: SessionData<T>,
         manager: ImapSessionManager,
     ) -> Result<Session<T>, ()> {
-        // Write greeting
         let is_tls = session.stream.is_tls();
-        let greeting = if !is_tls && session.instance.acceptor.is_tls() {
-            &GREETING_WITH_TLS
-        } else {
-            &GREETING_WITHOUT_TLS
-        };
-
-        if let Err(err) = session.stream.write_all(greeting).await {
-            trc::event!(
-                Network(trc::NetworkEvent::WriteError),
-                Reason = err.to_string(),
-                SpanId = session.session_id,
-                Details = "Failed to write to stream"
-            );
-            return Err(());
-        }
-        let _ = session.stream.flush().await;
+        let peer_certificate = session.stream.peer_certificate();
 
         // Split stream into read and write halves
         let (stream_rx, stream_tx) = tokio::io::split(session.stream);
         let server = manager.inner.build_server();
 
-        Ok(Session {
-            receiver: Receiver::with_max_request_size(server.core.imap.max_request_size),
+        let mut session = Session {
+            receiver: Receiver::with_max_request_size(server.core.imap.max_request_size)
+                .with_max_non_sync_size(MAX_NON_SYNC_LITERAL),
             version: ProtocolVersion::Rev1,
             state: State::NotAuthenticated { auth_failures: 0 },
             is_tls,
@@ -156,7 +166,32 @@ impl<T: SessionStream> Session<T> {
             remote_addr: session.remote_ip,
             stream_rx,
             stream_tx: Arc::new(tokio::sync::Mutex::new(stream_tx)),
-        })
+        };
+
+        // Clients presenting a known certificate on implicit TLS skip authentication
+        let is_preauth = match peer_certificate.filter(|_| is_tls) {
+            Some(certificate) => {
+                session
+                    .authenticate_client_certificate(certificate.as_ref())
+                    .await
+            }
+            None => false,
+        };
+        let greeting = if is_preauth {
+            &GREETING_PREAUTH
+        } else if !is_tls && session.instance.acceptor.is_tls() {
+            &GREETING_WITH_TLS
+        } else {
+            &GREETING_WITHOUT_TLS
+        };
+
+        // Write greeting
+        if let Err(err) = session.write_bytes(greeting.as_slice()).await {
+            trc::error!(err.span_id(session.session_id));
+            return Err(());
+        }
+
+        Ok(session)
     }
 
     pub async fn into_tls(self) -> Result<Session<TlsStream<T>>, ()> {
@@ -192,8 +227,11 @@ impl<T: SessionStream> Session<T> {
         };
 
         // Upgrade to TLS
-        let (stream_rx, stream_tx) =
-            tokio::io::split(self.instance.tls_accept(stream, self.session_id).await?);
+        let (stream_rx, stream_tx) = tokio::io::split(
+            self.instance
+                .tls_accept(stream, self.remote_addr, self.session_id)
+                .await?,
+        );
         let stream_tx = Arc::new(tokio::sync::Mutex::new(stream_tx));
 
         Ok(Session {
@@ -29,4 +29,12 @@ pub(crate) static GREETING_WITHOUT_TLS: LazyLock<Vec<u8>> = LazyLock::new(|| {
         .into_bytes()
 });
 
+pub(crate) static GREETING_PREAUTH: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    StatusResponse::preauth(SERVER_GREETING)
+        .with_code(ResponseCode::Capability {
+            capabilities: Capability::all_capabilities(true, false),
+        })
+        .into_bytes()
+});
+
 pub struct ImapError;
@@ -7,15 +7,16 @@
 use common::{
     auth::{
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
-        AuthRequest,
+        AccessToken, AuthRequest,
     },
+    config::sessions::SessionProtocol,
     listener::SessionStream,
 };
-use directory::Permission;
+use directory::{backend::internal::AppScope, Permission};
 use imap_proto::{
     protocol::{authenticate::Mechanism, capability::Capability},
     receiver::{self, Request},
-    Command, ResponseCode, StatusResponse,
+    Command, ResponseCode, ResponseType, StatusResponse,
 };
 use jmap::auth::rate_limit::RateLimiter;
 use mail_parser::decoders::base64::base64_decode;
@@ -30,15 +31,25 @@ impl<T: SessionStream> Session<T> {
 
         match args.mechanism {
             Mechanism::Plain | Mechanism::OAuthBearer => {
-                if !args.params.is_empty() {
-                    let challenge = base64_decode(args.params.pop().unwrap().as_bytes())
-                        .ok_or_else(|| {
+                if let Some(response) = args.params.pop() {
+                    // A single "*" cancels the exchange, "=" is an empty initial response (RFC 4959)
+                    let challenge = match response.trim() {
+                        "*" => {
+                            return Err(trc::AuthEvent::Error
+                                .into_err()
+                                .details("Authentication cancelled.")
+                                .id(args.tag)
+                                .ctx(trc::Key::Type, ResponseType::Bad));
+                        }
+                        "=" => Vec::new(),
+                        response => base64_decode(response.as_bytes()).ok_or_else(|| {
                             trc::AuthEvent::Error
                                 .into_err()
                                 .details("Failed to decode challenge.")
                                 .id(args.tag.clone())
                                 .code(ResponseCode::Parse)
-                        })?;
+                        })?,
+                    };
 
                     let credentials = if args.mechanism == Mechanism::Plain {
                         sasl_decode_challenge_plain(&challenge)
@@ -85,11 +96,10 @@ impl<T: SessionStream> Session<T> {
         // Authenticate
         let access_token = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
-                credentials,
-                self.session_id,
-                self.remote_addr,
-            ))
+            .authenticate(
+                &AuthRequest::from_credentials(credentials, self.session_id, self.remote_addr)
+                    .with_scope(AppScope::Imap),
+            )
             .await
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
@@ -110,7 +120,56 @@ impl<T: SessionStream> Session<T> {
                     .assert_has_permission(Permission::ImapAuthenticate)
                     .map(|_| token)
             })?;
+        self.start_authenticated_session(access_token)
+            .await
+            .map_err(|err| err.id(tag.clone()))?;
+        self.write_bytes(
+            StatusResponse::ok("Authentication successful")
+                .with_code(ResponseCode::Capability {
+                    capabilities: Capability::all_capabilities(
+                        true,
+                        !self.is_tls && self.instance.acceptor.is_tls(),
+                    ),
+                })
+                .with_tag(tag)
+                .into_bytes(),
+        )
+        .await
+    }
 
+    /// Authenticates clients presenting a certificate that is mapped to a principal,
+    /// returns `true` when the session no longer requires SASL authentication.
+    pub async fn authenticate_client_certificate(&mut self, certificate: &[u8]) -> bool {
+        let result = match self
+            .server
+            .authenticate_certificate(certificate, None, self.session_id, self.remote_addr)
+            .await
+        {
+            Ok(Some(access_token)) => {
+                match access_token.assert_has_permission(Permission::ImapAuthenticate) {
+                    Ok(_) => self.start_authenticated_session(access_token).await,
+                    Err(err) => Err(err),
+                }
+            }
+            Ok(None) => return false,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(_) => true,
+            Err(err) => {
+                trc::error!(err
+                    .ctx(trc::Key::RemoteIp, self.remote_addr)
+                    .span_id(self.session_id));
+                false
+            }
+        }
+    }
+
+    async fn start_authenticated_session(
+        &mut self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<()> {
         // Enforce concurrency limits
         let in_flight = match self
             .get_concurrency_limiter(access_token.primary_id())
@@ -119,32 +178,27 @@ impl<T: SessionStream> Session<T> {
             Some(Some(limiter)) => Some(limiter),
             None => None,
             Some(None) => {
-                return Err(trc::LimitEvent::ConcurrentRequest
-                    .into_err()
-                    .id(tag.clone()));
+                return Err(trc::LimitEvent::ConcurrentRequest.into_err());
             }
         };
 
+        // Enforce session limits
+        let session_guard = self
+            .server
+            .register_session(
+                &access_token,
+                SessionProtocol::Imap,
+                self.session_id,
+                self.remote_addr,
+            )
+            .await?;
+
         // Create session
         self.state = State::Authenticated {
-            data: Arc::new(
-                SessionData::new(self, access_token, in_flight)
-                    .await
-                    .map_err(|err| err.id(tag.clone()))?,
-            ),
+            data: Arc::new(SessionData::new(self, access_token, in_flight, session_guard).await?),
         };
-        self.write_bytes(
-            StatusResponse::ok("Authentication successful")
-                .with_code(ResponseCode::Capability {
-                    capabilities: Capability::all_capabilities(
-                        true,
-                        !self.is_tls && self.instance.acceptor.is_tls(),
-                    ),
-                })
-                .with_tag(tag)
-                .into_bytes(),
-        )
-        .await
+
+        Ok(())
     }
 
     pub async fn handle_unauthenticate(&mut self, request: Request<Command>) -> trc::Result<()> {
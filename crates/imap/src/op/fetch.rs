@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, sync::Arc, time::Instant};
+use std::{borrow::Cow, ops::Range, sync::Arc, time::Instant};
 
 use crate::{
     core::{SelectedMailbox, Session, SessionData},
@@ -213,6 +213,8 @@ impl<T: SessionStream> SessionData<T> {
         let mut set_seen_flags = false;
         let mut needs_thread_id = false;
         let mut needs_blobs = false;
+        let mut stream_attributes = 0;
+        let mut stream_partial = None;
 
         for attribute in &arguments.attributes {
             match attribute {
@@ -230,13 +232,30 @@ impl<T: SessionStream> SessionData<T> {
                     */
                     needs_blobs = true;
                 }
+                Attribute::BodySection {
+                    sections,
+                    peek,
+                    partial,
+                } if sections.is_empty() => {
+                    if mailbox.is_select && !*peek {
+                        set_seen_flags = true;
+                    }
+                    stream_attributes += 1;
+                    stream_partial = *partial;
+                }
                 Attribute::BodySection { peek, .. } | Attribute::Binary { peek, .. } => {
                     if mailbox.is_select && !*peek {
                         set_seen_flags = true;
                     }
                     needs_blobs = true;
                 }
-                Attribute::Rfc822Text | Attribute::Rfc822 => {
+                Attribute::Rfc822 => {
+                    if mailbox.is_select {
+                        set_seen_flags = true;
+                    }
+                    stream_attributes += 1;
+                }
+                Attribute::Rfc822Text => {
                     if mailbox.is_select {
                         set_seen_flags = true;
                     }
@@ -249,6 +268,13 @@ impl<T: SessionStream> SessionData<T> {
             }
         }
 
+        // A single full body fetch is streamed from the blob store in chunks,
+        // otherwise the whole message is loaded and parsed.
+        let stream_body = stream_attributes == 1 && !needs_blobs;
+        if stream_attributes > 0 && !stream_body {
+            needs_blobs = true;
+        }
+
         if set_seen_flags
             && !self
                 .check_mailbox_acl(
@@ -347,6 +373,44 @@ impl<T: SessionStream> SessionData<T> {
             };
             let message = email.contents.into_message(&raw_message);
 
+            // Read the first chunk of a streamed body before sending any output
+            let mut body_stream = None;
+            if stream_body {
+                let range = partial_range(email.size, stream_partial);
+                let size = range.len();
+                let mut chunks = self
+                    .server
+                    .core
+                    .storage
+                    .blob
+                    .get_blob_chunks(email.blob_hash.as_ref(), range);
+                let chunk = if size > 0 {
+                    match chunks
+                        .next()
+                        .await
+                        .imap_ctx(&arguments.tag, trc::location!())?
+                    {
+                        Some(chunk) => chunk,
+                        None => {
+                            trc::event!(
+                                Store(trc::StoreEvent::NotFound),
+                                AccountId = account_id,
+                                DocumentId = id,
+                                Collection = Collection::Email,
+                                BlobId = email.blob_hash.to_hex(),
+                                Details = "Blob not found.",
+                                CausedBy = trc::location!(),
+                            );
+
+                            continue;
+                        }
+                    }
+                } else {
+                    Vec::new()
+                };
+                body_stream = Some((chunks, chunk, size));
+            }
+
             // Build response
             let mut items = Vec::with_capacity(arguments.attributes.len());
             let set_seen_flag =
@@ -403,6 +467,9 @@ impl<T: SessionStream> SessionData<T> {
                     Attribute::Uid => {
                         items.push(DataItem::Uid { uid });
                     }
+                    Attribute::Rfc822 if stream_body => {
+                        items.push(DataItem::Rfc822Stream { size: email.size });
+                    }
                     Attribute::Rfc822 => {
                         items.push(DataItem::Rfc822 {
                             contents: raw_message.as_slice().into(),
@@ -433,6 +500,15 @@ impl<T: SessionStream> SessionData<T> {
                             part: message.body_structure(true),
                         });
                     }
+                    Attribute::BodySection {
+                        sections, partial, ..
+                    } if stream_body && sections.is_empty() => {
+                        items.push(DataItem::BodySectionStream {
+                            sections: vec![],
+                            origin_octet: partial.map(|(start, _)| start),
+                            size: body_stream.as_ref().map_or(0, |(_, _, size)| *size),
+                        });
+                    }
                     Attribute::BodySection {
                         sections, partial, ..
                     } => {
@@ -518,7 +594,47 @@ impl<T: SessionStream> SessionData<T> {
 
             // Serialize fetch item
             let mut buf = Vec::with_capacity(128);
-            FetchItem { id: seqnum, items }.serialize(&mut buf);
+            let item = FetchItem { id: seqnum, items };
+            if let Some((mut chunks, mut chunk, size)) = body_stream {
+                let next = item.serialize_head(&mut buf);
+                let mut written = 0;
+                loop {
+                    chunk.truncate(size - written);
+                    written += chunk.len();
+                    if buf.is_empty() {
+                        self.write_bytes(&chunk).await?;
+                    } else {
+                        buf.extend_from_slice(&chunk);
+                        self.write_bytes(&buf).await?;
+                        buf.clear();
+                    }
+                    if written == size {
+                        break;
+                    }
+                    match chunks.next().await {
+                        Ok(Some(next_chunk)) => {
+                            chunk = next_chunk;
+                        }
+                        result => {
+                            // The literal size was already sent, pad the remaining octets
+                            trc::error!(result
+                                .err()
+                                .unwrap_or_else(|| trc::StoreEvent::UnexpectedError
+                                    .into_err()
+                                    .details("Blob is shorter than the message size"))
+                                .account_id(account_id)
+                                .document_id(id)
+                                .span_id(self.session_id)
+                                .caused_by(trc::location!()));
+                            buf.resize(size - written, b' ');
+                            break;
+                        }
+                    }
+                }
+                item.serialize_tail(&mut buf, next);
+            } else {
+                item.serialize(&mut buf);
+            }
             self.write_bytes(buf).await?;
 
             // Add to set flags
@@ -1101,13 +1217,22 @@ impl<'x> AsImapDataItem<'x> for Message<'x> {
 fn get_partial_bytes(bytes: &[u8], partial: Option<(u32, u32)>) -> &[u8] {
     if let Some((start, end)) = partial {
         bytes
-            .get(start as usize..std::cmp::min((start + end) as usize, bytes.len()))
+            .get(start as usize..std::cmp::min(start.saturating_add(end) as usize, bytes.len()))
             .unwrap_or_default()
     } else {
         bytes
     }
 }
 
+fn partial_range(size: usize, partial: Option<(u32, u32)>) -> Range<usize> {
+    if let Some((start, len)) = partial {
+        let start = (start as usize).min(size);
+        start..start.saturating_add(len as usize).min(size)
+    } else {
+        0..size
+    }
+}
+
 trait AsImapAddress {
     fn as_imap_address(&self) -> Vec<fetch::Address>;
 }
@@ -71,6 +71,8 @@ impl<T: SessionStream> Session<T> {
 
         let op_start = Instant::now();
         let mut buf = vec![0; 4];
+        let disconnected = data.session_guard.disconnected();
+        tokio::pin!(disconnected);
         loop {
             tokio::select! {
                 result = tokio::time::timeout(self.server.core.imap.timeout_idle, self.stream_rx.read_exact(&mut buf)) => {
@@ -121,6 +123,10 @@ impl<T: SessionStream> Session<T> {
                         return Err(trc::NetworkEvent::Closed.into_err().details("IDLE channel closed.").id(request.tag));
                     }
                 }
+                _ = &mut disconnected => {
+                    self.write_bytes(&b"* BYE Session terminated by administrator.\r\n"[..]).await.ok();
+                    return Err(trc::NetworkEvent::Closed.into_err().details("Disconnected by administrator.").id(request.tag));
+                }
             }
         }
     }
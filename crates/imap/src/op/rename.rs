@@ -16,17 +16,16 @@ use imap_proto::{
     protocol::rename::Arguments, receiver::Request, Command, ResponseCode, StatusResponse,
 };
 use jmap::{
-    auth::acl::EffectiveAcl, changes::write::ChangeLog, mailbox::set::SCHEMA,
-    services::state::StateManager, JmapMethods,
+    auth::acl::EffectiveAcl, mailbox::set::MailboxSet, services::state::StateManager, JmapMethods,
 };
 use jmap_proto::{
-    object::{index::ObjectIndexBuilder, Object},
+    object::Object,
     types::{
-        acl::Acl, collection::Collection, id::Id, property::Property, state::StateChange,
+        acl::Acl, collection::Collection, property::Property, state::StateChange,
         type_state::DataType, value::Value,
     },
 };
-use store::write::{assert::HashedValue, BatchBuilder};
+use store::write::assert::HashedValue;
 use trc::AddContext;
 
 use super::ImapContext;
@@ -134,68 +133,17 @@ impl<T: SessionStream> SessionData<T> {
         // Get new mailbox name from path
         let new_mailbox_name = params.path.pop().unwrap();
 
-        // Build batch
-        let mut changes = self
+        // Rename mailbox
+        let (change_id, create_ids) = self
             .server
-            .begin_changes(params.account_id)
-            .await
-            .imap_ctx(&arguments.tag, trc::location!())?;
-
-        let mut parent_id = params.parent_mailbox_id.map(|id| id + 1).unwrap_or(0);
-        let mut create_ids = Vec::with_capacity(params.path.len());
-        for &path_item in params.path.iter() {
-            let mut batch = BatchBuilder::new();
-            batch
-                .with_account_id(params.account_id)
-                .with_collection(Collection::Mailbox)
-                .create_document()
-                .custom(
-                    ObjectIndexBuilder::new(SCHEMA).with_changes(
-                        Object::with_capacity(3)
-                            .with_property(Property::Name, path_item)
-                            .with_property(Property::ParentId, Value::Id(Id::from(parent_id)))
-                            .with_property(
-                                Property::Cid,
-                                Value::UnsignedInt(rand::random::<u32>() as u64),
-                            ),
-                    ),
-                );
-
-            let mailbox_id = self
-                .server
-                .write_batch_expect_id(batch)
-                .await
-                .imap_ctx(&arguments.tag, trc::location!())?;
-
-            changes.log_insert(Collection::Mailbox, mailbox_id);
-            parent_id = mailbox_id + 1;
-            create_ids.push(mailbox_id);
-        }
-
-        let mut batch = BatchBuilder::new();
-        batch
-            .with_account_id(params.account_id)
-            .with_collection(Collection::Mailbox)
-            .update_document(mailbox_id)
-            .custom(
-                ObjectIndexBuilder::new(SCHEMA)
-                    .with_current(mailbox)
-                    .with_changes(
-                        Object::with_capacity(3)
-                            .with_property(Property::Name, new_mailbox_name)
-                            .with_property(Property::ParentId, Value::Id(Id::from(parent_id)))
-                            .with_property(
-                                Property::Cid,
-                                Value::UnsignedInt(rand::random::<u32>() as u64),
-                            ),
-                    ),
-            );
-        changes.log_update(Collection::Mailbox, mailbox_id);
-
-        let change_id = changes.change_id;
-        batch.custom(changes);
-        self.server
-            .write_batch(batch)
+            .mailbox_rename(
+                params.account_id,
+                mailbox_id,
+                mailbox,
+                params.parent_mailbox_id,
+                &params.path,
+                new_mailbox_name,
+            )
             .await
             .imap_ctx(&arguments.tag, trc::location!())?;
 
@@ -35,6 +35,7 @@ pub enum RequestArguments {
     VacationResponse,
     Principal,
     Quota,
+    ThreadRule,
     Blob(blob::GetArguments),
 }
 
@@ -71,6 +72,7 @@ impl JsonObjectParser for GetRequest<RequestArguments> {
                 MethodObject::Principal => RequestArguments::Principal,
                 MethodObject::Blob => RequestArguments::Blob(Default::default()),
                 MethodObject::Quota => RequestArguments::Quota,
+                MethodObject::ThreadRule => RequestArguments::ThreadRule,
                 _ => {
                     return Err(trc::JmapEvent::UnknownMethod
                         .into_err()
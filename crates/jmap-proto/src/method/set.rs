@@ -51,6 +51,7 @@ pub enum RequestArguments {
     PushSubscription,
     SieveScript(sieve::SetArguments),
     VacationResponse,
+    ThreadRule,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize)]
@@ -111,6 +112,7 @@ impl JsonObjectParser for SetRequest<RequestArguments> {
                 MethodObject::PushSubscription => RequestArguments::PushSubscription,
                 MethodObject::VacationResponse => RequestArguments::VacationResponse,
                 MethodObject::SieveScript => RequestArguments::SieveScript(Default::default()),
+                MethodObject::ThreadRule => RequestArguments::ThreadRule,
                 _ => {
                     return Err(trc::JmapEvent::UnknownMethod
                         .into_err()
@@ -249,7 +251,10 @@ impl JsonObjectParser for Object<SetValue> {
                         .unwrap_uint_or_null("")?
                         .map(|uint| SetValue::Value(Value::UnsignedInt(uint)))
                         .unwrap_or(SetValue::Value(Value::Null)),
-                    Property::ParentId | Property::EmailId | Property::IdentityId => parser
+                    Property::ParentId
+                    | Property::EmailId
+                    | Property::IdentityId
+                    | Property::MailboxId => parser
                         .next_token::<MaybeReference<Id, String>>()?
                         .unwrap_string_or_null("")?
                         .map(SetValue::from)
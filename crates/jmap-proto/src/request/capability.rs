@@ -71,6 +71,8 @@ pub enum Capability {
     Blob = 1 << 8,
     #[serde(rename(serialize = "urn:ietf:params:jmap:quota"))]
     Quota = 1 << 9,
+    #[serde(rename(serialize = "urn:stalwart:jmap:threadrules"))]
+    ThreadRules = 1 << 10,
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -318,7 +320,31 @@ impl JsonObjectParser for Capability {
     where
         Self: Sized,
     {
-        for ch in b"urn:ietf:params:jmap:" {
+        for ch in b"urn:" {
+            if parser
+                .next_unescaped()?
+                .ok_or_else(|| parser.error_capability())?
+                != *ch
+            {
+                return Err(parser.error_capability());
+            }
+        }
+
+        // Server specific extensions use their own namespace
+        let is_ietf = match parser
+            .next_unescaped()?
+            .ok_or_else(|| parser.error_capability())?
+        {
+            b'i' => true,
+            b's' => false,
+            _ => return Err(parser.error_capability()),
+        };
+        let prefix: &[u8] = if is_ietf {
+            b"etf:params:jmap:"
+        } else {
+            b"talwart:jmap:"
+        };
+        for ch in prefix {
             if parser
                 .next_unescaped()?
                 .ok_or_else(|| parser.error_capability())?
@@ -329,6 +355,10 @@ impl JsonObjectParser for Capability {
         }
 
         match u128::parse(parser) {
+            Ok(key) if !is_ietf => match key {
+                0x0073_656c_7572_6461_6572_6874 => Ok(Capability::ThreadRules),
+                _ => Err(parser.error_capability()),
+            },
             Ok(key) => match key {
                 0x6572_6f63 => Ok(Capability::Core),
                 0x6c69_616d => Ok(Capability::Mail),
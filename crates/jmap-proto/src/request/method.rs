@@ -29,6 +29,7 @@ pub enum MethodObject {
     SieveScript,
     Principal,
     Quota,
+    ThreadRule,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -96,6 +97,7 @@ impl JsonObjectParser for MethodName {
                 0x0074_7069_7263_5365_7665_6953 => MethodObject::SieveScript,
                 0x006c_6170_6963_6e69_7250 => MethodObject::Principal,
                 0x0061_746f_7551 => MethodObject::Quota,
+                0x656c_7552_6461_6572_6854 => MethodObject::ThreadRule,
                 0x6572_6f43 => MethodObject::Core,
                 _ => return Err(parser.error_value()),
             },
@@ -150,6 +152,9 @@ impl MethodName {
             (MethodFunction::Get, MethodObject::Thread) => "Thread/get",
             (MethodFunction::Changes, MethodObject::Thread) => "Thread/changes",
 
+            (MethodFunction::Get, MethodObject::ThreadRule) => "ThreadRule/get",
+            (MethodFunction::Set, MethodObject::ThreadRule) => "ThreadRule/set",
+
             (MethodFunction::Get, MethodObject::Email) => "Email/get",
             (MethodFunction::Changes, MethodObject::Email) => "Email/changes",
             (MethodFunction::Query, MethodObject::Email) => "Email/query",
@@ -217,6 +222,7 @@ impl Display for MethodObject {
             MethodObject::Thread => "Thread",
             MethodObject::Email => "Email",
             MethodObject::Quota => "Quota",
+            MethodObject::ThreadRule => "ThreadRule",
         })
     }
 }
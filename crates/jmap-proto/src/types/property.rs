@@ -119,6 +119,9 @@ pub enum Property {
     WarnLimit,
     SoftLimit,
     Scope,
+    CollectedRecipients,
+    MailboxId,
+    ThreadRules,
     Digest(DigestProperty),
     Data(DataProperty),
     _T(String),
@@ -400,6 +403,7 @@ fn parse_property(first_char: u8, hash: u128) -> Option<Property> {
             _ => return None,
         },
         b'm' => match hash {
+            0x6449_786f_626c_6961 => Property::MailboxId,
             0x0073_6449_786f_626c_6961 => Property::MailboxIds,
             0x6574_656c_6544_7961 => Property::MayDelete,
             0x0073_6449_626f_6c42_6e64 => Property::MdnBlobIds,
@@ -881,6 +885,9 @@ impl Display for Property {
             Property::Used => write!(f, "used"),
             Property::HardLimit => write!(f, "hardLimit"),
             Property::Scope => write!(f, "scope"),
+            Property::CollectedRecipients => write!(f, "collectedRecipients"),
+            Property::MailboxId => write!(f, "mailboxId"),
+            Property::ThreadRules => write!(f, "threadRules"),
             Property::WarnLimit => write!(f, "warnLimit"),
             Property::SoftLimit => write!(f, "softLimit"),
             Property::_T(s) => write!(f, "{s}"),
@@ -1063,6 +1070,9 @@ impl From<&Property> for u8 {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::CollectedRecipients => 104,
+            Property::MailboxId => 105,
+            Property::ThreadRules => 106,
             Property::Digest(_) | Property::Data(_) => unreachable!("invalid property"),
         }
     }
@@ -1205,6 +1215,9 @@ impl SerializeInto for Property {
             Property::WarnLimit => 101,
             Property::SoftLimit => 102,
             Property::Scope => 103,
+            Property::CollectedRecipients => 104,
+            Property::MailboxId => 105,
+            Property::ThreadRules => 106,
             Property::Digest(_) | Property::Data(_) => {
                 unreachable!("Property::Digest and Property::Data are not serializable")
             }
@@ -1323,6 +1336,9 @@ impl DeserializeFrom for Property {
             101 => Some(Property::WarnLimit),
             102 => Some(Property::SoftLimit),
             103 => Some(Property::Scope),
+            104 => Some(Property::CollectedRecipients),
+            105 => Some(Property::MailboxId),
+            106 => Some(Property::ThreadRules),
             _ => None,
         }
     }
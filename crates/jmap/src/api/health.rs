@@ -0,0 +1,61 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Instant};
+
+use common::Server;
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::api::http::ToHttpResponse;
+
+use super::{HttpResponse, JsonResponse};
+
+pub trait HealthProbe: Sync + Send {
+    fn handle_liveness(&self) -> impl Future<Output = HttpResponse> + Send;
+    fn handle_readiness(&self) -> impl Future<Output = HttpResponse> + Send;
+}
+
+impl HealthProbe for Server {
+    async fn handle_liveness(&self) -> HttpResponse {
+        // A wedged runtime either does not answer or takes long to reschedule
+        let start = Instant::now();
+        tokio::task::yield_now().await;
+
+        JsonResponse::new(json!({
+            "status": "ok",
+            "latencyMs": start.elapsed().as_millis() as u64,
+        }))
+        .no_cache()
+        .into_http_response()
+    }
+
+    async fn handle_readiness(&self) -> HttpResponse {
+        let readiness = self.readiness().await;
+        let failing = readiness
+            .failing()
+            .filter(|component| component.fatal)
+            .map(|component| component.name.as_str())
+            .collect::<Vec<_>>();
+        let status = if readiness.ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        JsonResponse::with_status(
+            status,
+            json!({
+                "status": if readiness.ready { "ok" } else { "unavailable" },
+                "failing": failing,
+                "checkedAt": readiness.checked_at,
+                "components": readiness.components,
+            }),
+        )
+        .no_cache()
+        .into_http_response()
+    }
+}
@@ -15,10 +15,10 @@ use common::{
     manager::webadmin::Resource,
     Inner, Server,
 };
-use directory::Permission;
-use http_body_util::{BodyExt, Full};
+use directory::{backend::internal::manage::ErrorCode, Permission};
+use http_body_util::{combinators::BoxBody, BodyExt, Full, StreamBody};
 use hyper::{
-    body::{self, Bytes},
+    body::{self, Bytes, Frame},
     header::{self, CONTENT_TYPE},
     server::conn::http1,
     service::service_fn,
@@ -54,7 +54,10 @@ use super::{
     autoconfig::Autoconfig,
     event_source::EventSourceHandler,
     form::FormHandler,
-    management::{ManagementApi, ManagementApiError},
+    health::HealthProbe,
+    management::{
+        export::ManageTenantExport, ManagementApi, ManagementApiError, ManagementApiErrorResponse,
+    },
     request::RequestHandler,
     session::SessionHandler,
     HtmlResponse, HttpRequest, HttpResponse, HttpResponseBody, JmapSessionManager, JsonResponse,
@@ -141,8 +144,8 @@ impl ParseHttp for Server {
                             path.next().and_then(BlobId::from_base32),
                             path.next(),
                         ) {
-                            return match self.blob_download(&blob_id, &access_token).await? {
-                                Some(blob) => Ok(DownloadResponse {
+                            return match self.blob_download_chunks(&blob_id, &access_token).await? {
+                                Some((blob, chunks)) => Ok(DownloadResponse {
                                     filename: name.to_string(),
                                     content_type: req
                                         .uri()
@@ -154,6 +157,7 @@ impl ParseHttp for Server {
                                         })
                                         .unwrap_or("application/octet-stream".to_string()),
                                     blob,
+                                    chunks,
                                 }
                                 .into_http_response()),
                                 None => Err(trc::ResourceEvent::NotFound.into_err()),
@@ -161,6 +165,9 @@ impl ParseHttp for Server {
                         }
                     }
                     ("upload", &Method::POST) => {
+                        // Uploads are deferred while the server is shedding load
+                        self.assert_accepting_load()?;
+
                         // Authenticate request
                         let (_in_flight, access_token) =
                             self.authenticate_headers(&req, &session, false).await?;
@@ -398,6 +405,15 @@ impl ParseHttp for Server {
                         .await;
                 }
             }
+            "export" => {
+                if let (&Method::GET, Some(tenant_id), Some(job_id)) =
+                    (req.method(), path.next(), path.next())
+                {
+                    return self
+                        .handle_tenant_export_download(&req, tenant_id, job_id)
+                        .await;
+                }
+            }
             "robots.txt" => {
                 return Ok(
                     Resource::new("text/plain", b"User-agent: *\nDisallow: /\n".to_vec())
@@ -405,21 +421,17 @@ impl ParseHttp for Server {
                 );
             }
             "healthz" => match path.next().unwrap_or_default() {
-                "live" => {
-                    return Ok(StatusCode::OK.into_http_response());
+                "" | "live" => {
+                    return Ok(self.handle_liveness().await);
                 }
                 "ready" => {
-                    return Ok({
-                        if !self.core.storage.data.is_none() {
-                            StatusCode::OK
-                        } else {
-                            StatusCode::SERVICE_UNAVAILABLE
-                        }
-                    }
-                    .into_http_response());
+                    return Ok(self.handle_readiness().await);
                 }
                 _ => (),
             },
+            "readyz" => {
+                return Ok(self.handle_readiness().await);
+            }
             "metrics" => match path.next().unwrap_or_default() {
                 "prometheus" => {
                     if let Some(prometheus) = &self.core.metrics.prometheus {
@@ -607,6 +619,7 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                     };
 
                     // Parse HTTP request
+                    let mut retry_after = None;
                     let response = match server
                         .parse_http_request(
                             req,
@@ -624,6 +637,9 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
                     {
                         Ok(response) => response,
                         Err(err) => {
+                            if err.matches(trc::EventType::Limit(trc::LimitEvent::LoadShed)) {
+                                retry_after = Some(server.core.backpressure.retry_after.as_secs());
+                            }
                             let response = err.into_http_response();
                             trc::error!(err.span_id(session.session_id));
                             response
@@ -645,6 +661,11 @@ async fn handle_session<T: SessionStream>(inner: Arc<Inner>, session: SessionDat
 
                     // Build response
                     let mut response = response.build();
+                    if let Some(retry_after) = retry_after {
+                        response
+                            .headers_mut()
+                            .insert(header::RETRY_AFTER, retry_after.into());
+                    }
 
                     // Add custom headers
                     if !server.core.jmap.http_headers.is_empty() {
@@ -901,10 +922,20 @@ impl HttpResponse {
                     .map_err(|never| match never {})
                     .boxed(),
             ),
-            HttpResponseBody::Stream(stream) => builder
-                .header(header::CONTENT_TYPE, self.content_type.as_ref())
-                .header(header::CACHE_CONTROL, self.cache_control.as_ref())
-                .body(stream),
+            HttpResponseBody::Stream(stream) => {
+                let mut builder = builder
+                    .header(header::CONTENT_TYPE, self.content_type.as_ref())
+                    .header(header::CACHE_CONTROL, self.cache_control.as_ref());
+
+                if !self.content_disposition.is_empty() {
+                    builder = builder.header(
+                        header::CONTENT_DISPOSITION,
+                        self.content_disposition.as_ref(),
+                    );
+                }
+
+                builder.body(stream)
+            }
             HttpResponseBody::WebsocketUpgrade(derived_key) => builder
                 .header(header::CONNECTION, "upgrade")
                 .header(header::UPGRADE, "websocket")
@@ -940,8 +971,11 @@ impl<T: serde::Serialize> ToHttpResponse for JsonResponse<T> {
 impl ToHttpResponse for &trc::Error {
     fn into_http_response(self) -> HttpResponse {
         match self.as_ref() {
-            trc::EventType::Manage(cause) => {
-                match cause {
+            trc::EventType::Manage(cause) => ManagementApiErrorResponse {
+                code: self
+                    .value_as_str(trc::Key::Code)
+                    .unwrap_or_else(|| ErrorCode::from(*cause).as_str()),
+                error: match cause {
                     trc::ManageEvent::MissingParameter => ManagementApiError::FieldMissing {
                         field: self.value_as_str(trc::Key::Key).unwrap_or_default(),
                     },
@@ -960,13 +994,24 @@ impl ToHttpResponse for &trc::Error {
                             .unwrap_or("Requested action is unsupported"),
                     },
                     trc::ManageEvent::AssertFailed => ManagementApiError::AssertFailed,
-                    trc::ManageEvent::Error => ManagementApiError::Other {
+                    trc::ManageEvent::Error
+                    | trc::ManageEvent::DirectoryAlert
+                    | trc::ManageEvent::MaintenanceEnabled
+                    | trc::ManageEvent::MaintenanceDisabled
+                    | trc::ManageEvent::HoldPlaced
+                    | trc::ManageEvent::HoldReleased
+                    | trc::ManageEvent::DirectoryInconsistency
+                    | trc::ManageEvent::DirectoryVerified
+                    | trc::ManageEvent::QuotaMismatch
+                    | trc::ManageEvent::QuotaRecalculated => ManagementApiError::Other {
                         reason: self.value_as_str(trc::Key::Reason),
                         details: self
                             .value_as_str(trc::Key::Details)
                             .unwrap_or("Unknown error"),
+                        field: self.value_as_str(trc::Key::Key),
+                        value: self.value_as_str(trc::Key::Value),
                     },
-                }
+                },
             }
             .into_http_response(),
 
@@ -998,6 +1043,9 @@ impl ToRequestError for trc::Error {
                 trc::LimitEvent::SizeRequest => RequestError::limit(RequestLimitError::SizeRequest),
                 trc::LimitEvent::SizeUpload => RequestError::limit(RequestLimitError::SizeUpload),
                 trc::LimitEvent::CallsIn => RequestError::limit(RequestLimitError::CallsIn),
+                trc::LimitEvent::ConcurrentConnection if details_or_reason.is_some() => {
+                    RequestError::blank(429, "Too many sessions", details)
+                }
                 trc::LimitEvent::ConcurrentRequest | trc::LimitEvent::ConcurrentConnection => {
                     RequestError::limit(RequestLimitError::ConcurrentRequest)
                 }
@@ -1015,12 +1063,27 @@ impl ToRequestError for trc::Error {
                         .unwrap_or_default() as usize,
                 ),
                 trc::LimitEvent::TooManyRequests => RequestError::too_many_requests(),
+                trc::LimitEvent::LoadShed => RequestError::blank(
+                    503,
+                    "Service Unavailable",
+                    "The server is busy. Please try again later.",
+                ),
             },
             trc::EventType::Auth(cause) => match cause {
                 trc::AuthEvent::MissingTotp => {
                     RequestError::blank(402, "TOTP code required", cause.message())
                 }
                 trc::AuthEvent::TooManyAttempts => RequestError::too_many_auth_attempts(),
+                trc::AuthEvent::AccountDisabled => RequestError::blank(
+                    StatusCode::FORBIDDEN.as_u16(),
+                    "Account disabled",
+                    cause.message(),
+                ),
+                trc::AuthEvent::AccountLocked => RequestError::blank(
+                    StatusCode::FORBIDDEN.as_u16(),
+                    "Account locked",
+                    cause.message(),
+                ),
                 _ => RequestError::unauthorized(),
             },
             trc::EventType::Security(cause) => match cause {
@@ -1029,7 +1092,7 @@ impl ToRequestError for trc::Error {
                 | trc::SecurityEvent::AbuseBan
                 | trc::SecurityEvent::LoiterBan
                 | trc::SecurityEvent::IpBlocked => RequestError::too_many_auth_attempts(),
-                trc::SecurityEvent::Unauthorized => RequestError::forbidden(),
+                _ => RequestError::forbidden(),
             },
             trc::EventType::Resource(cause) => match cause {
                 trc::ResourceEvent::NotFound => RequestError::not_found(),
@@ -1094,7 +1157,7 @@ impl ToHttpResponse for Session {
     }
 }
 
-impl ToHttpResponse for ManagementApiError<'_> {
+impl ToHttpResponse for ManagementApiErrorResponse<'_> {
     fn into_http_response(self) -> super::HttpResponse {
         JsonResponse::new(self).into_http_response()
     }
@@ -1111,7 +1174,40 @@ impl ToHttpResponse for DownloadResponse {
             )
             .into(),
             cache_control: "private, immutable, max-age=31536000".into(),
-            body: HttpResponseBody::Binary(self.blob),
+            body: match self.chunks {
+                Some(mut chunks) => {
+                    // Chunks are read one at a time as the client consumes them
+                    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
+                    tokio::spawn(async move {
+                        loop {
+                            match chunks.next().await {
+                                Ok(Some(chunk)) => {
+                                    if tx.send(chunk).await.is_err() {
+                                        break;
+                                    }
+                                }
+                                Ok(None) => break,
+                                Err(err) => {
+                                    trc::error!(err
+                                        .details("Failed to stream blob")
+                                        .caused_by(trc::location!()));
+                                    break;
+                                }
+                            }
+                        }
+                    });
+
+                    let first_chunk = self.blob;
+                    HttpResponseBody::Stream(BoxBody::new(StreamBody::new(async_stream::stream! {
+                        yield Ok(Frame::data(Bytes::from(first_chunk)));
+
+                        while let Some(chunk) = rx.recv().await {
+                            yield Ok(Frame::data(Bytes::from(chunk)));
+                        }
+                    })))
+                }
+                None => HttpResponseBody::Binary(self.blob),
+            },
         }
     }
 }
@@ -0,0 +1,327 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::atomic::Ordering};
+
+use common::{auth::AccessToken, config::jmap::settings::FolderLimitAction, Server};
+use directory::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{err_not_found, ErrorCode, ManageDirectory},
+        PrincipalField,
+    },
+    Permission, Principal, QueryBy,
+};
+use hyper::Method;
+use jmap_proto::types::{collection::Collection, property::Property};
+use serde_json::json;
+use smtp::queue::{self, serialize::QueuedMessage, Status};
+use store::{
+    write::{now, QueueClass, TagValue, ValueClass},
+    Deserialize, IterateParams, ValueKey,
+};
+use trc::AddContext;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    mailbox::get::MailboxGet,
+    JmapMethods,
+};
+
+use super::{decode_path_element, queue::Message};
+
+const MAX_QUEUE_ITEMS: usize = 50;
+
+pub trait ManageActivity: Sync + Send {
+    fn handle_manage_activity(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageActivity for Server {
+    async fn handle_manage_activity(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (Some(name), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PrincipalActivity)?;
+
+                // Tenant support staff can only see their own users
+                let name = decode_path_element(name);
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name.as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+                    .map(|p| p.id)
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+                let principal = self
+                    .core
+                    .storage
+                    .data
+                    .query(QueryBy::Id(account_id), false)
+                    .await?
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+                let addresses = principal
+                    .iter_str(PrincipalField::Emails)
+                    .map(|email| email.to_lowercase())
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "id": account_id,
+                        "name": principal.name(),
+                        "logins": unavailable("Login tracking is not enabled"),
+                        "authFailures": section(self.activity_auth_failures(&principal).await),
+                        "quota": section(self.activity_quota(&principal).await),
+                        "queue": section(self.activity_queue(&addresses).await),
+                        "sessions": self.activity_sessions(account_id),
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait ActivitySections: Sync + Send {
+    fn activity_auth_failures(
+        &self,
+        principal: &Principal,
+    ) -> impl Future<Output = trc::Result<serde_json::Value>> + Send;
+
+    fn activity_quota(
+        &self,
+        principal: &Principal,
+    ) -> impl Future<Output = trc::Result<serde_json::Value>> + Send;
+
+    fn activity_queue(
+        &self,
+        addresses: &[String],
+    ) -> impl Future<Output = trc::Result<serde_json::Value>> + Send;
+
+    fn activity_sessions(&self, account_id: u32) -> serde_json::Value;
+}
+
+impl ActivitySections for Server {
+    async fn activity_auth_failures(
+        &self,
+        principal: &Principal,
+    ) -> trc::Result<serde_json::Value> {
+        let Some(rate) = self.core.network.security.auth_fail_rate() else {
+            return Ok(unavailable("Authentication rate limiting is not enabled"));
+        };
+
+        // Read the failure counters of the current rate limiting window
+        let period = rate.period.as_secs();
+        let window = now() / period;
+        let mut failures = 0;
+        for login in [principal.name()].into_iter().chain(
+            principal
+                .iter_str(PrincipalField::Emails)
+                .map(|e| e.as_str()),
+        ) {
+            let mut key = format!("b:{login}").into_bytes();
+            key.extend_from_slice(window.to_be_bytes().as_slice());
+            failures += self
+                .lookup_store()
+                .counter_get(key)
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(json!({
+            "failures": failures,
+            "limit": rate.requests,
+            "windowEnd": (window + 1) * period,
+        }))
+    }
+
+    async fn activity_quota(&self, principal: &Principal) -> trc::Result<serde_json::Value> {
+        let account_id = principal.id();
+        let mut folders = Vec::new();
+        for folder in &self.core.jmap.default_folders {
+            let (Some(limit), Some(role)) = (folder.max_messages, folder.special_use.as_role())
+            else {
+                continue;
+            };
+            let used = if let Some(mailbox_id) = self.mailbox_get_by_role(account_id, role).await? {
+                self.get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    TagValue::Id(mailbox_id),
+                )
+                .await?
+                .map_or(0, |ids| ids.len())
+            } else {
+                0
+            };
+
+            folders.push(json!({
+                "role": role,
+                "used": used,
+                "limit": limit,
+                "onLimit": match folder.on_limit {
+                    FolderLimitAction::Reject => "reject",
+                    FolderLimitAction::ExpungeOldest => "expunge-oldest",
+                },
+            }));
+        }
+
+        Ok(json!({
+            "used": self.get_used_quota(account_id).await?,
+            "limit": principal.quota(),
+            "messages": {
+                "used": self.get_message_count(account_id).await?,
+                "limit": principal.message_quota(),
+            },
+            "folders": folders,
+        }))
+    }
+
+    async fn activity_queue(&self, addresses: &[String]) -> trc::Result<serde_json::Value> {
+        if addresses.is_empty() {
+            return Ok(unavailable("Account has no email addresses"));
+        }
+
+        let mut outgoing = Vec::new();
+        let mut failures = Vec::new();
+        let mut total_outgoing = 0;
+        let mut total_failures = 0;
+
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(
+                    ValueKey::from(ValueClass::Queue(QueueClass::Message(0))),
+                    ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX))),
+                )
+                .descending(),
+                |key, value| {
+                    let message = QueuedMessage::deserialize(value)
+                        .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?
+                        .inner;
+
+                    // Messages sent by this account
+                    if addresses.contains(&message.return_path_lcase) {
+                        total_outgoing += 1;
+                        if outgoing.len() < MAX_QUEUE_ITEMS {
+                            outgoing.push(Message::from(&message));
+                        }
+                    }
+
+                    // Failed deliveries to this account
+                    let failed = message
+                        .recipients
+                        .iter()
+                        .filter(|rcpt| {
+                            addresses.contains(&rcpt.address_lcase)
+                                && matches!(
+                                    rcpt.status,
+                                    Status::TemporaryFailure(_) | Status::PermanentFailure(_)
+                                )
+                        })
+                        .map(|rcpt| rcpt_failure(&message, rcpt))
+                        .collect::<Vec<_>>();
+                    if !failed.is_empty() {
+                        total_failures += failed.len();
+                        failures.extend(failed);
+                        failures.truncate(MAX_QUEUE_ITEMS);
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(json!({
+            "outgoing": {
+                "total": total_outgoing,
+                "items": outgoing,
+            },
+            "deliveryFailures": {
+                "total": total_failures,
+                "items": failures,
+            },
+        }))
+    }
+
+    fn activity_sessions(&self, account_id: u32) -> serde_json::Value {
+        let jmap = self
+            .inner
+            .data
+            .jmap_limiter
+            .get(&account_id)
+            .map(|limiter| {
+                (
+                    limiter
+                        .concurrent_requests
+                        .concurrent
+                        .load(Ordering::Relaxed),
+                    limiter
+                        .concurrent_uploads
+                        .concurrent
+                        .load(Ordering::Relaxed),
+                )
+            })
+            .unwrap_or_default();
+        let imap = self
+            .inner
+            .data
+            .imap_limiter
+            .get(&account_id)
+            .map(|limiter| {
+                limiter
+                    .concurrent_requests
+                    .concurrent
+                    .load(Ordering::Relaxed)
+            })
+            .unwrap_or_default();
+
+        json!({
+            "jmapRequests": jmap.0,
+            "jmapUploads": jmap.1,
+            "imapRequests": imap,
+            "cachedSession": self.inner.data.access_tokens.contains_key(&account_id),
+        })
+    }
+}
+
+fn rcpt_failure(message: &queue::Message, rcpt: &queue::Recipient) -> serde_json::Value {
+    json!({
+        "queueId": message.queue_id,
+        "returnPath": message.return_path,
+        "address": rcpt.address,
+        "permanent": matches!(rcpt.status, Status::PermanentFailure(_)),
+        "reason": rcpt.status.to_string(),
+    })
+}
+
+fn section(result: trc::Result<serde_json::Value>) -> serde_json::Value {
+    result.unwrap_or_else(|err| {
+        trc::error!(err.details("Failed to build activity summary section"));
+        unavailable("Failed to obtain data, check the server logs for details")
+    })
+}
+
+fn unavailable(reason: &str) -> serde_json::Value {
+    json!({
+        "unavailable": reason,
+    })
+}
@@ -0,0 +1,228 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, str::FromStr};
+
+use common::{
+    auth::{api_key::ApiKey, AccessToken},
+    Server,
+};
+use directory::{
+    backend::internal::manage::{self, ManageDirectory},
+    Permission, Type,
+};
+use hyper::Method;
+use mail_parser::DateTime;
+use serde::Deserialize;
+use serde_json::json;
+use store::write::now;
+use trc::AddContext;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::FutureTimestamp;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiKeyRequest {
+    name: String,
+    #[serde(default)]
+    permissions: Vec<Permission>,
+    #[serde(default)]
+    expires_at: Option<String>,
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+pub trait ManageApiKeys: Sync + Send {
+    fn handle_manage_api_keys(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageApiKeys for Server {
+    async fn handle_manage_api_keys(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+
+        match (path.get(1), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ApiKeyList)?;
+
+                let items = self
+                    .list_api_keys(tenant_id)
+                    .await?
+                    .iter()
+                    .map(api_key_to_json)
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ApiKeyCreate)?;
+
+                let request =
+                    serde_json::from_slice::<ApiKeyRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+                let name = request.name.trim();
+                if name.is_empty() {
+                    return Err(manage::error("Invalid API key name.", None::<u32>));
+                }
+
+                // Keys are bound to the tenant of the creator, administrators
+                // without a tenant have to choose one
+                let key_tenant_id = match (tenant_id, request.tenant.as_deref()) {
+                    (Some(tenant_id), _) => tenant_id,
+                    (None, Some(tenant)) => {
+                        self.store()
+                            .get_principal_info(tenant)
+                            .await
+                            .caused_by(trc::location!())?
+                            .filter(|info| info.typ == Type::Tenant)
+                            .ok_or_else(|| manage::not_found(tenant.to_string()))?
+                            .id
+                    }
+                    (None, None) => {
+                        return Err(manage::error(
+                            "API keys must be bound to a tenant.",
+                            None::<u32>,
+                        ))
+                    }
+                };
+
+                // Keys can only carry administrative permissions held by the creator
+                if request.permissions.is_empty() {
+                    return Err(manage::error(
+                        "At least one permission is required.",
+                        None::<u32>,
+                    ));
+                }
+                for permission in &request.permissions {
+                    if permission.is_user_permission() || !access_token.has_permission(*permission)
+                    {
+                        return Err(manage::error(
+                            "Invalid permission",
+                            format!(
+                                "Your account cannot grant the {:?} permission",
+                                permission.name()
+                            )
+                            .into(),
+                        ));
+                    }
+                }
+
+                let expires_at = match request.expires_at.as_deref() {
+                    Some(expires_at) => Some(
+                        FutureTimestamp::from_str(expires_at)
+                            .map_err(|_| {
+                                manage::error(
+                                    "Invalid expiration date.",
+                                    Some(expires_at.to_string()),
+                                )
+                            })?
+                            .into_inner(),
+                    ),
+                    None => None,
+                };
+
+                let (key, token) = self
+                    .create_api_key(
+                        name.to_string(),
+                        key_tenant_id,
+                        request.permissions,
+                        expires_at,
+                        access_token.name.clone(),
+                    )
+                    .await?;
+
+                trc::event!(
+                    Auth(trc::AuthEvent::ApiKeyIssued),
+                    Id = key.id,
+                    Details = key.name.clone(),
+                    AccountId = access_token.primary_id(),
+                    AccountName = access_token.name.clone(),
+                );
+
+                // The token is only returned once, only its hash is stored
+                let mut response = api_key_to_json(&key);
+                response["token"] = token.into();
+
+                Ok(JsonResponse::new(json!({
+                    "data": response,
+                }))
+                .into_http_response())
+            }
+            (Some(id), method @ (&Method::GET | &Method::DELETE)) => {
+                // Validate the access token
+                access_token.assert_has_permission(if *method == Method::GET {
+                    Permission::ApiKeyGet
+                } else {
+                    Permission::ApiKeyDelete
+                })?;
+
+                let key = match id.parse::<u64>() {
+                    Ok(id) => self.api_key(id).await?,
+                    Err(_) => None,
+                }
+                .filter(|key| tenant_id.is_none_or(|tenant_id| tenant_id == key.tenant_id))
+                .ok_or_else(|| manage::not_found(id.to_string()))?;
+
+                if *method == Method::DELETE {
+                    self.revoke_api_key(key.id).await?;
+
+                    trc::event!(
+                        Auth(trc::AuthEvent::ApiKeyRevoked),
+                        Id = key.id,
+                        Details = key.name.clone(),
+                        AccountId = access_token.primary_id(),
+                        AccountName = access_token.name.clone(),
+                    );
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": api_key_to_json(&key),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+fn api_key_to_json(key: &ApiKey) -> serde_json::Value {
+    let timestamp = |ts: Option<u64>| ts.map(|ts| DateTime::from_timestamp(ts as i64).to_rfc3339());
+
+    json!({
+        "id": key.id.to_string(),
+        "name": key.name,
+        "tenantId": key.tenant_id,
+        "permissions": key.permissions,
+        "createdBy": key.created_by,
+        "createdAt": timestamp(Some(key.created_at)),
+        "expiresAt": timestamp(key.expires_at),
+        "lastUsedAt": timestamp(key.last_used_at),
+        "expired": key.expires_at.is_some_and(|expires_at| expires_at <= now()),
+    })
+}
@@ -0,0 +1,411 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, config::approval::ProtectedOperation, Server};
+use directory::{
+    backend::internal::manage::{self, err_not_found, ErrorCode, ManageDirectory},
+    Permission,
+};
+use hyper::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::{
+    write::{assert::AssertValue, now, BatchBuilder, TaskClass, ValueClass},
+    IterateParams, ValueKey,
+};
+use trc::{AddContext, SecurityEvent};
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::{
+    decode_path_element,
+    principal::PrincipalManager,
+    stores::ManageStore,
+    undo::{ManageUndo, UndoOperation},
+};
+
+const APPROVAL_REQUEST: &[u8] = b"approval.request.";
+
+/// A protected operation waiting for a second administrator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApprovalRequest {
+    pub id: String,
+    pub operation: ProtectedOperation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub requested_by: String,
+    pub requested_by_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<u32>,
+    pub created: u64,
+    pub expires: u64,
+}
+
+pub trait ManageApproval: Sync + Send {
+    fn handle_manage_approval(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn request_approval(
+        &self,
+        operation: ProtectedOperation,
+        target: Option<String>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<Option<HttpResponse>>> + Send;
+}
+
+impl ManageApproval for Server {
+    async fn handle_manage_approval(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), path.get(2).copied(), req.method()) {
+            (None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ApprovalList)?;
+
+                let tenant_id = access_token.tenant.map(|t| t.id);
+                let requests = self
+                    .list_approval_requests()
+                    .await?
+                    .into_iter()
+                    .filter(|request| tenant_id.is_none() || request.tenant_id == tenant_id)
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": requests,
+                        "total": requests.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(id), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ApprovalList)?;
+
+                let request = self
+                    .get_approval_request(decode_path_element(id).as_ref(), access_token)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": request,
+                }))
+                .into_http_response())
+            }
+            (Some(id), None, &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ApprovalList)?;
+
+                // Requests can be cancelled by their creator or by an approver
+                let request = self
+                    .get_approval_request(decode_path_element(id).as_ref(), access_token)
+                    .await?;
+                if request.requested_by_id != access_token.primary_id() {
+                    access_token.assert_has_permission(Permission::ApprovalDecide)?;
+                }
+                self.remove_approval_request(&request).await?;
+
+                trc::event!(
+                    Security(SecurityEvent::ApprovalCancelled),
+                    Id = request.id,
+                    Type = request.operation.as_str(),
+                    Details = request.target,
+                    AccountId = access_token.primary_id(),
+                    AccountName = access_token.name.clone(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(id), Some(action @ ("approve" | "reject")), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ApprovalDecide)?;
+
+                let request = self
+                    .get_approval_request(decode_path_element(id).as_ref(), access_token)
+                    .await?;
+                if request.requested_by_id == access_token.primary_id() {
+                    return Err(manage::err_code(
+                        ErrorCode::ApprovalNotAllowed,
+                        "Requests cannot be approved or rejected by the administrator that created them",
+                        None::<u32>,
+                    ));
+                }
+
+                // Removing the request first guarantees it is executed at most once
+                self.remove_approval_request(&request).await?;
+
+                if action == "approve" {
+                    trc::event!(
+                        Security(SecurityEvent::ApprovalGranted),
+                        Id = request.id.clone(),
+                        Type = request.operation.as_str(),
+                        Details = request.target.clone(),
+                        From = request.requested_by.clone(),
+                        AccountId = access_token.primary_id(),
+                        AccountName = access_token.name.clone(),
+                    );
+
+                    self.execute_approved(&request).await?;
+                } else {
+                    trc::event!(
+                        Security(SecurityEvent::ApprovalRejected),
+                        Id = request.id,
+                        Type = request.operation.as_str(),
+                        Details = request.target,
+                        From = request.requested_by,
+                        AccountId = access_token.primary_id(),
+                        AccountName = access_token.name.clone(),
+                    );
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn request_approval(
+        &self,
+        operation: ProtectedOperation,
+        target: Option<String>,
+        access_token: &AccessToken,
+    ) -> trc::Result<Option<HttpResponse>> {
+        if !self.core.approval.is_protected(operation) {
+            return Ok(None);
+        }
+
+        let created = now();
+        let request = ApprovalRequest {
+            id: self
+                .inner
+                .data
+                .queue_id_gen
+                .generate()
+                .unwrap_or(created)
+                .to_string(),
+            operation,
+            target,
+            requested_by: access_token.name.clone(),
+            requested_by_id: access_token.primary_id(),
+            tenant_id: access_token.tenant.map(|t| t.id),
+            created,
+            expires: created + self.core.approval.expiry.as_secs(),
+        };
+
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(request_key(&request.id))),
+            serde_json::to_vec(&request).unwrap_or_default(),
+        );
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Security(SecurityEvent::ApprovalRequested),
+            Id = request.id.clone(),
+            Type = request.operation.as_str(),
+            Details = request.target.clone(),
+            AccountId = request.requested_by_id,
+            AccountName = request.requested_by.clone(),
+            Expires = trc::Value::Timestamp(request.expires),
+        );
+
+        Ok(Some(
+            JsonResponse::with_status(
+                StatusCode::ACCEPTED,
+                json!({
+                    "data": request,
+                }),
+            )
+            .into_http_response(),
+        ))
+    }
+}
+
+trait ApprovalStore: Sync + Send {
+    fn list_approval_requests(
+        &self,
+    ) -> impl Future<Output = trc::Result<Vec<ApprovalRequest>>> + Send;
+
+    fn get_approval_request(
+        &self,
+        id: &str,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<ApprovalRequest>> + Send;
+
+    fn remove_approval_request(
+        &self,
+        request: &ApprovalRequest,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn execute_approved(
+        &self,
+        request: &ApprovalRequest,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ApprovalStore for Server {
+    async fn list_approval_requests(&self) -> trc::Result<Vec<ApprovalRequest>> {
+        let from_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            APPROVAL_REQUEST.to_vec(),
+        )));
+        let mut to_key = APPROVAL_REQUEST.to_vec();
+        to_key.push(u8::MAX);
+        let to_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(to_key)));
+
+        let now = now();
+        let mut requests = Vec::new();
+        let mut expired = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    if let Ok(request) = serde_json::from_slice::<ApprovalRequest>(value) {
+                        if request.expires > now {
+                            requests.push(request);
+                        } else {
+                            expired.push(request);
+                        }
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Expired requests are removed the next time the queue is read
+        for request in expired {
+            if self.remove_approval_request(&request).await.is_ok() {
+                trc::event!(
+                    Security(SecurityEvent::ApprovalExpired),
+                    Id = request.id,
+                    Type = request.operation.as_str(),
+                    Details = request.target,
+                    AccountId = request.requested_by_id,
+                    AccountName = request.requested_by,
+                );
+            }
+        }
+
+        Ok(requests)
+    }
+
+    async fn get_approval_request(
+        &self,
+        id: &str,
+        access_token: &AccessToken,
+    ) -> trc::Result<ApprovalRequest> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        self.list_approval_requests()
+            .await?
+            .into_iter()
+            .find(|request| {
+                request.id == id && (tenant_id.is_none() || request.tenant_id == tenant_id)
+            })
+            .ok_or_else(|| manage::not_found(id.to_string()))
+    }
+
+    async fn remove_approval_request(&self, request: &ApprovalRequest) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(
+                ValueClass::Task(TaskClass::State(request_key(&request.id))),
+                AssertValue::Some,
+            )
+            .clear(ValueClass::Task(TaskClass::State(request_key(&request.id))));
+
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .map(|_| ())
+            .map_err(|err| {
+                if err.matches(trc::EventType::Store(trc::StoreEvent::AssertValueFailed)) {
+                    manage::not_found(request.id.clone())
+                } else {
+                    err.caused_by(trc::location!())
+                }
+            })
+    }
+
+    async fn execute_approved(&self, request: &ApprovalRequest) -> trc::Result<()> {
+        match request.operation {
+            ProtectedOperation::DeleteIndividual
+            | ProtectedOperation::DeleteGroup
+            | ProtectedOperation::DeleteList
+            | ProtectedOperation::DeleteDomain
+            | ProtectedOperation::DeleteTenant
+            | ProtectedOperation::DeleteRole => {
+                let name = request.target.as_deref().unwrap_or_default();
+                let principal = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name)
+                    .await?
+                    .filter(|p| {
+                        p.has_tenant_access(request.tenant_id)
+                            && ProtectedOperation::delete(p.typ) == Some(request.operation)
+                    })
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+
+                let before = self.principal_snapshot(principal.id).await?;
+                self.delete_principal_by_id(principal.id, principal.typ, request.tenant_id, false)
+                    .await?;
+                self.record_undo(
+                    UndoOperation::Delete,
+                    before,
+                    None,
+                    &[],
+                    principal.tenant,
+                    &request.requested_by,
+                )
+                .await;
+
+                Ok(())
+            }
+            ProtectedOperation::PurgeAccount
+            | ProtectedOperation::PurgeData
+            | ProtectedOperation::PurgeBlob
+            | ProtectedOperation::PurgeLookup => {
+                let event = self
+                    .purge_request(request.operation, request.target.as_deref())
+                    .await?;
+                self.housekeeper_request(event).await.map(|_| ())
+            }
+        }
+    }
+}
+
+fn request_key(id: &str) -> Vec<u8> {
+    let mut key = Vec::with_capacity(APPROVAL_REQUEST.len() + id.len());
+    key.extend_from_slice(APPROVAL_REQUEST);
+    key.extend_from_slice(id.as_bytes());
+    key
+}
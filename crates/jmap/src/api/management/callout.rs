@@ -0,0 +1,50 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use directory::Permission;
+use hyper::Method;
+use serde_json::json;
+use smtp::inbound::callout::SmtpCallout;
+use std::future::Future;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageCallout: Sync + Send {
+    fn handle_manage_callout(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageCallout for Server {
+    async fn handle_manage_callout(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (Some(domain), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::CalloutCacheFlush)?;
+
+                self.flush_callout_cache(decode_path_element(domain).as_ref())
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
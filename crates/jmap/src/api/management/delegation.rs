@@ -0,0 +1,253 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::{
+        delegation::{DomainDelegation, DomainDelegationStore, DELEGABLE_PERMISSIONS},
+        lookup::DirectoryStore,
+        manage::{self, err_code, err_not_found, ErrorCode, ManageDirectory},
+        PrincipalInfo,
+    },
+    Permission, Principal, QueryBy, Type,
+};
+use hyper::Method;
+use serde_json::json;
+use std::future::Future;
+use trc::AddContext;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageDelegation: Sync + Send {
+    fn handle_manage_delegation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn delegated_domains(
+        &self,
+        access_token: &AccessToken,
+        permission: Permission,
+    ) -> impl Future<Output = trc::Result<Vec<String>>> + Send;
+
+    fn assert_domain_permission(
+        &self,
+        access_token: &AccessToken,
+        permission: Permission,
+        principal: &Principal,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn assert_domain_permission_by_id(
+        &self,
+        access_token: &AccessToken,
+        permission: Permission,
+        principal_id: u32,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ManageDelegation for Server {
+    async fn handle_manage_delegation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        let principal = match path.get(1) {
+            Some(name) => delegation_principal(self, name, tenant_id).await?,
+            None => return Err(trc::ResourceEvent::NotFound.into_err()),
+        };
+
+        match (path.get(2), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DelegationList)?;
+
+                let mut delegations = Vec::new();
+                for delegation in self.store().get_delegations(principal.id).await? {
+                    if let Some(domain) = self
+                        .store()
+                        .get_principal(delegation.domain_id)
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        delegations.push(json!({
+                            "domain": domain.name(),
+                            "permissions": delegation
+                                .permissions
+                                .iter()
+                                .map(|p| p.name())
+                                .collect::<Vec<_>>(),
+                        }));
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": delegations,
+                }))
+                .into_http_response())
+            }
+            (Some(domain), &Method::PUT) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DelegationUpdate)?;
+
+                let domain = delegation_domain(self, domain, tenant_id).await?;
+                let names =
+                    serde_json::from_slice::<Vec<String>>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+
+                // Only principal management permissions held by the
+                // granting account can be delegated
+                let mut permissions = Vec::with_capacity(names.len());
+                for name in names {
+                    let permission = Permission::from_name(&name)
+                        .filter(|p| DELEGABLE_PERMISSIONS.contains(p))
+                        .ok_or_else(|| {
+                            err_code(
+                                ErrorCode::PermissionInvalid,
+                                "Invalid permission",
+                                format!("Permission {name:?} cannot be delegated").into(),
+                            )
+                        })?;
+                    if !access_token.has_permission(permission) {
+                        return Err(err_code(
+                            ErrorCode::PermissionNotGrantable,
+                            "Invalid permission",
+                            format!("Your account cannot grant the {name:?} permission").into(),
+                        ));
+                    }
+                    if !permissions.contains(&permission) {
+                        permissions.push(permission);
+                    }
+                }
+
+                if !permissions.is_empty() {
+                    self.store()
+                        .set_delegation(
+                            principal.id,
+                            DomainDelegation {
+                                domain_id: domain.id,
+                                permissions,
+                            },
+                        )
+                        .await?;
+                } else {
+                    self.store()
+                        .delete_delegation(principal.id, domain.id)
+                        .await?;
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(domain), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DelegationUpdate)?;
+
+                let domain = delegation_domain(self, domain, tenant_id).await?;
+                self.store()
+                    .delete_delegation(principal.id, domain.id)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn delegated_domains(
+        &self,
+        access_token: &AccessToken,
+        permission: Permission,
+    ) -> trc::Result<Vec<String>> {
+        self.store()
+            .get_delegated_domains(access_token.primary_id(), permission)
+            .await
+    }
+
+    async fn assert_domain_permission(
+        &self,
+        access_token: &AccessToken,
+        permission: Permission,
+        principal: &Principal,
+    ) -> trc::Result<()> {
+        if access_token.has_permission(permission)
+            || (DELEGABLE_PERMISSIONS.contains(&permission)
+                && principal
+                    .is_under_domains(&self.delegated_domains(access_token, permission).await?))
+        {
+            Ok(())
+        } else {
+            access_token.assert_has_permission(permission)
+        }
+    }
+
+    async fn assert_domain_permission_by_id(
+        &self,
+        access_token: &AccessToken,
+        permission: Permission,
+        principal_id: u32,
+    ) -> trc::Result<()> {
+        if access_token.has_permission(permission) || !DELEGABLE_PERMISSIONS.contains(&permission) {
+            access_token.assert_has_permission(permission)
+        } else if let Some(principal) = self
+            .store()
+            .query(QueryBy::Id(principal_id), false)
+            .await
+            .caused_by(trc::location!())?
+        {
+            self.assert_domain_permission(access_token, permission, &principal)
+                .await
+        } else {
+            access_token.assert_has_permission(permission)
+        }
+    }
+}
+
+async fn delegation_principal(
+    server: &Server,
+    name: &str,
+    tenant_id: Option<u32>,
+) -> trc::Result<PrincipalInfo> {
+    let name = decode_path_element(name);
+    server
+        .store()
+        .get_principal_info(name.as_ref())
+        .await?
+        .filter(|p| {
+            p.has_tenant_access(tenant_id)
+                && matches!(p.typ, Type::Individual | Type::Group | Type::Service)
+        })
+        .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))
+}
+
+async fn delegation_domain(
+    server: &Server,
+    name: &str,
+    tenant_id: Option<u32>,
+) -> trc::Result<PrincipalInfo> {
+    let name = decode_path_element(name);
+    server
+        .store()
+        .get_principal_info(name.as_ref())
+        .await?
+        .filter(|p| p.typ == Type::Domain && p.has_tenant_access(tenant_id))
+        .ok_or_else(|| manage::not_found(name.to_string()))
+}
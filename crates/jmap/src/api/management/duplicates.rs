@@ -0,0 +1,278 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::manage::{err_not_found, ErrorCode, ManageDirectory},
+    Permission,
+};
+use hyper::Method;
+use jmap_proto::types::{collection::Collection, property::Property};
+use mail_parser::MimeHeaders;
+use serde_json::json;
+use store::{
+    ahash::AHashMap,
+    roaring::RoaringBitmap,
+    write::{key::DeserializeBigEndian, Bincode, ValueClass},
+    Deserialize, IterateParams, ValueKey, U32_LEN,
+};
+use trc::AddContext;
+use utils::{url_params::UrlParams, BlobHash};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    email::metadata::MessageMetadata,
+    mailbox::{UidMailbox, TOMBSTONE_ID},
+    JmapMethods,
+};
+
+use super::{
+    decode_path_element,
+    mailbox::{mailbox_path, MailboxTree},
+};
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 500;
+
+// Attachments are not hashed individually, they are identified by their
+// decoded size, content type and file name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct AttachmentKey {
+    size: usize,
+    content_type: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct DuplicateGroup {
+    size: usize,
+    document_ids: Vec<u32>,
+}
+
+pub trait ManageDuplicates: Sync + Send {
+    fn handle_manage_duplicates(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageDuplicates for Server {
+    async fn handle_manage_duplicates(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        if *req.method() != Method::GET {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        }
+
+        // Validate the access token
+        access_token.assert_has_permission(Permission::DuplicateReportGet)?;
+
+        let name = path
+            .get(1)
+            .map(|name| decode_path_element(name))
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let account_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(name.as_ref())
+            .await?
+            .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?
+            .id;
+        let limit = UrlParams::new(req.uri().query())
+            .parse::<usize>("limit")
+            .unwrap_or(DEFAULT_LIMIT)
+            .clamp(1, MAX_LIMIT);
+
+        // Messages pending deletion are skipped
+        let tombstoned = self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                TOMBSTONE_ID,
+            )
+            .await?
+            .unwrap_or_default();
+
+        // Group messages by blob and attachments by fingerprint, only the
+        // metadata is read so the message bodies are never loaded
+        let mut messages: AHashMap<BlobHash, DuplicateGroup> = AHashMap::new();
+        let mut attachments: AHashMap<AttachmentKey, DuplicateGroup> = AHashMap::new();
+        let mut total_messages = 0u64;
+        let mut total_size = 0u64;
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(
+                    ValueKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: 0,
+                        class: ValueClass::Property(Property::BodyStructure.into()),
+                    },
+                    ValueKey {
+                        account_id,
+                        collection: Collection::Email.into(),
+                        document_id: u32::MAX,
+                        class: ValueClass::Property(Property::BodyStructure.into()),
+                    },
+                ),
+                |key, value| {
+                    let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                    if tombstoned.contains(document_id) {
+                        return Ok(true);
+                    }
+                    let metadata = Bincode::<MessageMetadata>::deserialize(value)?.inner;
+                    total_messages += 1;
+                    total_size += metadata.size as u64;
+
+                    for part_id in &metadata.contents.attachments {
+                        if let Some(part) = metadata.contents.parts.get(*part_id) {
+                            let group = attachments
+                                .entry(AttachmentKey {
+                                    size: part.size,
+                                    content_type: part.content_type().map(|ct| {
+                                        if let Some(subtype) = ct.subtype() {
+                                            format!("{}/{}", ct.ctype(), subtype)
+                                        } else {
+                                            ct.ctype().to_string()
+                                        }
+                                        .to_lowercase()
+                                    }),
+                                    name: part.attachment_name().map(|name| name.to_string()),
+                                })
+                                .or_default();
+                            group.size = part.size;
+                            group.document_ids.push(document_id);
+                        }
+                    }
+
+                    let group = messages.entry(metadata.blob_hash).or_default();
+                    group.size = metadata.size;
+                    group.document_ids.push(document_id);
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Keep the groups that waste the most space
+        let messages = top_groups(messages, limit);
+        let attachments = top_groups(attachments, limit);
+
+        // Locate the folders of the duplicated messages
+        let mut document_ids = RoaringBitmap::new();
+        for group in messages
+            .iter()
+            .map(|(_, group)| group)
+            .chain(attachments.iter().map(|(_, group)| group))
+        {
+            document_ids.extend(group.document_ids.iter().copied());
+        }
+        let mailboxes = self.mailbox_tree(account_id).await?;
+        let locations = if !document_ids.is_empty() {
+            self.get_properties::<Vec<UidMailbox>, _, _>(
+                account_id,
+                Collection::Email,
+                &document_ids,
+                Property::MailboxIds,
+            )
+            .await
+            .caused_by(trc::location!())?
+            .into_iter()
+            .map(|(document_id, mailbox_ids)| {
+                let mut paths = mailbox_ids
+                    .iter()
+                    .map(|mailbox| mailbox_path(&mailboxes, mailbox.mailbox_id))
+                    .collect::<Vec<_>>();
+                paths.sort_unstable();
+                (document_id, paths)
+            })
+            .collect::<AHashMap<_, _>>()
+        } else {
+            AHashMap::new()
+        };
+        let emails = |group: &DuplicateGroup| {
+            group
+                .document_ids
+                .iter()
+                .map(|document_id| {
+                    json!({
+                        "documentId": document_id,
+                        "mailboxes": locations.get(document_id).cloned().unwrap_or_default(),
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let duplicate_messages = messages
+            .iter()
+            .map(|(hash, group)| {
+                json!({
+                    "hash": hash.to_hex(),
+                    "count": group.document_ids.len(),
+                    "size": group.size,
+                    "wastedSize": group.wasted_size(),
+                    "emails": emails(group),
+                })
+            })
+            .collect::<Vec<_>>();
+        let duplicate_attachments = attachments
+            .iter()
+            .map(|(key, group)| {
+                json!({
+                    "name": key.name,
+                    "contentType": key.content_type,
+                    "count": group.document_ids.len(),
+                    "size": group.size,
+                    "wastedSize": group.wasted_size(),
+                    "emails": emails(group),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(JsonResponse::new(json!({
+            "data": {
+                "totalMessages": total_messages,
+                "totalSize": total_size,
+                "messages": duplicate_messages,
+                "attachments": duplicate_attachments,
+            },
+        }))
+        .into_http_response())
+    }
+}
+
+impl DuplicateGroup {
+    fn wasted_size(&self) -> u64 {
+        (self.size as u64) * (self.document_ids.len().saturating_sub(1) as u64)
+    }
+}
+
+fn top_groups<K>(groups: AHashMap<K, DuplicateGroup>, limit: usize) -> Vec<(K, DuplicateGroup)> {
+    let mut groups = groups
+        .into_iter()
+        .filter(|(_, group)| group.document_ids.len() > 1)
+        .collect::<Vec<_>>();
+    groups.sort_unstable_by(|(_, a), (_, b)| {
+        b.wasted_size()
+            .cmp(&a.wasted_size())
+            .then_with(|| b.document_ids.len().cmp(&a.document_ids.len()))
+    });
+    groups.truncate(limit);
+    groups
+}
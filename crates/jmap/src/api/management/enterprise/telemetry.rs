@@ -21,7 +21,7 @@ use common::{
     },
     Server,
 };
-use directory::{backend::internal::manage, Permission};
+use directory::{backend::internal::manage, Permission, Type};
 use http_body_util::{combinators::BoxBody, StreamBody};
 use hyper::{
     body::{Bytes, Frame},
@@ -456,19 +456,25 @@ impl TelemetryApi for Server {
                 }
 
                 // Refresh expensive metrics
-                for metric_type in [
-                    MetricType::QueueCount,
-                    MetricType::UserCount,
-                    MetricType::DomainCount,
-                ] {
-                    if metric_types.contains(&metric_type) {
-                        let value = match metric_type {
-                            MetricType::QueueCount => self.total_queued_messages().await?,
-                            MetricType::UserCount => self.total_accounts().await?,
-                            MetricType::DomainCount => self.total_domains().await?,
-                            _ => unreachable!(),
-                        };
-                        Collector::update_gauge(metric_type, value);
+                if metric_types.contains(&MetricType::QueueCount) {
+                    Collector::update_gauge(
+                        MetricType::QueueCount,
+                        self.total_queued_messages().await?,
+                    );
+                }
+                if metric_types.contains(&MetricType::UserCount)
+                    || metric_types.contains(&MetricType::DomainCount)
+                {
+                    // Principals of all types are counted in a single pass
+                    let totals = self.total_principals().await?;
+                    for (metric_type, typ) in [
+                        (MetricType::UserCount, Type::Individual),
+                        (MetricType::DomainCount, Type::Domain),
+                    ] {
+                        Collector::update_gauge(
+                            metric_type,
+                            totals.get(&typ).copied().unwrap_or_default(),
+                        );
                     }
                 }
 
@@ -0,0 +1,55 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use directory::Permission;
+use hyper::Method;
+use serde_json::json;
+use smtp::inbound::expand::RecipientExpand;
+use std::future::Future;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageRecipientExpand: Sync + Send {
+    fn handle_manage_expand(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageRecipientExpand for Server {
+    async fn handle_manage_expand(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1), req.method()) {
+            (Some(address), &Method::GET) => {
+                // Validate the access token, the expansion reveals memberships
+                access_token.assert_has_permission(Permission::RecipientExpand)?;
+
+                // Dry run, nothing is delivered
+                let expansion = self
+                    .expand_recipient(
+                        decode_path_element(address).as_ref(),
+                        access_token.tenant.map(|t| t.id),
+                    )
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": expansion,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
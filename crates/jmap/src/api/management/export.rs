@@ -0,0 +1,889 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, SyncSender},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{self, err_not_found, ErrorCode, ManageDirectory},
+        PrincipalField,
+    },
+    Permission, QueryBy, Type,
+};
+use hkdf::hmac::{Hmac, Mac};
+use http_body_util::{combinators::BoxBody, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    Method, StatusCode,
+};
+use jmap_proto::{
+    object::Object,
+    types::{collection::Collection, keyword::Keyword, property::Property, value::Value},
+};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use store::{
+    ahash::AHashMap,
+    write::{now, BatchBuilder, Bincode, TaskClass, ValueClass},
+    Serialize as _, ValueKey,
+};
+use trc::AddContext;
+use utils::url_params::UrlParams;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, HttpResponseBody, JsonResponse},
+    blob::download::BlobDownload,
+    email::metadata::MessageMetadata,
+    mailbox::UidMailbox,
+    JmapMethods,
+};
+
+use super::{
+    decode_path_element,
+    mailbox::{mailbox_path, MailboxTree},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Time after which an export that stopped reporting progress can be resumed
+const LOCK_EXPIRY: u64 = 15 * 60;
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TenantExportJob {
+    id: String,
+    tenant_id: u32,
+    tenant_name: String,
+    requested_by: String,
+    created_at: u64,
+    updated_at: u64,
+    status: TenantExportStatus,
+    accounts: Vec<u32>,
+    completed: usize,
+    expires_at: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum TenantExportStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+pub trait ManageTenantExport: Sync + Send {
+    fn handle_manage_export(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_tenant_export_download(
+        &self,
+        req: &HttpRequest,
+        tenant_id: &str,
+        job_id: &str,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+pub trait AccountExport: Sync + Send {
+    fn export_account_archive(
+        &self,
+        account_id: u32,
+        path: PathBuf,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+trait TenantExportJobs: Sync + Send {
+    fn run_tenant_export(&self, job: TenantExportJob) -> impl Future<Output = ()> + Send;
+
+    fn write_tenant_export(
+        &self,
+        job: &mut TenantExportJob,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn tenant_export_job(
+        &self,
+        tenant_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<TenantExportJob>>> + Send;
+
+    fn write_tenant_export_job(
+        &self,
+        job: &TenantExportJob,
+        refresh_lock: bool,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn try_lock_tenant_export(
+        &self,
+        tenant_id: u32,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn is_tenant_export_locked(
+        &self,
+        tenant_id: u32,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn unlock_tenant_export(&self, tenant_id: u32) -> impl Future<Output = ()> + Send;
+
+    fn tenant_export_status(
+        &self,
+        job: &TenantExportJob,
+    ) -> impl Future<Output = trc::Result<serde_json::Value>> + Send;
+}
+
+impl ManageTenantExport for Server {
+    async fn handle_manage_export(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let (Some("tenant"), Some(name)) = (path.get(1).copied(), path.get(2).copied()) else {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        };
+
+        // Validate the access token
+        access_token.assert_has_permission(Permission::TenantExport)?;
+
+        let name = decode_path_element(name);
+        let tenant_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(name.as_ref())
+            .await?
+            .filter(|p| {
+                p.typ == Type::Tenant && p.has_tenant_access(access_token.tenant.map(|t| t.id))
+            })
+            .map(|p| p.id)
+            .ok_or_else(|| err_not_found(ErrorCode::TenantNotFound, name.to_string()))?;
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        #[cfg(feature = "enterprise")]
+        if !self.core.is_enterprise_edition() {
+            return Err(manage::enterprise());
+        }
+
+        // SPDX-SnippetEnd
+
+        match *req.method() {
+            Method::GET => {
+                let job = self
+                    .tenant_export_job(tenant_id)
+                    .await?
+                    .ok_or_else(|| manage::not_found(name.to_string()))?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.tenant_export_status(&job).await?,
+                }))
+                .into_http_response())
+            }
+            Method::POST => {
+                // Only one export per tenant can run at a time
+                if !self.try_lock_tenant_export(tenant_id).await? {
+                    return Err(manage::error(
+                        "An export is already running for this tenant.",
+                        None::<u32>,
+                    ));
+                }
+
+                // Interrupted or failed exports are resumed
+                let job = match self.tenant_export_job(tenant_id).await {
+                    Ok(Some(job)) if job.status != TenantExportStatus::Completed => {
+                        TenantExportJob {
+                            status: TenantExportStatus::Running,
+                            updated_at: now(),
+                            ..job
+                        }
+                    }
+                    Ok(previous) => {
+                        if let Some(previous) = previous {
+                            let _ = std::fs::remove_dir_all(
+                                self.core.jmap.tenant_export_path.join(&previous.id),
+                            );
+                        }
+
+                        match self
+                            .core
+                            .storage
+                            .data
+                            .list_principals(
+                                None,
+                                tenant_id.into(),
+                                &[Type::Individual, Type::Group],
+                                &[PrincipalField::Name],
+                                0,
+                                0,
+                            )
+                            .await
+                        {
+                            Ok(accounts) => TenantExportJob {
+                                id: format!("{:x}", thread_rng().gen::<u64>()),
+                                tenant_id,
+                                tenant_name: name.to_string(),
+                                requested_by: access_token.name.clone(),
+                                created_at: now(),
+                                updated_at: now(),
+                                status: TenantExportStatus::Running,
+                                accounts: accounts.items.iter().map(|p| p.id()).collect(),
+                                completed: 0,
+                                expires_at: 0,
+                            },
+                            Err(err) => {
+                                self.unlock_tenant_export(tenant_id).await;
+                                return Err(err);
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        self.unlock_tenant_export(tenant_id).await;
+                        return Err(err);
+                    }
+                };
+
+                if let Err(err) = self.write_tenant_export_job(&job, false).await {
+                    self.unlock_tenant_export(tenant_id).await;
+                    return Err(err);
+                }
+
+                trc::event!(
+                    Security(trc::SecurityEvent::TenantExportRequested),
+                    Id = job.id.clone(),
+                    Domain = job.tenant_name.clone(),
+                    AccountId = access_token.primary_id(),
+                    AccountName = access_token.name.clone(),
+                    Total = job.accounts.len(),
+                );
+
+                let response = self.tenant_export_status(&job).await?;
+                let server = self.clone();
+                tokio::spawn(async move {
+                    server.run_tenant_export(job).await;
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": response,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn handle_tenant_export_download(
+        &self,
+        req: &HttpRequest,
+        tenant_id: &str,
+        job_id: &str,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+        let expires = params.parse::<u64>("expires").unwrap_or_default();
+        let signature = params
+            .get("signature")
+            .and_then(|signature| URL_SAFE_NO_PAD.decode(signature).ok())
+            .unwrap_or_default();
+        let tenant_id = tenant_id.parse::<u32>().unwrap_or(u32::MAX);
+
+        // Validate the signed link
+        if expires <= now()
+            || export_signature(&self.core.oauth.oauth_key, tenant_id, job_id, expires)
+                .verify_slice(&signature)
+                .is_err()
+        {
+            return Err(trc::SecurityEvent::Unauthorized
+                .into_err()
+                .details("Invalid or expired download link")
+                .caused_by(trc::location!()));
+        }
+
+        let job = self
+            .tenant_export_job(tenant_id)
+            .await?
+            .filter(|job| {
+                job.id == job_id
+                    && job.status == TenantExportStatus::Completed
+                    && job.expires_at > now()
+            })
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let path = self
+            .core
+            .jmap
+            .tenant_export_path
+            .join(&job.id)
+            .join("bundle.zip");
+        let mut file = std::fs::File::open(&path).map_err(|err| {
+            trc::StoreEvent::FilesystemError
+                .reason(err)
+                .details("Failed to open export bundle")
+                .caused_by(trc::location!())
+        })?;
+
+        trc::event!(
+            Security(trc::SecurityEvent::TenantExportDownloaded),
+            Id = job.id.clone(),
+            Domain = job.tenant_name.clone(),
+        );
+
+        // The bundle is read one chunk at a time as the client consumes it
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
+        tokio::task::spawn_blocking(move || {
+            use std::io::Read;
+
+            loop {
+                let mut chunk = vec![0u8; DOWNLOAD_CHUNK_SIZE];
+                match file.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(size) => {
+                        chunk.truncate(size);
+                        if tx.blocking_send(chunk).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        trc::error!(trc::StoreEvent::FilesystemError
+                            .reason(err)
+                            .details("Failed to read export bundle")
+                            .caused_by(trc::location!()));
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(HttpResponse {
+            status: StatusCode::OK,
+            content_type: "application/zip".into(),
+            content_disposition: format!(
+                "attachment; filename=\"export-{}.zip\"",
+                job.tenant_name.replace('\"', "\\\"")
+            )
+            .into(),
+            cache_control: "private, no-store".into(),
+            body: HttpResponseBody::Stream(BoxBody::new(StreamBody::new(async_stream::stream! {
+                while let Some(chunk) = rx.recv().await {
+                    yield Ok(Frame::data(Bytes::from(chunk)));
+                }
+            }))),
+        })
+    }
+}
+
+impl TenantExportJobs for Server {
+    async fn run_tenant_export(&self, mut job: TenantExportJob) {
+        match self.write_tenant_export(&mut job).await {
+            Ok(_) => {
+                job.status = TenantExportStatus::Completed;
+                job.expires_at = now() + self.core.jmap.tenant_export_expiry.as_secs();
+
+                trc::event!(
+                    Security(trc::SecurityEvent::TenantExportCompleted),
+                    Id = job.id.clone(),
+                    Domain = job.tenant_name.clone(),
+                    Total = job.accounts.len(),
+                    Expires = trc::Value::Timestamp(job.expires_at),
+                );
+            }
+            Err(err) => {
+                job.status = TenantExportStatus::Failed;
+
+                trc::error!(err
+                    .id(job.id.clone())
+                    .details("Tenant export failed")
+                    .caused_by(trc::location!()));
+            }
+        }
+
+        job.updated_at = now();
+        if let Err(err) = self.write_tenant_export_job(&job, false).await {
+            trc::error!(err
+                .id(job.id.clone())
+                .details("Failed to write tenant export status")
+                .caused_by(trc::location!()));
+        }
+        self.unlock_tenant_export(job.tenant_id).await;
+    }
+
+    async fn write_tenant_export(&self, job: &mut TenantExportJob) -> trc::Result<()> {
+        let dir = self.core.jmap.tenant_export_path.join(&job.id);
+        let accounts_dir = dir.join("accounts");
+        std::fs::create_dir_all(&accounts_dir).map_err(fs_error)?;
+
+        // Export the directory, secrets are never included
+        let mut tenant = self
+            .core
+            .storage
+            .data
+            .query(QueryBy::Id(job.tenant_id), true)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| err_not_found(ErrorCode::TenantNotFound, job.tenant_name.clone()))?;
+        self.core
+            .storage
+            .data
+            .map_field_ids(&mut tenant, &[])
+            .await
+            .caused_by(trc::location!())?;
+        tenant.remove(PrincipalField::Secrets);
+        let mut principals = self
+            .core
+            .storage
+            .data
+            .list_principals(None, job.tenant_id.into(), &[], &[], 0, 0)
+            .await
+            .caused_by(trc::location!())?
+            .items;
+        let mut types: AHashMap<&'static str, u64> = AHashMap::new();
+        for principal in &mut principals {
+            principal.remove(PrincipalField::Secrets);
+            *types.entry(principal.typ().as_str()).or_default() += 1;
+        }
+        write_json(
+            &dir.join("directory.json"),
+            &json!({
+                "tenant": tenant,
+                "principals": principals,
+            }),
+        )?;
+
+        // Export tenant statistics
+        let mut messages = 0;
+        for account_id in &job.accounts {
+            messages += self
+                .get_message_count(*account_id)
+                .await
+                .caused_by(trc::location!())?;
+        }
+        write_json(
+            &dir.join("statistics.json"),
+            &json!({
+                "principals": types,
+                "accounts": job.accounts.len(),
+                "messages": messages,
+                "usedQuota": self.get_used_quota(job.tenant_id).await?,
+                "generatedAt": now(),
+            }),
+        )?;
+
+        // Export accounts, archives written by a previous run are kept
+        for (idx, account_id) in job.accounts.clone().into_iter().enumerate() {
+            let path = accounts_dir.join(format!("{account_id}.zip"));
+            if !path.exists() {
+                let tmp_path = accounts_dir.join(format!("{account_id}.zip.tmp"));
+                self.export_account_archive(account_id, tmp_path.clone())
+                    .await
+                    .add_context(|err| err.caused_by(trc::location!()).account_id(account_id))?;
+                std::fs::rename(&tmp_path, &path).map_err(fs_error)?;
+            }
+
+            job.completed = idx + 1;
+            job.updated_at = now();
+            self.write_tenant_export_job(job, true).await?;
+        }
+
+        // Bundle all parts into a single archive
+        let accounts = job.accounts.clone();
+        tokio::task::spawn_blocking(move || write_bundle(&dir, &accounts))
+            .await
+            .map_err(|err| {
+                trc::EventType::Server(trc::ServerEvent::ThreadError)
+                    .reason(err)
+                    .caused_by(trc::location!())
+            })?
+    }
+
+    async fn tenant_export_job(&self, tenant_id: u32) -> trc::Result<Option<TenantExportJob>> {
+        self.core
+            .storage
+            .data
+            .get_value::<Bincode<TenantExportJob>>(ValueKey::from(ValueClass::Task(
+                TaskClass::State(export_key(tenant_id)),
+            )))
+            .await
+            .map(|job| job.map(|job| job.inner))
+            .caused_by(trc::location!())
+    }
+
+    async fn write_tenant_export_job(
+        &self,
+        job: &TenantExportJob,
+        refresh_lock: bool,
+    ) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(export_key(job.tenant_id))),
+            Bincode::new(job.clone()).serialize(),
+        );
+        if refresh_lock {
+            batch.set(
+                ValueClass::Task(TaskClass::Lock(export_key(job.tenant_id))),
+                (now() + LOCK_EXPIRY).serialize(),
+            );
+        }
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn try_lock_tenant_export(&self, tenant_id: u32) -> trc::Result<bool> {
+        let mut batch = BatchBuilder::new();
+
+        match self
+            .core
+            .storage
+            .data
+            .get_value::<u64>(ValueKey::from(ValueClass::Task(TaskClass::Lock(
+                export_key(tenant_id),
+            ))))
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(expiry) if expiry > now() => {
+                return Ok(false);
+            }
+            Some(expiry) => {
+                batch.assert_value(
+                    ValueClass::Task(TaskClass::Lock(export_key(tenant_id))),
+                    expiry,
+                );
+            }
+            None => {
+                batch.assert_value(ValueClass::Task(TaskClass::Lock(export_key(tenant_id))), ());
+            }
+        }
+        batch.set(
+            ValueClass::Task(TaskClass::Lock(export_key(tenant_id))),
+            (now() + LOCK_EXPIRY).serialize(),
+        );
+
+        match self.core.storage.data.write(batch.build()).await {
+            Ok(_) => Ok(true),
+            Err(err) if err.is_assertion_failure() => Ok(false),
+            Err(err) => Err(err.caused_by(trc::location!())),
+        }
+    }
+
+    async fn is_tenant_export_locked(&self, tenant_id: u32) -> trc::Result<bool> {
+        self.core
+            .storage
+            .data
+            .get_value::<u64>(ValueKey::from(ValueClass::Task(TaskClass::Lock(
+                export_key(tenant_id),
+            ))))
+            .await
+            .map(|expiry| expiry.is_some_and(|expiry| expiry > now()))
+            .caused_by(trc::location!())
+    }
+
+    async fn unlock_tenant_export(&self, tenant_id: u32) {
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Task(TaskClass::Lock(export_key(tenant_id))));
+
+        if let Err(err) = self.core.storage.data.write(batch.build()).await {
+            trc::error!(err
+                .details("Failed to release tenant export lock")
+                .caused_by(trc::location!()));
+        }
+    }
+
+    async fn tenant_export_status(&self, job: &TenantExportJob) -> trc::Result<serde_json::Value> {
+        let status = match job.status {
+            TenantExportStatus::Running if self.is_tenant_export_locked(job.tenant_id).await? => {
+                "running"
+            }
+            TenantExportStatus::Running => "interrupted",
+            TenantExportStatus::Completed if job.expires_at > now() => "completed",
+            TenantExportStatus::Completed => "expired",
+            TenantExportStatus::Failed => "failed",
+        };
+        let url = (status == "completed").then(|| {
+            let signature = URL_SAFE_NO_PAD.encode(
+                export_signature(
+                    &self.core.oauth.oauth_key,
+                    job.tenant_id,
+                    &job.id,
+                    job.expires_at,
+                )
+                .finalize()
+                .into_bytes(),
+            );
+            format!(
+                "/export/{}/{}?expires={}&signature={signature}",
+                job.tenant_id, job.id, job.expires_at
+            )
+        });
+
+        Ok(json!({
+            "id": job.id,
+            "tenant": job.tenant_name,
+            "requestedBy": job.requested_by,
+            "createdAt": job.created_at,
+            "updatedAt": job.updated_at,
+            "status": status,
+            "accounts": job.accounts.len(),
+            "completed": job.completed,
+            "expiresAt": (status == "completed").then_some(job.expires_at),
+            "url": url,
+        }))
+    }
+}
+
+impl AccountExport for Server {
+    async fn export_account_archive(&self, account_id: u32, path: PathBuf) -> trc::Result<()> {
+        let archive = ArchiveWriter::create(path)?;
+
+        // Export mailboxes
+        let mailboxes = self.mailbox_tree(account_id).await?;
+        archive.add(
+            "mailboxes.json",
+            serde_json::to_vec_pretty(
+                &mailboxes
+                    .iter()
+                    .map(|(mailbox_id, mailbox)| {
+                        json!({
+                            "id": mailbox_id,
+                            "path": mailbox_path(&mailboxes, *mailbox_id),
+                            "role": mailbox.role,
+                            "subscribed": mailbox.subscribed,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap_or_default(),
+        )?;
+
+        // Export messages
+        let mut messages = Vec::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .unwrap_or_default()
+        {
+            let Some(metadata) = self
+                .get_property::<Bincode<MessageMetadata>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    &Property::BodyStructure,
+                )
+                .await?
+            else {
+                continue;
+            };
+            let Some(raw_message) = self
+                .get_blob(&metadata.inner.blob_hash, 0..usize::MAX)
+                .await?
+            else {
+                continue;
+            };
+            let mailbox_ids = self
+                .get_property::<Vec<UidMailbox>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::MailboxIds,
+                )
+                .await?
+                .unwrap_or_default();
+            let keywords = self
+                .get_property::<Vec<Keyword>>(
+                    account_id,
+                    Collection::Email,
+                    document_id,
+                    Property::Keywords,
+                )
+                .await?
+                .unwrap_or_default();
+
+            let file_name = format!("messages/{document_id}.eml");
+            archive.add(file_name.clone(), raw_message)?;
+            messages.push(json!({
+                "file": file_name,
+                "mailboxes": mailbox_ids
+                    .iter()
+                    .map(|m| mailbox_path(&mailboxes, m.mailbox_id))
+                    .collect::<Vec<_>>(),
+                "keywords": keywords.iter().map(|k| k.to_string()).collect::<Vec<_>>(),
+                "receivedAt": metadata.inner.received_at,
+            }));
+        }
+        archive.add(
+            "messages.json",
+            serde_json::to_vec_pretty(&messages).unwrap_or_default(),
+        )?;
+
+        // Export Sieve scripts
+        let mut scripts = Vec::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::SieveScript)
+            .await?
+            .unwrap_or_default()
+        {
+            let Some(script) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::SieveScript,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            else {
+                continue;
+            };
+            let Some((blob_id, size)) = script
+                .get(&Property::BlobId)
+                .as_blob_id()
+                .and_then(|blob_id| (blob_id, blob_id.section.as_ref()?.size).into())
+            else {
+                continue;
+            };
+            let Some(contents) = self.get_blob(&blob_id.hash, 0..size).await? else {
+                continue;
+            };
+
+            let file_name = format!("sieve/{document_id}.sieve");
+            archive.add(file_name.clone(), contents)?;
+            scripts.push(json!({
+                "file": file_name,
+                "name": script.get(&Property::Name).as_string(),
+                "isActive": matches!(script.get(&Property::IsActive), Value::Bool(true)),
+            }));
+        }
+        archive.add(
+            "sieve.json",
+            serde_json::to_vec_pretty(&scripts).unwrap_or_default(),
+        )?;
+
+        archive.finish().await
+    }
+}
+
+// Archives are written from a separate thread as the zip writer is blocking
+struct ArchiveWriter {
+    tx: SyncSender<(String, Vec<u8>)>,
+    handle: std::thread::JoinHandle<zip::result::ZipResult<()>>,
+}
+
+impl ArchiveWriter {
+    fn create(path: PathBuf) -> trc::Result<Self> {
+        let file = std::fs::File::create(path).map_err(fs_error)?;
+        let (tx, rx) = mpsc::sync_channel::<(String, Vec<u8>)>(10);
+        let handle = std::thread::spawn(move || {
+            let mut zip = ZipWriter::new(BufWriter::new(file));
+            while let Ok((name, contents)) = rx.recv() {
+                zip.start_file(
+                    name,
+                    SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+                )?;
+                zip.write_all(&contents)?;
+            }
+            zip.finish()?.flush()?;
+            Ok(())
+        });
+
+        Ok(Self { tx, handle })
+    }
+
+    fn add(&self, name: impl Into<String>, contents: Vec<u8>) -> trc::Result<()> {
+        self.tx.send((name.into(), contents)).map_err(|_| {
+            trc::StoreEvent::FilesystemError
+                .into_err()
+                .details("Archive writer stopped unexpectedly")
+                .caused_by(trc::location!())
+        })
+    }
+
+    async fn finish(self) -> trc::Result<()> {
+        drop(self.tx);
+        let handle = self.handle;
+        match tokio::task::spawn_blocking(move || handle.join()).await {
+            Ok(Ok(result)) => result.map_err(zip_error),
+            _ => Err(trc::EventType::Server(trc::ServerEvent::ThreadError)
+                .into_err()
+                .details("Archive writer panicked")
+                .caused_by(trc::location!())),
+        }
+    }
+}
+
+fn write_bundle(dir: &Path, accounts: &[u32]) -> trc::Result<()> {
+    let tmp_path = dir.join("bundle.zip.tmp");
+    let mut zip = ZipWriter::new(BufWriter::new(
+        std::fs::File::create(&tmp_path).map_err(fs_error)?,
+    ));
+
+    // Account archives are already compressed
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for file_name in ["directory.json", "statistics.json"] {
+        zip.start_file(file_name, options).map_err(zip_error)?;
+        std::io::copy(
+            &mut std::fs::File::open(dir.join(file_name)).map_err(fs_error)?,
+            &mut zip,
+        )
+        .map_err(fs_error)?;
+    }
+    for account_id in accounts {
+        zip.start_file(
+            format!("accounts/{account_id}.zip"),
+            options.large_file(true),
+        )
+        .map_err(zip_error)?;
+        std::io::copy(
+            &mut std::fs::File::open(dir.join("accounts").join(format!("{account_id}.zip")))
+                .map_err(fs_error)?,
+            &mut zip,
+        )
+        .map_err(fs_error)?;
+    }
+    zip.finish().map_err(zip_error)?.flush().map_err(fs_error)?;
+
+    std::fs::rename(&tmp_path, dir.join("bundle.zip")).map_err(fs_error)?;
+    std::fs::remove_dir_all(dir.join("accounts")).map_err(fs_error)
+}
+
+fn write_json(path: &Path, value: &serde_json::Value) -> trc::Result<()> {
+    std::fs::write(path, serde_json::to_vec_pretty(value).unwrap_or_default()).map_err(fs_error)
+}
+
+fn export_signature(key: &str, tenant_id: u32, job_id: &str, expires: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{tenant_id}:{job_id}:{expires}").as_bytes());
+    mac
+}
+
+fn export_key(tenant_id: u32) -> Vec<u8> {
+    format!("tenant-export:{tenant_id}").into_bytes()
+}
+
+fn fs_error(err: std::io::Error) -> trc::Error {
+    trc::StoreEvent::FilesystemError
+        .reason(err)
+        .caused_by(trc::location!())
+}
+
+fn zip_error(err: zip::result::ZipError) -> trc::Error {
+    trc::StoreEvent::FilesystemError
+        .reason(err)
+        .details("Failed to write archive")
+        .caused_by(trc::location!())
+}
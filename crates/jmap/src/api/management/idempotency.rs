@@ -0,0 +1,251 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc};
+
+use common::{auth::AccessToken, Server};
+use directory::backend::internal::manage::{self, ErrorCode};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use store::{
+    write::{
+        expire::{lookup_key_expiry, ExpiringRange},
+        Bincode,
+    },
+    Serialize as _, SUBSPACE_LOOKUP_VALUE,
+};
+use tokio::sync::OwnedMutexGuard;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, HttpResponseBody};
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+// Time to keep the result of a request
+const RESULT_EXPIRY: u64 = 86400;
+// Time after which an unfinished request no longer blocks its key
+const PENDING_EXPIRY: u64 = 300;
+const MAX_KEY_LEN: usize = 255;
+
+pub fn expiring_range() -> ExpiringRange {
+    ExpiringRange::with_prefix(
+        "idempotency",
+        SUBSPACE_LOOKUP_VALUE,
+        "idempotency:",
+        lookup_key_expiry,
+    )
+}
+
+pub enum Idempotency {
+    Execute(IdempotencyGuard),
+    Replay(HttpResponse),
+}
+
+pub struct IdempotencyGuard {
+    key: Vec<u8>,
+    request_hash: Vec<u8>,
+    lock: OwnedMutexGuard<()>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredResponse {
+    request_hash: Vec<u8>,
+    // None while the first request is still executing
+    status: Option<u16>,
+    content_type: String,
+    body: String,
+}
+
+pub trait IdempotentRequest: Sync + Send {
+    fn idempotency_begin(
+        &self,
+        req: &HttpRequest,
+        key: &str,
+        access_token: &AccessToken,
+        body: Option<&[u8]>,
+    ) -> impl Future<Output = trc::Result<Idempotency>> + Send;
+
+    fn idempotency_end(
+        &self,
+        guard: IdempotencyGuard,
+        result: &trc::Result<HttpResponse>,
+    ) -> impl Future<Output = ()> + Send;
+}
+
+impl IdempotentRequest for Server {
+    async fn idempotency_begin(
+        &self,
+        req: &HttpRequest,
+        key: &str,
+        access_token: &AccessToken,
+        body: Option<&[u8]>,
+    ) -> trc::Result<Idempotency> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Invalid Idempotency-Key header"));
+        }
+
+        // Keys are scoped to the acting principal and the endpoint
+        let key = format!(
+            "idempotency:{}:{} {}:{key}",
+            access_token.caller_id(),
+            req.method(),
+            req.uri().path()
+        )
+        .into_bytes();
+        let mut hasher = Sha256::new();
+        hasher.update(req.uri().query().unwrap_or_default().as_bytes());
+        hasher.update([0]);
+        hasher.update(body.unwrap_or_default());
+        let request_hash = hasher.finalize().to_vec();
+
+        // Concurrent requests using the same key are executed one at a time
+        let lock = self
+            .inner
+            .data
+            .idempotency_locks
+            .lock()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let lock = lock.lock_owned().await;
+
+        let result = match self
+            .core
+            .storage
+            .lookup
+            .key_get::<Bincode<StoredResponse>>(key.clone())
+            .await
+        {
+            Ok(Some(stored)) => {
+                let stored = stored.inner;
+                if stored.request_hash != request_hash {
+                    Err(manage::err_code(
+                        ErrorCode::IdempotencyConflict,
+                        "Idempotency key reused",
+                        "The idempotency key was used for a different request".into(),
+                    ))
+                } else if let Some(status) = stored.status {
+                    Ok(Some(HttpResponse {
+                        status: StatusCode::from_u16(status).unwrap_or(StatusCode::OK),
+                        content_type: stored.content_type.into(),
+                        content_disposition: "".into(),
+                        cache_control: "".into(),
+                        body: HttpResponseBody::Text(stored.body),
+                    }))
+                } else {
+                    Err(manage::err_code(
+                        ErrorCode::IdempotencyConflict,
+                        "Request in progress",
+                        "A request using this idempotency key is still being processed".into(),
+                    ))
+                }
+            }
+            Ok(None) => {
+                // Mark the key as in use so other servers do not execute the request
+                self.core
+                    .storage
+                    .lookup
+                    .key_set(
+                        key.clone(),
+                        Bincode::new(StoredResponse {
+                            request_hash: request_hash.clone(),
+                            status: None,
+                            content_type: String::new(),
+                            body: String::new(),
+                        })
+                        .serialize(),
+                        PENDING_EXPIRY.into(),
+                    )
+                    .await
+                    .map(|_| None)
+            }
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(None) => Ok(Idempotency::Execute(IdempotencyGuard {
+                key,
+                request_hash,
+                lock,
+            })),
+            Ok(Some(response)) => {
+                drop(lock);
+                self.release_idempotency_lock(&key);
+                Ok(Idempotency::Replay(response))
+            }
+            Err(err) => {
+                drop(lock);
+                self.release_idempotency_lock(&key);
+                Err(err)
+            }
+        }
+    }
+
+    async fn idempotency_end(&self, guard: IdempotencyGuard, result: &trc::Result<HttpResponse>) {
+        // Management errors are final, other errors can be retried
+        let error_response;
+        let response = match result {
+            Ok(response) => Some(response),
+            Err(err) if matches!(err.as_ref(), trc::EventType::Manage(_)) => {
+                error_response = err.into_http_response();
+                Some(&error_response)
+            }
+            Err(_) => None,
+        };
+
+        let result = match response.and_then(|response| match &response.body {
+            HttpResponseBody::Text(body) => Some((response, body.as_str())),
+            HttpResponseBody::Empty => Some((response, "")),
+            _ => None,
+        }) {
+            Some((response, body)) => {
+                self.core
+                    .storage
+                    .lookup
+                    .key_set(
+                        guard.key.clone(),
+                        Bincode::new(StoredResponse {
+                            request_hash: guard.request_hash,
+                            status: Some(response.status.as_u16()),
+                            content_type: response.content_type.to_string(),
+                            body: body.to_string(),
+                        })
+                        .serialize(),
+                        RESULT_EXPIRY.into(),
+                    )
+                    .await
+            }
+            None => self.core.storage.lookup.key_delete(guard.key.clone()).await,
+        };
+
+        if let Err(err) = result {
+            trc::error!(err
+                .details("Failed to store idempotent request result")
+                .caused_by(trc::location!()));
+        }
+
+        drop(guard.lock);
+        self.release_idempotency_lock(&guard.key);
+    }
+}
+
+trait IdempotencyLocks {
+    fn release_idempotency_lock(&self, key: &[u8]);
+}
+
+impl IdempotencyLocks for Server {
+    fn release_idempotency_lock(&self, key: &[u8]) {
+        let mut locks = self.inner.data.idempotency_locks.lock();
+        if locks
+            .get(key)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(key);
+        }
+    }
+}
@@ -0,0 +1,584 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::VecDeque, future::Future};
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::manage::{
+        ErrorCode, ImportAccount, ImportStatus, ImportedAccount, ManageDirectory,
+    },
+    Permission,
+};
+use http_body_util::BodyExt;
+use hyper::header::CONTENT_TYPE;
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::principal::PrincipalManager;
+
+// Number of accounts created at once
+const IMPORT_BATCH_SIZE: usize = 100;
+
+// Rows are buffered until complete, larger rows are rejected
+const MAX_ROW_SIZE: usize = 64 * 1024;
+
+pub trait ManageImport: Sync + Send {
+    fn handle_principal_import(
+        &self,
+        req: &mut HttpRequest,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageImport for Server {
+    async fn handle_principal_import(
+        &self,
+        req: &mut HttpRequest,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::PrincipalImport)?;
+        access_token.assert_has_permission(Permission::IndividualCreate)?;
+        let create_domains = UrlParams::new(req.uri().query())
+            .parse::<bool>("create-domains")
+            .unwrap_or_default();
+        if create_domains {
+            access_token.assert_has_permission(Permission::DomainCreate)?;
+        }
+        self.assert_writable_directory()?;
+        self.assert_supported_directory()?;
+
+        // Rows are read as the body arrives and created in batches
+        let mut reader = if req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"))
+        {
+            AccountReader::Json(JsonReader::default())
+        } else {
+            AccountReader::Csv(CsvReader::default())
+        };
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        let mut results = Vec::new();
+        let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+        let mut row = 0;
+        let mut is_eof = false;
+        while !is_eof {
+            match req.frame().await {
+                Some(Ok(frame)) => {
+                    if let Some(data) = frame.data_ref() {
+                        reader.feed(data)?;
+                    }
+                }
+                Some(Err(err)) => {
+                    return Err(trc::ResourceEvent::BadParameters
+                        .into_err()
+                        .details("Failed to read request body")
+                        .reason(err));
+                }
+                None => {
+                    reader.finish()?;
+                    is_eof = true;
+                }
+            }
+
+            while let Some(account) = reader.next_account() {
+                row += 1;
+                match account {
+                    Ok(account) => {
+                        batch.push((row, account));
+                    }
+                    Err(err) => {
+                        results.push(ImportedAccount {
+                            row,
+                            email: err.email,
+                            status: ImportStatus::Error,
+                            id: None,
+                            code: Some(ErrorCode::FieldInvalid.as_str().to_string()),
+                            reason: Some(err.reason),
+                        });
+                    }
+                }
+
+                if batch.len() == IMPORT_BATCH_SIZE {
+                    results.extend(
+                        self.core
+                            .storage
+                            .data
+                            .import_accounts(
+                                std::mem::take(&mut batch),
+                                tenant_id,
+                                Some(&access_token.permissions),
+                                create_domains,
+                            )
+                            .await?,
+                    );
+                }
+            }
+        }
+        if !batch.is_empty() {
+            results.extend(
+                self.core
+                    .storage
+                    .data
+                    .import_accounts(
+                        batch,
+                        tenant_id,
+                        Some(&access_token.permissions),
+                        create_domains,
+                    )
+                    .await?,
+            );
+        }
+        results.sort_unstable_by_key(|result| result.row);
+
+        Ok(JsonResponse::new(json!({
+            "data": {
+                "created": results.iter().filter(|r| r.status == ImportStatus::Created).count(),
+                "skipped": results.iter().filter(|r| r.status == ImportStatus::Skipped).count(),
+                "failed": results.iter().filter(|r| r.status == ImportStatus::Error).count(),
+                "rows": results,
+            },
+        }))
+        .into_http_response())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct RowError {
+    email: String,
+    reason: String,
+}
+
+enum AccountReader {
+    Csv(CsvReader),
+    Json(JsonReader),
+}
+
+impl AccountReader {
+    fn feed(&mut self, data: &[u8]) -> trc::Result<()> {
+        match self {
+            AccountReader::Csv(reader) => data.iter().try_for_each(|ch| reader.feed(*ch)),
+            AccountReader::Json(reader) => data.iter().try_for_each(|ch| reader.feed(*ch)),
+        }
+    }
+
+    fn finish(&mut self) -> trc::Result<()> {
+        match self {
+            AccountReader::Csv(reader) => reader.finish(),
+            AccountReader::Json(reader) => reader.finish(),
+        }
+    }
+
+    fn next_account(&mut self) -> Option<Result<ImportAccount, RowError>> {
+        match self {
+            AccountReader::Csv(reader) => reader.next_account(),
+            AccountReader::Json(reader) => reader.elements.pop_front().map(|element| {
+                serde_json::from_slice::<ImportAccount>(&element).map_err(|err| RowError {
+                    email: serde_json::from_slice::<serde_json::Value>(&element)
+                        .ok()
+                        .and_then(|value| value.get("email")?.as_str().map(String::from))
+                        .unwrap_or_default(),
+                    reason: err.to_string(),
+                })
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Email,
+    PasswordHash,
+    Quota,
+    DisplayName,
+    Ignored,
+}
+
+/// Reads rows of `email,password_hash,quota,display_name`. A first row
+/// containing an `email` column is a header that sets the column order.
+#[derive(Default)]
+struct CsvReader {
+    field: Vec<u8>,
+    record: Vec<String>,
+    record_size: usize,
+    in_quotes: bool,
+    quote_pending: bool,
+    columns: Option<Vec<Column>>,
+    records: VecDeque<Vec<String>>,
+}
+
+impl CsvReader {
+    fn feed(&mut self, ch: u8) -> trc::Result<()> {
+        if self.in_quotes {
+            if self.quote_pending {
+                self.quote_pending = false;
+                if ch == b'"' {
+                    return self.push(ch);
+                }
+                self.in_quotes = false;
+            } else if ch == b'"' {
+                self.quote_pending = true;
+                return Ok(());
+            } else {
+                return self.push(ch);
+            }
+        }
+
+        match ch {
+            b'"' if self.field.is_empty() => {
+                self.in_quotes = true;
+            }
+            b',' => {
+                self.end_field();
+            }
+            b'\n' => {
+                self.end_field();
+                self.end_record();
+            }
+            b'\r' => (),
+            _ => {
+                self.push(ch)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> trc::Result<()> {
+        if self.in_quotes && !self.quote_pending {
+            return Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Unterminated quoted field"));
+        }
+        self.in_quotes = false;
+        self.quote_pending = false;
+        if !self.field.is_empty() || !self.record.is_empty() {
+            self.end_field();
+            self.end_record();
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, ch: u8) -> trc::Result<()> {
+        if self.record_size + self.field.len() < MAX_ROW_SIZE {
+            self.field.push(ch);
+            Ok(())
+        } else {
+            Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Row exceeds maximum size")
+                .ctx(trc::Key::Limit, MAX_ROW_SIZE))
+        }
+    }
+
+    fn end_field(&mut self) {
+        let field = String::from_utf8_lossy(&self.field).trim().to_string();
+        self.record_size += self.field.len();
+        self.record.push(field);
+        self.field.clear();
+    }
+
+    fn end_record(&mut self) {
+        let record = std::mem::take(&mut self.record);
+        self.record_size = 0;
+        if record.iter().any(|field| !field.is_empty()) {
+            self.records.push_back(record);
+        }
+    }
+
+    fn next_account(&mut self) -> Option<Result<ImportAccount, RowError>> {
+        let mut record = self.records.pop_front()?;
+        if self.columns.is_none() {
+            let header = record
+                .iter()
+                .map(
+                    |field| match field.to_lowercase().replace(['-', ' '], "_").as_str() {
+                        "email" | "email_address" | "address" => Column::Email,
+                        "password_hash" | "password" | "secret" => Column::PasswordHash,
+                        "quota" => Column::Quota,
+                        "display_name" | "name" | "description" => Column::DisplayName,
+                        _ => Column::Ignored,
+                    },
+                )
+                .collect::<Vec<_>>();
+            if header.contains(&Column::Email) {
+                self.columns = Some(header);
+                record = self.records.pop_front()?;
+            } else {
+                self.columns = Some(vec![
+                    Column::Email,
+                    Column::PasswordHash,
+                    Column::Quota,
+                    Column::DisplayName,
+                ]);
+            }
+        }
+
+        let mut account = ImportAccount::default();
+        let mut quota = None;
+        for (column, value) in self.columns.as_ref()?.iter().zip(record) {
+            if value.is_empty() {
+                continue;
+            }
+            match column {
+                Column::Email => account.email = value,
+                Column::PasswordHash => account.password_hash = Some(value),
+                Column::Quota => quota = Some(value),
+                Column::DisplayName => account.display_name = Some(value),
+                Column::Ignored => (),
+            }
+        }
+
+        if account.email.is_empty() {
+            return Some(Err(RowError {
+                email: account.email,
+                reason: "Missing e-mail address".to_string(),
+            }));
+        }
+        if let Some(quota) = quota {
+            match quota.parse::<u64>() {
+                Ok(quota) => {
+                    account.quota = Some(quota);
+                }
+                Err(_) if quota.eq_ignore_ascii_case("unlimited") => (),
+                Err(_) => {
+                    return Some(Err(RowError {
+                        email: account.email,
+                        reason: format!("Invalid quota {quota:?}"),
+                    }));
+                }
+            }
+        }
+
+        Some(Ok(account))
+    }
+}
+
+/// Splits a JSON array into its top level elements without parsing the
+/// whole document.
+#[derive(Default)]
+struct JsonReader {
+    element: Vec<u8>,
+    depth: u32,
+    in_string: bool,
+    escape: bool,
+    started: bool,
+    finished: bool,
+    has_elements: bool,
+    elements: VecDeque<Vec<u8>>,
+}
+
+impl JsonReader {
+    fn feed(&mut self, ch: u8) -> trc::Result<()> {
+        if !self.started || self.finished {
+            return match ch {
+                b'[' if !self.started => {
+                    self.started = true;
+                    Ok(())
+                }
+                _ if ch.is_ascii_whitespace() => Ok(()),
+                _ => Err(trc::ResourceEvent::BadParameters
+                    .into_err()
+                    .details("Expected a JSON array of accounts")),
+            };
+        }
+
+        if self.in_string {
+            if self.escape {
+                self.escape = false;
+            } else if ch == b'\\' {
+                self.escape = true;
+            } else if ch == b'"' {
+                self.in_string = false;
+            }
+        } else {
+            match ch {
+                b'"' => {
+                    self.in_string = true;
+                }
+                b'{' | b'[' => {
+                    self.depth += 1;
+                }
+                b'}' | b']' if self.depth > 0 => {
+                    self.depth -= 1;
+                }
+                b']' => {
+                    self.finished = true;
+                    return self.end_element(false);
+                }
+                b',' if self.depth == 0 => {
+                    return self.end_element(true);
+                }
+                _ if ch.is_ascii_whitespace() && self.element.is_empty() => {
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+
+        if self.element.len() < MAX_ROW_SIZE {
+            self.element.push(ch);
+            Ok(())
+        } else {
+            Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Row exceeds maximum size")
+                .ctx(trc::Key::Limit, MAX_ROW_SIZE))
+        }
+    }
+
+    fn finish(&mut self) -> trc::Result<()> {
+        if self.finished {
+            Ok(())
+        } else {
+            Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Unterminated JSON array"))
+        }
+    }
+
+    fn end_element(&mut self, is_required: bool) -> trc::Result<()> {
+        if !self.element.is_empty() {
+            self.elements.push_back(std::mem::take(&mut self.element));
+            self.has_elements = true;
+            Ok(())
+        } else if !is_required && !self.has_elements {
+            Ok(())
+        } else if !is_required {
+            // Trailing commas are not valid JSON
+            Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Unexpected ',' in JSON array"))
+        } else {
+            Err(trc::ResourceEvent::BadParameters
+                .into_err()
+                .details("Empty element in JSON array"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use directory::backend::internal::manage::ImportAccount;
+
+    use super::{AccountReader, CsvReader, JsonReader, RowError};
+
+    fn read(mut reader: AccountReader, input: &str) -> Vec<Result<ImportAccount, RowError>> {
+        // Feed the input in small chunks to split rows across frames
+        for chunk in input.as_bytes().chunks(3) {
+            reader.feed(chunk).unwrap();
+        }
+        reader.finish().unwrap();
+        std::iter::from_fn(|| reader.next_account()).collect()
+    }
+
+    fn account(email: &str, hash: &str, quota: Option<u64>, name: &str) -> ImportAccount {
+        ImportAccount {
+            email: email.to_string(),
+            password_hash: Some(hash.to_string()).filter(|v| !v.is_empty()),
+            quota,
+            display_name: Some(name.to_string()).filter(|v| !v.is_empty()),
+        }
+    }
+
+    #[test]
+    fn read_csv_accounts() {
+        assert_eq!(
+            read(
+                AccountReader::Csv(CsvReader::default()),
+                concat!(
+                    "john@example.org,$6$salt$hash,1024,John Doe\r\n",
+                    "\n",
+                    "\"jane@example.org\",\"$6$a,b\",,\"Jane \"\"JD\"\" Doe\"\n",
+                    "bill@example.org,,unlimited,\n",
+                    "mike@example.org,,lots,Mike\n",
+                    ",hash,,Nobody"
+                )
+            ),
+            vec![
+                Ok(account(
+                    "john@example.org",
+                    "$6$salt$hash",
+                    Some(1024),
+                    "John Doe"
+                )),
+                Ok(account(
+                    "jane@example.org",
+                    "$6$a,b",
+                    None,
+                    "Jane \"JD\" Doe"
+                )),
+                Ok(account("bill@example.org", "", None, "")),
+                Err(RowError {
+                    email: "mike@example.org".to_string(),
+                    reason: "Invalid quota \"lots\"".to_string()
+                }),
+                Err(RowError {
+                    email: String::new(),
+                    reason: "Missing e-mail address".to_string()
+                }),
+            ]
+        );
+
+        // Headers set the column order
+        assert_eq!(
+            read(
+                AccountReader::Csv(CsvReader::default()),
+                "Display Name,Quota,Email,Ignored\nJohn,0,john@example.org,x\n"
+            ),
+            vec![Ok(account("john@example.org", "", Some(0), "John"))]
+        );
+    }
+
+    #[test]
+    fn read_json_accounts() {
+        assert_eq!(
+            read(
+                AccountReader::Json(JsonReader::default()),
+                concat!(
+                    "[{\"email\": \"john@example.org\", \"passwordHash\": \"$6$x\", ",
+                    "\"quota\": 1024, \"displayName\": \"John [\\\"JD\\\"], Doe\"},\n",
+                    " {\"email\": \"jane@example.org\", \"display_name\": \"Jane\"},",
+                    " {\"email\": \"bill@example.org\", \"quota\": \"lots\"} ]"
+                )
+            )
+            .into_iter()
+            .map(|result| result.map_err(|err| err.email))
+            .collect::<Vec<_>>(),
+            vec![
+                Ok(account(
+                    "john@example.org",
+                    "$6$x",
+                    Some(1024),
+                    "John [\"JD\"], Doe"
+                )),
+                Ok(account("jane@example.org", "", None, "Jane")),
+                Err("bill@example.org".to_string()),
+            ]
+        );
+        assert_eq!(
+            read(AccountReader::Json(JsonReader::default()), " [ ] "),
+            vec![]
+        );
+
+        for invalid in ["{}", "[{},]", "[{}", "[,{}]"] {
+            let mut reader = AccountReader::Json(JsonReader::default());
+            assert!(
+                reader
+                    .feed(invalid.as_bytes())
+                    .and_then(|_| reader.finish())
+                    .is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
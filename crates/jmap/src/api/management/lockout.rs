@@ -0,0 +1,82 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::manage::{err_not_found, ErrorCode, ManageDirectory},
+    Permission,
+};
+use hyper::Method;
+use serde_json::json;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageLockout: Sync + Send {
+    fn handle_manage_lockout(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageLockout for Server {
+    async fn handle_manage_lockout(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let name = path
+            .get(1)
+            .map(|name| decode_path_element(name))
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let principal = self
+            .core
+            .storage
+            .data
+            .get_principal_info(name.as_ref())
+            .await?
+            .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+
+        match *req.method() {
+            Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::LockoutGet)?;
+
+                let status = self.account_lockout_status(principal.id).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "locked": status.locked_until.is_some(),
+                        "lockedUntil": status.locked_until,
+                        "failures": status.failures,
+                        "threshold": self.core.account_lockout.threshold,
+                    },
+                }))
+                .into_http_response())
+            }
+            Method::DELETE => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::LockoutClear)?;
+
+                let was_locked = self.account_locked_until(principal.id).await?.is_some();
+                self.clear_account_lockout(principal.id).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": was_locked,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
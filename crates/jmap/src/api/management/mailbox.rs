@@ -0,0 +1,380 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::manage::{self, err_not_found, ErrorCode, ManageDirectory},
+    Permission,
+};
+use hyper::Method;
+use jmap_proto::{
+    object::Object,
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use serde::Deserialize;
+use serde_json::json;
+use store::{
+    ahash::AHashMap,
+    write::{assert::HashedValue, log::ChangeLogBuilder},
+};
+use trc::AddContext;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    changes::write::ChangeLog,
+    mailbox::set::MailboxSet,
+    services::state::StateManager,
+    JmapMethods,
+};
+
+use super::decode_path_element;
+
+#[derive(Debug, Deserialize)]
+struct MailboxRename {
+    path: String,
+}
+
+pub(super) struct MailboxNode {
+    name: String,
+    parent_id: Option<u32>,
+    pub(super) role: Option<String>,
+    pub(super) subscribed: bool,
+    uid_validity: u64,
+}
+
+pub trait ManageMailboxes: Sync + Send {
+    fn handle_manage_mailboxes(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageMailboxes for Server {
+    async fn handle_manage_mailboxes(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let name = path
+            .get(1)
+            .map(|name| decode_path_element(name))
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let account_id = self
+            .core
+            .storage
+            .data
+            .get_principal_info(name.as_ref())
+            .await?
+            .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?
+            .id;
+        let mailbox_id = path.get(2).map(|id| {
+            id.parse::<u32>()
+                .map_err(|_| manage::not_found(id.to_string()))
+        });
+
+        match (mailbox_id, req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailboxTreeGet)?;
+
+                let mailboxes = self.mailbox_tree(account_id).await?;
+                let mut items = Vec::with_capacity(mailboxes.len());
+                for (mailbox_id, mailbox) in &mailboxes {
+                    items.push(json!({
+                        "id": mailbox_id,
+                        "name": mailbox.name,
+                        "path": mailbox_path(&mailboxes, *mailbox_id),
+                        "parentId": mailbox.parent_id,
+                        "role": mailbox.role,
+                        "isSubscribed": mailbox.subscribed,
+                        "uidValidity": mailbox.uid_validity,
+                        "totalEmails": self
+                            .get_tag(
+                                account_id,
+                                Collection::Email,
+                                Property::MailboxIds,
+                                *mailbox_id,
+                            )
+                            .await?
+                            .map_or(0, |emails| emails.len()),
+                    }));
+                }
+                items.sort_unstable_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), &Method::PATCH) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailboxTreeUpdate)?;
+
+                let mailbox_id = mailbox_id?;
+                let request =
+                    serde_json::from_slice::<MailboxRename>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+                let new_path = self.validate_mailbox_path(&request.path)?;
+
+                // Validate source mailbox
+                let mailboxes = self.mailbox_tree(account_id).await?;
+                if !mailboxes.contains_key(&mailbox_id) {
+                    return Err(manage::not_found(mailbox_id.to_string()));
+                }
+                let old_path = mailbox_path(&mailboxes, mailbox_id);
+                let full_path = new_path.join("/");
+                if full_path == old_path {
+                    return Ok(JsonResponse::new(json!({
+                        "data": (),
+                    }))
+                    .into_http_response());
+                } else if full_path
+                    .strip_prefix(&old_path)
+                    .is_some_and(|suffix| suffix.starts_with('/'))
+                {
+                    return Err(manage::error(
+                        format!(
+                            "Mailbox '{old_path}' cannot be moved into one of its own subfolders."
+                        ),
+                        Some(full_path),
+                    ));
+                }
+
+                // Locate the deepest existing parent, the remaining path items are created
+                let paths = mailboxes
+                    .keys()
+                    .map(|id| (mailbox_path(&mailboxes, *id), *id))
+                    .collect::<AHashMap<_, _>>();
+                if paths.contains_key(&full_path) {
+                    return Err(manage::error(
+                        format!("Mailbox '{full_path}' already exists."),
+                        None::<u32>,
+                    ));
+                }
+                let mut parent_id = None;
+                let mut create_path = new_path;
+                let new_name = create_path.pop().unwrap();
+                let mut num_existing = create_path.len();
+                while num_existing > 0 {
+                    if let Some(id) = paths.get(&create_path[..num_existing].join("/")) {
+                        parent_id = Some(*id);
+                        break;
+                    }
+                    num_existing -= 1;
+                }
+                create_path.drain(..num_existing);
+
+                // Rename using the same code path as IMAP RENAME
+                let mailbox = self
+                    .get_property::<HashedValue<Object<Value>>>(
+                        account_id,
+                        Collection::Mailbox,
+                        mailbox_id,
+                        Property::Value,
+                    )
+                    .await?
+                    .ok_or_else(|| manage::not_found(mailbox_id.to_string()))?;
+                let (change_id, _) = self
+                    .mailbox_rename(
+                        account_id,
+                        mailbox_id,
+                        mailbox,
+                        parent_id,
+                        &create_path,
+                        new_name,
+                    )
+                    .await?;
+
+                self.broadcast_state_change(
+                    StateChange::new(account_id).with_change(DataType::Mailbox, change_id),
+                )
+                .await;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(mailbox_id), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MailboxTreeDelete)?;
+
+                let mailbox_id = mailbox_id?;
+                let mailboxes = self.mailbox_tree(account_id).await?;
+                if !mailboxes.contains_key(&mailbox_id) {
+                    return Err(manage::not_found(mailbox_id.to_string()));
+                }
+
+                // Deleting a parent would orphan its subfolders
+                let mut children = mailboxes
+                    .iter()
+                    .filter(|(_, mailbox)| mailbox.parent_id == Some(mailbox_id))
+                    .map(|(id, _)| mailbox_path(&mailboxes, *id))
+                    .collect::<Vec<_>>();
+                if !children.is_empty() {
+                    children.sort_unstable();
+                    return Err(manage::error(
+                        format!(
+                            "Mailbox '{}' has subfolders, move or delete them first.",
+                            mailbox_path(&mailboxes, mailbox_id)
+                        ),
+                        Some(children.join(", ")),
+                    ));
+                }
+
+                // Delete using the same code path as IMAP DELETE
+                let mut changelog = ChangeLogBuilder::new();
+                let did_remove_emails = self
+                    .mailbox_destroy(account_id, mailbox_id, &mut changelog, access_token, true)
+                    .await?
+                    .map_err(|err| {
+                        manage::error(
+                            err.description.unwrap_or_else(|| "Delete failed".into()),
+                            None::<u32>,
+                        )
+                    })?;
+                let change_id = self.commit_changes(account_id, changelog).await?;
+
+                self.broadcast_state_change(if did_remove_emails {
+                    StateChange::new(account_id)
+                        .with_change(DataType::Mailbox, change_id)
+                        .with_change(DataType::Email, change_id)
+                        .with_change(DataType::Thread, change_id)
+                } else {
+                    StateChange::new(account_id).with_change(DataType::Mailbox, change_id)
+                })
+                .await;
+
+                Ok(JsonResponse::new(json!({
+                    "data": did_remove_emails,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+pub(super) trait MailboxTree: Sync + Send {
+    fn mailbox_tree(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<AHashMap<u32, MailboxNode>>> + Send;
+
+    fn validate_mailbox_path<'x>(&self, path: &'x str) -> trc::Result<Vec<&'x str>>;
+}
+
+impl MailboxTree for Server {
+    async fn mailbox_tree(&self, account_id: u32) -> trc::Result<AHashMap<u32, MailboxNode>> {
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+
+        Ok(self
+            .get_properties::<Object<Value>, _, _>(
+                account_id,
+                Collection::Mailbox,
+                &mailbox_ids,
+                Property::Value,
+            )
+            .await
+            .caused_by(trc::location!())?
+            .into_iter()
+            .map(|(mailbox_id, mut mailbox)| {
+                let subscribed = matches!(mailbox.get(&Property::IsSubscribed), Value::List(ids)
+                        if ids.contains(&Value::Id(account_id.into())));
+                (
+                    mailbox_id,
+                    MailboxNode {
+                        name: mailbox
+                            .properties
+                            .remove(&Property::Name)
+                            .and_then(|name| name.try_unwrap_string())
+                            .unwrap_or_default(),
+                        parent_id: mailbox
+                            .properties
+                            .get(&Property::ParentId)
+                            .and_then(|id| id.as_id())
+                            .map(|id| id.document_id())
+                            .filter(|id| *id > 0)
+                            .map(|id| id - 1),
+                        role: mailbox
+                            .properties
+                            .remove(&Property::Role)
+                            .and_then(|role| role.try_unwrap_string()),
+                        subscribed,
+                        uid_validity: mailbox
+                            .properties
+                            .get(&Property::Cid)
+                            .and_then(|cid| cid.as_uint())
+                            .unwrap_or_default(),
+                    },
+                )
+            })
+            .collect())
+    }
+
+    fn validate_mailbox_path<'x>(&self, path: &'x str) -> trc::Result<Vec<&'x str>> {
+        let mut items = Vec::new();
+        for item in path.trim().trim_matches('/').split('/') {
+            let item = item.trim();
+            if item.is_empty() {
+                return Err(manage::error(
+                    "Invalid empty path item.",
+                    Some(path.to_string()),
+                ));
+            } else if item.len() > self.core.jmap.mailbox_name_max_len {
+                return Err(manage::error(
+                    "Mailbox name is too long.",
+                    Some(item.to_string()),
+                ));
+            }
+            items.push(item);
+        }
+
+        if items.len() > self.core.jmap.mailbox_max_depth {
+            Err(manage::error(
+                "Mailbox path is too deep.",
+                Some(path.to_string()),
+            ))
+        } else {
+            Ok(items)
+        }
+    }
+}
+
+pub(super) fn mailbox_path(mailboxes: &AHashMap<u32, MailboxNode>, mailbox_id: u32) -> String {
+    let mut path = Vec::new();
+    let mut next_id = Some(mailbox_id);
+    while let Some(mailbox) = next_id.and_then(|id| mailboxes.get(&id)) {
+        path.push(mailbox.name.as_str());
+        next_id = mailbox.parent_id;
+
+        // Guard against corrupted parent links
+        if path.len() > mailboxes.len() {
+            break;
+        }
+    }
+    path.reverse();
+    path.join("/")
+}
@@ -0,0 +1,138 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::{
+        maintenance::{MaintenanceStore, ReadOnlyMode},
+        manage::{err_not_found, ErrorCode, ManageDirectory},
+    },
+    Permission, Type,
+};
+use hyper::Method;
+use serde::Deserialize;
+use serde_json::json;
+use std::future::Future;
+use store::write::now;
+use trc::ManageEvent;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+#[derive(Debug, Default, Deserialize)]
+struct EnableRequest {
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+pub trait ManageMaintenance: Sync + Send {
+    fn handle_manage_maintenance(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn assert_not_maintenance(
+        &self,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ManageMaintenance for Server {
+    async fn handle_manage_maintenance(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::MaintenanceMode)?;
+
+        // Tenant administrators can only toggle their own tenant
+        let tenant_id = match (path.get(1), access_token.tenant) {
+            (Some(name), tenant) => {
+                let name = decode_path_element(name);
+                let tenant_id = self
+                    .store()
+                    .get_principal_info(name.as_ref())
+                    .await?
+                    .filter(|p| {
+                        p.typ == Type::Tenant && tenant.is_none_or(|tenant| tenant.id == p.id)
+                    })
+                    .ok_or_else(|| err_not_found(ErrorCode::TenantNotFound, name.to_string()))?
+                    .id;
+                Some(tenant_id)
+            }
+            (None, Some(tenant)) => Some(tenant.id),
+            (None, None) => None,
+        };
+
+        match *req.method() {
+            Method::GET => {
+                let mode = self.store().get_read_only(tenant_id).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": mode,
+                }))
+                .into_http_response())
+            }
+            Method::PUT => {
+                let request = match body.as_deref().filter(|body| !body.is_empty()) {
+                    Some(body) => serde_json::from_slice::<EnableRequest>(body).map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                            .from_json_error(err)
+                    })?,
+                    None => EnableRequest::default(),
+                };
+                let mode = ReadOnlyMode {
+                    reason: request.reason.filter(|reason| !reason.is_empty()),
+                    enabled_by: access_token.name.clone(),
+                    enabled_at: now(),
+                };
+                self.store()
+                    .set_read_only(tenant_id, mode.clone().into())
+                    .await?;
+
+                trc::event!(
+                    Manage(ManageEvent::MaintenanceEnabled),
+                    Id = tenant_id,
+                    Reason = mode.reason.clone(),
+                    AccountName = access_token.name.clone(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": mode,
+                }))
+                .into_http_response())
+            }
+            Method::DELETE => {
+                self.store().set_read_only(tenant_id, None).await?;
+
+                trc::event!(
+                    Manage(ManageEvent::MaintenanceDisabled),
+                    Id = tenant_id,
+                    AccountName = access_token.name.clone(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn assert_not_maintenance(&self, access_token: &AccessToken) -> trc::Result<()> {
+        self.store()
+            .assert_not_read_only(access_token.tenant.map(|t| t.id))
+            .await
+    }
+}
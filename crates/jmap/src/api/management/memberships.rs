@@ -0,0 +1,49 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc};
+
+use common::{auth::AccessToken, Server};
+use directory::{backend::internal::manage::ManageDirectory, Permission};
+use serde_json::json;
+
+use crate::api::{http::ToHttpResponse, HttpResponse, JsonResponse};
+
+pub trait ManageMemberships: Sync + Send {
+    fn handle_memberships_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageMemberships for Server {
+    async fn handle_memberships_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        // Roles are only listed to principals allowed to view them
+        let memberships = self
+            .core
+            .storage
+            .data
+            .get_memberships(
+                access_token.primary_id(),
+                self.core.jmap.member_of_max_depth,
+                access_token.has_permission(Permission::RoleGet),
+            )
+            .await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": memberships.into_iter().map(|membership| json!({
+                "name": membership.name,
+                "type": membership.typ.to_jmap(),
+                "description": membership.description,
+                "direct": membership.depth == 1,
+            })).collect::<Vec<_>>(),
+        }))
+        .into_http_response())
+    }
+}
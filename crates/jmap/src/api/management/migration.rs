@@ -0,0 +1,129 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, config::migration::MigrationType, Server};
+use directory::{backend::internal::manage, Permission};
+use hyper::Method;
+use serde_json::json;
+use std::future::Future;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    services::migration::MigrationManager,
+};
+
+use super::decode_path_element;
+
+pub trait ManageMigrations: Sync + Send {
+    fn handle_manage_migration(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageMigrations for Server {
+    async fn handle_manage_migration(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TaskList)?;
+
+                let mut migrations = Vec::with_capacity(MigrationType::all().len());
+                for migration in MigrationType::all() {
+                    migrations.push(self.migration_status(*migration).await?);
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": migrations,
+                }))
+                .into_http_response())
+            }
+            (Some(migration_id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TaskList)?;
+
+                let migration_id = decode_path_element(migration_id);
+                let migration = MigrationType::parse(migration_id.as_ref())
+                    .ok_or_else(|| manage::not_found(migration_id.to_string()))?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.migration_status(migration).await?,
+                }))
+                .into_http_response())
+            }
+            (Some(migration_id), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TaskRun)?;
+
+                let migration_id = decode_path_element(migration_id);
+                let migration = MigrationType::parse(migration_id.as_ref())
+                    .ok_or_else(|| manage::not_found(migration_id.to_string()))?;
+
+                if self
+                    .core
+                    .storage
+                    .data
+                    .is_migration_running(migration.as_str())
+                    .await?
+                {
+                    return Err(manage::error("Migration is already running.", None::<u32>));
+                }
+
+                let server = self.clone();
+                tokio::spawn(async move {
+                    let _ = server.run_migration(migration).await;
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait MigrationStatus: Sync + Send {
+    fn migration_status(
+        &self,
+        migration: MigrationType,
+    ) -> impl Future<Output = trc::Result<serde_json::Value>> + Send;
+}
+
+impl MigrationStatus for Server {
+    async fn migration_status(&self, migration: MigrationType) -> trc::Result<serde_json::Value> {
+        let store = &self.core.storage.data;
+        let progress = store.migration_progress(migration.as_str()).await?;
+        let status = if progress
+            .as_ref()
+            .is_some_and(|progress| progress.finished_at.is_some())
+        {
+            "applied"
+        } else if store.is_migration_running(migration.as_str()).await? {
+            "running"
+        } else {
+            "pending"
+        };
+
+        Ok(json!({
+            "id": migration.as_str(),
+            "description": migration.description(),
+            "status": status,
+            "processed": progress.as_ref().map_or(0, |progress| progress.processed),
+            "migrated": progress.as_ref().map_or(0, |progress| progress.migrated),
+            "startedAt": progress.as_ref().map(|progress| progress.started_at),
+            "finishedAt": progress.as_ref().and_then(|progress| progress.finished_at),
+        }))
+    }
+}
@@ -4,48 +4,125 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod activity;
+pub mod api_key;
+pub mod approval;
+pub mod callout;
+pub mod delegation;
 pub mod dkim;
 pub mod dns;
+pub mod duplicates;
 #[cfg(feature = "enterprise")]
 pub mod enterprise;
+pub mod expand;
+pub mod export;
+pub mod idempotency;
+pub mod import;
+pub mod lockout;
 pub mod log;
+pub mod mailbox;
+pub mod maintenance;
+pub mod memberships;
+pub mod migration;
+pub mod moderation;
+pub mod notify;
 pub mod principal;
+pub mod privacy;
 pub mod queue;
+pub mod recall;
+pub mod recipients;
 pub mod reload;
+pub mod replication;
 pub mod report;
+pub mod reputation;
+pub mod rewrite;
+pub mod roles;
+pub mod sessions;
 pub mod settings;
 pub mod sieve;
 pub mod stores;
+pub mod task;
+pub mod template;
+pub mod thread_rules;
+pub mod tls;
+pub mod undo;
+pub mod volume;
 
 use std::{borrow::Cow, str::FromStr, sync::Arc};
 
+use activity::ManageActivity;
+use api_key::ManageApiKeys;
+use approval::ManageApproval;
+use callout::ManageCallout;
 use common::{auth::AccessToken, Server};
-use directory::{backend::internal::manage, Permission};
+use delegation::ManageDelegation;
+use directory::{
+    backend::internal::manage::{self, ErrorCode},
+    Permission,
+};
 use dkim::DkimManagement;
 use dns::DnsManagement;
+use duplicates::ManageDuplicates;
 #[cfg(feature = "enterprise")]
 use enterprise::telemetry::TelemetryApi;
+use expand::ManageRecipientExpand;
+use export::ManageTenantExport;
 use hyper::Method;
+use idempotency::{Idempotency, IdempotentRequest, IDEMPOTENCY_KEY};
+use import::ManageImport;
+use lockout::ManageLockout;
 use log::LogManagement;
 use mail_parser::DateTime;
+use mailbox::ManageMailboxes;
+use maintenance::ManageMaintenance;
+use memberships::ManageMemberships;
+use migration::ManageMigrations;
+use moderation::ManageModeration;
 use principal::PrincipalManager;
+use privacy::ManagePrivacy;
 use queue::QueueManagement;
+use recall::ManageRecall;
+use recipients::ManageCollectedRecipients;
 use reload::ManageReload;
+use replication::ManageReplication;
 use report::ManageReports;
+use reputation::ManageReputation;
+use rewrite::ManageRewrite;
+use roles::ManageRoles;
 use serde::Serialize;
+use serde_json::json;
+use sessions::ManageSessions;
 use settings::ManageSettings;
 use sieve::SieveHandler;
 use store::write::now;
 use stores::ManageStore;
+use task::ManageTasks;
+use template::ManageTemplates;
+use thread_rules::ManageThreadRules;
+use tls::ManageTls;
+use undo::ManageUndo;
+use volume::ManageVolume;
 
 use crate::{auth::oauth::auth::OAuthApiHandler, email::crypto::CryptoHandler};
 
 use super::{
-    http::{fetch_body, HttpSessionData},
-    HttpRequest, HttpResponse,
+    http::{fetch_body, HttpSessionData, ToHttpResponse},
+    HttpRequest, HttpResponse, JsonResponse,
 };
 use std::future::Future;
 
+/// Routes that modify the directory and are disabled in maintenance mode.
+const MAINTENANCE_ROUTES: &[&str] = &[
+    "principal",
+    "delegation",
+    "roles",
+    "template",
+    "undo",
+    "approval",
+    "apikey",
+    "account",
+];
+
 #[derive(Serialize)]
 #[serde(tag = "error")]
 #[serde(rename_all = "camelCase")]
@@ -67,9 +144,20 @@ pub enum ManagementApiError<'x> {
     Other {
         details: &'x str,
         reason: Option<&'x str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        field: Option<&'x str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<&'x str>,
     },
 }
 
+#[derive(Serialize)]
+pub struct ManagementApiErrorResponse<'x> {
+    pub code: &'x str,
+    #[serde(flatten)]
+    pub error: ManagementApiError<'x>,
+}
+
 pub trait ManagementApi: Sync + Send {
     fn handle_api_manage_request(
         &self,
@@ -77,19 +165,72 @@ pub trait ManagementApi: Sync + Send {
         access_token: Arc<AccessToken>,
         session: &HttpSessionData,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_manage_route(
+        &self,
+        req: &mut HttpRequest,
+        body: Option<Vec<u8>>,
+        access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 }
 
 impl ManagementApi for Server {
-    #[allow(unused_variables)]
     async fn handle_api_manage_request(
         &self,
         req: &mut HttpRequest,
         access_token: Arc<AccessToken>,
         session: &HttpSessionData,
     ) -> trc::Result<HttpResponse> {
+        // Account imports are read as they arrive instead of being buffered
+        if req.method() == Method::POST && req.uri().path() == "/api/principal/import" {
+            self.assert_not_maintenance(&access_token).await?;
+            return self.handle_principal_import(req, &access_token).await;
+        }
+
         let body = fetch_body(req, 1024 * 1024, session.session_id).await;
+
+        // Retried requests carrying an idempotency key replay the first result
+        if req.method() != Method::GET {
+            if let Some(key) = req.headers().get(IDEMPOTENCY_KEY) {
+                let key = key.to_str().unwrap_or_default().to_string();
+                let guard = match self
+                    .idempotency_begin(req, &key, &access_token, body.as_deref())
+                    .await?
+                {
+                    Idempotency::Replay(response) => return Ok(response),
+                    Idempotency::Execute(guard) => guard,
+                };
+                let result = self
+                    .handle_manage_route(req, body, access_token, session)
+                    .await;
+                self.idempotency_end(guard, &result).await;
+
+                return result;
+            }
+        }
+
+        self.handle_manage_route(req, body, access_token, session)
+            .await
+    }
+
+    #[allow(unused_variables)]
+    async fn handle_manage_route(
+        &self,
+        req: &mut HttpRequest,
+        body: Option<Vec<u8>>,
+        access_token: Arc<AccessToken>,
+        session: &HttpSessionData,
+    ) -> trc::Result<HttpResponse> {
         let path = req.uri().path().split('/').skip(2).collect::<Vec<_>>();
 
+        // Directory changes are rejected while maintenance mode is enabled
+        if req.method() != Method::GET
+            && MAINTENANCE_ROUTES.contains(&path.first().copied().unwrap_or_default())
+        {
+            self.assert_not_maintenance(&access_token).await?;
+        }
+
         match path.first().copied().unwrap_or_default() {
             "queue" => self.handle_manage_queue(req, path, &access_token).await,
             "settings" => {
@@ -107,6 +248,66 @@ impl ManagementApi for Server {
                     .await
             }
             "reload" => self.handle_manage_reload(req, path, &access_token).await,
+            "task" => self.handle_manage_task(req, path, &access_token).await,
+            "migration" => self.handle_manage_migration(req, path, &access_token).await,
+            "activity" => self.handle_manage_activity(req, path, &access_token).await,
+            "callout" => self.handle_manage_callout(req, path, &access_token).await,
+            "rewrite" => {
+                self.handle_manage_rewrite(req, path, body, &access_token)
+                    .await
+            }
+            "expand" => self.handle_manage_expand(req, path, &access_token).await,
+            "template" => {
+                self.handle_manage_template(req, path, body, &access_token)
+                    .await
+            }
+            "delegation" => {
+                self.handle_manage_delegation(req, path, body, &access_token)
+                    .await
+            }
+            "maintenance" => {
+                self.handle_manage_maintenance(req, path, body, &access_token)
+                    .await
+            }
+            "replication" => {
+                self.handle_manage_replication(req, path, body, &access_token)
+                    .await
+            }
+            "approval" => self.handle_manage_approval(req, path, &access_token).await,
+            "undo" => self.handle_manage_undo(req, path, &access_token).await,
+            "volume" => self.handle_manage_volume(req, &access_token).await,
+            "tls" => self.handle_manage_tls(req, path, &access_token).await,
+            "export" => self.handle_manage_export(req, path, &access_token).await,
+            "roles" => {
+                self.handle_manage_roles(req, path, body, &access_token)
+                    .await
+            }
+            "reputation" => {
+                self.handle_manage_reputation(req, path, &access_token)
+                    .await
+            }
+            "recall" => {
+                self.handle_manage_recall(req, path, body, &access_token)
+                    .await
+            }
+            "privacy" => {
+                self.handle_manage_privacy(req, path, body, &access_token)
+                    .await
+            }
+            "apikey" => {
+                self.handle_manage_api_keys(req, path, body, &access_token)
+                    .await
+            }
+            "session" => self.handle_manage_sessions(req, path, &access_token).await,
+            "lockout" => self.handle_manage_lockout(req, path, &access_token).await,
+            "duplicates" => {
+                self.handle_manage_duplicates(req, path, &access_token)
+                    .await
+            }
+            "mailbox" => {
+                self.handle_manage_mailboxes(req, path, body, &access_token)
+                    .await
+            }
             "dkim" => {
                 self.handle_manage_dkim(req, path, body, &access_token)
                     .await
@@ -122,6 +323,16 @@ impl ManagementApi for Server {
 
                 Err(manage::unsupported("Restart is not yet supported"))
             }
+            "errors" if req.method() == Method::GET => Ok(JsonResponse::new(json!({
+                "data": ErrorCode::ALL
+                    .iter()
+                    .map(|code| json!({
+                        "code": code.as_str(),
+                        "description": code.description(),
+                    }))
+                    .collect::<Vec<_>>(),
+            }))
+            .into_http_response()),
             "oauth" => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::AuthenticateOauth)?;
@@ -153,6 +364,52 @@ impl ManagementApi for Server {
 
                     self.handle_account_auth_post(req, access_token, body).await
                 }
+                ("settings", &Method::PATCH) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageAccountSettings)?;
+
+                    self.handle_account_settings_patch(req, path, access_token, body)
+                        .await
+                }
+                ("recipients", &Method::GET) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageCollectedRecipients)?;
+
+                    self.handle_collected_recipients_get(req, access_token)
+                        .await
+                }
+                ("recipients", &Method::DELETE) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageCollectedRecipients)?;
+
+                    self.handle_collected_recipients_delete(path, access_token)
+                        .await
+                }
+                ("thread-rules", &Method::GET) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageThreadRules)?;
+
+                    self.handle_thread_rules_get(access_token).await
+                }
+                ("thread-rules", &Method::DELETE) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ManageThreadRules)?;
+
+                    self.handle_thread_rules_delete(path, access_token).await
+                }
+                ("memberships", &Method::GET) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::MembershipList)?;
+
+                    self.handle_memberships_get(access_token).await
+                }
+                ("moderation", &Method::GET | &Method::POST) => {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::ListModerate)?;
+
+                    self.handle_moderation_request(req, path, &access_token)
+                        .await
+                }
                 _ => Err(trc::ResourceEvent::NotFound.into_err()),
             },
             // SPDX-SnippetBegin
@@ -198,7 +455,10 @@ impl FromStr for Timestamp {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(dt) = DateTime::parse_rfc3339(s) {
+        // Dates without a time refer to midnight UTC
+        if let Some(dt) = DateTime::parse_rfc3339(s)
+            .or_else(|| DateTime::parse_rfc3339(&format!("{s}T00:00:00Z")))
+        {
             Ok(Timestamp(dt.to_timestamp() as u64))
         } else {
             Err(())
@@ -0,0 +1,235 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, ipc::QueueEvent, manager::webadmin::Resource, Server};
+use directory::backend::internal::{manage::ManageDirectory, PrincipalField};
+use hyper::Method;
+use mail_parser::{DateTime, MessageParser};
+use serde_json::json;
+use smtp::queue::{self, serialize::QueuedMessage, spool::SmtpSpool};
+use store::{
+    ahash::AHashMap,
+    write::{now, QueueClass, ValueClass},
+    Deserialize, IterateParams, ValueKey,
+};
+use trc::{AddContext, SecurityEvent};
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+pub trait ManageModeration: Sync + Send {
+    fn handle_moderation_request(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageModeration for Server {
+    async fn handle_moderation_request(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+        let mut moderated_lists = ModeratedLists::new(access_token.primary_id());
+
+        match (
+            path.get(2).and_then(|id| id.parse::<u64>().ok()),
+            path.get(3).copied(),
+            req.method(),
+        ) {
+            (None, None, &Method::GET) => {
+                // Obtain the posts held for the lists moderated by the caller
+                let mut held = Vec::new();
+                self.core
+                    .storage
+                    .data
+                    .iterate(
+                        IterateParams::new(
+                            ValueKey::from(ValueClass::Queue(QueueClass::Message(0))),
+                            ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX))),
+                        )
+                        .ascending(),
+                        |key, value| {
+                            let message = QueuedMessage::deserialize(value)
+                                .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?
+                                .inner;
+                            if message.moderated_list().is_some() {
+                                held.push(message);
+                            }
+
+                            Ok(true)
+                        },
+                    )
+                    .await
+                    .caused_by(trc::location!())?;
+
+                let page = params.parse::<usize>("page").unwrap_or_default();
+                let limit = params.parse::<usize>("limit").unwrap_or_default();
+                let mut offset = page.saturating_sub(1) * limit;
+                let mut total = 0;
+                let mut items = Vec::new();
+                for message in held {
+                    let Some(list) = moderated_lists.get(self, &message).await? else {
+                        continue;
+                    };
+                    total += 1;
+                    if offset > 0 {
+                        offset -= 1;
+                        continue;
+                    } else if limit > 0 && items.len() >= limit {
+                        continue;
+                    }
+
+                    let subject = self
+                        .core
+                        .storage
+                        .blob
+                        .get_blob(message.blob_hash.as_slice(), 0..usize::MAX)
+                        .await
+                        .caused_by(trc::location!())?
+                        .and_then(|raw| {
+                            MessageParser::new()
+                                .parse(&raw)
+                                .and_then(|m| m.subject().map(|s| s.to_string()))
+                        });
+                    items.push(json!({
+                        "id": message.queue_id,
+                        "list": list,
+                        "from": message.return_path,
+                        "subject": subject,
+                        "size": message.size,
+                        "created": DateTime::from_timestamp(message.created as i64).to_rfc3339(),
+                        "heldSince": message.hold.as_ref().map(|hold| {
+                            DateTime::from_timestamp(hold.since as i64).to_rfc3339()
+                        }),
+                    }));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "total": total,
+                        "items": items,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(queue_id), None, &Method::GET) => {
+                // Moderators review the full message before acting on it
+                let message = self.read_message(queue_id).await;
+                if let Some(message) = message {
+                    if moderated_lists.get(self, &message).await?.is_some() {
+                        let contents = self
+                            .core
+                            .storage
+                            .blob
+                            .get_blob(message.blob_hash.as_slice(), 0..usize::MAX)
+                            .await
+                            .caused_by(trc::location!())?
+                            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+
+                        return Ok(Resource::new("message/rfc822", contents).into_http_response());
+                    }
+                }
+
+                Err(trc::ResourceEvent::NotFound.into_err())
+            }
+            (Some(queue_id), Some(action @ ("approve" | "reject")), &Method::POST) => {
+                let Some(mut message) = self.read_message(queue_id).await else {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                };
+                let Some(list) = moderated_lists.get(self, &message).await? else {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                };
+
+                let found = if action == "approve" {
+                    message.release_hold()
+                } else {
+                    message.reject_hold(
+                        params
+                            .get("reason")
+                            .unwrap_or("Message rejected by the list moderator."),
+                    )
+                };
+                if found {
+                    // Held messages have no pending queue events
+                    let next_event = message.next_event().unwrap_or_else(now);
+                    message.save_changes(self, None, next_event.into()).await;
+                    let _ = self.inner.ipc.queue_tx.send(QueueEvent::Reload).await;
+
+                    if action == "approve" {
+                        trc::event!(
+                            Security(SecurityEvent::MessageHoldReleased),
+                            QueueId = queue_id,
+                            Id = list,
+                            From = access_token.name.clone(),
+                        );
+                    } else {
+                        trc::event!(
+                            Security(SecurityEvent::MessageHoldRejected),
+                            QueueId = queue_id,
+                            Id = list,
+                            From = access_token.name.clone(),
+                            Reason = params.get("reason").unwrap_or_default().to_string(),
+                        );
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": found,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+/// Names of the lists moderated by a principal, looked up once per list.
+struct ModeratedLists {
+    moderator_id: u32,
+    lists: AHashMap<u32, Option<String>>,
+}
+
+impl ModeratedLists {
+    fn new(moderator_id: u32) -> Self {
+        Self {
+            moderator_id,
+            lists: AHashMap::new(),
+        }
+    }
+
+    async fn get(
+        &mut self,
+        server: &Server,
+        message: &queue::Message,
+    ) -> trc::Result<Option<String>> {
+        let Some(list_id) = message.moderated_list() else {
+            return Ok(None);
+        };
+        if let Some(list) = self.lists.get(&list_id) {
+            return Ok(list.clone());
+        }
+
+        let list = server
+            .core
+            .storage
+            .data
+            .get_principal(list_id)
+            .await
+            .caused_by(trc::location!())?
+            .filter(|list| list.has_int_value(PrincipalField::Moderators, self.moderator_id as u64))
+            .and_then(|mut list| list.take_str(PrincipalField::Name));
+        self.lists.insert(list_id, list.clone());
+
+        Ok(list)
+    }
+}
@@ -0,0 +1,207 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{config::notify::NotifyEvent, Server};
+use directory::{backend::internal::PrincipalField, Principal, QueryBy, Type};
+use mail_builder::{
+    headers::{
+        address::{Address, EmailAddress},
+        HeaderType,
+    },
+    MessageBuilder,
+};
+use mail_parser::DateTime;
+use smtp::reporting::SmtpReporting;
+use trc::AddContext;
+
+use super::undo::UndoOperation;
+
+pub trait DirectoryNotifications: Sync + Send {
+    fn notify_directory_change(
+        &self,
+        operation: UndoOperation,
+        before: Option<&Principal>,
+        after: Option<&Principal>,
+        fields: &[PrincipalField],
+        performed_by: &str,
+    );
+
+    fn send_directory_alerts(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl DirectoryNotifications for Server {
+    fn notify_directory_change(
+        &self,
+        operation: UndoOperation,
+        before: Option<&Principal>,
+        after: Option<&Principal>,
+        fields: &[PrincipalField],
+        performed_by: &str,
+    ) {
+        let config = &self.core.directory_notify;
+        if !config.is_enabled() {
+            return;
+        }
+        let Some(principal) = after.or(before) else {
+            return;
+        };
+        let name = principal.name();
+
+        // Values present after the change that were not present before
+        let added = |field: PrincipalField| {
+            after
+                .into_iter()
+                .flat_map(move |after| after.iter_str(field))
+                .filter(move |value| {
+                    operation == UndoOperation::Create
+                        || !before.is_some_and(|before| before.has_str_value(field, value))
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        if operation == UndoOperation::Create && principal.typ() == Type::Tenant {
+            self.queue_directory_alert(
+                NotifyEvent::TenantCreate,
+                name,
+                format!("Tenant {name:?} was created"),
+                performed_by,
+            );
+        }
+
+        if operation == UndoOperation::Delete {
+            return;
+        }
+
+        if operation == UndoOperation::Create || fields.contains(&PrincipalField::Roles) {
+            for role in added(PrincipalField::Roles)
+                .into_iter()
+                .filter(|role| config.admin_roles.contains(role))
+            {
+                self.queue_directory_alert(
+                    NotifyEvent::RoleGrant,
+                    name,
+                    format!("Role {role:?} was granted to {name:?}"),
+                    performed_by,
+                );
+            }
+        }
+
+        if principal.typ() == Type::Role
+            && (operation == UndoOperation::Create
+                || fields.contains(&PrincipalField::EnabledPermissions))
+        {
+            let permissions = added(PrincipalField::EnabledPermissions);
+            if !permissions.is_empty() {
+                self.queue_directory_alert(
+                    NotifyEvent::PermissionChange,
+                    name,
+                    format!(
+                        "Permissions {} were added to role {name:?}",
+                        permissions.join(", ")
+                    ),
+                    performed_by,
+                );
+            }
+        }
+
+        if operation == UndoOperation::Update
+            && fields.contains(&PrincipalField::Secrets)
+            && config.protected.contains(&name.to_lowercase())
+        {
+            self.queue_directory_alert(
+                NotifyEvent::SecretChange,
+                name,
+                format!("Credentials of {name:?} were changed"),
+                performed_by,
+            );
+        }
+    }
+
+    async fn send_directory_alerts(&self) -> trc::Result<()> {
+        let alerts = self.take_directory_alerts();
+        let config = &self.core.directory_notify;
+        if alerts.is_empty() || config.to.is_empty() {
+            return Ok(());
+        }
+
+        // Recipients are either addresses or principal names
+        let mut rcpts = Vec::with_capacity(config.to.len());
+        for to in &config.to {
+            if to.contains('@') {
+                rcpts.push(to.clone());
+            } else if let Some(email) = self
+                .core
+                .storage
+                .directory
+                .query(QueryBy::Name(to), false)
+                .await
+                .caused_by(trc::location!())?
+                .and_then(|principal| principal.iter_str(PrincipalField::Emails).next().cloned())
+            {
+                rcpts.push(email);
+            }
+        }
+        if rcpts.is_empty() {
+            return Ok(());
+        }
+
+        let subject = if alerts.len() == 1 {
+            alerts[0].event.description().to_string()
+        } else {
+            format!("{} directory security alerts", alerts.len())
+        };
+        let mut body = String::from("The following directory changes were detected:\n\n");
+        for alert in &alerts {
+            body.push_str(&format!(
+                "- {} {}: {} (by {})\n",
+                DateTime::from_timestamp(alert.created as i64).to_rfc3339(),
+                alert.event.description(),
+                alert.details,
+                alert.performed_by
+            ));
+        }
+
+        let message = MessageBuilder::new()
+            .from(Address::Address(EmailAddress {
+                name: config.from_name.as_ref().map(|s| s.into()),
+                email: config.from_addr.as_str().into(),
+            }))
+            .header(
+                "To",
+                HeaderType::Address(Address::List(
+                    rcpts
+                        .iter()
+                        .map(|to| {
+                            Address::Address(EmailAddress {
+                                name: None,
+                                email: to.as_str().into(),
+                            })
+                        })
+                        .collect(),
+                )),
+            )
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .subject(subject)
+            .text_body(body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        // Notifications go through the queue so transient failures are retried
+        self.send_autogenerated(
+            config.from_addr.clone(),
+            rcpts.into_iter(),
+            message,
+            None,
+            0,
+        )
+        .await;
+
+        Ok(())
+    }
+}
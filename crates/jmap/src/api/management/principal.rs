@@ -4,15 +4,31 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::sync::{atomic::Ordering, Arc};
+use std::{
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
+};
 
-use common::{auth::AccessToken, Server};
+use common::{
+    auth::AccessToken,
+    config::approval::ProtectedOperation,
+    ipc::{HousekeeperEvent, PurgeType},
+    Server,
+};
 use directory::{
     backend::internal::{
+        delegation::DomainDelegationStore,
         lookup::DirectoryStore,
-        manage::{self, not_found, ManageDirectory, UpdatePrincipal},
-        PrincipalAction, PrincipalField, PrincipalUpdate, PrincipalValue, SpecialSecrets,
+        manage::{
+            self, err_not_found, ErrorCode, ImportMode, ManageDirectory, PrincipalExport,
+            PrincipalFilter, UpdatePrincipal,
+        },
+        permissions::{PermissionResolver, PermissionSource},
+        AppPassword, AppScope, PrincipalAction, PrincipalField, PrincipalUpdate, PrincipalValue,
+        SpecialSecrets, RECOVERY_CODE_PREFIX,
     },
+    backend::{ldap::LdapDirectory, sql::SqlDirectory},
+    core::secret::{generate_recovery_code, hash_secret},
     DirectoryInner, Permission, Principal, QueryBy, Type,
 };
 
@@ -21,20 +37,46 @@ use serde_json::json;
 use trc::AddContext;
 use utils::url_params::UrlParams;
 
-use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    email::delete::EmailDeletion,
+    identity::set::IdentitySet,
+};
 
-use super::decode_path_element;
+use super::{
+    approval::ManageApproval,
+    decode_path_element,
+    delegation::ManageDelegation,
+    undo::{ManageUndo, UndoOperation},
+    Timestamp,
+};
 use std::future::Future;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
 pub enum AccountAuthRequest {
-    SetPassword { password: String },
-    EnableOtpAuth { url: String },
-    DisableOtpAuth { url: Option<String> },
-    AddAppPassword { name: String, password: String },
-    RemoveAppPassword { name: String },
+    SetPassword {
+        password: String,
+    },
+    EnableOtpAuth {
+        url: String,
+    },
+    DisableOtpAuth {
+        url: Option<String>,
+    },
+    AddAppPassword {
+        name: String,
+        password: String,
+        #[serde(default)]
+        scopes: Option<Vec<String>>,
+    },
+    RemoveAppPassword {
+        name: String,
+    },
+    GenerateRecoveryCodes {
+        count: Option<usize>,
+    },
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -43,8 +85,13 @@ pub struct AccountAuthResponse {
     pub otp_auth: bool,
     #[serde(rename = "appPasswords")]
     pub app_passwords: Vec<String>,
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: usize,
 }
 
+const RECOVERY_CODES_DEFAULT: usize = 10;
+const RECOVERY_CODES_MAX: usize = 32;
+
 pub trait PrincipalManager: Sync + Send {
     fn handle_manage_principal(
         &self,
@@ -66,7 +113,39 @@ pub trait PrincipalManager: Sync + Send {
         body: Option<Vec<u8>>,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
 
+    fn handle_account_settings_patch(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: Arc<AccessToken>,
+        body: Option<Vec<u8>>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn delete_principal_by_id(
+        &self,
+        account_id: u32,
+        typ: Type,
+        tenant_id: Option<u32>,
+        cascade: bool,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn record_hold_change(
+        &self,
+        access_token: &AccessToken,
+        account_id: u32,
+        name: &str,
+        on_hold: bool,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
     fn assert_supported_directory(&self) -> trc::Result<()>;
+
+    fn assert_updatable_directory(&self) -> trc::Result<()>;
+
+    fn assert_writable_directory(&self) -> trc::Result<()>;
+
+    fn sql_directory(&self) -> Option<&SqlDirectory>;
+
+    fn ldap_directory(&self) -> Option<&LdapDirectory>;
 }
 
 impl PrincipalManager for Server {
@@ -77,6 +156,44 @@ impl PrincipalManager for Server {
         body: Option<Vec<u8>>,
         access_token: &AccessToken,
     ) -> trc::Result<HttpResponse> {
+        // Standby servers receive directory changes from the primary only
+        if req.method() != Method::GET {
+            self.assert_writable_directory()?;
+        }
+
+        // Principals can also be addressed by their external id
+        let external_name;
+        let path = if path.get(1) == Some(&"by-external-id") {
+            let external_id = path
+                .get(2)
+                .map(|id| decode_path_element(id))
+                .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+            let principal_id = self
+                .core
+                .storage
+                .data
+                .external_id_to_id(external_id.as_ref())
+                .await?
+                .ok_or_else(|| {
+                    err_not_found(ErrorCode::PrincipalNotFound, external_id.to_string())
+                })?;
+            external_name = self
+                .core
+                .storage
+                .data
+                .get_principal(principal_id)
+                .await?
+                .and_then(|mut p| p.take_str(PrincipalField::Name))
+                .ok_or_else(|| {
+                    err_not_found(ErrorCode::PrincipalNotFound, external_id.to_string())
+                })?;
+            let mut mapped_path = vec![path[0], external_name.as_str()];
+            mapped_path.extend_from_slice(&path[3..]);
+            mapped_path
+        } else {
+            path
+        };
+
         match (path.get(1), req.method()) {
             (None, &Method::POST) => {
                 // Parse principal
@@ -88,7 +205,7 @@ impl PrincipalManager for Server {
                         })?;
 
                 // Validate the access token
-                access_token.assert_has_permission(match principal.typ() {
+                let permission = match principal.typ() {
                     Type::Individual => Permission::IndividualCreate,
                     Type::Group => Permission::GroupCreate,
                     Type::List => Permission::MailingListCreate,
@@ -97,8 +214,12 @@ impl PrincipalManager for Server {
                     Type::Role => Permission::RoleCreate,
                     Type::ApiKey => Permission::ApiKeyCreate,
                     Type::OauthClient => Permission::OauthClientCreate,
-                    Type::Resource | Type::Location | Type::Other => Permission::PrincipalCreate,
-                })?;
+                    Type::Resource | Type::Location | Type::Other | Type::Service | Type::Alias => {
+                        Permission::PrincipalCreate
+                    }
+                };
+                self.assert_domain_permission(access_token, permission, &principal)
+                    .await?;
 
                 // SPDX-SnippetBegin
                 // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
@@ -146,26 +267,87 @@ impl PrincipalManager for Server {
                     }
                 }
 
-                // Create principal
-                let result = self
+                // Create principal, accounts are written to SQL directories
+                let inherits_defaults = matches!(principal.typ(), Type::Individual | Type::Group)
+                    && !principal.has_field(PrincipalField::Roles);
+                let result = match self.sql_directory() {
+                    Some(sql) if matches!(principal.typ(), Type::Individual) => {
+                        sql.create_principal(principal).await?
+                    }
+                    _ => {
+                        self.core
+                            .storage
+                            .data
+                            .create_principal(principal, tenant_id, Some(&access_token.permissions))
+                            .await?
+                    }
+                };
+
+                // Record the creation so it can be undone
+                let tenant_id = self
                     .core
                     .storage
                     .data
-                    .create_principal(principal, tenant_id, Some(&access_token.permissions))
-                    .await?;
+                    .get_principal(result)
+                    .await?
+                    .and_then(|p| p.tenant())
+                    .or(tenant_id);
+                let mut defaults = None;
+                if let Some(principal) = self.principal_snapshot(result).await? {
+                    // Roles of principals created without them are the tenant defaults
+                    if inherits_defaults && tenant_id.is_some() {
+                        defaults = json!({
+                            "roles": principal
+                                .get_str_array(PrincipalField::Roles)
+                                .unwrap_or_default(),
+                            "enabledPermissions": principal
+                                .get_str_array(PrincipalField::EnabledPermissions)
+                                .unwrap_or_default(),
+                        })
+                        .into();
+                    }
 
-                Ok(JsonResponse::new(json!({
+                    self.record_undo(
+                        UndoOperation::Create,
+                        None,
+                        Some(principal),
+                        &[],
+                        tenant_id,
+                        &access_token.name,
+                    )
+                    .await;
+                }
+
+                let mut response = json!({
                     "data": result,
-                }))
-                .into_http_response())
+                });
+                if let Some(defaults) = defaults {
+                    response["defaults"] = defaults;
+                }
+
+                Ok(JsonResponse::new(response).into_http_response())
             }
             (None, &Method::GET) => {
                 // List principal ids
                 let params = UrlParams::new(req.uri().query());
-                let filter = params.get("filter");
+                let mut filter = params
+                    .get("filter")
+                    .map(PrincipalFilter::parse)
+                    .unwrap_or_default();
+                if let Some(before) = params.get("last_login_before") {
+                    filter = filter.with_last_login_before(
+                        Timestamp::from_str(before)
+                            .map_err(|_| {
+                                manage::error("Invalid login date.", Some(before.to_string()))
+                            })?
+                            .into_inner(),
+                    );
+                }
+                let filter = Some(filter).filter(|filter| !filter.is_empty());
                 let page: usize = params.parse("page").unwrap_or(0);
                 let limit: usize = params.parse("limit").unwrap_or(0);
                 let count = params.get("count").is_some();
+                let after = params.get("after");
 
                 // Parse types
                 let mut types = Vec::new();
@@ -208,8 +390,9 @@ impl PrincipalManager for Server {
                         Type::OauthClient,
                     ]
                 };
+                let mut delegated_domains: Option<Vec<String>> = None;
                 for typ in validate_types {
-                    access_token.assert_has_permission(match typ {
+                    let permission = match typ {
                         Type::Individual => Permission::IndividualList,
                         Type::Group => Permission::GroupList,
                         Type::List => Permission::MailingListList,
@@ -218,8 +401,27 @@ impl PrincipalManager for Server {
                         Type::Role => Permission::RoleList,
                         Type::ApiKey => Permission::ApiKeyList,
                         Type::OauthClient => Permission::OauthClientList,
-                        Type::Resource | Type::Location | Type::Other => Permission::PrincipalList,
-                    })?;
+                        Type::Resource
+                        | Type::Location
+                        | Type::Other
+                        | Type::Service
+                        | Type::Alias => Permission::PrincipalList,
+                    };
+
+                    // Delegated administrators only see principals under their domains
+                    if !access_token.has_permission(permission) {
+                        let domains = self.delegated_domains(access_token, permission).await?;
+                        if domains.is_empty() {
+                            access_token.assert_has_permission(permission)?;
+                        }
+                        delegated_domains = Some(match delegated_domains {
+                            Some(mut current) => {
+                                current.retain(|domain| domains.contains(domain));
+                                current
+                            }
+                            None => domains,
+                        });
+                    }
                 }
 
                 // SPDX-SnippetBegin
@@ -249,11 +451,76 @@ impl PrincipalManager for Server {
 
                 // SPDX-SnippetEnd
 
+                // Service accounts are only listed when explicitly requested
+                if types.is_empty() {
+                    types = vec![
+                        Type::Individual,
+                        Type::Group,
+                        Type::Resource,
+                        Type::Location,
+                        Type::List,
+                        Type::Other,
+                        Type::Domain,
+                        Type::Tenant,
+                        Type::Role,
+                        Type::ApiKey,
+                        Type::OauthClient,
+                    ];
+                }
+
+                if let Some(domains) = delegated_domains {
+                    let mut principals = self
+                        .core
+                        .storage
+                        .data
+                        .list_delegated_principals(
+                            filter.as_ref(),
+                            tenant,
+                            &types,
+                            &fields,
+                            &domains,
+                            page,
+                            limit,
+                        )
+                        .await?;
+
+                    if count {
+                        principals.items.clear();
+                    }
+
+                    return Ok(JsonResponse::new(json!({
+                            "data": principals,
+                    }))
+                    .into_http_response());
+                }
+
+                // Cursor based listing stops reading once the page is full
+                if let Some(after) = after.filter(|_| !count) {
+                    let principals = self
+                        .core
+                        .storage
+                        .data
+                        .list_principals_after(
+                            filter.as_ref(),
+                            tenant,
+                            &types,
+                            &fields,
+                            Some(after).filter(|after| !after.is_empty()),
+                            limit,
+                        )
+                        .await?;
+
+                    return Ok(JsonResponse::new(json!({
+                            "data": principals,
+                    }))
+                    .into_http_response());
+                }
+
                 let mut principals = self
                     .core
                     .storage
                     .data
-                    .list_principals(filter, tenant, &types, &fields, page, limit)
+                    .list_principals(filter.as_ref(), tenant, &types, &fields, page, limit)
                     .await?;
 
                 if count {
@@ -265,18 +532,196 @@ impl PrincipalManager for Server {
                 }))
                 .into_http_response())
             }
+            (Some(&"export"), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PrincipalExport)?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self
+                        .core
+                        .storage
+                        .data
+                        .export_principals(access_token.tenant.map(|t| t.id))
+                        .await?,
+                }))
+                .into_http_response())
+            }
+            (Some(&"export"), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PrincipalImport)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let mode = match params.get("mode").unwrap_or("fail") {
+                    "skip" => ImportMode::Skip,
+                    "overwrite" => ImportMode::Overwrite,
+                    "fail" => ImportMode::Fail,
+                    mode => {
+                        return Err(trc::ResourceEvent::BadParameters
+                            .into_err()
+                            .details(format!("Invalid import mode {mode:?}")));
+                    }
+                };
+                let data =
+                    serde_json::from_slice::<PrincipalExport>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self
+                        .core
+                        .storage
+                        .data
+                        .import_principals(
+                            data,
+                            mode,
+                            access_token.tenant.map(|t| t.id),
+                            Some(&access_token.permissions),
+                        )
+                        .await?,
+                }))
+                .into_http_response())
+            }
+            (Some(name), &Method::GET) if path.get(2) == Some(&"permissions") => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::EffectivePermissionsGet)?;
+
+                let name = decode_path_element(name);
+                let (account_id, tenant_id) = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name.as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+                    .map(|p| (p.id, p.tenant))
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+                let mut permissions = self
+                    .core
+                    .storage
+                    .data
+                    .get_effective_permissions(account_id, self.core.jmap.member_of_max_depth)
+                    .await?
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+
+                // SPDX-SnippetBegin
+                // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+                // SPDX-License-Identifier: LicenseRef-SEL
+
+                #[cfg(feature = "enterprise")]
+                if self.core.is_enterprise_edition() {
+                    if let Some(tenant_id) = tenant_id {
+                        let tenant = self
+                            .core
+                            .storage
+                            .data
+                            .get_principal(tenant_id)
+                            .await?
+                            .map(|tenant| tenant.name().to_string())
+                            .unwrap_or_default();
+                        permissions.restrict(
+                            &self.get_role_permissions(tenant_id).await?.enabled,
+                            PermissionSource::Tenant { name: tenant },
+                        );
+                    }
+                }
+
+                // SPDX-SnippetEnd
+
+                Ok(JsonResponse::new(json!({
+                    "data": permissions,
+                }))
+                .into_http_response())
+            }
+            (Some(name), &Method::GET) if path.get(2) == Some(&"hold") => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::LitigationHold)?;
+
+                let name = decode_path_element(name);
+                let principal = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name.as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+                let since = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal(principal.id)
+                    .await?
+                    .and_then(|p| p.get_int(PrincipalField::Hold));
+
+                // Report the deleted messages kept by the hold
+                let items = self.emails_preserved(principal.id).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "onHold": since.is_some(),
+                        "since": since,
+                        "messages": items.len(),
+                        "bytes": items.iter().map(|item| item.size as u64).sum::<u64>(),
+                        "items": items,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(name), &Method::POST) if path.get(2) == Some(&"revoke-sessions") => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SessionDisconnect)?;
+
+                let name = decode_path_element(name);
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name.as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+                    .map(|p| p.id)
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+                let credentials_epoch = self
+                    .core
+                    .storage
+                    .data
+                    .revoke_credentials(account_id)
+                    .await?;
+
+                // Sessions on other nodes are closed on their next credentials check
+                self.invalidate_principal(account_id).await?;
+                let disconnected = self.disconnect_sessions(account_id).await?;
+
+                trc::event!(
+                    Security(trc::SecurityEvent::SessionsRevoked),
+                    AccountId = account_id,
+                    AccountName = name.to_string(),
+                    Total = disconnected,
+                    From = access_token.name.clone(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "credentialsEpoch": credentials_epoch,
+                        "disconnected": disconnected,
+                    },
+                }))
+                .into_http_response())
+            }
             (Some(name), method) => {
                 // Fetch, update or delete principal
                 let name = decode_path_element(name);
-                let (account_id, typ) = self
+                let (account_id, typ, tenant_id) = self
                     .core
                     .storage
                     .data
                     .get_principal_info(name.as_ref())
                     .await?
                     .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
-                    .map(|p| (p.id, p.typ))
-                    .ok_or_else(|| not_found(name.to_string()))?;
+                    .map(|p| (p.id, p.typ, p.tenant))
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
 
                 // SPDX-SnippetBegin
                 // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
@@ -292,7 +737,7 @@ impl PrincipalManager for Server {
                 match *method {
                     Method::GET => {
                         // Validate the access token
-                        access_token.assert_has_permission(match typ {
+                        let permission = match typ {
                             Type::Individual => Permission::IndividualGet,
                             Type::Group => Permission::GroupGet,
                             Type::List => Permission::MailingListGet,
@@ -301,10 +746,14 @@ impl PrincipalManager for Server {
                             Type::Role => Permission::RoleGet,
                             Type::ApiKey => Permission::ApiKeyGet,
                             Type::OauthClient => Permission::OauthClientGet,
-                            Type::Resource | Type::Location | Type::Other => {
-                                Permission::PrincipalGet
-                            }
-                        })?;
+                            Type::Resource
+                            | Type::Location
+                            | Type::Other
+                            | Type::Service
+                            | Type::Alias => Permission::PrincipalGet,
+                        };
+                        self.assert_domain_permission_by_id(access_token, permission, account_id)
+                            .await?;
 
                         let mut principal = self
                             .core
@@ -329,7 +778,7 @@ impl PrincipalManager for Server {
                     }
                     Method::DELETE => {
                         // Validate the access token
-                        access_token.assert_has_permission(match typ {
+                        let permission = match typ {
                             Type::Individual => Permission::IndividualDelete,
                             Type::Group => Permission::GroupDelete,
                             Type::List => Permission::MailingListDelete,
@@ -338,38 +787,86 @@ impl PrincipalManager for Server {
                             Type::Role => Permission::RoleDelete,
                             Type::ApiKey => Permission::ApiKeyDelete,
                             Type::OauthClient => Permission::OauthClientDelete,
-                            Type::Resource | Type::Location | Type::Other => {
-                                Permission::PrincipalDelete
-                            }
-                        })?;
-
-                        // Delete account
-                        self.core
-                            .storage
-                            .data
-                            .delete_principal(QueryBy::Id(account_id))
+                            Type::Resource
+                            | Type::Location
+                            | Type::Other
+                            | Type::Service
+                            | Type::Alias => Permission::PrincipalDelete,
+                        };
+                        self.assert_domain_permission_by_id(access_token, permission, account_id)
                             .await?;
 
-                        // Remove FTS index
-                        if matches!(typ, Type::Individual | Type::Group) {
-                            self.core.storage.fts.remove_all(account_id).await?;
+                        // Cascading a domain deletion also removes the accounts using it
+                        let params = UrlParams::new(req.uri().query());
+                        let cascade =
+                            typ == Type::Domain && params.parse("cascade").unwrap_or(false);
+                        if cascade {
+                            access_token.assert_has_permission(Permission::IndividualDelete)?;
                         }
 
-                        // Remove entries from cache
-                        self.inner
-                            .data
-                            .http_auth_cache
-                            .retain(|_, id| id.item != account_id);
+                        // Report what would be removed without deleting anything
+                        if matches!(params.get("dry_run"), Some("1" | "true")) {
+                            let preview = self
+                                .core
+                                .storage
+                                .data
+                                .preview_delete_principal(QueryBy::Id(account_id))
+                                .await?;
 
-                        if matches!(typ, Type::Role | Type::Tenant) {
-                            // Update permissions cache
-                            self.inner.data.permissions.clear();
-                            self.inner
+                            return Ok(JsonResponse::new(json!({
+                                "data": preview,
+                            }))
+                            .into_http_response());
+                        }
+
+                        // Protected deletions wait for a second administrator
+                        if let Some(operation) = ProtectedOperation::delete(typ) {
+                            if let Some(response) = self
+                                .request_approval(operation, Some(name.to_string()), access_token)
+                                .await?
+                            {
+                                return Ok(response);
+                            }
+                        }
+
+                        let before = self.principal_snapshot(account_id).await?;
+
+                        // Accounts under hold are only deleted when explicitly overridden
+                        if params.parse("override_hold").unwrap_or(false)
+                            && before.as_ref().is_some_and(|p| p.is_on_hold())
+                        {
+                            access_token.assert_has_permission(Permission::LitigationHold)?;
+                            self.core
+                                .storage
                                 .data
-                                .permissions_version
-                                .fetch_add(1, Ordering::Relaxed);
+                                .update_principal(UpdatePrincipal::by_id(account_id).with_updates(
+                                    vec![PrincipalUpdate::set(
+                                        PrincipalField::Hold,
+                                        PrincipalValue::Integer(0),
+                                    )],
+                                ))
+                                .await?;
+                            self.record_hold_change(access_token, account_id, &name, false)
+                                .await?;
                         }
 
+                        self.delete_principal_by_id(
+                            account_id,
+                            typ,
+                            access_token.tenant.map(|t| t.id),
+                            cascade,
+                        )
+                        .await?;
+                        self.record_undo(
+                            UndoOperation::Delete,
+                            before,
+                            None,
+                            &[],
+                            tenant_id,
+                            &access_token.name,
+                        )
+                        .await;
+
                         Ok(JsonResponse::new(json!({
                             "data": (),
                         }))
@@ -386,11 +883,18 @@ impl PrincipalManager for Server {
                             Type::Role => Permission::RoleUpdate,
                             Type::ApiKey => Permission::ApiKeyUpdate,
                             Type::OauthClient => Permission::OauthClientUpdate,
-                            Type::Resource | Type::Location | Type::Other => {
-                                Permission::PrincipalUpdate
-                            }
+                            Type::Resource
+                            | Type::Location
+                            | Type::Other
+                            | Type::Service
+                            | Type::Alias => Permission::PrincipalUpdate,
                         };
-                        access_token.assert_has_permission(permission_needed)?;
+                        self.assert_domain_permission_by_id(
+                            access_token,
+                            permission_needed,
+                            account_id,
+                        )
+                        .await?;
 
                         let changes = serde_json::from_slice::<Vec<PrincipalUpdate>>(
                             body.as_deref().unwrap_or_default(),
@@ -400,30 +904,104 @@ impl PrincipalManager for Server {
                                 .from_json_error(err)
                         })?;
 
+                        // Delegated administrators cannot move principals outside their domains
+                        if !access_token.has_permission(permission_needed) {
+                            let domains = self
+                                .delegated_domains(access_token, permission_needed)
+                                .await?;
+                            for change in &changes {
+                                if matches!(
+                                    change.action,
+                                    PrincipalAction::Set | PrincipalAction::AddItem
+                                ) {
+                                    let values = match (&change.field, &change.value) {
+                                        (
+                                            PrincipalField::Name | PrincipalField::Emails,
+                                            PrincipalValue::String(v),
+                                        ) => std::slice::from_ref(v),
+                                        (
+                                            PrincipalField::Name | PrincipalField::Emails,
+                                            PrincipalValue::StringList(v),
+                                        ) => v.as_slice(),
+                                        _ => continue,
+                                    };
+
+                                    for value in values {
+                                        let is_delegated = match value.rsplit_once('@') {
+                                            Some((_, domain)) => {
+                                                domains.contains(&domain.to_lowercase())
+                                            }
+                                            None => change.field == PrincipalField::Name,
+                                        };
+                                        if !is_delegated {
+                                            return Err(trc::SecurityEvent::Unauthorized
+                                                .into_err()
+                                                .details(permission_needed.name())
+                                                .ctx(
+                                                    trc::Key::Reason,
+                                                    "Address is outside the delegated domains",
+                                                ));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
                         // Validate changes
                         let mut needs_assert = false;
-                        let mut expire_session = false;
-                        let mut expire_token = false;
                         let mut is_role_change = false;
 
                         for change in &changes {
                             match change.field {
                                 PrincipalField::Secrets => {
-                                    expire_session = true;
                                     needs_assert = true;
                                 }
-                                PrincipalField::Name
+                                PrincipalField::CertificateFingerprints
+                                | PrincipalField::Name
                                 | PrincipalField::Emails
                                 | PrincipalField::Quota
                                 | PrincipalField::UsedQuota
+                                | PrincipalField::LastLogin
+                                | PrincipalField::CredentialsEpoch
                                 | PrincipalField::Description
-                                | PrincipalField::Type
                                 | PrincipalField::Picture
                                 | PrincipalField::MemberOf
                                 | PrincipalField::Members
                                 | PrincipalField::Lists
                                 | PrincipalField::Urls
-                                | PrincipalField::ExternalMembers => (),
+                                | PrincipalField::ExternalMembers
+                                | PrincipalField::ForwardTo
+                                | PrincipalField::CatchAll
+                                | PrincipalField::Subaddressing
+                                | PrincipalField::DefaultRoles
+                                | PrincipalField::DefaultPermissions
+                                | PrincipalField::PostingPolicy
+                                | PrincipalField::Moderators
+                                | PrincipalField::ReplyToList
+                                | PrincipalField::Locale
+                                | PrincipalField::Timezone
+                                | PrincipalField::Enabled
+                                | PrincipalField::ExternalId => (),
+                                PrincipalField::Type => {
+                                    // Converting also requires managing the target type
+                                    if let PrincipalValue::String(new_type) = &change.value {
+                                        match Type::parse(new_type) {
+                                            Some(Type::Individual) => access_token
+                                                .assert_has_permission(
+                                                    Permission::IndividualUpdate,
+                                                )?,
+                                            Some(Type::Service) => access_token
+                                                .assert_has_permission(
+                                                    Permission::PrincipalUpdate,
+                                                )?,
+                                            _ => (),
+                                        }
+                                    }
+                                }
+                                PrincipalField::Hold => {
+                                    access_token
+                                        .assert_has_permission(Permission::LitigationHold)?;
+                                }
                                 PrincipalField::Tenant => {
                                     // Tenants are not allowed to change their tenantId
                                     if access_token.tenant.is_some() {
@@ -441,8 +1019,6 @@ impl PrincipalManager for Server {
                                 | PrincipalField::DisabledPermissions => {
                                     if matches!(typ, Type::Role | Type::Tenant) {
                                         is_role_change = true;
-                                    } else {
-                                        expire_token = true;
                                     }
 
                                     if change.field == PrincipalField::Roles
@@ -496,28 +1072,75 @@ impl PrincipalManager for Server {
                         }
 
                         if needs_assert {
-                            self.assert_supported_directory()?;
+                            self.assert_updatable_directory()?;
                         }
 
                         // Update principal
-                        self.core
-                            .storage
-                            .data
-                            .update_principal(
-                                UpdatePrincipal::by_id(account_id)
+                        let mut fields = changes.iter().map(|c| c.field).collect::<Vec<_>>();
+                        fields.dedup();
+                        let before = self.principal_snapshot(account_id).await?;
+                        let cascade_rename = UrlParams::new(req.uri().query())
+                            .parse("cascade")
+                            .unwrap_or(false);
+                        match self.sql_directory() {
+                            Some(sql) if matches!(typ, Type::Individual) => {
+                                sql.update_principal(account_id, changes).await?;
+                            }
+                            _ => {
+                                let mut update = UpdatePrincipal::by_id(account_id)
                                     .with_updates(changes)
                                     .with_tenant(access_token.tenant.map(|t| t.id))
-                                    .with_allowed_permissions(&access_token.permissions),
-                            )
-                            .await?;
+                                    .with_allowed_permissions(&access_token.permissions);
+                                if cascade_rename {
+                                    update = update.cascade_rename();
+                                }
+                                match self.ldap_directory() {
+                                    Some(ldap) if matches!(typ, Type::Individual) => {
+                                        ldap.update_principal(account_id, update).await?;
+                                    }
+                                    _ => {
+                                        self.core.storage.data.update_principal(update).await?;
+                                    }
+                                }
+                            }
+                        }
+                        let after = self.principal_snapshot(account_id).await?;
+
+                        if let (Some(before), Some(after)) = (&before, &after) {
+                            if before.is_on_hold() != after.is_on_hold() {
+                                self.record_hold_change(
+                                    access_token,
+                                    account_id,
+                                    after.name(),
+                                    after.is_on_hold(),
+                                )
+                                .await?;
+                            }
+                        }
 
-                        if expire_session {
-                            // Remove entries from cache
-                            self.inner
-                                .data
-                                .http_auth_cache
-                                .retain(|_, id| id.item != account_id);
+                        // Identities sending from the renamed address follow it
+                        if let (true, Some(before), Some(after)) = (cascade_rename, &before, &after)
+                        {
+                            if before.name() != after.name()
+                                && before.has_str_value(PrincipalField::Emails, before.name())
+                                && after.has_str_value(PrincipalField::Emails, after.name())
+                            {
+                                self.identity_rename_email(account_id, before.name(), after.name())
+                                    .await?;
+                            }
                         }
+                        self.record_undo(
+                            UndoOperation::Update,
+                            before,
+                            after,
+                            &fields,
+                            tenant_id,
+                            &access_token.name,
+                        )
+                        .await;
+
+                        // Evict cached credentials and tokens on all nodes
+                        self.invalidate_principal(account_id).await?;
 
                         if is_role_change {
                             // Update permissions cache
@@ -528,10 +1151,6 @@ impl PrincipalManager for Server {
                                 .fetch_add(1, Ordering::Relaxed);
                         }
 
-                        if expire_token {
-                            self.inner.data.access_tokens.remove(&account_id);
-                        }
-
                         Ok(JsonResponse::new(json!({
                             "data": (),
                         }))
@@ -552,6 +1171,7 @@ impl PrincipalManager for Server {
         let mut response = AccountAuthResponse {
             otp_auth: false,
             app_passwords: Vec::new(),
+            recovery_codes: 0,
         };
 
         if access_token.primary_id() != u32::MAX {
@@ -566,10 +1186,10 @@ impl PrincipalManager for Server {
             for secret in principal.iter_str(PrincipalField::Secrets) {
                 if secret.is_otp_auth() {
                     response.otp_auth = true;
-                } else if let Some((app_name, _)) =
-                    secret.strip_prefix("$app$").and_then(|s| s.split_once('$'))
-                {
-                    response.app_passwords.push(app_name.to_string());
+                } else if let Some(app_password) = secret.app_password() {
+                    response.app_passwords.push(app_password.name.to_string());
+                } else if secret.is_recovery_code() {
+                    response.recovery_codes += 1;
                 }
             }
         }
@@ -586,6 +1206,8 @@ impl PrincipalManager for Server {
         access_token: Arc<AccessToken>,
         body: Option<Vec<u8>>,
     ) -> trc::Result<HttpResponse> {
+        self.assert_writable_directory()?;
+
         // Parse request
         let requests =
             serde_json::from_slice::<Vec<AccountAuthRequest>>(body.as_deref().unwrap_or_default())
@@ -606,12 +1228,9 @@ impl PrincipalManager for Server {
                 AccountAuthRequest::DisableOtpAuth { .. }
                     | AccountAuthRequest::EnableOtpAuth { .. }
                     | AccountAuthRequest::SetPassword { .. }
+                    | AccountAuthRequest::GenerateRecoveryCodes { .. }
             )
-        }) && req
-            .headers()
-            .get(header::AUTHORIZATION)
-            .and_then(|h| h.to_str().ok())
-            .map_or(true, |header| !header.to_lowercase().starts_with("basic "))
+        }) && !is_basic_auth(req)
         {
             return Err(manage::error(
                 "Password changes only allowed using Basic auth",
@@ -650,10 +1269,11 @@ impl PrincipalManager for Server {
         }
 
         // Make sure the current directory supports updates
-        self.assert_supported_directory()?;
+        self.assert_updatable_directory()?;
 
         // Build actions
         let mut actions = Vec::with_capacity(requests.len());
+        let mut recovery_codes: Option<Vec<String>> = None;
         for request in requests {
             let (action, secret) = match request {
                 AccountAuthRequest::SetPassword { password } => {
@@ -670,12 +1290,81 @@ impl PrincipalManager for Server {
                     PrincipalAction::RemoveItem,
                     url.unwrap_or_else(|| "otpauth://".to_string()),
                 ),
-                AccountAuthRequest::AddAppPassword { name, password } => {
-                    (PrincipalAction::AddItem, format!("$app${name}${password}"))
+                AccountAuthRequest::AddAppPassword {
+                    name,
+                    password,
+                    scopes,
+                } => {
+                    if !AppPassword::is_valid_name(&name) {
+                        return Err(manage::err_code(
+                            ErrorCode::FieldInvalid,
+                            "Invalid app password name",
+                            Some(name),
+                        ));
+                    }
+                    let scopes = scopes
+                        .map(|scopes| {
+                            scopes
+                                .iter()
+                                .map(|scope| {
+                                    AppScope::parse(scope).ok_or_else(|| {
+                                        manage::err_code(
+                                            ErrorCode::FieldInvalid,
+                                            "Invalid app password scope",
+                                            Some(scope.clone()),
+                                        )
+                                    })
+                                })
+                                .collect::<trc::Result<Vec<_>>>()
+                        })
+                        .transpose()?;
+                    if scopes.as_ref().is_some_and(|scopes| scopes.is_empty()) {
+                        return Err(manage::err_code(
+                            ErrorCode::FieldInvalid,
+                            "App passwords require at least one scope",
+                            Some(name),
+                        ));
+                    }
+
+                    (
+                        PrincipalAction::AddItem,
+                        AppPassword::new(&name, scopes, &password).build(),
+                    )
                 }
                 AccountAuthRequest::RemoveAppPassword { name } => {
                     (PrincipalAction::RemoveItem, format!("$app${name}"))
                 }
+                AccountAuthRequest::GenerateRecoveryCodes { count } => {
+                    let count = count.unwrap_or(RECOVERY_CODES_DEFAULT);
+                    if !(1..=RECOVERY_CODES_MAX).contains(&count) {
+                        return Err(manage::err_code(
+                            ErrorCode::FieldInvalid,
+                            "Invalid number of recovery codes",
+                            Some(format!("Expected between 1 and {RECOVERY_CODES_MAX}")),
+                        ));
+                    }
+
+                    // New codes replace the ones generated previously, only
+                    // their hashes are stored
+                    let codes = recovery_codes.get_or_insert_with(Vec::new);
+                    codes.clear();
+                    actions.push(PrincipalUpdate::remove_item(
+                        PrincipalField::Secrets,
+                        PrincipalValue::String(RECOVERY_CODE_PREFIX.to_string()),
+                    ));
+                    for _ in 0..count {
+                        let code = generate_recovery_code();
+                        actions.push(PrincipalUpdate::add_item(
+                            PrincipalField::Secrets,
+                            PrincipalValue::String(format!(
+                                "{RECOVERY_CODE_PREFIX}{}",
+                                hash_secret(&code).await?
+                            )),
+                        ));
+                        codes.push(code);
+                    }
+                    continue;
+                }
             };
 
             actions.push(PrincipalUpdate {
@@ -686,21 +1375,172 @@ impl PrincipalManager for Server {
         }
 
         // Update password
-        self.core
-            .storage
-            .data
-            .update_principal(
-                UpdatePrincipal::by_id(access_token.primary_id())
-                    .with_updates(actions)
-                    .with_tenant(access_token.tenant.map(|t| t.id)),
-            )
-            .await?;
+        if let Some(sql) = self.sql_directory() {
+            sql.update_principal(access_token.primary_id(), actions)
+                .await?;
+        } else {
+            let update = UpdatePrincipal::by_id(access_token.primary_id())
+                .with_updates(actions)
+                .with_tenant(access_token.tenant.map(|t| t.id));
+            if let Some(ldap) = self.ldap_directory() {
+                ldap.update_principal(access_token.primary_id(), update)
+                    .await?;
+            } else {
+                self.core.storage.data.update_principal(update).await?;
+            }
+        }
 
         // Remove entries from cache
-        self.inner
-            .data
-            .http_auth_cache
-            .retain(|_, id| id.item != access_token.primary_id());
+        self.invalidate_principal(access_token.primary_id()).await?;
+
+        // Generated recovery codes are only returned once
+        Ok(JsonResponse::new(json!({
+            "data": recovery_codes,
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_account_settings_patch(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: Arc<AccessToken>,
+        body: Option<Vec<u8>>,
+    ) -> trc::Result<HttpResponse> {
+        self.assert_writable_directory()?;
+
+        let changes =
+            serde_json::from_slice::<Vec<PrincipalUpdate>>(body.as_deref().unwrap_or_default())
+                .map_err(|err| {
+                    trc::EventType::Resource(trc::ResourceEvent::BadParameters).from_json_error(err)
+                })?;
+
+        if changes.is_empty() {
+            return Err(trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                .into_err()
+                .details("Empty request"));
+        } else if access_token.primary_id() == u32::MAX {
+            return Err(manage::unsupported(
+                "Fallback administrator accounts cannot be managed using this endpoint",
+            ));
+        }
+
+        // Users may update their own account, other accounts require an
+        // explicit delegation to manage them and must belong to the tenant
+        let account_id = if let Some(name) = path.get(2) {
+            let name = decode_path_element(name);
+            let account_id = self
+                .core
+                .storage
+                .data
+                .get_principal_info(name.as_ref())
+                .await
+                .caused_by(trc::location!())?
+                .filter(|p| {
+                    p.typ == Type::Individual
+                        && p.has_tenant_access(access_token.tenant.map(|t| t.id))
+                })
+                .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?
+                .id;
+            if account_id != access_token.primary_id() {
+                match self
+                    .assert_domain_permission_by_id(
+                        &access_token,
+                        Permission::IndividualUpdate,
+                        account_id,
+                    )
+                    .await
+                {
+                    Err(err)
+                        if err.matches(trc::EventType::Security(
+                            trc::SecurityEvent::Unauthorized,
+                        )) =>
+                    {
+                        return Err(err_not_found(
+                            ErrorCode::PrincipalNotFound,
+                            name.to_string(),
+                        ));
+                    }
+                    result => result?,
+                }
+            }
+            account_id
+        } else {
+            access_token.primary_id()
+        };
+
+        // Validate changes against the allowed fields
+        let allowed_fields = &self.core.jmap.self_service_fields;
+        let mut actions = Vec::with_capacity(changes.len());
+        for change in changes {
+            if !allowed_fields.contains(&change.field) {
+                return Err(manage::err_code(
+                    ErrorCode::FieldNotAllowed,
+                    format!("Field {:?} cannot be modified", change.field.as_str()),
+                    Some("Field is not included in the self-service allow list"),
+                )
+                .ctx(trc::Key::Key, change.field));
+            }
+
+            if change.field == PrincipalField::Secrets {
+                // Only password replacement is supported, other secrets
+                // are managed through the account authentication endpoint
+                match (change.action, change.value) {
+                    (PrincipalAction::Set, PrincipalValue::String(password))
+                        if !password.is_empty() =>
+                    {
+                        if !is_basic_auth(req) {
+                            return Err(manage::error(
+                                "Password changes only allowed using Basic auth",
+                                None::<u32>,
+                            ));
+                        }
+
+                        actions.push(PrincipalUpdate {
+                            action: PrincipalAction::RemoveItem,
+                            field: PrincipalField::Secrets,
+                            value: PrincipalValue::String(String::new()),
+                        });
+                        actions.push(PrincipalUpdate {
+                            action: PrincipalAction::AddItem,
+                            field: PrincipalField::Secrets,
+                            value: PrincipalValue::String(password),
+                        });
+                    }
+                    _ => {
+                        return Err(manage::err_code(
+                            ErrorCode::FieldInvalid,
+                            "Invalid secrets value",
+                            Some("Only setting a new password is supported"),
+                        )
+                        .ctx(trc::Key::Key, PrincipalField::Secrets));
+                    }
+                }
+            } else {
+                actions.push(change);
+            }
+        }
+
+        // Make sure the current directory supports updates
+        self.assert_updatable_directory()?;
+
+        // Update principal using the same validation as administrative updates
+        if let Some(sql) = self.sql_directory() {
+            sql.update_principal(account_id, actions).await?;
+        } else {
+            let update = UpdatePrincipal::by_id(account_id)
+                .with_updates(actions)
+                .with_tenant(access_token.tenant.map(|t| t.id))
+                .with_allowed_permissions(&access_token.permissions);
+            if let Some(ldap) = self.ldap_directory() {
+                ldap.update_principal(account_id, update).await?;
+            } else {
+                self.core.storage.data.update_principal(update).await?;
+            }
+        }
+
+        // Evict cached credentials and tokens on all nodes
+        self.invalidate_principal(account_id).await?;
 
         Ok(JsonResponse::new(json!({
             "data": (),
@@ -708,9 +1548,108 @@ impl PrincipalManager for Server {
         .into_http_response())
     }
 
+    async fn delete_principal_by_id(
+        &self,
+        account_id: u32,
+        typ: Type,
+        tenant_id: Option<u32>,
+        cascade: bool,
+    ) -> trc::Result<()> {
+        // Delete account, along with the accounts only addressed by a domain
+        if cascade {
+            for principal in self
+                .core
+                .storage
+                .data
+                .delete_principal_cascade(QueryBy::Id(account_id), tenant_id)
+                .await?
+            {
+                if matches!(principal.typ, Type::Individual | Type::Group) {
+                    self.core.storage.fts.remove_all(principal.id).await?;
+                }
+                self.invalidate_principal(principal.id).await?;
+            }
+        } else if let Some(sql) = self
+            .sql_directory()
+            .filter(|_| matches!(typ, Type::Individual))
+        {
+            sql.delete_principal(account_id).await?;
+        } else {
+            self.core
+                .storage
+                .data
+                .delete_principal(QueryBy::Id(account_id))
+                .await?;
+        }
+
+        // Remove FTS index
+        if matches!(typ, Type::Individual | Type::Group) {
+            self.core.storage.fts.remove_all(account_id).await?;
+        }
+
+        // Remove entries from cache
+        self.invalidate_principal(account_id).await?;
+
+        if matches!(typ, Type::Role | Type::Tenant) {
+            // Update permissions cache
+            self.inner.data.permissions.clear();
+            self.inner
+                .data
+                .permissions_version
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    async fn record_hold_change(
+        &self,
+        access_token: &AccessToken,
+        account_id: u32,
+        name: &str,
+        on_hold: bool,
+    ) -> trc::Result<()> {
+        if on_hold {
+            trc::event!(
+                Manage(trc::ManageEvent::HoldPlaced),
+                Id = account_id,
+                Details = name.to_string(),
+                AccountId = access_token.primary_id(),
+                AccountName = access_token.name.clone(),
+            );
+        } else {
+            trc::event!(
+                Manage(trc::ManageEvent::HoldReleased),
+                Id = account_id,
+                Details = name.to_string(),
+                AccountId = access_token.primary_id(),
+                AccountName = access_token.name.clone(),
+            );
+
+            // Messages deleted during the hold are purged in the background,
+            // the cached token is dropped first so the purge sees the release
+            self.inner.data.access_tokens.remove(&account_id);
+            self.inner
+                .ipc
+                .housekeeper_tx
+                .send(HousekeeperEvent::Purge(PurgeType::Account(Some(
+                    account_id,
+                ))))
+                .await
+                .map_err(|err| {
+                    trc::EventType::Server(trc::ServerEvent::ThreadError)
+                        .reason(err)
+                        .details("Failed to send housekeeper event")
+                })?;
+        }
+
+        Ok(())
+    }
+
     fn assert_supported_directory(&self) -> trc::Result<()> {
         let class = match &self.core.storage.directory.store {
             DirectoryInner::Internal(_) => return Ok(()),
+            DirectoryInner::Sql(store) if store.is_writable() => return Ok(()),
             DirectoryInner::Ldap(_) => "LDAP",
             DirectoryInner::Sql(_) => "SQL",
             DirectoryInner::Imap(_) => "IMAP",
@@ -729,4 +1668,47 @@ impl PrincipalManager for Server {
             class
         )))
     }
+
+    fn assert_updatable_directory(&self) -> trc::Result<()> {
+        // LDAP directories with write support accept updates but not inserts
+        if self.ldap_directory().is_some() {
+            Ok(())
+        } else {
+            self.assert_supported_directory()
+        }
+    }
+
+    fn sql_directory(&self) -> Option<&SqlDirectory> {
+        match &self.core.storage.directory.store {
+            DirectoryInner::Sql(store) if store.is_writable() => Some(store),
+            _ => None,
+        }
+    }
+
+    fn ldap_directory(&self) -> Option<&LdapDirectory> {
+        match &self.core.storage.directory.store {
+            DirectoryInner::Ldap(store) if store.is_writable() => Some(store),
+            _ => None,
+        }
+    }
+
+    fn assert_writable_directory(&self) -> trc::Result<()> {
+        if !self.core.replication.standby {
+            Ok(())
+        } else {
+            Err(manage::err_code(
+                ErrorCode::DirectoryReadOnly,
+                "Directory is read-only",
+                "This server is a replication standby, changes must be made on the primary server"
+                    .into(),
+            ))
+        }
+    }
+}
+
+fn is_basic_auth(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|header| header.to_lowercase().starts_with("basic "))
 }
@@ -0,0 +1,112 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, str::FromStr};
+
+use common::{auth::AccessToken, Server};
+use directory::{backend::internal::manage, Permission};
+use hyper::Method;
+use serde::Deserialize;
+use serde_json::json;
+use trc::{
+    ipc::privacy::{PrivacyMode, SubscriberPrivacy},
+    Collector,
+};
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+#[derive(Deserialize)]
+struct PrivacyModeRequest {
+    mode: String,
+}
+
+pub trait ManagePrivacy: Sync + Send {
+    fn handle_manage_privacy(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManagePrivacy for Server {
+    async fn handle_manage_privacy(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PrivacyModeGet)?;
+
+                let items = Collector::get_privacy_modes()
+                    .iter()
+                    .map(privacy_to_json)
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(id), method @ (&Method::PUT | &Method::DELETE)) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::PrivacyModeUpdate)?;
+
+                let id = decode_path_element(id);
+                let mode = if *method == Method::PUT {
+                    let request = serde_json::from_slice::<PrivacyModeRequest>(
+                        body.as_deref().unwrap_or_default(),
+                    )
+                    .map_err(|err| {
+                        trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                    })?;
+                    Some(
+                        PrivacyMode::from_str(&request.mode)
+                            .map_err(|mode| manage::error("Invalid privacy mode.", Some(mode)))?,
+                    )
+                } else {
+                    None
+                };
+
+                let privacy = Collector::override_privacy(id.as_ref(), mode)
+                    .ok_or_else(|| manage::not_found(id.to_string()))?;
+
+                trc::event!(
+                    Telemetry(trc::TelemetryEvent::PrivacyModeChanged),
+                    Id = id.to_string(),
+                    Details = privacy.override_mode.unwrap_or(privacy.configured).as_str(),
+                    AccountId = access_token.primary_id(),
+                    AccountName = access_token.name.clone(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": privacy_to_json(&privacy),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+fn privacy_to_json(privacy: &SubscriberPrivacy) -> serde_json::Value {
+    json!({
+        "id": privacy.id,
+        "configured": privacy.configured.as_str(),
+        "effective": privacy.override_mode.unwrap_or(privacy.configured).as_str(),
+        "overridden": privacy.override_mode.is_some(),
+    })
+}
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::future::Future;
+use std::{future::Future, net::IpAddr};
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use common::{auth::AccessToken, ipc::QueueEvent, Server};
@@ -22,14 +22,17 @@ use mail_parser::DateTime;
 use serde::{Deserializer, Serializer};
 use serde_json::json;
 use smtp::{
-    queue::{self, spool::SmtpSpool, ErrorDetails, HostResponse, QueueId, Status},
+    queue::{
+        self, serialize::QueuedMessage, spool::SmtpSpool, ErrorDetails, HoldReason, HostResponse,
+        QueueId, Status,
+    },
     reporting::{dmarc::DmarcReporting, tls::TlsReporting},
 };
 use store::{
-    write::{key::DeserializeBigEndian, now, Bincode, QueueClass, ReportEvent, ValueClass},
+    write::{key::DeserializeBigEndian, now, QueueClass, ReportEvent, ValueClass},
     Deserialize, IterateParams, ValueKey,
 };
-use trc::AddContext;
+use trc::{AddContext, SecurityEvent};
 use utils::url_params::UrlParams;
 
 use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
@@ -51,6 +54,23 @@ pub struct Message {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env_id: Option<String>,
     pub blob_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub hold: Option<Hold>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct Hold {
+    pub tenant: String,
+    pub reason: String,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub since: DateTime,
+    #[serde(deserialize_with = "deserialize_maybe_datetime")]
+    #[serde(serialize_with = "serialize_maybe_datetime")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub until: Option<DateTime>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -69,6 +89,32 @@ pub struct Domain {
     #[serde(deserialize_with = "deserialize_datetime")]
     #[serde(serialize_with = "serialize_datetime")]
     pub expires: DateTime,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub retry_class: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub retry_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub source_ip: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub connect_attempts: Vec<ConnectAttempt>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
+pub struct ConnectAttempt {
+    #[serde(deserialize_with = "deserialize_datetime")]
+    #[serde(serialize_with = "serialize_datetime")]
+    pub time: DateTime,
+    pub family: String,
+    pub remote_ip: IpAddr,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub local_ip: Option<IpAddr>,
+    pub success: bool,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
@@ -183,6 +229,7 @@ impl QueueManagement for Server {
                 let page = params.parse::<usize>("page").unwrap_or_default();
                 let limit = params.parse::<usize>("limit").unwrap_or_default();
                 let values = params.has_key("values");
+                let hold = params.get("hold");
 
                 let range_start = params.parse::<u64>("range-start").unwrap_or_default();
                 let range_end = params.parse::<u64>("range-end").unwrap_or(u64::MAX);
@@ -206,12 +253,18 @@ impl QueueManagement for Server {
                     .iterate(
                         IterateParams::new(from_key, to_key).ascending(),
                         |key, value| {
-                            let message = Bincode::<queue::Message>::deserialize(value)
+                            let message = QueuedMessage::deserialize(value)
                                 .add_context(|ctx| ctx.ctx(trc::Key::Key, key))?
                                 .inner;
                             let matches = tenant_domains
                                 .as_ref()
                                 .map_or(true, |domains| message.has_domain(domains))
+                                && hold.is_none_or(|hold| {
+                                    message
+                                        .hold
+                                        .as_ref()
+                                        .is_some_and(|h| hold == "any" || h.reason.as_str() == hold)
+                                })
                                 && (!has_filters
                                     || (text
                                         .as_ref()
@@ -354,6 +407,72 @@ impl QueueManagement for Server {
                     Err(trc::ResourceEvent::NotFound.into_err())
                 }
             }
+            ("messages", Some(queue_id), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::MessageQueueReview)?;
+
+                let action = path.get(3).copied().unwrap_or_default();
+                if !matches!(action, "release" | "reject") {
+                    return Err(trc::ResourceEvent::NotFound.into_err());
+                }
+
+                if let Some(mut message) = self
+                    .read_message(queue_id.parse().unwrap_or_default())
+                    .await
+                    .filter(|message| {
+                        tenant_domains
+                            .as_ref()
+                            .is_none_or(|domains| message.has_domain(domains))
+                    })
+                {
+                    let tenant = message
+                        .hold
+                        .as_ref()
+                        .map(|hold| hold.tenant.clone())
+                        .unwrap_or_default();
+                    let queue_id = message.queue_id;
+                    let found = if action == "release" {
+                        message.release_hold()
+                    } else {
+                        message.reject_hold(
+                            params
+                                .get("reason")
+                                .unwrap_or("Message rejected by compliance review."),
+                        )
+                    };
+
+                    if found {
+                        // Held messages have no pending queue events
+                        let next_event = message.next_event().unwrap_or_else(now);
+                        message.save_changes(self, None, next_event.into()).await;
+                        let _ = self.inner.ipc.queue_tx.send(QueueEvent::Reload).await;
+
+                        if action == "release" {
+                            trc::event!(
+                                Security(SecurityEvent::MessageHoldReleased),
+                                QueueId = queue_id,
+                                Id = tenant,
+                                From = access_token.name.clone(),
+                            );
+                        } else {
+                            trc::event!(
+                                Security(SecurityEvent::MessageHoldRejected),
+                                QueueId = queue_id,
+                                Id = tenant,
+                                From = access_token.name.clone(),
+                                Reason = params.get("reason").unwrap_or_default().to_string(),
+                            );
+                        }
+                    }
+
+                    Ok(JsonResponse::new(json!({
+                            "data": found,
+                    }))
+                    .into_http_response())
+                } else {
+                    Err(trc::ResourceEvent::NotFound.into_err())
+                }
+            }
             ("messages", Some(queue_id), &Method::DELETE) => {
                 // Validate the access token
                 access_token.assert_has_permission(Permission::MessageQueueDelete)?;
@@ -666,9 +785,48 @@ impl From<&queue::Message> for Message {
                         })
                         .collect(),
                     expires: DateTime::from_timestamp(domain.expires as i64),
+                    retry_class: message
+                        .deferrals
+                        .iter()
+                        .find(|deferral| deferral.domain_idx == idx)
+                        .map(|deferral| deferral.class.as_str().to_string()),
+                    retry_reason: message
+                        .deferrals
+                        .iter()
+                        .find(|deferral| deferral.domain_idx == idx)
+                        .map(|deferral| deferral.reason.clone()),
+                    source_ip: message.source_ip(idx),
+                    connect_attempts: message
+                        .connect_attempts
+                        .iter()
+                        .filter(|attempt| attempt.domain_idx == idx)
+                        .map(|attempt| ConnectAttempt {
+                            time: DateTime::from_timestamp(attempt.time as i64),
+                            family: if attempt.remote_ip.is_ipv6() {
+                                "ipv6"
+                            } else {
+                                "ipv4"
+                            }
+                            .to_string(),
+                            remote_ip: attempt.remote_ip,
+                            local_ip: attempt.local_ip,
+                            success: attempt.success,
+                        })
+                        .collect(),
                 })
                 .collect(),
             blob_hash: URL_SAFE_NO_PAD.encode::<&[u8]>(message.blob_hash.as_ref()),
+            hold: message.hold.as_ref().map(|hold| Hold {
+                tenant: hold.tenant.clone(),
+                reason: hold.reason.as_str().to_string(),
+                since: DateTime::from_timestamp(hold.since as i64),
+                until: match hold.reason {
+                    HoldReason::SendingWindow { until } => {
+                        Some(DateTime::from_timestamp(until as i64))
+                    }
+                    HoldReason::Compliance | HoldReason::Moderation { .. } => None,
+                },
+            }),
         }
     }
 }
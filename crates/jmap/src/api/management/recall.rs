@@ -0,0 +1,707 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::{
+        manage::{self, err_not_found, ErrorCode, ManageDirectory, PrincipalFilter},
+        PrincipalField,
+    },
+    Permission, Type,
+};
+use hyper::Method;
+use jmap_proto::types::{
+    collection::Collection, id::Id, property::Property, state::StateChange, type_state::DataType,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::{
+    ahash::AHashMap,
+    query::Filter,
+    roaring::RoaringBitmap,
+    write::{
+        assert::HashedValue, key::DeserializeBigEndian, log::ChangeLogBuilder, now, BatchBuilder,
+        BlobOp, TaskClass, ValueClass, F_VALUE,
+    },
+    IterateParams, ValueKey, U32_LEN,
+};
+use trc::{AddContext, SecurityEvent};
+use utils::{BlobHash, BLOB_HASH_LEN};
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    changes::write::ChangeLog,
+    email::{delete::EmailDeletion, ingest::EmailIngest, set::TagManager},
+    mailbox::{get::MailboxGet, UidMailbox, TOMBSTONE_ID},
+    services::state::StateManager,
+    JmapMethods,
+};
+
+use super::decode_path_element;
+
+const RECALL_JOB: &[u8] = b"recall.job.";
+const PROGRESS_INTERVAL: usize = 10;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecallRequest {
+    #[serde(default)]
+    message_id: Option<String>,
+    #[serde(default)]
+    hash: Option<String>,
+    #[serde(default)]
+    scope: RecallScope,
+    action: RecallAction,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum RecallScope {
+    #[default]
+    All,
+    Tenant {
+        name: String,
+    },
+    Domain {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecallAction {
+    Preview,
+    Junk,
+    Delete,
+}
+
+/// A recall running in the background, progress is persisted as accounts
+/// are processed so it can be followed from any node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecallJob {
+    pub id: u64,
+    pub action: RecallAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    pub scope: RecallScope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<u32>,
+    pub performed_by: String,
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<u64>,
+    pub status: RecallStatus,
+    pub total_accounts: usize,
+    pub processed_accounts: usize,
+    pub total_messages: u64,
+    pub recalled_messages: u64,
+    pub results: Vec<RecallResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum RecallStatus {
+    Running,
+    Completed,
+    Failed { reason: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecallResult {
+    pub account: String,
+    pub matched: u64,
+    pub recalled: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct RecallTarget {
+    account_id: u32,
+    name: String,
+    document_ids: RoaringBitmap,
+}
+
+pub trait ManageRecall: Sync + Send {
+    fn handle_manage_recall(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageRecall for Server {
+    async fn handle_manage_recall(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        // Validate the access token
+        access_token.assert_has_permission(Permission::MessageRecall)?;
+
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        match (path.get(1), req.method()) {
+            (None, &Method::POST) => {
+                let request =
+                    serde_json::from_slice::<RecallRequest>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+                let message_id = request
+                    .message_id
+                    .as_deref()
+                    .map(|id| id.trim().trim_start_matches('<').trim_end_matches('>'))
+                    .filter(|id| !id.is_empty());
+                let hash = request
+                    .hash
+                    .as_deref()
+                    .map(|hash| {
+                        BlobHash::try_from_hex(hash.trim()).ok_or_else(|| {
+                            manage::error("Invalid message hash.", Some(hash.to_string()))
+                        })
+                    })
+                    .transpose()?;
+                if message_id.is_none() && hash.is_none() {
+                    return Err(manage::error(
+                        "Either a Message-ID or a message hash is required.",
+                        None::<u32>,
+                    ));
+                }
+
+                // Locate matching messages
+                let targets = self
+                    .recall_targets(message_id, hash.as_ref(), &request.scope, tenant_id)
+                    .await?;
+
+                if request.action == RecallAction::Preview {
+                    let items = targets
+                        .iter()
+                        .map(|target| {
+                            json!({
+                                "account": target.name,
+                                "matched": target.document_ids.len(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+
+                    return Ok(JsonResponse::new(json!({
+                        "data": {
+                            "items": items,
+                            "totalAccounts": targets.len(),
+                            "totalMessages": targets
+                                .iter()
+                                .map(|target| target.document_ids.len())
+                                .sum::<u64>(),
+                        },
+                    }))
+                    .into_http_response());
+                }
+
+                let created = now();
+                let job = RecallJob {
+                    id: self.inner.data.queue_id_gen.generate().unwrap_or(created),
+                    action: request.action,
+                    message_id: message_id.map(|id| id.to_string()),
+                    hash: hash.as_ref().map(|hash| hash.to_hex()),
+                    scope: request.scope,
+                    tenant_id,
+                    performed_by: access_token.name.clone(),
+                    created,
+                    finished: None,
+                    status: RecallStatus::Running,
+                    total_accounts: targets.len(),
+                    processed_accounts: 0,
+                    total_messages: targets.iter().map(|target| target.document_ids.len()).sum(),
+                    recalled_messages: 0,
+                    results: Vec::with_capacity(targets.len()),
+                };
+                self.write_recall_job(&job).await?;
+
+                // Accounts are processed in the background
+                let job_id = job.id;
+                let server = self.clone();
+                tokio::spawn(async move {
+                    server.run_recall_job(job, targets).await;
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": job_id,
+                }))
+                .into_http_response())
+            }
+            (None, &Method::GET) => {
+                let jobs = self
+                    .list_recall_jobs()
+                    .await?
+                    .into_iter()
+                    .filter(|job| tenant_id.is_none() || job.tenant_id == tenant_id)
+                    .collect::<Vec<_>>();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": jobs,
+                        "total": jobs.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::GET) => {
+                let id = decode_path_element(id);
+                let job = self
+                    .get_recall_job(
+                        id.parse::<u64>()
+                            .map_err(|_| manage::not_found(id.to_string()))?,
+                    )
+                    .await?
+                    .filter(|job| tenant_id.is_none() || job.tenant_id == tenant_id)
+                    .ok_or_else(|| manage::not_found(id.to_string()))?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": job,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait RecallStore: Sync + Send {
+    fn recall_targets(
+        &self,
+        message_id: Option<&str>,
+        hash: Option<&BlobHash>,
+        scope: &RecallScope,
+        tenant_id: Option<u32>,
+    ) -> impl Future<Output = trc::Result<Vec<RecallTarget>>> + Send;
+
+    fn run_recall_job(
+        &self,
+        job: RecallJob,
+        targets: Vec<RecallTarget>,
+    ) -> impl Future<Output = ()> + Send;
+
+    fn recall_messages(
+        &self,
+        account_id: u32,
+        document_ids: RoaringBitmap,
+        action: RecallAction,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+
+    fn list_recall_jobs(&self) -> impl Future<Output = trc::Result<Vec<RecallJob>>> + Send;
+
+    fn get_recall_job(
+        &self,
+        id: u64,
+    ) -> impl Future<Output = trc::Result<Option<RecallJob>>> + Send;
+
+    fn write_recall_job(&self, job: &RecallJob) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl RecallStore for Server {
+    async fn recall_targets(
+        &self,
+        message_id: Option<&str>,
+        hash: Option<&BlobHash>,
+        scope: &RecallScope,
+        mut tenant_id: Option<u32>,
+    ) -> trc::Result<Vec<RecallTarget>> {
+        // Resolve the accounts in scope, tenant administrators are limited to their own tenant
+        let mut domain = None;
+        match scope {
+            RecallScope::All => {}
+            RecallScope::Tenant { name } => {
+                tenant_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name)
+                    .await?
+                    .filter(|p| p.typ == Type::Tenant && p.has_tenant_access(tenant_id))
+                    .ok_or_else(|| err_not_found(ErrorCode::TenantNotFound, name.to_string()))?
+                    .id
+                    .into();
+            }
+            RecallScope::Domain { name } => {
+                let name = name.trim().to_lowercase();
+                self.core
+                    .storage
+                    .data
+                    .get_principal_info(&name)
+                    .await?
+                    .filter(|p| p.typ == Type::Domain && p.has_tenant_access(tenant_id))
+                    .ok_or_else(|| err_not_found(ErrorCode::DomainNotFound, name.to_string()))?;
+                domain = Some(format!("@{name}"));
+            }
+        }
+        let filter = domain
+            .as_ref()
+            .map(|domain| PrincipalFilter::default().with_field(PrincipalField::Emails, domain));
+        let accounts = self
+            .core
+            .storage
+            .data
+            .list_principals(
+                filter.as_ref(),
+                tenant_id,
+                &[Type::Individual, Type::Group],
+                if domain.is_some() {
+                    &[PrincipalField::Name, PrincipalField::Emails]
+                } else {
+                    &[PrincipalField::Name]
+                },
+                0,
+                0,
+            )
+            .await?
+            .items
+            .into_iter()
+            .filter(|principal| {
+                domain.as_ref().is_none_or(|domain| {
+                    principal
+                        .iter_str(PrincipalField::Emails)
+                        .any(|email| email.ends_with(domain.as_str()))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Copies of the same message share a blob, which is linked to every account holding one
+        let mut linked: Option<AHashMap<u32, RoaringBitmap>> = None;
+        if let Some(hash) = hash {
+            let from_key = ValueKey {
+                account_id: 0,
+                collection: 0,
+                document_id: 0,
+                class: ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+            };
+            let to_key = ValueKey {
+                account_id: u32::MAX,
+                collection: u8::MAX,
+                document_id: u32::MAX,
+                class: ValueClass::Blob(BlobOp::Link { hash: hash.clone() }),
+            };
+            let email_collection = u8::from(Collection::Email);
+            let mut documents: AHashMap<u32, RoaringBitmap> = AHashMap::new();
+            self.core
+                .storage
+                .data
+                .iterate(
+                    IterateParams::new(from_key, to_key).ascending().no_values(),
+                    |key, _| {
+                        // Committed blob markers share the key layout but use u32::MAX as document id
+                        let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                        if key.len() == BLOB_HASH_LEN + U32_LEN * 2 + 1
+                            && key[BLOB_HASH_LEN + U32_LEN] == email_collection
+                            && document_id != u32::MAX
+                        {
+                            documents
+                                .entry(key.deserialize_be_u32(BLOB_HASH_LEN)?)
+                                .or_default()
+                                .insert(document_id);
+                        }
+
+                        Ok(true)
+                    },
+                )
+                .await
+                .caused_by(trc::location!())?;
+            linked = Some(documents);
+        }
+
+        let mut targets = Vec::new();
+        for principal in accounts {
+            let account_id = principal.id();
+            let mut document_ids = match &linked {
+                Some(linked) => match linked.get(&account_id) {
+                    Some(document_ids) => document_ids.clone(),
+                    None => continue,
+                },
+                None => match self.get_document_ids(account_id, Collection::Email).await? {
+                    Some(document_ids) => document_ids,
+                    None => continue,
+                },
+            };
+            if let Some(message_id) = message_id {
+                document_ids &= self
+                    .filter(
+                        account_id,
+                        Collection::Email,
+                        vec![Filter::eq(Property::MessageId, message_id)],
+                    )
+                    .await?
+                    .results;
+            }
+
+            // Messages pending deletion are skipped
+            if let Some(tombstoned) = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    TOMBSTONE_ID,
+                )
+                .await?
+            {
+                document_ids -= tombstoned;
+            }
+
+            if !document_ids.is_empty() {
+                targets.push(RecallTarget {
+                    account_id,
+                    name: principal.name().to_string(),
+                    document_ids,
+                });
+            }
+        }
+
+        Ok(targets)
+    }
+
+    async fn run_recall_job(&self, mut job: RecallJob, targets: Vec<RecallTarget>) {
+        for target in targets {
+            let matched = target.document_ids.len();
+            let result = match self
+                .recall_messages(target.account_id, target.document_ids, job.action)
+                .await
+            {
+                Ok(recalled) => {
+                    job.recalled_messages += recalled;
+
+                    trc::event!(
+                        Security(SecurityEvent::MessageRecalled),
+                        Id = job.id,
+                        AccountId = target.account_id,
+                        AccountName = target.name.clone(),
+                        Details = format!("{:?}", job.action),
+                        Total = recalled,
+                        From = job.performed_by.clone(),
+                    );
+
+                    RecallResult {
+                        account: target.name,
+                        matched,
+                        recalled,
+                        error: None,
+                    }
+                }
+                Err(err) => {
+                    let reason = err.to_string();
+                    trc::error!(err
+                        .details("Failed to recall messages")
+                        .account_id(target.account_id)
+                        .id(job.id));
+
+                    RecallResult {
+                        account: target.name,
+                        matched,
+                        recalled: 0,
+                        error: Some(reason),
+                    }
+                }
+            };
+            job.results.push(result);
+            job.processed_accounts += 1;
+
+            // Persist progress periodically
+            if job.processed_accounts.is_multiple_of(PROGRESS_INTERVAL) {
+                if let Err(err) = self.write_recall_job(&job).await {
+                    trc::error!(err.details("Failed to update recall progress").id(job.id));
+                }
+            }
+        }
+
+        job.finished = Some(now());
+        job.status = if job.results.iter().all(|result| result.error.is_none()) {
+            RecallStatus::Completed
+        } else {
+            RecallStatus::Failed {
+                reason: "One or more accounts could not be processed.".to_string(),
+            }
+        };
+        if let Err(err) = self.write_recall_job(&job).await {
+            trc::error!(err.details("Failed to update recall progress").id(job.id));
+        }
+    }
+
+    async fn recall_messages(
+        &self,
+        account_id: u32,
+        document_ids: RoaringBitmap,
+        action: RecallAction,
+    ) -> trc::Result<u64> {
+        let (changes, recalled) = match action {
+            RecallAction::Delete => {
+                let num_messages = document_ids.len();
+                let (changes, not_found) = self.emails_tombstone(account_id, document_ids).await?;
+                (changes, num_messages - not_found.len())
+            }
+            RecallAction::Junk => {
+                let junk_id = self
+                    .mailbox_get_by_role(account_id, "junk")
+                    .await?
+                    .ok_or_else(|| {
+                        manage::error("Account does not have a junk folder.", None::<u32>)
+                    })?;
+                let mut changes = ChangeLogBuilder::new();
+                let mut recalled = 0;
+                for document_id in document_ids {
+                    let (Some(mailboxes), Some(thread_id)) = (
+                        self.get_property::<HashedValue<Vec<UidMailbox>>>(
+                            account_id,
+                            Collection::Email,
+                            document_id,
+                            Property::MailboxIds,
+                        )
+                        .await?,
+                        self.get_property::<u32>(
+                            account_id,
+                            Collection::Email,
+                            document_id,
+                            Property::ThreadId,
+                        )
+                        .await?,
+                    ) else {
+                        continue;
+                    };
+                    let mut mailboxes = TagManager::new(mailboxes);
+                    let junk_mailbox = mailboxes
+                        .current()
+                        .iter()
+                        .find(|mailbox| mailbox.mailbox_id == junk_id)
+                        .copied()
+                        .unwrap_or_else(|| UidMailbox::new_unassigned(junk_id));
+                    if mailboxes.current() == [junk_mailbox] {
+                        continue;
+                    }
+
+                    // Move the message to the junk folder only
+                    mailboxes.set(vec![junk_mailbox]);
+                    for uid_mailbox in mailboxes.inner_tags_mut() {
+                        if uid_mailbox.uid == 0 {
+                            uid_mailbox.uid = self.assign_imap_uid(account_id, junk_id).await?;
+                        }
+                    }
+                    for mailbox in mailboxes.changed_tags() {
+                        changes.log_child_update(Collection::Mailbox, mailbox.mailbox_id);
+                    }
+
+                    if changes.change_id == u64::MAX {
+                        changes.change_id = self.assign_change_id(account_id).await?;
+                    }
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .with_account_id(account_id)
+                        .with_collection(Collection::Email)
+                        .update_document(document_id);
+                    mailboxes.update_batch(&mut batch, Property::MailboxIds);
+                    batch.value(Property::Cid, changes.change_id, F_VALUE);
+                    match self.core.storage.data.write(batch.build()).await {
+                        Ok(_) => {
+                            changes.log_update(
+                                Collection::Email,
+                                Id::from_parts(thread_id, document_id),
+                            );
+                            recalled += 1;
+                        }
+                        Err(err) if err.is_assertion_failure() => {
+                            // The message was modified concurrently, it is skipped
+                            continue;
+                        }
+                        Err(err) => return Err(err.caused_by(trc::location!())),
+                    }
+                }
+
+                (changes, recalled)
+            }
+            RecallAction::Preview => return Ok(0),
+        };
+
+        if recalled > 0 {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            self.broadcast_state_change(
+                StateChange::new(account_id)
+                    .with_change(DataType::Email, change_id)
+                    .with_change(DataType::Mailbox, change_id)
+                    .with_change(DataType::Thread, change_id),
+            )
+            .await;
+        }
+
+        Ok(recalled)
+    }
+
+    async fn list_recall_jobs(&self) -> trc::Result<Vec<RecallJob>> {
+        let from_key =
+            ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(job_key(0))));
+        let to_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            job_key(u64::MAX),
+        )));
+
+        let mut jobs = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    if let Ok(job) = serde_json::from_slice::<RecallJob>(value) {
+                        jobs.push(job);
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(jobs)
+    }
+
+    async fn get_recall_job(&self, id: u64) -> trc::Result<Option<RecallJob>> {
+        self.core
+            .storage
+            .data
+            .get_value::<String>(ValueKey::from(ValueClass::Task(TaskClass::State(job_key(
+                id,
+            )))))
+            .await
+            .caused_by(trc::location!())
+            .map(|value| value.and_then(|value| serde_json::from_str(&value).ok()))
+    }
+
+    async fn write_recall_job(&self, job: &RecallJob) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(job_key(job.id))),
+            serde_json::to_vec(job).unwrap_or_default(),
+        );
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+fn job_key(id: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(RECALL_JOB.len() + std::mem::size_of::<u64>());
+    key.extend_from_slice(RECALL_JOB);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
@@ -0,0 +1,93 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc};
+
+use common::{auth::AccessToken, Server};
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    submission::recipients::{CollectedRecipientStore, CollectedRecipients},
+};
+
+use super::decode_path_element;
+
+const MAX_RESULTS: usize = 100;
+
+pub trait ManageCollectedRecipients: Sync + Send {
+    fn handle_collected_recipients_get(
+        &self,
+        req: &HttpRequest,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_collected_recipients_delete(
+        &self,
+        path: Vec<&str>,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageCollectedRecipients for Server {
+    async fn handle_collected_recipients_get(
+        &self,
+        req: &HttpRequest,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let params = UrlParams::new(req.uri().query());
+        let text = params.get("q").unwrap_or_default();
+        let limit = params
+            .parse::<usize>("limit")
+            .unwrap_or(10)
+            .clamp(1, MAX_RESULTS);
+
+        let recipients = self.collected_recipients(access_token.primary_id()).await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": recipients.query(text, limit),
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_collected_recipients_delete(
+        &self,
+        path: Vec<&str>,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let account_id = access_token.primary_id();
+        let mut recipients = self.collected_recipients(account_id).await?;
+
+        if let Some(address) = path.get(2) {
+            // Removed addresses are remembered so they are not collected again
+            recipients.remove(
+                decode_path_element(address).as_ref(),
+                self.core.jmap.collected_recipients_max,
+            );
+        } else {
+            recipients = CollectedRecipients {
+                recipients: vec![],
+                removed: recipients.removed,
+            };
+        }
+
+        self.write_collected_recipients(
+            account_id,
+            if !recipients.recipients.is_empty() || !recipients.removed.is_empty() {
+                Some(recipients)
+            } else {
+                None
+            },
+        )
+        .await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": (),
+        }))
+        .into_http_response())
+    }
+}
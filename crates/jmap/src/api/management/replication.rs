@@ -0,0 +1,333 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Instant};
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::{
+        manage::{self, field_updates, ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Permission, QueryBy, Type,
+};
+use hyper::Method;
+use serde_json::json;
+use store::{
+    ahash::AHashSet,
+    write::{BatchBuilder, TaskClass, ValueClass},
+    Serialize,
+};
+use trc::{AddContext, ClusterEvent};
+use utils::config::ConfigKey;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    services::replication::{state_key, ReplicationBatch, REPLICATION_APPLIED},
+    JmapMethods,
+};
+
+pub trait ManageReplication: Sync + Send {
+    fn handle_manage_replication(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageReplication for Server {
+    async fn handle_manage_replication(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (None, &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::ReplicationApply)?;
+
+                if !self.core.replication.standby {
+                    return Err(manage::unsupported(
+                        "This server is not configured as a replication standby",
+                    ));
+                }
+
+                let batch =
+                    serde_json::from_slice::<ReplicationBatch>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.apply_replication(batch, access_token).await?,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait ApplyReplication: Sync + Send {
+    fn apply_replication(
+        &self,
+        batch: ReplicationBatch,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+}
+
+impl ApplyReplication for Server {
+    async fn apply_replication(
+        &self,
+        batch: ReplicationBatch,
+        access_token: &AccessToken,
+    ) -> trc::Result<bool> {
+        let store = &self.core.storage.data;
+        let time = Instant::now();
+
+        // Last writer wins, batches older than the last applied one are discarded
+        if let Some(last_change_id) = store
+            .get_value::<u64>(state_key(REPLICATION_APPLIED))
+            .await
+            .caused_by(trc::location!())?
+            .filter(|last_change_id| !batch.full_sync && batch.change_id < *last_change_id)
+        {
+            trc::event!(
+                Cluster(ClusterEvent::ReplicationConflict),
+                ChangeId = batch.change_id,
+                Limit = last_change_id,
+                Details = "Discarded changes older than the last applied batch",
+            );
+
+            return Ok(false);
+        }
+
+        // Apply renames
+        for (old_name, new_name) in &batch.renames {
+            let Some(principal_id) = store
+                .get_principal_id(old_name)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                continue;
+            };
+            if let Some(existing_id) = store
+                .get_principal_id(new_name)
+                .await
+                .caused_by(trc::location!())?
+            {
+                trc::event!(
+                    Cluster(ClusterEvent::ReplicationConflict),
+                    Id = new_name.clone(),
+                    Details = "Local principal was replaced by a renamed principal",
+                );
+                store
+                    .delete_principal(QueryBy::Id(existing_id))
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+            store
+                .update_principal(UpdatePrincipal::by_id(principal_id).with_updates(vec![
+                    PrincipalUpdate::set(
+                        PrincipalField::Name,
+                        PrincipalValue::String(new_name.clone()),
+                    ),
+                ]))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        // Apply deletions
+        for name in &batch.deletions {
+            if store
+                .get_principal_id(name)
+                .await
+                .caused_by(trc::location!())?
+                .is_some()
+            {
+                store
+                    .delete_principal(QueryBy::Name(name))
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        // Create or update principals without their memberships, which are
+        // set once all principals exist
+        let mut principals = batch.principals;
+        principals.sort_by_key(|principal| type_order(principal.typ()));
+        let mut names = AHashSet::with_capacity(principals.len());
+        let mut memberships = Vec::with_capacity(principals.len());
+        for mut principal in principals {
+            let name = principal.name().to_lowercase();
+            let mut updates = Vec::with_capacity(4);
+            for field in [
+                PrincipalField::MemberOf,
+                PrincipalField::Lists,
+                PrincipalField::Roles,
+            ] {
+                updates.push(PrincipalUpdate::set(
+                    field,
+                    PrincipalValue::StringList(principal.take_str_array(field).unwrap_or_default()),
+                ));
+            }
+            let members = principal.take_str_array(PrincipalField::Members);
+            if matches!(principal.typ(), Type::Group | Type::List | Type::Role) {
+                updates.push(PrincipalUpdate::set(
+                    PrincipalField::Members,
+                    PrincipalValue::StringList(members.unwrap_or_default()),
+                ));
+            }
+            memberships.push((name.clone(), updates));
+
+            match store
+                .get_principal_info(&name)
+                .await
+                .caused_by(trc::location!())?
+            {
+                Some(pinfo) if pinfo.typ == principal.typ() => {
+                    store
+                        .update_principal(
+                            UpdatePrincipal::by_id(pinfo.id).with_updates(field_updates(principal)),
+                        )
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                Some(pinfo) => {
+                    trc::event!(
+                        Cluster(ClusterEvent::ReplicationConflict),
+                        Id = name.clone(),
+                        Details = "Local principal of a different type was replaced",
+                    );
+                    store
+                        .delete_principal(QueryBy::Id(pinfo.id))
+                        .await
+                        .caused_by(trc::location!())?;
+                    store
+                        .create_principal(principal, None, None)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                None => {
+                    store
+                        .create_principal(principal, None, None)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+
+            names.insert(name);
+        }
+        for (name, updates) in memberships {
+            store
+                .update_principal(UpdatePrincipal::by_name(&name).with_updates(updates))
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        // Remove principals that do not exist on the primary
+        if batch.full_sync {
+            let mut local_only = store
+                .list_principals(None, None, &[], &[PrincipalField::Name], 0, 0)
+                .await
+                .caused_by(trc::location!())?
+                .items
+                .into_iter()
+                .filter(|principal| {
+                    principal.id() != access_token.primary_id() && !names.contains(principal.name())
+                })
+                .collect::<Vec<_>>();
+            local_only.sort_by_key(|principal| std::cmp::Reverse(type_order(principal.typ())));
+
+            for principal in local_only {
+                trc::event!(
+                    Cluster(ClusterEvent::ReplicationConflict),
+                    Id = principal.name().to_string(),
+                    Details = "Local principal does not exist on the primary and was removed",
+                );
+                store
+                    .delete_principal(QueryBy::Id(principal.id()))
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+
+        // Replace configuration, settings that control replication are never
+        // overwritten
+        let has_config = !batch.config.is_empty();
+        for config in batch.config {
+            if config.prefix.is_empty() || is_replication_key(&config.prefix) {
+                continue;
+            }
+
+            self.core
+                .storage
+                .config
+                .clear_prefix(&config.prefix)
+                .await
+                .caused_by(trc::location!())?;
+            self.core
+                .storage
+                .config
+                .set(
+                    config
+                        .values
+                        .into_iter()
+                        .filter(|(key, _)| {
+                            key.starts_with(&config.prefix) && !is_replication_key(key)
+                        })
+                        .map(|(key, value)| ConfigKey { key, value }),
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+        if has_config {
+            if let Some(core) = self.reload().await?.new_core {
+                self.inner.shared_core.store(core.into());
+                self.increment_config_version();
+            }
+        }
+
+        // Store the last applied change
+        let mut write_batch = BatchBuilder::new();
+        write_batch.set(
+            ValueClass::Task(TaskClass::State(REPLICATION_APPLIED.to_vec())),
+            batch.change_id.serialize(),
+        );
+        store
+            .write(write_batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        trc::event!(
+            Cluster(ClusterEvent::ReplicationApplied),
+            ChangeId = batch.change_id,
+            Total = names.len() + batch.deletions.len(),
+            Elapsed = time.elapsed(),
+        );
+
+        Ok(true)
+    }
+}
+
+// Tenants and domains must exist before the principals that reference them
+fn type_order(typ: Type) -> u8 {
+    match typ {
+        Type::Tenant => 0,
+        Type::Domain => 1,
+        Type::Role => 2,
+        _ => 3,
+    }
+}
+
+fn is_replication_key(key: &str) -> bool {
+    key.starts_with("replication.") || "replication.".starts_with(key)
+}
@@ -0,0 +1,122 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::manage::{err_not_found, ErrorCode, ManageDirectory},
+    Permission,
+};
+use hyper::Method;
+use serde_json::json;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageReputation: Sync + Send {
+    fn handle_manage_reputation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageReputation for Server {
+    async fn handle_manage_reputation(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+
+        match (path.get(1), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SenderReputationGet)?;
+
+                let mut items = Vec::new();
+                for state in self.list_sender_reputation().await? {
+                    let Some(principal) = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal(state.account_id)
+                        .await?
+                        .filter(|p| {
+                            tenant_id.is_none_or(|tenant_id| p.tenant() == Some(tenant_id))
+                        })
+                    else {
+                        continue;
+                    };
+
+                    items.push(json!({
+                        "name": principal.name(),
+                        "level": state.level,
+                        "since": state.since,
+                        "signals": state.signals,
+                    }));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": items,
+                        "total": items.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(name), method @ (&Method::GET | &Method::DELETE)) => {
+                let name = decode_path_element(name);
+                let account_id = self
+                    .core
+                    .storage
+                    .data
+                    .get_principal_info(name.as_ref())
+                    .await?
+                    .filter(|p| p.has_tenant_access(tenant_id))
+                    .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?
+                    .id;
+
+                if *method == Method::GET {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::SenderReputationGet)?;
+
+                    let state = self.sender_reputation(account_id).await?;
+                    Ok(JsonResponse::new(json!({
+                        "data": {
+                            "level": state.as_ref().map(|s| s.level).unwrap_or_default(),
+                            "since": state.as_ref().map(|s| s.since),
+                            "signals": self.reputation_signals(account_id).await?,
+                        },
+                    }))
+                    .into_http_response())
+                } else {
+                    // Validate the access token
+                    access_token.assert_has_permission(Permission::SenderReputationClear)?;
+
+                    let was_restricted = self.clear_sender_reputation(account_id).await?;
+
+                    trc::event!(
+                        Security(trc::SecurityEvent::SenderReputationCleared),
+                        AccountId = account_id,
+                        AccountName = name.to_string(),
+                        From = access_token.name.clone(),
+                    );
+
+                    Ok(JsonResponse::new(json!({
+                        "data": was_restricted,
+                    }))
+                    .into_http_response())
+                }
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, config::smtp::session::RewriteRule, Server};
+use directory::{backend::internal::manage, Permission};
+use hyper::Method;
+use serde_json::json;
+use smtp::inbound::rewrite::{validate_rewrite_rule, AddressRewrite};
+use std::future::Future;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageRewrite: Sync + Send {
+    fn handle_manage_rewrite(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageRewrite for Server {
+    async fn handle_manage_rewrite(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), path.get(2).copied(), req.method()) {
+            (Some("rule"), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RewriteRuleGet)?;
+
+                let rules = self.rewrite_rules().await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": rules,
+                        "total": rules.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("rule"), Some(id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RewriteRuleGet)?;
+
+                let id = decode_path_element(id);
+                let rule = self
+                    .rewrite_rules()
+                    .await?
+                    .into_iter()
+                    .find(|rule| rule.id == id)
+                    .ok_or_else(|| manage::not_found(id.to_string()))?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": rule,
+                }))
+                .into_http_response())
+            }
+            (Some("rule"), Some(id), &Method::PUT) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RewriteRuleUpdate)?;
+
+                let mut rule =
+                    serde_json::from_slice::<RewriteRule>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err)
+                        })?;
+                rule.id = decode_path_element(id).into_owned();
+                validate_rewrite_rule(&rule).map_err(|err| manage::error(err, None::<u32>))?;
+                self.set_rewrite_rule(rule).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("rule"), Some(id), &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RewriteRuleDelete)?;
+
+                let id = decode_path_element(id);
+                if !self.delete_rewrite_rule(id.as_ref()).await? {
+                    return Err(manage::not_found(id.to_string()));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some("resolve"), Some(address), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RewriteRuleGet)?;
+
+                // Dry run, shows how an address would be rewritten
+                let resolution = self
+                    .resolve_rewrite(decode_path_element(address).as_ref())
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": resolution,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
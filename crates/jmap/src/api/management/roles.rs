@@ -0,0 +1,108 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::atomic::Ordering};
+
+use common::{auth::AccessToken, Server};
+use directory::{
+    backend::internal::manage::{ManageDirectory, RoleAction, RoleDocument},
+    Permission,
+};
+use hyper::Method;
+use serde_json::json;
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::principal::PrincipalManager;
+
+pub trait ManageRoles: Sync + Send {
+    fn handle_manage_roles(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageRoles for Server {
+    async fn handle_manage_roles(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+
+        match (path.get(1).copied(), req.method()) {
+            (Some("export"), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RoleList)?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.core.storage.data.export_roles(tenant_id).await?,
+                }))
+                .into_http_response())
+            }
+            (Some("apply"), &Method::POST) => {
+                let params = UrlParams::new(req.uri().query());
+                let dry_run = params.parse("dry-run").unwrap_or(false);
+                let prune = params.parse("prune").unwrap_or(false);
+
+                // Validate the access token
+                access_token.assert_has_permission(Permission::RoleCreate)?;
+                access_token.assert_has_permission(Permission::RoleUpdate)?;
+                if prune {
+                    access_token.assert_has_permission(Permission::RoleDelete)?;
+                }
+                if !dry_run {
+                    self.assert_writable_directory()?;
+                }
+
+                let document =
+                    serde_json::from_slice::<RoleDocument>(body.as_deref().unwrap_or_default())
+                        .map_err(|err| {
+                            trc::EventType::Resource(trc::ResourceEvent::BadParameters)
+                                .from_json_error(err)
+                        })?;
+
+                let changes = self
+                    .core
+                    .storage
+                    .data
+                    .apply_roles(
+                        document,
+                        tenant_id,
+                        Some(&access_token.permissions),
+                        prune,
+                        dry_run,
+                    )
+                    .await?;
+
+                if !dry_run
+                    && changes.iter().any(|change| {
+                        !matches!(change.action, RoleAction::Unchanged | RoleAction::Extra)
+                    })
+                {
+                    // Update permissions cache
+                    self.inner.data.permissions.clear();
+                    self.inner
+                        .data
+                        .permissions_version
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": changes,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{auth::AccessToken, config::sessions::SessionProtocol, Server};
+use directory::{
+    backend::internal::manage::{err_not_found, ErrorCode, ManageDirectory},
+    Permission,
+};
+use hyper::Method;
+use serde_json::json;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::decode_path_element;
+
+pub trait ManageSessions: Sync + Send {
+    fn handle_manage_sessions(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageSessions for Server {
+    async fn handle_manage_sessions(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let name = path
+            .get(1)
+            .map(|name| decode_path_element(name))
+            .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?;
+        let principal = self
+            .core
+            .storage
+            .data
+            .get_principal_info(name.as_ref())
+            .await?
+            .filter(|p| p.has_tenant_access(access_token.tenant.map(|t| t.id)))
+            .ok_or_else(|| err_not_found(ErrorCode::PrincipalNotFound, name.to_string()))?;
+
+        match *req.method() {
+            Method::GET => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SessionList)?;
+
+                let mut protocols = serde_json::Map::new();
+                for protocol in SessionProtocol::ALL {
+                    protocols.insert(
+                        protocol.as_str().to_string(),
+                        json!({
+                            "count": self.session_count(principal.id, protocol).await?,
+                            "limit": self
+                                .session_limit(name.as_ref(), principal.tenant, protocol)
+                                .await?,
+                        }),
+                    );
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "protocols": protocols,
+                        "local": self
+                            .local_sessions(principal.id)
+                            .into_iter()
+                            .map(|session| json!({
+                                "id": session.session_id.to_string(),
+                                "protocol": session.protocol,
+                                "remoteIp": session.remote_ip.to_string(),
+                                "created": session.created,
+                            }))
+                            .collect::<Vec<_>>(),
+                    },
+                }))
+                .into_http_response())
+            }
+            Method::DELETE => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::SessionDisconnect)?;
+
+                let disconnected = self.disconnect_sessions(principal.id).await?;
+
+                trc::event!(
+                    Security(trc::SecurityEvent::SessionsDisconnected),
+                    AccountId = principal.id,
+                    AccountName = name.to_string(),
+                    Total = disconnected,
+                    From = access_token.name.clone(),
+                );
+
+                Ok(JsonResponse::new(json!({
+                    "data": disconnected,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
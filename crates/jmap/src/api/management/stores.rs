@@ -7,13 +7,17 @@
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use common::{
     auth::AccessToken,
+    config::approval::ProtectedOperation,
     ipc::{HousekeeperEvent, PurgeType},
     manager::webadmin::Resource,
     Server,
 };
 use directory::{
-    backend::internal::manage::{self, ManageDirectory},
-    Permission,
+    backend::internal::{
+        maintenance::MaintenanceStore,
+        manage::{self, ManageDirectory},
+    },
+    Permission, Type,
 };
 use hyper::Method;
 use serde_json::json;
@@ -24,14 +28,16 @@ use crate::{
         http::{HttpSessionData, ToHttpResponse},
         HttpRequest, HttpResponse, JsonResponse,
     },
-    services::index::Indexer,
+    services::{index::Indexer, quota::QuotaRecalculation},
 };
 
-use super::decode_path_element;
 #[cfg(feature = "enterprise")]
 use super::enterprise::undelete::UndeleteApi;
+use super::{approval::ManageApproval, decode_path_element};
 use std::future::Future;
 
+const MAX_REPORTED_IDS: usize = 1000;
+
 pub trait ManageStore: Sync + Send {
     fn handle_manage_store(
         &self,
@@ -46,6 +52,12 @@ pub trait ManageStore: Sync + Send {
         &self,
         event: HousekeeperEvent,
     ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn purge_request(
+        &self,
+        operation: ProtectedOperation,
+        id: Option<&str>,
+    ) -> impl Future<Output = trc::Result<HousekeeperEvent>> + Send;
 }
 
 impl ManageStore for Server {
@@ -94,68 +106,34 @@ impl ManageStore for Server {
 
                 Ok(Resource::new("application/octet-stream", contents).into_http_response())
             }
-            (Some("purge"), Some("blob"), _, &Method::GET) => {
-                // Validate the access token
-                access_token.assert_has_permission(Permission::PurgeBlobStore)?;
-
-                self.housekeeper_request(HousekeeperEvent::Purge(PurgeType::Blobs {
-                    store: self.core.storage.data.clone(),
-                    blob_store: self.core.storage.blob.clone(),
-                }))
-                .await
-            }
-            (Some("purge"), Some("data"), id, &Method::GET) => {
-                // Validate the access token
-                access_token.assert_has_permission(Permission::PurgeDataStore)?;
-
-                let store = if let Some(id) = id {
-                    if let Some(store) = self.core.storage.stores.get(id) {
-                        store.clone()
-                    } else {
-                        return Err(trc::ResourceEvent::NotFound.into_err());
-                    }
-                } else {
-                    self.core.storage.data.clone()
+            (Some("purge"), Some(store), id, &Method::GET) => {
+                let operation = match store {
+                    "blob" => ProtectedOperation::PurgeBlob,
+                    "data" => ProtectedOperation::PurgeData,
+                    "lookup" => ProtectedOperation::PurgeLookup,
+                    "account" => ProtectedOperation::PurgeAccount,
+                    _ => return Err(trc::ResourceEvent::NotFound.into_err()),
                 };
 
-                self.housekeeper_request(HousekeeperEvent::Purge(PurgeType::Data(store)))
-                    .await
-            }
-            (Some("purge"), Some("lookup"), id, &Method::GET) => {
                 // Validate the access token
-                access_token.assert_has_permission(Permission::PurgeLookupStore)?;
+                access_token.assert_has_permission(match operation {
+                    ProtectedOperation::PurgeData => Permission::PurgeDataStore,
+                    ProtectedOperation::PurgeLookup => Permission::PurgeLookupStore,
+                    ProtectedOperation::PurgeAccount => Permission::PurgeAccount,
+                    _ => Permission::PurgeBlobStore,
+                })?;
 
-                let store = if let Some(id) = id {
-                    if let Some(store) = self.core.storage.lookups.get(id) {
-                        store.clone()
-                    } else {
-                        return Err(trc::ResourceEvent::NotFound.into_err());
-                    }
-                } else {
-                    self.core.storage.lookup.clone()
-                };
+                let id = id
+                    .filter(|_| operation != ProtectedOperation::PurgeBlob)
+                    .map(|id| decode_path_element(id).into_owned());
+                let event = self.purge_request(operation, id.as_deref()).await?;
 
-                self.housekeeper_request(HousekeeperEvent::Purge(PurgeType::Lookup(store)))
-                    .await
-            }
-            (Some("purge"), Some("account"), id, &Method::GET) => {
-                // Validate the access token
-                access_token.assert_has_permission(Permission::PurgeAccount)?;
-
-                let account_id = if let Some(id) = id {
-                    self.core
-                        .storage
-                        .data
-                        .get_principal_id(decode_path_element(id).as_ref())
-                        .await?
-                        .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?
-                        .into()
+                // Protected purges wait for a second administrator
+                if let Some(response) = self.request_approval(operation, id, access_token).await? {
+                    Ok(response)
                 } else {
-                    None
-                };
-
-                self.housekeeper_request(HousekeeperEvent::Purge(PurgeType::Account(account_id)))
-                    .await
+                    self.housekeeper_request(event).await
+                }
             }
             (Some("reindex"), id, None, &Method::GET) => {
                 // Validate the access token
@@ -186,6 +164,128 @@ impl ManageStore for Server {
                 }))
                 .into_http_response())
             }
+            (Some("fts-verify"), id, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::FtsVerify)?;
+                let repair =
+                    UrlParams::new(req.uri().query()).parse::<bool>("repair") == Some(true);
+                if repair {
+                    access_token.assert_has_permission(Permission::FtsReindex)?;
+                }
+                let tenant_id = access_token.tenant.map(|t| t.id);
+
+                if let Some(id) = id {
+                    let name = decode_path_element(id);
+                    let account_id = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal_info(name.as_ref())
+                        .await?
+                        .filter(|p| p.has_tenant_access(tenant_id))
+                        .ok_or_else(|| manage::not_found(name.to_string()))?
+                        .id;
+                    let report = self.verify_index(account_id, repair).await?;
+
+                    Ok(JsonResponse::new(json!({
+                        "data": {
+                            "accountId": report.account_id,
+                            "stored": report.stored,
+                            "indexed": report.indexed,
+                            "pending": report.pending,
+                            "missing": report.missing.len(),
+                            "orphaned": report.orphaned.len(),
+                            "missingIds": report
+                                .missing
+                                .iter()
+                                .take(MAX_REPORTED_IDS)
+                                .collect::<Vec<_>>(),
+                            "rebuilt": report.rebuilt,
+                            "failed": report.failed,
+                        },
+                    }))
+                    .into_http_response())
+                } else {
+                    // Verifying every account can take a long time, progress
+                    // and gaps are reported as they are found
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = server.verify_index_all(tenant_id, repair).await {
+                            trc::error!(err.details("Failed to verify FTS index"));
+                        }
+                    });
+
+                    Ok(JsonResponse::new(json!({
+                        "data": (),
+                    }))
+                    .into_http_response())
+                }
+            }
+            (Some("quota-recalculate"), id, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::QuotaRecalculate)?;
+                let dry_run =
+                    UrlParams::new(req.uri().query()).parse::<bool>("dry-run") == Some(true);
+                let tenant_id = access_token.tenant.map(|t| t.id);
+
+                if let Some(id) = id {
+                    let name = decode_path_element(id);
+                    let account_id = self
+                        .core
+                        .storage
+                        .data
+                        .get_principal_info(name.as_ref())
+                        .await?
+                        .filter(|p| {
+                            p.has_tenant_access(tenant_id)
+                                && matches!(p.typ, Type::Individual | Type::Group)
+                        })
+                        .ok_or_else(|| manage::not_found(name.to_string()))?
+                        .id;
+                    let delta = self.recalculate_quota(account_id, dry_run).await?;
+
+                    Ok(JsonResponse::new(json!({
+                        "data": delta,
+                    }))
+                    .into_http_response())
+                } else {
+                    // Large installations take a while to scan, mismatches
+                    // are logged as they are found
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = server.recalculate_quota_all(tenant_id, dry_run).await {
+                            trc::error!(err.details("Failed to recalculate quotas"));
+                        }
+                    });
+
+                    Ok(JsonResponse::new(json!({
+                        "data": (),
+                    }))
+                    .into_http_response())
+                }
+            }
+            (Some("directory-verify"), None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::DirectoryVerify)?;
+                let repair =
+                    UrlParams::new(req.uri().query()).parse::<bool>("repair") == Some(true);
+
+                let report = self.core.storage.data.verify_directory(repair).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "principals": report.principals,
+                        "total": report.issues.len(),
+                        "repaired": report.repaired,
+                        "issues": report
+                            .issues
+                            .iter()
+                            .take(MAX_REPORTED_IDS)
+                            .collect::<Vec<_>>(),
+                    },
+                }))
+                .into_http_response())
+            }
             // SPDX-SnippetBegin
             // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
             // SPDX-License-Identifier: LicenseRef-SEL
@@ -232,4 +332,50 @@ impl ManageStore for Server {
         }))
         .into_http_response())
     }
+
+    async fn purge_request(
+        &self,
+        operation: ProtectedOperation,
+        id: Option<&str>,
+    ) -> trc::Result<HousekeeperEvent> {
+        let purge = match operation {
+            ProtectedOperation::PurgeData => PurgeType::Data(if let Some(id) = id {
+                self.core
+                    .storage
+                    .stores
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?
+            } else {
+                self.core.storage.data.clone()
+            }),
+            ProtectedOperation::PurgeLookup => PurgeType::Lookup(if let Some(id) = id {
+                self.core
+                    .storage
+                    .lookups
+                    .get(id)
+                    .cloned()
+                    .ok_or_else(|| trc::ResourceEvent::NotFound.into_err())?
+            } else {
+                self.core.storage.lookup.clone()
+            }),
+            ProtectedOperation::PurgeAccount => PurgeType::Account(if let Some(id) = id {
+                self.core
+                    .storage
+                    .data
+                    .get_principal_id(id)
+                    .await?
+                    .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?
+                    .into()
+            } else {
+                None
+            }),
+            _ => PurgeType::Blobs {
+                store: self.core.storage.data.clone(),
+                blob_store: self.core.storage.blob.clone(),
+            },
+        };
+
+        Ok(HousekeeperEvent::Purge(purge))
+    }
 }
@@ -0,0 +1,119 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    auth::AccessToken,
+    config::scheduler::{ScheduledTask, TaskType},
+    Server,
+};
+use directory::{backend::internal::manage, Permission};
+use hyper::Method;
+use serde_json::json;
+use std::future::Future;
+use store::write::now;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse},
+    services::scheduler::TaskScheduler,
+};
+
+use super::decode_path_element;
+
+pub trait ManageTasks: Sync + Send {
+    fn handle_manage_task(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageTasks for Server {
+    async fn handle_manage_task(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TaskList)?;
+
+                let mut tasks = Vec::with_capacity(self.core.scheduler.tasks.len());
+                for task in TaskType::all() {
+                    if let Some(schedule) = self.core.scheduler.tasks.get(task) {
+                        tasks.push(self.task_status(schedule).await?);
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": tasks,
+                }))
+                .into_http_response())
+            }
+            (Some(task_id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TaskList)?;
+
+                let task_id = decode_path_element(task_id);
+                let schedule = TaskType::parse(task_id.as_ref())
+                    .and_then(|task| self.core.scheduler.tasks.get(&task))
+                    .ok_or_else(|| manage::not_found(task_id.to_string()))?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": self.task_status(schedule).await?,
+                }))
+                .into_http_response())
+            }
+            (Some(task_id), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TaskRun)?;
+
+                let task_id = decode_path_element(task_id);
+                let task = TaskType::parse(task_id.as_ref())
+                    .filter(|task| self.core.scheduler.tasks.contains_key(task))
+                    .ok_or_else(|| manage::not_found(task_id.to_string()))?;
+
+                if self.is_task_running(task).await? {
+                    return Err(manage::error("Task is already running.", None::<u32>));
+                }
+
+                let server = self.clone();
+                tokio::spawn(async move {
+                    server.run_task(task).await;
+                });
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait TaskStatus: Sync + Send {
+    fn task_status(
+        &self,
+        schedule: &ScheduledTask,
+    ) -> impl Future<Output = trc::Result<serde_json::Value>> + Send;
+}
+
+impl TaskStatus for Server {
+    async fn task_status(&self, schedule: &ScheduledTask) -> trc::Result<serde_json::Value> {
+        Ok(json!({
+            "id": schedule.task.as_str(),
+            "scheduled": schedule.cron.is_some() || schedule.interval.is_some(),
+            "nextRun": schedule
+                .time_to_next()
+                .map(|time_to_next| now() + time_to_next.as_secs()),
+            "running": self.is_task_running(schedule.task).await?,
+            "lastRun": self.get_task_run(schedule.task).await?,
+        }))
+    }
+}
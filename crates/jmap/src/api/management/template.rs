@@ -0,0 +1,203 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{
+    auth::AccessToken,
+    templates::{Template, TemplateKind, TemplateSource, TEMPLATE_GLOBAL},
+    Server,
+};
+use directory::{
+    backend::internal::manage::{self, ManageDirectory},
+    Permission, Type,
+};
+use hyper::Method;
+use serde_json::json;
+use std::future::Future;
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+pub trait ManageTemplates: Sync + Send {
+    fn handle_manage_template(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageTemplates for Server {
+    async fn handle_manage_template(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        body: Option<Vec<u8>>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let kind = path
+            .get(1)
+            .map(|kind| {
+                TemplateKind::parse(kind).ok_or_else(|| manage::not_found(kind.to_string()))
+            })
+            .transpose()?;
+
+        match (kind, path.get(2).copied(), req.method()) {
+            (None, None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TemplateGet)?;
+
+                let tenant_id = self.template_tenant(req, access_token).await?;
+                let mut templates = Vec::with_capacity(TemplateKind::ALL.len());
+                for kind in TemplateKind::ALL {
+                    templates.push(json!({
+                        "kind": kind.as_str(),
+                        "variables": variables(*kind),
+                        "custom": self.template_source(tenant_id, *kind).await?.is_some(),
+                    }));
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": templates,
+                }))
+                .into_http_response())
+            }
+            (Some(kind), None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TemplateGet)?;
+
+                let tenant_id = self.template_tenant(req, access_token).await?;
+                let (source, custom) = match self.template_source(tenant_id, kind).await? {
+                    Some(source) => (source, true),
+                    None => (kind.default_source(), false),
+                };
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "kind": kind.as_str(),
+                        "variables": variables(kind),
+                        "custom": custom,
+                        "template": source,
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(kind), None, &Method::PUT) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TemplateUpdate)?;
+
+                let tenant_id = self.template_tenant(req, access_token).await?;
+                self.set_template(tenant_id, kind, parse_source(body.as_deref())?)
+                    .await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(kind), None, &Method::DELETE) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TemplateDelete)?;
+
+                let tenant_id = self.template_tenant(req, access_token).await?;
+                self.delete_template(tenant_id, kind).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": (),
+                }))
+                .into_http_response())
+            }
+            (Some(kind), Some("preview"), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TemplateGet)?;
+
+                // Preview the submitted template or, if none was provided,
+                // the one that is currently in effect for the tenant
+                let template = match body.as_deref().filter(|body| !body.is_empty()) {
+                    Some(body) => Template::parse(kind, &parse_source(Some(body))?)?,
+                    None => {
+                        let tenant_id = self.template_tenant(req, access_token).await?;
+                        self.template(Some(tenant_id).filter(|id| *id != TEMPLATE_GLOBAL), kind)
+                            .await
+                    }
+                };
+
+                Ok(JsonResponse::new(json!({
+                    "data": template.preview(kind),
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
+
+trait TemplateTenant: Sync + Send {
+    fn template_tenant(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<u32>> + Send;
+}
+
+impl TemplateTenant for Server {
+    async fn template_tenant(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> trc::Result<u32> {
+        // Tenant administrators can only manage their own templates
+        if let Some(tenant) = &access_token.tenant {
+            return Ok(tenant.id);
+        }
+
+        // SPDX-SnippetBegin
+        // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+        // SPDX-License-Identifier: LicenseRef-SEL
+
+        if let Some(tenant_name) = UrlParams::new(req.uri().query()).get("tenant") {
+            #[cfg(not(feature = "enterprise"))]
+            let is_enterprise = false;
+            #[cfg(feature = "enterprise")]
+            let is_enterprise = self.core.is_enterprise_edition();
+
+            if !is_enterprise {
+                return Err(manage::enterprise());
+            }
+
+            return self
+                .core
+                .storage
+                .data
+                .get_principal_info(tenant_name)
+                .await?
+                .filter(|p| p.typ == Type::Tenant)
+                .map(|p| p.id)
+                .ok_or_else(|| {
+                    manage::err_not_found(
+                        manage::ErrorCode::TenantNotFound,
+                        tenant_name.to_string(),
+                    )
+                });
+        }
+
+        // SPDX-SnippetEnd
+
+        Ok(TEMPLATE_GLOBAL)
+    }
+}
+
+fn parse_source(body: Option<&[u8]>) -> trc::Result<TemplateSource> {
+    serde_json::from_slice::<TemplateSource>(body.unwrap_or_default())
+        .map_err(|err| trc::EventType::Resource(trc::ResourceEvent::BadParameters).reason(err))
+}
+
+fn variables(kind: TemplateKind) -> Vec<&'static str> {
+    kind.variables()
+        .iter()
+        .map(|variable| variable.as_str())
+        .collect()
+}
@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{cmp::Reverse, future::Future, sync::Arc};
+
+use common::{auth::AccessToken, Server};
+use jmap_proto::types::id::Id;
+use mail_parser::DateTime;
+use serde_json::json;
+
+use crate::{
+    api::{http::ToHttpResponse, HttpResponse, JsonResponse},
+    thread::rules::{ThreadRuleStore, ThreadRules},
+};
+
+use super::decode_path_element;
+
+pub trait ManageThreadRules: Sync + Send {
+    fn handle_thread_rules_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn handle_thread_rules_delete(
+        &self,
+        path: Vec<&str>,
+        access_token: Arc<AccessToken>,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageThreadRules for Server {
+    async fn handle_thread_rules_get(
+        &self,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let expiry = self.core.jmap.thread_rules_expiry.as_secs();
+        let mut rules = self.thread_rules(access_token.primary_id()).await?.rules;
+
+        // Most recently active threads are listed first
+        rules.sort_unstable_by_key(|rule| Reverse(rule.last_activity));
+
+        Ok(JsonResponse::new(json!({
+            "data": rules.into_iter().map(|rule| json!({
+                "threadId": Id::from(rule.thread_id).to_string(),
+                "type": rule.action.as_str(),
+                "mailboxId": rule.action.mailbox_id().map(|id| Id::from(id).to_string()),
+                "createdAt": DateTime::from_timestamp(rule.created_at as i64).to_rfc3339(),
+                "lastActivity": DateTime::from_timestamp(rule.last_activity as i64).to_rfc3339(),
+                "expiresAt": DateTime::from_timestamp(rule.expires(expiry) as i64).to_rfc3339(),
+            })).collect::<Vec<_>>(),
+        }))
+        .into_http_response())
+    }
+
+    async fn handle_thread_rules_delete(
+        &self,
+        path: Vec<&str>,
+        access_token: Arc<AccessToken>,
+    ) -> trc::Result<HttpResponse> {
+        let account_id = access_token.primary_id();
+
+        let rules = if let Some(thread_id) = path.get(2) {
+            let thread_id = Id::from_bytes(decode_path_element(thread_id).as_bytes())
+                .ok_or_else(|| trc::ResourceEvent::BadParameters.into_err())?
+                .document_id();
+            let mut rules = self.thread_rules(account_id).await?;
+            if !rules.remove(thread_id) {
+                return Err(trc::ResourceEvent::NotFound.into_err());
+            }
+            rules
+        } else {
+            ThreadRules::default()
+        };
+        self.write_thread_rules(account_id, rules).await?;
+
+        Ok(JsonResponse::new(json!({
+            "data": (),
+        }))
+        .into_http_response())
+    }
+}
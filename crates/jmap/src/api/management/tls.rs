@@ -0,0 +1,187 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{cmp::Reverse, future::Future};
+
+use common::{auth::AccessToken, Server};
+use directory::Permission;
+use hyper::Method;
+use mail_parser::DateTime;
+use serde_json::json;
+use store::{ahash::AHashMap, write::now};
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+pub trait ManageTls: Sync + Send {
+    fn handle_manage_tls(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+#[derive(Default)]
+struct DomainUsage<'x> {
+    hosts: usize,
+    sessions: u64,
+    versions: Vec<&'x str>,
+    last_seen: u64,
+}
+
+impl ManageTls for Server {
+    async fn handle_manage_tls(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        match (path.get(1).copied(), req.method()) {
+            (Some("outdated"), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TlsUsageReport)?;
+
+                let params = UrlParams::new(req.uri().query());
+                let since = now().saturating_sub(params.parse::<u64>("days").unwrap_or(7) * 86400);
+                let listener = params.get("listener");
+
+                let hosts = self
+                    .inner
+                    .data
+                    .tls_usage
+                    .entries(since)
+                    .into_iter()
+                    .filter(|entry| listener.is_none_or(|id| entry.listener_id == id))
+                    .collect::<Vec<_>>();
+
+                // Aggregate by remote domain
+                let mut domains: AHashMap<&str, DomainUsage> = AHashMap::new();
+                for entry in &hosts {
+                    if let Some(domain) = &entry.domain {
+                        let usage = domains.entry(domain.as_str()).or_default();
+                        usage.hosts += 1;
+                        usage.sessions += entry.sessions;
+                        usage.last_seen = usage.last_seen.max(entry.last_seen);
+                        if !usage.versions.contains(&entry.version.as_ref()) {
+                            usage.versions.push(entry.version.as_ref());
+                        }
+                    }
+                }
+                let mut domains = domains.into_iter().collect::<Vec<_>>();
+                domains.sort_unstable_by_key(|(_, usage)| Reverse(usage.sessions));
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "hosts": hosts
+                            .iter()
+                            .map(|entry| json!({
+                                "listener": entry.listener_id,
+                                "remoteIp": entry.remote_ip.to_string(),
+                                "domain": entry.domain,
+                                "version": entry.version,
+                                "cipher": entry.cipher,
+                                "sessions": entry.sessions,
+                                "firstSeen": DateTime::from_timestamp(entry.first_seen as i64)
+                                    .to_rfc3339(),
+                                "lastSeen": DateTime::from_timestamp(entry.last_seen as i64)
+                                    .to_rfc3339(),
+                            }))
+                            .collect::<Vec<_>>(),
+                        "domains": domains
+                            .iter()
+                            .map(|(domain, usage)| json!({
+                                "domain": domain,
+                                "hosts": usage.hosts,
+                                "sessions": usage.sessions,
+                                "versions": usage.versions,
+                                "lastSeen": DateTime::from_timestamp(usage.last_seen as i64)
+                                    .to_rfc3339(),
+                            }))
+                            .collect::<Vec<_>>(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some("certificates"), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::TlsCertificateStatus)?;
+
+                // Every TLS listener resolves certificates from the same set
+                let mut config = self
+                    .core
+                    .storage
+                    .config
+                    .build_config("server.listener")
+                    .await?;
+                let served = self.served_certificates();
+                let default = served
+                    .iter()
+                    .find(|cert| cert.is_default)
+                    .and_then(|cert| cert.info.as_ref());
+                let mut listeners = Vec::new();
+                for id in config
+                    .sub_keys("server.listener", ".protocol")
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                {
+                    if config
+                        .property_or_default::<bool>(
+                            ("server.listener", id.as_str(), "tls.enable"),
+                            "true",
+                        )
+                        .unwrap_or(true)
+                    {
+                        let implicit = config
+                            .property_or_default::<bool>(
+                                ("server.listener", id.as_str(), "tls.implicit"),
+                                "false",
+                            )
+                            .unwrap_or(false);
+                        listeners.push(json!({
+                            "id": id,
+                            "implicit": implicit,
+                            "fingerprint": default.map(|info| info.fingerprint.as_str()),
+                            "notAfter": default.map(|info| DateTime::from_timestamp(info.not_after).to_rfc3339()),
+                        }));
+                    }
+                }
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "listeners": listeners,
+                        "certificates": served
+                            .iter()
+                            .map(|cert| json!({
+                                "names": cert.names,
+                                "default": cert.is_default,
+                                "fingerprint": cert.info.as_ref().map(|info| info.fingerprint.as_str()),
+                                "notBefore": cert.info.as_ref().map(|info| DateTime::from_timestamp(info.not_before).to_rfc3339()),
+                                "notAfter": cert.info.as_ref().map(|info| DateTime::from_timestamp(info.not_after).to_rfc3339()),
+                            }))
+                            .collect::<Vec<_>>(),
+                        "watched": self
+                            .inner
+                            .data
+                            .tls_watch
+                            .entries()
+                            .into_iter()
+                            .map(|(id, cert)| json!({
+                                "id": id,
+                                "files": cert.files.iter().map(|(path, _)| path.to_string_lossy()).collect::<Vec<_>>(),
+                                "fingerprint": cert.info.as_ref().map(|info| info.fingerprint.as_str()),
+                                "reloadedAt": cert.reloaded_at.map(|t| DateTime::from_timestamp(t as i64).to_rfc3339()),
+                                "error": cert.error,
+                            }))
+                            .collect::<Vec<_>>(),
+                    },
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+}
@@ -0,0 +1,655 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::atomic::Ordering};
+
+use common::{auth::AccessToken, config::approval::ProtectedOperation, Server};
+use directory::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{self, err_not_found, ErrorCode, ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Permission, Principal, QueryBy, Type,
+};
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::{
+    write::{now, BatchBuilder, TaskClass, ValueClass},
+    IterateParams, ValueKey,
+};
+use trc::{AddContext, SecurityEvent};
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+use super::{notify::DirectoryNotifications, principal::PrincipalManager};
+
+const UNDO_ENTRY: &[u8] = b"undo.entry.";
+
+/// A directory operation recorded with enough state to revert it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoEntry {
+    pub id: u64,
+    pub operation: UndoOperation,
+    pub principal_id: u32,
+    pub principal_name: String,
+    pub principal_type: Type,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FieldChange>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Principal>,
+    pub performed_by: String,
+    pub created: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undo_of: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undone_by: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UndoOperation {
+    Create,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub field: PrincipalField,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<PrincipalValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<PrincipalValue>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoResult {
+    pub restored: Vec<String>,
+    pub not_restored: Vec<NotRestored>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotRestored {
+    pub item: String,
+    pub reason: String,
+}
+
+pub trait ManageUndo: Sync + Send {
+    fn handle_manage_undo(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+
+    fn undo_operation(
+        &self,
+        id: u64,
+        force: bool,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<UndoResult>> + Send;
+
+    fn principal_snapshot(
+        &self,
+        principal_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<Principal>>> + Send;
+
+    fn record_undo(
+        &self,
+        operation: UndoOperation,
+        before: Option<Principal>,
+        after: Option<Principal>,
+        fields: &[PrincipalField],
+        tenant_id: Option<u32>,
+        performed_by: &str,
+    ) -> impl Future<Output = Option<u64>> + Send;
+}
+
+impl ManageUndo for Server {
+    async fn handle_manage_undo(
+        &self,
+        req: &HttpRequest,
+        path: Vec<&str>,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        let id = path
+            .get(1)
+            .map(|id| {
+                id.parse::<u64>()
+                    .map_err(|_| manage::not_found(id.to_string()))
+            })
+            .transpose()?;
+
+        match (id, req.method()) {
+            (None, &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::UndoList)?;
+
+                let tenant_id = access_token.tenant.map(|t| t.id);
+                let mut entries = self
+                    .list_undo_entries()
+                    .await?
+                    .into_iter()
+                    .filter(|entry| tenant_id.is_none() || entry.tenant_id == tenant_id)
+                    .collect::<Vec<_>>();
+                entries.reverse();
+
+                Ok(JsonResponse::new(json!({
+                    "data": {
+                        "items": entries,
+                        "total": entries.len(),
+                    },
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::GET) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::UndoList)?;
+
+                let entry = self.get_undo_entry(id, access_token).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": entry,
+                }))
+                .into_http_response())
+            }
+            (Some(id), &Method::POST) => {
+                // Validate the access token
+                access_token.assert_has_permission(Permission::UndoApply)?;
+
+                let force = UrlParams::new(req.uri().query()).has_key("force");
+                let result = self.undo_operation(id, force, access_token).await?;
+
+                Ok(JsonResponse::new(json!({
+                    "data": result,
+                }))
+                .into_http_response())
+            }
+            _ => Err(trc::ResourceEvent::NotFound.into_err()),
+        }
+    }
+
+    async fn undo_operation(
+        &self,
+        id: u64,
+        force: bool,
+        access_token: &AccessToken,
+    ) -> trc::Result<UndoResult> {
+        self.assert_writable_directory()?;
+
+        let entries = self.list_undo_entries().await?;
+        let mut entry = self.get_undo_entry(id, access_token).await?;
+        if let Some(undone_by) = entry.undone_by {
+            return Err(manage::error(
+                "Operation was already undone",
+                Some(format!("Reverted by entry {undone_by}")),
+            ));
+        }
+
+        // Later operations on the same principal
+        let later = entries
+            .iter()
+            .filter(|later| later.id > entry.id && later.principal_id == entry.principal_id)
+            .collect::<Vec<_>>();
+        let mut result = UndoResult::default();
+
+        let undo_id = match entry.operation {
+            UndoOperation::Delete => {
+                // Deleted principals are purged together with their data
+                result.not_restored.push(NotRestored {
+                    item: entry.principal_name.clone(),
+                    reason: concat!(
+                        "The principal and its data were permanently removed ",
+                        "when it was deleted and cannot be restored."
+                    )
+                    .to_string(),
+                });
+                return Ok(result);
+            }
+            UndoOperation::Create => {
+                if let Some(later) = later.first().filter(|_| !force) {
+                    return Err(manage::error(
+                        "Principal was modified after it was created",
+                        Some(format!(
+                            "Entry {} changed {:?}, use force to delete it anyway",
+                            later.id, entry.principal_name
+                        )),
+                    ));
+                }
+
+                // Undoing a creation is subject to the same approval rules as a deletion
+                if ProtectedOperation::delete(entry.principal_type)
+                    .is_some_and(|operation| self.core.approval.is_protected(operation))
+                {
+                    return Err(manage::error(
+                        "Deletion requires approval",
+                        Some("Delete the principal through the approval workflow instead"),
+                    ));
+                }
+
+                let before = self
+                    .principal_snapshot(entry.principal_id)
+                    .await?
+                    .ok_or_else(|| {
+                        err_not_found(ErrorCode::PrincipalNotFound, entry.principal_name.clone())
+                    })?;
+                self.delete_principal_by_id(
+                    entry.principal_id,
+                    entry.principal_type,
+                    entry.tenant_id,
+                    false,
+                )
+                .await?;
+                result.restored.push(entry.principal_name.clone());
+
+                self.record_undo(
+                    UndoOperation::Delete,
+                    Some(before),
+                    None,
+                    &[],
+                    entry.tenant_id,
+                    &access_token.name,
+                )
+                .await
+            }
+            UndoOperation::Update => {
+                let mut current = self
+                    .principal_snapshot(entry.principal_id)
+                    .await?
+                    .ok_or_else(|| {
+                        err_not_found(ErrorCode::PrincipalNotFound, entry.principal_name.clone())
+                    })?;
+
+                // Fields changed by later operations or outside the management API
+                // cannot be reverted without losing those changes
+                let mut conflicts = Vec::new();
+                let mut probe = current.clone();
+                for change in &entry.changes {
+                    if let Some(later) = later.iter().find(|later| {
+                        later.changes.iter().any(|c| c.field == change.field)
+                            || later.operation != UndoOperation::Update
+                    }) {
+                        conflicts.push(format!(
+                            "{} was changed by entry {}",
+                            change.field.as_str(),
+                            later.id
+                        ));
+                    } else if change.field != PrincipalField::Secrets
+                        && normalize(probe.take(change.field)) != normalize(change.after.clone())
+                    {
+                        conflicts.push(format!("{} was changed since", change.field.as_str()));
+                    }
+                }
+                if !conflicts.is_empty() && !force {
+                    return Err(manage::error(
+                        "Intervening changes touched the same fields, use force to overwrite them",
+                        Some(conflicts.join(", ")),
+                    ));
+                }
+
+                // Build the inverse updates
+                let mut updates = Vec::with_capacity(entry.changes.len());
+                let mut fields = Vec::with_capacity(entry.changes.len());
+                for change in &entry.changes {
+                    match inverse_update(change) {
+                        Ok(update) => {
+                            result.restored.push(change.field.as_str().to_string());
+                            fields.push(change.field);
+                            updates.push(update);
+                        }
+                        Err(reason) => {
+                            result.not_restored.push(NotRestored {
+                                item: change.field.as_str().to_string(),
+                                reason: reason.to_string(),
+                            });
+                        }
+                    }
+                }
+                if updates.is_empty() {
+                    return Ok(result);
+                }
+
+                let before = current.clone();
+                self.core
+                    .storage
+                    .data
+                    .update_principal(
+                        UpdatePrincipal::by_id(entry.principal_id)
+                            .with_updates(updates)
+                            .with_tenant(access_token.tenant.map(|t| t.id))
+                            .with_allowed_permissions(&access_token.permissions),
+                    )
+                    .await?;
+
+                // Expire cached credentials and permissions
+                self.invalidate_principal(entry.principal_id).await?;
+                if matches!(entry.principal_type, Type::Role | Type::Tenant) {
+                    self.inner.data.permissions.clear();
+                    self.inner
+                        .data
+                        .permissions_version
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+
+                current = self
+                    .principal_snapshot(entry.principal_id)
+                    .await?
+                    .unwrap_or(current);
+                self.record_undo(
+                    UndoOperation::Update,
+                    Some(before),
+                    Some(current),
+                    &fields,
+                    entry.tenant_id,
+                    &access_token.name,
+                )
+                .await
+            }
+        };
+
+        // Link both entries so the same operation is not reverted twice
+        if let Some(undo_id) = undo_id {
+            entry.undone_by = Some(undo_id);
+            self.write_undo_entry(&entry).await?;
+            if let Some(mut undo_entry) = self
+                .list_undo_entries()
+                .await?
+                .into_iter()
+                .find(|e| e.id == undo_id)
+            {
+                undo_entry.undo_of = Some(entry.id);
+                self.write_undo_entry(&undo_entry).await?;
+            }
+        }
+
+        trc::event!(
+            Security(SecurityEvent::OperationUndone),
+            Id = entry.id,
+            AccountId = entry.principal_id,
+            AccountName = entry.principal_name,
+            From = access_token.name.clone(),
+            Details = result.restored.clone(),
+        );
+
+        Ok(result)
+    }
+
+    async fn principal_snapshot(&self, principal_id: u32) -> trc::Result<Option<Principal>> {
+        let store = &self.core.storage.data;
+        if let Some(mut principal) = store
+            .query(QueryBy::Id(principal_id), true)
+            .await
+            .caused_by(trc::location!())?
+        {
+            store
+                .map_field_ids(&mut principal, &[])
+                .await
+                .caused_by(trc::location!())?;
+
+            // Credentials are never recorded
+            principal.remove(PrincipalField::Secrets);
+            principal.remove(PrincipalField::CredentialsEpoch);
+            principal.remove(PrincipalField::UsedQuota);
+            principal.remove(PrincipalField::LastLogin);
+
+            Ok(Some(principal))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn record_undo(
+        &self,
+        operation: UndoOperation,
+        mut before: Option<Principal>,
+        mut after: Option<Principal>,
+        fields: &[PrincipalField],
+        tenant_id: Option<u32>,
+        performed_by: &str,
+    ) -> Option<u64> {
+        self.notify_directory_change(
+            operation,
+            before.as_ref(),
+            after.as_ref(),
+            fields,
+            performed_by,
+        );
+
+        let principal = after.as_ref().or(before.as_ref())?;
+        let created = now();
+        let mut entry = UndoEntry {
+            id: self.inner.data.queue_id_gen.generate().unwrap_or(created),
+            operation,
+            principal_id: principal.id(),
+            principal_name: principal.name().to_string(),
+            principal_type: principal.typ(),
+            tenant_id,
+            changes: Vec::new(),
+            snapshot: None,
+            performed_by: performed_by.to_string(),
+            created,
+            undo_of: None,
+            undone_by: None,
+        };
+
+        match operation {
+            UndoOperation::Create => {
+                entry.snapshot = after;
+            }
+            UndoOperation::Delete => {
+                entry.snapshot = before;
+            }
+            UndoOperation::Update => {
+                for &field in fields {
+                    if entry.changes.iter().any(|c| c.field == field) {
+                        continue;
+                    }
+                    entry.changes.push(FieldChange {
+                        field,
+                        before: before.as_mut().and_then(|p| p.take(field)),
+                        after: after.as_mut().and_then(|p| p.take(field)),
+                    });
+                }
+            }
+        }
+
+        // Failing to record an operation does not fail the operation itself
+        match self.write_undo_entry(&entry).await {
+            Ok(_) => {
+                if let Err(err) = self.prune_undo_entries().await {
+                    trc::error!(err.details("Failed to prune undo log"));
+                }
+                Some(entry.id)
+            }
+            Err(err) => {
+                trc::error!(err.details("Failed to record undo log entry"));
+                None
+            }
+        }
+    }
+}
+
+trait UndoStore: Sync + Send {
+    fn list_undo_entries(&self) -> impl Future<Output = trc::Result<Vec<UndoEntry>>> + Send;
+
+    fn get_undo_entry(
+        &self,
+        id: u64,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<UndoEntry>> + Send;
+
+    fn write_undo_entry(&self, entry: &UndoEntry) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn prune_undo_entries(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl UndoStore for Server {
+    async fn list_undo_entries(&self) -> trc::Result<Vec<UndoEntry>> {
+        let from_key =
+            ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(entry_key(0))));
+        let to_key = ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            entry_key(u64::MAX),
+        )));
+
+        let mut entries = Vec::new();
+        self.core
+            .storage
+            .data
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |_, value| {
+                    if let Ok(entry) = serde_json::from_slice::<UndoEntry>(value) {
+                        entries.push(entry);
+                    }
+
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(entries)
+    }
+
+    async fn get_undo_entry(&self, id: u64, access_token: &AccessToken) -> trc::Result<UndoEntry> {
+        let tenant_id = access_token.tenant.map(|t| t.id);
+        self.list_undo_entries()
+            .await?
+            .into_iter()
+            .find(|entry| entry.id == id && (tenant_id.is_none() || entry.tenant_id == tenant_id))
+            .ok_or_else(|| manage::not_found(id.to_string()))
+    }
+
+    async fn write_undo_entry(&self, entry: &UndoEntry) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::State(entry_key(entry.id))),
+            serde_json::to_vec(entry).unwrap_or_default(),
+        );
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn prune_undo_entries(&self) -> trc::Result<()> {
+        let entries = self.list_undo_entries().await?;
+        let max_entries = self.core.jmap.undo_max_entries;
+        if entries.len() <= max_entries {
+            return Ok(());
+        }
+
+        // Only the most recent operations are kept
+        let mut batch = BatchBuilder::new();
+        for entry in &entries[..entries.len() - max_entries] {
+            batch.clear(ValueClass::Task(TaskClass::State(entry_key(entry.id))));
+        }
+        self.core
+            .storage
+            .data
+            .write(batch.build())
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+fn inverse_update(change: &FieldChange) -> Result<PrincipalUpdate, &'static str> {
+    let value = change.before.clone();
+    match change.field {
+        PrincipalField::Name => match value {
+            Some(PrincipalValue::String(name)) => Ok(PrincipalUpdate::set(
+                PrincipalField::Name,
+                PrincipalValue::String(name),
+            )),
+            _ => Err("The previous name was not recorded"),
+        },
+        PrincipalField::Description
+        | PrincipalField::Picture
+        | PrincipalField::Tenant
+        | PrincipalField::ExternalId
+        | PrincipalField::CatchAll
+        | PrincipalField::Subaddressing
+        | PrincipalField::PostingPolicy
+        | PrincipalField::Locale
+        | PrincipalField::Timezone => Ok(PrincipalUpdate::set(
+            change.field,
+            PrincipalValue::String(
+                value
+                    .and_then(|v| v.as_str().map(|v| v.to_string()))
+                    .unwrap_or_default(),
+            ),
+        )),
+        PrincipalField::Quota => Ok(PrincipalUpdate::set(
+            PrincipalField::Quota,
+            match value {
+                Some(value @ (PrincipalValue::Integer(_) | PrincipalValue::IntegerList(_))) => {
+                    value
+                }
+                _ => PrincipalValue::String(String::new()),
+            },
+        )),
+        PrincipalField::Hold => Err("Litigation holds cannot be restored"),
+        PrincipalField::Enabled => Ok(PrincipalUpdate::set(
+            PrincipalField::Enabled,
+            PrincipalValue::Integer(value.and_then(|v| v.as_int()).unwrap_or(1)),
+        )),
+        PrincipalField::ReplyToList => Ok(PrincipalUpdate::set(
+            PrincipalField::ReplyToList,
+            PrincipalValue::Integer(value.and_then(|v| v.as_int()).unwrap_or_default()),
+        )),
+        PrincipalField::Members if matches!(value, Some(PrincipalValue::Integer(_))) => {
+            Err("Member counts cannot be restored")
+        }
+        PrincipalField::Emails
+        | PrincipalField::MemberOf
+        | PrincipalField::Members
+        | PrincipalField::Lists
+        | PrincipalField::Roles
+        | PrincipalField::EnabledPermissions
+        | PrincipalField::DisabledPermissions
+        | PrincipalField::Urls
+        | PrincipalField::ExternalMembers
+        | PrincipalField::ForwardTo
+        | PrincipalField::DefaultRoles
+        | PrincipalField::DefaultPermissions
+        | PrincipalField::CertificateFingerprints
+        | PrincipalField::Moderators => Ok(PrincipalUpdate::set(
+            change.field,
+            PrincipalValue::StringList(value.map(|v| v.into_str_array()).unwrap_or_default()),
+        )),
+        PrincipalField::Secrets => Err("Credentials are not recorded in the undo log"),
+        PrincipalField::Type
+        | PrincipalField::UsedQuota
+        | PrincipalField::LastLogin
+        | PrincipalField::CredentialsEpoch => Err("Field cannot be modified"),
+    }
+}
+
+fn normalize(value: Option<PrincipalValue>) -> Vec<String> {
+    let mut values = value.map(|v| v.into_str_array()).unwrap_or_default();
+    values.retain(|v| !v.is_empty());
+    values.sort_unstable();
+    values
+}
+
+fn entry_key(id: u64) -> Vec<u8> {
+    let mut key = Vec::with_capacity(UNDO_ENTRY.len() + std::mem::size_of::<u64>());
+    key.extend_from_slice(UNDO_ENTRY);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
@@ -0,0 +1,165 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Write, future::Future};
+
+use chrono::NaiveDate;
+use common::{
+    auth::AccessToken,
+    telemetry::volume::{VolumePeriod, VolumeRecord},
+    Server,
+};
+use directory::{
+    backend::internal::manage::{self, err_not_found, ErrorCode, ManageDirectory},
+    Permission, Type,
+};
+use hyper::{Method, StatusCode};
+use serde_json::json;
+use store::write::now;
+use utils::url_params::UrlParams;
+
+use crate::api::{http::ToHttpResponse, HttpRequest, HttpResponse, JsonResponse};
+
+const MAX_RANGE_DAYS: u32 = 366 * 2;
+
+pub trait ManageVolume: Sync + Send {
+    fn handle_manage_volume(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<HttpResponse>> + Send;
+}
+
+impl ManageVolume for Server {
+    async fn handle_manage_volume(
+        &self,
+        req: &HttpRequest,
+        access_token: &AccessToken,
+    ) -> trc::Result<HttpResponse> {
+        if *req.method() != Method::GET {
+            return Err(trc::ResourceEvent::NotFound.into_err());
+        }
+
+        // Validate the access token
+        access_token.assert_has_permission(Permission::VolumeStatsGet)?;
+
+        let params = UrlParams::new(req.uri().query());
+        let today = (now() / 86400) as u32;
+        let to_day = params
+            .get("to")
+            .map(parse_day)
+            .transpose()?
+            .unwrap_or(today);
+        let from_day = params
+            .get("from")
+            .map(parse_day)
+            .transpose()?
+            .unwrap_or_else(|| to_day.saturating_sub(30));
+        if from_day > to_day || to_day - from_day > MAX_RANGE_DAYS {
+            return Err(manage::error(
+                "Invalid date range.",
+                Some(format!("Ranges are limited to {MAX_RANGE_DAYS} days.")),
+            ));
+        }
+        let domain = params.get("domain").map(|domain| domain.to_lowercase());
+
+        // Tenant administrators only see their own domains
+        let mut tenant_id = access_token.tenant.map(|t| t.id);
+        if let Some(tenant) = params.get("tenant") {
+            let id = self
+                .core
+                .storage
+                .data
+                .get_principal_info(tenant)
+                .await?
+                .filter(|p| p.typ == Type::Tenant && p.has_tenant_access(tenant_id))
+                .ok_or_else(|| err_not_found(ErrorCode::TenantNotFound, tenant.to_string()))?
+                .id;
+            tenant_id = Some(id);
+        }
+
+        // Include the counters accumulated on this node since the last flush
+        self.flush_volume_stats().await?;
+
+        let records = self
+            .query_volume_stats(from_day, to_day)
+            .await?
+            .into_iter()
+            .filter(|record| {
+                tenant_id.is_none_or(|id| record.tenant_id == Some(id))
+                    && domain.as_ref().is_none_or(|d| &record.domain == d)
+            })
+            .collect::<Vec<_>>();
+
+        if params.get("format") == Some("csv") {
+            Ok(HttpResponse::new_text(
+                StatusCode::OK,
+                "text/csv; charset=utf-8",
+                to_csv(&records),
+            ))
+        } else {
+            Ok(JsonResponse::new(json!({
+                "data": {
+                    "items": records
+                        .iter()
+                        .map(|record| {
+                            let mut item = serde_json::to_value(record).unwrap_or_default();
+                            item["date"] = record.date().into();
+                            item
+                        })
+                        .collect::<Vec<_>>(),
+                    "total": records.len(),
+                },
+            }))
+            .into_http_response())
+        }
+    }
+}
+
+fn parse_day(value: &str) -> trc::Result<u32> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| {
+            u32::try_from(
+                (date - NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default()).num_days(),
+            )
+            .ok()
+        })
+        .ok_or_else(|| {
+            manage::error(
+                "Invalid date, expected YYYY-MM-DD.",
+                Some(value.to_string()),
+            )
+        })
+}
+
+fn to_csv(records: &[VolumeRecord]) -> String {
+    let mut csv = String::from(
+        "period,date,domain,tenant_id,received,sent,bytes_received,bytes_sent,rejected\n",
+    );
+    for record in records {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{}",
+            match record.period {
+                VolumePeriod::Day => "day",
+                VolumePeriod::Month => "month",
+            },
+            record.date(),
+            record.domain,
+            record
+                .tenant_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            record.counters.received,
+            record.counters.sent,
+            record.counters.bytes_received,
+            record.counters.bytes_sent,
+            record.counters.rejected,
+        );
+    }
+    csv
+}
@@ -36,7 +36,10 @@ use crate::{
         validate::SieveScriptValidate,
     },
     submission::{get::EmailSubmissionGet, query::EmailSubmissionQuery, set::EmailSubmissionSet},
-    thread::get::ThreadGet,
+    thread::{
+        get::ThreadGet,
+        rules::{get::ThreadRuleGet, set::ThreadRuleSet},
+    },
     vacation::{get::VacationResponseGet, set::VacationResponseSet},
 };
 
@@ -228,6 +231,11 @@ impl RequestHandler for Server {
 
                     self.quota_get(req, access_token).await?.into()
                 }
+                get::RequestArguments::ThreadRule => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.thread_rule_get(req).await?.into()
+                }
                 get::RequestArguments::Blob(arguments) => {
                     access_token.assert_is_member(req.account_id)?;
 
@@ -314,6 +322,11 @@ impl RequestHandler for Server {
 
                     self.vacation_response_set(req, access_token).await?.into()
                 }
+                set::RequestArguments::ThreadRule => {
+                    access_token.assert_is_member(req.account_id)?;
+
+                    self.thread_rule_set(req).await?.into()
+                }
             },
             RequestMethod::Changes(req) => self.changes(req, access_token).await?.into(),
             RequestMethod::Copy(req) => {
@@ -6,7 +6,12 @@
 
 use std::sync::Arc;
 
-use common::{auth::AuthRequest, listener::limiter::InFlight, Server};
+use common::{
+    auth::{api_key::is_api_key, AuthRequest},
+    listener::limiter::InFlight,
+    Server,
+};
+use directory::backend::internal::AppScope;
 use hyper::header;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
@@ -36,67 +41,85 @@ impl Authenticator for Server {
         allow_api_access: bool,
     ) -> trc::Result<(InFlight, Arc<AccessToken>)> {
         if let Some((mechanism, token)) = req.authorization() {
-            let access_token =
-                if let Some(account_id) = self.inner.data.http_auth_cache.get_with_ttl(token) {
-                    self.get_cached_access_token(account_id).await?
-                } else {
-                    let credentials = if mechanism.eq_ignore_ascii_case("basic") {
-                        // Throttle authentication requests
-                        self.is_auth_allowed_soft(&session.remote_ip).await?;
-
-                        // Decode the base64 encoded credentials
-                        decode_plain_auth(token).ok_or_else(|| {
-                            trc::AuthEvent::Error
-                                .into_err()
-                                .details("Failed to decode Basic auth request.")
-                                .id(token.to_string())
-                                .caused_by(trc::location!())
-                        })?
-                    } else if mechanism.eq_ignore_ascii_case("bearer") {
-                        // Enforce anonymous rate limit
-                        self.is_anonymous_allowed(&session.remote_ip).await?;
-
-                        decode_bearer_token(token, allow_api_access).ok_or_else(|| {
-                            trc::AuthEvent::Error
-                                .into_err()
-                                .details("Failed to decode Bearer token.")
-                                .id(token.to_string())
-                                .caused_by(trc::location!())
-                        })?
-                    } else {
-                        // Enforce anonymous rate limit
-                        self.is_anonymous_allowed(&session.remote_ip).await?;
-
-                        return Err(trc::AuthEvent::Error
+            let access_token = if allow_api_access
+                && mechanism.eq_ignore_ascii_case("bearer")
+                && is_api_key(token)
+            {
+                // API keys are not cached so that revocations take effect immediately
+                self.is_auth_allowed_soft(&session.remote_ip).await?;
+
+                match self.authenticate_api_key(token).await {
+                    Ok(access_token) => access_token,
+                    Err(err) => {
+                        if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
+                            let _ = self.is_auth_allowed_hard(&session.remote_ip).await;
+                        }
+                        return Err(err);
+                    }
+                }
+            } else if let Some(account_id) = self.inner.data.http_auth_cache.get_with_ttl(token) {
+                self.get_cached_access_token(account_id).await?
+            } else {
+                let credentials = if mechanism.eq_ignore_ascii_case("basic") {
+                    // Throttle authentication requests
+                    self.is_auth_allowed_soft(&session.remote_ip).await?;
+
+                    // Decode the base64 encoded credentials
+                    decode_plain_auth(token).ok_or_else(|| {
+                        trc::AuthEvent::Error
+                            .into_err()
+                            .details("Failed to decode Basic auth request.")
+                            .id(token.to_string())
+                            .caused_by(trc::location!())
+                    })?
+                } else if mechanism.eq_ignore_ascii_case("bearer") {
+                    // Enforce anonymous rate limit
+                    self.is_anonymous_allowed(&session.remote_ip).await?;
+
+                    decode_bearer_token(token, allow_api_access).ok_or_else(|| {
+                        trc::AuthEvent::Error
                             .into_err()
-                            .reason("Unsupported authentication mechanism.")
-                            .details(token.to_string())
-                            .caused_by(trc::location!()));
-                    };
-
-                    // Authenticate
-                    let access_token = match self
-                        .authenticate(&AuthRequest::from_credentials(
+                            .details("Failed to decode Bearer token.")
+                            .id(token.to_string())
+                            .caused_by(trc::location!())
+                    })?
+                } else {
+                    // Enforce anonymous rate limit
+                    self.is_anonymous_allowed(&session.remote_ip).await?;
+
+                    return Err(trc::AuthEvent::Error
+                        .into_err()
+                        .reason("Unsupported authentication mechanism.")
+                        .details(token.to_string())
+                        .caused_by(trc::location!()));
+                };
+
+                // Authenticate
+                let access_token = match self
+                    .authenticate(
+                        &AuthRequest::from_credentials(
                             credentials,
                             session.session_id,
                             session.remote_ip,
-                        ))
-                        .await
-                    {
-                        Ok(access_token) => access_token,
-                        Err(err) => {
-                            if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
-                                let _ = self.is_auth_allowed_hard(&session.remote_ip).await;
-                            }
-                            return Err(err);
+                        )
+                        .with_scope(AppScope::Http),
+                    )
+                    .await
+                {
+                    Ok(access_token) => access_token,
+                    Err(err) => {
+                        if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
+                            let _ = self.is_auth_allowed_hard(&session.remote_ip).await;
                         }
-                    };
-
-                    // Cache session
-                    self.cache_session(token.to_string(), &access_token);
-                    access_token
+                        return Err(err);
+                    }
                 };
 
+                // Cache session
+                self.cache_session(token.to_string(), &access_token);
+                access_token
+            };
+
             // Enforce authenticated rate limit
             self.is_account_allowed(&access_token)
                 .await
@@ -6,6 +6,10 @@
 
 use hyper::header::CONTENT_TYPE;
 use serde::{Deserialize, Serialize};
+use store::{
+    write::expire::{lookup_key_expiry, ExpiringRange},
+    SUBSPACE_LOOKUP_VALUE,
+};
 use utils::map::vec_map::VecMap;
 
 use crate::api::{http::fetch_body, HttpRequest};
@@ -24,6 +28,11 @@ pub enum OAuthStatus {
 
 const MAX_POST_LEN: usize = 2048;
 
+/// Authorization, device and user codes issued during the OAuth flows.
+pub fn expiring_range() -> ExpiringRange {
+    ExpiringRange::with_prefix("oauth", SUBSPACE_LOOKUP_VALUE, "oauth:", lookup_key_expiry)
+}
+
 pub struct OAuth {
     pub key: String,
     pub expiry_user_code: u64,
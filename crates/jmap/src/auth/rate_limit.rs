@@ -18,6 +18,7 @@ use std::future::Future;
 
 pub trait RateLimiter: Sync + Send {
     fn get_concurrency_limiter(&self, account_id: u32) -> Arc<ConcurrencyLimiters>;
+    fn get_caller_limiter(&self, access_token: &AccessToken) -> Arc<ConcurrencyLimiters>;
     fn is_account_allowed(
         &self,
         access_token: &AccessToken,
@@ -52,14 +53,40 @@ impl RateLimiter for Server {
             })
     }
 
+    fn get_caller_limiter(&self, access_token: &AccessToken) -> Arc<ConcurrencyLimiters> {
+        // API keys share the same account id, each key gets its own limiter
+        let Some(key_id) = access_token.api_key_id else {
+            return self.get_concurrency_limiter(access_token.primary_id());
+        };
+        let key = (access_token.tenant.map_or(u32::MAX, |t| t.id), key_id);
+
+        self.inner
+            .data
+            .api_key_limiter
+            .get(&key)
+            .map(|limiter| limiter.clone())
+            .unwrap_or_else(|| {
+                let limiter = Arc::new(ConcurrencyLimiters {
+                    concurrent_requests: ConcurrencyLimiter::new(
+                        self.core.jmap.request_max_concurrent,
+                    ),
+                    concurrent_uploads: ConcurrencyLimiter::new(
+                        self.core.jmap.upload_max_concurrent,
+                    ),
+                });
+                self.inner.data.api_key_limiter.insert(key, limiter.clone());
+                limiter
+            })
+    }
+
     async fn is_account_allowed(&self, access_token: &AccessToken) -> trc::Result<InFlight> {
-        let limiter = self.get_concurrency_limiter(access_token.primary_id());
+        let limiter = self.get_caller_limiter(access_token);
         let is_rate_allowed = if let Some(rate) = &self.core.jmap.rate_authenticated {
             self.core
                 .storage
                 .lookup
                 .is_rate_allowed(
-                    format!("j:{}", access_token.primary_id).as_bytes(),
+                    format!("j:{}", access_token.caller_id()).as_bytes(),
                     rate,
                     false,
                 )
@@ -104,7 +131,7 @@ impl RateLimiter for Server {
 
     fn is_upload_allowed(&self, access_token: &AccessToken) -> trc::Result<InFlight> {
         if let Some(in_flight_request) = self
-            .get_concurrency_limiter(access_token.primary_id())
+            .get_caller_limiter(access_token)
             .concurrent_uploads
             .is_allowed()
         {
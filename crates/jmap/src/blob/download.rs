@@ -17,7 +17,10 @@ use mail_parser::{
     Encoding,
 };
 use std::future::Future;
-use store::BlobClass;
+use store::{
+    dispatch::blob::{BlobChunks, BLOB_CHUNK_SIZE},
+    BlobClass,
+};
 use trc::AddContext;
 use utils::BlobHash;
 
@@ -30,6 +33,18 @@ pub trait BlobDownload: Sync + Send {
         access_token: &AccessToken,
     ) -> impl Future<Output = trc::Result<Option<Vec<u8>>>> + Send;
 
+    fn blob_download_chunks(
+        &self,
+        blob_id: &BlobId,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<Option<(Vec<u8>, Option<BlobChunks>)>>> + Send;
+
+    fn has_download_access(
+        &self,
+        blob_id: &BlobId,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+
     fn get_blob_section(
         &self,
         hash: &BlobHash,
@@ -50,12 +65,63 @@ pub trait BlobDownload: Sync + Send {
 }
 
 impl BlobDownload for Server {
-    #[allow(clippy::blocks_in_conditions)]
     async fn blob_download(
         &self,
         blob_id: &BlobId,
         access_token: &AccessToken,
     ) -> trc::Result<Option<Vec<u8>>> {
+        if !self.has_download_access(blob_id, access_token).await? {
+            return Ok(None);
+        }
+
+        if let Some(section) = &blob_id.section {
+            self.get_blob_section(&blob_id.hash, section).await
+        } else {
+            self.get_blob(&blob_id.hash, 0..usize::MAX).await
+        }
+    }
+
+    async fn blob_download_chunks(
+        &self,
+        blob_id: &BlobId,
+        access_token: &AccessToken,
+    ) -> trc::Result<Option<(Vec<u8>, Option<BlobChunks>)>> {
+        if !self.has_download_access(blob_id, access_token).await? {
+            return Ok(None);
+        }
+
+        // Sections have to be decoded, so only whole blobs are read in chunks
+        if let Some(section) = &blob_id.section {
+            return Ok(self
+                .get_blob_section(&blob_id.hash, section)
+                .await?
+                .map(|bytes| (bytes, None)));
+        }
+
+        let mut chunks = self
+            .core
+            .storage
+            .blob
+            .get_blob_chunks(blob_id.hash.as_ref(), 0..usize::MAX);
+        Ok(chunks
+            .next()
+            .await
+            .caused_by(trc::location!())?
+            .map(|chunk| {
+                if chunk.len() < BLOB_CHUNK_SIZE {
+                    (chunk, None)
+                } else {
+                    (chunk, Some(chunks))
+                }
+            }))
+    }
+
+    #[allow(clippy::blocks_in_conditions)]
+    async fn has_download_access(
+        &self,
+        blob_id: &BlobId,
+        access_token: &AccessToken,
+    ) -> trc::Result<bool> {
         if !self
             .core
             .storage
@@ -64,7 +130,7 @@ impl BlobDownload for Server {
             .await
             .caused_by(trc::location!())?
         {
-            return Ok(None);
+            return Ok(false);
         }
 
         if !access_token.is_member(blob_id.class.account_id()) {
@@ -80,7 +146,7 @@ impl BlobDownload for Server {
                             .await
                         {
                             Ok(shared_messages) if shared_messages.contains(*document_id) => (),
-                            _ => return Ok(None),
+                            _ => return Ok(false),
                         }
                     } else {
                         match self
@@ -94,21 +160,17 @@ impl BlobDownload for Server {
                             .await
                         {
                             Ok(has_access) if has_access => (),
-                            _ => return Ok(None),
+                            _ => return Ok(false),
                         }
                     }
                 }
                 BlobClass::Reserved { .. } => {
-                    return Ok(None);
+                    return Ok(false);
                 }
             }
         }
 
-        if let Some(section) = &blob_id.section {
-            self.get_blob_section(&blob_id.hash, section).await
-        } else {
-            self.get_blob(&blob_id.hash, 0..usize::MAX).await
-        }
+        Ok(true)
     }
 
     async fn get_blob_section(
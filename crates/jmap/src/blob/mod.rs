@@ -5,6 +5,7 @@
  */
 
 use jmap_proto::types::{blob::BlobId, id::Id};
+use store::dispatch::blob::BlobChunks;
 
 pub mod copy;
 pub mod download;
@@ -26,4 +27,6 @@ pub struct DownloadResponse {
     pub filename: String,
     pub content_type: String,
     pub blob: Vec<u8>,
+    // Remaining contents of large blobs, streamed after `blob`
+    pub chunks: Option<BlobChunks>,
 }
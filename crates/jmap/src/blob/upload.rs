@@ -17,7 +17,7 @@ use jmap_proto::{
     types::{blob::BlobId, id::Id},
 };
 use store::{
-    write::{now, BatchBuilder, BlobOp},
+    write::{now, tier::BlobCommit, BatchBuilder, BlobOp},
     BlobClass, Serialize,
 };
 use trc::AddContext;
@@ -296,7 +296,10 @@ impl BlobUpload for Server {
 
             // Commit blob
             let mut batch = BatchBuilder::new();
-            batch.set(BlobOp::Commit { hash: hash.clone() }, Vec::new());
+            batch.set(
+                BlobOp::Commit { hash: hash.clone() },
+                BlobCommit::now().serialize(),
+            );
             self.write_batch(batch).await?;
         }
 
@@ -334,10 +334,17 @@ impl EmailCopy for Server {
         };
 
         // Check quota
-        match self
+        let quota_check = match self
             .has_available_quota(resource_token, metadata.size as u64)
             .await
         {
+            Ok(_) => {
+                self.has_available_message_quota(resource_token, &mailboxes)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match quota_check {
             Ok(_) => (),
             Err(err) => {
                 if err.matches(trc::EventType::Limit(trc::LimitEvent::Quota))
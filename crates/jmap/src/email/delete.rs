@@ -55,6 +55,27 @@ pub trait EmailDeletion: Sync + Send {
         &self,
         account_id: u32,
     ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn emails_expunge_oldest(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        count: u64,
+    ) -> impl Future<Output = trc::Result<u64>> + Send;
+
+    fn emails_preserved(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<Vec<PreservedEmail>>> + Send;
+}
+
+/// A deleted message kept while its account is under litigation hold.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreservedEmail {
+    pub document_id: u32,
+    pub size: usize,
+    pub received_at: u64,
 }
 
 impl EmailDeletion for Server {
@@ -280,20 +301,39 @@ impl EmailDeletion for Server {
             }
         }
 
-        // Auto-expunge deleted and junk messages
-        if let Some(period) = self.core.jmap.mail_autoexpunge_after {
-            if let Err(err) = self.emails_auto_expunge(account_id, period).await {
+        // Accounts under litigation hold keep their deleted messages,
+        // nothing is destroyed if the hold cannot be verified
+        let on_hold = match self.get_cached_access_token(account_id).await {
+            Ok(access_token) => access_token.on_hold,
+            Err(err) => {
                 trc::error!(err
-                    .details("Failed to auto-expunge messages.")
+                    .details("Failed to obtain account hold status.")
                     .account_id(account_id));
+                true
+            }
+        };
+
+        if on_hold {
+            trc::event!(
+                Purge(trc::PurgeEvent::AccountOnHold),
+                AccountId = account_id
+            );
+        } else {
+            // Auto-expunge deleted and junk messages
+            if let Some(period) = self.core.jmap.mail_autoexpunge_after {
+                if let Err(err) = self.emails_auto_expunge(account_id, period).await {
+                    trc::error!(err
+                        .details("Failed to auto-expunge messages.")
+                        .account_id(account_id));
+                }
             }
-        }
 
-        // Purge tombstoned messages
-        if let Err(err) = self.emails_purge_tombstoned(account_id).await {
-            trc::error!(err
-                .details("Failed to purge tombstoned messages.")
-                .account_id(account_id));
+            // Purge tombstoned messages
+            if let Err(err) = self.emails_purge_tombstoned(account_id).await {
+                trc::error!(err
+                    .details("Failed to purge tombstoned messages.")
+                    .account_id(account_id));
+            }
         }
 
         // Purge changelogs
@@ -391,6 +431,96 @@ impl EmailDeletion for Server {
         Ok(())
     }
 
+    async fn emails_expunge_oldest(
+        &self,
+        account_id: u32,
+        mailbox_id: u32,
+        count: u64,
+    ) -> trc::Result<u64> {
+        let candidates = self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                TagValue::Id(mailbox_id),
+            )
+            .await?
+            .unwrap_or_default();
+
+        if candidates.is_empty() || count == 0 {
+            return Ok(0);
+        }
+
+        // Change ids are time ordered, the lowest belong to the oldest messages
+        let mut messages = self
+            .get_properties::<u64, _, _>(account_id, Collection::Email, &candidates, Property::Cid)
+            .await?;
+        messages.sort_unstable_by_key(|(_, cid)| *cid);
+        let destroy_ids = messages
+            .into_iter()
+            .take(count as usize)
+            .map(|(document_id, _)| document_id)
+            .collect::<RoaringBitmap>();
+        let requested = destroy_ids.len();
+
+        // Tombstone messages
+        let (changes, not_destroyed) = self.emails_tombstone(account_id, destroy_ids).await?;
+        let total = requested - not_destroyed.len();
+
+        trc::event!(
+            Purge(trc::PurgeEvent::QuotaExpunge),
+            AccountId = account_id,
+            MailboxId = mailbox_id,
+            Total = total,
+        );
+
+        // Write and broadcast changes
+        if !changes.is_empty() {
+            let change_id = self.commit_changes(account_id, changes).await?;
+            self.broadcast_state_change(
+                StateChange::new(account_id)
+                    .with_change(DataType::Email, change_id)
+                    .with_change(DataType::Mailbox, change_id)
+                    .with_change(DataType::Thread, change_id),
+            )
+            .await;
+        }
+
+        Ok(total)
+    }
+
+    async fn emails_preserved(&self, account_id: u32) -> trc::Result<Vec<PreservedEmail>> {
+        // Tombstoned messages are only kept past the next purge while on hold
+        let tombstoned_ids = self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                TagValue::Id(TOMBSTONE_ID),
+            )
+            .await?
+            .unwrap_or_default();
+        if tombstoned_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(self
+            .get_properties::<Bincode<MessageMetadata>, _, _>(
+                account_id,
+                Collection::Email,
+                &tombstoned_ids,
+                Property::BodyStructure,
+            )
+            .await?
+            .into_iter()
+            .map(|(document_id, metadata)| PreservedEmail {
+                document_id,
+                size: metadata.inner.size,
+                received_at: metadata.inner.received_at,
+            })
+            .collect())
+    }
+
     async fn emails_purge_tombstoned(&self, account_id: u32) -> trc::Result<()> {
         // Obtain tombstoned messages
         let tombstoned_ids = self
@@ -19,8 +19,11 @@ use jmap_proto::{
         value::Value,
     },
 };
-use mail_parser::HeaderName;
-use store::{write::Bincode, BlobClass};
+use mail_parser::{HeaderName, MessageParser};
+use store::{
+    write::{assert::AssertValue, BatchBuilder, Bincode, F_VALUE},
+    BlobClass,
+};
 use trc::{AddContext, StoreEvent};
 
 use crate::{
@@ -33,6 +36,7 @@ use super::{
     body::{ToBodyPart, TruncateBody},
     cache::ThreadCache,
     headers::IntoForm,
+    index::message_preview,
     metadata::{MessageMetadata, MetadataPartType},
 };
 
@@ -139,6 +143,8 @@ impl EmailGet for Server {
             }
         }
 
+        let needs_preview = properties.contains(&Property::Preview);
+
         'outer: for id in ids {
             // Obtain the email object
             if !message_ids.contains(id.document_id()) {
@@ -154,13 +160,20 @@ impl EmailGet for Server {
                 )
                 .await?
             {
-                Some(metadata) => metadata.inner,
+                Some(metadata) => metadata,
                 None => {
                     response.not_found.push(id.into());
                     continue;
                 }
             };
 
+            // Messages stored without a preview obtain one on first request
+            if needs_preview && metadata.inner.preview.is_empty() {
+                self.generate_preview(account_id, id.document_id(), &mut metadata)
+                    .await?;
+            }
+            let mut metadata = metadata.inner;
+
             // Retrieve raw message if needed
             let raw_message = if needs_body {
                 if let Some(raw_message) = self.get_blob(&metadata.blob_hash, 0..usize::MAX).await?
@@ -437,3 +450,75 @@ impl EmailGet for Server {
         Ok(response)
     }
 }
+
+trait EmailPreview: Sync + Send {
+    fn generate_preview(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        metadata: &mut Bincode<MessageMetadata<'static>>,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl EmailPreview for Server {
+    async fn generate_preview(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        metadata: &mut Bincode<MessageMetadata<'static>>,
+    ) -> trc::Result<()> {
+        // Skip messages without any text to preview
+        let contents = &metadata.inner.contents;
+        if !contents
+            .text_body
+            .iter()
+            .chain(contents.html_body.iter())
+            .filter_map(|part_id| contents.parts.get(*part_id))
+            .any(|part| part.size > 0)
+        {
+            return Ok(());
+        }
+
+        // Messages flagged as having no preview are not parsed again
+        if self
+            .get_property::<()>(
+                account_id,
+                Collection::Email,
+                document_id,
+                Property::Preview,
+            )
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        let preview = self
+            .get_blob(&metadata.inner.blob_hash, 0..usize::MAX)
+            .await?
+            .and_then(|raw_message| {
+                MessageParser::new()
+                    .parse(&raw_message)
+                    .and_then(|message| message_preview(&message))
+            });
+
+        // Stash the preview, the message might have been deleted meanwhile
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .update_document(document_id)
+            .assert_value(Property::BodyStructure, AssertValue::Some);
+        if let Some(preview) = preview {
+            metadata.inner.preview = preview;
+            batch.value(Property::BodyStructure, &*metadata, F_VALUE);
+        } else {
+            batch.value(Property::Preview, (), F_VALUE);
+        }
+        match self.core.storage.data.write(batch.build()).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.is_assertion_failure() => Ok(()),
+            Err(err) => Err(err.caused_by(trc::location!())),
+        }
+    }
+}
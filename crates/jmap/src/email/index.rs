@@ -31,6 +31,33 @@ pub const MAX_SORT_FIELD_LENGTH: usize = 255;
 pub const MAX_STORED_FIELD_LENGTH: usize = 512;
 pub const PREVIEW_LENGTH: usize = 256;
 
+/// Returns a short plain-text preview of the first body part that contains
+/// any text. HTML parts are converted to text and control characters removed.
+pub fn message_preview(message: &Message<'_>) -> Option<String> {
+    message
+        .text_body
+        .iter()
+        .chain(message.html_body.iter())
+        .take(MAX_MESSAGE_PARTS)
+        .filter_map(|part_id| message.parts.get(*part_id))
+        .find_map(|part| {
+            let text = match &part.body {
+                PartType::Text(text) => text.replace('\r', ""),
+                PartType::Html(html) => html_to_text(html).replace('\r', ""),
+                _ => return None,
+            };
+            if text.trim().is_empty() {
+                return None;
+            }
+            let text = text
+                .chars()
+                .filter(|ch| !ch.is_control() || ch.is_whitespace())
+                .collect::<String>();
+
+            Some(preview_text(text.into(), PREVIEW_LENGTH).into_owned())
+        })
+}
+
 #[derive(Debug)]
 pub struct SortedAddressBuilder {
     last_is_space: bool,
@@ -91,13 +118,11 @@ impl IndexMessage for BatchBuilder {
         self.value(Property::ReceivedAt, received_at, F_INDEX);
 
         let mut has_attachments = false;
-        let mut preview = None;
-        let preview_part_id = message
-            .text_body
-            .first()
-            .or_else(|| message.html_body.first())
-            .copied()
-            .unwrap_or(usize::MAX);
+        // Messages without a preview are flagged so it is not generated on request
+        let preview = message_preview(&message);
+        if preview.is_none() {
+            self.value(Property::Preview, (), F_VALUE);
+        }
 
         for (part_id, part) in message.parts.iter().take(MAX_MESSAGE_PARTS).enumerate() {
             if part_id == 0 {
@@ -105,25 +130,7 @@ impl IndexMessage for BatchBuilder {
             }
 
             match &part.body {
-                PartType::Text(text) => {
-                    if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
-                    }
-
-                    if !message.text_body.contains(&part_id)
-                        && !message.html_body.contains(&part_id)
-                    {
-                        has_attachments = true;
-                    }
-                }
-                PartType::Html(html) => {
-                    let text = html_to_text(html);
-                    if part_id == preview_part_id {
-                        preview =
-                            preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH).into();
-                    }
-
+                PartType::Text(_) | PartType::Html(_) => {
                     if !message.text_body.contains(&part_id)
                         && !message.html_body.contains(&part_id)
                     {
@@ -155,7 +162,7 @@ impl IndexMessage for BatchBuilder {
         self.value(
             Property::BodyStructure,
             Bincode::new(MessageMetadata {
-                preview: preview.unwrap_or_default().into_owned(),
+                preview: preview.unwrap_or_default(),
                 size: message.raw_message.len(),
                 raw_headers: message
                     .raw_message
@@ -414,7 +421,9 @@ impl EmailIndexBuilder<'_> {
             0
         } else {
             // Delete metadata
-            batch.value(Property::BodyStructure, (), F_VALUE | F_CLEAR);
+            batch
+                .value(Property::BodyStructure, (), F_VALUE | F_CLEAR)
+                .value(Property::Preview, (), F_VALUE | F_CLEAR);
             F_CLEAR
         };
         let metadata = &self.inner.inner;
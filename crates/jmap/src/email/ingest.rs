@@ -9,7 +9,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use common::{auth::ResourceToken, Server};
+use common::{auth::ResourceToken, config::jmap::settings::FolderLimitAction, Server};
 use jmap_proto::{
     object::Object,
     types::{
@@ -39,9 +39,13 @@ use utils::map::vec_map::VecMap;
 use crate::{
     blob::upload::BlobUpload,
     changes::write::ChangeLog,
-    email::index::{IndexMessage, VisitValues, MAX_ID_LENGTH},
-    mailbox::{UidMailbox, INBOX_ID, JUNK_ID},
+    email::{
+        delete::EmailDeletion,
+        index::{IndexMessage, VisitValues, MAX_ID_LENGTH},
+    },
+    mailbox::{get::MailboxGet, UidMailbox, INBOX_ID, JUNK_ID},
     services::index::Indexer,
+    thread::rules::ThreadRuleStore,
     JmapMethods,
 };
 
@@ -97,6 +101,11 @@ pub trait EmailIngest: Sync + Send {
         account_id: u32,
         mailbox_id: u32,
     ) -> impl Future<Output = trc::Result<u32>> + Send;
+    fn has_available_message_quota(
+        &self,
+        resource: &ResourceToken,
+        mailbox_ids: &[u32],
+    ) -> impl Future<Output = trc::Result<()>> + Send;
 }
 
 impl EmailIngest for Server {
@@ -138,6 +147,11 @@ impl EmailIngest for Server {
             }
         }
 
+        // Check message count quotas
+        self.has_available_message_quota(&params.resource, &params.mailbox_ids)
+            .await
+            .caused_by(trc::location!())?;
+
         // Obtain message references and thread name
         let mut message_id = String::new();
         let thread_id = {
@@ -223,6 +237,33 @@ impl EmailIngest for Server {
             }
         };
 
+        // Apply thread rules
+        if let Some(thread_id) = thread_id {
+            if let Some(action) = self
+                .thread_rule_activity(account_id, thread_id)
+                .await
+                .caused_by(trc::location!())?
+                .filter(|_| params.source == IngestSource::Smtp && !is_spam)
+            {
+                let mailbox_id = params.mailbox_ids.first().copied();
+                self.apply_thread_action(
+                    account_id,
+                    action,
+                    &mut params.mailbox_ids,
+                    &mut params.keywords,
+                )
+                .await
+                .caused_by(trc::location!())?;
+
+                // Folder limits apply to the new destination
+                if params.mailbox_ids.first().copied() != mailbox_id {
+                    self.has_available_message_quota(&params.resource, &params.mailbox_ids)
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+            }
+        }
+
         // Encrypt message
         if params.encrypt && !message.is_encrypted() {
             if let Some(encrypt_params) = self
@@ -531,7 +572,18 @@ impl EmailIngest for Server {
             batch.custom(changes);
 
             match self.core.storage.data.write(batch.build()).await {
-                Ok(_) => return Ok(Some(thread_id)),
+                Ok(_) => {
+                    // Rules follow their messages into the surviving thread
+                    let merged_ids = thread_counts
+                        .keys()
+                        .copied()
+                        .filter(|id| *id != thread_id)
+                        .collect::<Vec<_>>();
+                    self.merge_thread_rules(account_id, thread_id, &merged_ids)
+                        .await
+                        .caused_by(trc::location!())?;
+                    return Ok(Some(thread_id));
+                }
                 Err(err) if err.is_assertion_failure() && try_count < MAX_RETRIES => {
                     let backoff = rand::thread_rng().gen_range(50..=300);
                     tokio::time::sleep(Duration::from_millis(backoff)).await;
@@ -559,6 +611,100 @@ impl EmailIngest for Server {
             .await
             .and_then(|v| v.last_counter_id().map(|id| id as u32))
     }
+
+    async fn has_available_message_quota(
+        &self,
+        resource: &ResourceToken,
+        mailbox_ids: &[u32],
+    ) -> trc::Result<()> {
+        let folders = &self.core.jmap.default_folders;
+        if resource.message_quota == 0 && folders.iter().all(|f| f.max_messages.is_none()) {
+            return Ok(());
+        }
+        let account_id = resource.account_id;
+
+        // Enforce folder limits
+        let mut expunge_mailbox_ids = Vec::new();
+        for folder in folders {
+            let Some(role) = folder.special_use.as_role().filter(|_| {
+                folder.max_messages.is_some()
+                    || (resource.message_quota != 0
+                        && folder.on_limit == FolderLimitAction::ExpungeOldest)
+            }) else {
+                continue;
+            };
+            let Some(mailbox_id) = self.mailbox_get_by_role(account_id, role).await? else {
+                continue;
+            };
+            if folder.on_limit == FolderLimitAction::ExpungeOldest {
+                expunge_mailbox_ids.push(mailbox_id);
+            }
+            let Some(max_messages) = folder
+                .max_messages
+                .filter(|_| mailbox_ids.contains(&mailbox_id))
+            else {
+                continue;
+            };
+
+            let total = self
+                .get_tag(
+                    account_id,
+                    Collection::Email,
+                    Property::MailboxIds,
+                    TagValue::Id(mailbox_id),
+                )
+                .await?
+                .map_or(0, |ids| ids.len());
+            if total >= max_messages {
+                match folder.on_limit {
+                    FolderLimitAction::Reject => {
+                        return Err(trc::LimitEvent::Quota
+                            .into_err()
+                            .details("Folder message limit exceeded")
+                            .ctx(trc::Key::MailboxName, folder.name.clone())
+                            .ctx(trc::Key::Limit, max_messages)
+                            .ctx(trc::Key::Total, total));
+                    }
+                    FolderLimitAction::ExpungeOldest => {
+                        self.emails_expunge_oldest(
+                            account_id,
+                            mailbox_id,
+                            total + 1 - max_messages,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+
+        // Enforce the account limit, folders that allow it are trimmed
+        // before new messages are rejected
+        if resource.message_quota != 0 {
+            let mut total = self.get_message_count(account_id).await?;
+            for mailbox_id in expunge_mailbox_ids {
+                if total < resource.message_quota {
+                    break;
+                }
+                total -= self
+                    .emails_expunge_oldest(
+                        account_id,
+                        mailbox_id,
+                        total + 1 - resource.message_quota,
+                    )
+                    .await?;
+            }
+
+            if total >= resource.message_quota {
+                return Err(trc::LimitEvent::Quota
+                    .into_err()
+                    .details("Message count quota exceeded")
+                    .ctx(trc::Key::Limit, resource.message_quota)
+                    .ctx(trc::Key::Total, total));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub struct LogEmailInsert(Option<u32>);
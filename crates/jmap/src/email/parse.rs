@@ -10,9 +10,7 @@ use jmap_proto::{
     object::Object,
     types::{property::Property, value::Value},
 };
-use mail_parser::{
-    decoders::html::html_to_text, parsers::preview::preview_text, MessageParser, PartType,
-};
+use mail_parser::{MessageParser, PartType};
 use std::future::Future;
 use utils::map::vec_map::VecMap;
 
@@ -21,7 +19,7 @@ use crate::blob::download::BlobDownload;
 use super::{
     body::{ToBodyPart, TruncateBody},
     headers::HeaderToValue,
-    index::PREVIEW_LENGTH,
+    index::message_preview,
 };
 
 pub trait EmailParse: Sync + Send {
@@ -100,7 +98,10 @@ impl EmailParse for Server {
                     continue;
                 }
             };
-            let message = if let Some(message) = MessageParser::new().parse(&raw_message) {
+            let message = if let Some(message) = MessageParser::new()
+                .parse(&raw_message)
+                .filter(|message| !message.root_part().headers.is_empty())
+            {
                 message
             } else {
                 response.not_parsable.push(blob_id);
@@ -136,24 +137,7 @@ impl EmailParse for Server {
                     Property::Preview => {
                         email.append(
                             Property::Preview,
-                            match message
-                                .text_body
-                                .first()
-                                .or_else(|| message.html_body.first())
-                                .and_then(|idx| message.parts.get(*idx))
-                                .map(|part| &part.body)
-                            {
-                                Some(PartType::Text(text)) => {
-                                    preview_text(text.replace('\r', "").into(), PREVIEW_LENGTH)
-                                        .into()
-                                }
-                                Some(PartType::Html(html)) => preview_text(
-                                    html_to_text(html).replace('\r', "").into(),
-                                    PREVIEW_LENGTH,
-                                )
-                                .into(),
-                                _ => Value::Null,
-                            },
+                            message_preview(&message).map_or(Value::Null, Value::Text),
                         );
                     }
                     Property::MessageId
@@ -28,6 +28,13 @@ pub trait IdentitySet: Sync + Send {
         &self,
         request: SetRequest<RequestArguments>,
     ) -> impl Future<Output = trc::Result<SetResponse>> + Send;
+
+    fn identity_rename_email(
+        &self,
+        account_id: u32,
+        old_email: &str,
+        new_email: &str,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
 }
 
 impl IdentitySet for Server {
@@ -189,6 +196,56 @@ impl IdentitySet for Server {
 
         Ok(response)
     }
+
+    async fn identity_rename_email(
+        &self,
+        account_id: u32,
+        old_email: &str,
+        new_email: &str,
+    ) -> trc::Result<()> {
+        let mut changes = ChangeLogBuilder::new();
+        for document_id in self
+            .get_document_ids(account_id, Collection::Identity)
+            .await?
+            .unwrap_or_default()
+        {
+            let Some(mut identity) = self
+                .get_property::<Object<Value>>(
+                    account_id,
+                    Collection::Identity,
+                    document_id,
+                    Property::Value,
+                )
+                .await?
+            else {
+                continue;
+            };
+            if !matches!(identity.get(&Property::Email), Value::Text(email) if email == old_email) {
+                continue;
+            }
+
+            // Default identities are named after the address when there is no description
+            if matches!(identity.get(&Property::Name), Value::Text(name) if name == old_email) {
+                identity.set(Property::Name, Value::Text(new_email.to_string()));
+            }
+            identity.set(Property::Email, Value::Text(new_email.to_string()));
+
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Identity)
+                .update_document(document_id)
+                .value(Property::Value, identity, F_VALUE);
+            self.write_batch(batch).await?;
+            changes.log_update(Collection::Identity, document_id);
+        }
+
+        if !changes.is_empty() {
+            self.commit_changes(account_id, changes).await?;
+        }
+
+        Ok(())
+    }
 }
 
 fn validate_identity_value(
@@ -13,6 +13,7 @@ use common::{
     Inner, Server,
 };
 use directory::QueryBy;
+use email::delete::EmailDeletion;
 use jmap_proto::{
     method::{
         query::{QueryRequest, QueryResponse},
@@ -261,7 +262,9 @@ impl JmapMethods for Server {
             ResourceToken {
                 account_id,
                 quota: access_token.quota,
+                message_quota: access_token.message_quota,
                 tenant: access_token.tenant,
+                on_hold: access_token.on_hold,
             }
         } else {
             let mut quotas = ResourceToken {
@@ -278,6 +281,8 @@ impl JmapMethods for Server {
                 .add_context(|err| err.caused_by(trc::location!()).account_id(account_id))?
             {
                 quotas.quota = principal.quota();
+                quotas.message_quota = principal.message_quota();
+                quotas.on_hold = principal.is_on_hold();
 
                 // SPDX-SnippetBegin
                 // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
@@ -320,9 +325,41 @@ impl JmapMethods for Server {
             .add_context(|err| err.caused_by(trc::location!()).account_id(account_id))
     }
 
+    async fn get_message_count(&self, account_id: u32) -> trc::Result<u64> {
+        // Tombstoned messages are excluded until they are purged
+        let mut message_ids = self
+            .get_document_ids(account_id, Collection::Email)
+            .await?
+            .unwrap_or_default();
+        if let Some(tombstoned_ids) = self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                TagValue::Id(mailbox::TOMBSTONE_ID),
+            )
+            .await?
+        {
+            message_ids -= tombstoned_ids;
+        }
+
+        Ok(message_ids.len())
+    }
+
     async fn has_available_quota(&self, quotas: &ResourceToken, item_size: u64) -> trc::Result<()> {
         if quotas.quota != 0 {
-            let used_quota = self.get_used_quota(quotas.account_id).await? as u64;
+            let mut used_quota = self.get_used_quota(quotas.account_id).await? as u64;
+
+            // Messages preserved by a litigation hold can be left out of the quota
+            if quotas.on_hold && !self.core.jmap.mail_hold_count_quota {
+                let preserved = self
+                    .emails_preserved(quotas.account_id)
+                    .await?
+                    .into_iter()
+                    .map(|email| email.size as u64)
+                    .sum::<u64>();
+                used_quota = used_quota.saturating_sub(preserved);
+            }
 
             if used_quota + item_size > quotas.quota {
                 return Err(trc::LimitEvent::Quota
@@ -540,6 +577,8 @@ pub trait JmapMethods: Sync + Send {
 
     fn get_used_quota(&self, account_id: u32) -> impl Future<Output = trc::Result<i64>> + Send;
 
+    fn get_message_count(&self, account_id: u32) -> impl Future<Output = trc::Result<u64>> + Send;
+
     fn has_available_quota(
         &self,
         quotas: &ResourceToken,
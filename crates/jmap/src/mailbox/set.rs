@@ -5,7 +5,7 @@
  */
 
 use common::{auth::AccessToken, config::jmap::settings::SpecialUse, Server};
-use directory::Permission;
+use directory::{Permission, QueryBy, Type};
 use jmap_proto::{
     error::set::{SetError, SetErrorType},
     method::set::{SetRequest, SetResponse},
@@ -31,7 +31,7 @@ use store::{
     write::{
         assert::{AssertValue, HashedValue},
         log::ChangeLogBuilder,
-        BatchBuilder, F_BITMAP, F_CLEAR, F_VALUE,
+        now, BatchBuilder, F_BITMAP, F_CLEAR, F_VALUE,
     },
 };
 use trc::AddContext;
@@ -40,6 +40,7 @@ use crate::{
     auth::acl::{AclMethods, EffectiveAcl},
     changes::write::ChangeLog,
     email::delete::EmailDeletion,
+    services::provision::AccountProvisioning,
     JmapMethods,
 };
 
@@ -91,6 +92,16 @@ pub trait MailboxSet: Sync + Send {
         remove_emails: bool,
     ) -> impl Future<Output = trc::Result<Result<bool, SetError>>> + Send;
 
+    fn mailbox_rename(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        mailbox: HashedValue<Object<Value>>,
+        parent_id: Option<u32>,
+        create_path: &[&str],
+        new_name: &str,
+    ) -> impl Future<Output = trc::Result<(u64, Vec<u32>)>> + Send;
+
     fn mailbox_set_item(
         &self,
         changes_: Object<SetValue>,
@@ -515,6 +526,72 @@ impl MailboxSet for Server {
         }
     }
 
+    async fn mailbox_rename(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        mailbox: HashedValue<Object<Value>>,
+        parent_id: Option<u32>,
+        create_path: &[&str],
+        new_name: &str,
+    ) -> trc::Result<(u64, Vec<u32>)> {
+        let mut changes = self.begin_changes(account_id).await?;
+
+        // Create any missing folders in the new path
+        let mut parent_id = parent_id.map(|id| id + 1).unwrap_or(0);
+        let mut create_ids = Vec::with_capacity(create_path.len());
+        for &path_item in create_path {
+            let mut batch = BatchBuilder::new();
+            batch
+                .with_account_id(account_id)
+                .with_collection(Collection::Mailbox)
+                .create_document()
+                .custom(
+                    ObjectIndexBuilder::new(SCHEMA).with_changes(
+                        Object::with_capacity(3)
+                            .with_property(Property::Name, path_item)
+                            .with_property(Property::ParentId, Value::Id(Id::from(parent_id)))
+                            .with_property(
+                                Property::Cid,
+                                Value::UnsignedInt(rand::random::<u32>() as u64),
+                            ),
+                    ),
+                );
+
+            let mailbox_id = self.write_batch_expect_id(batch).await?;
+            changes.log_insert(Collection::Mailbox, mailbox_id);
+            parent_id = mailbox_id + 1;
+            create_ids.push(mailbox_id);
+        }
+
+        // Renamed mailboxes get a new UIDVALIDITY, subscriptions and roles are kept
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Mailbox)
+            .update_document(document_id)
+            .custom(
+                ObjectIndexBuilder::new(SCHEMA)
+                    .with_current(mailbox)
+                    .with_changes(
+                        Object::with_capacity(3)
+                            .with_property(Property::Name, new_name)
+                            .with_property(Property::ParentId, Value::Id(Id::from(parent_id)))
+                            .with_property(
+                                Property::Cid,
+                                Value::UnsignedInt(rand::random::<u32>() as u64),
+                            ),
+                    ),
+            );
+        changes.log_update(Collection::Mailbox, document_id);
+
+        let change_id = changes.change_id;
+        batch.custom(changes);
+        self.write_batch(batch).await?;
+
+        Ok((change_id, create_ids))
+    }
+
     #[allow(clippy::blocks_in_conditions)]
     async fn mailbox_set_item(
         &self,
@@ -816,10 +893,36 @@ impl MailboxSet for Server {
             return Ok(mailbox_ids);
         }
 
+        // Service accounts are not provisioned with mailboxes
+        if self
+            .core
+            .storage
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .caused_by(trc::location!())?
+            .is_some_and(|principal| principal.typ() == Type::Service)
+        {
+            return Ok(mailbox_ids);
+        }
+
+        // Accounts are provisioned only once, even if all mailboxes are later deleted
+        let provision = self
+            .get_property::<u64>(account_id, Collection::Principal, 0, Property::Delivered)
+            .await
+            .caused_by(trc::location!())?
+            .is_none();
+
         let mut batch = BatchBuilder::new();
-        batch
-            .with_account_id(account_id)
-            .with_collection(Collection::Mailbox);
+        batch.with_account_id(account_id);
+        if provision {
+            batch
+                .with_collection(Collection::Principal)
+                .update_document(0)
+                .assert_value(Property::Delivered, ())
+                .value(Property::Delivered, now(), F_VALUE);
+        }
+        batch.with_collection(Collection::Mailbox);
 
         // Create mailboxes
         let mut last_document_id = ARCHIVE_ID;
@@ -860,13 +963,25 @@ impl MailboxSet for Server {
             mailbox_ids.insert(document_id);
         }
 
-        self.core
-            .storage
-            .data
-            .write(batch.build())
-            .await
-            .caused_by(trc::location!())
-            .map(|_| mailbox_ids)
+        match self.core.storage.data.write(batch.build()).await {
+            Ok(_) => {
+                if provision {
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        server.provision_account(account_id).await;
+                    });
+                }
+
+                Ok(mailbox_ids)
+            }
+            Err(err) if err.is_assertion_failure() => {
+                // Another session provisioned the account concurrently
+                self.get_document_ids(account_id, Collection::Mailbox)
+                    .await
+                    .map(|ids| ids.unwrap_or_default())
+            }
+            Err(err) => Err(err.caused_by(trc::location!())),
+        }
     }
 
     async fn mailbox_create_path(
@@ -12,7 +12,18 @@ pub mod set;
 use std::time::Instant;
 
 use common::ipc::{EncryptionKeys, PushSubscription};
-use jmap_proto::types::{id::Id, state::StateChange};
+use jmap_proto::{
+    object::Object,
+    types::{collection::Collection, id::Id, property::Property, state::StateChange, value::Value},
+};
+use store::{
+    write::{
+        expire::ExpiringRange,
+        key::{DeserializeBigEndian, KeySerializer},
+        BatchBuilder, F_CLEAR, F_VALUE,
+    },
+    Deserialize, SUBSPACE_PROPERTY, U32_LEN,
+};
 
 #[derive(Debug)]
 pub enum Event {
@@ -61,3 +72,37 @@ pub struct PushServer {
     state_changes: Vec<StateChange>,
     in_flight: bool,
 }
+
+/// Subscriptions of an account, these are removed once their expiry date passes.
+pub fn expiring_range(account_id: u32) -> ExpiringRange {
+    let key = |document_id: u32| {
+        KeySerializer::new(U32_LEN * 2 + 2)
+            .write(account_id)
+            .write(u8::from(Collection::PushSubscription))
+            .write(u8::from(Property::Value))
+            .write(document_id)
+            .finalize()
+    };
+
+    ExpiringRange::new(
+        "push-subscription",
+        SUBSPACE_PROPERTY,
+        key(0),
+        key(u32::MAX),
+        |_, value| {
+            Ok(Object::<Value>::deserialize(value)?
+                .properties
+                .get(&Property::Expires)
+                .and_then(|expires| expires.as_date())
+                .map(|expires| expires.timestamp() as u64))
+        },
+    )
+    .with_delete(|batch: &mut BatchBuilder, _, key| {
+        batch
+            .with_account_id(key.deserialize_be_u32(0)?)
+            .with_collection(Collection::PushSubscription)
+            .delete_document(key.deserialize_be_u32(key.len() - U32_LEN)?)
+            .value(Property::Value, (), F_VALUE | F_CLEAR);
+        Ok(())
+    })
+}
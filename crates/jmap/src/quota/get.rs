@@ -14,6 +14,8 @@ use std::future::Future;
 
 use crate::JmapMethods;
 
+use super::{QUOTA_COUNT_ID, QUOTA_OCTETS_ID};
+
 pub trait QuotaGet: Sync + Send {
     fn quota_get(
         &self,
@@ -42,11 +44,13 @@ impl QuotaGet for Server {
             Property::Types,
         ]);
         let account_id = request.account_id.document_id();
-        let quota_ids = if access_token.quota > 0 {
-            vec![0u32]
-        } else {
-            vec![]
-        };
+        let mut quota_ids = Vec::with_capacity(2);
+        if access_token.quota > 0 {
+            quota_ids.push(QUOTA_OCTETS_ID);
+        }
+        if access_token.message_quota > 0 {
+            quota_ids.push(QUOTA_COUNT_ID);
+        }
         let ids = if let Some(ids) = ids {
             ids
         } else {
@@ -71,12 +75,24 @@ impl QuotaGet for Server {
             for property in &properties {
                 let value = match property {
                     Property::Id => Value::Id(id),
+                    Property::ResourceType if document_id == QUOTA_COUNT_ID => {
+                        "count".to_string().into()
+                    }
                     Property::ResourceType => "octets".to_string().into(),
+                    Property::Used if document_id == QUOTA_COUNT_ID => {
+                        self.get_message_count(account_id).await?.into()
+                    }
                     Property::Used => (self.get_used_quota(account_id).await? as u64).into(),
+                    Property::HardLimit if document_id == QUOTA_COUNT_ID => {
+                        access_token.message_quota.into()
+                    }
                     Property::HardLimit => access_token.quota.into(),
                     Property::Scope => "account".to_string().into(),
                     Property::Name => access_token.name.clone().into(),
                     Property::Description => access_token.description.clone().into(),
+                    Property::Types if document_id == QUOTA_COUNT_ID => {
+                        vec![Value::Text(DataType::Email.to_string())].into()
+                    }
                     Property::Types => vec![
                         Value::Text(DataType::Email.to_string()),
                         Value::Text(DataType::SieveScript.to_string()),
@@ -6,3 +6,6 @@
 
 pub mod get;
 pub mod query;
+
+pub const QUOTA_OCTETS_ID: u32 = 0;
+pub const QUOTA_COUNT_ID: u32 = 1;
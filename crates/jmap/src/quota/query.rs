@@ -11,6 +11,8 @@ use jmap_proto::{
 };
 use std::future::Future;
 
+use super::{QUOTA_COUNT_ID, QUOTA_OCTETS_ID};
+
 pub trait QuotaQuery: Sync + Send {
     fn quota_query(
         &self,
@@ -25,17 +27,21 @@ impl QuotaQuery for Server {
         request: QueryRequest<RequestArguments>,
         access_token: &AccessToken,
     ) -> trc::Result<QueryResponse> {
+        let mut ids = Vec::with_capacity(2);
+        if access_token.quota > 0 {
+            ids.push(Id::from(QUOTA_OCTETS_ID));
+        }
+        if access_token.message_quota > 0 {
+            ids.push(Id::from(QUOTA_COUNT_ID));
+        }
+
         Ok(QueryResponse {
             account_id: request.account_id,
             query_state: State::Initial,
             can_calculate_changes: false,
             position: 0,
-            ids: if access_token.quota > 0 {
-                vec![Id::new(0)]
-            } else {
-                vec![]
-            },
-            total: Some(1),
+            total: Some(ids.len()),
+            ids,
             limit: None,
         })
 
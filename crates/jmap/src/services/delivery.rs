@@ -9,6 +9,8 @@ use std::sync::Arc;
 use common::{core::BuildServer, ipc::DeliveryEvent, Inner};
 use tokio::sync::mpsc;
 
+use crate::submission::status::EmailSubmissionStatus;
+
 use super::ingest::MailDelivery;
 
 pub fn spawn_delivery_manager(inner: Arc<Inner>, mut delivery_rx: mpsc::Receiver<DeliveryEvent>) {
@@ -20,6 +22,13 @@ pub fn spawn_delivery_manager(inner: Arc<Inner>, mut delivery_rx: mpsc::Receiver
                         .send(inner.build_server().deliver_message(message).await)
                         .ok();
                 }
+                DeliveryEvent::SubmissionStatus(status) => {
+                    if let Err(err) = inner.build_server().update_submission_status(status).await {
+                        trc::error!(err
+                            .details("Failed to update submission delivery status")
+                            .caused_by(trc::location!()));
+                    }
+                }
                 DeliveryEvent::Stop => break,
             }
         }
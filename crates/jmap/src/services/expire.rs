@@ -0,0 +1,71 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::Server;
+use jmap_proto::types::collection::Collection;
+use store::{write::expire::ExpiringRange, LookupStore};
+use trc::AddContext;
+
+use crate::{api::management::idempotency, auth::oauth, push, JmapMethods};
+
+// Number of keys read before expired records are deleted
+const CHUNK_SIZE: usize = 1000;
+
+pub trait ExpiredRecords: Sync + Send {
+    fn purge_expired(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ExpiredRecords for Server {
+    async fn purge_expired(&self) -> trc::Result<()> {
+        // Other lookup stores expire keys natively
+        if let LookupStore::Store(store) = &self.core.storage.lookup {
+            for range in [oauth::expiring_range(), idempotency::expiring_range()] {
+                let total = store
+                    .purge_expired(&range, CHUNK_SIZE)
+                    .await
+                    .caused_by(trc::location!())?;
+                report(&range, total);
+            }
+        }
+
+        // Push subscriptions are stored per account
+        let mut total = 0;
+        for account_id in self
+            .get_document_ids(u32::MAX, Collection::Principal)
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default()
+        {
+            if self
+                .get_document_ids(account_id, Collection::PushSubscription)
+                .await
+                .caused_by(trc::location!())?
+                .is_some_and(|ids| !ids.is_empty())
+            {
+                total += self
+                    .core
+                    .storage
+                    .data
+                    .purge_expired(&push::expiring_range(account_id), CHUNK_SIZE)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+        }
+        report(&push::expiring_range(u32::MAX), total);
+
+        Ok(())
+    }
+}
+
+fn report(range: &ExpiringRange, total: u64) {
+    trc::event!(
+        Purge(trc::PurgeEvent::ExpiredRecords),
+        Type = range.name,
+        Total = total,
+    );
+}
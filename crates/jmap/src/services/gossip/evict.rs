@@ -0,0 +1,36 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::net::IpAddr;
+
+use common::core::BuildServer;
+use trc::ClusterEvent;
+
+use super::{request::Request, Gossiper};
+
+impl Gossiper {
+    pub async fn broadcast_evictions(&self) {
+        let Some(account_ids) = self.inner.data.directory_sync.lock().take_evictions() else {
+            return;
+        };
+        for peer in &self.peers {
+            if !peer.is_offline() {
+                self.send_gossip(peer.addr, Request::Evict(account_ids.clone()))
+                    .await;
+            }
+        }
+    }
+
+    pub fn handle_evict(&self, addr: IpAddr, account_ids: Vec<u32>) {
+        trc::event!(
+            Cluster(ClusterEvent::PeerHasChanges),
+            RemoteIp = addr,
+            Details = "principals"
+        );
+
+        self.inner.build_server().apply_evictions(&account_ids);
+    }
+}
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod evict;
 pub mod heartbeat;
 pub mod leave;
 pub mod peer;
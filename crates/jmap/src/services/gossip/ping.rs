@@ -66,6 +66,9 @@ impl Gossiper {
         if node_became_offline {
             self.request_reload();
         }
+
+        // Broadcast principal evictions, at most once per heartbeat
+        self.broadcast_evictions().await;
     }
 
     pub fn request_reload(&self) {
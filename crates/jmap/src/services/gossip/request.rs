@@ -15,16 +15,25 @@ pub enum Request {
     Ping(Vec<PeerStatus>),
     Pong(Vec<PeerStatus>),
     Leave(Vec<PeerStatus>),
+    Evict(Vec<u32>),
 }
 
 impl Request {
     const PING: u8 = 0;
     const PONG: u8 = 1;
     const LEAVE: u8 = 2;
+    const EVICT: u8 = 3;
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Request> {
         let mut it = bytes.iter();
         let flags = it.next().copied()?;
+        if flags == Self::EVICT {
+            let mut account_ids = Vec::with_capacity(bytes.len() - 1);
+            while !it.as_slice().is_empty() {
+                account_ids.push(u32::from_leb128_it(&mut it)?);
+            }
+            return Request::Evict(account_ids).into();
+        }
         let is_ipv6 = flags & (1 << 7) != 0;
 
         let mut peers = Vec::with_capacity(bytes.len() / std::mem::size_of::<PeerStatus>());
@@ -73,6 +82,17 @@ impl Request {
             Request::Ping(peers) => (Self::PING, peers),
             Request::Pong(peers) => (Self::PONG, peers),
             Request::Leave(peers) => (Self::LEAVE, peers),
+            Request::Evict(account_ids) => {
+                let mut bytes = Vec::with_capacity(
+                    1 + (account_ids.len() * std::mem::size_of::<u32>())
+                        + SymmetricEncrypt::ENCRYPT_TAG_LEN,
+                );
+                bytes.push(Self::EVICT);
+                for account_id in account_ids {
+                    account_id.to_leb128_bytes(&mut bytes);
+                }
+                return bytes;
+            }
         };
 
         debug_assert!(!peers.is_empty());
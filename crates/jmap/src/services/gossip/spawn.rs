@@ -90,6 +90,9 @@ impl GossiperBuilder {
             LocalPort = self.port,
         );
 
+        // Evictions are gossiped from now on
+        inner.data.directory_sync.lock().enable_gossip();
+
         // Create gossiper
         let (gossip_tx, mut gossip_rx) = mpsc::channel::<(SocketAddr, Request)>(IPC_CHANNEL_BUFFER);
         let mut gossiper = Gossiper {
@@ -165,6 +168,9 @@ impl GossiperBuilder {
                                                 Request::Leave(peers) => {
                                                     gossiper.handle_leave(peers).await;
                                                 },
+                                                Request::Evict(account_ids) => {
+                                                    gossiper.handle_evict(addr.ip(), account_ids);
+                                                },
                                             }
                                         } else {
                                             trc::event!(
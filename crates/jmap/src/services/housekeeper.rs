@@ -11,7 +11,7 @@ use std::{
 };
 
 use common::{
-    config::telemetry::OtelMetrics,
+    config::{scheduler::TaskType, telemetry::OtelMetrics},
     core::BuildServer,
     ipc::{HousekeeperEvent, PurgeType},
     Inner,
@@ -29,7 +29,12 @@ use tokio::sync::mpsc;
 use trc::{Collector, MetricType};
 use utils::map::ttl_dashmap::TtlMap;
 
-use crate::{email::delete::EmailDeletion, JmapMethods, LONG_SLUMBER};
+use crate::{
+    api::management::notify::DirectoryNotifications, email::delete::EmailDeletion, JmapMethods,
+    LONG_SLUMBER,
+};
+
+use super::scheduler::TaskScheduler;
 
 #[derive(PartialEq, Eq)]
 struct Action {
@@ -40,8 +45,15 @@ struct Action {
 #[derive(PartialEq, Eq, Debug)]
 enum ActionClass {
     Session,
+    SessionHeartbeat,
+    VolumeStats,
+    Backpressure,
+    DirectoryNotify,
+    DirectorySync(String),
+    CertificateWatch,
     Account,
     Store(usize),
+    Task(TaskType),
     Acme(String),
     OtelMetrics,
     #[cfg(feature = "enterprise")]
@@ -77,6 +89,51 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                 ActionClass::Session,
             );
 
+            // Session limit counters
+            queue.schedule(
+                Instant::now() + server.core.session_limits.heartbeat,
+                ActionClass::SessionHeartbeat,
+            );
+
+            // Message volume statistics
+            if server.core.volume.enable {
+                queue.schedule(
+                    Instant::now() + server.core.volume.flush_interval,
+                    ActionClass::VolumeStats,
+                );
+            }
+
+            // Load shedding
+            if server.core.backpressure.is_enabled() {
+                queue.schedule(
+                    Instant::now() + server.core.backpressure.interval,
+                    ActionClass::Backpressure,
+                );
+            }
+
+            // Directory notifications
+            if server.core.directory_notify.is_enabled() {
+                queue.schedule(
+                    Instant::now() + server.core.directory_notify.batch_interval,
+                    ActionClass::DirectoryNotify,
+                );
+            }
+
+            // External directory synchronization
+            for (id, directory) in &server.core.storage.directories {
+                if let Some(sync) = &directory.sync {
+                    queue.schedule(
+                        Instant::now() + sync.interval,
+                        ActionClass::DirectorySync(id.clone()),
+                    );
+                }
+            }
+
+            // Certificate file changes
+            if let Some(interval) = server.core.network.certificate_watch {
+                queue.schedule(Instant::now() + interval, ActionClass::CertificateWatch);
+            }
+
             // Account purge
             queue.schedule(
                 Instant::now() + server.core.jmap.account_purge_frequency.time_to_next(),
@@ -91,6 +148,13 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                 );
             }
 
+            // Scheduled tasks
+            for (task, schedule) in &server.core.scheduler.tasks {
+                if let Some(time_to_next) = schedule.time_to_next() {
+                    queue.schedule(Instant::now() + time_to_next, ActionClass::Task(*task));
+                }
+            }
+
             // OTEL Push Metrics
             if let Some(otel) = &server.core.metrics.otel {
                 OtelMetrics::enable_errors();
@@ -168,6 +232,63 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                             _ => {}
                         }
 
+                        // Enable message volume statistics
+                        if server.core.volume.enable && !queue.has_action(&ActionClass::VolumeStats)
+                        {
+                            queue.schedule(
+                                Instant::now() + server.core.volume.flush_interval,
+                                ActionClass::VolumeStats,
+                            );
+                        }
+
+                        // Enable load shedding, it is disabled on the next evaluation
+                        if server.core.backpressure.is_enabled()
+                            && !queue.has_action(&ActionClass::Backpressure)
+                        {
+                            queue.schedule(
+                                Instant::now() + server.core.backpressure.interval,
+                                ActionClass::Backpressure,
+                            );
+                        }
+
+                        if server.core.directory_notify.is_enabled()
+                            && !queue.has_action(&ActionClass::DirectoryNotify)
+                        {
+                            queue.schedule(
+                                Instant::now() + server.core.directory_notify.batch_interval,
+                                ActionClass::DirectoryNotify,
+                            );
+                        }
+
+                        // Enable external directory synchronization
+                        for (id, directory) in &server.core.storage.directories {
+                            let action = ActionClass::DirectorySync(id.clone());
+                            if let Some(sync) = &directory.sync {
+                                if !queue.has_action(&action) {
+                                    queue.schedule(Instant::now() + sync.interval, action);
+                                }
+                            }
+                        }
+
+                        // Enable certificate file watching
+                        if let Some(interval) = server.core.network.certificate_watch {
+                            if !queue.has_action(&ActionClass::CertificateWatch) {
+                                queue.schedule(
+                                    Instant::now() + interval,
+                                    ActionClass::CertificateWatch,
+                                );
+                            }
+                        }
+
+                        // Reload scheduled tasks
+                        for (task, schedule) in &server.core.scheduler.tasks {
+                            let action = ActionClass::Task(*task);
+                            queue.remove_action(&action);
+                            if let Some(time_to_next) = schedule.time_to_next() {
+                                queue.schedule(Instant::now() + time_to_next, action);
+                            }
+                        }
+
                         // SPDX-SnippetBegin
                         // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
                         // SPDX-License-Identifier: LicenseRef-SEL
@@ -401,12 +522,19 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                 tokio::spawn(async move {
                                     trc::event!(Housekeeper(trc::HousekeeperEvent::PurgeSessions));
                                     server.inner.data.http_auth_cache.cleanup();
+                                    server.inner.data.principal_names.cleanup();
                                     server
                                         .inner
                                         .data
                                         .jmap_limiter
                                         .retain(|_, limiter| limiter.is_active());
+                                    server
+                                        .inner
+                                        .data
+                                        .api_key_limiter
+                                        .retain(|_, limiter| limiter.is_active());
                                     server.inner.data.access_tokens.cleanup();
+                                    server.inner.data.sieve_list_cache.cleanup();
 
                                     for throttle in [
                                         &server.inner.data.smtp_session_throttle,
@@ -418,6 +546,137 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     }
                                 });
                             }
+                            ActionClass::SessionHeartbeat => {
+                                let server = server.clone();
+                                queue.schedule(
+                                    Instant::now() + server.core.session_limits.heartbeat,
+                                    ActionClass::SessionHeartbeat,
+                                );
+
+                                tokio::spawn(async move {
+                                    if let Err(err) = server.session_heartbeat().await {
+                                        trc::error!(
+                                            err.details("Failed to refresh session counters")
+                                        );
+                                    }
+                                    server.sync_directory_changes().await;
+                                });
+                            }
+                            ActionClass::VolumeStats => {
+                                let server = server.clone();
+                                if server.core.volume.enable {
+                                    queue.schedule(
+                                        Instant::now() + server.core.volume.flush_interval,
+                                        ActionClass::VolumeStats,
+                                    );
+                                }
+
+                                tokio::spawn(async move {
+                                    if let Err(err) = server.flush_volume_stats().await {
+                                        trc::error!(err
+                                            .details("Failed to flush message volume statistics"));
+                                    }
+                                });
+                            }
+                            ActionClass::Backpressure => {
+                                let server = server.clone();
+                                if server.core.backpressure.is_enabled() {
+                                    queue.schedule(
+                                        Instant::now() + server.core.backpressure.interval,
+                                        ActionClass::Backpressure,
+                                    );
+                                }
+
+                                tokio::spawn(async move {
+                                    let memory = if server.core.backpressure.memory.is_some() {
+                                        tokio::task::spawn_blocking(memory_stats::memory_stats)
+                                            .await
+                                            .ok()
+                                            .flatten()
+                                            .map(|stats| stats.physical_mem as u64)
+                                    } else {
+                                        None
+                                    };
+
+                                    if let Err(err) = server.update_load_shedding(memory).await {
+                                        trc::error!(
+                                            err.details("Failed to evaluate load shedding signals")
+                                        );
+                                    }
+                                });
+                            }
+                            ActionClass::DirectoryNotify => {
+                                let server = server.clone();
+                                if server.core.directory_notify.is_enabled() {
+                                    queue.schedule(
+                                        Instant::now()
+                                            + server.core.directory_notify.batch_interval,
+                                        ActionClass::DirectoryNotify,
+                                    );
+                                }
+
+                                tokio::spawn(async move {
+                                    if let Err(err) = server.send_directory_alerts().await {
+                                        trc::error!(
+                                            err.details("Failed to send directory notifications")
+                                        );
+                                    }
+                                });
+                            }
+                            ActionClass::CertificateWatch => {
+                                let server = server.clone();
+                                if let Some(interval) = server.core.network.certificate_watch {
+                                    queue.schedule(
+                                        Instant::now() + interval,
+                                        ActionClass::CertificateWatch,
+                                    );
+                                }
+
+                                tokio::spawn(async move {
+                                    if let Err(err) = server.watch_certificates().await {
+                                        trc::error!(
+                                            err.details("Failed to check certificate files")
+                                        );
+                                    }
+                                });
+                            }
+                            ActionClass::DirectorySync(id) => {
+                                let Some(directory) = server
+                                    .core
+                                    .storage
+                                    .directories
+                                    .get(&id)
+                                    .filter(|d| d.sync.is_some())
+                                    .cloned()
+                                else {
+                                    continue;
+                                };
+                                if let Some(sync) = &directory.sync {
+                                    queue.schedule(
+                                        Instant::now() + sync.interval,
+                                        ActionClass::DirectorySync(id.clone()),
+                                    );
+                                }
+
+                                tokio::spawn(async move {
+                                    match directory.synchronize(&id).await {
+                                        Ok(result) => {
+                                            trc::event!(
+                                                Housekeeper(trc::HousekeeperEvent::DirectorySync),
+                                                Id = id,
+                                                Created = result.created,
+                                                Updated = result.updated,
+                                                Removed = result.removed,
+                                            );
+                                        }
+                                        Err(err) => {
+                                            trc::error!(err
+                                                .ctx(trc::Key::Id, id)
+                                                .details("Failed to synchronize directory"));
+                                        }
+                                    }
+                                });
+                            }
                             ActionClass::Store(idx) => {
                                 if let Some(schedule) =
                                     server.core.storage.purge_schedules.get(idx).cloned()
@@ -457,6 +716,25 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     });
                                 }
                             }
+                            ActionClass::Task(task) => {
+                                if let Some(time_to_next) = server
+                                    .core
+                                    .scheduler
+                                    .tasks
+                                    .get(&task)
+                                    .and_then(|schedule| schedule.time_to_next())
+                                {
+                                    queue.schedule(
+                                        Instant::now() + time_to_next,
+                                        ActionClass::Task(task),
+                                    );
+
+                                    let server = server.clone();
+                                    tokio::spawn(async move {
+                                        server.run_task(task).await;
+                                    });
+                                }
+                            }
                             ActionClass::OtelMetrics => {
                                 if let Some(otel) = &server.core.metrics.otel {
                                     queue.schedule(
@@ -513,31 +791,30 @@ pub fn spawn_housekeeper(inner: Arc<Inner>, mut rx: mpsc::Receiver<HousekeeperEv
                                     }
 
                                     if update_other_metrics {
-                                        match server.total_accounts().await {
-                                            Ok(total) => {
-                                                Collector::update_gauge(
-                                                    MetricType::UserCount,
-                                                    total,
-                                                );
+                                        match server.total_principals().await {
+                                            Ok(totals) => {
+                                                for (metric_type, typ) in [
+                                                    (
+                                                        MetricType::UserCount,
+                                                        directory::Type::Individual,
+                                                    ),
+                                                    (
+                                                        MetricType::DomainCount,
+                                                        directory::Type::Domain,
+                                                    ),
+                                                ] {
+                                                    Collector::update_gauge(
+                                                        metric_type,
+                                                        totals
+                                                            .get(&typ)
+                                                            .copied()
+                                                            .unwrap_or_default(),
+                                                    );
+                                                }
                                             }
                                             Err(err) => {
-                                                trc::error!(
-                                                    err.details("Failed to obtain account count")
-                                                );
-                                            }
-                                        }
-
-                                        match server.total_domains().await {
-                                            Ok(total) => {
-                                                Collector::update_gauge(
-                                                    MetricType::DomainCount,
-                                                    total,
-                                                );
-                                            }
-                                            Err(err) => {
-                                                trc::error!(
-                                                    err.details("Failed to obtain domain count")
-                                                );
+                                                trc::error!(err
+                                                    .details("Failed to obtain principal counts"));
                                             }
                                         }
                                     }
@@ -24,13 +24,15 @@ use store::{
 };
 
 use std::future::Future;
-use trc::{AddContext, FtsIndexEvent};
+use tokio::task::JoinSet;
+use trc::{AddContext, Collector, FtsIndexEvent, MetricType};
 use utils::{BlobHash, BLOB_HASH_LEN};
 
 use crate::{
     blob::download::BlobDownload,
     changes::write::ChangeLog,
     email::{index::IndexMessageText, metadata::MessageMetadata},
+    mailbox::TOMBSTONE_ID,
     JmapMethods,
 };
 
@@ -43,7 +45,20 @@ pub struct IndexEmail {
     insert_hash: BlobHash,
 }
 
+#[derive(Debug, Default)]
+pub struct IndexVerification {
+    pub account_id: u32,
+    pub stored: u64,
+    pub indexed: u64,
+    pub pending: u64,
+    pub missing: RoaringBitmap,
+    pub orphaned: RoaringBitmap,
+    pub rebuilt: u64,
+    pub failed: u64,
+}
+
 const INDEX_LOCK_EXPIRY: u64 = 60 * 5;
+const REBUILD_PROGRESS_INTERVAL: u64 = 100;
 
 pub fn spawn_index_task(inner: Arc<Inner>) {
     tokio::spawn(async move {
@@ -66,6 +81,22 @@ pub trait Indexer: Sync + Send {
         account_id: Option<u32>,
         tenant_id: Option<u32>,
     ) -> impl Future<Output = trc::Result<()>> + Send;
+    fn verify_index(
+        &self,
+        account_id: u32,
+        repair: bool,
+    ) -> impl Future<Output = trc::Result<IndexVerification>> + Send;
+    fn verify_index_all(
+        &self,
+        tenant_id: Option<u32>,
+        repair: bool,
+    ) -> impl Future<Output = trc::Result<Vec<IndexVerification>>> + Send;
+    fn index_email(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        metadata: MessageMetadata,
+    ) -> impl Future<Output = bool> + Send;
     fn request_fts_index(&self);
 }
 
@@ -92,6 +123,7 @@ impl Indexer for Server {
 
         // Retrieve entries pending to be indexed
         let mut entries = Vec::new();
+        let mut queued = 0;
         let now = now();
         let _ = self
             .core
@@ -101,6 +133,7 @@ impl Indexer for Server {
                 IterateParams::new(from_key, to_key).ascending(),
                 |key, value| {
                     let event = IndexEmail::deserialize(key, value)?;
+                    queued += 1;
 
                     if event.lock_expiry < now {
                         entries.push(event);
@@ -124,8 +157,9 @@ impl Indexer for Server {
             });
 
         // Add entries to the index
+        let mut pending = queued;
+        Collector::update_gauge(MetricType::FtsIndexLag, pending);
         for event in entries {
-            let op_start = Instant::now();
             // Lock index
             if !self.try_lock_index(&event).await {
                 continue;
@@ -143,46 +177,12 @@ impl Indexer for Server {
                 Ok(Some(metadata))
                     if metadata.inner.blob_hash.as_slice() == event.insert_hash.as_slice() =>
                 {
-                    // Obtain raw message
-                    let raw_message = if let Ok(Some(raw_message)) = self
-                        .get_blob(&metadata.inner.blob_hash, 0..usize::MAX)
+                    if !self
+                        .index_email(event.account_id, event.document_id, metadata.inner)
                         .await
                     {
-                        raw_message
-                    } else {
-                        trc::event!(
-                            FtsIndex(FtsIndexEvent::BlobNotFound),
-                            AccountId = event.account_id,
-                            DocumentId = event.document_id,
-                            BlobId = metadata.inner.blob_hash.to_hex(),
-                        );
-                        continue;
-                    };
-                    let message = metadata.inner.contents.into_message(&raw_message);
-
-                    // Index message
-                    let document =
-                        FtsDocument::with_default_language(self.core.jmap.default_language)
-                            .with_account_id(event.account_id)
-                            .with_collection(Collection::Email)
-                            .with_document_id(event.document_id)
-                            .index_message(&message);
-                    if let Err(err) = self.core.storage.fts.index(document).await {
-                        trc::error!(err
-                            .account_id(event.account_id)
-                            .document_id(event.document_id)
-                            .details("Failed to index email in FTS index"));
-
                         continue;
                     }
-
-                    trc::event!(
-                        FtsIndex(FtsIndexEvent::Index),
-                        AccountId = event.account_id,
-                        Collection = Collection::Email,
-                        DocumentId = event.document_id,
-                        Elapsed = op_start.elapsed(),
-                    );
                 }
 
                 Err(err) => {
@@ -226,7 +226,60 @@ impl Indexer for Server {
 
                 break;
             }
+
+            pending -= 1;
+            Collector::update_gauge(MetricType::FtsIndexLag, pending);
+        }
+    }
+
+    async fn index_email(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        metadata: MessageMetadata<'_>,
+    ) -> bool {
+        let op_start = Instant::now();
+
+        // Obtain raw message
+        let raw_message = if let Ok(Some(raw_message)) =
+            self.get_blob(&metadata.blob_hash, 0..usize::MAX).await
+        {
+            raw_message
+        } else {
+            trc::event!(
+                FtsIndex(FtsIndexEvent::BlobNotFound),
+                AccountId = account_id,
+                DocumentId = document_id,
+                BlobId = metadata.blob_hash.to_hex(),
+            );
+            return false;
+        };
+        let message = metadata.contents.into_message(&raw_message);
+
+        // Index message
+        let document = FtsDocument::with_default_language(self.core.jmap.default_language)
+            .with_account_id(account_id)
+            .with_collection(Collection::Email)
+            .with_document_id(document_id)
+            .index_message(&message);
+        if let Err(err) = self.core.storage.fts.index(document).await {
+            trc::error!(err
+                .account_id(account_id)
+                .document_id(document_id)
+                .details("Failed to index email in FTS index"));
+
+            return false;
         }
+
+        trc::event!(
+            FtsIndex(FtsIndexEvent::Index),
+            AccountId = account_id,
+            Collection = Collection::Email,
+            DocumentId = document_id,
+            Elapsed = op_start.elapsed(),
+        );
+
+        true
     }
 
     async fn try_lock_index(&self, event: &IndexEmail) -> bool {
@@ -268,26 +321,7 @@ impl Indexer for Server {
         let accounts = if let Some(account_id) = account_id {
             RoaringBitmap::from_sorted_iter([account_id]).unwrap()
         } else {
-            let mut accounts = RoaringBitmap::new();
-            for principal in self
-                .core
-                .storage
-                .data
-                .list_principals(
-                    None,
-                    tenant_id,
-                    &[Type::Individual, Type::Group],
-                    &[PrincipalField::Name],
-                    0,
-                    0,
-                )
-                .await
-                .caused_by(trc::location!())?
-                .items
-            {
-                accounts.insert(principal.id());
-            }
-            accounts
+            list_accounts(self, tenant_id).await?
         };
 
         // Validate linked blobs
@@ -373,6 +407,239 @@ impl Indexer for Server {
 
         Ok(())
     }
+
+    async fn verify_index(&self, account_id: u32, repair: bool) -> trc::Result<IndexVerification> {
+        let op_start = Instant::now();
+
+        // The index is read first so that messages added while the
+        // verification runs are never reported as orphaned
+        let indexed = self
+            .core
+            .storage
+            .fts
+            .indexed_documents(account_id, Collection::Email)
+            .await
+            .caused_by(trc::location!())?;
+        let document_ids = self
+            .get_document_ids(account_id, Collection::Email)
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default();
+        let tombstoned = self
+            .get_tag(
+                account_id,
+                Collection::Email,
+                Property::MailboxIds,
+                TOMBSTONE_ID,
+            )
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default();
+        let pending = queued_documents(self, account_id).await?;
+
+        // Messages pending deletion are removed from the index when purged
+        let mut missing = &document_ids - &indexed;
+        missing -= &tombstoned;
+        missing -= &pending;
+        let mut report = IndexVerification {
+            account_id,
+            stored: (&document_ids - &tombstoned).len(),
+            indexed: indexed.len(),
+            pending: pending.len(),
+            orphaned: &indexed - &document_ids,
+            missing,
+            ..Default::default()
+        };
+
+        if !report.missing.is_empty() {
+            trc::event!(
+                FtsIndex(FtsIndexEvent::MissingEntries),
+                AccountId = account_id,
+                Total = report.missing.len(),
+            );
+        }
+        if !report.orphaned.is_empty() {
+            trc::event!(
+                FtsIndex(FtsIndexEvent::OrphanedEntries),
+                AccountId = account_id,
+                Total = report.orphaned.len(),
+            );
+        }
+
+        if repair {
+            if !report.orphaned.is_empty() {
+                self.core
+                    .storage
+                    .fts
+                    .remove(account_id, Collection::Email.into(), &report.orphaned)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+
+            // Re-extract the text of missing messages from their blobs
+            let total = report.missing.len();
+            let mut tasks = JoinSet::new();
+            let mut done = 0;
+            let mut documents = report.missing.iter();
+            loop {
+                while tasks.len() < self.core.jmap.fts_rebuild_concurrency {
+                    if let Some(document_id) = documents.next() {
+                        tasks.spawn(rebuild_document(self.clone(), account_id, document_id));
+                    } else {
+                        break;
+                    }
+                }
+
+                match tasks.join_next().await {
+                    Some(Ok(Some(true))) => report.rebuilt += 1,
+                    Some(Ok(Some(false)) | Err(_)) => report.failed += 1,
+                    Some(Ok(None)) => {}
+                    None => break,
+                }
+
+                done += 1;
+                if done % REBUILD_PROGRESS_INTERVAL == 0 || done == total {
+                    trc::event!(
+                        FtsIndex(FtsIndexEvent::RebuildProgress),
+                        AccountId = account_id,
+                        Value = done,
+                        Total = total,
+                    );
+                }
+            }
+        }
+
+        trc::event!(
+            FtsIndex(FtsIndexEvent::VerifyCompleted),
+            AccountId = account_id,
+            Total = report.stored,
+            Elapsed = op_start.elapsed(),
+        );
+
+        Ok(report)
+    }
+
+    async fn verify_index_all(
+        &self,
+        tenant_id: Option<u32>,
+        repair: bool,
+    ) -> trc::Result<Vec<IndexVerification>> {
+        let op_start = Instant::now();
+        let accounts = list_accounts(self, tenant_id).await?;
+        let mut reports = Vec::new();
+
+        for account_id in &accounts {
+            let report = self.verify_index(account_id, repair).await?;
+            if !report.missing.is_empty() || !report.orphaned.is_empty() {
+                reports.push(report);
+            }
+        }
+
+        trc::event!(
+            FtsIndex(FtsIndexEvent::VerifyCompleted),
+            Total = accounts.len(),
+            Elapsed = op_start.elapsed(),
+        );
+
+        Ok(reports)
+    }
+}
+
+pub(crate) async fn list_accounts(
+    server: &Server,
+    tenant_id: Option<u32>,
+) -> trc::Result<RoaringBitmap> {
+    let mut accounts = RoaringBitmap::new();
+    for principal in server
+        .core
+        .storage
+        .data
+        .list_principals(
+            None,
+            tenant_id,
+            &[Type::Individual, Type::Group],
+            &[PrincipalField::Name],
+            0,
+            0,
+        )
+        .await
+        .caused_by(trc::location!())?
+        .items
+    {
+        accounts.insert(principal.id());
+    }
+    Ok(accounts)
+}
+
+async fn queued_documents(server: &Server, account_id: u32) -> trc::Result<RoaringBitmap> {
+    let mut document_ids = RoaringBitmap::new();
+    server
+        .core
+        .storage
+        .data
+        .iterate(
+            IterateParams::new(
+                ValueKey::<ValueClass<u32>> {
+                    account_id: 0,
+                    collection: 0,
+                    document_id: 0,
+                    class: ValueClass::FtsQueue(FtsQueueClass {
+                        seq: 0,
+                        hash: BlobHash::default(),
+                    }),
+                },
+                ValueKey::<ValueClass<u32>> {
+                    account_id: u32::MAX,
+                    collection: u8::MAX,
+                    document_id: u32::MAX,
+                    class: ValueClass::FtsQueue(FtsQueueClass {
+                        seq: u64::MAX,
+                        hash: BlobHash::default(),
+                    }),
+                },
+            ),
+            |key, value| {
+                let event = IndexEmail::deserialize(key, value)?;
+                if event.account_id == account_id {
+                    document_ids.insert(event.document_id);
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    Ok(document_ids)
+}
+
+// Returns None if the message was deleted before it could be indexed
+async fn rebuild_document(server: Server, account_id: u32, document_id: u32) -> Option<bool> {
+    match server
+        .get_property::<Bincode<MessageMetadata>>(
+            account_id,
+            Collection::Email,
+            document_id,
+            Property::BodyStructure,
+        )
+        .await
+    {
+        Ok(Some(metadata)) => Some(
+            server
+                .index_email(account_id, document_id, metadata.inner)
+                .await,
+        ),
+        Ok(None) => None,
+        Err(err) => {
+            trc::error!(err
+                .account_id(account_id)
+                .document_id(document_id)
+                .caused_by(trc::location!())
+                .details("Failed to retrieve email metadata"));
+
+            Some(false)
+        }
+    }
 }
 
 impl IndexEmail {
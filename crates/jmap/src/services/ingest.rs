@@ -68,34 +68,45 @@ impl MailDelivery for Server {
             }
         };
 
-        // Obtain the UIDs for each recipient
+        // Obtain the UIDs of all recipients at once
+        let rcpt_uids = match self
+            .email_to_ids(
+                &self.core.storage.directory,
+                &message
+                    .recipients
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>(),
+                message.session_id,
+            )
+            .await
+        {
+            Ok(rcpt_uids) => rcpt_uids,
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to lookup recipients.")
+                    .ctx(trc::Key::To, message.recipients.clone())
+                    .span_id(message.session_id)
+                    .caused_by(trc::location!()));
+
+                return (0..message.recipients.len())
+                    .map(|_| DeliveryResult::TemporaryFailure {
+                        reason: "Address lookup failed.".into(),
+                    })
+                    .collect::<Vec<_>>();
+            }
+        };
+
         let mut uids: AHashMap<u32, usize> = AHashMap::with_capacity(message.recipients.len());
         let mut results = Vec::with_capacity(message.recipients.len());
-        for rcpt in message.recipients {
-            let uid = match self
-                .email_to_id(&self.core.storage.directory, &rcpt, message.session_id)
-                .await
-            {
-                Ok(Some(uid)) => uid,
-                Ok(None) => {
-                    // Something went wrong
-                    results.push(DeliveryResult::PermanentFailure {
-                        code: [5, 5, 0],
-                        reason: "Mailbox not found.".into(),
-                    });
-                    continue;
-                }
-                Err(err) => {
-                    trc::error!(err
-                        .details("Failed to lookup recipient.")
-                        .ctx(trc::Key::To, rcpt)
-                        .span_id(message.session_id)
-                        .caused_by(trc::location!()));
-                    results.push(DeliveryResult::TemporaryFailure {
-                        reason: "Address lookup failed.".into(),
-                    });
-                    continue;
-                }
+        for (rcpt, uid) in message.recipients.into_iter().zip(rcpt_uids) {
+            let Some(uid) = uid else {
+                // Something went wrong
+                results.push(DeliveryResult::PermanentFailure {
+                    code: [5, 5, 0],
+                    reason: "Mailbox not found.".into(),
+                });
+                continue;
             };
             if let Some(result) = uids.get(&uid).and_then(|pos| results.get(*pos)) {
                 results.push(result.clone());
@@ -0,0 +1,88 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Instant};
+
+use common::{config::migration::MigrationType, Server};
+use directory::backend::internal::MigrateDirectory;
+use store::write::migrate::MigrationProgress;
+
+pub trait MigrationManager: Sync + Send {
+    fn run_migrations(&self) -> impl Future<Output = ()> + Send;
+
+    fn run_migration(
+        &self,
+        migration: MigrationType,
+    ) -> impl Future<Output = trc::Result<Option<MigrationProgress>>> + Send;
+}
+
+impl MigrationManager for Server {
+    async fn run_migrations(&self) {
+        // Later migrations may depend on earlier ones, stop at the first one
+        // that did not finish on this node
+        for migration in MigrationType::all() {
+            match self.run_migration(*migration).await {
+                Ok(Some(progress)) if progress.finished_at.is_some() => {}
+                _ => break,
+            }
+        }
+    }
+
+    async fn run_migration(
+        &self,
+        migration: MigrationType,
+    ) -> trc::Result<Option<MigrationProgress>> {
+        let store = &self.core.storage.data;
+        let config = &self.core.migrations;
+        let id = migration.as_str();
+
+        if let Some(progress) = store
+            .migration_progress(id)
+            .await?
+            .filter(|progress| progress.finished_at.is_some())
+        {
+            return Ok(Some(progress));
+        }
+
+        trc::event!(
+            Housekeeper(trc::HousekeeperEvent::MigrationStarted),
+            Id = id
+        );
+
+        let time = Instant::now();
+        let result = store
+            .run_migration(id, config.lock_expiry.as_secs(), |from| async move {
+                match migration {
+                    MigrationType::PrincipalInfoV2 => {
+                        store.migrate_principal_info(from, config.chunk_size).await
+                    }
+                }
+            })
+            .await;
+
+        match &result {
+            Ok(Some(progress)) => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::MigrationCompleted),
+                    Id = id,
+                    Total = progress.processed,
+                    Details = progress.migrated,
+                    Elapsed = time.elapsed(),
+                );
+            }
+            Ok(None) => {}
+            Err(err) => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::MigrationFailed),
+                    Id = id,
+                    CausedBy = err.clone(),
+                );
+            }
+        }
+
+        result
+    }
+}
@@ -5,8 +5,14 @@
  */
 
 pub mod delivery;
+pub mod expire;
 pub mod gossip;
 pub mod housekeeper;
 pub mod index;
 pub mod ingest;
+pub mod migration;
+pub mod provision;
+pub mod quota;
+pub mod replication;
+pub mod scheduler;
 pub mod state;
@@ -0,0 +1,227 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    auth::AccessToken,
+    templates::{TemplateKind, TemplateVariable},
+    Server,
+};
+use directory::{backend::internal::PrincipalField, QueryBy};
+use jmap_proto::{
+    object::{index::ObjectIndexBuilder, Object},
+    types::{blob::BlobId, collection::Collection, property::Property, value::Value},
+};
+use mail_builder::{headers::HeaderType, mime::make_boundary, MessageBuilder};
+use mail_parser::MessageParser;
+use smtp::queue::RecipientDomain;
+use store::{
+    write::{log::LogInsert, BatchBuilder, BlobOp, DirectoryClass},
+    BlobClass,
+};
+use trc::AddContext;
+
+use crate::{
+    blob::upload::BlobUpload,
+    email::ingest::{EmailIngest, IngestEmail, IngestSource},
+    mailbox::INBOX_ID,
+    sieve::set::{SieveScriptSet, SCHEMA},
+    JmapMethods,
+};
+
+pub trait AccountProvisioning: Sync + Send {
+    fn provision_account(&self, account_id: u32) -> impl Future<Output = ()> + Send;
+}
+
+trait AccountProvisioningSteps: Sync + Send {
+    fn deliver_welcome_message(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn install_default_sieve(
+        &self,
+        account_id: u32,
+        name: &str,
+        script: &str,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl AccountProvisioning for Server {
+    async fn provision_account(&self, account_id: u32) {
+        // Failures are logged rather than reported, provisioning must never block access
+        if self.core.jmap.provision_welcome {
+            if let Err(err) = self.deliver_welcome_message(account_id).await {
+                trc::error!(err
+                    .account_id(account_id)
+                    .details("Failed to deliver welcome message")
+                    .caused_by(trc::location!()));
+            }
+        }
+
+        if let Some((name, script)) = &self.core.jmap.provision_sieve {
+            if let Err(err) = self.install_default_sieve(account_id, name, script).await {
+                trc::error!(err
+                    .account_id(account_id)
+                    .details("Failed to install default Sieve script")
+                    .caused_by(trc::location!()));
+            }
+        }
+    }
+}
+
+impl AccountProvisioningSteps for Server {
+    async fn deliver_welcome_message(&self, account_id: u32) -> trc::Result<()> {
+        let principal = self
+            .core
+            .storage
+            .directory
+            .query(QueryBy::Id(account_id), false)
+            .await
+            .caused_by(trc::location!())?
+            .ok_or_else(|| trc::ManageEvent::NotFound.into_err())?;
+        let email = match principal.iter_str(PrincipalField::Emails).next() {
+            Some(email) => email.as_str(),
+            None => return Ok(()),
+        };
+        let domain = email
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain);
+        let hostname = self
+            .eval_if(
+                &self.core.smtp.report.submitter,
+                &RecipientDomain::new(domain),
+                0,
+            )
+            .await
+            .unwrap_or_else(|| "localhost".to_string());
+
+        // Tenant templates take precedence over the global one
+        let template = self
+            .template(principal.tenant(), TemplateKind::Welcome)
+            .await
+            .render(&[
+                (
+                    TemplateVariable::Name,
+                    principal.description().unwrap_or(principal.name()),
+                ),
+                (TemplateVariable::Email, email),
+                (TemplateVariable::Hostname, hostname.as_str()),
+            ]);
+        let from_address = format!("postmaster@{domain}");
+        let message = MessageBuilder::new()
+            .from((
+                template.from_name.as_deref().unwrap_or("Postmaster"),
+                from_address.as_str(),
+            ))
+            .to(email)
+            .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
+            .message_id(format!("<{}@{hostname}>", make_boundary(".")))
+            .subject(template.subject)
+            .text_body(template.body)
+            .write_to_vec()
+            .unwrap_or_default();
+
+        self.email_ingest(IngestEmail {
+            raw_message: &message,
+            message: MessageParser::new().parse(&message),
+            resource: self
+                .get_resource_token(&AccessToken::from_id(u32::MAX), account_id)
+                .await
+                .caused_by(trc::location!())?,
+            mailbox_ids: vec![INBOX_ID],
+            keywords: vec![],
+            received_at: None,
+            source: IngestSource::Smtp,
+            encrypt: false,
+            session_id: 0,
+        })
+        .await
+        .map(|_| ())
+    }
+
+    async fn install_default_sieve(
+        &self,
+        account_id: u32,
+        name: &str,
+        script: &str,
+    ) -> trc::Result<()> {
+        let mut script_bytes = script.as_bytes().to_vec();
+        let script_size = script_bytes.len() as i64;
+        let compiled_script = self
+            .core
+            .sieve
+            .untrusted_compiler
+            .compile(&script_bytes)
+            .map_err(|err| {
+                trc::StoreEvent::UnexpectedError
+                    .caused_by(trc::location!())
+                    .reason(err)
+                    .details("Default Sieve script failed to compile.")
+            })?;
+        script_bytes.extend(bincode::serialize(&compiled_script).unwrap_or_default());
+
+        // Write script blob
+        let blob_id = BlobId::new(
+            self.put_blob(account_id, &script_bytes, false)
+                .await
+                .caused_by(trc::location!())?
+                .hash,
+            BlobClass::Linked {
+                account_id,
+                collection: Collection::SieveScript.into(),
+                document_id: 0,
+            },
+        )
+        .with_section_size(script_size as usize);
+
+        // Write record
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::SieveScript)
+            .create_document()
+            .log(LogInsert())
+            .add(DirectoryClass::UsedQuota(account_id), script_size)
+            .set(
+                BlobOp::Link {
+                    hash: blob_id.hash.clone(),
+                },
+                Vec::new(),
+            )
+            .custom(
+                ObjectIndexBuilder::new(SCHEMA).with_changes(
+                    Object::with_capacity(3)
+                        .with_property(Property::Name, name.to_string())
+                        .with_property(Property::IsActive, Value::Bool(false))
+                        .with_property(Property::BlobId, Value::BlobId(blob_id)),
+                ),
+            );
+
+        // Update tenant quota
+        #[cfg(feature = "enterprise")]
+        if self.core.is_enterprise_edition() {
+            if let Some(tenant) = self
+                .get_resource_token(&AccessToken::from_id(u32::MAX), account_id)
+                .await
+                .caused_by(trc::location!())?
+                .tenant
+            {
+                batch.add(DirectoryClass::UsedQuota(tenant.id), script_size);
+            }
+        }
+
+        let document_id = self
+            .write_batch(batch)
+            .await
+            .caused_by(trc::location!())?
+            .last_document_id()?;
+        self.sieve_activate_script(account_id, document_id.into())
+            .await
+            .map(|_| ())
+    }
+}
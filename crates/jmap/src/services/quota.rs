@@ -0,0 +1,222 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Instant};
+
+use common::Server;
+use jmap_proto::{
+    object::Object,
+    types::{collection::Collection, property::Property, value::Value},
+};
+use serde::Serialize;
+use store::{
+    write::{key::DeserializeBigEndian, BatchBuilder, DirectoryClass},
+    IndexKey, IterateParams, Serialize as _, U32_LEN,
+};
+use trc::{AddContext, ManageEvent};
+
+use crate::{sieve::set::ObjectBlobId, JmapMethods};
+
+use super::index::list_accounts;
+
+// Attempts made before giving up on an account that is being written to
+const MAX_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaDelta {
+    pub account_id: u32,
+    pub counter: i64,
+    pub actual: i64,
+    pub corrected: bool,
+}
+
+pub trait QuotaRecalculation: Sync + Send {
+    fn recalculate_quota(
+        &self,
+        account_id: u32,
+        dry_run: bool,
+    ) -> impl Future<Output = trc::Result<QuotaDelta>> + Send;
+    fn recalculate_quota_all(
+        &self,
+        tenant_id: Option<u32>,
+        dry_run: bool,
+    ) -> impl Future<Output = trc::Result<Vec<QuotaDelta>>> + Send;
+    fn stored_quota(&self, account_id: u32) -> impl Future<Output = trc::Result<i64>> + Send;
+}
+
+impl QuotaRecalculation for Server {
+    async fn recalculate_quota(&self, account_id: u32, dry_run: bool) -> trc::Result<QuotaDelta> {
+        for _ in 0..MAX_ATTEMPTS {
+            let counter = self.get_used_quota(account_id).await?;
+            let actual = self.stored_quota(account_id).await?;
+
+            // Messages and scripts update the counter in the same batch, if it
+            // did not change the sizes were read from a consistent state
+            if self.get_used_quota(account_id).await? != counter {
+                continue;
+            }
+
+            let mut delta = QuotaDelta {
+                account_id,
+                counter,
+                actual,
+                corrected: false,
+            };
+            if counter != actual {
+                trc::event!(
+                    Manage(ManageEvent::QuotaMismatch),
+                    AccountId = account_id,
+                    Size = actual,
+                    Total = counter,
+                    Result = !dry_run,
+                );
+
+                if !dry_run {
+                    // The difference is added rather than set so that
+                    // concurrent updates are not lost
+                    let mut batch = BatchBuilder::new();
+                    batch.add(DirectoryClass::UsedQuota(account_id), actual - counter);
+
+                    // SPDX-SnippetBegin
+                    // SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+                    // SPDX-License-Identifier: LicenseRef-SEL
+
+                    // Update tenant quota
+                    #[cfg(feature = "enterprise")]
+                    if self.core.is_enterprise_edition() {
+                        if let Some(tenant) = self
+                            .get_cached_access_token(account_id)
+                            .await
+                            .caused_by(trc::location!())?
+                            .tenant
+                        {
+                            batch.add(DirectoryClass::UsedQuota(tenant.id), actual - counter);
+                        }
+                    }
+
+                    // SPDX-SnippetEnd
+
+                    self.core
+                        .storage
+                        .data
+                        .write(batch.build())
+                        .await
+                        .caused_by(trc::location!())?;
+                    delta.corrected = true;
+                }
+            }
+
+            return Ok(delta);
+        }
+
+        Err(trc::StoreEvent::AssertValueFailed
+            .into_err()
+            .account_id(account_id)
+            .details("Quota counter changed during recalculation"))
+    }
+
+    async fn recalculate_quota_all(
+        &self,
+        tenant_id: Option<u32>,
+        dry_run: bool,
+    ) -> trc::Result<Vec<QuotaDelta>> {
+        let op_start = Instant::now();
+        let accounts = list_accounts(self, tenant_id).await?;
+        let mut deltas = Vec::new();
+
+        for account_id in &accounts {
+            match self.recalculate_quota(account_id, dry_run).await {
+                Ok(delta) => {
+                    if delta.counter != delta.actual {
+                        deltas.push(delta);
+                    }
+                }
+                Err(err) if err.is_assertion_failure() => {
+                    // Busy accounts are checked again on the next run
+                    trc::error!(err.caused_by(trc::location!()));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        trc::event!(
+            Manage(ManageEvent::QuotaRecalculated),
+            Total = accounts.len(),
+            Updated = deltas.len(),
+            Elapsed = op_start.elapsed(),
+        );
+
+        Ok(deltas)
+    }
+
+    async fn stored_quota(&self, account_id: u32) -> trc::Result<i64> {
+        let mut total = 0i64;
+
+        // Message sizes are read from the index, tombstoned messages count
+        // until they are purged
+        if let Some(message_ids) = self
+            .get_document_ids(account_id, Collection::Email)
+            .await
+            .caused_by(trc::location!())?
+            .filter(|ids| !ids.is_empty())
+        {
+            self.core
+                .storage
+                .data
+                .iterate(
+                    IterateParams::new(
+                        IndexKey {
+                            account_id,
+                            collection: Collection::Email.into(),
+                            document_id: 0,
+                            field: Property::Size.into(),
+                            key: 0u32.serialize(),
+                        },
+                        IndexKey {
+                            account_id,
+                            collection: Collection::Email.into(),
+                            document_id: u32::MAX,
+                            field: Property::Size.into(),
+                            key: u32::MAX.serialize(),
+                        },
+                    )
+                    .no_values(),
+                    |key, _| {
+                        let document_id = key.deserialize_be_u32(key.len() - U32_LEN)?;
+                        if message_ids.contains(document_id) {
+                            total += key.deserialize_be_u32(key.len() - (U32_LEN * 2))? as i64;
+                        }
+
+                        Ok(true)
+                    },
+                )
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        // Sieve scripts are charged the size of their blob
+        for (_, script) in self
+            .get_properties::<Object<Value>, _, _>(
+                account_id,
+                Collection::SieveScript,
+                &(),
+                Property::Value,
+            )
+            .await
+            .caused_by(trc::location!())?
+        {
+            if let Some(section) = script
+                .blob_id()
+                .and_then(|blob_id| blob_id.section.as_ref())
+            {
+                total += section.size as i64;
+            }
+        }
+
+        Ok(total)
+    }
+}
@@ -0,0 +1,284 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{collections::BTreeMap, future::Future, time::Instant};
+
+use common::{config::replication::ReplicationPeer, Server};
+use directory::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{self, ManageDirectory},
+        PrincipalField,
+    },
+    Principal, QueryBy, Type,
+};
+use hyper::header::CONTENT_TYPE;
+use jmap_proto::types::collection::Collection;
+use serde::{Deserialize, Serialize};
+use store::{
+    query::log::{Change, Query},
+    write::{BatchBuilder, TaskClass, ValueClass},
+    Serialize as _, ValueKey,
+};
+use trc::{AddContext, ClusterEvent};
+
+pub const REPLICATION_CURSOR: &[u8] = b"replication.cursor";
+pub const REPLICATION_CONFIG: &[u8] = b"replication.config";
+pub const REPLICATION_APPLIED: &[u8] = b"replication.applied";
+const REPLICATION_NAME: &[u8] = b"replication.name.";
+
+/// A set of directory and configuration changes sent to a standby server.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicationBatch {
+    pub change_id: u64,
+    pub full_sync: bool,
+    pub principals: Vec<Principal>,
+    pub renames: Vec<(String, String)>,
+    pub deletions: Vec<String>,
+    pub config: Vec<ReplicatedConfig>,
+}
+
+#[derive(Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicatedConfig {
+    pub prefix: String,
+    pub values: BTreeMap<String, String>,
+}
+
+pub trait DirectoryReplication: Sync + Send {
+    fn replicate_directory(&self) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl DirectoryReplication for Server {
+    async fn replicate_directory(&self) -> trc::Result<()> {
+        let peer = self
+            .core
+            .replication
+            .peer
+            .as_ref()
+            .ok_or_else(|| manage::unsupported("No replication peer is configured"))?;
+        let store = &self.core.storage.data;
+        let time = Instant::now();
+
+        // Obtain the last change sent to the peer, the change id is read
+        // before the directory so no changes are missed
+        let cursor = store
+            .get_value::<u64>(state_key(REPLICATION_CURSOR))
+            .await
+            .caused_by(trc::location!())?;
+        let change_id = store
+            .get_last_change_id(u32::MAX, Collection::Principal)
+            .await
+            .caused_by(trc::location!())?
+            .unwrap_or_default();
+        let mut batch = ReplicationBatch {
+            change_id,
+            full_sync: cursor.is_none(),
+            ..Default::default()
+        };
+
+        // Obtain changed principals
+        let mut changed_ids = Vec::new();
+        let mut deleted_ids = Vec::new();
+        if let Some(cursor) = cursor {
+            if change_id != cursor {
+                for change in store
+                    .changes(u32::MAX, Collection::Principal, Query::Since(cursor))
+                    .await
+                    .caused_by(trc::location!())?
+                    .changes
+                {
+                    match change {
+                        Change::Insert(id) | Change::Update(id) | Change::ChildUpdate(id) => {
+                            changed_ids.push(id as u32);
+                        }
+                        Change::Delete(id) => {
+                            deleted_ids.push(id as u32);
+                        }
+                    }
+                }
+            }
+        } else {
+            changed_ids = store
+                .list_principals(None, None, &[], &[PrincipalField::Name], 0, 0)
+                .await
+                .caused_by(trc::location!())?
+                .items
+                .into_iter()
+                .map(|principal| principal.id())
+                .collect();
+        }
+
+        let mut names = Vec::with_capacity(changed_ids.len());
+        for principal_id in changed_ids {
+            let Some(mut principal) = store
+                .query(QueryBy::Id(principal_id), true)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                deleted_ids.push(principal_id);
+                continue;
+            };
+            store
+                .map_field_ids(&mut principal, &[])
+                .await
+                .caused_by(trc::location!())?;
+
+            // Member counts, quota usage, logins and revocations are tracked locally
+            principal.remove(PrincipalField::UsedQuota);
+            principal.remove(PrincipalField::LastLogin);
+            principal.remove(PrincipalField::CredentialsEpoch);
+            if matches!(principal.typ(), Type::Domain | Type::Tenant) {
+                principal.remove(PrincipalField::Members);
+            }
+
+            if let Some(old_name) = cached_name(self, principal_id)
+                .await?
+                .filter(|old_name| old_name != principal.name())
+            {
+                batch.renames.push((old_name, principal.name().to_string()));
+            }
+            names.push((principal_id, principal.name().to_string()));
+            batch.principals.push(principal);
+        }
+        for principal_id in &deleted_ids {
+            if let Some(name) = cached_name(self, *principal_id).await? {
+                batch.deletions.push(name);
+            }
+        }
+
+        // Send configuration only when it changed since the last run
+        for prefix in &peer.config_prefixes {
+            batch.config.push(ReplicatedConfig {
+                prefix: prefix.clone(),
+                values: self
+                    .core
+                    .storage
+                    .config
+                    .list(prefix, false)
+                    .await
+                    .caused_by(trc::location!())?,
+            });
+        }
+        let config = serde_json::to_string(&batch.config).unwrap_or_default();
+        if !batch.full_sync
+            && store
+                .get_value::<String>(state_key(REPLICATION_CONFIG))
+                .await
+                .caused_by(trc::location!())?
+                .is_some_and(|last_config| last_config == config)
+        {
+            batch.config.clear();
+        }
+
+        if !batch.full_sync
+            && batch.principals.is_empty()
+            && batch.deletions.is_empty()
+            && batch.config.is_empty()
+        {
+            return Ok(());
+        }
+
+        send_batch(peer, &batch).await?;
+
+        trc::event!(
+            Cluster(ClusterEvent::ReplicationSent),
+            Url = peer.url.clone(),
+            ChangeId = change_id,
+            Total = batch.principals.len() + batch.deletions.len(),
+            Elapsed = time.elapsed(),
+        );
+
+        // Update replication state
+        let mut write_batch = BatchBuilder::new();
+        write_batch
+            .set(
+                ValueClass::Task(TaskClass::State(REPLICATION_CURSOR.to_vec())),
+                change_id.serialize(),
+            )
+            .set(
+                ValueClass::Task(TaskClass::State(REPLICATION_CONFIG.to_vec())),
+                config.into_bytes(),
+            );
+        for (principal_id, name) in names {
+            write_batch.set(
+                ValueClass::Task(TaskClass::State(name_key(principal_id))),
+                name.into_bytes(),
+            );
+        }
+        for principal_id in deleted_ids {
+            write_batch.clear(ValueClass::Task(TaskClass::State(name_key(principal_id))));
+        }
+        store
+            .write(write_batch.build())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(())
+    }
+}
+
+async fn send_batch(peer: &ReplicationPeer, batch: &ReplicationBatch) -> trc::Result<()> {
+    let url = format!("{}/api/replication", peer.url);
+    let response = reqwest::Client::builder()
+        .timeout(peer.timeout)
+        .danger_accept_invalid_certs(peer.tls_allow_invalid_certs)
+        .build()
+        .map_err(|err| {
+            trc::ClusterEvent::Error
+                .into_err()
+                .details("Failed to create HTTP client")
+                .reason(err)
+        })?
+        .post(&url)
+        .basic_auth(&peer.username, Some(&peer.secret))
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(batch).unwrap_or_default())
+        .send()
+        .await
+        .map_err(|err| {
+            trc::ClusterEvent::Error
+                .into_err()
+                .details("Failed to send replication batch")
+                .ctx(trc::Key::Url, url.clone())
+                .reason(err)
+        })?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let code = response.status().as_u16();
+        let reason = response.text().await.unwrap_or_default();
+
+        Err(trc::ClusterEvent::Error
+            .into_err()
+            .details("Replication peer rejected batch")
+            .ctx(trc::Key::Url, url)
+            .ctx(trc::Key::Code, code)
+            .reason(reason))
+    }
+}
+
+async fn cached_name(server: &Server, principal_id: u32) -> trc::Result<Option<String>> {
+    server
+        .core
+        .storage
+        .data
+        .get_value::<String>(state_key(&name_key(principal_id)))
+        .await
+        .caused_by(trc::location!())
+}
+
+pub fn state_key(name: &[u8]) -> ValueKey<ValueClass<u32>> {
+    ValueKey::from(ValueClass::Task(TaskClass::State(name.to_vec())))
+}
+
+fn name_key(principal_id: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(REPLICATION_NAME.len() + std::mem::size_of::<u32>());
+    key.extend_from_slice(REPLICATION_NAME);
+    key.extend_from_slice(&principal_id.to_be_bytes());
+    key
+}
@@ -0,0 +1,252 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Instant};
+
+use common::{
+    config::scheduler::{ScheduledTask, TaskType},
+    Server,
+};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{now, BatchBuilder, Bincode, TaskClass, ValueClass},
+    Serialize as _, ValueKey,
+};
+use trc::AddContext;
+
+use crate::email::delete::EmailDeletion;
+
+use super::{expire::ExpiredRecords, quota::QuotaRecalculation, replication::DirectoryReplication};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRun {
+    pub node_id: u64,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+    pub next_run: Option<u64>,
+    pub outcome: TaskOutcome,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "camelCase")]
+pub enum TaskOutcome {
+    Running,
+    Success,
+    Failure { reason: String },
+}
+
+pub trait TaskScheduler: Sync + Send {
+    fn run_task(&self, task: TaskType) -> impl Future<Output = bool> + Send;
+
+    fn is_task_running(&self, task: TaskType) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn get_task_run(
+        &self,
+        task: TaskType,
+    ) -> impl Future<Output = trc::Result<Option<TaskRun>>> + Send;
+}
+
+impl TaskScheduler for Server {
+    async fn run_task(&self, task: TaskType) -> bool {
+        let Some(schedule) = self.core.scheduler.tasks.get(&task) else {
+            return false;
+        };
+
+        // Obtain lock, this prevents overlapping runs across the cluster
+        if !self.try_lock_task(schedule).await {
+            return false;
+        }
+
+        trc::event!(
+            Housekeeper(trc::HousekeeperEvent::TaskStarted),
+            Id = task.as_str(),
+        );
+
+        let time = Instant::now();
+        let mut run = TaskRun {
+            node_id: self.core.network.node_id,
+            started_at: now(),
+            finished_at: None,
+            next_run: schedule
+                .time_to_next()
+                .map(|time_to_next| now() + time_to_next.as_secs()),
+            outcome: TaskOutcome::Running,
+        };
+        self.write_task_run(task, &run).await;
+
+        let result = match task {
+            TaskType::PurgeAccounts => {
+                self.purge_accounts().await;
+                Ok(())
+            }
+            TaskType::PurgeDataStore => self.core.storage.data.purge_store().await,
+            TaskType::PurgeBlobStore => {
+                self.core
+                    .storage
+                    .data
+                    .purge_blobs(self.core.storage.blob.clone())
+                    .await
+            }
+            TaskType::PurgeLookupStore => self.core.storage.lookup.purge_lookup_store().await,
+            TaskType::PurgeExpired => self.purge_expired().await,
+            TaskType::ReplicateDirectory => self.replicate_directory().await,
+            TaskType::RecalculateQuota => self.recalculate_quota_all(None, false).await.map(|_| ()),
+            TaskType::TierBlobs => self
+                .core
+                .storage
+                .data
+                .tier_blobs(&self.core.storage.blob)
+                .await
+                .map(|_| ()),
+        };
+
+        run.finished_at = Some(now());
+        run.outcome = match result {
+            Ok(_) => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::TaskCompleted),
+                    Id = task.as_str(),
+                    Elapsed = time.elapsed(),
+                );
+
+                TaskOutcome::Success
+            }
+            Err(err) => {
+                let reason = err.to_string();
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::TaskFailed),
+                    Id = task.as_str(),
+                    CausedBy = err,
+                );
+
+                TaskOutcome::Failure { reason }
+            }
+        };
+        self.write_task_run(task, &run).await;
+
+        // Release lock
+        let mut batch = BatchBuilder::new();
+        batch.clear(ValueClass::Task(TaskClass::Lock(
+            task.as_str().as_bytes().to_vec(),
+        )));
+        if let Err(err) = self.core.storage.data.write(batch.build()).await {
+            trc::error!(err.details("Failed to release task lock").id(task.as_str()));
+        }
+
+        true
+    }
+
+    async fn is_task_running(&self, task: TaskType) -> trc::Result<bool> {
+        self.core
+            .storage
+            .data
+            .get_value::<u64>(ValueKey::from(ValueClass::Task(TaskClass::Lock(
+                task.as_str().as_bytes().to_vec(),
+            ))))
+            .await
+            .map(|expiry| expiry.is_some_and(|expiry| expiry > now()))
+            .caused_by(trc::location!())
+    }
+
+    async fn get_task_run(&self, task: TaskType) -> trc::Result<Option<TaskRun>> {
+        self.core
+            .storage
+            .data
+            .get_value::<Bincode<TaskRun>>(ValueKey::from(ValueClass::Task(TaskClass::Run(
+                task.as_str().as_bytes().to_vec(),
+            ))))
+            .await
+            .map(|run| run.map(|run| run.inner))
+            .caused_by(trc::location!())
+    }
+}
+
+trait TaskLock: Sync + Send {
+    fn try_lock_task(&self, schedule: &ScheduledTask) -> impl Future<Output = bool> + Send;
+
+    fn write_task_run(&self, task: TaskType, run: &TaskRun) -> impl Future<Output = ()> + Send;
+}
+
+impl TaskLock for Server {
+    async fn try_lock_task(&self, schedule: &ScheduledTask) -> bool {
+        let lock = schedule.task.as_str().as_bytes().to_vec();
+        let mut batch = BatchBuilder::new();
+
+        match self
+            .core
+            .storage
+            .data
+            .get_value::<u64>(ValueKey::from(ValueClass::Task(TaskClass::Lock(
+                lock.clone(),
+            ))))
+            .await
+        {
+            Ok(Some(expiry)) if expiry > now() => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::TaskLocked),
+                    Id = schedule.task.as_str(),
+                    Expires = trc::Value::Timestamp(expiry),
+                );
+
+                return false;
+            }
+            Ok(Some(expiry)) => {
+                batch.assert_value(ValueClass::Task(TaskClass::Lock(lock.clone())), expiry);
+            }
+            Ok(None) => {
+                batch.assert_value(ValueClass::Task(TaskClass::Lock(lock.clone())), ());
+            }
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to obtain task lock")
+                    .id(schedule.task.as_str()));
+
+                return false;
+            }
+        }
+
+        batch.set(
+            ValueClass::Task(TaskClass::Lock(lock)),
+            (now() + schedule.lock_expiry.as_secs()).serialize(),
+        );
+
+        match self.core.storage.data.write(batch.build()).await {
+            Ok(_) => true,
+            Err(err) if err.is_assertion_failure() => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::TaskLocked),
+                    Id = schedule.task.as_str(),
+                    CausedBy = err,
+                );
+
+                false
+            }
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to lock task")
+                    .id(schedule.task.as_str()));
+
+                false
+            }
+        }
+    }
+
+    async fn write_task_run(&self, task: TaskType, run: &TaskRun) {
+        let mut batch = BatchBuilder::new();
+        batch.set(
+            ValueClass::Task(TaskClass::Run(task.as_str().as_bytes().to_vec())),
+            Bincode::new(run.clone()).serialize(),
+        );
+
+        if let Err(err) = self.core.storage.data.write(batch.build()).await {
+            trc::error!(err
+                .details("Failed to write task run history")
+                .id(task.as_str()));
+        }
+    }
+}
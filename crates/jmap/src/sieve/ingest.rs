@@ -399,9 +399,25 @@ impl SieveScriptIngest for Server {
                             continue;
                         }
                     }
-                    Event::ListContains { .. }
-                    | Event::Notify { .. }
-                    | Event::SetEnvelope { .. } => {
+                    Event::ListContains {
+                        lists,
+                        values,
+                        match_as,
+                    } => {
+                        input = match self
+                            .sieve_list_contains(access_token, &lists, &values, match_as)
+                            .await
+                        {
+                            Ok(result) => result.into(),
+                            Err(err) => {
+                                trc::error!(err
+                                    .span_id(session_id)
+                                    .details("Sieve list lookup failed"));
+                                false.into()
+                            }
+                        };
+                    }
+                    Event::Notify { .. } | Event::SetEnvelope { .. } => {
                         // Not allowed
                         input = false.into();
                     }
@@ -10,11 +10,13 @@ use jmap_proto::{
     object::Object,
     types::{collection::Collection, property::Property, value::Value},
 };
-use smtp::queue::{self, spool::SmtpSpool};
+use smtp::queue::spool::SmtpSpool;
 use std::future::Future;
 
 use crate::{changes::state::StateManager, JmapMethods};
 
+use super::status::set_delivery_status;
+
 pub trait EmailSubmissionGet: Sync + Send {
     fn email_submission_get(
         &self,
@@ -98,43 +100,10 @@ impl EmailSubmissionGet for Server {
                     Property::DeliveryStatus => {
                         match (queued_message.as_ref(), push.remove(property)) {
                             (Some(message), Value::Object(mut status)) => {
-                                for rcpt in &message.recipients {
-                                    status.set(
-                                        Property::_T(rcpt.address.clone()),
-                                        Object::with_capacity(3)
-                                            .with_property(
-                                                Property::Delivered,
-                                                match &rcpt.status {
-                                                    queue::Status::Scheduled
-                                                    | queue::Status::TemporaryFailure(_) => {
-                                                        "queued"
-                                                    }
-                                                    queue::Status::Completed(_) => "yes",
-                                                    queue::Status::PermanentFailure(_) => "no",
-                                                },
-                                            )
-                                            .with_property(
-                                                Property::SmtpReply,
-                                                match &rcpt.status {
-                                                    queue::Status::Completed(reply) => reply
-                                                        .response
-                                                        .to_string()
-                                                        .replace('\n', " "),
-                                                    queue::Status::TemporaryFailure(reply)
-                                                    | queue::Status::PermanentFailure(reply) => {
-                                                        reply
-                                                            .response
-                                                            .to_string()
-                                                            .replace('\n', " ")
-                                                    }
-                                                    queue::Status::Scheduled => {
-                                                        "250 2.1.5 Queued".to_string()
-                                                    }
-                                                },
-                                            )
-                                            .with_property(Property::Displayed, "unknown"),
-                                    );
-                                }
+                                set_delivery_status(
+                                    &mut status,
+                                    &message.submission_status(false).recipients,
+                                );
 
                                 Value::Object(status)
                             }
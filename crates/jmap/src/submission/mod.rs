@@ -6,4 +6,6 @@
 
 pub mod get;
 pub mod query;
+pub mod recipients;
 pub mod set;
+pub mod status;
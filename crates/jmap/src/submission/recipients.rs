@@ -0,0 +1,162 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{cmp::Reverse, future::Future};
+
+use common::Server;
+use jmap_proto::types::{collection::Collection, property::Property};
+use serde::{Deserialize, Serialize};
+use store::write::{now, BatchBuilder, Bincode, F_CLEAR, F_VALUE};
+use trc::AddContext;
+
+use crate::JmapMethods;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct CollectedRecipients {
+    pub recipients: Vec<CollectedRecipient>,
+    // Addresses removed by the account owner are never collected again
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectedRecipient {
+    pub address: String,
+    pub name: Option<String>,
+    pub last_used: u64,
+    pub count: u32,
+}
+
+pub trait CollectedRecipientStore: Sync + Send {
+    fn collected_recipients(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<CollectedRecipients>> + Send;
+
+    fn write_collected_recipients(
+        &self,
+        account_id: u32,
+        recipients: Option<CollectedRecipients>,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl CollectedRecipientStore for Server {
+    async fn collected_recipients(&self, account_id: u32) -> trc::Result<CollectedRecipients> {
+        self.get_property::<Bincode<CollectedRecipients>>(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::CollectedRecipients,
+        )
+        .await
+        .caused_by(trc::location!())
+        .map(|recipients| recipients.map(|r| r.inner).unwrap_or_default())
+    }
+
+    async fn write_collected_recipients(
+        &self,
+        account_id: u32,
+        recipients: Option<CollectedRecipients>,
+    ) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0);
+        if let Some(recipients) = recipients {
+            batch.value(
+                Property::CollectedRecipients,
+                Bincode::new(recipients),
+                F_VALUE,
+            );
+        } else {
+            batch.value(Property::CollectedRecipients, (), F_VALUE | F_CLEAR);
+        }
+        self.write_batch(batch)
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+}
+
+impl CollectedRecipients {
+    pub fn collect(
+        &mut self,
+        recipients: impl IntoIterator<Item = (String, Option<String>)>,
+        max_entries: usize,
+    ) -> bool {
+        let mut has_changes = false;
+        let now = now();
+
+        for (address, name) in recipients {
+            let address = address.to_lowercase();
+            if self.removed.contains(&address) {
+                continue;
+            }
+
+            if let Some(recipient) = self.recipients.iter_mut().find(|r| r.address == address) {
+                recipient.last_used = now;
+                recipient.count = recipient.count.saturating_add(1);
+                if name.is_some() {
+                    recipient.name = name;
+                }
+            } else {
+                self.recipients.push(CollectedRecipient {
+                    address,
+                    name,
+                    last_used: now,
+                    count: 1,
+                });
+            }
+            has_changes = true;
+        }
+
+        // Evict the least recently used recipients
+        if self.recipients.len() > max_entries {
+            self.recipients
+                .sort_unstable_by_key(|recipient| Reverse(recipient.last_used));
+            self.recipients.truncate(max_entries);
+        }
+
+        has_changes
+    }
+
+    pub fn remove(&mut self, address: &str, max_entries: usize) {
+        let address = address.to_lowercase();
+        self.recipients.retain(|r| r.address != address);
+
+        if !self.removed.contains(&address) {
+            self.removed.push(address);
+            if self.removed.len() > max_entries {
+                self.removed.remove(0);
+            }
+        }
+    }
+
+    pub fn query(&self, text: &str, limit: usize) -> Vec<&CollectedRecipient> {
+        let text = text.to_lowercase();
+        let mut results = self
+            .recipients
+            .iter()
+            .filter(|r| {
+                text.is_empty()
+                    || r.address.contains(&text)
+                    || r.name
+                        .as_ref()
+                        .is_some_and(|name| name.to_lowercase().contains(&text))
+            })
+            .collect::<Vec<_>>();
+
+        // Frequently used recipients are listed first
+        results.sort_unstable_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| b.last_used.cmp(&a.last_used))
+        });
+        results.truncate(limit);
+        results
+    }
+}
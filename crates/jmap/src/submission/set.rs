@@ -34,18 +34,27 @@ use jmap_proto::{
 use mail_parser::{HeaderName, HeaderValue};
 use smtp::{
     core::{Session, SessionData, State},
-    queue::spool::SmtpSpool,
+    queue::{spool::SmtpSpool, MAIL_SUBMISSION},
 };
 use smtp_proto::{request::parser::Rfc5321Parser, MailFrom, RcptTo};
-use store::write::{assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder, Bincode};
+use store::write::{
+    assert::HashedValue, log::ChangeLogBuilder, now, BatchBuilder, Bincode, F_VALUE,
+};
 use utils::{map::vec_map::VecMap, sanitize_email};
 
 use crate::{
     blob::download::BlobDownload, changes::write::ChangeLog, email::metadata::MessageMetadata,
     JmapMethods,
 };
+
+use super::{recipients::CollectedRecipientStore, status::EmailSubmissionStatus};
 use std::future::Future;
 
+pub struct SentMessage {
+    pub submission: Object<Value>,
+    pub recipients: Vec<(String, Option<String>)>,
+}
+
 pub static SCHEMA: &[IndexProperty] = &[
     IndexProperty::new(Property::UndoStatus).index_as(IndexAs::Text {
         tokenize: false,
@@ -71,7 +80,7 @@ pub trait EmailSubmissionSet: Sync + Send {
         response: &SetResponse,
         instance: &Arc<ServerInstance>,
         object: Object<SetValue>,
-    ) -> impl Future<Output = trc::Result<Result<Object<Value>, SetError>>> + Send;
+    ) -> impl Future<Output = trc::Result<Result<SentMessage, SetError>>> + Send;
 }
 
 impl EmailSubmissionSet for Server {
@@ -88,12 +97,18 @@ impl EmailSubmissionSet for Server {
         // Process creates
         let mut changes = ChangeLogBuilder::new();
         let mut success_email_ids = HashMap::new();
+        let mut collected = None;
         for (id, object) in request.unwrap_create() {
             match self
                 .send_message(account_id, &response, instance, object)
                 .await?
             {
-                Ok(submission) => {
+                Ok(SentMessage {
+                    submission,
+                    recipients,
+                }) => {
+                    let queue_id = submission.get(&Property::MessageId).as_uint();
+
                     // Add id mapping
                     success_email_ids.insert(
                         id.clone(),
@@ -107,8 +122,33 @@ impl EmailSubmissionSet for Server {
                         .with_collection(Collection::EmailSubmission)
                         .create_document()
                         .custom(ObjectIndexBuilder::new(SCHEMA).with_changes(submission));
+
+                    // Collect recipients for autocomplete as part of the same write
+                    let max_entries = self.core.jmap.collected_recipients_max;
+                    if max_entries > 0 {
+                        let collected = match &mut collected {
+                            Some(collected) => collected,
+                            None => collected.insert(self.collected_recipients(account_id).await?),
+                        };
+                        if collected.collect(recipients, max_entries) {
+                            batch
+                                .with_collection(Collection::Principal)
+                                .update_document(0)
+                                .value(
+                                    Property::CollectedRecipients,
+                                    Bincode::new(collected.clone()),
+                                    F_VALUE,
+                                );
+                        }
+                    }
                     let document_id = self.write_batch_expect_id(batch).await?;
                     changes.log_insert(Collection::EmailSubmission, document_id);
+
+                    // Track delivery attempts of the queued message
+                    if let Some(queue_id) = queue_id {
+                        self.link_submission(account_id, document_id, queue_id)
+                            .await?;
+                    }
                     response.created(id, document_id);
                 }
                 Err(err) => {
@@ -319,7 +359,7 @@ impl EmailSubmissionSet for Server {
         response: &SetResponse,
         instance: &Arc<ServerInstance>,
         object: Object<SetValue>,
-    ) -> trc::Result<Result<Object<Value>, SetError>> {
+    ) -> trc::Result<Result<SentMessage, SetError>> {
         let mut submission = Object::with_capacity(object.properties.len());
         let mut email_id = u32::MAX;
         let mut identity_id = u32::MAX;
@@ -462,7 +502,7 @@ impl EmailSubmissionSet for Server {
         };
 
         // Make sure the envelope address matches the identity email address
-        let mail_from = if let Some(mail_from) = mail_from {
+        let mut mail_from = if let Some(mail_from) = mail_from {
             if !mail_from.address.eq_ignore_ascii_case(&identity_mail_from) {
                 return Ok(Err(SetError::new(SetErrorType::ForbiddenFrom)
                     .with_description(
@@ -583,6 +623,7 @@ impl EmailSubmissionSet for Server {
             Session::<NullIo>::local(self.clone(), instance.clone(), SessionData::default());
 
         // MAIL FROM
+        mail_from.flags |= MAIL_SUBMISSION;
         let _ = session.handle_mail_from(mail_from).await;
         if let Some(error) = session.has_failed() {
             return Ok(Err(SetError::new(SetErrorType::ForbiddenMailFrom)
@@ -605,6 +646,17 @@ impl EmailSubmissionSet for Server {
             responses.push((addr, response));
         }
 
+        // Recipients accepted for delivery are collected for autocomplete
+        let recipients = if has_success {
+            responses
+                .iter()
+                .filter(|(_, response)| response.is_none())
+                .map(|(addr, _)| (addr.clone(), recipient_name(&metadata, addr)))
+                .collect()
+        } else {
+            vec![]
+        };
+
         // DATA
         if has_success {
             session.data.message = message;
@@ -651,10 +703,39 @@ impl EmailSubmissionSet for Server {
             },
         );
 
-        Ok(Ok(submission))
+        Ok(Ok(SentMessage {
+            submission,
+            recipients,
+        }))
     }
 }
 
+fn recipient_name(metadata: &MessageMetadata, address: &str) -> Option<String> {
+    metadata
+        .contents
+        .parts
+        .first()?
+        .headers
+        .iter()
+        .filter(|header| {
+            matches!(
+                header.name,
+                HeaderName::To | HeaderName::Cc | HeaderName::Bcc
+            )
+        })
+        .find_map(|header| match &header.value {
+            HeaderValue::Address(addr) => addr
+                .iter()
+                .find(|addr| {
+                    addr.address()
+                        .is_some_and(|addr| addr.eq_ignore_ascii_case(address))
+                })
+                .and_then(|addr| addr.name())
+                .map(|name| name.to_string()),
+            _ => None,
+        })
+}
+
 fn parse_envelope_address(envelope: &Value) -> Result<(String, Option<String>), SetError> {
     if let Value::Object(envelope) = envelope {
         if let Some(Value::Text(addr)) = envelope.properties.get(&Property::Email) {
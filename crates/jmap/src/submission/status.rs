@@ -0,0 +1,205 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use common::{
+    ipc::{Delivered, SubmissionRecipient, SubmissionStatus},
+    Server,
+};
+use jmap_proto::{
+    object::{index::ObjectIndexBuilder, Object},
+    types::{
+        collection::Collection, property::Property, state::StateChange, type_state::DataType,
+        value::Value,
+    },
+};
+use serde::{Deserialize, Serialize};
+use store::{
+    write::{assert::HashedValue, log::ChangeLogBuilder, BatchBuilder, Bincode},
+    Serialize as _,
+};
+use trc::AddContext;
+
+use crate::{changes::write::ChangeLog, services::state::StateManager, JmapMethods};
+
+use super::set::SCHEMA;
+
+// Time to keep the link between a queued message and its submission
+const LINK_EXPIRY: u64 = 30 * 86400;
+// Time to keep statuses reported before the submission was linked
+const PENDING_EXPIRY: u64 = 3600;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SubmissionLink {
+    account_id: u32,
+    document_id: u32,
+}
+
+pub trait EmailSubmissionStatus: Sync + Send {
+    fn link_submission(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        queue_id: u64,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn update_submission_status(
+        &self,
+        status: SubmissionStatus,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl EmailSubmissionStatus for Server {
+    async fn link_submission(
+        &self,
+        account_id: u32,
+        document_id: u32,
+        queue_id: u64,
+    ) -> trc::Result<()> {
+        self.core
+            .storage
+            .lookup
+            .key_set(
+                link_key(queue_id),
+                Bincode::new(SubmissionLink {
+                    account_id,
+                    document_id,
+                })
+                .serialize(),
+                LINK_EXPIRY.into(),
+            )
+            .await?;
+
+        // Apply any status reported while the submission was being created
+        let pending_key = pending_key(queue_id);
+        if let Some(status) = self
+            .core
+            .storage
+            .lookup
+            .key_get::<Bincode<SubmissionStatus>>(pending_key.clone())
+            .await?
+        {
+            self.core.storage.lookup.key_delete(pending_key).await?;
+            self.update_submission_status(status.inner).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn update_submission_status(&self, status: SubmissionStatus) -> trc::Result<()> {
+        let key = link_key(status.queue_id);
+        let link = if let Some(link) = self
+            .core
+            .storage
+            .lookup
+            .key_get::<Bincode<SubmissionLink>>(key.clone())
+            .await?
+        {
+            link.inner
+        } else {
+            return self
+                .core
+                .storage
+                .lookup
+                .key_set(
+                    pending_key(status.queue_id),
+                    Bincode::new(status).serialize(),
+                    PENDING_EXPIRY.into(),
+                )
+                .await;
+        };
+
+        if let Some(submission) = self
+            .get_property::<HashedValue<Object<Value>>>(
+                link.account_id,
+                Collection::EmailSubmission,
+                link.document_id,
+                Property::Value,
+            )
+            .await?
+        {
+            let mut delivery_status = submission
+                .inner
+                .get(&Property::DeliveryStatus)
+                .as_obj()
+                .cloned()
+                .unwrap_or_default();
+            set_delivery_status(&mut delivery_status, &status.recipients);
+            let delivery_status = Value::Object(delivery_status);
+
+            // Only log a change when a recipient status was updated
+            if submission.inner.get(&Property::DeliveryStatus) != &delivery_status {
+                let mut batch = BatchBuilder::new();
+                batch
+                    .with_account_id(link.account_id)
+                    .with_collection(Collection::EmailSubmission)
+                    .update_document(link.document_id)
+                    .custom(
+                        ObjectIndexBuilder::new(SCHEMA)
+                            .with_current(submission)
+                            .with_changes(
+                                Object::with_capacity(1)
+                                    .with_property(Property::DeliveryStatus, delivery_status),
+                            ),
+                    );
+                self.write_batch(batch).await.caused_by(trc::location!())?;
+
+                let mut changes = ChangeLogBuilder::new();
+                changes.log_update(Collection::EmailSubmission, link.document_id);
+                let change_id = self
+                    .commit_changes(link.account_id, changes)
+                    .await
+                    .caused_by(trc::location!())?;
+                self.broadcast_state_change(
+                    StateChange::new(link.account_id)
+                        .with_change(DataType::EmailSubmission, change_id),
+                )
+                .await;
+            }
+        } else {
+            // The submission was destroyed
+            return self.core.storage.lookup.key_delete(key).await;
+        }
+
+        // Final statuses are no longer tracked
+        if status.is_final {
+            self.core.storage.lookup.key_delete(key).await
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub fn set_delivery_status(
+    delivery_status: &mut Object<Value>,
+    recipients: &[SubmissionRecipient],
+) {
+    for rcpt in recipients {
+        delivery_status.set(
+            Property::_T(rcpt.address.clone()),
+            Object::with_capacity(3)
+                .with_property(
+                    Property::Delivered,
+                    match rcpt.delivered {
+                        Delivered::Queued => "queued",
+                        Delivered::Yes => "yes",
+                        Delivered::No => "no",
+                    },
+                )
+                .with_property(Property::SmtpReply, rcpt.smtp_reply.clone())
+                .with_property(Property::Displayed, "unknown"),
+        );
+    }
+}
+
+fn link_key(queue_id: u64) -> Vec<u8> {
+    format!("submission:{queue_id}").into_bytes()
+}
+
+fn pending_key(queue_id: u64) -> Vec<u8> {
+    format!("submission-status:{queue_id}").into_bytes()
+}
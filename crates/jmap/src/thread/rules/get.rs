@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Server;
+use jmap_proto::{
+    method::get::{GetRequest, GetResponse, RequestArguments},
+    object::Object,
+    types::{date::UTCDate, id::Id, property::Property, state::State, value::Value},
+};
+use std::future::Future;
+
+use super::ThreadRuleStore;
+
+pub trait ThreadRuleGet: Sync + Send {
+    fn thread_rule_get(
+        &self,
+        request: GetRequest<RequestArguments>,
+    ) -> impl Future<Output = trc::Result<GetResponse>> + Send;
+}
+
+impl ThreadRuleGet for Server {
+    async fn thread_rule_get(
+        &self,
+        mut request: GetRequest<RequestArguments>,
+    ) -> trc::Result<GetResponse> {
+        let account_id = request.account_id.document_id();
+        let properties = request.unwrap_properties(&[
+            Property::Id,
+            Property::ThreadId,
+            Property::Type,
+            Property::MailboxId,
+            Property::Expires,
+        ]);
+        let rules = self.thread_rules(account_id).await?;
+        let ids = if let Some(ids) = request.unwrap_ids(self.core.jmap.get_max_objects)? {
+            ids
+        } else {
+            rules
+                .rules
+                .iter()
+                .take(self.core.jmap.get_max_objects)
+                .map(|rule| Id::from(rule.thread_id))
+                .collect()
+        };
+        let expiry = self.core.jmap.thread_rules_expiry.as_secs();
+        let mut response = GetResponse {
+            account_id: request.account_id.into(),
+            state: State::Initial.into(),
+            list: Vec::with_capacity(ids.len()),
+            not_found: vec![],
+        };
+
+        for id in ids {
+            let Some(rule) = rules.get(id.document_id()) else {
+                response.not_found.push(id.into());
+                continue;
+            };
+
+            let mut result = Object::with_capacity(properties.len());
+            for property in &properties {
+                let value = match property {
+                    Property::Id | Property::ThreadId => Value::Id(rule.thread_id.into()),
+                    Property::Type => Value::Text(rule.action.as_str().to_string()),
+                    Property::MailboxId => rule
+                        .action
+                        .mailbox_id()
+                        .map(|mailbox_id| Value::Id(mailbox_id.into()))
+                        .unwrap_or(Value::Null),
+                    Property::Expires => {
+                        Value::Date(UTCDate::from_timestamp(rule.expires(expiry) as i64))
+                    }
+                    _ => Value::Null,
+                };
+                result.append(property.clone(), value);
+            }
+            response.list.push(result);
+        }
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,281 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{cmp::Reverse, future::Future};
+
+use common::Server;
+use jmap_proto::types::{collection::Collection, keyword::Keyword, property::Property};
+use serde::{Deserialize, Serialize};
+use store::write::{now, BatchBuilder, Bincode, F_CLEAR, F_VALUE};
+use trc::AddContext;
+
+use crate::{
+    mailbox::{get::MailboxGet, INBOX_ID},
+    JmapMethods,
+};
+
+pub mod get;
+pub mod set;
+
+// Avoid rewriting the rules on every message delivered to a busy thread
+const ACTIVITY_RESOLUTION: u64 = 3600;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ThreadRules {
+    pub rules: Vec<ThreadRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadRule {
+    pub thread_id: u32,
+    pub action: ThreadAction,
+    pub created_at: u64,
+    pub last_activity: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThreadAction {
+    Mute,
+    Move { mailbox_id: u32 },
+    Flag,
+}
+
+pub trait ThreadRuleStore: Sync + Send {
+    fn thread_rules(
+        &self,
+        account_id: u32,
+    ) -> impl Future<Output = trc::Result<ThreadRules>> + Send;
+
+    fn write_thread_rules(
+        &self,
+        account_id: u32,
+        rules: ThreadRules,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn thread_rule_activity(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+    ) -> impl Future<Output = trc::Result<Option<ThreadAction>>> + Send;
+
+    fn apply_thread_action(
+        &self,
+        account_id: u32,
+        action: ThreadAction,
+        mailbox_ids: &mut [u32],
+        keywords: &mut Vec<Keyword>,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn merge_thread_rules(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+        merged_ids: &[u32],
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl ThreadRuleStore for Server {
+    async fn thread_rules(&self, account_id: u32) -> trc::Result<ThreadRules> {
+        self.get_property::<Bincode<ThreadRules>>(
+            account_id,
+            Collection::Principal,
+            0,
+            Property::ThreadRules,
+        )
+        .await
+        .caused_by(trc::location!())
+        .map(|rules| {
+            let mut rules = rules.map(|r| r.inner).unwrap_or_default();
+            rules.prune(self.core.jmap.thread_rules_expiry.as_secs());
+            rules
+        })
+    }
+
+    async fn write_thread_rules(&self, account_id: u32, rules: ThreadRules) -> trc::Result<()> {
+        let mut batch = BatchBuilder::new();
+        batch
+            .with_account_id(account_id)
+            .with_collection(Collection::Principal)
+            .update_document(0);
+        if !rules.rules.is_empty() {
+            batch.value(Property::ThreadRules, Bincode::new(rules), F_VALUE);
+        } else {
+            batch.value(Property::ThreadRules, (), F_VALUE | F_CLEAR);
+        }
+        self.write_batch(batch)
+            .await
+            .caused_by(trc::location!())
+            .map(|_| ())
+    }
+
+    async fn thread_rule_activity(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+    ) -> trc::Result<Option<ThreadAction>> {
+        let mut rules = self.thread_rules(account_id).await?;
+        let Some(rule) = rules.get_mut(thread_id) else {
+            return Ok(None);
+        };
+        let action = rule.action;
+
+        // Keep the rule alive while its thread receives messages
+        let now = now();
+        if rule.last_activity + ACTIVITY_RESOLUTION <= now {
+            rule.last_activity = now;
+            self.write_thread_rules(account_id, rules).await?;
+        }
+
+        Ok(Some(action))
+    }
+
+    async fn apply_thread_action(
+        &self,
+        account_id: u32,
+        action: ThreadAction,
+        mailbox_ids: &mut [u32],
+        keywords: &mut Vec<Keyword>,
+    ) -> trc::Result<()> {
+        // Rules only redirect messages that would otherwise land in the Inbox
+        let is_inbox = mailbox_ids == [INBOX_ID];
+        match action {
+            ThreadAction::Mute => {
+                if is_inbox {
+                    if let Some(archive_id) = self
+                        .mailbox_get_by_role(account_id, "archive")
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        mailbox_ids[0] = archive_id;
+                    }
+                }
+                if !keywords.contains(&Keyword::Seen) {
+                    keywords.push(Keyword::Seen);
+                }
+            }
+            ThreadAction::Move { mailbox_id } => {
+                if is_inbox
+                    && self
+                        .get_document_ids(account_id, Collection::Mailbox)
+                        .await?
+                        .unwrap_or_default()
+                        .contains(mailbox_id)
+                {
+                    mailbox_ids[0] = mailbox_id;
+                }
+            }
+            ThreadAction::Flag => {
+                if !keywords.contains(&Keyword::Flagged) {
+                    keywords.push(Keyword::Flagged);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn merge_thread_rules(
+        &self,
+        account_id: u32,
+        thread_id: u32,
+        merged_ids: &[u32],
+    ) -> trc::Result<()> {
+        let mut rules = self.thread_rules(account_id).await?;
+        if !rules
+            .rules
+            .iter()
+            .any(|rule| merged_ids.contains(&rule.thread_id))
+        {
+            return Ok(());
+        }
+
+        // When several threads had rules, the most recently active one is kept
+        rules
+            .rules
+            .sort_unstable_by_key(|rule| Reverse(rule.last_activity));
+        let mut has_rule = false;
+        rules.rules.retain_mut(|rule| {
+            if rule.thread_id == thread_id || merged_ids.contains(&rule.thread_id) {
+                if !has_rule {
+                    rule.thread_id = thread_id;
+                    has_rule = true;
+                    true
+                } else {
+                    false
+                }
+            } else {
+                true
+            }
+        });
+
+        self.write_thread_rules(account_id, rules).await
+    }
+}
+
+impl ThreadRules {
+    pub fn get(&self, thread_id: u32) -> Option<&ThreadRule> {
+        self.rules.iter().find(|rule| rule.thread_id == thread_id)
+    }
+
+    pub fn get_mut(&mut self, thread_id: u32) -> Option<&mut ThreadRule> {
+        self.rules
+            .iter_mut()
+            .find(|rule| rule.thread_id == thread_id)
+    }
+
+    pub fn insert(&mut self, thread_id: u32, action: ThreadAction) {
+        let now = now();
+        if let Some(rule) = self.get_mut(thread_id) {
+            rule.action = action;
+            rule.last_activity = now;
+        } else {
+            self.rules.push(ThreadRule {
+                thread_id,
+                action,
+                created_at: now,
+                last_activity: now,
+            });
+        }
+    }
+
+    pub fn remove(&mut self, thread_id: u32) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.thread_id != thread_id);
+        self.rules.len() != len
+    }
+
+    // Drop rules whose thread has been idle for longer than the expiry period
+    pub fn prune(&mut self, expiry: u64) -> bool {
+        let len = self.rules.len();
+        let now = now();
+        self.rules
+            .retain(|rule| rule.last_activity.saturating_add(expiry) > now);
+        self.rules.len() != len
+    }
+}
+
+impl ThreadRule {
+    pub fn expires(&self, expiry: u64) -> u64 {
+        self.last_activity.saturating_add(expiry)
+    }
+}
+
+impl ThreadAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThreadAction::Mute => "mute",
+            ThreadAction::Move { .. } => "move",
+            ThreadAction::Flag => "flag",
+        }
+    }
+
+    pub fn mailbox_id(&self) -> Option<u32> {
+        match self {
+            ThreadAction::Move { mailbox_id } => Some(*mailbox_id),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Server;
+use jmap_proto::{
+    error::set::SetError,
+    method::set::{RequestArguments, SetRequest, SetResponse},
+    object::Object,
+    response::references::EvalObjectReferences,
+    types::{
+        collection::Collection,
+        property::Property,
+        state::State,
+        value::{MaybePatchValue, SetValue, Value},
+    },
+};
+use std::future::Future;
+use store::roaring::RoaringBitmap;
+
+use crate::JmapMethods;
+
+use super::{ThreadAction, ThreadRuleStore};
+
+pub trait ThreadRuleSet: Sync + Send {
+    fn thread_rule_set(
+        &self,
+        request: SetRequest<RequestArguments>,
+    ) -> impl Future<Output = trc::Result<SetResponse>> + Send;
+}
+
+impl ThreadRuleSet for Server {
+    async fn thread_rule_set(
+        &self,
+        mut request: SetRequest<RequestArguments>,
+    ) -> trc::Result<SetResponse> {
+        let account_id = request.account_id.document_id();
+        let mut rules = self.thread_rules(account_id).await?;
+        let thread_ids = self
+            .get_document_ids(account_id, Collection::Thread)
+            .await?
+            .unwrap_or_default();
+        let mailbox_ids = self
+            .get_document_ids(account_id, Collection::Mailbox)
+            .await?
+            .unwrap_or_default();
+        let mut response = SetResponse::from_request(&request, self.core.jmap.set_max_objects)?
+            .with_state(State::Initial);
+        let will_destroy = request.unwrap_destroy();
+        let mut has_changes = false;
+
+        // Process creates
+        'create: for (id, object) in request.unwrap_create() {
+            let changes = match RuleChanges::parse(&response, object) {
+                Ok(changes) => changes,
+                Err(err) => {
+                    response.not_created.append(id, err);
+                    continue 'create;
+                }
+            };
+            let Some(thread_id) = changes.thread_id.filter(|id| thread_ids.contains(*id)) else {
+                response.not_created.append(
+                    id,
+                    SetError::invalid_properties()
+                        .with_property(Property::ThreadId)
+                        .with_description("Missing or invalid thread id."),
+                );
+                continue 'create;
+            };
+            let action = match changes.into_action(None, &mailbox_ids) {
+                Ok(action) => action,
+                Err(err) => {
+                    response.not_created.append(id, err);
+                    continue 'create;
+                }
+            };
+            if rules.get(thread_id).is_some() {
+                response.not_created.append(
+                    id,
+                    SetError::already_exists()
+                        .with_existing_id(thread_id.into())
+                        .with_description("A rule already exists for this thread."),
+                );
+                continue 'create;
+            } else if rules.rules.len() >= self.core.jmap.thread_rules_max {
+                response.not_created.append(
+                    id,
+                    SetError::forbidden().with_description(format!(
+                        "There are too many thread rules, maximum is {}.",
+                        self.core.jmap.thread_rules_max
+                    )),
+                );
+                continue 'create;
+            }
+
+            rules.insert(thread_id, action);
+            response.created(id, thread_id);
+            has_changes = true;
+        }
+
+        // Process updates
+        'update: for (id, object) in request.unwrap_update() {
+            // Make sure id won't be destroyed
+            if will_destroy.contains(&id) {
+                response.not_updated.append(id, SetError::will_destroy());
+                continue 'update;
+            }
+
+            let thread_id = id.document_id();
+            let Some(rule) = rules.get(thread_id) else {
+                response.not_updated.append(id, SetError::not_found());
+                continue 'update;
+            };
+            let current = rule.action;
+            let changes = match RuleChanges::parse(&response, object) {
+                Ok(changes) if changes.thread_id.is_some_and(|id| id != thread_id) => {
+                    response.not_updated.append(
+                        id,
+                        SetError::invalid_properties()
+                            .with_property(Property::ThreadId)
+                            .with_description("Thread id cannot be changed."),
+                    );
+                    continue 'update;
+                }
+                Ok(changes) => changes,
+                Err(err) => {
+                    response.not_updated.append(id, err);
+                    continue 'update;
+                }
+            };
+
+            match changes.into_action(Some(current), &mailbox_ids) {
+                Ok(action) => {
+                    rules.insert(thread_id, action);
+                    response.updated.append(id, None);
+                    has_changes = true;
+                }
+                Err(err) => {
+                    response.not_updated.append(id, err);
+                }
+            }
+        }
+
+        // Process deletions
+        for id in will_destroy {
+            if rules.remove(id.document_id()) {
+                response.destroyed.push(id);
+                has_changes = true;
+            } else {
+                response.not_destroyed.append(id, SetError::not_found());
+            }
+        }
+
+        if has_changes {
+            self.write_thread_rules(account_id, rules).await?;
+        }
+
+        Ok(response)
+    }
+}
+
+#[derive(Default)]
+struct RuleChanges {
+    thread_id: Option<u32>,
+    rule_type: Option<String>,
+    mailbox_id: Option<Option<u32>>,
+}
+
+impl RuleChanges {
+    fn parse(response: &SetResponse, object: Object<SetValue>) -> Result<RuleChanges, SetError> {
+        let mut changes = RuleChanges::default();
+        for (property, value) in object.properties {
+            match (&property, response.eval_object_references(value)?) {
+                (Property::ThreadId, MaybePatchValue::Value(Value::Id(id))) => {
+                    changes.thread_id = Some(id.document_id());
+                }
+                (Property::Type, MaybePatchValue::Value(Value::Text(value))) => {
+                    changes.rule_type = Some(value);
+                }
+                (Property::MailboxId, MaybePatchValue::Value(Value::Id(id))) => {
+                    changes.mailbox_id = Some(Some(id.document_id()));
+                }
+                (Property::MailboxId, MaybePatchValue::Value(Value::Null)) => {
+                    changes.mailbox_id = Some(None);
+                }
+                (Property::Id | Property::Expires, _) => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(property)
+                        .with_description("Field is server-set and cannot be modified."));
+                }
+                _ => {
+                    return Err(SetError::invalid_properties()
+                        .with_property(property)
+                        .with_description("Field could not be set."));
+                }
+            }
+        }
+
+        Ok(changes)
+    }
+
+    // Merges the changes into the current action, if any
+    fn into_action(
+        self,
+        current: Option<ThreadAction>,
+        mailbox_ids: &RoaringBitmap,
+    ) -> Result<ThreadAction, SetError> {
+        let rule_type = self
+            .rule_type
+            .as_deref()
+            .or_else(|| current.map(|action| action.as_str()))
+            .ok_or_else(|| {
+                SetError::invalid_properties()
+                    .with_property(Property::Type)
+                    .with_description("Missing rule type.")
+            })?;
+        match rule_type {
+            "mute" => Ok(ThreadAction::Mute),
+            "flag" => Ok(ThreadAction::Flag),
+            "move" => self
+                .mailbox_id
+                .unwrap_or_else(|| current.and_then(|action| action.mailbox_id()))
+                .filter(|mailbox_id| mailbox_ids.contains(*mailbox_id))
+                .map(|mailbox_id| ThreadAction::Move { mailbox_id })
+                .ok_or_else(|| {
+                    SetError::invalid_properties()
+                        .with_property(Property::MailboxId)
+                        .with_description("Missing or invalid mailbox id.")
+                }),
+            _ => Err(SetError::invalid_properties()
+                .with_property(Property::Type)
+                .with_description("Type must be one of mute, move or flag.")),
+        }
+    }
+}
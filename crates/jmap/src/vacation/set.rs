@@ -28,6 +28,7 @@ use store::write::{
     log::{Changes, LogInsert},
     BatchBuilder, BlobOp, DirectoryClass, F_CLEAR, F_VALUE,
 };
+use utils::locale::{Locale, Localization};
 
 use crate::{
     blob::upload::BlobUpload,
@@ -45,7 +46,11 @@ pub trait VacationResponseSet: Sync + Send {
         access_token: &AccessToken,
     ) -> impl Future<Output = trc::Result<SetResponse>> + Send;
 
-    fn build_script(&self, obj: &mut ObjectIndexBuilder) -> trc::Result<Vec<u8>>;
+    fn build_script(
+        &self,
+        obj: &mut ObjectIndexBuilder,
+        localization: &Localization,
+    ) -> trc::Result<Vec<u8>>;
 }
 
 impl VacationResponseSet for Server {
@@ -243,8 +248,13 @@ impl VacationResponseSet for Server {
             // Create sieve script only if there are changes
             if build_script {
                 // Upload new blob
+                let localization = self.localization(account_id).await?;
                 let hash = self
-                    .put_blob(account_id, &self.build_script(&mut obj)?, false)
+                    .put_blob(
+                        account_id,
+                        &self.build_script(&mut obj, &localization)?,
+                        false,
+                    )
                     .await?
                     .hash;
                 let blob_id = obj.changes_mut().unwrap().blob_id_mut().unwrap();
@@ -358,7 +368,11 @@ impl VacationResponseSet for Server {
         Ok(response)
     }
 
-    fn build_script(&self, obj: &mut ObjectIndexBuilder) -> trc::Result<Vec<u8>> {
+    fn build_script(
+        &self,
+        obj: &mut ObjectIndexBuilder,
+        localization: &Localization,
+    ) -> trc::Result<Vec<u8>> {
         // Build Sieve script
         let mut script = Vec::with_capacity(1024);
         script.extend_from_slice(b"require [\"vacation\", \"relational\", \"date\"];\r\n\r\n");
@@ -413,7 +427,13 @@ impl VacationResponseSet for Server {
                 text_body = Cow::from(html_to_text(html_body.as_ref())).into();
             }
             (None, None) => {
-                text_body = Cow::from("I am away.").into();
+                // Default reply in the language and time zone of the account
+                let until = if let Value::Date(value) = obj.get(&Property::ToDate) {
+                    Some(localization.format_date(value.timestamp()))
+                } else {
+                    None
+                };
+                text_body = Cow::from(away_text(localization.locale, until)).into();
             }
             _ => (),
         }
@@ -466,6 +486,21 @@ impl VacationResponseSet for Server {
     }
 }
 
+fn away_text(locale: Locale, until: Option<String>) -> String {
+    match (locale, until) {
+        (Locale::En, None) => "I am away.".to_string(),
+        (Locale::En, Some(until)) => format!("I am away until {until}."),
+        (Locale::De, None) => "Ich bin nicht erreichbar.".to_string(),
+        (Locale::De, Some(until)) => format!("Ich bin bis {until} nicht erreichbar."),
+        (Locale::Es, None) => "Estoy ausente.".to_string(),
+        (Locale::Es, Some(until)) => format!("Estoy ausente hasta el {until}."),
+        (Locale::Fr, None) => "Je suis absent.".to_string(),
+        (Locale::Fr, Some(until)) => format!("Je suis absent jusqu'au {until}."),
+        (Locale::Pt, None) => "Estou ausente.".to_string(),
+        (Locale::Pt, Some(until)) => format!("Estou ausente até {until}."),
+    }
+}
+
 fn set_error(mut response: SetResponse, id: Option<String>, err: SetError) -> SetResponse {
     if let Some(id) = id {
         response.not_created.append(id, err);
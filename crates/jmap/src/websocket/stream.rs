@@ -6,7 +6,10 @@
 
 use std::{sync::Arc, time::Instant};
 
-use common::{auth::AccessToken, Server};
+use common::{
+    auth::{sessions::SessionGuard, AccessToken},
+    Server,
+};
 use futures_util::{SinkExt, StreamExt};
 use hyper::upgrade::Upgraded;
 use hyper_util::rt::TokioIo;
@@ -37,6 +40,7 @@ pub trait WebSocketHandler: Sync + Send {
         stream: WebSocketStream<TokioIo<Upgraded>>,
         access_token: Arc<AccessToken>,
         session: HttpSessionData,
+        session_guard: SessionGuard,
     ) -> impl Future<Output = ()> + Send;
 }
 
@@ -46,6 +50,7 @@ impl WebSocketHandler for Server {
         mut stream: WebSocketStream<TokioIo<Upgraded>>,
         access_token: Arc<AccessToken>,
         session: HttpSessionData,
+        session_guard: SessionGuard,
     ) {
         trc::event!(
             Jmap(JmapEvent::WebsocketStart),
@@ -85,6 +90,8 @@ impl WebSocketHandler for Server {
 
         let mut changes = WebSocketStateChange::new(None);
         let mut change_types: Bitmap<DataType> = Bitmap::new();
+        let disconnected = session_guard.disconnected();
+        tokio::pin!(disconnected);
 
         loop {
             tokio::select! {
@@ -202,6 +209,16 @@ impl WebSocketHandler for Server {
                         break;
                     }
                 }
+                _ = &mut disconnected => {
+                    trc::event!(
+                        Jmap(JmapEvent::WebsocketStop),
+                        SpanId = session.session_id,
+                        Reason = "Disconnected by administrator"
+                    );
+
+                    let _ = stream.close(None).await;
+                    break;
+                }
             }
 
             if !changes.changed.is_empty() {
@@ -6,7 +6,7 @@
 
 use std::sync::Arc;
 
-use common::{auth::AccessToken, Server};
+use common::{auth::AccessToken, config::sessions::SessionProtocol, Server};
 use hyper::StatusCode;
 use hyper_util::rt::TokioIo;
 use tokio_tungstenite::WebSocketStream;
@@ -72,6 +72,16 @@ impl WebSocketUpgrade for Server {
             }
         };
 
+        // Enforce session limits
+        let session_guard = self
+            .register_session(
+                &access_token,
+                SessionProtocol::Jmap,
+                session.session_id,
+                session.remote_ip,
+            )
+            .await?;
+
         // Spawn WebSocket connection
         let jmap = self.clone();
         tokio::spawn(async move {
@@ -88,6 +98,7 @@ impl WebSocketUpgrade for Server {
                         .await,
                         access_token,
                         session,
+                        session_guard,
                     )
                     .await;
                 }
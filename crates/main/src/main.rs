@@ -9,10 +9,14 @@ use std::time::Duration;
 use common::{config::server::ServerProtocol, core::BuildServer, manager::boot::BootManager};
 use directory::backend::internal::MigrateDirectory;
 use imap::core::ImapSessionManager;
-use jmap::{api::JmapSessionManager, services::gossip::spawn::GossiperBuilder, StartServices};
+use jmap::{
+    api::JmapSessionManager,
+    services::{gossip::spawn::GossiperBuilder, migration::MigrationManager},
+    StartServices,
+};
 use managesieve::core::ManageSieveSessionManager;
 use pop3::Pop3SessionManager;
-use smtp::{core::SmtpSessionManager, StartQueueManager};
+use smtp::{core::SmtpSessionManager, queue::spool::SmtpSpool, StartQueueManager};
 use trc::Collector;
 use utils::wait_for_shutdown;
 
@@ -28,6 +32,13 @@ async fn main() -> std::io::Result<()> {
     // Load config and apply macros
     let mut init = BootManager::init().await;
 
+    // Make sure the queue can be read by this version before processing it
+    if let Err(err) = init.inner.build_server().check_queue_format().await {
+        eprintln!("Queue format check failed: {err}");
+        trc::error!(err);
+        std::process::exit(1);
+    }
+
     // Init services
     init.start_services().await;
     init.start_queue_manager();
@@ -49,6 +60,13 @@ async fn main() -> std::io::Result<()> {
             trc::error!(err.details("Directory migration failed"));
             std::process::exit(1);
         }
+
+        // Backfill data in the background, interrupted migrations resume
+        if server.core.migrations.run_at_startup {
+            tokio::spawn(async move {
+                server.run_migrations().await;
+            });
+        }
     }
 
     // Spawn servers
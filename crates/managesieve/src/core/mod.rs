@@ -10,7 +10,7 @@ pub mod session;
 use std::{borrow::Cow, net::IpAddr, sync::Arc};
 
 use common::{
-    auth::AccessToken,
+    auth::{sessions::SessionGuard, AccessToken},
     listener::{limiter::InFlight, ServerInstance},
     Inner, Server,
 };
@@ -35,6 +35,7 @@ pub enum State {
     Authenticated {
         access_token: Arc<AccessToken>,
         in_flight: Option<InFlight>,
+        session_guard: SessionGuard,
     },
 }
 
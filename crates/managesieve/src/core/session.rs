@@ -65,6 +65,11 @@ impl<T: SessionStream> Session<T> {
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
 
         loop {
+            let disconnected = match &self.state {
+                State::Authenticated { session_guard, .. } => Some(session_guard.disconnected()),
+                State::NotAuthenticated { .. } => None,
+            };
+
             tokio::select! {
                 result = tokio::time::timeout(
                     if !matches!(self.state, State::NotAuthenticated {..}) {
@@ -127,6 +132,24 @@ impl<T: SessionStream> Session<T> {
                     self.write(b"BYE \"Server shutting down.\"\r\n").await.ok();
                     break;
                 }
+                Some(_) = async {
+                    match disconnected {
+                        Some(disconnected) => {
+                            disconnected.await;
+                            Some(())
+                        }
+                        None => None,
+                    }
+                } => {
+                    trc::event!(
+                        Network(trc::NetworkEvent::Closed),
+                        SpanId = self.session_id,
+                        Reason = "Disconnected by administrator",
+                        CausedBy = trc::location!()
+                    );
+                    self.write(b"BYE \"Session terminated by administrator.\"\r\n").await.ok();
+                    break;
+                }
             };
         }
 
@@ -137,7 +160,7 @@ impl<T: SessionStream> Session<T> {
         Ok(Session {
             stream: self
                 .instance
-                .tls_accept(self.stream, self.session_id)
+                .tls_accept(self.stream, self.remote_addr, self.session_id)
                 .await?,
             state: self.state,
             instance: self.instance,
@@ -9,10 +9,11 @@ use common::{
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
         AuthRequest,
     },
+    config::sessions::SessionProtocol,
     listener::{limiter::ConcurrencyLimiter, SessionStream},
     ConcurrencyLimiters,
 };
-use directory::Permission;
+use directory::{backend::internal::AppScope, Permission};
 use imap_proto::{
     protocol::authenticate::Mechanism,
     receiver::{self, Request},
@@ -77,11 +78,10 @@ impl<T: SessionStream> Session<T> {
         // Authenticate
         let access_token = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
-                credentials,
-                self.session_id,
-                self.remote_addr,
-            ))
+            .authenticate(
+                &AuthRequest::from_credentials(credentials, self.session_id, self.remote_addr)
+                    .with_scope(AppScope::Sieve),
+            )
             .await
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
@@ -119,10 +119,22 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
+        // Enforce session limits
+        let session_guard = self
+            .server
+            .register_session(
+                &access_token,
+                SessionProtocol::ManageSieve,
+                self.session_id,
+                self.remote_addr,
+            )
+            .await?;
+
         // Create session
         self.state = State::Authenticated {
             access_token,
             in_flight,
+            session_guard,
         };
 
         Ok(StatusResponse::ok("Authentication successful").into_bytes())
@@ -9,7 +9,7 @@ use std::time::Instant;
 use common::listener::SessionStream;
 use jmap_proto::request::capability::Capabilities;
 
-use crate::core::{Session, StatusResponse};
+use crate::core::{Session, State, StatusResponse};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_capability(&self, message: &'static str) -> trc::Result<Vec<u8>> {
@@ -49,6 +49,16 @@ impl<T: SessionStream> Session<T> {
                 response.extend_from_slice(notification_methods.join(" ").as_bytes());
                 response.extend_from_slice(b"\"\r\n");
             }
+            let ext_lists = if let State::Authenticated { access_token, .. } = &self.state {
+                self.server.sieve_ext_list_names(access_token).await
+            } else {
+                sieve.ext_lists.clone().unwrap_or_default()
+            };
+            if !ext_lists.is_empty() {
+                response.extend_from_slice(b"\"EXTLISTS\" \"");
+                response.extend_from_slice(ext_lists.join(" ").as_bytes());
+                response.extend_from_slice(b"\"\r\n");
+            }
             if sieve.max_redirects > 0 {
                 response.extend_from_slice(b"\"MAXREDIRECTS\" \"");
                 response.extend_from_slice(sieve.max_redirects.to_string().as_bytes());
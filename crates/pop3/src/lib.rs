@@ -7,7 +7,7 @@
 use std::{net::IpAddr, sync::Arc};
 
 use common::{
-    auth::AccessToken,
+    auth::{sessions::SessionGuard, AccessToken},
     listener::{limiter::InFlight, ServerInstance, SessionStream},
     Inner, Server,
 };
@@ -52,6 +52,7 @@ pub enum State {
     Authenticated {
         mailbox: Mailbox,
         in_flight: Option<InFlight>,
+        session_guard: SessionGuard,
         access_token: Arc<AccessToken>,
     },
 }
@@ -9,10 +9,11 @@ use common::{
         sasl::{sasl_decode_challenge_oauth, sasl_decode_challenge_plain},
         AuthRequest,
     },
+    config::sessions::SessionProtocol,
     listener::{limiter::ConcurrencyLimiter, SessionStream},
     ConcurrencyLimiters,
 };
-use directory::Permission;
+use directory::{backend::internal::AppScope, Permission};
 use jmap::auth::rate_limit::RateLimiter;
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
@@ -74,11 +75,10 @@ impl<T: SessionStream> Session<T> {
         // Authenticate
         let access_token = self
             .server
-            .authenticate(&AuthRequest::from_credentials(
-                credentials,
-                self.session_id,
-                self.remote_addr,
-            ))
+            .authenticate(
+                &AuthRequest::from_credentials(credentials, self.session_id, self.remote_addr)
+                    .with_scope(AppScope::Pop3),
+            )
             .await
             .map_err(|err| {
                 if err.matches(trc::EventType::Auth(trc::AuthEvent::Failed)) {
@@ -118,12 +118,24 @@ impl<T: SessionStream> Session<T> {
             }
         };
 
+        // Enforce session limits
+        let session_guard = self
+            .server
+            .register_session(
+                &access_token,
+                SessionProtocol::Pop3,
+                self.session_id,
+                self.remote_addr,
+            )
+            .await?;
+
         // Fetch mailbox
         let mailbox = self.fetch_mailbox(access_token.primary_id()).await?;
 
         // Create session
         self.state = State::Authenticated {
             in_flight,
+            session_guard,
             mailbox,
             access_token,
         };
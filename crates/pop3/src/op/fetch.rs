@@ -8,12 +8,12 @@ use std::time::Instant;
 
 use common::listener::SessionStream;
 use directory::Permission;
-use jmap::{blob::download::BlobDownload, email::metadata::MessageMetadata, JmapMethods};
+use jmap::{email::metadata::MessageMetadata, JmapMethods};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::write::Bincode;
 use trc::AddContext;
 
-use crate::{protocol::response::Response, Session};
+use crate::{protocol::response::MessageEncoder, Session};
 
 impl<T: SessionStream> Session<T> {
     pub async fn handle_fetch(&mut self, msg: u32, lines: Option<u32>) -> trc::Result<()> {
@@ -36,27 +36,46 @@ impl<T: SessionStream> Session<T> {
                 .await
                 .caused_by(trc::location!())?
             {
-                if let Some(bytes) = self
+                // Stream the message in chunks rather than loading it in full
+                let document_id = message.id;
+                let mut chunks = self
                     .server
-                    .get_blob(&metadata.inner.blob_hash, 0..usize::MAX)
-                    .await
-                    .caused_by(trc::location!())?
-                {
+                    .core
+                    .storage
+                    .blob
+                    .get_blob_chunks(metadata.inner.blob_hash.as_ref(), 0..usize::MAX);
+                if let Some(mut chunk) = chunks.next().await.caused_by(trc::location!())? {
+                    let mut encoder = MessageEncoder::new(lines.unwrap_or(0));
+                    let mut buf = Vec::with_capacity(chunk.len() + 32);
+                    encoder.write_header(metadata.inner.size, &mut buf);
+                    while encoder.write_chunk(&chunk, &mut buf) {
+                        match chunks.next().await {
+                            Ok(Some(next_chunk)) => {
+                                self.write_bytes(&buf).await?;
+                                buf.clear();
+                                chunk = next_chunk;
+                            }
+                            Ok(None) => break,
+                            Err(err) => {
+                                // The response is already under way, terminate it
+                                trc::error!(err
+                                    .span_id(self.session_id)
+                                    .document_id(document_id)
+                                    .caused_by(trc::location!()));
+                                break;
+                            }
+                        }
+                    }
+                    encoder.write_end(&mut buf);
+
                     trc::event!(
                         Pop3(trc::Pop3Event::Fetch),
                         SpanId = self.session_id,
-                        DocumentId = message.id,
+                        DocumentId = document_id,
                         Elapsed = op_start.elapsed()
                     );
 
-                    self.write_bytes(
-                        Response::Message::<u32> {
-                            bytes,
-                            lines: lines.unwrap_or(0),
-                        }
-                        .serialize(),
-                    )
-                    .await
+                    self.write_bytes(buf).await
                 } else {
                     Err(trc::Pop3Event::Error
                         .into_err()
@@ -22,6 +22,71 @@ pub enum Response<T> {
     },
 }
 
+// Applies the transparency procedure to a message that may be
+// written in several chunks.
+pub struct MessageEncoder {
+    lines: u32,
+    line_count: u32,
+    last_byte: u8,
+}
+
+impl MessageEncoder {
+    pub fn new(lines: u32) -> Self {
+        MessageEncoder {
+            lines,
+            line_count: 0,
+            last_byte: 0,
+        }
+    }
+
+    pub fn write_header(&self, size: usize, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(b"+OK ");
+        buf.extend_from_slice(size.to_string().as_bytes());
+        buf.extend_from_slice(b" octets\r\n");
+    }
+
+    // Returns false once the requested number of lines has been written
+    pub fn write_chunk(&mut self, bytes: &[u8], buf: &mut Vec<u8>) -> bool {
+        if self.is_done() {
+            return false;
+        }
+
+        for &byte in bytes {
+            // POP3 requires that lines end with CRLF, do this check to ensure that
+            if byte == b'\n' && self.last_byte != b'\r' {
+                buf.push(b'\r');
+            }
+
+            if byte == b'.' && self.last_byte == b'\n' {
+                buf.push(b'.');
+            }
+            buf.push(byte);
+            self.last_byte = byte;
+
+            if self.lines > 0 && byte == b'\n' {
+                self.line_count += 1;
+                if self.is_done() {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    pub fn write_end(&self, buf: &mut Vec<u8>) {
+        if self.last_byte != b'\n' {
+            buf.extend_from_slice(b"\r\n");
+        }
+
+        buf.extend_from_slice(b".\r\n");
+    }
+
+    fn is_done(&self) -> bool {
+        self.lines > 0 && self.line_count == self.lines
+    }
+}
+
 impl<T: Display> Response<T> {
     pub fn serialize(&self) -> Vec<u8> {
         match self {
@@ -53,39 +118,10 @@ impl<T: Display> Response<T> {
             }
             Response::Message { bytes, lines } => {
                 let mut buf = Vec::with_capacity(bytes.len() + 10);
-                buf.extend_from_slice(b"+OK ");
-                buf.extend_from_slice(bytes.len().to_string().as_bytes());
-                buf.extend_from_slice(b" octets\r\n");
-
-                let mut line_count = 0;
-                let mut last_byte = 0;
-
-                // Transparency procedure
-                for &byte in bytes {
-                    // POP3 requires that lines end with CRLF, do this check to ensure that
-                    if byte == b'\n' && last_byte != b'\r' {
-                        buf.push(b'\r');
-                    }
-
-                    if byte == b'.' && last_byte == b'\n' {
-                        buf.push(b'.');
-                    }
-                    buf.push(byte);
-                    last_byte = byte;
-
-                    if *lines > 0 && byte == b'\n' {
-                        line_count += 1;
-                        if line_count == *lines {
-                            break;
-                        }
-                    }
-                }
-
-                if last_byte != b'\n' {
-                    buf.extend_from_slice(b"\r\n");
-                }
-
-                buf.extend_from_slice(b".\r\n");
+                let mut encoder = MessageEncoder::new(*lines);
+                encoder.write_header(bytes.len(), &mut buf);
+                encoder.write_chunk(bytes, &mut buf);
+                encoder.write_end(&mut buf);
                 buf
             }
             Response::Capability { mechanisms, stls } => {
@@ -168,7 +204,7 @@ mod tests {
 
     use crate::protocol::Mechanism;
 
-    use super::Response;
+    use super::{MessageEncoder, Response};
 
     #[test]
     fn serialize_response() {
@@ -217,4 +253,34 @@ mod tests {
             assert_eq!(expected, String::from_utf8(cmd.serialize()).unwrap());
         }
     }
+
+    #[test]
+    fn serialize_message_chunks() {
+        let message = "Subject: test\n\n.\r\ntest.\r\n.test\r\nline\r\na";
+        for lines in [0, 4] {
+            let expected = Response::Message::<u32> {
+                bytes: message.as_bytes().to_vec(),
+                lines,
+            }
+            .serialize();
+
+            // Splitting at any position must produce the same output
+            for chunk_size in 1..message.len() {
+                let mut buf = Vec::new();
+                let mut encoder = MessageEncoder::new(lines);
+                encoder.write_header(message.len(), &mut buf);
+                for chunk in message.as_bytes().chunks(chunk_size) {
+                    if !encoder.write_chunk(chunk, &mut buf) {
+                        break;
+                    }
+                }
+                encoder.write_end(&mut buf);
+                assert_eq!(
+                    String::from_utf8(expected.clone()).unwrap(),
+                    String::from_utf8(buf).unwrap(),
+                    "lines {lines}, chunk size {chunk_size}"
+                );
+            }
+        }
+    }
 }
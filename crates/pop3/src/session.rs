@@ -69,6 +69,11 @@ impl<T: SessionStream> Session<T> {
         let mut shutdown_rx = self.instance.shutdown_rx.clone();
 
         loop {
+            let disconnected = match &self.state {
+                State::Authenticated { session_guard, .. } => Some(session_guard.disconnected()),
+                State::NotAuthenticated { .. } => None,
+            };
+
             tokio::select! {
                 result = tokio::time::timeout(
                     if !matches!(self.state, State::NotAuthenticated {..}) {
@@ -130,6 +135,24 @@ impl<T: SessionStream> Session<T> {
                     self.write_bytes(&b"* BYE Server shutting down.\r\n"[..]).await.ok();
                     break;
                 }
+                Some(_) = async {
+                    match disconnected {
+                        Some(disconnected) => {
+                            disconnected.await;
+                            Some(())
+                        }
+                        None => None,
+                    }
+                } => {
+                    trc::event!(
+                        Network(trc::NetworkEvent::Closed),
+                        SpanId = self.session_id,
+                        Reason = "Disconnected by administrator",
+                        CausedBy = trc::location!()
+                    );
+                    self.write_bytes(&b"-ERR Session terminated by administrator.\r\n"[..]).await.ok();
+                    break;
+                }
             };
         }
 
@@ -140,7 +163,7 @@ impl<T: SessionStream> Session<T> {
         Ok(Session {
             stream: self
                 .instance
-                .tls_accept(self.stream, self.session_id)
+                .tls_accept(self.stream, self.remote_addr, self.session_id)
                 .await?,
             server: self.server,
             instance: self.instance,
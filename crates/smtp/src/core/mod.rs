@@ -29,7 +29,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use utils::snowflake::SnowflakeIdGenerator;
 
 use crate::{
-    inbound::auth::SaslToken,
+    inbound::{auth::SaslToken, dnsbl::DnsblVerdict},
     queue::{DomainPart, QueueId},
 };
 
@@ -81,11 +81,13 @@ pub struct SessionData {
 
     pub mail_from: Option<SessionAddress>,
     pub rcpt_to: Vec<SessionAddress>,
+    pub list_postings: Vec<ListPosting>,
     pub rcpt_errors: usize,
     pub rcpt_oks: usize,
     pub message: Vec<u8>,
 
     pub authenticated_as: Option<Arc<AccessToken>>,
+    pub authenticated_at: u64,
     pub auth_errors: usize,
 
     pub priority: i16,
@@ -100,6 +102,7 @@ pub struct SessionData {
     pub spf_ehlo: Option<SpfOutput>,
     pub spf_mail_from: Option<SpfOutput>,
     pub dnsbl_error: Option<Vec<u8>>,
+    pub dnsbl: Option<DnsblVerdict>,
 }
 
 #[derive(Clone, Debug)]
@@ -111,6 +114,17 @@ pub struct SessionAddress {
     pub dsn_info: Option<String>,
 }
 
+/// Members added by a mailing list that holds posts for moderation or
+/// rewrites Reply-To, they receive a separate copy of the message.
+#[derive(Clone, Debug)]
+pub struct ListPosting {
+    pub address: String,
+    pub list_id: u32,
+    pub members: Vec<String>,
+    pub moderate: bool,
+    pub reply_to_list: bool,
+}
+
 #[derive(Debug, Default)]
 pub struct SessionParameters {
     // Global parameters
@@ -161,7 +175,9 @@ impl SessionData {
             helo_domain: String::new(),
             mail_from: None,
             rcpt_to: Vec::new(),
+            list_postings: Vec::new(),
             authenticated_as: None,
+            authenticated_at: 0,
             priority: 0,
             valid_until: Instant::now(),
             rcpt_errors: 0,
@@ -176,6 +192,7 @@ impl SessionData {
             spf_ehlo: None,
             spf_mail_from: None,
             dnsbl_error: None,
+            dnsbl: None,
         }
     }
 }
@@ -311,10 +328,12 @@ impl SessionData {
             helo_domain: "localhost".into(),
             mail_from,
             rcpt_to,
+            list_postings: Vec::new(),
             rcpt_errors: 0,
             rcpt_oks: 0,
             message,
             authenticated_as: Some(Arc::new(AccessToken::from_id(0))),
+            authenticated_at: 0,
             auth_errors: 0,
             priority: 0,
             delivery_by: 0,
@@ -326,6 +345,7 @@ impl SessionData {
             spf_ehlo: None,
             spf_mail_from: None,
             dnsbl_error: None,
+            dnsbl: None,
         }
     }
 }
@@ -0,0 +1,322 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read},
+};
+
+use common::{
+    config::smtp::session::{AttachmentAction, AttachmentPolicy},
+    listener::SessionStream,
+};
+use directory::backend::internal::manage::ManageDirectory;
+use mail_parser::{Message, MessageParser, MimeHeaders};
+use trc::{AddContext, SmtpEvent};
+
+use crate::core::Session;
+
+// Enough bytes to detect the type of an archived file
+const MAX_SNIFF_LEN: u64 = 8192;
+const MAX_NAME_LEN: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttachmentViolation {
+    pub name: String,
+    pub reason: String,
+}
+
+pub enum AttachmentVerdict {
+    Accept,
+    Warn(Vec<AttachmentViolation>),
+    Reject(AttachmentViolation),
+}
+
+impl<T: SessionStream> Session<T> {
+    pub async fn check_attachments(&self, raw_message: &[u8]) -> AttachmentVerdict {
+        let policies = match self.attachment_policies().await {
+            Ok(policies) if !policies.is_empty() => policies,
+            Ok(_) => return AttachmentVerdict::Accept,
+            Err(err) => {
+                trc::error!(err
+                    .span_id(self.data.session_id)
+                    .details("Failed to obtain attachment policies"));
+                return AttachmentVerdict::Accept;
+            }
+        };
+        let Some(message) = MessageParser::new().parse(raw_message) else {
+            return AttachmentVerdict::Accept;
+        };
+
+        let mut warnings = Vec::new();
+        for policy in policies {
+            let violations = inspect_attachments(policy, &message);
+            if violations.is_empty() {
+                continue;
+            }
+
+            match policy.action {
+                AttachmentAction::Reject => {
+                    let violation = violations.into_iter().next().unwrap();
+
+                    trc::event!(
+                        Smtp(SmtpEvent::AttachmentRejected),
+                        SpanId = self.data.session_id,
+                        Details = violation.name.clone(),
+                        Reason = violation.reason.clone(),
+                    );
+
+                    return AttachmentVerdict::Reject(violation);
+                }
+                AttachmentAction::Warn => {
+                    for violation in violations {
+                        if !warnings.contains(&violation) {
+                            warnings.push(violation);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !warnings.is_empty() {
+            trc::event!(
+                Smtp(SmtpEvent::AttachmentWarning),
+                SpanId = self.data.session_id,
+                Details = warnings.iter().map(|v| v.name.clone()).collect::<Vec<_>>(),
+            );
+
+            AttachmentVerdict::Warn(warnings)
+        } else {
+            AttachmentVerdict::Accept
+        }
+    }
+
+    async fn attachment_policies(&self) -> trc::Result<Vec<&AttachmentPolicy>> {
+        let config = &self.server.core.smtp.session.data.attachments;
+        if config.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Policies are resolved for the sender's tenant on submission
+        // and for the tenant of each local recipient on delivery
+        let is_submission = self.is_authenticated();
+        let mut tenant_ids = Vec::new();
+        if !config.tenants.is_empty() {
+            if is_submission {
+                tenant_ids.push(
+                    self.data
+                        .authenticated_as
+                        .as_ref()
+                        .and_then(|token| token.tenant)
+                        .map(|tenant| tenant.id),
+                );
+            } else {
+                for rcpt in &self.data.rcpt_to {
+                    let tenant_id = self
+                        .server
+                        .tenant_by_address(&rcpt.address_lcase)
+                        .await
+                        .caused_by(trc::location!())?;
+                    if !tenant_ids.contains(&tenant_id) {
+                        tenant_ids.push(tenant_id);
+                    }
+                }
+            }
+        }
+
+        let mut tenant_policies = Vec::with_capacity(config.tenants.len());
+        if tenant_ids.iter().any(|id| id.is_some()) {
+            for (name, policy) in &config.tenants {
+                if let Some(tenant_id) = self
+                    .server
+                    .core
+                    .storage
+                    .data
+                    .get_principal_id(name)
+                    .await
+                    .caused_by(trc::location!())?
+                {
+                    tenant_policies.push((tenant_id, policy));
+                }
+            }
+        }
+
+        let mut policies: Vec<&AttachmentPolicy> = Vec::new();
+        if tenant_ids.is_empty() {
+            tenant_ids.push(None);
+        }
+        for tenant_id in tenant_ids {
+            if let Some(policy) = tenant_id
+                .and_then(|tenant_id| {
+                    tenant_policies
+                        .iter()
+                        .find(|(id, _)| *id == tenant_id)
+                        .map(|(_, policy)| *policy)
+                })
+                .or(config.global.as_ref())
+                .filter(|policy| policy.direction.matches(is_submission))
+            {
+                if !policies.iter().any(|p| std::ptr::eq(*p, policy)) {
+                    policies.push(policy);
+                }
+            }
+        }
+
+        Ok(policies)
+    }
+}
+
+pub fn inspect_attachments(
+    policy: &AttachmentPolicy,
+    message: &Message<'_>,
+) -> Vec<AttachmentViolation> {
+    let mut violations = Vec::new();
+    let mut total_size = 0;
+    let mut total_exceeded = false;
+
+    for part in message.attachments() {
+        let name = part.attachment_name().unwrap_or("unnamed");
+        let contents = part.contents();
+
+        // Size limits
+        total_size += contents.len();
+        if let Some(max_size) = policy.max_size.filter(|max| contents.len() > *max) {
+            violations.push(AttachmentViolation::new(
+                name,
+                format!("exceeds the maximum attachment size of {max_size} bytes"),
+            ));
+        }
+        if let Some(max_size) = policy
+            .max_total_size
+            .filter(|max| !total_exceeded && total_size > *max)
+        {
+            total_exceeded = true;
+            violations.push(AttachmentViolation::new(
+                name,
+                format!("exceeds the maximum total attachment size of {max_size} bytes"),
+            ));
+        }
+
+        // Banned extensions and types
+        let content_type = part.content_type().map(|ct| {
+            if let Some(subtype) = ct.subtype() {
+                format!("{}/{}", ct.ctype(), subtype).to_lowercase()
+            } else {
+                ct.ctype().to_lowercase()
+            }
+        });
+        if let Some(reason) = check_file(policy, name, content_type.as_deref(), contents) {
+            violations.push(AttachmentViolation::new(name, reason));
+        }
+
+        // Inspect archives one level deep
+        if policy.inspect_archives && infer::archive::is_zip(contents) {
+            inspect_archive(policy, name, contents, &mut violations);
+        }
+    }
+
+    violations
+}
+
+fn inspect_archive(
+    policy: &AttachmentPolicy,
+    archive_name: &str,
+    contents: &[u8],
+    violations: &mut Vec<AttachmentViolation>,
+) {
+    let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(contents)) else {
+        return;
+    };
+
+    for idx in 0..archive.len() {
+        let Ok((file_name, size, encrypted)) = archive
+            .by_index_raw(idx)
+            .map(|file| (file.name().to_string(), file.size(), file.encrypted()))
+        else {
+            continue;
+        };
+        if file_name.ends_with('/') {
+            continue;
+        }
+        let name = format!("{archive_name}/{file_name}");
+
+        if let Some(max_size) = policy.max_size.filter(|max| size > *max as u64) {
+            violations.push(AttachmentViolation::new(
+                &name,
+                format!("exceeds the maximum attachment size of {max_size} bytes"),
+            ));
+        }
+
+        // Only the first bytes are decompressed, encrypted files are
+        // checked by name only
+        let mut header = Vec::new();
+        if !encrypted {
+            if let Ok(file) = archive.by_index(idx) {
+                let _ = file.take(MAX_SNIFF_LEN).read_to_end(&mut header);
+            }
+        }
+        if let Some(reason) = check_file(policy, &file_name, None, &header) {
+            violations.push(AttachmentViolation::new(&name, reason));
+        }
+    }
+}
+
+fn check_file(
+    policy: &AttachmentPolicy,
+    name: &str,
+    content_type: Option<&str>,
+    contents: &[u8],
+) -> Option<String> {
+    if let Some(ext) = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .filter(|ext| policy.banned_extensions.contains(ext))
+    {
+        return Some(format!("has a banned extension {ext:?}"));
+    }
+
+    if let Some(content_type) = content_type.filter(|ct| policy.banned_types.contains(*ct)) {
+        return Some(format!("has a banned content type {content_type:?}"));
+    }
+
+    // Verify the actual contents so renamed files are caught
+    infer::get(contents)
+        .filter(|kind| {
+            policy.banned_types.contains(kind.mime_type())
+                || policy.banned_extensions.contains(kind.extension())
+        })
+        .map(|kind| format!("contains banned {:?} content", kind.mime_type()))
+}
+
+impl AttachmentViolation {
+    fn new(name: &str, reason: String) -> Self {
+        AttachmentViolation {
+            name: name
+                .chars()
+                .filter(|ch| !ch.is_control() && *ch != '"')
+                .take(MAX_NAME_LEN)
+                .collect(),
+            reason,
+        }
+    }
+
+    pub fn to_response(&self) -> Cow<'static, [u8]> {
+        format!(
+            "550 5.7.1 Attachment \"{}\" {}, message rejected by policy.\r\n",
+            self.name, self.reason
+        )
+        .into_bytes()
+        .into()
+    }
+
+    pub fn write_header(&self, headers: &mut Vec<u8>) {
+        headers.extend_from_slice(b"X-Attachment-Warning: \"");
+        headers.extend_from_slice(self.name.as_bytes());
+        headers.extend_from_slice(b"\" ");
+        headers.extend_from_slice(self.reason.as_bytes());
+        headers.extend_from_slice(b"\r\n");
+    }
+}
@@ -13,10 +13,11 @@ use common::{
     },
     listener::SessionStream,
 };
-use directory::Permission;
+use directory::{backend::internal::AppScope, Permission};
 use mail_parser::decoders::base64::base64_decode;
 use mail_send::Credentials;
 use smtp_proto::{IntoString, AUTH_LOGIN, AUTH_OAUTHBEARER, AUTH_PLAIN, AUTH_XOAUTH2};
+use store::write::now;
 use trc::{AuthEvent, SmtpEvent};
 
 use crate::core::Session;
@@ -124,7 +125,8 @@ impl<T: SessionStream> Session<T> {
                         self.data.session_id,
                         self.data.remote_ip,
                     )
-                    .with_directory(directory),
+                    .with_directory(directory)
+                    .with_scope(AppScope::Smtp),
                 )
                 .await
                 .and_then(|access_token| {
@@ -136,6 +138,7 @@ impl<T: SessionStream> Session<T> {
             match result {
                 Ok(access_token) => {
                     self.data.authenticated_as = access_token.into();
+                    self.data.authenticated_at = now();
                     self.eval_post_auth_params().await;
                     self.write(b"235 2.7.0 Authentication succeeded.\r\n")
                         .await?;
@@ -155,6 +158,22 @@ impl<T: SessionStream> Session<T> {
                         trc::EventType::Auth(trc::AuthEvent::TokenExpired) => {
                             return self.auth_error(b"535 5.7.8 OAuth token expired.\r\n").await;
                         }
+                        trc::EventType::Auth(trc::AuthEvent::AccountDisabled) => {
+                            self.write(b"535 5.7.8 This account has been disabled.\r\n")
+                                .await?;
+                            return Ok(false);
+                        }
+                        trc::EventType::Auth(trc::AuthEvent::AccountLocked) => {
+                            self.write(
+                                concat!(
+                                    "535 5.7.8 This account is temporarily locked ",
+                                    "after too many failed attempts.\r\n"
+                                )
+                                .as_bytes(),
+                            )
+                            .await?;
+                            return Ok(false);
+                        }
                         trc::EventType::Auth(trc::AuthEvent::MissingTotp) => {
                             return self
                             .auth_error(
@@ -192,6 +211,51 @@ impl<T: SessionStream> Session<T> {
         Ok(false)
     }
 
+    // Authenticates machine senders using a client certificate verified by the listener,
+    // the certificate is mapped to a principal by fingerprint or e-mail address.
+    pub async fn authenticate_client_certificate(&mut self) {
+        let Some(certificate) = self.stream.peer_certificate() else {
+            return;
+        };
+        let Some(directory) = &self.params.auth_directory else {
+            trc::event!(
+                Smtp(SmtpEvent::MissingAuthDirectory),
+                SpanId = self.data.session_id,
+            );
+            return;
+        };
+
+        let result = self
+            .server
+            .authenticate_certificate(
+                certificate.as_ref(),
+                Some(directory),
+                self.data.session_id,
+                self.data.remote_ip,
+            )
+            .await
+            .and_then(|access_token| match access_token {
+                Some(access_token) => access_token
+                    .assert_has_permission(Permission::EmailSend)
+                    .map(|_| Some(access_token)),
+                None => Ok(None),
+            });
+
+        match result {
+            Ok(Some(access_token)) => {
+                self.data.authenticated_as = access_token.into();
+                self.data.authenticated_at = now();
+                self.eval_post_auth_params().await;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                trc::error!(err
+                    .ctx(trc::Key::RemoteIp, self.data.remote_ip)
+                    .span_id(self.data.session_id));
+            }
+        }
+    }
+
     pub async fn auth_error(&mut self, response: &[u8]) -> Result<bool, ()> {
         tokio::time::sleep(self.params.auth_errors_wait).await;
         self.data.auth_errors += 1;
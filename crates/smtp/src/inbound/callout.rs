@@ -0,0 +1,417 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    future::Future,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use common::{
+    config::{server::ServerProtocol, smtp::queue::RelayHost},
+    Server,
+};
+use mail_send::smtp::AssertReply;
+use parking_lot::Mutex;
+use smtp_proto::{EhloResponse, Response, EXT_START_TLS};
+use store::{write::Bincode, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::client::TlsStream;
+use trc::{AddContext, SmtpEvent};
+
+use crate::outbound::{client::SmtpClient, session::SessionParams};
+
+static CALLOUT_POOL: LazyLock<Mutex<AHashMap<String, Vec<PooledClient>>>> =
+    LazyLock::new(Default::default);
+
+pub enum CalloutResult {
+    Accept,
+    Reject(Response<String>),
+    Defer(Response<String>),
+    Failed,
+}
+
+enum CalloutClient {
+    Plain(SmtpClient<TcpStream>),
+    Tls(Box<SmtpClient<TlsStream<TcpStream>>>),
+}
+
+struct PooledClient {
+    client: CalloutClient,
+    idle_since: Instant,
+}
+
+pub trait SmtpCallout: Sync + Send {
+    fn callout(
+        &self,
+        relay_id: &str,
+        address: &str,
+        domain: &str,
+        local_hostname: &str,
+        session_id: u64,
+    ) -> impl Future<Output = CalloutResult> + Send;
+
+    fn flush_callout_cache(&self, domain: &str) -> impl Future<Output = trc::Result<()>> + Send;
+}
+
+impl SmtpCallout for Server {
+    async fn callout(
+        &self,
+        relay_id: &str,
+        address: &str,
+        domain: &str,
+        local_hostname: &str,
+        session_id: u64,
+    ) -> CalloutResult {
+        // Check the cache first
+        let cache_key = match self.callout_cache_key(address, domain).await {
+            Ok(cache_key) => {
+                match self
+                    .lookup_store()
+                    .key_get::<Bincode<Response<String>>>(cache_key.clone())
+                    .await
+                {
+                    Ok(Some(response)) => {
+                        return callout_result(response.inner, address, relay_id, session_id, true)
+                    }
+                    Ok(None) => Some(cache_key),
+                    Err(err) => {
+                        trc::error!(err
+                            .span_id(session_id)
+                            .details("Failed to read callout cache"));
+                        None
+                    }
+                }
+            }
+            Err(err) => {
+                trc::error!(err
+                    .span_id(session_id)
+                    .details("Failed to read callout cache"));
+                None
+            }
+        };
+
+        let Some(relay_host) = self.get_relay_host(relay_id, session_id) else {
+            return CalloutResult::Failed;
+        };
+
+        // Verify the recipient with the next hop, pooled connections
+        // might have been closed by the remote end so retry once.
+        let config = &self.core.smtp.session.callout;
+        let mut pooled = take_pooled_client(relay_id, config.pool_idle_timeout);
+        let response = loop {
+            let is_pooled = pooled.is_some();
+            let result = match pooled.take() {
+                Some(client) => Ok(client),
+                None => {
+                    self.callout_client(relay_host, local_hostname, session_id)
+                        .await
+                }
+            };
+            let result = match result {
+                Ok(mut client) => {
+                    client.set_session_id(session_id);
+                    match client.verify(address).await {
+                        Ok(response) => {
+                            if client.reset().await.is_ok() {
+                                return_pooled_client(relay_id, client, config.pool_max_connections);
+                            }
+                            Ok(response)
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Ok(response) => break response,
+                Err(_) if is_pooled => {}
+                Err(err) => {
+                    trc::event!(
+                        Smtp(SmtpEvent::CalloutFailed),
+                        SpanId = session_id,
+                        Id = relay_id.to_string(),
+                        To = address.to_string(),
+                        Reason = err.to_string(),
+                    );
+                    return CalloutResult::Failed;
+                }
+            }
+        };
+
+        // Cache definitive answers
+        let ttl = match response.code / 100 {
+            2 => Some(config.cache_positive),
+            5 => Some(config.cache_negative),
+            _ => None,
+        };
+        if let (Some(ttl), Some(cache_key)) = (ttl, cache_key) {
+            if let Err(err) = self
+                .lookup_store()
+                .key_set(
+                    cache_key,
+                    Bincode::new(response.clone()).serialize(),
+                    ttl.as_secs().into(),
+                )
+                .await
+            {
+                trc::error!(err
+                    .span_id(session_id)
+                    .details("Failed to write callout cache"));
+            }
+        }
+
+        callout_result(response, address, relay_id, session_id, false)
+    }
+
+    async fn flush_callout_cache(&self, domain: &str) -> trc::Result<()> {
+        // Cached entries are keyed by a per-domain generation, bumping it
+        // invalidates all of them regardless of the lookup store backend.
+        self.lookup_store()
+            .counter_incr(
+                format!("callout-gen:{}", domain.to_lowercase()).into_bytes(),
+                1,
+                None,
+                false,
+            )
+            .await
+            .map(|_| ())
+            .caused_by(trc::location!())
+    }
+}
+
+trait CalloutConnect: Sync + Send {
+    fn callout_cache_key(
+        &self,
+        address: &str,
+        domain: &str,
+    ) -> impl Future<Output = trc::Result<Vec<u8>>> + Send;
+
+    fn callout_client(
+        &self,
+        relay_host: &RelayHost,
+        local_hostname: &str,
+        session_id: u64,
+    ) -> impl Future<Output = mail_send::Result<CalloutClient>> + Send;
+}
+
+impl CalloutConnect for Server {
+    async fn callout_cache_key(&self, address: &str, domain: &str) -> trc::Result<Vec<u8>> {
+        let generation = self
+            .lookup_store()
+            .counter_get(format!("callout-gen:{domain}").into_bytes())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(format!("callout:{domain}:{generation}:{address}").into_bytes())
+    }
+
+    async fn callout_client(
+        &self,
+        relay_host: &RelayHost,
+        local_hostname: &str,
+        session_id: u64,
+    ) -> mail_send::Result<CalloutClient> {
+        let config = &self.core.smtp.session.callout;
+        let remote_addr = tokio::net::lookup_host((relay_host.address.as_str(), relay_host.port))
+            .await?
+            .next()
+            .ok_or_else(|| {
+                mail_send::Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "Failed to resolve relay host",
+                ))
+            })?;
+        let client = SmtpClient::connect(remote_addr, config.timeout, session_id).await?;
+        let params = SessionParams {
+            server: self,
+            hostname: &relay_host.address,
+            credentials: relay_host.auth.as_ref(),
+            is_smtp: relay_host.protocol == ServerProtocol::Smtp,
+            local_hostname,
+            timeout_ehlo: config.timeout,
+            timeout_mail: config.timeout,
+            timeout_rcpt: config.timeout,
+            timeout_data: config.timeout,
+            session_id,
+        };
+        let tls_connector = if relay_host.tls_allow_invalid_certs {
+            &self.inner.data.smtp_connectors.dummy_verify
+        } else {
+            &self.inner.data.smtp_connectors.pki_verify
+        };
+
+        let mut client = if relay_host.tls_implicit {
+            let mut client = client.into_tls(tls_connector, &relay_host.address).await?;
+            read_greeting(&mut client).await?;
+            client
+        } else {
+            let mut client = client;
+            read_greeting(&mut client).await?;
+            let capabilities = say_helo(&mut client, &params).await?;
+            if !capabilities.has_capability(EXT_START_TLS) {
+                if let Some(credentials) = params.credentials {
+                    client.authenticate(credentials, &capabilities).await?;
+                }
+
+                return Ok(CalloutClient::Plain(client));
+            }
+
+            client.start_tls(tls_connector, &relay_host.address).await?
+        };
+
+        let capabilities = say_helo(&mut client, &params).await?;
+        if let Some(credentials) = params.credentials {
+            client.authenticate(credentials, &capabilities).await?;
+        }
+
+        Ok(CalloutClient::Tls(Box::new(client)))
+    }
+}
+
+impl CalloutClient {
+    async fn verify(&mut self, address: &str) -> mail_send::Result<Response<String>> {
+        match self {
+            CalloutClient::Plain(client) => verify(client, address).await,
+            CalloutClient::Tls(client) => verify(client, address).await,
+        }
+    }
+
+    async fn reset(&mut self) -> mail_send::Result<()> {
+        match self {
+            CalloutClient::Plain(client) => client.cmd(b"RSET\r\n").await,
+            CalloutClient::Tls(client) => client.cmd(b"RSET\r\n").await,
+        }?
+        .assert_positive_completion()
+    }
+
+    fn set_session_id(&mut self, session_id: u64) {
+        match self {
+            CalloutClient::Plain(client) => client.session_id = session_id,
+            CalloutClient::Tls(client) => client.session_id = session_id,
+        }
+    }
+}
+
+fn take_pooled_client(relay_id: &str, idle_timeout: Duration) -> Option<CalloutClient> {
+    let mut pool = CALLOUT_POOL.lock();
+    let clients = pool.get_mut(relay_id)?;
+    clients.retain(|c| c.idle_since.elapsed() < idle_timeout);
+    clients.pop().map(|c| c.client)
+}
+
+fn return_pooled_client(relay_id: &str, client: CalloutClient, max_connections: usize) {
+    let mut pool = CALLOUT_POOL.lock();
+    let clients = pool.entry(relay_id.to_string()).or_default();
+    if clients.len() < max_connections {
+        clients.push(PooledClient {
+            client,
+            idle_since: Instant::now(),
+        });
+    }
+}
+
+async fn read_greeting<T: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut SmtpClient<T>,
+) -> mail_send::Result<()> {
+    tokio::time::timeout(client.timeout, client.read())
+        .await
+        .map_err(|_| mail_send::Error::Timeout)?
+        .and_then(|r| r.assert_code(220))
+}
+
+async fn say_helo<T: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut SmtpClient<T>,
+    params: &SessionParams<'_>,
+) -> mail_send::Result<EhloResponse<String>> {
+    client
+        .say_helo(params)
+        .await
+        .map_err(|_| mail_send::Error::UnparseableReply)
+}
+
+async fn verify<T: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut SmtpClient<T>,
+    address: &str,
+) -> mail_send::Result<Response<String>> {
+    client
+        .cmd(b"MAIL FROM:<>\r\n")
+        .await?
+        .assert_positive_completion()?;
+    client.cmd(format!("RCPT TO:<{address}>\r\n")).await
+}
+
+fn callout_result(
+    response: Response<String>,
+    address: &str,
+    relay_id: &str,
+    session_id: u64,
+    is_cached: bool,
+) -> CalloutResult {
+    match response.code / 100 {
+        2 => {
+            trc::event!(
+                Smtp(SmtpEvent::CalloutAccepted),
+                SpanId = session_id,
+                Id = relay_id.to_string(),
+                To = address.to_string(),
+                Result = is_cached,
+            );
+
+            CalloutResult::Accept
+        }
+        5 => {
+            trc::event!(
+                Smtp(SmtpEvent::CalloutRejected),
+                SpanId = session_id,
+                Id = relay_id.to_string(),
+                To = address.to_string(),
+                Code = response.code,
+                Details = response.message.clone(),
+                Result = is_cached,
+            );
+
+            CalloutResult::Reject(response)
+        }
+        _ => {
+            trc::event!(
+                Smtp(SmtpEvent::CalloutFailed),
+                SpanId = session_id,
+                Id = relay_id.to_string(),
+                To = address.to_string(),
+                Code = response.code,
+                Details = response.message.clone(),
+            );
+
+            CalloutResult::Defer(response)
+        }
+    }
+}
+
+pub fn format_callout_response(response: &Response<String>, default_code: u16) -> String {
+    let code = if response.code / 100 == default_code / 100 {
+        response.code
+    } else {
+        default_code
+    };
+    let [class, subject, detail] = response.esc;
+    let message = response
+        .message
+        .replace(['\r', '\n'], " ")
+        .trim()
+        .to_string();
+
+    if class > 0 {
+        format!("{code} {class}.{subject}.{detail} {message}\r\n")
+    } else {
+        format!("{code} {message}\r\n")
+    }
+}
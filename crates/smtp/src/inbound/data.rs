@@ -12,10 +12,14 @@ use std::{
 };
 
 use common::{
-    config::smtp::{auth::VerifyStrategy, session::Stage},
+    config::smtp::{
+        auth::VerifyStrategy,
+        session::{MimeLimitAction, Stage},
+    },
     listener::SessionStream,
     psl,
     scripts::ScriptModification,
+    telemetry::volume::VolumeEvent,
 };
 use mail_auth::{
     common::{headers::HeaderWriter, verify::VerifySignature},
@@ -39,10 +43,34 @@ use crate::{
     scripts::ScriptResult,
 };
 
-use super::{ArcSeal, AuthResult, DkimSign};
+use super::{
+    attachments::AttachmentVerdict,
+    list_policy::split_list_postings,
+    mime::{build_opaque_message, scan_mime},
+    ArcSeal, AuthResult, DkimSign,
+};
 
 impl<T: SessionStream> Session<T> {
     pub async fn queue_message(&mut self) -> Cow<'static, [u8]> {
+        // Enforce MIME structure limits before handing the message to the parser
+        let mime_limits = &self.server.core.smtp.session.data.mime_limits;
+        if let Err(violation) = scan_mime(&self.data.message, mime_limits) {
+            let strict = mime_limits.action == MimeLimitAction::Reject;
+
+            trc::event!(
+                Smtp(SmtpEvent::MimeLimitExceeded),
+                SpanId = self.data.session_id,
+                Details = violation.to_string(),
+                Strict = strict,
+            );
+
+            if strict {
+                return (&b"554 5.6.0 Message structure exceeds server limits.\r\n"[..]).into();
+            }
+            self.data.message =
+                build_opaque_message(&self.data.message, &violation, &self.hostname);
+        }
+
         // Authenticate message
         let raw_message = Arc::new(std::mem::take(&mut self.data.message));
         let auth_message = if let Some(auth_message) = AuthenticatedMessage::parse_with_opts(
@@ -80,6 +108,13 @@ impl<T: SessionStream> Session<T> {
                 .into();
         }
 
+        // Enforce attachment policies
+        let attachment_warnings = match self.check_attachments(&raw_message).await {
+            AttachmentVerdict::Accept => vec![],
+            AttachmentVerdict::Warn(warnings) => warnings,
+            AttachmentVerdict::Reject(violation) => return violation.to_response(),
+        };
+
         // Verify DKIM
         let dkim = self
             .server
@@ -367,6 +402,11 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Add attachment policy warnings
+        for warning in &attachment_warnings {
+            warning.write_header(&mut headers);
+        }
+
         // ARC Seal
         if let (Some(arc_sealer), Some(arc_output)) = (arc_sealer, &arc_output) {
             if !dkim_output.is_empty() && arc_output.can_be_sealed() {
@@ -626,11 +666,14 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
-        // Build message
+        // Build message, members of lists that hold posts for moderation or
+        // rewrite Reply-To receive a separate copy
         let mail_from = self.data.mail_from.clone().unwrap();
-        let rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let mut rcpt_to = std::mem::take(&mut self.data.rcpt_to);
+        let list_copies =
+            split_list_postings(&mut rcpt_to, std::mem::take(&mut self.data.list_postings));
         let mut message = self
-            .build_message(mail_from, rcpt_to, message_id, self.data.session_id)
+            .build_message(mail_from.clone(), rcpt_to, message_id, self.data.session_id)
             .await;
 
         // Add Return-Path
@@ -669,30 +712,26 @@ impl<T: SessionStream> Session<T> {
             headers.extend_from_slice(b"\r\n");
         }
 
-        // DKIM sign
+        // Queue list copies
         let raw_message = edited_message
             .as_deref()
             .unwrap_or_else(|| raw_message.as_slice());
-        for signer in self
-            .server
-            .eval_if::<Vec<String>, _>(&ac.dkim.sign, self, self.data.session_id)
-            .await
-            .unwrap_or_default()
-        {
-            if let Some(signer) = self.server.get_dkim_signer(&signer, self.data.session_id) {
-                match signer.sign_chained(&[headers.as_ref(), raw_message]) {
-                    Ok(signature) => {
-                        signature.write_header(&mut headers);
-                    }
-                    Err(err) => {
-                        trc::error!(trc::Event::from(err)
-                            .span_id(self.data.session_id)
-                            .details("Failed to DKIM sign message"));
-                    }
-                }
+        if !list_copies.is_empty() {
+            if let Err(response) = self
+                .queue_list_copies(list_copies, &mail_from, &headers, raw_message)
+                .await
+            {
+                return response.into();
+            }
+            if message.recipients.is_empty() {
+                self.data.messages_sent += 1;
+                return (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into();
             }
         }
 
+        // DKIM sign
+        self.dkim_sign(&mut headers, raw_message).await;
+
         // Update size
         message.size = raw_message.len() + headers.len();
 
@@ -707,6 +746,9 @@ impl<T: SessionStream> Session<T> {
             } else {
                 MessageSource::Authenticated
             };
+            let sender_domain = message.return_path_domain.clone();
+            let size = message.size as u64;
+            let num_recipients = message.recipients.len() as u64;
             if message
                 .queue(
                     Some(&headers),
@@ -719,6 +761,19 @@ impl<T: SessionStream> Session<T> {
             {
                 self.state = State::Accepted(queue_id);
                 self.data.messages_sent += 1;
+                if let Some(access_token) = &self.data.authenticated_as {
+                    self.server
+                        .record_volume(&sender_domain, VolumeEvent::Sent, size);
+                    if let Err(err) = self
+                        .server
+                        .record_submission(access_token.primary_id(), num_recipients)
+                        .await
+                    {
+                        trc::error!(err
+                            .span_id(self.data.session_id)
+                            .details("Failed to update sender reputation"));
+                    }
+                }
                 (b"250 2.0.0 Message queued for delivery.\r\n"[..]).into()
             } else {
                 (b"451 4.3.5 Unable to accept message at this time.\r\n"[..]).into()
@@ -728,6 +783,32 @@ impl<T: SessionStream> Session<T> {
         }
     }
 
+    pub async fn dkim_sign(&self, headers: &mut Vec<u8>, raw_message: &[u8]) {
+        for signer in self
+            .server
+            .eval_if::<Vec<String>, _>(
+                &self.server.core.smtp.mail_auth.dkim.sign,
+                self,
+                self.data.session_id,
+            )
+            .await
+            .unwrap_or_default()
+        {
+            if let Some(signer) = self.server.get_dkim_signer(&signer, self.data.session_id) {
+                match signer.sign_chained(&[headers.as_ref(), raw_message]) {
+                    Ok(signature) => {
+                        signature.write_header(headers);
+                    }
+                    Err(err) => {
+                        trc::error!(trc::Event::from(err)
+                            .span_id(self.data.session_id)
+                            .details("Failed to DKIM sign message"));
+                    }
+                }
+            }
+        }
+    }
+
     pub async fn build_message(
         &self,
         mail_from: SessionAddress,
@@ -754,6 +835,10 @@ impl<T: SessionStream> Session<T> {
             env_id: mail_from.dsn_info,
             blob_hash: Default::default(),
             quota_keys: Vec::new(),
+            deferrals: Vec::new(),
+            source_ips: Vec::new(),
+            hold: None,
+            connect_attempts: Vec::new(),
         };
 
         // Add recipients
@@ -865,6 +950,23 @@ impl<T: SessionStream> Session<T> {
         message
     }
 
+    pub fn record_rejected_volume(&self, response: &[u8]) {
+        // Messages rejected before queueing count once per recipient domain
+        if response.first() == Some(&b'5') {
+            let mut domains = self
+                .data
+                .rcpt_to
+                .iter()
+                .map(|rcpt| rcpt.domain.as_str())
+                .collect::<Vec<_>>();
+            domains.sort_unstable();
+            domains.dedup();
+            for domain in domains {
+                self.server.record_volume(domain, VolumeEvent::Rejected, 0);
+            }
+        }
+    }
+
     pub async fn can_send_data(&mut self) -> Result<bool, ()> {
         if !self.data.rcpt_to.is_empty() {
             if self.data.messages_sent
@@ -0,0 +1,250 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{borrow::Cow, time::Instant};
+
+use common::{
+    config::smtp::session::{DnsblHealth, DnsblList, DnsblType},
+    listener::SessionStream,
+    Server,
+};
+use mail_auth::common::resolver::ToReverseName;
+use tokio::task::JoinSet;
+use trc::SmtpEvent;
+
+use crate::core::Session;
+
+#[derive(Debug, Clone, Default)]
+pub struct DnsblVerdict {
+    pub score: f64,
+    pub hits: Vec<DnsblHit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DnsblHit {
+    pub list: String,
+    pub zone: String,
+    pub typ: DnsblType,
+    pub target: String,
+    pub category: Option<String>,
+    pub score: f64,
+    pub blocking: bool,
+}
+
+impl<T: SessionStream> Session<T> {
+    pub async fn check_dnsbl(&self) -> DnsblVerdict {
+        let config = &self.server.core.smtp.session.dnsbl;
+        let time = Instant::now();
+
+        // Domain lists are queried for the sender and HELO domains
+        let ip_target = self.data.remote_ip.to_reverse_name();
+        let mut domain_targets = Vec::with_capacity(2);
+        for domain in [
+            self.data
+                .mail_from
+                .as_ref()
+                .map(|mail_from| mail_from.domain.as_str())
+                .unwrap_or_default(),
+            self.data.helo_domain.as_str(),
+        ] {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            if domain.contains('.') && !domain_targets.contains(&domain) {
+                domain_targets.push(domain);
+            }
+        }
+
+        // Lists are queried in parallel, lookups still pending when the
+        // deadline is reached are ignored
+        let mut lookups = JoinSet::new();
+        for (idx, list) in config.lists.iter().enumerate() {
+            let targets = match list.typ {
+                DnsblType::Ip => vec![ip_target.clone()],
+                DnsblType::Domain if !domain_targets.is_empty() => domain_targets.clone(),
+                DnsblType::Domain => continue,
+            };
+            lookups.spawn(query_list(self.server.clone(), idx, targets));
+        }
+
+        let deadline = tokio::time::Instant::now() + config.timeout;
+        let mut verdict = DnsblVerdict::default();
+        loop {
+            match tokio::time::timeout_at(deadline, lookups.join_next()).await {
+                Ok(Some(Ok(hits))) => {
+                    for hit in hits {
+                        verdict.score += hit.score;
+                        verdict.hits.push(hit);
+                    }
+                }
+                Ok(Some(Err(_))) => {}
+                Ok(None) => break,
+                Err(_) => {
+                    trc::event!(
+                        Smtp(SmtpEvent::DnsblTimeout),
+                        SpanId = self.data.session_id,
+                        Total = lookups.len(),
+                        Elapsed = time.elapsed(),
+                    );
+                    break;
+                }
+            }
+        }
+
+        if !verdict.hits.is_empty() {
+            trc::event!(
+                Smtp(SmtpEvent::DnsblListed),
+                SpanId = self.data.session_id,
+                RemoteIp = self.data.remote_ip,
+                Details = verdict
+                    .hits
+                    .iter()
+                    .map(|hit| trc::Value::String(hit.to_string()))
+                    .collect::<Vec<_>>(),
+                Value = verdict.score,
+                Elapsed = time.elapsed(),
+            );
+        }
+
+        verdict
+    }
+}
+
+async fn query_list(server: Server, idx: usize, targets: Vec<String>) -> Vec<DnsblHit> {
+    let list = &server.core.smtp.session.dnsbl.lists[idx];
+    let mut hits = Vec::new();
+    if !is_list_healthy(&server, list).await {
+        return hits;
+    }
+
+    for target in targets {
+        let Ok(addrs) = server
+            .core
+            .smtp
+            .resolvers
+            .dns
+            .ipv4_lookup(&format!("{target}.{}", list.zone))
+            .await
+        else {
+            continue;
+        };
+
+        // Only 127.0.0.x answers are listings, other addresses are
+        // returned by some lists to signal errors
+        let mut matched = Vec::new();
+        for addr in addrs.iter() {
+            let [a, b, c, octet] = addr.octets();
+            if (a, b, c) != (127, 0, 0) || matched.contains(&octet) {
+                continue;
+            }
+            matched.push(octet);
+
+            if let Some((category, score)) = list.match_code(octet) {
+                hits.push(DnsblHit {
+                    list: list.id.clone(),
+                    zone: list.zone.clone(),
+                    typ: list.typ,
+                    target: target.clone(),
+                    category: category.map(String::from),
+                    score,
+                    blocking: list.blocking,
+                });
+            }
+        }
+    }
+
+    hits
+}
+
+// Lists that answer for an entry that must never be listed are dead or
+// poisoned and are disabled until they stop doing so
+async fn is_list_healthy(server: &Server, list: &DnsblList) -> bool {
+    let now = Instant::now();
+    let interval = server.core.smtp.session.dnsbl.probe_interval;
+    let was_disabled = {
+        let mut health = server.inner.data.dnsbl_health.write();
+        let health = health.entry(list.id.clone()).or_insert(DnsblHealth {
+            disabled: false,
+            next_probe: now,
+        });
+        if health.next_probe > now {
+            return !health.disabled;
+        }
+        health.next_probe = now + interval;
+        health.disabled
+    };
+
+    let probe = format!("{}.{}", list.probe, list.zone);
+    let answer = server
+        .core
+        .smtp
+        .resolvers
+        .dns
+        .ipv4_lookup(&probe)
+        .await
+        .ok()
+        .and_then(|addrs| addrs.first().copied());
+    let is_disabled = answer.is_some();
+
+    if is_disabled != was_disabled {
+        if let Some(health) = server.inner.data.dnsbl_health.write().get_mut(&list.id) {
+            health.disabled = is_disabled;
+        }
+
+        if let Some(answer) = answer {
+            trc::event!(
+                Smtp(SmtpEvent::DnsblDisabled),
+                Id = list.id.clone(),
+                Hostname = probe,
+                Details = answer.to_string(),
+                Expires = interval,
+            );
+        } else {
+            trc::event!(
+                Smtp(SmtpEvent::DnsblEnabled),
+                Id = list.id.clone(),
+                Hostname = probe,
+            );
+        }
+    }
+
+    !is_disabled
+}
+
+impl DnsblVerdict {
+    pub fn blocked_by(&self) -> Option<&DnsblHit> {
+        self.hits.iter().find(|hit| hit.blocking)
+    }
+
+    pub fn lists(&self) -> Vec<String> {
+        self.hits.iter().map(|hit| hit.to_string()).collect()
+    }
+}
+
+impl DnsblHit {
+    pub fn to_response(&self) -> Cow<'static, [u8]> {
+        match self.typ {
+            DnsblType::Ip => format!(
+                "554 5.7.1 Client host is listed by {}, message rejected by policy.\r\n",
+                self.zone
+            ),
+            DnsblType::Domain => format!(
+                "554 5.7.1 Domain {} is listed by {}, message rejected by policy.\r\n",
+                self.target, self.zone
+            ),
+        }
+        .into_bytes()
+        .into()
+    }
+}
+
+impl std::fmt::Display for DnsblHit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(category) = &self.category {
+            write!(f, "{}/{}", self.list, category)
+        } else {
+            write!(f, "{}", self.list)
+        }
+    }
+}
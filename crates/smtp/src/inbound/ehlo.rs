@@ -9,7 +9,7 @@ use std::time::{Duration, Instant, SystemTime};
 use crate::{core::Session, scripts::ScriptResult};
 use common::{
     config::smtp::session::{Mechanism, Stage},
-    listener::SessionStream,
+    listener::{SessionStream, TcpAcceptor},
 };
 use mail_auth::{spf::verify::HasValidLabels, SpfResult};
 use smtp_proto::*;
@@ -121,6 +121,17 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Attach the EHLO domain to hosts below the target TLS version
+        if let TcpAcceptor::Tls { policy, .. } = &self.instance.acceptor {
+            if self.stream.is_tls() {
+                policy.usage.set_domain(
+                    &self.instance.id,
+                    self.data.remote_ip,
+                    &self.data.helo_domain,
+                );
+            }
+        }
+
         // Reset
         if self.data.mail_from.is_some() {
             self.reset();
@@ -0,0 +1,354 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use ahash::AHashSet;
+use common::{
+    expr::{functions::ResolveVariable, Variable, V_RECIPIENT, V_RECIPIENT_DOMAIN},
+    Server,
+};
+use directory::{
+    backend::internal::{lookup::DirectoryStore, manage::ManageDirectory, PrincipalField},
+    Principal, Type,
+};
+use serde::Serialize;
+use trc::AddContext;
+
+use crate::queue::DomainPart;
+
+use super::rewrite::{AddressRewrite, RewriteOutcome};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ResolutionStep {
+    Rewrite {
+        rule: String,
+        from: String,
+        to: String,
+    },
+    Subaddress {
+        from: String,
+        to: String,
+    },
+    CatchAll {
+        from: String,
+        to: String,
+    },
+    Member {
+        principal: String,
+        typ: Type,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandedRecipient {
+    pub address: String,
+    pub principal: Option<String>,
+    pub external: bool,
+    pub path: Vec<ResolutionStep>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExpansionLimit {
+    RewriteLoop,
+    RewriteHops,
+    Depth,
+    FanOut,
+    AliasLoop,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientExpansion {
+    pub address: String,
+    pub valid: bool,
+    pub recipients: Vec<ExpandedRecipient>,
+    pub limits: Vec<ExpansionLimit>,
+}
+
+pub trait RecipientExpand: Sync + Send {
+    fn expand_recipient(
+        &self,
+        address: &str,
+        tenant_id: Option<u32>,
+    ) -> impl Future<Output = trc::Result<RecipientExpansion>> + Send;
+}
+
+impl RecipientExpand for Server {
+    async fn expand_recipient(
+        &self,
+        address: &str,
+        tenant_id: Option<u32>,
+    ) -> trc::Result<RecipientExpansion> {
+        let store = self.store();
+        let mut expansion = RecipientExpansion {
+            address: address.to_lowercase(),
+            valid: false,
+            recipients: Vec::new(),
+            limits: Vec::new(),
+        };
+        let mut path = Vec::new();
+
+        // Rewrite map, covers alias domains and forwarding
+        let resolution = self.resolve_rewrite(address).await?;
+        path.extend(
+            resolution
+                .hops
+                .into_iter()
+                .map(|hop| ResolutionStep::Rewrite {
+                    rule: hop.rule,
+                    from: hop.from,
+                    to: hop.to,
+                }),
+        );
+        match resolution.outcome {
+            RewriteOutcome::Loop => {
+                expansion.limits.push(ExpansionLimit::RewriteLoop);
+                return Ok(expansion);
+            }
+            RewriteOutcome::TooManyHops => {
+                expansion.limits.push(ExpansionLimit::RewriteHops);
+                return Ok(expansion);
+            }
+            RewriteOutcome::Unchanged | RewriteOutcome::Rewritten => {}
+        }
+        let address = resolution.address;
+
+        // Remote recipients are relayed as-is
+        if !store
+            .is_local_domain(address.domain_part())
+            .await
+            .caused_by(trc::location!())?
+        {
+            expansion.valid = true;
+            expansion.recipients.push(ExpandedRecipient {
+                address,
+                principal: None,
+                external: true,
+                path,
+            });
+            return Ok(expansion);
+        }
+
+        // Subaddressing and catch-all
+        let rcpt = &self.core.smtp.session.rcpt;
+        let mut subaddress = address.clone();
+        let mut principal_id = store
+            .email_to_id(&address)
+            .await
+            .caused_by(trc::location!())?;
+        if principal_id.is_none() {
+            let routing = store
+                .domain_routing(address.domain_part())
+                .await
+                .caused_by(trc::location!())?;
+            let fallback = self
+                .rcpt_fallback(routing.unwrap_or_default(), &address, 0)
+                .await;
+            if let Some(fallback_subaddress) = fallback.subaddress {
+                path.push(ResolutionStep::Subaddress {
+                    from: address.clone(),
+                    to: fallback_subaddress.clone(),
+                });
+                principal_id = store
+                    .email_to_id(&fallback_subaddress)
+                    .await
+                    .caused_by(trc::location!())?;
+                subaddress = fallback_subaddress;
+            }
+            if let (None, Some(catch_all)) = (principal_id, fallback.catch_all) {
+                principal_id = store
+                    .email_to_id(&catch_all)
+                    .await
+                    .caused_by(trc::location!())?;
+                if principal_id.is_some() {
+                    path.push(ResolutionStep::CatchAll {
+                        from: subaddress.clone(),
+                        to: catch_all,
+                    });
+                }
+            }
+        }
+        let Some(principal) = (match principal_id {
+            Some(principal_id) => store
+                .get_principal(principal_id)
+                .await
+                .caused_by(trc::location!())?
+                .filter(|p| is_tenant_member(p, tenant_id)),
+            None => None,
+        }) else {
+            return Ok(expansion);
+        };
+
+        // Limits are shared with regular message delivery
+        let max_depth = self.core.jmap.member_of_max_depth;
+        let max_recipients = if principal.typ() == Type::Alias {
+            rcpt.alias_max_fan_out
+        } else {
+            self.eval_if::<usize, _>(&rcpt.max_recipients, &ExpandAddress(address.as_str()), 0)
+                .await
+                .unwrap_or(100)
+        };
+        expansion.valid = principal.typ() != Type::Service;
+
+        let mut seen_addresses = AHashSet::new();
+        let mut seen_ids = AHashSet::from_iter([principal.id()]);
+        let mut pending = vec![(principal, path, 0)];
+        'outer: while let Some((principal, path, depth)) = pending.pop() {
+            match principal.typ() {
+                Type::List | Type::Group => {
+                    let mut path = path;
+                    path.push(ResolutionStep::Member {
+                        principal: principal.name().to_string(),
+                        typ: principal.typ(),
+                    });
+
+                    if depth >= max_depth {
+                        if !expansion.limits.contains(&ExpansionLimit::Depth) {
+                            expansion.limits.push(ExpansionLimit::Depth);
+                        }
+                        continue;
+                    }
+
+                    // External members of a list are forwarded to
+                    for address in principal.iter_str(PrincipalField::ExternalMembers) {
+                        let address = address.to_lowercase();
+                        if seen_addresses.insert(address.clone()) {
+                            if expansion.recipients.len() >= max_recipients {
+                                expansion.limits.push(ExpansionLimit::FanOut);
+                                break 'outer;
+                            }
+                            expansion.recipients.push(ExpandedRecipient {
+                                address,
+                                principal: None,
+                                external: true,
+                                path: path.clone(),
+                            });
+                        }
+                    }
+
+                    for member_id in store
+                        .get_members(principal.id())
+                        .await
+                        .caused_by(trc::location!())?
+                    {
+                        if !seen_ids.insert(member_id) {
+                            continue;
+                        }
+                        if let Some(member) = store
+                            .get_principal(member_id)
+                            .await
+                            .caused_by(trc::location!())?
+                            .filter(|p| is_tenant_member(p, tenant_id))
+                        {
+                            pending.push((member, path.clone(), depth + 1));
+                        }
+                    }
+                }
+                Type::Alias => {
+                    let mut path = path;
+                    path.push(ResolutionStep::Member {
+                        principal: principal.name().to_string(),
+                        typ: principal.typ(),
+                    });
+
+                    if depth >= max_depth {
+                        if !expansion.limits.contains(&ExpansionLimit::Depth) {
+                            expansion.limits.push(ExpansionLimit::Depth);
+                        }
+                        continue;
+                    }
+
+                    for address in principal.iter_str(PrincipalField::ForwardTo) {
+                        let address = address.to_lowercase();
+                        let member = match store
+                            .email_to_id(&address)
+                            .await
+                            .caused_by(trc::location!())?
+                        {
+                            Some(member_id) => store
+                                .get_principal(member_id)
+                                .await
+                                .caused_by(trc::location!())?
+                                .filter(|p| is_tenant_member(p, tenant_id)),
+                            None => None,
+                        };
+
+                        if let Some(member) = member {
+                            // Destinations leading back to an alias on the path are skipped
+                            let is_loop = path.iter().any(|step| match step {
+                                ResolutionStep::Member { principal, .. } => {
+                                    principal == member.name()
+                                }
+                                _ => false,
+                            });
+                            if is_loop {
+                                if !expansion.limits.contains(&ExpansionLimit::AliasLoop) {
+                                    expansion.limits.push(ExpansionLimit::AliasLoop);
+                                }
+                            } else if seen_ids.insert(member.id()) {
+                                pending.push((member, path.clone(), depth + 1));
+                            }
+                        } else if seen_addresses.insert(address.clone()) {
+                            if expansion.recipients.len() >= max_recipients {
+                                expansion.limits.push(ExpansionLimit::FanOut);
+                                break 'outer;
+                            }
+                            expansion.recipients.push(ExpandedRecipient {
+                                address,
+                                principal: None,
+                                external: true,
+                                path: path.clone(),
+                            });
+                        }
+                    }
+                }
+                // Service accounts have no mailbox
+                Type::Service => {}
+                _ => {
+                    let address = principal
+                        .iter_str(PrincipalField::Emails)
+                        .next()
+                        .map(|email| email.to_lowercase())
+                        .unwrap_or_else(|| subaddress.clone());
+                    if seen_addresses.insert(address.clone()) {
+                        if expansion.recipients.len() >= max_recipients {
+                            expansion.limits.push(ExpansionLimit::FanOut);
+                            break;
+                        }
+                        expansion.recipients.push(ExpandedRecipient {
+                            address,
+                            principal: principal.name().to_string().into(),
+                            external: false,
+                            path,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(expansion)
+    }
+}
+
+fn is_tenant_member(principal: &Principal, tenant_id: Option<u32>) -> bool {
+    tenant_id.is_none_or(|tenant_id| principal.tenant() == Some(tenant_id))
+}
+
+struct ExpandAddress<'x>(&'x str);
+
+impl ResolveVariable for ExpandAddress<'_> {
+    fn resolve_variable(&self, variable: u32) -> Variable<'_> {
+        match variable {
+            V_RECIPIENT => self.0.into(),
+            V_RECIPIENT_DOMAIN => self.0.domain_part().into(),
+            _ => Variable::default(),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::listener::SessionStream;
+use directory::{backend::PostingPolicy, Directory};
+use store::write::now;
+use trc::AddContext;
+
+use crate::{
+    core::{ListPosting, Session, SessionAddress},
+    queue::{quota::HasQueueQuota, Hold, HoldReason, MessageSource},
+};
+
+pub enum PostingOutcome {
+    // Members receive the message as posted
+    Deliver,
+    // Members receive a separate copy, held for moderation or rewritten
+    Copy(ListPosting),
+    Reject(&'static [u8]),
+}
+
+impl<T: SessionStream> Session<T> {
+    /// Applies the posting policy of a mailing list to the current sender.
+    pub async fn list_posting(
+        &self,
+        directory: &Directory,
+        list: &str,
+    ) -> trc::Result<PostingOutcome> {
+        let Some(policy) = directory
+            .list_policy(list)
+            .await
+            .caused_by(trc::location!())?
+        else {
+            return Ok(PostingOutcome::Deliver);
+        };
+
+        // Authenticated senders are identified by their account, others by
+        // their address
+        let sender = self
+            .data
+            .mail_from
+            .as_ref()
+            .map_or("", |from| from.address_lcase.as_str());
+        let sender_ids = if let Some(access_token) = &self.data.authenticated_as {
+            let mut ids = access_token.member_of.clone();
+            ids.push(access_token.primary_id());
+            ids
+        } else if !sender.is_empty() {
+            directory
+                .email_to_id(sender)
+                .await
+                .caused_by(trc::location!())?
+                .into_iter()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let is_moderator = sender_ids.iter().any(|id| policy.is_moderator(*id));
+
+        let moderate = match policy.posting {
+            PostingPolicy::Anyone => false,
+            PostingPolicy::Members => {
+                if !is_moderator && !policy.is_member(&sender_ids, sender) {
+                    return Ok(PostingOutcome::Reject(
+                        b"550 5.7.1 Only list members are allowed to post to this list.\r\n",
+                    ));
+                }
+                false
+            }
+            PostingPolicy::Moderators => {
+                if policy.moderators.is_empty() {
+                    return Ok(PostingOutcome::Reject(
+                        b"550 5.7.1 This list does not accept posts.\r\n",
+                    ));
+                }
+                !is_moderator
+            }
+        };
+
+        Ok(if moderate || policy.reply_to_list {
+            PostingOutcome::Copy(ListPosting {
+                address: list.to_string(),
+                list_id: policy.list_id,
+                members: Vec::new(),
+                moderate,
+                reply_to_list: policy.reply_to_list,
+            })
+        } else {
+            PostingOutcome::Deliver
+        })
+    }
+
+    /// Queues the copies of list postings that are held for moderation or
+    /// have their Reply-To rewritten. Returns the SMTP response on failure.
+    pub async fn queue_list_copies(
+        &self,
+        copies: Vec<(ListPosting, Vec<SessionAddress>)>,
+        mail_from: &SessionAddress,
+        headers: &[u8],
+        raw_message: &[u8],
+    ) -> Result<(), &'static [u8]> {
+        let source = if !self.is_authenticated() {
+            MessageSource::Unauthenticated
+        } else {
+            MessageSource::Authenticated
+        };
+
+        for (posting, rcpt_to) in copies {
+            let queue_id = self
+                .server
+                .inner
+                .data
+                .queue_id_gen
+                .generate()
+                .unwrap_or_else(now);
+            let mut message = self
+                .build_message(mail_from.clone(), rcpt_to, queue_id, self.data.session_id)
+                .await;
+            if posting.moderate {
+                message.hold = Some(Hold {
+                    tenant: String::new(),
+                    reason: HoldReason::Moderation {
+                        list_id: posting.list_id,
+                    },
+                    since: now(),
+                });
+            }
+
+            // Replies are sent to the list rather than to the author
+            let mut copy_headers = headers.to_vec();
+            let stripped;
+            let raw_message = if posting.reply_to_list {
+                copy_headers.extend_from_slice(b"Reply-To: <");
+                copy_headers.extend_from_slice(posting.address.as_bytes());
+                copy_headers.extend_from_slice(b">\r\n");
+                stripped = strip_header(raw_message, "Reply-To");
+                stripped.as_slice()
+            } else {
+                raw_message
+            };
+            self.dkim_sign(&mut copy_headers, raw_message).await;
+            message.size = raw_message.len() + copy_headers.len();
+
+            if !self.server.has_quota(&mut message).await {
+                return Err(b"452 4.3.1 Mail system full, try again later.\r\n");
+            }
+            if !message
+                .queue(
+                    Some(&copy_headers),
+                    raw_message,
+                    self.data.session_id,
+                    &self.server,
+                    source,
+                )
+                .await
+            {
+                return Err(b"451 4.3.5 Unable to accept message at this time.\r\n");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Moves the members of list postings that need a separate copy out of the
+/// envelope.
+pub fn split_list_postings(
+    rcpt_to: &mut Vec<SessionAddress>,
+    postings: Vec<ListPosting>,
+) -> Vec<(ListPosting, Vec<SessionAddress>)> {
+    let mut copies = Vec::with_capacity(postings.len());
+    for posting in postings {
+        let (members, rest) = std::mem::take(rcpt_to)
+            .into_iter()
+            .partition::<Vec<_>, _>(|rcpt| posting.members.contains(&rcpt.address_lcase));
+        *rcpt_to = rest;
+        if !members.is_empty() {
+            copies.push((posting, members));
+        }
+    }
+    copies
+}
+
+/// Removes all instances of a header, including folded lines, from the
+/// header section of a message.
+pub fn strip_header(message: &[u8], name: &str) -> Vec<u8> {
+    let mut result = Vec::with_capacity(message.len());
+    let mut skip = false;
+    let mut pos = 0;
+    while pos < message.len() {
+        let end = message[pos..]
+            .iter()
+            .position(|&ch| ch == b'\n')
+            .map_or(message.len(), |i| pos + i + 1);
+        let line = &message[pos..end];
+        if line == b"\r\n" || line == b"\n" {
+            // End of headers
+            result.extend_from_slice(&message[pos..]);
+            return result;
+        } else if !line.starts_with(b" ") && !line.starts_with(b"\t") {
+            skip = line.len() > name.len()
+                && line[name.len()] == b':'
+                && line[..name.len()].eq_ignore_ascii_case(name.as_bytes());
+        }
+        if !skip {
+            result.extend_from_slice(line);
+        }
+        pos = end;
+    }
+    result
+}
@@ -6,10 +6,14 @@
 
 use std::time::{Duration, Instant, SystemTime};
 
-use common::{config::smtp::session::Stage, listener::SessionStream, scripts::ScriptModification};
+use common::{
+    config::smtp::session::{DnsblType, Stage},
+    listener::SessionStream,
+    scripts::ScriptModification,
+};
 use mail_auth::{IprevOutput, IprevResult, SpfOutput, SpfResult};
 use smtp_proto::{MailFrom, MtPriority, MAIL_BY_NOTIFY, MAIL_BY_RETURN, MAIL_REQUIRETLS};
-use trc::SmtpEvent;
+use trc::{LimitEvent, SmtpEvent};
 use utils::config::Rate;
 
 use crate::{
@@ -51,6 +55,16 @@ impl<T: SessionStream> Session<T> {
             return self
                 .write(b"503 5.5.1 You must authenticate first.\r\n")
                 .await;
+        } else if self.server.is_shedding_load() {
+            trc::event!(
+                Limit(LimitEvent::LoadShed),
+                SpanId = self.data.session_id,
+                RemoteIp = self.data.remote_ip,
+            );
+
+            return self
+                .write(b"451 4.3.2 Server busy, please try again later.\r\n")
+                .await;
         } else if self.data.iprev.is_none() && self.params.iprev.verify() {
             let time = Instant::now();
             let iprev = self
@@ -255,6 +269,12 @@ impl<T: SessionStream> Session<T> {
             _ => (),
         }
 
+        // Apply restrictions due to a poor sender reputation
+        if let Some(response) = self.verify_sender_reputation().await {
+            self.data.mail_from = None;
+            return self.write(response).await;
+        }
+
         // Validate parameters
         let config = &self.server.core.smtp.session.extensions;
         let config_data = &self.server.core.smtp.session.data;
@@ -488,6 +508,35 @@ impl<T: SessionStream> Session<T> {
                 }
             }
 
+            // Query DNS blocklists, listings add to the spam score unless
+            // the list is marked as blocking
+            if !self.server.core.smtp.session.dnsbl.lists.is_empty() && !self.is_authenticated() {
+                if let Some(message) = &self.data.dnsbl_error {
+                    let message = message.clone();
+                    self.data.mail_from = None;
+                    return self.write(&message).await;
+                }
+
+                let verdict = self.check_dnsbl().await;
+                if let Some(hit) = verdict.blocked_by() {
+                    trc::event!(
+                        Smtp(SmtpEvent::DnsblRejected),
+                        SpanId = self.data.session_id,
+                        Id = hit.list.clone(),
+                        Details = hit.target.clone(),
+                    );
+
+                    // Client host listings apply to the whole session
+                    let message = hit.to_response();
+                    if hit.typ == DnsblType::Ip {
+                        self.data.dnsbl_error = message.to_vec().into();
+                    }
+                    self.data.mail_from = None;
+                    return self.write(&message).await;
+                }
+                self.data.dnsbl = verdict.into();
+            }
+
             trc::event!(
                 Smtp(SmtpEvent::MailFrom),
                 SpanId = self.data.session_id,
@@ -0,0 +1,254 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::fmt::Display;
+
+use common::config::smtp::session::MimeLimits;
+use mail_builder::headers::message_id::generate_message_id_header;
+
+const MAX_BOUNDARY_LEN: usize = 256;
+const MAX_CONTENT_TYPE_LEN: usize = 4096;
+const MAX_PRESERVED_HEADER_LEN: usize = 998;
+const PRESERVED_HEADERS: &[&str] = &["from:", "to:", "cc:", "subject:", "date:", "message-id:"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MimeViolation {
+    HeaderSize { limit: usize },
+    PartCount { limit: usize },
+    Depth { limit: usize },
+}
+
+enum Frame {
+    Multipart(Vec<u8>),
+    Message,
+}
+
+// Walks the raw message line by line and stops as soon as a limit is exceeded,
+// the only state kept is the stack of open multipart boundaries which cannot
+// grow beyond the configured maximum depth.
+pub fn scan_mime(raw_message: &[u8], limits: &MimeLimits) -> Result<(), MimeViolation> {
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut parts = 1;
+    let mut in_header = true;
+    let mut header_size = 0;
+    let mut content_type: Vec<u8> = Vec::new();
+    let mut in_content_type = false;
+
+    for line in raw_message.split(|&ch| ch == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if in_header {
+            if line.is_empty() {
+                in_header = false;
+                header_size = 0;
+                in_content_type = false;
+
+                match parse_content_type(&content_type) {
+                    Some(ContentType::Multipart(boundary)) => {
+                        if stack.len() >= limits.max_depth {
+                            return Err(MimeViolation::Depth {
+                                limit: limits.max_depth,
+                            });
+                        }
+                        stack.push(Frame::Multipart(boundary.to_vec()));
+                    }
+                    Some(ContentType::Message) => {
+                        if stack.len() >= limits.max_depth {
+                            return Err(MimeViolation::Depth {
+                                limit: limits.max_depth,
+                            });
+                        }
+                        stack.push(Frame::Message);
+                        in_header = true;
+                    }
+                    None => (),
+                }
+                content_type.clear();
+                continue;
+            }
+
+            header_size += line.len() + 2;
+            if header_size > limits.max_header_size {
+                return Err(MimeViolation::HeaderSize {
+                    limit: limits.max_header_size,
+                });
+            }
+
+            if line.first().is_some_and(|ch| ch.is_ascii_whitespace()) {
+                if in_content_type && content_type.len() + line.len() <= MAX_CONTENT_TYPE_LEN {
+                    content_type.extend_from_slice(line);
+                }
+            } else if line.len() > 13 && line[..13].eq_ignore_ascii_case(b"content-type:") {
+                in_content_type = true;
+                content_type.clear();
+                content_type
+                    .extend_from_slice(&line[13..std::cmp::min(line.len(), MAX_CONTENT_TYPE_LEN)]);
+            } else {
+                in_content_type = false;
+            }
+        } else if let Some(line) = line.strip_prefix(b"--") {
+            let delimiter = stack
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(pos, frame)| match frame {
+                    Frame::Multipart(boundary) => {
+                        let rest = line.strip_prefix(boundary.as_slice())?;
+                        if rest.starts_with(b"--") {
+                            Some((pos, true))
+                        } else if rest.iter().all(|ch| ch.is_ascii_whitespace()) {
+                            Some((pos, false))
+                        } else {
+                            None
+                        }
+                    }
+                    Frame::Message => None,
+                });
+
+            match delimiter {
+                Some((pos, true)) => {
+                    stack.truncate(pos);
+                }
+                Some((pos, false)) => {
+                    stack.truncate(pos + 1);
+                    parts += 1;
+                    if parts > limits.max_parts {
+                        return Err(MimeViolation::PartCount {
+                            limit: limits.max_parts,
+                        });
+                    }
+                    in_header = true;
+                }
+                None => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Wraps a message that could not be safely parsed into a single opaque part,
+// keeping a copy of the headers needed to display it in a mailbox.
+pub fn build_opaque_message(
+    raw_message: &[u8],
+    violation: &MimeViolation,
+    hostname: &str,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(raw_message.len() + 1024);
+    let mut has_message_id = false;
+    let mut preserved = None;
+
+    for line in raw_message.split(|&ch| ch == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            break;
+        }
+
+        if line.first().is_some_and(|ch| ch.is_ascii_whitespace()) {
+            if let Some(size) = preserved.as_mut() {
+                *size += line.len() + 2;
+                if *size <= MAX_PRESERVED_HEADER_LEN {
+                    message.extend_from_slice(line);
+                    message.extend_from_slice(b"\r\n");
+                }
+            }
+        } else if let Some(header) = PRESERVED_HEADERS.iter().find(|header| {
+            line.len() > header.len()
+                && line[..header.len()].eq_ignore_ascii_case(header.as_bytes())
+        }) {
+            if line.len() + 2 <= MAX_PRESERVED_HEADER_LEN {
+                has_message_id |= *header == "message-id:";
+                message.extend_from_slice(line);
+                message.extend_from_slice(b"\r\n");
+                preserved = Some(line.len() + 2);
+            } else {
+                preserved = None;
+            }
+        } else {
+            preserved = None;
+        }
+    }
+
+    if !has_message_id {
+        message.extend_from_slice(b"Message-ID: ");
+        let _ = generate_message_id_header(&mut message, hostname);
+        message.extend_from_slice(b"\r\n");
+    }
+    message.extend_from_slice(
+        format!(
+            concat!(
+                "X-Malformed-Message: {}\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: application/octet-stream\r\n",
+                "Content-Disposition: attachment; filename=\"original.eml\"\r\n",
+                "Content-Transfer-Encoding: 8bit\r\n\r\n"
+            ),
+            violation
+        )
+        .as_bytes(),
+    );
+    message.extend_from_slice(raw_message);
+    message
+}
+
+enum ContentType<'x> {
+    Multipart(&'x [u8]),
+    Message,
+}
+
+fn parse_content_type(value: &[u8]) -> Option<ContentType<'_>> {
+    let value = &value[value
+        .iter()
+        .position(|ch| !ch.is_ascii_whitespace())
+        .unwrap_or(value.len())..];
+    if value.len() > 10 && value[..10].eq_ignore_ascii_case(b"multipart/") {
+        let pos = value
+            .windows(9)
+            .position(|w| w.eq_ignore_ascii_case(b"boundary="))?;
+        let boundary = &value[pos + 9..];
+        let boundary = if let Some(boundary) = boundary.strip_prefix(b"\"") {
+            &boundary[..boundary
+                .iter()
+                .position(|&ch| ch == b'"')
+                .unwrap_or(boundary.len())]
+        } else {
+            &boundary[..boundary
+                .iter()
+                .position(|&ch| ch == b';' || ch.is_ascii_whitespace())
+                .unwrap_or(boundary.len())]
+        };
+        if !boundary.is_empty() && boundary.len() <= MAX_BOUNDARY_LEN {
+            Some(ContentType::Multipart(boundary))
+        } else {
+            None
+        }
+    } else if value.len() > 8
+        && value[..8].eq_ignore_ascii_case(b"message/")
+        && ["rfc822", "global"].iter().any(|subtype| {
+            value[8..]
+                .get(..subtype.len())
+                .is_some_and(|v| v.eq_ignore_ascii_case(subtype.as_bytes()))
+        })
+    {
+        Some(ContentType::Message)
+    } else {
+        None
+    }
+}
+
+impl Display for MimeViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MimeViolation::HeaderSize { limit } => {
+                write!(f, "header section exceeds {limit} bytes")
+            }
+            MimeViolation::PartCount { limit } => write!(f, "more than {limit} MIME parts"),
+            MimeViolation::Depth { limit } => {
+                write!(f, "MIME nesting deeper than {limit} levels")
+            }
+        }
+    }
+}
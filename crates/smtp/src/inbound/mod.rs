@@ -12,13 +12,22 @@ use mail_auth::{
     AuthenticationResults, DkimResult, DmarcResult, IprevResult, SpfResult,
 };
 
+pub mod attachments;
 pub mod auth;
+pub mod callout;
 pub mod data;
+pub mod dnsbl;
 pub mod ehlo;
+pub mod expand;
 pub mod hooks;
+pub mod list_policy;
 pub mod mail;
 pub mod milter;
+pub mod mime;
 pub mod rcpt;
+pub mod rcpt_policy;
+pub mod reputation;
+pub mod rewrite;
 pub mod session;
 pub mod spawn;
 pub mod vrfy;
@@ -4,7 +4,11 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::{config::smtp::session::Stage, listener::SessionStream, scripts::ScriptModification};
+use common::{
+    config::smtp::session::{CalloutPolicy, Stage},
+    listener::SessionStream,
+    scripts::ScriptModification,
+};
 use directory::backend::RcptType;
 use smtp_proto::{
     RcptTo, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
@@ -13,6 +17,12 @@ use trc::{SecurityEvent, SmtpEvent};
 
 use crate::{
     core::{Session, SessionAddress},
+    inbound::{
+        callout::{format_callout_response, CalloutResult, SmtpCallout},
+        list_policy::PostingOutcome,
+        rcpt_policy::RecipientPolicyResolver,
+        rewrite::{AddressRewrite, RewriteOutcome},
+    },
     queue::DomainPart,
     scripts::ScriptResult,
 };
@@ -184,10 +194,75 @@ impl<T: SessionStream> Session<T> {
             }
         }
 
+        // Rewrite map
+        let rcpt = self.data.rcpt_to.last().unwrap();
+        match self.server.resolve_rewrite(&rcpt.address_lcase).await {
+            Ok(resolution) => {
+                for hop in &resolution.hops {
+                    trc::event!(
+                        Smtp(SmtpEvent::RcptToRewritten),
+                        SpanId = self.data.session_id,
+                        Id = hop.rule.clone(),
+                        Details = hop.from.clone(),
+                        To = hop.to.clone(),
+                    );
+                }
+
+                match resolution.outcome {
+                    RewriteOutcome::Unchanged => {}
+                    RewriteOutcome::Rewritten => {
+                        let rcpt = self.data.rcpt_to.last_mut().unwrap();
+                        rcpt.domain = resolution.address.domain_part().to_string();
+                        rcpt.address_lcase = resolution.address.clone();
+                        rcpt.address = resolution.address;
+
+                        // Check for duplicates
+                        let rcpt = self.data.rcpt_to.last().unwrap();
+                        if self.data.rcpt_to.iter().filter(|r| r == &rcpt).count() > 1 {
+                            trc::event!(
+                                Smtp(SmtpEvent::RcptToDuplicate),
+                                SpanId = self.data.session_id,
+                                To = rcpt.address_lcase.clone(),
+                            );
+                            self.data.rcpt_to.pop();
+                            self.data.rcpt_oks += 1;
+                            return self.write(b"250 2.1.5 OK\r\n").await;
+                        }
+                    }
+                    RewriteOutcome::Loop | RewriteOutcome::TooManyHops => {
+                        trc::event!(
+                            Smtp(SmtpEvent::RcptToRewriteLoop),
+                            SpanId = self.data.session_id,
+                            To = resolution.address,
+                            Total = resolution.hops.len(),
+                        );
+
+                        let rcpt_to = self.data.rcpt_to.pop().unwrap().address_lcase;
+                        return self
+                            .rcpt_error(b"550 5.4.6 Routing loop detected.\r\n", rcpt_to)
+                            .await;
+                    }
+                }
+            }
+            Err(err) => {
+                trc::error!(err
+                    .span_id(self.data.session_id)
+                    .caused_by(trc::location!())
+                    .details("Failed to resolve recipient rewrites."));
+
+                self.data.rcpt_to.pop();
+                return self
+                    .write(b"451 4.4.3 Unable to verify address at this time.\r\n")
+                    .await;
+            }
+        }
+
         // Verify address
         let rcpt = self.data.rcpt_to.last().unwrap();
         let mut rcpt_members = None;
-        if let Some(directory) = self
+        let mut list_posting = None;
+        let mut is_local = false;
+        let directory = self
             .server
             .eval_if::<String, _>(
                 &self.server.core.smtp.session.rcpt.directory,
@@ -195,10 +270,11 @@ impl<T: SessionStream> Session<T> {
                 self.data.session_id,
             )
             .await
-            .and_then(|name| self.server.get_directory(&name))
-        {
+            .and_then(|name| self.server.get_directory(&name).cloned());
+        if let Some(directory) = &directory {
             match directory.is_local_domain(&rcpt.domain).await {
                 Ok(true) => {
+                    is_local = true;
                     match self
                         .server
                         .rcpt(directory, &rcpt.address_lcase, self.data.session_id)
@@ -206,8 +282,103 @@ impl<T: SessionStream> Session<T> {
                     {
                         Ok(RcptType::Mailbox) => {}
                         Ok(RcptType::List(members)) => {
+                            match self.list_posting(directory, &rcpt.address_lcase).await {
+                                Ok(PostingOutcome::Deliver) => {}
+                                Ok(PostingOutcome::Copy(posting)) => {
+                                    list_posting = Some(posting);
+                                }
+                                Ok(PostingOutcome::Reject(response)) => {
+                                    trc::event!(
+                                        Smtp(SmtpEvent::RcptToListPostingDenied),
+                                        SpanId = self.data.session_id,
+                                        To = rcpt.address_lcase.clone(),
+                                        From = self
+                                            .data
+                                            .mail_from
+                                            .as_ref()
+                                            .map(|from| from.address_lcase.clone())
+                                            .unwrap_or_default(),
+                                    );
+
+                                    self.data.rcpt_to.pop();
+                                    return self.write(response).await;
+                                }
+                                Err(err) => {
+                                    trc::error!(err
+                                        .span_id(self.data.session_id)
+                                        .caused_by(trc::location!())
+                                        .details("Failed to verify list posting policy."));
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"451 4.4.3 Unable to verify address at this time.\r\n",
+                                        )
+                                        .await;
+                                }
+                            }
                             rcpt_members = Some(members);
                         }
+                        Ok(RcptType::Alias(forward_to)) => {
+                            let expansion = match self
+                                .server
+                                .expand_alias(
+                                    directory,
+                                    &rcpt.address_lcase,
+                                    forward_to,
+                                    self.data.session_id,
+                                )
+                                .await
+                            {
+                                Ok(expansion) => expansion,
+                                Err(err) => {
+                                    trc::error!(err
+                                        .span_id(self.data.session_id)
+                                        .caused_by(trc::location!())
+                                        .details("Failed to expand alias."));
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"451 4.4.3 Unable to verify address at this time.\r\n",
+                                        )
+                                        .await;
+                                }
+                            };
+
+                            for destination in &expansion.loops {
+                                trc::event!(
+                                    Smtp(SmtpEvent::RcptToAliasLoop),
+                                    SpanId = self.data.session_id,
+                                    To = rcpt.address_lcase.clone(),
+                                    Details = destination.clone(),
+                                );
+                            }
+
+                            if expansion.exceeded {
+                                trc::event!(
+                                    Smtp(SmtpEvent::RcptToAliasFanOut),
+                                    SpanId = self.data.session_id,
+                                    To = rcpt.address_lcase.clone(),
+                                    Limit = self.server.core.smtp.session.rcpt.alias_max_fan_out,
+                                );
+
+                                let rcpt_to = self.data.rcpt_to.pop().unwrap().address_lcase;
+                                return self
+                                    .rcpt_error(
+                                        b"550 5.5.3 Alias expands to too many recipients.\r\n",
+                                        rcpt_to,
+                                    )
+                                    .await;
+                            } else if expansion.destinations.is_empty() {
+                                let rcpt_to = self.data.rcpt_to.pop().unwrap().address_lcase;
+                                return self
+                                    .rcpt_error(b"550 5.4.6 Routing loop detected.\r\n", rcpt_to)
+                                    .await;
+                            }
+
+                            rcpt_members = Some(expansion.destinations);
+                        }
                         Ok(RcptType::Invalid) => {
                             trc::event!(
                                 Smtp(SmtpEvent::MailboxDoesNotExist),
@@ -290,6 +461,162 @@ impl<T: SessionStream> Session<T> {
                 .await;
         }
 
+        // Outbound recipient policy of the authenticated sender
+        if let Some(access_token) = self.data.authenticated_as.clone() {
+            match self.server.recipient_restriction(&access_token).await {
+                Ok(Some(restriction)) => {
+                    let rcpt = self.data.rcpt_to.last().unwrap();
+                    if !is_local && !restriction.allows_domain(&rcpt.domain) {
+                        trc::event!(
+                            Smtp(SmtpEvent::RcptToPolicyViolation),
+                            SpanId = self.data.session_id,
+                            To = rcpt.address_lcase.clone(),
+                            Details = restriction.description(),
+                        );
+
+                        self.data.rcpt_to.pop();
+                        return self
+                            .write(
+                                format!(
+                                    "550 5.7.1 Recipient not allowed by the {}.\r\n",
+                                    restriction.description()
+                                )
+                                .as_bytes(),
+                            )
+                            .await;
+                    }
+
+                    // Internal lists may contain external members
+                    if let (Some(directory), Some(members)) = (
+                        &directory,
+                        rcpt_members.as_mut().filter(|members| !members.is_empty()),
+                    ) {
+                        let mut allowed_members = Vec::with_capacity(members.len());
+                        for member in members.drain(..) {
+                            let address = member.to_lowercase();
+                            if restriction.allows_domain(address.domain_part()) {
+                                allowed_members.push(member);
+                                continue;
+                            }
+
+                            match self
+                                .server
+                                .is_internal_recipient(directory, &address, self.data.session_id)
+                                .await
+                            {
+                                Ok(true) => {
+                                    allowed_members.push(member);
+                                }
+                                Ok(false) => {
+                                    trc::event!(
+                                        Smtp(SmtpEvent::RcptToPolicyMemberRemoved),
+                                        SpanId = self.data.session_id,
+                                        To = address,
+                                        Details = restriction.description(),
+                                    );
+                                }
+                                Err(err) => {
+                                    trc::error!(err
+                                        .span_id(self.data.session_id)
+                                        .caused_by(trc::location!())
+                                        .details("Failed to verify list member."));
+
+                                    self.data.rcpt_to.pop();
+                                    return self
+                                        .write(
+                                            b"451 4.4.3 Unable to verify address at this time.\r\n",
+                                        )
+                                        .await;
+                                }
+                            }
+                        }
+
+                        if allowed_members.is_empty() {
+                            trc::event!(
+                                Smtp(SmtpEvent::RcptToPolicyViolation),
+                                SpanId = self.data.session_id,
+                                To = self.data.rcpt_to.last().unwrap().address_lcase.clone(),
+                                Details = restriction.description(),
+                            );
+
+                            self.data.rcpt_to.pop();
+                            return self
+                                .write(
+                                    format!(
+                                        "550 5.7.1 List members not allowed by the {}.\r\n",
+                                        restriction.description()
+                                    )
+                                    .as_bytes(),
+                                )
+                                .await;
+                        }
+                        *members = allowed_members;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    trc::error!(err
+                        .span_id(self.data.session_id)
+                        .caused_by(trc::location!())
+                        .details("Failed to resolve recipient policy."));
+
+                    self.data.rcpt_to.pop();
+                    return self
+                        .write(b"451 4.4.3 Unable to verify address at this time.\r\n")
+                        .await;
+                }
+            }
+        }
+
+        // Callout verification for relayed domains
+        if !is_local {
+            if let Some(relay_id) = self
+                .server
+                .eval_if::<String, _>(
+                    &self.server.core.smtp.session.rcpt.callout,
+                    self,
+                    self.data.session_id,
+                )
+                .await
+            {
+                let rcpt = self.data.rcpt_to.last().unwrap();
+                match self
+                    .server
+                    .callout(
+                        &relay_id,
+                        &rcpt.address_lcase,
+                        &rcpt.domain,
+                        &self.hostname,
+                        self.data.session_id,
+                    )
+                    .await
+                {
+                    CalloutResult::Accept => {}
+                    CalloutResult::Reject(response) => {
+                        let rcpt_to = self.data.rcpt_to.pop().unwrap().address_lcase;
+                        return self
+                            .rcpt_error(format_callout_response(&response, 550).as_bytes(), rcpt_to)
+                            .await;
+                    }
+                    CalloutResult::Defer(response) => {
+                        self.data.rcpt_to.pop();
+                        return self
+                            .write(format_callout_response(&response, 451).as_bytes())
+                            .await;
+                    }
+                    CalloutResult::Failed => {
+                        if self.server.core.smtp.session.callout.on_failure == CalloutPolicy::Defer
+                        {
+                            self.data.rcpt_to.pop();
+                            return self
+                                .write(b"451 4.4.3 Unable to verify address at this time.\r\n")
+                                .await;
+                        }
+                    }
+                }
+            }
+        }
+
         if self.is_allowed().await {
             trc::event!(
                 Smtp(SmtpEvent::RcptTo),
@@ -320,9 +647,25 @@ impl<T: SessionStream> Session<T> {
                 {
                     member_addr.dsn_info = orcpt.clone().into();
                     member_addr.flags = list_addr.flags;
+                    if let Some(posting) = &mut list_posting {
+                        posting.members.push(member_addr.address_lcase.clone());
+                    }
                     self.data.rcpt_to.push(member_addr);
                 }
             }
+
+            // Members needing a separate copy are split off on DATA
+            if let Some(posting) = list_posting {
+                if posting.moderate {
+                    trc::event!(
+                        Smtp(SmtpEvent::RcptToListModerated),
+                        SpanId = self.data.session_id,
+                        To = list_addr.address_lcase,
+                        Id = posting.list_id,
+                    );
+                }
+                self.data.list_postings.push(posting);
+            }
         }
 
         self.data.rcpt_oks += 1;
@@ -0,0 +1,177 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, time::Instant};
+
+use ahash::AHashSet;
+use common::{
+    auth::AccessToken,
+    config::smtp::session::{RecipientPolicy, RecipientPolicyMode},
+    Server,
+};
+use directory::{
+    backend::{internal::manage::ManageDirectory, RcptType},
+    Directory,
+};
+use trc::AddContext;
+use utils::map::ttl_dashmap::TtlMap;
+
+use crate::queue::DomainPart;
+
+/// Outbound restriction that applies to an authenticated sender.
+#[derive(Debug, Clone)]
+pub struct RecipientRestriction {
+    pub mode: RecipientPolicyMode,
+    pub allowed_domains: AHashSet<String>,
+    pub source: String,
+}
+
+pub trait RecipientPolicyResolver: Sync + Send {
+    fn recipient_restriction(
+        &self,
+        access_token: &AccessToken,
+    ) -> impl Future<Output = trc::Result<Option<RecipientRestriction>>> + Send;
+
+    fn is_internal_recipient(
+        &self,
+        directory: &Directory,
+        address: &str,
+        session_id: u64,
+    ) -> impl Future<Output = trc::Result<bool>> + Send;
+}
+
+impl RecipientPolicyResolver for Server {
+    async fn recipient_restriction(
+        &self,
+        access_token: &AccessToken,
+    ) -> trc::Result<Option<RecipientRestriction>> {
+        let policies = &self.core.smtp.session.recipient_policy;
+        if !policies.is_enabled() {
+            return Ok(None);
+        }
+
+        // Policies are read from the current configuration, only the names
+        // of tenants and groups are cached
+        let mut group_names = Vec::with_capacity(access_token.member_of.len());
+        for group_id in &access_token.member_of {
+            if let Some(name) = cached_principal_name(self, *group_id).await? {
+                group_names.push(name);
+            }
+        }
+
+        // Group policies override the tenant policy, when a sender belongs to
+        // several restricted groups the least restrictive one applies
+        let mut restriction: Option<RecipientRestriction> = None;
+        for name in &group_names {
+            let Some(policy) = policies.groups.get(name) else {
+                continue;
+            };
+            match &mut restriction {
+                Some(current) if current.mode > policy.mode => {}
+                Some(current) if current.mode == policy.mode => {
+                    if policy.mode == RecipientPolicyMode::AllowList {
+                        current
+                            .allowed_domains
+                            .extend(policy.allowed_domains.iter().cloned());
+                    }
+                }
+                _ => {
+                    restriction =
+                        Some(RecipientRestriction::new(policy, format!("group {name:?}")));
+                }
+            }
+        }
+        if restriction.is_some() {
+            return Ok(restriction.filter(|r| r.mode != RecipientPolicyMode::AllowAll));
+        }
+
+        let Some(tenant_id) = access_token.tenant.map(|t| t.id) else {
+            return Ok(None);
+        };
+        let Some(tenant_name) = cached_principal_name(self, tenant_id).await? else {
+            return Ok(None);
+        };
+        let Some(policy) = policies.tenants.get(&tenant_name) else {
+            return Ok(None);
+        };
+
+        // Designated principals or members of designated groups are exempt
+        if policy.mode == RecipientPolicyMode::AllowAll
+            || policy.exempt.contains(&access_token.name.to_lowercase())
+            || group_names.iter().any(|name| policy.exempt.contains(name))
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(RecipientRestriction::new(
+            policy,
+            format!("tenant {tenant_name:?}"),
+        )))
+    }
+
+    async fn is_internal_recipient(
+        &self,
+        directory: &Directory,
+        address: &str,
+        session_id: u64,
+    ) -> trc::Result<bool> {
+        if directory.is_local_domain(address.domain_part()).await? {
+            self.rcpt(directory, address, session_id)
+                .await
+                .map(|rcpt| rcpt != RcptType::Invalid)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+async fn cached_principal_name(server: &Server, principal_id: u32) -> trc::Result<Option<String>> {
+    if let Some(name) = server
+        .inner
+        .data
+        .principal_names
+        .get_with_ttl(&principal_id)
+    {
+        return Ok(name);
+    }
+
+    let name = server
+        .core
+        .storage
+        .data
+        .get_principal(principal_id)
+        .await
+        .caused_by(trc::location!())?
+        .map(|principal| principal.name().to_lowercase());
+
+    Ok(server.inner.data.principal_names.insert_with_ttl(
+        principal_id,
+        name,
+        Instant::now() + server.core.smtp.session.recipient_policy.cache_ttl,
+    ))
+}
+
+impl RecipientRestriction {
+    fn new(policy: &RecipientPolicy, source: String) -> Self {
+        RecipientRestriction {
+            mode: policy.mode,
+            allowed_domains: if policy.mode == RecipientPolicyMode::AllowList {
+                policy.allowed_domains.clone()
+            } else {
+                AHashSet::new()
+            },
+            source,
+        }
+    }
+
+    pub fn allows_domain(&self, domain: &str) -> bool {
+        self.allowed_domains.contains(domain)
+    }
+
+    pub fn description(&self) -> String {
+        format!("{} recipient policy of {}", self.mode.as_str(), self.source)
+    }
+}
@@ -0,0 +1,68 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::reputation::ReputationLevel, listener::SessionStream};
+use trc::SmtpEvent;
+
+use crate::core::Session;
+
+impl<T: SessionStream> Session<T> {
+    pub async fn verify_sender_reputation(&mut self) -> Option<&'static [u8]> {
+        if !self.server.core.sender_reputation.enable {
+            return None;
+        }
+
+        let account_id = self.data.authenticated_as.as_ref()?.primary_id();
+        let state = match self.server.sender_reputation(account_id).await {
+            Ok(Some(state)) => state,
+            Ok(None) => return None,
+            Err(err) => {
+                trc::error!(err
+                    .span_id(self.data.session_id)
+                    .details("Failed to obtain sender reputation"));
+                return None;
+            }
+        };
+
+        let response: &'static [u8] = match state.level {
+            ReputationLevel::Normal => return None,
+            ReputationLevel::Suspend => {
+                b"554 5.7.1 Submission suspended due to unusual sending activity, contact your administrator.\r\n"
+            }
+            ReputationLevel::Reauthenticate if self.data.authenticated_at <= state.since => {
+                // Sessions authenticated before the restriction have to log in again
+                self.data.authenticated_as = None;
+                b"530 5.7.0 Authentication required, please log in again.\r\n"
+            }
+            ReputationLevel::Throttle | ReputationLevel::Reauthenticate => {
+                match self.server.reputation_signals(account_id).await {
+                    Ok(signals)
+                        if signals.recipients_per_hour
+                            >= self.server.core.sender_reputation.throttle_rate =>
+                    {
+                        b"451 4.7.1 Sending rate limited due to unusual activity, try again later.\r\n"
+                    }
+                    Ok(_) => return None,
+                    Err(err) => {
+                        trc::error!(err
+                            .span_id(self.data.session_id)
+                            .details("Failed to obtain sender reputation"));
+                        return None;
+                    }
+                }
+            }
+        };
+
+        trc::event!(
+            Smtp(SmtpEvent::SenderReputationBlocked),
+            SpanId = self.data.session_id,
+            AccountId = account_id,
+            Details = format!("{:?}", state.level),
+        );
+
+        Some(response)
+    }
+}
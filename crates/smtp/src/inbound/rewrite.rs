@@ -0,0 +1,265 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{future::Future, sync::Arc, time::Instant};
+
+use ahash::AHashMap;
+use common::{
+    config::smtp::session::{CompiledRewriteMap, RewriteRule, RewriteRuleType},
+    Server,
+};
+use regex::Regex;
+use serde::Serialize;
+use store::{write::Bincode, LookupStore, Serialize as _};
+use trc::AddContext;
+
+const REWRITE_RULES_KEY: &[u8] = b"rewrite-map";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewriteHop {
+    pub rule: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RewriteOutcome {
+    Unchanged,
+    Rewritten,
+    Loop,
+    TooManyHops,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewriteResolution {
+    pub address: String,
+    pub outcome: RewriteOutcome,
+    pub hops: Vec<RewriteHop>,
+}
+
+pub trait AddressRewrite: Sync + Send {
+    fn rewrite_rules(&self) -> impl Future<Output = trc::Result<Vec<RewriteRule>>> + Send;
+
+    fn set_rewrite_rule(&self, rule: RewriteRule) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn delete_rewrite_rule(&self, id: &str) -> impl Future<Output = trc::Result<bool>> + Send;
+
+    fn resolve_rewrite(
+        &self,
+        address: &str,
+    ) -> impl Future<Output = trc::Result<RewriteResolution>> + Send;
+}
+
+impl AddressRewrite for Server {
+    async fn rewrite_rules(&self) -> trc::Result<Vec<RewriteRule>> {
+        self.rewrite_store()?
+            .key_get::<Bincode<Vec<RewriteRule>>>(REWRITE_RULES_KEY.to_vec())
+            .await
+            .caused_by(trc::location!())
+            .map(|rules| rules.map(|rules| rules.inner).unwrap_or_default())
+    }
+
+    async fn set_rewrite_rule(&self, rule: RewriteRule) -> trc::Result<()> {
+        let mut rules = self.rewrite_rules().await?;
+        if let Some(existing) = rules.iter_mut().find(|r| r.id == rule.id) {
+            *existing = rule;
+        } else {
+            rules.push(rule);
+        }
+
+        self.write_rewrite_rules(rules).await
+    }
+
+    async fn delete_rewrite_rule(&self, id: &str) -> trc::Result<bool> {
+        let mut rules = self.rewrite_rules().await?;
+        let num_rules = rules.len();
+        rules.retain(|r| r.id != id);
+
+        if rules.len() != num_rules {
+            self.write_rewrite_rules(rules).await.map(|_| true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    async fn resolve_rewrite(&self, address: &str) -> trc::Result<RewriteResolution> {
+        let rules = self.compiled_rewrite_rules().await?;
+        let max_hops = self.core.smtp.session.rewrite_map.max_hops;
+        let mut address = address.to_lowercase();
+        let mut hops: Vec<RewriteHop> = Vec::new();
+
+        while let Some((rule, target)) = apply_rules(&rules, &address) {
+            if hops.len() >= max_hops {
+                return Ok(RewriteResolution {
+                    address,
+                    outcome: RewriteOutcome::TooManyHops,
+                    hops,
+                });
+            }
+
+            // A target that was already visited would rewrite forever
+            let is_loop = target == address || hops.iter().any(|hop| hop.from == target);
+            let from = std::mem::replace(&mut address, target);
+            hops.push(RewriteHop {
+                rule: rule.id.clone(),
+                from,
+                to: address.clone(),
+            });
+
+            if is_loop {
+                return Ok(RewriteResolution {
+                    address,
+                    outcome: RewriteOutcome::Loop,
+                    hops,
+                });
+            }
+        }
+
+        Ok(RewriteResolution {
+            address,
+            outcome: if hops.is_empty() {
+                RewriteOutcome::Unchanged
+            } else {
+                RewriteOutcome::Rewritten
+            },
+            hops,
+        })
+    }
+}
+
+trait RewriteStore: Sync + Send {
+    fn rewrite_store(&self) -> trc::Result<&LookupStore>;
+
+    fn write_rewrite_rules(
+        &self,
+        rules: Vec<RewriteRule>,
+    ) -> impl Future<Output = trc::Result<()>> + Send;
+
+    fn compiled_rewrite_rules(
+        &self,
+    ) -> impl Future<Output = trc::Result<Arc<CompiledRewriteMap>>> + Send;
+}
+
+impl RewriteStore for Server {
+    fn rewrite_store(&self) -> trc::Result<&LookupStore> {
+        match &self.core.smtp.session.rewrite_map.store {
+            Some(id) => self.core.storage.lookups.get(id).ok_or_else(|| {
+                trc::StoreEvent::NotConfigured
+                    .into_err()
+                    .details("Rewrite map lookup store not found")
+                    .id(id.clone())
+            }),
+            None => Ok(&self.core.storage.lookup),
+        }
+    }
+
+    async fn write_rewrite_rules(&self, rules: Vec<RewriteRule>) -> trc::Result<()> {
+        self.rewrite_store()?
+            .key_set(
+                REWRITE_RULES_KEY.to_vec(),
+                Bincode::new(rules).serialize(),
+                None,
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+        // Changes made on this node are visible immediately, other nodes
+        // pick them up once their cached copy expires
+        *self.inner.data.rewrite_map.write() = None;
+
+        Ok(())
+    }
+
+    async fn compiled_rewrite_rules(&self) -> trc::Result<Arc<CompiledRewriteMap>> {
+        let cache_ttl = self.core.smtp.session.rewrite_map.cache_ttl;
+        if let Some(rules) = self
+            .inner
+            .data
+            .rewrite_map
+            .read()
+            .as_ref()
+            .filter(|rules| rules.loaded.elapsed() < cache_ttl)
+        {
+            return Ok(rules.clone());
+        }
+
+        let mut exact = AHashMap::new();
+        let mut regex = Vec::new();
+        for rule in self.rewrite_rules().await? {
+            match rule.typ {
+                RewriteRuleType::Exact => {
+                    exact.insert(rule.pattern.to_lowercase(), rule);
+                }
+                RewriteRuleType::Regex => match Regex::new(&rule.pattern) {
+                    Ok(compiled) => {
+                        regex.push((compiled, rule));
+                    }
+                    Err(err) => {
+                        trc::event!(
+                            Smtp(trc::SmtpEvent::RcptToRewriteInvalid),
+                            Id = rule.id,
+                            Details = rule.pattern,
+                            Reason = err.to_string(),
+                        );
+                    }
+                },
+            }
+        }
+
+        let rules = Arc::new(CompiledRewriteMap {
+            loaded: Instant::now(),
+            exact,
+            regex,
+        });
+        *self.inner.data.rewrite_map.write() = Some(rules.clone());
+
+        Ok(rules)
+    }
+}
+
+fn apply_rules<'x>(
+    rules: &'x CompiledRewriteMap,
+    address: &str,
+) -> Option<(&'x RewriteRule, String)> {
+    if let Some(rule) = rules.exact.get(address) {
+        return Some((rule, rule.target.to_lowercase()));
+    }
+
+    // Regex rules are evaluated in the order they were added
+    rules.regex.iter().find_map(|(regex, rule)| {
+        if regex.is_match(address) {
+            let target = regex.replace(address, rule.target.as_str()).to_lowercase();
+            if target.contains('@') {
+                return Some((rule, target));
+            }
+        }
+
+        None
+    })
+}
+
+pub fn validate_rewrite_rule(rule: &RewriteRule) -> Result<(), String> {
+    if rule.pattern.is_empty() || rule.target.is_empty() {
+        return Err("Rewrite rules require a pattern and a target".to_string());
+    }
+
+    match rule.typ {
+        RewriteRuleType::Exact => {
+            if !rule.pattern.contains('@') || !rule.target.contains('@') {
+                return Err("Exact rewrite rules must map an address to an address".to_string());
+            }
+        }
+        RewriteRuleType::Regex => {
+            Regex::new(&rule.pattern)
+                .map_err(|err| format!("Invalid regular expression: {err}"))?;
+        }
+    }
+
+    Ok(())
+}
@@ -339,6 +339,7 @@ impl<T: SessionStream> Session<T> {
                     if self.data.message.len() + bytes.len() < self.params.max_message_size {
                         if receiver.ingest(&mut iter, &mut self.data.message) {
                             let message = self.queue_message().await;
+                            self.record_rejected_volume(message.as_ref());
                             let num_responses = if self.instance.protocol == ServerProtocol::Smtp {
                                 1
                             } else {
@@ -366,6 +367,7 @@ impl<T: SessionStream> Session<T> {
                         if self.can_send_data().await? {
                             if receiver.is_last {
                                 let message = self.queue_message().await;
+                                self.record_rejected_volume(message.as_ref());
                                 if !message.is_empty() {
                                     let num_responses =
                                         if self.instance.protocol == ServerProtocol::Smtp {
@@ -460,7 +462,9 @@ impl<T: AsyncWrite + AsyncRead + Unpin> Session<T> {
     pub fn reset(&mut self) {
         self.data.mail_from = None;
         self.data.spf_mail_from = None;
+        self.data.dnsbl = None;
         self.data.rcpt_to.clear();
+        self.data.list_postings.clear();
         self.data.message = Vec::with_capacity(0);
         self.data.priority = 0;
         self.data.delivery_by = 0;
@@ -12,7 +12,7 @@ use common::{
     listener::{self, SessionManager, SessionStream},
 };
 use tokio_rustls::server::TlsStream;
-use trc::{SecurityEvent, SmtpEvent};
+use trc::{LimitEvent, SecurityEvent, SmtpEvent};
 
 use crate::{
     core::{Session, SessionData, SessionParameters, SmtpSessionManager, State},
@@ -50,6 +50,7 @@ impl SessionManager for SmtpSessionManager {
                 && session.instance.acceptor.is_tls()
             {
                 if let Ok(mut session) = session.into_tls().await {
+                    session.authenticate_client_certificate().await;
                     session.handle_conn().await;
                 }
             }
@@ -83,6 +84,20 @@ impl SessionManager for SmtpSessionManager {
 
 impl<T: SessionStream> Session<T> {
     pub async fn init_conn(&mut self) -> bool {
+        // Reject new connections while the server is shedding load
+        if self.server.is_shedding_load() {
+            trc::event!(
+                Limit(LimitEvent::LoadShed),
+                SpanId = self.data.session_id,
+                RemoteIp = self.data.remote_ip,
+            );
+
+            let _ = self
+                .write(b"421 4.3.2 Server busy, please try again later.\r\n")
+                .await;
+            return false;
+        }
+
         self.eval_session_params().await;
 
         let config = &self.server.core.smtp.session.connect;
@@ -150,6 +165,11 @@ impl<T: SessionStream> Session<T> {
             return false;
         }
 
+        // Client certificate authentication on implicit TLS
+        if self.stream.is_tls() {
+            self.authenticate_client_certificate().await;
+        }
+
         true
     }
 
@@ -269,7 +289,7 @@ impl<T: SessionStream> Session<T> {
             hostname: self.hostname,
             stream: self
                 .instance
-                .tls_accept(self.stream, self.data.session_id)
+                .tls_accept(self.stream, self.data.remote_ip, self.data.session_id)
                 .await?,
             state: self.state,
             data: self.data,
@@ -5,6 +5,7 @@
  */
 
 use std::{
+    future::Future,
     net::{IpAddr, SocketAddr},
     time::Duration,
 };
@@ -597,6 +598,63 @@ impl SmtpClient<TcpStream> {
     }
 }
 
+/// Connects to `remote_ip` and, when the attempt has not completed after `delay`,
+/// races it against a connection to `fallback_ip` (RFC 8305). Returns the
+/// completed attempts in order, the last one being the established connection
+/// if any succeeded. Attempts still pending once a connection is established
+/// are cancelled.
+pub async fn happy_eyeballs<T, E, F, Fut>(
+    remote_ip: IpAddr,
+    fallback_ip: Option<IpAddr>,
+    delay: Duration,
+    session_id: u64,
+    connect: F,
+) -> Vec<(IpAddr, Result<T, E>)>
+where
+    F: Fn(IpAddr) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let primary = connect(remote_ip);
+    tokio::pin!(primary);
+
+    let fallback_ip = match fallback_ip {
+        Some(fallback_ip) => {
+            tokio::select! {
+                result = &mut primary => return vec![(remote_ip, result)],
+                _ = tokio::time::sleep(delay) => fallback_ip,
+            }
+        }
+        None => return vec![(remote_ip, primary.await)],
+    };
+
+    trc::event!(
+        Delivery(DeliveryEvent::HappyEyeballsFallback),
+        SpanId = session_id,
+        RemoteIp = remote_ip,
+        Details = fallback_ip,
+        Elapsed = delay,
+    );
+
+    let fallback = connect(fallback_ip);
+    tokio::pin!(fallback);
+    tokio::select! {
+        result = &mut primary => {
+            if result.is_ok() {
+                vec![(remote_ip, result)]
+            } else {
+                vec![(remote_ip, result), (fallback_ip, fallback.await)]
+            }
+        }
+        result = &mut fallback => {
+            if result.is_ok() {
+                vec![(fallback_ip, result)]
+            } else {
+                vec![(fallback_ip, result), (remote_ip, primary.await)]
+            }
+        }
+    }
+}
+
 impl SmtpClient<TlsStream<TcpStream>> {
     pub fn tls_connection(&self) -> &ClientConnection {
         self.stream.get_ref().1
@@ -4,7 +4,9 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use crate::outbound::client::{from_error_status, from_mail_send_error, SmtpClient};
+use crate::outbound::client::{
+    from_error_status, from_mail_send_error, happy_eyeballs, SmtpClient,
+};
 use crate::outbound::dane::dnssec::TlsaLookup;
 use crate::outbound::lookup::DnsLookup;
 use crate::outbound::mta_sts::lookup::MtaStsLookup;
@@ -16,9 +18,12 @@ use crate::queue::throttle::IsAllowed;
 use crate::reporting::SmtpReporting;
 use common::config::{
     server::ServerProtocol,
-    smtp::{queue::RequireOptional, report::AggregateFrequency},
+    smtp::{
+        queue::{RequireOptional, SourceIpFallback},
+        report::AggregateFrequency,
+    },
 };
-use common::ipc::{OnHold, PolicyType, QueueEvent, TlsEvent};
+use common::ipc::{self, OnHold, PolicyType, QueueEvent, TlsEvent};
 use common::Server;
 use mail_auth::{
     mta_sts::TlsRpt,
@@ -38,7 +43,10 @@ use crate::{
 };
 
 use super::{lookup::ToNextHop, mta_sts, session::SessionParams, NextHop, TlsStrategy};
-use crate::queue::{throttle, DeliveryAttempt, Domain, Error, QueueEnvelope, Status};
+use crate::queue::{
+    throttle, ConnectAttempt, DeliveryAttempt, Domain, Error, QueueEnvelope, SourceIp, Status,
+    MAIL_HOLD_RELEASED, MAIL_SUBMISSION,
+};
 
 impl DeliveryAttempt {
     pub async fn try_deliver(mut self, server: Server) {
@@ -150,6 +158,7 @@ impl DeliveryAttempt {
             );
 
             // All message recipients expired, do not re-queue. (DSN has been already sent)
+            message.notify_submission(&server, true).await;
             message.remove(&server, self.event.due).await;
             if server
                 .inner
@@ -170,6 +179,88 @@ impl DeliveryAttempt {
             return;
         }
 
+        // Enforce tenant sending policies
+        let sender_tenant = server
+            .sender_tenant_name(&message)
+            .await
+            .unwrap_or_else(|err| {
+                trc::error!(err
+                    .span_id(span_id)
+                    .details("Failed to resolve sender tenant")
+                    .caused_by(trc::location!()));
+                None
+            });
+        let next_event = if let Some(list_id) = message.moderated_list() {
+            // Posts to moderated lists wait until a moderator acts on them
+            trc::event!(
+                Delivery(DeliveryEvent::HeldForModeration),
+                SpanId = span_id,
+                Id = list_id,
+            );
+
+            Some(None)
+        } else if let Some((tenant, policy)) = sender_tenant.as_deref().and_then(|tenant| {
+            server
+                .core
+                .smtp
+                .queue
+                .sending_policies
+                .get(tenant)
+                .map(|policy| (tenant, policy))
+        }) {
+            if policy.hold && (message.flags & MAIL_HOLD_RELEASED) == 0 {
+                // Park the message until a reviewer acts on it
+                if !message.is_held_for_review() {
+                    message.hold_for_review(tenant);
+                }
+
+                trc::event!(
+                    Delivery(DeliveryEvent::HeldForReview),
+                    SpanId = span_id,
+                    Id = tenant.to_string(),
+                );
+
+                Some(None)
+            } else if let Some(until) = policy.next_window(now()) {
+                message.hold_until(tenant, until);
+
+                trc::event!(
+                    Delivery(DeliveryEvent::SendingWindowClosed),
+                    SpanId = span_id,
+                    Id = tenant.to_string(),
+                    NextRetry = trc::Value::Timestamp(until),
+                );
+
+                Some(Some(message.next_event().unwrap_or(until)))
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+        if let Some(next_event) = next_event {
+            message
+                .save_changes(&server, self.event.due.into(), next_event)
+                .await;
+            if server
+                .inner
+                .ipc
+                .queue_tx
+                .send(QueueEvent::Reload)
+                .await
+                .is_err()
+            {
+                trc::event!(
+                    Server(ServerEvent::ThreadError),
+                    Reason = "Channel closed.",
+                    CausedBy = trc::location!(),
+                    SpanId = span_id
+                );
+            }
+            return;
+        }
+        message.hold = None;
+
         // Throttle sender
         for throttle in &server.core.smtp.queue.throttle.sender {
             if let Err(err) = server
@@ -232,6 +323,9 @@ impl DeliveryAttempt {
         let mut on_hold = Vec::new();
         let no_ip = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
         let mut recipients = std::mem::take(&mut message.recipients);
+        let mut attempted = Vec::new();
+        let mut source_ips = Vec::new();
+        let mut connect_attempts = Vec::new();
         'next_domain: for domain_idx in 0..message.domains.len() {
             // Only process domains due for delivery
             let domain = &message.domains[domain_idx];
@@ -240,6 +334,7 @@ impl DeliveryAttempt {
             {
                 continue;
             }
+            attempted.push(domain_idx);
 
             trc::event!(
                 Delivery(DeliveryEvent::DomainDeliveryStart),
@@ -250,6 +345,8 @@ impl DeliveryAttempt {
 
             // Build envelope
             let mut envelope = QueueEnvelope::new(&message, domain_idx);
+            let source_ip_rule =
+                server.source_ip_rule(&message, &domain.domain, sender_tenant.as_deref());
 
             // Throttle recipient domain
             let mut in_flight = Vec::new();
@@ -281,7 +378,7 @@ impl DeliveryAttempt {
                     let delivery_result = message
                         .deliver_local(
                             recipients.iter_mut().filter(|r| r.domain_idx == domain_idx),
-                            &server.inner.ipc.delivery_tx,
+                            &server,
                         )
                         .await;
 
@@ -651,7 +748,13 @@ impl DeliveryAttempt {
                 // Obtain source and remote IPs
                 let time = Instant::now();
                 let resolve_result = match server
-                    .resolve_host(remote_host, &envelope, max_multihomed, message.span_id)
+                    .resolve_host(
+                        remote_host,
+                        &envelope,
+                        max_multihomed,
+                        source_ip_rule,
+                        message.span_id,
+                    )
                     .await
                 {
                     Ok(result) => {
@@ -856,14 +959,30 @@ impl DeliveryAttempt {
                 };
 
                 // Try each IP address
-                'next_ip: for remote_ip in resolve_result.remote_ips {
-                    // Set source IP, if any
-                    let source_ip = if remote_ip.is_ipv4() {
+                let happy_eyeballs_delay = server
+                    .eval_if(
+                        &queue_config.timeout.happy_eyeballs,
+                        &envelope,
+                        message.span_id,
+                    )
+                    .await
+                    .unwrap_or_else(|| Duration::from_millis(250));
+                let source_ip_for = |remote_ip: IpAddr| {
+                    if remote_ip.is_ipv4() {
                         resolve_result.source_ipv4
                     } else {
                         resolve_result.source_ipv6
-                    };
-                    envelope.local_ip = source_ip.unwrap_or(no_ip);
+                    }
+                };
+                let mut raced_ips = Vec::new();
+                'next_ip: for (ip_idx, &remote_ip) in resolve_result.remote_ips.iter().enumerate() {
+                    // Addresses raced by a previous attempt were already tried
+                    if raced_ips.contains(&remote_ip) {
+                        continue;
+                    }
+
+                    // Set source IP, if any
+                    envelope.local_ip = source_ip_for(remote_ip).unwrap_or(no_ip);
 
                     // Throttle remote host
                     let mut in_flight_host = Vec::new();
@@ -884,58 +1003,196 @@ impl DeliveryAttempt {
                         }
                     }
 
+                    // Next address of the other family, raced if this attempt stalls
+                    let mut fallback_ip = None;
+                    let mut in_flight_fallback = Vec::new();
+                    if !happy_eyeballs_delay.is_zero() {
+                        if let Some(&ip) = resolve_result.remote_ips[ip_idx + 1..]
+                            .iter()
+                            .find(|ip| ip.is_ipv6() != remote_ip.is_ipv6())
+                        {
+                            envelope.remote_ip = ip;
+                            envelope.local_ip = source_ip_for(ip).unwrap_or(no_ip);
+                            let mut is_allowed = true;
+                            for throttle in &queue_config.throttle.host {
+                                if server
+                                    .is_allowed(
+                                        throttle,
+                                        &envelope,
+                                        &mut in_flight_fallback,
+                                        message.span_id,
+                                    )
+                                    .await
+                                    .is_err()
+                                {
+                                    is_allowed = false;
+                                    break;
+                                }
+                            }
+                            if is_allowed {
+                                fallback_ip = Some(ip);
+                            } else {
+                                in_flight_fallback.clear();
+                            }
+                            envelope.remote_ip = remote_ip;
+                            envelope.local_ip = source_ip_for(remote_ip).unwrap_or(no_ip);
+                        }
+                    }
+
                     // Connect
                     let time = Instant::now();
                     let conn_timeout = server
                         .eval_if(&queue_config.timeout.connect, &envelope, message.span_id)
                         .await
                         .unwrap_or_else(|| Duration::from_secs(5 * 60));
-                    let mut smtp_client = match if let Some(ip_addr) = source_ip {
-                        SmtpClient::connect_using(
-                            ip_addr,
-                            SocketAddr::new(remote_ip, remote_host.port()),
-                            conn_timeout,
-                            span_id,
-                        )
-                        .await
-                    } else {
-                        SmtpClient::connect(
-                            SocketAddr::new(remote_ip, remote_host.port()),
-                            conn_timeout,
-                            span_id,
-                        )
-                        .await
-                    } {
-                        Ok(smtp_client) => {
-                            trc::event!(
-                                Delivery(DeliveryEvent::Connect),
-                                SpanId = message.span_id,
-                                Domain = domain.domain.clone(),
-                                Hostname = envelope.mx.to_string(),
-                                LocalIp = source_ip.unwrap_or(no_ip),
-                                RemoteIp = remote_ip,
-                                RemotePort = remote_host.port(),
-                                Elapsed = time.elapsed(),
-                            );
+                    let port = remote_host.port();
+                    let attempts = happy_eyeballs(
+                        remote_ip,
+                        fallback_ip,
+                        happy_eyeballs_delay,
+                        message.span_id,
+                        |remote_ip| {
+                            let source_ip = source_ip_for(remote_ip);
+                            let domain = &domain.domain;
+                            async move {
+                                if let Some(ip_addr) = source_ip {
+                                    match SmtpClient::connect_using(
+                                        ip_addr,
+                                        SocketAddr::new(remote_ip, port),
+                                        conn_timeout,
+                                        span_id,
+                                    )
+                                    .await
+                                    {
+                                        Err(mail_send::Error::Io(err))
+                                            if err.kind()
+                                                == std::io::ErrorKind::AddrNotAvailable =>
+                                        {
+                                            trc::event!(
+                                                Delivery(DeliveryEvent::SourceIpBindFailed),
+                                                SpanId = span_id,
+                                                Domain = domain.clone(),
+                                                LocalIp = ip_addr,
+                                                RemoteIp = remote_ip,
+                                                Id = source_ip_rule.map(|rule| rule.id.clone()),
+                                                Reason = err.to_string(),
+                                            );
+
+                                            // Let the operating system choose the source address
+                                            if queue_config.source_ip.fallback
+                                                == SourceIpFallback::Any
+                                            {
+                                                SmtpClient::connect(
+                                                    SocketAddr::new(remote_ip, port),
+                                                    conn_timeout,
+                                                    span_id,
+                                                )
+                                                .await
+                                            } else {
+                                                Err(mail_send::Error::Io(err))
+                                            }
+                                        }
+                                        result => result,
+                                    }
+                                } else {
+                                    SmtpClient::connect(
+                                        SocketAddr::new(remote_ip, port),
+                                        conn_timeout,
+                                        span_id,
+                                    )
+                                    .await
+                                }
+                            }
+                        },
+                    )
+                    .await;
 
-                            smtp_client
+                    let mut connection = None;
+                    for (remote_ip, result) in attempts {
+                        if remote_ip != envelope.remote_ip {
+                            raced_ips.push(remote_ip);
                         }
-                        Err(err) => {
-                            trc::event!(
-                                Delivery(DeliveryEvent::ConnectError),
-                                SpanId = message.span_id,
-                                Domain = domain.domain.clone(),
-                                Hostname = envelope.mx.to_string(),
-                                LocalIp = source_ip,
-                                RemoteIp = remote_ip,
-                                RemotePort = remote_host.port(),
-                                CausedBy = from_mail_send_error(&err),
-                                Elapsed = time.elapsed(),
-                            );
+                        let source_ip = source_ip_for(remote_ip);
+                        if remote_ip.is_ipv6() {
+                            server
+                                .record_ipv6_connect(
+                                    &domain.domain,
+                                    result.is_ok(),
+                                    message.span_id,
+                                )
+                                .await;
+                        }
+
+                        match result {
+                            Ok(smtp_client) => {
+                                // Keep track of the address used, as the bind could have fallen back
+                                let local_ip =
+                                    smtp_client.stream.local_addr().map(|addr| addr.ip()).ok();
+                                if source_ip.is_some() {
+                                    source_ips.push(SourceIp {
+                                        domain_idx,
+                                        ip: local_ip.unwrap_or(no_ip),
+                                    });
+                                }
+                                connect_attempts.push(ConnectAttempt {
+                                    domain_idx,
+                                    time: now(),
+                                    remote_ip,
+                                    local_ip,
+                                    success: true,
+                                });
+
+                                trc::event!(
+                                    Delivery(DeliveryEvent::Connect),
+                                    SpanId = message.span_id,
+                                    Domain = domain.domain.clone(),
+                                    Hostname = envelope.mx.to_string(),
+                                    LocalIp = local_ip,
+                                    RemoteIp = remote_ip,
+                                    RemotePort = port,
+                                    Elapsed = time.elapsed(),
+                                );
 
-                            last_status = Status::from_smtp_error(envelope.mx, "", err);
-                            continue 'next_ip;
+                                connection = Some((remote_ip, source_ip, local_ip, smtp_client));
+                            }
+                            Err(err) => {
+                                connect_attempts.push(ConnectAttempt {
+                                    domain_idx,
+                                    time: now(),
+                                    remote_ip,
+                                    local_ip: source_ip,
+                                    success: false,
+                                });
+
+                                trc::event!(
+                                    Delivery(DeliveryEvent::ConnectError),
+                                    SpanId = message.span_id,
+                                    Domain = domain.domain.clone(),
+                                    Hostname = envelope.mx.to_string(),
+                                    LocalIp = source_ip,
+                                    RemoteIp = remote_ip,
+                                    RemotePort = port,
+                                    CausedBy = from_mail_send_error(&err),
+                                    Elapsed = time.elapsed(),
+                                );
+
+                                last_status = Status::from_smtp_error(envelope.mx, "", err);
+                            }
                         }
+                    }
+                    let Some((remote_ip, source_ip, local_ip, mut smtp_client)) = connection else {
+                        continue 'next_ip;
+                    };
+                    if remote_ip != envelope.remote_ip {
+                        envelope.remote_ip = remote_ip;
+                        std::mem::swap(&mut in_flight_host, &mut in_flight_fallback);
+                    }
+                    drop(in_flight_fallback);
+
+                    // The bind could have fallen back to the address chosen by the OS
+                    envelope.local_ip = match source_ip {
+                        Some(source_ip) if local_ip == Some(source_ip) => source_ip,
+                        _ => no_ip,
                     };
 
                     // Obtain session parameters
@@ -1316,9 +1573,30 @@ impl DeliveryAttempt {
         }
         message.recipients = recipients;
 
+        // Record the source addresses used for each domain
+        for source_ip in source_ips {
+            message.set_source_ip(source_ip);
+        }
+        for attempt in connect_attempts {
+            message.add_connect_attempt(attempt);
+        }
+
+        // Apply per deferral class schedules
+        for domain_idx in attempted {
+            message.apply_retry_policy(domain_idx, &queue_config.retry_policy);
+        }
+
         // Send Delivery Status Notifications
         server.send_dsn(&mut message).await;
 
+        // Report recipient statuses to the submitting account
+        message
+            .notify_submission(
+                &server,
+                on_hold.is_empty() && message.next_event().is_none(),
+            )
+            .await;
+
         // Notify queue manager
         let result = if !on_hold.is_empty() {
             // Save changes to disk
@@ -1374,6 +1652,28 @@ impl DeliveryAttempt {
 }
 
 impl Message {
+    /// Sends the current recipient statuses of messages submitted over JMAP
+    pub async fn notify_submission(&self, server: &Server, is_final: bool) {
+        if (self.flags & MAIL_SUBMISSION) != 0
+            && server
+                .inner
+                .ipc
+                .delivery_tx
+                .send(ipc::DeliveryEvent::SubmissionStatus(
+                    self.submission_status(is_final),
+                ))
+                .await
+                .is_err()
+        {
+            trc::event!(
+                Server(ServerEvent::ThreadError),
+                Reason = "Channel closed.",
+                CausedBy = trc::location!(),
+                SpanId = self.span_id
+            );
+        }
+    }
+
     /// Marks as failed all domains that reached their expiration time
     pub fn has_pending_delivery(&mut self) -> bool {
         let now = now();
@@ -4,9 +4,13 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use common::ipc::{DeliveryEvent, DeliveryResult, IngestMessage};
+use common::{
+    ipc::{DeliveryEvent, DeliveryResult, IngestMessage},
+    telemetry::volume::VolumeEvent,
+    Server,
+};
 use smtp_proto::Response;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::oneshot;
 use trc::ServerEvent;
 
 use crate::queue::{
@@ -17,7 +21,7 @@ impl Message {
     pub async fn deliver_local(
         &self,
         recipients: impl Iterator<Item = &mut Recipient>,
-        delivery_tx: &mpsc::Sender<DeliveryEvent>,
+        server: &Server,
     ) -> Status<(), Error> {
         // Prepare recipients list
         let mut total_rcpt = 0;
@@ -41,7 +45,10 @@ impl Message {
         let (result_tx, result_rx) = oneshot::channel();
 
         // Deliver message to JMAP server
-        let delivery_result = match delivery_tx
+        let delivery_result = match server
+            .inner
+            .ipc
+            .delivery_tx
             .send(DeliveryEvent::Ingest {
                 message: IngestMessage {
                     sender_address: self.return_path_lcase.clone(),
@@ -83,8 +90,13 @@ impl Message {
         // Process delivery results
         for (rcpt, result) in pending_recipients.into_iter().zip(delivery_result) {
             rcpt.flags |= RCPT_STATUS_CHANGED;
+            let domain = self
+                .domains
+                .get(rcpt.domain_idx)
+                .map_or("", |domain| domain.domain.as_str());
             match result {
                 DeliveryResult::Success => {
+                    server.record_volume(domain, VolumeEvent::Received, self.size as u64);
                     rcpt.status = Status::Completed(HostResponse {
                         hostname: "localhost".to_string(),
                         response: Response {
@@ -109,6 +121,7 @@ impl Message {
                     });
                 }
                 DeliveryResult::PermanentFailure { code, reason } => {
+                    server.record_volume(domain, VolumeEvent::Rejected, self.size as u64);
                     total_completed += 1;
                     rcpt.status = Status::PermanentFailure(HostResponse {
                         hostname: ErrorDetails {
@@ -11,13 +11,17 @@ use std::{
 };
 
 use common::{
-    expr::{functions::ResolveVariable, V_MX},
+    config::smtp::queue::{SourceIpFallback, SourceIpRule},
+    expr::{functions::ResolveVariable, V_MX, V_RECIPIENT_DOMAIN},
     Server,
 };
+use directory::backend::internal::manage::ManageDirectory;
 use mail_auth::{IpLookupStrategy, MX};
 use rand::{seq::SliceRandom, Rng};
+use store::write::now;
+use trc::{AddContext, DeliveryEvent};
 
-use crate::queue::{Error, ErrorDetails, Status};
+use crate::queue::{Error, ErrorDetails, Message, Status};
 
 use super::NextHop;
 
@@ -40,8 +44,30 @@ pub trait DnsLookup: Sync + Send {
         remote_host: &NextHop<'_>,
         envelope: &impl ResolveVariable,
         max_multihomed: usize,
+        source_ip_rule: Option<&SourceIpRule>,
         session_id: u64,
     ) -> impl Future<Output = Result<IpLookupResult, Status<(), Error>>> + Send;
+
+    fn source_ip_rule<'x>(
+        &'x self,
+        message: &Message,
+        rcpt_domain: &str,
+        tenant: Option<&str>,
+    ) -> Option<&'x SourceIpRule>;
+
+    fn sender_tenant_name(
+        &self,
+        message: &Message,
+    ) -> impl Future<Output = trc::Result<Option<String>>> + Send;
+
+    fn is_ipv6_demoted(&self, domain: &str) -> impl Future<Output = bool> + Send;
+
+    fn record_ipv6_connect(
+        &self,
+        domain: &str,
+        success: bool,
+        session_id: u64,
+    ) -> impl Future<Output = ()> + Send;
 }
 
 impl DnsLookup for Server {
@@ -105,16 +131,15 @@ impl DnsLookup for Server {
         remote_host: &NextHop<'_>,
         envelope: &impl ResolveVariable,
         max_multihomed: usize,
+        source_ip_rule: Option<&SourceIpRule>,
         session_id: u64,
     ) -> Result<IpLookupResult, Status<(), Error>> {
-        let remote_ips = self
-            .ip_lookup(
-                remote_host.fqdn_hostname().as_ref(),
-                self.eval_if(&self.core.smtp.queue.ip_strategy, envelope, session_id)
-                    .await
-                    .unwrap_or(IpLookupStrategy::Ipv4thenIpv6),
-                max_multihomed,
-            )
+        let strategy = self
+            .eval_if(&self.core.smtp.queue.ip_strategy, envelope, session_id)
+            .await
+            .unwrap_or(IpLookupStrategy::Ipv4thenIpv6);
+        let mut remote_ips = self
+            .ip_lookup(remote_host.fqdn_hostname().as_ref(), strategy, usize::MAX)
             .await
             .map_err(|err| {
                 if let mail_auth::Error::DnsRecordNotFound(_) = &err {
@@ -130,6 +155,46 @@ impl DnsLookup for Server {
                 }
             })?;
 
+        // Pinned source addresses of a single family restrict the remote addresses
+        if let Some(rule) = source_ip_rule {
+            let pinned_ipv6 = match (rule.ipv4.is_empty(), rule.ipv6.is_empty()) {
+                (false, true) => Some(false),
+                (true, false) => Some(true),
+                _ => None,
+            };
+            if let Some(pinned_ipv6) = pinned_ipv6 {
+                if remote_ips.iter().any(|ip| ip.is_ipv6() == pinned_ipv6) {
+                    remote_ips.retain(|ip| ip.is_ipv6() == pinned_ipv6);
+                } else if self.core.smtp.queue.source_ip.fallback == SourceIpFallback::Fail {
+                    return Err(Status::TemporaryFailure(Error::ConnectionError(
+                        ErrorDetails {
+                            entity: remote_host.hostname().to_string(),
+                            details: format!(
+                                "no {} address found for pinned source addresses of rule {:?}",
+                                if pinned_ipv6 { "IPv6" } else { "IPv4" },
+                                rule.id
+                            ),
+                        },
+                    )));
+                }
+            }
+        }
+
+        // Alternate address families so that a failing path falls back quickly (RFC 8305)
+        if matches!(
+            strategy,
+            IpLookupStrategy::Ipv4thenIpv6 | IpLookupStrategy::Ipv6thenIpv4
+        ) {
+            let rcpt_domain = envelope
+                .resolve_variable(V_RECIPIENT_DOMAIN)
+                .to_string()
+                .into_owned();
+            let ipv6_first = matches!(strategy, IpLookupStrategy::Ipv6thenIpv4)
+                && !self.is_ipv6_demoted(&rcpt_domain).await;
+            remote_ips = interleave_families(remote_ips, ipv6_first);
+        }
+        remote_ips.truncate(max_multihomed);
+
         if !remote_ips.is_empty() {
             let mut result = IpLookupResult {
                 source_ipv4: None,
@@ -179,6 +244,16 @@ impl DnsLookup for Server {
                 std::cmp::Ordering::Less => (),
             }
 
+            // Source IP rules take precedence over the expressions
+            if let Some(rule) = source_ip_rule {
+                if let Some(ip) = rule.ipv4.choose(&mut rand::thread_rng()) {
+                    result.source_ipv4 = Some(*ip);
+                }
+                if let Some(ip) = rule.ipv6.choose(&mut rand::thread_rng()) {
+                    result.source_ipv6 = Some(*ip);
+                }
+            }
+
             Ok(result)
         } else {
             Err(Status::TemporaryFailure(Error::DnsError(format!(
@@ -187,6 +262,132 @@ impl DnsLookup for Server {
             ))))
         }
     }
+
+    fn source_ip_rule<'x>(
+        &'x self,
+        message: &Message,
+        rcpt_domain: &str,
+        tenant: Option<&str>,
+    ) -> Option<&'x SourceIpRule> {
+        self.core
+            .smtp
+            .queue
+            .source_ip
+            .rules
+            .iter()
+            .find(|rule| rule.matches(tenant, &message.return_path_domain, rcpt_domain))
+    }
+
+    async fn is_ipv6_demoted(&self, domain: &str) -> bool {
+        if self.core.smtp.queue.ipv6_demotion.is_none() {
+            return false;
+        }
+
+        match self
+            .lookup_store()
+            .key_exists(format!("v6-demoted:{domain}").into_bytes())
+            .await
+        {
+            Ok(is_demoted) => is_demoted,
+            Err(err) => {
+                trc::error!(err
+                    .details("Failed to obtain IPv6 demotion status")
+                    .caused_by(trc::location!()));
+                false
+            }
+        }
+    }
+
+    async fn record_ipv6_connect(&self, domain: &str, success: bool, session_id: u64) {
+        let Some(demotion) = &self.core.smtp.queue.ipv6_demotion else {
+            return;
+        };
+        let key = format!("v6-failures:{domain}").into_bytes();
+        let result = if success {
+            self.lookup_store().counter_delete(key).await
+        } else {
+            match self
+                .lookup_store()
+                .counter_incr(key.clone(), 1, demotion.duration.as_secs().into(), true)
+                .await
+            {
+                Ok(failures) if failures >= demotion.failures as i64 => {
+                    trc::event!(
+                        Delivery(DeliveryEvent::Ipv6Demoted),
+                        SpanId = session_id,
+                        Domain = domain.to_string(),
+                        Total = failures,
+                        Expires = trc::Value::Timestamp(now() + demotion.duration.as_secs()),
+                    );
+
+                    // Start counting again once the demotion expires
+                    match self
+                        .lookup_store()
+                        .key_set(
+                            format!("v6-demoted:{domain}").into_bytes(),
+                            vec![],
+                            demotion.duration.as_secs().into(),
+                        )
+                        .await
+                    {
+                        Ok(_) => self.lookup_store().counter_delete(key).await,
+                        Err(err) => Err(err),
+                    }
+                }
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            }
+        };
+
+        if let Err(err) = result {
+            trc::error!(err
+                .span_id(session_id)
+                .details("Failed to update IPv6 demotion status")
+                .caused_by(trc::location!()));
+        }
+    }
+
+    async fn sender_tenant_name(&self, message: &Message) -> trc::Result<Option<String>> {
+        if (!self.core.smtp.queue.source_ip.has_tenant_rules()
+            && self.core.smtp.queue.sending_policies.is_empty())
+            || message.return_path.is_empty()
+        {
+            return Ok(None);
+        }
+
+        match self
+            .tenant_by_address(&message.return_path_lcase)
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(tenant_id) => self
+                .store()
+                .get_principal(tenant_id)
+                .await
+                .caused_by(trc::location!())
+                .map(|tenant| tenant.map(|tenant| tenant.name().to_string())),
+            None => Ok(None),
+        }
+    }
+}
+
+fn interleave_families(remote_ips: Vec<IpAddr>, ipv6_first: bool) -> Vec<IpAddr> {
+    let (preferred, other): (Vec<_>, Vec<_>) = remote_ips
+        .into_iter()
+        .partition(|ip| ip.is_ipv6() == ipv6_first);
+    let mut result = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (first, second) => {
+                result.extend(first);
+                result.extend(second);
+            }
+        }
+    }
+    result
 }
 
 pub trait ToNextHop {
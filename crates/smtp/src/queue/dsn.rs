@@ -4,12 +4,12 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use common::templates::{TemplateKind, TemplateVariable};
 use common::Server;
 use mail_builder::headers::content_type::ContentType;
 use mail_builder::headers::HeaderType;
 use mail_builder::mime::{make_boundary, BodyPart, MimePart};
 use mail_builder::MessageBuilder;
-use mail_parser::DateTime;
 use smtp_proto::{
     Response, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_NEVER, RCPT_NOTIFY_SUCCESS,
 };
@@ -17,6 +17,7 @@ use std::fmt::Write;
 use std::future::Future;
 use std::time::Duration;
 use store::write::now;
+use utils::locale::{Locale, Localization};
 
 use crate::outbound::client::from_error_status;
 use crate::reporting::SmtpReporting;
@@ -37,6 +38,32 @@ impl SendDsn for Server {
         // Send DSN events
         self.log_dsn(message).await;
 
+        // Count bounces towards the reputation of local senders, recipients
+        // are flagged once their DSN is built so they are only counted once
+        if self.core.sender_reputation.enable && !message.return_path.is_empty() {
+            let bounces = message
+                .recipients
+                .iter()
+                .filter(|rcpt| {
+                    !rcpt.has_flag(RCPT_DSN_SENT | RCPT_NOTIFY_NEVER)
+                        && (matches!(rcpt.status, Status::PermanentFailure(_))
+                            || (matches!(rcpt.status, Status::Scheduled)
+                                && matches!(
+                                    message.domains[rcpt.domain_idx].status,
+                                    Status::PermanentFailure(_)
+                                )))
+                })
+                .count() as u64;
+            if let Err(err) = self
+                .record_bounces(&message.return_path_lcase, bounces)
+                .await
+            {
+                trc::error!(err
+                    .span_id(message.span_id)
+                    .details("Failed to update sender reputation"));
+            }
+        }
+
         if !message.return_path.is_empty() {
             // Build DSN
             if let Some(dsn) = message.build_dsn(self).await {
@@ -164,6 +191,18 @@ impl Message {
         let config = &server.core.smtp.queue;
         let now = now();
 
+        // Reports are written in the language and time zone of local senders
+        let localization = server
+            .localization_by_address(&self.return_path_lcase)
+            .await
+            .unwrap_or_else(|err| {
+                trc::error!(err
+                    .span_id(self.span_id)
+                    .details("Failed to resolve sender locale")
+                    .caused_by(trc::location!()));
+                server.core.localization
+            });
+
         let mut txt_success = String::new();
         let mut txt_delay = String::new();
         let mut txt_failed = String::new();
@@ -189,7 +228,7 @@ impl Message {
                 {
                     rcpt.write_dsn(&mut dsn);
                     rcpt.status.write_dsn(&mut dsn);
-                    domain.write_dsn_will_retry_until(&mut dsn);
+                    domain.write_dsn_will_retry_until(&mut dsn, &localization);
                     response.write_dsn_text(&rcpt.address, &mut txt_delay);
                 }
                 Status::PermanentFailure(response) => {
@@ -218,7 +257,7 @@ impl Message {
                         {
                             rcpt.write_dsn(&mut dsn);
                             domain.status.write_dsn(&mut dsn);
-                            domain.write_dsn_will_retry_until(&mut dsn);
+                            domain.write_dsn_will_retry_until(&mut dsn, &localization);
                             err.write_dsn_text(&rcpt.address, &domain.domain, &mut txt_delay);
                         }
                         Status::Scheduled
@@ -227,7 +266,7 @@ impl Message {
                             // This case should not happen under normal circumstances
                             rcpt.write_dsn(&mut dsn);
                             domain.status.write_dsn(&mut dsn);
-                            domain.write_dsn_will_retry_until(&mut dsn);
+                            domain.write_dsn_will_retry_until(&mut dsn, &localization);
                             Error::ConcurrencyLimited.write_dsn_text(
                                 &rcpt.address,
                                 &domain.domain,
@@ -257,58 +296,44 @@ impl Message {
         let has_delay = !txt_delay.is_empty();
         let has_failure = !txt_failed.is_empty();
 
-        let mut txt = String::with_capacity(txt_len + 128);
-        let (subject, is_mixed) = if has_success && !has_delay && !has_failure {
-            txt.push_str(
-                "Your message has been successfully delivered to the following recipients:\r\n\r\n",
-            );
-            ("Successfully delivered message", false)
+        let [heading_success, heading_delay, heading_failure] =
+            section_headings(localization.locale);
+        let mut sections = String::with_capacity(txt_len + 128);
+        let (kind, is_mixed) = if has_success && !has_delay && !has_failure {
+            (TemplateKind::DsnSuccess, false)
         } else if has_delay && !has_success && !has_failure {
-            txt.push_str("There was a temporary problem delivering your message to the following recipients:\r\n\r\n");
-            ("Warning: Delay in message delivery", false)
+            (TemplateKind::DsnDelay, false)
         } else if has_failure && !has_success && !has_delay {
-            txt.push_str(
-                "Your message could not be delivered to the following recipients:\r\n\r\n",
-            );
-            ("Failed to deliver message", false)
+            (TemplateKind::DsnFailure, false)
         } else if has_success {
-            txt.push_str("Your message has been partially delivered:\r\n\r\n");
-            ("Partially delivered message", true)
+            (TemplateKind::DsnPartial, true)
         } else {
-            txt.push_str("Your message could not be delivered to some recipients:\r\n\r\n");
-            (
-                "Warning: Temporary and permanent failures during message delivery",
-                true,
-            )
+            (TemplateKind::DsnMixed, true)
         };
 
         if has_success {
             if is_mixed {
-                txt.push_str(
-                    "    ----- Delivery to the following addresses was successful -----\r\n",
-                );
+                let _ = write!(sections, "    ----- {heading_success} -----\r\n");
             }
 
-            txt.push_str(&txt_success);
-            txt.push_str("\r\n");
+            sections.push_str(&txt_success);
+            sections.push_str("\r\n");
         }
 
         if has_delay {
             if is_mixed {
-                txt.push_str(
-                    "    ----- There was a temporary problem delivering to these addresses -----\r\n",
-                );
+                let _ = write!(sections, "    ----- {heading_delay} -----\r\n");
             }
-            txt.push_str(&txt_delay);
-            txt.push_str("\r\n");
+            sections.push_str(&txt_delay);
+            sections.push_str("\r\n");
         }
 
         if has_failure {
             if is_mixed {
-                txt.push_str("    ----- Delivery to the following addresses failed -----\r\n");
+                let _ = write!(sections, "    ----- {heading_failure} -----\r\n");
             }
-            txt.push_str(&txt_failed);
-            txt.push_str("\r\n");
+            sections.push_str(&txt_failed);
+            sections.push_str("\r\n");
         }
 
         // Update next delay notification time
@@ -344,10 +369,6 @@ impl Message {
         }
 
         // Obtain hostname and sender addresses
-        let from_name = server
-            .eval_if(&config.dsn.name, self, self.span_id)
-            .await
-            .unwrap_or_else(|| String::from("Mail Delivery Subsystem"));
         let from_addr = server
             .eval_if(&config.dsn.address, self, self.span_id)
             .await
@@ -357,9 +378,36 @@ impl Message {
             .await
             .unwrap_or_else(|| String::from("localhost"));
 
+        // Render the template of the sender's tenant
+        let tenant_id = server
+            .tenant_by_address(&self.return_path_lcase)
+            .await
+            .unwrap_or_else(|err| {
+                trc::error!(err
+                    .span_id(self.span_id)
+                    .details("Failed to resolve tenant")
+                    .caused_by(trc::location!()));
+                None
+            });
+        let template = server
+            .localized_template(tenant_id, kind, localization.locale)
+            .await
+            .render(&[
+                (TemplateVariable::Sender, self.return_path.as_str()),
+                (TemplateVariable::Hostname, reporting_mta.as_str()),
+            ]);
+        let from_name = match template.from_name {
+            Some(from_name) => from_name,
+            None => server
+                .eval_if(&config.dsn.name, self, self.span_id)
+                .await
+                .unwrap_or_else(|| String::from("Mail Delivery Subsystem")),
+        };
+        let txt = format!("{}\r\n\r\n{sections}", template.body.trim_end());
+
         // Prepare DSN
         let mut dsn_header = String::with_capacity(dsn.len() + 128);
-        self.write_dsn_headers(&mut dsn_header, &reporting_mta);
+        self.write_dsn_headers(&mut dsn_header, &reporting_mta, &localization);
         let dsn = dsn_header + dsn.as_str();
 
         // Fetch up to 1024 bytes of message headers
@@ -419,7 +467,7 @@ impl Message {
             .header("To", HeaderType::Text(self.return_path.as_str().into()))
             .header("Auto-Submitted", HeaderType::Text("auto-generated".into()))
             .message_id(format!("<{}@{}>", make_boundary("."), reporting_mta))
-            .subject(subject)
+            .subject(template.subject)
             .body(MimePart::new(
                 ContentType::new("multipart/report").attribute("report-type", "delivery-status"),
                 BodyPart::Multipart(vec![
@@ -482,6 +530,36 @@ impl Message {
     }
 }
 
+fn section_headings(locale: Locale) -> [&'static str; 3] {
+    match locale {
+        Locale::En => [
+            "Delivery to the following addresses was successful",
+            "There was a temporary problem delivering to these addresses",
+            "Delivery to the following addresses failed",
+        ],
+        Locale::De => [
+            "Die Zustellung an die folgenden Adressen war erfolgreich",
+            "Bei der Zustellung an diese Adressen ist ein vorübergehendes Problem aufgetreten",
+            "Die Zustellung an die folgenden Adressen ist fehlgeschlagen",
+        ],
+        Locale::Es => [
+            "La entrega a las siguientes direcciones se realizó correctamente",
+            "Se produjo un problema temporal al entregar a estas direcciones",
+            "La entrega a las siguientes direcciones ha fallado",
+        ],
+        Locale::Fr => [
+            "La distribution aux adresses suivantes a réussi",
+            "Un problème temporaire est survenu lors de la distribution à ces adresses",
+            "La distribution aux adresses suivantes a échoué",
+        ],
+        Locale::Pt => [
+            "A entrega aos seguintes endereços foi bem-sucedida",
+            "Ocorreu um problema temporário na entrega a estes endereços",
+            "A entrega aos seguintes endereços falhou",
+        ],
+    }
+}
+
 impl HostResponse<String> {
     fn write_dsn_text(&self, addr: &str, dsn: &mut String) {
         let _ = write!(
@@ -572,10 +650,15 @@ impl Error {
 }
 
 impl Message {
-    fn write_dsn_headers(&self, dsn: &mut String, reporting_mta: &str) {
+    fn write_dsn_headers(
+        &self,
+        dsn: &mut String,
+        reporting_mta: &str,
+        localization: &Localization,
+    ) {
         let _ = write!(dsn, "Reporting-MTA: dns;{reporting_mta}\r\n");
         dsn.push_str("Arrival-Date: ");
-        dsn.push_str(&DateTime::from_timestamp(self.created as i64).to_rfc822());
+        dsn.push_str(&localization.format_rfc822(self.created as i64));
         dsn.push_str("\r\n");
         if let Some(env_id) = &self.env_id {
             let _ = write!(dsn, "Original-Envelope-Id: {env_id}\r\n");
@@ -594,11 +677,11 @@ impl Recipient {
 }
 
 impl Domain {
-    fn write_dsn_will_retry_until(&self, dsn: &mut String) {
+    fn write_dsn_will_retry_until(&self, dsn: &mut String, localization: &Localization) {
         let now = now();
         if self.expires > now {
             dsn.push_str("Will-Retry-Until: ");
-            dsn.push_str(&DateTime::from_timestamp(self.expires as i64).to_rfc822());
+            dsn.push_str(&localization.format_rfc822(self.expires as i64));
             dsn.push_str("\r\n");
         }
     }
@@ -5,6 +5,7 @@
  */
 
 use std::{
+    net::IpAddr,
     sync::{atomic::Ordering, Arc},
     time::Duration,
 };
@@ -17,10 +18,14 @@ use common::{
 use store::write::now;
 use tokio::sync::mpsc;
 
-use super::{spool::SmtpSpool, DeliveryAttempt, Message, Status};
+use super::{
+    spool::SmtpSpool, ConnectAttempt, DeliveryAttempt, Error, ErrorDetails, Hold, HoldReason,
+    HostResponse, Message, SourceIp, Status, MAIL_HOLD_RELEASED,
+};
 
 pub(crate) const SHORT_WAIT: Duration = Duration::from_millis(1);
 pub(crate) const LONG_WAIT: Duration = Duration::from_secs(86400 * 365);
+const MAX_CONNECT_ATTEMPTS: usize = 10;
 
 pub struct Queue {
     pub core: Arc<Inner>,
@@ -220,6 +225,162 @@ impl Message {
 
         next_event
     }
+
+    /// Records the last source address used to connect on behalf of a domain.
+    pub fn set_source_ip(&mut self, source_ip: SourceIp) {
+        match self
+            .source_ips
+            .iter_mut()
+            .find(|item| item.domain_idx == source_ip.domain_idx)
+        {
+            Some(item) => item.ip = source_ip.ip,
+            None => self.source_ips.push(source_ip),
+        }
+    }
+
+    /// Appends a connection attempt to the delivery history, keeping only the
+    /// most recent attempts for each domain.
+    pub fn add_connect_attempt(&mut self, attempt: ConnectAttempt) {
+        if self
+            .connect_attempts
+            .iter()
+            .filter(|item| item.domain_idx == attempt.domain_idx)
+            .count()
+            >= MAX_CONNECT_ATTEMPTS
+        {
+            if let Some(pos) = self
+                .connect_attempts
+                .iter()
+                .position(|item| item.domain_idx == attempt.domain_idx)
+            {
+                self.connect_attempts.remove(pos);
+            }
+        }
+        self.connect_attempts.push(attempt);
+    }
+
+    pub fn source_ip(&self, domain_idx: usize) -> Option<IpAddr> {
+        self.source_ips
+            .iter()
+            .find(|item| item.domain_idx == domain_idx)
+            .map(|item| item.ip)
+    }
+
+    pub fn is_held_for_review(&self) -> bool {
+        matches!(
+            self.hold,
+            Some(Hold {
+                reason: HoldReason::Compliance,
+                ..
+            })
+        )
+    }
+
+    /// Returns the mailing list whose moderators have to approve the message.
+    pub fn moderated_list(&self) -> Option<u32> {
+        match self.hold {
+            Some(Hold {
+                reason: HoldReason::Moderation { list_id },
+                ..
+            }) => Some(list_id),
+            _ => None,
+        }
+    }
+
+    /// Parks the message for compliance review, it will not be scheduled
+    /// again until a reviewer releases or rejects it.
+    pub fn hold_for_review(&mut self, tenant: &str) {
+        self.hold = Some(Hold {
+            tenant: tenant.to_string(),
+            reason: HoldReason::Compliance,
+            since: now(),
+        });
+    }
+
+    /// Delays all pending deliveries until the tenant's next sending window.
+    pub fn hold_until(&mut self, tenant: &str, until: u64) {
+        for domain in &mut self.domains {
+            if matches!(
+                domain.status,
+                Status::Scheduled | Status::TemporaryFailure(_)
+            ) && domain.retry.due < until
+            {
+                domain.retry.due = until;
+            }
+        }
+        let reason = HoldReason::SendingWindow { until };
+        if self.hold.as_ref().is_none_or(|hold| hold.reason != reason) {
+            self.hold = Some(Hold {
+                tenant: tenant.to_string(),
+                reason,
+                since: now(),
+            });
+        }
+    }
+
+    /// Releases a message held for review or moderation. The time spent on
+    /// hold does not count towards the expiration and delay notification
+    /// schedules.
+    pub fn release_hold(&mut self) -> bool {
+        let is_review = self.is_held_for_review();
+        if !is_review && self.moderated_list().is_none() {
+            return false;
+        }
+        let now = now();
+        let held_for = now.saturating_sub(self.hold.take().unwrap().since);
+        for domain in &mut self.domains {
+            if matches!(
+                domain.status,
+                Status::Scheduled | Status::TemporaryFailure(_)
+            ) {
+                domain.retry.due = now;
+                domain.notify.due = domain.notify.due.saturating_add(held_for);
+                domain.expires = domain.expires.saturating_add(held_for);
+            }
+        }
+        // Approved list posts are still subject to compliance review
+        if is_review {
+            self.flags |= MAIL_HOLD_RELEASED;
+        }
+        true
+    }
+
+    /// Fails all pending deliveries of a message held for review or
+    /// moderation, the sender is notified with a DSN on the next queue run.
+    pub fn reject_hold(&mut self, reason: &str) -> bool {
+        if !self.is_held_for_review() && self.moderated_list().is_none() {
+            return false;
+        }
+        self.hold = None;
+        let response = HostResponse {
+            hostname: ErrorDetails {
+                entity: "localhost".to_string(),
+                details: String::new(),
+            },
+            response: smtp_proto::Response {
+                code: 550,
+                esc: [5, 7, 1],
+                message: reason.to_string(),
+            },
+        };
+        let now = now();
+        for domain in &mut self.domains {
+            if matches!(
+                domain.status,
+                Status::Scheduled | Status::TemporaryFailure(_)
+            ) {
+                domain.status =
+                    Status::PermanentFailure(Error::UnexpectedResponse(response.clone()));
+                domain.retry.due = now;
+            }
+        }
+        for rcpt in &mut self.recipients {
+            if matches!(rcpt.status, Status::Scheduled | Status::TemporaryFailure(_)) {
+                rcpt.status = Status::PermanentFailure(response.clone());
+            }
+        }
+        true
+    }
 }
 
 pub trait SpawnQueue {
@@ -11,8 +11,9 @@ use std::{
 };
 
 use common::{
+    config::smtp::queue::DeferralClass,
     expr::{self, functions::ResolveVariable, *},
-    ipc::QueueEventLock,
+    ipc::{Delivered, QueueEventLock, SubmissionRecipient, SubmissionStatus},
     listener::limiter::InFlight,
 };
 use serde::{Deserialize, Serialize};
@@ -23,6 +24,8 @@ use utils::BlobHash;
 pub mod dsn;
 pub mod manager;
 pub mod quota;
+pub mod retry;
+pub mod serialize;
 pub mod spool;
 pub mod throttle;
 
@@ -61,11 +64,56 @@ pub struct Message {
 
     pub size: usize,
     pub quota_keys: Vec<QuotaKey>,
+    pub deferrals: Vec<Deferral>,
+    pub source_ips: Vec<SourceIp>,
+    pub hold: Option<Hold>,
+    pub connect_attempts: Vec<ConnectAttempt>,
 
     #[serde(skip)]
     pub span_id: u64,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deferral {
+    pub domain_idx: usize,
+    pub class: DeferralClass,
+    pub attempt: u32,
+    pub expires: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceIp {
+    pub domain_idx: usize,
+    pub ip: IpAddr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectAttempt {
+    pub domain_idx: usize,
+    pub time: u64,
+    pub remote_ip: IpAddr,
+    pub local_ip: Option<IpAddr>,
+    pub success: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hold {
+    pub tenant: String,
+    pub reason: HoldReason,
+    pub since: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HoldReason {
+    // Parked until released or rejected by a reviewer
+    Compliance,
+    // Delayed until the tenant's next sending window opens
+    SendingWindow { until: u64 },
+    // Posted to a moderated list, parked until a moderator approves it
+    Moderation { list_id: u32 },
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum QuotaKey {
     Size { key: Vec<u8>, id: u64 },
@@ -94,6 +142,9 @@ pub struct Recipient {
 pub const RCPT_DSN_SENT: u64 = 1 << 32;
 pub const RCPT_STATUS_CHANGED: u64 = 2 << 32;
 
+pub const MAIL_HOLD_RELEASED: u64 = 1 << 32;
+pub const MAIL_SUBMISSION: u64 = 2 << 32;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Status<T, E> {
     #[serde(rename = "scheduled")]
@@ -299,6 +350,62 @@ impl ResolveVariable for Message {
     }
 }
 
+impl Message {
+    pub fn submission_status(&self, is_final: bool) -> SubmissionStatus {
+        SubmissionStatus {
+            queue_id: self.queue_id,
+            recipients: self
+                .recipients
+                .iter()
+                .map(|rcpt| {
+                    let (delivered, smtp_reply) = match &rcpt.status {
+                        Status::Completed(reply) => (Delivered::Yes, reply.response.to_string()),
+                        Status::PermanentFailure(reply) => {
+                            (Delivered::No, reply.response.to_string())
+                        }
+                        Status::TemporaryFailure(reply) => {
+                            (Delivered::Queued, reply.response.to_string())
+                        }
+                        Status::Scheduled => {
+                            match self.domains.get(rcpt.domain_idx).map(|d| &d.status) {
+                                Some(Status::PermanentFailure(err)) => {
+                                    (Delivered::No, format!("550 5.0.0 {err}"))
+                                }
+                                Some(Status::TemporaryFailure(err)) => {
+                                    (Delivered::Queued, format!("451 4.0.0 {err}"))
+                                }
+                                _ => (Delivered::Queued, "250 2.1.5 Queued".to_string()),
+                            }
+                        }
+                    };
+
+                    SubmissionRecipient {
+                        address: rcpt.address.clone(),
+                        // Recipients still pending when the message leaves the queue were not delivered
+                        delivered: if is_final && delivered == Delivered::Queued {
+                            Delivered::No
+                        } else {
+                            delivered
+                        },
+                        smtp_reply: smtp_reply.replace('\n', " "),
+                    }
+                })
+                .collect(),
+            is_final,
+        }
+    }
+}
+
+impl HoldReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HoldReason::Compliance => "review",
+            HoldReason::SendingWindow { .. } => "sending-window",
+            HoldReason::Moderation { .. } => "moderation",
+        }
+    }
+}
+
 pub struct RecipientDomain<'x>(&'x str);
 
 impl<'x> RecipientDomain<'x> {
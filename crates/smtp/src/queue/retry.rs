@@ -0,0 +1,123 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::config::smtp::queue::{DeferralClass, QueueRetryPolicy};
+use smtp_proto::Response;
+use store::write::now;
+
+use super::{Deferral, Error, Message, Status};
+
+impl Message {
+    /// Reschedules a deferred domain using the schedule configured for the
+    /// class of its last failure, switching schedules when the class changes.
+    pub fn apply_retry_policy(&mut self, domain_idx: usize, policy: &QueueRetryPolicy) {
+        // Delivered, permanently failed or limited locally
+        let Some(class) = self.deferral_class(domain_idx) else {
+            return;
+        };
+
+        let pos = self
+            .deferrals
+            .iter()
+            .position(|deferral| deferral.domain_idx == domain_idx);
+        let domain = &mut self.domains[domain_idx];
+        let (attempt, expires) = match pos.map(|pos| &self.deferrals[pos]) {
+            Some(deferral) if deferral.class == class => (deferral.attempt + 1, deferral.expires),
+            Some(deferral) => (0, deferral.expires),
+            None => (0, domain.expires),
+        };
+
+        let reason = match policy.schedule(&domain.domain, class) {
+            Some((group, schedule)) => {
+                let delay =
+                    schedule.retry[std::cmp::min(attempt as usize, schedule.retry.len() - 1)];
+                domain.retry.due = now() + delay.as_secs();
+                domain.expires = schedule
+                    .expire
+                    .map_or(expires, |expire| self.created + expire.as_secs());
+
+                format!(
+                    "Attempt {} of the {:?} schedule for {} failures, next retry in {}s",
+                    attempt + 1,
+                    group,
+                    class.as_str(),
+                    delay.as_secs()
+                )
+            }
+            None => {
+                domain.expires = expires;
+
+                format!(
+                    "Attempt {} of the queue schedule, no schedule is defined for {} failures",
+                    domain.retry.inner,
+                    class.as_str()
+                )
+            }
+        };
+
+        let deferral = Deferral {
+            domain_idx,
+            class,
+            attempt,
+            expires,
+            reason,
+        };
+        match pos {
+            Some(pos) => self.deferrals[pos] = deferral,
+            None => self.deferrals.push(deferral),
+        }
+    }
+
+    pub fn deferral_class(&self, domain_idx: usize) -> Option<DeferralClass> {
+        match &self.domains[domain_idx].status {
+            Status::TemporaryFailure(err) => err.deferral_class(),
+            Status::Scheduled => {
+                // Some recipients were deferred by the remote host
+                self.recipients
+                    .iter()
+                    .filter(|rcpt| rcpt.domain_idx == domain_idx)
+                    .find_map(|rcpt| match &rcpt.status {
+                        Status::TemporaryFailure(err) => {
+                            Some(response_deferral_class(&err.response))
+                        }
+                        _ => None,
+                    })
+            }
+            Status::Completed(_) | Status::PermanentFailure(_) => None,
+        }
+    }
+}
+
+impl Error {
+    pub fn deferral_class(&self) -> Option<DeferralClass> {
+        match self {
+            Error::DnsError(_) => Some(DeferralClass::Dns),
+            Error::ConnectionError(_) | Error::Io(_) => Some(DeferralClass::Connection),
+            Error::TlsError(_) | Error::DaneError(_) | Error::MtaStsError(_) => {
+                Some(DeferralClass::Tls)
+            }
+            Error::UnexpectedResponse(err) => Some(response_deferral_class(&err.response)),
+            Error::RateLimited | Error::ConcurrencyLimited => None,
+        }
+    }
+}
+
+fn response_deferral_class(response: &Response<String>) -> DeferralClass {
+    let message = response.message.to_ascii_lowercase();
+
+    match response.esc {
+        [4, 2, 2] => DeferralClass::MailboxFull,
+        [0, 0, 0] if response.code == 452 => DeferralClass::MailboxFull,
+        [4, 7, _] if matches!(response.code, 450 | 451) => DeferralClass::Greylist,
+        _ if ["greylist", "graylist", "grey-list", "gray-list"]
+            .iter()
+            .any(|text| message.contains(text)) =>
+        {
+            DeferralClass::Greylist
+        }
+        _ => DeferralClass::Transient,
+    }
+}
@@ -0,0 +1,302 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use store::{write::Bincode, Deserialize, Serialize};
+use utils::BlobHash;
+
+use super::{Deferral, Domain, Hold, Message, QueueId, QuotaKey, Recipient, SourceIp};
+
+// Entries written before versioning was introduced carry no header
+pub const QUEUE_FORMAT_LEGACY: u8 = 1;
+pub const QUEUE_FORMAT_VERSION: u8 = 6;
+
+// Version that introduced the `deferrals` field
+const QUEUE_FORMAT_DEFERRALS: u8 = 3;
+
+// Version that introduced the `source_ips` field
+const QUEUE_FORMAT_SOURCE_IPS: u8 = 4;
+
+// Version that introduced the `hold` field
+const QUEUE_FORMAT_HOLD: u8 = 5;
+
+// Version that introduced the `connect_attempts` field
+const QUEUE_FORMAT_CONNECT_ATTEMPTS: u8 = 6;
+
+// Oldest version that is able to read entries written by this version.
+// Appending fields to the end of `Message` does not require bumping this value,
+// as readers ignore any trailing bytes they do not know about.
+pub const QUEUE_FORMAT_COMPAT: u8 = 2;
+
+const QUEUE_FORMAT_MAGIC: &[u8] = &[0xff, b'Q'];
+const QUEUE_HEADER_LEN: usize = QUEUE_FORMAT_MAGIC.len() + 2;
+
+#[derive(Debug)]
+pub struct QueuedMessage {
+    pub version: u8,
+    pub inner: Message,
+}
+
+impl Message {
+    pub fn serialize_versioned(self) -> Vec<u8> {
+        let payload = Bincode::new(self).serialize();
+        let mut bytes = Vec::with_capacity(QUEUE_HEADER_LEN + payload.len());
+        bytes.extend_from_slice(QUEUE_FORMAT_MAGIC);
+        bytes.push(QUEUE_FORMAT_VERSION);
+        bytes.push(QUEUE_FORMAT_COMPAT);
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+}
+
+// Layout of `Message` before the `deferrals` field was appended
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MessageV2 {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+}
+
+// Layout of `Message` before the `source_ips` field was appended
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MessageV3 {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+    deferrals: Vec<Deferral>,
+}
+
+// Layout of `Message` before the `hold` field was appended
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MessageV4 {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+    deferrals: Vec<Deferral>,
+    source_ips: Vec<SourceIp>,
+}
+
+// Layout of `Message` before the `connect_attempts` field was appended
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MessageV5 {
+    queue_id: QueueId,
+    created: u64,
+    blob_hash: BlobHash,
+    return_path: String,
+    return_path_lcase: String,
+    return_path_domain: String,
+    recipients: Vec<Recipient>,
+    domains: Vec<Domain>,
+    flags: u64,
+    env_id: Option<String>,
+    priority: i16,
+    size: usize,
+    quota_keys: Vec<QuotaKey>,
+    deferrals: Vec<Deferral>,
+    source_ips: Vec<SourceIp>,
+    hold: Option<Hold>,
+}
+
+impl QueuedMessage {
+    pub fn needs_upgrade(&self) -> bool {
+        self.version < QUEUE_FORMAT_VERSION
+    }
+
+    pub fn header(bytes: &[u8]) -> Option<(u8, u8)> {
+        if bytes.len() > QUEUE_HEADER_LEN && bytes.starts_with(QUEUE_FORMAT_MAGIC) {
+            Some((
+                bytes[QUEUE_FORMAT_MAGIC.len()],
+                bytes[QUEUE_FORMAT_MAGIC.len() + 1],
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Deserialize for QueuedMessage {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        if let Some((version, compat)) = QueuedMessage::header(bytes) {
+            let result = if compat <= QUEUE_FORMAT_VERSION {
+                deserialize_message(&bytes[QUEUE_HEADER_LEN..], version)
+            } else {
+                Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details("Queue entry was written by an incompatible server version")
+                    .ctx(trc::Key::Version, version as u64)
+                    .ctx(trc::Key::Limit, QUEUE_FORMAT_VERSION as u64))
+            };
+
+            match result {
+                Ok(message) => {
+                    return Ok(QueuedMessage {
+                        version,
+                        inner: message,
+                    })
+                }
+                Err(err) => {
+                    // The header could be a false positive on a legacy entry
+                    if let Ok(message) = deserialize_message(bytes, QUEUE_FORMAT_LEGACY) {
+                        return Ok(QueuedMessage {
+                            version: QUEUE_FORMAT_LEGACY,
+                            inner: message,
+                        });
+                    }
+
+                    return Err(err.caused_by(trc::location!()));
+                }
+            }
+        }
+
+        deserialize_message(bytes, QUEUE_FORMAT_LEGACY).map(|message| QueuedMessage {
+            version: QUEUE_FORMAT_LEGACY,
+            inner: message,
+        })
+    }
+}
+
+fn deserialize_message(bytes: &[u8], version: u8) -> trc::Result<Message> {
+    if version >= QUEUE_FORMAT_CONNECT_ATTEMPTS {
+        Bincode::<Message>::deserialize(bytes).map(|message| message.inner)
+    } else if version >= QUEUE_FORMAT_HOLD {
+        Bincode::<MessageV5>::deserialize(bytes).map(|message| message.inner.into())
+    } else if version >= QUEUE_FORMAT_SOURCE_IPS {
+        Bincode::<MessageV4>::deserialize(bytes).map(|message| message.inner.into())
+    } else if version >= QUEUE_FORMAT_DEFERRALS {
+        Bincode::<MessageV3>::deserialize(bytes).map(|message| message.inner.into())
+    } else {
+        Bincode::<MessageV2>::deserialize(bytes).map(|message| message.inner.into())
+    }
+}
+
+impl From<MessageV2> for Message {
+    fn from(message: MessageV2) -> Self {
+        Message {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            deferrals: Vec::new(),
+            source_ips: Vec::new(),
+            hold: None,
+            connect_attempts: Vec::new(),
+            span_id: 0,
+        }
+    }
+}
+
+impl From<MessageV3> for Message {
+    fn from(message: MessageV3) -> Self {
+        Message {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            deferrals: message.deferrals,
+            source_ips: Vec::new(),
+            hold: None,
+            connect_attempts: Vec::new(),
+            span_id: 0,
+        }
+    }
+}
+
+impl From<MessageV4> for Message {
+    fn from(message: MessageV4) -> Self {
+        Message {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            deferrals: message.deferrals,
+            source_ips: message.source_ips,
+            hold: None,
+            connect_attempts: Vec::new(),
+            span_id: 0,
+        }
+    }
+}
+
+impl From<MessageV5> for Message {
+    fn from(message: MessageV5) -> Self {
+        Message {
+            queue_id: message.queue_id,
+            created: message.created,
+            blob_hash: message.blob_hash,
+            return_path: message.return_path,
+            return_path_lcase: message.return_path_lcase,
+            return_path_domain: message.return_path_domain,
+            recipients: message.recipients,
+            domains: message.domains,
+            flags: message.flags,
+            env_id: message.env_id,
+            priority: message.priority,
+            size: message.size,
+            quota_keys: message.quota_keys,
+            deferrals: message.deferrals,
+            source_ips: message.source_ips,
+            hold: message.hold,
+            connect_attempts: Vec::new(),
+            span_id: 0,
+        }
+    }
+}
@@ -11,16 +11,19 @@ use std::borrow::Cow;
 use std::future::Future;
 use std::time::{Duration, SystemTime};
 use store::write::key::DeserializeBigEndian;
-use store::write::{now, BatchBuilder, Bincode, BlobOp, QueueClass, ValueClass};
+use store::write::tier::BlobCommit;
+use store::write::{assert::HashedValue, now, BatchBuilder, BlobOp, QueueClass, ValueClass};
 use store::{Deserialize, IterateParams, Serialize, ValueKey, U64_LEN};
 use trc::ServerEvent;
 use utils::BlobHash;
 
+use super::serialize::{QueuedMessage, QUEUE_FORMAT_VERSION};
 use super::{
     Domain, Message, MessageSource, QueueEnvelope, QueueId, QuotaKey, Recipient, Schedule, Status,
 };
 
 pub const LOCK_EXPIRY: u64 = 300;
+pub const QUEUE_CHECK_SAMPLE: usize = 1000;
 
 pub trait SmtpSpool: Sync + Send {
     fn new_message(
@@ -39,6 +42,8 @@ pub trait SmtpSpool: Sync + Send {
     ) -> impl Future<Output = Option<QueueEventLock>> + Send;
 
     fn read_message(&self, id: QueueId) -> impl Future<Output = Option<Message>> + Send;
+
+    fn check_queue_format(&self) -> impl Future<Output = trc::Result<()>> + Send;
 }
 
 impl SmtpSpool for Server {
@@ -67,6 +72,10 @@ impl SmtpSpool for Server {
             size: 0,
             blob_hash: Default::default(),
             quota_keys: Vec::new(),
+            deferrals: Vec::new(),
+            source_ips: Vec::new(),
+            hold: None,
+            connect_attempts: Vec::new(),
         }
     }
 
@@ -162,12 +171,33 @@ impl SmtpSpool for Server {
     async fn read_message(&self, id: QueueId) -> Option<Message> {
         match self
             .store()
-            .get_value::<Bincode<Message>>(ValueKey::from(ValueClass::Queue(QueueClass::Message(
-                id,
-            ))))
+            .get_value::<HashedValue<QueuedMessage>>(ValueKey::from(ValueClass::Queue(
+                QueueClass::Message(id),
+            )))
             .await
         {
-            Ok(Some(message)) => Some(message.inner),
+            Ok(Some(message)) => {
+                if message.inner.needs_upgrade() {
+                    // Upgrade entries written by previous versions on first access
+                    let mut batch = BatchBuilder::new();
+                    batch
+                        .assert_value(ValueClass::Queue(QueueClass::Message(id)), &message)
+                        .set(
+                            ValueClass::Queue(QueueClass::Message(id)),
+                            message.inner.inner.clone().serialize_versioned(),
+                        );
+                    if let Err(err) = self.store().write(batch.build()).await {
+                        if !err.is_assertion_failure() {
+                            trc::error!(err
+                                .details("Failed to upgrade queued message.")
+                                .ctx(trc::Key::QueueId, id)
+                                .caused_by(trc::location!()));
+                        }
+                    }
+                }
+
+                Some(message.inner.inner)
+            }
             Ok(None) => None,
             Err(err) => {
                 trc::error!(err
@@ -178,6 +208,38 @@ impl SmtpSpool for Server {
             }
         }
     }
+
+    async fn check_queue_format(&self) -> trc::Result<()> {
+        let from_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(0)));
+        let to_key = ValueKey::from(ValueClass::Queue(QueueClass::Message(u64::MAX)));
+        let mut checked = 0;
+
+        self.store()
+            .iterate(
+                IterateParams::new(from_key, to_key).ascending(),
+                |key, value| {
+                    QueuedMessage::deserialize(value).map_err(|err| {
+                        err.ctx(
+                            trc::Key::QueueId,
+                            key.deserialize_be_u64(0).unwrap_or_default(),
+                        )
+                        .details(format!(
+                            concat!(
+                                "The message queue contains entries that cannot be read by ",
+                                "this server version (queue format {}). Upgrade to the ",
+                                "server version that wrote them or empty the queue ",
+                                "before downgrading."
+                            ),
+                            QUEUE_FORMAT_VERSION
+                        ))
+                    })?;
+                    checked += 1;
+
+                    Ok(checked < QUEUE_CHECK_SAMPLE)
+                },
+            )
+            .await
+    }
 }
 
 impl Message {
@@ -302,11 +364,11 @@ impl Message {
                 BlobOp::Commit {
                     hash: self.blob_hash.clone(),
                 },
-                vec![],
+                BlobCommit::now().serialize(),
             )
             .set(
                 ValueClass::Queue(QueueClass::Message(self.queue_id)),
-                Bincode::new(self).serialize(),
+                self.serialize_versioned(),
             );
 
         if let Err(err) = server.store().write(batch.build()).await {
@@ -400,8 +462,6 @@ impl Message {
         prev_event: Option<u64>,
         next_event: Option<u64>,
     ) -> bool {
-        debug_assert!(prev_event.is_some() == next_event.is_some());
-
         let mut batch = BatchBuilder::new();
 
         // Release quota for completed deliveries
@@ -409,27 +469,28 @@ impl Message {
 
         // Update message queue
         let mut batch = BatchBuilder::new();
-        if let (Some(prev_event), Some(next_event)) = (prev_event, next_event) {
-            batch
-                .clear(ValueClass::Queue(QueueClass::MessageEvent(
-                    store::write::QueueEvent {
-                        due: prev_event,
-                        queue_id: self.queue_id,
-                    },
-                )))
-                .set(
-                    ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
-                        due: next_event,
-                        queue_id: self.queue_id,
-                    })),
-                    0u64.serialize(),
-                );
+        if let Some(prev_event) = prev_event {
+            batch.clear(ValueClass::Queue(QueueClass::MessageEvent(
+                store::write::QueueEvent {
+                    due: prev_event,
+                    queue_id: self.queue_id,
+                },
+            )));
+        }
+        if let Some(next_event) = next_event {
+            batch.set(
+                ValueClass::Queue(QueueClass::MessageEvent(store::write::QueueEvent {
+                    due: next_event,
+                    queue_id: self.queue_id,
+                })),
+                0u64.serialize(),
+            );
         }
 
         let span_id = self.span_id;
         batch.set(
             ValueClass::Queue(QueueClass::Message(self.queue_id)),
-            Bincode::new(self).serialize(),
+            self.serialize_versioned(),
         );
 
         if let Err(err) = server.store().write(batch.build()).await {
@@ -15,7 +15,7 @@ use ahash::AHashMap;
 use common::Server;
 use mail_auth::{
     flate2::read::GzDecoder,
-    report::{tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, Report},
+    report::{tlsrpt::TlsReport, ActionDisposition, DmarcResult, Feedback, FeedbackType, Report},
     zip,
 };
 use mail_parser::{MessageParser, MimeHeaders, PartType};
@@ -267,6 +267,25 @@ impl AnalyzeReport for Server {
                         Some(report) => {
                             // Log
                             report.log();
+
+                            // Complaints count towards the reputation of local senders
+                            if let Some(sender) = report.original_mail_from().filter(|_| {
+                                matches!(
+                                    report.feedback_type(),
+                                    FeedbackType::Abuse | FeedbackType::Fraud | FeedbackType::Virus
+                                )
+                            }) {
+                                let sender = sender
+                                    .trim()
+                                    .trim_start_matches('<')
+                                    .trim_end_matches('>')
+                                    .to_lowercase();
+                                if let Err(err) = core.record_complaint(&sender).await {
+                                    trc::error!(err
+                                        .span_id(session_id)
+                                        .details("Failed to update sender reputation"));
+                                }
+                            }
                             Format::Arf(report.into_owned())
                         }
                         None => {
@@ -51,6 +51,11 @@ impl<T: SessionStream> Session<T> {
             .set_variable("tls.version", tls_version)
             .set_variable("tls.cipher", tls_cipher)
             .set_variable("stage", stage);
+        if let Some(dnsbl) = &self.data.dnsbl {
+            params = params
+                .set_variable("dnsbl.score", dnsbl.score)
+                .set_variable("dnsbl.lists", dnsbl.lists().join(","));
+        }
         if let Some(ip_rev) = &self.data.iprev {
             params = params.set_variable("iprev.result", ip_rev.result().as_str());
             if let Some(ptr) = ip_rev.ptr.as_ref().and_then(|addrs| addrs.first()) {
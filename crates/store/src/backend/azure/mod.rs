@@ -136,16 +136,17 @@ impl AzureStore {
             };
 
             if let Some(e) = err {
-                return if matches!(
-                    e.kind(),
+                return match e.kind() {
                     ErrorKind::HttpResponse {
                         status: StatusCode::NotFound,
                         ..
-                    }
-                ) {
-                    Ok(None)
-                } else {
-                    Err(trc::StoreEvent::AzureError.reason(e))
+                    } => Ok(None),
+                    // Range starts past the end of the blob
+                    ErrorKind::HttpResponse {
+                        status: StatusCode::RequestedRangeNotSatisfiable,
+                        ..
+                    } => Ok(Some(Vec::new())),
+                    _ => Err(trc::StoreEvent::AzureError.reason(e)),
                 };
             }
         }
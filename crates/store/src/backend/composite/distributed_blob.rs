@@ -71,6 +71,8 @@ impl DistributedBlob {
                     Store::MySQL(store) => store.get_blob(key, read_range).await,
                     #[cfg(feature = "rocks")]
                     Store::RocksDb(store) => store.get_blob(key, read_range).await,
+                    #[cfg(feature = "test_mode")]
+                    Store::InMemory(store) => store.get_blob(key, read_range).await,
                     #[cfg(all(
                         feature = "enterprise",
                         any(feature = "postgres", feature = "mysql")
@@ -103,6 +105,8 @@ impl DistributedBlob {
                     Store::MySQL(store) => store.put_blob(key, data).await,
                     #[cfg(feature = "rocks")]
                     Store::RocksDb(store) => store.put_blob(key, data).await,
+                    #[cfg(feature = "test_mode")]
+                    Store::InMemory(store) => store.put_blob(key, data).await,
                     #[cfg(all(
                         feature = "enterprise",
                         any(feature = "postgres", feature = "mysql")
@@ -135,6 +139,8 @@ impl DistributedBlob {
                     Store::MySQL(store) => store.delete_blob(key).await,
                     #[cfg(feature = "rocks")]
                     Store::RocksDb(store) => store.delete_blob(key).await,
+                    #[cfg(feature = "test_mode")]
+                    Store::InMemory(store) => store.delete_blob(key).await,
                     #[cfg(all(
                         feature = "enterprise",
                         any(feature = "postgres", feature = "mysql")
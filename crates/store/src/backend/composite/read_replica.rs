@@ -18,7 +18,7 @@ use roaring::RoaringBitmap;
 use utils::config::{utils::AsKey, Config};
 
 use crate::{
-    write::{AssignedIds, Batch, BitmapClass, ValueClass},
+    write::{compress::ValueCompression, AssignedIds, Batch, BitmapClass, ValueClass},
     BitmapKey, Deserialize, IterateParams, Key, Store, Stores, ValueKey,
 };
 
@@ -261,6 +261,10 @@ impl SQLReadReplica {
         .await
     }
 
+    pub fn value_compression(&self) -> ValueCompression {
+        self.primary.value_compression()
+    }
+
     pub async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
         match &self.primary {
             #[cfg(feature = "postgres")]
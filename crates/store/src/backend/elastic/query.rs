@@ -14,6 +14,8 @@ use crate::fts::{Field, FtsFilter};
 
 use super::{assert_success, ElasticSearchStore, INDEX_NAMES};
 
+const PAGE_SIZE: usize = 10000;
+
 impl ElasticSearchStore {
     pub async fn fts_query<T: Into<u8> + Display + Clone + std::fmt::Debug>(
         &self,
@@ -120,6 +122,59 @@ impl ElasticSearchStore {
 
         Ok(results)
     }
+
+    pub async fn fts_indexed_documents(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+    ) -> trc::Result<RoaringBitmap> {
+        let index = INDEX_NAMES[collection.into() as usize];
+        let mut results = RoaringBitmap::new();
+        let mut search_after = None;
+
+        loop {
+            let mut body = json!({
+                "query": { "match": { "account_id": account_id } },
+                "sort": [{ "document_id": "asc" }],
+                "size": PAGE_SIZE,
+                "_source": ["document_id"]
+            });
+            if let Some(document_id) = search_after {
+                body["search_after"] = json!([document_id]);
+            }
+
+            let response = assert_success(
+                self.index
+                    .search(SearchParts::Index(&[index]))
+                    .body(body)
+                    .send()
+                    .await,
+            )
+            .await?;
+            let json: Value = response
+                .json()
+                .await
+                .map_err(|err| trc::StoreEvent::ElasticsearchError.reason(err))?;
+            let hits = json["hits"]["hits"].as_array().ok_or_else(|| {
+                trc::StoreEvent::ElasticsearchError.reason("Invalid response from ElasticSearch")
+            })?;
+
+            for hit in hits {
+                let document_id = hit["_source"]["document_id"].as_u64().ok_or_else(|| {
+                    trc::StoreEvent::ElasticsearchError
+                        .reason("Invalid response from ElasticSearch")
+                })? as u32;
+                results.insert(document_id);
+                search_after = Some(document_id);
+            }
+
+            if hits.len() < PAGE_SIZE {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
 }
 
 impl<T: Into<u8> + Display + Clone + std::fmt::Debug> Field<T> {
@@ -9,6 +9,8 @@ use std::time::Duration;
 use foundationdb::{api, options::DatabaseOption, Database};
 use utils::config::{utils::AsKey, Config};
 
+use crate::write::compress::ValueCompression;
+
 use super::FdbStore;
 
 impl FdbStore {
@@ -106,6 +108,7 @@ impl FdbStore {
             guard,
             db,
             version: Default::default(),
+            value_compression: ValueCompression::parse(config, &prefix),
         })
     }
 }
@@ -8,6 +8,8 @@ use std::time::{Duration, Instant};
 
 use foundationdb::{api::NetworkAutoStop, Database, FdbError, Transaction};
 
+use crate::write::compress::ValueCompression;
+
 pub mod blob;
 pub mod main;
 pub mod read;
@@ -22,6 +24,7 @@ pub struct FdbStore {
     db: Database,
     guard: NetworkAutoStop,
     version: parking_lot::Mutex<ReadVersion>,
+    pub(crate) value_compression: ValueCompression,
 }
 
 pub(crate) struct TimedTransaction {
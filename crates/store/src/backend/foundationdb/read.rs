@@ -84,7 +84,9 @@ impl FdbStore {
         let mut begin = params.begin.serialize(WITH_SUBSPACE);
         let end = params.end.serialize(WITH_SUBSPACE);
 
-        if !params.first {
+        // Value filters may skip the first row in range, so they are
+        // evaluated on the streaming path even when a single row is requested
+        if !params.first || params.has_value_filter() {
             let mut begin_selector = KeySelector::first_greater_or_equal(&begin);
 
             loop {
@@ -108,7 +110,10 @@ impl FdbStore {
 
                         for value in values.iter() {
                             last_key = value.key();
-                            if !cb(last_key.get(1..).unwrap_or_default(), value.value())? {
+                            if params.matches_value(value.value())
+                                && (!cb(last_key.get(1..).unwrap_or_default(), value.value())?
+                                    || params.first)
+                            {
                                 return Ok(());
                             }
                         }
@@ -60,12 +60,9 @@ impl FsStore {
         let mut blob = File::open(&blob_path).await.map_err(into_error)?;
 
         Ok(Some(if range.start != 0 || range.end != usize::MAX {
-            let from_offset = if range.start < blob_size {
-                range.start
-            } else {
-                0
-            };
-            let mut buf = vec![0; (std::cmp::min(range.end, blob_size) - from_offset) as usize];
+            // Ranges starting past the end of the blob are empty
+            let from_offset = std::cmp::min(range.start, blob_size);
+            let mut buf = vec![0; std::cmp::min(range.end, blob_size).saturating_sub(from_offset)];
 
             if from_offset > 0 {
                 blob.seek(SeekFrom::Start(from_offset as u64))
@@ -0,0 +1,87 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+
+use crate::{write::compress::ValueCompression, SUBSPACE_BLOBS};
+
+pub mod read;
+pub mod write;
+
+type Subspaces = AHashMap<u8, BTreeMap<Vec<u8>, Vec<u8>>>;
+
+/// Data store that keeps every subspace in an ordered map, intended for tests
+/// that need deterministic behaviour without an external backend.
+#[derive(Default)]
+pub struct InMemoryStore {
+    subspaces: Mutex<Subspaces>,
+    iterations: AtomicU64,
+    pub(crate) value_compression: ValueCompression,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_value_compression(mut self, value_compression: ValueCompression) -> Self {
+        self.value_compression = value_compression;
+        self
+    }
+
+    /// Number of range scans performed so far, used by tests to verify how
+    /// many times a key range is read.
+    pub fn iterations(&self) -> u64 {
+        self.iterations.load(Ordering::Relaxed)
+    }
+
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        Ok(self
+            .subspaces
+            .lock()
+            .get(&SUBSPACE_BLOBS)
+            .and_then(|blobs| blobs.get(key))
+            .map(|bytes| {
+                if range.start == 0 && range.end == usize::MAX {
+                    bytes.to_vec()
+                } else {
+                    bytes
+                        .get(range.start..std::cmp::min(bytes.len(), range.end))
+                        .unwrap_or_default()
+                        .to_vec()
+                }
+            }))
+    }
+
+    pub(crate) async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
+        self.subspaces
+            .lock()
+            .entry(SUBSPACE_BLOBS)
+            .or_default()
+            .insert(key.to_vec(), data.to_vec());
+        Ok(())
+    }
+
+    pub(crate) async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        Ok(self
+            .subspaces
+            .lock()
+            .get_mut(&SUBSPACE_BLOBS)
+            .and_then(|blobs| blobs.remove(key))
+            .is_some())
+    }
+}
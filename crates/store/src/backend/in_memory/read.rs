@@ -0,0 +1,111 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use roaring::RoaringBitmap;
+
+use super::InMemoryStore;
+
+use crate::{
+    backend::deserialize_i64_le,
+    write::{key::DeserializeBigEndian, BitmapClass, ValueClass},
+    BitmapKey, Deserialize, IterateParams, Key, ValueKey, U32_LEN,
+};
+
+impl InMemoryStore {
+    pub(crate) async fn get_value<U>(&self, key: impl Key) -> trc::Result<Option<U>>
+    where
+        U: Deserialize + 'static,
+    {
+        let subspaces = self.subspaces.lock();
+        if let Some(value) = subspaces
+            .get(&key.subspace())
+            .and_then(|subspace| subspace.get(&key.serialize(0)))
+        {
+            U::deserialize(value).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) async fn get_bitmap(
+        &self,
+        mut key: BitmapKey<BitmapClass<u32>>,
+    ) -> trc::Result<Option<RoaringBitmap>> {
+        let mut bm = RoaringBitmap::new();
+        let subspace = key.subspace();
+        let begin = key.serialize(0);
+        key.document_id = u32::MAX;
+        let end = key.serialize(0);
+        let key_len = begin.len();
+
+        if let Some(subspace) = self.subspaces.lock().get(&subspace) {
+            for key in subspace.range(begin..=end).map(|(key, _)| key) {
+                if key.len() == key_len {
+                    bm.insert(key.as_slice().deserialize_be_u32(key.len() - U32_LEN)?);
+                }
+            }
+        }
+
+        Ok(if !bm.is_empty() { Some(bm) } else { None })
+    }
+
+    pub(crate) async fn iterate<T: Key>(
+        &self,
+        params: IterateParams<T>,
+        mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+    ) -> trc::Result<()> {
+        self.iterations
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let begin = params.begin.serialize(0);
+        let end = params.end.serialize(0);
+        if begin > end {
+            return Ok(());
+        }
+
+        let subspaces = self.subspaces.lock();
+        let Some(subspace) = subspaces.get(&params.begin.subspace()) else {
+            return Ok(());
+        };
+        let mut range = subspace.range(begin..=end);
+
+        loop {
+            let row = if params.ascending {
+                range.next()
+            } else {
+                range.next_back()
+            };
+            match row {
+                Some((key, value)) => {
+                    if params.matches_value(value) && (!cb(key, value)? || params.first) {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn get_counter(
+        &self,
+        key: impl Into<ValueKey<ValueClass<u32>>> + Sync + Send,
+    ) -> trc::Result<i64> {
+        let key = key.into();
+        let subspace = key.subspace();
+        let key = key.serialize(0);
+
+        match self
+            .subspaces
+            .lock()
+            .get(&subspace)
+            .and_then(|subspace| subspace.get(&key))
+        {
+            Some(bytes) => deserialize_i64_le(&key, bytes),
+            None => Ok(0),
+        }
+    }
+}
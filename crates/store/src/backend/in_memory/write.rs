@@ -0,0 +1,270 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use roaring::RoaringBitmap;
+
+use super::{InMemoryStore, Subspaces};
+use crate::{
+    backend::deserialize_i64_le,
+    write::{
+        key::DeserializeBigEndian, AssignedIds, Batch, BitmapClass, Operation, RandomAvailableId,
+        ValueOp,
+    },
+    BitmapKey, Deserialize, IndexKey, Key, LogKey, SUBSPACE_COUNTER, SUBSPACE_INDEXES,
+    SUBSPACE_LOGS, SUBSPACE_QUOTA, U32_LEN,
+};
+
+impl InMemoryStore {
+    pub(crate) async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+        let mut subspaces = self.subspaces.lock();
+        let mut txn = InMemoryTransaction {
+            subspaces: &subspaces,
+            changes: BTreeMap::new(),
+        };
+
+        // Changes are applied only once all operations succeed
+        let result = txn.prepare(&batch)?;
+        let changes = txn.changes;
+        for ((subspace, key), value) in changes {
+            let subspace = subspaces.entry(subspace).or_default();
+            if let Some(value) = value {
+                subspace.insert(key, value);
+            } else {
+                subspace.remove(&key);
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) async fn delete_range(&self, from: impl Key, to: impl Key) -> trc::Result<()> {
+        let from_key = from.serialize(0);
+        let to_key = to.serialize(0);
+        if from_key >= to_key {
+            return Ok(());
+        }
+
+        if let Some(subspace) = self.subspaces.lock().get_mut(&from.subspace()) {
+            let keys = subspace
+                .range(from_key..to_key)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            for key in keys {
+                subspace.remove(&key);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn purge_store(&self) -> trc::Result<()> {
+        let mut subspaces = self.subspaces.lock();
+        for subspace in [SUBSPACE_QUOTA, SUBSPACE_COUNTER] {
+            if let Some(subspace) = subspaces.get_mut(&subspace) {
+                subspace.retain(|_, value| !matches!(i64::deserialize(value), Ok(0)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct InMemoryTransaction<'x> {
+    subspaces: &'x Subspaces,
+    changes: BTreeMap<(u8, Vec<u8>), Option<Vec<u8>>>,
+}
+
+impl InMemoryTransaction<'_> {
+    fn prepare(&mut self, batch: &Batch) -> trc::Result<AssignedIds> {
+        let mut account_id = u32::MAX;
+        let mut collection = u8::MAX;
+        let mut document_id = u32::MAX;
+        let mut change_id = u64::MAX;
+        let mut result = AssignedIds::default();
+
+        for op in &batch.ops {
+            match op {
+                Operation::AccountId {
+                    account_id: account_id_,
+                } => {
+                    account_id = *account_id_;
+                }
+                Operation::Collection {
+                    collection: collection_,
+                } => {
+                    collection = *collection_;
+                }
+                Operation::DocumentId {
+                    document_id: document_id_,
+                } => {
+                    document_id = *document_id_;
+                }
+                Operation::ChangeId {
+                    change_id: change_id_,
+                } => {
+                    change_id = *change_id_;
+                }
+                Operation::Value { class, op } => {
+                    let key =
+                        class.serialize(account_id, collection, document_id, 0, (&result).into());
+                    let subspace = class.subspace(collection);
+
+                    match op {
+                        ValueOp::Set(value) => {
+                            let value = value.resolve(&result)?.into_owned();
+                            self.set(subspace, key, value);
+                        }
+                        ValueOp::AtomicAdd(by) => {
+                            let num = self.get_counter(subspace, &key)? + *by;
+                            self.set(subspace, key, num.to_le_bytes().to_vec());
+                        }
+                        ValueOp::AddAndGet(by) => {
+                            let num = self.get_counter(subspace, &key)? + *by;
+                            self.set(subspace, key, num.to_le_bytes().to_vec());
+                            result.push_counter_id(num);
+                        }
+                        ValueOp::Clear => {
+                            self.clear(subspace, key);
+                        }
+                    }
+                }
+                Operation::Index { field, key, set } => {
+                    let key = IndexKey {
+                        account_id,
+                        collection,
+                        document_id,
+                        field: *field,
+                        key,
+                    }
+                    .serialize(0);
+
+                    if *set {
+                        self.set(SUBSPACE_INDEXES, key, vec![]);
+                    } else {
+                        self.clear(SUBSPACE_INDEXES, key);
+                    }
+                }
+                Operation::Bitmap { class, set } => {
+                    let is_document_id = matches!(class, BitmapClass::DocumentIds);
+                    let subspace = class.subspace();
+                    if *set && is_document_id && document_id == u32::MAX {
+                        let begin = BitmapKey {
+                            account_id,
+                            collection,
+                            class: BitmapClass::DocumentIds,
+                            document_id: 0,
+                        }
+                        .serialize(0);
+                        let end = BitmapKey {
+                            account_id,
+                            collection,
+                            class: BitmapClass::DocumentIds,
+                            document_id: u32::MAX,
+                        }
+                        .serialize(0);
+                        let key_len = begin.len();
+                        let mut found_ids = RoaringBitmap::new();
+                        for key in self.keys(subspace, &begin, &end) {
+                            if key.len() == key_len {
+                                found_ids.insert(
+                                    key.as_slice().deserialize_be_u32(key.len() - U32_LEN)?,
+                                );
+                            }
+                        }
+
+                        document_id = found_ids.random_available_id();
+                        result.push_document_id(document_id);
+                    }
+                    let key =
+                        class.serialize(account_id, collection, document_id, 0, (&result).into());
+
+                    if *set {
+                        self.set(subspace, key, vec![]);
+                    } else {
+                        self.clear(subspace, key);
+                    }
+                }
+                Operation::Log { set } => {
+                    let key = LogKey {
+                        account_id,
+                        collection,
+                        change_id,
+                    }
+                    .serialize(0);
+
+                    let value = set.resolve(&result)?.into_owned();
+                    self.set(SUBSPACE_LOGS, key, value);
+                }
+                Operation::AssertValue {
+                    class,
+                    assert_value,
+                } => {
+                    let key =
+                        class.serialize(account_id, collection, document_id, 0, (&result).into());
+                    let matches = self
+                        .get(class.subspace(collection), &key)
+                        .map(|value| assert_value.matches(value))
+                        .unwrap_or_else(|| assert_value.is_none());
+
+                    if !matches {
+                        return Err(trc::StoreEvent::AssertValueFailed.into());
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn get(&self, subspace: u8, key: &[u8]) -> Option<&[u8]> {
+        match self.changes.get(&(subspace, key.to_vec())) {
+            Some(value) => value.as_deref(),
+            None => self
+                .subspaces
+                .get(&subspace)
+                .and_then(|subspace| subspace.get(key))
+                .map(|value| value.as_slice()),
+        }
+    }
+
+    fn get_counter(&self, subspace: u8, key: &[u8]) -> trc::Result<i64> {
+        self.get(subspace, key)
+            .map_or(Ok(0), |bytes| deserialize_i64_le(key, bytes))
+    }
+
+    fn keys(&self, subspace: u8, begin: &[u8], end: &[u8]) -> BTreeSet<Vec<u8>> {
+        let mut keys = self
+            .subspaces
+            .get(&subspace)
+            .map(|map| {
+                map.range(begin.to_vec()..=end.to_vec())
+                    .map(|(key, _)| key.clone())
+                    .collect::<BTreeSet<_>>()
+            })
+            .unwrap_or_default();
+        for ((_, key), value) in self
+            .changes
+            .range((subspace, begin.to_vec())..=(subspace, end.to_vec()))
+        {
+            if value.is_some() {
+                keys.insert(key.clone());
+            } else {
+                keys.remove(key);
+            }
+        }
+        keys
+    }
+
+    fn set(&mut self, subspace: u8, key: Vec<u8>, value: Vec<u8>) {
+        self.changes.insert((subspace, key), Some(value));
+    }
+
+    fn clear(&mut self, subspace: u8, key: Vec<u8>) {
+        self.changes.insert((subspace, key), None);
+    }
+}
@@ -11,6 +11,8 @@ pub mod elastic;
 #[cfg(feature = "foundation")]
 pub mod foundationdb;
 pub mod fs;
+#[cfg(feature = "test_mode")]
+pub mod in_memory;
 pub mod memory;
 #[cfg(feature = "mysql")]
 pub mod mysql;
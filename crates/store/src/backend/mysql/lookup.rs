@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use mysql_async::{prelude::Queryable, Params, Row};
+use mysql_async::{prelude::Queryable, Error, Params, Row, TxOpts};
 
 use crate::{IntoRows, QueryResult, QueryType, Value};
 
@@ -39,6 +39,32 @@ impl MysqlStore {
                 .map_or_else(|e| Err(into_error(e)), |r| Ok(T::from_query_all(r))),
         }
     }
+
+    pub(crate) async fn execute_transaction(
+        &self,
+        statements: &[(&str, Vec<Value<'_>>)],
+    ) -> trc::Result<()> {
+        let mut conn = self.conn_pool.get_conn().await.map_err(into_error)?;
+        let mut trx = conn
+            .start_transaction(TxOpts::default())
+            .await
+            .map_err(into_error)?;
+
+        for (idx, (query, params)) in statements.iter().enumerate() {
+            let params = Params::Positional(params.iter().map(Into::into).collect());
+            match trx.exec_drop(*query, params).await {
+                Ok(_) => {}
+                Err(Error::Server(err)) if err.code == 1062 => {
+                    return Err(trc::StoreEvent::AssertValueFailed
+                        .reason(err)
+                        .ctx(trc::Key::Id, idx as u64));
+                }
+                Err(err) => return Err(into_error(err)),
+            }
+        }
+
+        trx.commit().await.map_err(into_error)
+    }
 }
 
 impl From<crate::Value<'_>> for mysql_async::Value {
@@ -9,7 +9,7 @@ use std::time::Duration;
 use mysql_async::{prelude::Queryable, OptsBuilder, Pool, PoolConstraints, PoolOpts, SslOpts};
 use utils::config::{utils::AsKey, Config};
 
-use crate::*;
+use crate::{write::compress::ValueCompression, *};
 
 use super::{into_error, MysqlStore};
 
@@ -76,6 +76,7 @@ impl MysqlStore {
 
         let db = Self {
             conn_pool: Pool::new(opts),
+            value_compression: ValueCompression::parse(config, &prefix),
         };
 
         if create_tables {
@@ -108,6 +109,7 @@ impl MysqlStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_TASK,
         ] {
             let table = char::from(table);
             conn.query_drop(format!(
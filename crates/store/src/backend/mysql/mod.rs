@@ -8,6 +8,8 @@ use std::fmt::Display;
 
 use mysql_async::Pool;
 
+use crate::write::compress::ValueCompression;
+
 pub mod blob;
 pub mod lookup;
 pub mod main;
@@ -16,6 +18,7 @@ pub mod write;
 
 pub struct MysqlStore {
     pub(crate) conn_pool: Pool,
+    pub(crate) value_compression: ValueCompression,
 }
 
 #[inline(always)]
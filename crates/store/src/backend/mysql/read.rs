@@ -5,7 +5,7 @@
  */
 
 use futures::TryStreamExt;
-use mysql_async::{prelude::Queryable, Row};
+use mysql_async::{prelude::Queryable, Params, Row};
 use roaring::RoaringBitmap;
 
 use crate::{
@@ -80,30 +80,38 @@ impl MysqlStore {
         let begin = params.begin.serialize(0);
         let end = params.end.serialize(0);
         let keys = if params.values { "k, v" } else { "k" };
+        let filter = params.value_filter_sql(|| "?".to_string());
 
         let s = conn
             .prep(&match (params.first, params.ascending) {
                 (true, true) => {
                     format!(
-                        "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k ASC LIMIT 1"
+                        "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k ASC LIMIT 1"
                     )
                 }
                 (true, false) => {
                     format!(
-                    "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k DESC LIMIT 1"
+                    "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k DESC LIMIT 1"
                 )
                 }
                 (false, true) => {
-                    format!("SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k ASC")
+                    format!("SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k ASC")
                 }
                 (false, false) => {
-                    format!("SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k DESC")
+                    format!("SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k DESC")
                 }
             })
             .await
             .map_err(into_error)?;
+        let args = Params::Positional(
+            [begin, end]
+                .into_iter()
+                .chain(params.value_prefixes().iter().cloned())
+                .map(Into::into)
+                .collect(),
+        );
         let mut rows = conn
-            .exec_stream::<Row, _, _>(&s, (begin, end))
+            .exec_stream::<Row, _, _>(&s, args)
             .await
             .map_err(into_error)?;
 
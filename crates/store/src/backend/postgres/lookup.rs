@@ -8,7 +8,10 @@ use crate::{QueryResult, QueryType};
 
 use bytes::BytesMut;
 use futures::{pin_mut, TryStreamExt};
-use tokio_postgres::types::{FromSql, ToSql, Type};
+use tokio_postgres::{
+    error::SqlState,
+    types::{FromSql, ToSql, Type},
+};
 
 use crate::IntoRows;
 
@@ -52,6 +55,32 @@ impl PostgresStore {
                 .map_or_else(|e| Err(into_error(e)), |r| Ok(T::from_query_all(r))),
         }
     }
+
+    pub(crate) async fn execute_transaction(
+        &self,
+        statements: &[(&str, Vec<crate::Value<'_>>)],
+    ) -> trc::Result<()> {
+        let mut conn = self.conn_pool.get().await.map_err(into_error)?;
+        let trx = conn.transaction().await.map_err(into_error)?;
+
+        for (idx, (query, params)) in statements.iter().enumerate() {
+            let params = params
+                .iter()
+                .map(|v| v as &(dyn tokio_postgres::types::ToSql + Sync))
+                .collect::<Vec<_>>();
+            if let Err(err) = trx.execute(*query, params.as_slice()).await {
+                return Err(if matches!(err.code(), Some(&SqlState::UNIQUE_VIOLATION)) {
+                    trc::StoreEvent::AssertValueFailed
+                        .reason(err)
+                        .ctx(trc::Key::Id, idx as u64)
+                } else {
+                    into_error(err)
+                });
+            }
+        }
+
+        trx.commit().await.map_err(into_error)
+    }
 }
 
 impl ToSql for crate::Value<'_> {
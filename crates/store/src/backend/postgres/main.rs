@@ -6,7 +6,7 @@
 
 use std::time::Duration;
 
-use crate::{backend::postgres::tls::MakeRustlsConnect, *};
+use crate::{backend::postgres::tls::MakeRustlsConnect, write::compress::ValueCompression, *};
 
 use super::{into_error, PostgresStore};
 
@@ -63,6 +63,7 @@ impl PostgresStore {
                 )
             })
             .ok()?,
+            value_compression: ValueCompression::parse(config, &prefix),
         };
 
         if create_tables {
@@ -96,6 +97,7 @@ impl PostgresStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_TASK,
         ] {
             let table = char::from(table);
             conn.execute(
@@ -8,6 +8,8 @@ use std::fmt::Display;
 
 use deadpool_postgres::Pool;
 
+use crate::write::compress::ValueCompression;
+
 pub mod blob;
 pub mod lookup;
 pub mod main;
@@ -17,6 +19,7 @@ pub mod write;
 
 pub struct PostgresStore {
     pub(crate) conn_pool: Pool,
+    pub(crate) value_compression: ValueCompression,
 }
 
 #[inline(always)]
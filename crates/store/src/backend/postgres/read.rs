@@ -82,29 +82,40 @@ impl PostgresStore {
         let begin = params.begin.serialize(0);
         let end = params.end.serialize(0);
         let keys = if params.values { "k, v" } else { "k" };
+        let mut arg_num = 2;
+        let filter = params.value_filter_sql(|| {
+            arg_num += 1;
+            format!("${arg_num}")
+        });
 
         let s = conn
             .prepare_cached(&match (params.first, params.ascending) {
                 (true, true) => {
                     format!(
-                        "SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2 ORDER BY k ASC LIMIT 1"
+                        "SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2{filter} ORDER BY k ASC LIMIT 1"
                     )
                 }
                 (true, false) => {
                     format!(
-                    "SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2 ORDER BY k DESC LIMIT 1"
+                    "SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2{filter} ORDER BY k DESC LIMIT 1"
                 )
                 }
                 (false, true) => {
-                    format!("SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2 ORDER BY k ASC")
+                    format!("SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2{filter} ORDER BY k ASC")
                 }
                 (false, false) => {
-                    format!("SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2 ORDER BY k DESC")
+                    format!("SELECT {keys} FROM {table} WHERE k >= $1 AND k <= $2{filter} ORDER BY k DESC")
                 }
             })
             .await.map_err(into_error)?;
         let rows = conn
-            .query_raw(&s, &[&begin, &end])
+            .query_raw(
+                &s,
+                [&begin, &end]
+                    .into_iter()
+                    .chain(params.value_prefixes().iter())
+                    .collect::<Vec<_>>(),
+            )
             .await
             .map_err(into_error)?;
 
@@ -11,7 +11,7 @@ use rocksdb::{ColumnFamilyDescriptor, MergeOperands, OptimisticTransactionDB, Op
 use tokio::sync::oneshot;
 use utils::config::{utils::AsKey, Config};
 
-use crate::*;
+use crate::{write::compress::ValueCompression, *};
 
 use super::{RocksDbStore, CF_BLOBS};
 
@@ -90,6 +90,7 @@ impl RocksDbStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_TASK,
         ] {
             let cf_opts = Options::default();
             cfs.push(ColumnFamilyDescriptor::new(
@@ -139,6 +140,7 @@ impl RocksDbStore {
                     )
                 })
                 .ok()?,
+            value_compression: ValueCompression::parse(config, &prefix),
         })
     }
 
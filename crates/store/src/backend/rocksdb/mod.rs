@@ -8,7 +8,7 @@ use std::sync::Arc;
 
 use rocksdb::{BoundColumnFamily, MultiThreaded, OptimisticTransactionDB};
 
-use crate::{SUBSPACE_BLOBS, SUBSPACE_INDEXES, SUBSPACE_LOGS};
+use crate::{write::compress::ValueCompression, SUBSPACE_BLOBS, SUBSPACE_INDEXES, SUBSPACE_LOGS};
 
 pub mod blob;
 pub mod main;
@@ -34,6 +34,7 @@ impl CfHandle for OptimisticTransactionDB<MultiThreaded> {
 pub struct RocksDbStore {
     db: Arc<OptimisticTransactionDB<MultiThreaded>>,
     worker_pool: rayon::ThreadPool,
+    pub(crate) value_compression: ValueCompression,
 }
 
 #[inline(always)]
@@ -90,8 +90,7 @@ impl RocksDbStore {
                 let (key, value) = row.map_err(into_error)?;
                 if key.as_ref() < begin.as_slice()
                     || key.as_ref() > end.as_slice()
-                    || !cb(&key, &value)?
-                    || params.first
+                    || (params.matches_value(&value) && (!cb(&key, &value)? || params.first))
                 {
                     break;
                 }
@@ -97,6 +97,8 @@ impl S3Store {
             match response.status_code() {
                 200..=299 => return Ok(Some(response.to_vec())),
                 404 => return Ok(None),
+                // Range starts past the end of the object
+                416 => return Ok(Some(Vec::new())),
                 500..=599 if retries_left > 0 => {
                     // wait backoff
                     tokio::time::sleep(Duration::from_secs(
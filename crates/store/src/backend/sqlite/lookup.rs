@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use rusqlite::{types::FromSql, Row, Rows, ToSql};
+use rusqlite::{ffi, types::FromSql, Row, Rows, ToSql, TransactionBehavior};
 
 use crate::{IntoRows, QueryResult, QueryType, Value};
 
@@ -43,6 +43,45 @@ impl SqliteStore {
         })
         .await
     }
+
+    pub(crate) async fn execute_transaction(
+        &self,
+        statements: &[(&str, Vec<Value<'_>>)],
+    ) -> trc::Result<()> {
+        let mut conn = self.conn_pool.get().map_err(into_error)?;
+        self.spawn_worker(move || {
+            let trx = conn
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(into_error)?;
+
+            for (idx, (query, params)) in statements.iter().enumerate() {
+                let params = params
+                    .iter()
+                    .map(|v| v as &dyn rusqlite::types::ToSql)
+                    .collect::<Vec<_>>();
+                trx.prepare_cached(query)
+                    .and_then(|mut s| s.execute(params.as_slice()))
+                    .map_err(|err| {
+                        if matches!(&err, rusqlite::Error::SqliteFailure(code, _)
+                            if [
+                                ffi::SQLITE_CONSTRAINT_UNIQUE,
+                                ffi::SQLITE_CONSTRAINT_PRIMARYKEY,
+                            ]
+                            .contains(&code.extended_code))
+                        {
+                            trc::StoreEvent::AssertValueFailed
+                                .reason(err)
+                                .ctx(trc::Key::Id, idx as u64)
+                        } else {
+                            into_error(err)
+                        }
+                    })?;
+            }
+
+            trx.commit().map_err(into_error)
+        })
+        .await
+    }
 }
 
 impl ToSql for Value<'_> {
@@ -8,7 +8,7 @@ use r2d2::Pool;
 use tokio::sync::oneshot;
 use utils::config::{utils::AsKey, Config};
 
-use crate::*;
+use crate::{write::compress::ValueCompression, *};
 
 use super::{into_error, pool::SqliteConnectionManager, SqliteStore};
 
@@ -56,6 +56,7 @@ impl SqliteStore {
                     )
                 })
                 .ok()?,
+            value_compression: ValueCompression::parse(config, &prefix),
         };
 
         if let Err(err) = db.create_tables() {
@@ -80,6 +81,7 @@ impl SqliteStore {
                 .map_err(|err| {
                     into_error(err).ctx(trc::Key::Reason, "Failed to build worker pool")
                 })?,
+            value_compression: ValueCompression::default(),
         };
         db.create_tables()?;
         Ok(db)
@@ -107,6 +109,7 @@ impl SqliteStore {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_TASK,
         ] {
             let table = char::from(table);
             conn.execute(
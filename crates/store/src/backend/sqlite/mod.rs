@@ -8,6 +8,8 @@ use std::fmt::Display;
 
 use r2d2::Pool;
 
+use crate::write::compress::ValueCompression;
+
 use self::pool::SqliteConnectionManager;
 
 pub mod blob;
@@ -20,6 +22,7 @@ pub mod write;
 pub struct SqliteStore {
     pub(crate) conn_pool: Pool<SqliteConnectionManager>,
     pub(crate) worker_pool: rayon::ThreadPool,
+    pub(crate) value_compression: ValueCompression,
 }
 
 #[inline(always)]
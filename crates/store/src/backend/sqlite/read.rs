@@ -5,7 +5,7 @@
  */
 
 use roaring::RoaringBitmap;
-use rusqlite::OptionalExtension;
+use rusqlite::{params_from_iter, OptionalExtension};
 
 use crate::{
     write::{key::DeserializeBigEndian, BitmapClass, ValueClass},
@@ -84,30 +84,37 @@ impl SqliteStore {
             let begin = params.begin.serialize(0);
             let end = params.end.serialize(0);
             let keys = if params.values { "k, v" } else { "k" };
+            let filter = params.value_filter_sql(|| "?".to_string());
 
             let mut query = conn
                 .prepare_cached(&match (params.first, params.ascending) {
                     (true, true) => {
                         format!(
-                        "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k ASC LIMIT 1"
+                        "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k ASC LIMIT 1"
                     )
                     }
                     (true, false) => {
                         format!(
-                        "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k DESC LIMIT 1"
+                        "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k DESC LIMIT 1"
                     )
                     }
                     (false, true) => {
-                        format!("SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k ASC")
+                        format!("SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k ASC")
                     }
                     (false, false) => {
                         format!(
-                            "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ? ORDER BY k DESC"
+                            "SELECT {keys} FROM {table} WHERE k >= ? AND k <= ?{filter} ORDER BY k DESC"
                         )
                     }
                 })
                 .map_err(into_error)?;
-            let mut rows = query.query([&begin, &end]).map_err(into_error)?;
+            let mut rows = query
+                .query(params_from_iter(
+                    [&begin, &end]
+                        .into_iter()
+                        .chain(params.value_prefixes().iter()),
+                ))
+                .map_err(into_error)?;
 
             if params.values {
                 while let Some(row) = rows.next().map_err(into_error)? {
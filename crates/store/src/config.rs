@@ -87,6 +87,26 @@ impl Stores {
                 .unwrap_or(CompressionAlgo::None);
 
             match protocol.as_str() {
+                #[cfg(feature = "test_mode")]
+                "in-memory" => {
+                    // Reopening would discard all data
+                    if is_reload && self.stores.contains_key(&store_id) {
+                        continue;
+                    }
+
+                    let db = Store::from(
+                        crate::backend::in_memory::InMemoryStore::new().with_value_compression(
+                            crate::write::compress::ValueCompression::parse(config, prefix),
+                        ),
+                    );
+                    self.stores.insert(store_id.clone(), db.clone());
+                    self.fts_stores.insert(store_id.clone(), db.clone().into());
+                    self.blob_stores.insert(
+                        store_id.clone(),
+                        BlobStore::from(db.clone()).with_compression(compression_algo),
+                    );
+                    self.lookup_stores.insert(store_id, db.into());
+                }
                 #[cfg(feature = "rocks")]
                 "rocksdb" => {
                     // Avoid opening the same store twice
@@ -274,6 +294,7 @@ impl Stores {
                         let store = BlobStore {
                             backend: crate::BlobBackend::Composite(db.into()),
                             compression,
+                            tiering: None,
                         };
                         self.blob_stores.insert(id, store);
                     }
@@ -0,0 +1,890 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeSet,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use trc::AddContext;
+use utils::{codec::leb128::Leb128Reader, BLOB_HASH_LEN};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::{
+    write::{
+        key::DeserializeBigEndian, AnyClass, AnyKey, BatchBuilder, BitmapClass, BitmapHash,
+        MaybeDynamicId, Operation, TagValue, ValueClass,
+    },
+    BlobStore, IterateParams, Store, ValueKey, SUBSPACE_ACL, SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_BLOBS, SUBSPACE_BLOB_LINK,
+    SUBSPACE_BLOB_RESERVE, SUBSPACE_COUNTER, SUBSPACE_DIRECTORY, SUBSPACE_FTS_INDEX,
+    SUBSPACE_FTS_QUEUE, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_LOOKUP_VALUE, SUBSPACE_PROPERTY,
+    SUBSPACE_QUEUE_EVENT, SUBSPACE_QUEUE_MESSAGE, SUBSPACE_QUOTA, SUBSPACE_REPORT_IN,
+    SUBSPACE_REPORT_OUT, SUBSPACE_SETTINGS, SUBSPACE_TASK, SUBSPACE_TELEMETRY_INDEX,
+    SUBSPACE_TELEMETRY_METRIC, SUBSPACE_TELEMETRY_SPAN, U32_LEN, U64_LEN,
+};
+
+// Archive layout, all integers are big endian:
+//
+//   archive: MAGIC | VERSION | id (u64) | parent id (u64) | change id (u64) | section* | SECTION_END
+//   section: kind (u8) | subspace (u8) | entry* | END_OF_SECTION | entries (u64) | checksum (u64)
+//   entry:   key length (u32) | key | value length (u32) | value
+//
+// The checksum is the xxh3 hash of all entries in the section. Full backups
+// have a parent id of zero, incremental backups start with a SECTION_ACCOUNTS
+// listing the accounts whose data they replace.
+const MAGIC: &[u8] = b"STWBAK";
+const VERSION: u8 = 2;
+
+const SECTION_END: u8 = 0;
+const SECTION_VALUES: u8 = 1;
+const SECTION_KEYS: u8 = 2;
+const SECTION_COUNTERS: u8 = 3;
+const SECTION_BLOBS: u8 = 4;
+const SECTION_ACCOUNTS: u8 = 5;
+const END_OF_SECTION: u32 = u32::MAX;
+
+type KeyRange = (AnyKey<Vec<u8>>, AnyKey<Vec<u8>>);
+
+pub(crate) const MAX_BATCH_OPS: usize = 1000;
+pub(crate) const MAX_BATCH_SIZE: usize = 5_000_000;
+
+// Subspaces holding key-value pairs
+pub(crate) const VALUE_SUBSPACES: &[u8] = &[
+    SUBSPACE_ACL,
+    SUBSPACE_DIRECTORY,
+    SUBSPACE_FTS_QUEUE,
+    SUBSPACE_BLOB_RESERVE,
+    SUBSPACE_BLOB_LINK,
+    SUBSPACE_LOOKUP_VALUE,
+    SUBSPACE_PROPERTY,
+    SUBSPACE_SETTINGS,
+    SUBSPACE_QUEUE_MESSAGE,
+    SUBSPACE_QUEUE_EVENT,
+    SUBSPACE_REPORT_OUT,
+    SUBSPACE_REPORT_IN,
+    SUBSPACE_FTS_INDEX,
+    SUBSPACE_LOGS,
+    SUBSPACE_TELEMETRY_SPAN,
+    SUBSPACE_TELEMETRY_METRIC,
+    SUBSPACE_TELEMETRY_INDEX,
+    SUBSPACE_TASK,
+];
+
+// Subspaces holding keys only
+pub(crate) const KEY_SUBSPACES: &[u8] = &[
+    SUBSPACE_INDEXES,
+    SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG,
+    SUBSPACE_BITMAP_TEXT,
+];
+
+// Counters are stored differently by each backend
+pub(crate) const COUNTER_SUBSPACES: &[u8] = &[SUBSPACE_COUNTER, SUBSPACE_QUOTA];
+
+// Subspaces prefixed by the account id, incremental backups only include
+// the accounts with entries in the change log. All other subspaces are
+// written in full.
+const ACCOUNT_SUBSPACES: &[u8] = &[
+    SUBSPACE_PROPERTY,
+    SUBSPACE_FTS_INDEX,
+    SUBSPACE_LOGS,
+    SUBSPACE_INDEXES,
+    SUBSPACE_BITMAP_ID,
+    SUBSPACE_BITMAP_TAG,
+    SUBSPACE_BITMAP_TEXT,
+];
+
+// Principals and other shared objects are not tracked in the change log
+const SHARED_ACCOUNT_ID: u32 = u32::MAX;
+const PRINCIPAL_PREFIX: u8 = 2;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BackupStats {
+    pub keys: u64,
+    pub blobs: u64,
+    pub missing_blobs: u64,
+    pub accounts: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ArchiveHeader {
+    id: u64,
+    parent_id: u64,
+    change_id: u64,
+}
+
+// What an incremental backup needs to know about its parent archive
+struct ParentArchive {
+    header: ArchiveHeader,
+    principals: BTreeSet<u32>,
+    pending_accounts: BTreeSet<u32>,
+    links: LinkCursor,
+}
+
+// Walks the sorted blob links of the parent archive alongside those of the
+// store, blobs linked in the parent are not written again
+struct LinkCursor {
+    reader: ArchiveReader,
+    hash: Option<Vec<u8>>,
+}
+
+impl Store {
+    /// Writes all keys in the store and the blobs they link to a single
+    /// archive that can be restored into any backend. Each subspace is read
+    /// in a single pass, writes should be paused to obtain a consistent copy.
+    pub async fn backup(&self, blob_store: &BlobStore, path: &Path) -> trc::Result<BackupStats> {
+        self.write_archive(blob_store, path, None).await
+    }
+
+    /// Writes an archive with the changes made since the `parent` archive was
+    /// created. Accounts with entries in the change log are written in full,
+    /// subspaces that are not scoped to an account are always written in full
+    /// and only blobs that were not linked in the parent are included.
+    pub async fn backup_incremental(
+        &self,
+        blob_store: &BlobStore,
+        path: &Path,
+        parent: &Path,
+    ) -> trc::Result<BackupStats> {
+        self.write_archive(blob_store, path, Some(ParentArchive::open(parent)?))
+            .await
+    }
+
+    async fn write_archive(
+        &self,
+        blob_store: &BlobStore,
+        path: &Path,
+        mut parent: Option<ParentArchive>,
+    ) -> trc::Result<BackupStats> {
+        // The change log is read before anything else, changes written while
+        // the archive is created are included again in the next increment
+        let parent_change_id = parent.as_ref().map(|parent| parent.header.change_id);
+        let mut change_id = parent_change_id.unwrap_or_default();
+        let mut accounts = BTreeSet::new();
+        self.iterate(
+            IterateParams::new(min_key(SUBSPACE_LOGS), max_key(SUBSPACE_LOGS)).no_values(),
+            |key, _| {
+                let key_change_id = key.deserialize_be_u64(U32_LEN + 1)?;
+                if parent_change_id.is_some_and(|parent| key_change_id > parent) {
+                    accounts.insert(key.deserialize_be_u32(0)?);
+                }
+                change_id = change_id.max(key_change_id);
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        let mut writer = ArchiveWriter::create(
+            path,
+            ArchiveHeader {
+                id: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(1, |d| d.as_nanos() as u64),
+                parent_id: parent.as_ref().map_or(0, |parent| parent.header.id),
+                change_id,
+            },
+        )?;
+        let mut stats = BackupStats::default();
+        let mut blob_hashes: Vec<Vec<u8>> = Vec::new();
+
+        let accounts = if let Some(parent) = &parent {
+            // Accounts that were still being indexed or have been deleted
+            // since the parent was written are replaced as well
+            let mut principals = BTreeSet::new();
+            self.iterate(
+                IterateParams::new(
+                    AnyKey {
+                        subspace: SUBSPACE_DIRECTORY,
+                        key: vec![PRINCIPAL_PREFIX],
+                    },
+                    AnyKey {
+                        subspace: SUBSPACE_DIRECTORY,
+                        key: vec![PRINCIPAL_PREFIX + 1],
+                    },
+                )
+                .no_values(),
+                |key, _| {
+                    if let Some(account_id) = principal_id(key) {
+                        principals.insert(account_id);
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            accounts.extend(parent.pending_accounts.iter().copied());
+            accounts.extend(parent.principals.difference(&principals).copied());
+            accounts.insert(SHARED_ACCOUNT_ID);
+
+            writer.begin_section(SECTION_ACCOUNTS, 0)?;
+            for account_id in &accounts {
+                writer.write_entry(&account_id.to_be_bytes(), &[])?;
+            }
+            stats.accounts = writer.end_section()?;
+
+            Some(accounts)
+        } else {
+            None
+        };
+
+        for &subspace in VALUE_SUBSPACES {
+            writer.begin_section(SECTION_VALUES, subspace)?;
+            for (from, to) in key_ranges(subspace, accounts.as_ref()) {
+                self.iterate(IterateParams::new(from, to), |key, value| {
+                    // Links are sorted by hash, blobs are only written once
+                    if subspace == SUBSPACE_BLOB_LINK {
+                        if let Some(hash) = key.get(..BLOB_HASH_LEN) {
+                            if blob_hashes.last().is_none_or(|last| last != hash)
+                                && !parent
+                                    .as_mut()
+                                    .map_or(Ok(false), |parent| parent.links.contains(hash))?
+                            {
+                                blob_hashes.push(hash.to_vec());
+                            }
+                        }
+                    }
+
+                    writer.write_entry(key, value)?;
+                    Ok(true)
+                })
+                .await
+                .caused_by(trc::location!())?;
+            }
+            stats.keys += writer.end_section()?;
+        }
+
+        for &subspace in KEY_SUBSPACES {
+            writer.begin_section(SECTION_KEYS, subspace)?;
+            for (from, to) in key_ranges(subspace, accounts.as_ref()) {
+                self.iterate(IterateParams::new(from, to).no_values(), |key, _| {
+                    writer.write_entry(key, &[])?;
+                    Ok(true)
+                })
+                .await
+                .caused_by(trc::location!())?;
+            }
+            stats.keys += writer.end_section()?;
+        }
+
+        for &subspace in COUNTER_SUBSPACES {
+            let mut keys = Vec::new();
+            self.iterate(
+                IterateParams::new(min_key(subspace), max_key(subspace)).no_values(),
+                |key, _| {
+                    keys.push(key.to_vec());
+                    Ok(true)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            writer.begin_section(SECTION_COUNTERS, subspace)?;
+            for key in keys {
+                let value = self
+                    .get_counter(ValueKey::from(ValueClass::Any(AnyClass {
+                        subspace,
+                        key: key.clone(),
+                    })))
+                    .await
+                    .caused_by(trc::location!())?;
+                writer.write_entry(&key, &value.to_be_bytes())?;
+            }
+            stats.keys += writer.end_section()?;
+        }
+
+        // Blobs are read from the blob store so that they can be restored into
+        // a different one
+        writer.begin_section(SECTION_BLOBS, SUBSPACE_BLOBS)?;
+        for hash in blob_hashes {
+            if let Some(blob) = blob_store
+                .get_blob(&hash, 0..usize::MAX)
+                .await
+                .caused_by(trc::location!())?
+            {
+                writer.write_entry(&hash, &blob)?;
+            } else {
+                stats.missing_blobs += 1;
+            }
+        }
+        stats.blobs = writer.end_section()?;
+        writer.finish()?;
+
+        Ok(stats)
+    }
+
+    /// Rebuilds an empty store from an archive written by [`Store::backup`].
+    /// All checksums are verified before anything is written.
+    pub async fn restore(&self, blob_store: &BlobStore, path: &Path) -> trc::Result<BackupStats> {
+        self.restore_chain(blob_store, &[path]).await
+    }
+
+    /// Rebuilds an empty store from a full backup followed by the incremental
+    /// backups written after it, in order. The chain is verified before
+    /// anything is written, each increment must have been created from the
+    /// archive that precedes it.
+    pub async fn restore_chain<P: AsRef<Path>>(
+        &self,
+        blob_store: &BlobStore,
+        paths: &[P],
+    ) -> trc::Result<BackupStats> {
+        let mut readers = Vec::with_capacity(paths.len());
+        let mut parent_id = 0;
+        for path in paths {
+            let mut reader = ArchiveReader::open(path.as_ref())?;
+            reader.verify()?;
+
+            if reader.header.parent_id != parent_id {
+                return Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details(if parent_id == 0 {
+                        "The first archive is not a full backup"
+                    } else {
+                        "Incremental backup was not created from the previous archive"
+                    })
+                    .ctx(trc::Key::Id, reader.header.parent_id)
+                    .ctx(trc::Key::Value, parent_id)
+                    .ctx(trc::Key::Path, path.as_ref().display().to_string()));
+            }
+            parent_id = reader.header.id;
+            readers.push(reader);
+        }
+
+        for &subspace in VALUE_SUBSPACES
+            .iter()
+            .chain(KEY_SUBSPACES)
+            .chain(COUNTER_SUBSPACES)
+        {
+            let mut is_empty = true;
+            self.iterate(
+                IterateParams::new(min_key(subspace), max_key(subspace))
+                    .no_values()
+                    .only_first(),
+                |_, _| {
+                    is_empty = false;
+                    Ok(false)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            if !is_empty {
+                return Err(trc::StoreEvent::NotSupported
+                    .into_err()
+                    .details("Backups can only be restored into an empty store")
+                    .ctx(trc::Key::Id, char::from(subspace).to_string()));
+            }
+        }
+
+        let mut stats = BackupStats::default();
+        for reader in readers {
+            self.apply_archive(blob_store, reader, &mut stats).await?;
+        }
+
+        Ok(stats)
+    }
+
+    async fn apply_archive(
+        &self,
+        blob_store: &BlobStore,
+        mut reader: ArchiveReader,
+        stats: &mut BackupStats,
+    ) -> trc::Result<()> {
+        let is_incremental = reader.header.parent_id != 0;
+        let mut batch = BatchBuilder::new();
+        let mut batch_size = 0;
+
+        while let Some((kind, subspace)) = reader.next_section()? {
+            // Increments contain the whole subspace unless it is scoped to an
+            // account, in which case the account's keys were removed below
+            if is_incremental
+                && matches!(kind, SECTION_VALUES | SECTION_KEYS | SECTION_COUNTERS)
+                && !ACCOUNT_SUBSPACES.contains(&subspace)
+            {
+                self.delete_range(min_key(subspace), max_key(subspace))
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+
+            while let Some((key, value)) = reader.next_entry()? {
+                batch_size += key.len() + value.len();
+
+                match kind {
+                    SECTION_VALUES => {
+                        batch.set(ValueClass::Any(AnyClass { subspace, key }), value);
+                    }
+                    SECTION_KEYS => {
+                        key_operation(&mut batch, subspace, &key, true)?;
+                    }
+                    SECTION_COUNTERS => {
+                        let value = value.as_slice().try_into().map_err(|_| {
+                            trc::Error::corrupted_key(&key, Some(&value), trc::location!())
+                        })?;
+                        batch.add(
+                            ValueClass::Any(AnyClass { subspace, key }),
+                            i64::from_be_bytes(value),
+                        );
+                    }
+                    SECTION_BLOBS => {
+                        blob_store
+                            .put_blob(&key, &value)
+                            .await
+                            .caused_by(trc::location!())?;
+                        stats.blobs += 1;
+                        continue;
+                    }
+                    SECTION_ACCOUNTS => {
+                        let account_id = key.as_slice().deserialize_be_u32(0)?;
+                        for &subspace in ACCOUNT_SUBSPACES {
+                            let (from, to) = account_range(subspace, account_id);
+                            self.delete_range(from, to)
+                                .await
+                                .caused_by(trc::location!())?;
+                        }
+                        stats.accounts += 1;
+                        continue;
+                    }
+                    _ => {
+                        return Err(trc::StoreEvent::DataCorruption
+                            .into_err()
+                            .details("Unknown archive section")
+                            .ctx(trc::Key::Type, kind as u64));
+                    }
+                }
+                stats.keys += 1;
+
+                if batch.ops.len() >= MAX_BATCH_OPS || batch_size >= MAX_BATCH_SIZE {
+                    self.write(batch.build_batch())
+                        .await
+                        .caused_by(trc::location!())?;
+                    batch_size = 0;
+                }
+            }
+        }
+
+        // Pending writes are flushed before the next increment clears them
+        if !batch.is_empty() {
+            self.write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+}
+
+// Keys without values are written or removed through the operations that
+// create them
+pub(crate) fn key_operation(
+    batch: &mut BatchBuilder,
+    subspace: u8,
+    key: &[u8],
+    set: bool,
+) -> trc::Result<()> {
+    const BM_MARKER: u8 = 1 << 7;
+
+    let corrupted = || trc::Error::corrupted_key(key, None, trc::location!());
+    let account_id = key.deserialize_be_u32(0)?;
+    let document_id_pos = key
+        .len()
+        .checked_sub(U32_LEN)
+        .filter(|pos| *pos > U32_LEN)
+        .ok_or_else(corrupted)?;
+    let document_id = key.deserialize_be_u32(document_id_pos)?;
+
+    let (collection, op) = match subspace {
+        SUBSPACE_INDEXES => (
+            key[U32_LEN],
+            Operation::Index {
+                field: *key.get(U32_LEN + 1).ok_or_else(corrupted)?,
+                key: key
+                    .get(U32_LEN + 2..document_id_pos)
+                    .ok_or_else(corrupted)?
+                    .to_vec(),
+                set,
+            },
+        ),
+        SUBSPACE_BITMAP_ID => (
+            key[U32_LEN],
+            Operation::Bitmap {
+                class: BitmapClass::DocumentIds,
+                set,
+            },
+        ),
+        SUBSPACE_BITMAP_TAG => {
+            let field = *key.get(U32_LEN + 1).ok_or_else(corrupted)?;
+            let value = key
+                .get(U32_LEN + 2..document_id_pos)
+                .ok_or_else(corrupted)?;
+
+            (
+                key[U32_LEN],
+                Operation::Bitmap {
+                    class: BitmapClass::Tag {
+                        field: field & !BM_MARKER,
+                        value: if field & BM_MARKER != 0 {
+                            TagValue::Text(value.to_vec())
+                        } else {
+                            TagValue::Id(MaybeDynamicId::Static(
+                                value.read_leb128::<u32>().ok_or_else(corrupted)?.0,
+                            ))
+                        },
+                    },
+                    set,
+                },
+            )
+        }
+        SUBSPACE_BITMAP_TEXT => {
+            // The token is followed by the collection and field, tokens
+            // shorter than 8 bytes are stored as-is without a length
+            let mut hash = [0u8; 8];
+            let len = match document_id_pos.checked_sub(U32_LEN + 2) {
+                Some(len @ 0..=7) => {
+                    hash[..len].copy_from_slice(&key[U32_LEN..U32_LEN + len]);
+                    len as u8
+                }
+                Some(9) => {
+                    hash.copy_from_slice(&key[U32_LEN..U32_LEN + 8]);
+                    key[U32_LEN + 8]
+                }
+                _ => return Err(corrupted()),
+            };
+
+            (
+                key[document_id_pos - 2],
+                Operation::Bitmap {
+                    class: BitmapClass::Text {
+                        field: key[document_id_pos - 1],
+                        token: BitmapHash { hash, len },
+                    },
+                    set,
+                },
+            )
+        }
+        _ => return Err(corrupted()),
+    };
+
+    batch
+        .with_account_id(account_id)
+        .with_collection(collection)
+        .update_document(document_id)
+        .ops
+        .push(op);
+
+    Ok(())
+}
+
+fn key_ranges(subspace: u8, accounts: Option<&BTreeSet<u32>>) -> Vec<KeyRange> {
+    match accounts {
+        Some(accounts) if ACCOUNT_SUBSPACES.contains(&subspace) => accounts
+            .iter()
+            .map(|account_id| account_range(subspace, *account_id))
+            .collect(),
+        _ => vec![(min_key(subspace), max_key(subspace))],
+    }
+}
+
+fn account_range(subspace: u8, account_id: u32) -> KeyRange {
+    let mut to = account_id.to_be_bytes().to_vec();
+    to.extend_from_slice(&[u8::MAX; 64]);
+
+    (
+        AnyKey {
+            subspace,
+            key: account_id.to_be_bytes().to_vec(),
+        },
+        AnyKey { subspace, key: to },
+    )
+}
+
+fn principal_id(key: &[u8]) -> Option<u32> {
+    key.split_first()
+        .filter(|(prefix, _)| **prefix == PRINCIPAL_PREFIX)
+        .and_then(|(_, id)| id.read_leb128::<u32>())
+        .map(|(id, _)| id)
+}
+
+pub(crate) fn min_key(subspace: u8) -> AnyKey<Vec<u8>> {
+    AnyKey {
+        subspace,
+        key: vec![0u8],
+    }
+}
+
+pub(crate) fn max_key(subspace: u8) -> AnyKey<Vec<u8>> {
+    AnyKey {
+        subspace,
+        key: vec![u8::MAX; 64],
+    }
+}
+
+struct ArchiveWriter {
+    file: BufWriter<File>,
+    hasher: Xxh3,
+    entries: u64,
+}
+
+impl ArchiveWriter {
+    fn create(path: &Path, header: ArchiveHeader) -> trc::Result<Self> {
+        let mut file = BufWriter::new(File::create(path).map_err(into_error)?);
+        file.write_all(MAGIC).map_err(into_error)?;
+        file.write_all(&[VERSION]).map_err(into_error)?;
+        for value in [header.id, header.parent_id, header.change_id] {
+            file.write_all(&value.to_be_bytes()).map_err(into_error)?;
+        }
+
+        Ok(Self {
+            file,
+            hasher: Xxh3::new(),
+            entries: 0,
+        })
+    }
+
+    fn begin_section(&mut self, kind: u8, subspace: u8) -> trc::Result<()> {
+        self.hasher.reset();
+        self.entries = 0;
+        self.file.write_all(&[kind, subspace]).map_err(into_error)
+    }
+
+    fn write_entry(&mut self, key: &[u8], value: &[u8]) -> trc::Result<()> {
+        for bytes in [key, value] {
+            let len = (bytes.len() as u32).to_be_bytes();
+            self.hasher.update(&len);
+            self.hasher.update(bytes);
+            self.file.write_all(&len).map_err(into_error)?;
+            self.file.write_all(bytes).map_err(into_error)?;
+        }
+        self.entries += 1;
+
+        Ok(())
+    }
+
+    fn end_section(&mut self) -> trc::Result<u64> {
+        self.file
+            .write_all(&END_OF_SECTION.to_be_bytes())
+            .map_err(into_error)?;
+        self.file
+            .write_all(&self.entries.to_be_bytes())
+            .map_err(into_error)?;
+        self.file
+            .write_all(&self.hasher.digest().to_be_bytes())
+            .map_err(into_error)?;
+
+        Ok(self.entries)
+    }
+
+    fn finish(mut self) -> trc::Result<()> {
+        self.file.write_all(&[SECTION_END]).map_err(into_error)?;
+        self.file
+            .into_inner()
+            .map_err(|err| into_error(err.into_error()))?
+            .sync_all()
+            .map_err(into_error)
+    }
+}
+
+struct ArchiveReader {
+    file: BufReader<File>,
+    header: ArchiveHeader,
+    hasher: Xxh3,
+    entries: u64,
+}
+
+impl ArchiveReader {
+    fn open(path: &Path) -> trc::Result<Self> {
+        let mut reader = Self {
+            file: BufReader::new(File::open(path).map_err(into_error)?),
+            header: ArchiveHeader::default(),
+            hasher: Xxh3::new(),
+            entries: 0,
+        };
+        reader.read_header()?;
+
+        Ok(reader)
+    }
+
+    fn read_header(&mut self) -> trc::Result<()> {
+        let mut header = [0u8; MAGIC.len() + 1 + U64_LEN * 3];
+        self.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            Err(trc::StoreEvent::DataCorruption
+                .into_err()
+                .details("Not a backup archive"))
+        } else if header[MAGIC.len()] != VERSION {
+            Err(trc::StoreEvent::NotSupported
+                .into_err()
+                .details("Unsupported backup archive version")
+                .ctx(trc::Key::Version, header[MAGIC.len()] as u64))
+        } else {
+            let fields = &header[MAGIC.len() + 1..];
+            self.header = ArchiveHeader {
+                id: fields.deserialize_be_u64(0)?,
+                parent_id: fields.deserialize_be_u64(U64_LEN)?,
+                change_id: fields.deserialize_be_u64(U64_LEN * 2)?,
+            };
+            Ok(())
+        }
+    }
+
+    /// Reads the whole archive validating the checksum of each section, then
+    /// rewinds it.
+    fn verify(&mut self) -> trc::Result<()> {
+        while self.next_section()?.is_some() {
+            while self.next_entry()?.is_some() {}
+        }
+
+        self.file.seek(SeekFrom::Start(0)).map_err(into_error)?;
+        self.read_header()
+    }
+
+    fn next_section(&mut self) -> trc::Result<Option<(u8, u8)>> {
+        let mut kind = [0u8; 1];
+        self.read_exact(&mut kind)?;
+        if kind[0] == SECTION_END {
+            return Ok(None);
+        }
+
+        let mut subspace = [0u8; 1];
+        self.read_exact(&mut subspace)?;
+        self.hasher.reset();
+        self.entries = 0;
+
+        Ok(Some((kind[0], subspace[0])))
+    }
+
+    fn next_entry(&mut self) -> trc::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let key_len = self.read_u32()?;
+        if key_len == END_OF_SECTION {
+            let entries = self.read_u64()?;
+            let checksum = self.read_u64()?;
+            return if entries == self.entries && checksum == self.hasher.digest() {
+                Ok(None)
+            } else {
+                Err(trc::StoreEvent::DataCorruption
+                    .into_err()
+                    .details("Backup archive checksum mismatch")
+                    .ctx(trc::Key::Total, entries))
+            };
+        }
+        let key = self.read_bytes(key_len)?;
+        let value_len = self.read_u32()?;
+        let value = self.read_bytes(value_len)?;
+        self.entries += 1;
+
+        Ok(Some((key, value)))
+    }
+
+    fn read_u32(&mut self) -> trc::Result<u32> {
+        let mut bytes = [0u8; 4];
+        self.read_exact(&mut bytes)?;
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> trc::Result<u64> {
+        let mut bytes = [0u8; 8];
+        self.read_exact(&mut bytes)?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn read_bytes(&mut self, len: u32) -> trc::Result<Vec<u8>> {
+        let mut bytes = vec![0u8; len as usize];
+        self.read_exact(&mut bytes)?;
+        self.hasher.update(&len.to_be_bytes());
+        self.hasher.update(&bytes);
+        Ok(bytes)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> trc::Result<()> {
+        self.file.read_exact(buf).map_err(|err| {
+            if err.kind() == ErrorKind::UnexpectedEof {
+                trc::StoreEvent::DataCorruption
+                    .into_err()
+                    .details("Backup archive is truncated")
+            } else {
+                into_error(err)
+            }
+        })
+    }
+}
+
+impl ParentArchive {
+    // Reads the sections that precede the blob links, the checksums of the
+    // parent are verified when the chain is restored
+    fn open(path: &Path) -> trc::Result<Self> {
+        let mut reader = ArchiveReader::open(path)?;
+        let mut principals = BTreeSet::new();
+        let mut pending_accounts = BTreeSet::new();
+
+        loop {
+            match reader.next_section()? {
+                Some((SECTION_VALUES, SUBSPACE_BLOB_LINK)) => break,
+                Some((kind, subspace)) => {
+                    while let Some((key, _)) = reader.next_entry()? {
+                        match (kind, subspace) {
+                            (SECTION_VALUES, SUBSPACE_DIRECTORY) => {
+                                principals.extend(principal_id(&key));
+                            }
+                            (SECTION_VALUES, SUBSPACE_FTS_QUEUE) => {
+                                pending_accounts
+                                    .insert(key.as_slice().deserialize_be_u32(U64_LEN)?);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                None => {
+                    return Err(trc::StoreEvent::DataCorruption
+                        .into_err()
+                        .details("Backup archive has no blob links")
+                        .ctx(trc::Key::Path, path.display().to_string()));
+                }
+            }
+        }
+
+        let mut links = LinkCursor { reader, hash: None };
+        links.next()?;
+
+        Ok(Self {
+            header: links.reader.header,
+            principals,
+            pending_accounts,
+            links,
+        })
+    }
+}
+
+impl LinkCursor {
+    // Hashes have to be looked up in ascending order
+    fn contains(&mut self, hash: &[u8]) -> trc::Result<bool> {
+        while let Some(current) = &self.hash {
+            match current.as_slice().cmp(hash) {
+                Ordering::Less => self.next()?,
+                Ordering::Equal => return Ok(true),
+                Ordering::Greater => return Ok(false),
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn next(&mut self) -> trc::Result<()> {
+        self.hash = self.reader.next_entry()?.map(|(mut key, _)| {
+            key.truncate(BLOB_HASH_LEN);
+            key
+        });
+        Ok(())
+    }
+}
+
+fn into_error(err: std::io::Error) -> trc::Error {
+    trc::StoreEvent::FilesystemError.reason(err)
+}
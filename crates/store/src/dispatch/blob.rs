@@ -4,15 +4,41 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use std::{borrow::Cow, ops::Range, time::Instant};
+use std::{borrow::Cow, ops::Range, sync::Arc, time::Instant};
 
 use trc::{AddContext, StoreEvent};
 use utils::config::utils::ParseValue;
 
-use crate::{BlobBackend, BlobStore, CompressionAlgo, Store};
+use crate::{write::tier::BlobTiering, BlobBackend, BlobStore, CompressionAlgo, Store};
+
+// Maximum number of bytes held in memory when streaming a blob
+pub const BLOB_CHUNK_SIZE: usize = 1024 * 1024;
+
+pub struct BlobChunks {
+    store: BlobStore,
+    key: Vec<u8>,
+    offset: usize,
+    end: usize,
+    chunk_size: usize,
+}
 
 impl BlobStore {
     pub async fn get_blob(&self, key: &[u8], range: Range<usize>) -> trc::Result<Option<Vec<u8>>> {
+        // Blobs missing from the primary store may have been moved to the secondary tier
+        match (
+            self.get_primary_blob(key, range.clone()).await,
+            &self.tiering,
+        ) {
+            (Ok(None), Some(tiering)) => tiering.get_blob(key, range).await,
+            (result, _) => result,
+        }
+    }
+
+    pub(crate) async fn get_primary_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
         let read_range = match self.compression {
             CompressionAlgo::None => range.clone(),
             CompressionAlgo::Lz4 => 0..usize::MAX,
@@ -30,6 +56,8 @@ impl BlobStore {
                 Store::MySQL(store) => store.get_blob(key, read_range).await,
                 #[cfg(feature = "rocks")]
                 Store::RocksDb(store) => store.get_blob(key, read_range).await,
+                #[cfg(feature = "test_mode")]
+                Store::InMemory(store) => store.get_blob(key, read_range).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Store::SQLReadReplica(store) => store.get_blob(key, read_range).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -77,18 +105,39 @@ impl BlobStore {
             _ => return result,
         };
 
-        if range.end > decompressed.len() {
+        let size = decompressed.len();
+        if range.start == 0 && range.end >= size {
             Ok(Some(decompressed))
         } else {
             Ok(Some(
                 decompressed
-                    .get(range.start..range.end)
+                    .get(range.start.min(size)..range.end.min(size))
                     .unwrap_or_default()
                     .to_vec(),
             ))
         }
     }
 
+    // Compressed blobs have to be read in full to obtain any range, so they
+    // are not worth reading in chunks.
+    pub fn has_range_reads(&self) -> bool {
+        matches!(self.compression, CompressionAlgo::None)
+    }
+
+    pub fn get_blob_chunks(&self, key: &[u8], range: Range<usize>) -> BlobChunks {
+        BlobChunks {
+            store: self.clone(),
+            key: key.to_vec(),
+            offset: range.start,
+            end: range.end,
+            chunk_size: if self.has_range_reads() {
+                BLOB_CHUNK_SIZE
+            } else {
+                usize::MAX
+            },
+        }
+    }
+
     pub async fn put_blob(&self, key: &[u8], data: &[u8]) -> trc::Result<()> {
         let data: Cow<[u8]> = match self.compression {
             CompressionAlgo::None => data.into(),
@@ -112,6 +161,8 @@ impl BlobStore {
                 Store::MySQL(store) => store.put_blob(key, data.as_ref()).await,
                 #[cfg(feature = "rocks")]
                 Store::RocksDb(store) => store.put_blob(key, data.as_ref()).await,
+                #[cfg(feature = "test_mode")]
+                Store::InMemory(store) => store.put_blob(key, data.as_ref()).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Store::SQLReadReplica(store) => store.put_blob(key, data.as_ref()).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -137,6 +188,15 @@ impl BlobStore {
     }
 
     pub async fn delete_blob(&self, key: &[u8]) -> trc::Result<bool> {
+        let deleted = self.delete_primary_blob(key).await?;
+        if let Some(tiering) = &self.tiering {
+            Ok(tiering.store.delete_primary_blob(key).await? || deleted)
+        } else {
+            Ok(deleted)
+        }
+    }
+
+    pub(crate) async fn delete_primary_blob(&self, key: &[u8]) -> trc::Result<bool> {
         let start_time = Instant::now();
         let result = match &self.backend {
             BlobBackend::Store(store) => match store {
@@ -150,6 +210,8 @@ impl BlobStore {
                 Store::MySQL(store) => store.delete_blob(key).await,
                 #[cfg(feature = "rocks")]
                 Store::RocksDb(store) => store.delete_blob(key).await,
+                #[cfg(feature = "test_mode")]
+                Store::InMemory(store) => store.delete_blob(key).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Store::SQLReadReplica(store) => store.delete_blob(key).await,
                 Store::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -177,6 +239,15 @@ impl BlobStore {
         Self {
             backend: self.backend,
             compression,
+            tiering: self.tiering,
+        }
+    }
+
+    pub fn with_tiering(self, tiering: BlobTiering) -> Self {
+        Self {
+            backend: self.backend,
+            compression: self.compression,
+            tiering: Some(Arc::new(tiering)),
         }
     }
 }
@@ -203,3 +274,30 @@ impl ParseValue for CompressionAlgo {
         }
     }
 }
+
+impl BlobChunks {
+    // Returns the next chunk of the range, or None once the range
+    // or the blob are exhausted.
+    pub async fn next(&mut self) -> trc::Result<Option<Vec<u8>>> {
+        if self.offset >= self.end {
+            return Ok(None);
+        }
+
+        let end = self.offset.saturating_add(self.chunk_size).min(self.end);
+        match self.store.get_blob(&self.key, self.offset..end).await? {
+            Some(chunk) if !chunk.is_empty() => {
+                // Short reads happen at the end of the blob
+                self.offset = if chunk.len() < end - self.offset {
+                    self.end
+                } else {
+                    end
+                };
+                Ok(Some(chunk))
+            }
+            _ => {
+                self.offset = self.end;
+                Ok(None)
+            }
+        }
+    }
+}
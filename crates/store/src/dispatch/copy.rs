@@ -0,0 +1,427 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    cmp::Ordering,
+    time::{Duration, Instant},
+};
+
+use trc::{AddContext, StoreEvent};
+use utils::{BlobHash, BLOB_HASH_LEN};
+
+use crate::{
+    write::{AnyClass, AnyKey, BatchBuilder, ValueClass},
+    BlobStore, IterateParams, Store, ValueKey, SUBSPACE_BLOB_LINK,
+};
+
+use super::backup::{
+    key_operation, max_key, COUNTER_SUBSPACES, KEY_SUBSPACES, MAX_BATCH_OPS, MAX_BATCH_SIZE,
+    VALUE_SUBSPACES,
+};
+
+// Keys read from the source at a time
+const CHUNK_KEYS: usize = 10_000;
+const CHUNK_SIZE: usize = 50_000_000;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMode {
+    /// Writes every key and linked blob, the destination should be empty
+    Full,
+    /// Writes the keys that differ and removes those missing from the
+    /// source, meant to be run with writes paused after a full copy
+    Delta,
+    /// Compares both stores without writing anything
+    Verify,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyStats {
+    pub keys: u64,
+    pub bytes: u64,
+    pub blobs: u64,
+    pub mismatches: u64,
+    pub missing_blobs: u64,
+    pub corrupted_blobs: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    Value,
+    KeyOnly,
+    Counter,
+}
+
+type Entries = Vec<(Vec<u8>, Vec<u8>)>;
+
+struct StoreCopy<'x> {
+    source: &'x Store,
+    dest: &'x Store,
+    mode: CopyMode,
+    stats: CopyStats,
+    batch: BatchBuilder,
+    batch_size: usize,
+    blob_hashes: Vec<Vec<u8>>,
+    started: Instant,
+    last_progress: Instant,
+}
+
+/// Copies all keys in `source` and the blobs they link to `dest`, which can
+/// use a different backend. Subspaces are read in key order in chunks that
+/// are compared against the destination, so a full copy can be made while
+/// the source is online and a delta pass with writes paused then brings the
+/// destination up to date.
+pub async fn copy_store(
+    source: &Store,
+    dest: &Store,
+    blob_source: &BlobStore,
+    blob_dest: &BlobStore,
+    mode: CopyMode,
+) -> trc::Result<CopyStats> {
+    let mut copy = StoreCopy {
+        source,
+        dest,
+        mode,
+        stats: CopyStats::default(),
+        batch: BatchBuilder::new(),
+        batch_size: 0,
+        blob_hashes: Vec::new(),
+        started: Instant::now(),
+        last_progress: Instant::now(),
+    };
+
+    for (subspaces, kind) in [
+        (VALUE_SUBSPACES, KeyKind::Value),
+        (KEY_SUBSPACES, KeyKind::KeyOnly),
+        (COUNTER_SUBSPACES, KeyKind::Counter),
+    ] {
+        for &subspace in subspaces {
+            copy.copy_subspace(subspace, kind).await?;
+        }
+    }
+    copy.flush(true).await?;
+    copy.copy_blobs(blob_source, blob_dest).await?;
+
+    trc::event!(
+        Store(StoreEvent::CopyCompleted),
+        Type = mode.as_str(),
+        Total = copy.stats.keys,
+        Size = copy.stats.bytes,
+        Updated = copy.stats.mismatches,
+        Elapsed = copy.started.elapsed(),
+    );
+
+    Ok(copy.stats)
+}
+
+impl StoreCopy<'_> {
+    async fn copy_subspace(&mut self, subspace: u8, kind: KeyKind) -> trc::Result<()> {
+        let mut from = vec![0u8];
+
+        loop {
+            let (source, has_more) = read_chunk(self.source, subspace, kind, &from, None).await?;
+            let to = match source.last() {
+                Some((last_key, _)) if has_more => last_key.clone(),
+                _ => max_key(subspace).key,
+            };
+            let dest = if self.mode != CopyMode::Full {
+                read_chunk(self.dest, subspace, kind, &from, Some(&to))
+                    .await?
+                    .0
+            } else {
+                Vec::new()
+            };
+
+            // Both chunks are sorted, keys only found in the destination
+            // were removed from the source
+            let (mut source_pos, mut dest_pos) = (0, 0);
+            loop {
+                let order = match (source.get(source_pos), dest.get(dest_pos)) {
+                    (Some((source_key, _)), Some((dest_key, _))) => source_key.cmp(dest_key),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => break,
+                };
+
+                match order {
+                    Ordering::Less => {
+                        let (key, value) = &source[source_pos];
+                        self.copy_key(subspace, kind, key, value, None).await?;
+                        source_pos += 1;
+                    }
+                    Ordering::Equal => {
+                        let (key, value) = &source[source_pos];
+                        let (_, existing) = &dest[dest_pos];
+                        self.copy_key(subspace, kind, key, value, Some(existing.as_slice()))
+                            .await?;
+                        source_pos += 1;
+                        dest_pos += 1;
+                    }
+                    Ordering::Greater => {
+                        let (key, _) = &dest[dest_pos];
+                        self.remove_key(subspace, kind, key).await?;
+                        dest_pos += 1;
+                    }
+                }
+            }
+
+            if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+                self.last_progress = Instant::now();
+                let elapsed = self.started.elapsed();
+                trc::event!(
+                    Store(StoreEvent::CopyProgress),
+                    Id = char::from(subspace).to_string(),
+                    Total = self.stats.keys,
+                    Size = self.stats.bytes,
+                    Value = self.stats.keys / elapsed.as_secs().max(1),
+                    Elapsed = elapsed,
+                );
+            }
+
+            if has_more {
+                from = to;
+                from.push(0);
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn copy_key(
+        &mut self,
+        subspace: u8,
+        kind: KeyKind,
+        key: &[u8],
+        value: &[u8],
+        existing: Option<&[u8]>,
+    ) -> trc::Result<()> {
+        self.stats.keys += 1;
+        self.stats.bytes += (key.len() + value.len()) as u64;
+
+        // Only blobs linked since the last pass are copied again
+        if subspace == SUBSPACE_BLOB_LINK && (self.mode != CopyMode::Delta || existing.is_none()) {
+            if let Some(hash) = key.get(..BLOB_HASH_LEN) {
+                if self.blob_hashes.last().is_none_or(|last| last != hash) {
+                    self.blob_hashes.push(hash.to_vec());
+                }
+            }
+        }
+
+        if existing.is_some_and(|existing| existing == value) {
+            return Ok(());
+        } else if self.mode != CopyMode::Full {
+            self.mismatch(subspace, key);
+
+            if self.mode == CopyMode::Verify {
+                return Ok(());
+            }
+        }
+
+        let class = ValueClass::Any(AnyClass {
+            subspace,
+            key: key.to_vec(),
+        });
+        match kind {
+            KeyKind::Value => {
+                self.batch.set(class, value.to_vec());
+            }
+            KeyKind::KeyOnly => {
+                key_operation(&mut self.batch, subspace, key, true)?;
+            }
+            KeyKind::Counter => {
+                // Counters can only be incremented
+                self.batch.add(
+                    class,
+                    counter_value(value) - existing.map_or(0, counter_value),
+                );
+            }
+        }
+        self.batch_size += key.len() + value.len();
+        self.flush(false).await
+    }
+
+    async fn remove_key(&mut self, subspace: u8, kind: KeyKind, key: &[u8]) -> trc::Result<()> {
+        self.mismatch(subspace, key);
+        if self.mode == CopyMode::Verify {
+            return Ok(());
+        }
+
+        match kind {
+            KeyKind::Value | KeyKind::Counter => {
+                self.batch.clear(ValueClass::Any(AnyClass {
+                    subspace,
+                    key: key.to_vec(),
+                }));
+            }
+            KeyKind::KeyOnly => {
+                key_operation(&mut self.batch, subspace, key, false)?;
+            }
+        }
+        self.batch_size += key.len();
+        self.flush(false).await
+    }
+
+    async fn copy_blobs(
+        &mut self,
+        blob_source: &BlobStore,
+        blob_dest: &BlobStore,
+    ) -> trc::Result<()> {
+        for hash in std::mem::take(&mut self.blob_hashes) {
+            // Blobs are verified where they are read from
+            let blob_store = if self.mode == CopyMode::Verify {
+                blob_dest
+            } else {
+                blob_source
+            };
+            let Some(blob) = blob_store
+                .get_blob(&hash, 0..usize::MAX)
+                .await
+                .caused_by(trc::location!())?
+            else {
+                self.stats.missing_blobs += 1;
+                trc::event!(
+                    Store(StoreEvent::CopyMismatch),
+                    Details = "Blob not found",
+                    Id = char::from(SUBSPACE_BLOB_LINK).to_string(),
+                    Key = hash,
+                );
+                continue;
+            };
+
+            // Blobs that do not match their hash are still copied, the
+            // destination mirrors the source
+            if BlobHash::from(blob.as_slice()).as_slice() != hash.as_slice() {
+                self.stats.corrupted_blobs += 1;
+                trc::event!(
+                    Store(StoreEvent::CopyMismatch),
+                    Details = "Blob does not match its hash",
+                    Id = char::from(SUBSPACE_BLOB_LINK).to_string(),
+                    Key = hash.clone(),
+                );
+            }
+
+            if self.mode != CopyMode::Verify {
+                blob_dest
+                    .put_blob(&hash, &blob)
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+            self.stats.blobs += 1;
+            self.stats.bytes += blob.len() as u64;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self, force: bool) -> trc::Result<()> {
+        if (force && !self.batch.is_empty())
+            || self.batch.ops.len() >= MAX_BATCH_OPS
+            || self.batch_size >= MAX_BATCH_SIZE
+        {
+            self.dest
+                .write(self.batch.build_batch())
+                .await
+                .caused_by(trc::location!())?;
+            self.batch_size = 0;
+        }
+
+        Ok(())
+    }
+
+    fn mismatch(&mut self, subspace: u8, key: &[u8]) {
+        self.stats.mismatches += 1;
+        trc::event!(
+            Store(StoreEvent::CopyMismatch),
+            Id = char::from(subspace).to_string(),
+            Key = key.to_vec(),
+        );
+    }
+}
+
+impl CopyMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "full" => Some(CopyMode::Full),
+            "delta" => Some(CopyMode::Delta),
+            "verify" => Some(CopyMode::Verify),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CopyMode::Full => "full",
+            CopyMode::Delta => "delta",
+            CopyMode::Verify => "verify",
+        }
+    }
+}
+
+// Reads up to a chunk of keys starting at `from`, or all keys up to `to`.
+// Counters are returned as big endian integers.
+async fn read_chunk(
+    store: &Store,
+    subspace: u8,
+    kind: KeyKind,
+    from: &[u8],
+    to: Option<&[u8]>,
+) -> trc::Result<(Entries, bool)> {
+    let mut entries = Vec::new();
+    let mut chunk_size = 0;
+    let mut has_more = false;
+
+    store
+        .iterate(
+            IterateParams::new(
+                AnyKey {
+                    subspace,
+                    key: from.to_vec(),
+                },
+                to.map_or_else(
+                    || max_key(subspace),
+                    |to| AnyKey {
+                        subspace,
+                        key: to.to_vec(),
+                    },
+                ),
+            )
+            .set_values(kind == KeyKind::Value),
+            |key, value| {
+                chunk_size += key.len() + value.len();
+                entries.push((key.to_vec(), value.to_vec()));
+
+                if to.is_none() && (entries.len() >= CHUNK_KEYS || chunk_size >= CHUNK_SIZE) {
+                    has_more = true;
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+    if kind == KeyKind::Counter {
+        for (key, value) in &mut entries {
+            *value = store
+                .get_counter(ValueKey::from(ValueClass::Any(AnyClass {
+                    subspace,
+                    key: key.clone(),
+                })))
+                .await
+                .caused_by(trc::location!())?
+                .to_be_bytes()
+                .to_vec();
+        }
+    }
+
+    Ok((entries, has_more))
+}
+
+fn counter_value(value: &[u8]) -> i64 {
+    value.try_into().map_or(0, i64::from_be_bytes)
+}
@@ -61,6 +61,21 @@ impl FtsStore {
         .caused_by(trc::location!())
     }
 
+    pub async fn indexed_documents(
+        &self,
+        account_id: u32,
+        collection: impl Into<u8>,
+    ) -> trc::Result<RoaringBitmap> {
+        match self {
+            FtsStore::Store(store) => store.fts_indexed_documents(account_id, collection).await,
+            #[cfg(feature = "elastic")]
+            FtsStore::ElasticSearch(store) => {
+                store.fts_indexed_documents(account_id, collection).await
+            }
+        }
+        .caused_by(trc::location!())
+    }
+
     pub async fn remove_all(&self, account_id: u32) -> trc::Result<()> {
         match self {
             FtsStore::Store(store) => store.fts_remove_all(account_id).await,
@@ -0,0 +1,79 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use trc::atomics::array::AtomicU64Array;
+
+// Upper bounds in milliseconds
+const UPPER_BOUNDS: [u64; 12] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1_000, 5_000, u64::MAX];
+
+/// Number of writes committed to the data store, grouped by latency.
+pub static DATA_WRITE_LATENCY: LatencyBuckets = LatencyBuckets::new();
+
+pub struct LatencyBuckets {
+    counts: AtomicU64Array<{ UPPER_BOUNDS.len() }>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySnapshot {
+    counts: [u64; UPPER_BOUNDS.len()],
+}
+
+impl LatencyBuckets {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        LatencyBuckets {
+            counts: AtomicU64Array::new(),
+        }
+    }
+
+    pub fn observe(&self, elapsed: Duration) {
+        let elapsed = elapsed.as_millis() as u64;
+        if let Some(idx) = UPPER_BOUNDS.iter().position(|bound| elapsed < *bound) {
+            self.counts.add(idx, 1);
+        }
+    }
+
+    pub fn snapshot(&self) -> LatencySnapshot {
+        let mut snapshot = LatencySnapshot::default();
+        for (idx, value) in snapshot.counts.iter_mut().enumerate() {
+            *value = self.counts.get(idx);
+        }
+        snapshot
+    }
+}
+
+impl LatencySnapshot {
+    /// Returns the upper bound of the bucket holding the requested percentile
+    /// of the writes observed since `previous`, or `None` if there were none.
+    pub fn percentile(&self, previous: &LatencySnapshot, percentile: f64) -> Option<Duration> {
+        let mut counts = [0u64; UPPER_BOUNDS.len()];
+        for ((count, current), previous) in counts
+            .iter_mut()
+            .zip(self.counts.iter())
+            .zip(previous.counts.iter())
+        {
+            *count = current.saturating_sub(*previous);
+        }
+
+        let total = counts.iter().sum::<u64>();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = ((total as f64) * percentile.clamp(0.0, 100.0) / 100.0).ceil() as u64;
+        let mut seen = 0;
+        for (count, upper_bound) in counts.iter().zip(UPPER_BOUNDS) {
+            seen += count;
+            if seen >= rank.max(1) {
+                return Some(Duration::from_millis(upper_bound));
+            }
+        }
+
+        None
+    }
+}
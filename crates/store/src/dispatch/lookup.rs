@@ -45,6 +45,39 @@ impl LookupStore {
         result.caused_by(trc::location!())
     }
 
+    /// Executes the statements in order within a single transaction. Unique
+    /// constraint violations are reported as `AssertValueFailed` errors with
+    /// the index of the failing statement as their id.
+    #[allow(unreachable_patterns)]
+    #[allow(unused_variables)]
+    pub async fn execute_transaction(
+        &self,
+        statements: &[(&str, Vec<Value<'_>>)],
+    ) -> trc::Result<()> {
+        let result = match self {
+            #[cfg(feature = "sqlite")]
+            LookupStore::Store(Store::SQLite(store)) => store.execute_transaction(statements).await,
+            #[cfg(feature = "postgres")]
+            LookupStore::Store(Store::PostgreSQL(store)) => {
+                store.execute_transaction(statements).await
+            }
+            #[cfg(feature = "mysql")]
+            LookupStore::Store(Store::MySQL(store)) => store.execute_transaction(statements).await,
+            _ => Err(trc::StoreEvent::NotSupported.into_err()),
+        };
+
+        trc::event!(
+            Store(trc::StoreEvent::SqlQuery),
+            Details = statements
+                .iter()
+                .map(|(query, _)| query.to_string())
+                .collect::<Vec<_>>(),
+            Result = &result,
+        );
+
+        result.caused_by(trc::location!())
+    }
+
     pub async fn key_set(
         &self,
         key: Vec<u8>,
@@ -8,8 +8,11 @@ use roaring::RoaringBitmap;
 
 use crate::Store;
 
+pub mod backup;
 pub mod blob;
+pub mod copy;
 pub mod fts;
+pub mod latency;
 pub mod lookup;
 pub mod store;
 
@@ -26,6 +29,8 @@ impl Store {
             Self::MySQL(_) => "mysql",
             #[cfg(feature = "rocks")]
             Self::RocksDb(_) => "rocksdb",
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(_) => "in_memory",
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(_) => "read_replica",
             Self::None => "none",
@@ -14,6 +14,7 @@ use trc::{AddContext, StoreEvent};
 
 use crate::{
     write::{
+        compress::{decompress_value, Decompressed, ValueCompression},
         key::{DeserializeBigEndian, KeySerializer},
         now, AnyClass, AnyKey, AssignedIds, Batch, BatchBuilder, BitmapClass, BitmapHash,
         Operation, ReportClass, ValueClass, ValueOp,
@@ -22,7 +23,7 @@ use crate::{
     SUBSPACE_BITMAP_TAG, SUBSPACE_BITMAP_TEXT, SUBSPACE_INDEXES, SUBSPACE_LOGS, U32_LEN,
 };
 
-use super::DocumentSet;
+use super::{latency::DATA_WRITE_LATENCY, DocumentSet};
 
 #[cfg(feature = "test_mode")]
 #[allow(clippy::type_complexity)]
@@ -39,7 +40,7 @@ impl Store {
     where
         U: Deserialize + 'static,
     {
-        match self {
+        let value: trc::Result<Option<Decompressed<U>>> = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.get_value(key).await,
             #[cfg(feature = "foundation")]
@@ -50,11 +51,16 @@ impl Store {
             Self::MySQL(store) => store.get_value(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_value(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.get_value(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_value(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
-        }
-        .caused_by(trc::location!())
+        };
+
+        value
+            .map(|value| value.map(|value| value.0))
+            .caused_by(trc::location!())
     }
 
     pub async fn get_bitmap(
@@ -72,6 +78,8 @@ impl Store {
             Self::MySQL(store) => store.get_bitmap(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_bitmap(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.get_bitmap(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_bitmap(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -104,9 +112,20 @@ impl Store {
     pub async fn iterate<T: Key>(
         &self,
         params: IterateParams<T>,
-        cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
+        mut cb: impl for<'x> FnMut(&'x [u8], &'x [u8]) -> trc::Result<bool> + Sync + Send,
     ) -> trc::Result<()> {
         let start_time = Instant::now();
+        let (params, filter) = params.split_value_filter();
+        let cb = move |key: &[u8], value: &[u8]| {
+            let value = decompress_value(value);
+            match &filter {
+                Some(filter) if filter.matches(&value) => {
+                    cb(key, filter.value(&value)).map(|more| more && !filter.is_first())
+                }
+                Some(_) => Ok(true),
+                None => cb(key, value.as_ref()),
+            }
+        };
         let result = match self {
             #[cfg(feature = "sqlite")]
             Self::SQLite(store) => store.iterate(params, cb).await,
@@ -118,6 +137,8 @@ impl Store {
             Self::MySQL(store) => store.iterate(params, cb).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.iterate(params, cb).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.iterate(params, cb).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.iterate(params, cb).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -147,6 +168,8 @@ impl Store {
             Self::MySQL(store) => store.get_counter(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_counter(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.get_counter(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_counter(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -154,7 +177,12 @@ impl Store {
         .caused_by(trc::location!())
     }
 
-    pub async fn write(&self, batch: Batch) -> trc::Result<AssignedIds> {
+    pub async fn write(&self, mut batch: Batch) -> trc::Result<AssignedIds> {
+        let value_compression = self.value_compression();
+        if value_compression.is_enabled() {
+            value_compression.compress_batch(&mut batch);
+        }
+
         #[cfg(feature = "test_mode")]
         if std::env::var("PARANOID_WRITE").map_or(false, |v| v == "1") {
             let mut account_id = u32::MAX;
@@ -212,6 +240,8 @@ impl Store {
                 Self::MySQL(store) => store.write(batch).await,
                 #[cfg(feature = "rocks")]
                 Self::RocksDb(store) => store.write(batch).await,
+                #[cfg(feature = "test_mode")]
+                Self::InMemory(store) => store.write(batch).await,
                 #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
                 Self::SQLReadReplica(store) => store.write(batch).await,
                 Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -259,20 +289,41 @@ impl Store {
             Self::MySQL(store) => store.write(batch).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.write(batch).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.write(batch).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.write(batch).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
         };
 
-        trc::event!(
-            Store(StoreEvent::DataWrite),
-            Elapsed = start_time.elapsed(),
-            Total = ops,
-        );
+        let elapsed = start_time.elapsed();
+        DATA_WRITE_LATENCY.observe(elapsed);
+
+        trc::event!(Store(StoreEvent::DataWrite), Elapsed = elapsed, Total = ops,);
 
         result
     }
 
+    pub fn value_compression(&self) -> ValueCompression {
+        match self {
+            #[cfg(feature = "sqlite")]
+            Self::SQLite(store) => store.value_compression,
+            #[cfg(feature = "foundation")]
+            Self::FoundationDb(store) => store.value_compression,
+            #[cfg(feature = "postgres")]
+            Self::PostgreSQL(store) => store.value_compression,
+            #[cfg(feature = "mysql")]
+            Self::MySQL(store) => store.value_compression,
+            #[cfg(feature = "rocks")]
+            Self::RocksDb(store) => store.value_compression,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.value_compression,
+            #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
+            Self::SQLReadReplica(store) => store.value_compression(),
+            Self::None => ValueCompression::default(),
+        }
+    }
+
     pub async fn purge_store(&self) -> trc::Result<()> {
         // Delete expired reports
         let now = now();
@@ -315,6 +366,8 @@ impl Store {
             Self::MySQL(store) => store.purge_store().await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.purge_store().await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.purge_store().await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.purge_store().await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -334,6 +387,8 @@ impl Store {
             Self::MySQL(store) => store.delete_range(from, to).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.delete_range(from, to).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.delete_range(from, to).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.delete_range(from, to).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -507,6 +562,8 @@ impl Store {
             Self::MySQL(store) => store.get_blob(key, range).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.get_blob(key, range).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.get_blob(key, range).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.get_blob(key, range).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -526,6 +583,8 @@ impl Store {
             Self::MySQL(store) => store.put_blob(key, data).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.put_blob(key, data).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.put_blob(key, data).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.put_blob(key, data).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -545,6 +604,8 @@ impl Store {
             Self::MySQL(store) => store.delete_blob(key).await,
             #[cfg(feature = "rocks")]
             Self::RocksDb(store) => store.delete_blob(key).await,
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(store) => store.delete_blob(key).await,
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(store) => store.delete_blob(key).await,
             Self::None => Err(trc::StoreEvent::NotConfigured.into()),
@@ -581,6 +642,7 @@ impl Store {
             SUBSPACE_TELEMETRY_SPAN,
             SUBSPACE_TELEMETRY_METRIC,
             SUBSPACE_TELEMETRY_INDEX,
+            SUBSPACE_TASK,
         ] {
             self.delete_range(
                 AnyKey {
@@ -766,6 +828,7 @@ impl Store {
             (SUBSPACE_TELEMETRY_SPAN, true),
             (SUBSPACE_TELEMETRY_METRIC, true),
             (SUBSPACE_TELEMETRY_INDEX, true),
+            (SUBSPACE_TASK, true),
         ] {
             let from_key = crate::write::AnyKey {
                 subspace,
@@ -20,7 +20,7 @@ pub use blake3;
 pub use parking_lot;
 pub use rand;
 pub use roaring;
-use write::{purge::PurgeSchedule, BitmapClass, ValueClass};
+use write::{purge::PurgeSchedule, tier::BlobTiering, BitmapClass, ValueClass};
 
 #[cfg(feature = "s3")]
 use backend::s3::S3Store;
@@ -40,6 +40,9 @@ use backend::foundationdb::FdbStore;
 #[cfg(feature = "rocks")]
 use backend::rocksdb::RocksDbStore;
 
+#[cfg(feature = "test_mode")]
+use backend::in_memory::InMemoryStore;
+
 #[cfg(feature = "elastic")]
 use backend::elastic::ElasticSearchStore;
 
@@ -153,8 +156,8 @@ pub const SUBSPACE_FTS_INDEX: u8 = b'g';
 pub const SUBSPACE_TELEMETRY_SPAN: u8 = b'o';
 pub const SUBSPACE_TELEMETRY_INDEX: u8 = b'w';
 pub const SUBSPACE_TELEMETRY_METRIC: u8 = b'x';
+pub const SUBSPACE_TASK: u8 = b'y';
 
-pub const SUBSPACE_RESERVED_1: u8 = b'y';
 pub const SUBSPACE_RESERVED_2: u8 = b'z';
 
 #[derive(Clone)]
@@ -164,6 +167,7 @@ pub struct IterateParams<T: Key> {
     first: bool,
     ascending: bool,
     values: bool,
+    value_prefixes: Vec<Vec<u8>>,
 }
 
 #[derive(Clone, Default)]
@@ -187,6 +191,8 @@ pub enum Store {
     MySQL(Arc<MysqlStore>),
     #[cfg(feature = "rocks")]
     RocksDb(Arc<RocksDbStore>),
+    #[cfg(feature = "test_mode")]
+    InMemory(Arc<InMemoryStore>),
     #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
     SQLReadReplica(Arc<backend::composite::read_replica::SQLReadReplica>),
     #[default]
@@ -197,6 +203,7 @@ pub enum Store {
 pub struct BlobStore {
     pub backend: BlobBackend,
     pub compression: CompressionAlgo,
+    pub tiering: Option<Arc<BlobTiering>>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -274,11 +281,19 @@ impl From<RocksDbStore> for Store {
     }
 }
 
+#[cfg(feature = "test_mode")]
+impl From<InMemoryStore> for Store {
+    fn from(store: InMemoryStore) -> Self {
+        Self::InMemory(Arc::new(store))
+    }
+}
+
 impl From<FsStore> for BlobStore {
     fn from(store: FsStore) -> Self {
         BlobStore {
             backend: BlobBackend::Fs(Arc::new(store)),
             compression: CompressionAlgo::None,
+            tiering: None,
         }
     }
 }
@@ -289,6 +304,7 @@ impl From<S3Store> for BlobStore {
         BlobStore {
             backend: BlobBackend::S3(Arc::new(store)),
             compression: CompressionAlgo::None,
+            tiering: None,
         }
     }
 }
@@ -299,6 +315,7 @@ impl From<AzureStore> for BlobStore {
         BlobStore {
             backend: BlobBackend::Azure(Arc::new(store)),
             compression: CompressionAlgo::None,
+            tiering: None,
         }
     }
 }
@@ -328,6 +345,7 @@ impl From<Store> for BlobStore {
         BlobStore {
             backend: BlobBackend::Store(store),
             compression: CompressionAlgo::None,
+            tiering: None,
         }
     }
 }
@@ -343,6 +361,7 @@ impl Default for BlobStore {
         Self {
             backend: BlobBackend::Store(Store::None),
             compression: CompressionAlgo::None,
+            tiering: None,
         }
     }
 }
@@ -729,6 +748,8 @@ impl std::fmt::Debug for Store {
             Self::MySQL(_) => f.debug_tuple("MySQL").finish(),
             #[cfg(feature = "rocks")]
             Self::RocksDb(_) => f.debug_tuple("RocksDb").finish(),
+            #[cfg(feature = "test_mode")]
+            Self::InMemory(_) => f.debug_tuple("InMemory").finish(),
             #[cfg(all(feature = "enterprise", any(feature = "postgres", feature = "mysql")))]
             Self::SQLReadReplica(_) => f.debug_tuple("SQLReadReplica").finish(),
             Self::None => f.debug_tuple("None").finish(),
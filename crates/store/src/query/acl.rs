@@ -7,7 +7,9 @@
 use trc::AddContext;
 
 use crate::{
-    write::{key::DeserializeBigEndian, BatchBuilder, Operation, ValueClass, ValueOp},
+    write::{
+        key::DeserializeBigEndian, BatchBuilder, MaybeDynamicId, Operation, ValueClass, ValueOp,
+    },
     Deserialize, IterateParams, Store, ValueKey, U32_LEN,
 };
 
@@ -80,35 +82,10 @@ impl Store {
     }
 
     pub async fn acl_revoke_all(&self, account_id: u32) -> trc::Result<()> {
-        let from_key = ValueKey {
-            account_id: 0,
-            collection: 0,
-            document_id: 0,
-            class: ValueClass::Acl(0),
-        };
-        let to_key = ValueKey {
-            account_id: u32::MAX,
-            collection: u8::MAX,
-            document_id: u32::MAX,
-            class: ValueClass::Acl(u32::MAX),
-        };
-
-        let mut delete_keys = Vec::new();
-        self.iterate(
-            IterateParams::new(from_key, to_key).ascending().no_values(),
-            |key, _| {
-                if account_id == key.deserialize_be_u32(U32_LEN)? {
-                    delete_keys.push((
-                        ValueClass::Acl(key.deserialize_be_u32(0)?),
-                        AclItem::deserialize(key)?,
-                    ));
-                }
-
-                Ok(true)
-            },
-        )
-        .await
-        .caused_by(trc::location!())?;
+        let delete_keys = self
+            .acl_grants_on(account_id)
+            .await
+            .caused_by(trc::location!())?;
 
         // Remove permissions
         let mut batch = BatchBuilder::new();
@@ -141,6 +118,48 @@ impl Store {
 
         Ok(())
     }
+
+    /// Returns the number of grants that `acl_revoke_all` would remove.
+    pub async fn acl_count_grants_on(&self, account_id: u32) -> trc::Result<usize> {
+        self.acl_grants_on(account_id).await.map(|keys| keys.len())
+    }
+
+    async fn acl_grants_on(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<Vec<(ValueClass<MaybeDynamicId>, AclItem)>> {
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Acl(0),
+        };
+        let to_key = ValueKey {
+            account_id: u32::MAX,
+            collection: u8::MAX,
+            document_id: u32::MAX,
+            class: ValueClass::Acl(u32::MAX),
+        };
+
+        let mut delete_keys = Vec::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending().no_values(),
+            |key, _| {
+                if account_id == key.deserialize_be_u32(U32_LEN)? {
+                    delete_keys.push((
+                        ValueClass::Acl(key.deserialize_be_u32(0)?),
+                        AclItem::deserialize(key)?,
+                    ));
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        Ok(delete_keys)
+    }
 }
 
 impl Deserialize for AclItem {
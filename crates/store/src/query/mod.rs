@@ -235,6 +235,7 @@ impl<T: Key> IterateParams<T> {
             first: false,
             ascending: true,
             values: true,
+            value_prefixes: vec![],
         }
     }
 
@@ -267,4 +268,55 @@ impl<T: Key> IterateParams<T> {
         self.values = false;
         self
     }
+
+    /// Only return rows whose value starts with one of the given prefixes.
+    /// SQL backends evaluate the predicate server-side, key-value backends
+    /// skip non-matching rows before invoking the callback.
+    pub fn with_value_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.value_prefixes.push(prefix.into());
+        self
+    }
+
+    pub fn with_value_prefixes(mut self, prefixes: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        self.value_prefixes.extend(prefixes);
+        self
+    }
+
+    #[cfg(feature = "foundation")]
+    pub(crate) fn has_value_filter(&self) -> bool {
+        !self.value_prefixes.is_empty()
+    }
+
+    #[cfg(any(feature = "rocks", feature = "foundation", feature = "test_mode"))]
+    pub(crate) fn matches_value(&self, value: &[u8]) -> bool {
+        self.value_prefixes.is_empty()
+            || self
+                .value_prefixes
+                .iter()
+                .any(|prefix| value.starts_with(prefix))
+    }
+
+    pub(crate) fn value_filter_sql(&self, mut placeholder: impl FnMut() -> String) -> String {
+        if self.value_prefixes.is_empty() {
+            return String::new();
+        }
+
+        let mut sql = String::from(" AND (");
+        for (pos, prefix) in self.value_prefixes.iter().enumerate() {
+            if pos > 0 {
+                sql.push_str(" OR ");
+            }
+            sql.push_str(&format!(
+                "substr(v, 1, {}) = {}",
+                prefix.len(),
+                placeholder()
+            ));
+        }
+        sql.push(')');
+        sql
+    }
+
+    pub(crate) fn value_prefixes(&self) -> &[Vec<u8>] {
+        &self.value_prefixes
+    }
 }
@@ -6,6 +6,8 @@
 
 use crate::{Deserialize, U32_LEN, U64_LEN};
 
+use super::compress::decompress_value;
+
 #[derive(Debug, Clone)]
 pub struct HashedValue<T: Deserialize> {
     pub hash: u64,
@@ -72,7 +74,7 @@ impl AssertValue {
         match self {
             AssertValue::U32(v) => bytes.len() == U32_LEN && u32::deserialize(bytes).unwrap() == *v,
             AssertValue::U64(v) => bytes.len() == U64_LEN && u64::deserialize(bytes).unwrap() == *v,
-            AssertValue::Hash(v) => xxhash_rust::xxh3::xxh3_64(bytes) == *v,
+            AssertValue::Hash(v) => xxhash_rust::xxh3::xxh3_64(&decompress_value(bytes)) == *v,
             AssertValue::None => false,
             AssertValue::Some => true,
         }
@@ -241,6 +241,54 @@ impl Store {
     }
 
     pub async fn blob_hash_unlink_account(&self, account_id: u32) -> trc::Result<()> {
+        let delete_keys = self
+            .blob_hash_account_links(account_id)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Unlink blobs
+        let mut batch = BatchBuilder::new();
+        batch.with_account_id(account_id);
+        let mut last_collection = u8::MAX;
+        for (collection, document_id, op) in delete_keys.into_iter() {
+            if batch.ops.len() >= 1000 {
+                self.write(batch.build())
+                    .await
+                    .caused_by(trc::location!())?;
+                batch = BatchBuilder::new();
+                batch.with_account_id(account_id);
+                last_collection = u8::MAX;
+            }
+            if collection != last_collection {
+                batch.with_collection(collection);
+                last_collection = collection;
+            }
+            batch.update_document(document_id);
+            batch.ops.push(Operation::Value {
+                class: ValueClass::Blob(op),
+                op: ValueOp::Clear,
+            });
+        }
+        if !batch.is_empty() {
+            self.write(batch.build())
+                .await
+                .caused_by(trc::location!())?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of blob links that `blob_hash_unlink_account` would remove.
+    pub async fn blob_hash_count_account(&self, account_id: u32) -> trc::Result<usize> {
+        self.blob_hash_account_links(account_id)
+            .await
+            .map(|keys| keys.len())
+    }
+
+    async fn blob_hash_account_links(
+        &self,
+        account_id: u32,
+    ) -> trc::Result<Vec<(u8, u32, BlobOp)>> {
         // Validate linked blobs
         let from_key = ValueKey {
             account_id: 0,
@@ -285,35 +333,6 @@ impl Store {
         .await
         .caused_by(trc::location!())?;
 
-        // Unlink blobs
-        let mut batch = BatchBuilder::new();
-        batch.with_account_id(account_id);
-        let mut last_collection = u8::MAX;
-        for (collection, document_id, op) in delete_keys.into_iter() {
-            if batch.ops.len() >= 1000 {
-                self.write(batch.build())
-                    .await
-                    .caused_by(trc::location!())?;
-                batch = BatchBuilder::new();
-                batch.with_account_id(account_id);
-                last_collection = u8::MAX;
-            }
-            if collection != last_collection {
-                batch.with_collection(collection);
-                last_collection = collection;
-            }
-            batch.update_document(document_id);
-            batch.ops.push(Operation::Value {
-                class: ValueClass::Blob(op),
-                op: ValueOp::Clear,
-            });
-        }
-        if !batch.is_empty() {
-            self.write(batch.build())
-                .await
-                .caused_by(trc::location!())?;
-        }
-
-        Ok(())
+        Ok(delete_keys)
     }
 }
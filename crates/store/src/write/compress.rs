@@ -0,0 +1,158 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::borrow::Cow;
+
+use utils::config::{utils::AsKey, Config};
+
+use crate::{CompressionAlgo, Deserialize, IterateParams, Key, U32_LEN};
+
+use super::{Batch, MaybeDynamicValue, Operation, ValueOp};
+
+// LZ4 cannot expand data by more than this ratio, larger sizes in the header
+// belong to values that were never compressed
+const MAX_LZ4_RATIO: usize = 255;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ValueCompression {
+    pub algo: CompressionAlgo,
+    pub threshold: usize,
+}
+
+/// Value prefix filter applied after decompression, the backend cannot
+/// look inside compressed values.
+pub(crate) struct ValueFilter {
+    prefixes: Vec<Vec<u8>>,
+    first: bool,
+    values: bool,
+}
+
+/// Value whose stored representation may carry a compression header, the
+/// header is removed before deserializing the inner type.
+pub struct Decompressed<T>(pub T);
+
+impl ValueCompression {
+    pub fn parse(config: &mut Config, prefix: impl AsKey) -> Self {
+        let prefix = prefix.as_key();
+        ValueCompression {
+            algo: config
+                .property_or_default((&prefix, "value-compression.algorithm"), "none")
+                .unwrap_or(CompressionAlgo::None),
+            threshold: config
+                .property_or_default((&prefix, "value-compression.threshold"), "1024")
+                .unwrap_or(1024),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self.algo, CompressionAlgo::None)
+    }
+
+    pub fn compress(&self, value: &[u8]) -> Option<Vec<u8>> {
+        match self.algo {
+            CompressionAlgo::Lz4 if value.len() >= self.threshold => {
+                let mut compressed = Vec::with_capacity(value.len() / 2);
+                compressed.push(CompressionAlgo::Lz4.marker());
+                compressed.extend_from_slice(&lz4_flex::compress_prepend_size(value));
+
+                // Values that do not shrink are stored as they are
+                if compressed.len() < value.len() {
+                    Some(compressed)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub fn compress_batch(&self, batch: &mut Batch) {
+        for op in batch.ops.iter_mut() {
+            if let Operation::Value {
+                op: ValueOp::Set(MaybeDynamicValue::Static(value)),
+                ..
+            } = op
+            {
+                if let Some(compressed) = self.compress(value) {
+                    *value = compressed;
+                }
+            }
+        }
+    }
+}
+
+impl Default for ValueCompression {
+    fn default() -> Self {
+        ValueCompression {
+            algo: CompressionAlgo::None,
+            threshold: 1024,
+        }
+    }
+}
+
+/// Returns the original bytes of a value written with compression enabled,
+/// values without a valid header are returned unchanged.
+pub fn decompress_value(bytes: &[u8]) -> Cow<'_, [u8]> {
+    if bytes.len() > U32_LEN + 1 && bytes[0] == CompressionAlgo::Lz4.marker() {
+        let data = &bytes[1..];
+        let size = u32::from_le_bytes(data[..U32_LEN].try_into().unwrap()) as usize;
+
+        // A value that was never compressed can start with the marker byte,
+        // it is only decompressed if the header and payload are consistent
+        if size > bytes.len() && size <= (data.len() - U32_LEN) * MAX_LZ4_RATIO {
+            if let Ok(decompressed) = lz4_flex::decompress_size_prepended(data) {
+                if decompressed.len() == size {
+                    return Cow::Owned(decompressed);
+                }
+            }
+        }
+    }
+
+    Cow::Borrowed(bytes)
+}
+
+impl<T: Key> IterateParams<T> {
+    /// Lets compressed values through the backend filter so that they can be
+    /// matched by the caller once decompressed.
+    pub(crate) fn split_value_filter(mut self) -> (Self, Option<ValueFilter>) {
+        if self.value_prefixes.is_empty() {
+            return (self, None);
+        }
+
+        let filter = ValueFilter {
+            prefixes: self.value_prefixes.clone(),
+            first: std::mem::take(&mut self.first),
+            values: std::mem::replace(&mut self.values, true),
+        };
+        self.value_prefixes
+            .push(vec![CompressionAlgo::Lz4.marker()]);
+        (self, Some(filter))
+    }
+}
+
+impl ValueFilter {
+    pub(crate) fn matches(&self, value: &[u8]) -> bool {
+        self.prefixes.iter().any(|prefix| value.starts_with(prefix))
+    }
+
+    pub(crate) fn is_first(&self) -> bool {
+        self.first
+    }
+
+    pub(crate) fn value<'x>(&self, value: &'x [u8]) -> &'x [u8] {
+        if self.values {
+            value
+        } else {
+            b""
+        }
+    }
+}
+
+impl<T: Deserialize> Deserialize for Decompressed<T> {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        T::deserialize(&decompress_value(bytes)).map(Decompressed)
+    }
+}
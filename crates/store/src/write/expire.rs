@@ -0,0 +1,165 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use trc::AddContext;
+
+use crate::{IterateParams, Serialize, Store, ValueKey};
+
+use super::{
+    key::DeserializeBigEndian, now, AnyClass, AnyKey, BatchBuilder, Bincode, TaskClass, ValueClass,
+};
+
+/// Returns the expiration timestamp of a record, `None` if it does not expire.
+pub type ExpiryFn = fn(key: &[u8], value: &[u8]) -> trc::Result<Option<u64>>;
+
+/// Adds the operations needed to delete an expired record to the batch.
+pub type DeleteFn = fn(batch: &mut BatchBuilder, subspace: u8, key: &[u8]) -> trc::Result<()>;
+
+/// A key range holding short-lived records that are removed once expired.
+#[derive(Clone)]
+pub struct ExpiringRange {
+    pub name: &'static str,
+    pub subspace: u8,
+    pub from: Vec<u8>,
+    pub to: Vec<u8>,
+    pub expiry: ExpiryFn,
+    pub delete: DeleteFn,
+}
+
+impl ExpiringRange {
+    pub fn new(
+        name: &'static str,
+        subspace: u8,
+        from: impl Into<Vec<u8>>,
+        to: impl Into<Vec<u8>>,
+        expiry: ExpiryFn,
+    ) -> Self {
+        ExpiringRange {
+            name,
+            subspace,
+            from: from.into(),
+            to: to.into(),
+            expiry,
+            delete: clear_key,
+        }
+    }
+
+    pub fn with_prefix(
+        name: &'static str,
+        subspace: u8,
+        prefix: impl Into<Vec<u8>>,
+        expiry: ExpiryFn,
+    ) -> Self {
+        let from = prefix.into();
+        let mut to = from.clone();
+        to.extend_from_slice(&[u8::MAX; 8]);
+        Self::new(name, subspace, from, to, expiry)
+    }
+
+    pub fn with_delete(mut self, delete: DeleteFn) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    fn continuation_key<T>(&self) -> ValueClass<T> {
+        let mut key = format!("expire:{}:", self.name).into_bytes();
+        key.extend_from_slice(&self.from);
+        ValueClass::Task(TaskClass::State(key))
+    }
+}
+
+/// Expiry of keys written with `LookupStore::key_set`.
+pub fn lookup_key_expiry(_: &[u8], value: &[u8]) -> trc::Result<Option<u64>> {
+    value
+        .deserialize_be_u64(0)
+        .map(|expiry| Some(expiry).filter(|expiry| *expiry != u64::MAX))
+}
+
+fn clear_key(batch: &mut BatchBuilder, subspace: u8, key: &[u8]) -> trc::Result<()> {
+    batch.clear(ValueClass::Any(AnyClass {
+        subspace,
+        key: key.to_vec(),
+    }));
+    Ok(())
+}
+
+impl Store {
+    /// Deletes the expired records in a range, reading at most `chunk_size` keys
+    /// before writing. The position is persisted after every chunk so a sweep
+    /// that is interrupted resumes from where it stopped.
+    pub async fn purge_expired(
+        &self,
+        range: &ExpiringRange,
+        chunk_size: usize,
+    ) -> trc::Result<u64> {
+        let mut from = self
+            .get_value::<Bincode<Vec<u8>>>(ValueKey::from(range.continuation_key()))
+            .await
+            .caused_by(trc::location!())?
+            .map(|key| key.inner)
+            .filter(|key| key.as_slice() > range.from.as_slice())
+            .unwrap_or_else(|| range.from.clone());
+        let current_time = now();
+        let mut total = 0;
+
+        loop {
+            let mut expired = Vec::new();
+            let mut last_key = None;
+            let mut count = 0;
+
+            self.iterate(
+                IterateParams::new(
+                    AnyKey {
+                        subspace: range.subspace,
+                        key: from.clone(),
+                    },
+                    AnyKey {
+                        subspace: range.subspace,
+                        key: range.to.clone(),
+                    },
+                ),
+                |key, value| {
+                    if (range.expiry)(key, value)?.is_some_and(|expiry| expiry <= current_time) {
+                        expired.push(key.to_vec());
+                    }
+                    last_key = Some(key.to_vec());
+                    count += 1;
+
+                    Ok(count < chunk_size)
+                },
+            )
+            .await
+            .caused_by(trc::location!())?;
+
+            let mut batch = BatchBuilder::new();
+            for key in &expired {
+                (range.delete)(&mut batch, range.subspace, key).caused_by(trc::location!())?;
+            }
+            let is_done = count < chunk_size;
+            if is_done {
+                batch.clear(range.continuation_key());
+            } else if let Some(mut key) = last_key {
+                // Continue right after the last key read
+                key.push(0);
+                batch.set(
+                    range.continuation_key(),
+                    Bincode::new(key.clone()).serialize(),
+                );
+                from = key;
+            }
+            if !batch.is_empty() {
+                self.write(batch.build())
+                    .await
+                    .caused_by(trc::location!())?;
+            }
+            total += expired.len() as u64;
+
+            if is_done {
+                return Ok(total);
+            }
+        }
+    }
+}
@@ -13,13 +13,13 @@ use crate::{
     SUBSPACE_BLOB_RESERVE, SUBSPACE_COUNTER, SUBSPACE_DIRECTORY, SUBSPACE_FTS_INDEX,
     SUBSPACE_FTS_QUEUE, SUBSPACE_INDEXES, SUBSPACE_LOGS, SUBSPACE_LOOKUP_VALUE, SUBSPACE_PROPERTY,
     SUBSPACE_QUEUE_EVENT, SUBSPACE_QUEUE_MESSAGE, SUBSPACE_QUOTA, SUBSPACE_REPORT_IN,
-    SUBSPACE_REPORT_OUT, SUBSPACE_SETTINGS, SUBSPACE_TELEMETRY_INDEX, SUBSPACE_TELEMETRY_METRIC,
-    SUBSPACE_TELEMETRY_SPAN, U32_LEN, U64_LEN, WITH_SUBSPACE,
+    SUBSPACE_REPORT_OUT, SUBSPACE_SETTINGS, SUBSPACE_TASK, SUBSPACE_TELEMETRY_INDEX,
+    SUBSPACE_TELEMETRY_METRIC, SUBSPACE_TELEMETRY_SPAN, U32_LEN, U64_LEN, WITH_SUBSPACE,
 };
 
 use super::{
     AnyKey, AssignedIds, BitmapClass, BlobOp, DirectoryClass, LookupClass, QueueClass, ReportClass,
-    ReportEvent, ResolveId, TagValue, TelemetryClass, ValueClass,
+    ReportEvent, ResolveId, TagValue, TaskClass, TelemetryClass, ValueClass,
 };
 
 pub struct KeySerializer {
@@ -319,6 +319,19 @@ impl<T: ResolveId> ValueClass<T> {
                     .write(6u8)
                     .write(principal_id.resolve_id(assigned_ids))
                     .write(has_member.resolve_id(assigned_ids)),
+                DirectoryClass::Template { tenant_id, kind } => {
+                    serializer.write(7u8).write(*tenant_id).write(*kind)
+                }
+                DirectoryClass::Delegation {
+                    principal_id,
+                    domain_id,
+                } => serializer.write(8u8).write(*principal_id).write(*domain_id),
+                DirectoryClass::ReadOnly(tenant_id) => serializer.write(9u8).write(*tenant_id),
+                DirectoryClass::ExternalIdToId(id) => serializer.write(10u8).write(id.as_slice()),
+                DirectoryClass::LastLogin(uid) => serializer.write(11u8).write(*uid),
+                DirectoryClass::CertificateToId(fingerprint) => {
+                    serializer.write(12u8).write(fingerprint.as_slice())
+                }
             },
             ValueClass::Queue(queue) => match queue {
                 QueueClass::Message(queue_id) => serializer.write(*queue_id),
@@ -379,6 +392,11 @@ impl<T: ResolveId> ValueClass<T> {
                     .write_leb128(*metric_id)
                     .write_leb128(*node_id),
             },
+            ValueClass::Task(task) => match task {
+                TaskClass::Lock(name) => serializer.write(0u8).write(name.as_slice()),
+                TaskClass::Run(name) => serializer.write(1u8).write(name.as_slice()),
+                TaskClass::State(name) => serializer.write(2u8).write(name.as_slice()),
+            },
             ValueClass::Any(any) => serializer.write(any.key.as_slice()),
         }
         .finalize()
@@ -532,9 +550,16 @@ impl<T> ValueClass<T> {
             ValueClass::Lookup(LookupClass::Counter(v) | LookupClass::Key(v))
             | ValueClass::Config(v) => v.len(),
             ValueClass::Directory(d) => match d {
-                DirectoryClass::NameToId(v) | DirectoryClass::EmailToId(v) => v.len(),
+                DirectoryClass::NameToId(v)
+                | DirectoryClass::EmailToId(v)
+                | DirectoryClass::ExternalIdToId(v)
+                | DirectoryClass::CertificateToId(v) => v.len(),
                 DirectoryClass::Principal(_) | DirectoryClass::UsedQuota(_) => U32_LEN,
-                DirectoryClass::Members { .. } | DirectoryClass::MemberOf { .. } => U32_LEN * 2,
+                DirectoryClass::Members { .. }
+                | DirectoryClass::MemberOf { .. }
+                | DirectoryClass::Delegation { .. } => U32_LEN * 2,
+                DirectoryClass::Template { .. } => U32_LEN + 2,
+                DirectoryClass::ReadOnly(_) | DirectoryClass::LastLogin(_) => U32_LEN + 1,
             },
             ValueClass::Blob(op) => match op {
                 BlobOp::Reserve { .. } => BLOB_HASH_LEN + U64_LEN + U32_LEN + 1,
@@ -560,6 +585,9 @@ impl<T> ValueClass<T> {
                 TelemetryClass::Index { value, .. } => U64_LEN + value.len() + 1,
                 TelemetryClass::Metric { .. } => U64_LEN * 2 + 1,
             },
+            ValueClass::Task(TaskClass::Lock(v) | TaskClass::Run(v) | TaskClass::State(v)) => {
+                v.len() + 1
+            }
             ValueClass::Any(v) => v.key.len(),
         }
     }
@@ -606,6 +634,7 @@ impl<T> ValueClass<T> {
                 TelemetryClass::Index { .. } => SUBSPACE_TELEMETRY_INDEX,
                 TelemetryClass::Metric { .. } => SUBSPACE_TELEMETRY_METRIC,
             },
+            ValueClass::Task(_) => SUBSPACE_TASK,
             ValueClass::Any(any) => any.subspace,
         }
     }
@@ -0,0 +1,187 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::future::Future;
+
+use trc::AddContext;
+
+use crate::{Serialize, Store, ValueKey};
+
+use super::{now, BatchBuilder, Bincode, TaskClass, ValueClass};
+
+/// Progress of a migration, persisted with every chunk so that an
+/// interrupted migration resumes from the last processed key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MigrationProgress {
+    pub next_key: Option<Vec<u8>>,
+    pub processed: u64,
+    pub migrated: u64,
+    pub started_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+/// One chunk of a migration. The batch holds the changes for the chunk and
+/// is written together with the updated progress, so a chunk is either fully
+/// applied and recorded or not applied at all.
+#[derive(Default)]
+pub struct MigrationChunk {
+    pub batch: BatchBuilder,
+    pub next_key: Option<Vec<u8>>,
+    pub processed: u64,
+    pub migrated: u64,
+}
+
+impl Store {
+    pub async fn migration_progress(&self, id: &str) -> trc::Result<Option<MigrationProgress>> {
+        self.get_value::<Bincode<MigrationProgress>>(ValueKey::from(progress_key(id)))
+            .await
+            .map(|progress| progress.map(|progress| progress.inner))
+            .caused_by(trc::location!())
+    }
+
+    /// Returns whether a migration was fully applied. Readers relying on the
+    /// migrated format check this first, rows are only rewritten in the
+    /// background.
+    pub async fn is_migration_finished(&self, id: &str) -> trc::Result<bool> {
+        self.migration_progress(id)
+            .await
+            .map(|progress| progress.is_some_and(|progress| progress.finished_at.is_some()))
+    }
+
+    pub async fn is_migration_running(&self, id: &str) -> trc::Result<bool> {
+        self.get_value::<u64>(ValueKey::from(lock_key(id)))
+            .await
+            .map(|expiry| expiry.is_some_and(|expiry| expiry > now()))
+            .caused_by(trc::location!())
+    }
+
+    /// Runs a migration chunk by chunk starting from its last persisted key.
+    /// The lock is shared by all nodes and extended with every chunk, a node
+    /// that lost its lock fails to record progress and stops. Returns `None`
+    /// when the migration is running elsewhere.
+    pub async fn run_migration<F, Fut>(
+        &self,
+        id: &str,
+        lock_expiry: u64,
+        mut migrate_chunk: F,
+    ) -> trc::Result<Option<MigrationProgress>>
+    where
+        F: FnMut(Option<Vec<u8>>) -> Fut + Send,
+        Fut: Future<Output = trc::Result<MigrationChunk>> + Send,
+    {
+        if let Some(progress) = self
+            .migration_progress(id)
+            .await?
+            .filter(|progress| progress.finished_at.is_some())
+        {
+            return Ok(Some(progress));
+        }
+
+        // Obtain lock
+        let mut lock = match self.try_lock_migration(id, lock_expiry).await? {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+
+        let result: trc::Result<MigrationProgress> = async {
+            // Progress is read again as another node may have advanced it
+            let mut progress =
+                self.migration_progress(id)
+                    .await?
+                    .unwrap_or_else(|| MigrationProgress {
+                        started_at: now(),
+                        ..Default::default()
+                    });
+
+            while progress.finished_at.is_none() {
+                let chunk = migrate_chunk(progress.next_key.clone()).await?;
+                progress.next_key = chunk.next_key;
+                progress.processed += chunk.processed;
+                progress.migrated += chunk.migrated;
+                if progress.next_key.is_none() {
+                    progress.finished_at = Some(now());
+                }
+
+                // Record progress and extend the lock along with the changes
+                let mut batch = chunk.batch;
+                let next_lock = now() + lock_expiry;
+                batch
+                    .assert_value(lock_key(id), lock)
+                    .set(lock_key(id), next_lock.serialize())
+                    .set(progress_key(id), Bincode::new(progress.clone()).serialize());
+                self.write(batch.build())
+                    .await
+                    .caused_by(trc::location!())?;
+                lock = next_lock;
+            }
+
+            Ok(progress)
+        }
+        .await;
+
+        // Release lock
+        let mut batch = BatchBuilder::new();
+        batch.assert_value(lock_key(id), lock).clear(lock_key(id));
+        if let Err(err) = self.write(batch.build()).await {
+            if !err.is_assertion_failure() {
+                trc::error!(err
+                    .details("Failed to release migration lock")
+                    .id(id.to_string()));
+            }
+        }
+
+        result.map(Some)
+    }
+
+    async fn try_lock_migration(&self, id: &str, lock_expiry: u64) -> trc::Result<Option<u64>> {
+        let mut batch = BatchBuilder::new();
+        match self
+            .get_value::<u64>(ValueKey::from(lock_key(id)))
+            .await
+            .caused_by(trc::location!())?
+        {
+            Some(expiry) if expiry > now() => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::MigrationLocked),
+                    Id = id.to_string(),
+                    Expires = trc::Value::Timestamp(expiry),
+                );
+
+                return Ok(None);
+            }
+            Some(expiry) => {
+                batch.assert_value(lock_key(id), expiry);
+            }
+            None => {
+                batch.assert_value(lock_key(id), ());
+            }
+        }
+
+        let lock = now() + lock_expiry;
+        batch.set(lock_key(id), lock.serialize());
+        match self.write(batch.build()).await {
+            Ok(_) => Ok(Some(lock)),
+            Err(err) if err.is_assertion_failure() => {
+                trc::event!(
+                    Housekeeper(trc::HousekeeperEvent::MigrationLocked),
+                    Id = id.to_string(),
+                    CausedBy = err,
+                );
+
+                Ok(None)
+            }
+            Err(err) => Err(err.caused_by(trc::location!())),
+        }
+    }
+}
+
+fn lock_key<T>(id: &str) -> ValueClass<T> {
+    ValueClass::Task(TaskClass::Lock(format!("migration:{id}").into_bytes()))
+}
+
+fn progress_key<T>(id: &str) -> ValueClass<T> {
+    ValueClass::Task(TaskClass::State(format!("migration:{id}").into_bytes()))
+}
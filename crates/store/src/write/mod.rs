@@ -28,10 +28,14 @@ use self::assert::AssertValue;
 pub mod assert;
 pub mod batch;
 pub mod blob;
+pub mod compress;
+pub mod expire;
 pub mod hash;
 pub mod key;
 pub mod log;
+pub mod migrate;
 pub mod purge;
+pub mod tier;
 
 pub trait SerializeWithId: Send + Sync {
     fn serialize_with_id(&self, ids: &AssignedIds) -> trc::Result<Vec<u8>>;
@@ -154,6 +158,7 @@ pub enum ValueClass<T> {
     Queue(QueueClass),
     Report(ReportClass),
     Telemetry(TelemetryClass),
+    Task(TaskClass),
     Any(AnyClass),
 }
 
@@ -183,6 +188,12 @@ pub enum DirectoryClass<T> {
     Members { principal_id: T, has_member: T },
     Principal(T),
     UsedQuota(u32),
+    Template { tenant_id: u32, kind: u8 },
+    Delegation { principal_id: u32, domain_id: u32 },
+    ReadOnly(u32),
+    ExternalIdToId(Vec<u8>),
+    LastLogin(u32),
+    CertificateToId(Vec<u8>),
 }
 
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
@@ -220,6 +231,13 @@ pub enum TelemetryClass {
     },
 }
 
+#[derive(Debug, PartialEq, Clone, Eq, Hash)]
+pub enum TaskClass {
+    Lock(Vec<u8>),
+    Run(Vec<u8>),
+    State(Vec<u8>),
+}
+
 #[derive(Debug, PartialEq, Clone, Eq, Hash)]
 pub struct QueueEvent {
     pub due: u64,
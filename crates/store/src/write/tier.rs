@@ -0,0 +1,370 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{ops::Range, time::Instant};
+
+use ahash::AHashMap;
+use parking_lot::Mutex;
+use trc::{AddContext, StoreEvent};
+use utils::{BlobHash, BLOB_HASH_LEN};
+
+use crate::{
+    write::BatchBuilder, BlobStore, Deserialize, IterateParams, Serialize, Store, ValueKey,
+    U32_LEN, U64_LEN,
+};
+
+use super::{assert::AssertValue, key::DeserializeBigEndian, now, BlobOp, ValueClass};
+
+/// Secondary blob store that receives blobs once they are older than
+/// `min_age`, the primary store keeps everything else.
+pub struct BlobTiering {
+    pub store: BlobStore,
+    pub min_age: u64,
+    pub promote_after: Option<u32>,
+    reads: Mutex<AHashMap<BlobHash, u32>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlobTier {
+    #[default]
+    Primary,
+    Secondary,
+}
+
+/// Value stored under the commit key of a blob. Blobs committed before
+/// tiering was available have an empty value and no creation time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BlobCommit {
+    pub created: u64,
+    pub tier: BlobTier,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TierStats {
+    pub migrated: usize,
+    pub migrated_bytes: usize,
+    pub promoted: usize,
+    pub failures: usize,
+}
+
+enum TierMove {
+    Migrate,
+    Promote,
+}
+
+impl BlobTiering {
+    pub fn new(store: BlobStore, min_age: u64, promote_after: Option<u32>) -> Self {
+        BlobTiering {
+            store,
+            min_age,
+            promote_after,
+            reads: Mutex::new(AHashMap::new()),
+        }
+    }
+
+    pub(crate) async fn get_blob(
+        &self,
+        key: &[u8],
+        range: Range<usize>,
+    ) -> trc::Result<Option<Vec<u8>>> {
+        let is_full_read = range.start == 0;
+        let result = self
+            .store
+            .get_primary_blob(key, range)
+            .await
+            .caused_by(trc::location!())?;
+
+        // Chunked reads are counted once
+        if result.is_some() && is_full_read && self.promote_after.is_some() {
+            if let Ok(hash) = BlobHash::try_from_hash_slice(key) {
+                *self.reads.lock().entry(hash).or_default() += 1;
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn take_reads(&self) -> AHashMap<BlobHash, u32> {
+        std::mem::take(&mut *self.reads.lock())
+    }
+}
+
+impl Store {
+    /// Moves blobs older than the configured age to the secondary store and,
+    /// if enabled, blobs that were read frequently back to the primary store.
+    pub async fn tier_blobs(&self, blob_store: &BlobStore) -> trc::Result<TierStats> {
+        let Some(tiering) = &blob_store.tiering else {
+            return Ok(TierStats::default());
+        };
+        let op_start = Instant::now();
+        let reads = tiering.take_reads();
+        let now = now();
+
+        let from_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Commit {
+                hash: BlobHash::default(),
+            }),
+        };
+        let to_key = ValueKey {
+            account_id: 0,
+            collection: 0,
+            document_id: 0,
+            class: ValueClass::Blob(BlobOp::Commit {
+                hash: BlobHash::new_max(),
+            }),
+        };
+        let mut moves = Vec::new();
+        let mut undated = Vec::new();
+        self.iterate(
+            IterateParams::new(from_key, to_key).ascending(),
+            |key, value| {
+                // Only commit keys hold blob metadata
+                if key.deserialize_be_u32(key.len() - U32_LEN)? != u32::MAX {
+                    return Ok(true);
+                }
+                let hash = BlobHash::try_from_hash_slice(
+                    key.get(0..BLOB_HASH_LEN)
+                        .ok_or_else(|| trc::Error::corrupted_key(key, None, trc::location!()))?,
+                )
+                .unwrap();
+                let commit = BlobCommit::deserialize(value)?;
+                let assert_value = AssertValue::Hash(xxhash_rust::xxh3::xxh3_64(value));
+
+                match commit.tier {
+                    BlobTier::Primary if commit.created == 0 => {
+                        undated.push((hash, assert_value));
+                    }
+                    BlobTier::Primary if commit.created + tiering.min_age <= now => {
+                        moves.push((hash, commit, assert_value, TierMove::Migrate));
+                    }
+                    BlobTier::Secondary
+                        if tiering.promote_after.is_some_and(|promote_after| {
+                            reads
+                                .get(&hash)
+                                .is_some_and(|reads| *reads >= promote_after)
+                        }) =>
+                    {
+                        moves.push((hash, commit, assert_value, TierMove::Promote));
+                    }
+                    _ => {}
+                }
+
+                Ok(true)
+            },
+        )
+        .await
+        .caused_by(trc::location!())?;
+
+        // The age of blobs committed before tiering was enabled is unknown,
+        // they are dated now and considered on later runs
+        for chunk in undated.chunks(1000) {
+            let mut batch = BatchBuilder::new();
+            for (hash, assert_value) in chunk {
+                let class = ValueClass::Blob(BlobOp::Commit { hash: hash.clone() });
+                batch.assert_value(class.clone(), *assert_value).set(
+                    class,
+                    BlobCommit {
+                        created: now,
+                        tier: BlobTier::Primary,
+                    }
+                    .serialize(),
+                );
+            }
+            if let Err(err) = self.write(batch.build()).await {
+                if !err.is_assertion_failure() {
+                    return Err(err.caused_by(trc::location!()));
+                }
+            }
+        }
+
+        let mut stats = TierStats::default();
+        for (hash, commit, assert_value, tier_move) in moves {
+            let (source, target, tier, created) = match tier_move {
+                TierMove::Migrate => (
+                    blob_store,
+                    &tiering.store,
+                    BlobTier::Secondary,
+                    commit.created,
+                ),
+                TierMove::Promote => (&tiering.store, blob_store, BlobTier::Primary, now),
+            };
+
+            match self
+                .move_blob(
+                    &hash,
+                    source,
+                    target,
+                    assert_value,
+                    BlobCommit { created, tier },
+                )
+                .await
+            {
+                Ok(Some(size)) => {
+                    if let TierMove::Migrate = tier_move {
+                        trc::event!(
+                            Store(StoreEvent::BlobTierMigrated),
+                            Key = hash.as_slice(),
+                            Size = size,
+                        );
+                        stats.migrated += 1;
+                        stats.migrated_bytes += size;
+                    } else {
+                        trc::event!(
+                            Store(StoreEvent::BlobTierPromoted),
+                            Key = hash.as_slice(),
+                            Size = size,
+                        );
+                        stats.promoted += 1;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    trc::event!(
+                        Store(StoreEvent::BlobTierFailed),
+                        Key = hash.as_slice(),
+                        CausedBy = err,
+                    );
+                    stats.failures += 1;
+                }
+            }
+        }
+
+        trc::event!(
+            Store(StoreEvent::BlobTierCompleted),
+            TotalSuccesses = stats.migrated,
+            Size = stats.migrated_bytes,
+            Updated = stats.promoted,
+            TotalFailures = stats.failures,
+            Elapsed = op_start.elapsed(),
+        );
+
+        Ok(stats)
+    }
+
+    // Returns the size of the moved blob, or None if the blob was committed
+    // again while it was being copied
+    async fn move_blob(
+        &self,
+        hash: &BlobHash,
+        source: &BlobStore,
+        target: &BlobStore,
+        assert_value: AssertValue,
+        commit: BlobCommit,
+    ) -> trc::Result<Option<usize>> {
+        let data = source
+            .get_primary_blob(hash.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?;
+        let size = if let Some(data) = data {
+            if BlobHash::from(data.as_slice()) != *hash {
+                return Err(trc::StoreEvent::DataCorruption
+                    .into_err()
+                    .details("Blob does not match its hash"));
+            }
+
+            // Copy and verify
+            target
+                .put_blob(hash.as_slice(), &data)
+                .await
+                .caused_by(trc::location!())?;
+            if !target
+                .get_primary_blob(hash.as_slice(), 0..usize::MAX)
+                .await
+                .caused_by(trc::location!())?
+                .is_some_and(|copy| BlobHash::from(copy.as_slice()) == *hash)
+            {
+                return Err(trc::StoreEvent::DataCorruption
+                    .into_err()
+                    .details("Blob copy does not match its hash"));
+            }
+
+            data.len()
+        } else if target
+            .get_primary_blob(hash.as_slice(), 0..usize::MAX)
+            .await
+            .caused_by(trc::location!())?
+            .is_some_and(|data| BlobHash::from(data.as_slice()) == *hash)
+        {
+            // A previous run was interrupted before the commit was updated
+            0
+        } else {
+            return Err(trc::StoreEvent::NotFound
+                .into_err()
+                .details("Blob not found in source tier"));
+        };
+
+        // Point the commit to the new tier
+        let class = ValueClass::Blob(BlobOp::Commit { hash: hash.clone() });
+        let mut batch = BatchBuilder::new();
+        batch
+            .assert_value(class.clone(), assert_value)
+            .set(class, commit.serialize());
+        match self.write(batch.build()).await {
+            Ok(_) => {}
+            Err(err) if err.is_assertion_failure() => {
+                // The blob was committed again or purged while it was being
+                // copied, the source copy is kept
+                if let BlobTier::Secondary = commit.tier {
+                    target
+                        .delete_primary_blob(hash.as_slice())
+                        .await
+                        .caused_by(trc::location!())?;
+                }
+                return Ok(None);
+            }
+            Err(err) => return Err(err.caused_by(trc::location!())),
+        }
+
+        source
+            .delete_primary_blob(hash.as_slice())
+            .await
+            .caused_by(trc::location!())?;
+
+        Ok(Some(size))
+    }
+}
+
+impl BlobCommit {
+    pub fn now() -> Self {
+        BlobCommit {
+            created: now(),
+            tier: BlobTier::Primary,
+        }
+    }
+}
+
+impl Serialize for BlobCommit {
+    fn serialize(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(U64_LEN + 1);
+        bytes.extend_from_slice(&self.created.to_be_bytes());
+        if let BlobTier::Secondary = self.tier {
+            bytes.push(1);
+        }
+        bytes
+    }
+}
+
+impl Deserialize for BlobCommit {
+    fn deserialize(bytes: &[u8]) -> trc::Result<Self> {
+        match bytes.len() {
+            0 => Ok(BlobCommit::default()),
+            U64_LEN => Ok(BlobCommit {
+                created: bytes.deserialize_be_u64(0)?,
+                tier: BlobTier::Primary,
+            }),
+            _ => Ok(BlobCommit {
+                created: bytes.deserialize_be_u64(0)?,
+                tier: match bytes[U64_LEN] {
+                    0 => BlobTier::Primary,
+                    _ => BlobTier::Secondary,
+                },
+            }),
+        }
+    }
+}
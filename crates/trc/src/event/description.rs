@@ -146,6 +146,9 @@ impl ClusterEvent {
             ClusterEvent::InvalidPacket => "Received an invalid gossip packet",
             ClusterEvent::DecryptionError => "Failed to decrypt a gossip packet",
             ClusterEvent::Error => "A cluster error occurred",
+            ClusterEvent::ReplicationSent => "Directory changes sent to replica",
+            ClusterEvent::ReplicationApplied => "Directory changes applied from primary",
+            ClusterEvent::ReplicationConflict => "Replication conflict",
         }
     }
 
@@ -164,6 +167,9 @@ impl ClusterEvent {
             ClusterEvent::InvalidPacket => "Received an invalid gossip packet",
             ClusterEvent::DecryptionError => "Failed to decrypt a gossip packet",
             ClusterEvent::Error => "An error occurred in the cluster",
+            ClusterEvent::ReplicationSent => "Directory and configuration changes were sent to the standby server.",
+            ClusterEvent::ReplicationApplied => "Directory and configuration changes received from the primary server were applied.",
+            ClusterEvent::ReplicationConflict => "A replicated change conflicts with a more recent local change, the most recent change was kept.",
         }
     }
 }
@@ -177,6 +183,15 @@ impl HousekeeperEvent {
             HousekeeperEvent::PurgeAccounts => "Purging accounts",
             HousekeeperEvent::PurgeSessions => "Purging sessions",
             HousekeeperEvent::PurgeStore => "Purging store",
+            HousekeeperEvent::TaskStarted => "Scheduled task started",
+            HousekeeperEvent::TaskCompleted => "Scheduled task completed",
+            HousekeeperEvent::TaskFailed => "Scheduled task failed",
+            HousekeeperEvent::TaskLocked => "Scheduled task locked",
+            HousekeeperEvent::DirectorySync => "Directory synchronization completed",
+            HousekeeperEvent::MigrationStarted => "Migration started",
+            HousekeeperEvent::MigrationCompleted => "Migration completed",
+            HousekeeperEvent::MigrationFailed => "Migration failed",
+            HousekeeperEvent::MigrationLocked => "Migration locked",
         }
     }
 
@@ -188,6 +203,21 @@ impl HousekeeperEvent {
             HousekeeperEvent::PurgeAccounts => "Purging accounts",
             HousekeeperEvent::PurgeSessions => "Purging sessions",
             HousekeeperEvent::PurgeStore => "Purging store",
+            HousekeeperEvent::TaskStarted => "A scheduled maintenance task has started running",
+            HousekeeperEvent::TaskCompleted => "A scheduled maintenance task has completed",
+            HousekeeperEvent::TaskFailed => "A scheduled maintenance task has failed",
+            HousekeeperEvent::TaskLocked => "A scheduled maintenance task is already running",
+            HousekeeperEvent::DirectorySync => {
+                "An external directory has been synchronized with the internal store"
+            }
+            HousekeeperEvent::MigrationStarted => "A background data migration has started running",
+            HousekeeperEvent::MigrationCompleted => "A background data migration has finished",
+            HousekeeperEvent::MigrationFailed => {
+                "A background data migration failed and will resume from its last checkpoint"
+            }
+            HousekeeperEvent::MigrationLocked => {
+                "A background data migration is already running on another node"
+            }
         }
     }
 }
@@ -200,6 +230,10 @@ impl FtsIndexEvent {
             FtsIndexEvent::LockBusy => "Full-text search index lock is busy",
             FtsIndexEvent::BlobNotFound => "Blob not found for full-text indexing",
             FtsIndexEvent::MetadataNotFound => "Metadata not found for full-text indexing",
+            FtsIndexEvent::MissingEntries => "Messages missing from the full-text index",
+            FtsIndexEvent::OrphanedEntries => "Orphaned full-text index entries",
+            FtsIndexEvent::RebuildProgress => "Full-text index rebuild progress",
+            FtsIndexEvent::VerifyCompleted => "Full-text index verification completed",
         }
     }
 
@@ -210,6 +244,18 @@ impl FtsIndexEvent {
             FtsIndexEvent::LockBusy => "The full-text search index lock is busy",
             FtsIndexEvent::BlobNotFound => "The blob was not found for full-text indexing",
             FtsIndexEvent::MetadataNotFound => "The metadata was not found for full-text indexing",
+            FtsIndexEvent::MissingEntries => {
+                "Stored messages were found without full-text index entries"
+            }
+            FtsIndexEvent::OrphanedEntries => {
+                "The full-text index contains entries for messages that no longer exist"
+            }
+            FtsIndexEvent::RebuildProgress => {
+                "Missing full-text index entries are being rebuilt from the stored messages"
+            }
+            FtsIndexEvent::VerifyCompleted => {
+                "The full-text index was compared against the stored messages"
+            }
         }
     }
 }
@@ -482,6 +528,26 @@ impl SmtpEvent {
             SmtpEvent::RequestTooLarge => "Request too large",
             SmtpEvent::ConnectionStart => "SMTP connection started",
             SmtpEvent::ConnectionEnd => "SMTP connection ended",
+            SmtpEvent::CalloutAccepted => "Callout verification accepted recipient",
+            SmtpEvent::CalloutRejected => "Callout verification rejected recipient",
+            SmtpEvent::CalloutFailed => "Callout verification failed",
+            SmtpEvent::AttachmentRejected => "Message rejected by attachment policy",
+            SmtpEvent::AttachmentWarning => "Attachment policy warning added",
+            SmtpEvent::DnsblListed => "Sender listed in DNS blocklist",
+            SmtpEvent::DnsblRejected => "Sender rejected by DNS blocklist",
+            SmtpEvent::DnsblTimeout => "DNS blocklist lookup timeout",
+            SmtpEvent::DnsblDisabled => "DNS blocklist disabled",
+            SmtpEvent::DnsblEnabled => "DNS blocklist enabled",
+            SmtpEvent::RcptToRewriteLoop => "Recipient rewrite loop",
+            SmtpEvent::RcptToRewriteInvalid => "Invalid recipient rewrite rule",
+            SmtpEvent::SenderReputationBlocked => "Submission refused due to the sender reputation",
+            SmtpEvent::MimeLimitExceeded => "MIME limits exceeded",
+            SmtpEvent::RcptToPolicyViolation => "Recipient rejected by outbound policy",
+            SmtpEvent::RcptToPolicyMemberRemoved => "List member removed by outbound policy",
+            SmtpEvent::RcptToAliasLoop => "Recipient alias loop",
+            SmtpEvent::RcptToAliasFanOut => "Recipient alias fan-out exceeded",
+            SmtpEvent::RcptToListPostingDenied => "Posting to mailing list denied",
+            SmtpEvent::RcptToListModerated => "Posting to mailing list requires moderation",
         }
     }
 
@@ -606,6 +672,30 @@ impl SmtpEvent {
             SmtpEvent::ConnectionStart => "A new SMTP connection was started",
             SmtpEvent::ConnectionEnd => "The SMTP connection was ended",
             SmtpEvent::StartTlsAlready => "TLS is already active",
+            SmtpEvent::CalloutAccepted => {
+                "The next hop accepted the recipient during callout verification"
+            }
+            SmtpEvent::CalloutRejected => {
+                "The next hop rejected the recipient during callout verification"
+            }
+            SmtpEvent::CalloutFailed => "The recipient could not be verified with the next hop",
+            SmtpEvent::AttachmentRejected => "The message contains an attachment that violates the attachment policy.",
+            SmtpEvent::AttachmentWarning => "The message contains an attachment that matched the attachment policy and a warning header was added.",
+            SmtpEvent::DnsblListed => "The connecting host or sender domain is listed in a DNS blocklist.",
+            SmtpEvent::DnsblRejected => "The connecting host or sender domain is listed in a DNS blocklist marked as blocking.",
+            SmtpEvent::DnsblTimeout => "Not all DNS blocklist lookups completed before the deadline.",
+            SmtpEvent::DnsblDisabled => "The DNS blocklist answered a query for a known clean entry and was disabled.",
+            SmtpEvent::DnsblEnabled => "The DNS blocklist no longer lists known clean entries and was enabled again.",
+            SmtpEvent::RcptToRewriteLoop => "The recipient address could not be resolved because the rewrite map contains a loop or exceeds the maximum number of hops.",
+            SmtpEvent::RcptToRewriteInvalid => "A recipient rewrite rule contains an invalid regular expression and was ignored.",
+            SmtpEvent::SenderReputationBlocked => "The authenticated account is throttled, must authenticate again or has its submission suspended due to unusual sending activity.",
+            SmtpEvent::MimeLimitExceeded => "The message structure exceeded the configured MIME limits and was either rejected or delivered as an opaque body.",
+            SmtpEvent::RcptToPolicyViolation => "The recipient is not allowed by the outbound recipient policy that applies to the authenticated sender.",
+            SmtpEvent::RcptToPolicyMemberRemoved => "A member of a mailing list was not added to the envelope because it is not allowed by the outbound recipient policy that applies to the authenticated sender.",
+            SmtpEvent::RcptToAliasLoop => "An alias forwards to an address that leads back to itself, the looping destination was skipped.",
+            SmtpEvent::RcptToAliasFanOut => "The recipient alias expands to more destinations than the configured maximum.",
+            SmtpEvent::RcptToListPostingDenied => "The sender is not allowed to post to a mailing list by its posting policy.",
+            SmtpEvent::RcptToListModerated => "Messages sent to a mailing list will be held until one of its moderators approves them.",
         }
     }
 }
@@ -651,6 +741,12 @@ impl DeliveryEvent {
             DeliveryEvent::DsnPermFail => "DSN permanent failure notification",
             DeliveryEvent::RawInput => "Raw SMTP input received",
             DeliveryEvent::RawOutput => "Raw SMTP output sent",
+            DeliveryEvent::SourceIpBindFailed => "Failed to bind source IP",
+            DeliveryEvent::HeldForReview => "Message held for review",
+            DeliveryEvent::SendingWindowClosed => "Sending window closed",
+            DeliveryEvent::Ipv6Demoted => "IPv6 demoted for destination",
+            DeliveryEvent::HappyEyeballsFallback => "Happy Eyeballs fallback",
+            DeliveryEvent::HeldForModeration => "Message held for moderation",
         }
     }
 
@@ -708,6 +804,14 @@ impl DeliveryEvent {
             }
             DeliveryEvent::RawInput => "Raw SMTP input received",
             DeliveryEvent::RawOutput => "Raw SMTP output sent",
+            DeliveryEvent::SourceIpBindFailed => {
+                "The selected source IP address could not be bound for an outbound connection"
+            }
+            DeliveryEvent::HeldForReview => "The sending policy of the sender's tenant requires outbound messages to be reviewed before delivery, the message was parked until it is released or rejected.",
+            DeliveryEvent::SendingWindowClosed => "The sending policy of the sender's tenant does not allow delivery at this time, the message was rescheduled for the next sending window.",
+            DeliveryEvent::Ipv6Demoted => "IPv6 connections to the destination failed repeatedly, IPv4 addresses will be tried first",
+            DeliveryEvent::HappyEyeballsFallback => "The connection attempt did not complete in time and an address of the other family was tried in parallel",
+            DeliveryEvent::HeldForModeration => "A message posted to a moderated mailing list has been parked until one of the list moderators approves or rejects it.",
         }
     }
 }
@@ -1089,6 +1193,9 @@ impl TlsEvent {
             TlsEvent::CertificateNotFound => "TLS certificate not found",
             TlsEvent::NoCertificatesAvailable => "No TLS certificates available",
             TlsEvent::MultipleCertificatesAvailable => "Multiple TLS certificates available",
+            TlsEvent::BelowTargetVersion => "Negotiated TLS version below target",
+            TlsEvent::CertificateReloaded => "Certificate reloaded",
+            TlsEvent::CertificateReloadFailed => "Certificate reload failed",
         }
     }
 
@@ -1100,6 +1207,9 @@ impl TlsEvent {
             TlsEvent::CertificateNotFound => "The TLS certificate was not found",
             TlsEvent::NoCertificatesAvailable => "No TLS certificates are available",
             TlsEvent::MultipleCertificatesAvailable => "Multiple TLS certificates are available",
+            TlsEvent::BelowTargetVersion => "A client negotiated a TLS version older than the target version configured for the listener.",
+            TlsEvent::CertificateReloaded => "A certificate was reloaded after its files changed on disk",
+            TlsEvent::CertificateReloadFailed => "A certificate changed on disk could not be loaded and the previous certificate remains active",
         }
     }
 }
@@ -1155,6 +1265,8 @@ impl ServerEvent {
             ServerEvent::StartupError => "Server startup error",
             ServerEvent::ThreadError => "Server thread error",
             ServerEvent::Licensing => "Server licensing event",
+            ServerEvent::BackpressureStart => "Load shedding started",
+            ServerEvent::BackpressureStop => "Load shedding stopped",
         }
     }
 
@@ -1165,6 +1277,8 @@ impl ServerEvent {
             ServerEvent::StartupError => "An error occurred while starting the server",
             ServerEvent::ThreadError => "An error occurred with a server thread",
             ServerEvent::Licensing => "A licensing event occurred",
+            ServerEvent::BackpressureStart => "The server started rejecting new connections because queue depth, store write latency or memory usage crossed their configured thresholds",
+            ServerEvent::BackpressureStop => "Queue depth, store write latency and memory usage recovered and new connections are accepted again",
         }
     }
 }
@@ -1179,6 +1293,7 @@ impl TelemetryEvent {
             TelemetryEvent::OtelExporterError => "OpenTelemetry exporter error",
             TelemetryEvent::OtelMetricsExporterError => "OpenTelemetry metrics exporter error",
             TelemetryEvent::PrometheusExporterError => "Prometheus exporter error",
+            TelemetryEvent::PrivacyModeChanged => "Privacy mode changed",
         }
     }
 
@@ -1197,6 +1312,9 @@ impl TelemetryEvent {
             TelemetryEvent::PrometheusExporterError => {
                 "An error occurred with the Prometheus exporter"
             }
+            TelemetryEvent::PrivacyModeChanged => {
+                "The privacy mode of a tracer was changed at runtime by an administrator"
+            }
         }
     }
 }
@@ -1277,6 +1395,9 @@ impl PurgeEvent {
             PurgeEvent::PurgeActive => "Active purge in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup executed",
+            PurgeEvent::QuotaExpunge => "Messages expunged to enforce a folder message limit",
+            PurgeEvent::ExpiredRecords => "Expired records removed",
+            PurgeEvent::AccountOnHold => "Account on hold",
         }
     }
 
@@ -1289,6 +1410,13 @@ impl PurgeEvent {
             PurgeEvent::PurgeActive => "An active purge is in progress",
             PurgeEvent::AutoExpunge => "Auto-expunge has been executed",
             PurgeEvent::TombstoneCleanup => "Tombstone cleanup has been executed",
+            PurgeEvent::QuotaExpunge => {
+                "The oldest messages in a folder were expunged to make room for new messages"
+            }
+            PurgeEvent::ExpiredRecords => {
+                "Expired short-lived records have been removed from the store"
+            }
+            PurgeEvent::AccountOnHold => "The account is under litigation hold, deleted messages are preserved and retention tasks are skipped.",
         }
     }
 }
@@ -1560,6 +1688,14 @@ impl StoreEvent {
             StoreEvent::BlobWrite => "Blob write operation",
             StoreEvent::BlobDelete => "Blob delete operation",
             StoreEvent::DataIterate => "Data store iteration operation",
+            StoreEvent::LdapModify => "LDAP entry modified",
+            StoreEvent::CopyProgress => "Store copy progress",
+            StoreEvent::CopyCompleted => "Store copy completed",
+            StoreEvent::CopyMismatch => "Store copy mismatch",
+            StoreEvent::BlobTierMigrated => "Blob moved to secondary store",
+            StoreEvent::BlobTierPromoted => "Blob promoted to primary store",
+            StoreEvent::BlobTierFailed => "Blob tiering failed",
+            StoreEvent::BlobTierCompleted => "Blob tiering completed",
         }
     }
 
@@ -1595,6 +1731,14 @@ impl StoreEvent {
             StoreEvent::BlobWrite => "A blob write operation was executed",
             StoreEvent::BlobDelete => "A blob delete operation was executed",
             StoreEvent::DataIterate => "A data store iteration operation was executed",
+            StoreEvent::LdapModify => "An LDAP entry was modified",
+            StoreEvent::CopyProgress => "Reports the number of keys and bytes copied so far while copying a store to another backend",
+            StoreEvent::CopyCompleted => "A full copy, delta pass or verification between two stores has finished",
+            StoreEvent::CopyMismatch => "A key or blob differs between the source and destination stores, or a blob does not match its hash",
+            StoreEvent::BlobTierMigrated => "A blob older than the tiering threshold was copied to the secondary blob store, verified and removed from the primary store",
+            StoreEvent::BlobTierPromoted => "A blob that was read frequently from the secondary blob store was moved back to the primary store",
+            StoreEvent::BlobTierFailed => "A blob could not be moved between blob store tiers, it is left in place and retried on the next run",
+            StoreEvent::BlobTierCompleted => "The blob tiering task finished moving cold blobs to the secondary store and hot blobs back to the primary store",
         }
     }
 }
@@ -1694,6 +1838,7 @@ impl LimitEvent {
             LimitEvent::BlobQuota => "Blob quota limit reached",
             LimitEvent::TooManyRequests => "Too many requests",
             LimitEvent::TenantQuota => "Tenant quota limit reached",
+            LimitEvent::LoadShed => "Connection shed",
         }
     }
 
@@ -1709,6 +1854,9 @@ impl LimitEvent {
             LimitEvent::BlobQuota => "The blob quota limit has been reached",
             LimitEvent::TooManyRequests => "Too many requests have been made",
             LimitEvent::TenantQuota => "One of the tenant quota limits has been reached",
+            LimitEvent::LoadShed => {
+                "A new connection or request was rejected because the server is shedding load"
+            }
         }
     }
 }
@@ -1722,6 +1870,15 @@ impl ManageEvent {
             ManageEvent::NotFound => "Managed resource not found",
             ManageEvent::NotSupported => "Management operation not supported",
             ManageEvent::Error => "Management error",
+            ManageEvent::DirectoryAlert => "Security-relevant directory change",
+            ManageEvent::MaintenanceEnabled => "Maintenance mode enabled",
+            ManageEvent::MaintenanceDisabled => "Maintenance mode disabled",
+            ManageEvent::HoldPlaced => "Litigation hold placed",
+            ManageEvent::HoldReleased => "Litigation hold released",
+            ManageEvent::DirectoryInconsistency => "Directory inconsistency found",
+            ManageEvent::DirectoryVerified => "Directory verified",
+            ManageEvent::QuotaMismatch => "Quota usage mismatch",
+            ManageEvent::QuotaRecalculated => "Quota recalculated",
         }
     }
 
@@ -1733,6 +1890,21 @@ impl ManageEvent {
             ManageEvent::NotFound => "The managed resource was not found",
             ManageEvent::NotSupported => "The management operation is not supported",
             ManageEvent::Error => "A management error occurred",
+            ManageEvent::DirectoryAlert => {
+                "A watched directory change such as a role grant or secret change was detected"
+            }
+            ManageEvent::MaintenanceEnabled => {
+                "Directory changes were disabled by an administrator"
+            }
+            ManageEvent::MaintenanceDisabled => {
+                "Directory changes were re-enabled by an administrator"
+            }
+            ManageEvent::HoldPlaced => "An administrator placed a litigation hold on an account, its data will be preserved until the hold is released.",
+            ManageEvent::HoldReleased => "An administrator released the litigation hold on an account, preserved data will be cleaned up.",
+            ManageEvent::DirectoryInconsistency => "A directory key references a missing principal or lacks its counterpart key",
+            ManageEvent::DirectoryVerified => "The directory consistency check has completed",
+            ManageEvent::QuotaMismatch => "The used quota counter of an account differs from the size of its stored messages and scripts",
+            ManageEvent::QuotaRecalculated => "The used quota of all accounts has been compared against their stored messages and scripts",
         }
     }
 }
@@ -1747,6 +1919,12 @@ impl AuthEvent {
             AuthEvent::Error => "Authentication error",
             AuthEvent::TokenExpired => "OAuth token expired",
             AuthEvent::ClientRegistration => "OAuth Client registration",
+            AuthEvent::ApiKeyIssued => "API key issued",
+            AuthEvent::ApiKeyRevoked => "API key revoked",
+            AuthEvent::AccountDisabled => "Account disabled",
+            AuthEvent::SecretUpgraded => "Legacy secret upgraded",
+            AuthEvent::RecoveryCodeUsed => "Recovery code used",
+            AuthEvent::AccountLocked => "Account locked",
         }
     }
 
@@ -1759,6 +1937,12 @@ impl AuthEvent {
             AuthEvent::Error => "An error occurred with authentication",
             AuthEvent::TokenExpired => "OAuth authentication token has expired",
             AuthEvent::ClientRegistration => "OAuth client successfully registered",
+            AuthEvent::ApiKeyIssued => "A tenant-scoped API key was issued for the management API",
+            AuthEvent::ApiKeyRevoked => "A tenant-scoped API key was revoked",
+            AuthEvent::AccountDisabled => "The account credentials are valid but the account has been disabled by an administrator",
+            AuthEvent::SecretUpgraded => "A password stored using a legacy hashing scheme was re-hashed with the default algorithm after a successful login",
+            AuthEvent::RecoveryCodeUsed => "A one-time recovery code was used in place of a TOTP token and has been removed from the account",
+            AuthEvent::AccountLocked => "The account is temporarily locked after too many failed authentication attempts",
         }
     }
 }
@@ -1794,6 +1978,25 @@ impl SecurityEvent {
             SecurityEvent::IpBlocked => "Blocked IP address",
             SecurityEvent::ScanBan => "Banned due to scan",
             SecurityEvent::Unauthorized => "Unauthorized access",
+            SecurityEvent::ApprovalRequested => "Approval requested",
+            SecurityEvent::ApprovalGranted => "Approval granted",
+            SecurityEvent::ApprovalRejected => "Approval rejected",
+            SecurityEvent::ApprovalCancelled => "Approval cancelled",
+            SecurityEvent::ApprovalExpired => "Approval expired",
+            SecurityEvent::SessionsDisconnected => "Sessions disconnected by administrator",
+            SecurityEvent::OperationUndone => "Directory operation undone",
+            SecurityEvent::SenderReputationAction => {
+                "Automatic action applied to a sender with a poor reputation"
+            }
+            SecurityEvent::SenderReputationCleared => "Sender reputation state cleared",
+            SecurityEvent::MessageRecalled => "Messages recalled",
+            SecurityEvent::MessageHoldReleased => "Held message released",
+            SecurityEvent::MessageHoldRejected => "Held message rejected",
+            SecurityEvent::TenantExportRequested => "Tenant data export requested",
+            SecurityEvent::TenantExportCompleted => "Tenant data export completed",
+            SecurityEvent::TenantExportDownloaded => "Tenant data export downloaded",
+            SecurityEvent::AccountLockout => "Account locked out",
+            SecurityEvent::SessionsRevoked => "Sessions revoked by administrator",
         }
     }
 
@@ -1809,6 +2012,23 @@ impl SecurityEvent {
             SecurityEvent::LoiterBan => "IP address was banned due to multiple loitering events",
             SecurityEvent::IpBlocked => "Rejected connection from blocked IP address",
             SecurityEvent::Unauthorized => "Account does not have permission to access resource",
+            SecurityEvent::ApprovalRequested => "A protected administrative operation was recorded for approval by a second administrator",
+            SecurityEvent::ApprovalGranted => "A protected administrative operation was approved and executed",
+            SecurityEvent::ApprovalRejected => "A protected administrative operation was rejected by an approver",
+            SecurityEvent::ApprovalCancelled => "A pending approval request was cancelled",
+            SecurityEvent::ApprovalExpired => "A pending approval request expired before being approved",
+            SecurityEvent::SessionsDisconnected => "An administrator forcibly disconnected the sessions of a principal.",
+            SecurityEvent::OperationUndone => "An administrator reverted a previously recorded directory operation.",
+            SecurityEvent::SenderReputationAction => "The outbound reputation score of an authenticated account crossed a configured threshold and the server throttled, required re-authentication or suspended submission for the account.",
+            SecurityEvent::SenderReputationCleared => "An administrator reviewed and cleared the reputation state of an account, lifting any automatic restrictions.",
+            SecurityEvent::MessageRecalled => "Delivered messages were recalled from an account by an administrator",
+            SecurityEvent::MessageHoldReleased => "A reviewer released a message that was held by the sending policy of its tenant.",
+            SecurityEvent::MessageHoldRejected => "A reviewer rejected a message that was held by the sending policy of its tenant, the sender was notified.",
+            SecurityEvent::TenantExportRequested => "A data export of all principals and accounts belonging to a tenant was requested",
+            SecurityEvent::TenantExportCompleted => "A tenant data export bundle was generated and is available for download",
+            SecurityEvent::TenantExportDownloaded => "A tenant data export bundle was downloaded using a signed link",
+            SecurityEvent::AccountLockout => "The account was temporarily locked after reaching the maximum number of consecutive failed authentication attempts",
+            SecurityEvent::SessionsRevoked => "An administrator revoked the sessions and tokens issued to a principal.",
         }
     }
 }
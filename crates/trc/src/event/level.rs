@@ -12,6 +12,11 @@ impl EventType {
     pub fn level(&self) -> Level {
         match self {
             EventType::Store(event) => match event {
+                StoreEvent::CopyMismatch | StoreEvent::BlobTierFailed => Level::Warn,
+                StoreEvent::CopyProgress
+                | StoreEvent::CopyCompleted
+                | StoreEvent::BlobTierCompleted => Level::Info,
+                StoreEvent::BlobTierMigrated | StoreEvent::BlobTierPromoted => Level::Debug,
                 StoreEvent::DataWrite
                 | StoreEvent::DataIterate
                 | StoreEvent::BlobRead
@@ -19,7 +24,8 @@ impl EventType {
                 | StoreEvent::BlobDelete
                 | StoreEvent::SqlQuery
                 | StoreEvent::LdapQuery
-                | StoreEvent::LdapBind => Level::Trace,
+                | StoreEvent::LdapBind
+                | StoreEvent::LdapModify => Level::Trace,
                 StoreEvent::NotFound => Level::Debug,
                 StoreEvent::AssertValueFailed
                 | StoreEvent::FoundationdbError
@@ -118,6 +124,22 @@ impl EventType {
                 Pop3Event::RawInput | Pop3Event::RawOutput => Level::Trace,
             },
             EventType::Smtp(event) => match event {
+                SmtpEvent::RcptToListModerated => Level::Info,
+                SmtpEvent::RcptToListPostingDenied => Level::Info,
+                SmtpEvent::RcptToAliasFanOut => Level::Info,
+                SmtpEvent::RcptToAliasLoop => Level::Info,
+                SmtpEvent::RcptToPolicyMemberRemoved => Level::Info,
+                SmtpEvent::RcptToPolicyViolation => Level::Info,
+                SmtpEvent::SenderReputationBlocked => Level::Info,
+                SmtpEvent::RcptToRewriteInvalid => Level::Warn,
+                SmtpEvent::RcptToRewriteLoop => Level::Info,
+                SmtpEvent::DnsblEnabled => Level::Info,
+                SmtpEvent::DnsblDisabled => Level::Warn,
+                SmtpEvent::DnsblTimeout => Level::Debug,
+                SmtpEvent::DnsblRejected => Level::Info,
+                SmtpEvent::DnsblListed => Level::Info,
+                SmtpEvent::AttachmentWarning => Level::Info,
+                SmtpEvent::AttachmentRejected => Level::Info,
                 SmtpEvent::ConnectionStart | SmtpEvent::ConnectionEnd => Level::Debug,
                 SmtpEvent::DidNotSayEhlo
                 | SmtpEvent::EhloExpected
@@ -158,7 +180,10 @@ impl EventType {
                 | SmtpEvent::PipeSuccess
                 | SmtpEvent::PipeError
                 | SmtpEvent::Error => Level::Debug,
-                SmtpEvent::MissingLocalHostname | SmtpEvent::RemoteIdNotFound => Level::Warn,
+                SmtpEvent::MissingLocalHostname
+                | SmtpEvent::RemoteIdNotFound
+                | SmtpEvent::CalloutFailed
+                | SmtpEvent::MimeLimitExceeded => Level::Warn,
                 SmtpEvent::ConcurrencyLimitExceeded
                 | SmtpEvent::TransferLimitExceeded
                 | SmtpEvent::RateLimitExceeded
@@ -186,6 +211,8 @@ impl EventType {
                 | SmtpEvent::MailboxDoesNotExist
                 | SmtpEvent::RelayNotAllowed
                 | SmtpEvent::RcptTo
+                | SmtpEvent::CalloutAccepted
+                | SmtpEvent::CalloutRejected
                 | SmtpEvent::TooManyInvalidRcpt
                 | SmtpEvent::Vrfy
                 | SmtpEvent::VrfyNotFound
@@ -223,14 +250,33 @@ impl EventType {
                 LimitEvent::BlobQuota => Level::Debug,
                 LimitEvent::TooManyRequests => Level::Warn,
                 LimitEvent::TenantQuota => Level::Info,
+                LimitEvent::LoadShed => Level::Info,
+            },
+            EventType::Manage(event) => match event {
+                ManageEvent::QuotaRecalculated => Level::Info,
+                ManageEvent::QuotaMismatch => Level::Warn,
+                ManageEvent::DirectoryVerified => Level::Info,
+                ManageEvent::DirectoryInconsistency => Level::Warn,
+                ManageEvent::DirectoryAlert
+                | ManageEvent::MaintenanceEnabled
+                | ManageEvent::MaintenanceDisabled
+                | ManageEvent::HoldPlaced
+                | ManageEvent::HoldReleased => Level::Info,
+                _ => Level::Debug,
             },
-            EventType::Manage(_) => Level::Debug,
             EventType::Auth(cause) => match cause {
                 AuthEvent::Failed | AuthEvent::TokenExpired => Level::Debug,
                 AuthEvent::MissingTotp => Level::Trace,
                 AuthEvent::TooManyAttempts => Level::Warn,
+                AuthEvent::AccountDisabled
+                | AuthEvent::SecretUpgraded
+                | AuthEvent::RecoveryCodeUsed
+                | AuthEvent::AccountLocked => Level::Info,
                 AuthEvent::Error => Level::Error,
-                AuthEvent::Success | AuthEvent::ClientRegistration => Level::Info,
+                AuthEvent::Success
+                | AuthEvent::ClientRegistration
+                | AuthEvent::ApiKeyIssued
+                | AuthEvent::ApiKeyRevoked => Level::Info,
             },
             EventType::Config(cause) => match cause {
                 ConfigEvent::ParseError
@@ -265,13 +311,16 @@ impl EventType {
             },
             EventType::MailAuth(_) => Level::Debug,
             EventType::Purge(event) => match event {
+                PurgeEvent::QuotaExpunge => Level::Info,
+                PurgeEvent::ExpiredRecords => Level::Info,
                 PurgeEvent::Started => Level::Debug,
                 PurgeEvent::Finished => Level::Debug,
                 PurgeEvent::Running => Level::Info,
                 PurgeEvent::Error => Level::Error,
                 PurgeEvent::PurgeActive
                 | PurgeEvent::AutoExpunge
-                | PurgeEvent::TombstoneCleanup => Level::Debug,
+                | PurgeEvent::TombstoneCleanup
+                | PurgeEvent::AccountOnHold => Level::Debug,
             },
             EventType::Eval(event) => match event {
                 EvalEvent::Error | EvalEvent::StoreNotFound => Level::Debug,
@@ -279,10 +328,12 @@ impl EventType {
                 EvalEvent::DirectoryNotFound => Level::Warn,
             },
             EventType::Server(event) => match event {
-                ServerEvent::Startup | ServerEvent::Shutdown | ServerEvent::Licensing => {
-                    Level::Info
-                }
+                ServerEvent::Startup
+                | ServerEvent::Shutdown
+                | ServerEvent::Licensing
+                | ServerEvent::BackpressureStop => Level::Info,
                 ServerEvent::StartupError | ServerEvent::ThreadError => Level::Error,
+                ServerEvent::BackpressureStart => Level::Warn,
             },
             EventType::Acme(event) => match event {
                 AcmeEvent::DnsRecordCreated
@@ -314,7 +365,9 @@ impl EventType {
                 | AcmeEvent::DnsRecordLookupFailed => Level::Debug,
             },
             EventType::Tls(event) => match event {
-                TlsEvent::Handshake => Level::Info,
+                TlsEvent::CertificateReloadFailed => Level::Error,
+                TlsEvent::CertificateReloaded => Level::Info,
+                TlsEvent::Handshake | TlsEvent::BelowTargetVersion => Level::Info,
                 TlsEvent::HandshakeError | TlsEvent::CertificateNotFound => Level::Debug,
                 TlsEvent::NotConfigured => Level::Error,
                 TlsEvent::NoCertificatesAvailable | TlsEvent::MultipleCertificatesAvailable => {
@@ -356,6 +409,9 @@ impl EventType {
                 PushSubscriptionEvent::Success => Level::Trace,
             },
             EventType::Cluster(event) => match event {
+                ClusterEvent::ReplicationConflict => Level::Warn,
+                ClusterEvent::ReplicationApplied => Level::Info,
+                ClusterEvent::ReplicationSent => Level::Info,
                 ClusterEvent::PeerAlive
                 | ClusterEvent::PeerDiscovered
                 | ClusterEvent::PeerOffline
@@ -374,12 +430,24 @@ impl EventType {
                 | HousekeeperEvent::PurgeAccounts
                 | HousekeeperEvent::PurgeSessions
                 | HousekeeperEvent::PurgeStore
+                | HousekeeperEvent::TaskStarted
+                | HousekeeperEvent::TaskCompleted
+                | HousekeeperEvent::MigrationStarted
+                | HousekeeperEvent::MigrationCompleted
+                | HousekeeperEvent::DirectorySync
                 | HousekeeperEvent::Stop => Level::Info,
-                HousekeeperEvent::Schedule => Level::Debug,
+                HousekeeperEvent::Schedule
+                | HousekeeperEvent::TaskLocked
+                | HousekeeperEvent::MigrationLocked => Level::Debug,
+                HousekeeperEvent::TaskFailed | HousekeeperEvent::MigrationFailed => Level::Error,
             },
             EventType::FtsIndex(event) => match event {
-                FtsIndexEvent::Index => Level::Info,
-                FtsIndexEvent::LockBusy => Level::Warn,
+                FtsIndexEvent::Index
+                | FtsIndexEvent::RebuildProgress
+                | FtsIndexEvent::VerifyCompleted => Level::Info,
+                FtsIndexEvent::LockBusy
+                | FtsIndexEvent::MissingEntries
+                | FtsIndexEvent::OrphanedEntries => Level::Warn,
                 FtsIndexEvent::BlobNotFound
                 | FtsIndexEvent::Locked
                 | FtsIndexEvent::MetadataNotFound => Level::Debug,
@@ -448,10 +516,15 @@ impl EventType {
                 | DeliveryEvent::StartTlsError
                 | DeliveryEvent::StartTlsDisabled
                 | DeliveryEvent::ImplicitTlsError
-                | DeliveryEvent::DoubleBounce => Level::Info,
+                | DeliveryEvent::DoubleBounce
+                | DeliveryEvent::HeldForReview
+                | DeliveryEvent::HeldForModeration
+                | DeliveryEvent::SendingWindowClosed => Level::Info,
                 DeliveryEvent::ConcurrencyLimitExceeded
                 | DeliveryEvent::RateLimitExceeded
-                | DeliveryEvent::MissingOutboundHostname => Level::Warn,
+                | DeliveryEvent::MissingOutboundHostname
+                | DeliveryEvent::SourceIpBindFailed
+                | DeliveryEvent::Ipv6Demoted => Level::Warn,
                 DeliveryEvent::DsnSuccess
                 | DeliveryEvent::DsnTempFail
                 | DeliveryEvent::DsnPermFail => Level::Info,
@@ -460,7 +533,8 @@ impl EventType {
                 | DeliveryEvent::Ehlo
                 | DeliveryEvent::Auth
                 | DeliveryEvent::MailFrom
-                | DeliveryEvent::RcptTo => Level::Debug,
+                | DeliveryEvent::RcptTo
+                | DeliveryEvent::HappyEyeballsFallback => Level::Debug,
                 DeliveryEvent::RawInput | DeliveryEvent::RawOutput => Level::Trace,
             },
             EventType::Queue(event) => match event {
@@ -524,7 +598,10 @@ impl EventType {
                 | OutgoingReportEvent::SubmissionError
                 | OutgoingReportEvent::NoRecipientsFound => Level::Info,
             },
-            EventType::Telemetry(_) => Level::Warn,
+            EventType::Telemetry(event) => match event {
+                TelemetryEvent::PrivacyModeChanged => Level::Info,
+                _ => Level::Warn,
+            },
             EventType::MessageIngest(event) => match event {
                 MessageIngestEvent::Ham
                 | MessageIngestEvent::Spam
@@ -36,6 +36,8 @@ impl MetricType {
             Self::QueueCount => "queue.count",
             Self::UserCount => "user.count",
             Self::DomainCount => "domain.count",
+            Self::FtsIndexLag => "message-ingest.index-lag",
+            Self::BlobTierSize => "store.blob-tier-size",
         }
     }
 
@@ -68,6 +70,8 @@ impl MetricType {
             Self::QueueCount => "Total number of messages in the queue",
             Self::UserCount => "Total number of users",
             Self::DomainCount => "Total number of domains",
+            Self::FtsIndexLag => "Number of messages waiting to be full-text indexed",
+            Self::BlobTierSize => "Size of blobs moved to the secondary blob store",
         }
     }
 
@@ -90,6 +94,7 @@ impl MetricType {
             Self::MessageSize
             | Self::MessageAuthSize
             | Self::ReportOutgoingSize
+            | Self::BlobTierSize
             | Self::ServerMemory => "bytes",
             Self::HttpActiveConnections
             | Self::ImapActiveConnections
@@ -100,6 +105,7 @@ impl MetricType {
             Self::QueueCount => "messages",
             Self::UserCount => "users",
             Self::DomainCount => "domains",
+            Self::FtsIndexLag => "messages",
         }
     }
 
@@ -132,6 +138,8 @@ impl MetricType {
             Self::QueueCount => 24,
             Self::UserCount => 25,
             Self::DomainCount => 26,
+            Self::FtsIndexLag => 27,
+            Self::BlobTierSize => 28,
         }
     }
 
@@ -164,6 +172,8 @@ impl MetricType {
             24 => Some(Self::QueueCount),
             25 => Some(Self::UserCount),
             26 => Some(Self::DomainCount),
+            27 => Some(Self::FtsIndexLag),
+            28 => Some(Self::BlobTierSize),
             _ => None,
         }
     }
@@ -197,6 +207,8 @@ impl MetricType {
             "queue.count" => Some(Self::QueueCount),
             "user.count" => Some(Self::UserCount),
             "domain.count" => Some(Self::DomainCount),
+            "message-ingest.index-lag" => Some(Self::FtsIndexLag),
+            "store.blob-tier-size" => Some(Self::BlobTierSize),
             _ => None,
         }
     }
@@ -230,6 +242,8 @@ impl MetricType {
             Self::QueueCount,
             Self::UserCount,
             Self::DomainCount,
+            Self::FtsIndexLag,
+            Self::BlobTierSize,
         ]
     }
 }
@@ -359,6 +359,7 @@ impl AuthEvent {
                 "Try authenticating again using 'secret$totp_token'."
             ),
             Self::TooManyAttempts => "Too many authentication attempts",
+            Self::AccountDisabled => "This account has been disabled",
             _ => "Authentication error",
         }
     }
@@ -393,6 +394,15 @@ impl ManageEvent {
             Self::NotFound => "Not found",
             Self::NotSupported => "Operation not supported",
             Self::Error => "Management API Error",
+            Self::DirectoryAlert => "Directory alert",
+            Self::MaintenanceEnabled => "Maintenance mode enabled",
+            Self::MaintenanceDisabled => "Maintenance mode disabled",
+            Self::HoldPlaced => "Litigation hold placed",
+            Self::HoldReleased => "Litigation hold released",
+            Self::DirectoryInconsistency => "Directory inconsistency found",
+            Self::DirectoryVerified => "Directory verified",
+            Self::QuotaMismatch => "Quota usage mismatch",
+            Self::QuotaRecalculated => "Quota usage recalculated",
         }
     }
 }
@@ -476,6 +486,7 @@ impl LimitEvent {
             Self::BlobQuota => "Blob quota exceeded",
             Self::TooManyRequests => "Too many requests",
             Self::TenantQuota => "Tenant quota exceeded",
+            Self::LoadShed => "Request shed under load",
         }
     }
 }
@@ -605,6 +616,13 @@ impl NetworkEvent {
     }
 }
 
+impl ClusterEvent {
+    #[inline(always)]
+    pub fn into_err(self) -> Error {
+        Error::new(EventType::Cluster(self))
+    }
+}
+
 impl Value {
     pub fn from_maybe_string(value: &[u8]) -> Self {
         if let Ok(value) = std::str::from_utf8(value) {
@@ -14,6 +14,7 @@ use ahash::AHashMap;
 use atomics::bitset::AtomicBitset;
 use ipc::{
     channel::{Receiver, CHANNEL_FLAGS, CHANNEL_UPDATE_MARKER},
+    privacy::{PrivacyMode, Redactor, SubscriberPrivacy},
     subscriber::{Interests, Subscriber},
     USIZE_BITS,
 };
@@ -26,6 +27,7 @@ pub(crate) type GlobalInterests = AtomicBitset<{ TOTAL_EVENT_COUNT.div_ceil(USIZ
 pub(crate) static TRACE_INTERESTS: GlobalInterests = GlobalInterests::new();
 pub(crate) type CollectorThread = JoinHandle<()>;
 pub(crate) static ACTIVE_SUBSCRIBERS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+pub(crate) static SUBSCRIBER_PRIVACY: Mutex<Vec<SubscriberPrivacy>> = Mutex::new(Vec::new());
 pub(crate) static COLLECTOR_UPDATES: Mutex<Vec<Update>> = Mutex::new(Vec::new());
 
 pub(crate) const EVENT_TYPES: [EventType; TOTAL_EVENT_COUNT] = EventType::variants();
@@ -45,6 +47,15 @@ pub(crate) enum Update {
         id: String,
         interests: Interests,
         lossy: bool,
+        privacy: PrivacyMode,
+    },
+    UpdatePrivacy {
+        id: String,
+        mode: Option<PrivacyMode>,
+    },
+    UpdatePrivacyKey {
+        secret: Option<Vec<u8>>,
+        rotate: u64,
     },
     UpdateLevels {
         levels: AHashMap<EventType, Level>,
@@ -57,6 +68,7 @@ pub struct Collector {
     subscribers: Vec<Subscriber>,
     levels: [Level; TOTAL_EVENT_COUNT],
     active_spans: AHashMap<u64, Arc<Event<EventDetails>>>,
+    redactor: Redactor,
 }
 
 const HTTP_CONN_START: usize = EventType::Http(HttpEvent::ConnectionStart).id();
@@ -111,6 +123,7 @@ impl Collector {
                 let timestamp = SystemTime::now()
                     .duration_since(SystemTime::UNIX_EPOCH)
                     .map_or(0, |d| d.as_secs());
+                self.redactor.refresh(timestamp);
 
                 loop {
                     match rx.try_recv() {
@@ -191,9 +204,24 @@ impl Collector {
                                 }
                             };
 
-                            // Send to subscribers
+                            // Send to subscribers, redacting the event at most once per privacy mode
+                            let mut redacted: [Option<Arc<Event<EventDetails>>>; 2] = [None, None];
                             for subscriber in self.subscribers.iter_mut() {
-                                subscriber.push_event(event_id, event.clone());
+                                let mode = subscriber.privacy();
+                                let slot = match mode {
+                                    PrivacyMode::Off => {
+                                        subscriber.push_event(event_id, event.clone());
+                                        continue;
+                                    }
+                                    PrivacyMode::Hash => 0,
+                                    PrivacyMode::Truncate => 1,
+                                };
+                                if subscriber.interests.get(event_id) {
+                                    let event = redacted[slot].get_or_insert_with(|| {
+                                        Arc::new(self.redactor.redact(&event, mode))
+                                    });
+                                    subscriber.push_event(event_id, event.clone());
+                                }
                             }
                         }
                         Ok(None) => {
@@ -241,24 +269,48 @@ impl Collector {
                 }
                 Update::RegisterSubscriber { subscriber } => {
                     ACTIVE_SUBSCRIBERS.lock().push(subscriber.id.clone());
+                    let mut privacy = SUBSCRIBER_PRIVACY.lock();
+                    privacy.retain(|s| s.id != subscriber.id);
+                    privacy.push(SubscriberPrivacy {
+                        id: subscriber.id.clone(),
+                        configured: subscriber.privacy,
+                        override_mode: None,
+                    });
                     self.subscribers.push(subscriber);
                 }
                 Update::UnregisterSubscriber { id } => {
                     ACTIVE_SUBSCRIBERS.lock().retain(|s| s != &id);
+                    SUBSCRIBER_PRIVACY.lock().retain(|s| s.id != id);
                     self.subscribers.retain(|s| s.id != id);
                 }
                 Update::UpdateSubscriber {
                     id,
                     interests,
                     lossy,
+                    privacy,
                 } => {
                     for subscriber in self.subscribers.iter_mut() {
                         if subscriber.id == id {
                             subscriber.interests = interests;
                             subscriber.lossy = lossy;
+                            subscriber.privacy = privacy;
                             break;
                         }
                     }
+                    if let Some(entry) = SUBSCRIBER_PRIVACY.lock().iter_mut().find(|s| s.id == id) {
+                        entry.configured = privacy;
+                    }
+                }
+                Update::UpdatePrivacy { id, mode } => {
+                    for subscriber in self.subscribers.iter_mut() {
+                        if subscriber.id == id {
+                            subscriber.privacy_override = mode;
+                            break;
+                        }
+                    }
+                }
+                Update::UpdatePrivacyKey { secret, rotate } => {
+                    self.redactor.set_key(secret.as_deref(), rotate);
                 }
                 Update::UpdateLevels { levels } => {
                     for event in EVENT_TYPES.iter() {
@@ -308,14 +360,44 @@ impl Collector {
             .push(Update::UpdateLevels { levels });
     }
 
-    pub fn update_subscriber(id: String, interests: Interests, lossy: bool) {
+    pub fn update_subscriber(id: String, interests: Interests, lossy: bool, privacy: PrivacyMode) {
         COLLECTOR_UPDATES.lock().push(Update::UpdateSubscriber {
             id,
             interests,
             lossy,
+            privacy,
         });
     }
 
+    pub fn update_privacy_key(secret: Option<Vec<u8>>, rotate: u64) {
+        COLLECTOR_UPDATES
+            .lock()
+            .push(Update::UpdatePrivacyKey { secret, rotate });
+    }
+
+    pub fn get_privacy_modes() -> Vec<SubscriberPrivacy> {
+        SUBSCRIBER_PRIVACY.lock().clone()
+    }
+
+    /// Overrides the configured privacy mode of a subscriber until the next
+    /// restart, `None` restores the configured mode.
+    pub fn override_privacy(id: &str, mode: Option<PrivacyMode>) -> Option<SubscriberPrivacy> {
+        let entry = {
+            let mut privacy = SUBSCRIBER_PRIVACY.lock();
+            let entry = privacy.iter_mut().find(|s| s.id == id)?;
+            entry.override_mode = mode;
+            entry.clone()
+        };
+
+        COLLECTOR_UPDATES.lock().push(Update::UpdatePrivacy {
+            id: id.to_string(),
+            mode,
+        });
+        Collector::reload();
+
+        Some(entry)
+    }
+
     pub fn remove_subscriber(id: String) {
         COLLECTOR_UPDATES
             .lock()
@@ -344,6 +426,7 @@ impl Default for Collector {
             levels: [Level::Disable; TOTAL_EVENT_COUNT],
             active_spans: AHashMap::new(),
             receivers: Vec::new(),
+            redactor: Redactor::default(),
         };
 
         for event in EVENT_TYPES.iter() {
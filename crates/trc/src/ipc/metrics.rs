@@ -41,6 +41,8 @@ static STORE_BLOB_READ_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::BlobReadTime);
 static STORE_BLOB_WRITE_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::BlobWriteTime);
+static STORE_BLOB_TIER_SIZE: AtomicHistogram<12> =
+    AtomicHistogram::<12>::new_message_sizes(MetricType::BlobTierSize);
 
 static DNS_LOOKUP_TIME: AtomicHistogram<12> =
     AtomicHistogram::<10>::new_short_durations(MetricType::DnsLookupTime);
@@ -49,6 +51,7 @@ static SERVER_MEMORY: AtomicGauge = AtomicGauge::new(MetricType::ServerMemory);
 static QUEUE_COUNT: AtomicGauge = AtomicGauge::new(MetricType::QueueCount);
 static USER_COUNT: AtomicGauge = AtomicGauge::new(MetricType::UserCount);
 static DOMAIN_COUNT: AtomicGauge = AtomicGauge::new(MetricType::DomainCount);
+static FTS_INDEX_LAG: AtomicGauge = AtomicGauge::new(MetricType::FtsIndexLag);
 
 const CONN_SMTP_IN: usize = 0;
 const CONN_SMTP_OUT: usize = 1;
@@ -193,6 +196,9 @@ impl Collector {
             EventType::Store(StoreEvent::DataIterate) => {
                 STORE_DATA_READ_TIME.observe(elapsed);
             }
+            EventType::Store(StoreEvent::BlobTierMigrated) => {
+                STORE_BLOB_TIER_SIZE.observe(size);
+            }
 
             _ => {}
         }
@@ -226,9 +232,15 @@ impl Collector {
     }
 
     pub fn collect_gauges(is_enterprise: bool) -> impl Iterator<Item = &'static AtomicGauge> {
-        static E_GAUGES: &[&AtomicGauge] =
-            &[&SERVER_MEMORY, &QUEUE_COUNT, &USER_COUNT, &DOMAIN_COUNT];
-        static C_GAUGES: &[&AtomicGauge] = &[&SERVER_MEMORY, &USER_COUNT, &DOMAIN_COUNT];
+        static E_GAUGES: &[&AtomicGauge] = &[
+            &SERVER_MEMORY,
+            &QUEUE_COUNT,
+            &USER_COUNT,
+            &DOMAIN_COUNT,
+            &FTS_INDEX_LAG,
+        ];
+        static C_GAUGES: &[&AtomicGauge] =
+            &[&SERVER_MEMORY, &USER_COUNT, &DOMAIN_COUNT, &FTS_INDEX_LAG];
 
         if is_enterprise { E_GAUGES } else { C_GAUGES }
             .iter()
@@ -250,12 +262,14 @@ impl Collector {
             &STORE_DATA_WRITE_TIME,
             &STORE_BLOB_READ_TIME,
             &STORE_BLOB_WRITE_TIME,
+            &STORE_BLOB_TIER_SIZE,
             &DNS_LOOKUP_TIME,
         ];
         static C_HISTOGRAMS: &[&AtomicHistogram<12>] = &[
             &MESSAGE_DELIVERY_TIME,
             &MESSAGE_INCOMING_SIZE,
             &MESSAGE_SUBMISSION_SIZE,
+            &STORE_BLOB_TIER_SIZE,
         ];
 
         if is_enterprise {
@@ -315,6 +329,8 @@ impl Collector {
             MetricType::SieveRequestTime => CONNECTION_METRICS[CONN_SIEVE].elapsed.average(),
             MetricType::UserCount => USER_COUNT.get() as f64,
             MetricType::DomainCount => DOMAIN_COUNT.get() as f64,
+            MetricType::FtsIndexLag => FTS_INDEX_LAG.get() as f64,
+            MetricType::BlobTierSize => STORE_BLOB_TIER_SIZE.average(),
         }
     }
 
@@ -324,6 +340,7 @@ impl Collector {
             MetricType::QueueCount => QUEUE_COUNT.set(value),
             MetricType::UserCount => USER_COUNT.set(value),
             MetricType::DomainCount => DOMAIN_COUNT.set(value),
+            MetricType::FtsIndexLag => FTS_INDEX_LAG.set(value),
             _ => {}
         }
     }
@@ -412,6 +429,7 @@ impl EventType {
         match self {
             EventType::Server(ServerEvent::ThreadError) => true,
             EventType::Purge(PurgeEvent::Error) => true,
+            EventType::Housekeeper(HousekeeperEvent::TaskFailed) => true,
             EventType::Eval(
                 EvalEvent::Error | EvalEvent::StoreNotFound | EvalEvent::DirectoryNotFound,
             ) => true,
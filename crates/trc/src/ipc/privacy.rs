@@ -0,0 +1,247 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{fmt::Write, str::FromStr, sync::Arc};
+
+use crate::{Event, EventDetails, EventType, Key, Value};
+
+const HASH_LEN: usize = 8;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrivacyMode {
+    #[default]
+    Off,
+    Hash,
+    Truncate,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberPrivacy {
+    pub id: String,
+    pub configured: PrivacyMode,
+    pub override_mode: Option<PrivacyMode>,
+}
+
+/// Pseudonymizes address-bearing fields before events reach a subscriber.
+/// Hashes are keyed with a secret that is rotated every `rotate` seconds,
+/// so the same address can be correlated only within a rotation window.
+pub(crate) struct Redactor {
+    secret: [u8; 32],
+    rotate: u64,
+    window: u64,
+    window_key: [u8; 32],
+}
+
+impl Redactor {
+    pub fn set_key(&mut self, secret: Option<&[u8]>, rotate: u64) {
+        if let Some(secret) = secret {
+            self.secret = *blake3::hash(secret).as_bytes();
+        }
+        self.rotate = rotate.max(1);
+        self.window = u64::MAX;
+    }
+
+    pub fn refresh(&mut self, timestamp: u64) {
+        let window = timestamp / self.rotate;
+        if window != self.window {
+            self.window = window;
+            self.window_key = *blake3::keyed_hash(&self.secret, &window.to_be_bytes()).as_bytes();
+        }
+    }
+
+    pub fn redact(&self, event: &Event<EventDetails>, mode: PrivacyMode) -> Event<EventDetails> {
+        Event {
+            inner: EventDetails {
+                typ: event.inner.typ,
+                timestamp: event.inner.timestamp,
+                level: event.inner.level,
+                span: event
+                    .inner
+                    .span
+                    .as_ref()
+                    .map(|span| Arc::new(self.redact(span, mode))),
+            },
+            keys: self.redact_keys(&event.keys, mode),
+        }
+    }
+
+    fn redact_keys(&self, keys: &[(Key, Value)], mode: PrivacyMode) -> Vec<(Key, Value)> {
+        keys.iter()
+            .map(|(key, value)| (*key, self.redact_value(*key, value, mode)))
+            .collect()
+    }
+
+    fn redact_value(&self, key: Key, value: &Value, mode: PrivacyMode) -> Value {
+        match value {
+            Value::String(text) if is_address_key(key) => {
+                Value::String(self.redact_text(text, mode))
+            }
+            Value::Static(text) if is_address_key(key) => {
+                Value::String(self.redact_text(text, mode))
+            }
+            Value::Array(values) => Value::Array(
+                values
+                    .iter()
+                    .map(|value| self.redact_value(key, value, mode))
+                    .collect(),
+            ),
+            Value::Event(event) => Value::Event(Event::<EventType> {
+                inner: event.inner,
+                keys: self.redact_keys(&event.keys, mode),
+            }),
+            value => value.clone(),
+        }
+    }
+
+    fn redact_text(&self, text: &str, mode: PrivacyMode) -> String {
+        if text.is_empty() {
+            return String::new();
+        }
+
+        let (local, domain) = text
+            .rsplit_once('@')
+            .map_or((text, None), |(local, domain)| (local, Some(domain)));
+        let mut result = String::with_capacity(text.len() + 8);
+        match mode {
+            PrivacyMode::Hash => {
+                let hash = blake3::keyed_hash(&self.window_key, text.to_lowercase().as_bytes());
+                result.push_str("anon-");
+                for byte in &hash.as_bytes()[..HASH_LEN] {
+                    let _ = write!(result, "{byte:02x}");
+                }
+            }
+            PrivacyMode::Truncate | PrivacyMode::Off => {
+                if let Some(ch) = local.chars().next() {
+                    result.push(ch);
+                }
+                result.push_str("***");
+            }
+        }
+        if let Some(domain) = domain {
+            result.push('@');
+            result.push_str(domain);
+        }
+
+        result
+    }
+}
+
+#[inline(always)]
+fn is_address_key(key: Key) -> bool {
+    matches!(key, Key::AccountName | Key::From | Key::To)
+}
+
+impl PrivacyMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PrivacyMode::Off => "off",
+            PrivacyMode::Hash => "hash",
+            PrivacyMode::Truncate => "truncate",
+        }
+    }
+}
+
+impl FromStr for PrivacyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "false" => Ok(PrivacyMode::Off),
+            "hash" => Ok(PrivacyMode::Hash),
+            "truncate" => Ok(PrivacyMode::Truncate),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Redactor {
+            secret: [0; 32],
+            rotate: 86400,
+            window: u64::MAX,
+            window_key: [0; 32],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{Event, EventDetails, EventType, Key, Level, SmtpEvent, Value};
+
+    use super::{PrivacyMode, Redactor};
+
+    fn event(from: &str, to: &[&str]) -> Event<EventDetails> {
+        Event::with_keys(
+            EventDetails {
+                typ: EventType::Smtp(SmtpEvent::MailFrom),
+                timestamp: 0,
+                level: Level::Info,
+                span: Some(Arc::new(Event::with_keys(
+                    EventDetails {
+                        typ: EventType::Smtp(SmtpEvent::ConnectionStart),
+                        timestamp: 0,
+                        level: Level::Info,
+                        span: None,
+                    },
+                    vec![(Key::AccountName, Value::Static("john"))],
+                ))),
+            },
+            vec![
+                (Key::From, Value::String(from.to_string())),
+                (
+                    Key::To,
+                    Value::Array(to.iter().map(|to| Value::String(to.to_string())).collect()),
+                ),
+                (Key::Domain, Value::String("example.org".to_string())),
+            ],
+        )
+    }
+
+    #[test]
+    fn redact_addresses() {
+        let mut redactor = Redactor::default();
+        redactor.set_key(Some(b"secret"), 3600);
+        redactor.refresh(0);
+
+        // Truncation keeps the first character and the domain
+        let redacted = redactor.redact(
+            &event("john@example.org", &["jane@example.org"]),
+            PrivacyMode::Truncate,
+        );
+        assert_eq!(redacted.value_as_str(Key::From), Some("j***@example.org"));
+        assert!(matches!(
+            redacted.value(Key::To),
+            Some(Value::Array(values)) if values[0].as_str() == Some("j***@example.org")
+        ));
+        assert_eq!(redacted.value_as_str(Key::Domain), Some("example.org"));
+        assert_eq!(
+            redacted
+                .inner
+                .span
+                .as_ref()
+                .unwrap()
+                .value_as_str(Key::AccountName),
+            Some("j***")
+        );
+
+        // Hashes are stable within a window and case insensitive
+        let first = redactor.redact(&event("john@example.org", &[]), PrivacyMode::Hash);
+        let second = redactor.redact(&event("John@Example.org", &[]), PrivacyMode::Hash);
+        let hash = first.value_as_str(Key::From).unwrap().to_string();
+        assert!(hash.starts_with("anon-"), "{hash}");
+        assert!(hash.ends_with("@example.org"), "{hash}");
+        assert!(!hash.contains("john"), "{hash}");
+        assert_eq!(second.value_as_str(Key::From).unwrap().to_lowercase(), hash);
+
+        // A new window produces unrelated hashes
+        redactor.refresh(3600);
+        let rotated = redactor.redact(&event("john@example.org", &[]), PrivacyMode::Hash);
+        assert_ne!(rotated.value_as_str(Key::From).unwrap(), hash);
+    }
+}
@@ -14,6 +14,7 @@ use super::{
     bitset::Bitset,
     channel::ChannelError,
     collector::{Collector, Update, COLLECTOR_UPDATES},
+    privacy::PrivacyMode,
     USIZE_BITS,
 };
 
@@ -28,6 +29,8 @@ pub(crate) struct Subscriber {
     pub interests: Interests,
     pub tx: mpsc::Sender<EventBatch>,
     pub lossy: bool,
+    pub privacy: PrivacyMode,
+    pub privacy_override: Option<PrivacyMode>,
     pub batch: EventBatch,
 }
 
@@ -35,9 +38,15 @@ pub struct SubscriberBuilder {
     pub id: String,
     pub interests: Interests,
     pub lossy: bool,
+    pub privacy: PrivacyMode,
 }
 
 impl Subscriber {
+    #[inline(always)]
+    pub fn privacy(&self) -> PrivacyMode {
+        self.privacy_override.unwrap_or(self.privacy)
+    }
+
     #[inline(always)]
     pub fn push_event(&mut self, event_id: usize, trace: Arc<Event<EventDetails>>) {
         if self.interests.get(event_id) {
@@ -76,6 +85,7 @@ impl SubscriberBuilder {
             id,
             interests: Default::default(),
             lossy: true,
+            privacy: PrivacyMode::Off,
         }
     }
 
@@ -105,6 +115,11 @@ impl SubscriberBuilder {
         self
     }
 
+    pub fn with_privacy(mut self, privacy: PrivacyMode) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
     pub fn register(self) -> (mpsc::Sender<EventBatch>, mpsc::Receiver<EventBatch>) {
         let (tx, rx) = mpsc::channel(8192);
 
@@ -114,6 +129,8 @@ impl SubscriberBuilder {
                 interests: self.interests,
                 tx: tx.clone(),
                 lossy: self.lossy,
+                privacy: self.privacy,
+                privacy_override: None,
                 batch: Vec::new(),
             },
         });
@@ -79,6 +79,7 @@ pub enum Key {
     Code,
     Collection,
     Contents,
+    Created,
     Details,
     DkimFail,
     DkimNone,
@@ -112,6 +113,7 @@ pub enum Key {
     RangeTo,
     Reason,
     RemoteIp,
+    Removed,
     RemotePort,
     ReportId,
     Result,
@@ -131,6 +133,7 @@ pub enum Key {
     Uid,
     UidNext,
     UidValidity,
+    Updated,
     Url,
     ValidFrom,
     ValidTo,
@@ -205,6 +208,23 @@ pub enum SecurityEvent {
     LoiterBan,
     IpBlocked,
     Unauthorized,
+    ApprovalRequested,
+    ApprovalGranted,
+    ApprovalRejected,
+    ApprovalCancelled,
+    ApprovalExpired,
+    SessionsDisconnected,
+    OperationUndone,
+    SenderReputationAction,
+    SenderReputationCleared,
+    MessageRecalled,
+    MessageHoldReleased,
+    MessageHoldRejected,
+    TenantExportRequested,
+    TenantExportCompleted,
+    TenantExportDownloaded,
+    AccountLockout,
+    SessionsRevoked,
 }
 
 #[event_type]
@@ -222,6 +242,9 @@ pub enum ClusterEvent {
     InvalidPacket,
     DecryptionError,
     Error,
+    ReplicationSent,
+    ReplicationApplied,
+    ReplicationConflict,
 }
 
 #[event_type]
@@ -232,6 +255,15 @@ pub enum HousekeeperEvent {
     PurgeAccounts,
     PurgeSessions,
     PurgeStore,
+    TaskStarted,
+    TaskCompleted,
+    TaskFailed,
+    TaskLocked,
+    DirectorySync,
+    MigrationStarted,
+    MigrationCompleted,
+    MigrationFailed,
+    MigrationLocked,
 }
 
 #[event_type]
@@ -241,6 +273,10 @@ pub enum FtsIndexEvent {
     LockBusy,
     BlobNotFound,
     MetadataNotFound,
+    MissingEntries,
+    OrphanedEntries,
+    RebuildProgress,
+    VerifyCompleted,
 }
 
 #[event_type]
@@ -431,6 +467,26 @@ pub enum SmtpEvent {
     UnsupportedParameter,
     SyntaxError,
     RequestTooLarge,
+    CalloutAccepted,
+    CalloutRejected,
+    CalloutFailed,
+    AttachmentRejected,
+    AttachmentWarning,
+    DnsblListed,
+    DnsblRejected,
+    DnsblTimeout,
+    DnsblDisabled,
+    DnsblEnabled,
+    RcptToRewriteLoop,
+    RcptToRewriteInvalid,
+    SenderReputationBlocked,
+    MimeLimitExceeded,
+    RcptToPolicyViolation,
+    RcptToPolicyMemberRemoved,
+    RcptToAliasLoop,
+    RcptToAliasFanOut,
+    RcptToListPostingDenied,
+    RcptToListModerated,
 }
 
 #[event_type]
@@ -473,6 +529,12 @@ pub enum DeliveryEvent {
     DsnPermFail,
     RawInput,
     RawOutput,
+    SourceIpBindFailed,
+    HeldForReview,
+    SendingWindowClosed,
+    Ipv6Demoted,
+    HappyEyeballsFallback,
+    HeldForModeration,
 }
 
 #[event_type]
@@ -634,6 +696,9 @@ pub enum TlsEvent {
     CertificateNotFound,
     NoCertificatesAvailable,
     MultipleCertificatesAvailable,
+    BelowTargetVersion,
+    CertificateReloaded,
+    CertificateReloadFailed,
 }
 
 #[event_type]
@@ -660,6 +725,8 @@ pub enum ServerEvent {
     StartupError,
     ThreadError,
     Licensing,
+    BackpressureStart,
+    BackpressureStop,
 }
 
 #[event_type]
@@ -671,6 +738,7 @@ pub enum TelemetryEvent {
     OtelMetricsExporterError,
     PrometheusExporterError,
     JournalError,
+    PrivacyModeChanged,
 }
 
 #[event_type]
@@ -713,6 +781,9 @@ pub enum PurgeEvent {
     PurgeActive,
     AutoExpunge,
     TombstoneCleanup,
+    QuotaExpunge,
+    ExpiredRecords,
+    AccountOnHold,
 }
 
 #[event_type]
@@ -852,6 +923,18 @@ pub enum StoreEvent {
     SqlQuery,
     LdapQuery,
     LdapBind,
+    LdapModify,
+
+    // Copy
+    CopyProgress,
+    CopyCompleted,
+    CopyMismatch,
+
+    // Blob tiering
+    BlobTierMigrated,
+    BlobTierPromoted,
+    BlobTierFailed,
+    BlobTierCompleted,
 }
 
 #[event_type]
@@ -910,6 +993,7 @@ pub enum LimitEvent {
     BlobQuota,
     TenantQuota,
     TooManyRequests,
+    LoadShed,
 }
 
 #[event_type]
@@ -920,6 +1004,15 @@ pub enum ManageEvent {
     NotFound,
     NotSupported,
     Error,
+    DirectoryAlert,
+    MaintenanceEnabled,
+    MaintenanceDisabled,
+    HoldPlaced,
+    HoldReleased,
+    DirectoryInconsistency,
+    DirectoryVerified,
+    QuotaMismatch,
+    QuotaRecalculated,
 }
 
 #[event_type]
@@ -931,6 +1024,12 @@ pub enum AuthEvent {
     TooManyAttempts,
     ClientRegistration,
     Error,
+    ApiKeyIssued,
+    ApiKeyRevoked,
+    AccountDisabled,
+    SecretUpgraded,
+    RecoveryCodeUsed,
+    AccountLocked,
 }
 
 #[event_type]
@@ -977,6 +1076,8 @@ pub enum MetricType {
     SieveRequestTime,
     UserCount,
     DomainCount,
+    FtsIndexLag,
+    BlobTierSize,
 }
 
 pub const TOTAL_EVENT_COUNT: usize = total_event_count!();
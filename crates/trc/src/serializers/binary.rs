@@ -865,6 +865,98 @@ impl EventType {
             EventType::Ai(AiEvent::ApiError) => 557,
             EventType::Security(SecurityEvent::ScanBan) => 558,
             EventType::Store(StoreEvent::AzureError) => 559,
+            EventType::Housekeeper(HousekeeperEvent::TaskStarted) => 560,
+            EventType::Housekeeper(HousekeeperEvent::TaskCompleted) => 561,
+            EventType::Housekeeper(HousekeeperEvent::TaskFailed) => 562,
+            EventType::Housekeeper(HousekeeperEvent::TaskLocked) => 563,
+            EventType::Smtp(SmtpEvent::CalloutAccepted) => 564,
+            EventType::Smtp(SmtpEvent::CalloutRejected) => 565,
+            EventType::Smtp(SmtpEvent::CalloutFailed) => 566,
+            EventType::Smtp(SmtpEvent::AttachmentRejected) => 567,
+            EventType::Smtp(SmtpEvent::AttachmentWarning) => 568,
+            EventType::Cluster(ClusterEvent::ReplicationSent) => 569,
+            EventType::Cluster(ClusterEvent::ReplicationApplied) => 570,
+            EventType::Cluster(ClusterEvent::ReplicationConflict) => 571,
+            EventType::Purge(PurgeEvent::QuotaExpunge) => 572,
+            EventType::Smtp(SmtpEvent::DnsblListed) => 573,
+            EventType::Smtp(SmtpEvent::DnsblRejected) => 574,
+            EventType::Smtp(SmtpEvent::DnsblTimeout) => 575,
+            EventType::Smtp(SmtpEvent::DnsblDisabled) => 576,
+            EventType::Smtp(SmtpEvent::DnsblEnabled) => 577,
+            EventType::Security(SecurityEvent::ApprovalRequested) => 578,
+            EventType::Security(SecurityEvent::ApprovalGranted) => 579,
+            EventType::Security(SecurityEvent::ApprovalRejected) => 580,
+            EventType::Security(SecurityEvent::ApprovalCancelled) => 581,
+            EventType::Security(SecurityEvent::ApprovalExpired) => 582,
+            EventType::Security(SecurityEvent::SessionsDisconnected) => 583,
+            EventType::Smtp(SmtpEvent::RcptToRewriteLoop) => 584,
+            EventType::Smtp(SmtpEvent::RcptToRewriteInvalid) => 585,
+            EventType::Security(SecurityEvent::OperationUndone) => 586,
+            EventType::Security(SecurityEvent::SenderReputationAction) => 587,
+            EventType::Security(SecurityEvent::SenderReputationCleared) => 588,
+            EventType::Smtp(SmtpEvent::SenderReputationBlocked) => 589,
+            EventType::Security(SecurityEvent::MessageRecalled) => 590,
+            EventType::Telemetry(TelemetryEvent::PrivacyModeChanged) => 591,
+            EventType::Auth(AuthEvent::ApiKeyIssued) => 592,
+            EventType::Auth(AuthEvent::ApiKeyRevoked) => 593,
+            EventType::Delivery(DeliveryEvent::SourceIpBindFailed) => 594,
+            EventType::Smtp(SmtpEvent::MimeLimitExceeded) => 595,
+            EventType::Delivery(DeliveryEvent::HeldForReview) => 596,
+            EventType::Delivery(DeliveryEvent::SendingWindowClosed) => 597,
+            EventType::Security(SecurityEvent::MessageHoldReleased) => 598,
+            EventType::Security(SecurityEvent::MessageHoldRejected) => 599,
+            EventType::FtsIndex(FtsIndexEvent::MissingEntries) => 600,
+            EventType::FtsIndex(FtsIndexEvent::OrphanedEntries) => 601,
+            EventType::FtsIndex(FtsIndexEvent::RebuildProgress) => 602,
+            EventType::FtsIndex(FtsIndexEvent::VerifyCompleted) => 603,
+            EventType::Tls(TlsEvent::BelowTargetVersion) => 604,
+            EventType::Security(SecurityEvent::TenantExportRequested) => 605,
+            EventType::Security(SecurityEvent::TenantExportCompleted) => 606,
+            EventType::Security(SecurityEvent::TenantExportDownloaded) => 607,
+            EventType::Auth(AuthEvent::AccountDisabled) => 608,
+            EventType::Server(ServerEvent::BackpressureStart) => 609,
+            EventType::Server(ServerEvent::BackpressureStop) => 610,
+            EventType::Limit(LimitEvent::LoadShed) => 611,
+            EventType::Manage(ManageEvent::DirectoryAlert) => 612,
+            EventType::Manage(ManageEvent::MaintenanceEnabled) => 613,
+            EventType::Manage(ManageEvent::MaintenanceDisabled) => 614,
+            EventType::Housekeeper(HousekeeperEvent::DirectorySync) => 615,
+            EventType::Purge(PurgeEvent::ExpiredRecords) => 616,
+            EventType::Delivery(DeliveryEvent::Ipv6Demoted) => 617,
+            EventType::Delivery(DeliveryEvent::HappyEyeballsFallback) => 618,
+            EventType::Manage(ManageEvent::HoldPlaced) => 619,
+            EventType::Manage(ManageEvent::HoldReleased) => 620,
+            EventType::Purge(PurgeEvent::AccountOnHold) => 621,
+            EventType::Tls(TlsEvent::CertificateReloaded) => 622,
+            EventType::Tls(TlsEvent::CertificateReloadFailed) => 623,
+            EventType::Manage(ManageEvent::DirectoryInconsistency) => 624,
+            EventType::Manage(ManageEvent::DirectoryVerified) => 625,
+            EventType::Smtp(SmtpEvent::RcptToPolicyViolation) => 626,
+            EventType::Smtp(SmtpEvent::RcptToPolicyMemberRemoved) => 627,
+            EventType::Smtp(SmtpEvent::RcptToAliasLoop) => 628,
+            EventType::Smtp(SmtpEvent::RcptToAliasFanOut) => 629,
+            EventType::Housekeeper(HousekeeperEvent::MigrationStarted) => 630,
+            EventType::Housekeeper(HousekeeperEvent::MigrationCompleted) => 631,
+            EventType::Housekeeper(HousekeeperEvent::MigrationFailed) => 632,
+            EventType::Housekeeper(HousekeeperEvent::MigrationLocked) => 633,
+            EventType::Store(StoreEvent::LdapModify) => 634,
+            EventType::Auth(AuthEvent::SecretUpgraded) => 635,
+            EventType::Auth(AuthEvent::RecoveryCodeUsed) => 636,
+            EventType::Auth(AuthEvent::AccountLocked) => 637,
+            EventType::Security(SecurityEvent::AccountLockout) => 638,
+            EventType::Smtp(SmtpEvent::RcptToListPostingDenied) => 639,
+            EventType::Smtp(SmtpEvent::RcptToListModerated) => 640,
+            EventType::Delivery(DeliveryEvent::HeldForModeration) => 641,
+            EventType::Security(SecurityEvent::SessionsRevoked) => 642,
+            EventType::Manage(ManageEvent::QuotaMismatch) => 643,
+            EventType::Manage(ManageEvent::QuotaRecalculated) => 644,
+            EventType::Store(StoreEvent::CopyProgress) => 645,
+            EventType::Store(StoreEvent::CopyCompleted) => 646,
+            EventType::Store(StoreEvent::CopyMismatch) => 647,
+            EventType::Store(StoreEvent::BlobTierMigrated) => 648,
+            EventType::Store(StoreEvent::BlobTierPromoted) => 649,
+            EventType::Store(StoreEvent::BlobTierFailed) => 650,
+            EventType::Store(StoreEvent::BlobTierCompleted) => 651,
         }
     }
 
@@ -1470,6 +1562,98 @@ impl EventType {
             557 => Some(EventType::Ai(AiEvent::ApiError)),
             558 => Some(EventType::Security(SecurityEvent::ScanBan)),
             559 => Some(EventType::Store(StoreEvent::AzureError)),
+            560 => Some(EventType::Housekeeper(HousekeeperEvent::TaskStarted)),
+            561 => Some(EventType::Housekeeper(HousekeeperEvent::TaskCompleted)),
+            562 => Some(EventType::Housekeeper(HousekeeperEvent::TaskFailed)),
+            563 => Some(EventType::Housekeeper(HousekeeperEvent::TaskLocked)),
+            564 => Some(EventType::Smtp(SmtpEvent::CalloutAccepted)),
+            565 => Some(EventType::Smtp(SmtpEvent::CalloutRejected)),
+            566 => Some(EventType::Smtp(SmtpEvent::CalloutFailed)),
+            567 => Some(EventType::Smtp(SmtpEvent::AttachmentRejected)),
+            568 => Some(EventType::Smtp(SmtpEvent::AttachmentWarning)),
+            569 => Some(EventType::Cluster(ClusterEvent::ReplicationSent)),
+            570 => Some(EventType::Cluster(ClusterEvent::ReplicationApplied)),
+            571 => Some(EventType::Cluster(ClusterEvent::ReplicationConflict)),
+            572 => Some(EventType::Purge(PurgeEvent::QuotaExpunge)),
+            573 => Some(EventType::Smtp(SmtpEvent::DnsblListed)),
+            574 => Some(EventType::Smtp(SmtpEvent::DnsblRejected)),
+            575 => Some(EventType::Smtp(SmtpEvent::DnsblTimeout)),
+            576 => Some(EventType::Smtp(SmtpEvent::DnsblDisabled)),
+            577 => Some(EventType::Smtp(SmtpEvent::DnsblEnabled)),
+            578 => Some(EventType::Security(SecurityEvent::ApprovalRequested)),
+            579 => Some(EventType::Security(SecurityEvent::ApprovalGranted)),
+            580 => Some(EventType::Security(SecurityEvent::ApprovalRejected)),
+            581 => Some(EventType::Security(SecurityEvent::ApprovalCancelled)),
+            582 => Some(EventType::Security(SecurityEvent::ApprovalExpired)),
+            583 => Some(EventType::Security(SecurityEvent::SessionsDisconnected)),
+            584 => Some(EventType::Smtp(SmtpEvent::RcptToRewriteLoop)),
+            585 => Some(EventType::Smtp(SmtpEvent::RcptToRewriteInvalid)),
+            586 => Some(EventType::Security(SecurityEvent::OperationUndone)),
+            587 => Some(EventType::Security(SecurityEvent::SenderReputationAction)),
+            588 => Some(EventType::Security(SecurityEvent::SenderReputationCleared)),
+            589 => Some(EventType::Smtp(SmtpEvent::SenderReputationBlocked)),
+            590 => Some(EventType::Security(SecurityEvent::MessageRecalled)),
+            591 => Some(EventType::Telemetry(TelemetryEvent::PrivacyModeChanged)),
+            592 => Some(EventType::Auth(AuthEvent::ApiKeyIssued)),
+            593 => Some(EventType::Auth(AuthEvent::ApiKeyRevoked)),
+            594 => Some(EventType::Delivery(DeliveryEvent::SourceIpBindFailed)),
+            595 => Some(EventType::Smtp(SmtpEvent::MimeLimitExceeded)),
+            596 => Some(EventType::Delivery(DeliveryEvent::HeldForReview)),
+            597 => Some(EventType::Delivery(DeliveryEvent::SendingWindowClosed)),
+            598 => Some(EventType::Security(SecurityEvent::MessageHoldReleased)),
+            599 => Some(EventType::Security(SecurityEvent::MessageHoldRejected)),
+            600 => Some(EventType::FtsIndex(FtsIndexEvent::MissingEntries)),
+            601 => Some(EventType::FtsIndex(FtsIndexEvent::OrphanedEntries)),
+            602 => Some(EventType::FtsIndex(FtsIndexEvent::RebuildProgress)),
+            603 => Some(EventType::FtsIndex(FtsIndexEvent::VerifyCompleted)),
+            604 => Some(EventType::Tls(TlsEvent::BelowTargetVersion)),
+            605 => Some(EventType::Security(SecurityEvent::TenantExportRequested)),
+            606 => Some(EventType::Security(SecurityEvent::TenantExportCompleted)),
+            607 => Some(EventType::Security(SecurityEvent::TenantExportDownloaded)),
+            608 => Some(EventType::Auth(AuthEvent::AccountDisabled)),
+            609 => Some(EventType::Server(ServerEvent::BackpressureStart)),
+            610 => Some(EventType::Server(ServerEvent::BackpressureStop)),
+            611 => Some(EventType::Limit(LimitEvent::LoadShed)),
+            612 => Some(EventType::Manage(ManageEvent::DirectoryAlert)),
+            613 => Some(EventType::Manage(ManageEvent::MaintenanceEnabled)),
+            614 => Some(EventType::Manage(ManageEvent::MaintenanceDisabled)),
+            615 => Some(EventType::Housekeeper(HousekeeperEvent::DirectorySync)),
+            616 => Some(EventType::Purge(PurgeEvent::ExpiredRecords)),
+            617 => Some(EventType::Delivery(DeliveryEvent::Ipv6Demoted)),
+            618 => Some(EventType::Delivery(DeliveryEvent::HappyEyeballsFallback)),
+            619 => Some(EventType::Manage(ManageEvent::HoldPlaced)),
+            620 => Some(EventType::Manage(ManageEvent::HoldReleased)),
+            621 => Some(EventType::Purge(PurgeEvent::AccountOnHold)),
+            622 => Some(EventType::Tls(TlsEvent::CertificateReloaded)),
+            623 => Some(EventType::Tls(TlsEvent::CertificateReloadFailed)),
+            624 => Some(EventType::Manage(ManageEvent::DirectoryInconsistency)),
+            625 => Some(EventType::Manage(ManageEvent::DirectoryVerified)),
+            626 => Some(EventType::Smtp(SmtpEvent::RcptToPolicyViolation)),
+            627 => Some(EventType::Smtp(SmtpEvent::RcptToPolicyMemberRemoved)),
+            628 => Some(EventType::Smtp(SmtpEvent::RcptToAliasLoop)),
+            629 => Some(EventType::Smtp(SmtpEvent::RcptToAliasFanOut)),
+            630 => Some(EventType::Housekeeper(HousekeeperEvent::MigrationStarted)),
+            631 => Some(EventType::Housekeeper(HousekeeperEvent::MigrationCompleted)),
+            632 => Some(EventType::Housekeeper(HousekeeperEvent::MigrationFailed)),
+            633 => Some(EventType::Housekeeper(HousekeeperEvent::MigrationLocked)),
+            634 => Some(EventType::Store(StoreEvent::LdapModify)),
+            635 => Some(EventType::Auth(AuthEvent::SecretUpgraded)),
+            636 => Some(EventType::Auth(AuthEvent::RecoveryCodeUsed)),
+            637 => Some(EventType::Auth(AuthEvent::AccountLocked)),
+            638 => Some(EventType::Security(SecurityEvent::AccountLockout)),
+            639 => Some(EventType::Smtp(SmtpEvent::RcptToListPostingDenied)),
+            640 => Some(EventType::Smtp(SmtpEvent::RcptToListModerated)),
+            641 => Some(EventType::Delivery(DeliveryEvent::HeldForModeration)),
+            642 => Some(EventType::Security(SecurityEvent::SessionsRevoked)),
+            643 => Some(EventType::Manage(ManageEvent::QuotaMismatch)),
+            644 => Some(EventType::Manage(ManageEvent::QuotaRecalculated)),
+            645 => Some(EventType::Store(StoreEvent::CopyProgress)),
+            646 => Some(EventType::Store(StoreEvent::CopyCompleted)),
+            647 => Some(EventType::Store(StoreEvent::CopyMismatch)),
+            648 => Some(EventType::Store(StoreEvent::BlobTierMigrated)),
+            649 => Some(EventType::Store(StoreEvent::BlobTierPromoted)),
+            650 => Some(EventType::Store(StoreEvent::BlobTierFailed)),
+            651 => Some(EventType::Store(StoreEvent::BlobTierCompleted)),
             _ => None,
         }
     }
@@ -1543,6 +1727,9 @@ impl Key {
             Key::ValidTo => 62,
             Key::Value => 63,
             Key::Version => 64,
+            Key::Created => 65,
+            Key::Updated => 66,
+            Key::Removed => 67,
         }
     }
 
@@ -1613,6 +1800,9 @@ impl Key {
             62 => Some(Key::ValidTo),
             63 => Some(Key::Value),
             64 => Some(Key::Version),
+            65 => Some(Key::Created),
+            66 => Some(Key::Updated),
+            67 => Some(Key::Removed),
             _ => None,
         }
     }
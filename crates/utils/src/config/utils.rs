@@ -595,6 +595,13 @@ impl ParseValue for trc::EventType {
     }
 }
 
+impl ParseValue for trc::ipc::privacy::PrivacyMode {
+    fn parse_value(value: &str) -> super::Result<Self> {
+        trc::ipc::privacy::PrivacyMode::from_str(value)
+            .map_err(|err| format!("Invalid privacy mode: {err}"))
+    }
+}
+
 impl ParseValue for () {
     fn parse_value(_: &str) -> super::Result<Self> {
         Ok(())
@@ -9,6 +9,7 @@ use std::sync::Arc;
 pub mod codec;
 pub mod config;
 pub mod glob;
+pub mod locale;
 pub mod lru_cache;
 pub mod map;
 pub mod snowflake;
@@ -45,6 +46,18 @@ impl BlobHash {
         }
         hex
     }
+
+    pub fn try_from_hex(value: &str) -> Option<Self> {
+        if value.len() != BLOB_HASH_LEN * 2 || !value.is_ascii() {
+            return None;
+        }
+
+        let mut hash = [0u8; BLOB_HASH_LEN];
+        for (pos, byte) in hash.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&value[pos * 2..pos * 2 + 2], 16).ok()?;
+        }
+        Some(BlobHash(hash))
+    }
 }
 
 impl From<&[u8]> for BlobHash {
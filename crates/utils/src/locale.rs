@@ -0,0 +1,133 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use chrono::{DateTime, TimeZone};
+use chrono_tz::Tz;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+    Pt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Localization {
+    pub locale: Locale,
+    pub timezone: Tz,
+}
+
+impl Locale {
+    pub const ALL: &'static [Locale] =
+        &[Locale::En, Locale::De, Locale::Es, Locale::Fr, Locale::Pt];
+
+    /// Parses a language tag such as "fr" or "fr-CA", only the primary
+    /// language subtag is taken into account.
+    pub fn parse(value: &str) -> Option<Self> {
+        let language = value.trim().split(['-', '_']).next()?;
+        Locale::ALL
+            .iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(language))
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Es => "es",
+            Locale::Fr => "fr",
+            Locale::Pt => "pt",
+        }
+    }
+
+    fn date_format(&self) -> &'static str {
+        match self {
+            Locale::En => "%b %-d, %Y %H:%M %Z",
+            Locale::De => "%d.%m.%Y %H:%M %Z",
+            Locale::Es | Locale::Fr | Locale::Pt => "%d/%m/%Y %H:%M %Z",
+        }
+    }
+}
+
+/// Parses a tz database name such as "Europe/Madrid".
+pub fn parse_timezone(value: &str) -> Option<Tz> {
+    value.trim().parse::<Tz>().ok()
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Localization {
+            locale: Locale::En,
+            timezone: Tz::UTC,
+        }
+    }
+}
+
+impl Localization {
+    pub fn parse(config: &mut Config) -> Self {
+        let mut localization = Localization::default();
+
+        if let Some(value) = config.value("server.locale") {
+            match Locale::parse(value) {
+                Some(locale) => localization.locale = locale,
+                None => {
+                    let err = format!("Unsupported locale {value:?}");
+                    config.new_parse_error("server.locale", err);
+                }
+            }
+        }
+        if let Some(value) = config.value("server.timezone") {
+            match parse_timezone(value) {
+                Some(timezone) => localization.timezone = timezone,
+                None => {
+                    let err = format!("Unknown time zone {value:?}");
+                    config.new_parse_error("server.timezone", err);
+                }
+            }
+        }
+
+        localization
+    }
+
+    /// Applies the settings of a principal, unset or unknown values keep
+    /// the server defaults.
+    pub fn with_overrides(mut self, locale: Option<&str>, timezone: Option<&str>) -> Self {
+        if let Some(locale) = locale.and_then(Locale::parse) {
+            self.locale = locale;
+        }
+        if let Some(timezone) = timezone.and_then(parse_timezone) {
+            self.timezone = timezone;
+        }
+        self
+    }
+
+    /// Formats a timestamp for display in the locale's date notation.
+    pub fn format_date(&self, timestamp: i64) -> String {
+        self.local_time(timestamp)
+            .format(self.locale.date_format())
+            .to_string()
+    }
+
+    /// Formats a timestamp as an RFC 5322 date in the local time zone.
+    pub fn format_rfc822(&self, timestamp: i64) -> String {
+        self.local_time(timestamp)
+            .format("%a, %d %b %Y %H:%M:%S %z")
+            .to_string()
+    }
+
+    fn local_time(&self, timestamp: i64) -> DateTime<Tz> {
+        self.timezone
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .unwrap_or_else(|| self.timezone.timestamp_opt(0, 0).unwrap())
+    }
+}
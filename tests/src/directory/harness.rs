@@ -0,0 +1,184 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use ahash::AHashMap;
+use directory::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{self, ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Principal, QueryBy, Type,
+};
+use store::{
+    backend::in_memory::InMemoryStore,
+    rand::{rngs::StdRng, Rng, SeedableRng},
+    Store,
+};
+
+pub fn in_memory_store() -> Store {
+    InMemoryStore::new().into()
+}
+
+pub fn individual(name: &str) -> Principal {
+    Principal::new(u32::MAX, Type::Individual).with_field(PrincipalField::Name, name)
+}
+
+pub fn group(name: &str) -> Principal {
+    Principal::new(u32::MAX, Type::Group).with_field(PrincipalField::Name, name)
+}
+
+pub fn domain(name: &str) -> Principal {
+    Principal::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, name)
+}
+
+pub fn tenant(name: &str) -> Principal {
+    Principal::new(u32::MAX, Type::Tenant).with_field(PrincipalField::Name, name)
+}
+
+pub trait PrincipalBuilder {
+    fn with_email(self, email: &str) -> Self;
+    fn with_member_of(self, group: &str) -> Self;
+    fn with_tenant(self, tenant: &str) -> Self;
+}
+
+impl PrincipalBuilder for Principal {
+    fn with_email(mut self, email: &str) -> Self {
+        self.append_str(PrincipalField::Emails, email);
+        self
+    }
+
+    fn with_member_of(mut self, group: &str) -> Self {
+        self.append_str(PrincipalField::MemberOf, group);
+        self
+    }
+
+    fn with_tenant(self, tenant: &str) -> Self {
+        self.with_field(PrincipalField::Tenant, tenant)
+    }
+}
+
+pub async fn create(store: &Store, principals: impl IntoIterator<Item = Principal>) -> Vec<u32> {
+    let mut ids = Vec::new();
+    for principal in principals {
+        ids.push(store.create_principal(principal, None, None).await.unwrap());
+    }
+    ids
+}
+
+#[tokio::test]
+async fn in_memory_builders() {
+    let store = in_memory_store();
+
+    let ids = create(
+        &store,
+        [
+            tenant("acme"),
+            domain("acme.org").with_tenant("acme"),
+            group("sales@acme.org")
+                .with_email("sales@acme.org")
+                .with_tenant("acme"),
+            individual("jane@acme.org")
+                .with_email("jane@acme.org")
+                .with_member_of("sales@acme.org")
+                .with_tenant("acme"),
+        ],
+    )
+    .await;
+
+    let jane = store
+        .query(QueryBy::Id(ids[3]), true)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(jane.tenant(), Some(ids[0]));
+    assert_eq!(
+        jane.get_int_array(PrincipalField::MemberOf),
+        Some(&[ids[2] as u64][..])
+    );
+    assert_eq!(store.get_members(ids[2]).await.unwrap(), vec![ids[3]]);
+}
+
+#[tokio::test]
+async fn in_memory_random_operations() {
+    const NAMES: u32 = 16;
+    const OPERATIONS: usize = 500;
+
+    for seed in 0..4 {
+        println!("Running random directory operations with seed {seed}...");
+        let store = in_memory_store();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut model: AHashMap<String, u32> = AHashMap::new();
+
+        for _ in 0..OPERATIONS {
+            let name = format!("user{}", rng.gen_range(0..NAMES));
+
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let result = store.create_principal(individual(&name), None, None).await;
+                    if model.contains_key(&name) {
+                        assert_eq!(
+                            result,
+                            Err(manage::err_exists(PrincipalField::Name, name.clone()))
+                        );
+                    } else {
+                        model.insert(name, result.unwrap());
+                    }
+                }
+                2 => {
+                    let new_name = format!("user{}", rng.gen_range(0..NAMES));
+                    let Some(&id) = model.get(&name).filter(|_| new_name != name) else {
+                        continue;
+                    };
+                    let result = store
+                        .update_principal(UpdatePrincipal::by_id(id).with_updates(vec![
+                            PrincipalUpdate::set(
+                                PrincipalField::Name,
+                                PrincipalValue::String(new_name.clone()),
+                            ),
+                        ]))
+                        .await;
+                    if model.contains_key(&new_name) {
+                        assert_eq!(
+                            result,
+                            Err(manage::err_exists(PrincipalField::Name, new_name))
+                        );
+                    } else {
+                        result.unwrap();
+                        model.remove(&name);
+                        model.insert(new_name, id);
+                    }
+                }
+                _ => {
+                    let result = store.delete_principal(QueryBy::Name(&name)).await;
+                    if model.remove(&name).is_some() {
+                        result.unwrap();
+                    } else {
+                        assert!(result.is_err());
+                    }
+                }
+            }
+
+            // NameToId and the principal record must always agree
+            for n in 0..NAMES {
+                let name = format!("user{n}");
+                let id = store.get_principal_id(&name).await.unwrap();
+                assert_eq!(id, model.get(&name).copied(), "name {name:?}");
+                if let Some(id) = id {
+                    assert_eq!(store.get_principal(id).await.unwrap().unwrap().name(), name);
+                }
+            }
+            assert_eq!(
+                store
+                    .list_principals(None, None, &[Type::Individual], &[], 0, 0)
+                    .await
+                    .unwrap()
+                    .total,
+                model.len() as u64
+            );
+        }
+    }
+}
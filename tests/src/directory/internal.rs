@@ -4,33 +4,55 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use std::time::Duration;
+
 use ahash::AHashSet;
 use directory::{
     backend::{
         internal::{
             lookup::DirectoryStore,
-            manage::{self, ManageDirectory, UpdatePrincipal},
-            PrincipalField, PrincipalUpdate, PrincipalValue,
+            maintenance::{DirectoryIssueKind, MaintenanceStore},
+            manage::{
+                self, CreateRetry, ErrorCode, ImportAccount, ImportStatus, ManageDirectory,
+                PrincipalFilter, UpdatePrincipal,
+            },
+            AppScope, MigrateDirectory, PrincipalField, PrincipalInfo, PrincipalUpdate,
+            PrincipalValue, SpecialSecrets, PRINCIPAL_INFO_MIGRATION, RECOVERY_CODE_PREFIX,
         },
         RcptType,
     },
-    Principal, QueryBy, Type,
+    core::secret::hash_secret,
+    ClientCertificate, Principal, QueryBy, Type, ROLE_ADMIN, ROLE_USER,
 };
 use jmap_proto::types::collection::Collection;
 use mail_send::Credentials;
 use store::{
     roaring::RoaringBitmap,
-    write::{BatchBuilder, BitmapClass, ValueClass},
-    BitmapKey, Store, ValueKey,
+    write::{
+        key::KeySerializer, BatchBuilder, BitmapClass, DirectoryClass, MaybeDynamicId, ValueClass,
+    },
+    BitmapKey, Serialize, Store, ValueKey, U32_LEN,
+};
+use utils::{
+    config::Config,
+    locale::{Locale, Localization},
 };
 
-use crate::directory::{DirectoryTest, IntoTestPrincipal, TestPrincipal};
+use crate::directory::{
+    harness::{create, domain, group, in_memory_store, individual, tenant, PrincipalBuilder},
+    DirectoryTest, IntoTestPrincipal, TestPrincipal,
+};
 
 #[tokio::test]
 async fn internal_directory() {
     let config = DirectoryTest::new(None).await;
+    let stores = config
+        .stores
+        .stores
+        .into_iter()
+        .chain([("in-memory".to_string(), in_memory_store())]);
 
-    for (store_id, store) in config.stores.stores {
+    for (store_id, store) in stores {
         println!("Testing internal directory with store {:?}", store_id);
         store.destroy().await;
 
@@ -88,7 +110,10 @@ async fn internal_directory() {
                     None
                 )
                 .await,
-            Err(manage::not_found("example.org".to_string()))
+            Err(manage::err_not_found(
+                ErrorCode::DomainNotFound,
+                "example.org".to_string()
+            ))
         );
 
         // Create a domain name
@@ -139,7 +164,10 @@ async fn internal_directory() {
                     )
                 ]))
                 .await,
-            Err(manage::not_found("otherdomain.org".to_string()))
+            Err(manage::err_not_found(
+                ErrorCode::DomainNotFound,
+                "otherdomain.org".to_string()
+            ))
         );
 
         // Create an account with an email address
@@ -385,7 +413,10 @@ async fn internal_directory() {
                     )
                 ]))
                 .await,
-            Err(manage::not_found("accounting".to_string()))
+            Err(manage::err_not_found(
+                ErrorCode::PrincipalNotFound,
+                "accounting".to_string()
+            ))
         );
 
         // Remove a member from a group
@@ -481,6 +512,63 @@ async fn internal_directory() {
             RcptType::Mailbox
         );
 
+        // Renaming a principal moves the address that matches its login name
+        let mike_id = store
+            .create_principal(
+                TestPrincipal {
+                    name: "mike@example.org".to_string(),
+                    emails: vec![
+                        "mike@example.org".to_string(),
+                        "sales.mike@example.org".to_string(),
+                    ],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .update_principal(
+                    UpdatePrincipal::by_id(mike_id)
+                        .with_updates(vec![PrincipalUpdate::set(
+                            PrincipalField::Name,
+                            PrincipalValue::String("michael@example.org".to_string())
+                        )])
+                        .cascade_rename()
+                )
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            store
+                .query(QueryBy::Id(mike_id), false)
+                .await
+                .unwrap()
+                .unwrap()
+                .into_test()
+                .emails,
+            vec![
+                "michael@example.org".to_string(),
+                "sales.mike@example.org".to_string()
+            ]
+        );
+        assert_eq!(
+            store.rcpt("michael@example.org").await.unwrap(),
+            RcptType::Mailbox
+        );
+        assert_eq!(
+            store.rcpt("mike@example.org").await.unwrap(),
+            RcptType::Invalid
+        );
+        assert_eq!(
+            store.rcpt("sales.mike@example.org").await.unwrap(),
+            RcptType::Mailbox
+        );
+        store.delete_principal(QueryBy::Id(mike_id)).await.unwrap();
+
         // Remove a member from a mailing list and then add it back
         assert_eq!(
             store
@@ -573,7 +661,7 @@ async fn internal_directory() {
         );
         assert_eq!(
             store
-                .list_principals("john".into(), None, &[], &[], 0, 0)
+                .list_principals(Some(&PrincipalFilter::parse("john")), None, &[], &[], 0, 0)
                 .await
                 .unwrap()
                 .items
@@ -582,6 +670,32 @@ async fn internal_directory() {
                 .collect::<Vec<_>>(),
             vec!["john.doe"]
         );
+        for (filter, expected) in [
+            ("doe", vec!["jane", "john.doe"]),
+            ("name:doe", vec!["john.doe"]),
+            ("emails:@example.org description:jane", vec!["jane"]),
+            ("description:team", vec!["sales", "support"]),
+        ] {
+            assert_eq!(
+                store
+                    .list_principals(
+                        Some(&PrincipalFilter::parse(filter)),
+                        None,
+                        &[Type::Individual, Type::Group, Type::List],
+                        &[PrincipalField::Name],
+                        0,
+                        0
+                    )
+                    .await
+                    .unwrap()
+                    .items
+                    .into_iter()
+                    .map(|p| p.name().to_string())
+                    .collect::<Vec<_>>(),
+                expected,
+                "filter: {filter}"
+            );
+        }
         assert_eq!(
             store
                 .list_principals(None, None, &[Type::Individual], &[], 0, 0)
@@ -622,6 +736,84 @@ async fn internal_directory() {
             vec!["list"]
         );
 
+        // Paginate accounts
+        let types = [Type::Individual, Type::Group, Type::List];
+        let page = store
+            .list_principals(None, None, &types, &[PrincipalField::Name], 2, 2)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(
+            page.items
+                .into_iter()
+                .map(|p| p.name().to_string())
+                .collect::<Vec<_>>(),
+            vec!["list", "sales"]
+        );
+        let page = store
+            .list_principals(None, None, &types, &[], 3, 2)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].name(), "support");
+        assert_eq!(page.items[0].description(), Some("Support Team"));
+        for (page, names) in [(1, vec!["jane", "john.doe"]), (4, vec![])] {
+            let page = store
+                .list_principals(
+                    None,
+                    None,
+                    &types,
+                    &[PrincipalField::Name, PrincipalField::Description],
+                    page,
+                    2,
+                )
+                .await
+                .unwrap();
+            assert_eq!(page.total, 5);
+            assert_eq!(
+                page.items
+                    .into_iter()
+                    .map(|p| p.name().to_string())
+                    .collect::<Vec<_>>(),
+                names
+            );
+        }
+
+        let mut after = None;
+        let mut names = Vec::new();
+        loop {
+            let page = store
+                .list_principals_after(None, None, &types, &[], after.as_deref(), 2)
+                .await
+                .unwrap();
+            assert!(page.items.len() <= 2);
+            names.extend(page.items.into_iter().map(|p| p.name().to_string()));
+            if page.next.is_none() {
+                break;
+            }
+            after = page.next;
+        }
+        assert_eq!(names, vec!["jane", "john.doe", "list", "sales", "support"]);
+        assert_eq!(
+            store
+                .list_principals_after(
+                    Some(&PrincipalFilter::parse("john")),
+                    None,
+                    &types,
+                    &[],
+                    Some("jane"),
+                    0
+                )
+                .await
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|p| p.name().to_string())
+                .collect::<Vec<_>>(),
+            vec!["john.doe"]
+        );
+
         // Write records on John's and Jane's accounts
         let mut document_id = u32::MAX;
         for account_id in [john_id, jane_id] {
@@ -743,7 +935,2387 @@ async fn internal_directory() {
                 .unwrap(),
             Some("hello".to_string())
         );
-    }
+
+        // Error codes
+        assert_eq!(
+            error_code(
+                store
+                    .create_principal(Principal::default(), None, None)
+                    .await
+            ),
+            ErrorCode::FieldMissing
+        );
+        assert_eq!(
+            error_code(
+                store
+                    .create_principal(
+                        TestPrincipal {
+                            name: "jane".to_string(),
+                            ..Default::default()
+                        }
+                        .into(),
+                        None,
+                        None
+                    )
+                    .await
+            ),
+            ErrorCode::PrincipalAlreadyExists
+        );
+        assert_eq!(
+            error_code(
+                store
+                    .create_principal(
+                        TestPrincipal {
+                            name: "jane.smith".to_string(),
+                            emails: vec!["jane@example.org".to_string()],
+                            ..Default::default()
+                        }
+                        .into(),
+                        None,
+                        None
+                    )
+                    .await
+            ),
+            ErrorCode::AddressAlreadyExists
+        );
+        assert_eq!(
+            error_code(
+                store
+                    .create_principal(
+                        TestPrincipal {
+                            name: "jane.smith".to_string(),
+                            emails: vec!["jane@unknown.org".to_string()],
+                            ..Default::default()
+                        }
+                        .into(),
+                        None,
+                        None
+                    )
+                    .await
+            ),
+            ErrorCode::DomainNotFound
+        );
+        for (update, expected_code) in [
+            (
+                PrincipalUpdate::add_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String("unknown".to_string()),
+                ),
+                ErrorCode::PrincipalNotFound,
+            ),
+            (
+                PrincipalUpdate::add_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String("example.org".to_string()),
+                ),
+                ErrorCode::MemberInvalid,
+            ),
+            (
+                PrincipalUpdate::set(
+                    PrincipalField::EnabledPermissions,
+                    PrincipalValue::StringList(vec!["not-a-permission".to_string()]),
+                ),
+                ErrorCode::PermissionInvalid,
+            ),
+            (
+                PrincipalUpdate::add_item(
+                    PrincipalField::ExternalMembers,
+                    PrincipalValue::String("not an address".to_string()),
+                ),
+                ErrorCode::AddressInvalid,
+            ),
+            (
+                PrincipalUpdate::set(PrincipalField::Emails, PrincipalValue::Integer(1)),
+                ErrorCode::FieldInvalid,
+            ),
+        ] {
+            assert_eq!(
+                error_code(
+                    store
+                        .update_principal(
+                            UpdatePrincipal::by_name("jane").with_updates(vec![update])
+                        )
+                        .await
+                ),
+                expected_code
+            );
+        }
+        assert_eq!(
+            error_code(
+                store
+                    .update_principal(UpdatePrincipal::by_name("unknown").with_updates(vec![]))
+                    .await
+            ),
+            ErrorCode::PrincipalNotFound
+        );
+
+        // Codes must be unique
+        let mut codes = AHashSet::new();
+        for code in ErrorCode::ALL {
+            assert!(codes.insert(code.as_str()), "duplicate code {code:?}");
+            assert_eq!(ErrorCode::parse(code.as_str()), Some(*code));
+        }
+
+        // Service accounts only authenticate with API tokens and have no mailbox
+        let bot_id = store
+            .create_principal(
+                TestPrincipal {
+                    name: "bot".to_string(),
+                    typ: Type::Service,
+                    emails: vec!["bot@example.org".to_string()],
+                    secrets: vec!["bot_password".to_string(), "$app$ci$bot_token".to_string()],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        for (secret, expected_id) in [("bot_password", None), ("bot_token", Some(bot_id))] {
+            assert_eq!(
+                store
+                    .query(
+                        QueryBy::Credentials(&Credentials::new(
+                            "bot".to_string(),
+                            secret.to_string()
+                        )),
+                        false
+                    )
+                    .await
+                    .unwrap()
+                    .map(|p| p.id()),
+                expected_id
+            );
+        }
+        assert_eq!(
+            store.rcpt("bot@example.org").await.unwrap(),
+            RcptType::Invalid
+        );
+        assert_eq!(
+            store
+                .list_principals(None, None, &[Type::Service], &[PrincipalField::Name], 0, 0)
+                .await
+                .unwrap()
+                .items
+                .into_iter()
+                .map(|p| p.name().to_string())
+                .collect::<Vec<_>>(),
+            vec!["bot".to_string()]
+        );
+
+        // Individuals can be converted to service accounts and back
+        store
+            .create_principal(
+                TestPrincipal {
+                    name: "robot".to_string(),
+                    emails: vec!["robot@example.org".to_string()],
+                    secrets: vec!["robot_password".to_string()],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        for (typ, expected_rcpt) in [
+            (Type::Service, RcptType::Invalid),
+            (Type::Individual, RcptType::Mailbox),
+        ] {
+            store
+                .update_principal(UpdatePrincipal::by_name("robot").with_updates(vec![
+                    PrincipalUpdate::set(
+                        PrincipalField::Type,
+                        PrincipalValue::String(typ.to_jmap().to_string()),
+                    ),
+                ]))
+                .await
+                .unwrap();
+            assert_eq!(
+                store
+                    .get_principal_info("robot")
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .typ,
+                typ
+            );
+            assert_eq!(
+                store.rcpt("robot@example.org").await.unwrap(),
+                expected_rcpt
+            );
+        }
+        assert_eq!(
+            error_code(
+                store
+                    .update_principal(UpdatePrincipal::by_name("bot").with_updates(vec![
+                        PrincipalUpdate::set(
+                            PrincipalField::Type,
+                            PrincipalValue::String("group".to_string()),
+                        ),
+                    ]))
+                    .await
+            ),
+            ErrorCode::PrincipalInvalidType
+        );
+
+        // Membership cycles are rejected
+        let groups = ["ring-a", "ring-b", "ring-c", "ring-d", "ring-e", "ring-f"];
+        for group in groups {
+            store.create_test_group(group, group, &[]).await;
+        }
+        for (name, update) in [
+            (
+                "ring-a",
+                PrincipalUpdate::add_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String("ring-a".to_string()),
+                ),
+            ),
+            (
+                "ring-a",
+                PrincipalUpdate::add_item(
+                    PrincipalField::Members,
+                    PrincipalValue::String("ring-a".to_string()),
+                ),
+            ),
+        ] {
+            assert_eq!(
+                error_code(
+                    store
+                        .update_principal(UpdatePrincipal::by_name(name).with_updates(vec![update]))
+                        .await
+                ),
+                ErrorCode::MemberInvalid
+            );
+        }
+        store.add_to_group("ring-a", "ring-b").await;
+        for (name, update) in [
+            (
+                "ring-b",
+                PrincipalUpdate::add_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String("ring-a".to_string()),
+                ),
+            ),
+            (
+                "ring-b",
+                PrincipalUpdate::set(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::StringList(vec!["ring-a".to_string()]),
+                ),
+            ),
+            (
+                "ring-a",
+                PrincipalUpdate::add_item(
+                    PrincipalField::Members,
+                    PrincipalValue::String("ring-b".to_string()),
+                ),
+            ),
+        ] {
+            assert_eq!(
+                error_code(
+                    store
+                        .update_principal(UpdatePrincipal::by_name(name).with_updates(vec![update]))
+                        .await
+                ),
+                ErrorCode::MemberInvalid
+            );
+        }
+        for pair in groups[1..].windows(2) {
+            store.add_to_group(pair[0], pair[1]).await;
+        }
+        for (name, update) in [
+            (
+                "ring-f",
+                PrincipalUpdate::add_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String("ring-a".to_string()),
+                ),
+            ),
+            (
+                "ring-a",
+                PrincipalUpdate::set(
+                    PrincipalField::Members,
+                    PrincipalValue::StringList(vec!["ring-f".to_string()]),
+                ),
+            ),
+        ] {
+            assert_eq!(
+                error_code(
+                    store
+                        .update_principal(UpdatePrincipal::by_name(name).with_updates(vec![update]))
+                        .await
+                ),
+                ErrorCode::MemberInvalid
+            );
+        }
+
+        // Shortcuts that do not close a loop are allowed
+        store.add_to_group("ring-a", "ring-f").await;
+        store.add_to_group("ring-b", "ring-e").await;
+
+        // Bulk creation reports every conflict and writes nothing
+        let result = store
+            .create_principals(
+                vec![
+                    TestPrincipal {
+                        name: "jane".to_string(),
+                        ..Default::default()
+                    }
+                    .into(),
+                    TestPrincipal {
+                        name: "bulk-dup".to_string(),
+                        ..Default::default()
+                    }
+                    .into(),
+                    TestPrincipal {
+                        name: "bulk-dup".to_string(),
+                        ..Default::default()
+                    }
+                    .into(),
+                    TestPrincipal {
+                        name: "bulk-address".to_string(),
+                        emails: vec!["jane@example.org".to_string()],
+                        ..Default::default()
+                    }
+                    .into(),
+                    TestPrincipal {
+                        name: "bulk-member".to_string(),
+                        member_of: vec!["unknown-group".to_string()],
+                        ..Default::default()
+                    }
+                    .into(),
+                ],
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(result.ids.is_empty());
+        assert_eq!(
+            result
+                .errors
+                .into_iter()
+                .map(|(idx, err)| (idx, error_code::<()>(Err(err))))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, ErrorCode::PrincipalAlreadyExists),
+                (2, ErrorCode::PrincipalAlreadyExists),
+                (3, ErrorCode::AddressAlreadyExists),
+                (4, ErrorCode::PrincipalNotFound),
+            ]
+        );
+        assert_eq!(store.get_principal_id("bulk-dup").await.unwrap(), None);
+
+        // Members can reference principals created in a later chunk
+        let mut principals = (0..249)
+            .map(|n| {
+                TestPrincipal {
+                    name: format!("bulk-user-{n}"),
+                    emails: vec![format!("bulk-user-{n}@example.org")],
+                    member_of: vec!["bulk-group".to_string()],
+                    ..Default::default()
+                }
+                .into()
+            })
+            .collect::<Vec<Principal>>();
+        principals.push(
+            TestPrincipal {
+                name: "bulk-group".to_string(),
+                typ: Type::Group,
+                ..Default::default()
+            }
+            .into(),
+        );
+        let result = store
+            .create_principals(principals, None, None)
+            .await
+            .unwrap();
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.ids.len(), 250);
+        for (n, id) in result.ids[..249].iter().enumerate() {
+            assert_eq!(
+                store
+                    .get_principal_id(&format!("bulk-user-{n}"))
+                    .await
+                    .unwrap(),
+                Some(*id)
+            );
+        }
+        let group_id = result.ids[249];
+        assert_eq!(
+            store.get_principal_id("bulk-group").await.unwrap(),
+            Some(group_id)
+        );
+        assert_eq!(store.get_members(group_id).await.unwrap().len(), 249);
+        assert_eq!(
+            store
+                .get_member_of(result.ids[0])
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|m| m.principal_id)
+                .collect::<Vec<_>>(),
+            vec![group_id]
+        );
+        assert_eq!(
+            store.rcpt("bulk-user-100@example.org").await.unwrap(),
+            RcptType::Mailbox
+        );
+
+        // Updates are applied in full or not at all
+        let batch_id = store
+            .create_test_user("batch", "secret", "Batch User", &["batch@example.org"])
+            .await;
+        let batch_principal = TestPrincipal {
+            id: batch_id,
+            name: "batch".to_string(),
+            description: Some("Batch User".to_string()),
+            secrets: vec!["secret".to_string()],
+            emails: vec!["batch@example.org".to_string()],
+            roles: vec!["user".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            error_code(
+                store
+                    .update_principal(
+                        UpdatePrincipal::by_name("batch")
+                            .with_updates(vec![
+                                PrincipalUpdate::set(
+                                    PrincipalField::Description,
+                                    PrincipalValue::String("Changed".to_string()),
+                                ),
+                                PrincipalUpdate::set(
+                                    PrincipalField::Emails,
+                                    PrincipalValue::StringList(vec![
+                                        "batch@new-domain.org".to_string()
+                                    ]),
+                                ),
+                                PrincipalUpdate::add_item(
+                                    PrincipalField::MemberOf,
+                                    PrincipalValue::String("bulk-group".to_string()),
+                                ),
+                                PrincipalUpdate::add_item(
+                                    PrincipalField::MemberOf,
+                                    PrincipalValue::String("unknown-group".to_string()),
+                                ),
+                            ])
+                            .create_domains(),
+                    )
+                    .await
+            ),
+            ErrorCode::PrincipalNotFound
+        );
+        assert_eq!(batch_test_principal(&store).await, batch_principal);
+        assert!(!store
+            .get_member_of(batch_id)
+            .await
+            .unwrap()
+            .iter()
+            .any(|member| member.typ == Type::Group));
+        assert_eq!(
+            store.get_principal_id("new-domain.org").await.unwrap(),
+            None
+        );
+        assert_eq!(
+            store.rcpt("batch@new-domain.org").await.unwrap(),
+            RcptType::Invalid
+        );
+        assert_eq!(
+            store.rcpt("batch@example.org").await.unwrap(),
+            RcptType::Mailbox
+        );
+
+        // Repeated changes collapse and removed addresses can be added back
+        store
+            .update_principal(
+                UpdatePrincipal::by_name("batch")
+                    .with_updates(vec![
+                        PrincipalUpdate::set(
+                            PrincipalField::Emails,
+                            PrincipalValue::StringList(vec![
+                                "batch@example.org".to_string(),
+                                "batch@new-domain.org".to_string(),
+                                "batch@example.org".to_string(),
+                            ]),
+                        ),
+                        PrincipalUpdate::remove_item(
+                            PrincipalField::Emails,
+                            PrincipalValue::String("batch@example.org".to_string()),
+                        ),
+                        PrincipalUpdate::add_item(
+                            PrincipalField::Emails,
+                            PrincipalValue::String("batch@example.org".to_string()),
+                        ),
+                        PrincipalUpdate::add_item(
+                            PrincipalField::MemberOf,
+                            PrincipalValue::String("bulk-group".to_string()),
+                        ),
+                        PrincipalUpdate::add_item(
+                            PrincipalField::MemberOf,
+                            PrincipalValue::String("bulk-group".to_string()),
+                        ),
+                    ])
+                    .create_domains(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            batch_test_principal(&store).await,
+            TestPrincipal {
+                emails: vec![
+                    "batch@new-domain.org".to_string(),
+                    "batch@example.org".to_string(),
+                ],
+                member_of: vec!["bulk-group".to_string()],
+                ..batch_principal
+            }
+        );
+        assert_eq!(
+            store
+                .get_member_of(batch_id)
+                .await
+                .unwrap()
+                .iter()
+                .filter(|member| member.typ == Type::Group)
+                .count(),
+            1
+        );
+        assert!(store
+            .get_principal_id("new-domain.org")
+            .await
+            .unwrap()
+            .is_some());
+        for email in ["batch@example.org", "batch@new-domain.org"] {
+            assert_eq!(store.rcpt(email).await.unwrap(), RcptType::Mailbox);
+        }
+
+        // Legacy password hashes are upgraded after a successful login
+        let legacy_id = store
+            .create_principal(
+                TestPrincipal {
+                    name: "legacy".to_string(),
+                    secrets: vec![
+                        "$app$phone$app_secret".to_string(),
+                        "$1$abcdefgh$bXJT8S7Tmlin.1pWMOOm7/".to_string(),
+                    ],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let sha_id = store
+            .create_principal(
+                TestPrincipal {
+                    name: "legacy-sha".to_string(),
+                    secrets: vec!["{SHA}tsGKXD0hLm6LW4kqkaYYx4wEmp4=".to_string()],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let secrets = |id: u32| {
+            let store = store.clone();
+            async move {
+                store
+                    .get_principal(id)
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .get_str_array(PrincipalField::Secrets)
+                    .unwrap_or_default()
+                    .to_vec()
+            }
+        };
+
+        // Upgrades are opt-in and only happen for the password that verified
+        for (name, secret, upgrade) in [
+            ("legacy-sha", "legacy_secret", false),
+            ("legacy", "app_secret", true),
+            ("legacy", "wrong_secret", true),
+        ] {
+            let credentials =
+                QueryBy::Credentials(&Credentials::new(name.to_string(), secret.to_string()));
+            let result = if upgrade {
+                store.query_and_upgrade(credentials, false).await
+            } else {
+                store.query(credentials, false).await
+            };
+            assert_eq!(
+                result.unwrap().is_some(),
+                secret != "wrong_secret",
+                "{name} {secret}"
+            );
+        }
+        assert_eq!(
+            secrets(sha_id).await,
+            vec!["{SHA}tsGKXD0hLm6LW4kqkaYYx4wEmp4=".to_string()]
+        );
+        assert_eq!(
+            secrets(legacy_id).await,
+            vec![
+                "$app$phone$app_secret".to_string(),
+                "$1$abcdefgh$bXJT8S7Tmlin.1pWMOOm7/".to_string(),
+            ]
+        );
+
+        // The legacy hash is replaced, app passwords are kept
+        for name in ["legacy", "legacy-sha"] {
+            let principal = store
+                .query_and_upgrade(
+                    QueryBy::Credentials(&Credentials::new(
+                        name.to_string(),
+                        "legacy_secret".to_string(),
+                    )),
+                    false,
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            let stored = secrets(principal.id()).await;
+            assert_eq!(
+                principal.get_str_array(PrincipalField::Secrets).unwrap(),
+                stored.as_slice()
+            );
+            let (app_secrets, new_secret) = stored.split_at(stored.len() - 1);
+            assert!(new_secret[0].starts_with("$argon2id$"), "{stored:?}");
+            if name == "legacy" {
+                assert_eq!(app_secrets, ["$app$phone$app_secret".to_string()]);
+            } else {
+                assert!(app_secrets.is_empty());
+            }
+        }
+        for secret in ["legacy_secret", "app_secret"] {
+            assert!(store
+                .query(
+                    QueryBy::Credentials(&Credentials::new(
+                        "legacy".to_string(),
+                        secret.to_string(),
+                    )),
+                    false,
+                )
+                .await
+                .unwrap()
+                .is_some());
+        }
+
+        // Updates based on an outdated principal are rejected
+        assert!(store
+            .update_principal(
+                UpdatePrincipal::by_id(legacy_id)
+                    .if_unchanged(0)
+                    .with_updates(vec![PrincipalUpdate::set(
+                        PrincipalField::Description,
+                        PrincipalValue::String("Legacy".to_string()),
+                    )]),
+            )
+            .await
+            .unwrap_err()
+            .is_assertion_failure());
+
+        // Scoped app passwords are only valid for the listed protocols
+        let app_id = store
+            .create_principal(
+                TestPrincipal {
+                    name: "scoped".to_string(),
+                    secrets: vec![
+                        "account_secret".to_string(),
+                        "$app$phone[imap,smtp]$phone_secret".to_string(),
+                        "$app$phone2$phone2_secret".to_string(),
+                        "$app$old[unknown]$old_secret".to_string(),
+                    ],
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        for (secret, scope, expected) in [
+            ("account_secret", AppScope::Pop3, true),
+            ("phone_secret", AppScope::Imap, true),
+            ("phone_secret", AppScope::Smtp, true),
+            ("phone_secret", AppScope::Pop3, false),
+            ("phone_secret", AppScope::Http, false),
+            ("phone2_secret", AppScope::Http, true),
+            ("old_secret", AppScope::Imap, false),
+        ] {
+            let principal = store
+                .query(
+                    QueryBy::Credentials(&Credentials::new(
+                        "scoped".to_string(),
+                        secret.to_string(),
+                    )),
+                    false,
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                principal.verify_scope(secret, scope).await.unwrap(),
+                expected,
+                "{secret} {scope:?}"
+            );
+        }
+
+        // App passwords are removed by name, regardless of their scopes
+        store
+            .update_principal(UpdatePrincipal::by_id(app_id).with_updates(vec![
+                PrincipalUpdate::remove_item(
+                    PrincipalField::Secrets,
+                    PrincipalValue::String("$app$phone".to_string()),
+                ),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(
+            secrets(app_id).await,
+            vec![
+                "account_secret".to_string(),
+                "$app$phone2$phone2_secret".to_string(),
+                "$app$old[unknown]$old_secret".to_string(),
+            ]
+        );
+
+        // Recovery codes replace the TOTP token and can only be used once
+        let recovery_codes = ["abcde-fghjk", "mnpqr-stuvw"];
+        let mut recovery_secrets = vec![
+            "otpauth://totp/Test:recovery?secret=JBSWY3DPEHPK3PXP&issuer=Test".to_string(),
+            "recovery_secret".to_string(),
+        ];
+        for code in recovery_codes {
+            recovery_secrets.push(format!(
+                "{RECOVERY_CODE_PREFIX}{}",
+                hash_secret(code).await.unwrap()
+            ));
+        }
+        let recovery_id = store
+            .create_principal(
+                TestPrincipal {
+                    name: "recovery".to_string(),
+                    secrets: recovery_secrets,
+                    ..Default::default()
+                }
+                .into(),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let login = |secret: &str| {
+            let store = store.clone();
+            let secret = secret.to_string();
+            async move {
+                store
+                    .query(
+                        QueryBy::Credentials(&Credentials::new("recovery".to_string(), secret)),
+                        false,
+                    )
+                    .await
+                    .map(|principal| principal.is_some())
+            }
+        };
+        assert!(login("recovery_secret")
+            .await
+            .unwrap_err()
+            .matches(trc::EventType::Auth(trc::AuthEvent::MissingTotp)));
+        for (secret, expected) in [
+            ("recovery_secret$zzzzz-zzzzz", false),
+            ("wrong_secret$abcde-fghjk", false),
+            ("recovery_secret$ABCDE-FGHJK", true),
+            ("recovery_secret$abcde-fghjk", false),
+        ] {
+            assert_eq!(login(secret).await.unwrap(), expected, "{secret}");
+        }
+        assert_eq!(
+            secrets(recovery_id)
+                .await
+                .iter()
+                .filter(|secret| secret.is_recovery_code())
+                .count(),
+            1
+        );
+
+        // Removing the prefix removes all recovery codes
+        store
+            .update_principal(UpdatePrincipal::by_id(recovery_id).with_updates(vec![
+                PrincipalUpdate::remove_item(
+                    PrincipalField::Secrets,
+                    PrincipalValue::String(RECOVERY_CODE_PREFIX.to_string()),
+                ),
+            ]))
+            .await
+            .unwrap();
+        assert_eq!(
+            secrets(recovery_id).await,
+            vec![
+                "otpauth://totp/Test:recovery?secret=JBSWY3DPEHPK3PXP&issuer=Test".to_string(),
+                "recovery_secret".to_string(),
+            ]
+        );
+        assert!(!login("recovery_secret$mnpqr-stuvw").await.unwrap());
+    }
+}
+
+#[tokio::test]
+async fn email_validation() {
+    let store = in_memory_store();
+    create(&store, [domain("xn--bcher-kva.example")]).await;
+
+    // Malformed and oversized addresses are rejected
+    for email in [
+        "foo bar@xn--bcher-kva.example".to_string(),
+        "foo@@xn--bcher-kva.example".to_string(),
+        "foo..bar@xn--bcher-kva.example".to_string(),
+        "foo@xn--bcher-kva..example".to_string(),
+        format!("{}@xn--bcher-kva.example", "a".repeat(65)),
+        format!(
+            "{}@{}.xn--bcher-kva.example",
+            "a".repeat(60),
+            ["b".repeat(63), "c".repeat(63), "d".repeat(50)].join(".")
+        ),
+    ] {
+        assert_eq!(
+            store
+                .create_principal(individual("jane").with_email(&email), None, None)
+                .await,
+            Err(manage::err_invalid_email(email.clone())),
+            "{email:?}"
+        );
+    }
+
+    // Internationalized domains are stored as punycode
+    let id = store
+        .create_principal(
+            individual("jane").with_email("Jane@Bücher.example"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .email_to_id("jane@xn--bcher-kva.example")
+            .await
+            .unwrap(),
+        Some(id)
+    );
+
+    // Updates are validated as well
+    assert_eq!(
+        store
+            .update_principal(UpdatePrincipal::by_id(id).with_updates(vec![
+                PrincipalUpdate::add_item(
+                    PrincipalField::Emails,
+                    PrincipalValue::String("jane smith@xn--bcher-kva.example".to_string()),
+                ),
+            ]))
+            .await,
+        Err(manage::err_invalid_email(
+            "jane smith@xn--bcher-kva.example"
+        ))
+    );
+    store
+        .update_principal(
+            UpdatePrincipal::by_id(id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::Emails,
+                PrincipalValue::StringList(vec!["j.doe@bücher.example".to_string()]),
+            )]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .email_to_id("j.doe@xn--bcher-kva.example")
+            .await
+            .unwrap(),
+        Some(id)
+    );
+}
+
+#[tokio::test]
+async fn domain_delete_cascade() {
+    let store = in_memory_store();
+    let ids = create(
+        &store,
+        [
+            domain("example.org"),
+            domain("example.net"),
+            individual("jane").with_email("jane@example.org"),
+            individual("john")
+                .with_email("john@example.org")
+                .with_email("john@example.net"),
+            individual("bill").with_email("bill@example.net"),
+        ],
+    )
+    .await;
+    let (jane_id, john_id, bill_id) = (ids[2], ids[3], ids[4]);
+
+    // Deleting a domain in use is refused by default
+    let err = store
+        .delete_principal(QueryBy::Name("example.org"))
+        .await
+        .unwrap_err();
+    assert!(
+        err.value(trc::Key::Reason)
+            .and_then(|v| v.as_str())
+            .is_some_and(|v| v.contains("jane@example.org")),
+        "{err:?}"
+    );
+    assert!(store
+        .get_principal_id("example.org")
+        .await
+        .unwrap()
+        .is_some());
+
+    // Cascading removes the domain's addresses and orphaned accounts
+    let deleted = store
+        .delete_principal_cascade(QueryBy::Name("example.org"), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        deleted.iter().map(|p| p.id).collect::<Vec<_>>(),
+        vec![jane_id]
+    );
+    assert_eq!(store.get_principal_id("example.org").await.unwrap(), None);
+    assert_eq!(store.get_principal(jane_id).await.unwrap(), None);
+    assert_eq!(store.email_to_id("john@example.org").await.unwrap(), None);
+    assert_eq!(
+        store.email_to_id("john@example.net").await.unwrap(),
+        Some(john_id)
+    );
+    assert_eq!(
+        store
+            .get_principal(john_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .iter_str(PrincipalField::Emails)
+            .collect::<Vec<_>>(),
+        vec!["john@example.net"]
+    );
+    assert_eq!(
+        store.email_to_id("bill@example.net").await.unwrap(),
+        Some(bill_id)
+    );
+
+    // Tenants only cascade over their own principals, nothing is modified otherwise
+    let ids = create(
+        &store,
+        [
+            tenant("acme"),
+            domain("acme.org").with_tenant("acme"),
+            individual("jim@acme.org")
+                .with_tenant("acme")
+                .with_email("jim@acme.org"),
+            individual("mallory").with_email("mallory@acme.org"),
+        ],
+    )
+    .await;
+    let (acme_id, jim_id, mallory_id) = (ids[0], ids[2], ids[3]);
+    assert!(store
+        .delete_principal_cascade(QueryBy::Name("example.net"), Some(acme_id))
+        .await
+        .err()
+        .unwrap()
+        .matches(trc::EventType::Manage(trc::ManageEvent::NotFound)));
+    let err = store
+        .delete_principal_cascade(QueryBy::Name("acme.org"), Some(acme_id))
+        .await
+        .err()
+        .unwrap();
+    assert!(
+        err.value(trc::Key::Reason)
+            .and_then(|v| v.as_str())
+            .is_some_and(|v| v.contains("another tenant")),
+        "{err:?}"
+    );
+    assert!(store.get_principal_id("acme.org").await.unwrap().is_some());
+    assert_eq!(
+        store.email_to_id("jim@acme.org").await.unwrap(),
+        Some(jim_id)
+    );
+    assert_eq!(
+        store.email_to_id("mallory@acme.org").await.unwrap(),
+        Some(mallory_id)
+    );
+}
+
+#[tokio::test]
+async fn tenant_principal_quota() {
+    let store = in_memory_store();
+    create(
+        &store,
+        [
+            tenant("acme").with_field(PrincipalField::Quota, vec![0u64, 2, 0]),
+            domain("acme.org").with_tenant("acme"),
+        ],
+    )
+    .await;
+    let acme_id = store.get_principal_id("acme").await.unwrap().unwrap();
+
+    // Limit 0 leaves the type unlimited
+    for name in ["sales", "support", "billing"] {
+        store
+            .create_principal(group(&format!("{name}@acme.org")), acme_id.into(), None)
+            .await
+            .unwrap();
+    }
+
+    // Creation is refused once the limit is reached
+    let mut ids = Vec::new();
+    for name in ["jane", "john"] {
+        ids.push(
+            store
+                .create_principal(
+                    individual(&format!("{name}@acme.org")),
+                    acme_id.into(),
+                    None,
+                )
+                .await
+                .unwrap(),
+        );
+    }
+    let err = store
+        .create_principal(individual("bill@acme.org"), acme_id.into(), None)
+        .await
+        .unwrap_err();
+    assert!(err.matches(trc::EventType::Limit(trc::LimitEvent::TenantQuota)));
+    assert_eq!(store.get_principal_id("bill@acme.org").await.unwrap(), None);
+
+    // Deleting a principal frees its slot
+    store.delete_principal(QueryBy::Id(ids[0])).await.unwrap();
+    store
+        .create_principal(individual("bill@acme.org"), acme_id.into(), None)
+        .await
+        .unwrap();
+
+    // Concurrent creations never exceed the limit
+    store.delete_principal(QueryBy::Id(ids[1])).await.unwrap();
+    let tasks = (0..8)
+        .map(|num| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                store
+                    .create_principal(
+                        individual(&format!("user{num}@acme.org")),
+                        acme_id.into(),
+                        None,
+                    )
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut created = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => created += 1,
+            Err(err) => {
+                assert!(err.matches(trc::EventType::Limit(trc::LimitEvent::TenantQuota)))
+            }
+        }
+    }
+    let total = store
+        .count_principals(None, Type::Individual.into(), acme_id.into())
+        .await
+        .unwrap();
+    assert!(created <= 1, "{created} principals created");
+    assert_eq!(total, 1 + created);
+}
+
+#[tokio::test]
+async fn external_id() {
+    let store = in_memory_store();
+    let external_id = "0f8fad5b-d9cb-469f-a165-70867728950e";
+    let jane_id = store
+        .create_principal(
+            individual("jane").with_field(PrincipalField::ExternalId, external_id),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.external_id_to_id(external_id).await.unwrap(),
+        Some(jane_id)
+    );
+
+    // The external id survives renames
+    store
+        .update_principal(
+            UpdatePrincipal::by_external_id(external_id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::Name,
+                PrincipalValue::String("jane.doe".to_string()),
+            )]),
+        )
+        .await
+        .unwrap();
+    let principal = store
+        .query(QueryBy::ExternalId(external_id), false)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(principal.id(), jane_id);
+    assert_eq!(principal.name(), "jane.doe");
+
+    // External ids are unique
+    assert!(store
+        .create_principal(
+            individual("john").with_field(PrincipalField::ExternalId, external_id),
+            None,
+            None,
+        )
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Manage(trc::ManageEvent::AlreadyExists)));
+    let result = store
+        .create_principals(
+            vec![
+                individual("bill").with_field(PrincipalField::ExternalId, "abc"),
+                individual("mike").with_field(PrincipalField::ExternalId, "abc"),
+            ],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(result.ids.is_empty());
+    assert_eq!(result.errors.len(), 1);
+    assert!(result.errors[0]
+        .1
+        .matches(trc::EventType::Manage(trc::ManageEvent::AlreadyExists)));
+    let john_id = store
+        .create_principal(individual("john"), None, None)
+        .await
+        .unwrap();
+    assert!(store
+        .update_principal(
+            UpdatePrincipal::by_id(john_id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::ExternalId,
+                PrincipalValue::String(external_id.to_string()),
+            )])
+        )
+        .await
+        .unwrap_err()
+        .matches(trc::EventType::Manage(trc::ManageEvent::AlreadyExists)));
+
+    // Setting an empty value clears the mapping
+    store
+        .update_principal(
+            UpdatePrincipal::by_id(jane_id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::ExternalId,
+                PrincipalValue::String(String::new()),
+            )]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.external_id_to_id(external_id).await.unwrap(), None);
+    assert!(store
+        .get_principal(jane_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .get_str(PrincipalField::ExternalId)
+        .is_none());
+
+    // Deleting a principal releases its external id
+    store
+        .update_principal(
+            UpdatePrincipal::by_id(john_id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::ExternalId,
+                PrincipalValue::String(external_id.to_string()),
+            )]),
+        )
+        .await
+        .unwrap();
+    store
+        .delete_principal(QueryBy::ExternalId(external_id))
+        .await
+        .unwrap();
+    assert_eq!(store.external_id_to_id(external_id).await.unwrap(), None);
+    assert_eq!(store.get_principal_id("john").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn last_login() {
+    let store = in_memory_store();
+    let ids = create(
+        &store,
+        [
+            individual("jane"),
+            individual("john"),
+            individual("bill"),
+            domain("example.org"),
+        ],
+    )
+    .await;
+    store.set_last_login(ids[0], 1_000).await.unwrap();
+    store.set_last_login(ids[1], 5_000).await.unwrap();
+    assert_eq!(store.get_last_login(ids[0]).await.unwrap(), Some(1_000));
+    assert_eq!(store.get_last_login(ids[2]).await.unwrap(), None);
+
+    // Accounts that never logged in are considered inactive
+    let filter = PrincipalFilter::default().with_last_login_before(2_000);
+    let mut names = store
+        .list_principals(Some(&filter), None, &[], &[], 0, 0)
+        .await
+        .unwrap()
+        .items
+        .into_iter()
+        .map(|p| {
+            assert_ne!(p.get_int(PrincipalField::LastLogin), Some(5_000));
+            p.name().to_string()
+        })
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(names, ["bill", "jane"]);
+    let page = store
+        .list_principals_after(Some(&filter), None, &[], &[PrincipalField::Name], None, 0)
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 2);
+
+    // The login time is read-only
+    assert!(store
+        .update_principal(
+            UpdatePrincipal::by_id(ids[0]).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::LastLogin,
+                PrincipalValue::Integer(0),
+            )]),
+        )
+        .await
+        .is_err());
+    let mut principal = store
+        .query(QueryBy::Id(ids[0]), false)
+        .await
+        .unwrap()
+        .unwrap();
+    store.map_field_ids(&mut principal, &[]).await.unwrap();
+    assert_eq!(principal.get_int(PrincipalField::LastLogin), Some(1_000));
+
+    // Deleting a principal removes its login time
+    store.delete_principal(QueryBy::Id(ids[0])).await.unwrap();
+    assert_eq!(store.get_last_login(ids[0]).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn verify_directory() {
+    let store = in_memory_store();
+    let ids = create(
+        &store,
+        [
+            domain("example.org"),
+            group("sales"),
+            individual("jane")
+                .with_email("jane@example.org")
+                .with_member_of("sales"),
+            individual("john"),
+        ],
+    )
+    .await;
+    let (sales_id, jane_id, john_id) = (ids[1], ids[2], ids[3]);
+    let report = store.verify_directory(false).await.unwrap();
+    assert_eq!(report.principals, 4);
+    assert!(report.issues.is_empty(), "{:?}", report.issues);
+
+    // Simulate an interrupted deletion and a lost mirror edge
+    let mut batch = BatchBuilder::new();
+    batch
+        .set(
+            ValueClass::Directory(DirectoryClass::EmailToId(b"ghost@example.org".to_vec())),
+            PrincipalInfo::new(u32::MAX - 1, Type::Individual, None).serialize(),
+        )
+        .clear(ValueClass::Directory(DirectoryClass::Members {
+            principal_id: MaybeDynamicId::Static(sales_id),
+            has_member: MaybeDynamicId::Static(jane_id),
+        }))
+        .clear(ValueClass::Directory(DirectoryClass::NameToId(
+            b"john".to_vec(),
+        )));
+    store.write(batch.build()).await.unwrap();
+    assert!(store.get_members(sales_id).await.unwrap().is_empty());
+
+    let mut kinds = store
+        .verify_directory(false)
+        .await
+        .unwrap()
+        .issues
+        .into_iter()
+        .map(|issue| {
+            assert!(!issue.repaired);
+            issue.kind
+        })
+        .collect::<Vec<_>>();
+    kinds.sort_unstable_by_key(|kind| kind.as_str());
+    assert_eq!(
+        kinds,
+        [
+            DirectoryIssueKind::MissingMembers,
+            DirectoryIssueKind::MissingName,
+            DirectoryIssueKind::OrphanedEmail,
+        ]
+    );
+
+    // Repairing removes orphans and restores the missing keys
+    let report = store.verify_directory(true).await.unwrap();
+    assert_eq!(report.repaired, 3);
+    assert!(store
+        .verify_directory(false)
+        .await
+        .unwrap()
+        .issues
+        .is_empty());
+    assert_eq!(store.get_members(sales_id).await.unwrap(), vec![jane_id]);
+    assert_eq!(store.get_principal_id("john").await.unwrap(), Some(john_id));
+    assert_eq!(
+        store
+            .get_value::<PrincipalInfo>(ValueKey::from(ValueClass::Directory(
+                DirectoryClass::EmailToId(b"ghost@example.org".to_vec())
+            )))
+            .await
+            .unwrap()
+            .map(|info| info.id),
+        None
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn get_or_create_contention() {
+    let store = in_memory_store();
+
+    // Concurrent first-time logins for the same group
+    let mut tasks = Vec::new();
+    for _ in 0..50 {
+        let store = store.clone();
+        tasks.push(tokio::spawn(async move {
+            store
+                .get_or_create_principal_id("Engineering", Type::Group, CreateRetry::default())
+                .await
+        }));
+    }
+    let mut ids = AHashSet::new();
+    for task in tasks {
+        ids.insert(task.await.unwrap().unwrap());
+    }
+    assert_eq!(ids.len(), 1);
+    assert_eq!(
+        store
+            .list_principals(None, None, &[Type::Group], &[], 0, 0)
+            .await
+            .unwrap()
+            .total,
+        1
+    );
+    assert_eq!(
+        store.get_principal_id("engineering").await.unwrap(),
+        ids.into_iter().next()
+    );
+
+    // Backoff grows exponentially up to the cap
+    let retry = CreateRetry {
+        max_retries: 3,
+        backoff_min: Duration::from_millis(10),
+        backoff_max: Duration::from_millis(50),
+    };
+    for (try_count, max) in [(0, 10), (1, 20), (2, 40), (3, 50), (40, 50)] {
+        let backoff = retry.backoff(try_count);
+        assert!(
+            backoff >= Duration::from_millis(max / 2) && backoff <= Duration::from_millis(max),
+            "{try_count}: {backoff:?}"
+        );
+    }
+
+    let mut config = Config::new(
+        r#"
+[directory.create]
+max-retries = 10
+backoff.min = "1ms"
+backoff.max = "2s"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        CreateRetry::parse(&mut config),
+        CreateRetry {
+            max_retries: 10,
+            backoff_min: Duration::from_millis(1),
+            backoff_max: Duration::from_secs(2),
+        }
+    );
+    let mut config = Config::new(
+        r#"
+[directory.create]
+backoff.min = "1s"
+backoff.max = "1ms"
+"#,
+    )
+    .unwrap();
+    assert_eq!(CreateRetry::parse(&mut config), CreateRetry::default());
+    assert!(config.errors.contains_key("directory.create.backoff.min"));
+}
+
+#[tokio::test]
+async fn tenant_default_roles() {
+    let store = in_memory_store();
+    create(
+        &store,
+        [
+            tenant("acme")
+                .with_field(PrincipalField::DefaultRoles, vec!["User".to_string()])
+                .with_field(
+                    PrincipalField::DefaultPermissions,
+                    vec!["email-send".to_string()],
+                ),
+            domain("acme.org").with_tenant("acme"),
+        ],
+    )
+    .await;
+    let acme_id = store.get_principal_id("acme").await.unwrap().unwrap();
+    let roles = |id: u32| {
+        let store = store.clone();
+        async move {
+            let mut principal = store.query(QueryBy::Id(id), true).await.unwrap().unwrap();
+            store.map_field_ids(&mut principal, &[]).await.unwrap();
+            (
+                principal
+                    .get_str_array(PrincipalField::Roles)
+                    .unwrap_or_default()
+                    .to_vec(),
+                principal
+                    .get_str_array(PrincipalField::EnabledPermissions)
+                    .unwrap_or_default()
+                    .to_vec(),
+            )
+        }
+    };
+
+    // Principals created without roles receive the tenant defaults
+    let jane_id = store
+        .create_principal(individual("jane@acme.org"), acme_id.into(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        roles(jane_id).await,
+        (vec!["user".to_string()], vec!["email-send".to_string()])
+    );
+
+    // Explicit roles take precedence over the defaults
+    let john_id = store
+        .create_principal(
+            individual("john@acme.org").with_field(PrincipalField::Roles, "tenant-admin"),
+            acme_id.into(),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        roles(john_id).await,
+        (vec!["tenant-admin".to_string()], vec![])
+    );
+
+    // Changing the defaults does not modify existing principals
+    store
+        .update_principal(
+            UpdatePrincipal::by_id(acme_id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::DefaultRoles,
+                PrincipalValue::StringList(vec!["tenant-admin".to_string()]),
+            )]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        roles(jane_id).await,
+        (vec!["user".to_string()], vec!["email-send".to_string()])
+    );
+    let bill_id = store
+        .create_principal(individual("bill@acme.org"), acme_id.into(), None)
+        .await
+        .unwrap();
+    assert_eq!(
+        roles(bill_id).await,
+        (
+            vec!["tenant-admin".to_string()],
+            vec!["email-send".to_string()]
+        )
+    );
+
+    // Defaults must be valid and can only be set on tenants
+    for (name, field, value) in [
+        ("acme", PrincipalField::DefaultRoles, "unknown-role"),
+        (
+            "acme",
+            PrincipalField::DefaultPermissions,
+            "not-a-permission",
+        ),
+        ("acme.org", PrincipalField::DefaultRoles, "user"),
+    ] {
+        assert!(
+            store
+                .update_principal(UpdatePrincipal::by_name(name).with_updates(vec![
+                    PrincipalUpdate::add_item(field, PrincipalValue::String(value.to_string()))
+                ]))
+                .await
+                .is_err(),
+            "{name} {field:?} {value}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn client_certificate() {
+    let store = in_memory_store();
+    create(&store, [domain("example.org")]).await;
+    let fingerprint = ["AB"; 32].join(":");
+    let jane_id = store
+        .create_principal(
+            individual("jane")
+                .with_email("jane@example.org")
+                .with_field(PrincipalField::CertificateFingerprints, "ab".repeat(32)),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let john_id = store
+        .create_principal(
+            individual("john").with_email("john@example.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let certificate = |emails: &[&str], fingerprint: &str| ClientCertificate {
+        subject: None,
+        emails: emails.iter().map(|email| email.to_string()).collect(),
+        fingerprint: fingerprint.to_string(),
+    };
+
+    // Certificates are matched by fingerprint first, then by e-mail address
+    for (cert, expected_id) in [
+        (
+            certificate(&["john@example.org"], &fingerprint),
+            Some(jane_id),
+        ),
+        (certificate(&["john@example.org"], "00"), Some(john_id)),
+        (
+            certificate(&["unknown@example.org", "jane@example.org"], "00"),
+            Some(jane_id),
+        ),
+        (certificate(&["unknown@example.org"], "00"), None),
+    ] {
+        assert_eq!(
+            store
+                .query(QueryBy::Certificate(&cert), false)
+                .await
+                .unwrap()
+                .map(|principal| principal.id()),
+            expected_id,
+            "{cert:?}"
+        );
+    }
+
+    // Fingerprints are normalized and unique
+    assert_eq!(
+        store
+            .get_principal(jane_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .get_str_array(PrincipalField::CertificateFingerprints),
+        Some(&[fingerprint.clone()][..])
+    );
+    for value in [fingerprint.to_lowercase(), "not-a-fingerprint".to_string()] {
+        assert!(store
+            .update_principal(UpdatePrincipal::by_id(john_id).with_updates(vec![
+                PrincipalUpdate::add_item(
+                    PrincipalField::CertificateFingerprints,
+                    PrincipalValue::String(value.clone()),
+                )
+            ]))
+            .await
+            .is_err());
+    }
+
+    // Removed fingerprints no longer match
+    store
+        .update_principal(UpdatePrincipal::by_id(jane_id).with_updates(vec![
+            PrincipalUpdate::remove_item(
+                PrincipalField::CertificateFingerprints,
+                PrincipalValue::String(fingerprint.clone()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(
+        store
+            .certificate_to_id(&certificate(&[], &fingerprint))
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn memberships() {
+    let store = in_memory_store();
+    create(
+        &store,
+        [
+            Principal::new(u32::MAX, Type::Role).with_field(PrincipalField::Name, "auditors"),
+            group("staff").with_field(PrincipalField::Description, "All staff"),
+            group("sales").with_member_of("staff"),
+            group("engineering"),
+            Principal::new(u32::MAX, Type::List).with_field(PrincipalField::Name, "announce"),
+        ],
+    )
+    .await;
+    let ids = create(
+        &store,
+        [
+            individual("jane")
+                .with_member_of("sales")
+                .with_field(PrincipalField::Lists, "announce")
+                .with_field(PrincipalField::Roles, "auditors"),
+            individual("john").with_member_of("engineering"),
+        ],
+    )
+    .await;
+    let memberships = |include_roles: bool| {
+        let store = store.clone();
+        let jane_id = ids[0];
+        async move {
+            let mut memberships = store
+                .get_memberships(jane_id, 10, include_roles)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.name, m.typ, m.description, m.depth))
+                .collect::<Vec<_>>();
+            memberships.sort_by(|a, b| a.0.cmp(&b.0));
+            memberships
+        }
+    };
+
+    // Indirect memberships are listed, groups the principal is not part of are not
+    assert_eq!(
+        memberships(false).await,
+        vec![
+            ("announce".to_string(), Type::List, None, 1),
+            ("sales".to_string(), Type::Group, None, 1),
+            (
+                "staff".to_string(),
+                Type::Group,
+                Some("All staff".to_string()),
+                2
+            ),
+        ]
+    );
+
+    // Roles are only included on request
+    assert!(memberships(true)
+        .await
+        .contains(&("auditors".to_string(), Type::Role, None, 1)));
+
+    // Deleted groups are not listed
+    store
+        .delete_principal(QueryBy::Name("staff"))
+        .await
+        .unwrap();
+    assert_eq!(
+        memberships(false)
+            .await
+            .into_iter()
+            .map(|(name, ..)| name)
+            .collect::<Vec<_>>(),
+        vec!["announce".to_string(), "sales".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn import_accounts() {
+    let store = in_memory_store();
+    create(
+        &store,
+        [
+            domain("example.org"),
+            individual("john").with_email("john@example.org"),
+        ],
+    )
+    .await;
+    let account = |email: &str| ImportAccount {
+        email: email.to_string(),
+        password_hash: Some("$6$salt$hash".to_string()),
+        quota: Some(1024),
+        display_name: Some("Imported".to_string()),
+    };
+    let accounts = [
+        "jane@example.org",
+        "JOHN@example.org",
+        "bill@unknown.org",
+        "not-an-address",
+        "mike@example.org",
+        "jane@example.org",
+    ]
+    .into_iter()
+    .enumerate()
+    .map(|(row, email)| (row + 1, account(email)))
+    .collect::<Vec<_>>();
+
+    // Existing addresses are skipped, unknown domains and invalid rows fail
+    let results = store
+        .import_accounts(accounts.clone(), None, None, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|r| (r.row, r.status, r.code.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, ImportStatus::Created, None),
+            (2, ImportStatus::Skipped, Some("principal.alreadyExists")),
+            (3, ImportStatus::Error, Some("domain.notFound")),
+            (4, ImportStatus::Error, Some("address.invalid")),
+            (5, ImportStatus::Created, None),
+            (6, ImportStatus::Skipped, Some("principal.alreadyExists")),
+        ]
+    );
+    let jane = store
+        .query(QueryBy::Id(results[0].id.unwrap()), true)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(jane.name(), "jane@example.org");
+    assert_eq!(
+        jane.get_str_array(PrincipalField::Emails),
+        Some(&["jane@example.org".to_string()][..])
+    );
+    assert_eq!(jane.quota(), 1024);
+    assert_eq!(jane.description(), Some("Imported"));
+    assert_eq!(
+        jane.get_str_array(PrincipalField::Secrets),
+        Some(&["$6$salt$hash".to_string()][..])
+    );
+
+    // Importing again skips the created accounts, missing domains are
+    // created when requested
+    let results = store
+        .import_accounts(accounts, None, None, true)
+        .await
+        .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.status).collect::<Vec<_>>(),
+        vec![
+            ImportStatus::Skipped,
+            ImportStatus::Skipped,
+            ImportStatus::Created,
+            ImportStatus::Error,
+            ImportStatus::Skipped,
+            ImportStatus::Skipped,
+        ]
+    );
+    assert_eq!(
+        store
+            .get_principal_info("unknown.org")
+            .await
+            .unwrap()
+            .map(|p| p.typ),
+        Some(Type::Domain)
+    );
+    assert_eq!(
+        store.email_to_id("bill@unknown.org").await.unwrap(),
+        results[2].id
+    );
+}
+
+#[tokio::test]
+async fn count_principals_by_type() {
+    let store = in_memory_store();
+    let Store::InMemory(memory) = &store else {
+        unreachable!()
+    };
+    create(
+        &store,
+        [tenant("acme"), domain("acme.org").with_tenant("acme")],
+    )
+    .await;
+    let acme_id = store.get_principal_id("acme").await.unwrap().unwrap();
+
+    // Create a few thousand principals of different types
+    let mut principals = Vec::new();
+    for num in 0..2000 {
+        principals.push(individual(&format!("user{num}")));
+    }
+    for num in 0..500 {
+        principals.push(group(&format!("group{num}")));
+    }
+    for num in 0..300 {
+        principals.push(
+            Principal::new(u32::MAX, Type::List)
+                .with_field(PrincipalField::Name, format!("list{num}")),
+        );
+    }
+    for num in 0..50 {
+        principals.push(domain(&format!("domain{num}.org")));
+    }
+    create(&store, principals).await;
+    for num in 0..200 {
+        store
+            .create_principal(
+                individual(&format!("user{num}@acme.org")),
+                acme_id.into(),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    for tenant_id in [None, Some(acme_id)] {
+        // All types are counted in a single iteration
+        let iterations = memory.iterations();
+        let counts = store.count_principals_by_type(tenant_id).await.unwrap();
+        assert_eq!(memory.iterations(), iterations + 1);
+
+        // Counts match the ones obtained one type at a time
+        let types = [
+            Type::Individual,
+            Type::Group,
+            Type::List,
+            Type::Domain,
+            Type::Tenant,
+            Type::Role,
+        ];
+        for typ in types {
+            assert_eq!(
+                counts.get(&typ).copied().unwrap_or_default(),
+                store
+                    .count_principals(None, typ.into(), tenant_id)
+                    .await
+                    .unwrap(),
+                "{typ:?} {tenant_id:?}"
+            );
+        }
+        assert_eq!(memory.iterations(), iterations + 1 + types.len() as u64);
+    }
+    assert_eq!(
+        store
+            .count_principals_by_type(None)
+            .await
+            .unwrap()
+            .get(&Type::Individual)
+            .copied(),
+        Some(2200)
+    );
+}
+
+#[tokio::test]
+async fn principal_info_migration() {
+    let store = in_memory_store();
+    create(
+        &store,
+        [tenant("acme"), domain("acme.org").with_tenant("acme")],
+    )
+    .await;
+    let acme_id = store.get_principal_id("acme").await.unwrap().unwrap();
+    let jane_id = store
+        .create_principal(individual("jane@acme.org"), acme_id.into(), None)
+        .await
+        .unwrap();
+    create(&store, [individual("john")]).await;
+
+    // Rewrite the name mapping using the legacy layout
+    let mut batch = BatchBuilder::new();
+    batch.set(
+        ValueClass::Directory(DirectoryClass::NameToId(b"jane@acme.org".to_vec())),
+        KeySerializer::new(U32_LEN * 2 + 1)
+            .write_leb128(jane_id)
+            .write(Type::Individual as u8)
+            .write_leb128(acme_id)
+            .finalize(),
+    );
+    store.write(batch.build()).await.unwrap();
+
+    // Legacy rows are found both before and after the migration completes
+    for is_finished in [false, true] {
+        assert_eq!(
+            store
+                .is_migration_finished(PRINCIPAL_INFO_MIGRATION)
+                .await
+                .unwrap(),
+            is_finished
+        );
+        assert_eq!(
+            store
+                .list_principals_after(
+                    None,
+                    acme_id.into(),
+                    &[Type::Individual],
+                    &[PrincipalField::Name],
+                    None,
+                    0
+                )
+                .await
+                .unwrap()
+                .items
+                .iter()
+                .map(|p| p.name())
+                .collect::<Vec<_>>(),
+            vec!["jane@acme.org"]
+        );
+        assert_eq!(
+            store
+                .count_principals(None, Type::Individual.into(), acme_id.into())
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            store
+                .count_principals(None, Type::Individual.into(), None)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            store
+                .count_principals_by_type(acme_id.into())
+                .await
+                .unwrap()
+                .get(&Type::Individual)
+                .copied(),
+            Some(1)
+        );
+
+        if !is_finished {
+            let progress = store
+                .run_migration(PRINCIPAL_INFO_MIGRATION, 60, |from| {
+                    let store = store.clone();
+                    async move { store.migrate_principal_info(from, 1).await }
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(progress.migrated, 1);
+        }
+    }
+}
+
+#[tokio::test]
+async fn protect_last_admin() {
+    let store = in_memory_store();
+    create(
+        &store,
+        [
+            individual("root").with_field(PrincipalField::Roles, vec!["admin".to_string()]),
+            individual("jane"),
+        ],
+    )
+    .await;
+    let remove_role = |name: &'static str, role: &str| {
+        UpdatePrincipal::by_name(name).with_updates(vec![PrincipalUpdate::remove_item(
+            PrincipalField::Roles,
+            PrincipalValue::String(role.to_string()),
+        )])
+    };
+
+    // The only administrator cannot demote itself or be deleted
+    assert_eq!(
+        error_code(store.update_principal(remove_role("root", "admin")).await),
+        ErrorCode::PrincipalProtected
+    );
+    assert_eq!(
+        error_code(
+            store
+                .update_principal(UpdatePrincipal::by_name("root").with_updates(vec![
+                    PrincipalUpdate::set(
+                        PrincipalField::Roles,
+                        PrincipalValue::StringList(vec!["user".to_string()]),
+                    )
+                ]))
+                .await
+        ),
+        ErrorCode::PrincipalProtected
+    );
+    assert_eq!(
+        error_code(store.delete_principal(QueryBy::Name("root")).await),
+        ErrorCode::PrincipalProtected
+    );
+    assert_eq!(
+        error_code(
+            store
+                .delete_principal_cascade(QueryBy::Name("root"), None)
+                .await
+                .map(|deleted| deleted.len())
+        ),
+        ErrorCode::PrincipalProtected
+    );
+
+    // Built-in roles cannot be deleted, modified or shadowed
+    for (by, result) in [
+        (
+            "admin",
+            store.delete_principal(QueryBy::Name("admin")).await,
+        ),
+        (
+            "ROLE_ADMIN",
+            store.delete_principal(QueryBy::Id(ROLE_ADMIN)).await,
+        ),
+        (
+            "ROLE_USER",
+            store
+                .update_principal(UpdatePrincipal::by_id(ROLE_USER).with_updates(vec![
+                    PrincipalUpdate::set(
+                        PrincipalField::Name,
+                        PrincipalValue::String("users".to_string()),
+                    ),
+                ]))
+                .await,
+        ),
+    ] {
+        assert_eq!(error_code(result), ErrorCode::PrincipalProtected, "{by}");
+    }
+    assert_eq!(
+        error_code(
+            store
+                .create_principal(
+                    Principal::new(u32::MAX, Type::Role)
+                        .with_field(PrincipalField::Name, "tenant-admin"),
+                    None,
+                    None,
+                )
+                .await
+        ),
+        ErrorCode::PrincipalAlreadyExists
+    );
+
+    // Administrators granted through a custom role count as well
+    create(
+        &store,
+        [Principal::new(u32::MAX, Type::Role)
+            .with_field(PrincipalField::Name, "superusers")
+            .with_field(PrincipalField::Roles, vec!["admin".to_string()])],
+    )
+    .await;
+    store
+        .update_principal(UpdatePrincipal::by_name("jane").with_updates(vec![
+            PrincipalUpdate::add_item(
+                PrincipalField::Roles,
+                PrincipalValue::String("superusers".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    store
+        .update_principal(remove_role("root", "admin"))
+        .await
+        .unwrap();
+    for result in [
+        store
+            .update_principal(remove_role("superusers", "admin"))
+            .await,
+        store
+            .update_principal(remove_role("jane", "superusers"))
+            .await,
+        store.delete_principal(QueryBy::Name("superusers")).await,
+        store.delete_principal(QueryBy::Name("jane")).await,
+    ] {
+        assert_eq!(error_code(result), ErrorCode::PrincipalProtected);
+    }
+
+    // A second administrator makes these operations legal again
+    store
+        .update_principal(UpdatePrincipal::by_name("root").with_updates(vec![
+            PrincipalUpdate::add_item(
+                PrincipalField::Roles,
+                PrincipalValue::String("admin".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    store
+        .update_principal(remove_role("jane", "superusers"))
+        .await
+        .unwrap();
+    store
+        .delete_principal(QueryBy::Name("superusers"))
+        .await
+        .unwrap();
+    store.delete_principal(QueryBy::Name("jane")).await.unwrap();
+    store
+        .create_principal(
+            individual("bill").with_field(PrincipalField::Roles, vec!["admin".to_string()]),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .update_principal(remove_role("root", "admin"))
+        .await
+        .unwrap();
+    store.delete_principal(QueryBy::Name("root")).await.unwrap();
+
+    // Tenants keep at least one administrator while they have accounts
+    create(
+        &store,
+        [tenant("acme"), domain("acme.org").with_tenant("acme")],
+    )
+    .await;
+    let acme_id = store.get_principal_id("acme").await.unwrap().unwrap();
+    for (name, role) in [
+        ("boss@acme.org", "tenant-admin"),
+        ("staff@acme.org", "user"),
+    ] {
+        store
+            .create_principal(
+                individual(name).with_field(PrincipalField::Roles, vec![role.to_string()]),
+                acme_id.into(),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        error_code(
+            store
+                .update_principal(remove_role("boss@acme.org", "tenant-admin"))
+                .await
+        ),
+        ErrorCode::PrincipalProtected
+    );
+    assert_eq!(
+        error_code(store.delete_principal(QueryBy::Name("boss@acme.org")).await),
+        ErrorCode::PrincipalProtected
+    );
+    store
+        .delete_principal(QueryBy::Name("staff@acme.org"))
+        .await
+        .unwrap();
+    store
+        .delete_principal(QueryBy::Name("boss@acme.org"))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn locale_settings() {
+    let store = in_memory_store();
+
+    // Settings are stored in their canonical form
+    let jane_id = store
+        .create_principal(
+            individual("jane")
+                .with_field(PrincipalField::Locale, "fr-CA")
+                .with_field(PrincipalField::Timezone, "America/Toronto"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let jane = store.get_principal(jane_id).await.unwrap().unwrap();
+    assert_eq!(jane.get_str(PrincipalField::Locale), Some("fr"));
+    assert_eq!(
+        jane.get_str(PrincipalField::Timezone),
+        Some("America/Toronto")
+    );
+
+    // Unknown locales and time zones are rejected
+    for (field, value) in [
+        (PrincipalField::Locale, "tlh"),
+        (PrincipalField::Timezone, "Europe/Atlantis"),
+        (PrincipalField::Timezone, "+02:00"),
+    ] {
+        assert_eq!(
+            error_code(
+                store
+                    .update_principal(UpdatePrincipal::by_id(jane_id).with_updates(vec![
+                        PrincipalUpdate::set(field, PrincipalValue::String(value.to_string())),
+                    ]))
+                    .await
+            ),
+            ErrorCode::FieldInvalid,
+            "{field:?} {value:?}"
+        );
+        assert_eq!(
+            error_code(
+                store
+                    .create_principal(individual("john").with_field(field, value), None, None)
+                    .await
+            ),
+            ErrorCode::FieldInvalid,
+            "{field:?} {value:?}"
+        );
+    }
+
+    // Updates replace the settings and empty values restore the defaults
+    store
+        .update_principal(UpdatePrincipal::by_id(jane_id).with_updates(vec![
+            PrincipalUpdate::set(
+                PrincipalField::Locale,
+                PrincipalValue::String("PT_br".to_string()),
+            ),
+            PrincipalUpdate::set(
+                PrincipalField::Timezone,
+                PrincipalValue::String(String::new()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    let jane = store.get_principal(jane_id).await.unwrap().unwrap();
+    assert_eq!(jane.get_str(PrincipalField::Locale), Some("pt"));
+    assert_eq!(jane.get_str(PrincipalField::Timezone), None);
+
+    // Unset values fall back to the server defaults
+    let mut config = Config::new(
+        r#"
+[server]
+locale = "de"
+timezone = "Asia/Tokyo"
+"#,
+    )
+    .unwrap();
+    let defaults = Localization::parse(&mut config);
+    assert!(config.errors.is_empty(), "{:?}", config.errors);
+    let localization = defaults.with_overrides(
+        jane.get_str(PrincipalField::Locale),
+        jane.get_str(PrincipalField::Timezone),
+    );
+    assert_eq!(localization.locale, Locale::Pt);
+    assert_eq!(localization.timezone, defaults.timezone);
+    assert_eq!(localization.format_date(0), "01/01/1970 09:00 JST");
+    assert_eq!(
+        localization.format_rfc822(0),
+        "Thu, 01 Jan 1970 09:00:00 +0900"
+    );
+    let mut config = Config::new(
+        r#"
+[server]
+timezone = "Mars/Olympus_Mons"
+"#,
+    )
+    .unwrap();
+    assert_eq!(Localization::parse(&mut config), Localization::default());
+    assert!(config.errors.contains_key("server.timezone"));
+}
+
+#[tokio::test]
+async fn bulk_principal_lookup() {
+    let store = in_memory_store();
+    create(&store, [domain("example.org")]).await;
+    let mut names = Vec::new();
+    for num in 0..300 {
+        let name = format!("user{num}");
+        store
+            .create_principal(
+                individual(&name).with_email(&format!("{name}@example.org")),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        names.push(name);
+    }
+    let list_id = store
+        .create_principal(
+            Principal::new(u32::MAX, Type::List)
+                .with_field(PrincipalField::Name, "all")
+                .with_email("all@example.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .update_principal(
+            UpdatePrincipal::by_id(list_id).with_updates(vec![PrincipalUpdate::set(
+                PrincipalField::Members,
+                PrincipalValue::StringList(names.clone()),
+            )]),
+        )
+        .await
+        .unwrap();
+
+    // Results keep the order of the input, including misses and duplicates
+    let mut lookups = names
+        .iter()
+        .rev()
+        .map(|name| format!("{name}@example.org"))
+        .collect::<Vec<_>>();
+    lookups.extend(
+        [
+            "missing@example.org",
+            "user7",
+            "all@example.org",
+            "user7@example.org",
+            "all",
+        ]
+        .map(String::from),
+    );
+    let lookups = lookups.iter().map(String::as_str).collect::<Vec<_>>();
+    let infos = store.get_principal_infos(&lookups).await.unwrap();
+    assert_eq!(infos.len(), lookups.len());
+    for (lookup, info) in lookups.iter().zip(infos) {
+        let expected = if lookup.contains('@') {
+            store.email_to_id(lookup).await.unwrap()
+        } else {
+            store.get_principal_id(lookup).await.unwrap()
+        };
+        assert_eq!(info.map(|info| info.id), expected, "{lookup}");
+    }
+    assert_eq!(
+        store
+            .get_principal_infos(&["missing@example.org", "all"])
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|info| info.map(|info| (info.id, info.typ)))
+            .collect::<Vec<_>>(),
+        vec![None, Some((list_id, Type::List))]
+    );
+    assert!(store.get_principal_infos(&[]).await.unwrap().is_empty());
+
+    // List expansion returns the address of every member in order
+    let mut expected = Vec::new();
+    for member_id in store.get_members(list_id).await.unwrap() {
+        expected.extend(
+            store
+                .get_principal(member_id)
+                .await
+                .unwrap()
+                .unwrap()
+                .take_str(PrincipalField::Emails),
+        );
+    }
+    assert_eq!(expected.len(), 300);
+    assert_eq!(store.expn_by_id(list_id).await.unwrap(), expected);
+}
+
+async fn batch_test_principal(store: &Store) -> TestPrincipal {
+    let mut principal = store
+        .query(QueryBy::Name("batch"), true)
+        .await
+        .unwrap()
+        .unwrap();
+    store.map_field_ids(&mut principal, &[]).await.unwrap();
+    principal.into_test()
+}
+
+fn error_code<T: std::fmt::Debug>(result: trc::Result<T>) -> ErrorCode {
+    result
+        .unwrap_err()
+        .value_as_str(trc::Key::Code)
+        .and_then(ErrorCode::parse)
+        .expect("missing error code")
 }
 
 #[allow(async_fn_in_trait)]
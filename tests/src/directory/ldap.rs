@@ -7,8 +7,15 @@
 use std::fmt::Debug;
 
 use directory::{
-    backend::{internal::manage::ManageDirectory, RcptType},
-    QueryBy, Type, ROLE_USER,
+    backend::{
+        internal::{
+            lookup::DirectoryStore,
+            manage::{ManageDirectory, UpdatePrincipal},
+            PrincipalField, PrincipalUpdate, PrincipalValue,
+        },
+        RcptType,
+    },
+    DirectoryInner, QueryBy, Type, ROLE_USER,
 };
 use mail_send::Credentials;
 
@@ -240,6 +247,70 @@ async fn ldap_directory() {
         Vec::<String>::new(),
     );
 
+    // Write-back, Glauth rejects modifications so changes to mapped
+    // fields must fail without reaching the internal store
+    let DirectoryInner::Ldap(ldap) = &handle.store else {
+        panic!("Expected LDAP directory");
+    };
+    assert!(ldap.is_writable());
+    let john_id = base_store.get_principal_id("john").await.unwrap().unwrap();
+    for change in [
+        PrincipalUpdate::set(
+            PrincipalField::Description,
+            PrincipalValue::String("Johnny".to_string()),
+        ),
+        PrincipalUpdate::add_item(
+            PrincipalField::Emails,
+            PrincipalValue::String("johnny@example.org".to_string()),
+        ),
+        PrincipalUpdate::add_item(
+            PrincipalField::Secrets,
+            PrincipalValue::String("abcde".to_string()),
+        ),
+    ] {
+        assert!(ldap
+            .update_principal(
+                john_id,
+                UpdatePrincipal::by_id(john_id).with_updates(vec![
+                    PrincipalUpdate::set(PrincipalField::Quota, PrincipalValue::Integer(1024)),
+                    change,
+                ])
+            )
+            .await
+            .is_err());
+    }
+    let john = base_store
+        .query(QueryBy::Id(john_id), false)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(john.description(), Some("John Doe"));
+    assert_eq!(john.quota(), 0);
+    assert!(!john
+        .get_str_array(PrincipalField::Emails)
+        .unwrap_or_default()
+        .contains(&"johnny@example.org".to_string()));
+
+    // Fields without an LDAP attribute are stored internally
+    ldap.update_principal(
+        john_id,
+        UpdatePrincipal::by_id(john_id).with_updates(vec![PrincipalUpdate::set(
+            PrincipalField::Quota,
+            PrincipalValue::Integer(1024),
+        )]),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        base_store
+            .query(QueryBy::Id(john_id), false)
+            .await
+            .unwrap()
+            .unwrap()
+            .quota(),
+        1024
+    );
+
     // EXPN
     // Now handled by the internal directory
     /*compare_sorted(
@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+pub mod harness;
 pub mod imap;
 pub mod internal;
 pub mod ldap;
@@ -39,6 +40,10 @@ store = "foundationdb"
 type = "sql"
 store = "sqlite"
 
+[directory."sqlite".sync]
+enable = true
+delete-policy = "disable"
+
 [directory."sqlite".columns]
 name = "name"
 description = "description"
@@ -54,6 +59,9 @@ path = "{TMP}/rocksdb"
 [store."foundationdb"]
 type = "foundationdb"
 
+[store."in-memory"]
+type = "in-memory"
+
 [store."sqlite"]
 type = "sqlite"
 path = "{TMP}/auth.db"
@@ -66,6 +74,18 @@ emails = "SELECT address FROM emails WHERE name = ? AND type != 'list' ORDER BY
 verify = "SELECT address FROM emails WHERE address LIKE '%' || ? || '%' AND type = 'primary' ORDER BY address LIMIT 5"
 expand = "SELECT p.address FROM emails AS p JOIN emails AS l ON p.name = l.name WHERE p.type = 'primary' AND l.address = ? AND l.type = 'list' ORDER BY p.address LIMIT 50"
 domains = "SELECT 1 FROM emails WHERE address LIKE '%@' || ? LIMIT 1"
+list = "SELECT name FROM accounts WHERE active = true ORDER BY name"
+insert = "INSERT INTO accounts (name, type, secret, description, quota, active) VALUES (?, ?, ?, ?, ?, true)"
+update-secret = "UPDATE accounts SET secret = ? WHERE name = ?"
+update-description = "UPDATE accounts SET description = ? WHERE name = ?"
+update-quota = "UPDATE accounts SET quota = ? WHERE name = ?"
+delete = "DELETE FROM accounts WHERE name = ?"
+insert-email = "INSERT INTO emails (name, address, type) VALUES (?, ?, 'primary')"
+delete-email = "DELETE FROM emails WHERE name = ? AND address = ?"
+delete-emails = "DELETE FROM emails WHERE name = ?"
+insert-member = "INSERT INTO group_members (name, member_of) VALUES (?, ?)"
+delete-member = "DELETE FROM group_members WHERE name = ? AND member_of = ?"
+delete-members = "DELETE FROM group_members WHERE name = ?"
 
 [storage]
 lookup = "sqlite"
@@ -76,6 +96,10 @@ lookup = "sqlite"
 type = "sql"
 store = "postgresql"
 
+[directory."postgresql".sync]
+enable = true
+delete-policy = "disable"
+
 [directory."postgresql".columns]
 name = "name"
 description = "description"
@@ -100,6 +124,18 @@ emails = "SELECT address FROM emails WHERE name = $1 AND type != 'list' ORDER BY
 verify = "SELECT address FROM emails WHERE address LIKE '%' || $1 || '%' AND type = 'primary' ORDER BY address LIMIT 5"
 expand = "SELECT p.address FROM emails AS p JOIN emails AS l ON p.name = l.name WHERE p.type = 'primary' AND l.address = $1 AND l.type = 'list' ORDER BY p.address LIMIT 50"
 domains = "SELECT 1 FROM emails WHERE address LIKE '%@' || $1 LIMIT 1"
+list = "SELECT name FROM accounts WHERE active = true ORDER BY name"
+insert = "INSERT INTO accounts (name, type, secret, description, quota, active) VALUES ($1, $2, $3, $4, $5, true)"
+update-secret = "UPDATE accounts SET secret = $1 WHERE name = $2"
+update-description = "UPDATE accounts SET description = $1 WHERE name = $2"
+update-quota = "UPDATE accounts SET quota = $1 WHERE name = $2"
+delete = "DELETE FROM accounts WHERE name = $1"
+insert-email = "INSERT INTO emails (name, address, type) VALUES ($1, $2, 'primary')"
+delete-email = "DELETE FROM emails WHERE name = $1 AND address = $2"
+delete-emails = "DELETE FROM emails WHERE name = $1"
+insert-member = "INSERT INTO group_members (name, member_of) VALUES ($1, $2)"
+delete-member = "DELETE FROM group_members WHERE name = $1 AND member_of = $2"
+delete-members = "DELETE FROM group_members WHERE name = $1"
 
 ##############################################################################
 
@@ -107,6 +143,10 @@ domains = "SELECT 1 FROM emails WHERE address LIKE '%@' || $1 LIMIT 1"
 type = "sql"
 store = "mysql"
 
+[directory."mysql".sync]
+enable = true
+delete-policy = "disable"
+
 [directory."mysql".columns]
 name = "name"
 description = "description"
@@ -131,6 +171,18 @@ emails = "SELECT address FROM emails WHERE name = ? AND type != 'list' ORDER BY
 verify = "SELECT address FROM emails WHERE address LIKE CONCAT('%', ?, '%') AND type = 'primary' ORDER BY address LIMIT 5"
 expand = "SELECT p.address FROM emails AS p JOIN emails AS l ON p.name = l.name WHERE p.type = 'primary' AND l.address = ? AND l.type = 'list' ORDER BY p.address LIMIT 50"
 domains = "SELECT 1 FROM emails WHERE address LIKE CONCAT('%@', ?) LIMIT 1"
+list = "SELECT name FROM accounts WHERE active = true ORDER BY name"
+insert = "INSERT INTO accounts (name, type, secret, description, quota, active) VALUES (?, ?, ?, ?, ?, true)"
+update-secret = "UPDATE accounts SET secret = ? WHERE name = ?"
+update-description = "UPDATE accounts SET description = ? WHERE name = ?"
+update-quota = "UPDATE accounts SET quota = ? WHERE name = ?"
+delete = "DELETE FROM accounts WHERE name = ?"
+insert-email = "INSERT INTO emails (name, address, type) VALUES (?, ?, 'primary')"
+delete-email = "DELETE FROM emails WHERE name = ? AND address = ?"
+delete-emails = "DELETE FROM emails WHERE name = ?"
+insert-member = "INSERT INTO group_members (name, member_of) VALUES (?, ?)"
+delete-member = "DELETE FROM group_members WHERE name = ? AND member_of = ?"
+delete-members = "DELETE FROM group_members WHERE name = ?"
 
 ##############################################################################
 
@@ -147,6 +199,13 @@ secret = "mysecret"
 enable = false
 dn = "cn=?,ou=svcaccts,dc=example,dc=org"
 
+[directory."ldap".groups.nested]
+enable = true
+max-depth = 4
+
+[directory."ldap".write]
+enable = true
+
 [directory."ldap".filter]
 name = "(&(|(objectClass=posixAccount)(objectClass=posixGroup))(uid=?))"
 email = "(&(|(objectClass=posixAccount)(objectClass=posixGroup))(|(mail=?)(givenName=?)(sn=?)))"
@@ -304,6 +363,21 @@ fields.email = "email"
 fields.username = "preferred_username"
 fields.full-name = "name"
 
+[directory."oidc-claims"]
+type = "oidc"
+store = "rocksdb"
+timeout = "1s"
+endpoint.url = "https://127.0.0.1:9090/userinfo-claims"
+endpoint.method = "userinfo"
+fields.email = "email"
+fields.username = "preferred_username"
+claims.groups = ["groups", "realm_access.roles"]
+claims.protected = ["legacy"]
+
+[directory."oidc-claims".claims.map]
+sso-admins = "role:admin"
+sales-team = "group:sales"
+
 "#;
 
 pub struct DirectoryStore {
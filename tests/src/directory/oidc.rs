@@ -8,10 +8,17 @@
  *
  */
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use base64::{engine::general_purpose, Engine};
-use directory::{backend::internal::PrincipalField, QueryBy};
+use directory::{
+    backend::internal::{
+        lookup::DirectoryStore,
+        manage::{CreateRetry, ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    QueryBy, Type,
+};
 use hyper::{Method, StatusCode};
 use jmap::api::{http::ToHttpResponse, JsonResponse};
 use mail_send::Credentials;
@@ -23,6 +30,8 @@ use crate::{
     http_server::{spawn_mock_http_server, HttpMessage},
 };
 
+static CLAIMS: Mutex<Option<serde_json::Value>> = Mutex::new(None);
+
 static TEST_TOKEN: &str = "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiaWF0IjoxNTE2MjM5MDIyfQ";
 
 #[tokio::test]
@@ -40,6 +49,16 @@ async fn oidc_directory() {
         .into_http_response();
 
         match (req.method.clone(), req.uri.path().split('/').nth(1)) {
+            (Method::GET, Some("userinfo-claims")) => {
+                let mut response = json!({
+                    "email": "jane@example.org",
+                    "preferred_username": "jane",
+                });
+                if let Some(serde_json::Value::Object(claims)) = CLAIMS.lock().unwrap().clone() {
+                    response.as_object_mut().unwrap().extend(claims);
+                }
+                JsonResponse::new(response).into_http_response()
+            }
             (Method::GET, Some("userinfo")) => match req.headers.get("authorization") {
                 Some(auth) if auth == &format!("Bearer {TEST_TOKEN}") => success_response,
                 Some(_) => StatusCode::UNAUTHORIZED.into_http_response(),
@@ -137,4 +156,79 @@ async fn oidc_directory() {
         );
         assert_eq!(principal.description(), Some("John Doe"));
     }
+
+    // Memberships are synchronized with the group claims
+    println!("Running OIDC claims test...");
+    let directory = config
+        .directories
+        .directories
+        .remove("oidc-claims")
+        .unwrap();
+    let store = config.stores.stores.get("rocksdb").unwrap();
+    for (claims, expected_groups, expected_roles) in [
+        (
+            json!({
+                "groups": ["sales-team", "Engineering"],
+                "realm_access": {"roles": ["sso-admins"]}
+            }),
+            vec!["engineering", "sales"],
+            vec!["admin"],
+        ),
+        (
+            json!({"groups": ["engineering", "legacy"]}),
+            vec!["engineering", "legacy"],
+            vec!["user"],
+        ),
+        (json!({}), vec!["engineering", "legacy"], vec!["user"]),
+        (json!({"groups": []}), vec!["legacy"], vec!["user"]),
+    ] {
+        *CLAIMS.lock().unwrap() = Some(claims);
+        let principal = directory
+            .query(
+                QueryBy::Credentials(&Credentials::OAuthBearer {
+                    token: TEST_TOKEN.to_string(),
+                }),
+                true,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let mut principal = store
+            .query(QueryBy::Id(principal.id()), true)
+            .await
+            .unwrap()
+            .unwrap();
+        store
+            .map_field_ids(
+                &mut principal,
+                &[PrincipalField::MemberOf, PrincipalField::Roles],
+            )
+            .await
+            .unwrap();
+        for (field, expected) in [
+            (PrincipalField::MemberOf, expected_groups),
+            (PrincipalField::Roles, expected_roles),
+        ] {
+            let mut values = principal.take_str_array(field).unwrap_or_default();
+            values.sort_unstable();
+            assert_eq!(values, expected, "{field:?}");
+        }
+
+        // Protected groups are only managed by administrators
+        if store.get_principal_id("legacy").await.unwrap().is_none() {
+            store
+                .get_or_create_principal_id("legacy", Type::Group, CreateRetry::default())
+                .await
+                .unwrap();
+            store
+                .update_principal(UpdatePrincipal::by_name("jane").with_updates(vec![
+                    PrincipalUpdate::add_item(
+                        PrincipalField::MemberOf,
+                        PrincipalValue::String("legacy".to_string()),
+                    ),
+                ]))
+                .await
+                .unwrap();
+        }
+    }
 }
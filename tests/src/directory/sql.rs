@@ -5,8 +5,15 @@
  */
 
 use directory::{
-    backend::{internal::manage::ManageDirectory, RcptType},
-    QueryBy, Type, ROLE_USER,
+    backend::{
+        internal::{
+            manage::{ErrorCode, ManageDirectory},
+            PrincipalField, PrincipalUpdate, PrincipalValue,
+        },
+        RcptType,
+    },
+    core::sync::SyncResult,
+    DirectoryInner, Principal, QueryBy, Type, ROLE_USER,
 };
 use mail_send::Credentials;
 
@@ -348,6 +355,182 @@ async fn sql_directory() {
             core.expn(&handle, "john@example.org", 0).await.unwrap(),
             Vec::<String>::new()
         );*/
+
+        // Synchronize accounts into the internal store
+        store.create_test_user("carol", "secret", "Carol Doe").await;
+        store
+            .link_test_address("carol", "carol@example.org", "primary")
+            .await;
+        let result = handle.synchronize(directory_id).await.unwrap();
+        assert_eq!(result.created, 1);
+        assert_eq!(result.removed, 0);
+        let carol_id = base_store.get_principal_id("carol").await.unwrap().unwrap();
+        let carol = base_store.get_principal(carol_id).await.unwrap().unwrap();
+        assert_eq!(carol.description(), Some("Carol Doe"));
+        assert_eq!(
+            carol.iter_str(PrincipalField::Emails).collect::<Vec<_>>(),
+            ["carol@example.org"]
+        );
+        assert!(!carol.has_field(PrincipalField::Secrets));
+        assert!(carol.is_enabled());
+
+        // Unchanged accounts are not updated again
+        assert_eq!(
+            handle.synchronize(directory_id).await.unwrap(),
+            SyncResult::default()
+        );
+
+        // Vanished accounts are disabled and re-enabled when they return
+        store.set_test_active("carol", false).await;
+        assert_eq!(
+            handle.synchronize(directory_id).await.unwrap(),
+            SyncResult {
+                created: 0,
+                updated: 0,
+                removed: 1
+            }
+        );
+        assert!(!base_store
+            .get_principal(carol_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_enabled());
+        store.set_test_active("carol", true).await;
+        handle.synchronize(directory_id).await.unwrap();
+        assert!(base_store
+            .get_principal(carol_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .is_enabled());
+
+        // Manage principals through the SQL directory
+        let DirectoryInner::Sql(sql) = &handle.store else {
+            unreachable!()
+        };
+        assert!(sql.is_writable());
+        let dave_id = sql
+            .create_principal(
+                Principal::new(u32::MAX, Type::Individual)
+                    .with_field(PrincipalField::Name, "dave")
+                    .with_field(PrincipalField::Secrets, "dave_pass")
+                    .with_field(PrincipalField::Description, "Dave Doe")
+                    .with_field(PrincipalField::Emails, "dave@example.org")
+                    .with_field(PrincipalField::MemberOf, "sales"),
+            )
+            .await
+            .unwrap();
+        let dave = handle
+            .query(
+                QueryBy::Credentials(&Credentials::Plain {
+                    username: "dave".to_string(),
+                    secret: "dave_pass".to_string(),
+                }),
+                true,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dave.id(), dave_id);
+        assert_eq!(dave.description(), Some("Dave Doe"));
+        assert_eq!(
+            dave.iter_str(PrincipalField::Emails).collect::<Vec<_>>(),
+            ["dave@example.org"]
+        );
+        assert_eq!(
+            dave.get_int_array(PrincipalField::MemberOf),
+            Some(&[base_store.get_principal_id("sales").await.unwrap().unwrap() as u64][..])
+        );
+        assert_eq!(
+            handle.email_to_id("dave@example.org").await.unwrap(),
+            Some(dave_id)
+        );
+
+        // Uniqueness violations are reported as the internal directory does
+        let err = sql
+            .create_principal(
+                Principal::new(u32::MAX, Type::Individual).with_field(PrincipalField::Name, "dave"),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.matches(trc::EventType::Manage(trc::ManageEvent::AlreadyExists)));
+        assert_eq!(
+            err.value(trc::Key::Code).and_then(|v| v.as_str()),
+            Some(ErrorCode::PrincipalAlreadyExists.as_str())
+        );
+
+        // Fields without a SQL mapping are refused
+        let err = sql
+            .update_principal(
+                dave_id,
+                vec![PrincipalUpdate::set(
+                    PrincipalField::Picture,
+                    PrincipalValue::String("en".to_string()),
+                )],
+            )
+            .await
+            .unwrap_err();
+        assert!(err.matches(trc::EventType::Manage(trc::ManageEvent::NotSupported)));
+
+        // Updates are applied in a single transaction
+        sql.update_principal(
+            dave_id,
+            vec![
+                PrincipalUpdate::set(
+                    PrincipalField::Description,
+                    PrincipalValue::String("David Doe".to_string()),
+                ),
+                PrincipalUpdate::remove_item(
+                    PrincipalField::Secrets,
+                    PrincipalValue::String(String::new()),
+                ),
+                PrincipalUpdate::add_item(
+                    PrincipalField::Secrets,
+                    PrincipalValue::String("new_pass".to_string()),
+                ),
+                PrincipalUpdate::add_item(
+                    PrincipalField::Emails,
+                    PrincipalValue::String("david@example.org".to_string()),
+                ),
+                PrincipalUpdate::remove_item(
+                    PrincipalField::MemberOf,
+                    PrincipalValue::String("sales".to_string()),
+                ),
+            ],
+        )
+        .await
+        .unwrap();
+        let dave = handle
+            .query(
+                QueryBy::Credentials(&Credentials::Plain {
+                    username: "dave".to_string(),
+                    secret: "new_pass".to_string(),
+                }),
+                true,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dave.description(), Some("David Doe"));
+        assert_eq!(
+            dave.iter_str(PrincipalField::Emails).collect::<Vec<_>>(),
+            ["dave@example.org", "david@example.org"]
+        );
+        assert!(dave
+            .get_int_array(PrincipalField::MemberOf)
+            .unwrap_or_default()
+            .is_empty());
+
+        // Deleting removes the account from both stores
+        sql.delete_principal(dave_id).await.unwrap();
+        assert!(handle
+            .query(QueryBy::Name("dave"), false)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(handle.email_to_id("dave@example.org").await.unwrap(), None);
+        assert_eq!(base_store.get_principal(dave_id).await.unwrap(), None);
     }
 }
 
@@ -511,6 +694,20 @@ impl DirectoryStore {
             .unwrap();
     }
 
+    pub async fn set_test_active(&self, login: &str, active: bool) {
+        self.store
+            .query::<usize>(
+                if self.is_postgresql() {
+                    "UPDATE accounts SET active = $1 WHERE name = $2"
+                } else {
+                    "UPDATE accounts SET active = ? WHERE name = ?"
+                },
+                vec![active.into(), login.into()],
+            )
+            .await
+            .unwrap();
+    }
+
     pub async fn remove_from_group(&self, login: &str, group: &str) {
         self.store
             .query::<usize>(
@@ -21,6 +21,15 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection, h
         .await
         .assert_response_code("TRYCREATE");
 
+    // Non-synchronizing literals over the LITERAL- limit are rejected
+    imap.send(&format!("APPEND INBOX {{4097+}}\r\n{}", "a".repeat(4097)))
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Bad)
+        .await
+        .assert_response_code("TOOBIG");
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
     // Import test messages
     let mut entries = fs::read_dir(resources_dir())
         .unwrap()
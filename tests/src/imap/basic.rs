@@ -16,7 +16,10 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
 
     // Test CAPABILITY
     imap.send("CAPABILITY").await;
-    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("SASL-IR")
+        .assert_contains("LITERAL-");
 
     // Test NOOP
     imap.send("NOOP").await;
@@ -37,6 +40,30 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
     imap.assert_read(Type::Continuation, ResponseType::Ok).await;
     imap.send_untagged("AGJvYXR5AG1jYm9hdGZhY2U=").await;
     imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Initial responses are accepted without a continuation
+    imap.send("AUTHENTICATE PLAIN AGJvYXR5AG1jYm9hdGZhY2U=")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+    imap.send("AUTHENTICATE PLAIN =").await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+
+    // Exchanges without an initial response can be cancelled
+    for mechanism in ["PLAIN", "OAUTHBEARER"] {
+        imap.send(&format!("AUTHENTICATE {mechanism}")).await;
+        imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+        imap.send_untagged("*").await;
+        imap.assert_read(Type::Tagged, ResponseType::Bad).await;
+    }
+
+    // Oversized non-synchronizing literals are drained and rejected
+    imap.send(&format!("ID ({{5000+}}\r\n{} NIL)", "a".repeat(5000)))
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Bad)
+        .await
+        .assert_response_code("TOOBIG");
+    imap.send("NOOP").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
 }
 
 #[test]
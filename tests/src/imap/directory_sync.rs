@@ -0,0 +1,89 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use directory::backend::internal::manage::ManageDirectory;
+
+use super::IMAPTest;
+
+pub async fn test(handle: &IMAPTest) {
+    println!("Running directory cache synchronization tests...");
+
+    let server = &handle.server;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .get_principal_id("popper@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+    let sync_interval = server.core.jmap.directory_sync_interval + Duration::from_millis(50);
+
+    // Obtain the current position in the change log
+    tokio::time::sleep(sync_interval).await;
+    server.sync_directory_changes().await;
+
+    // Changes published by another node are applied on the next sync
+    let access_token = server.get_cached_access_token(account_id).await.unwrap();
+    server.invalidate_principal(account_id).await.unwrap();
+    assert!(!server.inner.data.access_tokens.contains_key(&account_id));
+    server.cache_access_token(access_token.clone());
+    tokio::time::sleep(sync_interval).await;
+    server.sync_directory_changes().await;
+    assert!(!server.inner.data.access_tokens.contains_key(&account_id));
+
+    // Lookups are rate limited
+    server.cache_access_token(access_token.clone());
+    server.invalidate_principal(account_id).await.unwrap();
+    server.cache_access_token(access_token.clone());
+    server.sync_directory_changes().await;
+    assert!(server.inner.data.access_tokens.contains_key(&account_id));
+    tokio::time::sleep(sync_interval).await;
+    server.sync_directory_changes().await;
+    assert!(!server.inner.data.access_tokens.contains_key(&account_id));
+
+    // Nodes that missed a change clear their caches
+    server
+        .core
+        .storage
+        .lookup
+        .counter_incr(b"dir:seq".to_vec(), 1, None, false)
+        .await
+        .unwrap();
+    server.cache_access_token(access_token.clone());
+    tokio::time::sleep(sync_interval).await;
+    server.sync_directory_changes().await;
+    assert!(server.inner.data.access_tokens.is_empty());
+
+    // Evictions are queued for gossip once the node joins a cluster
+    server.invalidate_principal(account_id).await.unwrap();
+    assert_eq!(
+        server.inner.data.directory_sync.lock().take_evictions(),
+        None
+    );
+    server.inner.data.directory_sync.lock().enable_gossip();
+    for _ in 0..2 {
+        server.invalidate_principal(account_id).await.unwrap();
+    }
+    assert_eq!(
+        server.inner.data.directory_sync.lock().take_evictions(),
+        Some(vec![account_id])
+    );
+    assert_eq!(
+        server.inner.data.directory_sync.lock().take_evictions(),
+        None
+    );
+
+    // Gossiped evictions are applied right away
+    server.cache_access_token(access_token.clone());
+    server.apply_evictions(&[account_id]);
+    assert!(!server.inner.data.access_tokens.contains_key(&account_id));
+    server.cache_access_token(access_token);
+    server.apply_evictions(&[]);
+    assert!(server.inner.data.access_tokens.is_empty());
+}
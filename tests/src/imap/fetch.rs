@@ -85,6 +85,39 @@ pub async fn test(imap: &mut ImapConnection, _imap_check: &mut ImapConnection) {
         .assert_contains("ℌ𝔢𝔩𝔭 𝔪𝔢 𝔢𝔵𝔭𝔬𝔯𝔱 𝔪𝔶 𝔟𝔬𝔬𝔨")
         .assert_contains("Vandelay");
 
+    // Full body fetches are streamed from the blob store
+    imap.send("UID FETCH 10 (RFC822.SIZE RFC822)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("RFC822.SIZE 1457")
+        .assert_contains("RFC822 {1457}")
+        .assert_contains("From: Art Vandelay")
+        .assert_contains("--festivus--");
+    imap.send("UID FETCH 10 (UID BODY[]<0.10>)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("* 10 FETCH (UID 10 BODY[]<0> {10}")
+        .assert_contains("From: Art ");
+
+    // Partial fetches are clamped to the end of the message
+    imap.send("UID FETCH 10 (BODY[]<1450.100>)").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("BODY[]<1450> {7}")
+        .assert_contains("ivus--");
+    imap.send("UID FETCH 10 (BODY[]<5000.10> RFC822.SIZE)")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("BODY[]<5000> {0}")
+        .assert_contains("RFC822.SIZE 1457)");
+    imap.send("UID FETCH 10 (BODY[]<5000.10> BODY[1.TEXT])")
+        .await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok)
+        .await
+        .assert_contains("BODY[]<5000> {0}")
+        .assert_contains("BODY[1.TEXT] {239}");
+
     // We are in EXAMINE mode, fetching body should not set \Seen
     imap.send("UID FETCH 10 (FLAGS)").await;
     imap.assert_read(Type::Tagged, ResponseType::Ok)
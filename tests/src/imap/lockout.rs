@@ -0,0 +1,90 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::backend::internal::manage::ManageDirectory;
+use imap_proto::ResponseType;
+
+use super::{AssertResult, IMAPTest, ImapConnection, Type};
+
+pub async fn test(handle: &IMAPTest) {
+    println!("Running account lockout tests...");
+
+    let server = &handle.server;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .get_principal_id("foobar@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+    let mut imap = ImapConnection::connect(b"_l ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+
+    // A successful login resets the failed login counter
+    for _ in 0..2 {
+        imap.send("LOGIN foobar@example.com wrong").await;
+        imap.assert_read(Type::Tagged, ResponseType::No).await;
+    }
+    assert_eq!(
+        server
+            .account_lockout_status(account_id)
+            .await
+            .unwrap()
+            .failures,
+        2
+    );
+    imap.send("LOGIN foobar@example.com secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UNAUTHENTICATE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    assert_eq!(
+        server
+            .account_lockout_status(account_id)
+            .await
+            .unwrap()
+            .failures,
+        0
+    );
+
+    // The account is locked once the threshold is reached,
+    // even valid credentials are rejected
+    for _ in 0..3 {
+        imap.send("LOGIN foobar@example.com wrong").await;
+        imap.assert_read(Type::Tagged, ResponseType::No).await;
+    }
+    assert!(server
+        .account_lockout_status(account_id)
+        .await
+        .unwrap()
+        .locked_until
+        .is_some());
+    imap.send("LOGIN foobar@example.com secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("UNAVAILABLE");
+
+    // Administrators can clear the lock
+    server.clear_account_lockout(account_id).await.unwrap();
+    assert_eq!(
+        server.account_lockout_status(account_id).await.unwrap(),
+        Default::default()
+    );
+    imap.send("LOGIN foobar@example.com secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("UNAUTHENTICATE").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Principals with exempt roles are never locked
+    for _ in 0..3 {
+        imap.send("LOGIN admin wrong").await;
+        imap.assert_read(Type::Tagged, ResponseType::No).await;
+    }
+    imap.send("LOGIN admin secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("LOGOUT").await;
+    imap.assert_read(Type::Untagged, ResponseType::Bye).await;
+}
@@ -10,12 +10,16 @@ pub mod basic;
 pub mod body_structure;
 pub mod condstore;
 pub mod copy_move;
+pub mod directory_sync;
 pub mod fetch;
 pub mod idle;
+pub mod lockout;
 pub mod mailbox;
 pub mod managesieve;
 pub mod pop;
+pub mod revocation;
 pub mod search;
+pub mod sessions;
 pub mod store;
 pub mod thread;
 
@@ -98,6 +102,20 @@ certificate = "default"
 [session.ehlo]
 reject-non-fqdn = false
 
+[session-limit]
+credentials-check = "500ms"
+
+[session-limit.account."popper@example.com"]
+imap = 1
+
+[cache.directory]
+sync-interval = "100ms"
+
+[authentication.lockout]
+threshold = 3
+duration = "1h"
+exempt-roles = ["admin"]
+
 [session.rcpt]
 relay = [ { if = "!is_empty(authenticated_as)", then = true }, 
           { else = false } ]
@@ -472,6 +490,10 @@ pub async fn imap_tests() {
     idle::test(&mut imap, &mut imap_check).await;
     condstore::test(&mut imap, &mut imap_check).await;
     acl::test(&mut imap, &mut imap_check).await;
+    sessions::test(&handle).await;
+    lockout::test(&handle).await;
+    directory_sync::test(&handle).await;
+    revocation::test(&handle).await;
 
     // Logout
     for imap in [&mut imap, &mut imap_check] {
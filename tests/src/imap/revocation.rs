@@ -0,0 +1,119 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::auth::oauth::GrantType;
+use directory::backend::internal::{
+    manage::{ManageDirectory, UpdatePrincipal},
+    PrincipalField, PrincipalUpdate, PrincipalValue,
+};
+use imap_proto::ResponseType;
+
+use super::{AssertResult, IMAPTest, ImapConnection, Type};
+
+pub async fn test(handle: &IMAPTest) {
+    println!("Running session revocation tests...");
+
+    let server = &handle.server;
+    let store = &server.core.storage.data;
+    let account_id = store
+        .get_principal_id("foobar@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Open an IDLE session
+    let mut imap = ImapConnection::connect(b"_r ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send("LOGIN foobar@example.com secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("SELECT INBOX").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap.send("IDLE").await;
+    imap.assert_read(Type::Continuation, ResponseType::Ok).await;
+
+    // Adding a secret keeps the session open
+    let token = server
+        .encode_access_token(GrantType::AccessToken, account_id, "test", 3600)
+        .await
+        .unwrap();
+    store
+        .update_principal(UpdatePrincipal::by_id(account_id).with_updates(vec![
+            PrincipalUpdate::add_item(
+                PrincipalField::Secrets,
+                PrincipalValue::String("other-secret".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    server.invalidate_principal(account_id).await.unwrap();
+    assert!(server
+        .validate_access_token(GrantType::AccessToken.into(), &token)
+        .await
+        .is_ok());
+
+    // Changing the password disconnects the session within the check interval
+    store
+        .update_principal(UpdatePrincipal::by_id(account_id).with_updates(vec![
+            PrincipalUpdate::set(
+                PrincipalField::Secrets,
+                PrincipalValue::String("new-secret".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    server.invalidate_principal(account_id).await.unwrap();
+    imap.assert_read(Type::Untagged, ResponseType::Bye).await;
+    imap.assert_disconnect().await;
+
+    // Tokens issued before the change are rejected
+    assert!(server
+        .validate_access_token(GrantType::AccessToken.into(), &token)
+        .await
+        .is_err());
+    let mut imap = ImapConnection::connect(b"_r ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send("LOGIN foobar@example.com secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::No).await;
+    imap.send("LOGIN foobar@example.com new-secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+
+    // Explicit revocations also disconnect sessions and reject tokens
+    let token = server
+        .encode_access_token(GrantType::RefreshToken, account_id, "test", 3600)
+        .await
+        .unwrap();
+    let epoch = store
+        .get_principal(account_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .credentials_epoch();
+    assert_eq!(
+        store.revoke_credentials(account_id).await.unwrap(),
+        epoch + 1
+    );
+    server.invalidate_principal(account_id).await.unwrap();
+    imap.assert_read(Type::Untagged, ResponseType::Bye)
+        .await
+        .assert_contains("terminated");
+    imap.assert_disconnect().await;
+    assert!(server
+        .validate_access_token(GrantType::RefreshToken.into(), &token)
+        .await
+        .is_err());
+
+    // Restore the original password
+    store
+        .update_principal(UpdatePrincipal::by_id(account_id).with_updates(vec![
+            PrincipalUpdate::set(
+                PrincipalField::Secrets,
+                PrincipalValue::String("secret".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    server.invalidate_principal(account_id).await.unwrap();
+}
@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::config::sessions::SessionProtocol;
+use directory::backend::internal::manage::ManageDirectory;
+use imap_proto::ResponseType;
+
+use super::{AssertResult, IMAPTest, ImapConnection, Type};
+
+pub async fn test(handle: &IMAPTest) {
+    println!("Running session limit tests...");
+
+    let server = &handle.server;
+    let account_id = server
+        .core
+        .storage
+        .data
+        .get_principal_id("popper@example.com")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The first session is accepted
+    let mut imap = ImapConnection::connect(b"_s ").await;
+    imap.assert_read(Type::Untagged, ResponseType::Ok).await;
+    imap.send("LOGIN popper@example.com secret").await;
+    imap.assert_read(Type::Tagged, ResponseType::Ok).await;
+    assert_eq!(
+        server
+            .session_count(account_id, SessionProtocol::Imap)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(server.local_sessions(account_id).len(), 1);
+
+    // Sessions over the limit are rejected with an informative response
+    let mut imap_over = ImapConnection::connect(b"_o ").await;
+    imap_over
+        .assert_read(Type::Untagged, ResponseType::Ok)
+        .await;
+    imap_over.send("LOGIN popper@example.com secret").await;
+    imap_over
+        .assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_response_code("LIMIT")
+        .assert_contains("limit 1");
+    assert_eq!(
+        server
+            .session_count(account_id, SessionProtocol::Imap)
+            .await
+            .unwrap(),
+        1
+    );
+
+    // Force-disconnect the session
+    assert_eq!(server.disconnect_sessions(account_id).await.unwrap(), 1);
+    imap.assert_read(Type::Untagged, ResponseType::Bye)
+        .await
+        .assert_contains("terminated by administrator");
+    imap.assert_disconnect().await;
+
+    // Once the session is released, a new one can be opened
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(server.local_sessions(account_id).is_empty());
+    assert_eq!(
+        server
+            .session_count(account_id, SessionProtocol::Imap)
+            .await
+            .unwrap(),
+        0
+    );
+    imap_over.send("LOGIN popper@example.com secret").await;
+    imap_over.assert_read(Type::Tagged, ResponseType::Ok).await;
+    imap_over.send("LOGOUT").await;
+    imap_over
+        .assert_read(Type::Untagged, ResponseType::Bye)
+        .await;
+}
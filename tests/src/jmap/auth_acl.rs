@@ -4,6 +4,7 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
+use directory::{backend::internal::manage::ManageDirectory, QueryBy, Type};
 use jmap::mailbox::{INBOX_ID, TRASH_ID};
 use jmap_client::{
     core::{
@@ -779,6 +780,104 @@ pub async fn test(params: &mut JMAPTest) {
             .await,
     );
 
+    // Members of nested groups inherit access from the top group
+    let emea_id: Id = server
+        .core
+        .storage
+        .data
+        .create_test_group("emea@example.com", "EMEA", &["emea@example.com"])
+        .await
+        .into();
+    let uk_id: Id = server
+        .core
+        .storage
+        .data
+        .create_test_group("uk@example.com", "UK", &["uk@example.com"])
+        .await
+        .into();
+    for (member, group) in [
+        ("emea@example.com", "sales@example.com"),
+        ("uk@example.com", "emea@example.com"),
+        ("bill@example.com", "uk@example.com"),
+    ] {
+        server.core.storage.data.add_to_group(member, group).await;
+    }
+    let member_of = server
+        .core
+        .storage
+        .data
+        .get_member_of_recursive(bill_id.document_id(), 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|member_of| member_of.typ == Type::Group)
+        .map(|member_of| (member_of.principal_id, member_of.depth))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        member_of,
+        [
+            (uk_id.document_id(), 1),
+            (emea_id.document_id(), 2),
+            (sales_id.document_id(), 3)
+        ]
+    );
+    assert_eq!(
+        server
+            .core
+            .storage
+            .data
+            .get_member_of_recursive(bill_id.document_id(), 2)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|member_of| member_of.typ == Type::Group)
+            .count(),
+        2
+    );
+    server.inner.data.http_auth_cache.clear();
+    server.inner.data.access_tokens.clear();
+    bill_client.refresh_session().await.unwrap();
+    assert!(bill_client
+        .session()
+        .account(&sales_id.to_string())
+        .is_some());
+    assert_eq!(
+        bill_client
+            .set_default_account_id(sales_id.to_string())
+            .email_get(&email_id, [Property::Subject].into(),)
+            .await
+            .unwrap()
+            .unwrap()
+            .subject()
+            .unwrap(),
+        "Created by john in sales"
+    );
+
+    // Breaking the chain revokes access
+    server
+        .core
+        .storage
+        .data
+        .remove_from_group("uk@example.com", "emea@example.com")
+        .await;
+    server.inner.data.http_auth_cache.clear();
+    server.inner.data.access_tokens.clear();
+    assert_forbidden(
+        bill_client
+            .set_default_account_id(sales_id.to_string())
+            .email_get(&email_id, [Property::Subject].into())
+            .await,
+    );
+    for name in ["uk@example.com", "emea@example.com"] {
+        server
+            .core
+            .storage
+            .data
+            .delete_principal(QueryBy::Name(name))
+            .await
+            .unwrap();
+    }
+
     // Destroy test account data
     for id in [john_id, bill_id, jane_id, sales_id] {
         params.client.set_default_account_id(id.to_string());
@@ -11,10 +11,15 @@ use std::{
 };
 
 use common::listener::blocked::BLOCKED_IP_KEY;
+use directory::{
+    backend::internal::{PrincipalField, PrincipalUpdate, PrincipalValue},
+    Principal,
+};
 use imap_proto::ResponseType;
 use jmap_client::{
     client::{Client, Credentials},
     core::set::{SetError, SetErrorType},
+    email::query::Filter,
     mailbox::{self},
 };
 use jmap_proto::types::id::Id;
@@ -22,11 +27,11 @@ use store::write::now;
 
 use crate::{
     directory::internal::TestInternalDirectory,
-    imap::{ImapConnection, Type},
+    imap::{AssertResult, ImapConnection, Type},
     jmap::{assert_is_empty, mailbox::destroy_all_mailboxes},
 };
 
-use super::JMAPTest;
+use super::{delivery::SmtpConnection, JMAPTest, ManagementApi};
 
 pub async fn test(params: &mut JMAPTest) {
     println!("Running Authorization tests...");
@@ -255,6 +260,78 @@ pub async fn test(params: &mut JMAPTest) {
         client.upload(None, b"sleep".to_vec(), None).await,
         Err(jmap_client::Error::Problem(err)) if err.status() == Some(400)));
 
+    // Disabled accounts cannot authenticate but still receive mail
+    let api = ManagementApi::new(8899, "admin", "secret");
+    api.patch::<()>(
+        "/api/principal/jdoe@example.com",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Enabled,
+            PrincipalValue::Integer(0),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    assert!(!api
+        .get::<Principal>("/api/principal/jdoe@example.com")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .is_enabled());
+    assert!(matches!(
+        Client::new()
+            .credentials(Credentials::basic("jdoe@example.com", "12345"))
+            .accept_invalid_certs(true)
+            .connect("https://127.0.0.1:8899")
+            .await,
+        Err(jmap_client::Error::Problem(err)) if err.status() == Some(403)));
+    let mut imap = ImapConnection::connect(b"_z ").await;
+    imap.send("LOGIN jdoe@example.com 12345").await;
+    imap.assert_read(Type::Tagged, ResponseType::No)
+        .await
+        .assert_contains("CONTACTADMIN");
+    SmtpConnection::connect()
+        .await
+        .ingest(
+            "bill@remote.org",
+            &["jdoe@example.com"],
+            concat!(
+                "From: bill@remote.org\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: Still delivered\r\n",
+                "\r\n",
+                "Hello."
+            ),
+        )
+        .await;
+
+    // Re-enable the account
+    api.patch::<()>(
+        "/api/principal/jdoe@example.com",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Enabled,
+            PrincipalValue::Integer(1),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let client = Client::new()
+        .credentials(Credentials::basic("jdoe@example.com", "12345"))
+        .accept_invalid_certs(true)
+        .connect("https://127.0.0.1:8899")
+        .await
+        .unwrap();
+    assert_eq!(
+        client
+            .email_query(None::<Filter>, None::<Vec<_>>)
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        1
+    );
+
     // Destroy test accounts
     params.client.set_default_account_id(&account_id);
     destroy_all_mailboxes(params).await;
@@ -0,0 +1,191 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{manage::PrincipalList, PrincipalField, PrincipalUpdate, PrincipalValue},
+    Principal, Type,
+};
+use serde::Deserialize;
+
+use super::{JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+struct Delegation {
+    domain: String,
+    permissions: Vec<String>,
+}
+
+pub async fn test(_params: &JMAPTest) {
+    println!("Running domain delegation tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create two domains, an account on each one and a helpdesk account
+    for principal in [
+        Principal::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, "marketing.org"),
+        Principal::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, "sales.org"),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "jane@marketing.org")
+            .with_field(
+                PrincipalField::Emails,
+                vec!["jane@marketing.org".to_string()],
+            ),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "bill@sales.org")
+            .with_field(PrincipalField::Emails, vec!["bill@sales.org".to_string()]),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "helpdesk")
+            .with_field(PrincipalField::Roles, vec!["user".to_string()])
+            .with_field(
+                PrincipalField::Secrets,
+                PrincipalValue::String("helpdesk-secret".to_string()),
+            ),
+    ] {
+        api.post::<u32>("/api/principal", &principal)
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    let helpdesk = ManagementApi::new(8899, "helpdesk", "helpdesk-secret");
+
+    // Without a delegation the helpdesk cannot manage accounts
+    assert_eq!(
+        helpdesk
+            .get::<PrincipalList>("/api/principal?types=individual")
+            .await
+            .unwrap()
+            .unwrap_request_error()
+            .status,
+        403
+    );
+
+    // Delegations cannot include permissions unrelated to principals
+    api.put::<()>(
+        "/api/delegation/helpdesk/marketing.org",
+        &["individual-list", "settings-update"],
+    )
+    .await
+    .unwrap()
+    .expect_error("cannot be delegated");
+
+    // Delegate the marketing.org domain
+    api.put::<()>(
+        "/api/delegation/helpdesk/marketing.org",
+        &[
+            "individual-list",
+            "individual-get",
+            "individual-create",
+            "individual-update",
+            "individual-delete",
+        ],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let delegations = api
+        .get::<Vec<Delegation>>("/api/delegation/helpdesk")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(delegations.len(), 1);
+    assert_eq!(delegations[0].domain, "marketing.org");
+    assert_eq!(delegations[0].permissions.len(), 5);
+
+    // Only accounts under the delegated domain are listed
+    let list = helpdesk
+        .get::<PrincipalList>("/api/principal?types=individual")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(list.total, 1);
+    assert_eq!(list.items[0].name(), "jane@marketing.org");
+
+    // Accounts can be managed within the domain only
+    helpdesk
+        .get::<Principal>("/api/principal/jane@marketing.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        helpdesk
+            .get::<Principal>("/api/principal/bill@sales.org")
+            .await
+            .unwrap()
+            .unwrap_request_error()
+            .status,
+        403
+    );
+    helpdesk
+        .post::<u32>(
+            "/api/principal",
+            &Principal::new(u32::MAX, Type::Individual)
+                .with_field(PrincipalField::Name, "john@marketing.org")
+                .with_field(
+                    PrincipalField::Emails,
+                    vec!["john@marketing.org".to_string()],
+                ),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        helpdesk
+            .post::<u32>(
+                "/api/principal",
+                &Principal::new(u32::MAX, Type::Individual)
+                    .with_field(PrincipalField::Name, "john@sales.org")
+                    .with_field(PrincipalField::Emails, vec!["john@sales.org".to_string()]),
+            )
+            .await
+            .unwrap()
+            .unwrap_request_error()
+            .status,
+        403
+    );
+    assert_eq!(
+        helpdesk
+            .patch::<()>(
+                "/api/principal/john@marketing.org",
+                &vec![PrincipalUpdate::add_item(
+                    PrincipalField::Emails,
+                    PrincipalValue::String("john@sales.org".to_string()),
+                )],
+            )
+            .await
+            .unwrap()
+            .unwrap_request_error()
+            .status,
+        403
+    );
+    helpdesk
+        .delete::<()>("/api/principal/john@marketing.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    // Deleting the domain removes the delegation
+    api.delete::<()>("/api/principal/jane@marketing.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.delete::<()>("/api/principal/marketing.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(api
+        .get::<Vec<Delegation>>("/api/delegation/helpdesk")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .is_empty());
+
+    // Clean up
+    for name in ["bill@sales.org", "sales.org", "helpdesk"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
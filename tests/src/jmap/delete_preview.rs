@@ -0,0 +1,100 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::manage::{DeletePreview, ManageDirectory},
+    Type,
+};
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{delivery::SmtpConnection, JMAPTest, ManagementApi};
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running principal deletion preview tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let store = &params.server.core.storage.data;
+
+    store.create_test_domains(&["preview.org"]).await;
+    let account_id = store
+        .create_test_user(
+            "jane@preview.org",
+            "secret",
+            "Jane Preview",
+            &["jane@preview.org"],
+        )
+        .await;
+    store
+        .create_test_group("sales@preview.org", "Sales", &["sales@preview.org"])
+        .await;
+    store
+        .add_to_group("jane@preview.org", "sales@preview.org")
+        .await;
+
+    // Deliver two messages
+    let message = concat!(
+        "From: sender@remote.org\r\n",
+        "To: jane@preview.org\r\n",
+        "Subject: Preview\r\n",
+        "\r\n",
+        "Counting messages."
+    );
+    let mut lmtp = SmtpConnection::connect().await;
+    for _ in 0..2 {
+        lmtp.ingest("sender@remote.org", &["jane@preview.org"], message)
+            .await;
+    }
+
+    // A dry run reports what would be removed
+    let preview = api
+        .delete::<DeletePreview>("/api/principal/jane@preview.org?dry_run=1")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(preview.id, account_id);
+    assert_eq!(preview.name, "jane@preview.org");
+    assert_eq!(preview.typ, Type::Individual);
+    assert_eq!(preview.messages, 2);
+    assert!(preview.bytes >= 2 * message.len() as u64);
+    assert!(preview.blob_links >= 2);
+    assert_eq!(preview.acl_grants, 0);
+    assert_eq!(preview.member_of, vec!["sales@preview.org".to_string()]);
+    assert!(preview.members.is_empty());
+
+    let preview = api
+        .delete::<DeletePreview>("/api/principal/sales@preview.org?dry_run=true")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(preview.members, vec!["jane@preview.org".to_string()]);
+
+    // Nothing was deleted
+    assert_eq!(
+        store.get_principal_id("jane@preview.org").await.unwrap(),
+        Some(account_id)
+    );
+    assert_eq!(
+        api.delete::<DeletePreview>("/api/principal/jane@preview.org?dry_run=1")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .messages,
+        2
+    );
+
+    // Unknown principals are not found
+    api.delete::<DeletePreview>("/api/principal/nobody@preview.org?dry_run=1")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    for name in ["jane@preview.org", "sales@preview.org", "preview.org"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
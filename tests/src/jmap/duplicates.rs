@@ -0,0 +1,158 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use jmap::mailbox::set::MailboxSet;
+use jmap_proto::types::id::Id;
+use serde::Deserialize;
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicateReport {
+    total_messages: u64,
+    messages: Vec<DuplicateGroup>,
+    attachments: Vec<DuplicateGroup>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DuplicateGroup {
+    #[serde(default)]
+    name: Option<String>,
+    count: usize,
+    size: u64,
+    wasted_size: u64,
+    emails: Vec<DuplicateEmail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DuplicateEmail {
+    mailboxes: Vec<String>,
+}
+
+const NEWSLETTER: &str = concat!(
+    "From: news@example.org\r\n",
+    "Subject: Weekly digest\r\n",
+    "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+    "\r\n",
+    "--b\r\n",
+    "Content-Type: text/plain\r\n",
+    "\r\n",
+    "This week's news.\r\n",
+    "--b\r\n",
+    "Content-Type: application/pdf; name=\"digest.pdf\"\r\n",
+    "Content-Disposition: attachment; filename=\"digest.pdf\"\r\n",
+    "\r\n",
+    "%PDF-1.4 digest contents\r\n",
+    "--b--\r\n"
+);
+
+const FORWARD: &str = concat!(
+    "From: jane@example.org\r\n",
+    "Subject: Fwd: Weekly digest\r\n",
+    "Content-Type: multipart/mixed; boundary=\"b\"\r\n",
+    "\r\n",
+    "--b\r\n",
+    "Content-Type: text/plain\r\n",
+    "\r\n",
+    "Have a look.\r\n",
+    "--b\r\n",
+    "Content-Type: application/pdf; name=\"digest.pdf\"\r\n",
+    "Content-Disposition: attachment; filename=\"digest.pdf\"\r\n",
+    "\r\n",
+    "%PDF-1.4 digest contents\r\n",
+    "--b--\r\n"
+);
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running duplicate report tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "dupes@example.com",
+            "secret",
+            "Dupes",
+            &["dupes@example.com"],
+        )
+        .await;
+    let mut mailbox_ids = Vec::new();
+    for path in ["Newsletters", "Archive/2024", "Trash Can"] {
+        let (mailbox_id, _) = server
+            .mailbox_create_path(account_id, path)
+            .await
+            .unwrap()
+            .unwrap();
+        mailbox_ids.push(Id::from(mailbox_id).to_string());
+    }
+
+    // Import the same newsletter into every folder, and a forward of its attachment
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    for (message, mailbox_id) in [
+        (NEWSLETTER, &mailbox_ids[0]),
+        (NEWSLETTER, &mailbox_ids[1]),
+        (NEWSLETTER, &mailbox_ids[2]),
+        (FORWARD, &mailbox_ids[0]),
+        (
+            "From: bill@example.org\r\nSubject: Unique\r\n\r\nHi.\r\n",
+            &mailbox_ids[0],
+        ),
+    ] {
+        params
+            .client
+            .email_import(
+                message.as_bytes().to_vec(),
+                [mailbox_id],
+                None::<Vec<&str>>,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    let report = api
+        .get::<DuplicateReport>("/api/duplicates/dupes@example.com")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(report.total_messages, 5);
+
+    // Identical messages are grouped along with their folders
+    assert_eq!(report.messages.len(), 1, "{report:?}");
+    let group = &report.messages[0];
+    assert_eq!(group.count, 3);
+    assert_eq!(group.size, NEWSLETTER.len() as u64);
+    assert_eq!(group.wasted_size, 2 * NEWSLETTER.len() as u64);
+    let mut folders = group
+        .emails
+        .iter()
+        .flat_map(|email| email.mailboxes.iter().map(|m| m.as_str()))
+        .collect::<Vec<_>>();
+    folders.sort_unstable();
+    assert_eq!(folders, vec!["Archive/2024", "Newsletters", "Trash Can"]);
+
+    // Attachments are grouped across different messages
+    assert_eq!(report.attachments.len(), 1, "{report:?}");
+    let group = &report.attachments[0];
+    assert_eq!(group.name.as_deref(), Some("digest.pdf"));
+    assert_eq!(group.count, 4);
+    assert_eq!(group.wasted_size, 3 * group.size);
+
+    // Unknown principals are not found
+    api.get::<DuplicateReport>("/api/duplicates/nobody@example.com")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+}
@@ -4,15 +4,16 @@
  * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
  */
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use jmap_client::{
     core::set::{SetError, SetErrorType, SetObject},
     email_submission::{query::Filter, Address, Delivered, DeliveryStatus, Displayed, UndoStatus},
     mailbox::Role,
     Error,
 };
-use jmap_proto::types::id::Id;
+use jmap_proto::types::{id::Id, state::State};
 use mail_parser::DateTime;
+use serde::Deserialize;
 use std::{
     sync::Arc,
     time::{Duration, Instant},
@@ -30,7 +31,12 @@ use crate::{
     jmap::{assert_is_empty, email_set::assert_email_properties, mailbox::destroy_all_mailboxes},
 };
 
-use super::JMAPTest;
+use super::{JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+struct CollectedRecipient {
+    address: String,
+}
 
 #[derive(Default, Debug, PartialEq, Eq)]
 pub struct MockMessage {
@@ -222,6 +228,11 @@ pub async fn test(params: &mut JMAPTest) {
     .await;
 
     // Manually add recipients to the envelope and confirm submission
+    let state = client
+        .email_submission_changes(State::Initial.to_string(), 0)
+        .await
+        .unwrap()
+        .take_new_state();
     let email_submission_id = client
         .email_submission_create_envelope(
             &email_id,
@@ -260,30 +271,66 @@ pub async fn test(params: &mut JMAPTest) {
     }
 
     // Confirm that the email submission status was updated
-    tokio::time::sleep(Duration::from_millis(100)).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
     let email_submission = client
         .email_submission_get(&email_submission_id, None)
         .await
         .unwrap()
         .unwrap();
     assert_eq!(email_submission.undo_status().unwrap(), &UndoStatus::Final);
+    let delivered = DeliveryStatus::new(
+        "Code: 250, Enhanced code: 0.0.0, Message: OK",
+        Delivered::Yes,
+        Displayed::Unknown,
+    );
     assert_eq!(
         email_submission.delivery_status().unwrap(),
         &AHashMap::from_iter([
-            (
-                "tim@foobar.com".to_string(),
-                DeliveryStatus::new("250 2.1.5 Queued", Delivered::Unknown, Displayed::Unknown)
-            ),
-            (
-                "secret_rcpt@test.com".to_string(),
-                DeliveryStatus::new("250 2.1.5 Queued", Delivered::Unknown, Displayed::Unknown)
-            ),
-            (
-                "james@other_domain.com".to_string(),
-                DeliveryStatus::new("250 2.1.5 Queued", Delivered::Unknown, Displayed::Unknown)
-            ),
+            ("tim@foobar.com".to_string(), delivered.clone()),
+            ("secret_rcpt@test.com".to_string(), delivered.clone()),
+            ("james@other_domain.com".to_string(), delivered),
         ])
     );
+    let changes = client.email_submission_changes(state, 0).await.unwrap();
+    assert_eq!(changes.created(), [email_submission_id.as_str()]);
+
+    // Final statuses do not generate further changes
+    let state = changes.new_state().to_string();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        client
+            .email_submission_changes(state, 0)
+            .await
+            .unwrap()
+            .total_changes(),
+        0
+    );
+
+    // Accepted recipients are collected for autocomplete
+    let api = ManagementApi::new(8899, "jdoe@example.com", "12345");
+    let collected = collected_recipients(&api, "").await;
+    assert_eq!(
+        collected,
+        [
+            "tim@foobar.com",
+            "secret_rcpt@test.com",
+            "james@other_domain.com",
+            "jane_smith@remote.org"
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect::<AHashSet<_>>()
+    );
+    assert_eq!(
+        collected_recipients(&api, "SMITH").await,
+        AHashSet::from_iter(["jane_smith@remote.org".to_string()])
+    );
+
+    // Removed recipients are not collected again
+    api.delete::<()>("/api/account/recipients/tim@foobar.com")
+        .await
+        .unwrap()
+        .unwrap_data();
 
     // SMTP rejects some of the recipients
     let email_submission_id = client
@@ -356,8 +403,18 @@ pub async fn test(params: &mut JMAPTest) {
             ),
         ])
     );
+    let collected = collected_recipients(&api, "").await;
+    assert!(
+        collected.contains("delay@other_domain.com"),
+        "{collected:?}"
+    );
+    assert!(!collected.contains("tim@foobar.com"), "{collected:?}");
+    assert!(
+        !collected.contains("nonexistant@example.com"),
+        "{collected:?}"
+    );
 
-    // Cancel submission
+    // Cancel submission, the last reported statuses are kept
     client
         .email_submission_change_status(&email_submission_id, UndoStatus::Canceled)
         .await
@@ -384,15 +441,27 @@ pub async fn test(params: &mut JMAPTest) {
             ),
             (
                 "delay@other_domain.com".to_string(),
-                DeliveryStatus::new("250 2.1.5 Queued", Delivered::Unknown, Displayed::Unknown)
+                DeliveryStatus::new(
+                    "Code: 451, Enhanced code: 4.5.3, Message: Try again later.",
+                    Delivered::Queued,
+                    Displayed::Unknown
+                )
             ),
             (
                 "fail@test.com".to_string(),
-                DeliveryStatus::new("250 2.1.5 Queued", Delivered::Unknown, Displayed::Unknown)
+                DeliveryStatus::new(
+                    "Code: 550, Enhanced code: 0.0.0, Message: I refuse to accept that recipient.",
+                    Delivered::No,
+                    Displayed::Unknown
+                )
             ),
             (
                 "tim@foobar.com".to_string(),
-                DeliveryStatus::new("250 2.1.5 Queued", Delivered::Unknown, Displayed::Unknown)
+                DeliveryStatus::new(
+                    "Code: 250, Enhanced code: 0.0.0, Message: OK",
+                    Delivered::Yes,
+                    Displayed::Unknown
+                )
             ),
         ])
     );
@@ -651,3 +720,13 @@ pub async fn expect_nothing(event_rx: &mut mpsc::Receiver<MockMessage>) {
         }
     }
 }
+
+async fn collected_recipients(api: &ManagementApi, text: &str) -> AHashSet<String> {
+    api.get::<Vec<CollectedRecipient>>(&format!("/api/account/recipients?q={text}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+        .into_iter()
+        .map(|r| r.address)
+        .collect()
+}
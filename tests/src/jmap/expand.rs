@@ -0,0 +1,139 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{PrincipalField, PrincipalValue},
+    Principal, Type,
+};
+use serde::Deserialize;
+
+use super::{JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+struct Expansion {
+    valid: bool,
+    recipients: Vec<Recipient>,
+    limits: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Recipient {
+    address: String,
+    external: bool,
+    path: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Step {
+    #[serde(rename = "type")]
+    typ: String,
+}
+
+pub async fn test(_params: &JMAPTest) {
+    println!("Running recipient expansion tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create a list containing a group and an external member
+    for principal in [
+        Principal::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, "expand.org"),
+        Principal::new(u32::MAX, Type::List)
+            .with_field(PrincipalField::Name, "all@expand.org")
+            .with_field(PrincipalField::Emails, vec!["all@expand.org".to_string()])
+            .with_field(
+                PrincipalField::ExternalMembers,
+                PrincipalValue::StringList(vec!["partner@remote.org".to_string()]),
+            ),
+        Principal::new(u32::MAX, Type::Group)
+            .with_field(PrincipalField::Name, "team@expand.org")
+            .with_field(PrincipalField::Emails, vec!["team@expand.org".to_string()])
+            .with_field(PrincipalField::Lists, vec!["all@expand.org".to_string()]),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "ann@expand.org")
+            .with_field(PrincipalField::Emails, vec!["ann@expand.org".to_string()])
+            .with_field(
+                PrincipalField::MemberOf,
+                vec!["team@expand.org".to_string()],
+            )
+            .with_field(PrincipalField::Lists, vec!["all@expand.org".to_string()]),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "bob@expand.org")
+            .with_field(PrincipalField::Emails, vec!["bob@expand.org".to_string()])
+            .with_field(
+                PrincipalField::MemberOf,
+                vec!["team@expand.org".to_string()],
+            ),
+    ] {
+        api.post::<u32>("/api/principal", &principal)
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+
+    // Nested members are expanded once and annotated with their path
+    let expansion = api
+        .get::<Expansion>("/api/expand/all@expand.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(expansion.valid);
+    assert!(expansion.limits.is_empty());
+    let mut addresses = expansion
+        .recipients
+        .iter()
+        .map(|r| r.address.as_str())
+        .collect::<Vec<_>>();
+    addresses.sort_unstable();
+    assert_eq!(
+        addresses,
+        ["ann@expand.org", "bob@expand.org", "partner@remote.org"]
+    );
+    for recipient in &expansion.recipients {
+        assert_eq!(
+            recipient.external,
+            recipient.address == "partner@remote.org"
+        );
+        assert_eq!(recipient.path[0].typ, "member");
+    }
+    let bob = expansion
+        .recipients
+        .iter()
+        .find(|r| r.address == "bob@expand.org")
+        .unwrap();
+    assert_eq!(bob.path.len(), 2);
+
+    // Subaddresses are resolved before the directory lookup
+    let expansion = api
+        .get::<Expansion>("/api/expand/bob+news@expand.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(expansion.recipients.len(), 1);
+    assert_eq!(expansion.recipients[0].address, "bob@expand.org");
+    assert_eq!(expansion.recipients[0].path[0].typ, "subaddress");
+
+    // Unknown local addresses have no recipients
+    let expansion = api
+        .get::<Expansion>("/api/expand/nobody@expand.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(!expansion.valid);
+    assert!(expansion.recipients.is_empty());
+
+    // Clean up
+    for name in [
+        "ann@expand.org",
+        "bob@expand.org",
+        "team@expand.org",
+        "all@expand.org",
+        "expand.org",
+    ] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
@@ -0,0 +1,167 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use jmap::{email::index::IndexMessageText, mailbox::set::MailboxSet};
+use jmap_client::email::query::Filter;
+use jmap_proto::types::{collection::Collection, id::Id};
+use mail_parser::MessageParser;
+use serde::Deserialize;
+use store::{fts::index::FtsDocument, roaring::RoaringBitmap};
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{wait_for_index, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexReport {
+    stored: u64,
+    indexed: u64,
+    missing: u64,
+    orphaned: u64,
+    missing_ids: Vec<u32>,
+    rebuilt: u64,
+    failed: u64,
+}
+
+const ORPHAN_ID: u32 = 9999;
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running FTS index verification tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "fts@example.com",
+            "secret",
+            "FTS Verify",
+            &["fts@example.com"],
+        )
+        .await;
+    let (mailbox_id, _) = server
+        .mailbox_create_path(account_id, "Restored")
+        .await
+        .unwrap()
+        .unwrap();
+    let mailbox_id = Id::from(mailbox_id).to_string();
+
+    // Import a few messages
+    params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    let mut document_ids = Vec::new();
+    for word in ["aardvark", "bumblebee", "chinchilla"] {
+        let message = format!(
+            "From: bill@example.org\r\nSubject: About the {word}\r\n\r\nThe {word} is back.\r\n"
+        );
+        let email = params
+            .client
+            .email_import(message.into_bytes(), [&mailbox_id], None::<Vec<&str>>, None)
+            .await
+            .unwrap();
+        document_ids.push(
+            Id::from_bytes(email.id().unwrap().as_bytes())
+                .unwrap()
+                .document_id(),
+        );
+    }
+    wait_for_index(&server).await;
+
+    // A consistent index has no gaps
+    let report = verify(&api, "").await;
+    assert_eq!(report.stored, 3);
+    assert_eq!(report.indexed, 3);
+    assert_eq!(report.missing, 0);
+    assert_eq!(report.orphaned, 0);
+
+    // Simulate a blob restore: one message loses its index entries
+    // and the index references a message that no longer exists
+    server
+        .core
+        .storage
+        .fts
+        .remove(
+            account_id,
+            Collection::Email.into(),
+            &RoaringBitmap::from_iter([document_ids[0]]),
+        )
+        .await
+        .unwrap();
+    let orphan = MessageParser::new()
+        .parse(b"Subject: Ghost\r\n\r\nThis message was deleted.\r\n")
+        .unwrap();
+    server
+        .core
+        .storage
+        .fts
+        .index(
+            FtsDocument::with_default_language(server.core.jmap.default_language)
+                .with_account_id(account_id)
+                .with_collection(Collection::Email)
+                .with_document_id(ORPHAN_ID)
+                .index_message(&orphan),
+        )
+        .await
+        .unwrap();
+    assert_eq!(search(params, "aardvark").await, 0);
+
+    // Gaps are reported without modifying the index
+    for _ in 0..2 {
+        let report = verify(&api, "").await;
+        assert_eq!(report.stored, 3);
+        assert_eq!(report.indexed, 3);
+        assert_eq!(report.missing, 1);
+        assert_eq!(report.orphaned, 1);
+        assert_eq!(report.missing_ids, vec![document_ids[0]]);
+        assert_eq!(report.rebuilt, 0);
+    }
+
+    // Repair the index
+    let report = verify(&api, "?repair=true").await;
+    assert_eq!(report.missing, 1);
+    assert_eq!(report.orphaned, 1);
+    assert_eq!(report.rebuilt, 1);
+    assert_eq!(report.failed, 0);
+    let report = verify(&api, "").await;
+    assert_eq!(report.indexed, 3);
+    assert_eq!(report.missing, 0);
+    assert_eq!(report.orphaned, 0);
+    assert_eq!(search(params, "aardvark").await, 1);
+    assert_eq!(search(params, "ghost").await, 0);
+
+    // Full server verification runs in the background
+    api.get::<()>("/api/store/fts-verify")
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    // Unknown principals are not found
+    api.get::<IndexReport>("/api/store/fts-verify/nobody@example.com")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+}
+
+async fn verify(api: &ManagementApi, query: &str) -> IndexReport {
+    api.get::<IndexReport>(&format!("/api/store/fts-verify/fts@example.com{query}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+}
+
+async fn search(params: &mut JMAPTest, text: &str) -> usize {
+    params
+        .client
+        .email_query(Filter::text(text).into(), None::<Vec<_>>)
+        .await
+        .unwrap()
+        .ids()
+        .len()
+}
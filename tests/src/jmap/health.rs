@@ -0,0 +1,59 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+pub async fn test() {
+    println!("Running health check tests...");
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+
+    // Liveness does not depend on any store
+    for path in ["/healthz", "/healthz/live"] {
+        let response = client
+            .get(format!("https://127.0.0.1:8899{path}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{path}");
+        let body =
+            serde_json::from_slice::<serde_json::Value>(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(body["status"], "ok", "{path}");
+    }
+
+    // Readiness reports every dependency
+    for path in ["/readyz", "/healthz/ready"] {
+        let response = client
+            .get(format!("https://127.0.0.1:8899{path}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{path}");
+        let body =
+            serde_json::from_slice::<serde_json::Value>(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(body["status"], "ok", "{body}");
+        assert_eq!(body["failing"], serde_json::json!([]), "{body}");
+        let mut components = body["components"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|component| {
+                assert_eq!(component["healthy"], true, "{component}");
+                assert!(component["latencyMs"].is_u64(), "{component}");
+                component["name"].as_str().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        components.sort_unstable();
+        assert_eq!(
+            components,
+            vec!["blob", "certificates", "directory", "store"],
+            "{body}"
+        );
+    }
+}
@@ -0,0 +1,142 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use directory::{
+    backend::internal::{PrincipalField, PrincipalUpdate, PrincipalValue},
+    Principal,
+};
+use jmap::{
+    email::delete::{EmailDeletion, PreservedEmail},
+    JmapMethods,
+};
+use jmap_proto::types::collection::Collection;
+use serde::Deserialize;
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{delivery::SmtpConnection, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HoldReport {
+    on_hold: bool,
+    since: Option<u64>,
+    messages: usize,
+    bytes: u64,
+    items: Vec<PreservedEmail>,
+}
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running litigation hold tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let server = params.server.clone();
+    let store = &server.core.storage.data;
+
+    store.create_test_domains(&["hold.org"]).await;
+    let account_id = store
+        .create_test_user("jane@hold.org", "secret", "Jane Hold", &["jane@hold.org"])
+        .await;
+
+    // Deliver two messages
+    let message = concat!(
+        "From: sender@remote.org\r\n",
+        "To: jane@hold.org\r\n",
+        "Subject: Evidence\r\n",
+        "\r\n",
+        "Keep this."
+    );
+    let mut lmtp = SmtpConnection::connect().await;
+    for _ in 0..2 {
+        lmtp.ingest("sender@remote.org", &["jane@hold.org"], message)
+            .await;
+    }
+
+    // Place the hold
+    set_hold(&api, true).await;
+    let principal = api
+        .get::<Principal>("/api/principal/jane@hold.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(principal.is_on_hold());
+
+    // Deleted messages survive a purge while the account is on hold
+    let document_ids = server
+        .get_document_ids(account_id, Collection::Email)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(document_ids.len(), 2);
+    server
+        .emails_tombstone(account_id, document_ids)
+        .await
+        .unwrap();
+    server.purge_account(account_id).await;
+    let report = hold_report(&api).await;
+    assert!(report.on_hold);
+    assert!(report.since.is_some());
+    assert_eq!(report.messages, 2);
+    assert_eq!(report.items.len(), 2);
+    assert!(report.bytes >= 2 * message.len() as u64);
+
+    // Accounts on hold cannot be deleted without an explicit override
+    api.delete::<()>("/api/principal/jane@hold.org")
+        .await
+        .unwrap()
+        .expect_error("litigation hold");
+
+    // Releasing the hold purges the preserved messages in the background
+    set_hold(&api, false).await;
+    let mut report = hold_report(&api).await;
+    for _ in 0..50 {
+        if report.messages == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        report = hold_report(&api).await;
+    }
+    assert!(!report.on_hold);
+    assert_eq!(report.messages, 0);
+    assert_eq!(report.bytes, 0);
+
+    // Deleting with an override releases the hold first
+    set_hold(&api, true).await;
+    api.delete::<()>("/api/principal/jane@hold.org?override_hold=true")
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.get::<Principal>("/api/principal/jane@hold.org")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    api.delete::<()>("/api/principal/hold.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+}
+
+async fn set_hold(api: &ManagementApi, on_hold: bool) {
+    api.patch::<()>(
+        "/api/principal/jane@hold.org",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Hold,
+            PrincipalValue::Integer(on_hold as u64),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+}
+
+async fn hold_report(api: &ManagementApi) -> HoldReport {
+    api.get::<HoldReport>("/api/principal/jane@hold.org/hold")
+        .await
+        .unwrap()
+        .unwrap_data()
+}
@@ -0,0 +1,80 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use directory::{backend::internal::PrincipalField, Principal, Type};
+use reqwest::header::AUTHORIZATION;
+
+use super::{JMAPTest, ManagementApi, Response};
+
+pub async fn test(_params: &JMAPTest) {
+    println!("Running management API idempotency tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+    let group = Principal::new(0, Type::Group).with_field(PrincipalField::Name, "idem-group");
+
+    // Retries replay the result of the first request
+    let id = create(&group, Some("retry-1")).await.unwrap_data();
+    for _ in 0..2 {
+        assert_eq!(create(&group, Some("retry-1")).await.unwrap_data(), id);
+    }
+
+    // Requests without a key are executed again
+    create(&group, None)
+        .await
+        .expect_error("fieldAlreadyExists");
+
+    // The same key cannot be used for a different request
+    let other = Principal::new(0, Type::Group).with_field(PrincipalField::Name, "idem-other");
+    create(&other, Some("retry-1"))
+        .await
+        .expect_error("Idempotency key reused");
+
+    // Errors caused by the request are replayed as well
+    create(&group, Some("retry-2"))
+        .await
+        .expect_error("fieldAlreadyExists");
+    create(&group, Some("retry-2"))
+        .await
+        .expect_error("fieldAlreadyExists");
+
+    // Concurrent requests are serialized on the key
+    let other_ = other.clone();
+    let (first, second) = tokio::join!(
+        create(&other, Some("retry-3")),
+        create(&other_, Some("retry-3"))
+    );
+    assert_eq!(first.unwrap_data(), second.unwrap_data());
+
+    // Remove test data
+    for name in ["idem-group", "idem-other"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
+
+async fn create(principal: &Principal, key: Option<&str>) -> Response<u32> {
+    let mut request = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .post("https://127.0.0.1:8899/api/principal")
+        .header(
+            AUTHORIZATION,
+            format!("Basic {}", STANDARD.encode("admin:secret")),
+        )
+        .body(serde_json::to_string(principal).unwrap());
+    if let Some(key) = key {
+        request = request.header("Idempotency-Key", key);
+    }
+
+    let result = request.send().await.unwrap().text().await.unwrap();
+    serde_json::from_str::<Response<u32>>(&result).unwrap_or_else(|err| panic!("{err}: {result}"))
+}
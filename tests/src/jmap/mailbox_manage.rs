@@ -0,0 +1,137 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use jmap::mailbox::set::MailboxSet;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{enterprise::List, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MailboxItem {
+    id: u32,
+    path: String,
+    parent_id: Option<u32>,
+    is_subscribed: bool,
+    uid_validity: u64,
+}
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running mailbox management tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create a folder tree
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "shared.box@example.com",
+            "secret",
+            "Shared Box",
+            &["shared.box@example.com"],
+        )
+        .await;
+    for path in ["Projects/Alpha/Specs", "Projects/Beta", "Archive"] {
+        server
+            .mailbox_create_path(account_id, path)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    let mailboxes = list_mailboxes(&api).await;
+    let projects = find_mailbox(&mailboxes, "Projects");
+    let alpha = find_mailbox(&mailboxes, "Projects/Alpha");
+    let archive = find_mailbox(&mailboxes, "Archive");
+    assert_eq!(alpha.parent_id, Some(projects.id));
+
+    // Moving a subtree keeps its children
+    api.patch::<()>(
+        &format!("/api/mailbox/shared.box@example.com/{}", alpha.id),
+        &json!({"path": "Archive/2024/Alpha"}),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let mailboxes = list_mailboxes(&api).await;
+    let moved = find_mailbox(&mailboxes, "Archive/2024/Alpha");
+    assert_eq!(moved.id, alpha.id);
+    assert_ne!(moved.uid_validity, alpha.uid_validity);
+    assert_eq!(moved.is_subscribed, alpha.is_subscribed);
+    assert_eq!(
+        find_mailbox(&mailboxes, "Archive/2024").parent_id,
+        Some(archive.id)
+    );
+    find_mailbox(&mailboxes, "Archive/2024/Alpha/Specs");
+    assert!(!mailboxes
+        .iter()
+        .any(|m| m.path.starts_with("Projects/Alpha")));
+
+    // Name collisions and moves into a subfolder are rejected
+    api.patch::<()>(
+        &format!("/api/mailbox/shared.box@example.com/{}", projects.id),
+        &json!({"path": "Archive"}),
+    )
+    .await
+    .unwrap()
+    .expect_error("Mailbox 'Archive' already exists.");
+    api.patch::<()>(
+        &format!("/api/mailbox/shared.box@example.com/{}", archive.id),
+        &json!({"path": "Archive/2024/Archive"}),
+    )
+    .await
+    .unwrap()
+    .expect_error("cannot be moved into one of its own subfolders");
+    api.patch::<()>(
+        &format!("/api/mailbox/shared.box@example.com/{}", archive.id),
+        &json!({"path": "Archive//Old"}),
+    )
+    .await
+    .unwrap()
+    .expect_error("Invalid empty path item.");
+
+    // Mailboxes with children cannot be deleted
+    api.delete::<bool>(&format!(
+        "/api/mailbox/shared.box@example.com/{}",
+        archive.id
+    ))
+    .await
+    .unwrap()
+    .expect_error("Archive/2024");
+    let specs = find_mailbox(&mailboxes, "Archive/2024/Alpha/Specs");
+    assert!(!api
+        .delete::<bool>(&format!("/api/mailbox/shared.box@example.com/{}", specs.id))
+        .await
+        .unwrap()
+        .unwrap_data());
+    let mailboxes = list_mailboxes(&api).await;
+    assert!(!mailboxes.iter().any(|m| m.id == specs.id));
+
+    // Unknown principals are not found
+    api.get::<List<MailboxItem>>("/api/mailbox/nobody@example.com")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+}
+
+async fn list_mailboxes(api: &ManagementApi) -> Vec<MailboxItem> {
+    api.get::<List<MailboxItem>>("/api/mailbox/shared.box@example.com")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items
+}
+
+fn find_mailbox<'x>(mailboxes: &'x [MailboxItem], path: &str) -> &'x MailboxItem {
+    mailboxes
+        .iter()
+        .find(|m| m.path == path)
+        .unwrap_or_else(|| panic!("Mailbox {path:?} not found in {mailboxes:?}"))
+}
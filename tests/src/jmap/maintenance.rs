@@ -0,0 +1,136 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{
+        maintenance::ReadOnlyMode, manage::ManageDirectory, PrincipalField, PrincipalUpdate,
+        PrincipalValue,
+    },
+    Principal, QueryBy, Type,
+};
+use serde_json::json;
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running maintenance mode tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create a tenant and an account outside of it
+    for principal in [
+        Principal::new(u32::MAX, Type::Tenant).with_field(PrincipalField::Name, "maintco"),
+        Principal::new(u32::MAX, Type::Individual).with_field(PrincipalField::Name, "maint_user"),
+    ] {
+        api.post::<u32>("/api/principal", &principal)
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    assert_eq!(
+        api.get::<Option<ReadOnlyMode>>("/api/maintenance")
+            .await
+            .unwrap()
+            .unwrap_data(),
+        None
+    );
+
+    // Enable server-wide maintenance mode
+    let mode = api
+        .put::<ReadOnlyMode>(
+            "/api/maintenance",
+            &json!({"reason": "Store migration in progress"}),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(mode.enabled_by, "admin");
+    assert_eq!(
+        api.get::<Option<ReadOnlyMode>>("/api/maintenance")
+            .await
+            .unwrap()
+            .unwrap_data(),
+        Some(mode)
+    );
+
+    // Directory changes are rejected while reads continue
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Individual).with_field(PrincipalField::Name, "maint_new"),
+    )
+    .await
+    .unwrap()
+    .expect_error("maintenance mode");
+    api.patch::<()>(
+        "/api/principal/maint_user",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Description,
+            PrincipalValue::String("Updated".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .expect_error("Store migration in progress");
+    api.delete::<()>("/api/principal/maint_user")
+        .await
+        .unwrap()
+        .expect_error("maintenance mode");
+    api.get::<Principal>("/api/principal/maint_user")
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    // The switch is enforced by the directory itself
+    assert!(params
+        .server
+        .store()
+        .delete_principal(QueryBy::Name("maint_user"))
+        .await
+        .is_err());
+
+    // Disable maintenance mode and enable it for the tenant only
+    api.delete::<()>("/api/maintenance")
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.put::<ReadOnlyMode>("/api/maintenance/maintco", &json!({}))
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "maint_tenant_user")
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("maintco".to_string()),
+            ),
+    )
+    .await
+    .unwrap()
+    .expect_error("maintenance mode");
+    api.patch::<()>(
+        "/api/principal/maint_user",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Description,
+            PrincipalValue::String("Updated".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+
+    // Clean up
+    api.delete::<()>("/api/maintenance/maintco")
+        .await
+        .unwrap()
+        .unwrap_data();
+    for name in ["maint_user", "maintco"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
@@ -42,7 +42,7 @@ use smtp::{core::SmtpSessionManager, SpawnQueueManager};
 
 use store::{
     roaring::RoaringBitmap,
-    write::{key::DeserializeBigEndian, AnyKey, FtsQueueClass, ValueClass},
+    write::{key::DeserializeBigEndian, AnyKey, FtsQueueClass, TaskClass, ValueClass},
     IterateParams, Stores, ValueKey, SUBSPACE_PROPERTY,
 };
 use tokio::sync::watch;
@@ -58,7 +58,10 @@ pub mod auth_limits;
 pub mod auth_oauth;
 pub mod blob;
 pub mod crypto;
+pub mod delegation;
+pub mod delete_preview;
 pub mod delivery;
+pub mod duplicates;
 pub mod email_changes;
 pub mod email_copy;
 pub mod email_get;
@@ -70,16 +73,33 @@ pub mod email_set;
 pub mod email_submission;
 pub mod enterprise;
 pub mod event_source;
+pub mod expand;
+pub mod fts_verify;
+pub mod health;
+pub mod hold;
+pub mod idempotency;
 pub mod mailbox;
+pub mod mailbox_manage;
+pub mod maintenance;
+pub mod notify;
 pub mod permissions;
+pub mod principal_export;
+pub mod privacy;
+pub mod provision;
 pub mod purge;
 pub mod push_subscription;
 pub mod quota;
+pub mod recall;
+pub mod roles;
 pub mod sieve_script;
 pub mod stress_test;
+pub mod tenant_export;
 pub mod thread_get;
 pub mod thread_merge;
+pub mod thread_rules;
+pub mod undo;
 pub mod vacation_response;
+pub mod volume;
 pub mod webhooks;
 pub mod websocket;
 
@@ -219,6 +239,15 @@ directory = "{STORE}"
 [spam.header]
 is-spam  = "X-Spam-Status: Yes"
 
+[approval]
+protected = ["delete-tenant"]
+
+[directory.notify]
+events = ["role-grant", "permission-change", "secret-change", "tenant-create"]
+email.to = ["security@example.com"]
+protected = ["notify_user"]
+batch = "1h"
+
 [jmap.protocol.get]
 max-objects = 100000
 
@@ -338,6 +367,10 @@ signature-algorithm = "ES256"
 expn = true
 vrfy = true
 
+[metrics.volume]
+enable = true
+flush-interval = "1h"
+
 [tracer.console]
 type = "console"
 level = "{LEVEL}"
@@ -381,7 +414,11 @@ pub async fn jmap_tests() {
     email_copy::test(&mut params).await;
     thread_get::test(&mut params).await;
     thread_merge::test(&mut params).await;
+    thread_rules::test(&mut params).await;
     mailbox::test(&mut params).await;
+    mailbox_manage::test(&mut params).await;
+    duplicates::test(&mut params).await;
+    fts_verify::test(&mut params).await;
     delivery::test(&mut params).await;
     auth_acl::test(&mut params).await;
     auth_limits::test(&mut params).await;
@@ -396,6 +433,22 @@ pub async fn jmap_tests() {
     crypto::test(&mut params).await;
     blob::test(&mut params).await;
     permissions::test(&params).await;
+    undo::test(&params).await;
+    volume::test(&params).await;
+    delete_preview::test(&params).await;
+    hold::test(&params).await;
+    tenant_export::test(&params).await;
+    recall::test(&params).await;
+    roles::test(&params).await;
+    delegation::test(&params).await;
+    principal_export::test(&params).await;
+    notify::test(&params).await;
+    maintenance::test(&params).await;
+    expand::test(&params).await;
+    idempotency::test(&params).await;
+    provision::test(&mut params).await;
+    privacy::test().await;
+    health::test().await;
     purge::test(&mut params).await;*/
     enterprise::test(&mut params).await;
 
@@ -489,6 +542,33 @@ pub async fn assert_is_empty(server: Server) {
     // Wait for pending FTS index tasks
     wait_for_index(&server).await;
 
+    // Undo entries outlive the principals they refer to
+    let undo_key = |id: u64| {
+        ValueKey::<ValueClass<u32>>::from(ValueClass::Task(TaskClass::State(
+            b"undo.entry."
+                .iter()
+                .copied()
+                .chain(id.to_be_bytes())
+                .collect(),
+        )))
+    };
+    server
+        .core
+        .storage
+        .data
+        .delete_range(undo_key(0), undo_key(u64::MAX))
+        .await
+        .unwrap();
+
+    // The directory change sequence is never reset
+    server
+        .core
+        .storage
+        .lookup
+        .counter_delete(b"dir:seq".to_vec())
+        .await
+        .unwrap();
+
     // Purge accounts
     emails_purge_tombstoned(&server).await;
 
@@ -807,6 +887,7 @@ pub struct ManagementApi {
     pub port: u16,
     pub username: String,
     pub password: String,
+    pub token: Option<String>,
 }
 
 impl Default for ManagementApi {
@@ -815,6 +896,7 @@ impl Default for ManagementApi {
             port: 9980,
             username: "admin".to_string(),
             password: "secret".to_string(),
+            token: None,
         }
     }
 }
@@ -825,6 +907,16 @@ impl ManagementApi {
             port,
             username: username.to_string(),
             password: password.to_string(),
+            token: None,
+        }
+    }
+
+    pub fn with_token(port: u16, token: &str) -> Self {
+        Self {
+            port,
+            username: String::new(),
+            password: String::new(),
+            token: Some(token.to_string()),
         }
     }
 
@@ -862,6 +954,23 @@ impl ManagementApi {
         })
     }
 
+    pub async fn put<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        body: &impl Serialize,
+    ) -> Result<Response<T>, String> {
+        self.request_raw(
+            Method::PUT,
+            query,
+            Some(serde_json::to_string(body).unwrap()),
+        )
+        .await
+        .map(|result| {
+            serde_json::from_str::<Response<T>>(&result)
+                .unwrap_or_else(|err| panic!("{err}: {result}"))
+        })
+    }
+
     pub async fn delete<T: DeserializeOwned>(&self, query: &str) -> Result<Response<T>, String> {
         self.request_raw(Method::DELETE, query, None)
             .await
@@ -890,7 +999,7 @@ impl ManagementApi {
         })
     }
 
-    async fn request_raw(
+    pub async fn request_raw(
         &self,
         method: Method,
         query: &str,
@@ -907,14 +1016,17 @@ impl ManagementApi {
             request = request.body(body);
         }
 
-        request
-            .header(
-                AUTHORIZATION,
-                format!(
-                    "Basic {}",
-                    STANDARD.encode(format!("{}:{}", self.username, self.password).as_bytes())
-                ),
+        let authorization = if let Some(token) = &self.token {
+            format!("Bearer {token}")
+        } else {
+            format!(
+                "Basic {}",
+                STANDARD.encode(format!("{}:{}", self.username, self.password).as_bytes())
             )
+        };
+
+        request
+            .header(AUTHORIZATION, authorization)
             .send()
             .await
             .map_err(|err| err.to_string())?
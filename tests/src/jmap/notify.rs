@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::config::notify::NotifyEvent;
+use directory::{
+    backend::internal::{PrincipalField, PrincipalUpdate, PrincipalValue},
+    Principal, Type,
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running directory notification tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+    params.server.take_directory_alerts();
+
+    // Granting an administrator role and changing protected credentials is reported
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "notify_user")
+            .with_field(PrincipalField::Roles, vec!["admin".to_string()]),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    api.patch::<()>(
+        "/api/principal/notify_user",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Secrets,
+            PrincipalValue::String("notify-secret".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+
+    // Granting the same role again is suppressed
+    for update in [
+        PrincipalUpdate::remove_item(
+            PrincipalField::Roles,
+            PrincipalValue::String("admin".to_string()),
+        ),
+        PrincipalUpdate::add_item(
+            PrincipalField::Roles,
+            PrincipalValue::String("admin".to_string()),
+        ),
+    ] {
+        api.patch::<()>("/api/principal/notify_user", &vec![update])
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+
+    // Regular changes are not reported
+    api.patch::<()>(
+        "/api/principal/notify_user",
+        &vec![PrincipalUpdate::set(
+            PrincipalField::Description,
+            PrincipalValue::String("Notify User".to_string()),
+        )],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+
+    let alerts = params.server.take_directory_alerts();
+    assert_eq!(
+        alerts.iter().map(|a| a.event).collect::<Vec<_>>(),
+        vec![NotifyEvent::RoleGrant, NotifyEvent::SecretChange],
+        "{alerts:?}"
+    );
+    assert!(alerts.iter().all(|a| a.target == "notify_user"));
+    assert!(alerts.iter().all(|a| a.performed_by == "admin"));
+
+    // Clean up
+    api.delete::<()>("/api/principal/notify_user")
+        .await
+        .unwrap()
+        .unwrap_data();
+}
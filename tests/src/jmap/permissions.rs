@@ -14,12 +14,51 @@ use directory::{
     Permission, Principal, Type,
 };
 use jmap::{services::ingest::MailDelivery, JmapMethods};
+use mail_parser::DateTime;
+use serde::Deserialize;
+use serde_json::json;
+use store::write::now;
 use utils::BlobHash;
 
 use crate::jmap::assert_is_empty;
 
 use super::{enterprise::List, JMAPTest, ManagementApi};
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiKey {
+    id: String,
+    tenant_id: u32,
+    permissions: Vec<String>,
+    last_used_at: Option<String>,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EffectivePermissions {
+    permissions: Vec<String>,
+    sources: Vec<PermissionSources>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PermissionSources {
+    permission: String,
+    effective: bool,
+    granted_by: Vec<serde_json::Value>,
+    revoked_by: Vec<serde_json::Value>,
+}
+
+impl EffectivePermissions {
+    fn source(&self, permission: Permission) -> &PermissionSources {
+        self.sources
+            .iter()
+            .find(|s| s.permission == permission.name())
+            .unwrap_or_else(|| panic!("No sources for {permission:?}"))
+    }
+}
+
 pub async fn test(params: &JMAPTest) {
     println!("Running permissions tests...");
     let server = params.server.clone();
@@ -50,6 +89,101 @@ pub async fn test(params: &JMAPTest) {
             Permission::all().filter(|p| p.is_user_permission() && *p != Permission::Pop3Dele),
         );
 
+    // Users can update their own settings
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "self_service")
+            .with_field(PrincipalField::Roles, vec!["user".to_string()])
+            .with_field(PrincipalField::Secrets, vec!["selfpass".to_string()]),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let user_api = ManagementApi::new(8899, "self_service", "selfpass");
+    user_api
+        .patch::<()>(
+            "/api/account/settings",
+            &vec![PrincipalUpdate::set(
+                PrincipalField::Description,
+                PrincipalValue::String("Self Service".to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        api.get::<Principal>("/api/principal/self_service")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .get_str(PrincipalField::Description),
+        Some("Self Service")
+    );
+
+    // Fields outside the allow list cannot be modified
+    for (field, value) in [
+        (PrincipalField::Quota, PrincipalValue::Integer(1024)),
+        (
+            PrincipalField::Emails,
+            PrincipalValue::StringList(vec!["self@example.org".to_string()]),
+        ),
+        (
+            PrincipalField::Roles,
+            PrincipalValue::StringList(vec!["admin".to_string()]),
+        ),
+    ] {
+        user_api
+            .patch::<()>(
+                "/api/account/settings",
+                &vec![PrincipalUpdate::set(field, value)],
+            )
+            .await
+            .unwrap()
+            .expect_error(&format!("Field {:?} cannot be modified", field.as_str()));
+    }
+
+    // Other accounts cannot be modified without a delegation
+    user_api
+        .patch::<()>(
+            "/api/account/settings/role_player",
+            &vec![PrincipalUpdate::set(
+                PrincipalField::Description,
+                PrincipalValue::String("Hijacked".to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Change password
+    user_api
+        .patch::<()>(
+            "/api/account/settings",
+            &vec![PrincipalUpdate::set(
+                PrincipalField::Secrets,
+                PrincipalValue::String("newselfpass".to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    ManagementApi::new(8899, "self_service", "newselfpass")
+        .patch::<()>(
+            "/api/account/settings",
+            &vec![PrincipalUpdate::set(
+                PrincipalField::Picture,
+                PrincipalValue::String("https://example.org/picture.png".to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.delete::<()>("/api/principal/self_service")
+        .await
+        .unwrap()
+        .unwrap_data();
+
     // Create multiple roles
     for (role, permissions, parent_role) in &[
         (
@@ -139,6 +273,58 @@ pub async fn test(params: &JMAPTest) {
             Permission::Pop3List,
         ]);
 
+    // Effective permissions report where each permission comes from
+    let effective = api
+        .get::<EffectivePermissions>("/api/principal/role_player/permissions")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        effective
+            .permissions
+            .iter()
+            .cloned()
+            .collect::<AHashSet<_>>(),
+        [
+            Permission::EmailSend,
+            Permission::EmailReceive,
+            Permission::JmapEmailQuery,
+            Permission::AuthenticateOauth,
+            Permission::ImapAuthenticate,
+            Permission::ImapList,
+            Permission::Pop3Authenticate,
+            Permission::Pop3List,
+        ]
+        .iter()
+        .map(|p| p.name().to_string())
+        .collect::<AHashSet<_>>()
+    );
+    let source = effective.source(Permission::ManageEncryption);
+    assert!(!source.effective);
+    assert_eq!(
+        source.granted_by,
+        vec![json!({"type": "role", "name": "jmap_user"})]
+    );
+    assert_eq!(
+        source.revoked_by,
+        vec![json!({"type": "role", "name": "email_user"})]
+    );
+    let source = effective.source(Permission::Pop3Dele);
+    assert!(!source.effective);
+    assert!(source.granted_by.is_empty());
+    assert_eq!(
+        source.revoked_by,
+        vec![json!({"type": "disabledPermissions"})]
+    );
+    assert_eq!(
+        effective.source(Permission::EmailSend).granted_by,
+        vec![json!({"type": "role", "name": "email_user"})]
+    );
+    api.get::<EffectivePermissions>("/api/principal/unknown_user/permissions")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
     // Query all principals
     api.get::<List<Principal>>("/api/principal")
         .await
@@ -551,6 +737,19 @@ pub async fn test(params: &JMAPTest) {
         .unwrap()
         .unwrap_data();
 
+    // Tenant admins cannot change the settings of accounts in other tenants
+    tenant_api
+        .patch::<()>(
+            "/api/account/settings/role_player",
+            &vec![PrincipalUpdate::set(
+                PrincipalField::Secrets,
+                PrincipalValue::String("hijacked".to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
     // Tenants should only see their own principals
     tenant_api
         .get::<List<Principal>>("/api/principal?types=individual,group,role,list")
@@ -595,6 +794,178 @@ pub async fn test(params: &JMAPTest) {
             ],
         );
 
+    // Tenant admins can issue API keys with a subset of their permissions
+    for (permission, expires_at, error) in [
+        ("email-send", None, "Invalid permission"),
+        ("tenant-create", None, "Invalid permission"),
+        (
+            "individual-list",
+            Some(now() - 60),
+            "Invalid expiration date",
+        ),
+    ] {
+        tenant_api
+            .post::<ApiKey>(
+                "/api/apikey",
+                &serde_json::json!({
+                    "name": "provisioning",
+                    "permissions": [permission],
+                    "expiresAt": expires_at.map(|ts| DateTime::from_timestamp(ts as i64).to_rfc3339()),
+                }),
+            )
+            .await
+            .unwrap()
+            .expect_error(error);
+    }
+    let api_key = tenant_api
+        .post::<ApiKey>(
+            "/api/apikey",
+            &serde_json::json!({
+                "name": "provisioning",
+                "permissions": ["individual-list", "individual-get"],
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(api_key.tenant_id, tenant_id);
+    assert_eq!(
+        api_key.permissions,
+        vec!["individual-list", "individual-get"]
+    );
+    let key_api = ManagementApi::with_token(8899, api_key.token.as_deref().unwrap());
+
+    // API keys are scoped to the tenant and limited to the granted permissions
+    key_api
+        .get::<List<Principal>>("/api/principal?types=individual")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .assert_count(2);
+    key_api
+        .get::<List<Principal>>("/api/principal?types=role")
+        .await
+        .unwrap()
+        .expect_request_error("Forbidden");
+    key_api
+        .get::<Principal>("/api/principal/john.doe@foobar.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    key_api
+        .get::<Principal>("/api/principal/example.org")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+    let keys = tenant_api
+        .get::<List<ApiKey>>("/api/apikey")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(keys.total, 1);
+    assert!(keys.items[0].last_used_at.is_some());
+    assert!(keys.items[0].token.is_none());
+    assert_eq!(
+        api.get::<List<ApiKey>>("/api/apikey")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        1
+    );
+
+    // API keys cannot be used outside the management API
+    assert_eq!(
+        reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap()
+            .get("https://127.0.0.1:8899/.well-known/jmap")
+            .bearer_auth(api_key.token.as_deref().unwrap())
+            .send()
+            .await
+            .unwrap()
+            .status(),
+        401
+    );
+
+    // Expired keys are rejected
+    let expiring_key = tenant_api
+        .post::<ApiKey>(
+            "/api/apikey",
+            &serde_json::json!({
+                "name": "short-lived",
+                "permissions": ["individual-list"],
+                "expiresAt": DateTime::from_timestamp(now() as i64 + 2).to_rfc3339(),
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let expiring_api = ManagementApi::with_token(8899, expiring_key.token.as_deref().unwrap());
+    expiring_api
+        .get::<List<Principal>>("/api/principal?types=individual")
+        .await
+        .unwrap()
+        .unwrap_data();
+    tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+    expiring_api
+        .get::<List<Principal>>("/api/principal?types=individual")
+        .await
+        .unwrap()
+        .expect_request_error("Unauthorized");
+
+    // Keys created by administrators without a tenant must name one
+    api.post::<ApiKey>(
+        "/api/apikey",
+        &serde_json::json!({"name": "global", "permissions": ["individual-list"]}),
+    )
+    .await
+    .unwrap()
+    .expect_error("must be bound to a tenant");
+
+    // Keys share the account id but are limited and scoped individually
+    let other_key = tenant_api
+        .post::<ApiKey>(
+            "/api/apikey",
+            &serde_json::json!({
+                "name": "reporting",
+                "permissions": ["individual-list"],
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    let key_token = server
+        .authenticate_api_key(api_key.token.as_deref().unwrap())
+        .await
+        .unwrap();
+    let other_token = server
+        .authenticate_api_key(other_key.token.as_deref().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(key_token.primary_id(), other_token.primary_id());
+    assert_ne!(key_token.caller_id(), other_token.caller_id());
+
+    // Revoked keys stop working immediately
+    for id in [&api_key.id, &expiring_key.id, &other_key.id] {
+        tenant_api
+            .delete::<ApiKey>(&format!("/api/apikey/{id}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    key_api
+        .get::<List<Principal>>("/api/principal?types=individual")
+        .await
+        .unwrap()
+        .expect_request_error("Unauthorized");
+    tenant_api
+        .delete::<ApiKey>(&format!("/api/apikey/{}", api_key.id))
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
     // John should not be allowed to receive email
     let message_blob = BlobHash::from(TEST_MESSAGE.as_bytes());
     server
@@ -696,8 +1067,55 @@ pub async fn test(params: &JMAPTest) {
         TEST_MESSAGE.len() as i64
     );
 
+    // Deleting tenants requires the approval of a second administrator
+    let request = api
+        .delete::<serde_json::Value>("/api/principal/xanadu")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(request["operation"], "delete-tenant");
+    assert_eq!(request["target"], "xanadu");
+    let request_id = request["id"].as_str().unwrap().to_string();
+    api.post::<()>(&format!("/api/approval/{request_id}/approve"), &())
+        .await
+        .unwrap()
+        .expect_error("cannot be approved or rejected by the administrator that created them");
+    api.get::<serde_json::Value>("/api/principal/xanadu")
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    // Create a second administrator
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "approver")
+            .with_field(PrincipalField::Roles, vec!["admin".to_string()])
+            .with_field(PrincipalField::Secrets, vec!["approverpass".to_string()]),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let approver_api = ManagementApi::new(8899, "approver", "approverpass");
+
+    // Rejected requests are not executed
+    approver_api
+        .post::<()>(&format!("/api/approval/{request_id}/reject"), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+
     // Deleting tenants with data should fail
-    api.delete::<()>("/api/principal/xanadu")
+    let request = api
+        .delete::<serde_json::Value>("/api/principal/xanadu")
+        .await
+        .unwrap()
+        .unwrap_data();
+    approver_api
+        .post::<()>(
+            &format!("/api/approval/{}/approve", request["id"].as_str().unwrap()),
+            &(),
+        )
         .await
         .unwrap()
         .expect_error("Tenant has members");
@@ -711,12 +1129,64 @@ pub async fn test(params: &JMAPTest) {
     // Quota usage for tenant should be updated
     assert_eq!(server.get_used_quota(other_tenant_id).await.unwrap(), 0);
 
-    // Delete tenant
-    api.delete::<()>("/api/principal/xanadu")
+    // Cancelled requests are not executed
+    let request = api
+        .delete::<serde_json::Value>("/api/principal/xanadu")
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.delete::<()>(&format!(
+        "/api/approval/{}",
+        request["id"].as_str().unwrap()
+    ))
+    .await
+    .unwrap()
+    .unwrap_data();
+    assert_eq!(
+        approver_api
+            .get::<List<serde_json::Value>>("/api/approval")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        0
+    );
+    api.get::<serde_json::Value>("/api/principal/xanadu")
         .await
         .unwrap()
         .unwrap_data();
 
+    // Approved requests are executed
+    let request = api
+        .delete::<serde_json::Value>("/api/principal/xanadu")
+        .await
+        .unwrap()
+        .unwrap_data();
+    let request_id = request["id"].as_str().unwrap().to_string();
+    assert_eq!(
+        approver_api
+            .get::<List<serde_json::Value>>("/api/approval")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items[0]["id"],
+        request_id.as_str()
+    );
+    approver_api
+        .post::<()>(&format!("/api/approval/{request_id}/approve"), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.get::<serde_json::Value>("/api/principal/xanadu")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+    approver_api
+        .post::<()>(&format!("/api/approval/{request_id}/approve"), &())
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
     // Delete tenant information
     for query in [
         "/api/principal/no-mail-for-you@foobar.com",
@@ -728,7 +1198,20 @@ pub async fn test(params: &JMAPTest) {
     }
 
     // Delete tenant
-    api.delete::<()>("/api/principal/foobar")
+    let request = api
+        .delete::<serde_json::Value>("/api/principal/foobar")
+        .await
+        .unwrap()
+        .unwrap_data();
+    approver_api
+        .post::<()>(
+            &format!("/api/approval/{}/approve", request["id"].as_str().unwrap()),
+            &(),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.delete::<()>("/api/principal/approver")
         .await
         .unwrap()
         .unwrap_data();
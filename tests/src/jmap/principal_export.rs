@@ -0,0 +1,120 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{
+        manage::{ImportedPrincipals, PrincipalExport},
+        PrincipalField,
+    },
+    Principal, Type,
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(_params: &JMAPTest) {
+    println!("Running principal export and import tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create a domain, a group and a member of the group
+    for principal in [
+        Principal::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, "export.org"),
+        Principal::new(u32::MAX, Type::Group)
+            .with_field(PrincipalField::Name, "staff@export.org")
+            .with_field(PrincipalField::Emails, vec!["staff@export.org".to_string()]),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "sam@export.org")
+            .with_field(PrincipalField::Emails, vec!["sam@export.org".to_string()])
+            .with_field(
+                PrincipalField::MemberOf,
+                vec!["staff@export.org".to_string()],
+            ),
+    ] {
+        api.post::<u32>("/api/principal", &principal)
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+
+    // Export and keep the principals created above
+    let mut export = api
+        .get::<PrincipalExport>("/api/principal/export")
+        .await
+        .unwrap()
+        .unwrap_data();
+    export
+        .principals
+        .retain(|principal| principal.name().ends_with("export.org"));
+    assert_eq!(export.principals.len(), 3);
+
+    // Name conflicts fail the import unless skipped or overwritten
+    api.post::<ImportedPrincipals>("/api/principal/export", &export)
+        .await
+        .unwrap()
+        .expect_error("fieldAlreadyExists");
+    let result = api
+        .post::<ImportedPrincipals>("/api/principal/export?mode=skip", &export)
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(result.skipped.len(), 3);
+    assert!(result.created.is_empty());
+    let result = api
+        .post::<ImportedPrincipals>("/api/principal/export?mode=overwrite", &export)
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(result.updated.len(), 3);
+
+    // Recreate the principals from the export, memberships are restored by name
+    for name in ["sam@export.org", "staff@export.org", "export.org"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    let result = api
+        .post::<ImportedPrincipals>("/api/principal/export", &export)
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(result.created.len(), 3);
+    let sam = api
+        .get::<Principal>("/api/principal/sam@export.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        sam.get_str_array(PrincipalField::MemberOf),
+        Some(&["staff@export.org".to_string()][..])
+    );
+
+    // Addresses already taken by other principals are rejected
+    api.delete::<()>("/api/principal/sam@export.org")
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "sam-new@export.org")
+            .with_field(PrincipalField::Emails, vec!["sam@export.org".to_string()]),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    api.post::<ImportedPrincipals>("/api/principal/export?mode=skip", &export)
+        .await
+        .unwrap()
+        .expect_error("fieldAlreadyExists");
+
+    // Clean up
+    for name in ["sam-new@export.org", "staff@export.org", "export.org"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
@@ -0,0 +1,132 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::mpsc;
+use trc::{
+    ipc::{
+        privacy::PrivacyMode,
+        subscriber::{EventBatch, SubscriberBuilder},
+    },
+    Collector, EventType, Key, SmtpEvent,
+};
+
+use super::ManagementApi;
+
+#[derive(Debug, Deserialize)]
+struct PrivacyList {
+    items: Vec<SubscriberPrivacy>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubscriberPrivacy {
+    id: String,
+    configured: String,
+    effective: String,
+    overridden: bool,
+}
+
+pub async fn test() {
+    println!("Running privacy mode tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Register a tracer that pseudonymizes addresses
+    let event_type = EventType::Smtp(SmtpEvent::MailFrom);
+    let (_tx, mut rx) = SubscriberBuilder::new("privacy-test".to_string())
+        .set_interests([event_type])
+        .with_lossy(false)
+        .with_privacy(PrivacyMode::Hash)
+        .register();
+    let mut interests = trc::ipc::subscriber::Interests::default();
+    interests.set(event_type);
+    Collector::union_interests(interests);
+    Collector::reload();
+    let privacy = wait_for_tracer(&api).await;
+    assert_eq!(privacy.configured, "hash");
+    assert_eq!(privacy.effective, "hash");
+    assert!(!privacy.overridden);
+
+    // Addresses are hashed, keeping the domain
+    let from = next_from(&mut rx).await;
+    assert!(from.starts_with("anon-"), "{from}");
+    assert!(from.ends_with("@example.org"), "{from}");
+    assert_eq!(next_from(&mut rx).await, from);
+
+    // Switch the tracer to truncation at runtime
+    let privacy = api
+        .put::<SubscriberPrivacy>("/api/privacy/privacy-test", &json!({"mode": "truncate"}))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(privacy.configured, "hash");
+    assert_eq!(privacy.effective, "truncate");
+    assert!(privacy.overridden);
+    assert_eq!(next_from(&mut rx).await, "j***@example.org");
+
+    // Turn redaction off and then restore the configured mode
+    api.put::<SubscriberPrivacy>("/api/privacy/privacy-test", &json!({"mode": "off"}))
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(next_from(&mut rx).await, "john@example.org");
+    let privacy = api
+        .delete::<SubscriberPrivacy>("/api/privacy/privacy-test")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(privacy.effective, "hash");
+    assert!(!privacy.overridden);
+    assert!(next_from(&mut rx).await.starts_with("anon-"));
+
+    // Invalid requests are rejected
+    api.put::<SubscriberPrivacy>("/api/privacy/privacy-test", &json!({"mode": "scramble"}))
+        .await
+        .unwrap()
+        .expect_error("Invalid privacy mode");
+    api.put::<SubscriberPrivacy>("/api/privacy/unknown", &json!({"mode": "hash"}))
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    Collector::remove_subscriber("privacy-test".to_string());
+    Collector::reload();
+}
+
+async fn wait_for_tracer(api: &ManagementApi) -> SubscriberPrivacy {
+    for _ in 0..50 {
+        if let Some(privacy) = api
+            .get::<PrivacyList>("/api/privacy")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items
+            .into_iter()
+            .find(|privacy| privacy.id == "privacy-test")
+        {
+            return privacy;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("Tracer was not registered");
+}
+
+async fn next_from(rx: &mut mpsc::Receiver<EventBatch>) -> String {
+    trc::event!(Smtp(SmtpEvent::MailFrom), From = "john@example.org");
+
+    let batch = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .expect("Timed out waiting for event")
+        .unwrap();
+    batch
+        .last()
+        .and_then(|event| event.value_as_str(Key::From))
+        .unwrap()
+        .to_string()
+}
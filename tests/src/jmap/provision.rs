@@ -0,0 +1,153 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{
+    core::BuildServer,
+    templates::{TemplateKind, TemplateSource, TEMPLATE_GLOBAL},
+    Server,
+};
+use jmap::{mailbox::set::MailboxSet, sieve::get::SieveScriptGet, JmapMethods};
+use jmap_client::{
+    email::{self, Property},
+    mailbox::{self, Role},
+};
+use jmap_proto::types::{collection::Collection, id::Id};
+
+use crate::{directory::internal::TestInternalDirectory, jmap::mailbox::destroy_all_mailboxes};
+
+use super::JMAPTest;
+
+const SCRIPT: &str = "require \"fileinto\";\r\nif header :contains \"Subject\" \"[spam]\" {\r\n  fileinto \"Junk Mail\";\r\n}\r\n";
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running account provisioning tests...");
+    let original_core = params.server.inner.shared_core.load_full();
+    let mut core = original_core.as_ref().clone();
+    core.jmap.provision_welcome = true;
+    core.jmap.provision_sieve = Some(("starter".to_string(), SCRIPT.to_string()));
+    params.server.inner.shared_core.store(core.into());
+    let server = params.server.inner.build_server();
+
+    // Customize the global welcome template
+    server
+        .set_template(
+            TEMPLATE_GLOBAL,
+            TemplateKind::Welcome,
+            TemplateSource {
+                from_name: Some("Support".to_string()),
+                subject: "Welcome aboard, {{name}}".to_string(),
+                body: "Your address is {{email}}.".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+    // Nothing is provisioned until the account is first accessed
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "welcome@example.com",
+            "secret",
+            "Jane Welcome",
+            &["welcome@example.com"],
+        )
+        .await;
+    assert_eq!(email_count(&server, account_id).await, 0);
+    assert!(server
+        .sieve_script_get_active(account_id)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Concurrent first accesses provision the account only once
+    let (a, b) = tokio::join!(
+        server.mailbox_get_or_create(account_id),
+        server.mailbox_get_or_create(account_id)
+    );
+    assert_eq!(a.unwrap(), b.unwrap());
+    for _ in 0..50 {
+        if email_count(&server, account_id).await > 0
+            && server
+                .sieve_script_get_active(account_id)
+                .await
+                .unwrap()
+                .is_some()
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(email_count(&server, account_id).await, 1);
+    assert_eq!(
+        server
+            .sieve_script_get_active(account_id)
+            .await
+            .unwrap()
+            .unwrap()
+            .script_name,
+        "starter"
+    );
+
+    // The welcome message is rendered into the inbox
+    let client = params
+        .client
+        .set_default_account_id(Id::from(account_id).to_string());
+    let email_id = client
+        .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email = client
+        .email_get(&email_id, [Property::Subject, Property::MailboxIds].into())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(email.subject().unwrap(), "Welcome aboard, Jane Welcome");
+    let inbox_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Inbox).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    assert_eq!(email.mailbox_ids(), [inbox_id.as_str()]);
+
+    // Deleting all mailboxes does not provision the account again
+    destroy_all_mailboxes(params).await;
+    assert_eq!(email_count(&server, account_id).await, 0);
+    assert!(!server
+        .mailbox_get_or_create(account_id)
+        .await
+        .unwrap()
+        .is_empty());
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(email_count(&server, account_id).await, 0);
+
+    // Restore configuration
+    server
+        .delete_template(TEMPLATE_GLOBAL, TemplateKind::Welcome)
+        .await
+        .unwrap();
+    params.server.inner.shared_core.store(original_core);
+}
+
+async fn email_count(server: &Server, account_id: u32) -> u64 {
+    server
+        .get_document_ids(account_id, Collection::Email)
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
@@ -11,13 +11,21 @@ use crate::{
         mailbox::destroy_all_mailboxes, test_account_login,
     },
 };
-use jmap::{blob::upload::DISABLE_UPLOAD_QUOTA, mailbox::INBOX_ID, JmapMethods};
+use directory::backend::internal::{
+    manage::{ManageDirectory, UpdatePrincipal},
+    PrincipalField, PrincipalUpdate, PrincipalValue,
+};
+use jmap::{
+    blob::upload::DISABLE_UPLOAD_QUOTA, mailbox::INBOX_ID, services::quota::QuotaRecalculation,
+    JmapMethods,
+};
 use jmap_client::{
     core::set::{SetErrorType, SetObject},
     email::EmailBodyPart,
 };
 use jmap_proto::types::{collection::Collection, id::Id};
 use smtp::queue::spool::SmtpSpool;
+use store::write::{BatchBuilder, DirectoryClass};
 
 use super::JMAPTest;
 
@@ -332,6 +340,79 @@ pub async fn test(params: &mut JMAPTest) {
             .len(),
         1,
     );
+
+    // Drifting counters are detected and corrected
+    let mut batch = BatchBuilder::new();
+    batch.add(DirectoryClass::UsedQuota(account_id.document_id()), 1000);
+    server.core.storage.data.write(batch.build()).await.unwrap();
+    let delta = server
+        .recalculate_quota(account_id.document_id(), true)
+        .await
+        .unwrap();
+    assert_eq!(delta.counter, quota + 1000);
+    assert_eq!(delta.actual, quota);
+    assert!(!delta.corrected);
+    assert_eq!(
+        server
+            .get_used_quota(account_id.document_id())
+            .await
+            .unwrap(),
+        quota + 1000
+    );
+    let deltas = server.recalculate_quota_all(None, false).await.unwrap();
+    assert!(deltas
+        .iter()
+        .any(|delta| delta.account_id == account_id.document_id() && delta.corrected));
+    assert_eq!(
+        server
+            .get_used_quota(account_id.document_id())
+            .await
+            .unwrap(),
+        quota
+    );
+    assert!(server
+        .recalculate_quota_all(None, true)
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Test message count quota
+    server
+        .core
+        .storage
+        .data
+        .update_principal(
+            UpdatePrincipal::by_name("robert@example.com").with_updates(vec![
+                PrincipalUpdate::set(
+                    PrincipalField::Quota,
+                    PrincipalValue::IntegerList(vec![0, 2]),
+                ),
+            ]),
+        )
+        .await
+        .unwrap();
+    server.inner.data.access_tokens.clear();
+    for i in 0..2 {
+        lmtp.ingest(
+            "jane@example.com",
+            &["robert@example.com"],
+            &String::from_utf8(create_message_with_size(
+                "jane@example.com",
+                "robert@example.com",
+                &format!("Count test {i}"),
+                100,
+            ))
+            .unwrap(),
+        )
+        .await;
+    }
+    assert_eq!(
+        server
+            .get_message_count(account_id.document_id())
+            .await
+            .unwrap(),
+        2,
+    );
     DISABLE_UPLOAD_QUOTA.store(true, std::sync::atomic::Ordering::Relaxed);
 
     // Remove test data
@@ -0,0 +1,274 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use jmap::{
+    mailbox::{INBOX_ID, JUNK_ID},
+    JmapMethods,
+};
+use jmap_proto::types::{collection::Collection, property::Property};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{delivery::SmtpConnection, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecallPreview {
+    items: Vec<RecallPreviewItem>,
+    total_accounts: usize,
+    total_messages: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecallPreviewItem {
+    account: String,
+    matched: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecallJob {
+    status: serde_json::Value,
+    total_accounts: usize,
+    processed_accounts: usize,
+    recalled_messages: u64,
+    results: Vec<RecallResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RecallResult {
+    account: String,
+    matched: u64,
+    recalled: u64,
+}
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running message recall tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create test accounts in two domains
+    let mut account_ids = Vec::new();
+    for (name, email) in [
+        ("recall1", "recall1@example.net"),
+        ("recall2", "recall2@example.net"),
+        ("recall3", "recall3@other.net"),
+    ] {
+        account_ids.push(
+            params
+                .server
+                .core
+                .storage
+                .data
+                .create_test_user(name, "secret", name, &[email])
+                .await,
+        );
+    }
+
+    // Deliver the same message to all accounts and an unrelated one to the first
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "phisher@remote.org",
+        &[
+            "recall1@example.net",
+            "recall2@example.net",
+            "recall3@other.net",
+        ],
+        concat!(
+            "From: phisher@remote.org\r\n",
+            "Message-ID: <wave-1@remote.org>\r\n",
+            "Subject: Reset your password\r\n",
+            "\r\n",
+            "Click here."
+        ),
+    )
+    .await;
+    lmtp.ingest(
+        "friend@remote.org",
+        &["recall1@example.net"],
+        concat!(
+            "From: friend@remote.org\r\n",
+            "Message-ID: <hello@remote.org>\r\n",
+            "Subject: Hello\r\n",
+            "\r\n",
+            "Hi there."
+        ),
+    )
+    .await;
+
+    // Preview the accounts affected in a domain
+    let preview = api
+        .post::<RecallPreview>(
+            "/api/recall",
+            &json!({
+                "messageId": "<wave-1@remote.org>",
+                "scope": {"type": "domain", "name": "example.net"},
+                "action": "preview",
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(preview.total_accounts, 2);
+    assert_eq!(preview.total_messages, 2);
+    let mut accounts = preview
+        .items
+        .iter()
+        .map(|item| {
+            assert_eq!(item.matched, 1);
+            item.account.as_str()
+        })
+        .collect::<Vec<_>>();
+    accounts.sort_unstable();
+    assert_eq!(accounts, vec!["recall1", "recall2"]);
+
+    // Previews do not modify any mailbox
+    for (account_id, expected) in account_ids.iter().zip([2, 1, 1]) {
+        assert_eq!(mailbox_count(params, *account_id, INBOX_ID).await, expected);
+    }
+
+    // Move the messages to the junk folder
+    let job = run_recall(
+        &api,
+        json!({
+            "messageId": "wave-1@remote.org",
+            "scope": {"type": "domain", "name": "example.net"},
+            "action": "junk",
+        }),
+    )
+    .await;
+    assert_eq!(job.status["type"], "completed");
+    assert_eq!(job.total_accounts, 2);
+    assert_eq!(job.processed_accounts, 2);
+    assert_eq!(job.recalled_messages, 2);
+    for result in &job.results {
+        assert_eq!((result.matched, result.recalled), (1, 1), "{result:?}");
+    }
+    for account_id in &account_ids[..2] {
+        assert_eq!(mailbox_count(params, *account_id, JUNK_ID).await, 1);
+    }
+    assert_eq!(mailbox_count(params, account_ids[0], INBOX_ID).await, 1);
+    assert_eq!(mailbox_count(params, account_ids[1], INBOX_ID).await, 0);
+    assert_eq!(mailbox_count(params, account_ids[2], INBOX_ID).await, 1);
+
+    // Messages already in the junk folder are not moved again
+    let job = run_recall(
+        &api,
+        json!({
+            "messageId": "wave-1@remote.org",
+            "scope": {"type": "domain", "name": "example.net"},
+            "action": "junk",
+        }),
+    )
+    .await;
+    assert_eq!(job.recalled_messages, 0);
+
+    // Delete the messages from all accounts
+    let job = run_recall(
+        &api,
+        json!({
+            "messageId": "wave-1@remote.org",
+            "action": "delete",
+        }),
+    )
+    .await;
+    assert_eq!(job.status["type"], "completed");
+    assert_eq!(job.total_accounts, 3);
+    assert_eq!(job.recalled_messages, 3);
+    let mut accounts = job
+        .results
+        .iter()
+        .map(|result| result.account.as_str())
+        .collect::<Vec<_>>();
+    accounts.sort_unstable();
+    assert_eq!(accounts, vec!["recall1", "recall2", "recall3"]);
+    assert_eq!(mailbox_count(params, account_ids[1], JUNK_ID).await, 0);
+    assert_eq!(mailbox_count(params, account_ids[2], INBOX_ID).await, 0);
+
+    // Deleted messages are no longer matched
+    let preview = api
+        .post::<RecallPreview>(
+            "/api/recall",
+            &json!({
+                "messageId": "wave-1@remote.org",
+                "action": "preview",
+            }),
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(preview.total_accounts, 0);
+
+    // Invalid requests are rejected
+    api.post::<RecallPreview>("/api/recall", &json!({"action": "preview"}))
+        .await
+        .unwrap()
+        .expect_error("Message-ID or a message hash is required");
+    api.post::<RecallPreview>(
+        "/api/recall",
+        &json!({"hash": "not-a-hash", "action": "preview"}),
+    )
+    .await
+    .unwrap()
+    .expect_error("Invalid message hash");
+    api.post::<RecallPreview>(
+        "/api/recall",
+        &json!({
+            "messageId": "wave-1@remote.org",
+            "scope": {"type": "domain", "name": "unknown.org"},
+            "action": "preview",
+        }),
+    )
+    .await
+    .unwrap()
+    .expect_error("notFound");
+
+    for name in ["recall1", "recall2", "recall3"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
+
+async fn run_recall(api: &ManagementApi, request: serde_json::Value) -> RecallJob {
+    let id = api
+        .post::<u64>("/api/recall", &request)
+        .await
+        .unwrap()
+        .unwrap_data();
+
+    for _ in 0..50 {
+        let job = api
+            .get::<RecallJob>(&format!("/api/recall/{id}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+        if job.status["type"] != "running" {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("Recall {id} did not finish");
+}
+
+async fn mailbox_count(params: &JMAPTest, account_id: u32, mailbox_id: u32) -> u64 {
+    params
+        .server
+        .get_tag(
+            account_id,
+            Collection::Email,
+            Property::MailboxIds,
+            mailbox_id,
+        )
+        .await
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
@@ -0,0 +1,195 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::backend::internal::{
+    manage::{ManageDirectory, RoleAction, RoleChange, RoleDefinition, RoleDocument},
+    PrincipalField,
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running declarative roles tests...");
+    let server = params.server.clone();
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    let document = RoleDocument {
+        roles: vec![
+            role(
+                "helpdesk",
+                &["principal-get", "principal-list"],
+                &["restart"],
+                &["support"],
+            ),
+            role("support", &["Message-Queue-List"], &[], &["user"]),
+            role("auditor", &["undo-list", "principal-list"], &[], &[]),
+        ],
+    };
+
+    // Dry runs only produce a change plan
+    let plan = apply(&api, &document, "?dry-run=true").await;
+    assert_eq!(
+        plan,
+        vec![
+            change(
+                "auditor",
+                RoleAction::Create,
+                &[PrincipalField::EnabledPermissions]
+            ),
+            change(
+                "helpdesk",
+                RoleAction::Create,
+                &[
+                    PrincipalField::EnabledPermissions,
+                    PrincipalField::DisabledPermissions,
+                    PrincipalField::Roles
+                ]
+            ),
+            change(
+                "support",
+                RoleAction::Create,
+                &[PrincipalField::EnabledPermissions, PrincipalField::Roles]
+            ),
+        ]
+    );
+    assert!(export(&api).await.roles.is_empty());
+
+    // Apply the document, nested roles are created first
+    assert_eq!(apply(&api, &document, "").await, plan);
+    let exported = export(&api).await;
+    assert_eq!(
+        exported.roles,
+        vec![
+            role("auditor", &["principal-list", "undo-list"], &[], &[]),
+            role(
+                "helpdesk",
+                &["principal-get", "principal-list"],
+                &["restart"],
+                &["support"]
+            ),
+            role("support", &["message-queue-list"], &[], &["user"]),
+        ]
+    );
+    assert_eq!(
+        server.core.storage.data.export_roles(None).await.unwrap(),
+        exported
+    );
+
+    // Applying the same document is a no-op
+    assert!(apply(&api, &exported, "")
+        .await
+        .iter()
+        .all(|c| c.action == RoleAction::Unchanged));
+
+    // Update permissions and report extra roles
+    let mut document = exported.clone();
+    document.roles.retain(|r| r.name != "auditor");
+    document.roles[0].description = Some("First line support".to_string());
+    document.roles[0].disabled_permissions.clear();
+    assert_eq!(
+        apply(&api, &document, "").await,
+        vec![
+            change("auditor", RoleAction::Extra, &[]),
+            change(
+                "helpdesk",
+                RoleAction::Update,
+                &[
+                    PrincipalField::Description,
+                    PrincipalField::DisabledPermissions
+                ]
+            ),
+            change("support", RoleAction::Unchanged, &[]),
+        ]
+    );
+    let exported = export(&api).await;
+    assert_eq!(exported.roles.len(), 3);
+    assert_eq!(
+        exported.roles[1].description.as_deref(),
+        Some("First line support")
+    );
+    assert!(exported.roles[1].disabled_permissions.is_empty());
+
+    // Extra roles are only removed when pruning
+    assert_eq!(
+        apply(&api, &document, "?prune=true").await[0],
+        change("auditor", RoleAction::Delete, &[])
+    );
+    assert_eq!(export(&api).await, document);
+
+    // Invalid documents are rejected before any changes are made
+    for (roles, expected) in [
+        (
+            vec![
+                role("auditor", &["undo-list"], &[], &[]),
+                role("broken", &["not-a-permission"], &[], &[]),
+            ],
+            "not-a-permission",
+        ),
+        (
+            vec![
+                role("auditor", &["undo-list"], &[], &[]),
+                role("orphan", &[], &[], &["nobody"]),
+            ],
+            "notFound",
+        ),
+        (
+            vec![
+                role("auditor", &["undo-list"], &[], &["loop"]),
+                role("loop", &[], &[], &["auditor"]),
+            ],
+            "Circular role definition",
+        ),
+        (
+            vec![
+                role("auditor", &["undo-list"], &[], &[]),
+                role("Auditor", &[], &[], &[]),
+            ],
+            "fieldAlreadyExists",
+        ),
+    ] {
+        api.post::<Vec<RoleChange>>("/api/roles/apply", &RoleDocument { roles })
+            .await
+            .unwrap()
+            .expect_error(expected);
+    }
+    assert_eq!(export(&api).await, document);
+
+    // Remove test data
+    apply(&api, &RoleDocument::default(), "?prune=true").await;
+    assert!(export(&api).await.roles.is_empty());
+}
+
+async fn apply(api: &ManagementApi, document: &RoleDocument, query: &str) -> Vec<RoleChange> {
+    api.post::<Vec<RoleChange>>(&format!("/api/roles/apply{query}"), document)
+        .await
+        .unwrap()
+        .unwrap_data()
+}
+
+async fn export(api: &ManagementApi) -> RoleDocument {
+    api.get::<RoleDocument>("/api/roles/export")
+        .await
+        .unwrap()
+        .unwrap_data()
+}
+
+fn role(name: &str, enabled: &[&str], disabled: &[&str], roles: &[&str]) -> RoleDefinition {
+    RoleDefinition {
+        name: name.to_string(),
+        description: None,
+        enabled_permissions: enabled.iter().map(|s| s.to_string()).collect(),
+        disabled_permissions: disabled.iter().map(|s| s.to_string()).collect(),
+        roles: roles.iter().map(|s| s.to_string()).collect(),
+    }
+}
+
+fn change(name: &str, action: RoleAction, fields: &[PrincipalField]) -> RoleChange {
+    RoleChange {
+        name: name.to_string(),
+        action,
+        fields: fields.to_vec(),
+    }
+}
@@ -8,7 +8,7 @@ use std::{sync::Arc, time::Duration};
 
 use crate::jmap::{mailbox::destroy_all_mailboxes_no_wait, wait_for_index};
 use common::Server;
-use directory::backend::internal::manage::ManageDirectory;
+use directory::backend::internal::manage::{CreateRetry, ManageDirectory};
 use futures::future::join_all;
 use jmap::{mailbox::UidMailbox, JmapMethods};
 use jmap_client::{
@@ -30,7 +30,7 @@ pub async fn test(server: Server, mut client: Client) {
         .core
         .storage
         .data
-        .get_or_create_principal_id("john", directory::Type::Individual)
+        .get_or_create_principal_id("john", directory::Type::Individual, CreateRetry::default())
         .await
         .unwrap();
     client.set_default_account_id(Id::from(TEST_USER_ID).to_string());
@@ -0,0 +1,173 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    io::{Cursor, Read},
+    time::Duration,
+};
+
+use directory::{
+    backend::internal::{PrincipalField, PrincipalValue},
+    Principal, Type,
+};
+use serde::Deserialize;
+
+use super::{delivery::SmtpConnection, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportStatus {
+    status: String,
+    accounts: usize,
+    completed: usize,
+    url: Option<String>,
+}
+
+pub async fn test(_params: &JMAPTest) {
+    println!("Running tenant export tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create a tenant with one account
+    for principal in [
+        Principal::new(u32::MAX, Type::Tenant).with_field(PrincipalField::Name, "exportco"),
+        Principal::new(u32::MAX, Type::Domain)
+            .with_field(PrincipalField::Name, "exportco.org")
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("exportco".to_string()),
+            ),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "jane@exportco.org")
+            .with_field(
+                PrincipalField::Emails,
+                vec!["jane@exportco.org".to_string()],
+            )
+            .with_field(
+                PrincipalField::Secrets,
+                PrincipalValue::String("export-secret".to_string()),
+            )
+            .with_field(PrincipalField::Roles, vec!["user".to_string()])
+            .with_field(
+                PrincipalField::Tenant,
+                PrincipalValue::String("exportco".to_string()),
+            ),
+    ] {
+        api.post::<u32>("/api/principal", &principal)
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "sender@remote.org",
+        &["jane@exportco.org"],
+        concat!(
+            "From: sender@remote.org\r\n",
+            "To: jane@exportco.org\r\n",
+            "Subject: Exported\r\n",
+            "\r\n",
+            "Keep this message."
+        ),
+    )
+    .await;
+
+    // Nothing has been exported yet
+    api.get::<ExportStatus>("/api/export/tenant/exportco")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Start the export and wait for it to finish
+    let mut status = api
+        .post::<ExportStatus>("/api/export/tenant/exportco", &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(status.accounts, 1);
+    for _ in 0..50 {
+        if status.status == "completed" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        status = api
+            .get::<ExportStatus>("/api/export/tenant/exportco")
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    assert_eq!(status.status, "completed", "{status:?}");
+    assert_eq!(status.completed, 1);
+    let url = status.url.unwrap();
+
+    // Tampered links are rejected
+    assert_eq!(
+        download(&url.replace("signature=", "signature=x")).await.0,
+        403
+    );
+
+    // Download the bundle using the signed link
+    let (code, bundle) = download(&url).await;
+    assert_eq!(code, 200);
+    let mut bundle = zip::ZipArchive::new(Cursor::new(bundle)).unwrap();
+    let directory = read_entry(&mut bundle, "directory.json");
+    let directory = String::from_utf8(directory).unwrap();
+    assert!(directory.contains("jane@exportco.org"), "{directory}");
+    assert!(!directory.contains("export-secret"), "{directory}");
+    let statistics =
+        serde_json::from_slice::<serde_json::Value>(&read_entry(&mut bundle, "statistics.json"))
+            .unwrap();
+    assert_eq!(statistics["accounts"], 1);
+    assert_eq!(statistics["messages"], 1);
+    let account_name = bundle
+        .file_names()
+        .find(|name| name.starts_with("accounts/"))
+        .unwrap()
+        .to_string();
+    let mut account =
+        zip::ZipArchive::new(Cursor::new(read_entry(&mut bundle, &account_name))).unwrap();
+    let messages =
+        serde_json::from_slice::<serde_json::Value>(&read_entry(&mut account, "messages.json"))
+            .unwrap();
+    assert_eq!(messages[0]["mailboxes"][0], "Inbox");
+    let message = read_entry(&mut account, messages[0]["file"].as_str().unwrap());
+    assert!(String::from_utf8(message)
+        .unwrap()
+        .contains("Keep this message."));
+
+    // Clean up
+    for name in ["jane@exportco.org", "exportco.org", "exportco"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+}
+
+async fn download(url: &str) -> (u16, Vec<u8>) {
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .get(format!("https://127.0.0.1:8899{url}"))
+        .send()
+        .await
+        .unwrap();
+    (
+        response.status().as_u16(),
+        response.bytes().await.unwrap().to_vec(),
+    )
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<Cursor<Vec<u8>>>, name: &str) -> Vec<u8> {
+    let mut contents = Vec::new();
+    archive
+        .by_name(name)
+        .unwrap()
+        .read_to_end(&mut contents)
+        .unwrap();
+    contents
+}
@@ -0,0 +1,333 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::{auth::AccessToken, Server};
+use jmap::{
+    email::ingest::{EmailIngest, IngestEmail, IngestSource},
+    mailbox::{get::MailboxGet, INBOX_ID},
+    thread::rules::ThreadRuleStore,
+    JmapMethods,
+};
+use jmap_proto::types::{collection::Collection, id::Id, keyword::Keyword, property::Property};
+use mail_parser::MessageParser;
+use store::write::TagValue;
+
+use crate::{
+    directory::internal::TestInternalDirectory,
+    jmap::{assert_is_empty, jmap_json_request, mailbox::destroy_all_mailboxes},
+};
+
+use super::{JMAPTest, ManagementApi};
+
+pub async fn test(params: &mut JMAPTest) {
+    println!("Running Thread Rules tests...");
+    let server = params.server.clone();
+    let account_id = server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "threads@example.com",
+            "12345",
+            "Thread Rules",
+            &["threads@example.com"],
+        )
+        .await;
+    let account = Id::from(account_id).to_string();
+
+    // Create a folder for filed threads, default folders are created as well
+    let response = jmap_json_request(
+        r#"[[ "Mailbox/set", { "accountId": "$$", "create": { "m1": { "name": "Lists" } } }, "R1" ]]"#
+            .replace("$$", &account),
+        "threads@example.com",
+        "12345",
+    )
+    .await;
+    let lists_id = Id::from_bytes(
+        response
+            .pointer("/methodResponses/0/1/created/m1/id")
+            .and_then(|v| v.as_str())
+            .unwrap_or_else(|| panic!("Response: {response:?}"))
+            .as_bytes(),
+    )
+    .unwrap()
+    .document_id();
+    let archive_id = server
+        .mailbox_get_by_role(account_id, "archive")
+        .await
+        .unwrap()
+        .expect("missing archive folder");
+
+    // Start three conversations
+    let mut thread_ids = Vec::new();
+    for name in ["mute", "move", "flag"] {
+        let id = ingest(
+            &server,
+            account_id,
+            &format!("Message-ID: <{name}-1@test>\r\nSubject: Please {name}\r\n\r\nHello\r\n"),
+        )
+        .await;
+        thread_ids.push(Id::from(id.prefix_id()).to_string());
+    }
+
+    // Create rules, invalid ones are rejected
+    let response = jmap_json_request(
+        r#"[[ "ThreadRule/set", { "accountId": "$$", "create": {
+                "r1": { "threadId": "T1", "type": "mute" },
+                "r2": { "threadId": "T2", "type": "move", "mailboxId": "M1" },
+                "r3": { "threadId": "T3", "type": "flag" },
+                "r4": { "threadId": "T1", "type": "flag" },
+                "r5": { "threadId": "T2", "type": "move" },
+                "r6": { "threadId": "T9", "type": "mute" },
+                "r7": { "threadId": "T3", "type": "snooze" }
+            } }, "R1" ]]"#
+            .replace("$$", &account)
+            .replace("T1", &thread_ids[0])
+            .replace("T2", &thread_ids[1])
+            .replace("T3", &thread_ids[2])
+            .replace("T9", &Id::from(9999u32).to_string())
+            .replace("M1", &Id::from(lists_id).to_string()),
+        "threads@example.com",
+        "12345",
+    )
+    .await;
+    for (create_id, expected) in [
+        ("r1", Some(thread_ids[0].as_str())),
+        ("r2", Some(thread_ids[1].as_str())),
+        ("r3", Some(thread_ids[2].as_str())),
+    ] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/created/{create_id}/id"))
+                .and_then(|v| v.as_str()),
+            expected,
+            "Response: {response:?}"
+        );
+    }
+    for (create_id, error) in [
+        ("r4", "alreadyExists"),
+        ("r5", "invalidProperties"),
+        ("r6", "invalidProperties"),
+        ("r7", "invalidProperties"),
+    ] {
+        assert_eq!(
+            response
+                .pointer(&format!("/methodResponses/0/1/notCreated/{create_id}/type"))
+                .and_then(|v| v.as_str()),
+            Some(error),
+            "Response: {response:?}"
+        );
+    }
+
+    // Rules are listed through JMAP
+    let response = jmap_json_request(
+        r#"[[ "ThreadRule/get", { "accountId": "$$", "ids": null }, "R1" ]]"#
+            .replace("$$", &account),
+        "threads@example.com",
+        "12345",
+    )
+    .await;
+    let list = response
+        .pointer("/methodResponses/0/1/list")
+        .and_then(|v| v.as_array())
+        .unwrap_or_else(|| panic!("Response: {response:?}"));
+    assert_eq!(list.len(), 3, "Response: {response:?}");
+    assert_eq!(
+        list[1].get("mailboxId").and_then(|v| v.as_str()),
+        Some(Id::from(lists_id).to_string().as_str())
+    );
+
+    // Replies are filed according to the rules
+    let mut replies = Vec::new();
+    for name in ["mute", "move", "flag"] {
+        replies.push(
+            ingest(
+                &server,
+                account_id,
+                &format!(
+                    concat!(
+                        "Message-ID: <{name}-2@test>\r\nIn-Reply-To: <{name}-1@test>\r\n",
+                        "Subject: Re: Please {name}\r\n\r\nReply\r\n"
+                    ),
+                    name = name
+                ),
+            )
+            .await,
+        );
+    }
+    for (pos, id) in replies.iter().enumerate() {
+        assert_eq!(Id::from(id.prefix_id()).to_string(), thread_ids[pos]);
+    }
+    let muted = replies[0].document_id();
+    assert!(has_tag(&server, account_id, Property::MailboxIds, archive_id, muted).await);
+    assert!(!has_tag(&server, account_id, Property::MailboxIds, INBOX_ID, muted).await);
+    assert!(
+        has_tag(
+            &server,
+            account_id,
+            Property::Keywords,
+            Keyword::Seen,
+            muted
+        )
+        .await
+    );
+    let moved = replies[1].document_id();
+    assert!(has_tag(&server, account_id, Property::MailboxIds, lists_id, moved).await);
+    assert!(!has_tag(&server, account_id, Property::MailboxIds, INBOX_ID, moved).await);
+    assert!(
+        !has_tag(
+            &server,
+            account_id,
+            Property::Keywords,
+            Keyword::Seen,
+            moved
+        )
+        .await
+    );
+    let flagged = replies[2].document_id();
+    assert!(has_tag(&server, account_id, Property::MailboxIds, INBOX_ID, flagged).await);
+    assert!(
+        has_tag(
+            &server,
+            account_id,
+            Property::Keywords,
+            Keyword::Flagged,
+            flagged
+        )
+        .await
+    );
+
+    // Update and destroy rules
+    let response = jmap_json_request(
+        r#"[[ "ThreadRule/set", { "accountId": "$$",
+                "update": { "T1": { "type": "flag" } },
+                "destroy": [ "T2" ] }, "R1" ]]"#
+            .replace("$$", &account)
+            .replace("T1", &thread_ids[0])
+            .replace("T2", &thread_ids[1]),
+        "threads@example.com",
+        "12345",
+    )
+    .await;
+    assert_eq!(
+        response
+            .pointer("/methodResponses/0/1/destroyed/0")
+            .and_then(|v| v.as_str()),
+        Some(thread_ids[1].as_str()),
+        "Response: {response:?}"
+    );
+    let rules = server.thread_rules(account_id).await.unwrap();
+    assert_eq!(rules.rules.len(), 2);
+    assert_eq!(
+        rules
+            .get(
+                Id::from_bytes(thread_ids[0].as_bytes())
+                    .unwrap()
+                    .document_id()
+            )
+            .unwrap()
+            .action
+            .as_str(),
+        "flag"
+    );
+
+    // Rules can be reviewed and removed from the account settings
+    let api = ManagementApi::new(8899, "threads@example.com", "12345");
+    let listing = api
+        .get::<Vec<serde_json::Value>>("/api/account/thread-rules")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(listing.len(), 2, "{listing:?}");
+    assert!(listing.iter().all(|rule| rule.get("expiresAt").is_some()));
+    api.delete::<()>(&format!("/api/account/thread-rules/{}", thread_ids[2]))
+        .await
+        .unwrap()
+        .unwrap_data();
+    let rules = server.thread_rules(account_id).await.unwrap();
+    assert_eq!(rules.rules.len(), 1);
+
+    // Rules of idle threads expire
+    let mut rules = rules;
+    rules.rules[0].last_activity = 0;
+    server.write_thread_rules(account_id, rules).await.unwrap();
+    assert!(server
+        .thread_rules(account_id)
+        .await
+        .unwrap()
+        .rules
+        .is_empty());
+    let id = ingest(
+        &server,
+        account_id,
+        concat!(
+            "Message-ID: <mute-3@test>\r\nIn-Reply-To: <mute-2@test>\r\n",
+            "Subject: Re: Please mute\r\n\r\nReply\r\n"
+        ),
+    )
+    .await;
+    assert!(
+        has_tag(
+            &server,
+            account_id,
+            Property::MailboxIds,
+            INBOX_ID,
+            id.document_id()
+        )
+        .await
+    );
+    assert!(
+        !has_tag(
+            &server,
+            account_id,
+            Property::Keywords,
+            Keyword::Flagged,
+            id.document_id()
+        )
+        .await
+    );
+
+    // Remove test data
+    api.delete::<()>("/api/account/thread-rules")
+        .await
+        .unwrap()
+        .unwrap_data();
+    params.client.set_default_account_id(account.as_str());
+    destroy_all_mailboxes(params).await;
+    assert_is_empty(server).await;
+}
+
+async fn ingest(server: &Server, account_id: u32, message: &str) -> Id {
+    server
+        .email_ingest(IngestEmail {
+            raw_message: message.as_bytes(),
+            message: MessageParser::new().parse(message.as_bytes()),
+            resource: AccessToken::from_id(account_id).as_resource_token(),
+            mailbox_ids: vec![INBOX_ID],
+            keywords: vec![],
+            received_at: None,
+            source: IngestSource::Smtp,
+            encrypt: false,
+            session_id: 0,
+        })
+        .await
+        .unwrap()
+        .id
+}
+
+async fn has_tag(
+    server: &Server,
+    account_id: u32,
+    property: Property,
+    value: impl Into<TagValue<u32>> + Sync + Send,
+    document_id: u32,
+) -> bool {
+    server
+        .get_tag(account_id, Collection::Email, property, value)
+        .await
+        .unwrap()
+        .is_some_and(|ids| ids.contains(document_id))
+}
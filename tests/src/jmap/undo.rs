@@ -0,0 +1,171 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{PrincipalField, PrincipalUpdate, PrincipalValue},
+    Principal, Type,
+};
+use serde::Deserialize;
+
+use super::{enterprise::List, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UndoEntry {
+    id: u64,
+    operation: String,
+    principal_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UndoResult {
+    restored: Vec<String>,
+    not_restored: Vec<serde_json::Value>,
+}
+
+pub async fn test(_params: &JMAPTest) {
+    println!("Running undo log tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Create test principals
+    for principal in [
+        Principal::new(u32::MAX, Type::Group).with_field(PrincipalField::Name, "undo_group"),
+        Principal::new(u32::MAX, Type::Individual)
+            .with_field(PrincipalField::Name, "undo_user")
+            .with_field(PrincipalField::Description, "Original"),
+    ] {
+        api.post::<u32>("/api/principal", &principal)
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+
+    // Undo a bulk change
+    api.patch::<()>(
+        "/api/principal/undo_user",
+        &vec![
+            PrincipalUpdate::set(
+                PrincipalField::Description,
+                PrincipalValue::String("Changed".to_string()),
+            ),
+            PrincipalUpdate::add_item(
+                PrincipalField::MemberOf,
+                PrincipalValue::String("undo_group".to_string()),
+            ),
+        ],
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let entry = last_entry(&api, "undo_user", "update").await;
+    let result = api
+        .post::<UndoResult>(&format!("/api/undo/{}", entry.id), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(result.restored, vec!["description", "memberOf"]);
+    assert!(result.not_restored.is_empty());
+    let principal = api
+        .get::<Principal>("/api/principal/undo_user")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(principal.description(), Some("Original"));
+    assert!(principal.get_str_array(PrincipalField::MemberOf).is_none());
+
+    // Operations cannot be undone twice
+    api.post::<UndoResult>(&format!("/api/undo/{}", entry.id), &())
+        .await
+        .unwrap()
+        .expect_error("already undone");
+
+    // Intervening changes to the same field require force
+    for description in ["First", "Second"] {
+        api.patch::<()>(
+            "/api/principal/undo_user",
+            &vec![PrincipalUpdate::set(
+                PrincipalField::Description,
+                PrincipalValue::String(description.to_string()),
+            )],
+        )
+        .await
+        .unwrap()
+        .unwrap_data();
+    }
+    let entries = list_entries(&api).await;
+    let first = entries
+        .iter()
+        .filter(|e| e.principal_name == "undo_user" && e.operation == "update")
+        .nth(1)
+        .unwrap();
+    api.post::<UndoResult>(&format!("/api/undo/{}", first.id), &())
+        .await
+        .unwrap()
+        .expect_error("Intervening changes");
+    api.post::<UndoResult>(&format!("/api/undo/{}?force=true", first.id), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(
+        api.get::<Principal>("/api/principal/undo_user")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .description(),
+        Some("Original")
+    );
+
+    // Undoing a creation deletes the principal
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Group).with_field(PrincipalField::Name, "undo_temp"),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    let entry = last_entry(&api, "undo_temp", "create").await;
+    api.post::<UndoResult>(&format!("/api/undo/{}", entry.id), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.get::<Principal>("/api/principal/undo_temp")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Deletions report that nothing could be restored
+    for name in ["undo_user", "undo_group"] {
+        api.delete::<()>(&format!("/api/principal/{name}"))
+            .await
+            .unwrap()
+            .unwrap_data();
+    }
+    let entry = last_entry(&api, "undo_user", "delete").await;
+    let result = api
+        .post::<UndoResult>(&format!("/api/undo/{}", entry.id), &())
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert!(result.restored.is_empty());
+    assert_eq!(result.not_restored.len(), 1);
+}
+
+async fn list_entries(api: &ManagementApi) -> Vec<UndoEntry> {
+    api.get::<List<UndoEntry>>("/api/undo")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items
+}
+
+async fn last_entry(api: &ManagementApi, name: &str, operation: &str) -> UndoEntry {
+    list_entries(api)
+        .await
+        .into_iter()
+        .find(|e| e.principal_name == name && e.operation == operation)
+        .unwrap_or_else(|| panic!("No {operation} entry found for {name}"))
+}
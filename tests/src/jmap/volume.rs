@@ -0,0 +1,149 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{backend::internal::PrincipalField, Principal, Type};
+use hyper::Method;
+use serde::Deserialize;
+
+use crate::directory::internal::TestInternalDirectory;
+
+use super::{delivery::SmtpConnection, enterprise::List, JMAPTest, ManagementApi};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VolumeItem {
+    period: String,
+    domain: String,
+    received: u64,
+    bytes_received: u64,
+    sent: u64,
+    rejected: u64,
+}
+
+pub async fn test(params: &JMAPTest) {
+    println!("Running message volume statistics tests...");
+    let api = ManagementApi::new(8899, "admin", "secret");
+
+    // Only domains registered in the directory are tracked
+    api.post::<u32>(
+        "/api/principal",
+        &Principal::new(u32::MAX, Type::Domain).with_field(PrincipalField::Name, "volume.org"),
+    )
+    .await
+    .unwrap()
+    .unwrap_data();
+    params
+        .server
+        .core
+        .storage
+        .data
+        .create_test_user(
+            "user@volume.org",
+            "secret",
+            "Volume Test",
+            &["user@volume.org"],
+        )
+        .await;
+    let before = domain_volume(&api, "volume.org").await;
+
+    // Deliver two messages
+    let message = concat!(
+        "From: sender@remote.org\r\n",
+        "To: user@volume.org\r\n",
+        "Subject: Volume\r\n",
+        "\r\n",
+        "Counting messages."
+    );
+    let mut lmtp = SmtpConnection::connect().await;
+    for _ in 0..2 {
+        lmtp.ingest("sender@remote.org", &["user@volume.org"], message)
+            .await;
+    }
+    let after = domain_volume(&api, "volume.org").await;
+    assert_eq!(after.period, "day");
+    assert_eq!(after.received, before.received + 2);
+    assert!(after.bytes_received >= before.bytes_received + 2 * message.len() as u64);
+    assert_eq!(after.sent, before.sent);
+    assert_eq!(after.rejected, before.rejected);
+
+    // Sender domains that are not local are not tracked
+    assert!(!list_volume(&api, "")
+        .await
+        .iter()
+        .any(|item| item.domain == "remote.org"));
+
+    // Export as CSV
+    let csv = api
+        .request_raw(
+            Method::GET,
+            "/api/volume?format=csv&domain=volume.org",
+            None,
+        )
+        .await
+        .unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("period,date,domain,tenant_id,received,sent,bytes_received,bytes_sent,rejected")
+    );
+    assert!(lines
+        .next()
+        .unwrap()
+        .contains(&format!(",volume.org,,{},", after.received)));
+
+    // Invalid ranges are rejected
+    api.get::<List<VolumeItem>>("/api/volume?from=2024-02-01&to=2024-01-01")
+        .await
+        .unwrap()
+        .expect_error("Invalid date range");
+    api.get::<List<VolumeItem>>("/api/volume?from=yesterday")
+        .await
+        .unwrap()
+        .expect_error("Invalid date");
+
+    // Unknown tenants are not found
+    api.get::<List<VolumeItem>>("/api/volume?tenant=nobody")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+
+    // Domains in use are only deleted along with their accounts
+    api.delete::<()>("/api/principal/volume.org")
+        .await
+        .unwrap()
+        .expect_error("Domain has addresses");
+    api.delete::<()>("/api/principal/volume.org?cascade=true")
+        .await
+        .unwrap()
+        .unwrap_data();
+    api.get::<Principal>("/api/principal/user@volume.org")
+        .await
+        .unwrap()
+        .expect_error("notFound");
+}
+
+async fn list_volume(api: &ManagementApi, query: &str) -> Vec<VolumeItem> {
+    api.get::<List<VolumeItem>>(&format!("/api/volume{query}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items
+}
+
+async fn domain_volume(api: &ManagementApi, domain: &str) -> VolumeItem {
+    list_volume(api, &format!("?domain={domain}"))
+        .await
+        .into_iter()
+        .last()
+        .unwrap_or(VolumeItem {
+            period: "day".to_string(),
+            domain: domain.to_string(),
+            received: 0,
+            bytes_received: 0,
+            sent: 0,
+            rejected: 0,
+        })
+}
@@ -0,0 +1,244 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::{
+        internal::{
+            lookup::DirectoryStore,
+            manage::{ManageDirectory, UpdatePrincipal},
+            PrincipalField, PrincipalUpdate, PrincipalValue,
+        },
+        RcptType,
+    },
+    Principal, Type,
+};
+use smtp::core::Session;
+
+use crate::smtp::{
+    session::{DummyIo, TestSession},
+    TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[directory."internal"]
+type = "internal"
+store = "sqlite"
+
+[session.rcpt]
+directory = "'internal'"
+relay = true
+
+[session.rcpt.alias]
+max-fan-out = 3
+"#;
+
+#[tokio::test]
+async fn rcpt_alias() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_rcpt_alias", CONFIG).await;
+    let store = &test.server.core.storage.data;
+
+    // Create a domain, an individual and a list
+    store
+        .create_principal(
+            Principal::new(0, Type::Domain).with_field(PrincipalField::Name, "example.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .create_principal(
+            Principal::new(0, Type::Individual)
+                .with_field(PrincipalField::Name, "jane")
+                .with_field(PrincipalField::Emails, "jane@example.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .create_principal(
+            Principal::new(0, Type::List)
+                .with_field(PrincipalField::Name, "team")
+                .with_field(PrincipalField::Emails, "team@example.org")
+                .with_field(
+                    PrincipalField::ExternalMembers,
+                    vec!["bob@remote.org".to_string()],
+                ),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .update_principal(UpdatePrincipal::by_name("team").with_updates(vec![
+            PrincipalUpdate::add_item(
+                PrincipalField::Members,
+                PrincipalValue::String("jane".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+
+    // Create aliases
+    for (name, forward_to) in [
+        ("fwd", vec!["ext@remote.org", "jane@example.org"]),
+        (
+            "nested",
+            vec!["fwd@example.org", "team@example.org", "carol@remote.org"],
+        ),
+        ("loop-a", vec!["loop-b@example.org"]),
+        ("loop-b", vec!["loop-a@example.org"]),
+        ("loop-c", vec!["loop-a@example.org", "dave@remote.org"]),
+        (
+            "wide",
+            vec![
+                "w1@remote.org",
+                "w2@remote.org",
+                "w3@remote.org",
+                "w4@remote.org",
+            ],
+        ),
+    ] {
+        store
+            .create_principal(alias(name, forward_to), None, None)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        store.rcpt("fwd@example.org").await.unwrap(),
+        RcptType::Alias(vec![
+            "ext@remote.org".to_string(),
+            "jane@example.org".to_string()
+        ])
+    );
+    assert_eq!(
+        store
+            .list_principals(None, None, &[Type::Alias], &[PrincipalField::Name], 0, 0)
+            .await
+            .unwrap()
+            .total,
+        6
+    );
+
+    // Aliases need valid destinations, other principals cannot forward
+    assert!(store
+        .create_principal(alias("empty", vec![]), None, None)
+        .await
+        .is_err());
+    assert!(store
+        .create_principal(alias("invalid", vec!["not-an-address"]), None, None)
+        .await
+        .is_err());
+    for updates in [
+        vec![PrincipalUpdate::set(
+            PrincipalField::ForwardTo,
+            PrincipalValue::StringList(vec![]),
+        )],
+        vec![PrincipalUpdate::remove_item(
+            PrincipalField::ForwardTo,
+            PrincipalValue::String("loop-b@example.org".to_string()),
+        )],
+    ] {
+        assert!(store
+            .update_principal(UpdatePrincipal::by_name("loop-a").with_updates(updates))
+            .await
+            .is_err());
+    }
+    assert!(store
+        .update_principal(UpdatePrincipal::by_name("jane").with_updates(vec![
+            PrincipalUpdate::add_item(
+                PrincipalField::ForwardTo,
+                PrincipalValue::String("ext@remote.org".to_string()),
+            )
+        ]))
+        .await
+        .is_err());
+
+    // Aliases are expanded to their destinations
+    let mut session = new_session(&test).await;
+    session.rcpt_to("fwd@example.org", "250").await;
+    assert_eq!(rcpt_to(&session), ["ext@remote.org", "jane@example.org"]);
+
+    // Nested aliases and lists are followed
+    let mut session = new_session(&test).await;
+    session.rcpt_to("nested@example.org", "250").await;
+    assert_eq!(
+        rcpt_to(&session),
+        [
+            "bob@remote.org",
+            "carol@remote.org",
+            "ext@remote.org",
+            "jane@example.org"
+        ]
+    );
+
+    // Loops are skipped, aliases that only loop are rejected
+    let mut session = new_session(&test).await;
+    session.rcpt_to("loop-a@example.org", "550 5.4.6").await;
+    session.rcpt_to("loop-c@example.org", "250").await;
+    assert_eq!(rcpt_to(&session), ["dave@remote.org"]);
+
+    // Fan-out is limited
+    session.rcpt_to("wide@example.org", "550 5.5.3").await;
+    assert_eq!(rcpt_to(&session), ["dave@remote.org"]);
+
+    // Destinations can be updated
+    store
+        .update_principal(UpdatePrincipal::by_name("wide").with_updates(vec![
+            PrincipalUpdate::remove_item(
+                PrincipalField::ForwardTo,
+                PrincipalValue::String("w4@remote.org".to_string()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    let mut session = new_session(&test).await;
+    session.rcpt_to("wide@example.org", "250").await;
+    assert_eq!(
+        rcpt_to(&session),
+        ["w1@remote.org", "w2@remote.org", "w3@remote.org"]
+    );
+}
+
+fn alias(name: &str, forward_to: Vec<&str>) -> Principal {
+    Principal::new(0, Type::Alias)
+        .with_field(PrincipalField::Name, name)
+        .with_field(PrincipalField::Emails, format!("{name}@example.org"))
+        .with_opt_field(
+            PrincipalField::ForwardTo,
+            Some(
+                forward_to
+                    .into_iter()
+                    .map(|address| address.to_string())
+                    .collect::<Vec<_>>(),
+            )
+            .filter(|forward_to| !forward_to.is_empty()),
+        )
+}
+
+async fn new_session(test: &TestSMTP) -> Session<DummyIo> {
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.org").await;
+    session.mail_from("sender@example.org", "250").await;
+    session
+}
+
+fn rcpt_to(session: &Session<DummyIo>) -> Vec<String> {
+    let mut rcpt_to = session
+        .data
+        .rcpt_to
+        .iter()
+        .map(|rcpt| rcpt.address_lcase.clone())
+        .collect::<Vec<_>>();
+    rcpt_to.sort_unstable();
+    rcpt_to
+}
@@ -0,0 +1,202 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::io::{Cursor, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{TestSession, VerifyResponse},
+    TestSMTP,
+};
+
+const REJECT: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.data.attachments.global]
+direction = "delivery"
+max-size = 1024
+banned-extensions = ["exe", ".bat"]
+banned-types = ["application/x-msdos-program"]
+"#;
+
+const WARN: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.data.attachments.global]
+action = "warn"
+banned-extensions = ["exe"]
+"#;
+
+#[tokio::test]
+async fn attachment_policy() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_attachment_reject", REJECT).await;
+    let mut session = test.new_session();
+    let mut qr = test.queue_receiver;
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+
+    // Allowed attachments are accepted
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &message("report.pdf", "application/pdf", b"%PDF-1.4 test"),
+            "250",
+        )
+        .await;
+    qr.expect_message().await;
+
+    // Banned extensions and content types are rejected
+    for (name, content_type) in [
+        ("invoice.exe", "application/octet-stream"),
+        ("INSTALL.BAT", "application/octet-stream"),
+        ("install.com", "application/x-msdos-program"),
+    ] {
+        session.mail_from("john@doe.org", "250").await;
+        session.rcpt_to("bill@foobar.org", "250").await;
+        session.ingest(b"DATA\r\n").await.unwrap();
+        session.response().assert_code("354");
+        session
+            .ingest(message(name, content_type, b"hello").as_bytes())
+            .await
+            .unwrap();
+        session.ingest(b"\r\n.\r\n").await.unwrap();
+        session
+            .response()
+            .assert_code("550 5.7.1")
+            .assert_contains(name);
+    }
+
+    // Renamed executables are detected by their contents
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &message("report.pdf", "application/pdf", &executable()),
+            "550 5.7.1",
+        )
+        .await;
+
+    // Archives are inspected one level deep
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &message(
+                "docs.zip",
+                "application/zip",
+                &archive(&[
+                    ("readme.txt", &b"hello"[..]),
+                    ("setup.pdf", &executable()[..]),
+                ]),
+            ),
+            "550 5.7.1",
+        )
+        .await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &message(
+                "docs.zip",
+                "application/zip",
+                &archive(&[("readme.txt", &b"hello"[..])]),
+            ),
+            "250",
+        )
+        .await;
+    qr.expect_message().await;
+
+    // Size limits
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &message("notes.txt", "text/plain", &[b'a'; 2048]),
+            "550 5.7.1",
+        )
+        .await;
+    qr.assert_no_events();
+
+    // Matched attachments can be allowed with a warning header
+    let test = TestSMTP::new("smtp_attachment_warn", WARN).await;
+    let mut session = test.new_session();
+    let mut qr = test.queue_receiver;
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            &message("invoice.exe", "application/octet-stream", b"hello"),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Attachment-Warning: \"invoice.exe\" has a banned extension");
+}
+
+fn message(name: &str, content_type: &str, contents: &[u8]) -> String {
+    format!(
+        concat!(
+            "From: john@doe.org\r\n",
+            "To: bill@foobar.org\r\n",
+            "Subject: Attachment test\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"boundary\"\r\n",
+            "\r\n",
+            "--boundary\r\n",
+            "Content-Type: text/plain\r\n",
+            "\r\n",
+            "See attached.\r\n",
+            "--boundary\r\n",
+            "Content-Type: {}\r\n",
+            "Content-Disposition: attachment; filename=\"{}\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "{}\r\n",
+            "--boundary--\r\n",
+        ),
+        content_type,
+        name,
+        STANDARD
+            .encode(contents)
+            .as_bytes()
+            .chunks(76)
+            .map(|line| std::str::from_utf8(line).unwrap())
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    )
+}
+
+fn executable() -> Vec<u8> {
+    let mut contents = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff".to_vec();
+    contents.resize(256, 0);
+    contents
+}
+
+fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, contents) in files {
+        archive
+            .start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        archive.write_all(contents).unwrap();
+    }
+    archive.finish().unwrap().into_inner()
+}
@@ -0,0 +1,59 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::Core;
+
+use smtp::core::Session;
+use utils::config::Config;
+
+use crate::smtp::{
+    session::{TestSession, VerifyResponse},
+    TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[server.backpressure]
+enable = true
+memory.high = 1000
+memory.low = 500
+"#;
+
+#[tokio::test]
+async fn backpressure() {
+    // Enable logging
+    crate::enable_logging();
+
+    let mut config = Config::new(CONFIG).unwrap();
+    let core = Core::parse(&mut config, Default::default(), Default::default()).await;
+    let server = TestSMTP::from_core(core).server;
+
+    // Connections are accepted while the signals are below the thresholds
+    server.update_load_shedding(Some(800)).await.unwrap();
+    assert!(!server.is_shedding_load());
+    let mut session = Session::test(server.clone());
+    assert!(session.init_conn().await);
+    session.response().assert_code("220");
+
+    // Exceeding a threshold starts shedding new connections and transactions
+    server.update_load_shedding(Some(2000)).await.unwrap();
+    assert!(server.is_shedding_load());
+    session.ehlo("mx.foobar.org").await;
+    session.mail_from("john@foobar.org", "451 4.3.2").await;
+    let mut session = Session::test(server.clone());
+    assert!(!session.init_conn().await);
+    session.response().assert_code("421 4.3.2");
+
+    // Shedding continues until the signals drop below the low threshold
+    server.update_load_shedding(Some(800)).await.unwrap();
+    assert!(server.is_shedding_load());
+    server.update_load_shedding(Some(400)).await.unwrap();
+    assert!(!server.is_shedding_load());
+    let mut session = Session::test(server);
+    assert!(session.init_conn().await);
+    session.response().assert_code("220");
+    session.ehlo("mx.foobar.org").await;
+    session.mail_from("john@foobar.org", "250").await;
+}
@@ -0,0 +1,84 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::config::server::ServerProtocol;
+use smtp::inbound::callout::SmtpCallout;
+
+use crate::smtp::{session::TestSession, TestSMTP};
+
+const REMOTE: &str = r#"
+[session.ehlo]
+reject-non-fqdn = false
+
+[session.rcpt]
+relay = true
+"#;
+
+const LOCAL: &str = r#"
+[session.rcpt]
+relay = true
+callout = [{if = "rcpt_domain = 'foobar.org'", then = "'callout'"},
+           {else = false}]
+
+[session.callout]
+on-failure = "defer"
+
+[remote.callout]
+address = 127.0.0.1
+port = 9925
+protocol = 'smtp'
+
+[remote.callout.tls]
+implicit = false
+allow-invalid-certs = true
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn rcpt_callout() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start test server
+    let mut remote = TestSMTP::new("smtp_callout_remote", REMOTE).await;
+    let remote_shutdown = remote.start(&[ServerProtocol::Smtp]).await;
+
+    let local = TestSMTP::new("smtp_callout_local", LOCAL).await;
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session.mail_from("john@test.org", "250").await;
+
+    // Recipients are verified with the next hop
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session.rcpt_to("fail@foobar.org", "503 5.5.1").await;
+    session.rcpt_to("delay@foobar.org", "451 4.5.3").await;
+
+    // Domains without a callout rule are not verified
+    session.rcpt_to("fail@example.org", "250").await;
+
+    // Definitive answers are cached
+    remote_shutdown.send(true).unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    session.rset().await;
+    session.mail_from("john@test.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session.rcpt_to("fail@foobar.org", "503 5.5.1").await;
+
+    // Flushing the cache forces a new verification, which now fails
+    local
+        .server
+        .flush_callout_cache("foobar.org")
+        .await
+        .unwrap();
+    session.rset().await;
+    session.mail_from("john@test.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "451 4.4.3").await;
+
+    // No messages should have been delivered to the remote server
+    remote.queue_receiver.assert_no_events();
+}
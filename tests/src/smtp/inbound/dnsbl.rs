@@ -0,0 +1,106 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use crate::smtp::{
+    session::{TestSession, VerifyResponse},
+    TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.dnsbl.list.scored]
+zone = "zen.example.org"
+weight = 2.0
+codes = ["2-3 sbl 1.5", "10 pbl 0.5"]
+
+[session.dnsbl.list.rhsbl]
+zone = "dbl.example.org"
+type = "domain"
+
+[session.dnsbl.list.poisoned]
+zone = "dead.example.org"
+blocking = true
+
+[session.dnsbl.list.blocking]
+zone = "block.example.org"
+blocking = true
+"#;
+
+#[tokio::test]
+async fn dnsbl() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_dnsbl_test", CONFIG).await;
+    let dns = &test.server.core.smtp.resolvers.dns;
+    for (name, addr) in [
+        ("1.0.0.10.zen.example.org", "127.0.0.2"),
+        ("1.0.0.10.dbl.example.org", "127.0.0.2"),
+        ("spammer.org.dbl.example.org", "127.0.0.2"),
+        ("1.0.0.10.dead.example.org", "127.0.0.2"),
+        ("1.0.0.127.dead.example.org", "127.0.0.2"),
+        ("2.0.0.10.zen.example.org", "127.0.0.10"),
+        ("2.0.0.10.block.example.org", "127.0.0.2"),
+        ("3.0.0.10.zen.example.org", "127.255.255.254"),
+    ] {
+        dns.ipv4_add(
+            name,
+            vec![addr.parse().unwrap()],
+            Instant::now() + Duration::from_secs(5),
+        );
+    }
+
+    // Scoring lists do not reject, the poisoned list is ignored
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session.mail_from("john@doe.org", "250").await;
+    let verdict = session.data.dnsbl.clone().unwrap();
+    assert_eq!(verdict.score, 3.0);
+    assert_eq!(verdict.lists(), vec!["scored/sbl".to_string()]);
+
+    // Domain lists are queried for the sender domain
+    session.rset().await;
+    session.mail_from("john@spammer.org", "250").await;
+    let verdict = session.data.dnsbl.clone().unwrap();
+    assert_eq!(verdict.score, 4.0);
+    assert!(verdict.lists().contains(&"rhsbl".to_string()));
+
+    // Blocking lists reject the session
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.2".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .ingest(b"MAIL FROM:<john@doe.org>\r\n")
+        .await
+        .unwrap();
+    session
+        .response()
+        .assert_code("554 5.7.1")
+        .assert_contains("block.example.org");
+    session
+        .ingest(b"MAIL FROM:<jane@doe.org>\r\n")
+        .await
+        .unwrap();
+    session.response().assert_code("554 5.7.1");
+
+    // Answers outside 127.0.0.0/24 are not listings
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.3".to_string();
+    session.data.remote_ip = session.data.remote_ip_str.parse().unwrap();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session.mail_from("john@doe.org", "250").await;
+    assert_eq!(session.data.dnsbl.as_ref().unwrap().score, 0.0);
+}
@@ -0,0 +1,172 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{
+        manage::{ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Principal, Type,
+};
+use smtp::core::Session;
+
+use crate::smtp::{
+    session::{DummyIo, TestSession},
+    TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[directory."internal"]
+type = "internal"
+store = "sqlite"
+
+[session.rcpt]
+directory = "'internal'"
+relay = false
+"#;
+
+#[tokio::test]
+async fn rcpt_domain_routing() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_rcpt_domain_routing", CONFIG).await;
+    let store = &test.server.core.storage.data;
+    let directory = test.server.get_directory("internal").unwrap().clone();
+
+    // Create domains using the global settings, custom separators
+    // with a catch-all and disabled subaddressing
+    for (domain, catch_all, subaddressing) in [
+        ("plus.org", None, None),
+        ("dash.org", Some("support@dash.org"), Some("-_")),
+        ("nosub.org", None, Some("disabled")),
+    ] {
+        store
+            .create_principal(
+                Principal::new(0, Type::Domain)
+                    .with_field(PrincipalField::Name, domain)
+                    .with_opt_field(PrincipalField::CatchAll, catch_all)
+                    .with_opt_field(PrincipalField::Subaddressing, subaddressing),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+    let jane_id = store
+        .create_principal(
+            Principal::new(0, Type::Individual)
+                .with_field(PrincipalField::Name, "jane")
+                .with_field(
+                    PrincipalField::Emails,
+                    vec![
+                        "jane@plus.org".to_string(),
+                        "jane@dash.org".to_string(),
+                        "jane@nosub.org".to_string(),
+                    ],
+                ),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let support_id = store
+        .create_principal(
+            Principal::new(0, Type::Individual)
+                .with_field(PrincipalField::Name, "support")
+                .with_field(PrincipalField::Emails, "support@dash.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // Domains without settings use the global subaddressing
+    let mut session = new_session(&test).await;
+    session.rcpt_to("jane+tag@plus.org", "250").await;
+    session.rcpt_to("jane-tag@plus.org", "550 5.1.2").await;
+
+    // Domains with custom separators
+    session.rcpt_to("jane-tag@dash.org", "250").await;
+    session.rcpt_to("jane_tag@dash.org", "250").await;
+    for (address, expected_id) in [
+        ("jane-tag@dash.org", jane_id),
+        ("jane_other-tag@dash.org", jane_id),
+        ("jane+tag@dash.org", support_id),
+        ("unknown@dash.org", support_id),
+        ("-tag@dash.org", support_id),
+    ] {
+        assert_eq!(
+            test.server
+                .email_to_id(&directory, address, 0)
+                .await
+                .unwrap(),
+            Some(expected_id),
+            "{address}"
+        );
+    }
+
+    // Domains with subaddressing disabled
+    session.rcpt_to("jane@nosub.org", "250").await;
+    session.rcpt_to("jane+tag@nosub.org", "550 5.1.2").await;
+
+    // Settings can only be stored on domains and must be valid
+    for (name, field, value) in [
+        ("jane", PrincipalField::Subaddressing, "+"),
+        ("jane", PrincipalField::CatchAll, "support@dash.org"),
+        ("dash.org", PrincipalField::Subaddressing, "ab"),
+        ("dash.org", PrincipalField::Subaddressing, "+@"),
+        ("dash.org", PrincipalField::CatchAll, "not-an-address"),
+    ] {
+        assert!(
+            store
+                .update_principal(UpdatePrincipal::by_name(name).with_updates(vec![
+                    PrincipalUpdate::set(field, PrincipalValue::String(value.to_string()))
+                ]))
+                .await
+                .is_err(),
+            "{name} {field:?} {value}"
+        );
+    }
+    assert!(store
+        .create_principal(
+            Principal::new(0, Type::Individual)
+                .with_field(PrincipalField::Name, "john")
+                .with_field(PrincipalField::CatchAll, "support@dash.org"),
+            None,
+            None,
+        )
+        .await
+        .is_err());
+
+    // Removing the settings restores the global behavior
+    store
+        .update_principal(UpdatePrincipal::by_name("dash.org").with_updates(vec![
+            PrincipalUpdate::set(
+                PrincipalField::Subaddressing,
+                PrincipalValue::String(String::new()),
+            ),
+            PrincipalUpdate::set(
+                PrincipalField::CatchAll,
+                PrincipalValue::String(String::new()),
+            ),
+        ]))
+        .await
+        .unwrap();
+    let mut session = new_session(&test).await;
+    session.rcpt_to("jane+tag@dash.org", "250").await;
+    session.rcpt_to("jane-tag@dash.org", "550 5.1.2").await;
+    session.rcpt_to("unknown@dash.org", "550 5.1.2").await;
+}
+
+async fn new_session(test: &TestSMTP) -> Session<DummyIo> {
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.org").await;
+    session.mail_from("sender@example.org", "250").await;
+    session
+}
@@ -0,0 +1,201 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use directory::{
+    backend::internal::{
+        manage::{ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Principal, Type,
+};
+use smtp::core::Session;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{DummyIo, TestSession},
+    TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[directory."internal"]
+type = "internal"
+store = "sqlite"
+
+[session.rcpt]
+directory = "'internal'"
+relay = false
+"#;
+
+const MESSAGE: &str = concat!(
+    "From: sender@remote.org\r\n",
+    "Reply-To: other@remote.org\r\n",
+    "Subject: Announcement\r\n",
+    "\r\n",
+    "Hello list!\r\n",
+);
+
+#[tokio::test]
+async fn rcpt_list_policy() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_rcpt_list_policy", CONFIG).await;
+    let store = &test.server.core.storage.data;
+    let qr = &test.queue_receiver;
+
+    // Create a domain, members and a moderator
+    store
+        .create_principal(
+            Principal::new(0, Type::Domain).with_field(PrincipalField::Name, "example.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    for name in ["jane", "john", "mod"] {
+        store
+            .create_principal(
+                Principal::new(0, Type::Individual)
+                    .with_field(PrincipalField::Name, name)
+                    .with_field(PrincipalField::Emails, format!("{name}@example.org")),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    // Create lists with different posting policies
+    for (name, policy, moderators, reply_to_list) in [
+        ("open", "anyone", vec![], false),
+        ("members", "members", vec![], false),
+        ("announce", "moderators", vec!["mod".to_string()], true),
+        ("closed", "moderators", vec![], false),
+    ] {
+        let mut list = Principal::new(0, Type::List)
+            .with_field(PrincipalField::Name, name)
+            .with_field(PrincipalField::Emails, format!("{name}@example.org"))
+            .with_field(PrincipalField::PostingPolicy, policy)
+            .with_field(PrincipalField::Moderators, moderators);
+        if reply_to_list {
+            list.set(PrincipalField::ReplyToList, 1u64);
+        }
+        store.create_principal(list, None, None).await.unwrap();
+        store
+            .update_principal(UpdatePrincipal::by_name(name).with_updates(vec![
+                PrincipalUpdate::set(
+                    PrincipalField::Members,
+                    PrincipalValue::StringList(vec!["jane".to_string(), "john".to_string()]),
+                ),
+            ]))
+            .await
+            .unwrap();
+    }
+
+    // Posting settings are validated and limited to lists
+    for (name, field, value) in [
+        (
+            "members",
+            PrincipalField::PostingPolicy,
+            PrincipalValue::String("everyone".to_string()),
+        ),
+        (
+            "announce",
+            PrincipalField::Moderators,
+            PrincipalValue::StringList(vec!["members".to_string()]),
+        ),
+        (
+            "jane",
+            PrincipalField::PostingPolicy,
+            PrincipalValue::String("members".to_string()),
+        ),
+        (
+            "jane",
+            PrincipalField::ReplyToList,
+            PrincipalValue::Integer(1),
+        ),
+    ] {
+        assert!(
+            store
+                .update_principal(
+                    UpdatePrincipal::by_name(name)
+                        .with_updates(vec![PrincipalUpdate::set(field, value.clone())])
+                )
+                .await
+                .is_err(),
+            "{name} {field:?} {value:?}"
+        );
+    }
+
+    // Anyone can post to open lists
+    let mut session = new_session(&test, "sender@remote.org").await;
+    session.rcpt_to("open@example.org", "250").await;
+    session.data(MESSAGE, "250").await;
+    let message = qr.last_queued_message().await;
+    assert_eq!(message.hold, None);
+    message
+        .remove(&test.server, qr.last_queued_due().await)
+        .await;
+
+    // Only members can post to member lists, nobody to closed lists
+    let mut session = new_session(&test, "sender@remote.org").await;
+    session.rcpt_to("members@example.org", "550 5.7.1").await;
+    session.rcpt_to("closed@example.org", "550 5.7.1").await;
+    let mut session = new_session(&test, "jane@example.org").await;
+    session.rcpt_to("members@example.org", "250").await;
+
+    // Posts by non-moderators are held and replies are sent to the list
+    let mut session = new_session(&test, "sender@remote.org").await;
+    session.rcpt_to("announce@example.org", "250").await;
+    session.rcpt_to("mod@example.org", "250").await;
+    session.data(MESSAGE, "250").await;
+    let messages = qr.read_queued_messages().await;
+    assert_eq!(messages.len(), 2);
+    let (mut held, direct): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .partition(|message| message.moderated_list().is_some());
+    let mut held = held.pop().expect("Missing held message");
+    assert_eq!(
+        held.recipients
+            .iter()
+            .map(|rcpt| rcpt.address_lcase.as_str())
+            .collect::<Vec<_>>(),
+        ["jane@example.org", "john@example.org"]
+    );
+    let contents = held.read_message(qr).await;
+    assert!(contents.contains("Reply-To: <announce@example.org>"));
+    assert!(!contents.contains("other@remote.org"), "{contents}");
+    assert_eq!(direct[0].recipients[0].address_lcase, "mod@example.org");
+    assert!(direct[0]
+        .read_message(qr)
+        .await
+        .contains("Reply-To: other@remote.org"));
+
+    // Approved posts are released for delivery
+    assert!(held.release_hold());
+    assert_eq!(held.hold, None);
+    assert!(!held.release_hold());
+    qr.clear_queue(&test.server).await;
+
+    // Moderators post directly
+    let mut session = new_session(&test, "mod@example.org").await;
+    session.rcpt_to("announce@example.org", "250").await;
+    session.data(MESSAGE, "250").await;
+    assert!(qr
+        .read_queued_messages()
+        .await
+        .iter()
+        .all(|message| message.moderated_list().is_none()));
+}
+
+async fn new_session(test: &TestSMTP, from: &str) -> Session<DummyIo> {
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.org").await;
+    session.mail_from(from, "250").await;
+    session
+}
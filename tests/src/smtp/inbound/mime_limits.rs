@@ -0,0 +1,254 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::config::smtp::session::{MimeLimitAction, MimeLimits};
+use mail_parser::{MessageParser, MimeHeaders};
+use smtp::inbound::mime::{build_opaque_message, scan_mime, MimeViolation};
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::TestMessage,
+    session::{TestSession, VerifyResponse},
+    TestSMTP,
+};
+
+const OPAQUE: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.data.mime]
+max-header-size = 16384
+max-parts = 50
+max-depth = 10
+"#;
+
+const STRICT: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.data.mime]
+max-depth = 10
+action = "reject"
+"#;
+
+#[test]
+fn mime_limits_scan() {
+    let limits = MimeLimits {
+        max_header_size: 16384,
+        max_parts: 50,
+        max_depth: 10,
+        action: MimeLimitAction::Opaque,
+    };
+
+    // Configuration
+    let mut config = Config::new(OPAQUE).unwrap();
+    assert_eq!(MimeLimits::parse(&mut config), limits);
+    let mut config = Config::new(STRICT).unwrap();
+    assert_eq!(
+        MimeLimits::parse(&mut config).action,
+        MimeLimitAction::Reject
+    );
+
+    // Well-formed messages are accepted
+    for message in [
+        nested_multipart(9),
+        nested_messages(9),
+        many_parts(49),
+        huge_header(15000),
+        invalid_utf8(),
+    ] {
+        assert_eq!(scan_mime(&message, &limits), Ok(()));
+    }
+
+    // Pathological messages are detected
+    for (message, expected) in [
+        (nested_multipart(1000), MimeViolation::Depth { limit: 10 }),
+        (nested_messages(1000), MimeViolation::Depth { limit: 10 }),
+        (many_parts(10000), MimeViolation::PartCount { limit: 50 }),
+        (
+            huge_header(10 * 1024 * 1024),
+            MimeViolation::HeaderSize { limit: 16384 },
+        ),
+    ] {
+        assert_eq!(scan_mime(&message, &limits), Err(expected));
+
+        // The opaque wrapper only adds a bounded amount of data and parses as a single part
+        let opaque = build_opaque_message(&message, &expected, "localhost");
+        assert!(opaque.len() <= message.len() + 8192);
+        let parsed = MessageParser::new().parse(&opaque).unwrap();
+        assert_eq!(parsed.parts.len(), 1);
+        assert_eq!(parsed.subject(), Some("Pathological message"));
+        assert_eq!(
+            parsed
+                .header("X-Malformed-Message")
+                .and_then(|h| h.as_text()),
+            Some(expected.to_string().as_str())
+        );
+        assert_eq!(parsed.attachment_name(), Some("original.eml"));
+        assert_eq!(parsed.parts[0].contents(), message.as_slice());
+    }
+
+    // Randomly assembled fragments never escape the limits
+    let fragments: &[&[u8]] = &[
+        b"Content-Type: multipart/mixed; boundary=\"a\"\r\n",
+        b"Content-Type: multipart/alternative;\r\n boundary=b\r\n",
+        b"Content-Type: message/rfc822\r\n",
+        b"Content-Type: text/plain\r\n",
+        b"Subject: \xff\xfe\xc3\x28 broken\r\n",
+        b"\r\n",
+        b"--a\r\n",
+        b"--b\r\n",
+        b"--a--\r\n",
+        b"--b--\r\n",
+        b"body text\r\n",
+        b"X-Long: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n",
+    ];
+    let mut seed = 0x2545f4914f6cdd1du64;
+    for _ in 0..500 {
+        let mut message = b"Subject: Pathological message\r\n".to_vec();
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            message.extend_from_slice(fragments[(seed % fragments.len() as u64) as usize]);
+        }
+        match scan_mime(&message, &limits) {
+            Ok(()) => {
+                MessageParser::new().parse(&message);
+            }
+            Err(violation) => {
+                let opaque = build_opaque_message(&message, &violation, "localhost");
+                assert!(opaque.len() <= message.len() + 8192);
+                assert_eq!(MessageParser::new().parse(&opaque).unwrap().parts.len(), 1);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn mime_limits_delivery() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Messages exceeding the limits are delivered as an opaque body
+    let test = TestSMTP::new("smtp_mime_limits_opaque", OPAQUE).await;
+    let mut session = test.new_session();
+    let mut qr = test.queue_receiver;
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            std::str::from_utf8(&nested_multipart(1000)).unwrap(),
+            "250",
+        )
+        .await;
+    qr.expect_message()
+        .await
+        .read_lines(&qr)
+        .await
+        .assert_contains("X-Malformed-Message: MIME nesting deeper than 10 levels")
+        .assert_contains("Subject: Pathological message")
+        .assert_contains("Content-Disposition: attachment; filename=\"original.eml\"");
+
+    // Invalid UTF-8 in headers is not a violation
+    session.mail_from("john@doe.org", "250").await;
+    session.rcpt_to("bill@foobar.org", "250").await;
+    session.ingest(b"DATA\r\n").await.unwrap();
+    session.response().assert_code("354");
+    session.ingest(&invalid_utf8()).await.unwrap();
+    session.ingest(b"\r\n.\r\n").await.unwrap();
+    session.response().assert_code("250");
+    qr.expect_message().await;
+
+    // Strict mode rejects at DATA
+    let test = TestSMTP::new("smtp_mime_limits_strict", STRICT).await;
+    let mut session = test.new_session();
+    let mut qr = test.queue_receiver;
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            std::str::from_utf8(&nested_messages(1000)).unwrap(),
+            "554 5.6.0",
+        )
+        .await;
+    session
+        .send_message(
+            "john@doe.org",
+            &["bill@foobar.org"],
+            std::str::from_utf8(&nested_multipart(5)).unwrap(),
+            "250",
+        )
+        .await;
+    qr.expect_message().await;
+    qr.assert_no_events();
+}
+
+fn header() -> Vec<u8> {
+    b"From: john@doe.org\r\nTo: bill@foobar.org\r\nSubject: Pathological message\r\n".to_vec()
+}
+
+fn nested_multipart(depth: usize) -> Vec<u8> {
+    let mut message = header();
+    for level in 0..depth {
+        message.extend_from_slice(
+            format!("Content-Type: multipart/mixed; boundary=\"b{level}\"\r\n\r\n--b{level}\r\n")
+                .as_bytes(),
+        );
+    }
+    message.extend_from_slice(b"Content-Type: text/plain\r\n\r\nHello.\r\n");
+    for level in (0..depth).rev() {
+        message.extend_from_slice(format!("--b{level}--\r\n").as_bytes());
+    }
+    message
+}
+
+fn nested_messages(depth: usize) -> Vec<u8> {
+    let mut message = header();
+    for _ in 0..depth {
+        message.extend_from_slice(b"Content-Type: message/rfc822\r\n\r\nSubject: Inner\r\n");
+    }
+    message.extend_from_slice(b"\r\nHello.\r\n");
+    message
+}
+
+fn many_parts(count: usize) -> Vec<u8> {
+    let mut message = header();
+    message.extend_from_slice(b"Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n");
+    for _ in 0..count {
+        message.extend_from_slice(b"--b\r\nContent-Type: text/plain\r\n\r\nPart.\r\n");
+    }
+    message.extend_from_slice(b"--b--\r\n");
+    message
+}
+
+fn huge_header(size: usize) -> Vec<u8> {
+    let mut message = header();
+    message.extend_from_slice(b"X-Huge: ");
+    let mut line_len = 0;
+    for _ in 0..size.saturating_sub(message.len() + 64) {
+        if line_len == 76 {
+            message.extend_from_slice(b"\r\n ");
+            line_len = 0;
+        }
+        message.push(b'a');
+        line_len += 1;
+    }
+    message.extend_from_slice(b"\r\n\r\nHello.\r\n");
+    message
+}
+
+fn invalid_utf8() -> Vec<u8> {
+    let mut message = header();
+    message.extend_from_slice(b"X-Broken: \xff\xfe\xc3\x28\r\nComments: caf\xe9\r\n\r\nHello.\r\n");
+    message
+}
@@ -11,29 +11,41 @@ use common::{
     Server,
 };
 use store::{
-    write::{key::DeserializeBigEndian, Bincode, QueueClass, ReportEvent, ValueClass},
+    write::{key::DeserializeBigEndian, QueueClass, ReportEvent, ValueClass},
     Deserialize, IterateParams, ValueKey, U64_LEN,
 };
 use tokio::sync::mpsc::error::TryRecvError;
 
-use smtp::queue::{DeliveryAttempt, Message, QueueId};
+use smtp::queue::{serialize::QueuedMessage, DeliveryAttempt, Message, QueueId};
 
 use super::{QueueReceiver, ReportReceiver};
 
+pub mod alias;
 pub mod antispam;
+pub mod attachments;
 pub mod auth;
+pub mod backpressure;
 pub mod basic;
+pub mod callout;
 pub mod data;
 pub mod dmarc;
+pub mod dnsbl;
+pub mod domain_routing;
 pub mod ehlo;
 pub mod limits;
+pub mod list_policy;
 pub mod mail;
 pub mod milter;
+pub mod mime_limits;
 pub mod rcpt;
+pub mod rcpt_policy;
+pub mod reputation;
 pub mod rewrite;
+pub mod rewrite_map;
 pub mod scripts;
 pub mod sign;
 pub mod throttle;
+pub mod tls_policy;
 pub mod vrfy;
 
 impl QueueReceiver {
@@ -186,7 +198,7 @@ impl QueueReceiver {
             .iterate(
                 IterateParams::new(from_key, to_key).descending(),
                 |key, value| {
-                    let value = Bincode::<Message>::deserialize(value)?;
+                    let value = QueuedMessage::deserialize(value)?;
                     assert_eq!(key.deserialize_be_u64(0)?, value.inner.queue_id);
                     messages.push(value.inner);
                     Ok(true)
@@ -0,0 +1,250 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use directory::{
+    backend::internal::{
+        manage::{ManageDirectory, UpdatePrincipal},
+        PrincipalField, PrincipalUpdate, PrincipalValue,
+    },
+    Principal, Type,
+};
+use smtp::core::Session;
+
+use crate::smtp::{
+    session::{DummyIo, TestSession, VerifyResponse},
+    TestSMTP,
+};
+
+const CONFIG: &str = r#"
+[directory."internal"]
+type = "internal"
+store = "sqlite"
+
+[session.auth]
+must-match-sender = false
+
+[session.rcpt]
+directory = "'internal'"
+relay = true
+
+[session.rcpt.policy.tenant.school]
+mode = "internal-only"
+exempt = ["staff"]
+
+[session.rcpt.policy.tenant.acme]
+mode = "allow-list"
+allowed-domains = ["partner.org"]
+
+[session.rcpt.policy.group.interns]
+mode = "internal-only"
+"#;
+
+#[tokio::test]
+async fn rcpt_policy() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_rcpt_policy", CONFIG).await;
+    let store = &test.server.core.storage.data;
+
+    // Create tenants, domains and principals
+    let mut ids = Vec::new();
+    for (tenant, domain) in [("school", "school.edu"), ("acme", "acme.org")] {
+        let tenant_id = store
+            .create_principal(
+                Principal::new(0, Type::Tenant).with_field(PrincipalField::Name, tenant),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        store
+            .create_principal(
+                Principal::new(0, Type::Domain).with_field(PrincipalField::Name, domain),
+                Some(tenant_id),
+                None,
+            )
+            .await
+            .unwrap();
+        ids.push(tenant_id);
+    }
+    for (name, typ, tenant_id, member_of) in [
+        ("staff", Type::Group, ids[0], None),
+        ("interns", Type::Group, ids[1], None),
+        ("student", Type::Individual, ids[0], None),
+        ("teacher", Type::Individual, ids[0], Some("staff")),
+        ("jane", Type::Individual, ids[1], None),
+        ("intern", Type::Individual, ids[1], Some("interns")),
+    ] {
+        let domain = if tenant_id == ids[0] {
+            "school.edu"
+        } else {
+            "acme.org"
+        };
+        store
+            .create_principal(
+                Principal::new(0, typ)
+                    .with_field(PrincipalField::Name, name)
+                    .with_field(PrincipalField::Emails, format!("{name}@{domain}"))
+                    .with_opt_field(
+                        PrincipalField::MemberOf,
+                        member_of.map(|group| vec![group.to_string()]),
+                    ),
+                Some(tenant_id),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    // Create a list with external members
+    store
+        .create_principal(
+            Principal::new(0, Type::List)
+                .with_field(PrincipalField::Name, "class")
+                .with_field(PrincipalField::Emails, "class@school.edu"),
+            Some(ids[0]),
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .update_principal(UpdatePrincipal::by_name("class").with_updates(vec![
+            PrincipalUpdate::set(
+                PrincipalField::Members,
+                PrincipalValue::StringList(vec!["student".to_string(), "teacher".to_string()]),
+            ),
+            PrincipalUpdate::set(
+                PrincipalField::ExternalMembers,
+                PrincipalValue::StringList(vec![
+                    "parent@example.com".to_string(),
+                    "tutor@partner.org".to_string(),
+                ]),
+            ),
+        ]))
+        .await
+        .unwrap();
+    store
+        .create_principal(
+            Principal::new(0, Type::List)
+                .with_field(PrincipalField::Name, "parents")
+                .with_field(PrincipalField::Emails, "parents@school.edu")
+                .with_field(
+                    PrincipalField::ExternalMembers,
+                    vec!["parent@example.com".to_string()],
+                ),
+            Some(ids[0]),
+            None,
+        )
+        .await
+        .unwrap();
+
+    // Internal-only senders may only reach local principals
+    let mut session = new_session(&test, Some("student")).await;
+    session.rcpt_to("teacher@school.edu", "250").await;
+    session.rcpt_to("jane@acme.org", "250").await;
+    session
+        .ingest(b"RCPT TO:<friend@example.com>\r\n")
+        .await
+        .unwrap();
+    session
+        .response()
+        .assert_code("550 5.7.1")
+        .assert_contains("internal-only recipient policy of tenant \"school\"");
+    session.rcpt_to("tutor@partner.org", "550 5.7.1").await;
+
+    // External list members are removed from the envelope
+    session.rcpt_to("class@school.edu", "250").await;
+    assert_eq!(
+        rcpt_to(&session),
+        ["jane@acme.org", "student@school.edu", "teacher@school.edu"]
+    );
+
+    // Lists without internal members are rejected
+    session.rcpt_to("parents@school.edu", "550 5.7.1").await;
+
+    // Members of exempt groups are not restricted
+    let mut session = new_session(&test, Some("teacher")).await;
+    session.rcpt_to("friend@example.com", "250").await;
+    session.rcpt_to("class@school.edu", "250").await;
+    assert_eq!(
+        rcpt_to(&session),
+        [
+            "friend@example.com",
+            "parent@example.com",
+            "student@school.edu",
+            "teacher@school.edu",
+            "tutor@partner.org"
+        ]
+    );
+
+    // Allow-listed domains are accepted, including list members
+    let mut session = new_session(&test, Some("jane")).await;
+    session.rcpt_to("tutor@partner.org", "250").await;
+    session.rcpt_to("friend@example.com", "550 5.7.1").await;
+    session.rcpt_to("class@school.edu", "250").await;
+    assert_eq!(
+        rcpt_to(&session),
+        [
+            "student@school.edu",
+            "teacher@school.edu",
+            "tutor@partner.org"
+        ]
+    );
+
+    // Group policies override the tenant policy
+    let mut session = new_session(&test, Some("intern")).await;
+    session.rcpt_to("student@school.edu", "250").await;
+    session
+        .ingest(b"RCPT TO:<tutor@partner.org>\r\n")
+        .await
+        .unwrap();
+    session
+        .response()
+        .assert_code("550 5.7.1")
+        .assert_contains("internal-only recipient policy of group \"interns\"");
+
+    // Unauthenticated sessions are not restricted
+    let mut session = new_session(&test, None).await;
+    session.rcpt_to("friend@example.com", "250").await;
+}
+
+async fn new_session(test: &TestSMTP, name: Option<&str>) -> Session<DummyIo> {
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.example.org").await;
+    session.data.authenticated_as = None;
+    if let Some(name) = name {
+        let account_id = test
+            .server
+            .core
+            .storage
+            .data
+            .get_principal_id(name)
+            .await
+            .unwrap()
+            .unwrap();
+        session.data.authenticated_as = Some(Arc::new(
+            test.server.get_access_token(account_id).await.unwrap(),
+        ));
+    }
+    session.mail_from("sender@example.org", "250").await;
+    session
+}
+
+fn rcpt_to(session: &Session<DummyIo>) -> Vec<String> {
+    let mut rcpt_to = session
+        .data
+        .rcpt_to
+        .iter()
+        .map(|rcpt| rcpt.address_lcase.clone())
+        .collect::<Vec<_>>();
+    rcpt_to.sort_unstable();
+    rcpt_to
+}
@@ -0,0 +1,177 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::Duration;
+
+use common::{auth::reputation::ReputationLevel, Core, Server};
+
+use store::Stores;
+use utils::config::Config;
+
+use crate::{
+    smtp::{
+        session::{DummyIo, TestSession},
+        TempDir, TestSMTP,
+    },
+    AssertConfig,
+};
+use smtp::core::Session;
+
+const CONFIG: &str = r#"
+[storage]
+data = "sqlite"
+lookup = "sqlite"
+blob = "sqlite"
+fts = "sqlite"
+directory = "local"
+
+[store."sqlite"]
+type = "sqlite"
+path = "{TMP}/queue.db"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "john"
+description = "John Doe"
+secret = "secret"
+email = "john@example.org"
+
+[[directory."local".principals]]
+name = "admin"
+class = "admin"
+description = "Administrator"
+secret = "secret"
+email = "admin@example.org"
+
+[session.auth]
+mechanisms = "[plain]"
+directory = "'local'"
+
+[session.rcpt]
+relay = true
+
+[sender-reputation]
+enable = true
+limit.recipients-per-hour = 10
+throttle-rate = 10
+"#;
+
+#[tokio::test]
+async fn sender_reputation() {
+    // Enable logging
+    crate::enable_logging();
+
+    let tmp_dir = TempDir::new("smtp_reputation_test", true);
+    let mut config = Config::new(tmp_dir.update_config(CONFIG)).unwrap();
+    let stores = Stores::parse_all(&mut config).await;
+    let core = Core::parse(&mut config, stores, Default::default()).await;
+    config.assert_no_errors();
+    let test = TestSMTP::from_core(core);
+    let server = test.server.clone();
+    let john_id = server
+        .core
+        .storage
+        .directory
+        .email_to_id("john@example.org")
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Reaching the recipients per hour limit throttles the account
+    let mut session = authenticated_session(&server, "AGpvaG4Ac2VjcmV0").await;
+    send_to(&mut session, 10).await;
+    assert_eq!(level(&server, john_id).await, ReputationLevel::Throttle);
+    let signals = server.reputation_signals(john_id).await.unwrap();
+    assert_eq!(signals.recipients_per_hour, 10);
+    assert_eq!(signals.score, 100);
+    session.mail_from("john@example.org", "451 4.7.1").await;
+
+    // Clearing the state lifts the restrictions
+    assert!(server.clear_sender_reputation(john_id).await.unwrap());
+    assert_eq!(level(&server, john_id).await, ReputationLevel::Normal);
+    session.mail_from("john@example.org", "250").await;
+    session.rset().await;
+
+    // Crossing the next threshold requires logging in again
+    send_to(&mut session, 15).await;
+    assert_eq!(
+        level(&server, john_id).await,
+        ReputationLevel::Reauthenticate
+    );
+    session.mail_from("john@example.org", "530 5.7.0").await;
+    assert!(session.data.authenticated_as.is_none());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    session
+        .cmd("AUTH PLAIN AGpvaG4Ac2VjcmV0", "235 2.7.0")
+        .await;
+    session.mail_from("john@example.org", "451 4.7.1").await;
+
+    // Submission is suspended once the last threshold is crossed
+    server.clear_sender_reputation(john_id).await.unwrap();
+    send_to(&mut session, 20).await;
+    assert_eq!(level(&server, john_id).await, ReputationLevel::Suspend);
+    session.mail_from("john@example.org", "554 5.7.1").await;
+
+    // Principals holding the exemption permission are never restricted
+    let admin_id = server
+        .core
+        .storage
+        .directory
+        .email_to_id("admin@example.org")
+        .await
+        .unwrap()
+        .unwrap();
+    let mut session = authenticated_session(&server, "AGFkbWluAHNlY3JldA==").await;
+    send_to(&mut session, 20).await;
+    assert_eq!(level(&server, admin_id).await, ReputationLevel::Normal);
+    assert_eq!(
+        server.reputation_signals(admin_id).await.unwrap().score,
+        200
+    );
+    session.mail_from("admin@example.org", "250").await;
+}
+
+async fn authenticated_session(server: &Server, credentials: &str) -> Session<DummyIo> {
+    let mut session = Session::test(server.clone());
+    session.eval_session_params().await;
+    session.stream.tls = true;
+    session.ehlo("mx.foobar.org").await;
+    session
+        .cmd(&format!("AUTH PLAIN {credentials}"), "235 2.7.0")
+        .await;
+    session
+}
+
+async fn send_to(session: &mut Session<DummyIo>, num_recipients: usize) {
+    let from = session
+        .data
+        .authenticated_as
+        .as_ref()
+        .and_then(|token| token.emails.first().cloned())
+        .unwrap();
+    let recipients = (0..num_recipients)
+        .map(|num| format!("rcpt{num}@remote.org"))
+        .collect::<Vec<_>>();
+    session
+        .send_message(
+            &from,
+            &recipients.iter().map(|r| r.as_str()).collect::<Vec<_>>(),
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+}
+
+async fn level(server: &Server, account_id: u32) -> ReputationLevel {
+    server
+        .sender_reputation(account_id)
+        .await
+        .unwrap()
+        .map(|state| state.level)
+        .unwrap_or_default()
+}
@@ -0,0 +1,163 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use common::config::smtp::session::{RewriteRule, RewriteRuleType};
+use smtp::inbound::rewrite::{validate_rewrite_rule, AddressRewrite, RewriteOutcome};
+
+use crate::smtp::{session::TestSession, TestSMTP};
+
+const CONFIG: &str = r#"
+[session.rcpt]
+relay = true
+
+[session.rewrite-map]
+max-hops = 3
+"#;
+
+#[tokio::test]
+async fn rcpt_rewrite_map() {
+    // Enable logging
+    crate::enable_logging();
+
+    let test = TestSMTP::new("smtp_rewrite_map_test", CONFIG).await;
+    let server = &test.server;
+
+    // Invalid rules are rejected
+    assert!(
+        validate_rewrite_rule(&rule("bad", RewriteRuleType::Regex, "([a-z", "x@y.org")).is_err()
+    );
+    assert!(validate_rewrite_rule(&rule(
+        "bad",
+        RewriteRuleType::Exact,
+        "old",
+        "new@foobar.org"
+    ))
+    .is_err());
+
+    for rule in [
+        rule(
+            "rename",
+            RewriteRuleType::Exact,
+            "Old-Name@foobar.org",
+            "new-name@foobar.org",
+        ),
+        rule(
+            "legacy",
+            RewriteRuleType::Regex,
+            r"^(.+)@legacy\.foobar\.org$",
+            "$1@foobar.org",
+        ),
+        rule(
+            "loop-a",
+            RewriteRuleType::Exact,
+            "a@foobar.org",
+            "b@foobar.org",
+        ),
+        rule(
+            "loop-b",
+            RewriteRuleType::Exact,
+            "b@foobar.org",
+            "a@foobar.org",
+        ),
+        rule(
+            "chain-1",
+            RewriteRuleType::Exact,
+            "c1@foobar.org",
+            "c2@foobar.org",
+        ),
+        rule(
+            "chain-2",
+            RewriteRuleType::Exact,
+            "c2@foobar.org",
+            "c3@foobar.org",
+        ),
+        rule(
+            "chain-3",
+            RewriteRuleType::Exact,
+            "c3@foobar.org",
+            "c4@foobar.org",
+        ),
+        rule(
+            "chain-4",
+            RewriteRuleType::Exact,
+            "c4@foobar.org",
+            "c5@foobar.org",
+        ),
+    ] {
+        validate_rewrite_rule(&rule).unwrap();
+        server.set_rewrite_rule(rule).await.unwrap();
+    }
+    assert_eq!(server.rewrite_rules().await.unwrap().len(), 8);
+
+    // Dry run
+    let resolution = server
+        .resolve_rewrite("old-name@legacy.foobar.org")
+        .await
+        .unwrap();
+    assert_eq!(resolution.outcome, RewriteOutcome::Rewritten);
+    assert_eq!(resolution.address, "new-name@foobar.org");
+    assert_eq!(
+        resolution
+            .hops
+            .iter()
+            .map(|hop| hop.rule.as_str())
+            .collect::<Vec<_>>(),
+        vec!["legacy", "rename"]
+    );
+    let resolution = server.resolve_rewrite("jane@foobar.org").await.unwrap();
+    assert_eq!(resolution.outcome, RewriteOutcome::Unchanged);
+    assert!(resolution.hops.is_empty());
+    assert_eq!(
+        server
+            .resolve_rewrite("a@foobar.org")
+            .await
+            .unwrap()
+            .outcome,
+        RewriteOutcome::Loop
+    );
+    let resolution = server.resolve_rewrite("c1@foobar.org").await.unwrap();
+    assert_eq!(resolution.outcome, RewriteOutcome::TooManyHops);
+    assert_eq!(resolution.hops.len(), 3);
+
+    // Rewrites are applied during recipient resolution
+    let mut session = test.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.doe.org").await;
+    session.mail_from("john@doe.org", "250").await;
+    session.rcpt_to("old-name@foobar.org", "250").await;
+    assert_eq!(
+        session.data.rcpt_to.last().unwrap().address_lcase,
+        "new-name@foobar.org"
+    );
+    session.rcpt_to("new-name@foobar.org", "250").await;
+    assert_eq!(session.data.rcpt_to.len(), 1);
+    session.rcpt_to("a@foobar.org", "550 5.4.6").await;
+    session.rcpt_to("c1@foobar.org", "550 5.4.6").await;
+    assert_eq!(session.data.rcpt_to.len(), 1);
+
+    // Deleted rules no longer apply
+    assert!(server.delete_rewrite_rule("rename").await.unwrap());
+    assert!(!server.delete_rewrite_rule("rename").await.unwrap());
+    assert_eq!(
+        server
+            .resolve_rewrite("old-name@foobar.org")
+            .await
+            .unwrap()
+            .outcome,
+        RewriteOutcome::Unchanged
+    );
+}
+
+fn rule(id: &str, typ: RewriteRuleType, pattern: &str, target: &str) -> RewriteRule {
+    RewriteRule {
+        id: id.to_string(),
+        typ,
+        pattern: pattern.to_string(),
+        target: target.to_string(),
+        description: None,
+    }
+}
@@ -0,0 +1,236 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Arc;
+
+use common::{
+    config::server::{Listeners, ServerProtocol},
+    listener::TcpAcceptor,
+    Inner,
+};
+use mail_send::SmtpClientBuilder;
+use rustls::ProtocolVersion;
+use serde::Deserialize;
+use smtp::core::SmtpSessionManager;
+use utils::config::Config;
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{add_test_certs, TestSMTP},
+    AssertConfig,
+};
+
+const POLICIES: &str = r#"
+[server.listener.modern]
+bind = ['127.0.0.1:9926']
+protocol = 'smtp'
+tls.min-version = 'TLSv1.3'
+
+[server.listener.legacy]
+bind = ['127.0.0.1:9927']
+protocol = 'smtp'
+tls.min-version = 'TLSv1.0'
+tls.target-version = 'TLSv1.2'
+
+[server.listener.mtls]
+bind = ['127.0.0.1:9928']
+protocol = 'smtp'
+tls.client-auth = 'optional'
+tls.client-ca = '%{file:{CERT}}%'
+tls.ciphers = ['TLS13_AES_256_GCM_SHA384', 'TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384']
+
+[server.listener.invalid]
+bind = ['127.0.0.1:9929']
+protocol = 'smtp'
+tls.target-version = 'SSLv3'
+"#;
+
+const MISSING_CA: &str = r#"
+[server.listener.mtls]
+bind = ['127.0.0.1:9928']
+protocol = 'smtp'
+tls.client-auth = 'require'
+"#;
+
+const LOCAL: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+
+[auth.spf.verify]
+ehlo = "disable"
+mail-from = "disable"
+"#;
+
+const LISTENERS: &str = r#"
+[server.listener.smtp-legacy]
+bind = ['127.0.0.1:9926']
+protocol = 'smtp'
+tls.disable-protocols = ['TLSv1.3']
+
+[server.listener.smtp-modern]
+bind = ['127.0.0.1:9927']
+protocol = 'smtp'
+
+[server.socket]
+reuse-addr = true
+
+[server.tls]
+enable = true
+implicit = false
+target-version = 'TLSv1.3'
+"#;
+
+#[derive(Debug, Deserialize)]
+struct TlsReport {
+    hosts: Vec<TlsHost>,
+    domains: Vec<TlsDomain>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TlsHost {
+    listener: String,
+    remote_ip: String,
+    domain: Option<String>,
+    version: String,
+    sessions: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TlsDomain {
+    domain: String,
+    hosts: usize,
+    sessions: u64,
+    versions: Vec<String>,
+}
+
+#[tokio::test]
+async fn tls_policy_config() {
+    let mut config = Config::new(add_test_certs(POLICIES)).unwrap();
+    config.resolve_all_macros().await;
+    let mut servers = Listeners::parse(&mut config);
+    servers.parse_tcp_acceptors(&mut config, Arc::new(Inner::default()));
+
+    // Versions below TLSv1.2 cannot be enforced
+    assert!(config
+        .warnings
+        .keys()
+        .any(|key| key == "server.listener.legacy.tls.min-version"));
+    assert!(config
+        .errors
+        .keys()
+        .any(|key| key == "server.listener.invalid.tls.target-version"));
+    assert_eq!(config.errors.len(), 1, "{:?}", config.errors);
+
+    for (id, target_version) in [
+        ("modern", ProtocolVersion::TLSv1_3),
+        ("legacy", ProtocolVersion::TLSv1_2),
+        ("mtls", ProtocolVersion::TLSv1_3),
+        ("invalid", ProtocolVersion::TLSv1_3),
+    ] {
+        match servers.tcp_acceptors.get(id).unwrap() {
+            TcpAcceptor::Tls { policy, .. } => {
+                assert_eq!(policy.target_version, target_version, "{id}");
+            }
+            TcpAcceptor::Plain => panic!("Expected TLS acceptor for {id}"),
+        }
+    }
+
+    // Client authentication requires trusted CAs
+    let mut config = Config::new(MISSING_CA).unwrap();
+    let mut servers = Listeners::parse(&mut config);
+    servers.parse_tcp_acceptors(&mut config, Arc::new(Inner::default()));
+    assert!(config
+        .errors
+        .keys()
+        .any(|key| key == "server.listener.mtls.tls.client-ca"));
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn tls_usage_report() {
+    // Enable logging
+    crate::enable_logging();
+
+    let local = TestSMTP::new("smtp_tls_usage", LOCAL).await;
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+
+    // Start listeners with different TLS policies
+    let inner = local.server.inner.clone();
+    let mut config = Config::new(LISTENERS).unwrap();
+    let mut servers = Listeners::parse(&mut config);
+    servers.parse_tcp_acceptors(&mut config, inner.clone());
+    servers.bind_and_drop_priv(&mut config);
+    config.assert_no_errors();
+    let _rx = servers
+        .spawn(|server, acceptor, shutdown_rx| {
+            server.spawn(
+                SmtpSessionManager::new(inner.clone()),
+                inner.clone(),
+                acceptor,
+                shutdown_rx,
+            );
+        })
+        .0;
+
+    // Connect using STARTTLS
+    for (port, helo) in [
+        (9926, "mx.legacy.org"),
+        (9926, "MX.Legacy.org"),
+        (9927, "mx.modern.org"),
+    ] {
+        SmtpClientBuilder::new("127.0.0.1", port)
+            .implicit_tls(false)
+            .allow_invalid_certs()
+            .helo_host(helo)
+            .connect()
+            .await
+            .unwrap()
+            .quit()
+            .await
+            .unwrap();
+    }
+
+    // Only hosts below the target version are reported
+    let api = ManagementApi::default();
+    let report = api
+        .get::<TlsReport>("/api/tls/outdated")
+        .await
+        .unwrap()
+        .unwrap_data();
+    assert_eq!(report.hosts.len(), 1, "{report:?}");
+    let host = &report.hosts[0];
+    assert_eq!(host.listener, "smtp-legacy");
+    assert_eq!(host.remote_ip, "127.0.0.1");
+    assert_eq!(host.domain.as_deref(), Some("mx.legacy.org"));
+    assert_eq!(host.version, "TLSv1.2");
+    assert_eq!(host.sessions, 2);
+    assert_eq!(report.domains.len(), 1, "{report:?}");
+    let domain = &report.domains[0];
+    assert_eq!(domain.domain, "mx.legacy.org");
+    assert_eq!(domain.hosts, 1);
+    assert_eq!(domain.sessions, 2);
+    assert_eq!(domain.versions, vec!["TLSv1.2"]);
+
+    // Filter by listener
+    assert!(api
+        .get::<TlsReport>("/api/tls/outdated?listener=smtp-modern")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .hosts
+        .is_empty());
+}
@@ -90,6 +90,7 @@ async fn lookup_ip() {
             &NextHop::MX("mx.foobar.org"),
             &RecipientDomain::new("envelope"),
             2,
+            None,
             0,
         )
         .await
@@ -125,6 +126,7 @@ async fn lookup_ip() {
             &NextHop::MX("mx.foobar.org"),
             &RecipientDomain::new("envelope"),
             2,
+            None,
             0,
         )
         .await
@@ -138,6 +140,83 @@ async fn lookup_ip() {
         .contains(&"e:f::a".parse().unwrap()));
 }
 
+const CONFIG_FAMILIES: &str = r#"
+[queue.outbound]
+ip-strategy = [{if = "rcpt_domain == 'v4.org'", then = "ipv4_only"},
+               {else = "ipv6_then_ipv4"}]
+
+[queue.outbound.ipv6-demotion]
+failures = 2
+duration = "1h"
+"#;
+
+#[tokio::test]
+async fn lookup_ip_families() {
+    let test = TestSMTP::new("smtp_lookup_ip_families", CONFIG_FAMILIES).await;
+    test.server.core.smtp.resolvers.dns.ipv4_add(
+        "mx.foobar.org",
+        vec![
+            "172.168.0.100".parse().unwrap(),
+            "172.168.0.101".parse().unwrap(),
+        ],
+        Instant::now() + Duration::from_secs(10),
+    );
+    test.server.core.smtp.resolvers.dns.ipv6_add(
+        "mx.foobar.org",
+        vec!["e:f::a".parse().unwrap(), "e:f::b".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    let resolve = |domain: &'static str| {
+        let server = test.server.clone();
+        async move {
+            server
+                .resolve_host(
+                    &NextHop::MX("mx.foobar.org"),
+                    &RecipientDomain::new(domain),
+                    4,
+                    None,
+                    0,
+                )
+                .await
+                .unwrap()
+                .remote_ips
+                .into_iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // Address families are interleaved, starting with the preferred one
+    assert_eq!(
+        resolve("foobar.org").await,
+        ["e:f::a", "172.168.0.100", "e:f::b", "172.168.0.101"]
+    );
+
+    // Per destination overrides
+    assert_eq!(resolve("v4.org").await, ["172.168.0.100", "172.168.0.101"]);
+
+    // A successful connection resets the failure count
+    test.server
+        .record_ipv6_connect("foobar.org", false, 0)
+        .await;
+    test.server.record_ipv6_connect("foobar.org", true, 0).await;
+    test.server
+        .record_ipv6_connect("foobar.org", false, 0)
+        .await;
+    assert!(!test.server.is_ipv6_demoted("foobar.org").await);
+
+    // Repeated failures demote IPv6
+    test.server
+        .record_ipv6_connect("foobar.org", false, 0)
+        .await;
+    assert!(test.server.is_ipv6_demoted("foobar.org").await);
+    assert!(!test.server.is_ipv6_demoted("other.org").await);
+    assert_eq!(
+        resolve("foobar.org").await,
+        ["172.168.0.100", "e:f::a", "172.168.0.101", "e:f::b"]
+    );
+}
+
 #[test]
 fn to_remote_hosts() {
     let mx = vec![
@@ -0,0 +1,313 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::time::{Duration, Instant};
+
+use common::config::{
+    server::ServerProtocol,
+    smtp::queue::{QueueConfig, SendingWindow},
+};
+use directory::{
+    backend::internal::{manage::ManageDirectory, PrincipalField},
+    Principal, Type,
+};
+use jmap::api::management::queue::Message;
+use mail_auth::MX;
+use reqwest::Method;
+use smtp::queue::{manager::SpawnQueue, QueueId};
+use store::write::now;
+use utils::config::{utils::ParseValue, Config};
+
+use crate::{
+    jmap::ManagementApi,
+    smtp::{management::queue::List, session::TestSession, TestSMTP},
+};
+
+const LOCAL: &str = r#"
+[storage]
+directory = "local"
+
+[directory."local"]
+type = "memory"
+
+[[directory."local".principals]]
+name = "admin"
+type = "admin"
+description = "Superuser"
+secret = "secret"
+class = "admin"
+
+[session.rcpt]
+relay = true
+
+[queue.sending-policy.acme]
+hold = true
+
+[queue.sending-policy.globex]
+timezone = "UTC"
+window = "{TOMORROW} 00:00-24:00"
+"#;
+
+const REMOTE: &str = r#"
+[session.ehlo]
+reject-non-fqdn = false
+
+[session.rcpt]
+relay = true
+"#;
+
+const POLICIES: &str = r#"
+[queue.sending-policy.acme]
+timezone = "+02:00"
+window = ["mon-fri 09:00-17:00", "sat 10:00-12:00"]
+
+[queue.sending-policy.initech]
+timezone = "-0530"
+window = "fri-mon 22:00-24:00"
+
+[queue.sending-policy.invalid]
+timezone = "Mars/Olympus"
+"#;
+
+const DAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+// Monday, 1 January 2024 00:00:00 UTC
+const MONDAY: u64 = 1704067200;
+const HOUR: u64 = 3600;
+const DAY: u64 = 86400;
+
+#[test]
+fn sending_windows() {
+    let mut config = Config::new(POLICIES).unwrap();
+    let policies = QueueConfig::parse(&mut config).sending_policies;
+    assert!(config
+        .errors
+        .keys()
+        .any(|key| key == "queue.sending-policy.invalid.timezone"));
+    assert!(!policies.contains_key("invalid"));
+
+    // Windows are evaluated in the policy's local time
+    let policy = policies.get("acme").unwrap();
+    assert_eq!(policy.utc_offset, 2 * HOUR as i64);
+    assert!(!policy.hold);
+    for (now, expected) in [
+        // 02:00 local on Monday, opens at 09:00 local
+        (MONDAY, Some(MONDAY + 7 * HOUR)),
+        // 10:00 local on Monday
+        (MONDAY + 8 * HOUR, None),
+        // 17:00 local on Monday, opens on Tuesday
+        (MONDAY + 15 * HOUR, Some(MONDAY + DAY + 7 * HOUR)),
+        // 18:00 local on Friday, opens on Saturday morning
+        (
+            MONDAY + 4 * DAY + 16 * HOUR,
+            Some(MONDAY + 5 * DAY + 8 * HOUR),
+        ),
+        // 12:00 local on Saturday, opens on Monday
+        (
+            MONDAY + 5 * DAY + 10 * HOUR,
+            Some(MONDAY + 7 * DAY + 7 * HOUR),
+        ),
+    ] {
+        assert_eq!(policy.next_window(now), expected, "{now}");
+    }
+
+    // Day ranges wrap around the week
+    let policy = policies.get("initech").unwrap();
+    assert_eq!(policy.utc_offset, -(5 * HOUR as i64 + 1800));
+    assert_eq!(policy.windows[0].days, 0b1110001);
+    assert_eq!(
+        policy.next_window(MONDAY + 2 * DAY),
+        Some(MONDAY + 4 * DAY + 22 * HOUR + 5 * HOUR + 1800)
+    );
+
+    // Invalid windows
+    for window in [
+        "mon 17:00-09:00",
+        "funday 09:00-17:00",
+        "mon 09:00",
+        "mon 09:00-25:00",
+    ] {
+        assert!(SendingWindow::parse_value(window).is_err(), "{window}");
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn sending_holds() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Start remote test server
+    let mut remote = TestSMTP::new("smtp_sending_hold_remote", REMOTE).await;
+    let _rx = remote.start(&[ServerProtocol::Smtp]).await;
+    let remote_core = remote.build_smtp();
+
+    // Only allow sending tomorrow
+    let today = now() / DAY;
+    let tomorrow = DAYS[((today + 4) % 7) as usize];
+    let local = TestSMTP::new(
+        "smtp_sending_hold_local",
+        LOCAL.replace("{TOMORROW}", tomorrow),
+    )
+    .await;
+    let core = local.build_smtp();
+    for domain in ["foobar.org", "acme.org"] {
+        core.core.smtp.resolvers.dns.mx_add(
+            domain,
+            vec![MX {
+                exchanges: vec!["mx1.foobar.org".to_string()],
+                preference: 10,
+            }],
+            Instant::now() + Duration::from_secs(10),
+        );
+    }
+    core.core.smtp.resolvers.dns.ipv4_add(
+        "mx1.foobar.org",
+        vec!["127.0.0.1".parse().unwrap()],
+        Instant::now() + Duration::from_secs(10),
+    );
+    let _rx_manage = local.start(&[ServerProtocol::Http]).await;
+
+    // Create tenants and their domains
+    let store = &local.server.core.storage.data;
+    for (tenant, domain) in [("acme", "acme.org"), ("globex", "globex.org")] {
+        let tenant_id = store
+            .create_principal(
+                Principal::new(0, Type::Tenant).with_field(PrincipalField::Name, tenant),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        store
+            .create_principal(
+                Principal::new(0, Type::Domain).with_field(PrincipalField::Name, domain),
+                Some(tenant_id),
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    // Send test messages
+    let mut session = local.new_session();
+    local
+        .queue_receiver
+        .queue_rx
+        .spawn(local.server.inner.clone());
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("foobar.net").await;
+    for sender in ["john@acme.org", "jane@acme.org", "bill@globex.org"] {
+        session
+            .send_message(sender, &["rcpt@foobar.org"], "test:no_dkim", "250")
+            .await;
+    }
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    remote.queue_receiver.assert_no_events();
+
+    // Held messages are listed separately from ordinary deferrals
+    let api = ManagementApi::default();
+    let held = api
+        .request::<List<QueueId>>(Method::GET, "/api/queue/messages?hold=review")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(held.len(), 2);
+    let delayed = api
+        .request::<List<QueueId>>(Method::GET, "/api/queue/messages?hold=sending-window")
+        .await
+        .unwrap()
+        .unwrap_data()
+        .items;
+    assert_eq!(delayed.len(), 1);
+    assert_eq!(
+        api.request::<List<QueueId>>(Method::GET, "/api/queue/messages?hold=any")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .total,
+        3
+    );
+
+    let mut review = Vec::new();
+    for id in &held {
+        let message = get_message(&api, *id).await;
+        let hold = message.hold.as_ref().unwrap();
+        assert_eq!(hold.tenant, "acme");
+        assert_eq!(hold.reason, "review");
+        assert!(hold.until.is_none());
+        review.push((message.return_path, *id));
+    }
+    review.sort_unstable();
+    let message = get_message(&api, delayed[0]).await;
+    let hold = message.hold.as_ref().unwrap();
+    assert_eq!(hold.tenant, "globex");
+    assert_eq!(hold.reason, "sending-window");
+    let until = hold.until.as_ref().unwrap().to_timestamp();
+    assert_eq!(until as u64, (today + 1) * DAY);
+    assert_eq!(
+        message.domains[0]
+            .next_retry
+            .as_ref()
+            .unwrap()
+            .to_timestamp(),
+        until
+    );
+
+    // Only messages held for review can be released
+    assert!(!post(&api, delayed[0], "release").await);
+
+    // Released messages are delivered
+    let (sender, released) = &review[1];
+    assert_eq!(sender, "john@acme.org");
+    assert!(post(&api, *released, "release").await);
+    assert!(!post(&api, *released, "release").await);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        remote
+            .queue_receiver
+            .consume_message(&remote_core)
+            .await
+            .return_path,
+        "john@acme.org"
+    );
+
+    // Rejected messages are bounced to the sender
+    let (sender, rejected) = &review[0];
+    assert_eq!(sender, "jane@acme.org");
+    assert!(post(&api, *rejected, "reject").await);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let dsn = remote.queue_receiver.consume_message(&remote_core).await;
+    assert_eq!(dsn.return_path, "");
+    assert_eq!(dsn.recipients[0].address, "jane@acme.org");
+    remote.queue_receiver.assert_no_events();
+
+    // Only the message waiting for its sending window is left
+    assert_eq!(
+        api.request::<List<QueueId>>(Method::GET, "/api/queue/messages")
+            .await
+            .unwrap()
+            .unwrap_data()
+            .items,
+        vec![delayed[0]]
+    );
+}
+
+async fn get_message(api: &ManagementApi, id: QueueId) -> Message {
+    api.request::<Message>(Method::GET, &format!("/api/queue/messages/{id}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+}
+
+async fn post(api: &ManagementApi, id: QueueId, action: &str) -> bool {
+    api.request::<bool>(Method::POST, &format!("/api/queue/messages/{id}/{action}"))
+        .await
+        .unwrap()
+        .unwrap_data()
+}
@@ -11,5 +11,6 @@ pub mod ip_lookup;
 pub mod lmtp;
 pub mod mta_sts;
 pub mod smtp;
+pub mod source_ip;
 pub mod throttle;
 pub mod tls;
@@ -0,0 +1,199 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use common::config::{
+    server::ServerProtocol,
+    smtp::queue::{QueueConfig, SourceIpFallback},
+};
+use mail_auth::MX;
+use utils::config::Config;
+
+use crate::smtp::{
+    inbound::{TestMessage, TestQueueEvent},
+    session::TestSession,
+    TestSMTP,
+};
+
+const LOCAL: &str = r#"
+[session.rcpt]
+relay = true
+
+[queue.schedule]
+retry = "1s"
+
+[queue.outbound.source-ip]
+v4 = "'127.0.0.3'"
+fallback = "fail"
+
+[queue.outbound.source-ip.rule.a-partner]
+sender-domain = "test.org"
+rcpt-domain = "*.foobar.org"
+address = "127.0.0.2"
+
+[queue.outbound.source-ip.rule.b-catch-all]
+rcpt-domain = "foobar.org"
+address = "127.0.0.4"
+"#;
+
+const REMOTE: &str = r#"
+[session.ehlo]
+reject-non-fqdn = false
+
+[session.rcpt]
+relay = true
+
+[session.data.add-headers]
+received = true
+"#;
+
+const RULES: &str = r#"
+[queue.outbound.source-ip]
+fallback = "any"
+
+[queue.outbound.source-ip.rule.a]
+tenant = "acme"
+address = "127.0.0.2"
+
+[queue.outbound.source-ip.rule.b]
+sender-domain = ["example.org", "*.example.net"]
+address = ["127.0.0.3", "192.0.2.1"]
+
+[queue.outbound.source-ip.rule.c]
+address = "192.0.2.1"
+"#;
+
+#[tokio::test]
+#[serial_test::serial]
+async fn source_ip_selection() {
+    // Enable logging
+    crate::enable_logging();
+
+    // Rules are matched in order
+    let mut config = Config::new(RULES).unwrap();
+    let source_ip = QueueConfig::parse(&mut config).source_ip;
+    assert_eq!(source_ip.fallback, SourceIpFallback::Any);
+    assert_eq!(
+        source_ip
+            .rules
+            .iter()
+            .map(|rule| rule.id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert!(source_ip.has_tenant_rules());
+    let rule = &source_ip.rules[0];
+    assert_eq!(rule.ipv4, vec!["127.0.0.2".parse::<IpAddr>().unwrap()]);
+    assert!(rule.ipv6.is_empty());
+    assert!(rule.matches(Some("acme"), "example.org", "foobar.org"));
+    assert!(!rule.matches(Some("other"), "example.org", "foobar.org"));
+    assert!(!rule.matches(None, "example.org", "foobar.org"));
+
+    // Addresses not present on the host are rejected at config time
+    let rule = &source_ip.rules[1];
+    assert_eq!(rule.ipv4, vec!["127.0.0.3".parse::<IpAddr>().unwrap()]);
+    assert!(rule.matches(None, "example.org", "foobar.org"));
+    assert!(rule.matches(None, "mail.example.net", "foobar.org"));
+    assert!(!rule.matches(None, "example.com", "foobar.org"));
+    for key in [
+        "queue.outbound.source-ip.rule.b.address",
+        "queue.outbound.source-ip.rule.c.address",
+    ] {
+        assert!(
+            config.errors.keys().any(|error| error.starts_with(key)),
+            "{key} {:?}",
+            config.errors
+        );
+    }
+
+    // Start test server
+    let mut remote = TestSMTP::new("smtp_source_ip_remote", REMOTE).await;
+    let _rx = remote.start(&[ServerProtocol::Smtp]).await;
+
+    let mut local = TestSMTP::new("smtp_source_ip_local", LOCAL).await;
+    let core = local.build_smtp();
+    for domain in ["foobar.org", "mx.foobar.org"] {
+        core.core.smtp.resolvers.dns.mx_add(
+            domain,
+            vec![MX {
+                exchanges: vec![format!("mx.{domain}")],
+                preference: 10,
+            }],
+            Instant::now() + Duration::from_secs(10),
+        );
+        core.core.smtp.resolvers.dns.ipv4_add(
+            format!("mx.{domain}"),
+            vec!["127.0.0.1".parse().unwrap()],
+            Instant::now() + Duration::from_secs(10),
+        );
+    }
+
+    // Each domain uses the address selected by the first matching rule
+    let mut session = local.new_session();
+    session.data.remote_ip_str = "10.0.0.1".to_string();
+    session.eval_session_params().await;
+    session.ehlo("mx.test.org").await;
+    session
+        .send_message(
+            "john@test.org",
+            &["delay@foobar.org", "delay@mx.foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone())
+        .await;
+    local.queue_receiver.read_event().await.assert_reload();
+    let message = local.queue_receiver.last_queued_message().await;
+    for (domain, ip) in [("foobar.org", "127.0.0.4"), ("mx.foobar.org", "127.0.0.2")] {
+        let domain_idx = message
+            .domains
+            .iter()
+            .position(|d| d.domain == domain)
+            .unwrap();
+        assert_eq!(
+            message.source_ip(domain_idx),
+            Some(ip.parse().unwrap()),
+            "{domain}"
+        );
+    }
+    local.queue_receiver.clear_queue(&core).await;
+
+    // Domains without a matching rule fall back to the expression
+    session
+        .send_message(
+            "jane@example.org",
+            &["bill@mx.foobar.org"],
+            "test:no_dkim",
+            "250",
+        )
+        .await;
+    local
+        .queue_receiver
+        .expect_message_then_deliver()
+        .await
+        .try_deliver(core.clone())
+        .await;
+    local.queue_receiver.read_event().await.assert_reload();
+    remote
+        .queue_receiver
+        .expect_message()
+        .await
+        .read_lines(&remote.queue_receiver)
+        .await
+        .iter()
+        .find(|line| line.starts_with("Received: from"))
+        .filter(|line| line.contains("[127.0.0.3]"))
+        .expect("Missing Received header with source address");
+}
@@ -6,6 +6,11 @@
 
 use std::{fs, path::PathBuf, time::SystemTime};
 
+use common::templates::{Template, TemplateKind, TemplateSource, TEMPLATE_GLOBAL};
+use directory::{
+    backend::internal::{manage::ManageDirectory, PrincipalField},
+    Principal, Type,
+};
 use smtp_proto::{Response, RCPT_NOTIFY_DELAY, RCPT_NOTIFY_FAILURE, RCPT_NOTIFY_SUCCESS};
 use store::write::now;
 use utils::BlobHash;
@@ -89,6 +94,10 @@ async fn generate_dsn() {
         priority: 0,
         blob_hash: BlobHash::from(dsn_original.as_bytes()),
         quota_keys: vec![],
+        deferrals: vec![],
+        source_ips: vec![],
+        hold: None,
+        connect_attempts: vec![],
     };
 
     // Load config
@@ -158,6 +167,131 @@ async fn generate_dsn() {
     // Load queue
     let queue = qr.read_queued_messages().await;
     assert_eq!(queue.len(), 4);
+
+    // Invalid templates should be rejected on upload
+    for (subject, body) in [
+        ("Delivery report", "Hello {{sender}"),
+        ("Delivery report", "Hello {{ unknown }}"),
+        ("Delivery report", "Used {{quota_used}} bytes"),
+        ("Delivery\r\nBcc: victim@example.org", "Hello"),
+    ] {
+        assert!(
+            core.set_template(
+                TEMPLATE_GLOBAL,
+                TemplateKind::DsnDelay,
+                TemplateSource {
+                    from_name: None,
+                    subject: subject.to_string(),
+                    body: body.to_string(),
+                },
+            )
+            .await
+            .is_err(),
+            "{subject:?} {body:?}"
+        );
+    }
+    assert!(core
+        .set_template(
+            TEMPLATE_GLOBAL,
+            TemplateKind::DsnDelay,
+            TemplateSource {
+                from_name: None,
+                subject: "Delivery report".to_string(),
+                body: "a".repeat(17 * 1024),
+            },
+        )
+        .await
+        .is_err());
+
+    // Template overrides
+    core.set_template(
+        TEMPLATE_GLOBAL,
+        TemplateKind::DsnDelay,
+        TemplateSource {
+            from_name: Some("Example Postmaster".to_string()),
+            subject: "Delivery report for {{ sender }}".to_string(),
+            body: "Hello {{sender}},\nyour message is still waiting at {{hostname}}.\n".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    message.domains[0].notify.due = now();
+    core.send_dsn(&mut message).await;
+    let dsn_message = qr.expect_message().await;
+    let dsn = String::from_utf8(
+        qr.blob_store
+            .get_blob(dsn_message.blob_hash.as_slice(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+    )
+    .unwrap();
+    for expected in [
+        "From: \"Example Postmaster\" <MAILER-DAEMON@example.org>",
+        "Subject: Delivery report for sender@foobar.org",
+        "Hello sender@foobar.org,\r\nyour message is still waiting at mx.example.org.\r\n\r\n",
+    ] {
+        assert!(dsn.contains(expected), "{expected:?} not found in {dsn}");
+    }
+
+    // Removing the override restores the default template
+    core.delete_template(TEMPLATE_GLOBAL, TemplateKind::DsnDelay)
+        .await
+        .unwrap();
+    assert_eq!(
+        core.template(None, TemplateKind::DsnDelay).await,
+        Template::parse(
+            TemplateKind::DsnDelay,
+            &TemplateKind::DsnDelay.default_source()
+        )
+        .unwrap()
+    );
+
+    // Reports follow the locale and time zone of local senders
+    let store = &core.core.storage.data;
+    store
+        .create_principal(
+            Principal::new(0, Type::Domain).with_field(PrincipalField::Name, "foobar.org"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    store
+        .create_principal(
+            Principal::new(0, Type::Individual)
+                .with_field(PrincipalField::Name, "sender")
+                .with_field(PrincipalField::Emails, "sender@foobar.org")
+                .with_field(PrincipalField::Locale, "de")
+                .with_field(PrincipalField::Timezone, "Asia/Kolkata"),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    message.return_path_lcase = "sender@foobar.org".to_string();
+    message.recipients[0].flags = flags;
+    core.send_dsn(&mut message).await;
+    let dsn_message = qr.expect_message().await;
+    let dsn = String::from_utf8(
+        qr.blob_store
+            .get_blob(dsn_message.blob_hash.as_slice(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+    )
+    .unwrap();
+    let arrival_date = dsn
+        .lines()
+        .find_map(|line| line.strip_prefix("Arrival-Date: "))
+        .unwrap();
+    for expected in [
+        "Subject: Nachricht konnte nicht zugestellt werden",
+        "<foobar@example.org> (host 'mx.example.org' rejected command",
+    ] {
+        assert!(dsn.contains(expected), "{expected:?} not found in {dsn}");
+    }
+    assert!(arrival_date.ends_with(" +0530"), "{arrival_date}");
 }
 
 impl QueueReceiver {
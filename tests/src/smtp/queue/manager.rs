@@ -8,8 +8,15 @@ use std::time::Duration;
 
 use mail_auth::hickory_resolver::proto::op::ResponseCode;
 
-use smtp::queue::{spool::SmtpSpool, Domain, Message, Schedule, Status};
-use store::write::now;
+use smtp::queue::{
+    serialize::{QueuedMessage, QUEUE_FORMAT_LEGACY, QUEUE_FORMAT_VERSION},
+    spool::SmtpSpool,
+    ConnectAttempt, Domain, Hold, HoldReason, Message, Schedule, SourceIp, Status,
+};
+use store::{
+    write::{now, BatchBuilder, Bincode, QueueClass, ValueClass},
+    Deserialize, Serialize, ValueKey,
+};
 
 use crate::smtp::TestSMTP;
 
@@ -65,6 +72,196 @@ async fn queue_due() {
     qr.assert_queue_is_empty().await;
 }
 
+#[tokio::test]
+async fn queue_format_upgrade() {
+    // Enable logging
+    crate::enable_logging();
+
+    let local = TestSMTP::new("smtp_queue_format_test", CONFIG).await;
+    let core = local.build_smtp();
+
+    // Write an entry using the legacy format
+    let mut message = new_message(1);
+    message.domains.push(domain("a", 1, 2, 3));
+    let mut batch = BatchBuilder::new();
+    batch.set(
+        ValueClass::Queue(QueueClass::Message(1)),
+        Bincode::new(message.clone()).serialize(),
+    );
+    core.store().write(batch.build()).await.unwrap();
+    core.check_queue_format().await.unwrap();
+
+    // Entries are upgraded on first read
+    assert_eq!(core.read_message(1).await.unwrap(), message);
+    let stored = core
+        .store()
+        .get_value::<QueuedMessage>(ValueKey::from(ValueClass::Queue(QueueClass::Message(1))))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.version, QUEUE_FORMAT_VERSION);
+    assert_eq!(stored.inner, message);
+
+    // Entries from an incompatible future version prevent startup
+    let mut bytes = new_message(2).serialize_versioned();
+    bytes[2] = QUEUE_FORMAT_VERSION + 1;
+    bytes[3] = QUEUE_FORMAT_VERSION + 1;
+    let mut batch = BatchBuilder::new();
+    batch.set(ValueClass::Queue(QueueClass::Message(2)), bytes);
+    core.store().write(batch.build()).await.unwrap();
+    assert!(core.check_queue_format().await.is_err());
+}
+
+#[test]
+fn queue_format_versions() {
+    let mut message = new_message(0);
+    message.domains.push(domain("a", 1, 2, 3));
+    message.env_id = Some("envid".to_string());
+
+    // Legacy entries are readable
+    let legacy = QueuedMessage::deserialize(&Bincode::new(message.clone()).serialize()).unwrap();
+    assert_eq!(legacy.version, QUEUE_FORMAT_LEGACY);
+    assert!(legacy.needs_upgrade());
+    assert_eq!(legacy.inner, message);
+
+    // Entries written before source addresses were recorded are readable
+    let mut v3 = message.clone().serialize_versioned();
+    v3.truncate(4);
+    v3[2] = 3;
+    v3.extend_from_slice(
+        &Bincode::new((
+            message.queue_id,
+            message.created,
+            message.blob_hash.clone(),
+            message.return_path.clone(),
+            message.return_path_lcase.clone(),
+            message.return_path_domain.clone(),
+            message.recipients.clone(),
+            message.domains.clone(),
+            message.flags,
+            message.env_id.clone(),
+            message.priority,
+            message.size,
+            message.quota_keys.clone(),
+            message.deferrals.clone(),
+        ))
+        .serialize(),
+    );
+    let v3 = QueuedMessage::deserialize(&v3).unwrap();
+    assert_eq!(v3.version, 3);
+    assert!(v3.needs_upgrade());
+    assert_eq!(v3.inner, message);
+
+    // Entries written before holds were recorded are readable
+    message.set_source_ip(SourceIp {
+        domain_idx: 0,
+        ip: "127.0.0.2".parse().unwrap(),
+    });
+    let mut v4 = message.clone().serialize_versioned();
+    v4.truncate(4);
+    v4[2] = 4;
+    v4.extend_from_slice(
+        &Bincode::new((
+            message.queue_id,
+            message.created,
+            message.blob_hash.clone(),
+            message.return_path.clone(),
+            message.return_path_lcase.clone(),
+            message.return_path_domain.clone(),
+            message.recipients.clone(),
+            message.domains.clone(),
+            message.flags,
+            message.env_id.clone(),
+            message.priority,
+            message.size,
+            message.quota_keys.clone(),
+            message.deferrals.clone(),
+            message.source_ips.clone(),
+        ))
+        .serialize(),
+    );
+    let v4 = QueuedMessage::deserialize(&v4).unwrap();
+    assert_eq!(v4.version, 4);
+    assert!(v4.needs_upgrade());
+    assert_eq!(v4.inner, message);
+
+    // Entries written before connection attempts were recorded are readable
+    message.hold = Some(Hold {
+        tenant: "acme".to_string(),
+        reason: HoldReason::SendingWindow { until: 1234 },
+        since: 1000,
+    });
+    let mut v5 = message.clone().serialize_versioned();
+    v5.truncate(4);
+    v5[2] = 5;
+    v5.extend_from_slice(
+        &Bincode::new((
+            message.queue_id,
+            message.created,
+            message.blob_hash.clone(),
+            message.return_path.clone(),
+            message.return_path_lcase.clone(),
+            message.return_path_domain.clone(),
+            message.recipients.clone(),
+            message.domains.clone(),
+            message.flags,
+            message.env_id.clone(),
+            message.priority,
+            message.size,
+            message.quota_keys.clone(),
+            message.deferrals.clone(),
+            message.source_ips.clone(),
+            message.hold.clone(),
+        ))
+        .serialize(),
+    );
+    let v5 = QueuedMessage::deserialize(&v5).unwrap();
+    assert_eq!(v5.version, 5);
+    assert!(v5.needs_upgrade());
+    assert_eq!(v5.inner, message);
+
+    // Current entries round-trip
+    for num in 0..12 {
+        message.add_connect_attempt(ConnectAttempt {
+            domain_idx: 0,
+            time: 1000 + num,
+            remote_ip: if num % 2 == 0 {
+                "::1".parse().unwrap()
+            } else {
+                "127.0.0.1".parse().unwrap()
+            },
+            local_ip: None,
+            success: num == 11,
+        });
+    }
+    assert_eq!(message.connect_attempts.len(), 10);
+    assert_eq!(message.connect_attempts[0].time, 1002);
+    let current = QueuedMessage::deserialize(&message.clone().serialize_versioned()).unwrap();
+    assert_eq!(current.version, QUEUE_FORMAT_VERSION);
+    assert!(!current.needs_upgrade());
+    assert_eq!(current.inner, message);
+    assert_eq!(
+        current.inner.source_ip(0),
+        Some("127.0.0.2".parse().unwrap())
+    );
+
+    // Newer compatible entries with unknown trailing fields are readable
+    let mut newer = message.clone().serialize_versioned();
+    newer.truncate(4);
+    newer[2] = QUEUE_FORMAT_VERSION + 1;
+    newer.extend_from_slice(
+        &Bincode::new((message.clone(), "unknown field".to_string(), 1234u64)).serialize(),
+    );
+    let newer = QueuedMessage::deserialize(&newer).unwrap();
+    assert_eq!(newer.version, QUEUE_FORMAT_VERSION + 1);
+    assert_eq!(newer.inner, message);
+
+    // Newer incompatible entries are rejected
+    let mut incompatible = message.clone().serialize_versioned();
+    incompatible[3] = QUEUE_FORMAT_VERSION + 1;
+    assert!(QueuedMessage::deserialize(&incompatible).is_err());
+}
+
 #[test]
 fn delivery_events() {
     let mut message = new_message(0);
@@ -141,6 +338,10 @@ pub fn new_message(queue_id: u64) -> Message {
         env_id: None,
         priority: 0,
         quota_keys: vec![],
+        deferrals: vec![],
+        source_ips: vec![],
+        hold: None,
+        connect_attempts: vec![],
         blob_hash: Default::default(),
     }
 }
@@ -8,12 +8,20 @@ use std::time::Duration;
 
 use crate::smtp::{
     inbound::{TestMessage, TestQueueEvent},
+    queue::manager::new_message,
     session::{TestSession, VerifyResponse},
     TestSMTP,
 };
-use common::ipc::QueueEvent;
-use smtp::queue::{spool::SmtpSpool, DeliveryAttempt};
+use common::{
+    config::smtp::queue::{DeferralClass, QueueConfig},
+    ipc::QueueEvent,
+};
+use smtp::queue::{
+    spool::SmtpSpool, DeliveryAttempt, Domain, Error, ErrorDetails, HostResponse, Schedule, Status,
+};
+use smtp_proto::Response;
 use store::write::now;
+use utils::config::Config;
 
 const CONFIG: &str = r#"
 [session.ehlo]
@@ -34,6 +42,22 @@ expire = [{if = "sender_domain = 'test.org'", then = "6s"},
           {else = '1d'}]
 "#;
 
+const CONFIG_CLASSES: &str = r#"
+[queue.schedule.class.mailbox-full]
+retry = ["1h", "4h"]
+expire = "2d"
+
+[queue.schedule.class.greylist]
+retry = ["5m"]
+
+[queue.schedule.group.example]
+domains = ["example.org", "*.example.net"]
+
+[queue.schedule.group.example.class.greylist]
+retry = ["1m", "2m"]
+expire = "1h"
+"#;
+
 #[tokio::test]
 async fn queue_retry() {
     // Enable logging
@@ -179,3 +203,96 @@ async fn queue_retry() {
     let schedule = qr.expect_message().await;
     assert!([3599, 3600].contains(&(schedule.domains.first().unwrap().notify.due - now())));
 }
+
+#[test]
+fn deferral_schedules() {
+    let mut config = Config::new(CONFIG_CLASSES).unwrap();
+    let policy = QueueConfig::parse(&mut config).retry_policy;
+    assert!(config.errors.is_empty(), "{:?}", config.errors);
+
+    let mut message = new_message(0);
+    message.created = now();
+    for name in ["foobar.org", "mx.example.net"] {
+        message.domains.push(Domain {
+            domain: name.to_string(),
+            retry: Schedule::now(),
+            notify: Schedule::now(),
+            expires: message.created + 86400,
+            status: Status::Scheduled,
+        });
+    }
+    let schedule = [Duration::from_secs(60)];
+
+    // Mailbox full deferrals use their own schedule and lifetime
+    for (attempt, delay) in [(0, 3600), (1, 4 * 3600), (2, 4 * 3600)] {
+        message.domains[0].set_status(response(452, [4, 2, 2], "Mailbox full"), &schedule);
+        message.apply_retry_policy(0, &policy);
+        let deferral = &message.deferrals[0];
+        assert_eq!(deferral.class, DeferralClass::MailboxFull);
+        assert_eq!(deferral.attempt, attempt);
+        assert!([delay - 1, delay].contains(&(message.domains[0].retry.due - now())));
+        assert_eq!(message.domains[0].expires, message.created + 2 * 86400);
+    }
+
+    // Switching class restores the original lifetime and schedule
+    message.domains[0].set_status(
+        Status::TemporaryFailure(Error::DnsError("Temporary DNS failure".to_string())),
+        &schedule,
+    );
+    message.apply_retry_policy(0, &policy);
+    let deferral = &message.deferrals[0];
+    assert_eq!(deferral.class, DeferralClass::Dns);
+    assert_eq!(deferral.attempt, 0);
+    assert!([59, 60].contains(&(message.domains[0].retry.due - now())));
+    assert_eq!(message.domains[0].expires, message.created + 86400);
+
+    // DNS is fixed, now greylisted
+    message.domains[0].set_status(response(451, [4, 7, 1], "Try again later"), &schedule);
+    message.apply_retry_policy(0, &policy);
+    let deferral = &message.deferrals[0];
+    assert_eq!(deferral.class, DeferralClass::Greylist);
+    assert_eq!(deferral.attempt, 0);
+    assert!([299, 300].contains(&(message.domains[0].retry.due - now())));
+    assert!(
+        deferral.reason.contains("\"default\""),
+        "{}",
+        deferral.reason
+    );
+
+    // Domain groups override the default schedules
+    message.domains[1].set_status(response(450, [0, 0, 0], "Greylisting in action"), &schedule);
+    message.apply_retry_policy(1, &policy);
+    let deferral = &message.deferrals[1];
+    assert_eq!(deferral.domain_idx, 1);
+    assert_eq!(deferral.class, DeferralClass::Greylist);
+    assert!([59, 60].contains(&(message.domains[1].retry.due - now())));
+    assert_eq!(message.domains[1].expires, message.created + 3600);
+    assert!(
+        deferral.reason.contains("\"example\""),
+        "{}",
+        deferral.reason
+    );
+
+    // Other transient errors fall back to the queue schedule
+    message.domains[1].set_status(response(421, [4, 4, 2], "Service shutting down"), &schedule);
+    message.apply_retry_policy(1, &policy);
+    assert_eq!(message.deferrals[1].class, DeferralClass::Transient);
+    assert_eq!(message.domains[1].expires, message.created + 86400);
+
+    // Local limits are not deferral classes
+    let deferrals = message.deferrals.clone();
+    message.domains[1].set_status(Status::TemporaryFailure(Error::RateLimited), &schedule);
+    message.apply_retry_policy(1, &policy);
+    assert_eq!(message.deferrals, deferrals);
+}
+
+fn response(code: u16, esc: [u8; 3], message: &str) -> Status<(), Error> {
+    Status::TemporaryFailure(Error::UnexpectedResponse(HostResponse {
+        hostname: ErrorDetails::default(),
+        response: Response {
+            code,
+            esc,
+            message: message.to_string(),
+        },
+    }))
+}
@@ -76,6 +76,10 @@ impl SessionStream for DummyIo {
     fn tls_version_and_cipher(&self) -> (Cow<'static, str>, Cow<'static, str>) {
         ("".into(), "".into())
     }
+
+    fn peer_certificate(&self) -> Option<rustls_pki_types::CertificateDer<'static>> {
+        None
+    }
 }
 
 impl Unpin for DummyIo {}
@@ -358,6 +362,7 @@ impl TestServerInstance for ServerInstance {
                 config: tls_config.clone(),
                 acceptor: TlsAcceptor::from(tls_config),
                 implicit: false,
+                policy: Default::default(),
             },
             limiter: ConcurrencyLimiter::new(100),
             shutdown_rx,
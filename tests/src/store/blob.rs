@@ -6,8 +6,14 @@
 
 use ahash::AHashMap;
 use store::{
-    write::{blob::BlobQuota, now, BatchBuilder, BlobOp},
-    BlobClass, BlobStore, Serialize, Stores,
+    dispatch::blob::BLOB_CHUNK_SIZE,
+    write::{
+        blob::BlobQuota,
+        now,
+        tier::{BlobCommit, BlobTier, BlobTiering, TierStats},
+        BatchBuilder, BlobOp, ValueClass,
+    },
+    BlobClass, BlobStore, Serialize, Store, Stores, ValueKey,
 };
 use utils::{config::Config, BlobHash};
 
@@ -19,6 +25,7 @@ pub async fn blob_tests() {
     let mut config =
         Config::new(CONFIG.replace("{TMP}", temp_dir.path.as_path().to_str().unwrap())).unwrap();
     let stores = Stores::parse_all(&mut config).await;
+    let secondary_store = stores.blob_stores.get("fs").unwrap().clone();
 
     for (store_id, blob_store) in &stores.blob_stores {
         println!("Testing blob store {}...", store_id);
@@ -399,10 +406,140 @@ pub async fn blob_tests() {
                     ^ ct
             );
         }
+
+        // Test blob tiering
+        test_tiering(store.clone(), blob_store.clone(), secondary_store.clone()).await;
     }
     temp_dir.delete();
 }
 
+async fn test_tiering(store: Store, primary_store: BlobStore, secondary_store: BlobStore) {
+    // Start with an empty store, any committed blob would be moved
+    store.destroy().await;
+
+    let blob_store =
+        primary_store
+            .clone()
+            .with_tiering(BlobTiering::new(secondary_store.clone(), 0, Some(2)));
+    let data = b"tiered blob".repeat(100);
+    let hash = BlobHash::from(data.as_slice());
+
+    // Blobs committed before tiering was enabled are dated on the first run
+    blob_store.put_blob(hash.as_ref(), &data).await.unwrap();
+    store
+        .write(
+            BatchBuilder::new()
+                .set(BlobOp::Commit { hash: hash.clone() }, Vec::new())
+                .build_batch(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        store.tier_blobs(&blob_store).await.unwrap(),
+        TierStats::default()
+    );
+    assert_eq!(blob_commit(&store, &hash).await.tier, BlobTier::Primary);
+
+    // Migrate blob to the secondary store
+    assert_eq!(
+        store.tier_blobs(&blob_store).await.unwrap(),
+        TierStats {
+            migrated: 1,
+            migrated_bytes: data.len(),
+            ..Default::default()
+        }
+    );
+    assert_eq!(blob_commit(&store, &hash).await.tier, BlobTier::Secondary);
+    assert!(primary_store
+        .get_blob(hash.as_ref(), 0..usize::MAX)
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        secondary_store
+            .get_blob(hash.as_ref(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        data
+    );
+
+    // Reads are served from the secondary store
+    assert!(store.blob_exists(&hash).await.unwrap());
+    assert_eq!(
+        blob_store
+            .get_blob(hash.as_ref(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        data
+    );
+    assert_eq!(
+        blob_store
+            .get_blob(hash.as_ref(), 5..20)
+            .await
+            .unwrap()
+            .unwrap(),
+        &data[5..20]
+    );
+
+    // A single full read does not promote the blob
+    assert_eq!(
+        store.tier_blobs(&blob_store).await.unwrap(),
+        TierStats::default()
+    );
+
+    // Promote blob back to the primary store after two reads
+    for _ in 0..2 {
+        blob_store
+            .get_blob(hash.as_ref(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    assert_eq!(
+        store.tier_blobs(&blob_store).await.unwrap(),
+        TierStats {
+            promoted: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(blob_commit(&store, &hash).await.tier, BlobTier::Primary);
+    assert_eq!(
+        primary_store
+            .get_blob(hash.as_ref(), 0..usize::MAX)
+            .await
+            .unwrap()
+            .unwrap(),
+        data
+    );
+    assert!(secondary_store
+        .get_blob(hash.as_ref(), 0..usize::MAX)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Migrate blob again and purge it from both tiers
+    assert_eq!(store.tier_blobs(&blob_store).await.unwrap().migrated, 1);
+    store.purge_blobs(blob_store.clone()).await.unwrap();
+    assert!(!store.blob_exists(&hash).await.unwrap());
+    assert!(blob_store
+        .get_blob(hash.as_ref(), 0..usize::MAX)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+async fn blob_commit(store: &Store, hash: &BlobHash) -> BlobCommit {
+    store
+        .get_value::<BlobCommit>(ValueKey::from(ValueClass::Blob(BlobOp::Commit {
+            hash: hash.clone(),
+        })))
+        .await
+        .unwrap()
+        .unwrap()
+}
+
 async fn test_store(store: BlobStore) {
     // Test small blob
     const DATA: &[u8] = b"Lorem ipsum dolor sit amet, consectetur adipiscing elit. Fusce erat nisl, dignissim a porttitor id, varius nec arcu. Sed mauris.";
@@ -470,6 +607,37 @@ async fn test_store(store: BlobStore) {
         .unwrap(),
         std::str::from_utf8(&data[3000111..4000999]).unwrap()
     );
+
+    // Large blobs are read in bounded chunks
+    for range in [0..usize::MAX, 3000111..7000999] {
+        let expected = &data[range.start..range.end.min(data.len())];
+        let mut chunks = store.get_blob_chunks(hash.as_slice(), range);
+        let mut contents = Vec::with_capacity(expected.len());
+        while let Some(chunk) = chunks.next().await.unwrap() {
+            if store.has_range_reads() {
+                assert!(chunk.len() <= BLOB_CHUNK_SIZE);
+            }
+            contents.extend_from_slice(&chunk);
+        }
+        assert_eq!(contents.len(), expected.len());
+        assert!(contents == expected);
+    }
+
+    // Ranges past the end of the blob are empty
+    assert_eq!(
+        store
+            .get_blob(hash.as_slice(), data.len() + 10..data.len() + 20)
+            .await
+            .unwrap()
+            .unwrap_or_default(),
+        Vec::<u8>::new()
+    );
+    assert!(store
+        .get_blob_chunks(hash.as_slice(), data.len() + 10..usize::MAX)
+        .next()
+        .await
+        .unwrap()
+        .is_none());
     assert!(store.delete_blob(hash.as_slice()).await.unwrap());
     assert!(store
         .get_blob(hash.as_slice(), 0..usize::MAX)
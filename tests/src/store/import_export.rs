@@ -8,6 +8,7 @@ use ahash::AHashSet;
 use common::{manager::backup::BackupParams, Core};
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
+    dispatch::copy::{copy_store, CopyMode},
     rand,
     write::{
         AnyKey, BatchBuilder, BitmapClass, BitmapHash, BlobOp, DirectoryClass, LookupClass,
@@ -15,7 +16,7 @@ use store::{
     },
     *,
 };
-use utils::BlobHash;
+use utils::{config::Config, BlobHash};
 
 use crate::store::TempDir;
 
@@ -250,9 +251,151 @@ pub async fn test(db: Store) {
     snapshot.assert_is_eq(&Snapshot::new(&db).await);
     println!(" GREAT SUCCESS!");
 
+    // Write archive
+    println!("Archiving store...");
+    let archive_dir = TempDir::new("art_vandelay_archive_tests", true);
+    let archive = archive_dir.path.join("backup.bin");
+    let stats = db.backup(&core.storage.blob, &archive).await.unwrap();
+    assert_eq!(stats.blobs, blob_hashes.len() as u64);
+    assert_eq!(stats.missing_blobs, 0);
+
+    // Archives are only restored into empty stores
+    assert!(db.restore(&core.storage.blob, &archive).await.is_err());
+    db.destroy().await;
+    db.assert_is_empty(db.clone().into()).await;
+
+    // Damaged archives are rejected before anything is written
+    let damaged = archive_dir.path.join("damaged.bin");
+    let mut bytes = std::fs::read(&archive).unwrap();
+    let pos = bytes.len() - 30;
+    bytes[pos] ^= 0xff;
+    std::fs::write(&damaged, bytes).unwrap();
+    assert!(db.restore(&core.storage.blob, &damaged).await.is_err());
+    db.assert_is_empty(db.clone().into()).await;
+
+    // Restore archive
+    println!("Restoring archive...");
+    assert_eq!(
+        db.restore(&core.storage.blob, &archive).await.unwrap(),
+        stats
+    );
+    print!("Verifying restored store hash...");
+    snapshot.assert_is_eq(&Snapshot::new(&db).await);
+    println!(" GREAT SUCCESS!");
+
+    // Modify one account and the settings
+    println!("Writing incremental archive...");
+    let data = random_bytes(512);
+    let hash = BlobHash::from(data.as_slice());
+    core.storage
+        .blob
+        .put_blob(hash.as_ref(), &data)
+        .await
+        .unwrap();
+    let mut batch = BatchBuilder::new();
+    batch
+        .set(
+            ValueClass::Blob(BlobOp::Commit { hash: hash.clone() }),
+            vec![],
+        )
+        .with_account_id(3)
+        .with_collection(0)
+        .update_document(0)
+        .clear(ValueClass::Property(0))
+        .set(ValueClass::Property(10), random_bytes(32))
+        .set(ValueClass::Blob(BlobOp::Link { hash }), vec![]);
+    batch.ops.push(Operation::ChangeId { change_id: 1000 });
+    batch.ops.push(Operation::Log {
+        set: MaybeDynamicValue::Static(vec![3, 0, 0]),
+    });
+    batch.set(ValueClass::Config(random_bytes(20)), random_bytes(20));
+    db.write(batch.build()).await.unwrap();
+    let increment_snapshot = Snapshot::new(&db).await;
+
+    // Only the changed account, shared objects and new blobs are included
+    let increment = archive_dir.path.join("increment.bin");
+    let increment_stats = db
+        .backup_incremental(&core.storage.blob, &increment, &archive)
+        .await
+        .unwrap();
+    assert_eq!(increment_stats.accounts, 2);
+    assert_eq!(increment_stats.blobs, 1);
+    assert!(increment_stats.keys < stats.keys);
+    db.destroy().await;
+
+    // Increments are only applied in order on top of their parent
+    for chain in [
+        vec![&increment],
+        vec![&increment, &archive],
+        vec![&archive, &increment, &increment],
+    ] {
+        assert!(db.restore_chain(&core.storage.blob, &chain).await.is_err());
+        db.assert_is_empty(db.clone().into()).await;
+    }
+
+    // Restore full archive and increment
+    println!("Restoring incremental archive...");
+    db.restore_chain(&core.storage.blob, &[&archive, &increment])
+        .await
+        .unwrap();
+    print!("Verifying incremental store hash...");
+    increment_snapshot.assert_is_eq(&Snapshot::new(&db).await);
+    println!(" GREAT SUCCESS!");
+
+    // Copy store to another backend
+    println!("Copying store...");
+    let copy_dir = TempDir::new("art_vandelay_copy_tests", true);
+    let mut config = Config::new(format!(
+        "[store.\"copy\"]\ntype = \"sqlite\"\npath = \"{}/copy.db\"\n",
+        copy_dir.path.display()
+    ))
+    .unwrap();
+    let copy_stores = Stores::parse_all(&mut config).await;
+    let dest = copy_stores.stores.get("copy").unwrap().clone();
+    let blob_dest = copy_stores.blob_stores.get("copy").unwrap().clone();
+    let stats = copy_store(&db, &dest, &core.storage.blob, &blob_dest, CopyMode::Full)
+        .await
+        .unwrap();
+    assert_eq!(stats.blobs, blob_hashes.len() as u64 + 1);
+    assert_eq!(stats.missing_blobs + stats.corrupted_blobs, 0);
+    assert_eq!(
+        copy_store(&db, &dest, &core.storage.blob, &blob_dest, CopyMode::Verify)
+            .await
+            .unwrap()
+            .mismatches,
+        0
+    );
+
+    // Changes made after the first pass are copied by the delta pass
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(5)
+        .with_collection(0)
+        .update_document(10)
+        .set(ValueClass::Property(0), random_bytes(64))
+        .clear(ValueClass::Property(1));
+    batch.set(ValueClass::Config(random_bytes(25)), random_bytes(25));
+    db.write(batch.build()).await.unwrap();
+    for (mode, mismatches) in [
+        (CopyMode::Verify, 3),
+        (CopyMode::Delta, 3),
+        (CopyMode::Verify, 0),
+    ] {
+        assert_eq!(
+            copy_store(&db, &dest, &core.storage.blob, &blob_dest, mode)
+                .await
+                .unwrap()
+                .mismatches,
+            mismatches,
+            "{mode:?}"
+        );
+    }
+    copy_dir.delete();
+
     // Destroy store
     db.destroy().await;
     temp_dir.delete();
+    archive_dir.delete();
 }
 
 #[derive(Debug, PartialEq, Eq)]
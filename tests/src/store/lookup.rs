@@ -6,7 +6,10 @@
 
 use std::time::Duration;
 
-use store::{LookupStore, Stores};
+use store::{
+    write::expire::{lookup_key_expiry, ExpiringRange},
+    LookupStore, Stores, SUBSPACE_LOOKUP_VALUE,
+};
 use utils::config::{Config, Rate};
 
 use crate::{
@@ -63,6 +66,26 @@ pub async fn lookup_tests() {
         tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
         assert_eq!(None, store.key_get::<String>(key.clone()).await.unwrap());
 
+        // Test expired range purge
+        if let LookupStore::Store(inner) = &store {
+            for (key, expires) in [("ttl:a", Some(1)), ("ttl:b", None), ("ttl:c", Some(1))] {
+                store
+                    .key_set(key.as_bytes().to_vec(), b"v".to_vec(), expires)
+                    .await
+                    .unwrap();
+            }
+            tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+            let range =
+                ExpiringRange::with_prefix("ttl", SUBSPACE_LOOKUP_VALUE, "ttl:", lookup_key_expiry);
+            assert_eq!(inner.purge_expired(&range, 1).await.unwrap(), 2);
+            assert_eq!(inner.purge_expired(&range, 1).await.unwrap(), 0);
+            assert_eq!(
+                store.key_get::<String>(b"ttl:b".to_vec()).await.unwrap(),
+                Some("v".to_string())
+            );
+            store.key_delete(b"ttl:b".to_vec()).await.unwrap();
+        }
+
         store.purge_lookup_store().await.unwrap();
         if let LookupStore::Store(store) = &store {
             store.assert_is_empty(store.clone().into()).await;
@@ -0,0 +1,166 @@
+/*
+ * SPDX-FileCopyrightText: 2020 Stalwart Labs Ltd <hello@stalw.art>
+ *
+ * SPDX-License-Identifier: AGPL-3.0-only OR LicenseRef-SEL
+ */
+
+use std::sync::Mutex;
+
+use store::{
+    write::{migrate::MigrationChunk, now, BatchBuilder, TaskClass, ValueClass},
+    IterateParams, Serialize, Store, ValueKey,
+};
+
+const CHUNK_SIZE: usize = 3;
+
+pub async fn test(db: Store) {
+    println!("Running migration tests...");
+
+    // Populate the store with records using the old format
+    let mut batch = BatchBuilder::new();
+    for n in 0..10 {
+        batch.set(
+            ValueClass::Config(format!("migrate.{n:02}").into_bytes()),
+            b"v1".to_vec(),
+        );
+    }
+    db.write(batch.build()).await.unwrap();
+
+    // Simulate a crash after two chunks
+    let calls = Mutex::new(Vec::new());
+    let result = db
+        .run_migration("test", 60, |from| {
+            calls.lock().unwrap().push(from.clone());
+            let fail = calls.lock().unwrap().len() == 3;
+            let db = db.clone();
+            async move {
+                if fail {
+                    Err(trc::StoreEvent::UnexpectedError.into_err())
+                } else {
+                    migrate_chunk(&db, from).await
+                }
+            }
+        })
+        .await;
+    assert!(result.is_err());
+    let progress = db.migration_progress("test").await.unwrap().unwrap();
+    assert_eq!(progress.processed, 6);
+    assert_eq!(progress.migrated, 6);
+    assert_eq!(progress.finished_at, None);
+    assert_eq!(values(&db).await, "v2,v2,v2,v2,v2,v2,v1,v1,v1,v1");
+    assert!(!db.is_migration_running("test").await.unwrap());
+    assert!(!db.is_migration_finished("test").await.unwrap());
+
+    // The migration resumes from the last recorded key
+    let resume_key = progress.next_key.clone();
+    assert!(resume_key.is_some());
+    let calls = Mutex::new(Vec::new());
+    let progress = db
+        .run_migration("test", 60, |from| {
+            calls.lock().unwrap().push(from.clone());
+            let db = db.clone();
+            async move { migrate_chunk(&db, from).await }
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(calls.lock().unwrap().first(), Some(&resume_key));
+    assert_eq!(progress.processed, 10);
+    assert_eq!(progress.migrated, 4 + 6);
+    assert!(progress.finished_at.is_some());
+    assert_eq!(values(&db).await, "v2,v2,v2,v2,v2,v2,v2,v2,v2,v2");
+    assert!(!db.is_migration_running("test").await.unwrap());
+    assert!(db.is_migration_finished("test").await.unwrap());
+
+    // Applied migrations do not run again
+    let progress = db
+        .run_migration("test", 60, not_expected)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(progress.processed, 10);
+
+    // Migrations locked by another node are skipped
+    let mut batch = BatchBuilder::new();
+    batch.set(
+        ValueClass::Task(TaskClass::Lock(b"migration:locked".to_vec())),
+        (now() + 60).serialize(),
+    );
+    db.write(batch.build()).await.unwrap();
+    assert!(db.is_migration_running("locked").await.unwrap());
+    assert_eq!(
+        db.run_migration("locked", 60, not_expected).await.unwrap(),
+        None
+    );
+    assert_eq!(db.migration_progress("locked").await.unwrap(), None);
+
+    // Remove test data
+    let mut batch = BatchBuilder::new();
+    for n in 0..10 {
+        batch.clear(ValueClass::Config(format!("migrate.{n:02}").into_bytes()));
+    }
+    for id in ["test", "locked"] {
+        batch
+            .clear(ValueClass::Task(TaskClass::Lock(
+                format!("migration:{id}").into_bytes(),
+            )))
+            .clear(ValueClass::Task(TaskClass::State(
+                format!("migration:{id}").into_bytes(),
+            )));
+    }
+    db.write(batch.build()).await.unwrap();
+}
+
+async fn migrate_chunk(db: &Store, from: Option<Vec<u8>>) -> trc::Result<MigrationChunk> {
+    let mut chunk = MigrationChunk::default();
+    let mut last_key = None;
+    db.iterate(
+        IterateParams::new(
+            ValueKey::from(ValueClass::Config(
+                from.unwrap_or_else(|| b"migrate.".to_vec()),
+            )),
+            ValueKey::from(ValueClass::Config(b"migrate.\xff".to_vec())),
+        ),
+        |key, value| {
+            if value == b"v1" {
+                chunk
+                    .batch
+                    .set(ValueClass::Config(key.to_vec()), b"v2".to_vec());
+                chunk.migrated += 1;
+            }
+            chunk.processed += 1;
+            last_key = Some(key.to_vec());
+
+            Ok(chunk.processed < CHUNK_SIZE as u64)
+        },
+    )
+    .await?;
+
+    if chunk.processed == CHUNK_SIZE as u64 {
+        chunk.next_key = last_key.map(|mut key| {
+            key.push(0);
+            key
+        });
+    }
+
+    Ok(chunk)
+}
+
+async fn not_expected(_: Option<Vec<u8>>) -> trc::Result<MigrationChunk> {
+    panic!("Migration should not run")
+}
+
+async fn values(db: &Store) -> String {
+    let mut values = Vec::new();
+    for n in 0..10 {
+        values.push(
+            db.get_value::<String>(ValueKey::from(ValueClass::Config(
+                format!("migrate.{n:02}").into_bytes(),
+            )))
+            .await
+            .unwrap()
+            .unwrap(),
+        );
+    }
+    values.join(",")
+}
@@ -8,6 +8,7 @@ pub mod assign_id;
 pub mod blob;
 pub mod import_export;
 pub mod lookup;
+pub mod migrate;
 pub mod ops;
 pub mod query;
 
@@ -38,13 +39,19 @@ path = "{TMP}"
 [store."rocksdb"]
 type = "rocksdb"
 path = "{TMP}/rocksdb"
+value-compression.algorithm = "lz4"
+value-compression.threshold = 512
 
 [store."foundationdb"]
 type = "foundationdb"
+value-compression.algorithm = "lz4"
+value-compression.threshold = 512
 
 [store."sqlite"]
 type = "sqlite"
 path = "{TMP}/sqlite.db"
+value-compression.algorithm = "lz4"
+value-compression.threshold = 512
 
 [store."postgresql"]
 type = "postgresql"
@@ -53,6 +60,8 @@ port = 5432
 database = "stalwart"
 user = "postgres"
 password = "mysecretpassword"
+value-compression.algorithm = "lz4"
+value-compression.threshold = 512
 
 [store."mysql"]
 type = "mysql"
@@ -61,6 +70,8 @@ port = 3307
 database = "stalwart"
 user = "root"
 password = "password"
+value-compression.algorithm = "lz4"
+value-compression.threshold = 512
 
 [store."redis"]
 type = "redis"
@@ -94,6 +105,7 @@ pub async fn store_tests() {
     import_export::test(store.clone()).await;
     assign_id::test(store.clone()).await;
     ops::test(store.clone()).await;
+    migrate::test(store.clone()).await;
     query::test(store.clone(), FtsStore::Store(store.clone()), insert).await;
 
     if insert {
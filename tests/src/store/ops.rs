@@ -9,9 +9,10 @@ use std::collections::HashSet;
 use jmap_proto::types::{collection::Collection, property::Property};
 use store::{
     write::{
-        BatchBuilder, BitmapClass, DirectoryClass, MaybeDynamicId, TagValue, ValueClass, F_CLEAR,
+        assert::HashedValue, compress::decompress_value, BatchBuilder, BitmapClass, DirectoryClass,
+        MaybeDynamicId, TagValue, ValueClass, F_CLEAR,
     },
-    BitmapKey, Store, ValueKey,
+    BitmapKey, IterateParams, Store, ValueKey,
 };
 
 // FDB max value
@@ -343,4 +344,212 @@ pub async fn test(db: Store) {
         // Make sure everything is deleted
         db.assert_is_empty(db.clone().into()).await;
     }
+
+    // Value prefix filters
+    println!("Running value prefix iteration tests...");
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0);
+    for (key, value) in [
+        ("filter-a", b"\x01\x02a".as_slice()),
+        ("filter-b", b"\x01\x03b"),
+        ("filter-c", b"\x02\x02c"),
+        ("filter-d", b"\x01\x02d"),
+        ("filter-e", b"\x01"),
+    ] {
+        batch.set(ValueClass::Config(key.as_bytes().to_vec()), value.to_vec());
+    }
+    db.write(batch.build_batch()).await.unwrap();
+    let range = |params: store::IterateParams<ValueKey<ValueClass<u32>>>| {
+        let db = db.clone();
+        async move {
+            let mut keys = Vec::new();
+            db.iterate(params, |key, _| {
+                keys.push(String::from_utf8(key.to_vec()).unwrap());
+                Ok(true)
+            })
+            .await
+            .unwrap();
+            keys
+        }
+    };
+    let params = store::IterateParams::new(
+        ValueKey::from(ValueClass::Config(b"filter-".to_vec())),
+        ValueKey::from(ValueClass::Config(b"filter-\xFF".to_vec())),
+    );
+    assert_eq!(
+        range(params.clone().with_value_prefix(vec![1u8, 2])).await,
+        vec!["filter-a", "filter-d"]
+    );
+    assert_eq!(
+        range(
+            params
+                .clone()
+                .with_value_prefixes([vec![1u8, 3], vec![2u8]])
+                .no_values()
+        )
+        .await,
+        vec!["filter-b", "filter-c"]
+    );
+    assert_eq!(
+        range(
+            params
+                .clone()
+                .with_value_prefix(vec![1u8, 2])
+                .descending()
+                .only_first()
+        )
+        .await,
+        vec!["filter-d"]
+    );
+    assert!(range(params.clone().with_value_prefix(vec![3u8]))
+        .await
+        .is_empty());
+    assert_eq!(range(params).await.len(), 5);
+
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0);
+    for key in ["filter-a", "filter-b", "filter-c", "filter-d", "filter-e"] {
+        batch.clear(ValueClass::Config(key.as_bytes().to_vec()));
+    }
+    db.write(batch.build_batch()).await.unwrap();
+
+    println!("Running value compression tests...");
+    let compression = db.value_compression();
+    assert!(
+        compression.is_enabled(),
+        "value compression is not configured"
+    );
+    let large = "compressible value ".repeat(compression.threshold);
+    let small = "small";
+    let compressed = compression.compress(large.as_bytes()).unwrap();
+    assert!(compressed.len() < large.len());
+    assert_eq!(decompress_value(&compressed).as_ref(), large.as_bytes());
+    assert!(compression.compress(small.as_bytes()).is_none());
+
+    // Incompressible values that happen to start with the marker byte
+    let mut seed = 0x2545f491u32;
+    let incompressible = [compressed[0]]
+        .into_iter()
+        .chain((0..compression.threshold * 2).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        }))
+        .collect::<Vec<_>>();
+    assert!(compression.compress(&incompressible).is_none());
+
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0);
+    for (key, value) in [
+        ("compress-a", [&[1u8, 2], large.as_bytes()].concat()),
+        ("compress-b", small.as_bytes().to_vec()),
+        ("compress-c", incompressible.clone()),
+        ("compress-d", [&[3u8], large.as_bytes()].concat()),
+    ] {
+        batch.set(ValueClass::Config(key.as_bytes().to_vec()), value);
+    }
+    db.write(batch.build_batch()).await.unwrap();
+    let params = IterateParams::new(
+        ValueKey::from(ValueClass::Config(b"compress-".to_vec())),
+        ValueKey::from(ValueClass::Config(b"compress-\xFF".to_vec())),
+    );
+    let mut values = Vec::new();
+    db.iterate(params.clone(), |key, value| {
+        values.push((String::from_utf8(key.to_vec()).unwrap(), value.to_vec()));
+        Ok(true)
+    })
+    .await
+    .unwrap();
+    assert_eq!(
+        values,
+        vec![
+            (
+                "compress-a".to_string(),
+                [&[1u8, 2], large.as_bytes()].concat()
+            ),
+            ("compress-b".to_string(), small.as_bytes().to_vec()),
+            ("compress-c".to_string(), incompressible),
+            (
+                "compress-d".to_string(),
+                [&[3u8], large.as_bytes()].concat()
+            ),
+        ]
+    );
+    assert_eq!(
+        range(params.clone().with_value_prefix(vec![1u8, 2])).await,
+        vec!["compress-a"]
+    );
+    assert_eq!(
+        range(
+            params
+                .clone()
+                .with_value_prefixes([vec![3u8], small.as_bytes().to_vec()])
+                .no_values()
+        )
+        .await,
+        vec!["compress-b", "compress-d"]
+    );
+    assert_eq!(
+        range(
+            params
+                .clone()
+                .with_value_prefixes([vec![1u8], vec![3u8]])
+                .descending()
+                .only_first()
+        )
+        .await,
+        vec!["compress-d"]
+    );
+
+    // Assertions are evaluated against the decompressed value
+    let key = ValueKey::from(ValueClass::Property(1));
+    db.write(
+        BatchBuilder::new()
+            .with_account_id(0)
+            .with_collection(0)
+            .update_document(0)
+            .set(ValueClass::Property(1), large.as_bytes())
+            .build_batch(),
+    )
+    .await
+    .unwrap();
+    let value = db
+        .get_value::<HashedValue<String>>(key.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(value.inner, large);
+    db.write(
+        BatchBuilder::new()
+            .with_account_id(0)
+            .with_collection(0)
+            .update_document(0)
+            .assert_value(ValueClass::Property(1), &value)
+            .clear(ValueClass::Property(1))
+            .build_batch(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(db.get_value::<String>(key).await.unwrap(), None);
+
+    let mut batch = BatchBuilder::new();
+    batch
+        .with_account_id(0)
+        .with_collection(0)
+        .update_document(0);
+    for key in ["compress-a", "compress-b", "compress-c", "compress-d"] {
+        batch.clear(ValueClass::Config(key.as_bytes().to_vec()));
+    }
+    db.write(batch.build_batch()).await.unwrap();
+    db.assert_is_empty(db.clone().into()).await;
 }